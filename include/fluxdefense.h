@@ -0,0 +1,114 @@
+#ifndef FLUXDEFENSE_H
+#define FLUXDEFENSE_H
+
+/* Generated by cbindgen from src/ffi.rs; edit that file and run `make header`. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+// Version of this API; bumped whenever a signature or behavior changes.
+#define FLUX_API_VERSION 1
+
+// Success.
+#define FLUX_OK 0
+
+// A pointer was null, a string was not UTF-8 or the JSON did not parse.
+#define FLUX_ERR_INVALID_ARGUMENT -1
+
+// flux_init has not been called, or flux_shutdown has.
+#define FLUX_ERR_NOT_INITIALIZED -2
+
+// flux_init was already called, or flux_start already ran.
+#define FLUX_ERR_ALREADY_INITIALIZED -3
+
+// The engine failed; flux_last_error has the details.
+#define FLUX_ERR_INTERNAL -4
+
+// Called with each security event as a JSON object. The string is only
+// valid during the call. Calls come from an engine thread, one at a time.
+typedef void (*FluxEventCallback)(const char *event_json, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Version of the C API this library implements, FLUX_API_VERSION.
+uint32_t flux_api_version(void);
+
+// Creates the engine from the configuration file at `config_path`, or from
+// the default locations when it is null. Nothing is monitored until
+// flux_start.
+//
+// # Safety
+// `config_path` must be null or a NUL-terminated string.
+int flux_init(const char *config_path);
+
+// Starts monitoring and begins delivering events to subscribers.
+int flux_start(void);
+
+// Replaces the enforced policies with `policy_json`, an object with an
+// optional "file_policy" and "network_policy", as served by
+// GET /api/policies/active. Omitted halves are left as they are.
+//
+// # Safety
+// `policy_json` must be a NUL-terminated string.
+int flux_set_policy_json(const char *policy_json);
+
+// Calls `callback` with every security event until flux_unsubscribe_events
+// or flux_shutdown. Returns a subscription id above zero, or an error code.
+// Subscribing before flux_start is allowed.
+int flux_subscribe_events(FluxEventCallback callback, void *user_data);
+
+// Stops the callbacks of a subscription; no call is in progress or made
+// after this returns, unless it is called from within the callback itself.
+int flux_unsubscribe_events(int subscription);
+
+// Stops monitoring, delivers queued events and releases the engine; flux_init
+// may be called again afterwards.
+int flux_shutdown(void);
+
+// The message of the last error on this thread, or null. Free it with
+// flux_defense_free_string.
+char *flux_last_error(void);
+
+// Initializes the engine from the default configuration; kept for the
+// system extension, new embedders use flux_init.
+void flux_defense_init(void);
+
+// Releases the engine; kept for the system extension, see flux_shutdown.
+void flux_defense_cleanup(void);
+
+// Evaluates a file system event and returns whether it should be allowed.
+//
+// # Safety
+// `path_ptr` must be null or a NUL-terminated string.
+bool flux_defense_evaluate_file_event(int event_type, uint32_t pid, const char *path_ptr);
+
+// Evaluates a network connection and returns whether it should be allowed.
+//
+// # Safety
+// `remote_ip_ptr` must be null or a NUL-terminated string, and so must
+// `domain_ptr`.
+bool flux_defense_evaluate_network_connection(const char *remote_ip_ptr,
+                                              uint16_t remote_port,
+                                              const char *domain_ptr);
+
+// The current file policy as JSON, or null; free it with
+// flux_defense_free_string.
+char *flux_defense_get_file_policy(void);
+
+// The current network policy as JSON, or null; free it with
+// flux_defense_free_string.
+char *flux_defense_get_network_policy(void);
+
+// Frees a string returned by this library.
+//
+// # Safety
+// `s` must be null or a string returned by this library, not yet freed.
+void flux_defense_free_string(char *s);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* FLUXDEFENSE_H */
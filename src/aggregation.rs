@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::monitor::{FileAccessType, ProcessInfo, SecurityEvent, SecurityEventType, Verdict};
+
+// Open groups beyond this are not tracked; their accesses are reported one by one
+const MAX_GROUPS: usize = 65_536;
+// How often open groups are checked for an elapsed window
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregationRule {
+    pub prefix: PathBuf,
+    // 0 reports every access under the prefix
+    pub window_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregationConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Window for paths no rule matches
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+    // Paths kept as examples in each summary
+    #[serde(default = "default_max_samples")]
+    pub max_samples: usize,
+    // The longest matching prefix wins
+    #[serde(default)]
+    pub rules: Vec<AggregationRule>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_window() -> u64 {
+    5
+}
+
+fn default_max_samples() -> usize {
+    5
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            window_secs: default_window(),
+            max_samples: default_max_samples(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl AggregationConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(rule) = self.rules.iter().find(|rule| !rule.prefix.is_absolute()) {
+            return Err(anyhow!("file_access_aggregation prefix {:?} is not absolute", rule.prefix));
+        }
+        Ok(())
+    }
+
+    fn window_for(&self, path: &Path) -> Duration {
+        let secs = self.rules.iter()
+            .filter(|rule| path.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.components().count())
+            .map_or(self.window_secs, |rule| rule.window_secs);
+        Duration::from_secs(secs)
+    }
+}
+
+// Accesses of one kind by one process in one directory, after the first
+// one, which was reported as it happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileAccessSummary {
+    pub directory: PathBuf,
+    pub access_type: FileAccessType,
+    pub process_info: ProcessInfo,
+    pub count: u64,
+    pub sample_paths: Vec<PathBuf>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl FileAccessSummary {
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let samples: Vec<String> = self.sample_paths.iter().map(|path| path.display().to_string()).collect();
+        let access = format!("{:?}", self.access_type).to_lowercase();
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: self.last_seen,
+            event_type: SecurityEventType::FileAccess {
+                target_path: self.directory.clone(),
+                access_type: self.access_type.clone(),
+            },
+            process_info: self.process_info.clone(),
+            verdict: Verdict::Log,
+            policy_reason: format!(
+                "Aggregated {} more {} accesses in {} over {}s, e.g. {}",
+                self.count,
+                access,
+                self.directory.display(),
+                (self.last_seen - self.first_seen).num_seconds(),
+                samples.join(", ")
+            ),
+        }
+    }
+}
+
+type GroupKey = (u32, PathBuf, FileAccessType);
+
+struct Group {
+    opened: Instant,
+    window: Duration,
+    summary: FileAccessSummary,
+}
+
+#[derive(Default)]
+struct AggregatorState {
+    groups: HashMap<GroupKey, Group>,
+    // Closed groups waiting for `take_due`
+    finished: Vec<FileAccessSummary>,
+    last_sweep: Option<Instant>,
+}
+
+// Collapses a process reading or writing file after file in one directory
+// into its first event plus one summary per window. Denied accesses and
+// other event types pass through untouched
+#[derive(Default)]
+pub struct FileAccessAggregator {
+    config: Mutex<AggregationConfig>,
+    state: Mutex<AggregatorState>,
+}
+
+impl FileAccessAggregator {
+    pub fn new(config: AggregationConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            state: Mutex::new(AggregatorState::default()),
+        }
+    }
+
+    pub fn configure(&self, config: AggregationConfig) {
+        if let Ok(mut current) = self.config.lock() {
+            *current = config;
+        }
+    }
+
+    // The event to report now, or None when it was folded into a summary
+    pub fn offer(&self, event: SecurityEvent) -> Option<SecurityEvent> {
+        self.offer_at(event, Instant::now())
+    }
+
+    fn offer_at(&self, event: SecurityEvent, now: Instant) -> Option<SecurityEvent> {
+        let SecurityEventType::FileAccess { ref target_path, ref access_type } = event.event_type else {
+            return Some(event);
+        };
+        if matches!(event.verdict, Verdict::Deny) {
+            return Some(event);
+        }
+        let Ok(config) = self.config.lock().map(|config| config.clone()) else { return Some(event) };
+        let window = config.window_for(target_path);
+        if !config.enabled || window.is_zero() {
+            return Some(event);
+        }
+        let Ok(mut state) = self.state.lock() else { return Some(event) };
+
+        let directory = target_path.parent().unwrap_or(target_path).to_path_buf();
+        let key = (event.process_info.pid, directory, access_type.clone());
+        if let Some(group) = state.groups.get_mut(&key) {
+            if now.saturating_duration_since(group.opened) < group.window {
+                group.summary.count += 1;
+                group.summary.last_seen = event.timestamp;
+                if group.summary.sample_paths.len() < config.max_samples {
+                    group.summary.sample_paths.push(target_path.clone());
+                }
+                return None;
+            }
+            // The window ended before a sweep closed it
+            let group = state.groups.remove(&key).expect("group was just found");
+            if group.summary.count > 0 {
+                state.finished.push(group.summary);
+            }
+        }
+        if state.groups.len() >= MAX_GROUPS {
+            debug!("File access aggregation is full; reporting {:?} as is", target_path);
+            return Some(event);
+        }
+
+        let summary = FileAccessSummary {
+            directory: key.1.clone(),
+            access_type: access_type.clone(),
+            process_info: event.process_info.clone(),
+            count: 0,
+            sample_paths: Vec::new(),
+            first_seen: event.timestamp,
+            last_seen: event.timestamp,
+        };
+        state.groups.insert(key, Group { opened: now, window, summary });
+        Some(event)
+    }
+
+    // Summaries of groups whose window has ended; groups that saw nothing
+    // after their first access are closed without one
+    pub fn take_due(&self) -> Vec<FileAccessSummary> {
+        self.take_due_at(Instant::now())
+    }
+
+    fn take_due_at(&self, now: Instant) -> Vec<FileAccessSummary> {
+        let Ok(mut state) = self.state.lock() else { return Vec::new() };
+        if state.last_sweep.is_some_and(|last| now.saturating_duration_since(last) < SWEEP_INTERVAL) {
+            return std::mem::take(&mut state.finished);
+        }
+        state.last_sweep = Some(now);
+
+        let due: Vec<GroupKey> = state.groups.iter()
+            .filter(|(_, group)| now.saturating_duration_since(group.opened) >= group.window)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in due {
+            if let Some(group) = state.groups.remove(&key) {
+                if group.summary.count > 0 {
+                    state.finished.push(group.summary);
+                }
+            }
+        }
+        std::mem::take(&mut state.finished)
+    }
+
+    // Everything still open, e.g. at shutdown
+    pub fn take_all(&self) -> Vec<FileAccessSummary> {
+        let Ok(mut state) = self.state.lock() else { return Vec::new() };
+        let open: Vec<FileAccessSummary> = state.groups.drain()
+            .map(|(_, group)| group.summary)
+            .filter(|summary| summary.count > 0)
+            .collect();
+        let mut summaries = std::mem::take(&mut state.finished);
+        summaries.extend(open);
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(pid: u32, path: &str, verdict: Verdict) -> SecurityEvent {
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::FileAccess {
+                target_path: PathBuf::from(path),
+                access_type: FileAccessType::Read,
+            },
+            process_info: ProcessInfo {
+                pid,
+                path: PathBuf::from("/usr/bin/find"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict,
+            policy_reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_file_access_aggregation() {
+        let aggregator = FileAccessAggregator::new(AggregationConfig {
+            max_samples: 2,
+            rules: vec![AggregationRule { prefix: PathBuf::from("/etc"), window_secs: 0 }],
+            ..AggregationConfig::default()
+        });
+        let start = Instant::now();
+
+        // The first read is reported, the rest are folded into a summary
+        assert!(aggregator.offer_at(access(1, "/usr/lib/a.so", Verdict::Log), start).is_some());
+        for name in ["b", "c", "d"] {
+            assert!(aggregator.offer_at(access(1, &format!("/usr/lib/{}.so", name), Verdict::Log), start).is_none());
+        }
+        // Another process, a denial and an unaggregated prefix are reported as is
+        assert!(aggregator.offer_at(access(2, "/usr/lib/a.so", Verdict::Log), start).is_some());
+        assert!(aggregator.offer_at(access(1, "/usr/lib/e.so", Verdict::Deny), start).is_some());
+        assert!(aggregator.offer_at(access(1, "/etc/passwd", Verdict::Log), start).is_some());
+        assert!(aggregator.offer_at(access(1, "/etc/shadow", Verdict::Log), start).is_some());
+
+        assert!(aggregator.take_due_at(start).is_empty());
+        let summaries = aggregator.take_due_at(start + Duration::from_secs(5));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].count, 3);
+        assert_eq!(summaries[0].directory, PathBuf::from("/usr/lib"));
+        assert_eq!(summaries[0].sample_paths, vec![PathBuf::from("/usr/lib/b.so"), PathBuf::from("/usr/lib/c.so")]);
+        assert!(summaries[0].to_security_event().policy_reason.starts_with("Aggregated 3 more read accesses in /usr/lib"));
+
+        // A new window starts with a reported event again
+        assert!(aggregator.offer_at(access(1, "/usr/lib/f.so", Verdict::Log), start + Duration::from_secs(6)).is_some());
+        assert!(aggregator.take_all().is_empty());
+    }
+}
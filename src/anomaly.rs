@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::monitor::{SecurityEvent, SecurityEventType};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineConfig {
+    // How long each executable is observed before deviations are scored
+    #[serde(default = "default_training_hours")]
+    pub training_hours: u64,
+    // Profiles with fewer observations keep learning past the training period
+    #[serde(default = "default_min_observations")]
+    pub min_observations: u64,
+    // Deviations scoring below this are learned silently
+    #[serde(default = "default_min_score")]
+    pub min_score: f64,
+    #[serde(default)]
+    pub state_path: Option<PathBuf>,
+}
+
+fn default_training_hours() -> u64 {
+    72
+}
+
+fn default_min_observations() -> u64 {
+    20
+}
+
+fn default_min_score() -> f64 {
+    0.3
+}
+
+impl Default for BaselineConfig {
+    fn default() -> Self {
+        Self {
+            training_hours: default_training_hours(),
+            min_observations: default_min_observations(),
+            min_score: default_min_score(),
+            state_path: None,
+        }
+    }
+}
+
+// What one executable has been seen doing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessProfile {
+    pub executable: PathBuf,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub observations: u64,
+    pub children: HashMap<PathBuf, u64>,
+    pub ports: HashMap<u16, u64>,
+    // File accesses are tracked by parent directory to keep profiles stable
+    pub directories: HashMap<PathBuf, u64>,
+}
+
+impl ProcessProfile {
+    fn new(executable: PathBuf, now: DateTime<Utc>) -> Self {
+        Self {
+            executable,
+            first_seen: now,
+            last_seen: now,
+            observations: 0,
+            children: HashMap::new(),
+            ports: HashMap::new(),
+            directories: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Deviation {
+    NewChild { child: PathBuf, known_children: usize },
+    NewPort { port: u16, remote_ip: String, known_ports: usize },
+    NewDirectory { path: PathBuf, known_directories: usize },
+}
+
+impl Deviation {
+    fn weight(&self) -> f64 {
+        match self {
+            Deviation::NewChild { .. } => 0.6,
+            Deviation::NewPort { .. } => 0.5,
+            Deviation::NewDirectory { .. } => 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnomalyEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub executable: PathBuf,
+    pub pid: u32,
+    // 0.0 - 1.0
+    pub score: f64,
+    pub deviations: Vec<Deviation>,
+    pub source_event_id: String,
+    pub profile_observations: u64,
+}
+
+// Learns per-executable behavior from security events and reports deviations
+// once an executable's training period is over
+pub struct BehaviorBaseline {
+    config: BaselineConfig,
+    profiles: Arc<RwLock<HashMap<PathBuf, ProcessProfile>>>,
+    anomaly_handler: Arc<dyn Fn(AnomalyEvent) + Send + Sync>,
+}
+
+impl BehaviorBaseline {
+    pub fn new<F>(config: BaselineConfig, anomaly_handler: F) -> Result<Self>
+    where
+        F: Fn(AnomalyEvent) + Send + Sync + 'static,
+    {
+        let profiles = match &config.state_path {
+            Some(path) if path.exists() => Self::load_profiles(path)?,
+            _ => HashMap::new(),
+        };
+
+        Ok(Self {
+            config,
+            profiles: Arc::new(RwLock::new(profiles)),
+            anomaly_handler: Arc::new(anomaly_handler),
+        })
+    }
+
+    fn load_profiles(path: &Path) -> Result<HashMap<PathBuf, ProcessProfile>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read behavior baseline {:?}", path))?;
+        let profiles: Vec<ProcessProfile> = serde_json::from_str(&content)?;
+        info!("Loaded {} process profiles from {:?}", profiles.len(), path);
+        Ok(profiles.into_iter().map(|p| (p.executable.clone(), p)).collect())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.config.state_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let profiles: Vec<ProcessProfile> = self.profiles.read()
+            .map_err(|_| anyhow!("Failed to acquire profiles read lock"))?
+            .values()
+            .cloned()
+            .collect();
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(&profiles)?)?;
+        info!("Saved {} process profiles to {:?}", profiles.len(), path);
+        Ok(())
+    }
+
+    pub fn profile(&self, executable: &Path) -> Option<ProcessProfile> {
+        self.profiles.read().ok()?.get(executable).cloned()
+    }
+
+    pub fn profile_count(&self) -> usize {
+        self.profiles.read().map(|p| p.len()).unwrap_or(0)
+    }
+
+    pub fn is_training(&self, profile: &ProcessProfile, now: DateTime<Utc>) -> bool {
+        now - profile.first_seen < Duration::hours(self.config.training_hours as i64)
+            || profile.observations < self.config.min_observations
+    }
+
+    // Ends the training period early for every known profile. Profiles below
+    // `min_observations` still keep learning.
+    pub fn finish_training(&self) -> Result<()> {
+        let cutoff = Utc::now() - Duration::hours(self.config.training_hours as i64);
+        let mut profiles = self.profiles.write()
+            .map_err(|_| anyhow!("Failed to acquire profiles write lock"))?;
+        for profile in profiles.values_mut() {
+            if profile.first_seen > cutoff {
+                profile.first_seen = cutoff;
+            }
+        }
+        Ok(())
+    }
+
+    // Records the event in its executable's profile. Returns and reports an anomaly
+    // when a trained profile sees something new; the new behavior is learned either
+    // way so it is reported only once.
+    pub fn observe(&self, event: &SecurityEvent) -> Option<AnomalyEvent> {
+        let executable = event.process_info.path.clone();
+        let now = event.timestamp;
+
+        let mut profiles = self.profiles.write().ok()?;
+        let profile = profiles.entry(executable.clone())
+            .or_insert_with(|| ProcessProfile::new(executable.clone(), now));
+        let training = self.is_training(profile, now);
+
+        let mut deviations = Vec::new();
+        match &event.event_type {
+            SecurityEventType::FileExecution { target_path, .. } => {
+                let known = profile.children.len();
+                let count = profile.children.entry(target_path.clone()).or_insert(0);
+                if *count == 0 && !training {
+                    deviations.push(Deviation::NewChild { child: target_path.clone(), known_children: known });
+                }
+                *count += 1;
+            }
+            SecurityEventType::NetworkConnection { remote_ip, remote_port, .. } => {
+                let known = profile.ports.len();
+                let count = profile.ports.entry(*remote_port).or_insert(0);
+                if *count == 0 && !training {
+                    deviations.push(Deviation::NewPort { port: *remote_port, remote_ip: remote_ip.clone(), known_ports: known });
+                }
+                *count += 1;
+            }
+            SecurityEventType::FileAccess { target_path, .. } => {
+                let directory = target_path.parent().unwrap_or(target_path).to_path_buf();
+                let known = profile.directories.len();
+                let count = profile.directories.entry(directory.clone()).or_insert(0);
+                if *count == 0 && !training {
+                    deviations.push(Deviation::NewDirectory { path: directory, known_directories: known });
+                }
+                *count += 1;
+            }
+            _ => return None,
+        }
+
+        profile.observations += 1;
+        profile.last_seen = now;
+        let observations = profile.observations;
+        drop(profiles);
+
+        // Independent signals combine as 1 - Π(1 - w)
+        let score = 1.0 - deviations.iter().map(|d| 1.0 - d.weight()).product::<f64>();
+        if deviations.is_empty() || score < self.config.min_score {
+            return None;
+        }
+
+        let anomaly = AnomalyEvent {
+            id: Uuid::new_v4().to_string(),
+            timestamp: now,
+            executable,
+            pid: event.process_info.pid,
+            score,
+            deviations,
+            source_event_id: event.id.clone(),
+            profile_observations: observations,
+        };
+
+        warn!("Behavior anomaly for {:?} (score {:.2}): {:?}", anomaly.executable, anomaly.score, anomaly.deviations);
+        (self.anomaly_handler)(anomaly.clone());
+        Some(anomaly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{NetworkProtocol, ProcessInfo, Verdict};
+
+    fn event(event_type: SecurityEventType, timestamp: DateTime<Utc>) -> SecurityEvent {
+        SecurityEvent {
+            id: Uuid::new_v4().to_string(),
+            timestamp,
+            event_type,
+            process_info: ProcessInfo {
+                pid: 42,
+                path: PathBuf::from("/usr/sbin/nginx"),
+                parent_pid: Some(1),
+                user_id: 33,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        }
+    }
+
+    fn connection(port: u16) -> SecurityEventType {
+        SecurityEventType::NetworkConnection {
+            remote_ip: "203.0.113.5".to_string(),
+            remote_port: port,
+            domain: None,
+            protocol: NetworkProtocol::Tcp,
+        }
+    }
+
+    #[test]
+    fn test_learns_then_flags_deviations() {
+        let config = BaselineConfig { training_hours: 1, min_observations: 3, ..Default::default() };
+        let baseline = BehaviorBaseline::new(config, |_| {}).unwrap();
+        let start: DateTime<Utc> = "2024-03-05T10:00:00Z".parse().unwrap();
+
+        for i in 0..5 {
+            assert!(baseline.observe(&event(connection(443), start + Duration::minutes(i))).is_none());
+        }
+        // Still inside the training window
+        assert!(baseline.observe(&event(connection(8080), start + Duration::minutes(30))).is_none());
+
+        let later = start + Duration::hours(2);
+        assert!(baseline.observe(&event(connection(443), later)).is_none());
+        assert!(baseline.observe(&event(connection(8080), later)).is_none());
+
+        let anomaly = baseline.observe(&event(connection(4444), later)).unwrap();
+        assert_eq!(anomaly.deviations, vec![Deviation::NewPort { port: 4444, remote_ip: "203.0.113.5".to_string(), known_ports: 2 }]);
+        assert!((anomaly.score - 0.5).abs() < 1e-9);
+        // Learned, so not reported again
+        assert!(baseline.observe(&event(connection(4444), later)).is_none());
+
+        let child = SecurityEventType::FileExecution { target_path: PathBuf::from("/bin/sh"), file_hash: None, code_signature: None };
+        assert!(matches!(baseline.observe(&event(child, later)).unwrap().deviations[0], Deviation::NewChild { .. }));
+    }
+}
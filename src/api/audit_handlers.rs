@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditEntry, ChainVerification};
+
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+pub async fn get_audit_entries(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<ApiResponse<Vec<AuditEntry>>>, StatusCode> {
+    let audit_log = Arc::clone(state.audit_log.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    let result = tokio::task::spawn_blocking(move || audit_log.tail(limit))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(entries) => Ok(Json(ApiResponse::success(entries))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+// Re-hashes the whole chain; `valid` is false from the first entry that was
+// altered, removed or reordered
+pub async fn verify_audit_log(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<ChainVerification>>, StatusCode> {
+    let audit_log = Arc::clone(state.audit_log.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let result = tokio::task::spawn_blocking(move || audit_log.verify())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(verification) => Ok(Json(ApiResponse::success(verification))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Query, State, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::capture::{CaptureInfo, CaptureRequest, CaptureStatus};
+
+#[derive(Debug, Deserialize)]
+pub struct CaptureQuery {
+    pub incident_id: Option<String>,
+}
+
+pub async fn start_capture(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CaptureRequest>,
+) -> Result<Json<ApiResponse<CaptureInfo>>, StatusCode> {
+    match state.captures.start(request) {
+        Ok(info) => Ok(Json(ApiResponse::success(info))),
+        Err(e) => Ok(Json(ApiResponse::error(e.to_string()))),
+    }
+}
+
+pub async fn get_captures(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CaptureQuery>,
+) -> Result<Json<ApiResponse<Vec<CaptureInfo>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(state.captures.list(query.incident_id.as_deref()))))
+}
+
+pub async fn get_capture(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<CaptureInfo>>, StatusCode> {
+    state.captures.get(&id)
+        .map(|info| Json(ApiResponse::success(info)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn stop_capture(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    match state.captures.stop(&id) {
+        Ok(()) => Ok(Json(ApiResponse::success(format!("Capture {} stopping", id)))),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Serves the .pcap once the capture has finished
+pub async fn download_capture(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let info = state.captures.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    if info.status == CaptureStatus::Running {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let data = tokio::fs::read(&info.path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let disposition = format!("attachment; filename=\"{}-{}.pcap\"", info.incident_id, info.id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.tcpdump.pcap".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    ).into_response())
+}
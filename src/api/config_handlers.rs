@@ -0,0 +1,38 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::config::ReloadReport;
+
+// Same as sending SIGHUP: re-reads the config file, applies the fields that
+// can change at runtime and lists the ones that need a restart
+pub async fn reload_config(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<ReloadReport>>, StatusCode> {
+    let config = Arc::clone(state.config.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let result = tokio::task::spawn_blocking(move || config.reload())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(report) => {
+            if !report.is_unchanged() {
+                let target = report.path.as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| "config".to_string());
+                let mut reason = format!("applied: {}", report.applied.join(", "));
+                if !report.restart_required.is_empty() {
+                    reason.push_str(&format!("; restart required: {}", report.restart_required.join(", ")));
+                }
+                state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "reload_config", target, reason));
+            }
+            Ok(Json(ApiResponse::success(report)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -0,0 +1,32 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::linux_security::CorrelationRule;
+use crate::linux_security::event_correlation::RuleReloadSummary;
+
+pub async fn get_correlation_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<CorrelationRule>>>, StatusCode> {
+    let correlator = state.correlator.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(correlator.rules())))
+}
+
+// Re-reads the rules directory; invalid files leave the running rules untouched
+pub async fn reload_correlation_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<RuleReloadSummary>>, StatusCode> {
+    let correlator = Arc::clone(state.correlator.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let result = tokio::task::spawn_blocking(move || correlator.reload_rules())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(summary) => Ok(Json(ApiResponse::success(summary))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
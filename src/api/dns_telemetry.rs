@@ -0,0 +1,200 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use tracing::{info, error};
+use uuid::Uuid;
+
+use crate::api::handlers::AppState;
+use crate::api::models::DnsQuery;
+use crate::linux_security::dns::{query_type_name, rcode_name};
+use crate::linux_security::dns_filter::{DnsAction, DnsEvent, DnsFilter, DnsQueryType};
+use crate::linux_security::{FilterAction, NetworkEvent};
+use crate::socket_index::{SocketIndex, SocketProtocol};
+
+// Retained in AppState, newest first
+const MAX_DNS_QUERIES: usize = 1000;
+// How long a captured query waits for its response
+const ANSWER_WINDOW_SECS: i64 = 10;
+
+// Turns DNS filter and packet capture events into the records behind
+// /api/network/dns
+#[derive(Clone)]
+pub struct DnsRecorder {
+    queries: Arc<Mutex<Vec<DnsQuery>>>,
+    sockets: SocketIndex,
+}
+
+impl DnsRecorder {
+    pub fn new(queries: Arc<Mutex<Vec<DnsQuery>>>, sockets: SocketIndex) -> Self {
+        Self { queries, sockets }
+    }
+
+    // The proxy sees query and response together
+    pub fn record_filter_event(&self, event: &DnsEvent) {
+        let status = match event.action {
+            DnsAction::Allowed => "allowed",
+            DnsAction::Blocked => "blocked",
+            DnsAction::Cached => "cached",
+            DnsAction::Error => "error",
+        };
+        let query_type = match &event.query_type {
+            DnsQueryType::A => "A".to_string(),
+            DnsQueryType::AAAA => "AAAA".to_string(),
+            DnsQueryType::MX => "MX".to_string(),
+            DnsQueryType::TXT => "TXT".to_string(),
+            DnsQueryType::CNAME => "CNAME".to_string(),
+            DnsQueryType::Other(other) => other.parse().map(query_type_name).unwrap_or_else(|_| other.clone()),
+        };
+        let mut query = self.new_query(event.timestamp, &event.domain, query_type, event.source, status);
+        query.response = event.rcode.map(rcode_name);
+        query.answers = event.answers.iter().map(ToString::to_string).collect();
+        query.reason = event.reason.clone();
+        self.push(query);
+    }
+
+    // Captured queries are recorded as they leave and completed when the
+    // matching response comes back
+    pub fn record_network_event(&self, event: &NetworkEvent) {
+        match event {
+            NetworkEvent::DnsQuery { timestamp, domain, query_type, source, action } => {
+                let status = match action {
+                    FilterAction::Allow => "allowed",
+                    FilterAction::Block => "blocked",
+                    FilterAction::Log => "logged",
+                };
+                self.push(self.new_query(*timestamp, domain, query_type.clone(), SocketAddr::from(*source), status));
+            }
+            NetworkEvent::DnsAnswer { timestamp, domain, answers, rcode, client, .. } => {
+                let cutoff = wall_clock(*timestamp) - Duration::seconds(ANSWER_WINDOW_SECS);
+                let client_ip = client.0.to_string();
+                let mut queries = self.queries.lock().unwrap();
+                let pending = queries.iter_mut()
+                    .take_while(|query| query.timestamp >= cutoff)
+                    .find(|query| {
+                        query.response.is_none()
+                            && query.source_port == Some(client.1)
+                            && query.source_ip == client_ip
+                            && query.domain.eq_ignore_ascii_case(domain)
+                    });
+                if let Some(query) = pending {
+                    query.response = Some(rcode_name(*rcode));
+                    query.answers = answers.iter().map(ToString::to_string).collect();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&self, query: DnsQuery) {
+        let mut queries = self.queries.lock().unwrap();
+        queries.insert(0, query);
+        queries.truncate(MAX_DNS_QUERIES);
+    }
+
+    // The querying process is looked up from the local UDP socket. Resolvers
+    // often close theirs right after the answer, so this is best effort.
+    fn new_query(&self, timestamp: Instant, domain: &str, query_type: String, source: SocketAddr, status: &str) -> DnsQuery {
+        let process = self.sockets.owner_of(SocketProtocol::Udp, source);
+        DnsQuery {
+            id: Uuid::new_v4().to_string(),
+            timestamp: wall_clock(timestamp),
+            domain: domain.to_string(),
+            query_type,
+            source_ip: source.ip().to_string(),
+            source_port: Some(source.port()),
+            status: status.to_string(),
+            response: None,
+            answers: Vec::new(),
+            reason: None,
+            process: process.as_ref().map(|owner| owner.name.clone()),
+            pid: process.map(|owner| owner.pid),
+        }
+    }
+}
+
+fn wall_clock(instant: Instant) -> DateTime<Utc> {
+    Utc::now() - Duration::from_std(instant.elapsed()).unwrap_or_else(|_| Duration::zero())
+}
+
+// With FLUX_DNS_PROXY=1, runs the filtering DNS proxy and records what it
+// answers. Captured queries are recorded by traffic::start_traffic_capture.
+pub fn start_dns_telemetry(state: &AppState) -> Result<()> {
+    if !std::env::var("FLUX_DNS_PROXY").is_ok_and(|value| value == "1") {
+        return Ok(());
+    }
+    let recorder = DnsRecorder::new(Arc::clone(&state.dns_queries), state.socket_index.clone());
+    let filter = DnsFilter::new()?;
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<DnsEvent>(1024);
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            recorder.record_filter_event(&event);
+        }
+    });
+    tokio::spawn(async move {
+        if let Err(e) = filter.start_dns_proxy(tx).await {
+            error!("DNS proxy stopped: {:#}", e);
+        }
+    });
+    info!("Recording DNS queries answered by the filtering proxy");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_records() {
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let recorder = DnsRecorder::new(Arc::clone(&queries), SocketIndex::new());
+
+        // A query from a socket this process holds is attributed to it
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let source = socket.local_addr().unwrap();
+        recorder.record_filter_event(&DnsEvent {
+            timestamp: Instant::now(),
+            query_type: DnsQueryType::Other("28".to_string()),
+            domain: "example.com".to_string(),
+            source,
+            action: DnsAction::Allowed,
+            reason: None,
+            answers: vec!["2606:2800:220:1::1".parse().unwrap()],
+            rcode: Some(0),
+        });
+        let query = queries.lock().unwrap()[0].clone();
+        assert_eq!((query.query_type.as_str(), query.status.as_str()), ("AAAA", "allowed"));
+        assert_eq!(query.response.as_deref(), Some("NOERROR"));
+        assert_eq!(query.answers, vec!["2606:2800:220:1::1"]);
+        assert_eq!(query.pid, Some(std::process::id()));
+
+        // Captured queries pick up the response sent back to the same port
+        let client: std::net::IpAddr = "10.0.0.5".parse().unwrap();
+        recorder.record_network_event(&NetworkEvent::DnsQuery {
+            timestamp: Instant::now(),
+            domain: "evil.test".to_string(),
+            query_type: "A".to_string(),
+            source: (client, 40512),
+            action: FilterAction::Log,
+        });
+        let answer = |port| NetworkEvent::DnsAnswer {
+            timestamp: Instant::now(),
+            domain: "EVIL.test".to_string(),
+            query_type: "A".to_string(),
+            answers: vec!["203.0.113.7".parse().unwrap()],
+            rcode: 0,
+            resolver: "1.1.1.1".parse().unwrap(),
+            client: (client, port),
+        };
+        recorder.record_network_event(&answer(40513));
+        assert!(queries.lock().unwrap()[0].response.is_none());
+        recorder.record_network_event(&answer(40512));
+
+        let queries = queries.lock().unwrap();
+        assert_eq!(queries.len(), 2);
+        assert_eq!((queries[0].status.as_str(), queries[0].source_port), ("logged", Some(40512)));
+        assert_eq!(queries[0].answers, vec!["203.0.113.7"]);
+        assert_eq!(queries[0].response.as_deref(), Some("NOERROR"));
+    }
+}
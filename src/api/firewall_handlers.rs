@@ -0,0 +1,102 @@
+use axum::{
+    extract::{State, Path},
+    http::StatusCode,
+    response::Json,
+};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::linux_security::{BulkLoadSummary, NetfilterManager, TempBan};
+use crate::audit_log::{AuditKind, AuditRecord};
+
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    pub ip: IpAddr,
+    // Omitted for a ban that lasts until it is lifted
+    pub minutes: Option<u64>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlocklistRequest {
+    pub ips: Vec<String>,
+    pub ttl_minutes: Option<u64>,
+}
+
+fn firewall(state: &AppState) -> Result<Arc<Mutex<NetfilterManager>>, StatusCode> {
+    state.firewall.as_ref().map(Arc::clone).ok_or(StatusCode::NOT_FOUND)
+}
+
+fn minutes(minutes: Option<u64>) -> Option<Duration> {
+    minutes.map(|minutes| Duration::from_secs(minutes.saturating_mul(60)))
+}
+
+// Runs a netfilter operation off the async runtime, as it talks to the kernel
+async fn with_firewall<T, F>(state: &AppState, op: F) -> Result<Json<ApiResponse<T>>, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut NetfilterManager) -> anyhow::Result<T> + Send + 'static,
+{
+    let firewall = firewall(state)?;
+    let result = tokio::task::spawn_blocking(move || {
+        let mut firewall = firewall.lock().map_err(|_| anyhow::anyhow!("Firewall lock poisoned"))?;
+        op(&mut firewall)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(value) => Ok(Json(ApiResponse::success(value))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+pub async fn get_bans(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<TempBan>>>, StatusCode> {
+    let firewall = firewall(&state)?;
+    let bans = firewall.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.list_bans();
+    Ok(Json(ApiResponse::success(bans)))
+}
+
+pub async fn ban_ip(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BanRequest>,
+) -> Result<Json<ApiResponse<TempBan>>, StatusCode> {
+    let ttl = minutes(request.minutes);
+    let record = AuditRecord::new(AuditKind::ResponseAction, "api", "ban", request.ip.to_string(), request.reason.clone());
+    let response = with_firewall(&state, move |firewall| firewall.ban_ip(request.ip, ttl, &request.reason)).await?;
+    if response.success {
+        state.audit(record);
+    }
+    Ok(response)
+}
+
+pub async fn unban_ip(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<IpAddr>,
+) -> Result<Json<ApiResponse<bool>>, StatusCode> {
+    let response = with_firewall(&state, move |firewall| firewall.unban_ip(ip)).await?;
+    if response.data == Some(true) {
+        state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "unban", ip.to_string(), "Ban lifted"));
+    }
+    Ok(response)
+}
+
+// Bulk-loads threat intel addresses without tracking them as individual bans
+pub async fn load_blocklist(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BlocklistRequest>,
+) -> Result<Json<ApiResponse<BulkLoadSummary>>, StatusCode> {
+    let ttl = minutes(request.ttl_minutes);
+    let response = with_firewall(&state, move |firewall| firewall.block_ips(&request.ips, ttl)).await?;
+    if let Some(ref summary) = response.data {
+        state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "block_list", "block sets",
+            format!("Loaded {} IPv4 and {} IPv6 addresses", summary.loaded_v4, summary.loaded_v6)));
+    }
+    Ok(response)
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Query, State, Path},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::api::tenancy::{caller_tenant, Caller};
+use crate::fleet::{AgentSummary, FleetEvent, FleetServer, PolicyUpdate, PROTOCOL_VERSION};
+use crate::fleet::protocol::{
+    EnrollRequest, EnrollResponse, EventBatch, EventBatchAck, Heartbeat, HeartbeatResponse,
+};
+use crate::policy::{CandidatePolicy, SignedPolicy};
+
+#[derive(Debug, Deserialize)]
+pub struct FleetEventQuery {
+    // Agent id or hostname
+    pub host: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FleetPolicyQuery {
+    // Admins address a tenant's policy with this; tenant tokens always get their own
+    pub tenant: Option<String>,
+}
+
+fn fleet_server(state: &AppState) -> Result<&Arc<FleetServer>, StatusCode> {
+    state.fleet.as_ref().ok_or(StatusCode::NOT_FOUND)
+}
+
+fn authenticated_agent(fleet: &FleetServer, headers: &HeaderMap) -> Result<String, StatusCode> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| fleet.authenticate(value))
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+// Agent-facing endpoints
+
+pub async fn fleet_enroll(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<EnrollRequest>,
+) -> Result<Json<ApiResponse<EnrollResponse>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    if request.protocol_version != PROTOCOL_VERSION {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match fleet.enroll(request) {
+        Ok(response) => Ok(Json(ApiResponse::success(response))),
+        Err(_) => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+pub async fn fleet_heartbeat(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(heartbeat): Json<Heartbeat>,
+) -> Result<Json<ApiResponse<HeartbeatResponse>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    let agent_id = authenticated_agent(fleet, &headers)?;
+
+    match fleet.heartbeat(&agent_id, heartbeat) {
+        Ok(response) => Ok(Json(ApiResponse::success(response))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Heartbeat failed: {}", e)))),
+    }
+}
+
+pub async fn fleet_ingest_events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(batch): Json<EventBatch>,
+) -> Result<Json<ApiResponse<EventBatchAck>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    let agent_id = authenticated_agent(fleet, &headers)?;
+    if batch.protocol_version != PROTOCOL_VERSION {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let events = batch.events.clone();
+    match fleet.ingest(&agent_id, batch) {
+        Ok(ack) => {
+            if !ack.duplicate {
+                for event in &events {
+                    state.incidents.record_event(Some(&agent_id), event);
+                }
+            }
+            Ok(Json(ApiResponse::success(ack)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("Event ingestion failed: {}", e)))),
+    }
+}
+
+// Aggregated views
+
+pub async fn get_fleet_agents(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+) -> Result<Json<ApiResponse<Vec<AgentSummary>>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    Ok(Json(ApiResponse::success(fleet.agents(caller_tenant(&caller)))))
+}
+
+pub async fn get_fleet_agent(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(agent_id): Path<String>,
+) -> Result<Json<ApiResponse<AgentSummary>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    match fleet.agent(caller_tenant(&caller), &agent_id) {
+        Some(agent) => Ok(Json(ApiResponse::success(agent))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+pub async fn delete_fleet_agent(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(agent_id): Path<String>,
+) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    if fleet.remove_agent(caller_tenant(&caller), &agent_id) {
+        Ok(Json(ApiResponse::success(format!("Agent {} removed", agent_id))))
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+pub async fn get_fleet_events(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<FleetEventQuery>,
+) -> Result<Json<ApiResponse<Vec<FleetEvent>>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    let limit = query.limit.unwrap_or(100).min(10_000);
+    Ok(Json(ApiResponse::success(fleet.events(caller_tenant(&caller), query.host.as_deref(), limit))))
+}
+
+fn policy_tenant<'a>(caller: &'a Option<Extension<Caller>>, query: &'a FleetPolicyQuery) -> Option<&'a str> {
+    caller_tenant(caller).or(query.tenant.as_deref())
+}
+
+pub async fn get_fleet_policy(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<FleetPolicyQuery>,
+) -> Result<Json<ApiResponse<Option<PolicyUpdate>>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    Ok(Json(ApiResponse::success(fleet.policy(policy_tenant(&caller, &query)))))
+}
+
+pub async fn update_fleet_policy(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<FleetPolicyQuery>,
+    Json(policy): Json<CandidatePolicy>,
+) -> Result<Json<ApiResponse<PolicyUpdate>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    if policy.file_policy.is_none() && policy.network_policy.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Agents configured for signing would refuse it
+    if state.policy_verifier.is_some() {
+        return Ok(Json(ApiResponse::error("Policy signing is enabled; use PUT /api/fleet/policy/signed".to_string())));
+    }
+
+    match fleet.set_policy(policy_tenant(&caller, &query), policy) {
+        Ok(update) => Ok(Json(ApiResponse::success(update))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Invalid fleet policy: {:#}", e)))),
+    }
+}
+
+pub async fn update_signed_fleet_policy(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<FleetPolicyQuery>,
+    Json(signed): Json<SignedPolicy>,
+) -> Result<Json<ApiResponse<PolicyUpdate>>, StatusCode> {
+    let fleet = fleet_server(&state)?;
+    if let Some(verifier) = state.policy_verifier.as_ref() {
+        if let Err(e) = signed.verify(verifier) {
+            return Ok(Json(ApiResponse::error(format!("Invalid fleet policy: {:#}", e))));
+        }
+    }
+
+    match fleet.set_signed_policy(policy_tenant(&caller, &query), signed) {
+        Ok(update) => Ok(Json(ApiResponse::success(update))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Invalid fleet policy: {:#}", e)))),
+    }
+}
@@ -1,10 +1,11 @@
 use axum::{
     extract::{Query, State, Path},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::Json,
 };
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
@@ -12,13 +13,31 @@ use crate::api::models::{
     ApiResponse, HealthCheck, SystemStatus, ThreatMetrics, NetworkMetrics,
     SecurityEvent, NetworkConnection, DnsQuery, ThreatDetection, MalwareSignature,
     LogEntry, LogLevel, LogCategory, LiveEvent, AllSettings, SecuritySettings, NetworkSettings,
-    NotificationSettings, SystemSettings, EventQuery, LogQuery,
+    NotificationSettings, SystemSettings, EventQuery, LogQuery, MetricsHistoryQuery, MetricsSeries, UnitUsageQuery,
     ProcessInfo, ProcessStats,
 };
 use crate::api::system_monitor::SystemMonitor;
+use crate::api::metrics_history::MetricsHistory;
+use crate::cgroup_metrics::{CgroupSampler, WorkloadUsage};
+use crate::api::pagination::{ListOptions, ListQuery, Page};
+use crate::fleet::FleetServer;
+use crate::capture::CaptureManager;
+use crate::incidents::IncidentManager;
+use crate::audit_log::{AuditLog, AuditRecord};
+use crate::config::ReloadableConfig;
+use crate::health::{HealthRegistry, OverallStatus};
+use crate::policy::{FilePolicy, NetworkPolicy};
+use crate::scanner::ScanProgress;
+use crate::socket_index::SocketIndex;
+use crate::privacy::Pseudonymizer;
+use sha2::{Digest, Sha256};
 
 pub struct AppState {
     pub system_monitor: Arc<Mutex<SystemMonitor>>,
+    // Per-second usage samples and their minute and hour averages
+    pub metrics_history: Arc<Mutex<MetricsHistory>>,
+    // Last cgroup readings, which unit CPU and I/O rates are computed against
+    pub cgroup_sampler: Arc<Mutex<CgroupSampler>>,
     pub security_events: Arc<Mutex<Vec<SecurityEvent>>>,
     pub network_connections: Arc<Mutex<Vec<NetworkConnection>>>,
     pub dns_queries: Arc<Mutex<Vec<DnsQuery>>>,
@@ -30,6 +49,73 @@ pub struct AppState {
     pub process_stats: Arc<Mutex<ProcessStats>>,
     pub settings: Arc<Mutex<AllSettings>>,
     pub start_time: DateTime<Utc>,
+    // Set when this instance aggregates a fleet of agents
+    pub fleet: Option<Arc<FleetServer>>,
+    pub captures: Arc<CaptureManager>,
+    pub incidents: Arc<IncidentManager>,
+    // Hash-chained log of policy changes and response actions taken through the API
+    pub audit_log: Option<Arc<AuditLog>>,
+    // Config file reloadable through the API and SIGHUP
+    pub config: Option<Arc<ReloadableConfig>>,
+    // Subsystem status behind /api/health and /api/ready
+    pub health: Arc<HealthRegistry>,
+    // Enforced file and network policies, replaced through PUT /api/policies/active;
+    // share them with PassiveMonitor::shared_policies when both run in one process
+    pub file_policy: Arc<RwLock<FilePolicy>>,
+    pub network_policy: Arc<RwLock<NetworkPolicy>>,
+    // Progress of on-demand scans started through POST /api/scan
+    pub scan_progress: tokio::sync::broadcast::Sender<ScanProgress>,
+    // Records ingested from journald or the auth log, as they arrive
+    pub log_stream: tokio::sync::broadcast::Sender<LogEntry>,
+    // Socket inode -> process, shared with the packet capture
+    pub socket_index: SocketIndex,
+    // Per-address and per-token request limits; None serves every request
+    pub rate_limiter: Option<Arc<crate::api::rate_limit::ApiRateLimiter>>,
+    // Recurring scans, baseline reconciliation and intel refreshes
+    pub scheduler: Arc<crate::scheduler::Scheduler>,
+    // IOC sync with a MISP instance
+    pub misp: Option<Arc<crate::misp::MispConnector>>,
+    // Interactive logins and the processes started from them
+    pub sessions: Option<Arc<crate::sessions::SessionMonitor>>,
+    // Privacy mode: the pseudonym vault behind re-identification
+    pub privacy: Option<Arc<Pseudonymizer>>,
+    // SHA-256 digests of the tokens admin-only endpoints accept; empty
+    // rejects every request to them
+    pub admin_tokens: Vec<String>,
+    // Tenants and their API tokens; when set, every API request needs an
+    // admin or tenant token and tenant tokens only see their own data
+    pub tenants: Option<Arc<crate::api::tenancy::TenantDirectory>>,
+    // Set when policies must be signed; unsigned replacements are refused
+    pub policy_verifier: Option<Arc<crate::policy::PolicyVerifier>>,
+    // Every change to the enforced policies, for diffs and rollbacks
+    pub policy_history: Option<Arc<crate::policy::PolicyHistory>>,
+    // Hits of rules in shadow mode, shared with the engines that run them
+    pub shadow: crate::shadow::ShadowLog,
+    // Match counters of the rules of every engine, for the unused-rule report
+    pub rule_stats: crate::rule_stats::RuleStats,
+    // What the host allowed at startup and what runs degraded because of it
+    pub capabilities: crate::preflight::CapabilityReport,
+    // Correlation engine whose rules can be listed and reloaded
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub correlator: Option<Arc<crate::linux_security::EventCorrelator>>,
+    // Ancestor/descendant trees for the process endpoints
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub process_trees: Option<Arc<crate::linux_security::ProcessTreeSource>>,
+    // nftables manager with the block sets enabled, for temporary bans
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub firewall: Option<Arc<Mutex<crate::linux_security::NetfilterManager>>>,
+    // Connections seen by the packet capture, for byte and packet counters
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub connection_table: Option<crate::linux_security::ConnectionTable>,
+    // ARP bindings, DHCP servers and LAN alerts from the packet capture
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub lan: Option<Arc<crate::linux_security::LanMonitor>>,
+    // Response playbooks that can be managed, run and rolled back
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub playbooks: Option<Arc<crate::linux_security::PlaybookEngine>>,
+    // Incident-response host isolation through the firewall
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    pub isolation: Option<Arc<crate::linux_security::HostIsolation>>,
 }
 
 impl AppState {
@@ -91,6 +177,8 @@ impl AppState {
 
         Self {
             system_monitor: Arc::new(Mutex::new(SystemMonitor::new())),
+            metrics_history: Arc::new(Mutex::new(MetricsHistory::new())),
+            cgroup_sampler: Arc::new(Mutex::new(CgroupSampler::new())),
             security_events: Arc::new(Mutex::new(Vec::new())),
             network_connections: Arc::new(Mutex::new(Vec::new())),
             dns_queries: Arc::new(Mutex::new(Vec::new())),
@@ -102,28 +190,149 @@ impl AppState {
             process_stats: Arc::new(Mutex::new(initial_stats)),
             settings: Arc::new(Mutex::new(settings)),
             start_time: Utc::now(),
+            fleet: None,
+            captures: Arc::new(CaptureManager::new(PathBuf::from("/var/lib/fluxdefense/captures"))),
+            incidents: Arc::new(IncidentManager::default()),
+            audit_log: None,
+            config: None,
+            health: Arc::new(HealthRegistry::new()),
+            file_policy: Arc::new(RwLock::new(FilePolicy::default())),
+            network_policy: Arc::new(RwLock::new(NetworkPolicy::default())),
+            scan_progress: tokio::sync::broadcast::channel(64).0,
+            log_stream: tokio::sync::broadcast::channel(256).0,
+            socket_index: SocketIndex::new(),
+            rate_limiter: None,
+            scheduler: Arc::new(crate::scheduler::Scheduler::new()),
+            misp: None,
+            sessions: None,
+            privacy: None,
+            admin_tokens: Vec::new(),
+            tenants: None,
+            policy_verifier: None,
+            policy_history: None,
+            shadow: crate::shadow::ShadowLog::new(),
+            rule_stats: crate::rule_stats::RuleStats::new(),
+            capabilities: crate::preflight::CapabilityReport::default(),
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            correlator: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            process_trees: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            firewall: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            connection_table: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            lan: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            playbooks: None,
+            #[cfg(all(target_os = "linux", feature = "pcap"))]
+            isolation: None,
         }
     }
+    
+    // The socket table with live counters from the packet capture, when one runs
+    pub fn network_connections(&self) -> Vec<NetworkConnection> {
+        let connections = self.network_connections.lock().unwrap().clone();
+        #[cfg(all(target_os = "linux", feature = "pcap"))]
+        let connections = {
+            let mut connections = connections;
+            if let Some(ref table) = self.connection_table {
+                crate::api::traffic::apply_flow_counters(&mut connections, &table.flows());
+            }
+            connections
+        };
+        connections
+    }
+    
+    // No-op unless the server was started with an audit log
+    pub fn audit(&self, record: AuditRecord) {
+        if let Some(ref audit_log) = self.audit_log {
+            audit_log.record(record);
+        }
+    }
+    
+    pub fn set_admin_tokens(&mut self, tokens: &[String]) {
+        self.admin_tokens = tokens.iter().filter(|token| !token.is_empty()).map(|token| token_digest(token)).collect();
+    }
+    
+    // Checks `Authorization: Bearer <token>` against the admin tokens;
+    // returns a short digest of the token to name the caller in audit records
+    pub fn require_admin(&self, headers: &HeaderMap) -> Result<String, StatusCode> {
+        let token = headers.get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        if !self.is_admin_token(token) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(format!("admin:{}", &token_digest(token)[..12]))
+    }
+    
+    // Who made a change, for records that outlive the request; tokens are
+    // identified by a digest prefix
+    pub fn request_author(&self, headers: &HeaderMap) -> String {
+        let token = headers.get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if self.is_admin_token(token) => format!("admin:{}", &token_digest(token)[..12]),
+            Some(token) => format!("token:{}", &token_digest(token)[..12]),
+            None => "api".to_string(),
+        }
+    }
+    
+    // Digests are compared, so timing reveals nothing about the token itself
+    pub fn is_admin_token(&self, token: &str) -> bool {
+        self.admin_tokens.contains(&token_digest(token))
+    }
+}
+
+pub(crate) fn token_digest(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
 // Health Check
-pub async fn health_check(State(state): State<Arc<AppState>>) -> Json<ApiResponse<HealthCheck>> {
+// Liveness: 503 once a critical subsystem is down
+pub async fn health_check(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ApiResponse<HealthCheck>>) {
+    let health = health_report(&state);
+    let code = if health.status == OverallStatus::Unhealthy.as_str() { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    (code, Json(ApiResponse::success(health)))
+}
+
+// Readiness: 503 until every critical subsystem is up
+pub async fn readiness_check(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ApiResponse<HealthCheck>>) {
+    let health = health_report(&state);
+    let code = if health.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(ApiResponse::success(health)))
+}
+
+pub async fn get_capabilities(State(state): State<Arc<AppState>>) -> Json<ApiResponse<crate::preflight::CapabilityReport>> {
+    Json(ApiResponse::success(state.capabilities.clone()))
+}
+
+fn health_report(state: &AppState) -> HealthCheck {
     let uptime = (Utc::now() - state.start_time).num_seconds() as u64;
-    
-    let mut services = HashMap::new();
-    services.insert("system_monitor".to_string(), "active".to_string());
-    services.insert("security_monitor".to_string(), "active".to_string());
-    services.insert("network_monitor".to_string(), "active".to_string());
-    services.insert("threat_detector".to_string(), "active".to_string());
-
-    let health = HealthCheck {
-        status: "healthy".to_string(),
+    let report = state.health.report();
+
+    let services = report.components.iter()
+        .map(|component| (component.name.clone(), component.state.as_str().to_string()))
+        .collect();
+    // Without a monitor reporting events, fall back to the newest one held here
+    let last_event_at = report.last_event_at.or_else(|| {
+        state.security_events.lock().ok()
+            .and_then(|events| events.iter().map(|event| event.timestamp).max())
+    });
+
+    HealthCheck {
+        status: report.status.as_str().to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         uptime,
         services,
-    };
-
-    Json(ApiResponse::success(health))
+        ready: report.ready,
+        components: report.components,
+        event_queue_depth: report.event_queue_depth,
+        last_event_at,
+    }
 }
 
 // Dashboard Overview
@@ -164,7 +373,7 @@ pub async fn get_threat_metrics(State(state): State<Arc<AppState>>) -> Json<ApiR
 }
 
 pub async fn get_network_metrics(State(state): State<Arc<AppState>>) -> Json<ApiResponse<NetworkMetrics>> {
-    let connections = state.network_connections.lock().unwrap();
+    let connections = state.network_connections();
     let dns_queries = state.dns_queries.lock().unwrap();
     
     let active_connections = connections.iter().filter(|c| c.status == "active").count() as u32;
@@ -193,19 +402,65 @@ pub async fn get_system_metrics(State(state): State<Arc<AppState>>) -> Json<ApiR
     Json(ApiResponse::success(metrics))
 }
 
+pub async fn get_metrics_history(
+    Query(query): Query<MetricsHistoryQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<MetricsSeries>>, (StatusCode, String)> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - chrono::Duration::hours(1));
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "`from` is after `to`".to_string()));
+    }
+    let series = state.metrics_history.lock().unwrap().series(from, to, query.resolution);
+    Ok(Json(ApiResponse::success(series)))
+}
+
+// Systemd units and containers using the most of a resource. Rates cover
+// the time since the previous request, or a short first interval.
+pub async fn get_top_units(
+    Query(query): Query<UnitUsageQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<WorkloadUsage>>>, (StatusCode, String)> {
+    let unavailable = |e: anyhow::Error| (StatusCode::SERVICE_UNAVAILABLE, e.to_string());
+    let primed = state.cgroup_sampler.lock().unwrap().has_previous();
+    if !primed {
+        state.cgroup_sampler.lock().unwrap().sample().map_err(unavailable)?;
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    let mut units = state.cgroup_sampler.lock().unwrap().sample().map_err(unavailable)?;
+    query.sort.sort(&mut units);
+    units.truncate(query.limit.unwrap_or(10));
+    Ok(Json(ApiResponse::success(units)))
+}
+
 pub async fn get_system_resources(State(state): State<Arc<AppState>>) -> Json<ApiResponse<crate::api::models::SystemResources>> {
     let mut monitor = state.system_monitor.lock().unwrap();
     let resources = monitor.get_system_resources();
     Json(ApiResponse::success(resources))
 }
 
+const EVENT_LIST: ListOptions = ListOptions::new("-timestamp", "id", 50);
+const CONNECTION_LIST: ListOptions = ListOptions::new("-timestamp", "id", 100);
+const DNS_LIST: ListOptions = ListOptions::new("-timestamp", "id", 100);
+const LOG_LIST: ListOptions = ListOptions::new("-timestamp", "id", 100);
+const PROCESS_LIST: ListOptions = ListOptions::new("pid", "pid", 500);
+
+pub(crate) type ListResult<T> = Result<Json<ApiResponse<Vec<T>>>, (StatusCode, String)>;
+
+// One page of a list endpoint; a malformed filter or cursor is a 400
+pub(crate) fn list_page<T: serde::Serialize>(items: Vec<T>, list: &ListQuery, options: ListOptions) -> ListResult<T> {
+    list.apply(items, options)
+        .map(|page: Page<T>| Json(ApiResponse::page(page)))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
 // Security Events
 pub async fn get_security_events(
     Query(query): Query<EventQuery>,
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<SecurityEvent>>> {
-    let events = state.security_events.lock().unwrap();
-    let mut filtered_events: Vec<SecurityEvent> = events.iter().cloned().collect();
+) -> ListResult<SecurityEvent> {
+    let mut filtered_events: Vec<SecurityEvent> = state.security_events.lock().unwrap().clone();
     
     // Apply filters
     if let Some(severity) = &query.severity {
@@ -221,20 +476,7 @@ pub async fn get_security_events(
         filtered_events.retain(|e| e.timestamp <= until);
     }
     
-    // Sort by timestamp (newest first)
-    filtered_events.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    
-    // Apply pagination
-    let offset = query.offset.unwrap_or(0) as usize;
-    let limit = query.limit.unwrap_or(50) as usize;
-    
-    let paginated: Vec<SecurityEvent> = filtered_events
-        .into_iter()
-        .skip(offset)
-        .take(limit)
-        .collect();
-    
-    Json(ApiResponse::success(paginated))
+    list_page(filtered_events, &list, EVENT_LIST)
 }
 
 pub async fn get_security_event(
@@ -252,25 +494,18 @@ pub async fn get_security_event(
 
 // Network Monitoring
 pub async fn get_network_connections(
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<NetworkConnection>>> {
-    let connections = state.network_connections.lock().unwrap();
-    let mut sorted: Vec<NetworkConnection> = connections.iter().cloned().collect();
-    sorted.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    sorted.truncate(100); // Limit to recent connections
-    
-    Json(ApiResponse::success(sorted))
+) -> ListResult<NetworkConnection> {
+    list_page(state.network_connections(), &list, CONNECTION_LIST)
 }
 
 pub async fn get_dns_queries(
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<DnsQuery>>> {
-    let queries = state.dns_queries.lock().unwrap();
-    let mut sorted: Vec<DnsQuery> = queries.iter().cloned().collect();
-    sorted.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    sorted.truncate(100);
-    
-    Json(ApiResponse::success(sorted))
+) -> ListResult<DnsQuery> {
+    let queries = state.dns_queries.lock().unwrap().clone();
+    list_page(queries, &list, DNS_LIST)
 }
 
 // Threat Detection
@@ -296,10 +531,10 @@ pub async fn get_malware_signatures(
 // Event Logs
 pub async fn get_event_logs(
     Query(query): Query<LogQuery>,
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<LogEntry>>> {
-    let logs = state.log_entries.lock().unwrap();
-    let mut filtered_logs: Vec<LogEntry> = logs.iter().cloned().collect();
+) -> ListResult<LogEntry> {
+    let mut filtered_logs: Vec<LogEntry> = state.log_entries.lock().unwrap().clone();
     
     // Apply filters
     if let Some(level_str) = &query.level {
@@ -329,20 +564,7 @@ pub async fn get_event_logs(
         filtered_logs.retain(|l| l.timestamp <= until);
     }
     
-    // Sort by timestamp (newest first)
-    filtered_logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    
-    // Apply pagination
-    let offset = query.offset.unwrap_or(0) as usize;
-    let limit = query.limit.unwrap_or(100) as usize;
-    
-    let paginated: Vec<LogEntry> = filtered_logs
-        .into_iter()
-        .skip(offset)
-        .take(limit)
-        .collect();
-    
-    Json(ApiResponse::success(paginated))
+    list_page(filtered_logs, &list, LOG_LIST)
 }
 
 // Live Events
@@ -392,10 +614,11 @@ pub async fn update_security_settings(
 
 // Process Management
 pub async fn get_processes(
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<ProcessInfo>>> {
-    let processes = state.processes.lock().unwrap();
-    Json(ApiResponse::success(processes.clone()))
+) -> ListResult<ProcessInfo> {
+    let processes = state.processes.lock().unwrap().clone();
+    list_page(processes, &list, PROCESS_LIST)
 }
 
 pub async fn get_process_stats(
@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Query, State, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::api::tenancy::{caller_tenant, incident_in_scope, Caller};
+use crate::incidents::{Incident, IncidentStatus};
+use crate::incident_report::IncidentReport;
+use crate::stix;
+
+// Audit entries searched for an incident's response actions
+const REPORT_AUDIT_LIMIT: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentQuery {
+    pub status: Option<IncidentStatus>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Html,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentStatusUpdate {
+    pub status: IncidentStatus,
+    pub note: Option<String>,
+}
+
+// Incidents the caller may see, as if the others did not exist
+fn scoped_incidents(state: &AppState, caller: &Option<Extension<Caller>>, status: Option<IncidentStatus>) -> Vec<Incident> {
+    let tenant = caller_tenant(caller);
+    state.incidents.list(status).into_iter()
+        .filter(|incident| incident_in_scope(state, tenant, incident))
+        .collect()
+}
+
+fn scoped_incident(state: &AppState, caller: &Option<Extension<Caller>>, id: &str) -> Result<Incident, StatusCode> {
+    state.incidents.get(id)
+        .filter(|incident| incident_in_scope(state, caller_tenant(caller), incident))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn get_incidents(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<IncidentQuery>,
+) -> Result<Json<ApiResponse<Vec<Incident>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(scoped_incidents(&state, &caller, query.status))))
+}
+
+pub async fn get_incident(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Incident>>, StatusCode> {
+    scoped_incident(&state, &caller, &id).map(|incident| Json(ApiResponse::success(incident)))
+}
+
+pub async fn update_incident_status(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<String>,
+    Json(update): Json<IncidentStatusUpdate>,
+) -> Result<Json<ApiResponse<Incident>>, StatusCode> {
+    scoped_incident(&state, &caller, &id)?;
+    match state.incidents.update_status(&id, update.status, update.note) {
+        Ok(incident) => Ok(Json(ApiResponse::success(incident))),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// The incident with its events, process tree, connections, detections and
+// responses; `?format=html` returns a standalone page
+pub async fn get_incident_report(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<String>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Response, StatusCode> {
+    let incident = scoped_incident(&state, &caller, &id)?;
+    let events = state.incidents.events(&id);
+    let audit = match state.audit_log.clone() {
+        // An unreadable audit log only leaves the responses section short
+        Some(audit_log) => tokio::task::spawn_blocking(move || audit_log.tail(REPORT_AUDIT_LIMIT))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let report = IncidentReport::build(incident, events, &audit);
+    Ok(match query.format {
+        ReportFormat::Json => Json(ApiResponse::success(report)).into_response(),
+        ReportFormat::Html => ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], report.to_html()).into_response(),
+    })
+}
+
+fn stix_download(bundle: serde_json::Value, name: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, stix::STIX_MEDIA_TYPE.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.stix.json\"", name)),
+        ],
+        bundle.to_string(),
+    ).into_response()
+}
+
+// STIX 2.1 bundle of one incident's indicators and observables
+pub async fn get_incident_stix(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let incident = scoped_incident(&state, &caller, &id)?;
+    let events = state.incidents.events(&id);
+    Ok(stix_download(stix::export_bundle(&[(incident, events)]), &format!("incident-{}", id)))
+}
+
+// Every incident, or those with the given status, as one bundle
+pub async fn get_stix_bundle(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<IncidentQuery>,
+) -> Result<Response, StatusCode> {
+    let incidents: Vec<_> = scoped_incidents(&state, &caller, query.status).into_iter()
+        .map(|incident| {
+            let events = state.incidents.events(&incident.id);
+            (incident, events)
+        })
+        .collect();
+    Ok(stix_download(stix::export_bundle(&incidents), "fluxdefense"))
+}
+
+// Streams an artifact such as a forensic snapshot bundle
+pub async fn download_incident_artifact(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path((id, artifact_id)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+    scoped_incident(&state, &caller, &id)?;
+    let artifact = state.incidents.artifact(&id, &artifact_id).ok_or(StatusCode::NOT_FOUND)?;
+    let data = tokio::fs::read(&artifact.path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let name = artifact.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or(artifact.id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        data,
+    ).into_response())
+}
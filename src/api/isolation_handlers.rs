@@ -0,0 +1,75 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::linux_security::{HostIsolation, IsolationStatus};
+
+#[derive(Debug, Deserialize)]
+pub struct IsolateRequest {
+    // Until released when 0; the configured default when missing
+    pub minutes: Option<u64>,
+    #[serde(default)]
+    pub reason: String,
+    // Addresses or CIDRs to keep reachable on top of the management plane
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+fn isolation(state: &AppState) -> Result<Arc<HostIsolation>, StatusCode> {
+    state.isolation.as_ref().map(Arc::clone).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn get_isolation(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<IsolationStatus>>, StatusCode> {
+    Ok(Json(ApiResponse::success(isolation(&state)?.status())))
+}
+
+pub async fn isolate_host(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<IsolateRequest>,
+) -> Result<Json<ApiResponse<IsolationStatus>>, StatusCode> {
+    let isolation = isolation(&state)?;
+    let reason = if request.reason.is_empty() { "Isolated from the API".to_string() } else { request.reason };
+    let duration = request.minutes.map(|minutes| Duration::from_secs(minutes * 60));
+    let audit_reason = reason.clone();
+    // Fleet server names are resolved before the rules go in
+    let result = tokio::task::spawn_blocking(move || isolation.isolate(duration, &reason, &request.allow))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(status) => {
+            let until = status.expires_at.map_or("until released".to_string(), |expires_at| format!("until {}", expires_at));
+            state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "isolate_host", "host",
+                format!("{} ({})", audit_reason, until)));
+            Ok(Json(ApiResponse::success(status)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+pub async fn release_isolation(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<bool>>, StatusCode> {
+    let isolation = isolation(&state)?;
+    let result = tokio::task::spawn_blocking(move || isolation.release())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(released) => {
+            if released {
+                state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "release_isolation", "host", "Isolation lifted"));
+            }
+            Ok(Json(ApiResponse::success(released)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
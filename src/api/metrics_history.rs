@@ -0,0 +1,319 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::api::handlers::AppState;
+use crate::api::models::{MetricsPoint, MetricsResolution, MetricsSeries};
+
+// One hour of seconds, a day of minutes and 30 days of hours
+const SECOND_POINTS: usize = 3600;
+const MINUTE_POINTS: usize = 24 * 60;
+const HOUR_POINTS: usize = 30 * 24;
+// How often the minute and hour tiers are written to FLUX_METRICS_HISTORY
+const SAVE_INTERVAL_SECS: u64 = 60;
+
+impl MetricsResolution {
+    pub fn seconds(self) -> i64 {
+        match self {
+            MetricsResolution::Second => 1,
+            MetricsResolution::Minute => 60,
+            MetricsResolution::Hour => 3600,
+        }
+    }
+
+    // Finest resolution that keeps a chart of the span readable
+    pub fn for_span(span: Duration) -> Self {
+        if span <= Duration::minutes(15) {
+            MetricsResolution::Second
+        } else if span <= Duration::hours(24) {
+            MetricsResolution::Minute
+        } else {
+            MetricsResolution::Hour
+        }
+    }
+
+    fn bucket(self, timestamp: DateTime<Utc>) -> i64 {
+        timestamp.timestamp().div_euclid(self.seconds()) * self.seconds()
+    }
+}
+
+// Points of one resolution, plus the finer points of the bucket still open
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tier {
+    resolution: MetricsResolution,
+    capacity: usize,
+    points: VecDeque<MetricsPoint>,
+    #[serde(default)]
+    pending: Vec<MetricsPoint>,
+}
+
+impl Tier {
+    fn new(resolution: MetricsResolution, capacity: usize) -> Self {
+        Self { resolution, capacity, points: VecDeque::new(), pending: Vec::new() }
+    }
+
+    // Adds a finer point; returns the bucket it closed, if any
+    fn add(&mut self, point: &MetricsPoint) -> Option<MetricsPoint> {
+        let bucket = self.resolution.bucket(point.timestamp);
+        let closed = match self.pending.first() {
+            Some(first) if self.resolution.bucket(first.timestamp) != bucket => self.close(),
+            _ => None,
+        };
+        self.pending.push(point.clone());
+        closed
+    }
+
+    fn close(&mut self) -> Option<MetricsPoint> {
+        let point = self.open_point()?;
+        self.pending.clear();
+        self.points.push_back(point.clone());
+        while self.points.len() > self.capacity {
+            self.points.pop_front();
+        }
+        Some(point)
+    }
+
+    // The bucket still filling, averaged so far
+    fn open_point(&self) -> Option<MetricsPoint> {
+        let first = self.pending.first()?;
+        let start = Utc.timestamp_opt(self.resolution.bucket(first.timestamp), 0).single()?;
+        Some(average(start, &self.pending))
+    }
+
+    fn oldest(&self) -> Option<DateTime<Utc>> {
+        self.points.front().or(self.pending.first()).map(|point| point.timestamp)
+    }
+}
+
+fn average(timestamp: DateTime<Utc>, points: &[MetricsPoint]) -> MetricsPoint {
+    let samples: u32 = points.iter().map(|point| point.samples.max(1)).sum();
+    let weighted = |value: fn(&MetricsPoint) -> f64| {
+        points.iter().map(|point| value(point) * f64::from(point.samples.max(1))).sum::<f64>() / f64::from(samples.max(1))
+    };
+    MetricsPoint {
+        timestamp,
+        cpu_usage: weighted(|point| f64::from(point.cpu_usage)) as f32,
+        memory_usage: weighted(|point| f64::from(point.memory_usage)) as f32,
+        disk_usage: weighted(|point| f64::from(point.disk_usage)) as f32,
+        load_average: weighted(|point| point.load_average),
+        network_rx_rate: weighted(|point| point.network_rx_rate),
+        network_tx_rate: weighted(|point| point.network_tx_rate),
+        samples,
+    }
+}
+
+// Ring buffers of per-second samples, downsampled into minutes and hours as
+// each bucket closes
+#[derive(Debug, Clone)]
+pub struct MetricsHistory {
+    tiers: [Tier; 3],
+}
+
+// What survives a restart; a gap in the seconds tier is not worth the writes
+#[derive(Serialize, Deserialize)]
+struct SavedHistory {
+    minutes: Tier,
+    hours: Tier,
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self {
+            tiers: [
+                Tier::new(MetricsResolution::Second, SECOND_POINTS),
+                Tier::new(MetricsResolution::Minute, MINUTE_POINTS),
+                Tier::new(MetricsResolution::Hour, HOUR_POINTS),
+            ],
+        }
+    }
+}
+
+impl MetricsHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, sample: MetricsPoint) {
+        let mut point = sample;
+        for tier in &mut self.tiers {
+            match tier.add(&point) {
+                Some(closed) => point = closed,
+                None => break,
+            }
+        }
+    }
+
+    // Points overlapping [from, to], including the bucket still filling
+    pub fn query(&self, from: DateTime<Utc>, to: DateTime<Utc>, resolution: MetricsResolution) -> Vec<MetricsPoint> {
+        let tier = self.tier(resolution);
+        let step = Duration::seconds(resolution.seconds());
+        tier.points.iter()
+            .cloned()
+            .chain(tier.open_point())
+            .filter(|point| point.timestamp + step > from && point.timestamp <= to)
+            .collect()
+    }
+
+    // The resolution asked for, or the finest one whose retention reaches
+    // back to `from` and whose point count suits the span
+    pub fn series(&self, from: DateTime<Utc>, to: DateTime<Utc>, resolution: Option<MetricsResolution>) -> MetricsSeries {
+        let resolution = resolution.unwrap_or_else(|| {
+            let mut resolution = MetricsResolution::for_span(to - from);
+            while resolution != MetricsResolution::Hour && self.tier(resolution).oldest().is_some_and(|oldest| oldest > from) {
+                resolution = match resolution {
+                    MetricsResolution::Second => MetricsResolution::Minute,
+                    _ => MetricsResolution::Hour,
+                };
+            }
+            resolution
+        });
+        MetricsSeries { resolution, from, to, points: self.query(from, to, resolution) }
+    }
+
+    fn tier(&self, resolution: MetricsResolution) -> &Tier {
+        &self.tiers[match resolution {
+            MetricsResolution::Second => 0,
+            MetricsResolution::Minute => 1,
+            MetricsResolution::Hour => 2,
+        }]
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read metrics history {:?}", path))?;
+        let saved: SavedHistory = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse metrics history {:?}", path))?;
+        let mut history = Self::new();
+        history.tiers[1].points = saved.minutes.points;
+        history.tiers[1].pending = saved.minutes.pending;
+        history.tiers[2].points = saved.hours.points;
+        history.tiers[2].pending = saved.hours.pending;
+        Ok(history)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let saved = SavedHistory { minutes: self.tiers[1].clone(), hours: self.tiers[2].clone() };
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, serde_json::to_vec(&saved)?)
+            .with_context(|| format!("Failed to write metrics history {:?}", temp))?;
+        std::fs::rename(&temp, path).with_context(|| format!("Failed to replace metrics history {:?}", path))?;
+        Ok(())
+    }
+}
+
+// Samples usage once a second into AppState::metrics_history. With
+// FLUX_METRICS_HISTORY set, minutes and hours are kept in that file across
+// restarts.
+pub fn spawn_metrics_history(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let path = std::env::var("FLUX_METRICS_HISTORY").ok().map(PathBuf::from);
+    if let Some(ref path) = path {
+        if path.exists() {
+            match MetricsHistory::load(path) {
+                Ok(history) => {
+                    info!("Loaded metrics history from {}", path.display());
+                    *state.metrics_history.lock().unwrap() = history;
+                }
+                Err(e) => warn!("Starting metrics history afresh: {:#}", e),
+            }
+        }
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut previous: Option<(Instant, u64, u64)> = None;
+        let mut saved_at = Instant::now();
+        loop {
+            ticker.tick().await;
+            let (metrics, rx_total, tx_total) = state.system_monitor.lock().unwrap().sample_usage();
+            let now = Instant::now();
+            // Counters can go backwards when an interface disappears
+            let rate = |total: u64, before: u64, elapsed: f64| total.saturating_sub(before) as f64 / elapsed;
+            let (network_rx_rate, network_tx_rate) = match previous {
+                Some((at, rx, tx)) => {
+                    let elapsed = now.duration_since(at).as_secs_f64().max(0.001);
+                    (rate(rx_total, rx, elapsed), rate(tx_total, tx, elapsed))
+                }
+                None => (0.0, 0.0),
+            };
+            previous = Some((now, rx_total, tx_total));
+
+            let mut history = state.metrics_history.lock().unwrap();
+            history.record(MetricsPoint {
+                timestamp: Utc::now(),
+                cpu_usage: metrics.cpu_usage,
+                memory_usage: metrics.memory_usage,
+                disk_usage: metrics.disk_usage,
+                load_average: metrics.load_average.first().copied().unwrap_or(0.0),
+                network_rx_rate,
+                network_tx_rate,
+                samples: 1,
+            });
+            if let Some(ref path) = path {
+                if saved_at.elapsed().as_secs() >= SAVE_INTERVAL_SECS {
+                    saved_at = Instant::now();
+                    if let Err(e) = history.save(path) {
+                        warn!("{:#}", e);
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_downsampling() {
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let mut history = MetricsHistory::new();
+        // Two hours of samples with CPU rising by one every minute
+        for second in 0..7200 {
+            history.record(MetricsPoint {
+                timestamp: start + Duration::seconds(second),
+                cpu_usage: (second / 60) as f32,
+                memory_usage: 50.0,
+                disk_usage: 10.0,
+                load_average: 1.0,
+                network_rx_rate: if second % 2 == 0 { 100.0 } else { 300.0 },
+                network_tx_rate: 0.0,
+                samples: 1,
+            });
+        }
+        let end = start + Duration::seconds(7199);
+
+        let seconds = history.query(end - Duration::seconds(9), end, MetricsResolution::Second);
+        assert_eq!(seconds.len(), 10);
+        assert_eq!(seconds.last().unwrap().timestamp, end);
+
+        let minutes = history.query(start, start + Duration::minutes(2), MetricsResolution::Minute);
+        assert_eq!(minutes.len(), 3);
+        assert_eq!((minutes[1].cpu_usage, minutes[1].samples, minutes[1].network_rx_rate), (1.0, 60, 200.0));
+
+        // The first hour is closed, the second still filling
+        let hours = history.query(start, end, MetricsResolution::Hour);
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].samples, 3600);
+        assert!((hours[0].cpu_usage - 29.5).abs() < 0.01);
+        assert_eq!(hours[1].samples, 3540);
+
+        // Seconds only reach back an hour, so a two-hour span uses minutes
+        let series = history.series(start, end, None);
+        assert_eq!(series.resolution, MetricsResolution::Minute);
+        assert_eq!(history.series(end - Duration::minutes(5), end, None).resolution, MetricsResolution::Second);
+
+        let path = std::env::temp_dir().join(format!("flux-metrics-{}.json", std::process::id()));
+        history.save(&path).unwrap();
+        let restored = MetricsHistory::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.query(start, end, MetricsResolution::Hour), hours);
+        assert!(restored.query(start, end, MetricsResolution::Second).is_empty());
+    }
+}
@@ -0,0 +1,60 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use std::sync::Arc;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::api::tenancy::{caller_tenant, Caller};
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::misp::{MispConnector, MispStatus};
+
+fn misp(state: &AppState) -> Result<Arc<MispConnector>, StatusCode> {
+    state.misp.as_ref().map(Arc::clone).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn get_misp_status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<MispStatus>>, StatusCode> {
+    Ok(Json(ApiResponse::success(misp(&state)?.status())))
+}
+
+// Pulls the configured MISP events now instead of waiting for the schedule
+pub async fn sync_misp(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<MispStatus>>, StatusCode> {
+    match misp(&state)?.pull().await {
+        Ok(status) => {
+            state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "misp_pull", "blocklists",
+                format!("{} hashes, {} domains, {} addresses from MISP", status.hashes, status.domains, status.ips)));
+            Ok(Json(ApiResponse::success(status)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+// Shares an incident's IOCs with MISP whatever its status, short of a false positive
+pub async fn push_incident_to_misp(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<usize>>, StatusCode> {
+    let misp = misp(&state)?;
+    // The MISP connection is shared by every tenant, so only admins publish to it
+    if caller_tenant(&caller).is_some() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let incident = state.incidents.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let events = state.incidents.events(&id);
+    match misp.push_incident(&incident, &events).await {
+        Ok(added) => {
+            state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "misp_push", &id,
+                format!("{} IOCs shared with MISP", added)));
+            Ok(Json(ApiResponse::success(added)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -1,11 +1,80 @@
 pub mod models;
+pub mod pagination;
 pub mod handlers;
 pub mod websocket;
+pub mod websocket_commands;
 pub mod system_monitor;
+pub mod metrics_history;
+pub mod system_logs;
 pub mod policy_handlers;
+pub mod policy_history_handlers;
+pub mod shadow_handlers;
+pub mod rule_stats_handlers;
+pub mod fleet_handlers;
+pub mod capture_handlers;
+pub mod incident_handlers;
+pub mod audit_handlers;
+pub mod config_handlers;
+pub mod scan_handlers;
+pub mod misp_handlers;
+pub mod scheduled_tasks;
+pub mod schedule_handlers;
+pub mod session_handlers;
+pub mod privacy_handlers;
+pub mod tenancy;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod correlation_handlers;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod process_tree_handlers;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod firewall_handlers;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod dns_telemetry;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod traffic;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod playbook_handlers;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub mod isolation_handlers;
+pub mod tls;
+pub mod rate_limit;
 
 pub use models::*;
+pub use pagination::{ListOptions, ListQuery, Page, Pagination};
 pub use handlers::*;
 pub use websocket::*;
 pub use system_monitor::*;
-pub use policy_handlers::*;
\ No newline at end of file
+pub use system_logs::{spawn_log_ingestion, LogSource};
+pub use metrics_history::{spawn_metrics_history, MetricsHistory};
+pub use policy_handlers::*;
+pub use policy_history_handlers::*;
+pub use shadow_handlers::*;
+pub use rule_stats_handlers::*;
+pub use fleet_handlers::*;
+pub use capture_handlers::*;
+pub use incident_handlers::*;
+pub use audit_handlers::*;
+pub use config_handlers::*;
+pub use scan_handlers::*;
+pub use misp_handlers::*;
+pub use scheduled_tasks::{schedule_tasks, ScheduleConfig, ScheduledTaskConfig, TaskKind};
+pub use schedule_handlers::*;
+pub use session_handlers::*;
+pub use privacy_handlers::*;
+pub use tenancy::{tenant_access, get_tenants, Caller, TenantDirectory, TenantsConfig};
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use correlation_handlers::*;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use process_tree_handlers::*;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use firewall_handlers::*;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use dns_telemetry::{start_dns_telemetry, DnsRecorder};
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use traffic::{apply_flow_counters, get_lan_status, get_network_beacons, start_traffic_capture};
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use playbook_handlers::*;
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+pub use isolation_handlers::*;
+pub use tls::TlsSettings;
+pub use rate_limit::{rate_limit, ApiRateLimitConfig, ApiRateLimiter};
\ No newline at end of file
@@ -36,6 +36,45 @@ pub struct SystemMetrics {
     pub disk_usage: f32,
     pub load_average: Vec<f64>,
     pub uptime: u64,
+    // Hardware detail, left empty by the lightweight per-second samples
+    #[serde(default)]
+    pub disks: Vec<crate::system_metrics::DiskMetrics>,
+    #[serde(default)]
+    pub temperatures: Vec<crate::system_metrics::TemperatureReading>,
+    #[serde(default)]
+    pub gpus: Vec<crate::system_metrics::GpuMetrics>,
+}
+
+// One point of /api/metrics/history: averages over `samples` one-second
+// samples starting at `timestamp`; network rates are bytes per second
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MetricsPoint {
+    pub timestamp: DateTime<Utc>,
+    pub cpu_usage: f32,
+    pub memory_usage: f32,
+    pub disk_usage: f32,
+    pub load_average: f64,
+    pub network_rx_rate: f64,
+    pub network_tx_rate: f64,
+    pub samples: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsResolution {
+    #[serde(rename = "1s")]
+    Second,
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "1h")]
+    Hour,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetricsSeries {
+    pub resolution: MetricsResolution,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub points: Vec<MetricsPoint>,
 }
 
 // Security Events Models
@@ -82,8 +121,21 @@ pub struct DnsQuery {
     pub domain: String,
     pub query_type: String,
     pub source_ip: String,
+    #[serde(default)]
+    pub source_port: Option<u16>,
+    // allowed, blocked, cached, logged or error
     pub status: String,
+    // Response code, e.g. NOERROR or NXDOMAIN, once an answer was seen
     pub response: Option<String>,
+    #[serde(default)]
+    pub answers: Vec<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    // Process that owned the querying socket, when it could still be found
+    #[serde(default)]
+    pub process: Option<String>,
+    #[serde(default)]
+    pub pid: Option<u32>,
 }
 
 // Activity Monitor Models
@@ -317,6 +369,9 @@ pub struct ApiResponse<T> {
     pub data: Option<T>,
     pub error: Option<String>,
     pub timestamp: DateTime<Utc>,
+    // Only on list endpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<crate::api::pagination::Pagination>,
 }
 
 impl<T> ApiResponse<T> {
@@ -326,6 +381,7 @@ impl<T> ApiResponse<T> {
             data: Some(data),
             error: None,
             timestamp: Utc::now(),
+            pagination: None,
         }
     }
 
@@ -335,6 +391,16 @@ impl<T> ApiResponse<T> {
             data: None,
             error: Some(error),
             timestamp: Utc::now(),
+            pagination: None,
+        }
+    }
+}
+
+impl<T> ApiResponse<Vec<T>> {
+    pub fn page(page: crate::api::pagination::Page<T>) -> Self {
+        Self {
+            pagination: Some(page.pagination),
+            ..Self::success(page.items)
         }
     }
 }
@@ -344,7 +410,12 @@ pub struct HealthCheck {
     pub status: String,
     pub version: String,
     pub uptime: u64,
+    // Component name to state, for older dashboards
     pub services: HashMap<String, String>,
+    pub ready: bool,
+    pub components: Vec<crate::health::ComponentStatus>,
+    pub event_queue_depth: Option<usize>,
+    pub last_event_at: Option<DateTime<Utc>>,
 }
 
 // WebSocket Message Types
@@ -357,24 +428,73 @@ pub enum WebSocketMessage {
     SystemMetrics { data: SystemMetrics },
     ThreatDetection { data: ThreatDetection },
     LogEntry { data: LogEntry },
+    Incident { data: crate::incidents::Incident },
+    ScanProgress { data: crate::scanner::ScanProgress },
     Heartbeat { timestamp: DateTime<Utc> },
+    // Reply to a client command; `request_id` echoes the one sent with it
+    CommandResult {
+        request_id: Option<String>,
+        command: String,
+        success: bool,
+        data: Option<serde_json::Value>,
+        error: Option<CommandError>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandErrorCode {
+    InvalidMessage,
+    UnknownCommand,
+    InvalidArgument,
+    NotFound,
+    InvalidState,
+    Internal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommandError {
+    pub code: CommandErrorCode,
+    pub message: String,
 }
 
-// Query Parameters
+// Current figures sent in answer to a `snapshot` command
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatsSnapshot {
+    pub system: SystemMetrics,
+    pub processes: ProcessStats,
+    pub network_connections: usize,
+    pub open_incidents: usize,
+    pub threat_detections: usize,
+}
+
+// Query Parameters; paging, sorting and generic filters come from ListQuery
 #[derive(Debug, Deserialize)]
 pub struct EventQuery {
-    pub limit: Option<u32>,
-    pub offset: Option<u32>,
     pub severity: Option<String>,
     pub event_type: Option<String>,
     pub since: Option<DateTime<Utc>>,
     pub until: Option<DateTime<Utc>>,
 }
 
+// Defaults to the last hour, at a resolution picked from the span
+#[derive(Debug, Deserialize)]
+pub struct MetricsHistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub resolution: Option<MetricsResolution>,
+}
+
+// Top `limit` (default 10) systemd units and containers by `sort`
+#[derive(Debug, Deserialize)]
+pub struct UnitUsageQuery {
+    #[serde(default)]
+    pub sort: crate::cgroup_metrics::UnitSort,
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LogQuery {
-    pub limit: Option<u32>,
-    pub offset: Option<u32>,
     pub level: Option<String>,
     pub category: Option<String>,
     pub search: Option<String>,
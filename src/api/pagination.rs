@@ -0,0 +1,284 @@
+use std::cmp::Ordering;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const MAX_PER_PAGE: usize = 1000;
+
+// Common list parameters:
+//   ?page=2&per_page=50         numbered pages, starting at 1
+//   ?cursor=...                 the page after `next_cursor` of the previous one
+//   ?sort=-timestamp            field to sort by, `-` for descending
+//   ?filter=severity:high,process~ssh
+//                               `field:value` matches exactly, `field~value` as
+//                               a substring, both case-insensitive; nested
+//                               fields use dots (details.rule)
+// `limit` and `offset` are accepted as older spellings of per_page and page.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ListQuery {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub filter: Option<String>,
+}
+
+// Per-endpoint defaults
+#[derive(Debug, Clone, Copy)]
+pub struct ListOptions {
+    pub default_sort: &'static str,
+    // Unique field that breaks ties in the sort order and anchors cursors
+    pub key: &'static str,
+    pub default_per_page: usize,
+}
+
+impl ListOptions {
+    pub const fn new(default_sort: &'static str, key: &'static str, default_per_page: usize) -> Self {
+        Self { default_sort, key, default_per_page }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pagination {
+    // Items matching the filter, across all pages
+    pub total: usize,
+    pub per_page: usize,
+    // None when the page was requested by cursor
+    pub page: Option<usize>,
+    pub pages: usize,
+    pub sort: String,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub pagination: Pagination,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Equals(String, String),
+    Contains(String, String),
+}
+
+impl Condition {
+    fn parse(term: &str) -> Result<Self, String> {
+        let split = term.find([':', '~']).ok_or_else(|| format!("Filter term '{}' needs field:value or field~value", term))?;
+        let (field, value) = (term[..split].trim(), term[split + 1..].trim().to_lowercase());
+        if field.is_empty() {
+            return Err(format!("Filter term '{}' has no field", term));
+        }
+        Ok(if term.as_bytes()[split] == b':' {
+            Condition::Equals(field.to_string(), value)
+        } else {
+            Condition::Contains(field.to_string(), value)
+        })
+    }
+
+    fn matches(&self, item: &Value) -> bool {
+        let (field, value) = match self {
+            Condition::Equals(field, value) | Condition::Contains(field, value) => (field, value),
+        };
+        let Some(actual) = lookup(item, field).and_then(text) else {
+            return false;
+        };
+        let actual = actual.to_lowercase();
+        match self {
+            Condition::Equals(..) => actual == *value,
+            Condition::Contains(..) => actual.contains(value.as_str()),
+        }
+    }
+}
+
+impl ListQuery {
+    // Filters, sorts and cuts one page out of `items`; the error is a message
+    // for a malformed filter or cursor
+    pub fn apply<T: Serialize>(&self, items: Vec<T>, options: ListOptions) -> Result<Page<T>, String> {
+        let conditions = self.filter.as_deref()
+            .map(|filter| {
+                filter.split(',')
+                    .filter(|term| !term.trim().is_empty())
+                    .map(Condition::parse)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let sort = self.sort.as_deref().filter(|sort| !sort.is_empty()).unwrap_or(options.default_sort);
+        let (field, descending) = match sort.strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (sort, false),
+        };
+
+        // Items are compared through their JSON form, so any serialized field
+        // can be filtered and sorted on
+        let mut rows: Vec<(Value, Value, T)> = Vec::with_capacity(items.len());
+        for item in items {
+            let value = serde_json::to_value(&item).map_err(|e| e.to_string())?;
+            if conditions.iter().all(|condition| condition.matches(&value)) {
+                let sort_value = lookup(&value, field).cloned().unwrap_or(Value::Null);
+                let key = lookup(&value, options.key).cloned().unwrap_or(Value::Null);
+                rows.push((sort_value, key, item));
+            }
+        }
+        let order = |a: (&Value, &Value), b: (&Value, &Value)| {
+            let ordering = compare(a.0, b.0).then_with(|| compare(a.1, b.1));
+            if descending { ordering.reverse() } else { ordering }
+        };
+        rows.sort_by(|a, b| order((&a.0, &a.1), (&b.0, &b.1)));
+
+        let total = rows.len();
+        let per_page = self.per_page.or(self.limit).unwrap_or(options.default_per_page).clamp(1, MAX_PER_PAGE);
+        let (start, page) = match self.cursor.as_deref() {
+            Some(cursor) => {
+                let (sort_value, key) = decode_cursor(cursor)?;
+                let start = rows.partition_point(|row| order((&row.0, &row.1), (&sort_value, &key)) != Ordering::Greater);
+                (start, None)
+            }
+            None => match (self.page, self.offset) {
+                (Some(page), _) => {
+                    let page = page.max(1);
+                    ((page - 1).saturating_mul(per_page), Some(page))
+                }
+                (None, Some(offset)) => (offset, Some(offset / per_page + 1)),
+                (None, None) => (0, Some(1)),
+            },
+        };
+
+        let end = start.saturating_add(per_page).min(total);
+        let next_cursor = (end < total && end > 0).then(|| encode_cursor(&rows[end - 1].0, &rows[end - 1].1));
+        let items = rows.into_iter().skip(start).take(per_page).map(|(_, _, item)| item).collect();
+        Ok(Page {
+            items,
+            pagination: Pagination {
+                total,
+                per_page,
+                page,
+                pages: total.div_ceil(per_page),
+                sort: sort.to_string(),
+                next_cursor,
+            },
+        })
+    }
+}
+
+fn lookup<'a>(value: &'a Value, field: &str) -> Option<&'a Value> {
+    field.split('.').try_fold(value, |value, part| value.get(part))
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// Mixed types order by kind; timestamps compare by time, since their
+// serialized fractions vary in length
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => {
+            match (a.parse::<DateTime<Utc>>(), b.parse::<DateTime<Utc>>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            }
+        }
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+// The sort value and key of the last item served, so the next page starts
+// after it even if newer items arrived in between
+fn encode_cursor(sort_value: &Value, key: &Value) -> String {
+    hex::encode(Value::Array(vec![sort_value.clone(), key.clone()]).to_string())
+}
+
+fn decode_cursor(cursor: &str) -> Result<(Value, Value), String> {
+    let invalid = || "Invalid cursor".to_string();
+    let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+    match serde_json::from_slice(&bytes).map_err(|_| invalid())? {
+        Value::Array(mut parts) if parts.len() == 2 => {
+            let key = parts.pop().unwrap_or(Value::Null);
+            Ok((parts.pop().unwrap_or(Value::Null), key))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(params: &str) -> ListQuery {
+        let uri: axum::http::Uri = format!("/api/items?{}", params).parse().unwrap();
+        axum::extract::Query::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_filter_sort_and_paginate() {
+        let items: Vec<Value> = (1..=7)
+            .map(|i| json!({
+                "id": format!("evt-{}", i),
+                "severity": if i % 2 == 0 { "High" } else { "low" },
+                "timestamp": format!("2024-05-01T10:00:0{}Z", i),
+                "details": { "process": if i == 3 { "sshd" } else { "bash" } },
+            }))
+            .collect();
+        let options = ListOptions::new("-timestamp", "id", 2);
+        let ids = |page: &Page<Value>| -> Vec<String> {
+            page.items.iter().map(|item| item["id"].as_str().unwrap().to_string()).collect()
+        };
+
+        let first = query("").apply(items.clone(), options).unwrap();
+        assert_eq!(ids(&first), vec!["evt-7", "evt-6"]);
+        assert_eq!((first.pagination.total, first.pagination.pages, first.pagination.page), (7, 4, Some(1)));
+
+        let third = query("page=3&sort=timestamp").apply(items.clone(), options).unwrap();
+        assert_eq!(ids(&third), vec!["evt-5", "evt-6"]);
+        let legacy = query("limit=3&offset=3&sort=timestamp").apply(items.clone(), options).unwrap();
+        assert_eq!(ids(&legacy), vec!["evt-4", "evt-5", "evt-6"]);
+
+        let high = query("filter=severity:high&per_page=10").apply(items.clone(), options).unwrap();
+        assert_eq!(ids(&high), vec!["evt-6", "evt-4", "evt-2"]);
+        assert!(high.pagination.next_cursor.is_none());
+        let ssh = query("filter=details.process~SSH,severity:low").apply(items.clone(), options).unwrap();
+        assert_eq!(ids(&ssh), vec!["evt-3"]);
+
+        // Following cursors walks every item once, even as new ones arrive
+        let mut seen = ids(&first);
+        let mut cursor = first.pagination.next_cursor.clone();
+        let mut items = items;
+        items.push(json!({ "id": "evt-8", "timestamp": "2024-05-01T10:00:08Z" }));
+        while let Some(next) = cursor {
+            let page = query(&format!("cursor={}", next)).apply(items.clone(), options).unwrap();
+            assert_eq!(page.pagination.page, None);
+            seen.extend(ids(&page));
+            cursor = page.pagination.next_cursor;
+        }
+        assert_eq!(seen, vec!["evt-7", "evt-6", "evt-5", "evt-4", "evt-3", "evt-2", "evt-1"]);
+
+        assert!(query("cursor=zz").apply(items.clone(), options).is_err());
+        assert!(query("filter=severity").apply(items, options).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{
+    extract::{State, Path},
+    http::StatusCode,
+    response::Json,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::linux_security::{Playbook, PlaybookContext, PlaybookEngine, PlaybookRun};
+use crate::linux_security::event_correlation::Severity;
+use crate::linux_security::playbooks::PlaybookReloadSummary;
+
+// A run started by hand during incident response
+#[derive(Debug, Deserialize)]
+pub struct PlaybookRunRequest {
+    pub pid: Option<u32>,
+    pub path: Option<PathBuf>,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+    #[serde(default)]
+    pub reason: String,
+}
+
+fn default_severity() -> Severity {
+    Severity::High
+}
+
+fn engine(state: &AppState) -> Result<Arc<PlaybookEngine>, StatusCode> {
+    state.playbooks.as_ref().map(Arc::clone).ok_or(StatusCode::NOT_FOUND)
+}
+
+// Playbook steps block on processes, files and the firewall
+async fn with_engine<T, F>(state: &AppState, op: F) -> Result<Json<ApiResponse<T>>, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce(&PlaybookEngine) -> anyhow::Result<T> + Send + 'static,
+{
+    let engine = engine(state)?;
+    let result = tokio::task::spawn_blocking(move || op(&engine))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(value) => Ok(Json(ApiResponse::success(value))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+pub async fn get_playbooks(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<Playbook>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(engine(&state)?.playbooks())))
+}
+
+pub async fn put_playbook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(playbook): Json<Playbook>,
+) -> Result<Json<ApiResponse<Playbook>>, StatusCode> {
+    if playbook.id != id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let record = AuditRecord::new(AuditKind::PolicyChange, "api", "save_playbook", id, playbook.name.clone());
+    let response = with_engine(&state, move |engine| engine.save_playbook(playbook.clone()).map(|_| playbook)).await?;
+    if response.success {
+        state.audit(record);
+    }
+    Ok(response)
+}
+
+pub async fn delete_playbook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<bool>>, StatusCode> {
+    let target = id.clone();
+    let response = with_engine(&state, move |engine| engine.delete_playbook(&target)).await?;
+    match response.data {
+        Some(true) => state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "delete_playbook", id, "Playbook removed")),
+        Some(false) => return Err(StatusCode::NOT_FOUND),
+        None => {}
+    }
+    Ok(response)
+}
+
+// Re-reads the playbooks directory; invalid files leave the loaded playbooks in place
+pub async fn reload_playbooks(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<PlaybookReloadSummary>>, StatusCode> {
+    with_engine(&state, |engine| engine.reload_playbooks()).await
+}
+
+pub async fn run_playbook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<PlaybookRunRequest>,
+) -> Result<Json<ApiResponse<PlaybookRun>>, StatusCode> {
+    let reason = if request.reason.is_empty() { "Started from the API".to_string() } else { request.reason };
+    let context = PlaybookContext {
+        detection_id: format!("manual-{}", uuid::Uuid::new_v4()),
+        rule_id: None,
+        severity: request.severity,
+        description: reason.clone(),
+        pid: request.pid,
+        path: request.path,
+        events: Vec::new(),
+    };
+    let target = id.clone();
+    let response = with_engine(&state, move |engine| engine.run_playbook(&target, &context)).await?;
+    if let Some(ref run) = response.data {
+        state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "run_playbook", id,
+            format!("{} ({:?})", reason, run.status)));
+    }
+    Ok(response)
+}
+
+pub async fn get_playbook_runs(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<PlaybookRun>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(engine(&state)?.runs())))
+}
+
+// Undoes a run's reversible steps, e.g. lifting an isolation
+pub async fn rollback_playbook_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<String>,
+) -> Result<Json<ApiResponse<PlaybookRun>>, StatusCode> {
+    let target = run_id.clone();
+    let response = with_engine(&state, move |engine| engine.rollback_run(&target)).await?;
+    if let Some(ref run) = response.data {
+        state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "rollback_playbook", run_id,
+            format!("Rolled back playbook {}", run.playbook_id)));
+    }
+    Ok(response)
+}
@@ -1,6 +1,6 @@
 use axum::{
     extract::{Query, State, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
 };
 use std::sync::{Arc, Mutex};
@@ -8,9 +8,15 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use anyhow::Context;
 
 use crate::api::models::ApiResponse;
-use crate::api::handlers::AppState;
+use crate::api::handlers::{list_page, AppState, ListResult};
+use crate::api::pagination::{ListOptions, ListQuery};
+use crate::config::Config;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::policy::edit::write_atomic;
+use crate::policy::{validate_policy, CandidatePolicy, PolicyReplay, PolicyVersion, ReplayReport, ReplayWindow, SignedDocument, SignedPolicy, write_signed};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityPolicy {
@@ -252,6 +258,8 @@ pub async fn create_policy(
     new_policy.id = format!("pol_{}", Uuid::new_v4());
     new_policy.created_at = Utc::now();
     new_policy.updated_at = Utc::now();
+    state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "create", new_policy.id.clone(),
+                                 format!("Created policy '{}'", new_policy.name)));
     
     Json(ApiResponse::success(new_policy))
 }
@@ -264,6 +272,8 @@ pub async fn update_policy(
     let mut updated_policy = policy;
     updated_policy.id = id;
     updated_policy.updated_at = Utc::now();
+    state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "update", updated_policy.id.clone(),
+                                 format!("Updated policy '{}' (enabled: {})", updated_policy.name, updated_policy.enabled)));
     
     Json(ApiResponse::success(updated_policy))
 }
@@ -272,14 +282,202 @@ pub async fn delete_policy(
     Path(id): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> Json<ApiResponse<()>> {
+    state.audit(AuditRecord::new(AuditKind::PolicyChange, "api", "delete", id, "Deleted policy"));
     Json(ApiResponse::success(()))
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyReplayRequest {
+    pub policy: CandidatePolicy,
+    // Defaults to the configured event log
+    pub log_file: Option<std::path::PathBuf>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub include_allowed: bool,
+}
+
+// Replay stored events through a candidate policy without enforcing it
+pub async fn replay_policy(
+    State(_state): State<Arc<AppState>>,
+    Json(request): Json<PolicyReplayRequest>,
+) -> Result<Json<ApiResponse<ReplayReport>>, StatusCode> {
+    if request.policy.file_policy.is_none() && request.policy.network_policy.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let log_file = match request.log_file.or(Config::default().log_file_path) {
+        Some(path) => path,
+        None => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let mut policy = request.policy;
+    if let Err(e) = policy.validate() {
+        return Ok(Json(ApiResponse::error(format!("{:#}", e))));
+    }
+
+    let window = ReplayWindow { start: request.start, end: request.end };
+    let mut replay = PolicyReplay::new(policy);
+    replay.set_include_allowed(request.include_allowed);
+
+    let report = tokio::task::spawn_blocking(move || replay.replay_log(&log_file, window))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match report {
+        Ok(report) => Ok(Json(ApiResponse::success(report))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Policy replay failed: {}", e)))),
+    }
+}
+
+// The file and network policies currently enforced
+pub async fn get_active_policy(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<CandidatePolicy>>, StatusCode> {
+    let file_policy = state.file_policy.read().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.clone();
+    let network_policy = state.network_policy.read().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.clone();
+    Ok(Json(ApiResponse::success(CandidatePolicy {
+        file_policy: Some(file_policy),
+        network_policy: Some(network_policy),
+    })))
+}
+
+// Replaces the enforced policies without a restart; either half may be
+// omitted. With a config file, the new policies are also written to its
+// policy paths so they survive a restart.
+pub async fn apply_active_policy(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(mut policy): Json<CandidatePolicy>,
+) -> Result<Json<ApiResponse<CandidatePolicy>>, StatusCode> {
+    if policy.file_policy.is_none() && policy.network_policy.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Files written from here would fail verification on the next start
+    if state.policy_verifier.is_some() {
+        return Ok(Json(ApiResponse::error("Policy signing is enabled; use PUT /api/policies/active/signed".to_string())));
+    }
+    if let Err(e) = validate_policy(&mut policy) {
+        return Ok(Json(ApiResponse::error(format!("Invalid policy: {:#}", e))));
+    }
+    let change = PolicyChange::new(state.request_author(&headers), "api");
+    match apply_policy_change(&state, &policy, change) {
+        Ok(_) => Ok(Json(ApiResponse::success(policy))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+// Takes the policy files exactly as signed, e.g. by `flux-monitor policy sign`
+pub async fn apply_signed_policy(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(signed): Json<SignedPolicy>,
+) -> Result<Json<ApiResponse<CandidatePolicy>>, StatusCode> {
+    let policy = match state.policy_verifier.as_ref() {
+        Some(verifier) => signed.verify(verifier),
+        None => signed.parse(),
+    };
+    let policy = match policy {
+        Ok(policy) => policy,
+        Err(e) => return Ok(Json(ApiResponse::error(format!("Invalid policy: {:#}", e)))),
+    };
+    let change = PolicyChange { signed: Some(&signed), ..PolicyChange::new(state.request_author(&headers), "api") };
+    match apply_policy_change(&state, &policy, change) {
+        Ok(_) => Ok(Json(ApiResponse::success(policy))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+// How a policy change came about, for the history and the audit log
+pub(crate) struct PolicyChange<'a> {
+    pub author: String,
+    // api, reload or rollback
+    pub source: &'static str,
+    // The signed files to write instead of re-serializing the policy
+    pub signed: Option<&'a SignedPolicy>,
+    pub restored: Option<u64>,
+    // False when the policy was just read from those files
+    pub write_files: bool,
+}
+
+impl PolicyChange<'_> {
+    pub fn new(author: String, source: &'static str) -> Self {
+        Self { author, source, signed: None, restored: None, write_files: true }
+    }
+}
+
+// Writes and swaps in the given halves of the policy. With a history, this
+// happens under its lock and the result is recorded as a new version
+pub(crate) fn apply_policy_change(
+    state: &AppState,
+    policy: &CandidatePolicy,
+    change: PolicyChange,
+) -> anyhow::Result<Option<PolicyVersion>> {
+    let apply = |latest: Option<&PolicyVersion>| -> anyhow::Result<(CandidatePolicy, SignedPolicy)> {
+        if change.write_files {
+            write_policy_files(state, policy, change.signed)?;
+        }
+        
+        // Both halves are swapped together, so readers never see half of a rollback
+        let mut file_policy = state.file_policy.write().map_err(|_| anyhow::anyhow!("Failed to acquire file policy write lock"))?;
+        let mut network_policy = state.network_policy.write().map_err(|_| anyhow::anyhow!("Failed to acquire network policy write lock"))?;
+        let action = if change.source == "api" { "replace" } else { change.source };
+        if let Some(ref new) = policy.file_policy {
+            *file_policy = new.clone();
+            state.audit(AuditRecord::new(AuditKind::PolicyChange, &change.author, action, "file_policy",
+                                         format!("{} hashes, {} paths, {} rules",
+                                                 new.allowed_hashes.len(), new.allowed_paths.len(), new.rules.len())));
+        }
+        if let Some(ref new) = policy.network_policy {
+            *network_policy = new.clone();
+            state.audit(AuditRecord::new(AuditKind::PolicyChange, &change.author, action, "network_policy",
+                                         format!("{} blocked IPs, {} blocked domains, {} rules",
+                                                 new.blocked_ips.len(), new.blocked_domains.len(), new.rules.len())));
+        }
+        
+        // Halves left alone keep the signed files they had
+        let previous = latest.map(|latest| latest.signed.clone()).unwrap_or_default();
+        let signed_half = |replaced: bool, new: Option<&SignedDocument>, old: Option<SignedDocument>| {
+            if replaced { new.cloned() } else { old }
+        };
+        let signed = SignedPolicy {
+            file_policy: signed_half(policy.file_policy.is_some(), change.signed.and_then(|s| s.file_policy.as_ref()), previous.file_policy),
+            network_policy: signed_half(policy.network_policy.is_some(), change.signed.and_then(|s| s.network_policy.as_ref()), previous.network_policy),
+        };
+        Ok((CandidatePolicy { file_policy: Some(file_policy.clone()), network_policy: Some(network_policy.clone()) }, signed))
+    };
+    
+    match state.policy_history.as_ref() {
+        Some(history) => history.record_with(&change.author, change.source, change.restored, apply),
+        None => apply(None).map(|_| None),
+    }
+}
+
+fn write_policy_files(state: &AppState, policy: &CandidatePolicy, signed: Option<&SignedPolicy>) -> anyhow::Result<()> {
+    let Some(config) = state.config.as_ref().map(|config| config.current()) else { return Ok(()) };
+    if let (Some(file_policy), Some(path)) = (policy.file_policy.as_ref(), config.file_policy_path.as_ref()) {
+        match signed.and_then(|signed| signed.file_policy.as_ref()) {
+            Some(document) => write_signed(path, document),
+            None => write_atomic(path, &serde_json::to_string_pretty(file_policy)?),
+        }.context("Failed to save file policy")?;
+    }
+    if let (Some(network_policy), Some(path)) = (policy.network_policy.as_ref(), config.network_policy_path.as_ref()) {
+        match signed.and_then(|signed| signed.network_policy.as_ref()) {
+            Some(document) => write_signed(path, document),
+            None => write_atomic(path, &serde_json::to_string_pretty(network_policy)?),
+        }.context("Failed to save network policy")?;
+    }
+    Ok(())
+}
+
 // Alert management endpoints
 
+const ALERT_LIST: ListOptions = ListOptions::new("-timestamp", "id", 50);
+
 pub async fn get_alerts(
+    Query(list): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<Alert>>> {
+) -> ListResult<Alert> {
     let alerts = vec![
         Alert {
             id: "alert_1".to_string(),
@@ -318,7 +516,7 @@ pub async fn get_alerts(
         },
     ];
     
-    Json(ApiResponse::success(alerts))
+    list_page(alerts, &list, ALERT_LIST)
 }
 
 pub async fn get_alert(
@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::api::policy_handlers::{apply_policy_change, PolicyChange};
+use crate::policy::{read_policy_files, FieldChange, PolicyHistory, PolicyVersion, PolicyVersionSummary};
+
+#[derive(Debug, Deserialize)]
+pub struct PolicyDiffQuery {
+    // Compare with this version instead of the one before
+    pub against: Option<u64>,
+}
+
+fn policy_history(state: &AppState) -> Result<&Arc<PolicyHistory>, StatusCode> {
+    state.policy_history.as_ref().ok_or(StatusCode::NOT_FOUND)
+}
+
+// Newest first
+pub async fn get_policy_history(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<PolicyVersionSummary>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(policy_history(&state)?.versions())))
+}
+
+pub async fn get_policy_version(
+    State(state): State<Arc<AppState>>,
+    Path(version): Path<u64>,
+) -> Result<Json<ApiResponse<PolicyVersion>>, StatusCode> {
+    let version = policy_history(&state)?.version(version).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(version)))
+}
+
+pub async fn get_policy_version_diff(
+    State(state): State<Arc<AppState>>,
+    Path(version): Path<u64>,
+    Query(query): Query<PolicyDiffQuery>,
+) -> Result<Json<ApiResponse<Vec<FieldChange>>>, StatusCode> {
+    let history = policy_history(&state)?;
+    let entry = history.version(version).ok_or(StatusCode::NOT_FOUND)?;
+    let Some(against) = query.against else {
+        return Ok(Json(ApiResponse::success(entry.changes)));
+    };
+    match history.diff(against, version) {
+        Ok(changes) => Ok(Json(ApiResponse::success(changes))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
+
+// Restores both halves of an earlier version and records that as a new
+// version; the policy files are rewritten, signed ones from the stored
+// signed files
+pub async fn rollback_policy(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(version): Path<u64>,
+) -> Result<Json<ApiResponse<PolicyVersion>>, StatusCode> {
+    let target = policy_history(&state)?.version(version).ok_or(StatusCode::NOT_FOUND)?;
+    if state.policy_verifier.is_some() && (target.signed.file_policy.is_none() || target.signed.network_policy.is_none()) {
+        return Ok(Json(ApiResponse::error(format!(
+            "Version {} was not fully signed and cannot be restored while policy signing is enabled", version))));
+    }
+
+    let signed = state.policy_verifier.is_some().then_some(&target.signed);
+    let change = PolicyChange {
+        signed,
+        restored: Some(version),
+        ..PolicyChange::new(state.request_author(&headers), "rollback")
+    };
+    match apply_policy_change(&state, &target.policy, change) {
+        Ok(Some(recorded)) => Ok(Json(ApiResponse::success(recorded))),
+        Ok(None) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Rollback to version {} failed: {:#}", version, e)))),
+    }
+}
+
+// Re-reads the configured policy files, e.g. after they were edited by hand
+// or replaced by configuration management
+pub async fn reload_policy_files(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Option<PolicyVersion>>>, StatusCode> {
+    let config = state.config.as_ref().ok_or(StatusCode::NOT_FOUND)?.current();
+    let read = read_policy_files(
+        config.file_policy_path.as_deref(),
+        config.network_policy_path.as_deref(),
+        state.policy_verifier.as_deref(),
+    );
+    let (policy, signed) = match read {
+        Ok(read) => read,
+        Err(e) => return Ok(Json(ApiResponse::error(format!("Policy files not reloaded: {:#}", e)))),
+    };
+    if policy.file_policy.is_none() && policy.network_policy.is_none() {
+        return Ok(Json(ApiResponse::error("No policy files to reload".to_string())));
+    }
+
+    let change = PolicyChange {
+        signed: Some(&signed),
+        write_files: false,
+        ..PolicyChange::new(state.request_author(&headers), "reload")
+    };
+    match apply_policy_change(&state, &policy, change) {
+        Ok(recorded) => Ok(Json(ApiResponse::success(recorded))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
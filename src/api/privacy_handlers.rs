@@ -0,0 +1,48 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditKind, AuditRecord};
+
+#[derive(Debug, Deserialize)]
+pub struct ReidentifyRequest {
+    pub pseudonyms: Vec<String>,
+    // Why the data is needed, kept in the audit log
+    pub reason: String,
+}
+
+// Maps pseudonyms back to usernames and addresses; admin tokens only, and
+// every lookup is audited
+pub async fn reidentify_pseudonyms(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<ReidentifyRequest>,
+) -> Result<Json<ApiResponse<BTreeMap<String, Option<String>>>>, StatusCode> {
+    let privacy = Arc::clone(state.privacy.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let actor = state.require_admin(&headers)?;
+    if request.reason.trim().is_empty() {
+        return Ok(Json(ApiResponse::error("A reason is required to re-identify pseudonyms".to_string())));
+    }
+
+    let pseudonyms = request.pseudonyms.clone();
+    let result = tokio::task::spawn_blocking(move || privacy.reidentify(&pseudonyms))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match result {
+        Ok(originals) => {
+            warn!("{} re-identified {} pseudonyms: {}", actor, request.pseudonyms.len(), request.reason);
+            state.audit(AuditRecord::new(AuditKind::DataAccess, &actor, "reidentify",
+                request.pseudonyms.join(","), request.reason));
+            Ok(Json(ApiResponse::success(originals)))
+        }
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Query, State, Path},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::linux_security::ProcessTree;
+use crate::linux_security::process_tree::DEFAULT_TREE_DEPTH;
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessTreeQuery {
+    // Levels of descendants below the requested process
+    pub depth: Option<usize>,
+}
+
+pub async fn get_process_tree(
+    State(state): State<Arc<AppState>>,
+    Path(pid): Path<u32>,
+    Query(query): Query<ProcessTreeQuery>,
+) -> Result<Json<ApiResponse<ProcessTree>>, StatusCode> {
+    let source = Arc::clone(state.process_trees.as_ref().ok_or(StatusCode::NOT_FOUND)?);
+    let depth = query.depth.unwrap_or(DEFAULT_TREE_DEPTH);
+
+    // Events attributed to a process, so the dashboard can link nodes to them
+    let events: Vec<(u32, String)> = state.security_events.lock()
+        .map(|events| events.iter()
+            .filter_map(|event| event.pid.map(|pid| (pid, event.id.clone())))
+            .collect())
+        .unwrap_or_default();
+
+    let tree = tokio::task::spawn_blocking(move || {
+        source.build_with_events(pid, depth, &|node| {
+            events.iter()
+                .filter(|(event_pid, _)| *event_pid == node)
+                .map(|(_, id)| id.clone())
+                .collect()
+        })
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tree.map(|tree| Json(ApiResponse::success(tree)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,356 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::api::handlers::AppState;
+use crate::api::models::{ApiResponse, LogCategory, LogEntry, LogLevel};
+use crate::api::system_logs;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::rate_limit::{RateLimiter, RateLimiterConfig};
+
+// Idle buckets and failure windows are dropped after this long
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct ApiRateLimitConfig {
+    // Requests per second and burst for each client address
+    pub client_rate: u32,
+    pub client_burst: u32,
+    // The same for each bearer token or API key, wherever it is used from
+    pub token_rate: u32,
+    pub token_burst: u32,
+    // Unauthorized or rate-limited requests from one address within
+    // `failure_window` that lock it out for `lockout_duration`
+    pub lockout_failures: u32,
+    pub failure_window: Duration,
+    pub lockout_duration: Duration,
+    // Take the client address from X-Forwarded-For; only safe behind a proxy
+    // that overwrites it
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for ApiRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            client_rate: 20,
+            client_burst: 100,
+            token_rate: 50,
+            token_burst: 200,
+            lockout_failures: 10,
+            failure_window: Duration::from_secs(60),
+            lockout_duration: Duration::from_secs(900),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl ApiRateLimitConfig {
+    // On by default. FLUX_API_RATE_LIMIT=rate/burst per client address, or
+    // `off`; FLUX_API_TOKEN_RATE_LIMIT=rate/burst per token;
+    // FLUX_API_LOCKOUT=failures/window_secs/lockout_secs; FLUX_API_TRUST_PROXY=1
+    pub fn from_env() -> Result<Option<Self>> {
+        let mut config = Self::default();
+        match std::env::var("FLUX_API_RATE_LIMIT").as_deref() {
+            Ok("off") => return Ok(None),
+            Ok(value) => (config.client_rate, config.client_burst) = parse_limit("FLUX_API_RATE_LIMIT", value)?,
+            Err(_) => {}
+        }
+        if let Ok(value) = std::env::var("FLUX_API_TOKEN_RATE_LIMIT") {
+            (config.token_rate, config.token_burst) = parse_limit("FLUX_API_TOKEN_RATE_LIMIT", &value)?;
+        }
+        if let Ok(value) = std::env::var("FLUX_API_LOCKOUT") {
+            let parts = value.split('/')
+                .map(|part| part.trim().parse::<u32>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid FLUX_API_LOCKOUT value: {}", value))?;
+            let [failures, window, lockout] = parts[..] else {
+                return Err(anyhow!("FLUX_API_LOCKOUT must be failures/window_secs/lockout_secs"));
+            };
+            config.lockout_failures = failures;
+            config.failure_window = Duration::from_secs(window.into());
+            config.lockout_duration = Duration::from_secs(lockout.into());
+        }
+        config.trust_forwarded_for = std::env::var("FLUX_API_TRUST_PROXY").is_ok_and(|value| value == "1");
+        Ok(Some(config))
+    }
+}
+
+fn parse_limit(name: &str, value: &str) -> Result<(u32, u32)> {
+    let (rate, burst) = value.split_once('/').unwrap_or((value, value));
+    let rate: u32 = rate.trim().parse().with_context(|| format!("Invalid {} rate: {}", name, value))?;
+    let burst: u32 = burst.trim().parse().with_context(|| format!("Invalid {} burst: {}", name, value))?;
+    if rate == 0 || burst == 0 {
+        return Err(anyhow!("{} needs a rate and burst of at least 1", name));
+    }
+    Ok((rate, burst))
+}
+
+#[derive(Default)]
+struct ClientState {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+struct Limiters {
+    clients: RateLimiter,
+    tokens: RateLimiter,
+    cleaned_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    ClientLimit(Duration),
+    TokenLimit(Duration),
+    LockedOut(Duration),
+}
+
+impl Rejection {
+    fn retry_after(self) -> Duration {
+        match self {
+            Rejection::ClientLimit(wait) | Rejection::TokenLimit(wait) | Rejection::LockedOut(wait) => wait,
+        }
+    }
+}
+
+pub struct ApiRateLimiter {
+    config: ApiRateLimitConfig,
+    limiters: Mutex<Limiters>,
+    clients: Mutex<HashMap<IpAddr, ClientState>>,
+}
+
+impl ApiRateLimiter {
+    pub fn new(config: ApiRateLimitConfig) -> Self {
+        let bucket = |rate, burst| RateLimiter::new(RateLimiterConfig {
+            default_rate: rate,
+            default_burst: burst,
+            cleanup_interval: CLEANUP_INTERVAL,
+        });
+        Self {
+            limiters: Mutex::new(Limiters {
+                clients: bucket(config.client_rate, config.client_burst),
+                tokens: bucket(config.token_rate, config.token_burst),
+                cleaned_at: Instant::now(),
+            }),
+            clients: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &ApiRateLimitConfig {
+        &self.config
+    }
+
+    // Spends a token from the client's bucket and, when the request carries
+    // one, from the API token's bucket
+    pub fn check(&self, client: IpAddr, token: Option<&str>) -> Result<(), Rejection> {
+        let now = Instant::now();
+        if let Some(until) = self.clients.lock().unwrap().get(&client).and_then(|state| state.locked_until) {
+            if until > now {
+                return Err(Rejection::LockedOut(until - now));
+            }
+        }
+
+        let mut limiters = self.limiters.lock().unwrap();
+        if now.duration_since(limiters.cleaned_at) >= CLEANUP_INTERVAL {
+            limiters.clients.cleanup_old_buckets();
+            limiters.tokens.cleanup_old_buckets();
+            limiters.cleaned_at = now;
+        }
+        let client_key = client.to_string();
+        if !limiters.clients.check_and_update(&client_key) {
+            return Err(Rejection::ClientLimit(limiters.clients.retry_after(&client_key)));
+        }
+        if let Some(token) = token {
+            let token_key = token_key(token);
+            if !limiters.tokens.check_and_update(&token_key) {
+                return Err(Rejection::TokenLimit(limiters.tokens.retry_after(&token_key)));
+            }
+        }
+        Ok(())
+    }
+
+    // Counts an unauthorized or rate-limited request; returns how long the
+    // client is locked out when this one crossed the threshold
+    pub fn record_failure(&self, client: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|_, state| {
+            state.locked_until.is_some_and(|until| until > now)
+                || state.failures.back().is_some_and(|last| now.duration_since(*last) < self.config.failure_window)
+        });
+        let state = clients.entry(client).or_default();
+        if state.locked_until.is_some_and(|until| until > now) {
+            return None;
+        }
+        state.failures.push_back(now);
+        while state.failures.front().is_some_and(|first| now.duration_since(*first) >= self.config.failure_window) {
+            state.failures.pop_front();
+        }
+        if state.failures.len() < self.config.lockout_failures as usize {
+            return None;
+        }
+        state.failures.clear();
+        state.locked_until = Some(now + self.config.lockout_duration);
+        Some(self.config.lockout_duration)
+    }
+
+    fn client_ip(&self, request: &Request) -> IpAddr {
+        let forwarded = self.config.trust_forwarded_for
+            .then(|| request.headers().get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|first| first.trim().parse().ok());
+        forwarded
+            .or_else(|| request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip()))
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+// Buckets are keyed by a digest so tokens are not kept in memory longer than
+// the request that carried them
+fn token_key(token: &str) -> String {
+    hex::encode(&Sha256::digest(token.as_bytes())[..16])
+}
+
+fn request_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .or_else(|| headers.get("x-api-key"))
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+// Applied with axum::middleware::from_fn_with_state; a no-op unless
+// AppState::rate_limiter is set
+pub async fn rate_limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(limiter) = state.rate_limiter.clone() else {
+        return next.run(request).await;
+    };
+    let client = limiter.client_ip(&request);
+
+    if let Err(rejection) = limiter.check(client, request_token(request.headers())) {
+        if !matches!(rejection, Rejection::LockedOut(_)) {
+            if let Some(lockout) = limiter.record_failure(client) {
+                report_lockout(&state, client, lockout, "rate limit exceeded repeatedly");
+            }
+        }
+        return too_many_requests(rejection);
+    }
+
+    let response = next.run(request).await;
+    if matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        if let Some(lockout) = limiter.record_failure(client) {
+            report_lockout(&state, client, lockout, "repeated authentication failures");
+        }
+    }
+    response
+}
+
+fn too_many_requests(rejection: Rejection) -> Response {
+    let message = match rejection {
+        Rejection::ClientLimit(_) => "Too many requests from this address",
+        Rejection::TokenLimit(_) => "Too many requests for this token",
+        Rejection::LockedOut(_) => "Temporarily locked out after repeated failures",
+    };
+    let retry_after = rejection.retry_after().as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ApiResponse::<()>::error(message.to_string())),
+    ).into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+fn report_lockout(state: &AppState, client: IpAddr, lockout: Duration, reason: &str) {
+    warn!("Locking out API client {} for {}s: {}", client, lockout.as_secs(), reason);
+    let details = HashMap::from([
+        ("client_ip".to_string(), Value::from(client.to_string())),
+        ("lockout_seconds".to_string(), Value::from(lockout.as_secs())),
+    ]);
+    system_logs::record(state, LogEntry {
+        id: Uuid::new_v4().to_string(),
+        timestamp: Utc::now(),
+        level: LogLevel::Warning,
+        category: LogCategory::Security,
+        source: "api".to_string(),
+        message: format!("API client {} locked out for {}s: {}", client, lockout.as_secs(), reason),
+        details: Some(details),
+        user: None,
+        pid: None,
+        tags: Some(vec!["rate_limit".to_string(), "lockout".to_string()]),
+    });
+    state.audit(AuditRecord::new(AuditKind::ResponseAction, "api", "lockout", client.to_string(), reason));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::Service;
+
+    #[tokio::test]
+    async fn test_rate_limit_and_lockout() {
+        let mut state = AppState::new();
+        state.rate_limiter = Some(Arc::new(ApiRateLimiter::new(ApiRateLimitConfig {
+            client_rate: 1,
+            client_burst: 3,
+            token_rate: 1,
+            token_burst: 2,
+            lockout_failures: 3,
+            trust_forwarded_for: true,
+            ..ApiRateLimitConfig::default()
+        })));
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/api/data", get(|| async { "ok" }))
+            .route("/api/login", get(|| async { StatusCode::UNAUTHORIZED }))
+            .layer(axum::middleware::from_fn_with_state(Arc::clone(&state), rate_limit))
+            .with_state(Arc::clone(&state));
+        let send = |path: &str, client: &str, token: Option<&str>| {
+            let mut request = Request::builder().uri(path).header("x-forwarded-for", client);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, token);
+            }
+            app.clone().call(request.body(Body::empty()).unwrap())
+        };
+
+        // The address bucket runs dry after the burst
+        for _ in 0..3 {
+            assert_eq!(send("/api/data", "10.0.0.1", None).await.unwrap().status(), StatusCode::OK);
+        }
+        let limited = send("/api/data", "10.0.0.1", None).await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "1");
+
+        // A token is limited across addresses
+        assert_eq!(send("/api/data", "10.0.0.2", Some("Bearer abc")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("/api/data", "10.0.0.3", Some("Bearer abc")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("/api/data", "10.0.0.4", Some("Bearer abc")).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Repeated 401s lock the address out, even for endpoints it could use
+        for _ in 0..3 {
+            assert_eq!(send("/api/login", "10.0.0.5", None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        }
+        let locked = send("/api/data", "10.0.0.5", None).await.unwrap();
+        assert_eq!(locked.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(locked.headers()[header::RETRY_AFTER].to_str().unwrap().parse::<u64>().unwrap() > 800);
+
+        let logs = state.log_entries.lock().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].message.contains("10.0.0.5"));
+        assert_eq!(state.live_events.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::rule_stats::{RuleEngine, RuleHits, UnusedRulesReport};
+
+#[derive(Debug, Deserialize)]
+pub struct RuleStatsQuery {
+    pub engine: Option<RuleEngine>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnusedRulesQuery {
+    // Rules without a match in this many days are reported
+    pub days: Option<u32>,
+}
+
+// Matches of every rule, most hits first
+pub async fn get_rule_stats(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RuleStatsQuery>,
+) -> Result<Json<ApiResponse<Vec<RuleHits>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(state.rule_stats.stats(query.engine))))
+}
+
+// Rules that have not matched in the last `days` (default 30), candidates
+// for removal
+pub async fn get_unused_rules(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UnusedRulesQuery>,
+) -> Result<Json<ApiResponse<UnusedRulesReport>>, StatusCode> {
+    match state.rule_stats.unused(query.days.unwrap_or(30)) {
+        Ok(report) => Ok(Json(ApiResponse::success(report))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -0,0 +1,52 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::scanner::{DirectoryScanner, ScanOptions, ScanReport};
+
+#[derive(Debug, Deserialize)]
+pub struct ScanRequest {
+    pub path: PathBuf,
+    // YARA rules file or directory
+    pub rules: Option<PathBuf>,
+    // SHA-256 list, one hash per line
+    pub known_bad: Option<PathBuf>,
+    #[serde(flatten)]
+    pub options: ScanOptions,
+}
+
+// Runs the scan to completion and returns the report; progress is broadcast
+// to WebSocket clients as ScanProgress messages while it runs
+pub async fn start_scan(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ScanRequest>,
+) -> Result<Json<ApiResponse<ScanReport>>, StatusCode> {
+    let progress = state.scan_progress.clone();
+    let result = tokio::task::spawn_blocking(move || -> anyhow::Result<ScanReport> {
+        let mut scanner = DirectoryScanner::new(request.options);
+        if let Some(ref rules) = request.rules {
+            scanner.load_rules(rules)?;
+        }
+        if let Some(ref known_bad) = request.known_bad {
+            scanner.load_known_bad(known_bad)?;
+        }
+        // No subscribers is not an error
+        scanner.scan(&request.path, |update| {
+            let _ = progress.send(update.clone());
+        })
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match result {
+        Ok(report) => Ok(Json(ApiResponse::success(report))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::scheduler::TaskStatus;
+
+pub async fn get_scheduled_tasks(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<TaskStatus>>>, StatusCode> {
+    Ok(Json(ApiResponse::success(state.scheduler.list())))
+}
+
+pub async fn get_scheduled_task(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<TaskStatus>>, StatusCode> {
+    state.scheduler.get(&id)
+        .map(|status| Json(ApiResponse::success(status)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+// Starts a run now; 409 while the previous run is still going
+pub async fn run_scheduled_task(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<TaskStatus>>, StatusCode> {
+    if state.scheduler.get(&id).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    match state.scheduler.run_now(&id) {
+        Ok(true) => state.scheduler.get(&id)
+            .map(|status| Json(ApiResponse::success(status)))
+            .ok_or(StatusCode::NOT_FOUND),
+        Ok(false) => Err(StatusCode::CONFLICT),
+        Err(e) => Ok(Json(ApiResponse::error(format!("{:#}", e)))),
+    }
+}
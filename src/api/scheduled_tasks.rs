@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::api::handlers::AppState;
+use crate::audit_log::{AuditKind, AuditRecord};
+use crate::scanner::persistence::{PersistenceScanner, Severity};
+use crate::scanner::{BaselineBuilder, BaselineManifest, BaselineOptions, DirectoryScanner, ScanOptions};
+use crate::scheduler::{CronSchedule, TaskJob};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskKind {
+    // Directory scan against YARA rules and known-bad hashes, with progress
+    // broadcast like POST /api/scan
+    FullScan {
+        #[serde(default = "default_scan_root")]
+        path: PathBuf,
+        #[serde(default)]
+        rules: Option<PathBuf>,
+        #[serde(default)]
+        known_bad: Option<PathBuf>,
+        #[serde(flatten)]
+        options: ScanOptions,
+    },
+    // Diffs autostart locations against the baseline file; added and
+    // modified entries go to the incidents
+    PersistenceScan { baseline: PathBuf },
+    // Rebuilds the executable baseline and swaps its hashes into the file policy
+    BaselineReconcile {
+        manifest: PathBuf,
+        // Defaults to the usual binary directories
+        #[serde(default)]
+        roots: Vec<PathBuf>,
+        #[serde(default = "default_include_packages")]
+        include_packages: bool,
+    },
+    // Pulls the MISP events when MISP is configured, and reloads a blocklist
+    // file into the firewall block sets
+    IntelRefresh {
+        #[serde(default)]
+        blocklist: Option<PathBuf>,
+        #[serde(default)]
+        ttl_minutes: Option<u64>,
+    },
+}
+
+fn default_scan_root() -> PathBuf {
+    PathBuf::from("/")
+}
+
+fn default_include_packages() -> bool {
+    true
+}
+
+impl TaskKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskKind::FullScan { .. } => "full_scan",
+            TaskKind::PersistenceScan { .. } => "persistence_scan",
+            TaskKind::BaselineReconcile { .. } => "baseline_reconcile",
+            TaskKind::IntelRefresh { .. } => "intel_refresh",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledTaskConfig {
+    pub id: String,
+    pub schedule: CronSchedule,
+    // Disabled tasks only run through POST /api/schedule/:id/run
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub task: TaskKind,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// Example:
+//   { "tasks": [
+//     { "id": "nightly-scan", "schedule": "0 2 * * *", "task": { "type": "full_scan", "path": "/home" } },
+//     { "id": "autostart", "schedule": "*/30 * * * *",
+//       "task": { "type": "persistence_scan", "baseline": "/var/lib/fluxdefense/persistence.json" } } ] }
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub tasks: Vec<ScheduledTaskConfig>,
+}
+
+impl ScheduleConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schedule {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid schedule {:?}", path))
+    }
+}
+
+// Adds the configured tasks to the state's scheduler; call once the
+// integrations they use, such as MISP and the firewall, are set up
+pub fn schedule_tasks(state: &AppState, config: ScheduleConfig) -> Result<()> {
+    for task in config.tasks {
+        let kind = task.task.name();
+        let job = task_job(state, task.task).with_context(|| format!("Scheduled task {}", task.id))?;
+        state.scheduler.add(&task.id, kind, task.schedule, task.enabled, job)?;
+    }
+    Ok(())
+}
+
+fn task_job(state: &AppState, kind: TaskKind) -> Result<TaskJob> {
+    Ok(match kind {
+        TaskKind::FullScan { path, rules, known_bad, options } => {
+            let progress = state.scan_progress.clone();
+            Arc::new(move || {
+                let mut scanner = DirectoryScanner::new(options.clone());
+                if let Some(ref rules) = rules {
+                    scanner.load_rules(rules)?;
+                }
+                if let Some(ref known_bad) = known_bad {
+                    scanner.load_known_bad(known_bad)?;
+                }
+                let report = scanner.scan(&path, |update| {
+                    let _ = progress.send(update.clone());
+                })?;
+                let malicious = report.findings.iter().filter(|finding| finding.is_malicious()).count();
+                Ok(format!("{} files scanned, {} findings ({} malicious)", report.files_scanned, report.findings.len(), malicious))
+            })
+        }
+        TaskKind::PersistenceScan { baseline } => {
+            let scanner = PersistenceScanner::new(baseline, |_| {})?;
+            let incidents = Arc::clone(&state.incidents);
+            Arc::new(move || {
+                let findings = scanner.check()?;
+                for finding in findings.iter().filter(|finding| finding.severity >= Severity::High) {
+                    incidents.record_event(None, &finding.to_security_event());
+                }
+                Ok(format!("{} persistence changes", findings.len()))
+            })
+        }
+        TaskKind::BaselineReconcile { manifest, roots, include_packages } => {
+            // The rewritten policy would be unsigned and refused on the next start
+            if state.policy_verifier.is_some() {
+                bail!("Baseline reconcile cannot rewrite the file policy while policy signing is enabled");
+            }
+            let mut options = BaselineOptions { include_packages, ..Default::default() };
+            if !roots.is_empty() {
+                options.roots = roots;
+            }
+            let file_policy = Arc::clone(&state.file_policy);
+            let config = state.config.clone();
+            let audit_log = state.audit_log.clone();
+            Arc::new(move || {
+                let previous = BaselineManifest::load(&manifest)?;
+                let (current, changes) = BaselineBuilder::new(options.clone()).build(previous.as_ref())?;
+                {
+                    let mut policy = file_policy.write().map_err(|_| anyhow!("Failed to acquire file policy write lock"))?;
+                    current.apply_to(&mut policy, previous.as_ref());
+                    if let Some(path) = config.as_ref().and_then(|config| config.current().file_policy_path) {
+                        policy.save_to_file(&path)?;
+                    }
+                }
+                current.save(&manifest)?;
+
+                let summary = format!("{} executables: {} added, {} changed, {} removed",
+                    current.entries.len(), changes.added.len(), changes.changed.len(), changes.removed.len());
+                if let Some(ref audit_log) = audit_log {
+                    audit_log.record(AuditRecord::new(AuditKind::PolicyChange, "scheduler", "baseline_reconcile", "file_policy", summary.clone()));
+                }
+                Ok(summary)
+            })
+        }
+        TaskKind::IntelRefresh { blocklist, ttl_minutes } => intel_refresh_job(state, blocklist, ttl_minutes)?,
+    })
+}
+
+fn intel_refresh_job(state: &AppState, blocklist: Option<PathBuf>, ttl_minutes: Option<u64>) -> Result<TaskJob> {
+    // The MISP client is async; task jobs run on their own threads
+    let misp = match state.misp.clone() {
+        Some(misp) => Some((misp, tokio::runtime::Handle::try_current().context("Intel refresh needs a Tokio runtime")?)),
+        None => None,
+    };
+    if misp.is_none() && blocklist.is_none() {
+        bail!("Intel refresh needs MISP configured or a blocklist file");
+    }
+
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    let blocklist = match blocklist {
+        Some(path) => {
+            let firewall = state.firewall.clone().ok_or_else(|| anyhow!("Blocklist refresh needs the firewall (FLUX_FIREWALL=1)"))?;
+            Some((path, firewall))
+        }
+        None => None,
+    };
+    #[cfg(not(all(target_os = "linux", feature = "pcap")))]
+    if blocklist.is_some() {
+        bail!("Blocklist refresh needs the Linux firewall");
+    }
+    let ttl = ttl_minutes.map(|minutes| std::time::Duration::from_secs(minutes * 60));
+
+    Ok(Arc::new(move || {
+        let mut summary = Vec::new();
+        if let Some((ref misp, ref runtime)) = misp {
+            let status = runtime.block_on(misp.pull())?;
+            summary.push(format!("MISP: {} hashes, {} domains, {} addresses", status.hashes, status.domains, status.ips));
+        }
+        #[cfg(all(target_os = "linux", feature = "pcap"))]
+        if let Some((ref path, ref firewall)) = blocklist {
+            let loaded = firewall.lock()
+                .map_err(|_| anyhow!("Failed to acquire firewall lock"))?
+                .load_blocklist_file(path, ttl)?;
+            summary.push(format!("{}: {} addresses ({} invalid)", path.display(), loaded.loaded_v4 + loaded.loaded_v6, loaded.invalid));
+        }
+        #[cfg(not(all(target_os = "linux", feature = "pcap")))]
+        let _ = ttl;
+        Ok(summary.join("; "))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_config() {
+        let config: ScheduleConfig = serde_json::from_str(r#"{ "tasks": [
+            { "id": "scan", "schedule": "0 2 * * *", "task": { "type": "full_scan", "path": "/home", "max_depth": 4 } },
+            { "id": "baseline", "schedule": "@weekly", "enabled": false,
+              "task": { "type": "baseline_reconcile", "manifest": "/tmp/manifest.json" } }
+        ] }"#).unwrap();
+        let TaskKind::FullScan { ref path, ref options, .. } = config.tasks[0].task else { panic!("not a full scan") };
+        assert_eq!((path.as_path(), options.max_depth), (Path::new("/home"), Some(4)));
+        assert!(matches!(config.tasks[1].task, TaskKind::BaselineReconcile { include_packages: true, .. }));
+
+        let state = AppState::new();
+        schedule_tasks(&state, config).unwrap();
+        let tasks = state.scheduler.list();
+        assert_eq!(tasks.iter().map(|task| task.kind.as_str()).collect::<Vec<_>>(), ["full_scan", "baseline_reconcile"]);
+        assert!(tasks[0].next_run.is_some() && tasks[1].next_run.is_none());
+        // Nothing to refresh without MISP or a blocklist
+        let refresh = serde_json::from_str(r#"{ "tasks": [{ "id": "intel", "schedule": "@hourly", "task": { "type": "intel_refresh" } }] }"#).unwrap();
+        assert!(schedule_tasks(&state, refresh).is_err());
+    }
+}
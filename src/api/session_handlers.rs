@@ -0,0 +1,72 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::sessions::{Session, SessionAnomaly, SessionMonitor};
+
+const DEFAULT_SESSION_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    // Ended sessions to include, newest first
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionList {
+    pub active: Vec<Session>,
+    pub closed: Vec<Session>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub session: Session,
+    // Processes running in the session now, found through their ancestry
+    pub live_pids: Vec<u32>,
+}
+
+fn sessions(state: &AppState) -> Result<Arc<SessionMonitor>, StatusCode> {
+    state.sessions.as_ref().map(Arc::clone).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn get_sessions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SessionQuery>,
+) -> Result<Json<ApiResponse<SessionList>>, StatusCode> {
+    let sessions = sessions(&state)?;
+    Ok(Json(ApiResponse::success(SessionList {
+        active: sessions.active(),
+        closed: sessions.closed(query.limit.unwrap_or(DEFAULT_SESSION_LIMIT)),
+    })))
+}
+
+pub async fn get_session(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<SessionDetail>>, StatusCode> {
+    let sessions = sessions(&state)?;
+    let session = sessions.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let live_pids = if session.ended_at.is_none() {
+        tokio::task::spawn_blocking(move || sessions.live_processes(&id))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    } else {
+        Vec::new()
+    };
+    Ok(Json(ApiResponse::success(SessionDetail { session, live_pids })))
+}
+
+pub async fn get_session_anomalies(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SessionQuery>,
+) -> Result<Json<ApiResponse<Vec<SessionAnomaly>>>, StatusCode> {
+    let sessions = sessions(&state)?;
+    Ok(Json(ApiResponse::success(sessions.anomalies(query.limit.unwrap_or(DEFAULT_SESSION_LIMIT)))))
+}
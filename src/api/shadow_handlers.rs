@@ -0,0 +1,28 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use std::sync::Arc;
+use serde::Deserialize;
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::AppState;
+use crate::rule_stats::RuleEngine;
+use crate::shadow::ShadowRuleStats;
+
+#[derive(Debug, Deserialize)]
+pub struct ShadowRulesQuery {
+    pub engine: Option<RuleEngine>,
+}
+
+// What the rules in shadow mode would have blocked or raised, most hits first
+pub async fn get_shadow_rules(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ShadowRulesQuery>,
+) -> Result<Json<ApiResponse<Vec<ShadowRuleStats>>>, StatusCode> {
+    let stats = state.shadow.stats().into_iter()
+        .filter(|stats| query.engine.is_none_or(|engine| stats.engine == engine))
+        .collect();
+    Ok(Json(ApiResponse::success(stats)))
+}
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn, debug};
+use uuid::Uuid;
+
+use crate::api::handlers::AppState;
+use crate::api::models::{LiveEvent, LogCategory, LogEntry, LogLevel};
+use crate::rule_stats::RuleEngine;
+use crate::shadow::parse_netfilter_log;
+
+// Retained in AppState, newest first
+const MAX_LOG_ENTRIES: usize = 1000;
+const MAX_LIVE_EVENTS: usize = 100;
+// Records read at startup so the log view is not empty
+const BACKLOG_LINES: usize = 200;
+const FILE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+// journald identifiers worth showing: logins, privilege changes, account
+// management, service state and kernel (firewall, audit) messages
+const JOURNAL_IDENTIFIERS: &[&str] = &[
+    "sshd", "sshd-session", "sudo", "su", "login", "systemd-logind", "polkitd", "pkexec",
+    "useradd", "usermod", "userdel", "groupadd", "passwd", "chpasswd", "systemd", "kernel",
+];
+const AUTH_IDENTIFIERS: &[&str] = &[
+    "sudo", "su", "login", "systemd-logind", "polkitd", "pkexec", "useradd", "usermod",
+    "userdel", "groupadd", "passwd", "chpasswd", "unix_chkpwd", "gdm-password",
+];
+const NETWORK_IDENTIFIERS: &[&str] = &["NetworkManager", "systemd-networkd", "systemd-resolved", "dhclient", "wpa_supplicant"];
+// Debian writes authentication records to the first, Red Hat to the second
+const AUTH_LOG_PATHS: &[&str] = &["/var/log/auth.log", "/var/log/secure"];
+
+// Where ingested records come from; set FLUX_SYSTEM_LOGS to `off`, `journald`
+// or a syslog-format file to override the automatic choice
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogSource {
+    Journald,
+    File(PathBuf),
+}
+
+impl LogSource {
+    pub fn detect() -> Option<Self> {
+        match std::env::var("FLUX_SYSTEM_LOGS").ok().as_deref() {
+            Some("off") => return None,
+            Some("journald") => return Some(LogSource::Journald),
+            Some(path) if !path.is_empty() => return Some(LogSource::File(PathBuf::from(path))),
+            _ => {}
+        }
+        if Path::new("/run/systemd/journal").is_dir() {
+            return Some(LogSource::Journald);
+        }
+        AUTH_LOG_PATHS.iter()
+            .map(PathBuf::from)
+            .find(|path| path.exists())
+            .map(LogSource::File)
+    }
+}
+
+// Follows the system log in the background, adding each record to the log
+// and live event stores and to the WebSocket stream
+pub fn spawn_log_ingestion(state: Arc<AppState>) -> Option<tokio::task::JoinHandle<()>> {
+    let source = LogSource::detect()?;
+    info!("Ingesting system logs from {:?}", source);
+    Some(tokio::spawn(async move {
+        let mut source = source;
+        // Where a restarted journalctl picks up
+        let mut cursor = None;
+        loop {
+            let result = match source {
+                LogSource::Journald => follow_journal(&state, &mut cursor).await,
+                LogSource::File(ref path) => follow_file(path, &state).await,
+            };
+            match result {
+                Err(e) if source == LogSource::Journald => {
+                    // No journalctl, or no permission to read the journal
+                    match AUTH_LOG_PATHS.iter().map(PathBuf::from).find(|path| path.exists()) {
+                        Some(path) => {
+                            warn!("journald unavailable ({}), reading {}", e, path.display());
+                            source = LogSource::File(path);
+                        }
+                        None => {
+                            warn!("journald unavailable ({}) and no auth log found", e);
+                            return;
+                        }
+                    }
+                }
+                Err(e) => warn!("System log ingestion stopped: {}", e),
+                Ok(()) => debug!("System log source ended, restarting"),
+            }
+            sleep(RESTART_DELAY).await;
+        }
+    }))
+}
+
+pub(crate) fn record(state: &AppState, entry: LogEntry) {
+    // Hits of netfilter rules in shadow mode
+    if entry.source == "kernel" {
+        if let Some((verdict, rule_id)) = parse_netfilter_log(&entry.message) {
+            state.shadow.record(RuleEngine::Netfilter, rule_id, rule_id, verdict, entry.message.clone());
+        }
+    }
+    {
+        let mut events = state.live_events.lock().unwrap();
+        events.insert(0, live_event(&entry));
+        events.truncate(MAX_LIVE_EVENTS);
+    }
+    {
+        let mut logs = state.log_entries.lock().unwrap();
+        logs.insert(0, entry.clone());
+        logs.truncate(MAX_LOG_ENTRIES);
+    }
+    // No receivers just means no dashboard is connected
+    let _ = state.log_stream.send(entry);
+}
+
+async fn follow_journal(state: &AppState, cursor: &mut Option<String>) -> std::io::Result<()> {
+    let mut command = tokio::process::Command::new("journalctl");
+    command.args(["--follow", "--output=json", "--no-pager", "--quiet"]);
+    match cursor {
+        Some(cursor) => command.arg(format!("--after-cursor={}", cursor)),
+        None => command.arg(format!("--lines={}", BACKLOG_LINES)),
+    };
+    command.args(JOURNAL_IDENTIFIERS.iter().map(|identifier| format!("SYSLOG_IDENTIFIER={}", identifier)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().ok_or_else(|| std::io::Error::other("journalctl has no stdout"))?;
+
+    let mut lines = BufReader::new(stdout).lines();
+    let mut records = 0usize;
+    while let Some(line) = lines.next_line().await? {
+        let Ok(fields) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if let Some(position) = fields.get("__CURSOR").and_then(Value::as_str) {
+            *cursor = Some(position.to_string());
+        }
+        if let Some(entry) = journal_entry(&fields) {
+            records += 1;
+            record(state, entry);
+        }
+    }
+    let status = child.wait().await?;
+    // Exiting before producing anything means the journal could not be read
+    if records == 0 && !status.success() {
+        return Err(std::io::Error::other(format!("journalctl exited with {}", status)));
+    }
+    Ok(())
+}
+
+// Tails a syslog-format file, starting with its last lines and reopening it
+// when logrotate replaces or truncates it
+async fn follow_file(path: &Path, state: &AppState) -> std::io::Result<()> {
+    let content = tokio::fs::read(path).await?;
+    let text = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = text.lines().collect();
+    for line in &lines[lines.len().saturating_sub(BACKLOG_LINES)..] {
+        if let Some(entry) = parse_syslog_line(line, Utc::now()) {
+            record(state, entry);
+        }
+    }
+
+    let mut offset = content.len() as u64;
+    let mut inode = tokio::fs::metadata(path).await?.ino();
+    let mut pending = String::new();
+    loop {
+        sleep(FILE_POLL_INTERVAL).await;
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            // Between rotation and the new file being created
+            Err(_) => continue,
+        };
+        if metadata.ino() != inode || metadata.len() < offset {
+            inode = metadata.ino();
+            offset = 0;
+            pending.clear();
+        }
+        if metadata.len() == offset {
+            continue;
+        }
+
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut chunk = Vec::new();
+        file.read_to_end(&mut chunk).await?;
+        offset += chunk.len() as u64;
+
+        // A line may still be half written
+        pending.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = pending.find('\n') {
+            let line: String = pending.drain(..=end).collect();
+            if let Some(entry) = parse_syslog_line(line.trim_end(), Utc::now()) {
+                record(state, entry);
+            }
+        }
+    }
+}
+
+// One record of `journalctl --output=json`
+pub fn parse_journal_record(line: &str) -> Option<LogEntry> {
+    journal_entry(&serde_json::from_str(line).ok()?)
+}
+
+fn journal_entry(record: &Value) -> Option<LogEntry> {
+    let field = |name: &str| record.get(name).and_then(Value::as_str);
+
+    // Non-UTF-8 messages are exported as byte arrays
+    let message = match record.get("MESSAGE")? {
+        Value::String(message) => message.clone(),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes.iter().filter_map(Value::as_u64).map(|byte| byte as u8).collect();
+            String::from_utf8_lossy(&bytes).to_string()
+        }
+        _ => return None,
+    };
+    let identifier = field("SYSLOG_IDENTIFIER").or_else(|| field("_COMM")).unwrap_or("unknown");
+    let timestamp = field("__REALTIME_TIMESTAMP")
+        .and_then(|micros| micros.parse::<i64>().ok())
+        .and_then(DateTime::from_timestamp_micros)
+        .unwrap_or_else(Utc::now);
+    let priority = field("PRIORITY").and_then(|priority| priority.parse().ok());
+    let pid = field("_PID").or_else(|| field("SYSLOG_PID")).and_then(|pid| pid.parse().ok());
+
+    let mut entry = classify(identifier, &message, priority, timestamp, pid);
+    let details = entry.details.get_or_insert_with(HashMap::new);
+    for (name, key) in [("_HOSTNAME", "hostname"), ("_SYSTEMD_UNIT", "unit"), ("_UID", "uid")] {
+        if let Some(value) = field(name) {
+            details.insert(key.to_string(), Value::from(value));
+        }
+    }
+    Some(entry)
+}
+
+// "May  1 10:00:00 host ident[pid]: message" as written by rsyslog's
+// traditional format, or with an RFC 3339 timestamp in its high-precision one
+pub fn parse_syslog_line(line: &str, now: DateTime<Utc>) -> Option<LogEntry> {
+    let (timestamp, rest) = match line.split_once(' ') {
+        Some((first, rest)) if first.contains('T') => {
+            (DateTime::parse_from_rfc3339(first).ok()?.with_timezone(&Utc), rest)
+        }
+        _ => {
+            // The month, day and time take the first 15 characters
+            let stamp = line.get(..15)?;
+            let naive = NaiveDateTime::parse_from_str(&format!("{} {}", now.year(), stamp), "%Y %b %e %H:%M:%S").ok()?;
+            let mut timestamp = Local.from_local_datetime(&naive).earliest()?.with_timezone(&Utc);
+            // December records read in January
+            if timestamp > now + chrono::Duration::days(1) {
+                let naive = naive.with_year(now.year() - 1)?;
+                timestamp = Local.from_local_datetime(&naive).earliest()?.with_timezone(&Utc);
+            }
+            (timestamp, line.get(16..)?)
+        }
+    };
+
+    let (hostname, rest) = rest.trim_start().split_once(' ')?;
+    let (tag, message) = rest.split_once(": ")?;
+    let (identifier, pid) = match tag.split_once('[') {
+        Some((identifier, pid)) => (identifier, pid.trim_end_matches(']').parse().ok()),
+        None => (tag, None),
+    };
+
+    let mut entry = classify(identifier, message, None, timestamp, pid);
+    entry.details.get_or_insert_with(HashMap::new).insert("hostname".to_string(), Value::from(hostname));
+    Some(entry)
+}
+
+// Maps a record to its category and level and pulls out the user, address
+// and command of the authentication messages the dashboard cares about
+fn classify(identifier: &str, message: &str, priority: Option<u8>, timestamp: DateTime<Utc>, pid: Option<u32>) -> LogEntry {
+    let mut level = priority.map(priority_level).unwrap_or(LogLevel::Info);
+    let mut category = LogCategory::System;
+    let mut tags = Vec::new();
+    let mut details: HashMap<String, Value> = HashMap::new();
+    let mut user = None;
+    let lower = message.to_lowercase();
+
+    if identifier.starts_with("sshd") {
+        category = LogCategory::Auth;
+        tags.push("ssh");
+        if let Some(rest) = message.strip_prefix("Accepted ") {
+            tags.push("login");
+            details.insert("auth_method".to_string(), Value::from(rest.split(' ').next().unwrap_or("")));
+            user = word_after(message, " for ");
+        } else if message.starts_with("Failed ") || message.starts_with("Invalid user ") {
+            tags.push("login_failure");
+            raise(&mut level, LogLevel::Warning);
+            user = word_after(message, "invalid user ")
+                .or_else(|| word_after(message, "Invalid user "))
+                .or_else(|| word_after(message, " for "));
+        }
+        if lower.contains("possible break-in attempt") || lower.contains("maximum authentication attempts exceeded") {
+            category = LogCategory::Security;
+            tags.push("brute_force");
+            raise(&mut level, LogLevel::Warning);
+        }
+        if let Some(ip) = word_after(message, " from ") {
+            details.insert("remote_ip".to_string(), Value::from(ip));
+        }
+        if let Some(port) = word_after(message, " port ").and_then(|port| port.parse::<u16>().ok()) {
+            details.insert("remote_port".to_string(), Value::from(port));
+        }
+    } else if identifier == "sudo" {
+        category = LogCategory::Auth;
+        tags.push("sudo");
+        // "alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/id"
+        if let Some((who, fields)) = message.split_once(" : ") {
+            user = Some(who.trim().to_string());
+            for field in fields.split(" ; ") {
+                match field.split_once('=') {
+                    Some(("USER", target)) => { details.insert("target_user".to_string(), Value::from(target)); }
+                    Some(("COMMAND", command)) => { details.insert("command".to_string(), Value::from(command)); }
+                    Some(("TTY", tty)) => { details.insert("tty".to_string(), Value::from(tty)); }
+                    _ => {}
+                }
+            }
+        }
+        if lower.contains("incorrect password") || lower.contains("not in sudoers") || lower.contains("authentication failure") {
+            tags.push("privilege_escalation_failure");
+            raise(&mut level, LogLevel::Warning);
+        }
+    } else if AUTH_IDENTIFIERS.contains(&identifier) {
+        category = LogCategory::Auth;
+        if lower.contains("authentication failure") || lower.contains("failed") {
+            tags.push("login_failure");
+            raise(&mut level, LogLevel::Warning);
+        }
+        if let Some(name) = word_after(message, "user ").or_else(|| word_after(message, "name=")) {
+            user = Some(name.trim_matches(|c| c == '\'' || c == '"' || c == '.' || c == ',').to_string());
+        }
+    } else if identifier == "kernel" {
+        if message.contains(" IN=") && message.contains(" OUT=") {
+            // Netfilter LOG targets, including UFW's "[UFW BLOCK]"
+            category = LogCategory::Network;
+            tags.push("firewall");
+            for (key, name) in [("SRC=", "source_ip"), ("DST=", "dest_ip"), ("PROTO=", "protocol"), ("DPT=", "dest_port")] {
+                if let Some(value) = message.split(' ').find_map(|word| word.strip_prefix(key)) {
+                    details.insert(name.to_string(), Value::from(value));
+                }
+            }
+            if let Some((verdict, rule_id)) = parse_netfilter_log(message) {
+                tags.push("shadow_rule");
+                details.insert("shadow_verdict".to_string(), Value::from(verdict));
+                details.insert("rule_id".to_string(), Value::from(rule_id));
+            }
+        } else if lower.contains("apparmor=\"denied\"") || lower.contains("avc:  denied") || message.starts_with("audit:") {
+            category = LogCategory::Security;
+            tags.push("mac_denial");
+            raise(&mut level, LogLevel::Warning);
+        }
+    } else if NETWORK_IDENTIFIERS.contains(&identifier) {
+        category = LogCategory::Network;
+    } else if identifier.starts_with("systemd") && (message.starts_with("Failed ") || lower.contains(": failed with result")) {
+        raise(&mut level, LogLevel::Error);
+    }
+
+    LogEntry {
+        id: Uuid::new_v4().to_string(),
+        timestamp,
+        level,
+        category,
+        source: identifier.to_string(),
+        message: message.to_string(),
+        details: (!details.is_empty()).then_some(details),
+        user,
+        pid,
+        tags: (!tags.is_empty()).then(|| tags.into_iter().map(String::from).collect()),
+    }
+}
+
+// syslog severities: 0 emerg .. 7 debug
+fn priority_level(priority: u8) -> LogLevel {
+    match priority {
+        0..=2 => LogLevel::Critical,
+        3 => LogLevel::Error,
+        4 => LogLevel::Warning,
+        5 | 6 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+fn raise(level: &mut LogLevel, to: LogLevel) {
+    if rank(&to) > rank(level) {
+        *level = to;
+    }
+}
+
+fn rank(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::Debug => 0,
+        LogLevel::Info => 1,
+        LogLevel::Warning => 2,
+        LogLevel::Error => 3,
+        LogLevel::Critical => 4,
+    }
+}
+
+fn word_after(message: &str, marker: &str) -> Option<String> {
+    let start = message.find(marker)? + marker.len();
+    message[start..].split_whitespace().next().map(String::from)
+}
+
+pub fn live_event(entry: &LogEntry) -> LiveEvent {
+    let severity = match entry.level {
+        LogLevel::Debug | LogLevel::Info => "info",
+        LogLevel::Warning => "warning",
+        LogLevel::Error => "error",
+        LogLevel::Critical => "critical",
+    };
+    let category = serde_json::to_value(&entry.category)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default();
+    let mut details = entry.details.clone().unwrap_or_default();
+    details.insert("log_id".to_string(), Value::from(entry.id.clone()));
+    if let Some(pid) = entry.pid {
+        details.insert("process_id".to_string(), Value::from(pid));
+    }
+    LiveEvent {
+        id: Uuid::new_v4().to_string(),
+        timestamp: entry.timestamp,
+        event_type: category,
+        severity: severity.to_string(),
+        title: entry.source.clone(),
+        description: entry.message.clone(),
+        source: entry.source.clone(),
+        details,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_classify_records() {
+        let journal = r#"{"__REALTIME_TIMESTAMP":"1714557600000000","PRIORITY":"6","SYSLOG_IDENTIFIER":"sshd","_PID":"812","_HOSTNAME":"web1","MESSAGE":"Failed password for invalid user admin from 203.0.113.7 port 52144 ssh2"}"#;
+        let entry = parse_journal_record(journal).unwrap();
+        assert_eq!((entry.category, entry.level), (LogCategory::Auth, LogLevel::Warning));
+        assert_eq!(entry.timestamp, DateTime::from_timestamp(1714557600, 0).unwrap());
+        assert_eq!((entry.user.as_deref(), entry.pid), (Some("admin"), Some(812)));
+        let details = entry.details.unwrap();
+        assert_eq!(details["remote_ip"], "203.0.113.7");
+        assert_eq!(details["remote_port"], 52144);
+        assert_eq!(details["hostname"], "web1");
+
+        let sudo = r#"{"PRIORITY":"5","SYSLOG_IDENTIFIER":"sudo","MESSAGE":"alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update"}"#;
+        let entry = parse_journal_record(sudo).unwrap();
+        assert_eq!((entry.category, entry.level, entry.user.as_deref()), (LogCategory::Auth, LogLevel::Info, Some("alice")));
+        assert_eq!(entry.details.unwrap()["command"], "/usr/bin/apt update");
+
+        let failed_unit = r#"{"PRIORITY":"6","SYSLOG_IDENTIFIER":"systemd","MESSAGE":"nginx.service: Failed with result 'exit-code'."}"#;
+        assert_eq!(parse_journal_record(failed_unit).unwrap().level, LogLevel::Error);
+        assert!(parse_journal_record("not json").is_none());
+
+        let now = Utc::now();
+        let line = "May  1 10:00:00 web1 kernel: [UFW BLOCK] IN=eth0 OUT= SRC=198.51.100.4 DST=10.0.0.5 PROTO=TCP SPT=4444 DPT=22";
+        let entry = parse_syslog_line(line, now).unwrap();
+        assert_eq!((entry.category, entry.source.as_str()), (LogCategory::Network, "kernel"));
+        assert_eq!(entry.details.as_ref().unwrap()["source_ip"], "198.51.100.4");
+        assert!(entry.timestamp <= now + chrono::Duration::days(1));
+        let line = "May  1 10:00:00 web1 kernel: flux-shadow:drop:trial_ssh IN=eth0 OUT= SRC=198.51.100.4 DST=10.0.0.5 PROTO=TCP DPT=22";
+        let entry = parse_syslog_line(line, now).unwrap();
+        assert_eq!(entry.tags.unwrap(), vec!["firewall", "shadow_rule"]);
+        assert_eq!(entry.details.unwrap()["rule_id"], "trial_ssh");
+
+        let line = "2024-05-01T10:00:00.123456+00:00 web1 sshd[901]: Accepted publickey for bob from 10.0.0.9 port 40022 ssh2: ED25519 SHA256:abc";
+        let entry = parse_syslog_line(line, now).unwrap();
+        assert_eq!((entry.user.as_deref(), entry.pid, entry.level), (Some("bob"), Some(901), LogLevel::Info));
+        assert_eq!(entry.tags.unwrap(), vec!["ssh", "login"]);
+        assert!(parse_syslog_line("garbage", now).is_none());
+    }
+}
@@ -2,6 +2,8 @@ use crate::api::models::{
     SystemResources, CpuInfo, MemoryInfo, DiskInfo, NetworkInfo, 
     NetworkInterface, ProcessInfo, SystemMetrics
 };
+use crate::system_metrics::SystemMetricsCollector;
+use crate::redaction::Redactor;
 use sysinfo::{System, Disks, Networks};
 use chrono::{DateTime, Utc};
 use std::time::SystemTime;
@@ -10,6 +12,10 @@ pub struct SystemMonitor {
     system: System,
     disks: Disks,
     networks: Networks,
+    // Per-disk I/O, temperatures and GPUs, which sysinfo does not cover
+    hardware: SystemMetricsCollector,
+    // Applied to process command lines before they are served
+    redactor: Redactor,
 }
 
 impl SystemMonitor {
@@ -18,7 +24,11 @@ impl SystemMonitor {
         system.refresh_all();
         let disks = Disks::new_with_refreshed_list();
         let networks = Networks::new_with_refreshed_list();
-        Self { system, disks, networks }
+        Self { system, disks, networks, hardware: SystemMetricsCollector::new(), redactor: Redactor::default() }
+    }
+
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.redactor = redactor;
     }
 
     pub fn refresh(&mut self) {
@@ -29,7 +39,25 @@ impl SystemMonitor {
 
     pub fn get_system_metrics(&mut self) -> SystemMetrics {
         self.refresh();
-        
+        let mut metrics = self.current_metrics();
+        metrics.disks = self.hardware.collect_disks();
+        metrics.temperatures = self.hardware.collect_temperatures();
+        metrics.gpus = self.hardware.collect_gpus();
+        metrics
+    }
+
+    // Usage plus bytes received and sent on all interfaces since boot, with a
+    // refresh that skips the process table so it can run every second
+    pub fn sample_usage(&mut self) -> (SystemMetrics, u64, u64) {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        self.disks.refresh();
+        self.networks.refresh();
+        let network = self.get_network_info();
+        (self.current_metrics(), network.bytes_in, network.bytes_out)
+    }
+
+    fn current_metrics(&self) -> SystemMetrics {
         let cpu_usage = self.system.global_cpu_info().cpu_usage();
         let memory_used = self.system.used_memory();
         let memory_total = self.system.total_memory();
@@ -66,6 +94,9 @@ impl SystemMonitor {
             disk_usage,
             load_average: vec![load_average.one, load_average.five, load_average.fifteen],
             uptime,
+            disks: Vec::new(),
+            temperatures: Vec::new(),
+            gpus: Vec::new(),
         }
     }
 
@@ -204,7 +235,7 @@ impl SystemMonitor {
                     pid: pid.as_u32(),
                     ppid: process.parent().map_or(0, |p| p.as_u32()),
                     name: process.name().to_string(),
-                    command: process.cmd().join(" "),
+                    command: self.redactor.redact(&process.cmd().join(" ")).into_owned(),
                     user: process.user_id().map(|uid| uid.to_string()).unwrap_or_else(|| "unknown".to_string()),
                     cpu_percent: process.cpu_usage(),
                     memory_percent: (process.memory() as f32 / (1024.0 * 1024.0 * 1024.0)) * 100.0, // Convert to percentage
@@ -246,7 +277,7 @@ impl SystemMonitor {
                 pid,
                 ppid: process.parent().map_or(0, |p| p.as_u32()),
                 name: process.name().to_string(),
-                command: process.cmd().join(" "),
+                command: self.redactor.redact(&process.cmd().join(" ")).into_owned(),
                 user: process.user_id().map(|uid| uid.to_string()).unwrap_or_else(|| "unknown".to_string()),
                 cpu_percent: process.cpu_usage(),
                 memory_percent: (process.memory() as f32 / (1024.0 * 1024.0 * 1024.0)) * 100.0,
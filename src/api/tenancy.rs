@@ -0,0 +1,232 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::api::models::ApiResponse;
+use crate::api::handlers::{token_digest, AppState};
+use crate::fleet::protocol::{ENROLL_PATH, EVENTS_PATH, HEARTBEAT_PATH};
+use crate::incidents::Incident;
+
+// What a tenant token may reach; everything else describes the server host
+// itself or acts on it, and stays with admin tokens
+const TENANT_ROUTES: &[&str] = &[
+    "/api/fleet/agents",
+    "/api/fleet/events",
+    "/api/fleet/policy",
+    "/api/incidents",
+    "/api/stix",
+    "/api/tenants",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantDefinition {
+    pub id: String,
+    pub name: String,
+    // Agents enrolling with one of these belong to the tenant
+    #[serde(default)]
+    pub enrollment_tokens: Vec<String>,
+    // Bearer tokens whose requests are limited to the tenant's data
+    #[serde(default)]
+    pub api_tokens: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantsConfig {
+    pub tenants: Vec<TenantDefinition>,
+}
+
+impl TenantsConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tenants config {:?}", path))?;
+        let config: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid tenants config {:?}", path))?;
+        config.validate().with_context(|| format!("Invalid tenants config {:?}", path))?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let mut ids = HashSet::new();
+        let mut tokens = HashSet::new();
+        for tenant in &self.tenants {
+            if tenant.id.is_empty() {
+                bail!("Tenant {:?} has no id", tenant.name);
+            }
+            if !ids.insert(tenant.id.as_str()) {
+                bail!("Tenant id {} is used twice", tenant.id);
+            }
+            for token in tenant.enrollment_tokens.iter().chain(&tenant.api_tokens) {
+                if !tokens.insert(token.as_str()) {
+                    bail!("A token of tenant {} is also used elsewhere", tenant.id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantSummary {
+    pub id: String,
+    pub name: String,
+}
+
+// Tenants and the API tokens bound to them; tokens are only kept as digests
+pub struct TenantDirectory {
+    tenants: Vec<TenantSummary>,
+    api_tokens: HashMap<String, String>,
+}
+
+impl TenantDirectory {
+    pub fn new(config: &TenantsConfig) -> Result<Self> {
+        config.validate()?;
+        let api_tokens = config.tenants.iter()
+            .flat_map(|tenant| tenant.api_tokens.iter().filter(|t| !t.is_empty()).map(|token| (token_digest(token), tenant.id.clone())))
+            .collect();
+        Ok(Self {
+            tenants: config.tenants.iter().map(|tenant| TenantSummary { id: tenant.id.clone(), name: tenant.name.clone() }).collect(),
+            api_tokens,
+        })
+    }
+
+    pub fn tenant_for_token(&self, token: &str) -> Option<&str> {
+        self.api_tokens.get(&token_digest(token)).map(String::as_str)
+    }
+
+    pub fn tenants(&self) -> &[TenantSummary] {
+        &self.tenants
+    }
+}
+
+// Who a request was authenticated as, once tenants are configured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caller {
+    Admin,
+    Tenant(String),
+}
+
+// The tenant a handler must limit its answer to; None sees everything
+pub fn caller_tenant(caller: &Option<Extension<Caller>>) -> Option<&str> {
+    match caller {
+        Some(Extension(Caller::Tenant(tenant))) => Some(tenant),
+        _ => None,
+    }
+}
+
+// Incidents belong to the tenant of the fleet agent they were raised on;
+// those of the server host itself are never a tenant's
+pub fn incident_in_scope(state: &AppState, tenant: Option<&str>, incident: &Incident) -> bool {
+    let Some(tenant) = tenant else { return true };
+    match (state.fleet.as_ref(), incident.host.as_deref()) {
+        (Some(fleet), Some(host)) => fleet.tenant_of(host).as_deref() == Some(tenant),
+        _ => false,
+    }
+}
+
+// Agents authenticate themselves, and probes must work without a token
+fn is_public(method: &Method, path: &str) -> bool {
+    matches!(path, "/api/health" | "/api/ready")
+        || path == ENROLL_PATH
+        || path == HEARTBEAT_PATH
+        || (path == EVENTS_PATH && method == Method::POST)
+}
+
+// A route in TENANT_ROUTES or below it, compared by whole path segments so
+// that e.g. /api/incidentsX is not taken for /api/incidents
+fn is_tenant_route(path: &str) -> bool {
+    TENANT_ROUTES.iter().any(|route| {
+        path.strip_prefix(route).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+// Applied with axum::middleware::from_fn_with_state; a no-op unless
+// AppState::tenants is set. Admin tokens see everything, tenant tokens
+// only their own agents, events, policies and incidents
+pub async fn tenant_access(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let Some(tenants) = state.tenants.clone() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    let protected = path.starts_with("/api/") || path == "/ws";
+    if !protected || is_public(request.method(), path) {
+        return next.run(request).await;
+    }
+
+    let token = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let caller = match token {
+        Some(token) if state.is_admin_token(token) => Caller::Admin,
+        Some(token) => match tenants.tenant_for_token(token) {
+            Some(tenant) => Caller::Tenant(tenant.to_string()),
+            None => return StatusCode::UNAUTHORIZED.into_response(),
+        },
+        None => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    if matches!(caller, Caller::Tenant(_)) && !is_tenant_route(path) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+// The caller's own tenant, or every tenant for admins
+pub async fn get_tenants(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Extension<Caller>>,
+) -> Result<Json<ApiResponse<Vec<TenantSummary>>>, StatusCode> {
+    let tenants = state.tenants.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let tenant = caller_tenant(&caller);
+    let visible = tenants.tenants().iter()
+        .filter(|summary| tenant.is_none_or(|tenant| summary.id == tenant))
+        .cloned()
+        .collect();
+    Ok(Json(ApiResponse::success(visible)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_directory() {
+        let tenant = |id: &str, token: &str| TenantDefinition {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            enrollment_tokens: vec![format!("enroll-{}", id)],
+            api_tokens: vec![token.to_string()],
+        };
+        let config = TenantsConfig { tenants: vec![tenant("blue", "blue-token"), tenant("red", "red-token")] };
+        let directory = TenantDirectory::new(&config).unwrap();
+        assert_eq!(directory.tenant_for_token("red-token"), Some("red"));
+        assert_eq!(directory.tenant_for_token("green-token"), None);
+        assert_eq!(directory.tenants().len(), 2);
+
+        assert_eq!(caller_tenant(&Some(Extension(Caller::Tenant("blue".to_string())))), Some("blue"));
+        assert_eq!(caller_tenant(&Some(Extension(Caller::Admin))), None);
+
+        assert!(is_public(&Method::POST, EVENTS_PATH));
+        assert!(!is_public(&Method::GET, EVENTS_PATH));
+
+        assert!(is_tenant_route("/api/incidents"));
+        assert!(is_tenant_route("/api/incidents/inc-1/notes"));
+        assert!(!is_tenant_route("/api/incidentsX"));
+        assert!(!is_tenant_route("/api/fleet/agents-admin"));
+
+        // A token shared between tenants would make scoping ambiguous
+        let clash = TenantsConfig { tenants: vec![tenant("blue", "same"), tenant("red", "same")] };
+        assert!(TenantDirectory::new(&clash).is_err());
+        let duplicate = TenantsConfig { tenants: vec![tenant("blue", "a"), tenant("blue", "b")] };
+        assert!(duplicate.validate().is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result, Context};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use tracing::info;
+
+// TLS termination for the API server. When `client_ca_path` is set, client
+// certificates signed by that CA are verified; `require_client_cert` turns
+// this into full mutual TLS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+    #[serde(default)]
+    pub require_client_cert: bool,
+}
+
+impl TlsSettings {
+    // FLUX_TLS_CERT and FLUX_TLS_KEY enable TLS; FLUX_TLS_CLIENT_CA and
+    // FLUX_TLS_CLIENT_AUTH=required|optional configure client verification
+    pub fn from_env() -> Result<Option<Self>> {
+        let cert = std::env::var("FLUX_TLS_CERT").ok();
+        let key = std::env::var("FLUX_TLS_KEY").ok();
+
+        let (cert_path, key_path) = match (cert, key) {
+            (Some(cert), Some(key)) => (PathBuf::from(cert), PathBuf::from(key)),
+            (None, None) => return Ok(None),
+            _ => return Err(anyhow!("FLUX_TLS_CERT and FLUX_TLS_KEY must be set together")),
+        };
+
+        let client_ca_path = std::env::var("FLUX_TLS_CLIENT_CA").ok().map(PathBuf::from);
+        let require_client_cert = match std::env::var("FLUX_TLS_CLIENT_AUTH").as_deref() {
+            Ok("required") => true,
+            Ok("optional") | Err(_) => client_ca_path.is_some(),
+            Ok(other) => return Err(anyhow!("Invalid FLUX_TLS_CLIENT_AUTH value: {}", other)),
+        };
+
+        if require_client_cert && client_ca_path.is_none() {
+            return Err(anyhow!("FLUX_TLS_CLIENT_AUTH=required needs FLUX_TLS_CLIENT_CA"));
+        }
+
+        Ok(Some(Self { cert_path, key_path, client_ca_path, require_client_cert }))
+    }
+
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match &self.client_ca_path {
+            Some(ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca_path)? {
+                    roots.add(cert)
+                        .with_context(|| format!("Invalid CA certificate in {:?}", ca_path))?;
+                }
+
+                let mut verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                if !self.require_client_cert {
+                    verifier = verifier.allow_unauthenticated();
+                }
+                info!("Verifying API client certificates against {:?} (required: {})", ca_path, self.require_client_cert);
+                builder.with_client_cert_verifier(verifier.build()?)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(load_certs(&self.cert_path)?, load_private_key(&self.key_path)?)
+            .context("Server certificate and key do not match")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open certificate file {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates in {:?}", path))?;
+
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {:?}", path));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open private key file {:?}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {:?}", path))?
+        .ok_or_else(|| anyhow!("No private key found in {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_missing_pem_material() {
+        let path = std::env::temp_dir().join(format!("fluxdefense-tls-test-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a certificate\n").unwrap();
+
+        let settings = TlsSettings {
+            cert_path: path.clone(),
+            key_path: path.clone(),
+            client_ca_path: None,
+            require_client_cert: false,
+        };
+        let err = settings.server_config().unwrap_err().to_string();
+        assert!(err.contains("No certificates found"), "{}", err);
+        assert!(load_private_key(&path).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
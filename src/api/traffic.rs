@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::api::dns_telemetry::DnsRecorder;
+use crate::api::handlers::AppState;
+use crate::api::models::{ApiResponse, NetworkConnection};
+use crate::incidents::{CorrelationSignal, IncidentSeverity};
+use crate::linux_security::{BeaconDetection, FlowRecord, LanStatus, NetworkEvent, NetworkFilter};
+
+type FlowKey = (u8, IpAddr, u16, IpAddr, u16);
+
+const DEFAULT_DETECTION_LIMIT: usize = 100;
+
+// With FLUX_CAPTURE_INTERFACE set (an interface name, `any` or a prefix like
+// `eth*`), captures traffic there without filtering it: DNS queries go to
+// /api/network/dns and per-connection counters to /api/network/connections.
+// Beaconing found in the captured connections is raised as an incident, as
+// are ARP spoofing and DHCP replies from servers outside FLUX_DHCP_SERVERS
+// (a comma-separated list; without it the first server seen is trusted).
+// ICMP and DNS tunneling detections also go through the correlation rules.
+// The returned capture must be kept alive for as long as it should run.
+pub fn start_traffic_capture(state: &mut AppState) -> Result<Option<NetworkFilter>> {
+    let configured = state.config.as_ref().map(|config| config.current().capture_interfaces.join(","));
+    let Some(interfaces) = std::env::var("FLUX_CAPTURE_INTERFACE").ok().or(configured).filter(|interfaces| !interfaces.is_empty()) else {
+        return Ok(None);
+    };
+    let recorder = DnsRecorder::new(Arc::clone(&state.dns_queries), state.socket_index.clone());
+    let incidents = Arc::clone(&state.incidents);
+    let correlator = state.correlator.clone();
+    let mut capture = NetworkFilter::new(move |event| {
+        match event {
+            NetworkEvent::Beaconing { ref detection, .. } => {
+                incidents.record_correlation(None, CorrelationSignal {
+                    rule_id: "network_beaconing".to_string(),
+                    rule_name: "Outbound beaconing".to_string(),
+                    description: detection.description(),
+                    severity: IncidentSeverity::High,
+                    detected_at: detection.detected_at,
+                    events: Vec::new(),
+                });
+            }
+            NetworkEvent::Tunneling { ref detection, .. } => {
+                let event = detection.to_security_event();
+                incidents.record_correlation(None, CorrelationSignal {
+                    rule_id: detection.kind.rule_id().to_string(),
+                    rule_name: detection.kind.rule_name().to_string(),
+                    description: detection.description.clone(),
+                    severity: IncidentSeverity::High,
+                    detected_at: detection.timestamp,
+                    events: vec![event.clone()],
+                });
+                if let Some(correlated) = correlator.as_ref().and_then(|correlator| correlator.process_event(event)) {
+                    incidents.record_correlation(None, CorrelationSignal::from(&correlated));
+                }
+            }
+            NetworkEvent::LanAnomaly { ref alert, .. } => {
+                incidents.record_correlation(None, CorrelationSignal {
+                    rule_id: alert.kind.rule_id().to_string(),
+                    rule_name: alert.kind.rule_id().to_string(),
+                    description: alert.description.clone(),
+                    severity: IncidentSeverity::High,
+                    detected_at: alert.timestamp,
+                    events: Vec::new(),
+                });
+            }
+            _ => {}
+        }
+        recorder.record_network_event(&event);
+    })?;
+    capture.set_socket_index(state.socket_index.clone());
+    if let Ok(servers) = std::env::var("FLUX_DHCP_SERVERS") {
+        let servers = servers.split(',')
+            .map(|server| server.trim().parse().with_context(|| format!("Invalid DHCP server address '{}'", server.trim())))
+            .collect::<Result<Vec<_>>>()?;
+        capture.lan_monitor().set_authorized_dhcp_servers(servers);
+    }
+    capture.set_filtering_enabled(false);
+    capture.set_dns_filtering_enabled(true);
+    capture.start()?;
+    capture.start_capture_interfaces(interfaces.split(',').map(|name| name.trim().to_string()).collect())?;
+    state.connection_table = Some(capture.connection_table());
+    state.lan = Some(capture.lan_monitor());
+    info!("Accounting traffic captured on {}", interfaces);
+    Ok(Some(capture))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DetectionQuery {
+    pub limit: Option<usize>,
+}
+
+// /api/network/beacons; not found unless traffic is being captured
+pub async fn get_network_beacons(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DetectionQuery>,
+) -> Result<Json<ApiResponse<Vec<BeaconDetection>>>, StatusCode> {
+    let table = state.connection_table.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(table.beacons(query.limit.unwrap_or(DEFAULT_DETECTION_LIMIT)))))
+}
+
+// /api/network/lan: the ARP table, DHCP servers and recent alerts
+pub async fn get_lan_status(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DetectionQuery>,
+) -> Result<Json<ApiResponse<LanStatus>>, StatusCode> {
+    let lan = state.lan.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(lan.status(query.limit.unwrap_or(DEFAULT_DETECTION_LIMIT)))))
+}
+
+// Fills in bytes, packets and duration of socket-table rows from the
+// captured flows; each row's source is the local end of the connection
+pub fn apply_flow_counters(connections: &mut [NetworkConnection], flows: &[FlowRecord]) {
+    if flows.is_empty() {
+        return;
+    }
+    let flows: HashMap<FlowKey, &FlowRecord> = flows.iter()
+        .map(|flow| ((flow.protocol, flow.src_addr, flow.src_port, flow.dst_addr, flow.dst_port), flow))
+        .collect();
+    let now = SystemTime::now();
+
+    for connection in connections {
+        let protocol = connection.protocol.to_ascii_lowercase();
+        let protocol = if protocol.starts_with("tcp") {
+            6
+        } else if protocol.starts_with("udp") {
+            17
+        } else {
+            continue;
+        };
+        let (Ok(local), Ok(remote)) = (connection.source_ip.parse::<IpAddr>(), connection.dest_ip.parse::<IpAddr>()) else {
+            continue;
+        };
+        let outbound = flows.get(&(protocol, local, connection.source_port, remote, connection.dest_port));
+        let inbound = flows.get(&(protocol, remote, connection.dest_port, local, connection.source_port));
+        if outbound.is_none() && inbound.is_none() {
+            continue;
+        }
+
+        connection.bytes_out = outbound.map_or(0, |flow| flow.bytes);
+        connection.bytes_in = inbound.map_or(0, |flow| flow.bytes);
+        let packets = outbound.map_or(0, |flow| flow.packets) + inbound.map_or(0, |flow| flow.packets);
+        connection.packets = u32::try_from(packets).unwrap_or(u32::MAX);
+        let start = outbound.into_iter().chain(inbound).map(|flow| flow.start).min().unwrap_or(now);
+        connection.duration = now.duration_since(start).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use chrono::Utc;
+
+    #[test]
+    fn test_apply_flow_counters() {
+        let row = |protocol: &str, source: &str, source_port, dest: &str, dest_port| NetworkConnection {
+            id: "c".to_string(),
+            timestamp: Utc::now(),
+            protocol: protocol.to_string(),
+            source_ip: source.to_string(),
+            source_port,
+            dest_ip: dest.to_string(),
+            dest_port,
+            status: "ESTABLISHED".to_string(),
+            bytes_in: 0,
+            bytes_out: 0,
+            packets: 0,
+            duration: 0,
+            process: "curl".to_string(),
+            pid: 42,
+        };
+        let now = SystemTime::now();
+        let flow = |protocol, src: &str, src_port, dst: &str, dst_port, packets, bytes, age| FlowRecord {
+            protocol,
+            src_addr: src.parse().unwrap(),
+            src_port,
+            dst_addr: dst.parse().unwrap(),
+            dst_port,
+            packets,
+            bytes,
+            start: now - Duration::from_secs(age),
+            end: now,
+        };
+        let flows = vec![
+            flow(6, "10.0.0.5", 40000, "93.184.216.34", 443, 10, 1200, 30),
+            flow(6, "93.184.216.34", 443, "10.0.0.5", 40000, 12, 48000, 29),
+            flow(17, "2001:db8::5", 5353, "2001:db8::1", 53, 1, 80, 5),
+        ];
+
+        let mut connections = vec![
+            row("TCP", "10.0.0.5", 40000, "93.184.216.34", 443),
+            row("UDP6", "2001:db8::5", 5353, "2001:db8::1", 53),
+            // Same ports, different protocol
+            row("UDP", "10.0.0.5", 40000, "93.184.216.34", 443),
+        ];
+        apply_flow_counters(&mut connections, &flows);
+
+        let tcp = &connections[0];
+        assert_eq!((tcp.bytes_out, tcp.bytes_in, tcp.packets), (1200, 48000, 22));
+        assert!((29..=31).contains(&tcp.duration), "{}", tcp.duration);
+        assert_eq!((connections[1].bytes_out, connections[1].bytes_in, connections[1].packets), (80, 0, 1));
+        assert_eq!((connections[2].bytes_out, connections[2].packets), (0, 0));
+    }
+}
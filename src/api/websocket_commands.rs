@@ -0,0 +1,251 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::handlers::AppState;
+use crate::api::models::{CommandError, CommandErrorCode, StatsSnapshot, WebSocketMessage};
+use crate::incidents::IncidentStatus;
+
+const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_METRICS_INTERVAL_SECS: u64 = 300;
+const COMMANDS: [&str; 5] = ["pause", "resume", "set_metrics_interval", "snapshot", "ack_alert"];
+
+// Pushed streams a client can pause; heartbeats and command results always flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Metrics,
+    Logs,
+    Incidents,
+    Scans,
+}
+
+const ALL_STREAMS: [Stream; 4] = [Stream::Metrics, Stream::Logs, Stream::Incidents, Stream::Scans];
+
+// Text frames from the client, e.g.
+//   {"command": "pause", "streams": ["logs"], "request_id": "1"}
+//   {"command": "set_metrics_interval", "seconds": 1}
+//   {"command": "snapshot"}
+//   {"command": "ack_alert", "id": "...", "note": "looking into it"}
+// Every command is answered with a CommandResult message.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ClientCommand {
+    // No streams pauses or resumes all of them
+    Pause {
+        #[serde(default)]
+        streams: Vec<Stream>,
+    },
+    Resume {
+        #[serde(default)]
+        streams: Vec<Stream>,
+    },
+    SetMetricsInterval { seconds: u64 },
+    Snapshot,
+    // An incident or threat detection id
+    AckAlert {
+        id: String,
+        #[serde(default)]
+        note: Option<String>,
+    },
+}
+
+// What one connection's commands have changed
+pub struct Session {
+    paused: HashSet<Stream>,
+    metrics_interval: Duration,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            paused: HashSet::new(),
+            metrics_interval: DEFAULT_METRICS_INTERVAL,
+        }
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self, stream: Stream) -> bool {
+        self.paused.contains(&stream)
+    }
+
+    pub fn metrics_interval(&self) -> Duration {
+        self.metrics_interval
+    }
+
+    // Runs one client message and builds the reply to send back
+    pub fn handle(&mut self, state: &AppState, text: &str) -> WebSocketMessage {
+        let message: Value = match serde_json::from_str(text) {
+            Ok(value @ Value::Object(_)) => value,
+            Ok(_) => return failure(None, "", CommandErrorCode::InvalidMessage, "Expected a JSON object".to_string()),
+            Err(e) => return failure(None, "", CommandErrorCode::InvalidMessage, format!("Invalid JSON: {}", e)),
+        };
+        let request_id = message.get("request_id").and_then(Value::as_str).map(String::from);
+        let Some(name) = message.get("command").and_then(Value::as_str).map(String::from) else {
+            return failure(request_id, "", CommandErrorCode::InvalidMessage, "Missing `command`".to_string());
+        };
+        if !COMMANDS.contains(&name.as_str()) {
+            return failure(request_id, &name, CommandErrorCode::UnknownCommand, format!("Unknown command '{}'", name));
+        }
+        let command = match serde_json::from_value::<ClientCommand>(message) {
+            Ok(command) => command,
+            Err(e) => return failure(request_id, &name, CommandErrorCode::InvalidArgument, e.to_string()),
+        };
+
+        match self.run(state, command) {
+            Ok(data) => WebSocketMessage::CommandResult {
+                request_id,
+                command: name,
+                success: true,
+                data: Some(data),
+                error: None,
+            },
+            Err(error) => WebSocketMessage::CommandResult {
+                request_id,
+                command: name,
+                success: false,
+                data: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    fn run(&mut self, state: &AppState, command: ClientCommand) -> Result<Value, CommandError> {
+        match command {
+            ClientCommand::Pause { streams } => {
+                self.paused.extend(or_all(streams));
+                Ok(self.paused_streams())
+            }
+            ClientCommand::Resume { streams } => {
+                for stream in or_all(streams) {
+                    self.paused.remove(&stream);
+                }
+                Ok(self.paused_streams())
+            }
+            ClientCommand::SetMetricsInterval { seconds } => {
+                if !(1..=MAX_METRICS_INTERVAL_SECS).contains(&seconds) {
+                    return Err(error(
+                        CommandErrorCode::InvalidArgument,
+                        format!("Interval must be between 1 and {} seconds", MAX_METRICS_INTERVAL_SECS),
+                    ));
+                }
+                self.metrics_interval = Duration::from_secs(seconds);
+                Ok(json!({ "seconds": seconds }))
+            }
+            ClientCommand::Snapshot => {
+                let snapshot = StatsSnapshot {
+                    system: state.system_monitor.lock().unwrap().get_system_metrics(),
+                    processes: state.process_stats.lock().unwrap().clone(),
+                    network_connections: state.network_connections.lock().unwrap().len(),
+                    open_incidents: state.incidents.list(None).iter().filter(|incident| incident.status.is_active()).count(),
+                    threat_detections: state.threat_detections.lock().unwrap().len(),
+                };
+                to_value(&snapshot)
+            }
+            ClientCommand::AckAlert { id, note } => acknowledge(state, &id, note),
+        }
+    }
+
+    fn paused_streams(&self) -> Value {
+        let mut paused: Vec<Stream> = self.paused.iter().copied().collect();
+        paused.sort();
+        json!({ "paused": paused })
+    }
+}
+
+// Open incidents move to investigating; detections are marked acknowledged
+fn acknowledge(state: &AppState, id: &str, note: Option<String>) -> Result<Value, CommandError> {
+    if let Some(incident) = state.incidents.get(id) {
+        return match incident.status {
+            IncidentStatus::Open => {
+                let note = note.or_else(|| Some("Acknowledged from the live view".to_string()));
+                let incident = state.incidents.update_status(id, IncidentStatus::Investigating, note)
+                    .map_err(|e| error(CommandErrorCode::Internal, e.to_string()))?;
+                to_value(&incident)
+            }
+            IncidentStatus::Investigating => to_value(&incident),
+            status => Err(error(CommandErrorCode::InvalidState, format!("Incident {} is already {:?}", id, status))),
+        };
+    }
+
+    let mut detections = state.threat_detections.lock().unwrap();
+    let detection = detections.iter_mut()
+        .find(|detection| detection.id == id)
+        .ok_or_else(|| error(CommandErrorCode::NotFound, format!("No incident or detection {}", id)))?;
+    detection.status = "acknowledged".to_string();
+    to_value(&*detection)
+}
+
+fn or_all(streams: Vec<Stream>) -> Vec<Stream> {
+    if streams.is_empty() { ALL_STREAMS.to_vec() } else { streams }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<Value, CommandError> {
+    serde_json::to_value(value).map_err(|e| error(CommandErrorCode::Internal, e.to_string()))
+}
+
+fn error(code: CommandErrorCode, message: String) -> CommandError {
+    CommandError { code, message }
+}
+
+fn failure(request_id: Option<String>, command: &str, code: CommandErrorCode, message: String) -> WebSocketMessage {
+    WebSocketMessage::CommandResult {
+        request_id,
+        command: command.to_string(),
+        success: false,
+        data: None,
+        error: Some(error(code, message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(session: &mut Session, state: &AppState, text: &str) -> (bool, Option<Value>, Option<CommandErrorCode>) {
+        match session.handle(state, text) {
+            WebSocketMessage::CommandResult { success, data, error, .. } => (success, data, error.map(|error| error.code)),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_websocket_commands() {
+        let state = AppState::new();
+        let mut session = Session::new();
+
+        let (success, data, _) = reply(&mut session, &state, r#"{"command":"pause","streams":["logs","metrics"]}"#);
+        assert!(success);
+        assert_eq!(data.unwrap()["paused"], json!(["metrics", "logs"]));
+        assert!(session.is_paused(Stream::Logs) && !session.is_paused(Stream::Scans));
+        reply(&mut session, &state, r#"{"command":"resume"}"#);
+        assert!(!session.is_paused(Stream::Logs));
+
+        assert!(reply(&mut session, &state, r#"{"command":"set_metrics_interval","seconds":2}"#).0);
+        assert_eq!(session.metrics_interval(), Duration::from_secs(2));
+        let (_, _, code) = reply(&mut session, &state, r#"{"command":"set_metrics_interval","seconds":0}"#);
+        assert_eq!(code, Some(CommandErrorCode::InvalidArgument));
+        assert_eq!(session.metrics_interval(), Duration::from_secs(2));
+
+        let (success, data, _) = reply(&mut session, &state, r#"{"command":"snapshot","request_id":"7"}"#);
+        assert!(success);
+        assert_eq!(data.unwrap()["open_incidents"], 0);
+
+        match session.handle(&state, r#"{"command":"reboot","request_id":"8"}"#) {
+            WebSocketMessage::CommandResult { request_id, error, .. } => {
+                assert_eq!(request_id.as_deref(), Some("8"));
+                assert_eq!(error.unwrap().code, CommandErrorCode::UnknownCommand);
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(reply(&mut session, &state, "pause").2, Some(CommandErrorCode::InvalidMessage));
+        assert_eq!(reply(&mut session, &state, r#"{"command":"ack_alert"}"#).2, Some(CommandErrorCode::InvalidArgument));
+        assert_eq!(reply(&mut session, &state, r#"{"command":"ack_alert","id":"nope"}"#).2, Some(CommandErrorCode::NotFound));
+    }
+}
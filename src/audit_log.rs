@@ -0,0 +1,395 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Context, Result};
+use tracing::{info, warn};
+
+use crate::monitor::{SecurityEvent, SecurityEventType, Verdict};
+
+// Hash the first entry chains from
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    // An allow/deny verdict on a file or network operation
+    Decision,
+    PolicyChange,
+    // Something done in response to a detection: a ban, kill, quarantine
+    ResponseAction,
+    // Access to protected data, such as re-identifying pseudonymized values
+    DataAccess,
+    // About the log itself: a torn entry cut off on restart, entries lost to
+    // failed writes
+    Chain,
+}
+
+// A record to append; sequence number and hashes are filled in by the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub kind: AuditKind,
+    // Who made the decision or change, e.g. "monitor", "api", "fleet"
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub reason: String,
+    pub rule_id: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(kind: AuditKind, actor: &str, action: &str, target: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.into(),
+            reason: reason.into(),
+            rule_id: None,
+        }
+    }
+
+    pub fn with_rule(mut self, rule_id: impl Into<String>) -> Self {
+        self.rule_id = Some(rule_id.into());
+        self
+    }
+
+    // The verdict the monitor reached for an event
+    pub fn decision(event: &SecurityEvent) -> Self {
+        let action = match event.verdict {
+            Verdict::Allow => "allow",
+            Verdict::Deny => "deny",
+            // Passive mode: the operation went ahead and was only recorded
+            Verdict::Log => "log",
+        };
+        let target = match &event.event_type {
+            SecurityEventType::FileExecution { target_path, .. } => format!("exec {}", target_path.display()),
+            SecurityEventType::FileAccess { target_path, .. } => format!("file {}", target_path.display()),
+            SecurityEventType::NetworkConnection { remote_ip, remote_port, .. } => format!("connect {}:{}", remote_ip, remote_port),
+            SecurityEventType::Authentication { user, service, .. } => format!("auth {}@{}", user, service),
+            SecurityEventType::Syscall { syscall, .. } => format!("syscall {}", syscall),
+        };
+        Self::new(AuditKind::Decision, "monitor", action,
+                  format!("{} (pid {} {})", target, event.process_info.pid, event.process_info.path.display()),
+                  event.policy_reason.clone())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub prev_hash: String,
+    // SHA-256 over the previous hash and every other field of this entry
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(self.seq.to_be_bytes());
+        hasher.update(self.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true).as_bytes());
+        // Length-prefixed so fields can't be shifted into each other
+        let kind = serde_json::to_string(&self.record.kind).unwrap_or_default();
+        let rule_id = self.record.rule_id.as_deref().unwrap_or("");
+        for field in [kind.as_str(), &self.record.actor, &self.record.action, &self.record.target, &self.record.reason, rule_id] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.update([self.record.rule_id.is_some() as u8]);
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainVerification {
+    pub valid: bool,
+    pub entries: u64,
+    pub head_hash: String,
+    // First entry (1-based line) that breaks the chain
+    pub broken_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+struct ChainHead {
+    file: File,
+    seq: u64,
+    hash: String,
+}
+
+// Append-only, hash-chained log of enforcement decisions, policy changes and
+// response actions. Each entry carries the hash of the one before it, so
+// editing, removing or reordering entries breaks verification from that point.
+pub struct AuditLog {
+    path: PathBuf,
+    head: Mutex<ChainHead>,
+    // Records `record` failed to write since the last successful append
+    lost: AtomicU64,
+}
+
+impl AuditLog {
+    // Continues the chain of an existing log. A log whose tail doesn't verify
+    // is still appended to, but the break stays visible to `verify`. A last
+    // entry torn by a crash mid-write is cut off and the cut is recorded as a
+    // chain entry, rather than keeping the daemon from starting.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create audit log directory {:?}", parent))?;
+        }
+
+        let (seq, hash, torn) = match File::open(path) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                let mut last = None;
+                let mut last_line = None;
+                let mut offset = 0u64;
+                let mut line = Vec::new();
+                loop {
+                    line.clear();
+                    let read = reader.read_until(b'\n', &mut line)
+                        .with_context(|| format!("Failed to read audit log {:?}", path))?;
+                    if read == 0 {
+                        break;
+                    }
+                    let text = String::from_utf8_lossy(&line);
+                    if !text.trim().is_empty() {
+                        last_line = Some((offset, read as u64));
+                        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&text) {
+                            last = Some(entry);
+                            last_line = None;
+                        }
+                    }
+                    offset += read as u64;
+                }
+                // An unparseable last line is a torn write; earlier ones are
+                // left for `verify` to report
+                let torn = match last_line {
+                    Some((start, len)) if start + len == offset => {
+                        let file = OpenOptions::new().write(true).open(path)
+                            .with_context(|| format!("Failed to open audit log {:?}", path))?;
+                        file.set_len(start)
+                            .with_context(|| format!("Failed to truncate torn audit entry in {:?}", path))?;
+                        warn!("Cut off a torn entry of {} bytes at the end of audit log {:?}", len, path);
+                        Some(len)
+                    }
+                    _ => None,
+                };
+                match last {
+                    Some(entry) => {
+                        if entry.compute_hash() != entry.hash {
+                            warn!("Last audit log entry {} does not match its hash", entry.seq);
+                        }
+                        (entry.seq, entry.hash, torn)
+                    }
+                    None => (0, GENESIS_HASH.to_string(), torn),
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, GENESIS_HASH.to_string(), None),
+            Err(e) => return Err(anyhow!("Failed to read audit log {:?}: {}", path, e)),
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {:?}", path))?;
+        info!("Audit log {:?} at entry {}", path, seq);
+
+        let log = Self {
+            path: path.to_path_buf(),
+            head: Mutex::new(ChainHead { file, seq, hash }),
+            lost: AtomicU64::new(0),
+        };
+        if let Some(len) = torn {
+            log.append(AuditRecord::new(AuditKind::Chain, "audit-log", "recover", format!("after entry {}", seq),
+                                        format!("Discarded a torn entry of {} bytes", len)))?;
+        }
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: AuditRecord) -> Result<AuditEntry> {
+        let mut head = self.head.lock().map_err(|_| anyhow!("Audit log lock poisoned"))?;
+        let mut entry = AuditEntry {
+            seq: head.seq + 1,
+            timestamp: Utc::now(),
+            record,
+            prev_hash: head.hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let end = head.file.metadata()
+            .with_context(|| format!("Failed to stat audit log {:?}", self.path))?
+            .len();
+        // One write per entry so a concurrent reader never sees half of one
+        if let Err(e) = head.file.write_all(line.as_bytes()).and_then(|_| head.file.sync_data()) {
+            // Don't leave half an entry for the next one to be appended to
+            let _ = head.file.set_len(end);
+            return Err(anyhow!("Failed to write audit log {:?}: {}", self.path, e));
+        }
+
+        head.seq = entry.seq;
+        head.hash = entry.hash.clone();
+        Ok(entry)
+    }
+
+    // Logs instead of failing, for callers that must not be held up by the
+    // audit log. Records that couldn't be written are counted, and the count
+    // goes into the chain with the next one that can.
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(e) = self.append(record) {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            warn!("Failed to write audit entry: {:#}", e);
+            return;
+        }
+        let lost = self.lost.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            let chain = AuditRecord::new(AuditKind::Chain, "audit-log", "lost", self.path.display().to_string(),
+                                         format!("{} audit entries could not be written", lost));
+            if let Err(e) = self.append(chain) {
+                self.lost.fetch_add(lost, Ordering::Relaxed);
+                warn!("Failed to write audit entry: {:#}", e);
+            }
+        }
+    }
+
+    // The newest `limit` entries, oldest first
+    pub fn tail(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to read audit log {:?}", self.path))?;
+        let mut entries = std::collections::VecDeque::with_capacity(limit.min(1024));
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) {
+                if entries.len() == limit {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+        Ok(entries.into())
+    }
+
+    pub fn verify(&self) -> Result<ChainVerification> {
+        // Entries appended meanwhile would otherwise race the read
+        let _head = self.head.lock().map_err(|_| anyhow!("Audit log lock poisoned"))?;
+        verify_file(&self.path)
+    }
+}
+
+// Walks the whole chain: every entry must hash to its recorded hash, point at
+// the previous entry's hash and carry the next sequence number
+pub fn verify_file(path: &Path) -> Result<ChainVerification> {
+    let file = File::open(path).with_context(|| format!("Failed to read audit log {:?}", path))?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut entries = 0;
+
+    let broken = |line: u64, error: String, head_hash: String| ChainVerification {
+        valid: false,
+        entries: line - 1,
+        head_hash,
+        broken_at: Some(line),
+        error: Some(error),
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let position = entries + 1;
+        let entry: AuditEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => return Ok(broken(position, format!("unparseable entry: {}", e), prev_hash)),
+        };
+        if entry.seq != position {
+            return Ok(broken(position, format!("expected sequence {}, found {}", position, entry.seq), prev_hash));
+        }
+        if entry.prev_hash != prev_hash {
+            return Ok(broken(position, "previous hash does not match".to_string(), prev_hash));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Ok(broken(position, "entry was modified".to_string(), prev_hash));
+        }
+        prev_hash = entry.hash;
+        entries = position;
+    }
+
+    Ok(ChainVerification {
+        valid: true,
+        entries,
+        head_hash: prev_hash,
+        broken_at: None,
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_survives_reopen_and_detects_tampering() {
+        let path = std::env::temp_dir().join(format!("flux-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let log = AuditLog::open(&path).unwrap();
+        log.append(AuditRecord::new(AuditKind::Decision, "monitor", "deny", "exec /tmp/x", "denied path").with_rule("tmp-exec")).unwrap();
+        log.append(AuditRecord::new(AuditKind::PolicyChange, "api", "update", "pol_1", "")).unwrap();
+        drop(log);
+
+        // Appending after a restart continues the same chain
+        let log = AuditLog::open(&path).unwrap();
+        let entry = log.append(AuditRecord::new(AuditKind::ResponseAction, "auto-block", "ban", "203.0.113.9", "brute force")).unwrap();
+        assert_eq!(entry.seq, 3);
+        let verification = log.verify().unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 3);
+        assert_eq!(verification.head_hash, entry.hash);
+        assert_eq!(log.tail(2).unwrap().iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2, 3]);
+
+        // Rewriting a decision is caught at that entry
+        let original = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, original.replacen("\"deny\"", "\"allow\"", 1)).unwrap();
+        let verification = verify_file(&path).unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.broken_at, Some(1));
+
+        // So is dropping one
+        let lines: Vec<&str> = original.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let verification = verify_file(&path).unwrap();
+        assert_eq!(verification.broken_at, Some(2));
+        assert_eq!(verification.entries, 1);
+
+        // A crash mid-write leaves a torn last line; reopening cuts it off,
+        // records the cut and keeps the chain valid
+        std::fs::write(&path, format!("{}{}", original, &lines[2][..40])).unwrap();
+        let log = AuditLog::open(&path).unwrap();
+        let verification = log.verify().unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 4);
+        let recovery = &log.tail(1).unwrap()[0];
+        assert_eq!(recovery.record.kind, AuditKind::Chain);
+        assert_eq!(recovery.record.reason, "Discarded a torn entry of 40 bytes");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
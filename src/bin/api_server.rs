@@ -8,46 +8,434 @@ use tower_http::{
     cors::{CorsLayer, Any},
     trace::TraceLayer,
 };
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing::{info, error};
-use tracing_subscriber;
+use tracing::{info, warn, error};
+
+use fluxdefense::api::{rate_limit, spawn_log_ingestion, spawn_metrics_history, ApiRateLimitConfig, ApiRateLimiter, TlsSettings};
+use fluxdefense::fleet::FleetServer;
+use fluxdefense::capture::CaptureManager;
+use axum_server::tls_rustls::RustlsConfig;
 
 use fluxdefense::api::{
     handlers::{
-        AppState, health_check, get_system_status, get_threat_metrics, get_network_metrics,
-        get_system_metrics, get_system_resources, get_security_events, get_security_event,
+        AppState, health_check, readiness_check, get_capabilities, get_system_status, get_threat_metrics, get_network_metrics,
+        get_system_metrics, get_metrics_history, get_top_units, get_system_resources, get_security_events, get_security_event,
         get_network_connections, get_dns_queries, get_threat_detections, get_malware_signatures,
         get_event_logs, get_live_events, get_settings, update_settings, get_security_settings,
         update_security_settings, get_processes, get_process_stats, get_process_by_pid,
         get_network_stats,
     },
-    websocket::{websocket_handler, populate_mock_data},
+    websocket::{websocket_handler, populate_realistic_data},
     policy_handlers::{
         get_policies, get_policy, create_policy, update_policy, delete_policy,
         get_alerts, get_alert, update_alert_status, add_alert_note, get_policy_stats,
+        replay_policy, get_active_policy, apply_active_policy, apply_signed_policy,
+    },
+    policy_history_handlers::{
+        get_policy_history, get_policy_version, get_policy_version_diff, rollback_policy, reload_policy_files,
+    },
+    shadow_handlers::get_shadow_rules,
+    rule_stats_handlers::{get_rule_stats, get_unused_rules},
+    fleet_handlers::{
+        fleet_enroll, fleet_heartbeat, fleet_ingest_events, get_fleet_agents, get_fleet_agent,
+        delete_fleet_agent, get_fleet_events, get_fleet_policy, update_fleet_policy, update_signed_fleet_policy,
+    },
+    capture_handlers::{
+        start_capture, get_captures, get_capture, stop_capture, download_capture,
+    },
+    incident_handlers::{
+        get_incidents, get_incident, update_incident_status, download_incident_artifact, get_incident_report,
+        get_incident_stix, get_stix_bundle,
     },
+    audit_handlers::{
+        get_audit_entries, verify_audit_log,
+    },
+    config_handlers::reload_config,
+    scan_handlers::start_scan,
+    misp_handlers::{get_misp_status, sync_misp, push_incident_to_misp},
+    schedule_handlers::{get_scheduled_tasks, get_scheduled_task, run_scheduled_task},
+    session_handlers::{get_sessions, get_session, get_session_anomalies},
+    privacy_handlers::reidentify_pseudonyms,
+    tenancy::{tenant_access, get_tenants, TenantDirectory, TenantsConfig},
 };
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    // Initialize logging; a config reload can change the level later
+    let log_level = fluxdefense::config::reload::init_logging("info");
 
     info!("Starting FluxDefense API Server...");
 
     // Create application state
-    let state = Arc::new(AppState::new());
+    let mut app_state = AppState::new();
+    
+    // Subsystems whose privileges are missing run observe-only; the report
+    // is served at /api/capabilities
+    app_state.capabilities = fluxdefense::preflight::CapabilityReport::probe();
+    app_state.capabilities.log();
+    app_state.capabilities.report_health(&app_state.health);
+    
+    // Tenants (JSON): their enrollment tokens place agents in the tenant and
+    // their API tokens only see its agents, events, policies and incidents
+    let tenants = match std::env::var("FLUX_TENANTS") {
+        Ok(path) => Some(TenantsConfig::load_from_file(path.as_ref())?),
+        Err(_) => None,
+    };
     
-    // Check if we should use real monitoring or mock data
-    let use_real_monitoring = std::env::var("USE_REAL_MONITORING")
-        .unwrap_or_else(|_| "true".to_string()) == "true";
+    // Fleet mode: accept agents presenting one of the comma-separated enrollment tokens
+    let fleet_tokens = std::env::var("FLUX_FLEET_TOKENS").ok()
+        .map(|tokens| tokens.split(',').map(|t| t.trim().to_string()).collect::<Vec<String>>());
+    let tenant_enrollment = tenants.iter().flat_map(|config| &config.tenants).any(|tenant| !tenant.enrollment_tokens.is_empty());
+    if fleet_tokens.is_some() || tenant_enrollment {
+        let state_path = std::env::var("FLUX_FLEET_STATE")
+            .unwrap_or_else(|_| "/var/lib/fluxdefense/fleet-server.json".to_string());
+        let mut fleet = FleetServer::new(fleet_tokens.unwrap_or_default(), Some(state_path.into()))?;
+        for tenant in tenants.iter().flat_map(|config| &config.tenants) {
+            fleet.add_tenant_enrollment_tokens(&tenant.id, &tenant.enrollment_tokens);
+        }
+        app_state.fleet = Some(Arc::new(fleet));
+        info!("Fleet aggregation enabled");
+    }
+    if let Some(ref config) = tenants {
+        app_state.tenants = Some(Arc::new(TenantDirectory::new(config)?));
+        info!("Multi-tenancy enabled for {} tenants; API requests need an admin or tenant token", config.tenants.len());
+    }
     
-    // For now, always use mock data until we fix the real monitor
-    populate_mock_data(Arc::clone(&state));
-    info!("Using mock data for demonstration");
+    // Comma-separated bearer tokens for admin-only endpoints such as re-identification
+    if let Ok(tokens) = std::env::var("FLUX_ADMIN_TOKENS") {
+        let tokens: Vec<String> = tokens.split(',').map(|t| t.trim().to_string()).collect();
+        app_state.set_admin_tokens(&tokens);
+    }
+    
+    if let Ok(path) = std::env::var("FLUX_AUDIT_LOG") {
+        app_state.audit_log = Some(Arc::new(fluxdefense::audit_log::AuditLog::open(path.as_ref())?));
+    }
+    
+    // Runtime-safe settings of this file are re-read on SIGHUP or POST /api/config/reload
+    if let Ok(path) = std::env::var(fluxdefense::config::CONFIG_PATH_ENV) {
+        let loaded = fluxdefense::config::Config::load_effective(Some(path.as_ref()))?;
+        loaded.validate()?;
+        log_level.set(&loaded.log_level)?;
+        app_state.system_monitor.lock().unwrap().set_redactor(fluxdefense::redaction::Redactor::from_config(&loaded.redaction)?);
+        if let Some(privacy) = loaded.privacy.clone() {
+            app_state.privacy = Some(Arc::new(fluxdefense::privacy::Pseudonymizer::open(privacy)?));
+        }
+        let config = Arc::new(fluxdefense::config::ReloadableConfig::new(Some(path.into()), &loaded, loaded.clone())?);
+        config.on_reload(move |config, report| {
+            if report.applied.iter().any(|field| field == "log_level") {
+                if let Err(e) = log_level.set(&config.log_level) {
+                    error!("{:#}", e);
+                }
+            }
+        });
+        fluxdefense::config::reload::spawn_sighup_reload(config.clone())?;
+        
+        if let Some(signing) = loaded.policy_signing.as_ref() {
+            app_state.policy_verifier = Some(Arc::new(fluxdefense::policy::PolicyVerifier::new(signing)?));
+            info!("Policy signatures required");
+        }
+        
+        // Start from the policies on disk so PUT /api/policies/active edits what is enforced
+        let (policy, signed) = fluxdefense::policy::read_policy_files(
+            loaded.file_policy_path.as_deref(),
+            loaded.network_policy_path.as_deref(),
+            app_state.policy_verifier.as_deref(),
+        )?;
+        // A profile's starter policies stand in for missing files
+        let profile = loaded.profile;
+        if let Some(file_policy) = policy.file_policy.or_else(|| profile.map(|profile| profile.file_policy())) {
+            app_state.file_policy = Arc::new(std::sync::RwLock::new(file_policy));
+        }
+        if let Some(network_policy) = policy.network_policy.or_else(|| profile.map(|profile| profile.network_policy())) {
+            app_state.network_policy = Arc::new(std::sync::RwLock::new(network_policy));
+        }
+        
+        // Every policy change is kept as a version; files edited while the
+        // server was down show up as a startup version
+        if let Ok(path) = std::env::var("FLUX_POLICY_HISTORY") {
+            let history = fluxdefense::policy::PolicyHistory::open(path.as_ref())?;
+            let current = fluxdefense::policy::CandidatePolicy {
+                file_policy: Some(app_state.file_policy.read().unwrap().clone()),
+                network_policy: Some(app_state.network_policy.read().unwrap().clone()),
+            };
+            history.record("system", "startup", current, signed)?;
+            app_state.policy_history = Some(Arc::new(history));
+        }
+        app_state.config = Some(config);
+    }
+    
+    if let Ok(dir) = std::env::var("FLUX_CAPTURE_DIR") {
+        app_state.captures = Arc::new(CaptureManager::new(dir.into()));
+    }
+    
+    // Rule match counters survive restarts when kept in this file, so rules
+    // that stay unused over weeks can be found
+    if let Ok(path) = std::env::var("FLUX_RULE_STATS") {
+        app_state.rule_stats = fluxdefense::rule_stats::RuleStats::open(path.as_ref())?;
+    }
+    
+    // Correlation rules: built-in rules plus any *.yaml/*.yml/*.json files in this directory
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Ok(dir) = std::env::var("FLUX_CORRELATION_RULES_DIR") {
+        let mut correlator = match app_state.policy_verifier.clone() {
+            Some(verifier) => fluxdefense::linux_security::EventCorrelator::with_signed_rules_dir(dir.into(), verifier)?,
+            None => fluxdefense::linux_security::EventCorrelator::with_rules_dir(dir.into())?,
+        };
+        correlator.set_shadow_log(app_state.shadow.clone());
+        correlator.set_rule_stats(app_state.rule_stats.clone());
+        app_state.correlator = Some(Arc::new(correlator));
+    }
+    
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    match fluxdefense::linux_security::ProcessTreeSource::standalone() {
+        Ok(source) => app_state.process_trees = Some(Arc::new(source)),
+        Err(e) => error!("Process trees unavailable: {}", e),
+    }
+    
+    // Temporary bans and blocklist loading through the nftables block sets
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if std::env::var("FLUX_FIREWALL").is_ok_and(|value| value == "1") && !app_state.capabilities.has(fluxdefense::preflight::NET_ADMIN) {
+        warn!("FLUX_FIREWALL is set but CAP_NET_ADMIN is missing; network verdicts are only logged");
+    } else if std::env::var("FLUX_FIREWALL").is_ok_and(|value| value == "1") {
+        let mut firewall = fluxdefense::linux_security::NetfilterManager::new()?;
+        match firewall.initialize().and_then(|_| firewall.enable_block_sets()) {
+            Ok(()) => {
+                app_state.firewall = Some(Arc::new(std::sync::Mutex::new(firewall)));
+                info!("Firewall bans enabled");
+            }
+            Err(e) => error!("Firewall bans unavailable: {}", e),
+        }
+    }
+    
+    // Forensic snapshots of the processes behind Critical detections,
+    // attached to their incidents
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Ok(dir) = std::env::var("FLUX_FORENSICS_DIR") {
+        use fluxdefense::linux_security::{ForensicCollector, ForensicConfig};
+        let collector = ForensicCollector::new(ForensicConfig { dir: dir.into(), ..Default::default() })?;
+        Arc::new(collector).attach_to(&app_state.incidents)?;
+    }
+    
+    // Host isolation for incident response; FLUX_ISOLATION_CONFIG names a JSON
+    // file with the management networks, fleet servers and DNS resolvers
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Some(firewall) = app_state.firewall.clone() {
+        use fluxdefense::linux_security::{HostIsolation, IsolationConfig};
+        let mut config = match std::env::var("FLUX_ISOLATION_CONFIG") {
+            Ok(path) => IsolationConfig::load_from_file(path.as_ref())?,
+            Err(_) => IsolationConfig::default(),
+        };
+        // Isolation must not cut off the API that lifts it
+        let port = std::env::var("PORT").ok().and_then(|port| port.parse().ok()).unwrap_or(3177);
+        if !config.api_ports.contains(&port) {
+            config.api_ports.push(port);
+        }
+        let isolation = Arc::new(HostIsolation::new(firewall, config)?);
+        isolation.start_auto_release()?;
+        app_state.isolation = Some(isolation);
+    }
+    
+    // Response playbooks, one *.yaml/*.yml/*.json file each; their alerts open incidents
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Ok(dir) = std::env::var("FLUX_PLAYBOOKS_DIR") {
+        use fluxdefense::linux_security::{PlaybookEngine, PlaybookSettings};
+        let mut settings = PlaybookSettings { isolation: app_state.isolation.clone(), ..Default::default() };
+        if let Some(ref config) = app_state.config {
+            let config = config.current();
+            settings.quarantine_dir = config.quarantine_directory;
+            settings.exemptions = fluxdefense::exemptions::Exemptions::new(&config.exemptions);
+        }
+        let incidents = Arc::clone(&app_state.incidents);
+        let engine = PlaybookEngine::new(Some(dir.into()), settings, move |alert| {
+            incidents.record_correlation(None, fluxdefense::incidents::CorrelationSignal {
+                rule_id: format!("playbook:{}", alert.playbook_id),
+                rule_name: alert.playbook_id,
+                description: alert.message,
+                severity: alert.severity.into(),
+                detected_at: chrono::Utc::now(),
+                events: Vec::new(),
+            });
+        })?;
+        app_state.playbooks = Some(Arc::new(engine));
+    }
+    
+    // IOC sync with MISP; FLUX_MISP_CONFIG names a JSON file with the URL,
+    // API key and the events to pull from and push to
+    if let Ok(path) = std::env::var("FLUX_MISP_CONFIG") {
+        let config = fluxdefense::misp::MispConfig::load_from_file(path.as_ref())?;
+        let misp = Arc::new(fluxdefense::misp::MispConnector::new(
+            config,
+            Arc::clone(&app_state.file_policy),
+            Arc::clone(&app_state.network_policy),
+        )?);
+        misp.start(Arc::clone(&app_state.incidents));
+        let status = Arc::clone(&misp);
+        app_state.health.probe("misp", false, move || {
+            use fluxdefense::health::ComponentState;
+            let status = status.status();
+            match (status.last_error, status.last_pull) {
+                (Some(error), _) => (ComponentState::Degraded, error),
+                (None, Some(last_pull)) => (ComponentState::Up, format!("last pull {}", last_pull)),
+                (None, None) => (ComponentState::Up, "no pull yet".to_string()),
+            }
+        });
+        app_state.misp = Some(misp);
+    }
+    
+    // Login sessions from utmp/wtmp and the auth records of the system log;
+    // FLUX_SESSIONS=1 enables it, FLUX_SESSIONS_CONFIG names a JSON file
+    // with paths and thresholds
+    if std::env::var("FLUX_SESSIONS").is_ok() || std::env::var("FLUX_SESSIONS_CONFIG").is_ok() {
+        use fluxdefense::sessions::{SessionConfig, SessionMonitor};
+        let config = match std::env::var("FLUX_SESSIONS_CONFIG") {
+            Ok(path) => SessionConfig::load_from_file(path.as_ref())?,
+            Err(_) => SessionConfig::default(),
+        };
+        let incidents = Arc::clone(&app_state.incidents);
+        let monitor = Arc::new(SessionMonitor::new(config, move |anomaly, session| {
+            incidents.record_correlation(None, fluxdefense::incidents::CorrelationSignal {
+                rule_id: anomaly.rule_id().to_string(),
+                rule_name: anomaly.rule_id().to_string(),
+                description: anomaly.description.clone(),
+                severity: fluxdefense::incidents::IncidentSeverity::Medium,
+                detected_at: anomaly.timestamp,
+                events: vec![anomaly.to_security_event(session)],
+            });
+        }));
+        if let Err(e) = monitor.load_history() {
+            error!("Session history unavailable: {:#}", e);
+        }
+        monitor.start()?;
+        let mut logs = app_state.log_stream.subscribe();
+        let observer = Arc::clone(&monitor);
+        tokio::spawn(async move {
+            loop {
+                match logs.recv().await {
+                    Ok(entry) => { observer.observe_log(&entry); }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        app_state.sessions = Some(monitor);
+    }
+    
+    // Recurring tasks from the JSON file FLUX_SCHEDULE_CONFIG names
+    if let Ok(path) = std::env::var("FLUX_SCHEDULE_CONFIG") {
+        let schedule = fluxdefense::api::ScheduleConfig::load_from_file(path.as_ref())?;
+        fluxdefense::api::schedule_tasks(&app_state, schedule)?;
+    }
+    
+    // Reads the kernel's counters of the netfilter rules and saves the
+    // match counters
+    let stats = app_state.rule_stats.clone();
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    let firewall = app_state.firewall.clone();
+    app_state.scheduler.add("rule-stats", "rule_stats", fluxdefense::scheduler::CronSchedule::parse("* * * * *")?, true, Arc::new(move || {
+        #[cfg(all(target_os = "linux", feature = "pcap"))]
+        if let Some(firewall) = &firewall {
+            use fluxdefense::rule_stats::RuleEngine;
+            let counters = firewall.lock().map_err(|_| anyhow::anyhow!("firewall lock poisoned"))?.rule_counters()?;
+            stats.sync(RuleEngine::Netfilter, counters.iter().map(|(rule, _)| {
+                (rule.id.as_str(), if rule.comment.is_empty() { rule.id.as_str() } else { rule.comment.as_str() })
+            }));
+            for (rule, packets) in &counters {
+                if let Some(packets) = packets {
+                    stats.observe_counter(RuleEngine::Netfilter, &rule.id, *packets);
+                }
+            }
+        }
+        stats.save()?;
+        Ok(format!("{} rules tracked", stats.stats(None).len()))
+    }))?;
+    app_state.scheduler.start()?;
+    
+    // Subsystem probes behind /api/health and /api/ready
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    match app_state.firewall.clone() {
+        Some(firewall) => app_state.health.probe("nftables", false, move || {
+            use fluxdefense::health::ComponentState;
+            use fluxdefense::linux_security::NftBackendKind;
+            let Ok(firewall) = firewall.lock() else {
+                return (ComponentState::Down, "firewall lock poisoned".to_string());
+            };
+            let bans = firewall.list_bans().len();
+            match firewall.active_backend() {
+                Some(NftBackendKind::Cli) => (ComponentState::Degraded, format!("nft CLI backend, {} bans", bans)),
+                Some(_) => (ComponentState::Up, format!("netlink backend, {} bans", bans)),
+                None => (ComponentState::Down, "block sets not programmed".to_string()),
+            }
+        }),
+        None if std::env::var("FLUX_FIREWALL").is_ok_and(|value| value == "1") => {
+            app_state.health.set("nftables", false, fluxdefense::health::ComponentState::Degraded, "observe-only: firewall could not be set up")
+        }
+        None => app_state.health.set("nftables", false, fluxdefense::health::ComponentState::Disabled, "FLUX_FIREWALL not set"),
+    }
+    // An isolated host shows as degraded so it is hard to miss on dashboards
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Some(isolation) = app_state.isolation.clone() {
+        app_state.health.probe("isolation", false, move || {
+            use fluxdefense::health::ComponentState;
+            let status = isolation.status();
+            match (status.isolated, status.expires_at) {
+                (false, _) => (ComponentState::Up, "not isolated".to_string()),
+                (true, Some(expires_at)) => (ComponentState::Degraded, format!("host isolated until {}", expires_at)),
+                (true, None) => (ComponentState::Degraded, "host isolated until released".to_string()),
+            }
+        });
+    }
+    
+    // DNS queries come from the filtering proxy (FLUX_DNS_PROXY) and the
+    // traffic capture (FLUX_CAPTURE_INTERFACE), which also counts connection bytes
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    if let Err(e) = fluxdefense::api::start_dns_telemetry(&app_state) {
+        error!("DNS proxy unavailable: {:#}", e);
+    }
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    let _traffic_capture = if app_state.capabilities.has(fluxdefense::preflight::PCAP) {
+        fluxdefense::api::start_traffic_capture(&mut app_state).unwrap_or_else(|e| {
+            error!("Traffic capture unavailable: {:#}", e);
+            None
+        })
+    } else {
+        warn!("Traffic capture skipped: CAP_NET_RAW is missing");
+        None
+    };
+    
+    // Request limits per client address and API token, with lockouts
+    match ApiRateLimitConfig::from_env()? {
+        Some(config) => {
+            info!(
+                "API rate limits: {}/s per address, {}/s per token, lockout after {} failures",
+                config.client_rate, config.token_rate, config.lockout_failures
+            );
+            app_state.rate_limiter = Some(Arc::new(ApiRateLimiter::new(config)));
+        }
+        None => info!("API rate limiting disabled"),
+    }
+    
+    let captures = Arc::clone(&app_state.captures);
+    app_state.health.probe("packet_captures", false, move || {
+        let running = captures.list(None).iter()
+            .filter(|capture| capture.status == fluxdefense::capture::CaptureStatus::Running)
+            .count();
+        (fluxdefense::health::ComponentState::Up, format!("{} running", running))
+    });
+    
+    let state = Arc::new(app_state);
+    
+    // Process and connection snapshots of this host
+    populate_realistic_data(Arc::clone(&state));
+    
+    // Usage history behind /api/metrics/history
+    spawn_metrics_history(Arc::clone(&state));
+    
+    // Log entries come from journald, or the auth log where there is none
+    if spawn_log_ingestion(Arc::clone(&state)).is_none() {
+        info!("System log ingestion disabled");
+    }
 
     // Configure CORS
     let cors = CorsLayer::new()
@@ -59,6 +447,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app = Router::new()
         // Health check
         .route("/api/health", get(health_check))
+        .route("/api/ready", get(readiness_check))
+        .route("/api/capabilities", get(get_capabilities))
         
         // Dashboard overview
         .route("/api/dashboard/status", get(get_system_status))
@@ -68,6 +458,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // System monitoring
         .route("/api/system/metrics", get(get_system_metrics))
         .route("/api/system/resources", get(get_system_resources))
+        .route("/api/metrics/history", get(get_metrics_history))
+        .route("/api/metrics/units", get(get_top_units))
         
         // Process management
         .route("/api/processes", get(get_processes))
@@ -102,6 +494,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/policies", get(get_policies).post(create_policy))
         .route("/api/policies/:id", get(get_policy).put(update_policy).delete(delete_policy))
         .route("/api/policies/stats", get(get_policy_stats))
+        .route("/api/policies/replay", post(replay_policy))
+        .route("/api/policies/active", get(get_active_policy).put(apply_active_policy))
+        .route("/api/policies/active/signed", put(apply_signed_policy))
+        .route("/api/policies/reload", post(reload_policy_files))
+        .route("/api/policies/history", get(get_policy_history))
+        .route("/api/policies/history/:version", get(get_policy_version))
+        .route("/api/policies/history/:version/diff", get(get_policy_version_diff))
+        .route("/api/policies/history/:version/rollback", post(rollback_policy))
+        
+        // Rules in shadow mode and what they would have done
+        .route("/api/rules/shadow", get(get_shadow_rules))
+        // Match counts, and rules that have stopped matching
+        .route("/api/rules/stats", get(get_rule_stats))
+        .route("/api/rules/unused", get(get_unused_rules))
+        
+        // Fleet aggregation
+        .route("/api/fleet/enroll", post(fleet_enroll))
+        .route("/api/fleet/heartbeat", post(fleet_heartbeat))
+        .route("/api/fleet/events", get(get_fleet_events).post(fleet_ingest_events))
+        .route("/api/fleet/agents", get(get_fleet_agents))
+        .route("/api/fleet/agents/:id", get(get_fleet_agent).delete(delete_fleet_agent))
+        .route("/api/fleet/policy", get(get_fleet_policy).put(update_fleet_policy))
+        .route("/api/fleet/policy/signed", put(update_signed_fleet_policy))
+        
+        // Packet captures
+        .route("/api/captures", get(get_captures).post(start_capture))
+        .route("/api/captures/:id", get(get_capture))
+        .route("/api/captures/:id/stop", post(stop_capture))
+        .route("/api/captures/:id/download", get(download_capture))
         
         // Alerts
         .route("/api/alerts", get(get_alerts))
@@ -109,6 +530,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/alerts/:id/status", put(update_alert_status))
         .route("/api/alerts/:id/notes", post(add_alert_note))
         
+        // Incidents
+        .route("/api/incidents", get(get_incidents))
+        .route("/api/incidents/:id", get(get_incident))
+        .route("/api/incidents/:id/status", put(update_incident_status))
+        .route("/api/incidents/:id/report", get(get_incident_report))
+        .route("/api/incidents/:id/stix", get(get_incident_stix))
+        .route("/api/stix", get(get_stix_bundle))
+        .route("/api/incidents/:id/artifacts/:artifact_id", get(download_incident_artifact))
+        .route("/api/incidents/:id/misp", post(push_incident_to_misp))
+        
+        // MISP IOC sync
+        .route("/api/misp", get(get_misp_status))
+        .route("/api/misp/sync", post(sync_misp))
+        .route("/api/sessions", get(get_sessions))
+        .route("/api/sessions/anomalies", get(get_session_anomalies))
+        .route("/api/sessions/:id", get(get_session))
+        
+        // Audit log
+        .route("/api/audit", get(get_audit_entries))
+        .route("/api/audit/verify", get(verify_audit_log))
+        
+        // Privacy mode
+        .route("/api/privacy/reidentify", post(reidentify_pseudonyms))
+        
+        // Tenants visible to the caller
+        .route("/api/tenants", get(get_tenants))
+        
+        // Configuration
+        .route("/api/config/reload", post(reload_config))
+        
+        // On-demand directory scans
+        .route("/api/scan", post(start_scan))
+        
+        // Scheduled tasks
+        .route("/api/schedule", get(get_scheduled_tasks))
+        .route("/api/schedule/:id", get(get_scheduled_task))
+        .route("/api/schedule/:id/run", post(run_scheduled_task));
+    
+    // Correlation rules
+    #[cfg(all(target_os = "linux", feature = "pcap"))]
+    let app = app
+        .route("/api/correlation/rules", get(fluxdefense::api::correlation_handlers::get_correlation_rules))
+        .route("/api/correlation/rules/reload", post(fluxdefense::api::correlation_handlers::reload_correlation_rules))
+        .route("/api/processes/:pid/tree", get(fluxdefense::api::process_tree_handlers::get_process_tree))
+        .route("/api/network/beacons", get(fluxdefense::api::get_network_beacons))
+        .route("/api/network/lan", get(fluxdefense::api::get_lan_status))
+        .route("/api/firewall/bans", get(fluxdefense::api::firewall_handlers::get_bans).post(fluxdefense::api::firewall_handlers::ban_ip))
+        .route("/api/firewall/bans/:ip", delete(fluxdefense::api::firewall_handlers::unban_ip))
+        .route("/api/firewall/blocklist", post(fluxdefense::api::firewall_handlers::load_blocklist))
+        .route("/api/playbooks", get(fluxdefense::api::playbook_handlers::get_playbooks))
+        .route("/api/playbooks/reload", post(fluxdefense::api::playbook_handlers::reload_playbooks))
+        .route("/api/playbooks/runs", get(fluxdefense::api::playbook_handlers::get_playbook_runs))
+        .route("/api/playbooks/runs/:id/rollback", post(fluxdefense::api::playbook_handlers::rollback_playbook_run))
+        .route("/api/playbooks/:id", put(fluxdefense::api::playbook_handlers::put_playbook).delete(fluxdefense::api::playbook_handlers::delete_playbook))
+        .route("/api/playbooks/:id/run", post(fluxdefense::api::playbook_handlers::run_playbook))
+        .route("/api/isolation", get(fluxdefense::api::isolation_handlers::get_isolation)
+            .post(fluxdefense::api::isolation_handlers::isolate_host)
+            .delete(fluxdefense::api::isolation_handlers::release_isolation));
+    
+    let app = app
         // Static file serving for the web dashboard
         .fallback_service(tower_http::services::ServeDir::new("web-dashboard/dist"))
         
@@ -117,6 +598,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
                 .layer(cors)
+                .layer(axum::middleware::from_fn_with_state(Arc::clone(&state), rate_limit))
+                .layer(axum::middleware::from_fn_with_state(Arc::clone(&state), tenant_access))
         )
         .with_state(state);
 
@@ -126,14 +609,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     info!("Starting server on {}", addr);
     
-    let listener = TcpListener::bind(&addr).await?;
+    let tls = TlsSettings::from_env()?;
+    let (http, ws) = if tls.is_some() { ("https", "wss") } else { ("http", "ws") };
     
-    info!("FluxDefense API Server running on http://{}", addr);
-    info!("Dashboard available at http://{}", addr);
-    info!("API endpoints available at http://{}/api/", addr);
-    info!("WebSocket endpoint: ws://{}/api/live/ws", addr);
+    info!("FluxDefense API Server running on {}://{}", http, addr);
+    info!("Dashboard available at {}://{}", http, addr);
+    info!("API endpoints available at {}://{}/api/", http, addr);
+    info!("WebSocket endpoint: {}://{}/api/live/ws", ws, addr);
     
-    axum::serve(listener, app).await?;
+    match tls {
+        Some(tls) => {
+            let config = RustlsConfig::from_config(tls.server_config()?);
+            axum_server::bind_rustls(addr.parse()?, config)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+        None => {
+            let listener = TcpListener::bind(&addr).await?;
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+        }
+    }
 
     Ok(())
 }
@@ -182,7 +677,7 @@ mod tests {
     #[tokio::test]
     async fn test_security_events() {
         let state = Arc::new(AppState::new());
-        populate_mock_data(Arc::clone(&state));
+        populate_realistic_data(Arc::clone(&state));
         
         let app = Router::new()
             .route("/api/security/events", get(get_security_events))
@@ -1,15 +1,23 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use clap::{Arg, Command};
-use tracing::{info, error};
+use tracing::{info, warn, error};
 use anyhow::Result;
-use fluxdefense::{FluxDefense, config::Config};
+use std::sync::Arc;
+use fluxdefense::{FluxDefense, config::{write_starter_files, Config, ConfigFormat, LogLevelHandle, Profile, ReloadableConfig}};
+use fluxdefense::config::Enforcement;
+use fluxdefense::event_log;
+use fluxdefense::setup::{HostCapabilities, SetupAnswers};
+use fluxdefense::update::{self, TrialState};
+use fluxdefense::fleet::FleetAgentConfig;
+use fluxdefense::capture::{CaptureManager, CaptureRequest, CaptureStatus};
 use fluxdefense::monitor::{Verdict, ProcessInfo, NetworkProtocol};
+use fluxdefense::policy::{CandidatePolicy, PolicyEntry, PolicyFiles, PolicyReplay, ReplayVerdict, ReplayWindow, RuleAction, RuleContext};
+use fluxdefense::policy::{PolicySigner, PolicySigningConfig, PolicyVerifier, SignedPolicy};
 use std::io::{self, Write};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+fn main() -> Result<()> {
+    // Initialize logging; a config reload can change the level later
+    let log_level = fluxdefense::config::reload::init_logging("info");
     
     let matches = Command::new("flux-monitor")
         .version("1.0.0")
@@ -31,6 +39,24 @@ async fn main() -> Result<()> {
                         .default_value("./fluxdefense-events.log")
                         .value_parser(clap::value_parser!(PathBuf))
                 )
+                .arg(
+                    Arg::new("fleet-server")
+                        .long("fleet-server")
+                        .help("URL of a fleet server to report events to")
+                )
+                .arg(
+                    Arg::new("enroll-token")
+                        .long("enroll-token")
+                        .help("Enrollment token for the fleet server (first run only)")
+                        .requires("fleet-server")
+                )
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .short('c')
+                        .help("Config file (JSON, TOML or YAML); FLUXDEFENSE_* variables override it")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
         )
         .subcommand(
             Command::new("test")
@@ -96,12 +122,604 @@ async fn main() -> Result<()> {
                         .action(clap::ArgAction::SetTrue)
                 )
         )
+        .subcommand(
+            Command::new("replay")
+                .about("Replay stored events through a candidate policy without enforcing it")
+                .arg(
+                    Arg::new("policy")
+                        .long("policy")
+                        .short('p')
+                        .help("Candidate policy file (file, network or combined policy JSON)")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("log-file")
+                        .long("log-file")
+                        .help("Path to event log file")
+                        .default_value("./fluxdefense-events.log")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .help("Start of the window (RFC 3339 or relative, e.g. 24h)")
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .help("End of the window (RFC 3339 or relative, e.g. 1h)")
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .help("List allowed events as well as denied, alerted and changed ones")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .short('j')
+                        .help("Output the report in JSON format")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("capture")
+                .about("Capture packets to a .pcap file for an incident")
+                .arg(
+                    Arg::new("incident")
+                        .long("incident")
+                        .help("Incident ID the capture belongs to")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("pid")
+                        .long("pid")
+                        .help("Only traffic on sockets owned by this process")
+                        .value_parser(clap::value_parser!(u32))
+                )
+                .arg(
+                    Arg::new("ip")
+                        .long("ip")
+                        .help("Only traffic to or from this address")
+                        .value_parser(clap::value_parser!(std::net::IpAddr))
+                )
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .help("Only traffic to or from this port")
+                        .value_parser(clap::value_parser!(u16))
+                )
+                .arg(
+                    Arg::new("interface")
+                        .long("interface")
+                        .short('i')
+                        .help("Interface to capture on (default: all)")
+                )
+                .arg(
+                    Arg::new("duration")
+                        .long("duration")
+                        .short('d')
+                        .help("Capture duration in seconds")
+                        .default_value("60")
+                        .value_parser(clap::value_parser!(u64))
+                )
+                .arg(
+                    Arg::new("max-packets")
+                        .long("max-packets")
+                        .help("Stop after this many packets")
+                        .value_parser(clap::value_parser!(u64))
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .help("Directory for capture files (one subdirectory per incident)")
+                        .default_value("./captures")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Check or show the effective configuration")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .short('c')
+                        .help("Config file (default: FLUXDEFENSE_CONFIG, then the user and system config)")
+                        .global(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .subcommand(
+                    Command::new("validate")
+                        .about("Load and validate the configuration")
+                )
+                .subcommand(
+                    Command::new("print")
+                        .about("Print the configuration with environment overrides applied")
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .short('f')
+                                .help("Output format")
+                                .value_parser(["json", "toml", "yaml"])
+                                .default_value("json")
+                        )
+                )
+                .subcommand_required(true)
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Write a config and starter policies for a host role; asks about the host unless --profile is given")
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .short('p')
+                        .help("Hardening profile; skips the questions")
+                        .value_parser(fluxdefense::config::PROFILE_NAMES)
+                )
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .short('c')
+                        .help("Config file to write (JSON, TOML or YAML); the policies go next to it")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Replace existing config and policy files")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("events")
+                .about("Work with event log files")
+                .subcommand(
+                    Command::new("convert")
+                        .about("Rewrite a legacy event log in the versioned JSONL schema")
+                        .arg(
+                            Arg::new("input")
+                                .help("Event log to convert")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .help("Where to write the converted log")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                )
+                .subcommand(
+                    Command::new("search")
+                        .about("Search the event log, including rotated files")
+                        .arg(
+                            Arg::new("log")
+                                .long("log")
+                                .help("Event log (default: the configured one)")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("config")
+                                .long("config")
+                                .short('c')
+                                .help("Config file naming the event log")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(Arg::new("since").long("since").help("RFC 3339 time or age such as 15m, 2h, 7d"))
+                        .arg(Arg::new("until").long("until").help("RFC 3339 time or age such as 15m, 2h, 7d"))
+                        .args(event_filter_args())
+                        .arg(
+                            Arg::new("limit")
+                                .long("limit")
+                                .short('n')
+                                .help("Show at most this many of the newest matches")
+                                .default_value("100")
+                                .value_parser(clap::value_parser!(usize))
+                        )
+                )
+                .subcommand(
+                    Command::new("tail")
+                        .about("Follow live events from a running API server")
+                        .arg(
+                            Arg::new("api")
+                                .long("api")
+                                .help("API server URL")
+                                .default_value("http://localhost:3177")
+                        )
+                        .args(event_filter_args())
+                )
+                .subcommand_required(true)
+        )
+        .subcommand(
+            Command::new("seccomp-report")
+                .about("List the syscalls a log-mode seccomp filter recorded, as allowlist entries")
+                .arg(
+                    Arg::new("log")
+                        .help("audit.log or kernel log output; - reads stdin")
+                        .default_value("/var/log/audit/audit.log")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("pid")
+                        .long("pid")
+                        .help("Only records for this process")
+                        .value_parser(clap::value_parser!(u32))
+                )
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Install a signed agent update from the configured release manifest")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .short('c')
+                        .help("Config file with the update settings")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Only report whether an update is available")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("rollback")
+                        .long("rollback")
+                        .help("Put the binary the last update replaced back")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("check")
+                )
+        )
+        .subcommand(
+            Command::new("preflight")
+                .about("Check the privileges and platform features monitoring needs, and what runs degraded without them")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the report as JSON")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("service")
+                .about("Manage the systemd unit for `flux-monitor start`")
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .help("Unit name")
+                        .global(true)
+                        .default_value(fluxdefense::systemd::DEFAULT_UNIT_NAME)
+                )
+                .arg(
+                    Arg::new("unit-dir")
+                        .long("unit-dir")
+                        .help("Directory the unit file is written to")
+                        .global(true)
+                        .default_value(fluxdefense::systemd::DEFAULT_UNIT_DIR)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .subcommand(
+                    Command::new("install")
+                        .about("Write a hardened unit and enable it")
+                        .arg(
+                            Arg::new("config")
+                                .long("config")
+                                .short('c')
+                                .help("Config file the service starts with")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("now")
+                                .long("now")
+                                .help("Start the service as well")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                        .arg(
+                            Arg::new("print")
+                                .long("print")
+                                .help("Print the unit instead of installing it")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(
+                    Command::new("uninstall")
+                        .about("Stop and disable the service and remove its unit")
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("Show the service status")
+                )
+                .subcommand_required(true)
+        )
+        .subcommand(
+            Command::new("policy")
+                .about("List, edit and test the file and network policies")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .short('c')
+                        .help("Config file naming the policy files (default: FLUXDEFENSE_CONFIG, then the user and system config)")
+                        .global(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("file-policy")
+                        .long("file-policy")
+                        .help("File policy JSON, instead of the configured one")
+                        .global(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("network-policy")
+                        .long("network-policy")
+                        .help("Network policy JSON, instead of the configured one")
+                        .global(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("Show the policy entries and rules")
+                        .arg(
+                            Arg::new("kind")
+                                .long("kind")
+                                .help("Only one of the policies")
+                                .value_parser(["file", "network"])
+                        )
+                )
+                .subcommand(policy_entry_command("add", "Add an entry and validate the result"))
+                .subcommand(policy_entry_command("remove", "Remove an entry"))
+                .subcommand(
+                    Command::new("test")
+                        .about("Evaluate an execution or connection against the policies")
+                        .arg(Arg::new("path").long("path").help("Executable path").value_parser(clap::value_parser!(PathBuf)))
+                        .arg(Arg::new("hash").long("hash").help("Executable SHA-256"))
+                        .arg(Arg::new("signer").long("signer").help("Code signing authority"))
+                        .arg(Arg::new("ip").long("ip").help("Remote IP address").value_parser(clap::value_parser!(std::net::IpAddr)))
+                        .arg(Arg::new("port").long("port").help("Remote port").value_parser(clap::value_parser!(u16)))
+                        .arg(Arg::new("domain").long("domain").help("Remote domain"))
+                )
+                .subcommand(
+                    Command::new("keygen")
+                        .about("Create an Ed25519 policy signing key and print its public key")
+                        .arg(
+                            Arg::new("out")
+                                .long("out")
+                                .short('o')
+                                .help("Private key file to create")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                )
+                .subcommand(
+                    Command::new("sign")
+                        .about("Write a .sig next to the policies and correlation rule files")
+                        .arg(
+                            Arg::new("key")
+                                .long("key")
+                                .short('k')
+                                .help("Private key file from `policy keygen`")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("rules-dir")
+                                .long("rules-dir")
+                                .help("Also sign every correlation rule file in this directory")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("apply")
+                                .long("apply")
+                                .help("Also apply the signed policies to a running daemon through its API, e.g. http://localhost:3177")
+                        )
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check the signatures of the policies and correlation rule files")
+                        .arg(
+                            Arg::new("public-key")
+                                .long("public-key")
+                                .help("Trusted public key in hex, instead of the configured ones")
+                                .action(clap::ArgAction::Append)
+                        )
+                        .arg(
+                            Arg::new("rules-dir")
+                                .long("rules-dir")
+                                .help("Also verify every correlation rule file in this directory")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                )
+                .subcommand_required(true)
+        )
+        .subcommand(
+            Command::new("scan")
+                .about("Hash and check every file under a directory; exits with 1 when a malicious file is found")
+                .arg(
+                    Arg::new("path")
+                        .help("Directory or file to scan")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("rules")
+                        .long("rules")
+                        .short('r')
+                        .help("YARA rules file or directory")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("known-bad")
+                        .long("known-bad")
+                        .help("Known-bad SHA-256 list, one hash per line")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("max-depth")
+                        .long("max-depth")
+                        .help("Maximum directory depth")
+                        .value_parser(clap::value_parser!(usize))
+                )
+                .arg(
+                    Arg::new("follow-symlinks")
+                        .long("follow-symlinks")
+                        .help("Follow symbolic links")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("api")
+                        .long("api")
+                        .help("Run the scan on this API server instead, e.g. http://localhost:3177")
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .help("Output format")
+                        .value_parser(["table", "json"])
+                        .default_value("table")
+                )
+        )
+        .subcommand(
+            Command::new("baseline")
+                .about("Fingerprint the installed system into a hash allowlist")
+                .subcommand(
+                    Command::new("build")
+                        .about("Hash installed executables and write them to the file policy; reruns only hash what changed")
+                        .arg(
+                            Arg::new("config")
+                                .long("config")
+                                .short('c')
+                                .help("Config file naming the file policy (default: FLUXDEFENSE_CONFIG, then the user and system config)")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .short('o')
+                                .help("File policy to update, instead of the configured one")
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("manifest")
+                                .long("manifest")
+                                .help("Baseline state used for incremental updates")
+                                .default_value(fluxdefense::scanner::baseline::DEFAULT_MANIFEST_PATH)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("root")
+                                .long("root")
+                                .help("Directory to fingerprint, instead of the standard binary locations (repeatable)")
+                                .action(clap::ArgAction::Append)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                        .arg(
+                            Arg::new("no-packages")
+                                .long("no-packages")
+                                .help("Skip executables listed in the dpkg/rpm databases")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                        .arg(
+                            Arg::new("rehash")
+                                .long("rehash")
+                                .help("Hash every file again, even when its size and times are unchanged")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .help("Show what would change without writing anything")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                )
+                .subcommand_required(true)
+        )
+        .subcommand(
+            Command::new("isolate")
+                .about("Cut the host off the network except for the management plane, or lift the isolation")
+                .arg(
+                    Arg::new("api")
+                        .long("api")
+                        .help("API server to act through")
+                        .default_value("http://localhost:3177")
+                )
+                .arg(
+                    Arg::new("minutes")
+                        .long("minutes")
+                        .short('m')
+                        .help("Release automatically after this long; 0 keeps the isolation until released (default: the server's setting)")
+                        .value_parser(clap::value_parser!(u64))
+                )
+                .arg(
+                    Arg::new("reason")
+                        .long("reason")
+                        .short('r')
+                        .help("Why the host is isolated, for the audit log")
+                )
+                .arg(
+                    Arg::new("allow")
+                        .long("allow")
+                        .help("Address or CIDR to keep reachable (repeatable)")
+                        .action(clap::ArgAction::Append)
+                )
+                .arg(
+                    Arg::new("release")
+                        .long("release")
+                        .help("Lift the isolation")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["minutes", "reason", "allow", "status"])
+                )
+                .arg(
+                    Arg::new("status")
+                        .long("status")
+                        .help("Show whether the host is isolated")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["minutes", "reason", "allow"])
+                )
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Export an incident with its events, process tree, connections and responses")
+                .arg(
+                    Arg::new("incident-id")
+                        .help("Incident to export")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("api")
+                        .long("api")
+                        .help("API server holding the incident")
+                        .default_value("http://localhost:3177")
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .help("Report format")
+                        .value_parser(["html", "json"])
+                        .default_value("html")
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("File to write (default: stdout)")
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+        )
         .get_matches();
     
+    // The daemon drops privileges before the runtime starts its threads
+    if let Some(("start", sub_matches)) = matches.subcommand() {
+        return start_daemon(sub_matches, log_level);
+    }
+    tokio::runtime::Runtime::new()?.block_on(run_command(&matches))
+}
+
+async fn run_command(matches: &clap::ArgMatches) -> Result<()> {
     match matches.subcommand() {
-        Some(("start", sub_matches)) => {
-            start_monitoring(sub_matches).await?;
-        }
         Some(("test", sub_matches)) => {
             run_tests(sub_matches).await?;
         }
@@ -114,6 +732,48 @@ async fn main() -> Result<()> {
         Some(("metrics", sub_matches)) => {
             monitor_system_metrics(sub_matches).await?;
         }
+        Some(("replay", sub_matches)) => {
+            replay_events(sub_matches)?;
+        }
+        Some(("capture", sub_matches)) => {
+            capture_packets(sub_matches).await?;
+        }
+        Some(("config", sub_matches)) => {
+            show_config(sub_matches)?;
+        }
+        Some(("init", sub_matches)) => {
+            init_config(sub_matches)?;
+        }
+        Some(("events", sub_matches)) => {
+            manage_events(sub_matches).await?;
+        }
+        Some(("seccomp-report", sub_matches)) => {
+            seccomp_report(sub_matches)?;
+        }
+        Some(("update", sub_matches)) => {
+            update_agent(sub_matches).await?;
+        }
+        Some(("preflight", sub_matches)) => {
+            preflight(sub_matches)?;
+        }
+        Some(("service", sub_matches)) => {
+            manage_service(sub_matches)?;
+        }
+        Some(("policy", sub_matches)) => {
+            manage_policy(sub_matches).await?;
+        }
+        Some(("scan", sub_matches)) => {
+            scan_directory(sub_matches).await?;
+        }
+        Some(("baseline", sub_matches)) => {
+            manage_baseline(sub_matches)?;
+        }
+        Some(("isolate", sub_matches)) => {
+            isolate_host(sub_matches).await?;
+        }
+        Some(("report", sub_matches)) => {
+            export_incident_report(sub_matches).await?;
+        }
         _ => {
             println!("No subcommand provided. Use --help for usage information.");
         }
@@ -122,22 +782,175 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn start_monitoring(matches: &clap::ArgMatches) -> Result<()> {
+// Runs as root until the privilege drop, on this thread alone: capability
+// sets are per thread, so the runtime is only built afterwards. What needs
+// root is opened first and stays usable after the drop
+fn start_daemon(matches: &clap::ArgMatches, log_level: LogLevelHandle) -> Result<()> {
     info!("Starting FluxDefense passive monitoring...");
+    let (loaded, config) = start_config(matches, &log_level)?;
+    
+    // A runtime of its own, dropped with its threads before the drop below
+    let trial = tokio::runtime::Builder::new_current_thread().enable_all().build()?
+        .block_on(resume_update(&config))?;
     
+    let mut defense = FluxDefense::new_with_config(config.clone())?;
+    if let Some(ref privileges) = config.privileges {
+        // The logs themselves and directories only the daemon uses; a log's
+        // parent, such as /var/log or the working directory, is shared
+        let mut writable: Vec<PathBuf> = vec![
+            PathBuf::from("/var/lib/fluxdefense"),
+            PathBuf::from("/var/log/fluxdefense"),
+            config.quarantine_directory.clone(),
+        ];
+        for log in [&config.log_file_path, &config.audit_log_path].into_iter().flatten() {
+            // Created now, as the user could not create it next to others
+            if let Some(parent) = log.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::OpenOptions::new().create(true).append(true).open(log)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", log.display(), e))?;
+            writable.push(log.clone());
+        }
+        defense.acquire()?;
+        fluxdefense::privileges::hand_over(privileges, &writable)?;
+        fluxdefense::privileges::drop_privileges(privileges)?;
+    }
+    
+    tokio::runtime::Runtime::new()?.block_on(start_monitoring(matches, loaded, config, defense, trial, log_level))
+}
+
+// The loaded config file, and it with the command line applied
+fn start_config(matches: &clap::ArgMatches, log_level: &LogLevelHandle) -> Result<(Config, Config)> {
     let log_file = matches.get_one::<PathBuf>("log-file").unwrap().clone();
     
-    let mut config = Config::default();
-    config.log_file_path = Some(log_file);
+    let config_path = matches.get_one::<PathBuf>("config");
+    let loaded = match config_path {
+        Some(path) => {
+            let config = Config::load_effective(Some(path))?;
+            config.validate()?;
+            log_level.set(&config.log_level)?;
+            config
+        }
+        None => Config::default(),
+    };
+    let mut config = loaded.clone();
+    // The config file's log path wins unless one was given explicitly
+    if config.log_file_path.is_none()
+        || matches.value_source("log-file") == Some(clap::parser::ValueSource::CommandLine)
+    {
+        config.log_file_path = Some(log_file);
+    }
     
     if let Some(whitelist_dir) = matches.get_one::<PathBuf>("whitelist-dir") {
         config.file_policy_path = Some(whitelist_dir.join("file_policy.json"));
         config.network_policy_path = Some(whitelist_dir.join("network_policy.json"));
     }
     
-    let mut defense = FluxDefense::new_with_config(config)?;
+    if let Some(server_url) = matches.get_one::<String>("fleet-server") {
+        let token = matches.get_one::<String>("enroll-token").cloned();
+        config.fleet = Some(FleetAgentConfig::new(server_url.clone(), token));
+    }
+    Ok((loaded, config))
+}
+
+// Binaries are only swapped here at startup, while still root: installs a
+// waiting update or rolls a failed one back, and restarts into the result.
+// Returns the trial of a version still on it
+async fn resume_update(config: &Config) -> Result<Option<update::TrialRecord>> {
+    let binary = std::env::current_exe()?;
+    match update::resume_pending(&binary)? {
+        TrialState::Settled => {
+            if let Some(update_config) = config.update.clone().filter(|update| update.check_interval_secs.is_some()) {
+                match update::Updater::new(update_config)?.update().await {
+                    Ok(Some(version)) => {
+                        info!("Restarting into version {}", version);
+                        update::restart()?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Update failed: {:#}", e),
+                }
+            }
+            Ok(None)
+        }
+        TrialState::RolledBack(pending) => {
+            error!("Version {} failed; restarting into {}", pending.to_version, pending.from_version);
+            update::restart()?;
+            Ok(None)
+        }
+        TrialState::OnTrial(trial) => Ok(Some(trial)),
+    }
+}
+
+async fn start_monitoring(
+    matches: &clap::ArgMatches,
+    loaded: Config,
+    config: Config,
+    mut defense: FluxDefense,
+    trial: Option<update::TrialRecord>,
+    log_level: LogLevelHandle,
+) -> Result<()> {
+    let config_path = matches.get_one::<PathBuf>("config");
+    // With a config file, SIGHUP re-reads it and applies what can change live
+    if let Some(path) = config_path {
+        let live = Arc::new(ReloadableConfig::new(Some(path.clone()), &loaded, config.clone())?);
+        live.on_reload(move |config, report| {
+            if report.applied.iter().any(|field| field == "log_level") {
+                if let Err(e) = log_level.set(&config.log_level) {
+                    error!("{:#}", e);
+                }
+            }
+        });
+        fluxdefense::config::reload::spawn_sighup_reload(live)?;
+        info!("Send SIGHUP to reload {}", path.display());
+    }
+    
+    // Restarts requested by the updater: to install a new version, or to roll
+    // back when the new one is unhealthy. The daemon exits and leaves the
+    // restart to its supervisor, so the swap happens as root at startup
+    let (restart_tx, mut restart_rx) = tokio::sync::mpsc::channel::<String>(1);
+    let auto_update = config.update.clone().filter(|update| update.check_interval_secs.is_some());
+    if let Some(trial) = trial {
+        info!("Version {} is on trial (start {})", trial.pending.to_version, trial.pending.boots);
+        let health_url = config.update.as_ref().and_then(|update| update.health_url.clone());
+        let grace = std::time::Duration::from_secs(config.update.as_ref().map_or(60, |update| update.health_grace_secs));
+        let restart_tx = restart_tx.clone();
+        tokio::spawn(async move {
+            let from_version = trial.pending.from_version.clone();
+            match update::confirm_when_healthy(trial, health_url.as_deref(), grace).await {
+                Ok(true) => {}
+                Ok(false) => { restart_tx.send(format!("unhealthy, rolling back to {}", from_version)).await.ok(); }
+                Err(e) => error!("Update trial failed: {:#}", e),
+            }
+        });
+    }
+    // Only checks; the restart installs what it finds
+    if let Some(update_config) = auto_update {
+        let updater = update::Updater::new(update_config.clone())?;
+        let restart_tx = restart_tx.clone();
+        tokio::spawn(async move {
+            let period = std::time::Duration::from_secs(update_config.check_interval_secs.unwrap_or_default().max(60));
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match updater.check().await {
+                    Ok(Some(release)) => {
+                        restart_tx.send(format!("version {} is available", release.version)).await.ok();
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Update check failed: {:#}", e),
+                }
+            }
+        });
+    }
+    
     defense.start().await?;
     
+    // The filter applies to every thread, so it can wait until startup is done
+    if let Some(ref seccomp) = config.seccomp {
+        fluxdefense::seccomp::install(seccomp)?;
+    }
+    
     // Start system metrics collection in background
     let _metrics_handle = if let Some(monitor) = defense.get_monitor() {
         Some(monitor.start_system_metrics_collection())
@@ -146,11 +959,34 @@ async fn start_monitoring(matches: &clap::ArgMatches) -> Result<()> {
     };
     
     info!("FluxDefense monitoring started with system metrics collection. Press Ctrl+C to stop...");
+    fluxdefense::systemd::notify_ready("Monitoring");
+    let _watchdog = fluxdefense::systemd::spawn_watchdog();
     
-    // Wait for shutdown signal
-    tokio::signal::ctrl_c().await?;
+    // Wait for shutdown signal; systemd stops services with SIGTERM. Until
+    // then, hand platform watcher events (BSD) to the monitor
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut platform_events = tokio::time::interval(std::time::Duration::from_millis(500));
+    let mut restart = false;
+    loop {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                break;
+            }
+            _ = terminate.recv() => break,
+            Some(reason) = restart_rx.recv() => {
+                info!("Restarting: {}", reason);
+                restart = true;
+                break;
+            }
+            _ = platform_events.tick() => {
+                defense.process_platform_events();
+            }
+        }
+    }
     
     info!("Received shutdown signal");
+    fluxdefense::systemd::notify_stopping();
     defense.stop().await?;
     
     // Show final statistics
@@ -160,21 +996,929 @@ async fn start_monitoring(matches: &clap::ArgMatches) -> Result<()> {
         println!("Total Events: {}", stats.total_events);
         println!("Events by Type: {:#?}", stats.events_by_type);
         println!("Events by Verdict: {:#?}", stats.events_by_verdict);
+        let sampling = monitor.sampling_metrics();
+        for sampling in sampling.types.iter().filter(|sampling| sampling.dropped > 0) {
+            println!("Sampled {}: {} of {} kept (rate now {:.1}%)",
+                     sampling.event_type, sampling.kept, sampling.seen, sampling.rate * 100.0);
+        }
     }
     
+    // Exec'ing here would keep the dropped privileges and the seccomp
+    // filter; the supervisor starts a fresh process instead
+    if restart {
+        std::process::exit(update::RESTART_EXIT_CODE);
+    }
     Ok(())
 }
 
-async fn run_tests(matches: &clap::ArgMatches) -> Result<()> {
-    info!("Running FluxDefense monitoring tests...");
-    
-    let mut config = Config::default();
-    config.log_file_path = Some(PathBuf::from("./test-events.log"));
+fn show_config(matches: &clap::ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("config");
+    let mut config = match path {
+        Some(path) => Config::load_from_file(path)?,
+        None => Config::load_config()?,
+    };
+    let overrides = config.apply_env_overrides(std::env::vars())?;
     
-    if let Some(whitelist_dir) = matches.get_one::<PathBuf>("whitelist-dir") {
-        config.file_policy_path = Some(whitelist_dir.join("file_policy.json"));
-        config.network_policy_path = Some(whitelist_dir.join("network_policy.json"));
-    }
+    match matches.subcommand() {
+        Some(("validate", _)) => {
+            config.validate()?;
+            match path {
+                Some(path) => println!("Configuration {} is valid", path.display()),
+                None => println!("Configuration is valid"),
+            }
+            for name in overrides {
+                println!("  overridden by {}", name);
+            }
+        }
+        Some(("print", sub_matches)) => {
+            let format = sub_matches.get_one::<String>("format")
+                .and_then(|name| ConfigFormat::from_name(name))
+                .unwrap_or(ConfigFormat::Json);
+            println!("{}", config.to_string(format)?);
+        }
+        _ => unreachable!("subcommand is required"),
+    }
+    Ok(())
+}
+
+fn init_config(matches: &clap::ArgMatches) -> Result<()> {
+    let Some(profile) = matches.get_one::<String>("profile") else {
+        return run_setup_wizard(matches);
+    };
+    let profile = Profile::from_name(profile).expect("clap checks the profile name");
+    let config_path = matches.get_one::<PathBuf>("config").cloned()
+        .unwrap_or_else(Config::get_default_config_path);
+    let dir = config_path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+    let config = Config {
+        file_policy_path: Some(dir.join("file_policy.json")),
+        network_policy_path: Some(dir.join("network_policy.json")),
+        ..profile.config()
+    };
+    
+    for path in write_starter_files(&config_path, &config, profile, matches.get_flag("force"))? {
+        println!("Wrote {}", path.display());
+    }
+    println!("Start monitoring with: flux-monitor start --config {}", config_path.display());
+    Ok(())
+}
+
+// Reads one answer; an empty line takes the default
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    Ok(if input.is_empty() { default.to_string() } else { input.to_string() })
+}
+
+fn ask_yes_no(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        match ask(&format!("{} ({})", question, hint), "")?.to_ascii_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+fn run_setup_wizard(matches: &clap::ArgMatches) -> Result<()> {
+    let capabilities = HostCapabilities::detect();
+    let check = |found: bool| if found { "yes" } else { "no" };
+    println!("FluxDefense setup");
+    println!();
+    println!("  running as root:               {}", check(capabilities.root));
+    println!("  fanotify blocking (SYS_ADMIN): {}", check(capabilities.fanotify_permission));
+    println!("  nftables (NET_ADMIN, nft):     {}", check(capabilities.net_admin && capabilities.nft_binary.is_some()));
+    println!("  packet capture (pcap):         {}", check(capabilities.pcap));
+    println!("  systemd:                       {}", check(capabilities.systemd));
+    println!("  container runtime:             {}", check(capabilities.container_runtime));
+    println!();
+    
+    let profile = loop {
+        let name = ask(&format!("Host role ({})", fluxdefense::config::PROFILE_NAMES.join(", ")),
+                       capabilities.suggested_profile().name())?;
+        match Profile::from_name(&name) {
+            Some(profile) => break profile,
+            None => println!("Unknown role {}", name),
+        }
+    };
+    
+    let enforcement = loop {
+        let default = match capabilities.suggested_enforcement() {
+            Enforcement::Passive => "passive",
+            Enforcement::Enforcing => "enforcing",
+        };
+        match ask("Enforcement mode (passive only logs verdicts, enforcing blocks)", default)?.as_str() {
+            "passive" => break Enforcement::Passive,
+            "enforcing" => {
+                if !capabilities.fanotify_permission {
+                    println!("Warning: without CAP_SYS_ADMIN fanotify cannot block; run the monitor as root");
+                }
+                break Enforcement::Enforcing;
+            }
+            other => println!("Unknown mode {}", other),
+        }
+    };
+    
+    let interfaces = if capabilities.pcap {
+        let answer = ask(&format!("Interfaces to capture traffic on, comma separated or none ({})",
+                                  capabilities.interfaces.join(", ")), "none")?;
+        if answer == "none" {
+            Vec::new()
+        } else {
+            answer.split(',').map(|interface| interface.trim().to_string()).filter(|interface| !interface.is_empty()).collect()
+        }
+    } else {
+        Vec::new()
+    };
+    
+    let default_log = profile.config().log_file_path.map(|path| path.display().to_string()).unwrap_or_default();
+    let log_file_path = match ask("Event log file, or stdout", &default_log)?.as_str() {
+        "stdout" | "" => None,
+        path => Some(PathBuf::from(path)),
+    };
+    
+    let default_config = matches.get_one::<PathBuf>("config").cloned()
+        .unwrap_or_else(Config::get_default_config_path);
+    let config_path = PathBuf::from(ask("Config file to write", &default_config.display().to_string())?);
+    
+    let answers = SetupAnswers { profile, enforcement, interfaces, log_file_path, config_path };
+    let config = answers.config();
+    config.validate()?;
+    let overwrite = matches.get_flag("force")
+        || (answers.config_path.exists() && ask_yes_no(&format!("{} exists; replace it", answers.config_path.display()), false)?);
+    for path in write_starter_files(&answers.config_path, &config, profile, overwrite)? {
+        println!("Wrote {}", path.display());
+    }
+    
+    if capabilities.systemd && ask_yes_no("Install a systemd unit", capabilities.root)? {
+        let start = ask_yes_no("Start it now", true)?;
+        let options = fluxdefense::systemd::UnitOptions::new(std::env::current_exe()?, Some(std::fs::canonicalize(&answers.config_path)?));
+        let unit_dir = Path::new(fluxdefense::systemd::DEFAULT_UNIT_DIR);
+        let path = fluxdefense::systemd::install_unit(unit_dir, &options, &config, start)?;
+        println!("Installed {}", path.display());
+    } else {
+        println!("Start monitoring with: flux-monitor start --config {}", answers.config_path.display());
+    }
+    Ok(())
+}
+
+async fn manage_events(matches: &clap::ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("convert", sub_matches)) => {
+            let input = sub_matches.get_one::<PathBuf>("input").unwrap();
+            let output = sub_matches.get_one::<PathBuf>("output").unwrap();
+            let summary = event_log::convert_log(input, output)?;
+            println!("Converted {} events to {} (schema {} v{})",
+                     summary.converted, output.display(), event_log::EVENT_SCHEMA, event_log::EVENT_SCHEMA_VERSION);
+            if summary.skipped > 0 {
+                println!("Skipped {} unparseable lines", summary.skipped);
+            }
+        }
+        Some(("search", sub_matches)) => {
+            let log = match sub_matches.get_one::<PathBuf>("log") {
+                Some(log) => log.clone(),
+                None => Config::load_effective(sub_matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?
+                    .log_file_path
+                    .ok_or_else(|| anyhow::anyhow!("No event log configured; use --log"))?,
+            };
+            let now = chrono::Utc::now();
+            let filter = event_log::EventFilter {
+                since: sub_matches.get_one::<String>("since").map(|value| event_log::parse_time(value, now)).transpose()?,
+                until: sub_matches.get_one::<String>("until").map(|value| event_log::parse_time(value, now)).transpose()?,
+                min_severity: sub_matches.get_one::<String>("severity").map(|value| parse_severity(value)).transpose()?,
+                pid: sub_matches.get_one::<u32>("pid").copied(),
+                event_type: sub_matches.get_one::<String>("type").cloned(),
+            };
+            let result = event_log::search_log(&log, &filter, *sub_matches.get_one::<usize>("limit").unwrap())?;
+            
+            let json = sub_matches.get_one::<String>("format").map(String::as_str) == Some("json");
+            if !json {
+                println!("{:<20} {:<8} {:<18} {:>7} {:<6} SUMMARY", "TIME", "SEVERITY", "TYPE", "PID", "VERDICT");
+            }
+            for event in &result.events {
+                if json {
+                    println!("{}", serde_json::to_string(&event_log::EventRecord::from_event(event))?);
+                } else {
+                    println!("{:<20} {:<8} {:<18} {:>7} {:<6} {}",
+                             event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                             format!("{:?}", fluxdefense::incidents::event_severity(event)).to_lowercase(),
+                             event_log::event_type_name(event),
+                             event.process_info.pid,
+                             format!("{:?}", event.verdict).to_lowercase(),
+                             fluxdefense::incidents::describe_event(event));
+                }
+            }
+            if !json && result.truncated > 0 {
+                println!("{} older matches not shown; raise --limit to see them", result.truncated);
+            }
+        }
+        Some(("tail", sub_matches)) => {
+            tail_events(sub_matches).await?;
+        }
+        _ => unreachable!("subcommand is required"),
+    }
+    Ok(())
+}
+
+fn event_filter_args() -> Vec<Arg> {
+    vec![
+        Arg::new("severity")
+            .long("severity")
+            .help("Minimum severity")
+            .value_parser(["low", "medium", "high", "critical"]),
+        Arg::new("pid")
+            .long("pid")
+            .help("Only events from this process")
+            .value_parser(clap::value_parser!(u32)),
+        Arg::new("type")
+            .long("type")
+            .help("Event type, e.g. file_execution, network_connection, authentication"),
+        Arg::new("format")
+            .long("format")
+            .short('f')
+            .help("Output format")
+            .value_parser(["table", "json"])
+            .default_value("table"),
+    ]
+}
+
+fn parse_severity(value: &str) -> Result<fluxdefense::incidents::IncidentSeverity> {
+    Ok(serde_json::from_value(serde_json::Value::String(value.to_lowercase()))?)
+}
+
+// Follows /api/live/ws; events carry their severity as a string, where
+// anything not known (such as "info") ranks below low
+async fn tail_events(matches: &clap::ArgMatches) -> Result<()> {
+    use futures_util::StreamExt;
+    use fluxdefense::api::WebSocketMessage;
+    
+    let api = matches.get_one::<String>("api").unwrap().trim_end_matches('/');
+    let url = format!("{}/api/live/ws", api.replacen("http", "ws", 1));
+    let min_severity = matches.get_one::<String>("severity").map(|value| parse_severity(value)).transpose()?;
+    let pid = matches.get_one::<u32>("pid").copied();
+    let event_type = matches.get_one::<String>("type");
+    let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+    
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str()).await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to {}: {}", url, e))?;
+    info!("Following {}", url);
+    if !json {
+        println!("{:<20} {:<8} {:<18} {:>7} SUMMARY", "TIME", "SEVERITY", "TYPE", "PID");
+    }
+    
+    loop {
+        let text = tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(tokio_tungstenite::tungstenite::Message::Text(text))) => text,
+                Some(Ok(tokio_tungstenite::tungstenite::Message::Close(_))) | None => break,
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(anyhow::anyhow!("Connection to {} failed: {}", url, e)),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let Ok(message) = serde_json::from_str::<WebSocketMessage>(&text) else { continue };
+        
+        let (timestamp, severity, kind, event_pid, summary, data) = match message {
+            WebSocketMessage::LiveEvent { data } => (
+                data.timestamp, data.severity.clone(), data.event_type.clone(),
+                data.details.get("process_id").and_then(serde_json::Value::as_u64).map(|pid| pid as u32),
+                data.title.clone(), serde_json::to_value(&data)?,
+            ),
+            WebSocketMessage::SecurityEvent { data } => (
+                data.timestamp, data.severity.clone(), data.event_type.clone(), data.pid,
+                data.title.clone(), serde_json::to_value(&data)?,
+            ),
+            WebSocketMessage::ThreatDetection { data } => (
+                data.timestamp, data.severity.clone(), "threat".to_string(), None,
+                format!("{} ({})", data.name, data.file_path), serde_json::to_value(&data)?,
+            ),
+            WebSocketMessage::Incident { data } => (
+                data.updated_at, format!("{:?}", data.severity).to_lowercase(), "incident".to_string(), None,
+                data.title.clone(), serde_json::to_value(&data)?,
+            ),
+            _ => continue,
+        };
+        
+        if min_severity.is_some_and(|min| parse_severity(&severity).map_or(true, |severity| severity < min))
+            || pid.is_some_and(|pid| event_pid != Some(pid))
+            || event_type.is_some_and(|event_type| *event_type != kind)
+        {
+            continue;
+        }
+        if json {
+            println!("{}", data);
+        } else {
+            println!("{:<20} {:<8} {:<18} {:>7} {}",
+                     timestamp.format("%Y-%m-%d %H:%M:%S"), severity, kind,
+                     event_pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".to_string()), summary);
+        }
+    }
+    Ok(())
+}
+
+fn seccomp_report(matches: &clap::ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("log").unwrap();
+    let log = if path.as_os_str() == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    
+    let logged = fluxdefense::seccomp::logged_syscalls(&log, matches.get_one::<u32>("pid").copied());
+    if logged.is_empty() {
+        println!("# No seccomp records found");
+        return Ok(());
+    }
+    println!("seccomp:\n  allow:");
+    for number in logged {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = fluxdefense::seccomp::syscall_name(number as i64) {
+            println!("  - {}", name);
+            continue;
+        }
+        println!("  # unknown syscall {}", number);
+    }
+    Ok(())
+}
+
+async fn update_agent(matches: &clap::ArgMatches) -> Result<()> {
+    if matches.get_flag("rollback") {
+        update::rollback(&std::env::current_exe()?)?;
+        println!("Rolled back; restart the service to run the previous version");
+        return Ok(());
+    }
+    let config = Config::load_effective(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?;
+    let Some(update_config) = config.update else {
+        return Err(anyhow::anyhow!("No update section in the config"));
+    };
+    let updater = update::Updater::new(update_config)?;
+    
+    if matches.get_flag("check") {
+        match updater.check().await? {
+            Some(release) => println!("Version {} is available (running {})", release.version, update::CURRENT_VERSION),
+            None => println!("Version {} is up to date", update::CURRENT_VERSION),
+        }
+        return Ok(());
+    }
+    match updater.update().await? {
+        Some(version) => println!("Installed version {}; restart the service to run it. It is rolled back unless it stays healthy", version),
+        None => println!("Version {} is up to date", update::CURRENT_VERSION),
+    }
+    Ok(())
+}
+
+fn preflight(matches: &clap::ArgMatches) -> Result<()> {
+    let report = fluxdefense::preflight::CapabilityReport::probe();
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    for check in &report.checks {
+        println!("{:<20} {:<8} {}", check.name, if check.available { "ok" } else { "MISSING" }, check.detail);
+        if !check.available {
+            println!("{:<20} {:<8} {} falls back to {}", "", "", check.needed_by, check.fallback);
+        }
+    }
+    match report.missing().count() {
+        0 => println!("All {} checks passed", report.checks.len()),
+        missing => println!("{} of {} checks failed; the subsystems above run degraded", missing, report.checks.len()),
+    }
+    Ok(())
+}
+
+fn manage_service(matches: &clap::ArgMatches) -> Result<()> {
+    let name = matches.get_one::<String>("name").unwrap();
+    let unit_dir = matches.get_one::<PathBuf>("unit-dir").unwrap();
+    
+    match matches.subcommand() {
+        Some(("install", sub_matches)) => {
+            // The unit must not depend on the directory it was installed from
+            let config_path = match sub_matches.get_one::<PathBuf>("config") {
+                Some(path) => Some(std::fs::canonicalize(path)?),
+                None => None,
+            };
+            let config = match config_path {
+                Some(ref path) => {
+                    let config = Config::load_effective(Some(path))?;
+                    config.validate()?;
+                    config
+                }
+                None => Config::default(),
+            };
+            let mut options = fluxdefense::systemd::UnitOptions::new(std::env::current_exe()?, config_path);
+            options.name = name.clone();
+            
+            if sub_matches.get_flag("print") {
+                print!("{}", fluxdefense::systemd::render_unit(&options, &config));
+                return Ok(());
+            }
+            let path = fluxdefense::systemd::install_unit(unit_dir, &options, &config, sub_matches.get_flag("now"))?;
+            println!("Installed {}", path.display());
+        }
+        Some(("uninstall", _)) => {
+            let path = fluxdefense::systemd::uninstall_unit(unit_dir, name)?;
+            println!("Removed {}", path.display());
+        }
+        Some(("status", _)) => {
+            let path = fluxdefense::systemd::unit_path(unit_dir, name);
+            if !path.exists() {
+                println!("{} is not installed", path.display());
+                return Ok(());
+            }
+            fluxdefense::systemd::unit_status(name)?;
+        }
+        _ => unreachable!("subcommand is required"),
+    }
+    Ok(())
+}
+
+fn policy_entry_command(name: &'static str, about: &'static str) -> Command {
+    Command::new(name)
+        .about(about)
+        .arg(
+            Arg::new("kind")
+                .help("Entry kind")
+                .required(true)
+                .value_parser(clap::builder::PossibleValuesParser::new(fluxdefense::policy::ENTRY_KINDS))
+        )
+        .arg(
+            Arg::new("value")
+                .help("SHA-256, absolute path or glob, signer, IP, domain or port")
+                .required(true)
+        )
+        .arg(
+            Arg::new("action")
+                .long("action")
+                .help("What a glob rule does with matching executions")
+                .value_parser(["allow", "deny", "alert"])
+                .default_value("deny")
+        )
+        .arg(
+            Arg::new("apply")
+                .long("apply")
+                .help("Also apply the result to a running daemon through its API, e.g. http://localhost:3177")
+        )
+}
+
+async fn manage_policy(matches: &clap::ArgMatches) -> Result<()> {
+    let config = Config::load_effective(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?;
+    let files = PolicyFiles {
+        file_policy: matches.get_one::<PathBuf>("file-policy").cloned()
+            .or(config.file_policy_path)
+            .ok_or_else(|| anyhow::anyhow!("No file policy path configured"))?,
+        network_policy: matches.get_one::<PathBuf>("network-policy").cloned()
+            .or(config.network_policy_path)
+            .ok_or_else(|| anyhow::anyhow!("No network policy path configured"))?,
+    };
+    if let Some((command @ ("keygen" | "sign" | "verify"), sub_matches)) = matches.subcommand() {
+        return manage_policy_signing(command, sub_matches, &files, config.policy_signing.as_ref()).await;
+    }
+    let mut policy = files.load()?;
+    
+    match matches.subcommand() {
+        Some(("list", sub_matches)) => {
+            let kind = sub_matches.get_one::<String>("kind").map(String::as_str);
+            if kind != Some("network") {
+                print_file_policy(&files.file_policy, policy.file_policy.as_ref().unwrap());
+            }
+            if kind != Some("file") {
+                print_network_policy(&files.network_policy, policy.network_policy.as_ref().unwrap());
+            }
+        }
+        Some((command @ ("add" | "remove"), sub_matches)) => {
+            let action = match sub_matches.get_one::<String>("action").map(String::as_str) {
+                Some("allow") => RuleAction::Allow,
+                Some("alert") => RuleAction::Alert,
+                _ => RuleAction::Deny,
+            };
+            let entry = PolicyEntry::parse(
+                sub_matches.get_one::<String>("kind").unwrap(),
+                sub_matches.get_one::<String>("value").unwrap(),
+                action,
+            )?;
+            let changed = if command == "add" {
+                entry.add_to(&mut policy)?
+            } else {
+                entry.remove_from(&mut policy)
+            };
+            
+            // Only the policy that was edited is written and applied
+            if entry.is_network() {
+                policy.file_policy = None;
+            } else {
+                policy.network_policy = None;
+            }
+            if changed {
+                files.save(&mut policy)?;
+                println!("{} {:?}", if command == "add" { "Added" } else { "Removed" }, entry);
+            } else {
+                println!("Unchanged: {:?} {}", entry, if command == "add" { "is already present" } else { "is not present" });
+            }
+            
+            if let Some(url) = sub_matches.get_one::<String>("apply") {
+                apply_policy(url, &policy).await?;
+                println!("Applied to {}", url);
+            }
+        }
+        Some(("test", sub_matches)) => {
+            let path = sub_matches.get_one::<PathBuf>("path");
+            let ip = sub_matches.get_one::<std::net::IpAddr>("ip").copied();
+            if path.is_none() && ip.is_none() {
+                return Err(anyhow::anyhow!("Give --path for an execution or --ip for a connection"));
+            }
+            if let Some(path) = path {
+                let ctx = RuleContext {
+                    path: Some(path.as_path()),
+                    hash: sub_matches.get_one::<String>("hash").map(String::as_str),
+                    signer: sub_matches.get_one::<String>("signer").map(String::as_str),
+                    ..Default::default()
+                };
+                let (action, reason) = policy.file_policy.as_ref().unwrap().evaluate_execution(&ctx);
+                println!("exec {}: {:?} ({})", path.display(), action, reason);
+            }
+            if let Some(ip) = ip {
+                let port = sub_matches.get_one::<u16>("port").copied().unwrap_or(443);
+                let ctx = RuleContext {
+                    remote_ip: Some(ip),
+                    remote_port: Some(port),
+                    domain: sub_matches.get_one::<String>("domain").map(String::as_str),
+                    ..Default::default()
+                };
+                let (action, reason) = policy.network_policy.as_ref().unwrap().evaluate_connection(&ctx);
+                println!("connect {}:{}: {:?} ({})", ip, port, action, reason);
+            }
+        }
+        _ => unreachable!("subcommand is required"),
+    }
+    Ok(())
+}
+
+// Signatures cover the files byte for byte, so any later edit, including
+// `policy add`, needs a new `policy sign`
+async fn manage_policy_signing(
+    command: &str,
+    matches: &clap::ArgMatches,
+    files: &PolicyFiles,
+    signing: Option<&PolicySigningConfig>,
+) -> Result<()> {
+    let rules_dir = matches.try_get_one::<PathBuf>("rules-dir").ok().flatten();
+    let mut paths: Vec<PathBuf> = [&files.file_policy, &files.network_policy].into_iter()
+        .filter(|path| path.exists())
+        .cloned()
+        .collect();
+    if let Some(dir) = rules_dir {
+        let mut rules: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml" | "json")))
+            .collect();
+        rules.sort();
+        paths.extend(rules);
+    }
+    
+    match command {
+        "keygen" => {
+            let out = matches.get_one::<PathBuf>("out").unwrap();
+            if out.exists() {
+                return Err(anyhow::anyhow!("{} already exists", out.display()));
+            }
+            let key = PolicySigner::generate()?;
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            options.open(out)?.write_all(key.as_bytes())?;
+            println!("Private key written to {}", out.display());
+            println!("Public key (add to policy_signing.public_keys): {}", PolicySigner::from_pkcs8_hex(&key)?.public_key_hex());
+        }
+        "sign" => {
+            let signer = PolicySigner::from_key_file(matches.get_one::<PathBuf>("key").unwrap())?;
+            for path in &paths {
+                println!("Signed {} -> {}", path.display(), signer.sign_file(path)?.display());
+            }
+            if let Some(url) = matches.get_one::<String>("apply") {
+                let read = |path: &PathBuf| path.exists().then(|| std::fs::read_to_string(path)).transpose();
+                let signed = SignedPolicy::sign(&signer, read(&files.file_policy)?, read(&files.network_policy)?);
+                put_policy(&format!("{}/api/policies/active/signed", url.trim_end_matches('/')), &signed).await?;
+                println!("Applied to {}", url);
+            }
+        }
+        "verify" => {
+            let keys: Vec<String> = matches.get_many::<String>("public-key").map(|keys| keys.cloned().collect()).unwrap_or_default();
+            let verifier = match signing {
+                _ if !keys.is_empty() => PolicyVerifier::new(&PolicySigningConfig { public_keys: keys })?,
+                Some(signing) => PolicyVerifier::new(signing)?,
+                None => return Err(anyhow::anyhow!("No policy_signing in the config; give --public-key")),
+            };
+            let mut failed = 0;
+            for path in &paths {
+                match verifier.read_verified(path) {
+                    Ok(_) => println!("OK       {}", path.display()),
+                    Err(e) => {
+                        failed += 1;
+                        println!("INVALID  {:#}", e);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!("{} of {} files failed verification", failed, paths.len()));
+            }
+        }
+        _ => unreachable!("only signing subcommands are passed"),
+    }
+    Ok(())
+}
+
+fn print_sorted<T: ToString>(title: &str, values: impl IntoIterator<Item = T>) {
+    let mut values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+    if values.is_empty() {
+        return;
+    }
+    values.sort();
+    println!("  {}:", title);
+    for value in values {
+        println!("    {}", value);
+    }
+}
+
+fn print_rules(rules: &[fluxdefense::policy::Rule]) {
+    if rules.is_empty() {
+        return;
+    }
+    println!("  rules:");
+    for rule in rules {
+        println!("    {} {:?} priority {}{}: {}", rule.id, rule.action, rule.priority,
+                 if rule.enabled { "" } else { " (disabled)" }, rule.description);
+    }
+}
+
+fn print_file_policy(path: &std::path::Path, policy: &fluxdefense::policy::FilePolicy) {
+    println!("File policy ({}):", path.display());
+    print_sorted("allowed hashes", &policy.allowed_hashes);
+    print_sorted("allowed paths", policy.allowed_paths.iter().map(|path| path.display()));
+    print_sorted("allowed signers", &policy.allowed_signers);
+    print_sorted("trusted directories", policy.trusted_directories.iter().map(|path| path.display()));
+    print_sorted("system paths", policy.system_paths.iter().map(|path| path.display()));
+    print_rules(&policy.rules);
+}
+
+fn print_network_policy(path: &std::path::Path, policy: &fluxdefense::policy::NetworkPolicy) {
+    println!("Network policy ({}):", path.display());
+    print_sorted("allowed IPs", &policy.allowed_ips);
+    print_sorted("allowed domains", &policy.allowed_domains);
+    print_sorted("allowed ports", &policy.allowed_ports);
+    print_sorted("blocked IPs", &policy.blocked_ips);
+    print_sorted("blocked domains", &policy.blocked_domains);
+    print_sorted("blocked ports", &policy.blocked_ports);
+    println!("  allow local network: {}", policy.allow_local_network);
+    println!("  allow system processes: {}", policy.allow_system_processes);
+    print_rules(&policy.rules);
+}
+
+// Replaces the running policy through PUT /api/policies/active
+async fn apply_policy(url: &str, policy: &CandidatePolicy) -> Result<()> {
+    put_policy(&format!("{}/api/policies/active", url.trim_end_matches('/')), policy).await
+}
+
+async fn put_policy<T: serde::Serialize>(endpoint: &str, body: &T) -> Result<()> {
+    let response: serde_json::Value = reqwest::Client::new()
+        .put(endpoint)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if response["success"] != serde_json::Value::Bool(true) {
+        return Err(anyhow::anyhow!("{} rejected the policy: {}", endpoint,
+                                   response["error"].as_str().unwrap_or("unknown error")));
+    }
+    Ok(())
+}
+
+// Drives /api/isolation, so the firewall is changed by the server that owns it
+async fn isolate_host(matches: &clap::ArgMatches) -> Result<()> {
+    let endpoint = format!("{}/api/isolation", matches.get_one::<String>("api").unwrap().trim_end_matches('/'));
+    let client = reqwest::Client::new();
+    let request = if matches.get_flag("release") {
+        client.delete(&endpoint)
+    } else if matches.get_flag("status") {
+        client.get(&endpoint)
+    } else {
+        let allow: Vec<&String> = matches.get_many::<String>("allow").map(Iterator::collect).unwrap_or_default();
+        client.post(&endpoint).json(&serde_json::json!({
+            "minutes": matches.get_one::<u64>("minutes"),
+            "reason": matches.get_one::<String>("reason").cloned().unwrap_or_else(|| "Isolated with flux-monitor".to_string()),
+            "allow": allow,
+        }))
+    };
+    let response: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+    if response["success"] != serde_json::Value::Bool(true) {
+        return Err(anyhow::anyhow!("{} failed: {}", endpoint, response["error"].as_str().unwrap_or("unknown error")));
+    }
+    
+    let data = &response["data"];
+    if matches.get_flag("release") {
+        println!("{}", if data.as_bool() == Some(true) { "Isolation lifted" } else { "Host was not isolated" });
+        return Ok(());
+    }
+    if data["isolated"] != serde_json::Value::Bool(true) {
+        println!("Host is not isolated");
+        return Ok(());
+    }
+    println!("HOST ISOLATED since {}", data["since"].as_str().unwrap_or("?"));
+    println!("  reason:  {}", data["reason"].as_str().unwrap_or(""));
+    println!("  release: {}", data["expires_at"].as_str().map_or("manual (flux-monitor isolate --release)".to_string(), |at| format!("automatic at {}", at)));
+    if let Some(allowed) = data["allowed"].as_array() {
+        println!("  allowed:");
+        for entry in allowed {
+            println!("    {}", entry.as_str().unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+// Fetches /api/incidents/<id>/report; the JSON form is the report without
+// the API envelope
+async fn export_incident_report(matches: &clap::ArgMatches) -> Result<()> {
+    let id = matches.get_one::<String>("incident-id").unwrap();
+    let format = matches.get_one::<String>("format").unwrap();
+    let endpoint = format!("{}/api/incidents/{}/report", matches.get_one::<String>("api").unwrap().trim_end_matches('/'), id);
+    let response = reqwest::Client::new()
+        .get(&endpoint)
+        .query(&[("format", format)])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(anyhow::anyhow!("No incident {} on {}", id, endpoint));
+    }
+    let response = response.error_for_status()?;
+    let report = if format == "html" {
+        response.text().await?
+    } else {
+        let response: serde_json::Value = response.json().await?;
+        serde_json::to_string_pretty(&response["data"])?
+    };
+    
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => {
+            std::fs::write(path, report)?;
+            eprintln!("Wrote incident {} to {}", id, path.display());
+        }
+        None => println!("{}", report),
+    }
+    Ok(())
+}
+
+async fn scan_directory(matches: &clap::ArgMatches) -> Result<()> {
+    use fluxdefense::scanner::{DirectoryScanner, ScanOptions, ScanReport};
+    
+    let path = matches.get_one::<PathBuf>("path").unwrap();
+    let rules = matches.get_one::<PathBuf>("rules");
+    let known_bad = matches.get_one::<PathBuf>("known-bad");
+    let options = ScanOptions {
+        max_depth: matches.get_one::<usize>("max-depth").copied(),
+        follow_symlinks: matches.get_flag("follow-symlinks"),
+        ..Default::default()
+    };
+    let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+    
+    let report: ScanReport = match matches.get_one::<String>("api") {
+        // Paths are resolved on the server
+        Some(api) => {
+            let endpoint = format!("{}/api/scan", api.trim_end_matches('/'));
+            let mut request = serde_json::to_value(&options)?;
+            request["path"] = serde_json::to_value(path)?;
+            request["rules"] = serde_json::to_value(rules)?;
+            request["known_bad"] = serde_json::to_value(known_bad)?;
+            let response: serde_json::Value = reqwest::Client::new()
+                .post(&endpoint)
+                .json(&request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            if response["success"] != serde_json::Value::Bool(true) {
+                return Err(anyhow::anyhow!("Scan on {} failed: {}", endpoint,
+                                           response["error"].as_str().unwrap_or("unknown error")));
+            }
+            serde_json::from_value(response["data"].clone())?
+        }
+        None => {
+            let mut scanner = DirectoryScanner::new(options);
+            if let Some(rules) = rules {
+                info!("Loaded {} YARA rules", scanner.load_rules(rules)?);
+            }
+            if let Some(known_bad) = known_bad {
+                info!("Loaded {} known-bad hashes", scanner.load_known_bad(known_bad)?);
+            }
+            scanner.scan(path, |progress| {
+                if !json && !progress.done {
+                    eprint!("\rScanned {} files, {} findings", progress.files_scanned, progress.findings);
+                }
+            })?
+        }
+    };
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        eprintln!();
+        println!("Scanned {} files ({}) under {} in {:.1}s",
+                 report.files_scanned, format_bytes(report.bytes_scanned as f64), report.root.display(),
+                 (report.finished_at - report.started_at).num_milliseconds() as f64 / 1000.0);
+        if !report.errors.is_empty() {
+            println!("{} entries could not be read", report.errors.len());
+        }
+        for finding in &report.findings {
+            let verdict = if finding.is_malicious() { "MALICIOUS" } else { "SUSPICIOUS" };
+            println!("\n{:<10} {}", verdict, finding.path.display());
+            println!("           sha256 {}", finding.sha256);
+            for reason in &finding.reasons {
+                println!("           - {}", reason);
+            }
+        }
+        if report.findings.is_empty() {
+            println!("No findings");
+        }
+    }
+    
+    if report.findings.iter().any(|finding| finding.is_malicious()) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn manage_baseline(matches: &clap::ArgMatches) -> Result<()> {
+    use fluxdefense::policy::FilePolicy;
+    use fluxdefense::scanner::{baseline, BaselineBuilder, BaselineManifest, BaselineOptions};
+    
+    let Some(("build", sub_matches)) = matches.subcommand() else { unreachable!("subcommand is required") };
+    let config = Config::load_effective(sub_matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?;
+    let output = sub_matches.get_one::<PathBuf>("output").cloned()
+        .or(config.file_policy_path)
+        .ok_or_else(|| anyhow::anyhow!("No file policy path configured"))?;
+    let manifest_path = sub_matches.get_one::<PathBuf>("manifest").unwrap();
+    
+    let mut options = BaselineOptions {
+        include_packages: !sub_matches.get_flag("no-packages"),
+        rehash: sub_matches.get_flag("rehash"),
+        ..Default::default()
+    };
+    if let Some(roots) = sub_matches.get_many::<PathBuf>("root") {
+        options.roots = roots.cloned().collect();
+    }
+    
+    let previous = BaselineManifest::load(manifest_path)?;
+    let (manifest, changes) = BaselineBuilder::new(options).build(previous.as_ref())?;
+    
+    println!("{} executables: {} added, {} changed, {} removed, {} unchanged",
+             manifest.entries.len(), changes.added.len(), changes.changed.len(), changes.removed.len(), changes.unchanged);
+    // A first build adds everything, which is not worth listing
+    if previous.is_some() {
+        for (label, paths) in [("+", &changes.added), ("~", &changes.changed), ("-", &changes.removed)] {
+            for path in paths {
+                println!("  {} {}", label, path.display());
+            }
+        }
+    }
+    for error in &changes.errors {
+        println!("  ! {}", error);
+    }
+    if sub_matches.get_flag("dry-run") {
+        return Ok(());
+    }
+    
+    let mut policy = if output.exists() { FilePolicy::load_from_file(&output)? } else { baseline::empty_policy() };
+    manifest.apply_to(&mut policy, previous.as_ref());
+    let files = PolicyFiles { file_policy: output.clone(), network_policy: config.network_policy_path.unwrap_or_default() };
+    files.save(&mut CandidatePolicy { file_policy: Some(policy), network_policy: None })?;
+    manifest.save(manifest_path)?;
+    println!("Wrote {} and {}", output.display(), manifest_path.display());
+    Ok(())
+}
+
+async fn run_tests(matches: &clap::ArgMatches) -> Result<()> {
+    info!("Running FluxDefense monitoring tests...");
+    
+    let mut config = Config::default();
+    config.log_file_path = Some(PathBuf::from("./test-events.log"));
+    
+    if let Some(whitelist_dir) = matches.get_one::<PathBuf>("whitelist-dir") {
+        config.file_policy_path = Some(whitelist_dir.join("file_policy.json"));
+        config.network_policy_path = Some(whitelist_dir.join("network_policy.json"));
+    }
     
     let mut defense = FluxDefense::new_with_config(config)?;
     defense.start().await?;
@@ -257,6 +2001,93 @@ async fn show_statistics(_matches: &clap::ArgMatches) -> Result<()> {
     Ok(())
 }
 
+fn replay_events(matches: &clap::ArgMatches) -> Result<()> {
+    let policy_path = matches.get_one::<PathBuf>("policy").unwrap();
+    let log_file = matches.get_one::<PathBuf>("log-file").unwrap();
+
+    let window = ReplayWindow {
+        start: matches.get_one::<String>("since").map(|s| ReplayWindow::parse_bound(s)).transpose()?,
+        end: matches.get_one::<String>("until").map(|s| ReplayWindow::parse_bound(s)).transpose()?,
+    };
+
+    let mut replay = PolicyReplay::new(CandidatePolicy::load_from_file(policy_path)?);
+    replay.set_include_allowed(matches.get_flag("all"));
+    let report = replay.replay_log(log_file, window)?;
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Policy replay: {:?} against {:?}", policy_path, log_file);
+    println!("  Events replayed: {}", report.total_events);
+    println!("  Allowed: {}  Denied: {}  Alerted: {}", report.allowed, report.denied, report.alerted);
+    println!("  Verdict changes vs recorded: {}", report.changed);
+    if report.skipped_lines > 0 {
+        println!("  Unparseable log lines skipped: {}", report.skipped_lines);
+    }
+
+    if !report.results.is_empty() {
+        println!();
+    }
+    for result in &report.results {
+        let verdict = match result.verdict {
+            ReplayVerdict::Allow => "ALLOW",
+            ReplayVerdict::Deny => "DENY ",
+            ReplayVerdict::Alert => "ALERT",
+        };
+        println!(
+            "{} {} {}{} (was {:?}) {} - {:?}",
+            result.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            verdict,
+            if result.changed { "* " } else { "" },
+            result.reason,
+            result.original_verdict,
+            result.process_path,
+            result.event_type,
+        );
+    }
+
+    Ok(())
+}
+
+async fn capture_packets(matches: &clap::ArgMatches) -> Result<()> {
+    let manager = CaptureManager::new(matches.get_one::<PathBuf>("output-dir").unwrap().clone());
+    let request = CaptureRequest {
+        incident_id: matches.get_one::<String>("incident").unwrap().clone(),
+        pid: matches.get_one::<u32>("pid").copied(),
+        ip: matches.get_one::<std::net::IpAddr>("ip").copied(),
+        port: matches.get_one::<u16>("port").copied(),
+        interface: matches.get_one::<String>("interface").cloned(),
+        duration_secs: matches.get_one::<u64>("duration").copied(),
+        max_packets: matches.get_one::<u64>("max-packets").copied(),
+        ..Default::default()
+    };
+
+    let info = manager.start(request)?;
+    println!("Capturing {} to {:?} (Ctrl+C to stop early)", info.filter, info.path);
+
+    let info = loop {
+        tokio::select! {
+            _ = tokio::time::sleep(std::time::Duration::from_millis(500)) => {},
+            _ = tokio::signal::ctrl_c() => {
+                let _ = manager.stop(&info.id);
+            }
+        }
+        match manager.get(&info.id) {
+            Some(current) if current.status != CaptureStatus::Running => break current,
+            _ => {}
+        }
+    };
+
+    if let Some(ref err) = info.error {
+        error!("Capture failed: {}", err);
+        return Err(anyhow::anyhow!("Capture failed: {}", err));
+    }
+    println!("Captured {} packets ({}) to {:?}", info.packets, format_bytes(info.bytes as f64), info.path);
+    Ok(())
+}
+
 async fn run_interactive_mode(matches: &clap::ArgMatches) -> Result<()> {
     info!("Starting FluxDefense interactive mode...");
     
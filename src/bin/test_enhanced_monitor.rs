@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::Parser;
+use fluxdefense::config::{Config, ReloadableConfig};
 use fluxdefense::linux_security::{EnhancedSecurityMonitor, EnforcementMode};
 use fluxdefense::monitor::SecurityEvent;
 use std::path::PathBuf;
@@ -34,6 +35,10 @@ struct Args {
     /// Run duration in seconds (0 for infinite)
     #[arg(short, long, default_value = "0")]
     duration: u64,
+    
+    /// Agent config file whose monitor settings are applied
+    #[arg(short, long)]
+    config: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
@@ -93,6 +98,26 @@ fn main() -> Result<()> {
                     event.verdict.to_string()
                 );
             }
+            fluxdefense::monitor::SecurityEventType::Authentication { user, service, success, .. } => {
+                info!(
+                    "[{}] AUTH: {} via {} ({}) [{}]",
+                    count,
+                    user,
+                    service,
+                    if *success { "success" } else { "failed" },
+                    event.verdict.to_string()
+                );
+            }
+            fluxdefense::monitor::SecurityEventType::Syscall { syscall, success, .. } => {
+                info!(
+                    "[{}] SYSCALL: {} {} (success: {}) [{}]",
+                    count,
+                    event.process_info.path.display(),
+                    syscall,
+                    success,
+                    event.verdict.to_string()
+                );
+            }
         }
     })?;
     
@@ -110,6 +135,21 @@ fn main() -> Result<()> {
     monitor.set_enforcement_mode(mode)?;
     info!("Enforcement mode set to: {:?}", mode);
     
+    // Drives the SIGHUP reload
+    let runtime = tokio::runtime::Runtime::new()?;
+    if let Some(ref path) = args.config {
+        let config = Config::load_effective(Some(path))?;
+        config.validate()?;
+        monitor.apply_config(&config)?;
+        info!("Applied monitor settings from {}", path.display());
+        
+        let live = Arc::new(ReloadableConfig::new(Some(path.clone()), &config, config.clone())?);
+        monitor.follow_reloads(&live);
+        let _runtime = runtime.enter();
+        fluxdefense::config::reload::spawn_sighup_reload(live)?;
+        info!("Send SIGHUP to reload {}", path.display());
+    }
+    
     // Add allowed executables
     for exe in args.allow_exe {
         monitor.add_allowed_executable(exe.clone())?;
@@ -183,6 +223,9 @@ fn main() -> Result<()> {
     
     let total_events = event_counter.load(Ordering::SeqCst);
     info!("Monitoring stopped. Total events captured: {}", total_events);
+    let cache = monitor.decision_cache_stats();
+    info!("Exec decision cache: {} hits, {} misses ({:.0}%), {} evicted, {} invalidated",
+          cache.hits, cache.misses, cache.hit_rate * 100.0, cache.evictions, cache.invalidations);
     
     Ok(())
 }
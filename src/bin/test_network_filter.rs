@@ -2,9 +2,11 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 use fluxdefense::linux_security::{
     NetworkFilter, NetworkFilterRule, NetworkEvent, FilterAction, Direction, Protocol,
-    IptablesManager, IptablesRule, Chain, RuleAction,
+    IptablesManager, Chain, FlowExportConfig, FlowFormat,
+    CaptureOptions, CaptureBackend, DnsFilter, DnsAction, DnsEvent,
 };
-use fluxdefense::linux_security::network_filter::{IpMatcher, PortMatcher};
+use fluxdefense::linux_security::network_filter::{IpMatcher, PortMatcher, HostnameMatcher};
+use fluxdefense::network::{GeoIpDatabase, GeoIpInfo};
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -23,7 +25,7 @@ struct Args {
 enum Commands {
     /// Start packet capture and filtering
     Capture {
-        /// Network interface to capture on (default: any)
+        /// Network interface(s) to capture on, comma separated; accepts "any" and wildcards like "eth*" (default: system default device)
         #[arg(short, long)]
         interface: Option<String>,
         
@@ -38,6 +40,38 @@ enum Commands {
         /// Duration to capture in seconds (0 for infinite)
         #[arg(short = 't', long, default_value = "0")]
         duration: u64,
+        
+        /// Enrich events with country/ASN from the installed GeoIP databases
+        #[arg(short, long)]
+        geoip: bool,
+        
+        /// Export connection flows to this NetFlow/IPFIX collector (host:port)
+        #[arg(long)]
+        flow_collector: Option<String>,
+        
+        /// Flow export format: ipfix or netflow-v9
+        #[arg(long, default_value = "ipfix")]
+        flow_format: String,
+        
+        /// Flow export interval in seconds
+        #[arg(long, default_value = "60")]
+        flow_interval: u64,
+        
+        /// BPF filter expression applied in the kernel
+        #[arg(long)]
+        bpf: Option<String>,
+        
+        /// Derive the BPF filter from the active rules
+        #[arg(long)]
+        bpf_from_rules: bool,
+        
+        /// Use an AF_PACKET TPACKET_V3 ring instead of libpcap
+        #[arg(long)]
+        ring: bool,
+        
+        /// Packets processed per batch
+        #[arg(long, default_value = "64")]
+        batch_size: usize,
     },
     
     /// Manage iptables rules
@@ -76,6 +110,22 @@ enum Commands {
         #[arg(short = 'D', long)]
         dest_port: Option<u16>,
         
+        /// JA3/JA3S fingerprint (MD5) to match
+        #[arg(long)]
+        ja3: Option<String>,
+        
+        /// Destination hostname (TLS SNI or HTTP Host), matches subdomains too
+        #[arg(long)]
+        host: Option<String>,
+        
+        /// Destination country codes, comma separated (requires GeoIP)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["dest_ip", "dest_asn"])]
+        dest_country: Option<Vec<String>>,
+        
+        /// Destination AS numbers, comma separated (requires GeoIP)
+        #[arg(long, value_delimiter = ',', conflicts_with = "dest_ip")]
+        dest_asn: Option<Vec<u32>>,
+        
         /// Rule priority (higher = more important)
         #[arg(short = 'P', long, default_value = "100")]
         priority: i32,
@@ -86,6 +136,21 @@ enum Commands {
         /// Domain to block
         domain: String,
     },
+    
+    /// Run the filtering DNS proxy
+    DnsProxy {
+        /// Local port to listen on
+        #[arg(short, long, default_value = "5353")]
+        port: u16,
+        
+        /// Redirect this host's outbound DNS to the proxy with nftables
+        #[arg(long)]
+        transparent: bool,
+        
+        /// Upstream resolvers, comma separated (default: public resolvers)
+        #[arg(long, value_delimiter = ',')]
+        upstream: Option<Vec<std::net::SocketAddr>>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -136,24 +201,58 @@ fn main() -> Result<()> {
     let args = Args::parse();
     
     match args.command {
-        Commands::Capture { interface, filter, dns_filter, duration } => {
-            capture_packets(interface, filter, dns_filter, duration)?;
+        Commands::Capture { interface, filter, dns_filter, duration, geoip, flow_collector, flow_format, flow_interval, bpf, bpf_from_rules, ring, batch_size } => {
+            let flow_export = match flow_collector {
+                Some(collector) => Some(FlowExportConfig {
+                    collector,
+                    format: match flow_format.as_str() {
+                        "netflow-v9" | "v9" => FlowFormat::NetflowV9,
+                        "ipfix" => FlowFormat::Ipfix,
+                        other => return Err(anyhow::anyhow!("Unknown flow format {}", other)),
+                    },
+                    interval_secs: flow_interval,
+                    observation_domain_id: 0,
+                }),
+                None => None,
+            };
+            let capture_options = CaptureOptions {
+                backend: if ring { CaptureBackend::Tpacket } else { CaptureBackend::Pcap },
+                bpf_filter: bpf,
+                filter_from_rules: bpf_from_rules,
+                batch_size,
+                ..Default::default()
+            };
+            capture_packets(interface, filter, dns_filter, duration, geoip, flow_export, capture_options)?;
         }
         Commands::Iptables { action } => {
             handle_iptables(action)?;
         }
-        Commands::AddRule { name, action, protocol, source_ip, dest_ip, source_port, dest_port, priority } => {
-            add_filter_rule(name, action, protocol, source_ip, dest_ip, source_port, dest_port, priority)?;
+        Commands::AddRule { name, action, protocol, source_ip, dest_ip, source_port, dest_port, ja3, host, dest_country, dest_asn, priority } => {
+            add_filter_rule(name, action, protocol, source_ip, dest_ip, source_port, dest_port, ja3, host, dest_country, dest_asn, priority)?;
         }
         Commands::BlockDomain { domain } => {
             block_domain(domain)?;
         }
+        Commands::DnsProxy { port, transparent, upstream } => {
+            run_dns_proxy(port, transparent, upstream)?;
+        }
     }
     
     Ok(())
 }
 
-fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bool, duration: u64) -> Result<()> {
+fn format_geo(geo: &Option<GeoIpInfo>) -> String {
+    match geo {
+        Some(geo) => format!(
+            " [{}{}]",
+            geo.country.as_deref().unwrap_or("??"),
+            geo.asn.map(|asn| format!(" AS{}", asn)).unwrap_or_default()
+        ),
+        None => String::new(),
+    }
+}
+
+fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bool, duration: u64, geoip: bool, flow_export: Option<FlowExportConfig>, capture_options: CaptureOptions) -> Result<()> {
     // Check if running as root for packet capture
     let uid = unsafe { libc::geteuid() };
     if uid != 0 {
@@ -176,7 +275,7 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
     // Create network filter
     let mut filter = NetworkFilter::new(move |event: NetworkEvent| {
         match event {
-            NetworkEvent::PacketCaptured { timestamp, protocol, source, destination, size, action, rule_id } => {
+            NetworkEvent::PacketCaptured { protocol, source, destination, size, action, rule_id, geo, .. } => {
                 packet_count_clone.fetch_add(1, Ordering::SeqCst);
                 
                 let action_str = match action {
@@ -189,11 +288,12 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
                 };
                 
                 info!(
-                    "[{}] {:?} {}:{} -> {}:{} ({} bytes) [{}]{}",
+                    "[{}] {:?} {}:{} -> {}:{}{} ({} bytes) [{}]{}",
                     packet_count_clone.load(Ordering::SeqCst),
                     protocol,
                     source.0, source.1,
                     destination.0, destination.1,
+                    format_geo(&geo),
                     size,
                     action_str,
                     rule_id.map(|id| format!(" (rule: {})", id)).unwrap_or_default()
@@ -211,16 +311,38 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
                 };
                 
                 info!(
-                    "[DNS] {} ({}) from {} [{}]",
-                    domain, query_type, source, action_str
+                    "[DNS] {} ({}) from {}:{} [{}]",
+                    domain, query_type, source.0, source.1, action_str
                 );
             }
-            NetworkEvent::ConnectionNew { protocol, source, destination, .. } => {
+            NetworkEvent::DnsAnswer { domain, answers, rcode, client, .. } => {
+                info!("[DNS] {} -> {:?} (rcode {}) for {}:{}", domain, answers, rcode, client.0, client.1);
+            }
+            NetworkEvent::DnsRebinding { domain, resolved_ips, resolver, .. } => {
+                warn!("[DNS] Rebinding: {} resolved to {:?} via {}", domain, resolved_ips, resolver);
+            }
+            NetworkEvent::Tunneling { detection, .. } => {
+                warn!("[TUNNEL] {}", detection.description);
+            }
+            NetworkEvent::LanAnomaly { alert, .. } => {
+                warn!("[LAN] {}", alert.description);
+            }
+            NetworkEvent::Beaconing { detection, .. } => {
+                warn!("[BEACON] {}", detection.description());
+            }
+            NetworkEvent::DnsFastFlux { domain, unique_ips, unique_networks, min_ttl, .. } => {
+                warn!(
+                    "[DNS] Fast-flux: {} ({} addresses across {} networks, min TTL {}s)",
+                    domain, unique_ips.len(), unique_networks, min_ttl
+                );
+            }
+            NetworkEvent::ConnectionNew { protocol, source, destination, geo, .. } => {
                 info!(
-                    "[NEW] {:?} connection: {}:{} -> {}:{}",
+                    "[NEW] {:?} connection: {}:{} -> {}:{}{}",
                     protocol,
                     source.0, source.1,
-                    destination.0, destination.1
+                    destination.0, destination.1,
+                    format_geo(&geo)
                 );
             }
             NetworkEvent::ConnectionClosed { protocol, source, destination, duration, packets, bytes, .. } => {
@@ -235,6 +357,45 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
             NetworkEvent::RuleMatched { rule_name, action, packet_info, .. } => {
                 info!("[RULE] {} - {:?}: {}", rule_name, action, packet_info);
             }
+            NetworkEvent::TlsFingerprint { kind, source, destination, fingerprint, action, threat, .. } => {
+                if action == FilterAction::Block {
+                    blocked_count_clone.fetch_add(1, Ordering::SeqCst);
+                }
+                
+                info!(
+                    "[TLS] {:?} {}:{} -> {}:{} fingerprint {} [{:?}]{}",
+                    kind,
+                    source.0, source.1,
+                    destination.0, destination.1,
+                    fingerprint,
+                    action,
+                    threat.map(|t| format!(" (threat: {})", t)).unwrap_or_default()
+                );
+            }
+            NetworkEvent::HostnameObserved { source, destination, hostname, hostname_source, action, .. } => {
+                if action == FilterAction::Block {
+                    blocked_count_clone.fetch_add(1, Ordering::SeqCst);
+                }
+                
+                info!(
+                    "[HOST] {}:{} -> {}:{} {} via {:?} [{:?}]",
+                    source.0, source.1,
+                    destination.0, destination.1,
+                    hostname, hostname_source, action
+                );
+            }
+            NetworkEvent::TlsHelloIncomplete { source, destination, action, .. } => {
+                if action == FilterAction::Block {
+                    blocked_count_clone.fetch_add(1, Ordering::SeqCst);
+                }
+                
+                info!(
+                    "[TLS] {}:{} -> {}:{} incomplete hello [{:?}]",
+                    source.0, source.1,
+                    destination.0, destination.1,
+                    action
+                );
+            }
         }
     })?;
     
@@ -242,6 +403,13 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
     filter.set_filtering_enabled(filtering);
     filter.set_dns_filtering_enabled(dns_filtering);
     
+    if geoip {
+        match GeoIpDatabase::open_default() {
+            Ok(db) => filter.set_geoip_database(Some(db))?,
+            Err(e) => warn!("GeoIP enrichment disabled: {}", e),
+        }
+    }
+    
     // Add some example rules if filtering is enabled
     if filtering {
         info!("Adding example filtering rules");
@@ -257,8 +425,12 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
             dest_ip: Some(IpMatcher::Subnet(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 16)),
             source_port: None,
             dest_port: None,
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: None,
             priority: 100,
             enabled: true,
+            shadow: false,
         })?;
         
         // Log all HTTP/HTTPS traffic
@@ -272,8 +444,12 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
             dest_ip: None,
             source_port: None,
             dest_port: Some(PortMatcher::Single(80)),
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: None,
             priority: 50,
             enabled: true,
+            shadow: false,
         })?;
         
         filter.add_rule(NetworkFilterRule {
@@ -286,8 +462,12 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
             dest_ip: None,
             source_port: None,
             dest_port: Some(PortMatcher::Single(443)),
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: None,
             priority: 50,
             enabled: true,
+            shadow: false,
         })?;
     }
     
@@ -302,14 +482,27 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
     // Start filter
     filter.start()?;
     
+    if let Some(flow_config) = flow_export {
+        filter.start_flow_export(flow_config)?;
+    }
+    
     // Start packet capture
-    filter.start_capture(interface.as_deref())?;
+    filter.set_capture_options(capture_options);
+    if let Some(expression) = filter.capture_filter()? {
+        info!("BPF filter: {}", expression);
+    }
+    match interface.as_deref() {
+        Some(interfaces) => filter.start_capture_interfaces(
+            interfaces.split(',').map(|i| i.trim().to_string()).filter(|i| !i.is_empty()).collect(),
+        )?,
+        None => filter.start_capture(None)?,
+    }
     
     // Show status
     println!("\n╔═══════════════════════════════════════════════════════════════╗");
     println!("║            FluxDefense Network Filter Active                  ║");
     println!("╠═══════════════════════════════════════════════════════════════╣");
-    println!("║ Interface: {:^50} ║", interface.as_deref().unwrap_or("any"));
+    println!("║ Interface: {:^50} ║", interface.as_deref().unwrap_or("default"));
     println!("║ Filtering: {:^50} ║", if filtering { "ENABLED" } else { "DISABLED (monitoring only)" });
     println!("║ DNS Filter: {:^49} ║", if dns_filtering { "ENABLED" } else { "DISABLED" });
     println!("║                                                               ║");
@@ -341,6 +534,9 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
         }
     }
     
+    // Per-interface counters are gone once the capture is stopped
+    let interface_stats = filter.get_interface_stats();
+    
     // Stop filter
     filter.stop()?;
     
@@ -358,6 +554,22 @@ fn capture_packets(interface: Option<String>, filtering: bool, dns_filtering: bo
     println!("║ Connections tracked: {:>40} ║", stats.connections_tracked);
     println!("╚═══════════════════════════════════════════════════════════════╝");
     
+    if !interface_stats.is_empty() {
+        println!("\n{:<16} {:>8} {:>12} {:>14} {:>8} {:>8} {:>9}", "INTERFACE", "STATE", "PACKETS", "BYTES", "DROPPED", "ERRORS", "ATTACHES");
+        for iface in &interface_stats {
+            println!(
+                "{:<16} {:>8} {:>12} {:>14} {:>8} {:>8} {:>9}",
+                iface.interface,
+                if iface.attached { "up" } else { "down" },
+                iface.packets,
+                iface.bytes,
+                iface.dropped + iface.if_dropped,
+                iface.errors,
+                iface.attach_count,
+            );
+        }
+    }
+    
     Ok(())
 }
 
@@ -431,6 +643,10 @@ fn add_filter_rule(
     dest_ip: Option<String>,
     source_port: Option<u16>,
     dest_port: Option<u16>,
+    ja3: Option<String>,
+    host: Option<String>,
+    dest_country: Option<Vec<String>>,
+    dest_asn: Option<Vec<u32>>,
     priority: i32,
 ) -> Result<()> {
     info!("Adding network filter rule: {}", name);
@@ -467,11 +683,17 @@ fn add_filter_rule(
         }),
         dest_ip: dest_ip.map(|ip| {
             IpMatcher::Single(ip.parse().expect("Invalid destination IP"))
-        }),
+        })
+        .or_else(|| dest_country.map(|c| IpMatcher::Country(c.iter().map(|c| c.to_uppercase()).collect())))
+        .or_else(|| dest_asn.map(IpMatcher::Asn)),
         source_port: source_port.map(PortMatcher::Single),
         dest_port: dest_port.map(PortMatcher::Single),
+        tls_fingerprints: ja3.map(|fp| vec![fp.to_lowercase()]),
+        dest_hostname: host.map(|h| HostnameMatcher::Suffix(h.to_lowercase())),
+        process: None,
         priority,
         enabled: true,
+        shadow: false,
     };
     
     info!("Rule created: {:?}", rule);
@@ -484,4 +706,40 @@ fn block_domain(domain: String) -> Result<()> {
     info!("Blocking domain: {}", domain);
     info!("Note: This would be added to an active filter's DNS blacklist");
     Ok(())
-}
\ No newline at end of file
+}
+
+fn run_dns_proxy(port: u16, transparent: bool, upstream: Option<Vec<std::net::SocketAddr>>) -> Result<()> {
+    let filter = DnsFilter::new()?;
+    filter.load_default_blacklists()?;
+    let mut config = filter.config();
+    config.listen_port = port;
+    config.transparent_redirect = transparent;
+    if let Some(upstream) = upstream {
+        config.upstream_servers = upstream;
+    }
+    filter.set_config(config)?;
+    
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async move {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<DnsEvent>(1024);
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                match event.action {
+                    DnsAction::Blocked => warn!("[DNS] Blocked {} from {} ({})", event.domain, event.source, event.reason.unwrap_or_default()),
+                    _ => info!("[DNS] {} from {} [{:?}]", event.domain, event.source, event.action),
+                }
+            }
+        });
+        
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        // Returning drops the proxy future, which removes any redirect rules
+        tokio::select! {
+            result = filter.start_dns_proxy(tx) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+            _ = sigterm.recv() => Ok(()),
+        }
+    })?;
+    
+    info!("DNS proxy stopped");
+    Ok(())
+}
@@ -107,8 +107,12 @@ fn test_network_filter() -> Result<()> {
         dest_ip: None,
         source_port: None,
         dest_port: Some(PortMatcher::Single(23)),
+        tls_fingerprints: None,
+        dest_hostname: None,
+        process: None,
         priority: 100,
         enabled: true,
+        shadow: false,
     })?;
     
     filter.add_dns_blacklist("malware.com".to_string())?;
@@ -0,0 +1,211 @@
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+// Batch size for one kevent call
+const MAX_EVENTS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessEvent {
+    Exec { pid: u32 },
+    Fork { pid: u32, parent_pid: u32 },
+    Exit { pid: u32, status: i64 },
+}
+
+// Process lifecycle events through EVFILT_PROC. Every process is watched
+// with NOTE_TRACK, so the kernel attaches the same filter to each child as
+// it forks and nothing started later is missed. Needs root to watch
+// processes of other users
+pub struct ProcessWatcher {
+    kq: OwnedFd,
+}
+
+impl ProcessWatcher {
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            return Err(anyhow!("Failed to create kqueue: {}", std::io::Error::last_os_error()));
+        }
+        Ok(Self { kq: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+
+    pub fn watch(&self, pid: u32) -> Result<()> {
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        change.ident = pid as libc::uintptr_t;
+        change.filter = libc::EVFILT_PROC;
+        change.flags = libc::EV_ADD | libc::EV_ENABLE;
+        change.fflags = libc::NOTE_EXEC | libc::NOTE_FORK | libc::NOTE_EXIT | libc::NOTE_TRACK;
+        let ret = unsafe {
+            libc::kevent(self.kq.as_raw_fd(), &change, 1, std::ptr::null_mut(), 0, std::ptr::null())
+        };
+        if ret < 0 {
+            return Err(anyhow!("Failed to watch process {}: {}", pid, std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    // Watches every running process; ones that exit in the meantime are
+    // skipped. Returns how many are watched
+    pub fn watch_all(&self) -> Result<usize> {
+        let watched = running_pids()?.into_iter()
+            .filter(|&pid| pid != 0)
+            .filter(|&pid| match self.watch(pid) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!("{}", e);
+                    false
+                }
+            })
+            .count();
+        Ok(watched)
+    }
+
+    // Waits up to `timeout` for events
+    pub fn read_events(&self, timeout: Duration) -> Result<Vec<ProcessEvent>> {
+        let mut events: Vec<libc::kevent> = vec![unsafe { std::mem::zeroed() }; MAX_EVENTS];
+        let timeout = libc::timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        let received = unsafe {
+            libc::kevent(self.kq.as_raw_fd(), std::ptr::null(), 0, events.as_mut_ptr(), MAX_EVENTS as libc::c_int, &timeout)
+        };
+        if received < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(anyhow!("Failed to read process events: {}", err));
+        }
+
+        let mut parsed = Vec::new();
+        for event in &events[..received as usize] {
+            let pid = event.ident as u32;
+            if event.flags & libc::EV_ERROR != 0 {
+                debug!("Process event error for {}: {}", pid, std::io::Error::from_raw_os_error(event.data as i32));
+                continue;
+            }
+            // The parent's NOTE_FORK is reported again as the child's
+            // NOTE_CHILD, which carries both pids
+            if event.fflags & libc::NOTE_CHILD != 0 {
+                parsed.push(ProcessEvent::Fork { pid, parent_pid: event.data as u32 });
+            }
+            if event.fflags & libc::NOTE_TRACKERR != 0 {
+                warn!("Could not attach to a child of process {}; its events are missed", pid);
+            }
+            if event.fflags & libc::NOTE_EXEC != 0 {
+                parsed.push(ProcessEvent::Exec { pid });
+            }
+            if event.fflags & libc::NOTE_EXIT != 0 {
+                parsed.push(ProcessEvent::Exit { pid, status: event.data as i64 });
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn running_pids() -> Result<Vec<u32>> {
+    let output = std::process::Command::new("ps").args(["-ax", "-o", "pid="]).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("ps failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect())
+}
+
+// Parent pid and user id from the kernel's process table
+#[cfg(target_os = "freebsd")]
+pub fn process_ids(pid: u32) -> Option<(u32, u32)> {
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid as libc::c_int];
+    let mut info: libc::kinfo_proc = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::kinfo_proc>();
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, &mut info as *mut _ as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0)
+    };
+    (ret == 0 && len > 0).then(|| (info.ki_ppid as u32, info.ki_uid))
+}
+
+#[cfg(target_os = "openbsd")]
+pub fn process_ids(pid: u32) -> Option<(u32, u32)> {
+    let size = std::mem::size_of::<libc::kinfo_proc>();
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid as libc::c_int, size as libc::c_int, 1];
+    let mut info: libc::kinfo_proc = unsafe { std::mem::zeroed() };
+    let mut len = size;
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, &mut info as *mut _ as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0)
+    };
+    (ret == 0 && len > 0).then(|| (info.p_ppid as u32, info.p_uid))
+}
+
+// The executable a process runs, read right after its exec
+#[cfg(target_os = "freebsd")]
+pub fn executable_path(pid: u32) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PATHNAME, pid as libc::c_int];
+    let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+    let mut len = buffer.len();
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, buffer.as_mut_ptr() as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0)
+    };
+    if ret != 0 || len == 0 {
+        return None;
+    }
+    let end = buffer[..len].iter().position(|&b| b == 0).unwrap_or(len);
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(&buffer[..end])))
+}
+
+// OpenBSD keeps no executable path; argv[0] is the best there is
+#[cfg(target_os = "openbsd")]
+pub fn executable_path(pid: u32) -> Option<PathBuf> {
+    command_line(pid)?.into_iter().next().map(PathBuf::from)
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn command_line(pid: u32) -> Option<Vec<String>> {
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_ARGS, pid as libc::c_int];
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut len = buffer.len();
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, buffer.as_mut_ptr() as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0)
+    };
+    if ret != 0 {
+        return None;
+    }
+    // NUL separated arguments
+    Some(buffer[..len].split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect())
+}
+
+#[cfg(target_os = "openbsd")]
+pub fn command_line(pid: u32) -> Option<Vec<String>> {
+    let mib = [libc::CTL_KERN, libc::KERN_PROC_ARGS, pid as libc::c_int, libc::KERN_PROC_ARGV];
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut len = buffer.len();
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, buffer.as_mut_ptr() as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0)
+    };
+    if ret != 0 {
+        return None;
+    }
+    // A NULL terminated argv whose pointers the kernel has rewritten to
+    // point into the buffer
+    let argv = buffer.as_ptr() as *const *const libc::c_char;
+    let mut args = Vec::new();
+    for index in 0.. {
+        if (index + 1) * std::mem::size_of::<*const libc::c_char>() > len {
+            break;
+        }
+        let arg = unsafe { *argv.add(index) };
+        if arg.is_null() {
+            break;
+        }
+        args.push(unsafe { std::ffi::CStr::from_ptr(arg) }.to_string_lossy().into_owned());
+    }
+    Some(args)
+}
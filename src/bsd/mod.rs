@@ -0,0 +1,168 @@
+pub mod pf;
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+pub mod kqueue;
+
+pub use pf::{PfState, PfStateTracker};
+
+use crate::monitor::{PassiveMonitor, ProcessInfo};
+
+// What the BSD watchers hand to the passive monitor
+#[derive(Debug, Clone)]
+pub enum BsdEvent {
+    Exec(ProcessInfo),
+    // A new outbound pf state; pf does not know the process behind it
+    Connection(PfState),
+}
+
+pub fn handle_event(monitor: &PassiveMonitor, event: BsdEvent) {
+    match event {
+        BsdEvent::Exec(process) => {
+            let target = process.path.clone();
+            monitor.handle_file_execution_event(process, target, None, None);
+        }
+        BsdEvent::Connection(state) => {
+            let Some(protocol) = state.network_protocol() else { return };
+            let process = ProcessInfo {
+                pid: 0,
+                path: std::path::PathBuf::from("unknown"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            };
+            monitor.handle_network_connection_event(process, state.remote.ip().to_string(), state.remote.port(), None, protocol);
+        }
+    }
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+pub use watchers::BsdMonitor;
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+mod watchers {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+    use anyhow::Result;
+    use tracing::{debug, info, warn};
+
+    use super::kqueue::{self, ProcessEvent, ProcessWatcher};
+    use super::{pf, BsdEvent, PfStateTracker};
+    use crate::monitor::ProcessInfo;
+
+    // Events waiting for `try_events`; beyond this they are dropped
+    const QUEUE_CAPACITY: usize = 10_000;
+    const PROCESS_POLL: Duration = Duration::from_millis(500);
+    // pf has no change notifications, so its state table is diffed
+    const PF_POLL: Duration = Duration::from_secs(2);
+
+    // Process events from kqueue and connections from pf's state table, each
+    // watched on its own thread
+    pub struct BsdMonitor {
+        running: Arc<AtomicBool>,
+        events: Receiver<BsdEvent>,
+        threads: Vec<JoinHandle<()>>,
+    }
+
+    impl BsdMonitor {
+        pub fn start() -> Result<Self> {
+            let running = Arc::new(AtomicBool::new(true));
+            let (sender, events) = sync_channel(QUEUE_CAPACITY);
+            let mut threads = Vec::new();
+
+            let watcher = ProcessWatcher::new()?;
+            info!("Watching {} processes through kqueue", watcher.watch_all()?);
+            let (process_running, process_sender) = (Arc::clone(&running), sender.clone());
+            threads.push(std::thread::spawn(move || watch_processes(watcher, process_sender, process_running)));
+
+            match pf::read_states() {
+                Ok(states) => {
+                    // Connections open before startup are not reported
+                    let mut tracker = PfStateTracker::new();
+                    tracker.update(states);
+                    let pf_running = Arc::clone(&running);
+                    threads.push(std::thread::spawn(move || watch_pf(tracker, sender, pf_running)));
+                }
+                Err(e) => warn!("pf state table unavailable, connections are not monitored: {}", e),
+            }
+            Ok(Self { running, events, threads })
+        }
+
+        pub fn try_events(&self) -> Vec<BsdEvent> {
+            self.events.try_iter().collect()
+        }
+
+        pub fn stop(&mut self) {
+            self.running.store(false, Ordering::Relaxed);
+            for thread in self.threads.drain(..) {
+                thread.join().ok();
+            }
+        }
+    }
+
+    impl Drop for BsdMonitor {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
+    fn send(sender: &SyncSender<BsdEvent>, event: BsdEvent) -> bool {
+        match sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                debug!("BSD event queue full, dropping an event");
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    fn watch_processes(watcher: ProcessWatcher, sender: SyncSender<BsdEvent>, running: Arc<AtomicBool>) {
+        while running.load(Ordering::Relaxed) {
+            let events = match watcher.read_events(PROCESS_POLL) {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Process watching stopped: {}", e);
+                    return;
+                }
+            };
+            for event in events {
+                let ProcessEvent::Exec { pid } = event else { continue };
+                // The process may already be gone
+                let Some(path) = kqueue::executable_path(pid) else { continue };
+                let (parent_pid, user_id) = kqueue::process_ids(pid).unwrap_or((0, 0));
+                let process = ProcessInfo {
+                    pid,
+                    path,
+                    parent_pid: Some(parent_pid),
+                    user_id,
+                    executable_hash: None,
+                    command_line: kqueue::command_line(pid).map(|args| args.join(" ")),
+                };
+                if !send(&sender, BsdEvent::Exec(process)) {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn watch_pf(mut tracker: PfStateTracker, sender: SyncSender<BsdEvent>, running: Arc<AtomicBool>) {
+        while running.load(Ordering::Relaxed) {
+            std::thread::sleep(PF_POLL);
+            let states = match pf::read_states() {
+                Ok(states) => states,
+                Err(e) => {
+                    warn!("Reading pf states failed: {}", e);
+                    continue;
+                }
+            };
+            for state in tracker.update(states).into_iter().filter(|state| state.outbound) {
+                if !send(&sender, BsdEvent::Connection(state)) {
+                    return;
+                }
+            }
+        }
+    }
+}
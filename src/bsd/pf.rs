@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use anyhow::{anyhow, Result};
+
+use crate::monitor::NetworkProtocol;
+
+// One entry of pf's state table, as printed by `pfctl -s states`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PfState {
+    pub interface: String,
+    pub protocol: String,
+    // This host's end, before any NAT
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    // Opened from this host
+    pub outbound: bool,
+    pub state: String,
+}
+
+impl PfState {
+    pub fn network_protocol(&self) -> Option<NetworkProtocol> {
+        match self.protocol.as_str() {
+            "tcp" => Some(NetworkProtocol::Tcp),
+            "udp" => Some(NetworkProtocol::Udp),
+            "icmp" | "ipv6-icmp" => Some(NetworkProtocol::Icmp),
+            _ => None,
+        }
+    }
+}
+
+// pfctl prints IPv4 ends as addr:port and IPv6 ends as addr[port]; ICMP
+// ends carry the ICMP id as the port
+fn parse_endpoint(text: &str) -> Option<SocketAddr> {
+    if let Some((addr, port)) = text.strip_suffix(']').and_then(|text| text.split_once('[')) {
+        return Some(SocketAddr::new(addr.parse().ok()?, port.parse().ok()?));
+    }
+    let (addr, port) = text.rsplit_once(':')?;
+    Some(SocketAddr::new(addr.parse::<IpAddr>().ok()?, port.parse().ok()?))
+}
+
+// Lines look like
+//   all tcp 192.168.1.10:52344 (203.0.113.7:61234) -> 93.184.216.34:443       ESTABLISHED:ESTABLISHED
+//   em0 tcp 10.0.0.1:22 <- 10.0.0.5:51000       ESTABLISHED:ESTABLISHED
+// where the parenthesized address is the NAT translation of the left end.
+// Lines in any other shape are skipped
+pub fn parse_states(output: &str) -> Vec<PfState> {
+    output.lines().filter_map(parse_state).collect()
+}
+
+fn parse_state(line: &str) -> Option<PfState> {
+    let words: Vec<&str> = line.split_whitespace()
+        .filter(|word| !(word.starts_with('(') && word.ends_with(')')))
+        .collect();
+    let [interface, protocol, left, direction, right, state] = words[..] else { return None };
+    let outbound = match direction {
+        "->" => true,
+        "<-" => false,
+        _ => return None,
+    };
+    Some(PfState {
+        interface: interface.to_string(),
+        protocol: protocol.to_string(),
+        local: parse_endpoint(left)?,
+        remote: parse_endpoint(right)?,
+        outbound,
+        state: state.to_string(),
+    })
+}
+
+pub fn read_states() -> Result<Vec<PfState>> {
+    let output = std::process::Command::new("pfctl").args(["-q", "-s", "states"]).output()
+        .map_err(|e| anyhow!("Failed to run pfctl: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!("pfctl -s states failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(parse_states(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Reports each connection once, when its state first shows up. States are
+// keyed by protocol and both ends, so a state that expires and is opened
+// again later counts as new
+#[derive(Default)]
+pub struct PfStateTracker {
+    seen: HashSet<(String, SocketAddr, SocketAddr)>,
+}
+
+impl PfStateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, states: Vec<PfState>) -> Vec<PfState> {
+        let current: HashSet<_> = states.iter()
+            .map(|state| (state.protocol.clone(), state.local, state.remote))
+            .collect();
+        let new = states.into_iter()
+            .filter(|state| !self.seen.contains(&(state.protocol.clone(), state.local, state.remote)))
+            .collect();
+        self.seen = current;
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pf_states() {
+        let output = "\
+all tcp 192.168.1.10:52344 (203.0.113.7:61234) -> 93.184.216.34:443       ESTABLISHED:ESTABLISHED
+em0 tcp 10.0.0.1:22 <- 10.0.0.5:51000       ESTABLISHED:ESTABLISHED
+all udp 2001:db8::10[5353] -> 2001:db8::1[53]       MULTIPLE:SINGLE
+No ALTQ support in kernel
+all carp 10.0.0.2 -> 224.0.0.18       NO_TRAFFIC:SINGLE
+";
+        let states = parse_states(output);
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[0].local, "192.168.1.10:52344".parse().unwrap());
+        assert_eq!(states[0].remote, "93.184.216.34:443".parse().unwrap());
+        assert!(states[0].outbound && !states[1].outbound);
+        assert_eq!(states[1].remote, "10.0.0.5:51000".parse().unwrap());
+        assert_eq!(states[2].remote, "[2001:db8::1]:53".parse().unwrap());
+        assert!(matches!(states[2].network_protocol(), Some(NetworkProtocol::Udp)));
+
+        let mut tracker = PfStateTracker::new();
+        assert_eq!(tracker.update(states.clone()).len(), 3);
+        assert!(tracker.update(states[..2].to_vec()).is_empty());
+        // Expired, then opened again
+        assert_eq!(tracker.update(states.clone()).len(), 1);
+    }
+}
@@ -0,0 +1,506 @@
+pub mod pcap_file;
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+use tracing::{info, warn, error};
+use uuid::Uuid;
+
+pub use pcap_file::PcapWriter;
+
+const DEFAULT_DURATION_SECS: u64 = 60;
+const MAX_DURATION_SECS: u64 = 3600;
+const DEFAULT_MAX_PACKETS: u64 = 100_000;
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+const MAX_BYTES: u64 = 1024 * 1024 * 1024;
+const DEFAULT_SNAPLEN: u32 = 65_535;
+const MAX_CONCURRENT_CAPTURES: usize = 4;
+// How often a pid filter re-resolves the process's sockets
+const PID_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureRequest {
+    pub incident_id: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub ip: Option<IpAddr>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    // All interfaces when unset
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    #[serde(default)]
+    pub max_packets: Option<u64>,
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub snaplen: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureStatus {
+    Running,
+    Completed,
+    Stopped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureInfo {
+    pub id: String,
+    pub incident_id: String,
+    pub filter: String,
+    pub interface: Option<String>,
+    pub path: PathBuf,
+    pub status: CaptureStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub packets: u64,
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+// Which packets a capture keeps. A pid matches packets on any local port the
+// process has a socket bound to.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilter {
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub pid: Option<u32>,
+}
+
+impl CaptureFilter {
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ip) = self.ip {
+            parts.push(format!("host {}", ip));
+        }
+        if let Some(port) = self.port {
+            parts.push(format!("port {}", port));
+        }
+        if let Some(pid) = self.pid {
+            parts.push(format!("pid {}", pid));
+        }
+        if parts.is_empty() {
+            "all".to_string()
+        } else {
+            parts.join(" and ")
+        }
+    }
+
+    pub fn matches(&self, packet: &PacketSummary, pid_ports: &HashSet<u16>) -> bool {
+        if let Some(ip) = self.ip {
+            if packet.src != ip && packet.dst != ip {
+                return false;
+            }
+        }
+
+        let ports = [packet.src_port, packet.dst_port];
+        if let Some(port) = self.port {
+            if !ports.contains(&Some(port)) {
+                return false;
+            }
+        }
+        if self.pid.is_some() && !ports.iter().flatten().any(|p| pid_ports.contains(p)) {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketSummary {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+}
+
+// Addresses and TCP/UDP ports of a network-layer packet
+pub fn parse_packet(ethertype: u16, data: &[u8]) -> Option<PacketSummary> {
+    let (src, dst, protocol, payload) = match ethertype {
+        0x0800 if data.len() >= 20 => {
+            let header_len = ((data[0] & 0x0f) as usize) * 4;
+            let src = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
+            let dst = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+            (IpAddr::V4(src), IpAddr::V4(dst), data[9], data.get(header_len..)?)
+        }
+        0x86dd if data.len() >= 40 => {
+            let src: [u8; 16] = data[8..24].try_into().ok()?;
+            let dst: [u8; 16] = data[24..40].try_into().ok()?;
+            (IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)), data[6], &data[40..])
+        }
+        _ => return None,
+    };
+
+    let (src_port, dst_port) = match protocol {
+        6 | 17 if payload.len() >= 4 => (
+            Some(u16::from_be_bytes([payload[0], payload[1]])),
+            Some(u16::from_be_bytes([payload[2], payload[3]])),
+        ),
+        _ => (None, None),
+    };
+
+    Some(PacketSummary { src, dst, src_port, dst_port })
+}
+
+// Local ports of every TCP/UDP socket the process holds
+pub fn process_ports(pid: u32) -> HashSet<u16> {
+    let mut inodes = HashSet::new();
+    if let Ok(entries) = std::fs::read_dir(format!("/proc/{}/fd", pid)) {
+        for entry in entries.flatten() {
+            if let Ok(target) = std::fs::read_link(entry.path()) {
+                let target = target.to_string_lossy();
+                if let Some(inode) = target.strip_prefix("socket:[").and_then(|s| s.strip_suffix(']')) {
+                    inodes.insert(inode.to_string());
+                }
+            }
+        }
+    }
+
+    let mut ports = HashSet::new();
+    for table in ["tcp", "tcp6", "udp", "udp6"] {
+        let content = match std::fs::read_to_string(format!("/proc/net/{}", table)) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || !inodes.contains(fields[9]) {
+                continue;
+            }
+            if let Some(port) = fields[1].rsplit(':').next().and_then(|p| u16::from_str_radix(p, 16).ok()) {
+                ports.insert(port);
+            }
+        }
+    }
+    ports
+}
+
+struct CaptureLimits {
+    duration: Duration,
+    max_packets: u64,
+    max_bytes: u64,
+    snaplen: u32,
+}
+
+// Runs bounded packet captures tied to incidents and keeps track of their files
+pub struct CaptureManager {
+    output_dir: PathBuf,
+    captures: Arc<RwLock<HashMap<String, CaptureInfo>>>,
+    stop_flags: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl CaptureManager {
+    pub fn new(output_dir: PathBuf) -> Self {
+        Self {
+            output_dir,
+            captures: Arc::new(RwLock::new(HashMap::new())),
+            stop_flags: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    // Starts a capture in the background and returns its initial state
+    pub fn start(&self, request: CaptureRequest) -> Result<CaptureInfo> {
+        let (info, filter, limits) = self.prepare(&request)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        {
+            let mut stop_flags = self.stop_flags.lock()
+                .map_err(|_| anyhow!("Failed to acquire capture lock"))?;
+            if stop_flags.len() >= MAX_CONCURRENT_CAPTURES {
+                return Err(anyhow!("Too many captures running (max {})", MAX_CONCURRENT_CAPTURES));
+            }
+            stop_flags.insert(info.id.clone(), Arc::clone(&stop));
+        }
+        self.captures.write()
+            .map_err(|_| anyhow!("Failed to acquire captures write lock"))?
+            .insert(info.id.clone(), info.clone());
+
+        let captures = Arc::clone(&self.captures);
+        let stop_flags = Arc::clone(&self.stop_flags);
+        let id = info.id.clone();
+        let path = info.path.clone();
+        let interface = request.interface.clone();
+
+        std::thread::spawn(move || {
+            let result = run_capture(&path, interface.as_deref(), &filter, &limits, &stop, &captures, &id);
+
+            if let Ok(mut captures) = captures.write() {
+                if let Some(info) = captures.get_mut(&id) {
+                    info.finished_at = Some(Utc::now());
+                    match result {
+                        Ok(()) if stop.load(Ordering::Relaxed) => info.status = CaptureStatus::Stopped,
+                        Ok(()) => info.status = CaptureStatus::Completed,
+                        Err(e) => {
+                            error!("Capture {} failed: {}", id, e);
+                            info.status = CaptureStatus::Failed;
+                            info.error = Some(e.to_string());
+                        }
+                    }
+                    info!("Capture {} finished: {} packets, {} bytes in {:?}", id, info.packets, info.bytes, info.path);
+                }
+            }
+            if let Ok(mut stop_flags) = stop_flags.lock() {
+                stop_flags.remove(&id);
+            }
+        });
+
+        info!("Started capture {} for incident {} ({})", info.id, info.incident_id, info.filter);
+        Ok(info)
+    }
+
+    fn prepare(&self, request: &CaptureRequest) -> Result<(CaptureInfo, CaptureFilter, CaptureLimits)> {
+        let valid_id = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+        if request.incident_id.is_empty() || request.incident_id.starts_with('.') || !request.incident_id.chars().all(valid_id) {
+            return Err(anyhow!("Invalid incident id {:?}", request.incident_id));
+        }
+
+        let limits = CaptureLimits {
+            duration: Duration::from_secs(request.duration_secs.unwrap_or(DEFAULT_DURATION_SECS).clamp(1, MAX_DURATION_SECS)),
+            max_packets: request.max_packets.unwrap_or(DEFAULT_MAX_PACKETS).max(1),
+            max_bytes: request.max_bytes.unwrap_or(DEFAULT_MAX_BYTES).clamp(1, MAX_BYTES),
+            snaplen: request.snaplen.unwrap_or(DEFAULT_SNAPLEN).clamp(64, DEFAULT_SNAPLEN),
+        };
+        let filter = CaptureFilter { ip: request.ip, port: request.port, pid: request.pid };
+
+        let id = Uuid::new_v4().to_string();
+        let dir = self.output_dir.join(&request.incident_id);
+        std::fs::create_dir_all(&dir)?;
+
+        let info = CaptureInfo {
+            path: dir.join(format!("{}.pcap", id)),
+            id,
+            incident_id: request.incident_id.clone(),
+            filter: filter.describe(),
+            interface: request.interface.clone(),
+            status: CaptureStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            packets: 0,
+            bytes: 0,
+            error: None,
+        };
+        Ok((info, filter, limits))
+    }
+
+    pub fn stop(&self, id: &str) -> Result<()> {
+        let stop_flags = self.stop_flags.lock()
+            .map_err(|_| anyhow!("Failed to acquire capture lock"))?;
+        let flag = stop_flags.get(id).ok_or_else(|| anyhow!("Capture {} is not running", id))?;
+        flag.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<CaptureInfo> {
+        self.captures.read().ok()?.get(id).cloned()
+    }
+
+    pub fn list(&self, incident_id: Option<&str>) -> Vec<CaptureInfo> {
+        let mut captures: Vec<CaptureInfo> = self.captures.read()
+            .map(|c| c.values()
+                .filter(|info| incident_id.is_none_or(|id| info.incident_id == id))
+                .cloned()
+                .collect())
+            .unwrap_or_default();
+        captures.sort_by_key(|capture| std::cmp::Reverse(capture.started_at));
+        captures
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_capture(
+    path: &Path,
+    interface: Option<&str>,
+    filter: &CaptureFilter,
+    limits: &CaptureLimits,
+    stop: &AtomicBool,
+    captures: &RwLock<HashMap<String, CaptureInfo>>,
+    id: &str,
+) -> Result<()> {
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+    use pcap_file::{sll_header, LINKTYPE_LINUX_SLL, SLL_HEADER_LEN};
+
+    // SOCK_DGRAM strips link-layer headers so every interface type looks the same
+    let fd = unsafe {
+        libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, (libc::ETH_P_ALL as u16).to_be() as i32)
+    };
+    if fd < 0 {
+        return Err(anyhow!("Failed to open packet socket: {}", std::io::Error::last_os_error()));
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if let Some(name) = interface {
+        let c_name = std::ffi::CString::new(name)?;
+        let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if index == 0 {
+            return Err(anyhow!("Unknown interface {}", name));
+        }
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+        addr.sll_ifindex = index as i32;
+        let ret = unsafe {
+            libc::bind(socket.as_raw_fd(), &addr as *const _ as *const libc::sockaddr, std::mem::size_of::<libc::sockaddr_ll>() as u32)
+        };
+        if ret != 0 {
+            return Err(anyhow!("Failed to bind to {}: {}", name, std::io::Error::last_os_error()));
+        }
+    }
+
+    // Short receive timeout so stop requests and the deadline are noticed
+    let timeout = libc::timeval { tv_sec: 0, tv_usec: 200_000 };
+    unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_RCVTIMEO,
+            &timeout as *const _ as *const libc::c_void, std::mem::size_of::<libc::timeval>() as u32,
+        );
+    }
+
+    let loopback = unsafe { libc::if_nametoindex(c"lo".as_ptr()) } as i32;
+    let mut writer = PcapWriter::create(path, limits.snaplen, LINKTYPE_LINUX_SLL)?;
+    let mut buffer = vec![0u8; SLL_HEADER_LEN + 65_536];
+    let deadline = Instant::now() + limits.duration;
+    let mut pid_ports = HashSet::new();
+    let mut pid_refreshed: Option<Instant> = None;
+    let (mut packets, mut bytes) = (0u64, 0u64);
+
+    while !stop.load(Ordering::Relaxed) && Instant::now() < deadline && packets < limits.max_packets && bytes < limits.max_bytes {
+        if let Some(pid) = filter.pid {
+            if pid_refreshed.is_none_or(|t| t.elapsed() >= PID_REFRESH_INTERVAL) {
+                pid_ports = process_ports(pid);
+                pid_refreshed = Some(Instant::now());
+            }
+        }
+
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        let mut addr_len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        let payload = &mut buffer[SLL_HEADER_LEN..];
+        let received = unsafe {
+            libc::recvfrom(
+                socket.as_raw_fd(), payload.as_mut_ptr() as *mut libc::c_void, payload.len(), libc::MSG_TRUNC,
+                &mut addr as *mut _ as *mut libc::sockaddr, &mut addr_len,
+            )
+        };
+        if received < 0 {
+            let err = std::io::Error::last_os_error();
+            match err.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut | std::io::ErrorKind::Interrupted => continue,
+                _ => return Err(anyhow!("Packet capture read failed: {}", err)),
+            }
+        }
+
+        // Loopback traffic is seen once outgoing and once incoming
+        if interface.is_none() && addr.sll_ifindex == loopback && addr.sll_pkttype == libc::PACKET_OUTGOING {
+            continue;
+        }
+
+        let original_len = received as usize;
+        let captured_len = original_len.min(payload.len());
+        let ethertype = u16::from_be(addr.sll_protocol);
+        let summary = match parse_packet(ethertype, &payload[..captured_len]) {
+            Some(summary) => summary,
+            None if filter.ip.is_none() && filter.port.is_none() && filter.pid.is_none() => {
+                PacketSummary { src: IpAddr::V4(Ipv4Addr::UNSPECIFIED), dst: IpAddr::V4(Ipv4Addr::UNSPECIFIED), src_port: None, dst_port: None }
+            }
+            None => continue,
+        };
+        if !filter.matches(&summary, &pid_ports) {
+            continue;
+        }
+
+        let header = sll_header(addr.sll_pkttype as u16, addr.sll_hatype, &addr.sll_addr[..addr.sll_halen as usize], ethertype);
+        buffer[..SLL_HEADER_LEN].copy_from_slice(&header);
+        bytes += writer.write_packet(std::time::SystemTime::now(), &buffer[..SLL_HEADER_LEN + captured_len], SLL_HEADER_LEN + original_len)? as u64;
+        packets += 1;
+
+        if packets % 100 == 0 {
+            update_progress(captures, id, packets, bytes);
+        }
+    }
+
+    writer.flush()?;
+    update_progress(captures, id, packets, bytes);
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_capture(
+    _path: &Path,
+    _interface: Option<&str>,
+    _filter: &CaptureFilter,
+    _limits: &CaptureLimits,
+    _stop: &AtomicBool,
+    _captures: &RwLock<HashMap<String, CaptureInfo>>,
+    _id: &str,
+) -> Result<()> {
+    Err(anyhow!("Packet capture is only supported on Linux"))
+}
+
+fn update_progress(captures: &RwLock<HashMap<String, CaptureInfo>>, id: &str, packets: u64, bytes: u64) {
+    match captures.write() {
+        Ok(mut captures) => {
+            if let Some(info) = captures.get_mut(id) {
+                info.packets = packets;
+                info.bytes = bytes;
+            }
+        }
+        Err(_) => warn!("Failed to update progress of capture {}", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_parsing_and_filter() {
+        // IPv4/TCP 10.0.0.5:51000 -> 203.0.113.7:443
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x45;
+        packet[9] = 6;
+        packet[12..16].copy_from_slice(&[10, 0, 0, 5]);
+        packet[16..20].copy_from_slice(&[203, 0, 113, 7]);
+        packet[20..22].copy_from_slice(&51000u16.to_be_bytes());
+        packet[22..24].copy_from_slice(&443u16.to_be_bytes());
+
+        let summary = parse_packet(0x0800, &packet).unwrap();
+        assert_eq!(summary.dst, "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(summary.src_port, Some(51000));
+
+        let none = HashSet::new();
+        let by_host = CaptureFilter { ip: Some("203.0.113.7".parse().unwrap()), port: Some(443), pid: None };
+        assert!(by_host.matches(&summary, &none));
+        assert_eq!(by_host.describe(), "host 203.0.113.7 and port 443");
+        assert!(!CaptureFilter { port: Some(80), ..Default::default() }.matches(&summary, &none));
+
+        let by_pid = CaptureFilter { pid: Some(1234), ..Default::default() };
+        assert!(!by_pid.matches(&summary, &none));
+        assert!(by_pid.matches(&summary, &[51000].into_iter().collect()));
+
+        assert!(parse_packet(0x0806, &packet).is_none());
+    }
+
+    #[test]
+    fn test_rejects_unsafe_incident_ids() {
+        let manager = CaptureManager::new(std::env::temp_dir().join("fluxdefense-capture-test"));
+        for incident_id in ["", "../etc", "a/b", ".hidden"] {
+            let request = CaptureRequest { incident_id: incident_id.to_string(), ..Default::default() };
+            assert!(manager.start(request).is_err());
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+// Linux "cooked" capture (tcpdump -i any); works for every interface type
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const SLL_HEADER_LEN: usize = 16;
+
+// Classic libpcap savefile writer
+pub struct PcapWriter {
+    out: BufWriter<File>,
+    snaplen: u32,
+}
+
+impl PcapWriter {
+    pub fn create(path: &Path, snaplen: u32, linktype: u32) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&0i32.to_le_bytes())?; // thiszone
+        out.write_all(&0u32.to_le_bytes())?; // sigfigs
+        out.write_all(&snaplen.to_le_bytes())?;
+        out.write_all(&linktype.to_le_bytes())?;
+        Ok(Self { out, snaplen })
+    }
+
+    // Writes one record, truncating to the snaplen. Returns the bytes written.
+    pub fn write_packet(&mut self, timestamp: SystemTime, data: &[u8], original_len: usize) -> Result<usize> {
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let captured = &data[..data.len().min(self.snaplen as usize)];
+
+        self.out.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+        self.out.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+        self.out.write_all(&(captured.len() as u32).to_le_bytes())?;
+        self.out.write_all(&(original_len as u32).to_le_bytes())?;
+        self.out.write_all(captured)?;
+        Ok(16 + captured.len())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+// Builds the 16-byte Linux cooked header in front of a network-layer packet
+pub fn sll_header(packet_type: u16, hardware_type: u16, address: &[u8], protocol: u16) -> [u8; SLL_HEADER_LEN] {
+    let mut header = [0u8; SLL_HEADER_LEN];
+    header[0..2].copy_from_slice(&packet_type.to_be_bytes());
+    header[2..4].copy_from_slice(&hardware_type.to_be_bytes());
+    let address_len = address.len().min(8);
+    header[4..6].copy_from_slice(&(address_len as u16).to_be_bytes());
+    header[6..6 + address_len].copy_from_slice(&address[..address_len]);
+    header[14..16].copy_from_slice(&protocol.to_be_bytes());
+    header
+}
@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkloadKind {
+    Service,
+    Scope,
+    Container,
+}
+
+// The systemd unit or container a cgroup belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workload {
+    pub name: String,
+    pub kind: WorkloadKind,
+    // Path below the cgroup root, e.g. /system.slice/nginx.service
+    pub cgroup: String,
+    pub container_id: Option<String>,
+    pub runtime: Option<String>,
+}
+
+// Usage of one workload, descendants included; rates are per second since
+// the previous sample and CPU is in percent of one core
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkloadUsage {
+    #[serde(flatten)]
+    pub workload: Workload,
+    pub cpu_percent: f64,
+    pub cpu_usage_usec: u64,
+    pub memory_bytes: u64,
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+    pub io_read_rate: f64,
+    pub io_write_rate: f64,
+    pub pids: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSort {
+    #[default]
+    Cpu,
+    Memory,
+    Io,
+    Pids,
+}
+
+impl UnitSort {
+    // Highest first
+    pub fn sort(self, usage: &mut [WorkloadUsage]) {
+        match self {
+            UnitSort::Cpu => usage.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent)),
+            UnitSort::Memory => usage.sort_by_key(|usage| Reverse(usage.memory_bytes)),
+            UnitSort::Io => usage.sort_by(|a, b| {
+                (b.io_read_rate + b.io_write_rate).total_cmp(&(a.io_read_rate + a.io_write_rate))
+            }),
+            UnitSort::Pids => usage.sort_by_key(|usage| Reverse(usage.pids)),
+        }
+    }
+}
+
+// Reads the cgroup v2 hierarchy and attributes CPU, memory and I/O to each
+// service, scope and container in it
+pub struct CgroupSampler {
+    root: PathBuf,
+    // CPU microseconds and bytes read and written per cgroup
+    previous: HashMap<String, (u64, u64, u64)>,
+    previous_at: Option<Instant>,
+}
+
+impl Default for CgroupSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CgroupSampler {
+    pub fn new() -> Self {
+        Self::with_root(CGROUP_ROOT)
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), previous: HashMap::new(), previous_at: None }
+    }
+
+    // Whether a sample exists to compute rates against
+    pub fn has_previous(&self) -> bool {
+        self.previous_at.is_some()
+    }
+
+    pub fn sample(&mut self) -> Result<Vec<WorkloadUsage>> {
+        self.sample_at(Instant::now())
+    }
+
+    fn sample_at(&mut self, now: Instant) -> Result<Vec<WorkloadUsage>> {
+        if !self.root.join("cgroup.controllers").exists() {
+            return Err(anyhow!("No cgroup v2 hierarchy at {}", self.root.display()));
+        }
+        let mut workloads = Vec::new();
+        find_workloads(&self.root, "", &mut workloads);
+
+        let elapsed = self.previous_at
+            .map(|at| now.saturating_duration_since(at).as_secs_f64())
+            .filter(|elapsed| *elapsed > 0.0);
+        let mut current = HashMap::new();
+        let mut usage = Vec::new();
+        for workload in workloads {
+            let dir = self.root.join(workload.cgroup.trim_start_matches('/'));
+            let cpu_usage_usec = read_keyed(&dir.join("cpu.stat"), "usage_usec").unwrap_or(0);
+            let (io_read_bytes, io_write_bytes) = read_io_stat(&dir.join("io.stat"));
+            let (cpu_percent, io_read_rate, io_write_rate) = match (self.previous.get(&workload.cgroup), elapsed) {
+                (Some(&(cpu, read, write)), Some(elapsed)) => (
+                    cpu_usage_usec.saturating_sub(cpu) as f64 / 1e6 / elapsed * 100.0,
+                    io_read_bytes.saturating_sub(read) as f64 / elapsed,
+                    io_write_bytes.saturating_sub(write) as f64 / elapsed,
+                ),
+                _ => (0.0, 0.0, 0.0),
+            };
+            current.insert(workload.cgroup.clone(), (cpu_usage_usec, io_read_bytes, io_write_bytes));
+            usage.push(WorkloadUsage {
+                memory_bytes: read_number(&dir.join("memory.current")).unwrap_or(0),
+                pids: read_number(&dir.join("pids.current")).unwrap_or(0),
+                workload,
+                cpu_percent,
+                cpu_usage_usec,
+                io_read_bytes,
+                io_write_bytes,
+                io_read_rate,
+                io_write_rate,
+            });
+        }
+        self.previous = current;
+        self.previous_at = Some(now);
+        Ok(usage)
+    }
+}
+
+// The workload a process runs in, from its /proc/<pid>/cgroup entry
+pub fn workload_for_pid(pid: u32) -> Option<Workload> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    // The unified hierarchy's line is "0::/path"
+    let path = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+    workload_for_path(path.trim())
+}
+
+// The outermost service, scope or container along a cgroup path
+pub fn workload_for_path(path: &str) -> Option<Workload> {
+    let mut cgroup = String::new();
+    let mut parent = "";
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        cgroup.push('/');
+        cgroup.push_str(component);
+        if let Some(workload) = classify(component, parent, &cgroup) {
+            return Some(workload);
+        }
+        parent = component;
+    }
+    None
+}
+
+fn classify(name: &str, parent: &str, cgroup: &str) -> Option<Workload> {
+    let workload = |name: &str, kind, container_id: Option<&str>, runtime: Option<&str>| Workload {
+        name: name.to_string(),
+        kind,
+        cgroup: cgroup.to_string(),
+        container_id: container_id.map(String::from),
+        runtime: runtime.map(String::from),
+    };
+    // systemd cgroup driver: docker-<id>.scope, cri-containerd-<id>.scope, ...
+    if let Some(scope) = name.strip_suffix(".scope") {
+        for (prefix, runtime) in [("docker-", "docker"), ("cri-containerd-", "containerd"), ("crio-", "cri-o"), ("libpod-", "podman")] {
+            if let Some(id) = scope.strip_prefix(prefix).filter(|id| is_container_id(id)) {
+                return Some(workload(&id[..12], WorkloadKind::Container, Some(id), Some(runtime)));
+            }
+        }
+        return Some(workload(name, WorkloadKind::Scope, None, None));
+    }
+    if name.ends_with(".service") {
+        return Some(workload(name, WorkloadKind::Service, None, None));
+    }
+    // cgroupfs driver: /docker/<id>, /kubepods/.../<id>
+    if is_container_id(name) {
+        let runtime = match parent {
+            "docker" => "docker",
+            "libpod_parent" => "podman",
+            _ => "containerd",
+        };
+        return Some(workload(&name[..12], WorkloadKind::Container, Some(name), Some(runtime)));
+    }
+    None
+}
+
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// Workloads below `dir`; their own children are counted in them
+fn find_workloads(dir: &Path, cgroup: &str, workloads: &mut Vec<Workload>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let parent = cgroup.rsplit('/').next().unwrap_or("");
+    for entry in entries.flatten() {
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = format!("{}/{}", cgroup, name);
+        match classify(&name, parent, &path) {
+            Some(workload) => workloads.push(workload),
+            None => find_workloads(&entry.path(), &path, workloads),
+        }
+    }
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn read_keyed(path: &Path, key: &str) -> Option<u64> {
+    fs::read_to_string(path).ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.trim().parse().ok())
+}
+
+// io.stat has a line per device: "8:0 rbytes=1459200 wbytes=314773504 rios=192 ..."
+fn read_io_stat(path: &Path) -> (u64, u64) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return (0, 0);
+    };
+    content.split_whitespace().fold((0, 0), |(read, write), field| {
+        match field.split_once('=') {
+            Some(("rbytes", value)) => (read + value.parse::<u64>().unwrap_or(0), write),
+            Some(("wbytes", value)) => (read, write + value.parse::<u64>().unwrap_or(0)),
+            _ => (read, write),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cgroup_workload_usage() {
+        let root = std::env::temp_dir().join(format!("flux-cgroup-{}", std::process::id()));
+        let container = "4f3a9c2e8b7d6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f";
+        let write = |cgroup: &str, cpu_usec: u64, memory: u64, written: u64| {
+            let dir = root.join(cgroup);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cpu.stat"), format!("usage_usec {}\nuser_usec 0\n", cpu_usec)).unwrap();
+            fs::write(dir.join("memory.current"), format!("{}\n", memory)).unwrap();
+            fs::write(dir.join("io.stat"), format!("8:0 rbytes=4096 wbytes={} rios=1 wios=1\n", written)).unwrap();
+            fs::write(dir.join("pids.current"), "3\n").unwrap();
+        };
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("cgroup.controllers"), "cpu io memory pids\n").unwrap();
+        write("system.slice/nginx.service", 1_000_000, 50 << 20, 0);
+        // A child of the service counts towards it, not separately
+        write("system.slice/nginx.service/worker", 0, 0, 0);
+        write(&format!("system.slice/docker-{}.scope", container), 0, 300 << 20, 0);
+        write("user.slice/user-1000.slice/session-2.scope", 0, 1 << 20, 0);
+
+        let mut sampler = CgroupSampler::with_root(&root);
+        let start = Instant::now();
+        let first = sampler.sample_at(start).unwrap();
+        assert_eq!(first.len(), 3);
+        assert!(first.iter().all(|usage| usage.cpu_percent == 0.0));
+
+        // Two seconds later nginx used one core and the container wrote 2 MiB
+        write("system.slice/nginx.service", 3_000_000, 50 << 20, 0);
+        write(&format!("system.slice/docker-{}.scope", container), 0, 300 << 20, 2 << 20);
+        let mut usage = sampler.sample_at(start + Duration::from_secs(2)).unwrap();
+        UnitSort::Cpu.sort(&mut usage);
+        assert_eq!(usage[0].workload.name, "nginx.service");
+        assert_eq!(usage[0].workload.kind, WorkloadKind::Service);
+        assert!((usage[0].cpu_percent - 100.0).abs() < 0.01);
+        UnitSort::Io.sort(&mut usage);
+        assert_eq!(usage[0].workload.kind, WorkloadKind::Container);
+        assert_eq!(usage[0].workload.name, &container[..12]);
+        assert_eq!(usage[0].workload.runtime.as_deref(), Some("docker"));
+        assert_eq!(usage[0].io_write_rate, (1 << 20) as f64);
+        fs::remove_dir_all(&root).unwrap();
+
+        let workload = workload_for_path(&format!("/kubepods/burstable/pod1234/{}", container)).unwrap();
+        assert_eq!((workload.kind, workload.runtime.as_deref()), (WorkloadKind::Container, Some("containerd")));
+        assert_eq!(workload_for_path("/system.slice/cron.service").unwrap().cgroup, "/system.slice/cron.service");
+        assert!(workload_for_path("/").is_none());
+        assert!(CgroupSampler::with_root("/nonexistent").sample().is_err());
+    }
+}
@@ -1,33 +1,175 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::path::{Path, PathBuf};
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use tracing::{info, warn, error};
 
+pub mod profile;
+pub mod reload;
+pub use profile::{write_starter_files, Profile, PROFILE_NAMES};
+pub use reload::{ReloadableConfig, ReloadReport, LogLevelHandle};
+
+// Prefix of environment variables overriding config fields; nested fields
+// are separated by a double underscore, e.g. FLUXDEFENSE_INCIDENTS__WINDOW_SECS
+pub const ENV_PREFIX: &str = "FLUXDEFENSE_";
+// Points at a config file to use instead of the default locations
+pub const CONFIG_PATH_ENV: &str = "FLUXDEFENSE_CONFIG";
+// Exec verdicts the Linux monitor keeps unless configured otherwise
+pub const DEFAULT_DECISION_CACHE_CAPACITY: usize = 16_384;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    // Anything without a .toml/.yaml/.yml extension is read as JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()).as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Enforcement {
+    // Verdicts are logged but never applied
+    Passive,
+    Enforcing,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    // Preset the rest of the file is applied on top of
+    #[serde(default)]
+    pub profile: Option<Profile>,
     pub file_policy_path: Option<PathBuf>,
     pub network_policy_path: Option<PathBuf>,
     pub log_level: String,
     pub log_file_path: Option<PathBuf>,
+    // Size/age rotation of the event log; unset lets it grow
+    #[serde(default)]
+    pub log_rotation: Option<crate::output::LogRotationConfig>,
+    // Versioned records (v1) or the bare pre-schema events (legacy)
+    #[serde(default)]
+    pub event_log_format: crate::event_log::EventLogFormat,
+    // Unset follows the build: passive with the passive-mode feature
+    #[serde(default)]
+    pub enforcement: Option<Enforcement>,
     pub enable_file_monitoring: bool,
     pub enable_network_monitoring: bool,
+    // Where the API server captures traffic when FLUX_CAPTURE_INTERFACE is
+    // not set; entries as for NetworkFilter::start_capture_interfaces
+    #[serde(default)]
+    pub capture_interfaces: Vec<String>,
     pub quarantine_directory: PathBuf,
     pub alert_webhook_url: Option<String>,
+    #[serde(default)]
+    pub splunk_hec: Option<crate::output::SplunkConfig>,
     pub update_interval_seconds: u64,
+    // Report to a central fleet server when set
+    #[serde(default)]
+    pub fleet: Option<crate::fleet::FleetAgentConfig>,
+    // Ship events to Elasticsearch/OpenSearch when set
+    #[serde(default)]
+    pub elasticsearch: Option<crate::output::ElasticConfig>,
+    // Learn per-executable behavior and report deviations when set
+    #[serde(default)]
+    pub behavior_baseline: Option<crate::anomaly::BaselineConfig>,
+    // Grouping of related events into incidents
+    #[serde(default)]
+    pub incidents: crate::incidents::IncidentConfig,
+    // Hash-chained record of every enforcement decision and policy change
+    #[serde(default)]
+    pub audit_log_path: Option<PathBuf>,
+    // Switch to an unprivileged user once startup has opened what needs root
+    #[serde(default)]
+    pub privileges: Option<crate::privileges::PrivilegeConfig>,
+    // Syscall allowlist installed after startup and the privilege drop
+    #[serde(default)]
+    pub seccomp: Option<crate::seccomp::SeccompConfig>,
+    // Masking of secrets in command lines before events are logged or shipped
+    #[serde(default)]
+    pub redaction: crate::redaction::RedactionConfig,
+    // Pseudonymize usernames, home directories and remote addresses when set
+    #[serde(default)]
+    pub privacy: Option<crate::privacy::PrivacyConfig>,
+    // When set, policies and correlation rules must carry a valid signature
+    #[serde(default)]
+    pub policy_signing: Option<crate::policy::PolicySigningConfig>,
+    // Signed agent updates from a release manifest
+    #[serde(default)]
+    pub update: Option<crate::update::UpdateConfig>,
+    // How event types are throttled under load
+    #[serde(default)]
+    pub sampling: crate::sampling::SamplingConfig,
+    // Folding of repeated file accesses into summaries, per path prefix
+    #[serde(default)]
+    pub file_access_aggregation: crate::aggregation::AggregationConfig,
+    // Paths fanotify watches on Linux, and for which events
+    #[serde(default)]
+    pub file_watch: crate::file_watch::FileWatchConfig,
+    // Processes response actions never block or kill, on top of the built-in ones
+    #[serde(default)]
+    pub exemptions: crate::exemptions::ExemptionConfig,
+    // Exec verdicts remembered by file identity, so repeated runs of a binary
+    // are not hashed and checked again
+    #[serde(default = "default_decision_cache_capacity")]
+    pub decision_cache_capacity: usize,
+}
+
+fn default_decision_cache_capacity() -> usize {
+    DEFAULT_DECISION_CACHE_CAPACITY
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            profile: None,
             file_policy_path: Some(PathBuf::from("/etc/fluxdefense/file_policy.json")),
             network_policy_path: Some(PathBuf::from("/etc/fluxdefense/network_policy.json")),
             log_level: "info".to_string(),
             log_file_path: Some(PathBuf::from("/var/log/fluxdefense.log")),
+            log_rotation: None,
+            event_log_format: crate::event_log::EventLogFormat::default(),
+            enforcement: None,
             enable_file_monitoring: true,
             enable_network_monitoring: true,
+            capture_interfaces: Vec::new(),
             quarantine_directory: PathBuf::from("/var/quarantine/fluxdefense"),
             alert_webhook_url: None,
+            splunk_hec: None,
             update_interval_seconds: 300, // 5 minutes
+            fleet: None,
+            elasticsearch: None,
+            behavior_baseline: None,
+            incidents: crate::incidents::IncidentConfig::default(),
+            audit_log_path: None,
+            privileges: None,
+            seccomp: None,
+            redaction: crate::redaction::RedactionConfig::default(),
+            privacy: None,
+            policy_signing: None,
+            update: None,
+            sampling: crate::sampling::SamplingConfig::default(),
+            file_access_aggregation: crate::aggregation::AggregationConfig::default(),
+            file_watch: crate::file_watch::FileWatchConfig::default(),
+            exemptions: crate::exemptions::ExemptionConfig::default(),
+            decision_cache_capacity: DEFAULT_DECISION_CACHE_CAPACITY,
         }
     }
 }
@@ -43,20 +185,91 @@ impl Config {
             return Ok(Self::default());
         }
         
-        let content = std::fs::read_to_string(path)?;
-        let config: Self = serde_json::from_str(&content)?;
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {:?}", path))?;
+        let config = Self::parse(&content, ConfigFormat::from_path(path))
+            .with_context(|| format!("Invalid config {:?}", path))?;
         info!("Configuration loaded from: {:?}", path);
         Ok(config)
     }
     
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<Self> {
+        let tree: Value = match format {
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+        };
+        Ok(serde_json::from_value(profile::apply_profile(tree)?)?)
+    }
+    
+    pub fn to_string(&self, format: ConfigFormat) -> Result<String> {
+        Ok(match format {
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(self)?,
+        })
+    }
+    
+    // The file (or defaults) with FLUXDEFENSE_* environment overrides applied
+    pub fn load_effective(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::load_from_file(path)?,
+            None => Self::load_config()?,
+        };
+        let applied = config.apply_env_overrides(std::env::vars())?;
+        if !applied.is_empty() {
+            info!("Configuration overridden from environment: {}", applied.join(", "));
+        }
+        Ok(config)
+    }
+    
+    // Applies FLUXDEFENSE_<FIELD> variables from `vars` and returns the names
+    // of those that were used. Values are taken as JSON when the field is not
+    // a string, so booleans and numbers work; an empty value clears an
+    // optional field.
+    pub fn apply_env_overrides<I>(&mut self, vars: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut tree = serde_json::to_value(&*self)?;
+        let mut applied = Vec::new();
+        
+        let mut vars: Vec<(String, String)> = vars.into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != CONFIG_PATH_ENV)
+            .collect();
+        vars.sort();
+        for (name, raw) in vars {
+            let path: Vec<String> = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(|part| part.to_ascii_lowercase())
+                .collect();
+            if path.iter().any(|part| part.is_empty()) {
+                continue;
+            }
+            // Only fields the config has at the top level, so unrelated
+            // FLUXDEFENSE_* variables are left alone
+            if tree.get(&path[0]).is_none() {
+                continue;
+            }
+            set_path(&mut tree, &path, &raw);
+            applied.push(name);
+        }
+        
+        if !applied.is_empty() {
+            *self = serde_json::from_value(tree)
+                .map_err(|e| anyhow!("Invalid environment override ({}): {}", applied.join(", "), e))?;
+        }
+        Ok(applied)
+    }
+    
     pub fn save_to_file(&self, path: &Path) -> Result<()> {
         // Create parent directory if it doesn't exist
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
         
-        let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, json)?;
+        let content = self.to_string(ConfigFormat::from_path(path))?;
+        std::fs::write(path, content)?;
         info!("Configuration saved to: {:?}", path);
         Ok(())
     }
@@ -78,17 +291,26 @@ impl Config {
         }
     }
     
+    // config.json, or a config.toml/config.yaml/config.yml next to it
+    fn find_config_file(json_path: &Path) -> Option<PathBuf> {
+        ["json", "toml", "yaml", "yml"].iter()
+            .map(|ext| json_path.with_extension(ext))
+            .find(|path| path.exists())
+    }
+    
     pub fn load_config() -> Result<Self> {
+        if let Ok(path) = std::env::var(CONFIG_PATH_ENV) {
+            info!("Loading configuration from {}", CONFIG_PATH_ENV);
+            return Self::load_from_file(Path::new(&path));
+        }
+        
         // Try user config first, then system config
-        if let Some(user_config_path) = Self::get_user_config_path() {
-            if user_config_path.exists() {
-                info!("Loading user configuration");
-                return Self::load_from_file(&user_config_path);
-            }
+        if let Some(user_config_path) = Self::get_user_config_path().and_then(|path| Self::find_config_file(&path)) {
+            info!("Loading user configuration");
+            return Self::load_from_file(&user_config_path);
         }
         
-        let system_config_path = Self::get_default_config_path();
-        if system_config_path.exists() {
+        if let Some(system_config_path) = Self::find_config_file(&Self::get_default_config_path()) {
             info!("Loading system configuration");
             return Self::load_from_file(&system_config_path);
         }
@@ -126,6 +348,33 @@ impl Config {
             }
         }
         
+        if let Some(ref privileges) = self.privileges {
+            privileges.capability_mask()?;
+        }
+        
+        #[cfg(target_os = "linux")]
+        if let Some(ref seccomp) = self.seccomp {
+            crate::seccomp::allowlist(seccomp)?;
+        }
+        
+        crate::redaction::Redactor::from_config(&self.redaction)?;
+        
+        if let Some(ref signing) = self.policy_signing {
+            signing.validate()?;
+        }
+        
+        if let Some(ref update) = self.update {
+            update.validate()?;
+        }
+        
+        self.sampling.validate()?;
+        self.file_access_aggregation.validate()?;
+        self.file_watch.validate()?;
+        self.exemptions.validate()?;
+        if self.decision_cache_capacity == 0 {
+            return Err(anyhow!("decision_cache_capacity must be at least 1"));
+        }
+        
         Ok(())
     }
     
@@ -157,4 +406,75 @@ impl Config {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Sets `path` in a JSON tree, creating objects along the way. The value keeps
+// the type of what it replaces where it parses as one.
+fn set_path(tree: &mut Value, path: &[String], raw: &str) {
+    let mut node = tree;
+    for key in &path[..path.len() - 1] {
+        if !node.is_object() {
+            *node = Value::Object(Default::default());
+        }
+        node = node.as_object_mut()
+            .map(|object| object.entry(key.clone()).or_insert(Value::Null))
+            .expect("node was made an object");
+        if node.is_null() {
+            *node = Value::Object(Default::default());
+        }
+    }
+    
+    if !node.is_object() {
+        *node = Value::Object(Default::default());
+    }
+    let Some(object) = node.as_object_mut() else { return };
+    let key = &path[path.len() - 1];
+    let value = match object.get(key) {
+        _ if raw.is_empty() => Value::Null,
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    };
+    object.insert(key.clone(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_formats_and_env_overrides() {
+        let config = Config::default();
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let parsed = Config::parse(&config.to_string(format).unwrap(), format).unwrap();
+            assert_eq!(parsed.log_file_path, config.log_file_path);
+            assert_eq!(parsed.incidents.window_secs, config.incidents.window_secs);
+        }
+        assert_eq!(ConfigFormat::from_path(Path::new("/etc/fluxdefense/config.yml")), ConfigFormat::Yaml);
+        
+        let toml = "log_level = \"debug\"\nlog_file_path = \"/var/log/flux.log\"\nenable_file_monitoring = true\n\
+                    enable_network_monitoring = false\nquarantine_directory = \"/var/quarantine\"\n\
+                    update_interval_seconds = 60\n\n[incidents]\nwindow_secs = 300\n";
+        let mut config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.incidents.window_secs, 300);
+        
+        let vars = [
+            ("FLUXDEFENSE_LOG_LEVEL", "warn"),
+            ("FLUXDEFENSE_ENABLE_NETWORK_MONITORING", "true"),
+            ("FLUXDEFENSE_INCIDENTS__WINDOW_SECS", "120"),
+            ("FLUXDEFENSE_LOG_FILE_PATH", ""),
+            ("FLUXDEFENSE_AUDIT_LOG_PATH", "/var/log/flux-audit.jsonl"),
+            ("FLUXDEFENSE_UNRELATED", "x"),
+            ("PATH", "/usr/bin"),
+        ].map(|(name, value)| (name.to_string(), value.to_string()));
+        let applied = config.apply_env_overrides(vars).unwrap();
+        assert_eq!(applied.len(), 5);
+        assert_eq!(config.log_level, "warn");
+        assert!(config.enable_network_monitoring);
+        assert_eq!(config.incidents.window_secs, 120);
+        assert_eq!(config.log_file_path, None);
+        assert_eq!(config.audit_log_path, Some(PathBuf::from("/var/log/flux-audit.jsonl")));
+        
+        let bad = [("FLUXDEFENSE_UPDATE_INTERVAL_SECONDS".to_string(), "soon".to_string())];
+        assert!(config.apply_env_overrides(bad).is_err());
+    }
+}
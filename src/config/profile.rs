@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Result};
+use tracing::info;
+
+use super::Config;
+use crate::policy::{FilePolicy, NetworkPolicy};
+
+// Hardening presets for common host roles. A config naming a profile starts
+// from its settings; whatever the file sets itself wins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    // Internet-facing server: few outbound destinations, everything audited
+    Server,
+    // Developer workstation: broad network access, no behavior scoring
+    Workstation,
+    // Kubernetes or container runtime node
+    ContainerHost,
+}
+
+pub const PROFILE_NAMES: [&str; 3] = ["server", "workstation", "container-host"];
+
+impl Profile {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "server" => Some(Profile::Server),
+            "workstation" => Some(Profile::Workstation),
+            "container-host" | "container_host" | "k8s" => Some(Profile::ContainerHost),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Server => "server",
+            Profile::Workstation => "workstation",
+            Profile::ContainerHost => "container-host",
+        }
+    }
+
+    // The defaults with this profile's subsystems switched on or off
+    pub fn config(self) -> Config {
+        let mut config = Config { profile: Some(self), ..Config::default() };
+        match self {
+            Profile::Server => {
+                // A directory of its own, which the dropped user can rotate in
+                config.log_file_path = Some(PathBuf::from("/var/log/fluxdefense/events.log"));
+                config.audit_log_path = Some(PathBuf::from("/var/log/fluxdefense/audit.jsonl"));
+                config.behavior_baseline = Some(Default::default());
+                config.privileges = Some(Default::default());
+                config.seccomp = Some(Default::default());
+            }
+            Profile::Workstation => {
+                // Compilers and IDEs keep spawning new binaries
+                config.behavior_baseline = None;
+                config.incidents.min_severity = crate::incidents::IncidentSeverity::High;
+                config.update_interval_seconds = 600;
+            }
+            Profile::ContainerHost => {
+                config.log_file_path = Some(PathBuf::from("/var/log/fluxdefense/events.log"));
+                config.audit_log_path = Some(PathBuf::from("/var/log/fluxdefense/audit.jsonl"));
+                config.behavior_baseline = Some(Default::default());
+                config.privileges = Some(Default::default());
+                // Pods come and go quickly
+                config.incidents.window_secs = 300;
+            }
+        }
+        config
+    }
+
+    pub fn file_policy(self) -> FilePolicy {
+        let mut policy = FilePolicy::default();
+        for path in ["/usr/bin", "/usr/sbin", "/bin", "/sbin", "/usr/lib", "/usr/libexec"] {
+            policy.system_paths.insert(PathBuf::from(path));
+        }
+        let trusted: &[&str] = match self {
+            Profile::Server => &["/usr/local/bin", "/usr/local/sbin"],
+            Profile::Workstation => &["/usr/local/bin", "/opt", "/snap/bin", "/var/lib/flatpak/exports/bin"],
+            Profile::ContainerHost => &[
+                "/usr/local/bin",
+                "/opt/cni/bin",
+                "/var/lib/containerd",
+                "/var/lib/docker/overlay2",
+                "/var/lib/kubelet",
+                "/run/containerd",
+            ],
+        };
+        for dir in trusted {
+            policy.add_trusted_directory(PathBuf::from(dir));
+        }
+        policy
+    }
+
+    pub fn network_policy(self) -> NetworkPolicy {
+        let mut policy = NetworkPolicy::default();
+        match self {
+            Profile::Server => {
+                policy.allow_local_network = false;
+                policy.allowed_ports = [53, 80, 123, 443].into_iter().collect();
+                policy.allowed_domains = [
+                    "debian.org", "ubuntu.com", "fedoraproject.org", "centos.org", "letsencrypt.org",
+                ].into_iter().map(String::from).collect();
+                // Telnet, SMB and RDP have no business leaving a server
+                policy.blocked_ports = [23, 135, 139, 445, 3389].into_iter().collect();
+            }
+            Profile::Workstation => {
+                policy.allowed_ports.extend([123, 9418]);
+                policy.allowed_domains.extend([
+                    "gitlab.com", "crates.io", "npmjs.org", "pypi.org", "docker.io", "golang.org",
+                ].into_iter().map(String::from));
+            }
+            Profile::ContainerHost => {
+                // API server, kubelet, etcd, BGP and VXLAN overlays
+                policy.allowed_ports.extend([123, 179, 2379, 2380, 4789, 6443, 8472, 10250]);
+                policy.allowed_domains.extend([
+                    "docker.io", "quay.io", "gcr.io", "ghcr.io", "registry.k8s.io",
+                ].into_iter().map(String::from));
+                // The cloud metadata service hands out node credentials
+                policy.blocked_ips.insert(IpAddr::V4(Ipv4Addr::new(169, 254, 169, 254)));
+            }
+        }
+        policy
+    }
+}
+
+// Puts the profile's settings under those of a config file, which may leave
+// out anything the profile provides
+pub(super) fn apply_profile(tree: Value) -> Result<Value> {
+    let Some(profile) = tree.get("profile").filter(|profile| !profile.is_null()) else {
+        return Ok(tree);
+    };
+    let profile: Profile = serde_json::from_value(profile.clone())
+        .map_err(|_| anyhow::anyhow!("Unknown profile {}; expected one of {}", profile, PROFILE_NAMES.join(", ")))?;
+    let mut base = serde_json::to_value(profile.config())?;
+    merge(&mut base, tree);
+    Ok(base)
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => { base.insert(key, value); }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Writes `config` to `config_path` and the profile's starter policies to the
+// paths the config names. Existing files are only replaced with `overwrite`
+pub fn write_starter_files(config_path: &Path, config: &Config, profile: Profile, overwrite: bool) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![config_path.to_path_buf()];
+    paths.extend(config.file_policy_path.clone());
+    paths.extend(config.network_policy_path.clone());
+    if !overwrite {
+        if let Some(existing) = paths.iter().find(|path| path.exists()) {
+            bail!("{} already exists; pass --force to replace it", existing.display());
+        }
+    }
+
+    config.save_to_file(config_path)?;
+    for path in config.file_policy_path.iter().chain(&config.network_policy_path) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    if let Some(path) = &config.file_policy_path {
+        profile.file_policy().save_to_file(path)?;
+    }
+    if let Some(path) = &config.network_policy_path {
+        profile.network_policy().save_to_file(path)?;
+    }
+    info!("Wrote the {} profile to {}", profile.name(), config_path.display());
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFormat;
+
+    #[test]
+    fn test_profiles() {
+        // The file's own settings win over the profile's
+        let toml = "profile = \"server\"\nlog_level = \"debug\"\naudit_log_path = \"/srv/audit.jsonl\"\n\n[incidents]\nwindow_secs = 60\n";
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.profile, Some(Profile::Server));
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.audit_log_path, Some(PathBuf::from("/srv/audit.jsonl")));
+        assert_eq!(config.incidents.window_secs, 60);
+        assert!(config.seccomp.is_some() && config.privileges.is_some());
+
+        let yaml = "profile: workstation\nbehavior_baseline:\n  training_hours: 12\n";
+        let config = Config::parse(yaml, ConfigFormat::Yaml).unwrap();
+        assert_eq!(config.behavior_baseline.unwrap().training_hours, 12);
+        assert!(Config::parse("{\"profile\": \"laptop\"}", ConfigFormat::Json).is_err());
+
+        for name in PROFILE_NAMES {
+            let profile = Profile::from_name(name).unwrap();
+            assert_eq!(profile.name(), name);
+            assert!(profile.file_policy().is_path_allowed(Path::new("/usr/bin/ls")));
+        }
+        assert!(!Profile::Server.network_policy().is_port_allowed(445));
+        assert!(Profile::ContainerHost.network_policy().is_port_allowed(6443));
+
+        let dir = std::env::temp_dir().join(format!("flux-profile-{}", uuid::Uuid::new_v4()));
+        let config_path = dir.join("config.json");
+        let config = Config {
+            file_policy_path: Some(dir.join("file_policy.json")),
+            network_policy_path: Some(dir.join("network_policy.json")),
+            ..Profile::ContainerHost.config()
+        };
+        assert_eq!(write_starter_files(&config_path, &config, Profile::ContainerHost, false).unwrap().len(), 3);
+        assert!(write_starter_files(&config_path, &config, Profile::ContainerHost, false).is_err());
+        let written = Config::load_from_file(&config_path).unwrap();
+        assert_eq!(written.profile, Some(Profile::ContainerHost));
+        assert!(NetworkPolicy::load_from_file(&dir.join("network_policy.json")).unwrap().allowed_ports.contains(&10250));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,239 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+use super::Config;
+
+// Fields a reload applies in place; anything else only takes effect after a
+// restart because monitors, sinks and shippers are built from it at startup
+pub const RUNTIME_FIELDS: &[&str] = &[
+    "log_level",
+    "alert_webhook_url",
+    "update_interval_seconds",
+    "enable_file_monitoring",
+    "enable_network_monitoring",
+    "file_watch",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReloadReport {
+    pub path: Option<PathBuf>,
+    // Changed fields that are now in effect
+    pub applied: Vec<String>,
+    // Changed fields that were left as they are until the next restart
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadReport {
+    pub fn is_unchanged(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+type ReloadListener = Box<dyn Fn(&Config, &ReloadReport) + Send + Sync>;
+
+// The running configuration and the file it came from, re-read on SIGHUP or
+// an API request
+pub struct ReloadableConfig {
+    path: Option<PathBuf>,
+    current: RwLock<Config>,
+    // What the file said at the last (re)load, so fields overridden on the
+    // command line are not mistaken for changes
+    loaded: Mutex<Value>,
+    listeners: RwLock<Vec<ReloadListener>>,
+}
+
+impl ReloadableConfig {
+    // `loaded` is the config as read from `path`; `current` may differ from it
+    // by command line overrides
+    pub fn new(path: Option<PathBuf>, loaded: &Config, current: Config) -> Result<Self> {
+        Ok(Self {
+            path,
+            loaded: Mutex::new(serde_json::to_value(loaded)?),
+            current: RwLock::new(current),
+            listeners: RwLock::new(Vec::new()),
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn current(&self) -> Config {
+        self.current.read().map(|config| config.clone()).unwrap_or_default()
+    }
+
+    // Called after every reload that applied something
+    pub fn on_reload<F>(&self, listener: F)
+    where
+        F: Fn(&Config, &ReloadReport) + Send + Sync + 'static
+    {
+        if let Ok(mut listeners) = self.listeners.write() {
+            listeners.push(Box::new(listener));
+        }
+    }
+
+    // Re-reads the file with environment overrides, applies the runtime-safe
+    // changes and reports the rest. An invalid file changes nothing.
+    pub fn reload(&self) -> Result<ReloadReport> {
+        if let Some(path) = &self.path {
+            // Loading would fall back to defaults and revert everything
+            if !path.exists() {
+                return Err(anyhow!("Config file {:?} no longer exists", path));
+            }
+        }
+        let config = Config::load_effective(self.path.as_deref())?;
+        config.validate()?;
+        let fresh = serde_json::to_value(&config)?;
+
+        let mut loaded = self.loaded.lock().map_err(|_| anyhow!("Config lock poisoned"))?;
+        let mut current = self.current.write().map_err(|_| anyhow!("Config lock poisoned"))?;
+        let mut running = serde_json::to_value(&*current)?;
+
+        let mut report = ReloadReport { path: self.path.clone(), ..Default::default() };
+        for (field, value) in fresh.as_object().into_iter().flatten() {
+            if loaded.get(field) == Some(value) {
+                continue;
+            }
+            if RUNTIME_FIELDS.contains(&field.as_str()) {
+                loaded[field] = value.clone();
+                running[field] = value.clone();
+                report.applied.push(field.clone());
+            } else {
+                // Left out of `loaded` so it is reported until the restart
+                report.restart_required.push(field.clone());
+            }
+        }
+
+        report.applied.sort();
+        report.restart_required.sort();
+        if !report.applied.is_empty() {
+            *current = serde_json::from_value(running)?;
+        }
+        let snapshot = current.clone();
+        drop(current);
+        drop(loaded);
+
+        if report.is_unchanged() {
+            info!("Configuration reloaded, nothing changed");
+        } else {
+            info!("Configuration reloaded: applied [{}], restart required for [{}]",
+                  report.applied.join(", "), report.restart_required.join(", "));
+        }
+        if !report.applied.is_empty() {
+            if let Ok(listeners) = self.listeners.read() {
+                for listener in listeners.iter() {
+                    listener(&snapshot, &report);
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+// Changes the level of the subscriber installed by `init_logging`
+#[derive(Clone)]
+pub struct LogLevelHandle(reload::Handle<LevelFilter, Registry>);
+
+impl LogLevelHandle {
+    pub fn set(&self, level: &str) -> Result<()> {
+        let filter = LevelFilter::from_str(level)
+            .map_err(|_| anyhow!("Invalid log level '{}'", level))?;
+        self.0.reload(filter).map_err(|e| anyhow!("Failed to change log level: {}", e))
+    }
+}
+
+// Installs the global fmt subscriber behind a level filter a reload can change
+pub fn init_logging(level: &str) -> LogLevelHandle {
+    let filter = LevelFilter::from_str(level).unwrap_or_else(|_| {
+        warn!("Invalid log level '{}', using info", level);
+        LevelFilter::INFO
+    });
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    LogLevelHandle(handle)
+}
+
+// Reloads `config` on every SIGHUP for the life of the process
+#[cfg(unix)]
+pub fn spawn_sighup_reload(config: std::sync::Arc<ReloadableConfig>) -> Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            let config = config.clone();
+            match tokio::task::spawn_blocking(move || config.reload()).await {
+                Ok(Err(e)) => warn!("Configuration reload failed: {:#}", e),
+                Err(e) => warn!("Configuration reload panicked: {}", e),
+                Ok(Ok(_)) => {}
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::config::ConfigFormat;
+
+    #[test]
+    fn test_reload_applies_runtime_fields_only() {
+        let path = std::env::temp_dir().join(format!("flux-reload-{}.toml", std::process::id()));
+        let file = Config::default();
+        std::fs::write(&path, file.to_string(ConfigFormat::Toml).unwrap()).unwrap();
+
+        // The running config has its log path overridden on the command line
+        let mut running = file.clone();
+        running.log_file_path = Some(PathBuf::from("./events.log"));
+        let live = ReloadableConfig::new(Some(path.clone()), &file, running).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        live.on_reload(move |config, _| {
+            assert_eq!(config.log_level, "debug");
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert!(live.reload().unwrap().is_unchanged());
+
+        let mut edited = file.clone();
+        edited.log_level = "debug".to_string();
+        edited.alert_webhook_url = Some("https://hooks.example.com/flux".to_string());
+        edited.quarantine_directory = PathBuf::from("/srv/quarantine");
+        std::fs::write(&path, edited.to_string(ConfigFormat::Toml).unwrap()).unwrap();
+
+        let report = live.reload().unwrap();
+        assert_eq!(report.applied, vec!["alert_webhook_url", "log_level"]);
+        assert_eq!(report.restart_required, vec!["quarantine_directory"]);
+        let current = live.current();
+        assert_eq!(current.log_level, "debug");
+        assert_eq!(current.quarantine_directory, file.quarantine_directory);
+        assert_eq!(current.log_file_path, Some(PathBuf::from("./events.log")));
+
+        // Still pending on the next reload; applied fields are not reported twice
+        let report = live.reload().unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.restart_required, vec!["quarantine_directory"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A broken file leaves the running config alone
+        std::fs::write(&path, "log_level = \"loud\"").unwrap();
+        assert!(live.reload().is_err());
+        assert_eq!(live.current().log_level, "debug");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
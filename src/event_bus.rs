@@ -0,0 +1,240 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::Duration;
+use crossbeam_queue::ArrayQueue;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+pub const DEFAULT_SINK_CAPACITY: usize = 4096;
+
+// Upper bound on how long a delivery thread sleeps if a wakeup is missed
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SinkMetrics {
+    pub name: String,
+    pub capacity: usize,
+    pub depth: usize,
+    pub high_water: usize,
+    pub delivered: u64,
+    // Oldest events discarded because the sink fell behind
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventBusMetrics {
+    pub published: u64,
+    pub sinks: Vec<SinkMetrics>,
+}
+
+struct SinkQueue<T> {
+    name: String,
+    queue: ArrayQueue<T>,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    high_water: AtomicUsize,
+    closed: AtomicBool,
+    worker: Thread,
+}
+
+impl<T> SinkQueue<T> {
+    fn metrics(&self) -> SinkMetrics {
+        SinkMetrics {
+            name: self.name.clone(),
+            capacity: self.queue.capacity(),
+            depth: self.queue.len(),
+            high_water: self.high_water.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Fans events out from producers to sinks without ever blocking the producer.
+// Every sink has its own bounded lock-free queue and delivery thread; when a
+// sink falls behind, its oldest queued events are dropped and counted.
+pub struct EventBus<T> {
+    sinks: RwLock<Vec<Arc<SinkQueue<T>>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    capacity: usize,
+    published: AtomicU64,
+}
+
+impl<T: Clone + Send + 'static> EventBus<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sinks: RwLock::new(Vec::new()),
+            workers: Mutex::new(Vec::new()),
+            capacity: capacity.max(1),
+            published: AtomicU64::new(0),
+        }
+    }
+
+    pub fn subscribe<F>(&self, name: &str, sink: F)
+    where
+        F: Fn(T) + Send + 'static,
+    {
+        self.subscribe_with_capacity(name, self.capacity, sink);
+    }
+
+    pub fn subscribe_with_capacity<F>(&self, name: &str, capacity: usize, sink: F)
+    where
+        F: Fn(T) + Send + 'static,
+    {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Arc<SinkQueue<T>>>();
+        let worker = thread::Builder::new()
+            .name(format!("flux-sink-{}", name))
+            .spawn(move || {
+                let Ok(queue) = ready_rx.recv() else { return };
+                Self::deliver(&queue, sink);
+            })
+            .expect("failed to spawn event sink thread");
+
+        let queue = Arc::new(SinkQueue {
+            name: name.to_string(),
+            queue: ArrayQueue::new(capacity.max(1)),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            high_water: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            worker: worker.thread().clone(),
+        });
+        let _ = ready_tx.send(Arc::clone(&queue));
+
+        if let Ok(mut sinks) = self.sinks.write() {
+            sinks.push(queue);
+        }
+        if let Ok(mut workers) = self.workers.lock() {
+            workers.push(worker);
+        }
+    }
+
+    fn deliver<F: Fn(T)>(queue: &SinkQueue<T>, sink: F) {
+        loop {
+            while let Some(event) = queue.queue.pop() {
+                sink(event);
+                queue.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            if queue.closed.load(Ordering::Acquire) && queue.queue.is_empty() {
+                break;
+            }
+            thread::park_timeout(IDLE_WAIT);
+        }
+        debug!("Event sink {} stopped", queue.name);
+    }
+
+    // Never blocks: a full queue gives up its oldest event instead
+    pub fn publish(&self, event: T) {
+        self.published.fetch_add(1, Ordering::Relaxed);
+        let Ok(sinks) = self.sinks.read() else { return };
+
+        if let Some((last, rest)) = sinks.split_last() {
+            for sink in rest {
+                Self::enqueue(sink, event.clone());
+            }
+            Self::enqueue(last, event);
+        }
+    }
+
+    fn enqueue(sink: &SinkQueue<T>, event: T) {
+        if sink.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        if sink.queue.force_push(event).is_some() {
+            let dropped = sink.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // Log the first drop and then every 10000th so a stuck sink is visible but not noisy
+            if dropped == 1 || dropped.is_multiple_of(10_000) {
+                warn!("Event sink {} is falling behind; {} events dropped", sink.name, dropped);
+            }
+        }
+        sink.high_water.fetch_max(sink.queue.len(), Ordering::Relaxed);
+        sink.worker.unpark();
+    }
+
+    pub fn metrics(&self) -> EventBusMetrics {
+        EventBusMetrics {
+            published: self.published.load(Ordering::Relaxed),
+            sinks: self.sinks.read()
+                .map(|sinks| sinks.iter().map(|sink| sink.metrics()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    // Stops accepting events; delivery threads finish what is queued and exit
+    pub fn shutdown(&self) {
+        if let Ok(sinks) = self.sinks.read() {
+            for sink in sinks.iter() {
+                sink.closed.store(true, Ordering::Release);
+                sink.worker.unpark();
+            }
+        }
+    }
+
+    // Shuts down and waits for the queued events to be delivered
+    pub fn drain(&self) {
+        self.shutdown();
+        let workers: Vec<_> = self.workers.lock()
+            .map(|mut workers| workers.drain(..).collect())
+            .unwrap_or_default();
+        for worker in workers {
+            if worker.thread().id() != thread::current().id() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+impl<T> Drop for EventBus<T> {
+    fn drop(&mut self) {
+        if let Ok(sinks) = self.sinks.read() {
+            for sink in sinks.iter() {
+                sink.closed.store(true, Ordering::Release);
+                sink.worker.unpark();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_sink_drops_oldest_without_blocking() {
+        let bus = EventBus::new(4);
+        let fast = Arc::new(Mutex::new(Vec::new()));
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let gate = Arc::new(Mutex::new(()));
+
+        let fast_seen = Arc::clone(&fast);
+        bus.subscribe_with_capacity("fast", 1000, move |event: u32| fast_seen.lock().unwrap().push(event));
+        let slow_seen = Arc::clone(&slow);
+        let slow_gate = Arc::clone(&gate);
+        bus.subscribe("slow", move |event: u32| {
+            let _open = slow_gate.lock().unwrap();
+            slow_seen.lock().unwrap().push(event);
+        });
+
+        // Hold the slow sink while publishing far more than it can queue
+        let held = gate.lock().unwrap();
+        for event in 0..100u32 {
+            bus.publish(event);
+        }
+        drop(held);
+        bus.drain();
+
+        assert_eq!(*fast.lock().unwrap(), (0..100).collect::<Vec<_>>());
+        let slow = slow.lock().unwrap();
+        // At most one event in flight plus a full queue; the newest always survives
+        assert!(slow.len() <= 5);
+        assert_eq!(slow.last(), Some(&99));
+
+        let metrics = bus.metrics();
+        assert_eq!(metrics.published, 100);
+        let slow_metrics = metrics.sinks.iter().find(|s| s.name == "slow").unwrap();
+        assert_eq!(slow_metrics.dropped + slow_metrics.delivered, 100);
+        assert_eq!(slow_metrics.high_water, 4);
+        assert_eq!(slow_metrics.depth, 0);
+    }
+}
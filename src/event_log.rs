@@ -0,0 +1,617 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Context, Result};
+use sysinfo::System;
+
+use crate::monitor::{
+    EnhancedSecurityEvent, FileAccessType, NetworkProtocol, ProcessInfo, SecurityEvent, SecurityEventType, Verdict,
+};
+use crate::incidents::{event_severity, IncidentSeverity};
+use crate::output::{LogRotationConfig, RotatingLogWriter};
+use crate::system_metrics::SystemMetrics;
+
+// Versioned JSON-lines event log. Each file starts with a header record and
+// every event carries `schema_version`; the layout is described in
+// EVENT_LOG_SCHEMA.md. Fields are only ever added within a version, so
+// parsers should ignore fields they don't know.
+pub const EVENT_SCHEMA: &str = "fluxdefense.event";
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EventLogFormat {
+    // Bare serialized SecurityEvents, as written before schema versioning
+    Legacy,
+    #[default]
+    V1,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventLogHeader {
+    pub record: String,
+    pub schema: String,
+    pub schema_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub producer: String,
+    pub hostname: String,
+}
+
+impl EventLogHeader {
+    pub fn new() -> Self {
+        Self {
+            record: "header".to_string(),
+            schema: EVENT_SCHEMA.to_string(),
+            schema_version: EVENT_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            producer: concat!("fluxdefense/", env!("CARGO_PKG_VERSION")).to_string(),
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+        }
+    }
+}
+
+impl Default for EventLogHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub path: PathBuf,
+    pub uid: u32,
+    pub sha256: Option<String>,
+    pub command_line: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    // read, write, execute, create or delete
+    pub access: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub code_signature: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkRecord {
+    pub remote_ip: String,
+    pub remote_port: u16,
+    // tcp, udp or icmp
+    pub protocol: String,
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthRecord {
+    pub user: String,
+    pub service: String,
+    pub success: bool,
+    #[serde(default)]
+    pub remote_host: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyscallRecord {
+    pub name: String,
+    pub success: bool,
+    #[serde(default)]
+    pub exit_code: Option<i64>,
+    #[serde(default)]
+    pub audit_key: Option<String>,
+}
+
+// One event in schema version 1. `event_type` says which of the detail
+// objects is present; the others are omitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub record: String,
+    pub schema_version: u32,
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    // file_execution, file_access, network_connection, authentication or syscall
+    pub event_type: String,
+    // allow, deny or log
+    pub verdict: String,
+    pub reason: String,
+    pub process: ProcessRecord,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syscall: Option<SyscallRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_metrics: Option<SystemMetrics>,
+}
+
+impl EventRecord {
+    pub fn from_event(event: &SecurityEvent) -> Self {
+        let mut record = Self {
+            record: "event".to_string(),
+            schema_version: EVENT_SCHEMA_VERSION,
+            id: event.id.clone(),
+            timestamp: event.timestamp,
+            event_type: String::new(),
+            verdict: match event.verdict {
+                Verdict::Allow => "allow",
+                Verdict::Deny => "deny",
+                Verdict::Log => "log",
+            }.to_string(),
+            reason: event.policy_reason.clone(),
+            process: ProcessRecord {
+                pid: event.process_info.pid,
+                ppid: event.process_info.parent_pid,
+                path: event.process_info.path.clone(),
+                uid: event.process_info.user_id,
+                sha256: event.process_info.executable_hash.clone(),
+                command_line: event.process_info.command_line.clone(),
+            },
+            file: None,
+            network: None,
+            auth: None,
+            syscall: None,
+            system_metrics: None,
+        };
+
+        record.event_type = match &event.event_type {
+            SecurityEventType::FileExecution { target_path, file_hash, code_signature } => {
+                record.file = Some(FileRecord {
+                    path: target_path.clone(),
+                    access: "execute".to_string(),
+                    sha256: file_hash.clone(),
+                    code_signature: code_signature.clone(),
+                });
+                "file_execution"
+            }
+            SecurityEventType::FileAccess { target_path, access_type } => {
+                record.file = Some(FileRecord {
+                    path: target_path.clone(),
+                    access: match access_type {
+                        FileAccessType::Read => "read",
+                        FileAccessType::Write => "write",
+                        FileAccessType::Execute => "execute",
+                        FileAccessType::Create => "create",
+                        FileAccessType::Delete => "delete",
+                    }.to_string(),
+                    sha256: None,
+                    code_signature: None,
+                });
+                "file_access"
+            }
+            SecurityEventType::NetworkConnection { remote_ip, remote_port, domain, protocol } => {
+                record.network = Some(NetworkRecord {
+                    remote_ip: remote_ip.clone(),
+                    remote_port: *remote_port,
+                    protocol: match protocol {
+                        NetworkProtocol::Tcp => "tcp",
+                        NetworkProtocol::Udp => "udp",
+                        NetworkProtocol::Icmp => "icmp",
+                    }.to_string(),
+                    domain: domain.clone(),
+                });
+                "network_connection"
+            }
+            SecurityEventType::Authentication { user, service, success, remote_host } => {
+                record.auth = Some(AuthRecord {
+                    user: user.clone(),
+                    service: service.clone(),
+                    success: *success,
+                    remote_host: remote_host.clone(),
+                });
+                "authentication"
+            }
+            SecurityEventType::Syscall { syscall, success, exit_code, audit_key } => {
+                record.syscall = Some(SyscallRecord {
+                    name: syscall.clone(),
+                    success: *success,
+                    exit_code: *exit_code,
+                    audit_key: audit_key.clone(),
+                });
+                "syscall"
+            }
+        }.to_string();
+        record
+    }
+
+    pub fn with_metrics(mut self, metrics: Option<SystemMetrics>) -> Self {
+        self.system_metrics = metrics;
+        self
+    }
+
+    pub fn to_event(&self) -> Result<SecurityEvent> {
+        let missing = |detail: &str| anyhow!("{} event {} has no {} object", self.event_type, self.id, detail);
+        let event_type = match self.event_type.as_str() {
+            "file_execution" => {
+                let file = self.file.as_ref().ok_or_else(|| missing("file"))?;
+                SecurityEventType::FileExecution {
+                    target_path: file.path.clone(),
+                    file_hash: file.sha256.clone(),
+                    code_signature: file.code_signature.clone(),
+                }
+            }
+            "file_access" => {
+                let file = self.file.as_ref().ok_or_else(|| missing("file"))?;
+                SecurityEventType::FileAccess {
+                    target_path: file.path.clone(),
+                    access_type: match file.access.as_str() {
+                        "read" => FileAccessType::Read,
+                        "write" => FileAccessType::Write,
+                        "execute" => FileAccessType::Execute,
+                        "create" => FileAccessType::Create,
+                        "delete" => FileAccessType::Delete,
+                        other => return Err(anyhow!("Unknown file access '{}'", other)),
+                    },
+                }
+            }
+            "network_connection" => {
+                let network = self.network.as_ref().ok_or_else(|| missing("network"))?;
+                SecurityEventType::NetworkConnection {
+                    remote_ip: network.remote_ip.clone(),
+                    remote_port: network.remote_port,
+                    domain: network.domain.clone(),
+                    protocol: match network.protocol.as_str() {
+                        "tcp" => NetworkProtocol::Tcp,
+                        "udp" => NetworkProtocol::Udp,
+                        "icmp" => NetworkProtocol::Icmp,
+                        other => return Err(anyhow!("Unknown protocol '{}'", other)),
+                    },
+                }
+            }
+            "authentication" => {
+                let auth = self.auth.as_ref().ok_or_else(|| missing("auth"))?;
+                SecurityEventType::Authentication {
+                    user: auth.user.clone(),
+                    service: auth.service.clone(),
+                    success: auth.success,
+                    remote_host: auth.remote_host.clone(),
+                }
+            }
+            "syscall" => {
+                let syscall = self.syscall.as_ref().ok_or_else(|| missing("syscall"))?;
+                SecurityEventType::Syscall {
+                    syscall: syscall.name.clone(),
+                    success: syscall.success,
+                    exit_code: syscall.exit_code,
+                    audit_key: syscall.audit_key.clone(),
+                }
+            }
+            other => return Err(anyhow!("Unknown event type '{}'", other)),
+        };
+
+        Ok(SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type,
+            process_info: ProcessInfo {
+                pid: self.process.pid,
+                path: self.process.path.clone(),
+                parent_pid: self.process.ppid,
+                user_id: self.process.uid,
+                executable_hash: self.process.sha256.clone(),
+                command_line: self.process.command_line.clone(),
+            },
+            verdict: match self.verdict.as_str() {
+                "allow" => Verdict::Allow,
+                "deny" => Verdict::Deny,
+                "log" => Verdict::Log,
+                other => return Err(anyhow!("Unknown verdict '{}'", other)),
+            },
+            policy_reason: self.reason.clone(),
+        })
+    }
+}
+
+// Serializes an event as one log line in `format`
+pub fn format_event(event: &SecurityEvent, metrics: Option<SystemMetrics>, format: EventLogFormat) -> Result<String> {
+    Ok(match (format, metrics) {
+        (EventLogFormat::V1, metrics) => serde_json::to_string(&EventRecord::from_event(event).with_metrics(metrics))?,
+        (EventLogFormat::Legacy, None) => serde_json::to_string(event)?,
+        (EventLogFormat::Legacy, Some(metrics)) => serde_json::to_string(&EnhancedSecurityEvent {
+            security_event: event.clone(),
+            system_metrics: Some(metrics),
+            timestamp: Utc::now(),
+        })?,
+    })
+}
+
+// Event log writer for `format`; versioned files begin with a header record
+pub fn open_writer(path: PathBuf, rotation: Option<LogRotationConfig>, format: EventLogFormat) -> RotatingLogWriter {
+    let writer = RotatingLogWriter::new(path, rotation);
+    match format {
+        EventLogFormat::V1 => writer.with_header(|| serde_json::to_string(&EventLogHeader::new()).unwrap_or_default()),
+        EventLogFormat::Legacy => writer,
+    }
+}
+
+// Reads a line in any format the monitor has written: a versioned record, a
+// bare legacy event, or a legacy event wrapped with system metrics. Header
+// lines give Ok(None).
+pub fn parse_event_line(line: &str) -> Result<Option<SecurityEvent>> {
+    let value: Value = serde_json::from_str(line)?;
+    match value.get("record").and_then(Value::as_str) {
+        Some("header") => Ok(None),
+        Some("event") => {
+            let version = value.get("schema_version").and_then(Value::as_u64).unwrap_or(0);
+            if version == 0 || version > EVENT_SCHEMA_VERSION as u64 {
+                return Err(anyhow!("Unsupported event schema version {}", version));
+            }
+            let record: EventRecord = serde_json::from_value(value)?;
+            record.to_event().map(Some)
+        }
+        Some(other) => Err(anyhow!("Unknown record type '{}'", other)),
+        None if value.get("security_event").is_some() => {
+            Ok(Some(serde_json::from_value::<EnhancedSecurityEvent>(value)?.security_event))
+        }
+        None => Ok(Some(serde_json::from_value(value)?)),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConversionSummary {
+    pub converted: usize,
+    // Lines that were neither legacy nor versioned events
+    pub skipped: usize,
+}
+
+// Rewrites a legacy (or mixed) event log in the current schema
+pub fn convert_log(input: &Path, output: &Path) -> Result<ConversionSummary> {
+    let reader = BufReader::new(File::open(input)
+        .with_context(|| format!("Failed to open event log {:?}", input))?);
+    let mut writer = BufWriter::new(File::create(output)
+        .with_context(|| format!("Failed to create {:?}", output))?);
+    writeln!(writer, "{}", serde_json::to_string(&EventLogHeader::new())?)?;
+
+    let mut summary = ConversionSummary::default();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let metrics = serde_json::from_str::<EnhancedSecurityEvent>(&line).ok()
+            .and_then(|enhanced| enhanced.system_metrics);
+        match parse_event_line(&line) {
+            Ok(Some(event)) => {
+                writeln!(writer, "{}", format_event(&event, metrics, EventLogFormat::V1)?)?;
+                summary.converted += 1;
+            }
+            Ok(None) => {}
+            Err(_) => summary.skipped += 1,
+        }
+    }
+    writer.flush()?;
+    Ok(summary)
+}
+
+// Which events `search_log` returns; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    // At least this severity, as the incident tracker rates events
+    pub min_severity: Option<IncidentSeverity>,
+    pub pid: Option<u32>,
+    // file_execution, file_access, network_connection, authentication or syscall
+    pub event_type: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &SecurityEvent) -> bool {
+        self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp <= until)
+            && self.min_severity.is_none_or(|min| event_severity(event) >= min)
+            && self.pid.is_none_or(|pid| event.process_info.pid == pid)
+            && self.event_type.as_deref().is_none_or(|event_type| event_type_name(event) == event_type)
+    }
+}
+
+pub fn event_type_name(event: &SecurityEvent) -> &'static str {
+    match event.event_type {
+        SecurityEventType::FileExecution { .. } => "file_execution",
+        SecurityEventType::FileAccess { .. } => "file_access",
+        SecurityEventType::NetworkConnection { .. } => "network_connection",
+        SecurityEventType::Authentication { .. } => "authentication",
+        SecurityEventType::Syscall { .. } => "syscall",
+    }
+}
+
+// An RFC 3339 timestamp, or a duration before `now` such as 30s, 15m, 2h or 7d
+pub fn parse_time(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let (amount, unit) = value.split_at(value.len().saturating_sub(1));
+    let amount: i64 = amount.parse().map_err(|_| anyhow!("Invalid time '{}': use RFC 3339 or e.g. 15m, 2h, 7d", value))?;
+    let ago = match unit {
+        "s" => chrono::Duration::seconds(amount),
+        "m" => chrono::Duration::minutes(amount),
+        "h" => chrono::Duration::hours(amount),
+        "d" => chrono::Duration::days(amount),
+        _ => return Err(anyhow!("Invalid time '{}': use RFC 3339 or e.g. 15m, 2h, 7d", value)),
+    };
+    Ok(now - ago)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub events: Vec<SecurityEvent>,
+    // Matches older than the returned ones that did not fit the limit
+    pub truncated: usize,
+    pub skipped_lines: usize,
+}
+
+// Searches the event log and its rotated (possibly compressed) files, oldest
+// first, and returns the newest `limit` matches in time order
+pub fn search_log(path: &Path, filter: &EventFilter, limit: usize) -> Result<SearchResult> {
+    let mut files = crate::output::rotating_log::rotated_files(path).unwrap_or_default();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No event log at {:?}", path));
+    }
+
+    let mut result = SearchResult::default();
+    let mut matches = std::collections::VecDeque::new();
+    for file in files {
+        for line in open_log(&file)?.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_event_line(&line) {
+                Ok(Some(event)) if filter.matches(&event) => {
+                    matches.push_back(event);
+                    if matches.len() > limit {
+                        matches.pop_front();
+                        result.truncated += 1;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => result.skipped_lines += 1,
+            }
+        }
+    }
+    result.events = matches.into();
+    Ok(result)
+}
+
+fn open_log(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("Failed to open event log {:?}", path))?;
+    Ok(match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => Box::new(BufReader::new(flate2::read::GzDecoder::new(file))),
+        Some("zst") => Box::new(BufReader::new(zstd::stream::read::Decoder::new(file)?)),
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<SecurityEvent> {
+        let process = ProcessInfo {
+            pid: 4242,
+            path: PathBuf::from("/usr/bin/curl"),
+            parent_pid: Some(1),
+            user_id: 1000,
+            executable_hash: Some("ab".repeat(32)),
+            command_line: Some("curl http://example.com".to_string()),
+        };
+        let event = |event_type, verdict| SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type,
+            process_info: process.clone(),
+            verdict,
+            policy_reason: "test".to_string(),
+        };
+        vec![
+            event(SecurityEventType::FileExecution {
+                target_path: PathBuf::from("/tmp/payload"), file_hash: None, code_signature: None,
+            }, Verdict::Deny),
+            event(SecurityEventType::FileAccess {
+                target_path: PathBuf::from("/etc/shadow"), access_type: FileAccessType::Read,
+            }, Verdict::Log),
+            event(SecurityEventType::NetworkConnection {
+                remote_ip: "203.0.113.9".to_string(), remote_port: 443,
+                domain: Some("example.com".to_string()), protocol: NetworkProtocol::Tcp,
+            }, Verdict::Allow),
+            event(SecurityEventType::Authentication {
+                user: "root".to_string(), service: "sshd".to_string(), success: false, remote_host: None,
+            }, Verdict::Log),
+            event(SecurityEventType::Syscall {
+                syscall: "ptrace".to_string(), success: true, exit_code: Some(0), audit_key: None,
+            }, Verdict::Log),
+        ]
+    }
+
+    #[test]
+    fn test_versioned_records_and_legacy_conversion() {
+        let events = events();
+        for event in &events {
+            let line = format_event(event, None, EventLogFormat::V1).unwrap();
+            let value: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(value["schema_version"], 1);
+            assert_eq!(value["process"]["ppid"], 1);
+            let parsed = parse_event_line(&line).unwrap().unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(event).unwrap());
+        }
+
+        // Newer records may add fields; a newer version is refused
+        let mut value = serde_json::to_value(EventRecord::from_event(&events[2])).unwrap();
+        value["added_later"] = Value::from(true);
+        assert!(parse_event_line(&value.to_string()).unwrap().is_some());
+        value["schema_version"] = Value::from(EVENT_SCHEMA_VERSION + 1);
+        assert!(parse_event_line(&value.to_string()).is_err());
+
+        let dir = std::env::temp_dir().join(format!("flux-event-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join("legacy.log");
+        let mut lines: Vec<String> = events.iter()
+            .map(|event| format_event(event, None, EventLogFormat::Legacy).unwrap())
+            .collect();
+        lines.push("not json".to_string());
+        std::fs::write(&legacy, lines.join("\n")).unwrap();
+
+        let converted = dir.join("converted.log");
+        let summary = convert_log(&legacy, &converted).unwrap();
+        assert_eq!((summary.converted, summary.skipped), (5, 1));
+        let content = std::fs::read_to_string(&converted).unwrap();
+        let header: EventLogHeader = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!((header.schema.as_str(), header.schema_version), (EVENT_SCHEMA, EVENT_SCHEMA_VERSION));
+        let parsed: Vec<SecurityEvent> = content.lines().filter_map(|line| parse_event_line(line).unwrap()).collect();
+        assert_eq!(parsed.iter().map(|e| &e.id).collect::<Vec<_>>(), events.iter().map(|e| &e.id).collect::<Vec<_>>());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_across_rotated_logs() {
+        let dir = std::env::temp_dir().join(format!("flux-event-search-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.log");
+        let mut events = events();
+        events[0].timestamp = Utc::now() - chrono::Duration::hours(3);
+
+        // The denied execution sits in a compressed rotated file
+        let rotated = File::create(dir.join("events.log.20240101T000000.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(rotated, flate2::Compression::default());
+        writeln!(encoder, "{}", format_event(&events[0], None, EventLogFormat::V1).unwrap()).unwrap();
+        encoder.finish().unwrap();
+        let current: Vec<String> = events[1..].iter()
+            .map(|event| format_event(event, None, EventLogFormat::V1).unwrap())
+            .collect();
+        std::fs::write(&path, current.join("\n") + "\nnot json\n").unwrap();
+
+        let all = search_log(&path, &EventFilter::default(), 100).unwrap();
+        assert_eq!((all.events.len(), all.skipped_lines), (5, 1));
+        assert_eq!(all.events[0].id, events[0].id);
+
+        let high = EventFilter { min_severity: Some(IncidentSeverity::High), ..Default::default() };
+        assert_eq!(search_log(&path, &high, 100).unwrap().events[0].id, events[0].id);
+        let recent = EventFilter {
+            since: Some(parse_time("1h", Utc::now()).unwrap()),
+            event_type: Some("authentication".to_string()),
+            pid: Some(4242),
+            ..Default::default()
+        };
+        assert_eq!(search_log(&path, &recent, 100).unwrap().events[0].id, events[3].id);
+        let newest = search_log(&path, &EventFilter::default(), 2).unwrap();
+        assert_eq!((newest.events.len(), newest.truncated), (2, 3));
+        assert_eq!(newest.events[1].id, events[4].id);
+        assert!(parse_time("yesterday", Utc::now()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
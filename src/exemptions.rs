@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+// Linux task flag of kernel threads, in field 9 of /proc/PID/stat
+#[cfg(target_os = "linux")]
+const PF_KTHREAD: u64 = 0x0020_0000;
+// Cached verdicts beyond this start the cache over
+const MAX_CACHED: usize = 65_536;
+
+// pid -> (start time, verdict)
+type ExemptionCache = HashMap<u32, (u64, Option<String>)>;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExemptionConfig {
+    // Absolute paths of management agents and other executables that must
+    // keep running whatever the policy says. Names are not accepted: any
+    // process can take one
+    #[serde(default)]
+    pub processes: Vec<String>,
+}
+
+impl ExemptionConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(process) = self.processes.iter().find(|process| !Path::new(process).is_absolute()) {
+            return Err(anyhow!("exemption {:?} is not an absolute executable path", process));
+        }
+        Ok(())
+    }
+}
+
+// Processes that are never blocked or killed, and files never quarantined,
+// so an aggressive policy cannot take down the agent or the host. Built in
+// are this agent and anything running its binary, init and kernel threads.
+// Children of an exempt process are not exempt themselves
+#[derive(Debug, Clone)]
+pub struct Exemptions {
+    own_exe: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+    // So /proc is read once per process rather than on every event
+    cache: Arc<Mutex<ExemptionCache>>,
+}
+
+impl Default for Exemptions {
+    fn default() -> Self {
+        Self::new(&ExemptionConfig::default())
+    }
+}
+
+impl Exemptions {
+    // Executables that another user could replace, or place a lookalike
+    // next to, are left out
+    pub fn new(config: &ExemptionConfig) -> Self {
+        let paths = config.processes.iter()
+            .map(PathBuf::from)
+            .filter(|path| match root_owned(path) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Ignoring exemption {:?}: {}", path, e);
+                    false
+                }
+            })
+            .collect();
+        // The agent binary itself too: were it writable by another user,
+        // whatever they put there would run exempt
+        let own_exe = std::env::current_exe().ok()
+            .map(|exe| strip_deleted(&exe))
+            .filter(|exe| match root_owned(exe) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Not exempting processes that run the agent binary {:?}: {}", exe, e);
+                    false
+                }
+            });
+        Self {
+            own_exe,
+            paths,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Why `pid` must be left alone, if it must
+    pub fn exemption(&self, pid: u32) -> Option<String> {
+        if pid == std::process::id() {
+            return Some("the agent itself".to_string());
+        }
+        if pid == 1 {
+            return Some("init".to_string());
+        }
+        if let Some(exe) = process_exe(pid) {
+            if self.own_exe.as_ref() == Some(&exe) {
+                return Some("runs the agent binary".to_string());
+            }
+            if let Some(path) = self.paths.iter().find(|path| **path == exe) {
+                return Some(format!("exempt executable {}", path.display()));
+            }
+        }
+        if is_kernel_thread(pid) {
+            return Some("kernel thread".to_string());
+        }
+        None
+    }
+
+    // As `exemption`, remembered for the process started at `start_time`.
+    // A process that execs keeps its pid and start time, so callers must
+    // `forget` it then
+    pub fn cached_exemption(&self, pid: u32, start_time: u64) -> Option<String> {
+        if let Ok(cache) = self.cache.lock() {
+            if let Some((started, exemption)) = cache.get(&pid) {
+                if *started == start_time {
+                    return exemption.clone();
+                }
+            }
+        }
+        let exemption = self.exemption(pid);
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.len() >= MAX_CACHED {
+                cache.clear();
+            }
+            cache.insert(pid, (start_time, exemption.clone()));
+        }
+        exemption
+    }
+
+    pub fn forget(&self, pid: u32) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.remove(&pid);
+        }
+    }
+
+    pub fn is_exempt(&self, pid: u32) -> bool {
+        self.exemption(pid).is_some()
+    }
+
+    // Why `path` must not be quarantined, if it must not: it is the binary
+    // of the agent, init or an exempt process
+    pub fn protected_file(&self, path: &Path) -> Option<String> {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.own_exe.as_ref() == Some(&path) {
+            return Some("the agent binary".to_string());
+        }
+        if process_exe(1).as_ref() == Some(&path) {
+            return Some("the init binary".to_string());
+        }
+        self.paths.iter()
+            .find(|exempt| **exempt == path)
+            .map(|exempt| format!("exempt executable {}", exempt.display()))
+    }
+}
+
+// The executable and its directory must belong to root and be writable by
+// no one else. That also keeps anyone else from creating the "PATH (deleted)"
+// name a replaced binary shows up under
+#[cfg(unix)]
+fn root_owned(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let directory = path.parent().ok_or_else(|| anyhow!("no parent directory"))?;
+    for checked in [path, directory] {
+        let metadata = std::fs::metadata(checked).map_err(|e| anyhow!("{:?}: {}", checked, e))?;
+        if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+            return Err(anyhow!("{:?} is not owned and only writable by root", checked));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn root_owned(_path: &Path) -> Result<()> {
+    Err(anyhow!("ownership cannot be checked on this platform"))
+}
+
+// The kernel marks a replaced or removed binary, e.g. after a self-update
+fn strip_deleted(exe: &Path) -> PathBuf {
+    let exe = exe.to_string_lossy();
+    PathBuf::from(exe.strip_suffix(" (deleted)").unwrap_or(&exe))
+}
+
+#[cfg(target_os = "linux")]
+fn process_exe(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/exe", pid)).ok().map(|exe| strip_deleted(&exe))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_exe(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
+// Only the kernel sets the flag; a parent of kthreadd is no proof, since
+// usermode helpers the kernel starts have it too
+#[cfg(target_os = "linux")]
+fn is_kernel_thread(pid: u32) -> bool {
+    if pid == 2 {
+        return true;
+    }
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else { return false };
+    // The command name can hold spaces, so count from the last `)`; the
+    // flags are the seventh field after it
+    let Some(end) = stat.rfind(')') else { return false };
+    stat[end + 1..].split_whitespace().nth(6)
+        .and_then(|flags| flags.parse::<u64>().ok())
+        .is_some_and(|flags| flags & PF_KTHREAD != 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_kernel_thread(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_exemptions() {
+        let exemptions = Exemptions::default();
+        assert_eq!(exemptions.exemption(std::process::id()).as_deref(), Some("the agent itself"));
+        assert!(exemptions.is_exempt(1));
+        if Path::new("/proc/2/stat").exists() {
+            assert!(exemptions.is_exempt(2));
+        }
+        let own_exe = std::env::current_exe().unwrap();
+        // Only a binary no one but root can replace is exempt
+        assert_eq!(exemptions.protected_file(&own_exe).is_some(), root_owned(&own_exe).is_ok());
+
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let sleep = std::fs::read_link(format!("/proc/{}/exe", child.id())).unwrap();
+        assert!(!exemptions.is_exempt(child.id()));
+        assert!(exemptions.protected_file(Path::new("/etc/hostname")).is_none());
+
+        // Names are refused, and so are paths outside root's control
+        assert!(ExemptionConfig { processes: vec!["sleep".to_string()] }.validate().is_err());
+        let lookalike = std::env::temp_dir().join(format!("flux-exemption-{}", std::process::id()));
+        std::fs::copy(&sleep, &lookalike).unwrap();
+        let config = ExemptionConfig {
+            processes: vec![sleep.display().to_string(), lookalike.display().to_string()],
+        };
+        config.validate().unwrap();
+        let exemptions = Exemptions::new(&config);
+        assert!(exemptions.protected_file(&lookalike).is_none());
+        std::fs::remove_file(&lookalike).unwrap();
+
+        let exempt = exemptions.exemption(child.id());
+        if root_owned(&sleep).is_ok() {
+            assert_eq!(exempt, Some(format!("exempt executable {}", sleep.display())));
+        }
+        // Cached per process start; a new start time looks again
+        assert_eq!(exemptions.cached_exemption(child.id(), 7), exempt);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(exemptions.cached_exemption(child.id(), 7), exempt);
+        assert_eq!(exemptions.cached_exemption(child.id(), 8), None);
+        exemptions.forget(child.id());
+        assert!(exemptions.cache.lock().unwrap().is_empty());
+    }
+}
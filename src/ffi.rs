@@ -1,115 +1,371 @@
-use std::ffi::{CStr, CString};
+//! C API for embedding the engine, e.g. from the Swift system extension.
+//! include/fluxdefense.h is generated from this file with `make header`.
+
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::{c_char, c_int};
-use std::sync::Once;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard, Once};
 use tracing::{info, warn, error};
+use crate::config::Config;
+use crate::policy::CandidatePolicy;
 use crate::FluxDefense;
-use crate::policy::{FilePolicy, NetworkPolicy};
-use std::path::PathBuf;
 
-static INIT: Once = Once::new();
-static mut FLUX_DEFENSE: Option<FluxDefense> = None;
+/// Version of this API; bumped whenever a signature or behavior changes.
+pub const FLUX_API_VERSION: u32 = 1;
+
+/// Success.
+pub const FLUX_OK: c_int = 0;
+/// A pointer was null, a string was not UTF-8 or the JSON did not parse.
+pub const FLUX_ERR_INVALID_ARGUMENT: c_int = -1;
+/// flux_init has not been called, or flux_shutdown has.
+pub const FLUX_ERR_NOT_INITIALIZED: c_int = -2;
+/// flux_init was already called, or flux_start already ran.
+pub const FLUX_ERR_ALREADY_INITIALIZED: c_int = -3;
+/// The engine failed; flux_last_error has the details.
+pub const FLUX_ERR_INTERNAL: c_int = -4;
+
+/// Called with each security event as a JSON object. The string is only
+/// valid during the call. Calls come from an engine thread, one at a time.
+pub type FluxEventCallback = Option<extern "C" fn(event_json: *const c_char, user_data: *mut c_void)>;
+
+struct Engine {
+    defense: FluxDefense,
+    runtime: tokio::runtime::Runtime,
+    started: bool,
+    // Policy set before flux_start, applied once the monitor exists
+    pending_policy: Option<CandidatePolicy>,
+}
+
+#[derive(Clone, Copy)]
+struct Subscriber {
+    id: c_int,
+    callback: extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// The embedder owns user_data and promises it can be used from any thread
+unsafe impl Send for Subscriber {}
+
+static LOGGING: Once = Once::new();
+static ENGINE: Mutex<Option<Engine>> = Mutex::new(None);
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static NEXT_SUBSCRIPTION: AtomicI32 = AtomicI32::new(1);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    error!("{}", message);
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn engine() -> MutexGuard<'static, Option<Engine>> {
+    // A panic while holding the lock leaves the engine usable
+    ENGINE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn subscribers() -> MutexGuard<'static, Vec<Subscriber>> {
+    SUBSCRIBERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 
-// Initialize the FluxDefense system
+fn init_logging() {
+    LOGGING.call_once(|| {
+        let _ = tracing_subscriber::fmt::try_init();
+    });
+}
+
+unsafe fn read_str<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, c_int> {
+    if ptr.is_null() {
+        set_last_error(format!("{} is null", what));
+        return Err(FLUX_ERR_INVALID_ARGUMENT);
+    }
+    CStr::from_ptr(ptr).to_str().map_err(|_| {
+        set_last_error(format!("{} is not valid UTF-8", what));
+        FLUX_ERR_INVALID_ARGUMENT
+    })
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+    CString::new(value).map(CString::into_raw).unwrap_or(std::ptr::null_mut())
+}
+
+fn dispatch_event(event: &crate::monitor::SecurityEvent) {
+    // Copied out so a callback may subscribe or unsubscribe
+    let subscribers: Vec<Subscriber> = subscribers().clone();
+    if subscribers.is_empty() {
+        return;
+    }
+    let Ok(json) = serde_json::to_string(event).map(CString::new) else {
+        return;
+    };
+    let Ok(json) = json else {
+        return;
+    };
+    for subscriber in subscribers {
+        (subscriber.callback)(json.as_ptr(), subscriber.user_data);
+    }
+}
+
+/// Version of the C API this library implements, FLUX_API_VERSION.
 #[no_mangle]
-pub extern "C" fn flux_defense_init() {
-    INIT.call_once(|| {
-        // Initialize logging
-        tracing_subscriber::fmt::init();
-        
-        info!("Initializing FluxDefense from Swift extension");
-        
-        match FluxDefense::new() {
-            Ok(defense) => {
-                unsafe {
-                    FLUX_DEFENSE = Some(defense);
-                }
-                info!("FluxDefense initialized successfully");
-            }
-            Err(e) => {
-                error!("Failed to initialize FluxDefense: {}", e);
-            }
+pub extern "C" fn flux_api_version() -> u32 {
+    FLUX_API_VERSION
+}
+
+/// Creates the engine from the configuration file at `config_path`, or from
+/// the default locations when it is null. Nothing is monitored until
+/// flux_start.
+///
+/// # Safety
+/// `config_path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn flux_init(config_path: *const c_char) -> c_int {
+    init_logging();
+    let path = if config_path.is_null() {
+        None
+    } else {
+        match read_str(config_path, "config_path") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(code) => return code,
         }
-    });
+    };
+
+    let mut engine = engine();
+    if engine.is_some() {
+        set_last_error("FluxDefense is already initialized".to_string());
+        return FLUX_ERR_ALREADY_INITIALIZED;
+    }
+    let defense = Config::load_effective(path.as_deref()).and_then(FluxDefense::new_with_config);
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build();
+    match (defense, runtime) {
+        (Ok(defense), Ok(runtime)) => {
+            *engine = Some(Engine { defense, runtime, started: false, pending_policy: None });
+            info!("FluxDefense initialized through the C API");
+            FLUX_OK
+        }
+        (Err(e), _) => {
+            set_last_error(format!("Failed to initialize FluxDefense: {:#}", e));
+            FLUX_ERR_INTERNAL
+        }
+        (_, Err(e)) => {
+            set_last_error(format!("Failed to start the FluxDefense runtime: {}", e));
+            FLUX_ERR_INTERNAL
+        }
+    }
+}
+
+/// Starts monitoring and begins delivering events to subscribers.
+#[no_mangle]
+pub extern "C" fn flux_start() -> c_int {
+    let mut guard = engine();
+    let Some(engine) = guard.as_mut() else {
+        set_last_error("FluxDefense is not initialized".to_string());
+        return FLUX_ERR_NOT_INITIALIZED;
+    };
+    if engine.started {
+        set_last_error("FluxDefense is already started".to_string());
+        return FLUX_ERR_ALREADY_INITIALIZED;
+    }
+    if let Err(e) = engine.runtime.block_on(engine.defense.start()) {
+        set_last_error(format!("Failed to start FluxDefense: {:#}", e));
+        return FLUX_ERR_INTERNAL;
+    }
+    engine.started = true;
+    if let Some(monitor) = engine.defense.get_monitor_mut() {
+        monitor.add_event_sink("ffi", dispatch_event);
+    }
+    if let Some(policy) = engine.pending_policy.take() {
+        if let Err(e) = engine.defense.set_policies(policy) {
+            set_last_error(format!("Failed to apply the policy set before start: {:#}", e));
+            return FLUX_ERR_INTERNAL;
+        }
+    }
+    FLUX_OK
+}
+
+/// Replaces the enforced policies with `policy_json`, an object with an
+/// optional "file_policy" and "network_policy", as served by
+/// GET /api/policies/active. Omitted halves are left as they are.
+///
+/// # Safety
+/// `policy_json` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn flux_set_policy_json(policy_json: *const c_char) -> c_int {
+    let json = match read_str(policy_json, "policy_json") {
+        Ok(json) => json,
+        Err(code) => return code,
+    };
+    let mut policy: CandidatePolicy = match serde_json::from_str(json) {
+        Ok(policy) => policy,
+        Err(e) => {
+            set_last_error(format!("Invalid policy JSON: {}", e));
+            return FLUX_ERR_INVALID_ARGUMENT;
+        }
+    };
+    if policy.file_policy.is_none() && policy.network_policy.is_none() {
+        set_last_error("Policy JSON has neither file_policy nor network_policy".to_string());
+        return FLUX_ERR_INVALID_ARGUMENT;
+    }
+    if let Err(e) = policy.validate() {
+        set_last_error(format!("Invalid policy: {:#}", e));
+        return FLUX_ERR_INVALID_ARGUMENT;
+    }
+
+    let mut guard = engine();
+    let Some(engine) = guard.as_mut() else {
+        set_last_error("FluxDefense is not initialized".to_string());
+        return FLUX_ERR_NOT_INITIALIZED;
+    };
+    if !engine.started {
+        // Merged so a file and a network policy can be set separately
+        let pending = engine.pending_policy.get_or_insert_with(CandidatePolicy::default);
+        pending.file_policy = policy.file_policy.or(pending.file_policy.take());
+        pending.network_policy = policy.network_policy.or(pending.network_policy.take());
+        return FLUX_OK;
+    }
+    match engine.defense.set_policies(policy) {
+        Ok(()) => FLUX_OK,
+        Err(e) => {
+            set_last_error(format!("Failed to apply policy: {:#}", e));
+            FLUX_ERR_INTERNAL
+        }
+    }
 }
 
-// Cleanup the FluxDefense system
+/// Calls `callback` with every security event until flux_unsubscribe_events
+/// or flux_shutdown. Returns a subscription id above zero, or an error code.
+/// Subscribing before flux_start is allowed.
+#[no_mangle]
+pub extern "C" fn flux_subscribe_events(callback: FluxEventCallback, user_data: *mut c_void) -> c_int {
+    let Some(callback) = callback else {
+        set_last_error("callback is null".to_string());
+        return FLUX_ERR_INVALID_ARGUMENT;
+    };
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+    subscribers().push(Subscriber { id, callback, user_data });
+    id
+}
+
+/// Stops the callbacks of a subscription; no call is in progress or made
+/// after this returns, unless it is called from within the callback itself.
+#[no_mangle]
+pub extern "C" fn flux_unsubscribe_events(subscription: c_int) -> c_int {
+    let mut subscribers = subscribers();
+    let before = subscribers.len();
+    subscribers.retain(|subscriber| subscriber.id != subscription);
+    if subscribers.len() == before {
+        set_last_error(format!("No subscription {}", subscription));
+        return FLUX_ERR_INVALID_ARGUMENT;
+    }
+    FLUX_OK
+}
+
+/// Stops monitoring, delivers queued events and releases the engine; flux_init
+/// may be called again afterwards.
+#[no_mangle]
+pub extern "C" fn flux_shutdown() -> c_int {
+    let Some(mut engine) = engine().take() else {
+        set_last_error("FluxDefense is not initialized".to_string());
+        return FLUX_ERR_NOT_INITIALIZED;
+    };
+    let result = if engine.started {
+        engine.runtime.block_on(engine.defense.stop())
+    } else {
+        Ok(())
+    };
+    subscribers().clear();
+    drop(engine);
+    match result {
+        Ok(()) => FLUX_OK,
+        Err(e) => {
+            set_last_error(format!("Failed to stop FluxDefense cleanly: {:#}", e));
+            FLUX_ERR_INTERNAL
+        }
+    }
+}
+
+/// The message of the last error on this thread, or null. Free it with
+/// flux_defense_free_string.
+#[no_mangle]
+pub extern "C" fn flux_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| last.borrow().clone()).map_or(std::ptr::null_mut(), into_c_string)
+}
+
+/// Initializes the engine from the default configuration; kept for the
+/// system extension, new embedders use flux_init.
+#[no_mangle]
+pub extern "C" fn flux_defense_init() {
+    let code = unsafe { flux_init(std::ptr::null()) };
+    if code != FLUX_OK && code != FLUX_ERR_ALREADY_INITIALIZED {
+        error!("Failed to initialize FluxDefense from Swift extension");
+    }
+}
+
+/// Releases the engine; kept for the system extension, see flux_shutdown.
 #[no_mangle]
 pub extern "C" fn flux_defense_cleanup() {
     info!("Cleaning up FluxDefense");
-    unsafe {
-        FLUX_DEFENSE = None;
-    }
+    flux_shutdown();
 }
 
-// Evaluate a file system event and return whether it should be allowed
+/// Evaluates a file system event and returns whether it should be allowed.
+///
+/// # Safety
+/// `path_ptr` must be null or a NUL-terminated string.
 #[no_mangle]
-pub extern "C" fn flux_defense_evaluate_file_event(
+pub unsafe extern "C" fn flux_defense_evaluate_file_event(
     event_type: c_int,
     pid: u32,
     path_ptr: *const c_char,
 ) -> bool {
-    if path_ptr.is_null() {
-        warn!("Received null path pointer");
-        return false;
-    }
-    
-    let path_cstr = unsafe { CStr::from_ptr(path_ptr) };
-    let path_str = match path_cstr.to_str() {
-        Ok(s) => s,
-        Err(_) => {
-            warn!("Invalid UTF-8 in path");
-            return false;
-        }
+    let path = match read_str(path_ptr, "path") {
+        Ok(path) => Path::new(path),
+        Err(_) => return false,
     };
-    
-    let path = PathBuf::from(path_str);
-    
+
     info!("Evaluating file event: type={}, pid={}, path={:?}", event_type, pid, path);
-    
-    unsafe {
-        if let Some(ref defense) = FLUX_DEFENSE {
-            // Use the monitor to handle the event if available
-            if let Some(_monitor) = defense.get_monitor() {
-                let verdict = defense.simulate_file_execution(
-                    PathBuf::from(format!("PID:{}", pid)),
-                    path.clone()
-                );
-                match verdict {
-                    crate::monitor::Verdict::Allow | crate::monitor::Verdict::Log => true,
-                    crate::monitor::Verdict::Deny => false,
-                }
-            } else {
-                // Fallback to simple file policy check
-                defense.file_policy.is_path_allowed(&path)
-            }
-        } else {
-            warn!("FluxDefense not initialized, denying by default");
-            false
+
+    let guard = engine();
+    let Some(ref engine) = *guard else {
+        warn!("FluxDefense not initialized, denying by default");
+        return false;
+    };
+    let defense = &engine.defense;
+    // Use the monitor to handle the event if available
+    if defense.get_monitor().is_some() {
+        let verdict = defense.simulate_file_execution(
+            PathBuf::from(format!("PID:{}", pid)),
+            path.to_path_buf(),
+        );
+        match verdict {
+            crate::monitor::Verdict::Allow | crate::monitor::Verdict::Log => true,
+            crate::monitor::Verdict::Deny => false,
         }
+    } else {
+        // Fallback to simple file policy check
+        defense.file_policy.is_path_allowed(path)
     }
 }
 
-// Evaluate a network connection and return whether it should be allowed
+/// Evaluates a network connection and returns whether it should be allowed.
+///
+/// # Safety
+/// `remote_ip_ptr` must be null or a NUL-terminated string, and so must
+/// `domain_ptr`.
 #[no_mangle]
-pub extern "C" fn flux_defense_evaluate_network_connection(
+pub unsafe extern "C" fn flux_defense_evaluate_network_connection(
     remote_ip_ptr: *const c_char,
     remote_port: u16,
     domain_ptr: *const c_char,
 ) -> bool {
-    if remote_ip_ptr.is_null() {
-        warn!("Received null remote IP pointer");
-        return false;
-    }
-    
-    let ip_cstr = unsafe { CStr::from_ptr(remote_ip_ptr) };
-    let ip_str = match ip_cstr.to_str() {
-        Ok(s) => s,
-        Err(_) => {
-            warn!("Invalid UTF-8 in IP address");
-            return false;
-        }
+    let ip_str = match read_str(remote_ip_ptr, "remote IP") {
+        Ok(ip) => ip,
+        Err(_) => return false,
     };
-    
+
     let remote_ip = match ip_str.parse() {
         Ok(ip) => ip,
         Err(_) => {
@@ -117,79 +373,130 @@ pub extern "C" fn flux_defense_evaluate_network_connection(
             return false;
         }
     };
-    
+
     let domain = if domain_ptr.is_null() {
         None
     } else {
-        let domain_cstr = unsafe { CStr::from_ptr(domain_ptr) };
-        match domain_cstr.to_str() {
-            Ok(s) => Some(s),
-            Err(_) => {
-                warn!("Invalid UTF-8 in domain");
-                None
-            }
-        }
+        read_str(domain_ptr, "domain").ok()
     };
-    
-    info!("Evaluating network connection: ip={}, port={}, domain={:?}", 
+
+    info!("Evaluating network connection: ip={}, port={}, domain={:?}",
           remote_ip, remote_port, domain);
-    
-    unsafe {
-        if let Some(ref defense) = FLUX_DEFENSE {
-            defense.network_policy.is_connection_allowed(remote_ip, remote_port, domain)
-        } else {
+
+    match *engine() {
+        Some(ref engine) => engine.defense.network_policy.is_connection_allowed(remote_ip, remote_port, domain),
+        None => {
             warn!("FluxDefense not initialized, denying by default");
             false
         }
     }
 }
 
-// Get the current file policy as JSON
+/// The current file policy as JSON, or null; free it with
+/// flux_defense_free_string.
 #[no_mangle]
 pub extern "C" fn flux_defense_get_file_policy() -> *mut c_char {
-    unsafe {
-        if let Some(ref defense) = FLUX_DEFENSE {
-            match serde_json::to_string(&defense.file_policy) {
-                Ok(json) => {
-                    match CString::new(json) {
-                        Ok(cstring) => cstring.into_raw(),
-                        Err(_) => std::ptr::null_mut(),
-                    }
-                }
-                Err(_) => std::ptr::null_mut(),
-            }
-        } else {
-            std::ptr::null_mut()
-        }
+    match *engine() {
+        Some(ref engine) => serde_json::to_string(&engine.defense.file_policy)
+            .map_or(std::ptr::null_mut(), into_c_string),
+        None => std::ptr::null_mut(),
     }
 }
 
-// Get the current network policy as JSON
+/// The current network policy as JSON, or null; free it with
+/// flux_defense_free_string.
 #[no_mangle]
 pub extern "C" fn flux_defense_get_network_policy() -> *mut c_char {
-    unsafe {
-        if let Some(ref defense) = FLUX_DEFENSE {
-            match serde_json::to_string(&defense.network_policy) {
-                Ok(json) => {
-                    match CString::new(json) {
-                        Ok(cstring) => cstring.into_raw(),
-                        Err(_) => std::ptr::null_mut(),
-                    }
-                }
-                Err(_) => std::ptr::null_mut(),
-            }
-        } else {
-            std::ptr::null_mut()
-        }
+    match *engine() {
+        Some(ref engine) => serde_json::to_string(&engine.defense.network_policy)
+            .map_or(std::ptr::null_mut(), into_c_string),
+        None => std::ptr::null_mut(),
     }
 }
 
-// Free a string allocated by Rust
+/// Frees a string returned by this library.
+///
+/// # Safety
+/// `s` must be null or a string returned by this library, not yet freed.
 #[no_mangle]
-pub extern "C" fn flux_defense_free_string(s: *mut c_char) {
+pub unsafe extern "C" fn flux_defense_free_string(s: *mut c_char) {
     if !s.is_null() {
-        unsafe {
-            let _ = CString::from_raw(s);
-        }
+        let _ = CString::from_raw(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    extern "C" fn count_event(event_json: *const c_char, user_data: *mut c_void) {
+        let json = unsafe { CStr::from_ptr(event_json) }.to_str().unwrap();
+        assert!(json.starts_with('{'));
+        let count = unsafe { &*(user_data as *const AtomicUsize) };
+        count.fetch_add(1, Ordering::SeqCst);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_c_api_lifecycle() {
+        assert_eq!(flux_api_version(), FLUX_API_VERSION);
+        assert_eq!(flux_start(), FLUX_ERR_NOT_INITIALIZED);
+        let error = flux_last_error();
+        assert!(unsafe { CStr::from_ptr(error) }.to_str().unwrap().contains("not initialized"));
+        unsafe { flux_defense_free_string(error) };
+
+        let config = std::env::temp_dir().join(format!("flux-ffi-{}.json", std::process::id()));
+        std::fs::write(&config, "{ not json").unwrap();
+        let config_path = CString::new(config.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { flux_init(config_path.as_ptr()) }, FLUX_ERR_INTERNAL);
+        Config::default().save_to_file(&config).unwrap();
+        assert_eq!(unsafe { flux_init(config_path.as_ptr()) }, FLUX_OK);
+        assert_eq!(unsafe { flux_init(config_path.as_ptr()) }, FLUX_ERR_ALREADY_INITIALIZED);
+        std::fs::remove_file(&config).unwrap();
+
+        let count = Box::leak(Box::new(AtomicUsize::new(0)));
+        let subscription = flux_subscribe_events(Some(count_event), count as *mut AtomicUsize as *mut c_void);
+        assert!(subscription > 0);
+        assert_eq!(flux_subscribe_events(None, std::ptr::null_mut()), FLUX_ERR_INVALID_ARGUMENT);
+
+        // Policies set before start are kept for it
+        let bad = CString::new(r#"{"file_policy": 3}"#).unwrap();
+        assert_eq!(unsafe { flux_set_policy_json(bad.as_ptr()) }, FLUX_ERR_INVALID_ARGUMENT);
+        assert_eq!(unsafe { flux_set_policy_json(std::ptr::null()) }, FLUX_ERR_INVALID_ARGUMENT);
+        let network = serde_json::json!({ "network_policy": crate::policy::NetworkPolicy::default() }).to_string();
+        let network = CString::new(network).unwrap();
+        assert_eq!(unsafe { flux_set_policy_json(network.as_ptr()) }, FLUX_OK);
+        assert!(engine().as_ref().unwrap().pending_policy.as_ref().unwrap().network_policy.is_some());
+
+        // Events reach subscribers as JSON
+        let event = crate::monitor::SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: crate::monitor::SecurityEventType::NetworkConnection {
+                remote_ip: "192.0.2.1".to_string(),
+                remote_port: 443,
+                domain: None,
+                protocol: crate::monitor::NetworkProtocol::Tcp,
+            },
+            process_info: crate::monitor::ProcessInfo {
+                pid: 1,
+                path: PathBuf::from("/usr/bin/curl"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: crate::monitor::Verdict::Allow,
+            policy_reason: "test".to_string(),
+        };
+        dispatch_event(&event);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(flux_unsubscribe_events(subscription), FLUX_OK);
+        assert_eq!(flux_unsubscribe_events(subscription), FLUX_ERR_INVALID_ARGUMENT);
+        dispatch_event(&event);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        assert_eq!(flux_shutdown(), FLUX_OK);
+        assert_eq!(flux_shutdown(), FLUX_ERR_NOT_INITIALIZED);
+    }
+}
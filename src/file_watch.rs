@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchEvents {
+    // Executions only, which are gated
+    Exec,
+    // Opens, reads and writes, but not executions
+    ReadWrite,
+    #[default]
+    All,
+}
+
+impl WatchEvents {
+    fn covers(self, exec: bool) -> bool {
+        match self {
+            WatchEvents::Exec => exec,
+            WatchEvents::ReadWrite => !exec,
+            WatchEvents::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchPath {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub events: WatchEvents,
+}
+
+// Which paths the file monitor watches and for what. The kernel marks whole
+// mounts, so a path that is not a mount point widens the mark to the mount
+// holding it; events outside the config are filtered before any policy work
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileWatchConfig {
+    // The longest matching prefix decides the events a path is watched for
+    #[serde(default = "default_include")]
+    pub include: Vec<WatchPath>,
+    // Never watched; permission events for them are allowed unseen
+    #[serde(default)]
+    pub exclude: Vec<PathBuf>,
+    // Also watch the included paths in other mount namespaces, e.g. containers
+    #[serde(default = "default_mount_namespaces")]
+    pub mount_namespaces: bool,
+    #[serde(default)]
+    pub permission_timeout: PermissionTimeoutConfig,
+}
+
+// Guards against a stalled decision path, which would leave every process
+// opening a watched file hanging
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionTimeoutConfig {
+    // A permission event still undecided after this is allowed
+    #[serde(default = "default_deadline_ms")]
+    pub deadline_ms: u64,
+    // This many overruns within `window_secs` drop permission events for
+    // plain notifications until restart
+    #[serde(default = "default_max_overruns")]
+    pub max_overruns: usize,
+    #[serde(default = "default_overrun_window")]
+    pub window_secs: u64,
+}
+
+fn default_deadline_ms() -> u64 {
+    1500
+}
+
+fn default_max_overruns() -> usize {
+    3
+}
+
+fn default_overrun_window() -> u64 {
+    60
+}
+
+impl Default for PermissionTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            deadline_ms: default_deadline_ms(),
+            max_overruns: default_max_overruns(),
+            window_secs: default_overrun_window(),
+        }
+    }
+}
+
+fn default_include() -> Vec<WatchPath> {
+    vec![WatchPath { path: PathBuf::from("/"), events: WatchEvents::All }]
+}
+
+fn default_mount_namespaces() -> bool {
+    true
+}
+
+impl Default for FileWatchConfig {
+    fn default() -> Self {
+        Self {
+            include: default_include(),
+            exclude: Vec::new(),
+            mount_namespaces: default_mount_namespaces(),
+            permission_timeout: PermissionTimeoutConfig::default(),
+        }
+    }
+}
+
+impl FileWatchConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.include.is_empty() {
+            return Err(anyhow!("file_watch.include must name at least one path"));
+        }
+        let mut paths = self.include.iter().map(|include| &include.path).chain(&self.exclude);
+        if let Some(path) = paths.find(|path| !path.is_absolute()) {
+            return Err(anyhow!("file_watch path {:?} is not absolute", path));
+        }
+        if self.permission_timeout.deadline_ms == 0 || self.permission_timeout.max_overruns == 0 {
+            return Err(anyhow!("file_watch.permission_timeout needs a positive deadline_ms and max_overruns"));
+        }
+        Ok(())
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|excluded| path.starts_with(excluded))
+    }
+
+    // The events `path` is watched for, if any
+    pub fn events_for(&self, path: &Path) -> Option<WatchEvents> {
+        if self.is_excluded(path) {
+            return None;
+        }
+        self.include.iter()
+            .filter(|include| path.starts_with(&include.path))
+            .max_by_key(|include| include.path.components().count())
+            .map(|include| include.events)
+    }
+
+    pub fn wants(&self, path: &Path, exec: bool) -> bool {
+        self.events_for(path).is_some_and(|events| events.covers(exec))
+    }
+}
+
+// One process in every mount namespace other than our own, found through
+// /proc; its root reaches the mounts of that namespace
+#[cfg(target_os = "linux")]
+pub fn other_mount_namespaces() -> Vec<(u64, u32)> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(own) = std::fs::metadata("/proc/self/ns/mnt").map(|metadata| metadata.ino()) else { return Vec::new() };
+    let Ok(entries) = std::fs::read_dir("/proc") else { return Vec::new() };
+    let mut namespaces = HashMap::new();
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        // Kernel threads and processes that just exited have none to read
+        let Ok(namespace) = std::fs::metadata(format!("/proc/{}/ns/mnt", pid)).map(|metadata| metadata.ino()) else { continue };
+        if namespace != own {
+            namespaces.entry(namespace).or_insert(pid);
+        }
+    }
+    namespaces.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_watch_paths() {
+        let config = FileWatchConfig {
+            include: vec![
+                WatchPath { path: PathBuf::from("/"), events: WatchEvents::Exec },
+                WatchPath { path: PathBuf::from("/home"), events: WatchEvents::All },
+                WatchPath { path: PathBuf::from("/etc"), events: WatchEvents::ReadWrite },
+            ],
+            exclude: vec![PathBuf::from("/var/cache"), PathBuf::from("/home/build/target")],
+            mount_namespaces: true,
+            permission_timeout: PermissionTimeoutConfig::default(),
+        };
+        config.validate().unwrap();
+
+        assert!(config.wants(Path::new("/usr/bin/ls"), true));
+        assert!(!config.wants(Path::new("/usr/lib/libc.so.6"), false));
+        assert!(config.wants(Path::new("/home/user/notes"), false));
+        assert!(!config.wants(Path::new("/etc/passwd"), true));
+        assert!(config.wants(Path::new("/etc/passwd"), false));
+        assert!(!config.wants(Path::new("/var/cache/apt/pkgcache.bin"), true));
+        assert!(!config.wants(Path::new("/home/build/target/debug/app"), true));
+        // A prefix matches whole components only
+        assert!(config.wants(Path::new("/var/cachefiles/x"), true));
+
+        let relative = FileWatchConfig { exclude: vec![PathBuf::from("tmp")], ..FileWatchConfig::default() };
+        assert!(relative.validate().is_err());
+    }
+}
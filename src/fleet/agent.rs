@@ -0,0 +1,419 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use chrono::Utc;
+use anyhow::{anyhow, Result, Context};
+use reqwest::StatusCode;
+use tracing::{info, warn, debug, error};
+
+use crate::api::models::ApiResponse;
+use crate::monitor::SecurityEvent;
+use crate::system_metrics::SystemMetricsCollector;
+use super::identity::HostIdentity;
+use super::protocol::*;
+
+// Upper bound for the retry delay while the server is unreachable
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetAgentConfig {
+    pub server_url: String,
+    // Only needed for the first enrollment; the issued credentials are kept in state_path
+    #[serde(default)]
+    pub enrollment_token: Option<String>,
+    #[serde(default = "default_state_path")]
+    pub state_path: PathBuf,
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval_secs: u64,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    // Events queued while the server is unreachable; the oldest are dropped first
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+    // PEM CA bundle for servers using a private CA
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+    // PEM client certificate and key for servers requiring mutual TLS
+    #[serde(default)]
+    pub client_cert_path: Option<PathBuf>,
+    #[serde(default)]
+    pub client_key_path: Option<PathBuf>,
+}
+
+fn default_state_path() -> PathBuf {
+    PathBuf::from("/var/lib/fluxdefense/fleet-agent.json")
+}
+
+fn default_heartbeat_interval() -> u64 {
+    30
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_max_queue() -> usize {
+    10_000
+}
+
+impl FleetAgentConfig {
+    pub fn new(server_url: String, enrollment_token: Option<String>) -> Self {
+        Self {
+            server_url,
+            enrollment_token,
+            state_path: default_state_path(),
+            heartbeat_interval_secs: default_heartbeat_interval(),
+            batch_size: default_batch_size(),
+            max_queue: default_max_queue(),
+            ca_cert_path: None,
+            client_cert_path: None,
+            client_key_path: None,
+        }
+    }
+}
+
+// Credentials issued by the server at enrollment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentState {
+    pub agent_id: String,
+    pub agent_secret: String,
+    pub server_url: String,
+    pub host_id: String,
+    #[serde(default)]
+    pub policy_version: u64,
+}
+
+impl AgentState {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fleet agent state {:?}", path))?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(self)?;
+
+        // The file holds the agent secret
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+            use std::os::unix::fs::OpenOptionsExt;
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(path)?;
+            file.write_all(json.as_bytes())?;
+        }
+        #[cfg(not(unix))]
+        std::fs::write(path, json)?;
+
+        Ok(())
+    }
+}
+
+// Streams events and heartbeats to a fleet server and applies the policies it pushes back
+#[derive(Clone)]
+pub struct FleetAgent {
+    config: FleetAgentConfig,
+    client: reqwest::Client,
+    state: Arc<RwLock<Option<AgentState>>>,
+    queue: Arc<Mutex<VecDeque<SecurityEvent>>>,
+    dropped_events: Arc<AtomicU64>,
+    next_sequence: Arc<AtomicU64>,
+    running: Arc<Mutex<bool>>,
+    started_at: Instant,
+    policy_handler: Arc<dyn Fn(PolicyUpdate) -> Result<()> + Send + Sync>,
+}
+
+impl FleetAgent {
+    pub fn new<F>(config: FleetAgentConfig, policy_handler: F) -> Result<Self>
+    where
+        F: Fn(PolicyUpdate) -> Result<()> + Send + Sync + 'static,
+    {
+        let client = Self::build_client(&config)?;
+
+        // Credentials issued by a different server are useless
+        let state = AgentState::load(&config.state_path)?
+            .filter(|state| state.server_url == config.server_url);
+
+        Ok(Self {
+            config,
+            client,
+            state: Arc::new(RwLock::new(state)),
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            dropped_events: Arc::new(AtomicU64::new(0)),
+            // Seeded from the clock so sequences keep increasing across restarts
+            next_sequence: Arc::new(AtomicU64::new(Utc::now().timestamp_millis() as u64)),
+            running: Arc::new(Mutex::new(false)),
+            started_at: Instant::now(),
+            policy_handler: Arc::new(policy_handler),
+        })
+    }
+
+    fn build_client(config: &FleetAgentConfig) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("fluxdefense-agent/", env!("CARGO_PKG_VERSION")));
+
+        if let Some(ref path) = config.ca_cert_path {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read fleet CA certificate {:?}", path))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        match (&config.client_cert_path, &config.client_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                let mut pem = std::fs::read(cert_path)
+                    .with_context(|| format!("Failed to read fleet client certificate {:?}", cert_path))?;
+                pem.extend(std::fs::read(key_path)
+                    .with_context(|| format!("Failed to read fleet client key {:?}", key_path))?);
+                builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+            }
+            (None, None) => {}
+            _ => return Err(anyhow!("Fleet client certificate and key must be configured together")),
+        }
+
+        Ok(builder.build()?)
+    }
+
+    pub fn is_enrolled(&self) -> bool {
+        self.state.read().map(|s| s.is_some()).unwrap_or(false)
+    }
+
+    pub fn agent_id(&self) -> Option<String> {
+        self.state.read().ok()?.as_ref().map(|s| s.agent_id.clone())
+    }
+
+    pub fn queued_events(&self) -> usize {
+        self.queue.lock().map(|q| q.len()).unwrap_or(0)
+    }
+
+    pub fn submit_event(&self, event: SecurityEvent) {
+        if let Ok(mut queue) = self.queue.lock() {
+            if queue.len() >= self.config.max_queue {
+                queue.pop_front();
+                self.dropped_events.fetch_add(1, Ordering::Relaxed);
+            }
+            queue.push_back(event);
+        }
+    }
+
+    pub async fn enroll(&self) -> Result<()> {
+        let token = self.config.enrollment_token.clone()
+            .ok_or_else(|| anyhow!("Fleet agent is not enrolled and no enrollment token is configured"))?;
+
+        let fallback_id = self.state.read()
+            .map_err(|_| anyhow!("Failed to acquire fleet state read lock"))?
+            .as_ref()
+            .map(|s| s.host_id.clone());
+        let identity = HostIdentity::detect(fallback_id.as_deref());
+        let host_id = identity.host_id.clone();
+
+        let request = EnrollRequest {
+            protocol_version: PROTOCOL_VERSION,
+            enrollment_token: token,
+            identity,
+        };
+
+        let response = self.client.post(self.url(ENROLL_PATH))
+            .json(&request)
+            .send()
+            .await?;
+        let enrolled: EnrollResponse = Self::read_response(response).await?;
+
+        let state = AgentState {
+            agent_id: enrolled.agent_id,
+            agent_secret: enrolled.agent_secret,
+            server_url: self.config.server_url.clone(),
+            host_id,
+            policy_version: 0,
+        };
+        state.save(&self.config.state_path)?;
+        info!("Enrolled with fleet server {} as agent {}", self.config.server_url, state.agent_id);
+
+        *self.state.write().map_err(|_| anyhow!("Failed to acquire fleet state write lock"))? = Some(state);
+        Ok(())
+    }
+
+    pub fn start(&self) -> tokio::task::JoinHandle<()> {
+        *self.running.lock().unwrap() = true;
+        let agent = self.clone();
+
+        tokio::spawn(async move {
+            info!("Fleet agent started for server {}", agent.config.server_url);
+
+            let mut metrics_collector = SystemMetricsCollector::new();
+            let mut interval = Duration::from_secs(agent.config.heartbeat_interval_secs.max(1));
+            let mut in_flight: Option<EventBatch> = None;
+            let mut failures: u32 = 0;
+
+            while *agent.running.lock().unwrap() {
+                match agent.run_once(&mut metrics_collector, &mut in_flight).await {
+                    Ok(server_interval) => {
+                        failures = 0;
+                        if let Some(secs) = server_interval {
+                            interval = Duration::from_secs(secs.max(1));
+                        }
+                    }
+                    Err(e) => {
+                        failures = failures.saturating_add(1);
+                        warn!("Fleet server communication failed (attempt {}): {:#}", failures, e);
+                    }
+                }
+
+                let delay = if failures == 0 {
+                    interval
+                } else {
+                    interval.saturating_mul(1 << failures.min(8)).min(MAX_BACKOFF)
+                };
+                tokio::time::sleep(delay).await;
+            }
+
+            info!("Fleet agent stopped");
+        })
+    }
+
+    pub fn stop(&self) {
+        *self.running.lock().unwrap() = false;
+    }
+
+    // One heartbeat plus as many event batches as are queued. Returns the
+    // heartbeat interval requested by the server.
+    async fn run_once(
+        &self,
+        metrics_collector: &mut SystemMetricsCollector,
+        in_flight: &mut Option<EventBatch>,
+    ) -> Result<Option<u64>> {
+        if !self.is_enrolled() {
+            self.enroll().await?;
+        }
+
+        let heartbeat = Heartbeat {
+            protocol_version: PROTOCOL_VERSION,
+            sent_at: Utc::now(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            policy_version: self.current_policy_version(),
+            queued_events: self.queued_events(),
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            metrics: metrics_collector.collect_metrics().ok(),
+        };
+
+        let response: HeartbeatResponse = self.post_authenticated(HEARTBEAT_PATH, &heartbeat).await?;
+        if let Some(update) = response.policy {
+            self.apply_policy(update);
+        }
+
+        loop {
+            if in_flight.is_none() {
+                *in_flight = self.next_batch();
+            }
+            let Some(batch) = in_flight.as_ref() else { break };
+
+            let ack: EventBatchAck = self.post_authenticated(EVENTS_PATH, batch).await?;
+            if ack.duplicate {
+                debug!("Fleet server already had event batch {}", ack.sequence);
+            }
+            *in_flight = None;
+        }
+
+        Ok(Some(response.heartbeat_interval_secs))
+    }
+
+    fn next_batch(&self) -> Option<EventBatch> {
+        let mut queue = self.queue.lock().ok()?;
+        if queue.is_empty() {
+            return None;
+        }
+        let count = queue.len().min(self.config.batch_size.max(1));
+        let events: Vec<SecurityEvent> = queue.drain(..count).collect();
+
+        Some(EventBatch {
+            protocol_version: PROTOCOL_VERSION,
+            sequence: self.next_sequence.fetch_add(1, Ordering::Relaxed),
+            events,
+        })
+    }
+
+    fn apply_policy(&self, update: PolicyUpdate) {
+        let version = update.version;
+        if version <= self.current_policy_version() {
+            return;
+        }
+
+        match (self.policy_handler)(update) {
+            Ok(()) => {
+                info!("Applied fleet policy version {}", version);
+                if let Ok(mut state) = self.state.write() {
+                    if let Some(state) = state.as_mut() {
+                        state.policy_version = version;
+                        if let Err(e) = state.save(&self.config.state_path) {
+                            warn!("Failed to persist fleet agent state: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => error!("Failed to apply fleet policy version {}: {:#}", version, e),
+        }
+    }
+
+    fn current_policy_version(&self) -> u64 {
+        self.state.read().ok()
+            .and_then(|s| s.as_ref().map(|s| s.policy_version))
+            .unwrap_or(0)
+    }
+
+    async fn post_authenticated<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let authorization = {
+            let state = self.state.read().map_err(|_| anyhow!("Failed to acquire fleet state read lock"))?;
+            let state = state.as_ref().ok_or_else(|| anyhow!("Fleet agent is not enrolled"))?;
+            bearer_token(&state.agent_id, &state.agent_secret)
+        };
+
+        let response = self.client.post(self.url(path))
+            .header(reqwest::header::AUTHORIZATION, authorization)
+            .json(body)
+            .send()
+            .await?;
+
+        // The server forgot us (agent removed or state reset); enroll again on the next round
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Ok(mut state) = self.state.write() {
+                *state = None;
+            }
+            return Err(anyhow!("Fleet server rejected agent credentials"));
+        }
+
+        Self::read_response(response).await
+    }
+
+    async fn read_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Fleet server returned {}", status));
+        }
+
+        let body: ApiResponse<T> = response.json().await?;
+        match body.data {
+            Some(data) if body.success => Ok(data),
+            _ => Err(anyhow!("Fleet server error: {}", body.error.unwrap_or_else(|| "no data".to_string()))),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.server_url.trim_end_matches('/'), path)
+    }
+}
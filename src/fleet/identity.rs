@@ -0,0 +1,46 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sysinfo::System;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostIdentity {
+    // Stable across reboots and re-enrollment
+    pub host_id: String,
+    pub hostname: String,
+    pub os: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub architecture: String,
+    pub agent_version: String,
+}
+
+impl HostIdentity {
+    // `fallback_id` is used when the platform offers no machine identifier
+    pub fn detect(fallback_id: Option<&str>) -> Self {
+        Self {
+            host_id: Self::machine_host_id()
+                .or_else(|| fallback_id.map(|id| id.to_string()))
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            os: System::name().unwrap_or_else(|| std::env::consts::OS.to_string()),
+            os_version: System::long_os_version().unwrap_or_default(),
+            kernel_version: System::kernel_version().unwrap_or_default(),
+            architecture: std::env::consts::ARCH.to_string(),
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    // machine-id must not be exposed directly, so derive an application-specific id from it
+    fn machine_host_id() -> Option<String> {
+        let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())?;
+
+        let digest = Sha256::digest(format!("fluxdefense-fleet:{}", machine_id).as_bytes());
+        Some(hex::encode(&digest[..16]))
+    }
+}
@@ -0,0 +1,9 @@
+pub mod protocol;
+pub mod identity;
+pub mod agent;
+pub mod server;
+
+pub use protocol::{PolicyUpdate, PROTOCOL_VERSION};
+pub use identity::HostIdentity;
+pub use agent::{FleetAgent, FleetAgentConfig, AgentState};
+pub use server::{FleetServer, AgentSummary, FleetEvent};
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+use crate::monitor::SecurityEvent;
+use crate::policy::{CandidatePolicy, SignedPolicy};
+use crate::system_metrics::SystemMetrics;
+use super::identity::HostIdentity;
+
+// Bumped on incompatible changes; the server rejects agents speaking a different version
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Endpoint paths, relative to the server URL
+pub const ENROLL_PATH: &str = "/api/fleet/enroll";
+pub const HEARTBEAT_PATH: &str = "/api/fleet/heartbeat";
+pub const EVENTS_PATH: &str = "/api/fleet/events";
+
+// Agents authenticate after enrollment with `Authorization: Bearer <agent_id>:<secret>`
+pub fn bearer_token(agent_id: &str, secret: &str) -> String {
+    format!("Bearer {}:{}", agent_id, secret)
+}
+
+pub fn parse_bearer_token(header: &str) -> Option<(&str, &str)> {
+    header.strip_prefix("Bearer ")?.split_once(':')
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollRequest {
+    pub protocol_version: u32,
+    pub enrollment_token: String,
+    pub identity: HostIdentity,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollResponse {
+    pub agent_id: String,
+    // Shown once; the server only keeps a hash
+    pub agent_secret: String,
+    pub heartbeat_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub protocol_version: u32,
+    pub sent_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub policy_version: u64,
+    pub queued_events: usize,
+    pub dropped_events: u64,
+    pub metrics: Option<SystemMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyUpdate {
+    pub version: u64,
+    pub policy: CandidatePolicy,
+    pub published_at: DateTime<Utc>,
+    // Set when the policy only applies to one tenant's agents
+    #[serde(default)]
+    pub tenant: Option<String>,
+    // The signed files behind `policy`, for agents that require signatures
+    #[serde(default)]
+    pub signed: Option<SignedPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    pub heartbeat_interval_secs: u64,
+    // Present only when the server has a newer policy than the agent reported
+    pub policy: Option<PolicyUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventBatch {
+    pub protocol_version: u32,
+    // Monotonic per agent so retried batches can be recognised
+    pub sequence: u64,
+    pub events: Vec<SecurityEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventBatchAck {
+    pub sequence: u64,
+    pub accepted: usize,
+    pub duplicate: bool,
+}
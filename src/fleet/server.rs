@@ -0,0 +1,527 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+use anyhow::{anyhow, Result, Context};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::monitor::SecurityEvent;
+use crate::policy::{CandidatePolicy, SignedPolicy};
+use super::identity::HostIdentity;
+use super::protocol::*;
+
+const DEFAULT_MAX_EVENTS: usize = 50_000;
+const DEFAULT_HEARTBEAT_INTERVAL: u64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AgentRecord {
+    agent_id: String,
+    secret_hash: String,
+    identity: HostIdentity,
+    enrolled_at: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    #[serde(default)]
+    last_heartbeat: Option<Heartbeat>,
+    #[serde(default)]
+    events_received: u64,
+    #[serde(default)]
+    last_sequence: u64,
+    // From the enrollment token the agent joined with; None for shared tokens
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentSummary {
+    pub agent_id: String,
+    pub tenant: Option<String>,
+    pub identity: HostIdentity,
+    pub enrolled_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    // No heartbeat for three intervals
+    pub online: bool,
+    pub policy_version: u64,
+    pub events_received: u64,
+    pub last_heartbeat: Option<Heartbeat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetEvent {
+    pub agent_id: String,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub hostname: String,
+    pub received_at: DateTime<Utc>,
+    pub event: SecurityEvent,
+}
+
+// Persisted so agents stay enrolled across server restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FleetServerState {
+    agents: HashMap<String, AgentRecord>,
+    policy: Option<PolicyUpdate>,
+    #[serde(default)]
+    tenant_policies: HashMap<String, PolicyUpdate>,
+}
+
+// Central side of fleet mode: enrolls agents, aggregates their events and
+// hands out the current policy on heartbeat. Agents enrolled with a
+// tenant's token belong to that tenant, and every query can be limited to one
+pub struct FleetServer {
+    enrollment_tokens: HashSet<String>,
+    // Enrollment token -> tenant
+    tenant_tokens: HashMap<String, String>,
+    heartbeat_interval_secs: u64,
+    max_events: usize,
+    state_path: Option<PathBuf>,
+    agents: RwLock<HashMap<String, AgentRecord>>,
+    policy: RwLock<Option<PolicyUpdate>>,
+    // Replace the shared policy for the tenant's agents
+    tenant_policies: RwLock<HashMap<String, PolicyUpdate>>,
+    events: Mutex<VecDeque<FleetEvent>>,
+}
+
+impl FleetServer {
+    pub fn new(enrollment_tokens: Vec<String>, state_path: Option<PathBuf>) -> Result<Self> {
+        let state = match &state_path {
+            Some(path) if path.exists() => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read fleet server state {:?}", path))?;
+                serde_json::from_str(&content)?
+            }
+            _ => FleetServerState::default(),
+        };
+
+        info!("Fleet server loaded {} enrolled agents", state.agents.len());
+
+        Ok(Self {
+            enrollment_tokens: enrollment_tokens.into_iter().filter(|t| !t.is_empty()).collect(),
+            tenant_tokens: HashMap::new(),
+            heartbeat_interval_secs: DEFAULT_HEARTBEAT_INTERVAL,
+            max_events: DEFAULT_MAX_EVENTS,
+            state_path,
+            agents: RwLock::new(state.agents),
+            policy: RwLock::new(state.policy),
+            tenant_policies: RwLock::new(state.tenant_policies),
+            events: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn set_heartbeat_interval(&mut self, secs: u64) {
+        self.heartbeat_interval_secs = secs.max(1);
+    }
+
+    pub fn set_max_events(&mut self, max_events: usize) {
+        self.max_events = max_events;
+    }
+
+    pub fn add_tenant_enrollment_tokens(&mut self, tenant: &str, tokens: &[String]) {
+        for token in tokens.iter().filter(|t| !t.is_empty()) {
+            self.tenant_tokens.insert(token.clone(), tenant.to_string());
+        }
+    }
+
+    pub fn enroll(&self, request: EnrollRequest) -> Result<EnrollResponse> {
+        if request.protocol_version != PROTOCOL_VERSION {
+            return Err(anyhow!("Unsupported fleet protocol version {}", request.protocol_version));
+        }
+        let tenant = if self.enrollment_tokens.contains(&request.enrollment_token) {
+            None
+        } else {
+            Some(self.tenant_tokens.get(&request.enrollment_token).ok_or_else(|| anyhow!("Invalid enrollment token"))?.clone())
+        };
+
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let now = Utc::now();
+
+        let agent_id = {
+            let mut agents = self.agents.write()
+                .map_err(|_| anyhow!("Failed to acquire fleet agents write lock"))?;
+
+            // A re-enrolling host keeps its agent id and the old secret is
+            // replaced, but only within the tenant the token belongs to: the
+            // host id is the agent's own claim, and another tenant's token
+            // must not take over the record
+            let agent_id = agents.values()
+                .find(|a| a.identity.host_id == request.identity.host_id && a.tenant == tenant)
+                .map(|a| a.agent_id.clone())
+                .unwrap_or_else(|| {
+                    if agents.values().any(|a| a.identity.host_id == request.identity.host_id) {
+                        warn!("Host {} is enrolled for another tenant; issuing a new agent id", request.identity.host_id);
+                    }
+                    Uuid::new_v4().to_string()
+                });
+
+            info!("Enrolled fleet agent {} ({}){}", agent_id, request.identity.hostname,
+                  tenant.as_ref().map(|t| format!(" for tenant {}", t)).unwrap_or_default());
+            agents.insert(agent_id.clone(), AgentRecord {
+                agent_id: agent_id.clone(),
+                secret_hash: hash_secret(&secret),
+                identity: request.identity,
+                enrolled_at: now,
+                last_seen: now,
+                last_heartbeat: None,
+                events_received: 0,
+                last_sequence: 0,
+                tenant,
+            });
+            agent_id
+        };
+
+        self.save_state();
+
+        Ok(EnrollResponse {
+            agent_id,
+            agent_secret: secret,
+            heartbeat_interval_secs: self.heartbeat_interval_secs,
+        })
+    }
+
+    // Returns the agent id for a valid `Authorization` header value
+    pub fn authenticate(&self, authorization: &str) -> Option<String> {
+        let (agent_id, secret) = parse_bearer_token(authorization)?;
+        let agents = self.agents.read().ok()?;
+        let record = agents.get(agent_id)?;
+
+        if constant_time_eq(record.secret_hash.as_bytes(), hash_secret(secret).as_bytes()) {
+            Some(agent_id.to_string())
+        } else {
+            None
+        }
+    }
+
+    pub fn heartbeat(&self, agent_id: &str, heartbeat: Heartbeat) -> Result<HeartbeatResponse> {
+        let agent_policy_version = heartbeat.policy_version;
+        let tenant = {
+            let mut agents = self.agents.write()
+                .map_err(|_| anyhow!("Failed to acquire fleet agents write lock"))?;
+            let record = agents.get_mut(agent_id)
+                .ok_or_else(|| anyhow!("Unknown fleet agent {}", agent_id))?;
+            record.last_seen = Utc::now();
+            record.last_heartbeat = Some(heartbeat);
+            record.tenant.clone()
+        };
+
+        // A tenant's own policy wins over the shared one
+        let tenant_policy = match tenant {
+            Some(ref tenant) => self.policy(Some(tenant)),
+            None => None,
+        };
+        let policy = tenant_policy.or_else(|| self.policy(None))
+            .filter(|p| p.version > agent_policy_version);
+
+        Ok(HeartbeatResponse {
+            heartbeat_interval_secs: self.heartbeat_interval_secs,
+            policy,
+        })
+    }
+
+    pub fn ingest(&self, agent_id: &str, batch: EventBatch) -> Result<EventBatchAck> {
+        let (hostname, tenant) = {
+            let mut agents = self.agents.write()
+                .map_err(|_| anyhow!("Failed to acquire fleet agents write lock"))?;
+            let record = agents.get_mut(agent_id)
+                .ok_or_else(|| anyhow!("Unknown fleet agent {}", agent_id))?;
+
+            // Retried batch whose acknowledgement was lost
+            if batch.sequence <= record.last_sequence {
+                return Ok(EventBatchAck { sequence: batch.sequence, accepted: 0, duplicate: true });
+            }
+
+            record.last_sequence = batch.sequence;
+            record.last_seen = Utc::now();
+            record.events_received += batch.events.len() as u64;
+            (record.identity.hostname.clone(), record.tenant.clone())
+        };
+
+        let accepted = batch.events.len();
+        let received_at = Utc::now();
+        let mut events = self.events.lock()
+            .map_err(|_| anyhow!("Failed to acquire fleet events lock"))?;
+        for event in batch.events {
+            events.push_back(FleetEvent {
+                agent_id: agent_id.to_string(),
+                tenant: tenant.clone(),
+                hostname: hostname.clone(),
+                received_at,
+                event,
+            });
+        }
+        while events.len() > self.max_events {
+            events.pop_front();
+        }
+
+        Ok(EventBatchAck { sequence: batch.sequence, accepted, duplicate: false })
+    }
+
+    // `tenant` limits every query to that tenant's agents; None covers all of them
+    pub fn agents(&self, tenant: Option<&str>) -> Vec<AgentSummary> {
+        let offline_after = Duration::seconds(self.heartbeat_interval_secs as i64 * 3);
+        let now = Utc::now();
+
+        let mut agents: Vec<AgentSummary> = self.agents.read()
+            .map(|agents| agents.values().filter(|record| in_scope(tenant, record.tenant.as_deref())).map(|record| AgentSummary {
+                agent_id: record.agent_id.clone(),
+                tenant: record.tenant.clone(),
+                identity: record.identity.clone(),
+                enrolled_at: record.enrolled_at,
+                last_seen: record.last_seen,
+                online: now - record.last_seen <= offline_after,
+                policy_version: record.last_heartbeat.as_ref().map(|h| h.policy_version).unwrap_or(0),
+                events_received: record.events_received,
+                last_heartbeat: record.last_heartbeat.clone(),
+            }).collect())
+            .unwrap_or_default();
+
+        agents.sort_by(|a, b| a.identity.hostname.cmp(&b.identity.hostname));
+        agents
+    }
+
+    pub fn agent(&self, tenant: Option<&str>, agent_id: &str) -> Option<AgentSummary> {
+        self.agents(tenant).into_iter().find(|a| a.agent_id == agent_id)
+    }
+
+    pub fn tenant_of(&self, agent_id: &str) -> Option<String> {
+        self.agents.read().ok()?.get(agent_id)?.tenant.clone()
+    }
+
+    pub fn remove_agent(&self, tenant: Option<&str>, agent_id: &str) -> bool {
+        let removed = self.agents.write()
+            .map(|mut agents| {
+                let owned = agents.get(agent_id).is_some_and(|record| in_scope(tenant, record.tenant.as_deref()));
+                owned && agents.remove(agent_id).is_some()
+            })
+            .unwrap_or(false);
+        if removed {
+            self.save_state();
+        }
+        removed
+    }
+
+    // Most recent events first; `host` matches either the agent id or the hostname
+    pub fn events(&self, tenant: Option<&str>, host: Option<&str>, limit: usize) -> Vec<FleetEvent> {
+        let events = match self.events.lock() {
+            Ok(events) => events,
+            Err(_) => return Vec::new(),
+        };
+
+        events.iter()
+            .rev()
+            .filter(|e| in_scope(tenant, e.tenant.as_deref()))
+            .filter(|e| host.is_none_or(|h| e.agent_id == h || e.hostname == h))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    // The shared policy, or the one published for `tenant`
+    pub fn policy(&self, tenant: Option<&str>) -> Option<PolicyUpdate> {
+        match tenant {
+            Some(tenant) => self.tenant_policies.read().ok()?.get(tenant).cloned(),
+            None => self.policy.read().ok()?.clone(),
+        }
+    }
+
+    // Publishes a new policy version, delivered to agents on their next heartbeat
+    pub fn set_policy(&self, tenant: Option<&str>, mut policy: CandidatePolicy) -> Result<PolicyUpdate> {
+        policy.validate()?;
+        self.publish(tenant, policy, None)
+    }
+
+    // Agents that require signatures verify and write the signed documents
+    // themselves; the parsed policy goes along for the others
+    pub fn set_signed_policy(&self, tenant: Option<&str>, signed: SignedPolicy) -> Result<PolicyUpdate> {
+        let policy = signed.parse()?;
+        self.publish(tenant, policy, Some(signed))
+    }
+
+    fn publish(&self, tenant: Option<&str>, policy: CandidatePolicy, signed: Option<SignedPolicy>) -> Result<PolicyUpdate> {
+        let update = {
+            let mut current = self.policy.write()
+                .map_err(|_| anyhow!("Failed to acquire fleet policy write lock"))?;
+            let mut tenant_policies = self.tenant_policies.write()
+                .map_err(|_| anyhow!("Failed to acquire fleet tenant policies write lock"))?;
+            // One sequence across all policies, so an agent moved between the
+            // shared and a tenant policy never sees the version go backwards
+            let latest = current.iter().chain(tenant_policies.values()).map(|p| p.version).max().unwrap_or(0);
+            let update = PolicyUpdate {
+                version: latest + 1,
+                policy,
+                published_at: Utc::now(),
+                tenant: tenant.map(str::to_string),
+                signed,
+            };
+            match tenant {
+                Some(tenant) => {
+                    tenant_policies.insert(tenant.to_string(), update.clone());
+                }
+                None => *current = Some(update.clone()),
+            }
+            update
+        };
+
+        info!("Published fleet policy version {}{}", update.version,
+              tenant.map(|t| format!(" for tenant {}", t)).unwrap_or_default());
+        self.save_state();
+        Ok(update)
+    }
+
+    fn save_state(&self) {
+        let Some(path) = &self.state_path else { return };
+
+        let result = (|| -> Result<()> {
+            let state = FleetServerState {
+                agents: self.agents.read().map_err(|_| anyhow!("Failed to acquire fleet agents read lock"))?.clone(),
+                policy: self.policy.read().map_err(|_| anyhow!("Failed to acquire fleet policy read lock"))?.clone(),
+                tenant_policies: self.tenant_policies.read().map_err(|_| anyhow!("Failed to acquire fleet tenant policies read lock"))?.clone(),
+            };
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, serde_json::to_string_pretty(&state)?)?;
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            warn!("Failed to save fleet server state to {:?}: {}", path, e);
+        }
+    }
+}
+
+fn in_scope(tenant: Option<&str>, owner: Option<&str>) -> bool {
+    tenant.is_none_or(|tenant| owner == Some(tenant))
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{ProcessInfo, SecurityEventType, Verdict};
+    use crate::policy::FilePolicy;
+
+    fn identity(host_id: &str) -> HostIdentity {
+        HostIdentity {
+            host_id: host_id.to_string(),
+            hostname: "web-01".to_string(),
+            os: "Linux".to_string(),
+            os_version: String::new(),
+            kernel_version: String::new(),
+            architecture: "x86_64".to_string(),
+            agent_version: "0.1.0".to_string(),
+        }
+    }
+
+    fn heartbeat(policy_version: u64) -> Heartbeat {
+        Heartbeat {
+            protocol_version: PROTOCOL_VERSION,
+            sent_at: Utc::now(),
+            uptime_secs: 10,
+            policy_version,
+            queued_events: 0,
+            dropped_events: 0,
+            metrics: None,
+        }
+    }
+
+    #[test]
+    fn test_enroll_heartbeat_and_ingest() {
+        let server = FleetServer::new(vec!["join-me".to_string()], None).unwrap();
+
+        let bad = EnrollRequest { protocol_version: PROTOCOL_VERSION, enrollment_token: "nope".to_string(), identity: identity("h1") };
+        assert!(server.enroll(bad).is_err());
+
+        let request = EnrollRequest { protocol_version: PROTOCOL_VERSION, enrollment_token: "join-me".to_string(), identity: identity("h1") };
+        let enrolled = server.enroll(request.clone()).unwrap();
+        let auth = bearer_token(&enrolled.agent_id, &enrolled.agent_secret);
+        assert_eq!(server.authenticate(&auth), Some(enrolled.agent_id.clone()));
+        assert_eq!(server.authenticate(&bearer_token(&enrolled.agent_id, "wrong")), None);
+
+        // Re-enrollment keeps the id and invalidates the old secret
+        let again = server.enroll(request).unwrap();
+        assert_eq!(again.agent_id, enrolled.agent_id);
+        assert_eq!(server.authenticate(&auth), None);
+
+        assert!(server.heartbeat(&again.agent_id, heartbeat(0)).unwrap().policy.is_none());
+        server.set_policy(None, CandidatePolicy { file_policy: Some(FilePolicy::default()), network_policy: None }).unwrap();
+        let response = server.heartbeat(&again.agent_id, heartbeat(0)).unwrap();
+        assert_eq!(response.policy.map(|p| p.version), Some(1));
+        assert!(server.heartbeat(&again.agent_id, heartbeat(1)).unwrap().policy.is_none());
+
+        let event = SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::Syscall { syscall: "execve".to_string(), success: true, exit_code: None, audit_key: None },
+            process_info: ProcessInfo { pid: 1, path: PathBuf::from("/bin/true"), parent_pid: None, user_id: 0, executable_hash: None, command_line: None },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        };
+        let batch = EventBatch { protocol_version: PROTOCOL_VERSION, sequence: 5, events: vec![event] };
+        assert_eq!(server.ingest(&again.agent_id, batch.clone()).unwrap().accepted, 1);
+        assert!(server.ingest(&again.agent_id, batch).unwrap().duplicate);
+
+        assert_eq!(server.events(None, Some("web-01"), 10).len(), 1);
+        assert_eq!(server.agents(None)[0].events_received, 1);
+    }
+
+    #[test]
+    fn test_tenant_scoping() {
+        let mut server = FleetServer::new(vec!["shared".to_string()], None).unwrap();
+        server.add_tenant_enrollment_tokens("blue", &["join-blue".to_string()]);
+        server.add_tenant_enrollment_tokens("red", &["join-red".to_string()]);
+        let enroll = |token: &str, host: &str| server.enroll(EnrollRequest {
+            protocol_version: PROTOCOL_VERSION,
+            enrollment_token: token.to_string(),
+            identity: identity(host),
+        }).unwrap().agent_id;
+        let blue = enroll("join-blue", "b1");
+        let red = enroll("join-red", "r1");
+        let shared = enroll("shared", "s1");
+
+        // Claiming another tenant's host gets a new agent, leaving blue's alone
+        let claimed = enroll("join-red", "b1");
+        assert_ne!(claimed, blue);
+        assert_eq!(server.tenant_of(&blue).as_deref(), Some("blue"));
+        assert!(server.remove_agent(None, &claimed));
+
+        assert_eq!(server.agents(None).len(), 3);
+        let visible: Vec<_> = server.agents(Some("blue")).into_iter().map(|a| a.agent_id).collect();
+        assert_eq!(visible, vec![blue.clone()]);
+        assert!(server.agent(Some("red"), &blue).is_none());
+        assert!(!server.remove_agent(Some("red"), &blue));
+        assert_eq!(server.tenant_of(&red).as_deref(), Some("red"));
+
+        let event = SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::Syscall { syscall: "execve".to_string(), success: true, exit_code: None, audit_key: None },
+            process_info: ProcessInfo { pid: 1, path: PathBuf::from("/bin/true"), parent_pid: None, user_id: 0, executable_hash: None, command_line: None },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        };
+        for (sequence, agent) in [&blue, &red].iter().enumerate() {
+            server.ingest(agent, EventBatch { protocol_version: PROTOCOL_VERSION, sequence: sequence as u64 + 1, events: vec![event.clone()] }).unwrap();
+        }
+        let events = server.events(Some("red"), None, 10);
+        assert_eq!((events.len(), events[0].tenant.as_deref()), (1, Some("red")));
+
+        // Blue gets its own policy, everyone else the shared one
+        let policy = || CandidatePolicy { file_policy: Some(FilePolicy::default()), network_policy: None };
+        server.set_policy(None, policy()).unwrap();
+        server.set_policy(Some("blue"), policy()).unwrap();
+        let delivered = |agent: &str| server.heartbeat(agent, heartbeat(0)).unwrap().policy.unwrap();
+        assert_eq!((delivered(&blue).version, delivered(&blue).tenant.as_deref()), (2, Some("blue")));
+        assert_eq!((delivered(&red).version, delivered(&red).tenant), (1, None));
+        assert_eq!(delivered(&shared).version, 1);
+        assert!(server.policy(Some("red")).is_none());
+    }
+}
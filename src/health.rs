@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Starting,
+    Up,
+    // Running, but with reduced coverage, e.g. a fallback backend
+    Degraded,
+    Down,
+    // Not configured on this host; never affects health or readiness
+    Disabled,
+}
+
+impl ComponentState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComponentState::Starting => "starting",
+            ComponentState::Up => "up",
+            ComponentState::Degraded => "degraded",
+            ComponentState::Down => "down",
+            ComponentState::Disabled => "disabled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentStatus {
+    pub name: String,
+    pub state: ComponentState,
+    // A critical component that is down makes the agent unhealthy and not ready
+    pub critical: bool,
+    pub detail: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverallStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+impl OverallStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverallStatus::Healthy => "healthy",
+            OverallStatus::Degraded => "degraded",
+            OverallStatus::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub status: OverallStatus,
+    pub ready: bool,
+    pub components: Vec<ComponentStatus>,
+    pub event_queue_depth: Option<usize>,
+    pub last_event_at: Option<DateTime<Utc>>,
+}
+
+type Probe = Box<dyn Fn() -> (ComponentState, String) + Send + Sync>;
+
+struct ProbeEntry {
+    name: String,
+    critical: bool,
+    check: Probe,
+}
+
+// Per-subsystem status for the health and readiness endpoints. Subsystems
+// either push their state when it changes (`set`) or register a probe that
+// is asked at report time.
+#[derive(Default)]
+pub struct HealthRegistry {
+    reported: RwLock<BTreeMap<String, ComponentStatus>>,
+    probes: RwLock<Vec<ProbeEntry>>,
+    queue_depth: RwLock<Option<Box<dyn Fn() -> usize + Send + Sync>>>,
+    last_event: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, name: &str, critical: bool, state: ComponentState, detail: impl Into<String>) {
+        if let Ok(mut reported) = self.reported.write() {
+            reported.insert(name.to_string(), ComponentStatus {
+                name: name.to_string(),
+                state,
+                critical,
+                detail: detail.into(),
+                updated_at: Utc::now(),
+            });
+        }
+    }
+
+    // Replaces an earlier probe of the same name
+    pub fn probe<F>(&self, name: &str, critical: bool, check: F)
+    where
+        F: Fn() -> (ComponentState, String) + Send + Sync + 'static
+    {
+        if let Ok(mut probes) = self.probes.write() {
+            probes.retain(|probe| probe.name != name);
+            probes.push(ProbeEntry { name: name.to_string(), critical, check: Box::new(check) });
+        }
+    }
+
+    // Events waiting to be handled, summed over whatever queues the host has
+    pub fn set_queue_depth<F>(&self, depth: F)
+    where
+        F: Fn() -> usize + Send + Sync + 'static
+    {
+        if let Ok(mut queue_depth) = self.queue_depth.write() {
+            *queue_depth = Some(Box::new(depth));
+        }
+    }
+
+    pub fn record_event(&self, at: DateTime<Utc>) {
+        if let Ok(mut last_event) = self.last_event.write() {
+            if last_event.is_none_or(|last| at > last) {
+                *last_event = Some(at);
+            }
+        }
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let now = Utc::now();
+        let mut components: BTreeMap<String, ComponentStatus> = self.reported.read()
+            .map(|reported| reported.clone())
+            .unwrap_or_default();
+        if let Ok(probes) = self.probes.read() {
+            for probe in probes.iter() {
+                let (state, detail) = (probe.check)();
+                components.insert(probe.name.clone(), ComponentStatus {
+                    name: probe.name.clone(),
+                    state,
+                    critical: probe.critical,
+                    detail,
+                    updated_at: now,
+                });
+            }
+        }
+        let components: Vec<ComponentStatus> = components.into_values().collect();
+
+        let critical_unavailable = components.iter()
+            .any(|c| c.critical && matches!(c.state, ComponentState::Down | ComponentState::Starting));
+        let impaired = components.iter()
+            .any(|c| matches!(c.state, ComponentState::Degraded | ComponentState::Down | ComponentState::Starting));
+        let status = if components.iter().any(|c| c.critical && c.state == ComponentState::Down) {
+            OverallStatus::Unhealthy
+        } else if impaired {
+            OverallStatus::Degraded
+        } else {
+            OverallStatus::Healthy
+        };
+
+        HealthReport {
+            status,
+            ready: !critical_unavailable,
+            components,
+            event_queue_depth: self.queue_depth.read().ok()
+                .and_then(|depth| depth.as_ref().map(|depth| depth())),
+            last_event_at: self.last_event.read().ok().and_then(|last| *last),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_readiness_follows_critical_components() {
+        let registry = HealthRegistry::new();
+        assert_eq!(registry.report().status, OverallStatus::Healthy);
+
+        let attached = Arc::new(AtomicBool::new(false));
+        let probe_attached = Arc::clone(&attached);
+        registry.probe("fanotify", true, move || match probe_attached.load(Ordering::SeqCst) {
+            true => (ComponentState::Up, "attached".to_string()),
+            false => (ComponentState::Starting, "not attached yet".to_string()),
+        });
+        registry.set("dns_proxy", false, ComponentState::Disabled, "not configured");
+        registry.set_queue_depth(|| 7);
+
+        let report = registry.report();
+        assert!(!report.ready);
+        assert_eq!(report.status, OverallStatus::Degraded);
+        assert_eq!(report.event_queue_depth, Some(7));
+
+        attached.store(true, Ordering::SeqCst);
+        registry.set("nftables", false, ComponentState::Degraded, "nft CLI fallback");
+        let report = registry.report();
+        assert!(report.ready);
+        assert_eq!(report.status, OverallStatus::Degraded);
+        assert_eq!(report.components.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+                   vec!["dns_proxy", "fanotify", "nftables"]);
+
+        registry.set("nftables", false, ComponentState::Up, "netlink");
+        let earlier = Utc::now() - chrono::Duration::seconds(5);
+        registry.record_event(Utc::now());
+        registry.record_event(earlier);
+        let report = registry.report();
+        assert_eq!(report.status, OverallStatus::Healthy);
+        assert!(report.last_event_at.unwrap() > earlier);
+
+        registry.probe("fanotify", true, || (ComponentState::Down, "fd closed".to_string()));
+        let report = registry.report();
+        assert_eq!(report.status, OverallStatus::Unhealthy);
+        assert!(!report.ready);
+    }
+}
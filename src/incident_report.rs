@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::audit_log::{AuditEntry, AuditKind};
+use crate::incidents::{describe_event, Incident, IncidentStatus, TimelineEntry, TimelineKind};
+use crate::monitor::{SecurityEvent, SecurityEventType, Verdict};
+
+// Everything known about one incident, for sharing outside the console.
+// Serialized as JSON or rendered as a single HTML file with no external
+// resources.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentReport {
+    pub generated_at: DateTime<Utc>,
+    pub incident: Incident,
+    pub events: Vec<SecurityEvent>,
+    // Roots are processes whose parent took no part in the incident
+    pub process_tree: Vec<ReportProcess>,
+    pub connections: Vec<ReportConnection>,
+    pub detections: Vec<TimelineEntry>,
+    pub responses: Vec<ReportResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportProcess {
+    pub pid: u32,
+    pub parent_pid: Option<u32>,
+    pub path: PathBuf,
+    pub command_line: Option<String>,
+    pub user_id: u32,
+    pub events: usize,
+    pub children: Vec<ReportProcess>,
+}
+
+// Connections from one process to one endpoint, folded together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportConnection {
+    pub pid: u32,
+    pub process: PathBuf,
+    pub remote_ip: String,
+    pub remote_port: u16,
+    pub domain: Option<String>,
+    pub protocol: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub count: usize,
+    pub denied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportResponse {
+    pub timestamp: DateTime<Utc>,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub detail: String,
+}
+
+impl IncidentReport {
+    // Response actions are taken from `audit` by time: those recorded from
+    // the incident's first activity until it was closed
+    pub fn build(incident: Incident, events: Vec<SecurityEvent>, audit: &[AuditEntry]) -> Self {
+        let detections = incident.timeline.iter()
+            .filter(|entry| entry.kind == TimelineKind::Correlation)
+            .cloned()
+            .collect();
+
+        let closed_at = (!incident.status.is_active()).then_some(incident.updated_at);
+        let mut responses: Vec<ReportResponse> = audit.iter()
+            .filter(|entry| entry.record.kind == AuditKind::ResponseAction
+                && entry.timestamp >= incident.first_seen
+                && closed_at.is_none_or(|closed_at| entry.timestamp <= closed_at))
+            .map(|entry| ReportResponse {
+                timestamp: entry.timestamp,
+                actor: entry.record.actor.clone(),
+                action: entry.record.action.clone(),
+                target: entry.record.target.clone(),
+                detail: entry.record.reason.clone(),
+            })
+            .collect();
+        responses.extend(incident.artifacts.iter().map(|artifact| ReportResponse {
+            timestamp: artifact.created_at,
+            actor: "fluxdefense".to_string(),
+            action: format!("collect_{}", artifact.kind),
+            target: artifact.path.display().to_string(),
+            detail: format!("{} (sha256 {})", artifact.description, artifact.sha256),
+        }));
+        responses.extend(incident.timeline.iter()
+            .filter(|entry| matches!(entry.kind, TimelineKind::StatusChange | TimelineKind::Note))
+            .map(|entry| ReportResponse {
+                timestamp: entry.timestamp,
+                actor: "analyst".to_string(),
+                action: format!("{:?}", entry.kind).to_lowercase(),
+                target: incident.id.clone(),
+                detail: entry.summary.clone(),
+            }));
+        responses.sort_by_key(|response| response.timestamp);
+
+        Self {
+            generated_at: Utc::now(),
+            process_tree: process_tree(&events),
+            connections: connections(&events),
+            detections,
+            responses,
+            events,
+            incident,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_html(&self) -> String {
+        let incident = &self.incident;
+        let mut html = String::new();
+        let _ = write!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Incident {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape(&incident.title), STYLE);
+
+        let _ = write!(html, "<h1>{}</h1>\n<p class=\"meta\"><span class=\"severity {:?}\">{:?}</span> {} &middot; incident {} &middot; generated {}</p>\n",
+            escape(&incident.title), incident.severity, incident.severity, status_label(incident.status),
+            escape(&incident.id), self.generated_at.to_rfc3339());
+        html.push_str("<table class=\"summary\">\n");
+        let entities = &incident.entities;
+        let summary = [
+            ("Host", incident.host.clone().unwrap_or_else(|| "local".to_string())),
+            ("First seen", incident.first_seen.to_rfc3339()),
+            ("Last seen", incident.last_seen.to_rfc3339()),
+            ("Events", incident.event_count.to_string()),
+            ("Rules", join(incident.correlation_rules.iter())),
+            ("Processes", join(entities.pids.iter())),
+            ("Executables", join(entities.executables.iter().map(|path| path.display()))),
+            ("Remote addresses", join(entities.remote_ips.iter())),
+            ("Files", join(entities.files.iter().map(|path| path.display()))),
+            ("Users", join(entities.users.iter())),
+        ];
+        for (label, value) in summary {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Detections</h2>\n");
+        table(&mut html, &["Time", "Severity", "Detection"], self.detections.iter().map(|entry| vec![
+            entry.timestamp.to_rfc3339(), format!("{:?}", entry.severity), entry.summary.clone(),
+        ]));
+
+        html.push_str("<h2>Timeline</h2>\n");
+        table(&mut html, &["Time", "Kind", "Severity", "Summary"], incident.timeline.iter().map(|entry| vec![
+            entry.timestamp.to_rfc3339(), format!("{:?}", entry.kind), format!("{:?}", entry.severity), entry.summary.clone(),
+        ]));
+
+        html.push_str("<h2>Process tree</h2>\n");
+        if self.process_tree.is_empty() {
+            html.push_str("<p class=\"empty\">None</p>\n");
+        } else {
+            html.push_str("<ul class=\"tree\">\n");
+            for process in &self.process_tree {
+                render_process(&mut html, process);
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("<h2>Network connections</h2>\n");
+        table(&mut html, &["Process", "Remote", "Domain", "Protocol", "Count", "First seen", "Last seen", "Verdict"],
+            self.connections.iter().map(|connection| vec![
+                format!("{} ({})", connection.process.display(), connection.pid),
+                format!("{}:{}", connection.remote_ip, connection.remote_port),
+                connection.domain.clone().unwrap_or_default(),
+                connection.protocol.clone(),
+                connection.count.to_string(),
+                connection.first_seen.to_rfc3339(),
+                connection.last_seen.to_rfc3339(),
+                if connection.denied { "denied".to_string() } else { "allowed".to_string() },
+            ]));
+
+        html.push_str("<h2>Responses</h2>\n");
+        table(&mut html, &["Time", "Actor", "Action", "Target", "Detail"], self.responses.iter().map(|response| vec![
+            response.timestamp.to_rfc3339(), response.actor.clone(), response.action.clone(),
+            response.target.clone(), response.detail.clone(),
+        ]));
+
+        html.push_str("<h2>Events</h2>\n");
+        table(&mut html, &["Time", "Process", "Event", "Verdict"], self.events.iter().map(|event| vec![
+            event.timestamp.to_rfc3339(),
+            format!("{} ({})", event.process_info.path.display(), event.process_info.pid),
+            describe_event(event),
+            format!("{:?}", event.verdict),
+        ]));
+
+        // The full report rides along for tools that want the raw data; '<'
+        // only occurs inside JSON strings, where \u003c means the same
+        let json = serde_json::to_string(self).unwrap_or_default().replace('<', "\\u003c");
+        let _ = write!(html, "<script type=\"application/json\" id=\"report-data\">{}</script>\n</body>\n</html>\n", json);
+        html
+    }
+}
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+h1{margin-bottom:.2em}h2{margin-top:1.6em;border-bottom:1px solid #ccc}\
+.meta{color:#555}table{border-collapse:collapse;width:100%;font-size:.9em}\
+th,td{border:1px solid #ddd;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#f4f4f4}.summary th{width:12em}.empty{color:#888}\
+.severity{padding:2px 8px;border-radius:4px;color:#fff;background:#888}\
+.Medium{background:#c90}.High{background:#d60}.Critical{background:#c00}\
+.tree,.tree ul{list-style:none;padding-left:1.2em}.tree code{background:#f4f4f4}";
+
+fn status_label(status: IncidentStatus) -> &'static str {
+    match status {
+        IncidentStatus::Open => "open",
+        IncidentStatus::Investigating => "investigating",
+        IncidentStatus::Resolved => "resolved",
+        IncidentStatus::FalsePositive => "false positive",
+    }
+}
+
+fn join<T: std::fmt::Display>(values: impl Iterator<Item = T>) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn table(html: &mut String, headers: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+    let rows: Vec<Vec<String>> = rows.collect();
+    if rows.is_empty() {
+        html.push_str("<p class=\"empty\">None</p>\n");
+        return;
+    }
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", header);
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{}</td>", escape(&cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_process(html: &mut String, process: &ReportProcess) {
+    let _ = write!(html, "<li><code>{}</code> pid {} uid {}, {} events",
+        escape(&process.path.display().to_string()), process.pid, process.user_id, process.events);
+    if let Some(ref command_line) = process.command_line {
+        let _ = write!(html, "<br><small>{}</small>", escape(command_line));
+    }
+    if !process.children.is_empty() {
+        html.push_str("\n<ul>\n");
+        for child in &process.children {
+            render_process(html, child);
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</li>\n");
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Parent links as reported with the events; the latest details win when a
+// pid shows up more than once
+fn process_tree(events: &[SecurityEvent]) -> Vec<ReportProcess> {
+    let mut processes: BTreeMap<u32, ReportProcess> = BTreeMap::new();
+    for event in events {
+        let info = &event.process_info;
+        let process = processes.entry(info.pid).or_insert_with(|| ReportProcess {
+            pid: info.pid,
+            parent_pid: None,
+            path: PathBuf::new(),
+            command_line: None,
+            user_id: info.user_id,
+            events: 0,
+            children: Vec::new(),
+        });
+        process.parent_pid = info.parent_pid.or(process.parent_pid);
+        process.path = info.path.clone();
+        process.command_line = info.command_line.clone().or(process.command_line.take());
+        process.user_id = info.user_id;
+        process.events += 1;
+    }
+
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut roots = Vec::new();
+    for process in processes.values() {
+        match process.parent_pid.filter(|parent| processes.contains_key(parent) && *parent != process.pid) {
+            Some(parent) => children.entry(parent).or_default().push(process.pid),
+            None => roots.push(process.pid),
+        }
+    }
+    let mut tree: Vec<ReportProcess> = roots.iter()
+        .filter_map(|pid| attach_children(*pid, &mut processes, &children))
+        .collect();
+    // A parent loop has no root; break it at its lowest pid
+    while let Some(&pid) = processes.keys().next() {
+        tree.extend(attach_children(pid, &mut processes, &children));
+    }
+    tree
+}
+
+fn attach_children(pid: u32, processes: &mut BTreeMap<u32, ReportProcess>, children: &HashMap<u32, Vec<u32>>) -> Option<ReportProcess> {
+    let mut process = processes.remove(&pid)?;
+    for child in children.get(&pid).into_iter().flatten() {
+        process.children.extend(attach_children(*child, processes, children));
+    }
+    Some(process)
+}
+
+fn connections(events: &[SecurityEvent]) -> Vec<ReportConnection> {
+    let mut connections: BTreeMap<(u32, String, u16), ReportConnection> = BTreeMap::new();
+    for event in events {
+        let SecurityEventType::NetworkConnection { remote_ip, remote_port, domain, protocol } = &event.event_type else {
+            continue;
+        };
+        let key = (event.process_info.pid, remote_ip.clone(), *remote_port);
+        let connection = connections.entry(key).or_insert_with(|| ReportConnection {
+            pid: event.process_info.pid,
+            process: event.process_info.path.clone(),
+            remote_ip: remote_ip.clone(),
+            remote_port: *remote_port,
+            domain: None,
+            protocol: format!("{:?}", protocol).to_lowercase(),
+            first_seen: event.timestamp,
+            last_seen: event.timestamp,
+            count: 0,
+            denied: false,
+        });
+        connection.domain = domain.clone().or(connection.domain.take());
+        connection.first_seen = connection.first_seen.min(event.timestamp);
+        connection.last_seen = connection.last_seen.max(event.timestamp);
+        connection.count += 1;
+        connection.denied |= matches!(event.verdict, Verdict::Deny);
+    }
+    let mut connections: Vec<ReportConnection> = connections.into_values().collect();
+    connections.sort_by_key(|connection| connection.first_seen);
+    connections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit_log::AuditRecord;
+    use crate::incidents::{CorrelationSignal, IncidentManager, IncidentSeverity};
+    use crate::monitor::{NetworkProtocol, ProcessInfo};
+
+    fn event(pid: u32, parent: u32, event_type: SecurityEventType) -> SecurityEvent {
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type,
+            process_info: ProcessInfo {
+                pid,
+                path: PathBuf::from(if pid == 10 { "/bin/bash" } else { "/usr/bin/curl" }),
+                parent_pid: Some(parent),
+                user_id: 1000,
+                executable_hash: None,
+                command_line: Some("curl http://203.0.113.5/<x>".to_string()),
+            },
+            verdict: Verdict::Deny,
+            policy_reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_incident_report() {
+        let manager = IncidentManager::default();
+        let connect = || SecurityEventType::NetworkConnection {
+            remote_ip: "203.0.113.5".to_string(),
+            remote_port: 80,
+            domain: Some("evil.example".to_string()),
+            protocol: NetworkProtocol::Tcp,
+        };
+        let exec = SecurityEventType::FileExecution { target_path: PathBuf::from("/usr/bin/curl"), file_hash: None, code_signature: None };
+        let id = manager.record_event(None, &event(10, 1, exec)).unwrap();
+        manager.record_event(None, &event(11, 10, connect()));
+        manager.record_event(None, &event(11, 10, connect()));
+        manager.record_correlation(None, CorrelationSignal {
+            rule_id: "c2".to_string(),
+            rule_name: "C2 Beacon".to_string(),
+            description: "curl beaconing".to_string(),
+            severity: IncidentSeverity::Critical,
+            detected_at: Utc::now(),
+            events: vec![event(11, 10, connect())],
+        });
+        let events = manager.events(&id);
+        assert_eq!(events.len(), 4);
+
+        let audit = vec![AuditEntry {
+            seq: 1,
+            timestamp: Utc::now(),
+            record: AuditRecord::new(AuditKind::ResponseAction, "api", "ban", "203.0.113.5", "C2"),
+            prev_hash: String::new(),
+            hash: String::new(),
+        }];
+        let report = IncidentReport::build(manager.get(&id).unwrap(), events, &audit);
+        assert_eq!(report.process_tree.len(), 1);
+        assert_eq!(report.process_tree[0].pid, 10);
+        assert_eq!(report.process_tree[0].children[0].events, 3);
+        assert_eq!(report.connections.len(), 1);
+        assert_eq!(report.connections[0].count, 3);
+        assert!(report.connections[0].denied);
+        assert_eq!(report.detections.len(), 1);
+        assert_eq!(report.responses[0].action, "ban");
+
+        let html = report.to_html();
+        assert!(html.contains("C2 Beacon") && html.contains("evil.example"));
+        assert!(html.contains("&lt;x&gt;") && !html.contains("<x>"));
+        let parsed: IncidentReport = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.events.len(), 4);
+    }
+}
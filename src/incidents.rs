@@ -0,0 +1,708 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+use anyhow::{anyhow, Result};
+use tokio::sync::broadcast;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::monitor::{SecurityEvent, SecurityEventType, Verdict};
+
+// Updated incidents are pushed to subscribers such as WebSocket clients;
+// a lagging subscriber misses intermediate versions, not the latest one
+const UPDATE_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentConfig {
+    // Events this close to an incident's activity can join it
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    // Weaker events only join existing incidents and never open one
+    #[serde(default = "default_min_severity")]
+    pub min_severity: IncidentSeverity,
+    #[serde(default = "default_max_timeline")]
+    pub max_timeline: usize,
+    #[serde(default = "default_max_incidents")]
+    pub max_incidents: usize,
+}
+
+fn default_window_secs() -> u64 {
+    900
+}
+
+fn default_min_severity() -> IncidentSeverity {
+    IncidentSeverity::Medium
+}
+
+fn default_max_timeline() -> usize {
+    500
+}
+
+fn default_max_incidents() -> usize {
+    1000
+}
+
+impl Default for IncidentConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: default_window_secs(),
+            min_severity: default_min_severity(),
+            max_timeline: default_max_timeline(),
+            max_incidents: default_max_incidents(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentSeverity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentStatus {
+    Open,
+    Investigating,
+    Resolved,
+    FalsePositive,
+}
+
+impl IncidentStatus {
+    // Closed incidents no longer absorb new activity
+    pub fn is_active(&self) -> bool {
+        matches!(self, IncidentStatus::Open | IncidentStatus::Investigating)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncidentEntities {
+    pub pids: BTreeSet<u32>,
+    pub executables: BTreeSet<PathBuf>,
+    pub remote_ips: BTreeSet<String>,
+    pub files: BTreeSet<PathBuf>,
+    pub users: BTreeSet<u32>,
+    // Parents of involved processes, so a parent's later activity joins too
+    #[serde(skip)]
+    parent_pids: BTreeSet<u32>,
+}
+
+impl IncidentEntities {
+    fn from_event(event: &SecurityEvent) -> Self {
+        let mut entities = Self::default();
+        let process = &event.process_info;
+        entities.pids.insert(process.pid);
+        entities.executables.insert(process.path.clone());
+        entities.users.insert(process.user_id);
+        if let Some(parent) = process.parent_pid {
+            entities.parent_pids.insert(parent);
+        }
+
+        match &event.event_type {
+            SecurityEventType::FileExecution { target_path, .. }
+            | SecurityEventType::FileAccess { target_path, .. } => {
+                entities.files.insert(target_path.clone());
+            }
+            SecurityEventType::NetworkConnection { remote_ip, .. } => {
+                entities.remote_ips.insert(remote_ip.clone());
+            }
+            SecurityEventType::Authentication { remote_host: Some(host), .. } => {
+                entities.remote_ips.insert(host.clone());
+            }
+            _ => {}
+        }
+        entities
+    }
+
+    // Same process tree or same remote address
+    fn overlaps(&self, other: &IncidentEntities) -> bool {
+        !self.pids.is_disjoint(&other.pids)
+            || !self.pids.is_disjoint(&other.parent_pids)
+            || !self.parent_pids.is_disjoint(&other.pids)
+            || !self.remote_ips.is_disjoint(&other.remote_ips)
+    }
+
+    fn merge(&mut self, other: IncidentEntities) {
+        self.pids.extend(other.pids);
+        self.executables.extend(other.executables);
+        self.remote_ips.extend(other.remote_ips);
+        self.files.extend(other.files);
+        self.users.extend(other.users);
+        self.parent_pids.extend(other.parent_pids);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineKind {
+    Event,
+    Correlation,
+    StatusChange,
+    Note,
+    Artifact,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub timestamp: DateTime<Utc>,
+    pub kind: TimelineKind,
+    pub severity: IncidentSeverity,
+    pub summary: String,
+    pub event_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Incident {
+    pub id: String,
+    pub title: String,
+    pub status: IncidentStatus,
+    // Highest severity of anything in the incident
+    pub severity: IncidentSeverity,
+    // Agent or host the activity came from, when aggregating a fleet
+    pub host: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub entities: IncidentEntities,
+    pub event_count: u64,
+    pub correlation_rules: BTreeSet<String>,
+    // Oldest entries are dropped past the configured limit
+    pub timeline: Vec<TimelineEntry>,
+    #[serde(default)]
+    pub artifacts: Vec<IncidentArtifact>,
+}
+
+// Evidence collected for an incident and stored on disk, e.g. a forensic
+// snapshot of a process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentArtifact {
+    pub id: String,
+    pub kind: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+    pub description: String,
+}
+
+// Called with the incident a Critical correlation was recorded in
+type CriticalHook = Arc<dyn Fn(&str, &CorrelationSignal) + Send + Sync>;
+
+// A correlation engine detection, independent of the platform engine that produced it
+#[derive(Debug, Clone)]
+pub struct CorrelationSignal {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub description: String,
+    pub severity: IncidentSeverity,
+    pub detected_at: DateTime<Utc>,
+    pub events: Vec<SecurityEvent>,
+}
+
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+impl From<crate::linux_security::event_correlation::Severity> for IncidentSeverity {
+    fn from(severity: crate::linux_security::event_correlation::Severity) -> Self {
+        use crate::linux_security::event_correlation::Severity;
+        match severity {
+            Severity::Low => IncidentSeverity::Low,
+            Severity::Medium => IncidentSeverity::Medium,
+            Severity::High => IncidentSeverity::High,
+            Severity::Critical => IncidentSeverity::Critical,
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "pcap"))]
+impl From<&crate::linux_security::CorrelatedEvent> for CorrelationSignal {
+    fn from(correlated: &crate::linux_security::CorrelatedEvent) -> Self {
+        Self {
+            rule_id: correlated.rule.id.clone(),
+            rule_name: correlated.rule.name.clone(),
+            description: correlated.description.clone(),
+            severity: correlated.severity.into(),
+            detected_at: Utc::now(),
+            events: correlated.events.clone(),
+        }
+    }
+}
+
+// Something that can open or extend an incident
+struct Observation {
+    entry: TimelineEntry,
+    entities: IncidentEntities,
+    title: String,
+    correlation_rule: Option<String>,
+    events: u64,
+    raw: Vec<SecurityEvent>,
+}
+
+pub fn event_severity(event: &SecurityEvent) -> IncidentSeverity {
+    match (&event.verdict, &event.event_type) {
+        (Verdict::Deny, _) => IncidentSeverity::High,
+        (_, SecurityEventType::Authentication { success: false, .. }) => IncidentSeverity::Medium,
+        _ => IncidentSeverity::Low,
+    }
+}
+
+// One line such as "curl connected to 203.0.113.9:443"
+pub fn describe_event(event: &SecurityEvent) -> String {
+    let process = event.process_info.path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("pid {}", event.process_info.pid));
+    let action = match &event.event_type {
+        SecurityEventType::FileExecution { target_path, .. } => format!("executed {}", target_path.display()),
+        SecurityEventType::FileAccess { target_path, access_type } => {
+            format!("{:?} access to {}", access_type, target_path.display()).to_lowercase()
+        }
+        SecurityEventType::NetworkConnection { remote_ip, remote_port, .. } => {
+            format!("connected to {}:{}", remote_ip, remote_port)
+        }
+        SecurityEventType::Authentication { user, service, success, .. } => {
+            let outcome = if *success { "succeeded" } else { "failed" };
+            format!("{} authentication for {} {}", service, user, outcome)
+        }
+        SecurityEventType::Syscall { syscall, .. } => format!("called {}", syscall),
+    };
+    match event.verdict {
+        Verdict::Deny => format!("{} {} (denied)", process, action),
+        _ => format!("{} {}", process, action),
+    }
+}
+
+// Groups security events and correlation detections that share a process
+// tree or remote address within a time window into incidents
+pub struct IncidentManager {
+    config: IncidentConfig,
+    incidents: Arc<RwLock<Vec<Incident>>>,
+    updates: broadcast::Sender<Incident>,
+    critical_hooks: RwLock<Vec<CriticalHook>>,
+    // The events behind each incident, for reports; capped like the timeline
+    // and only ever locked after `incidents`
+    events: RwLock<HashMap<String, VecDeque<SecurityEvent>>>,
+}
+
+impl IncidentManager {
+    pub fn new(config: IncidentConfig) -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_CHANNEL_CAPACITY);
+        Self {
+            config,
+            incidents: Arc::new(RwLock::new(Vec::new())),
+            updates,
+            critical_hooks: RwLock::new(Vec::new()),
+            events: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Incident> {
+        self.updates.subscribe()
+    }
+
+    // Runs on the recording thread, so slow work belongs on a queue
+    pub fn on_critical<F>(&self, hook: F)
+    where
+        F: Fn(&str, &CorrelationSignal) + Send + Sync + 'static,
+    {
+        if let Ok(mut hooks) = self.critical_hooks.write() {
+            hooks.push(Arc::new(hook));
+        }
+    }
+
+    // Returns the incident the event was added to, if any
+    pub fn record_event(&self, host: Option<&str>, event: &SecurityEvent) -> Option<String> {
+        let severity = event_severity(event);
+        let summary = describe_event(event);
+        let observation = Observation {
+            entry: TimelineEntry {
+                timestamp: event.timestamp,
+                kind: TimelineKind::Event,
+                severity,
+                summary: summary.clone(),
+                event_id: Some(event.id.clone()),
+            },
+            entities: IncidentEntities::from_event(event),
+            title: summary,
+            correlation_rule: None,
+            events: 1,
+            raw: vec![event.clone()],
+        };
+        self.observe(host, observation, severity >= self.config.min_severity)
+    }
+
+    // Correlations always open an incident when nothing related is active
+    pub fn record_correlation(&self, host: Option<&str>, signal: CorrelationSignal) -> Option<String> {
+        let mut entities = IncidentEntities::default();
+        for event in &signal.events {
+            entities.merge(IncidentEntities::from_event(event));
+        }
+        let observation = Observation {
+            entry: TimelineEntry {
+                timestamp: signal.detected_at,
+                kind: TimelineKind::Correlation,
+                severity: signal.severity,
+                summary: format!("{}: {}", signal.rule_name, signal.description),
+                event_id: None,
+            },
+            entities,
+            title: signal.rule_name.clone(),
+            correlation_rule: Some(signal.rule_id.clone()),
+            events: 0,
+            raw: signal.events.clone(),
+        };
+        let id = self.observe(host, observation, true)?;
+        if signal.severity == IncidentSeverity::Critical {
+            let hooks = self.critical_hooks.read().map(|hooks| hooks.clone()).unwrap_or_default();
+            for hook in hooks {
+                hook(&id, &signal);
+            }
+        }
+        Some(id)
+    }
+
+    fn observe(&self, host: Option<&str>, observation: Observation, may_open: bool) -> Option<String> {
+        let window = Duration::seconds(self.config.window_secs as i64);
+        let timestamp = observation.entry.timestamp;
+        let mut incidents = self.incidents.write().ok()?;
+
+        let related: Vec<usize> = incidents.iter().enumerate()
+            .filter(|(_, incident)| {
+                incident.status.is_active()
+                    && incident.host.as_deref() == host
+                    && timestamp <= incident.last_seen + window
+                    && timestamp >= incident.first_seen - window
+                    && incident.entities.overlaps(&observation.entities)
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut absorbed_ids = Vec::new();
+        let index = match related.split_first() {
+            Some((&first, rest)) => {
+                // The activity links incidents that were separate so far
+                for &other in rest.iter().rev() {
+                    let absorbed = incidents.remove(other);
+                    absorbed_ids.push(absorbed.id.clone());
+                    Self::absorb(&mut incidents[first], absorbed);
+                }
+                first
+            }
+            None if may_open => {
+                let now = Utc::now();
+                incidents.push(Incident {
+                    id: Uuid::new_v4().to_string(),
+                    title: observation.title.clone(),
+                    status: IncidentStatus::Open,
+                    severity: observation.entry.severity,
+                    host: host.map(str::to_string),
+                    created_at: now,
+                    updated_at: now,
+                    first_seen: timestamp,
+                    last_seen: timestamp,
+                    entities: IncidentEntities::default(),
+                    event_count: 0,
+                    correlation_rules: BTreeSet::new(),
+                    timeline: Vec::new(),
+                    artifacts: Vec::new(),
+                });
+                info!("Opened incident: {}", observation.title);
+                incidents.len() - 1
+            }
+            None => return None,
+        };
+
+        let incident = &mut incidents[index];
+        // A correlation describes the incident better than the event that opened it
+        if observation.correlation_rule.is_some() && incident.correlation_rules.is_empty() && incident.event_count > 0 {
+            incident.title = observation.title;
+        }
+        if let Some(rule) = observation.correlation_rule {
+            incident.correlation_rules.insert(rule);
+        }
+        incident.entities.merge(observation.entities);
+        incident.event_count += observation.events;
+        incident.severity = incident.severity.max(observation.entry.severity);
+        incident.first_seen = incident.first_seen.min(timestamp);
+        incident.last_seen = incident.last_seen.max(timestamp);
+        incident.updated_at = Utc::now();
+        incident.timeline.push(observation.entry);
+        Self::trim_timeline(incident, self.config.max_timeline);
+
+        let updated = incident.clone();
+        let evicted = Self::evict(&mut incidents, self.config.max_incidents);
+        if let Ok(mut events) = self.events.write() {
+            let mut merged = events.remove(&updated.id).unwrap_or_default();
+            for id in absorbed_ids {
+                merged.extend(events.remove(&id).unwrap_or_default());
+            }
+            for event in observation.raw {
+                if !merged.iter().any(|known| known.id == event.id) {
+                    merged.push_back(event);
+                }
+            }
+            merged.make_contiguous().sort_by_key(|event| event.timestamp);
+            while merged.len() > self.config.max_timeline {
+                merged.pop_front();
+            }
+            if !evicted.contains(&updated.id) {
+                events.insert(updated.id.clone(), merged);
+            }
+            for id in &evicted {
+                events.remove(id);
+            }
+        }
+        drop(incidents);
+
+        let id = updated.id.clone();
+        let _ = self.updates.send(updated);
+        Some(id)
+    }
+
+    fn absorb(target: &mut Incident, other: Incident) {
+        target.entities.merge(other.entities);
+        target.event_count += other.event_count;
+        target.severity = target.severity.max(other.severity);
+        target.first_seen = target.first_seen.min(other.first_seen);
+        target.last_seen = target.last_seen.max(other.last_seen);
+        target.correlation_rules.extend(other.correlation_rules);
+        target.timeline.extend(other.timeline);
+        target.timeline.sort_by_key(|entry| entry.timestamp);
+        target.artifacts.extend(other.artifacts);
+    }
+
+    fn trim_timeline(incident: &mut Incident, max_timeline: usize) {
+        if incident.timeline.len() > max_timeline {
+            let excess = incident.timeline.len() - max_timeline;
+            incident.timeline.drain(..excess);
+        }
+    }
+
+    // Closed incidents go first, then the oldest; returns the removed ids
+    fn evict(incidents: &mut Vec<Incident>, max_incidents: usize) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while incidents.len() > max_incidents {
+            let index = incidents.iter()
+                .position(|incident| !incident.status.is_active())
+                .unwrap_or(0);
+            evicted.push(incidents.remove(index).id);
+        }
+        evicted
+    }
+
+    // Newest first
+    pub fn list(&self, status: Option<IncidentStatus>) -> Vec<Incident> {
+        self.incidents.read()
+            .map(|incidents| incidents.iter().rev()
+                .filter(|incident| status.is_none_or(|status| incident.status == status))
+                .cloned()
+                .collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Incident> {
+        self.incidents.read().ok()?
+            .iter()
+            .find(|incident| incident.id == id)
+            .cloned()
+    }
+
+    pub fn update_status(&self, id: &str, status: IncidentStatus, note: Option<String>) -> Result<Incident> {
+        let updated = {
+            let mut incidents = self.incidents.write()
+                .map_err(|_| anyhow!("Failed to acquire incidents write lock"))?;
+            let incident = incidents.iter_mut()
+                .find(|incident| incident.id == id)
+                .ok_or_else(|| anyhow!("Unknown incident {}", id))?;
+
+            let now = Utc::now();
+            let severity = incident.severity;
+            incident.timeline.push(TimelineEntry {
+                timestamp: now,
+                kind: TimelineKind::StatusChange,
+                severity,
+                summary: format!("Status changed from {:?} to {:?}", incident.status, status),
+                event_id: None,
+            });
+            if let Some(note) = note.filter(|note| !note.trim().is_empty()) {
+                incident.timeline.push(TimelineEntry {
+                    timestamp: now,
+                    kind: TimelineKind::Note,
+                    severity,
+                    summary: note,
+                    event_id: None,
+                });
+            }
+            incident.status = status;
+            incident.updated_at = now;
+            Self::trim_timeline(incident, self.config.max_timeline);
+            incident.clone()
+        };
+
+        let _ = self.updates.send(updated.clone());
+        Ok(updated)
+    }
+
+    pub fn attach_artifact(&self, id: &str, artifact: IncidentArtifact) -> Result<Incident> {
+        let updated = {
+            let mut incidents = self.incidents.write()
+                .map_err(|_| anyhow!("Failed to acquire incidents write lock"))?;
+            let incident = incidents.iter_mut()
+                .find(|incident| incident.id == id)
+                .ok_or_else(|| anyhow!("Unknown incident {}", id))?;
+
+            incident.timeline.push(TimelineEntry {
+                timestamp: artifact.created_at,
+                kind: TimelineKind::Artifact,
+                severity: incident.severity,
+                summary: artifact.description.clone(),
+                event_id: None,
+            });
+            incident.artifacts.push(artifact);
+            incident.updated_at = Utc::now();
+            Self::trim_timeline(incident, self.config.max_timeline);
+            incident.clone()
+        };
+
+        let _ = self.updates.send(updated.clone());
+        Ok(updated)
+    }
+
+    // Oldest first
+    pub fn events(&self, id: &str) -> Vec<SecurityEvent> {
+        self.events.read().ok()
+            .and_then(|events| events.get(id).map(|events| events.iter().cloned().collect()))
+            .unwrap_or_default()
+    }
+
+    pub fn artifact(&self, id: &str, artifact_id: &str) -> Option<IncidentArtifact> {
+        self.get(id)?.artifacts.into_iter().find(|artifact| artifact.id == artifact_id)
+    }
+}
+
+impl Default for IncidentManager {
+    fn default() -> Self {
+        Self::new(IncidentConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{NetworkProtocol, ProcessInfo};
+
+    fn event(pid: u32, parent: Option<u32>, verdict: Verdict, remote_ip: &str) -> SecurityEvent {
+        SecurityEvent {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::NetworkConnection {
+                remote_ip: remote_ip.to_string(),
+                remote_port: 443,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            },
+            process_info: ProcessInfo {
+                pid,
+                path: PathBuf::from("/usr/bin/curl"),
+                parent_pid: parent,
+                user_id: 1000,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict,
+            policy_reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_related_activity_is_grouped() {
+        let manager = IncidentManager::default();
+
+        // Low-severity activity alone does not open an incident
+        assert!(manager.record_event(None, &event(10, Some(1), Verdict::Allow, "198.51.100.7")).is_none());
+
+        let id = manager.record_event(None, &event(10, Some(1), Verdict::Deny, "203.0.113.5")).unwrap();
+        // A child of the involved process, then an unrelated process talking to the same address
+        assert_eq!(manager.record_event(None, &event(11, Some(10), Verdict::Allow, "192.0.2.1")), Some(id.clone()));
+        assert_eq!(manager.record_event(None, &event(99, Some(1), Verdict::Allow, "203.0.113.5")), Some(id.clone()));
+        // Same pid on another host is a different incident
+        assert_ne!(manager.record_event(Some("agent-2"), &event(10, Some(1), Verdict::Deny, "203.0.113.5")), Some(id.clone()));
+
+        let incident = manager.get(&id).unwrap();
+        assert_eq!(incident.event_count, 3);
+        assert_eq!(incident.severity, IncidentSeverity::High);
+        assert_eq!(incident.entities.pids.iter().copied().collect::<Vec<_>>(), vec![10, 11, 99]);
+
+        let resolved = manager.update_status(&id, IncidentStatus::Resolved, Some("Blocked at firewall".to_string())).unwrap();
+        assert_eq!(resolved.timeline.last().unwrap().kind, TimelineKind::Note);
+        // Closed incidents stop absorbing activity
+        assert_ne!(manager.record_event(None, &event(10, Some(1), Verdict::Deny, "203.0.113.5")), Some(id));
+    }
+
+    #[test]
+    fn test_correlation_merges_incidents() {
+        let manager = IncidentManager::default();
+        let first = manager.record_event(None, &event(20, Some(1), Verdict::Deny, "203.0.113.5")).unwrap();
+        let second = manager.record_event(None, &event(30, Some(1), Verdict::Deny, "203.0.113.9")).unwrap();
+        assert_ne!(first, second);
+
+        let mut updates = manager.subscribe();
+        let merged = manager.record_correlation(None, CorrelationSignal {
+            rule_id: "port_scan".to_string(),
+            rule_name: "Port Scanning Activity".to_string(),
+            description: "2 targets scanned".to_string(),
+            severity: IncidentSeverity::Critical,
+            detected_at: Utc::now(),
+            events: vec![
+                event(20, Some(1), Verdict::Allow, "203.0.113.5"),
+                event(30, Some(1), Verdict::Allow, "203.0.113.9"),
+            ],
+        }).unwrap();
+
+        assert_eq!(merged, first);
+        assert!(manager.get(&second).is_none());
+        let incident = updates.try_recv().unwrap();
+        assert_eq!(incident.title, "Port Scanning Activity");
+        assert_eq!(incident.severity, IncidentSeverity::Critical);
+        assert_eq!(incident.event_count, 2);
+        assert_eq!(incident.timeline.len(), 3);
+    }
+
+    #[test]
+    fn test_critical_hooks_and_artifacts() {
+        let manager = Arc::new(IncidentManager::default());
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        manager.on_critical(move |id, signal| sink.write().unwrap().push((id.to_string(), signal.events.len())));
+
+        let signal = |severity| CorrelationSignal {
+            rule_id: "reverse_shell".to_string(),
+            rule_name: "Reverse Shell".to_string(),
+            description: "sh connected out".to_string(),
+            severity,
+            detected_at: Utc::now(),
+            events: vec![event(40, Some(1), Verdict::Allow, "203.0.113.40")],
+        };
+        manager.record_correlation(None, signal(IncidentSeverity::High)).unwrap();
+        assert!(seen.read().unwrap().is_empty());
+        let id = manager.record_correlation(None, signal(IncidentSeverity::Critical)).unwrap();
+        assert_eq!(*seen.read().unwrap(), vec![(id.clone(), 1)]);
+
+        let incident = manager.attach_artifact(&id, IncidentArtifact {
+            id: "a1".to_string(),
+            kind: "forensic_snapshot".to_string(),
+            path: PathBuf::from("/tmp/a1.tar.gz"),
+            size: 10,
+            sha256: String::new(),
+            created_at: Utc::now(),
+            description: "Forensic snapshot of pid 40".to_string(),
+        }).unwrap();
+        assert_eq!(incident.timeline.last().unwrap().kind, TimelineKind::Artifact);
+        assert_eq!(manager.artifact(&id, "a1").unwrap().size, 10);
+        assert!(manager.artifact(&id, "a2").is_none());
+        assert!(manager.attach_artifact("missing", manager.artifact(&id, "a1").unwrap()).is_err());
+    }
+}
@@ -6,7 +6,40 @@ pub mod ffi;
 pub mod scanner;
 pub mod monitor;
 pub mod system_metrics;
+pub mod cgroup_metrics;
 pub mod api;
+pub mod fleet;
+pub mod output;
+pub mod anomaly;
+pub mod capture;
+pub mod event_bus;
+pub mod incidents;
+pub mod incident_report;
+pub mod stix;
+pub mod misp;
+pub mod scheduler;
+pub mod sessions;
+pub mod audit_log;
+pub mod event_log;
+pub mod health;
+pub mod privileges;
+pub mod seccomp;
+pub mod systemd;
+pub mod socket_index;
+pub mod rate_limit;
+pub mod scripting;
+pub mod redaction;
+pub mod privacy;
+pub mod rule_stats;
+pub mod shadow;
+pub mod preflight;
+pub mod bsd;
+pub mod update;
+pub mod sampling;
+pub mod aggregation;
+pub mod file_watch;
+pub mod exemptions;
+pub mod setup;
 
 #[cfg(all(target_os = "linux", feature = "pcap"))]
 pub mod linux_security;
@@ -14,6 +47,7 @@ pub mod linux_security;
 use anyhow::Result;
 use tracing::{info, warn};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct FluxDefense {
     esf_client: Option<esf::EsfClient>,
@@ -21,6 +55,16 @@ pub struct FluxDefense {
     pub file_policy: policy::FilePolicy,
     pub network_policy: policy::NetworkPolicy,
     monitor: Option<monitor::PassiveMonitor>,
+    fleet_agent: Option<fleet::FleetAgent>,
+    elastic_shipper: Option<output::ElasticShipper>,
+    splunk_sink: Option<output::SplunkHecSink>,
+    behavior_baseline: Option<Arc<anomaly::BehaviorBaseline>>,
+    incidents: Arc<incidents::IncidentManager>,
+    audit_log: Option<Arc<audit_log::AuditLog>>,
+    capabilities: Option<preflight::CapabilityReport>,
+    passive_mode: bool,
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+    bsd_monitor: Option<bsd::BsdMonitor>,
     config: config::Config,
 }
 
@@ -36,6 +80,16 @@ impl FluxDefense {
             file_policy: policy::FilePolicy::default(),
             network_policy: policy::NetworkPolicy::default(),
             monitor: None,
+            fleet_agent: None,
+            elastic_shipper: None,
+            splunk_sink: None,
+            behavior_baseline: None,
+            incidents: Arc::new(incidents::IncidentManager::new(config.incidents.clone())),
+            audit_log: None,
+            capabilities: None,
+            passive_mode: false,
+            #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+            bsd_monitor: None,
             config,
         })
     }
@@ -46,26 +100,75 @@ impl FluxDefense {
         Ok(Self {
             esf_client: None,
             network_filter: None,
-            file_policy: policy::FilePolicy::default(),
-            network_policy: policy::NetworkPolicy::default(),
+            file_policy: config.profile.map(|profile| profile.file_policy()).unwrap_or_default(),
+            network_policy: config.profile.map(|profile| profile.network_policy()).unwrap_or_default(),
             monitor: None,
+            fleet_agent: None,
+            elastic_shipper: None,
+            splunk_sink: None,
+            behavior_baseline: None,
+            incidents: Arc::new(incidents::IncidentManager::new(config.incidents.clone())),
+            audit_log: None,
+            capabilities: None,
+            passive_mode: false,
+            #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+            bsd_monitor: None,
             config,
         })
     }
     
-    pub async fn start(&mut self) -> Result<()> {
-        info!("Starting FluxDefense protection");
+    // Opens what needs the privileges the daemon starts with: the capability
+    // probe, the event and audit logs and the pseudonymization key. Starts
+    // no threads, so privileges can be dropped between this and `start`,
+    // which calls it if it has not run yet
+    pub fn acquire(&mut self) -> Result<()> {
+        if self.monitor.is_some() {
+            return Ok(());
+        }
         
         // Create passive monitor
         let log_path = self.config.log_file_path.clone()
             .unwrap_or_else(|| PathBuf::from("./fluxdefense-events.log"));
         
-        #[cfg(feature = "passive-mode")]
-        let passive_mode = true;
-        #[cfg(not(feature = "passive-mode"))]
-        let passive_mode = false;
+        let capabilities = preflight::CapabilityReport::probe();
+        capabilities.log();
+        let mut passive_mode = match self.config.enforcement {
+            Some(enforcement) => enforcement == config::Enforcement::Passive,
+            None => cfg!(feature = "passive-mode"),
+        };
+        // Without a way to block, enforcing would only pretend to
+        let blocking = if cfg!(target_os = "macos") { preflight::ES_ENTITLEMENT } else { preflight::FANOTIFY_PERMISSION };
+        if !passive_mode && !capabilities.has(blocking) {
+            warn!("Enforcement needs {}; running observe-only", blocking);
+            passive_mode = true;
+        }
+        self.capabilities = Some(capabilities);
+        self.passive_mode = passive_mode;
         
         let mut monitor = monitor::PassiveMonitor::new(log_path, passive_mode)?;
+        monitor.set_event_log_format(self.config.event_log_format);
+        monitor.set_log_rotation(self.config.log_rotation.clone());
+        monitor.set_redactor(Arc::new(redaction::Redactor::from_config(&self.config.redaction)?));
+        monitor.set_sampling(self.config.sampling.clone());
+        monitor.set_file_access_aggregation(self.config.file_access_aggregation.clone());
+        if let Some(privacy_config) = self.config.privacy.clone() {
+            monitor.set_pseudonymizer(Some(Arc::new(privacy::Pseudonymizer::open(privacy_config)?)));
+        }
+        
+        if let Some(ref path) = self.config.audit_log_path {
+            self.audit_log = Some(Arc::new(audit_log::AuditLog::open(path)?));
+        }
+        monitor.set_audit_log(self.audit_log.clone());
+        self.monitor = Some(monitor);
+        Ok(())
+    }
+    
+    pub async fn start(&mut self) -> Result<()> {
+        info!("Starting FluxDefense protection");
+        
+        self.acquire()?;
+        let passive_mode = self.passive_mode;
+        let mut monitor = self.monitor.take().expect("acquired above");
         
         // Load whitelist data if available
         if let Some(ref policy_path) = self.config.file_policy_path {
@@ -76,6 +179,44 @@ impl FluxDefense {
             }
         }
         
+        if let Some(fleet_config) = self.config.fleet.clone() {
+            let agent = self.create_fleet_agent(fleet_config, &monitor)?;
+            let sink_agent = agent.clone();
+            monitor.add_event_sink("fleet", move |event| sink_agent.submit_event(event.clone()));
+            agent.start();
+            self.fleet_agent = Some(agent);
+        }
+        
+        if let Some(elastic_config) = self.config.elasticsearch.clone() {
+            let shipper = output::ElasticShipper::new(elastic_config)?;
+            let sink_shipper = shipper.clone();
+            monitor.add_event_sink("elasticsearch", move |event| sink_shipper.submit(output::OutputRecord::SecurityEvent(event.clone())));
+            shipper.start();
+            self.elastic_shipper = Some(shipper);
+        }
+        
+        if let Some(splunk_config) = self.config.splunk_hec.clone() {
+            let sink = output::SplunkHecSink::new(splunk_config)?;
+            let event_sink = sink.clone();
+            monitor.add_event_sink("splunk-hec", move |event| event_sink.submit(output::OutputRecord::SecurityEvent(event.clone())));
+            sink.start();
+            self.splunk_sink = Some(sink);
+        }
+        
+        if let Some(baseline_config) = self.config.behavior_baseline.clone() {
+            let baseline = Arc::new(self.create_behavior_baseline(baseline_config)?);
+            let sink_baseline = baseline.clone();
+            monitor.add_event_sink("behavior-baseline", move |event| {
+                sink_baseline.observe(event);
+            });
+            self.behavior_baseline = Some(baseline);
+        }
+        
+        let incidents = Arc::clone(&self.incidents);
+        monitor.add_event_sink("incidents", move |event| {
+            incidents.record_event(None, event);
+        });
+        
         self.monitor = Some(monitor);
         
         // Platform-specific initialization
@@ -83,8 +224,13 @@ impl FluxDefense {
         {
             // Initialize ESF client only if not in passive mode
             if !passive_mode {
-                self.esf_client = Some(esf::EsfClient::new()?);
-                self.network_filter = Some(network::NetworkFilter::new()?);
+                match esf::EsfClient::new() {
+                    Ok(client) => {
+                        self.esf_client = Some(client);
+                        self.network_filter = Some(network::NetworkFilter::new()?);
+                    }
+                    Err(e) => warn!("Endpoint Security unavailable, running observe-only: {}", e),
+                }
             } else {
                 info!("Running in passive mode - ESF and network filtering disabled");
             }
@@ -96,6 +242,14 @@ impl FluxDefense {
             info!("Running on Linux - using passive monitoring mode");
         }
         
+        #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+        {
+            match bsd::BsdMonitor::start() {
+                Ok(bsd_monitor) => self.bsd_monitor = Some(bsd_monitor),
+                Err(e) => warn!("kqueue process monitoring unavailable: {}", e),
+            }
+        }
+        
         info!("FluxDefense protection started successfully (passive_mode: {})", passive_mode);
         Ok(())
     }
@@ -111,11 +265,168 @@ impl FluxDefense {
             filter.stop()?;
         }
         
+        #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+        if let Some(mut bsd_monitor) = self.bsd_monitor.take() {
+            bsd_monitor.stop();
+        }
+        
+        // Hand queued events to the sinks before they shut down
+        if let Some(ref monitor) = self.monitor {
+            monitor.flush_file_access_summaries(true);
+            monitor.flush_event_sinks();
+        }
+        
+        if let Some(ref agent) = self.fleet_agent {
+            agent.stop();
+        }
+        
+        if let Some(ref shipper) = self.elastic_shipper {
+            shipper.stop();
+        }
+        
+        if let Some(ref sink) = self.splunk_sink {
+            sink.stop();
+        }
+        
+        if let Some(ref baseline) = self.behavior_baseline {
+            if let Err(e) = baseline.save() {
+                warn!("Failed to save behavior baseline: {}", e);
+            }
+        }
         
         info!("FluxDefense protection stopped");
         Ok(())
     }
     
+    // Anomalies go to the same outputs as security events
+    fn create_behavior_baseline(&self, baseline_config: anomaly::BaselineConfig) -> Result<anomaly::BehaviorBaseline> {
+        let elastic_shipper = self.elastic_shipper.clone();
+        let splunk_sink = self.splunk_sink.clone();
+        
+        anomaly::BehaviorBaseline::new(baseline_config, move |anomaly| {
+            if let Some(ref shipper) = elastic_shipper {
+                shipper.submit(output::OutputRecord::Anomaly(anomaly.clone()));
+            }
+            if let Some(ref sink) = splunk_sink {
+                sink.submit(output::OutputRecord::Anomaly(anomaly));
+            }
+        })
+    }
+    
+    pub fn behavior_baseline(&self) -> Option<Arc<anomaly::BehaviorBaseline>> {
+        self.behavior_baseline.clone()
+    }
+    
+    // Policies pushed by the fleet server replace the running ones and are written
+    // to the configured policy files so they survive a restart
+    fn create_fleet_agent(&self, fleet_config: fleet::FleetAgentConfig, monitor: &monitor::PassiveMonitor) -> Result<fleet::FleetAgent> {
+        let (file_policy, network_policy) = monitor.shared_policies();
+        let file_policy_path = self.config.file_policy_path.clone();
+        let network_policy_path = self.config.network_policy_path.clone();
+        let audit_log = self.audit_log.clone();
+        let record_change = move |target: &str, version: u64| {
+            if let Some(ref audit_log) = audit_log {
+                audit_log.record(audit_log::AuditRecord::new(
+                    audit_log::AuditKind::PolicyChange, "fleet", "replace", target,
+                    format!("Policy version {} pushed by the fleet server", version),
+                ));
+            }
+        };
+        
+        let verifier = self.config.policy_signing.as_ref().map(policy::PolicyVerifier::new).transpose()?;
+        
+        fleet::FleetAgent::new(fleet_config, move |mut update| {
+            // Only the signed documents are trusted, and they are written as
+            // received so the files keep verifying
+            let signed = match verifier {
+                Some(ref verifier) => {
+                    let signed = update.signed.take()
+                        .ok_or_else(|| anyhow::anyhow!("Refusing unsigned fleet policy version {}", update.version))?;
+                    update.policy = signed.verify(verifier)
+                        .map_err(|e| anyhow::anyhow!("Refusing fleet policy version {}: {:#}", update.version, e))?;
+                    Some(signed)
+                }
+                None => None,
+            };
+            
+            if let Some(policy) = update.policy.file_policy {
+                if let Some(ref path) = file_policy_path {
+                    match signed.as_ref().and_then(|signed| signed.file_policy.as_ref()) {
+                        Some(document) => policy::write_signed(path, document)?,
+                        None => policy.save_to_file(path)?,
+                    }
+                }
+                *file_policy.write().map_err(|_| anyhow::anyhow!("Failed to acquire file policy write lock"))? = policy;
+                record_change("file_policy", update.version);
+            }
+            if let Some(policy) = update.policy.network_policy {
+                if let Some(ref path) = network_policy_path {
+                    match signed.as_ref().and_then(|signed| signed.network_policy.as_ref()) {
+                        Some(document) => policy::write_signed(path, document)?,
+                        None => policy.save_to_file(path)?,
+                    }
+                }
+                *network_policy.write().map_err(|_| anyhow::anyhow!("Failed to acquire network policy write lock"))? = policy;
+                record_change("network_policy", update.version);
+            }
+            Ok(())
+        })
+    }
+    
+    // Passes what the platform watchers queued since the last call to the
+    // monitor (only BSD queues anything) and logs file access summaries that
+    // are due. Returns how many platform events were handled
+    pub fn process_platform_events(&self) -> usize {
+        if let Some(ref monitor) = self.monitor {
+            monitor.flush_file_access_summaries(false);
+        }
+        #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+        if let (Some(bsd_monitor), Some(monitor)) = (&self.bsd_monitor, &self.monitor) {
+            let events = bsd_monitor.try_events();
+            let count = events.len();
+            for event in events {
+                bsd::handle_event(monitor, event);
+            }
+            return count;
+        }
+        0
+    }
+    
+    // Probed by `start`
+    pub fn capabilities(&self) -> Option<&preflight::CapabilityReport> {
+        self.capabilities.as_ref()
+    }
+    
+    pub fn audit_log(&self) -> Option<Arc<audit_log::AuditLog>> {
+        self.audit_log.clone()
+    }
+    
+    pub fn incidents(&self) -> Arc<incidents::IncidentManager> {
+        Arc::clone(&self.incidents)
+    }
+    
+    // Replaces the given halves of the policy, in the running monitor too
+    pub fn set_policies(&mut self, mut policy: policy::CandidatePolicy) -> Result<()> {
+        policy.validate()?;
+        if let Some(file_policy) = policy.file_policy {
+            if let Some(ref monitor) = self.monitor {
+                monitor.set_file_policy(file_policy.clone())?;
+            }
+            self.file_policy = file_policy;
+        }
+        if let Some(network_policy) = policy.network_policy {
+            if let Some(ref monitor) = self.monitor {
+                monitor.set_network_policy(network_policy.clone())?;
+            }
+            self.network_policy = network_policy;
+        }
+        Ok(())
+    }
+    
+    pub fn get_fleet_agent(&self) -> Option<&fleet::FleetAgent> {
+        self.fleet_agent.as_ref()
+    }
+    
     pub fn get_monitor(&self) -> Option<&monitor::PassiveMonitor> {
         self.monitor.as_ref()
     }
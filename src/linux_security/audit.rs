@@ -0,0 +1,728 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, TimeZone, Utc};
+use libc::{self, c_int, sockaddr_nl};
+use tracing::{info, warn, error, debug};
+use uuid::Uuid;
+
+use crate::monitor::{SecurityEvent, SecurityEventType, ProcessInfo, Verdict};
+use super::event_correlation::{EventCorrelator, CorrelatedEvent};
+use super::injection::InjectionDetector;
+
+// Linux audit subsystem ingestion
+// Reads records from the kernel audit multicast group (read-only, coexists with auditd)
+// or tails audit.log, groups multi-record events by serial and converts them to SecurityEvents.
+
+const NETLINK_AUDIT: c_int = 9;
+const AUDIT_NLGRP_READLOG: u32 = 1;
+const MAX_AUDIT_MESSAGE_LENGTH: usize = 8970;
+
+// Record types from linux/audit.h
+const AUDIT_USER_AUTH: u16 = 1100;
+const AUDIT_SYSCALL: u16 = 1300;
+const AUDIT_PATH: u16 = 1302;
+const AUDIT_EXECVE: u16 = 1309;
+const AUDIT_EOE: u16 = 1320;
+
+const AUDIT_ARCH_X86_64: &str = "c000003e";
+
+// Fields that auditd hex-encodes when the value contains spaces or control characters
+const ENCODED_FIELDS: &[&str] = &["exe", "comm", "acct", "cwd", "name", "proctitle", "cmd", "key"];
+
+// Pending event groups kept while waiting for an EOE record
+const MAX_PENDING_EVENTS: usize = 256;
+
+pub const DEFAULT_AUDIT_LOG: &str = "/var/log/audit/audit.log";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditRecordType {
+    UserAuth,
+    Syscall,
+    // A file name the syscall resolved
+    Path,
+    Execve,
+    Eoe,
+    Other(u16),
+}
+
+impl AuditRecordType {
+    fn from_code(code: u16) -> Self {
+        match code {
+            AUDIT_USER_AUTH => AuditRecordType::UserAuth,
+            AUDIT_SYSCALL => AuditRecordType::Syscall,
+            AUDIT_PATH => AuditRecordType::Path,
+            AUDIT_EXECVE => AuditRecordType::Execve,
+            AUDIT_EOE => AuditRecordType::Eoe,
+            other => AuditRecordType::Other(other),
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "USER_AUTH" => AuditRecordType::UserAuth,
+            "SYSCALL" => AuditRecordType::Syscall,
+            "PATH" => AuditRecordType::Path,
+            "EXECVE" => AuditRecordType::Execve,
+            "EOE" => AuditRecordType::Eoe,
+            _ => AuditRecordType::Other(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub record_type: AuditRecordType,
+    pub timestamp: DateTime<Utc>,
+    pub serial: u64,
+    pub fields: HashMap<String, String>,
+}
+
+impl AuditRecord {
+    pub(crate) fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name)
+            .map(|v| v.as_str())
+            .filter(|v| !v.is_empty() && *v != "?" && *v != "(null)")
+    }
+
+    pub(crate) fn field_u32(&self, name: &str) -> Option<u32> {
+        self.field(name).and_then(|v| v.parse().ok())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AuditSource {
+    Netlink,
+    LogFile(PathBuf),
+}
+
+pub struct AuditMonitor {
+    source: AuditSource,
+    running: Arc<Mutex<bool>>,
+    event_handler: Arc<dyn Fn(SecurityEvent) + Send + Sync>,
+    correlator: Option<Arc<EventCorrelator>>,
+    correlation_handler: Option<Arc<dyn Fn(CorrelatedEvent) + Send + Sync>>,
+    injection: Option<Arc<InjectionDetector>>,
+}
+
+impl AuditMonitor {
+    pub fn new<F>(event_handler: F) -> Result<Self>
+    where
+        F: Fn(SecurityEvent) + Send + Sync + 'static
+    {
+        Ok(Self {
+            source: AuditSource::Netlink,
+            running: Arc::new(Mutex::new(false)),
+            event_handler: Arc::new(event_handler),
+            correlator: None,
+            correlation_handler: None,
+            injection: None,
+        })
+    }
+
+    pub fn set_source(&mut self, source: AuditSource) {
+        self.source = source;
+    }
+
+    // Feed every audit event through the correlation engine
+    pub fn set_correlator<F>(&mut self, correlator: Arc<EventCorrelator>, handler: F)
+    where
+        F: Fn(CorrelatedEvent) + Send + Sync + 'static
+    {
+        self.correlator = Some(correlator);
+        self.correlation_handler = Some(Arc::new(handler));
+    }
+
+    // Check every audit event for process injection; detections go to the
+    // event handler after the event itself
+    pub fn set_injection_detector(&mut self, detector: Arc<InjectionDetector>) {
+        self.injection = Some(detector);
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return Ok(());
+            }
+            *running = true;
+        }
+
+        let source = match &self.source {
+            AuditSource::Netlink => match Self::open_netlink_socket() {
+                Ok(socket) => {
+                    info!("Audit monitor reading from kernel audit multicast group");
+                    self.start_netlink_thread(socket);
+                    return Ok(());
+                }
+                Err(e) if Path::new(DEFAULT_AUDIT_LOG).exists() => {
+                    warn!("Audit netlink unavailable ({}), falling back to {}", e, DEFAULT_AUDIT_LOG);
+                    PathBuf::from(DEFAULT_AUDIT_LOG)
+                }
+                Err(e) => {
+                    *self.running.lock().unwrap() = false;
+                    return Err(e);
+                }
+            },
+            AuditSource::LogFile(path) => path.clone(),
+        };
+
+        info!("Audit monitor tailing {}", source.display());
+        self.start_log_thread(source);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        let mut running = self.running.lock().unwrap();
+        *running = false;
+        info!("Audit monitor stopped");
+        Ok(())
+    }
+
+    fn open_netlink_socket() -> Result<RawFd> {
+        let socket = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW, NETLINK_AUDIT)
+        };
+
+        if socket < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to create audit netlink socket: {}", err));
+        }
+
+        // Join the read-only log multicast group (requires CAP_AUDIT_READ)
+        let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_pid = 0;
+        addr.nl_groups = AUDIT_NLGRP_READLOG;
+
+        let ret = unsafe {
+            libc::bind(
+                socket,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<sockaddr_nl>() as u32
+            )
+        };
+
+        if ret < 0 {
+            unsafe { libc::close(socket) };
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to bind audit netlink socket: {}", err));
+        }
+
+        // Use a receive timeout so the reader thread can notice stop()
+        let timeout = libc::timeval { tv_sec: 1, tv_usec: 0 };
+        unsafe {
+            libc::setsockopt(
+                socket,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            );
+        }
+
+        Ok(socket)
+    }
+
+    fn start_netlink_thread(&self, socket: RawFd) {
+        let running = Arc::clone(&self.running);
+        let event_handler = Arc::clone(&self.event_handler);
+        let correlator = self.correlator.clone();
+        let correlation_handler = self.correlation_handler.clone();
+        let injection = self.injection.clone();
+
+        thread::spawn(move || {
+            let mut assembler = AuditAssembler::default();
+            let mut buffer = vec![0u8; MAX_AUDIT_MESSAGE_LENGTH * 2];
+
+            while *running.lock().unwrap() {
+                let len = unsafe {
+                    libc::recv(socket, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+                };
+
+                if len < 0 {
+                    let err = std::io::Error::last_os_error();
+                    match err.kind() {
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => continue,
+                        _ => {
+                            error!("Audit netlink receive failed: {}", err);
+                            thread::sleep(Duration::from_millis(500));
+                            continue;
+                        }
+                    }
+                }
+
+                for record in parse_netlink_buffer(&buffer[..len as usize]) {
+                    for records in assembler.push(record) {
+                        Self::dispatch(&records, &event_handler, &correlator, &correlation_handler, &injection);
+                    }
+                }
+            }
+
+            unsafe { libc::close(socket) };
+            debug!("Audit netlink thread exited");
+        });
+    }
+
+    fn start_log_thread(&self, path: PathBuf) {
+        let running = Arc::clone(&self.running);
+        let event_handler = Arc::clone(&self.event_handler);
+        let correlator = self.correlator.clone();
+        let correlation_handler = self.correlation_handler.clone();
+        let injection = self.injection.clone();
+
+        thread::spawn(move || {
+            let mut assembler = AuditAssembler::default();
+            let mut reader: Option<BufReader<File>> = None;
+            let mut position = 0u64;
+            let mut line = String::new();
+
+            while *running.lock().unwrap() {
+                // (Re)open the log, starting at the end on first open
+                if reader.is_none() {
+                    match File::open(&path) {
+                        Ok(mut file) => {
+                            if position == 0 {
+                                position = file.seek(SeekFrom::End(0)).unwrap_or(0);
+                            }
+                            reader = Some(BufReader::new(file));
+                        }
+                        Err(e) => {
+                            warn!("Failed to open audit log {}: {}", path.display(), e);
+                            thread::sleep(Duration::from_secs(5));
+                            continue;
+                        }
+                    }
+                }
+
+                let r = reader.as_mut().unwrap();
+                line.clear();
+                match r.read_line(&mut line) {
+                    Ok(0) => {
+                        // Detect rotation/truncation
+                        let current_len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                        if current_len < position {
+                            debug!("Audit log rotated, reopening");
+                            reader = None;
+                            position = 0;
+                            // Read the new file from the start
+                            if let Ok(file) = File::open(&path) {
+                                reader = Some(BufReader::new(file));
+                            }
+                        } else {
+                            thread::sleep(Duration::from_millis(250));
+                        }
+                    }
+                    Ok(n) => {
+                        position += n as u64;
+                        if let Some(record) = parse_log_line(&line) {
+                            for records in assembler.push(record) {
+                                Self::dispatch(&records, &event_handler, &correlator, &correlation_handler, &injection);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error reading audit log: {}", e);
+                        reader = None;
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+
+            debug!("Audit log thread exited");
+        });
+    }
+
+    fn dispatch(
+        records: &[AuditRecord],
+        event_handler: &Arc<dyn Fn(SecurityEvent) + Send + Sync>,
+        correlator: &Option<Arc<EventCorrelator>>,
+        correlation_handler: &Option<Arc<dyn Fn(CorrelatedEvent) + Send + Sync>>,
+        injection: &Option<Arc<InjectionDetector>>,
+    ) {
+        let detection = injection.as_ref().and_then(|detector| detector.observe(records));
+        let event = match records_to_event(records) {
+            Some(event) => event,
+            None => return,
+        };
+
+        if let Some(correlator) = correlator {
+            if let Some(correlated) = correlator.process_event(event.clone()) {
+                warn!("Correlated detection from audit events: {}", correlated.description);
+                if let Some(handler) = correlation_handler {
+                    handler(correlated);
+                }
+            }
+        }
+
+        event_handler(event);
+        if let Some(detection) = detection {
+            warn!("Process injection: {}", detection.description);
+            event_handler(detection.to_security_event());
+        }
+    }
+}
+
+// Groups records belonging to the same audit event (same serial)
+#[derive(Default)]
+struct AuditAssembler {
+    pending: BTreeMap<u64, Vec<AuditRecord>>,
+}
+
+impl AuditAssembler {
+    // Returns any record groups that are complete after adding this record
+    fn push(&mut self, record: AuditRecord) -> Vec<Vec<AuditRecord>> {
+        let mut complete = Vec::new();
+
+        match record.record_type {
+            // User-space records are single-record events
+            AuditRecordType::UserAuth => complete.push(vec![record]),
+            AuditRecordType::Eoe => {
+                if let Some(records) = self.pending.remove(&record.serial) {
+                    complete.push(records);
+                }
+            }
+            _ => {
+                self.pending.entry(record.serial).or_default().push(record);
+
+                // Flush the oldest groups if EOE records are being lost
+                while self.pending.len() > MAX_PENDING_EVENTS {
+                    let oldest = *self.pending.keys().next().unwrap();
+                    if let Some(records) = self.pending.remove(&oldest) {
+                        complete.push(records);
+                    }
+                }
+            }
+        }
+
+        complete
+    }
+}
+
+// Parse a line from audit.log: `type=SYSCALL msg=audit(1700000000.123:456): arch=...`
+pub fn parse_log_line(line: &str) -> Option<AuditRecord> {
+    let line = line.trim_end();
+    let rest = line.strip_prefix("type=")?;
+    let (type_name, rest) = rest.split_once(' ')?;
+    let rest = rest.trim_start().strip_prefix("msg=").unwrap_or(rest);
+    parse_record_body(AuditRecordType::from_name(type_name), rest)
+}
+
+// Parse all netlink messages in a datagram received from the audit socket
+fn parse_netlink_buffer(buffer: &[u8]) -> Vec<AuditRecord> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while offset + 16 <= buffer.len() {
+        let msg_len = u32::from_ne_bytes([
+            buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3],
+        ]) as usize;
+        let msg_type = u16::from_ne_bytes([buffer[offset + 4], buffer[offset + 5]]);
+
+        if msg_len < 16 || offset + msg_len > buffer.len() {
+            break;
+        }
+
+        let payload = &buffer[offset + 16..offset + msg_len];
+        let text = String::from_utf8_lossy(payload);
+        if let Some(record) = parse_record_body(
+            AuditRecordType::from_code(msg_type),
+            text.trim_end_matches('\0'),
+        ) {
+            records.push(record);
+        }
+
+        // Netlink messages are 4-byte aligned
+        offset += (msg_len + 3) & !3;
+    }
+
+    records
+}
+
+// Parse `audit(1700000000.123:456): key=value ...`
+fn parse_record_body(record_type: AuditRecordType, body: &str) -> Option<AuditRecord> {
+    let body = body.strip_prefix("audit(")?;
+    let (header, fields) = body.split_once("):")?;
+    let (timestamp, serial) = header.split_once(':')?;
+    let (secs, millis) = timestamp.split_once('.').unwrap_or((timestamp, "0"));
+
+    let timestamp = Utc.timestamp_opt(
+        secs.parse().ok()?,
+        millis.parse::<u32>().unwrap_or(0) * 1_000_000,
+    ).single()?;
+
+    // Enriched logs append interpreted fields after a GS separator; ignore them
+    let fields = fields.split('\x1d').next().unwrap_or("");
+
+    Some(AuditRecord {
+        record_type,
+        timestamp,
+        serial: serial.parse().ok()?,
+        fields: parse_fields(fields, record_type == AuditRecordType::Execve),
+    })
+}
+
+// Parse audit key=value pairs, flattening nested msg='...' blocks from user-space records.
+// aN are hex-encoded arguments in EXECVE records but raw syscall arguments in SYSCALL ones.
+fn parse_fields(text: &str, execve: bool) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        let key_start = i;
+        while i < chars.len() && chars[i] != '=' && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        if i >= chars.len() || chars[i] != '=' {
+            continue;
+        }
+        let key: String = chars[key_start..i].iter().collect();
+        i += 1;
+
+        match chars.get(i) {
+            Some('"') => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                fields.insert(key, chars[start..i.min(chars.len())].iter().collect());
+                i += 1;
+            }
+            Some('\'') => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                let inner: String = chars[start..i.min(chars.len())].iter().collect();
+                fields.extend(parse_fields(&inner, execve));
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() {
+                    i += 1;
+                }
+                let value: String = chars[start..i].iter().collect();
+                let is_encoded = ENCODED_FIELDS.contains(&key.as_str())
+                    || (execve && key.starts_with('a') && key[1..].parse::<u32>().is_ok());
+                let value = if is_encoded {
+                    decode_hex(&value).unwrap_or(value)
+                } else {
+                    value
+                };
+                fields.insert(key, value);
+            }
+        }
+    }
+
+    fields
+}
+
+fn decode_hex(value: &str) -> Option<String> {
+    if value.len() < 2 || !value.len().is_multiple_of(2) || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let bytes = hex::decode(value).ok()?;
+    // Arguments are NUL separated in proctitle
+    Some(String::from_utf8_lossy(&bytes).replace('\0', " "))
+}
+
+// Convert a complete record group into a SecurityEvent
+pub fn records_to_event(records: &[AuditRecord]) -> Option<SecurityEvent> {
+    if let Some(auth) = records.iter().find(|r| r.record_type == AuditRecordType::UserAuth) {
+        return Some(user_auth_event(auth));
+    }
+
+    let syscall = records.iter().find(|r| r.record_type == AuditRecordType::Syscall)?;
+    let execve = records.iter().find(|r| r.record_type == AuditRecordType::Execve);
+
+    let exe = PathBuf::from(syscall.field("exe").unwrap_or("unknown"));
+    let command_line = execve.map(execve_command_line);
+
+    let process_info = ProcessInfo {
+        pid: syscall.field_u32("pid").unwrap_or(0),
+        path: exe.clone(),
+        parent_pid: syscall.field_u32("ppid"),
+        user_id: syscall.field_u32("uid").unwrap_or(0),
+        executable_hash: None,
+        command_line,
+    };
+
+    let success = syscall.field("success").map(|s| s == "yes").unwrap_or(true);
+
+    let (event_type, reason) = if execve.is_some() {
+        (SecurityEventType::FileExecution {
+            target_path: exe,
+            file_hash: None,
+            code_signature: None,
+        }, "Audit EXECVE record".to_string())
+    } else {
+        let syscall_name = syscall_name(
+            syscall.field("arch").unwrap_or(""),
+            syscall.field("syscall").unwrap_or(""),
+        );
+        let reason = format!("Audit SYSCALL record: {}", syscall_name);
+        (SecurityEventType::Syscall {
+            syscall: syscall_name,
+            success,
+            exit_code: syscall.field("exit").and_then(|v| v.parse().ok()),
+            audit_key: syscall.field("key").map(|k| k.to_string()),
+        }, reason)
+    };
+
+    Some(SecurityEvent {
+        id: Uuid::new_v4().to_string(),
+        timestamp: syscall.timestamp,
+        event_type,
+        process_info,
+        verdict: Verdict::Log,
+        policy_reason: reason,
+    })
+}
+
+fn user_auth_event(record: &AuditRecord) -> SecurityEvent {
+    let exe = PathBuf::from(record.field("exe").unwrap_or("unknown"));
+    let service = exe.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let success = record.field("res").map(|r| r == "success").unwrap_or(false);
+
+    SecurityEvent {
+        id: Uuid::new_v4().to_string(),
+        timestamp: record.timestamp,
+        event_type: SecurityEventType::Authentication {
+            user: record.field("acct").unwrap_or("unknown").to_string(),
+            service,
+            success,
+            remote_host: record.field("addr")
+                .or_else(|| record.field("hostname"))
+                .map(|h| h.to_string()),
+        },
+        process_info: ProcessInfo {
+            pid: record.field_u32("pid").unwrap_or(0),
+            path: exe,
+            parent_pid: None,
+            user_id: record.field_u32("uid").unwrap_or(0),
+            executable_hash: None,
+            command_line: None,
+        },
+        verdict: Verdict::Log,
+        policy_reason: format!("Audit USER_AUTH record ({})", if success { "success" } else { "failed" }),
+    }
+}
+
+fn execve_command_line(record: &AuditRecord) -> String {
+    let argc = record.field_u32("argc").unwrap_or(0);
+    (0..argc)
+        .filter_map(|i| record.fields.get(&format!("a{}", i)).cloned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Map a syscall number to its name for common security-relevant x86_64 syscalls
+pub(crate) fn syscall_name(arch: &str, number: &str) -> String {
+    if arch != AUDIT_ARCH_X86_64 {
+        return format!("syscall_{}", number);
+    }
+
+    let name = match number {
+        "2" => "open",
+        "42" => "connect",
+        "43" => "accept",
+        "49" => "bind",
+        "57" => "fork",
+        "59" => "execve",
+        "62" => "kill",
+        "82" => "rename",
+        "87" => "unlink",
+        "90" => "chmod",
+        "92" => "chown",
+        "101" => "ptrace",
+        "105" => "setuid",
+        "106" => "setgid",
+        "165" => "mount",
+        "175" => "init_module",
+        "176" => "delete_module",
+        "257" => "openat",
+        "263" => "unlinkat",
+        "268" => "fchmodat",
+        "310" => "process_vm_readv",
+        "311" => "process_vm_writev",
+        "313" => "finit_module",
+        "319" => "memfd_create",
+        "321" => "bpf",
+        "322" => "execveat",
+        _ => return format!("syscall_{}", number),
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user_auth_failure() {
+        let line = "type=USER_AUTH msg=audit(1700000000.123:4242): pid=1234 uid=0 auid=1000 ses=3 \
+                    msg='op=PAM:authentication grantors=? acct=\"alice\" exe=\"/usr/bin/sudo\" \
+                    hostname=? addr=? terminal=/dev/pts/0 res=failed'";
+
+        let record = parse_log_line(line).unwrap();
+        assert_eq!(record.record_type, AuditRecordType::UserAuth);
+        assert_eq!(record.serial, 4242);
+
+        let mut assembler = AuditAssembler::default();
+        let groups = assembler.push(record);
+        let event = records_to_event(&groups[0]).unwrap();
+
+        match event.event_type {
+            SecurityEventType::Authentication { user, service, success, remote_host } => {
+                assert_eq!(user, "alice");
+                assert_eq!(service, "sudo");
+                assert!(!success);
+                assert!(remote_host.is_none());
+            }
+            other => panic!("unexpected event type: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_syscall_execve_grouping() {
+        let lines = [
+            "type=SYSCALL msg=audit(1700000001.000:77): arch=c000003e syscall=59 success=yes exit=0 \
+             ppid=100 pid=200 auid=1000 uid=1000 comm=\"curl\" exe=\"/usr/bin/curl\" key=(null)",
+            "type=EXECVE msg=audit(1700000001.000:77): argc=3 a0=\"curl\" a1=\"-s\" a2=68747470733A2F2F782E696F2F6120622E7368",
+            "type=EOE msg=audit(1700000001.000:77): ",
+        ];
+
+        let mut assembler = AuditAssembler::default();
+        let mut groups = Vec::new();
+        for line in lines {
+            groups.extend(assembler.push(parse_log_line(line).unwrap()));
+        }
+
+        assert_eq!(groups.len(), 1);
+        let event = records_to_event(&groups[0]).unwrap();
+        assert_eq!(event.process_info.pid, 200);
+        assert_eq!(event.process_info.parent_pid, Some(100));
+        assert_eq!(event.process_info.command_line.as_deref(), Some("curl -s https://x.io/a b.sh"));
+        assert!(matches!(event.event_type, SecurityEventType::FileExecution { .. }));
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::monitor::SecurityEventType;
+use super::event_correlation::CorrelatedEvent;
+use super::netfilter::NetfilterManager;
+
+// fail2ban-style blocking: when a brute force correlation fires, the sources
+// behind it are added to the nftables block sets for `ban_duration`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoBlockConfig {
+    #[serde(default = "default_rule_ids")]
+    pub rule_ids: Vec<String>,
+    #[serde(default = "default_ban_duration", with = "duration_secs")]
+    pub ban_duration: Duration,
+    // Sources with fewer events in the correlation are left alone, so one
+    // failed login among many other clients' does not get a host banned
+    #[serde(default = "default_min_events")]
+    pub min_events_per_source: usize,
+    // Addresses or CIDRs that are never banned, e.g. management networks
+    #[serde(default = "default_allowlist")]
+    pub allowlist: Vec<String>,
+}
+
+fn default_rule_ids() -> Vec<String> {
+    vec!["brute_force".to_string()]
+}
+
+fn default_ban_duration() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_min_events() -> usize {
+    3
+}
+
+fn default_allowlist() -> Vec<String> {
+    vec!["127.0.0.0/8".to_string(), "::1".to_string()]
+}
+
+mod duration_secs {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+impl Default for AutoBlockConfig {
+    fn default() -> Self {
+        Self {
+            rule_ids: default_rule_ids(),
+            ban_duration: default_ban_duration(),
+            min_events_per_source: default_min_events(),
+            allowlist: default_allowlist(),
+        }
+    }
+}
+
+impl AutoBlockConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read auto-block config {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid auto-block config {:?}", path))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemediationAction {
+    Banned,
+    // The source is on the allowlist
+    Skipped,
+    Failed,
+}
+
+// What the blocker did about one source of a correlated detection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemediationEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub action: RemediationAction,
+    pub ip: IpAddr,
+    pub rule_id: String,
+    pub correlation_id: String,
+    pub event_count: usize,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub description: String,
+}
+
+pub struct BruteForceBlocker {
+    config: AutoBlockConfig,
+    allowlist: Vec<(IpAddr, u8)>,
+    firewall: Arc<Mutex<NetfilterManager>>,
+    on_remediation: Arc<dyn Fn(RemediationEvent) + Send + Sync>,
+    // Allowlisted sources already reported, so a rule that keeps firing
+    // reports them once per ban duration
+    skipped: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl BruteForceBlocker {
+    // `firewall` must be initialized; the block sets are enabled here
+    pub fn new<F>(config: AutoBlockConfig, firewall: Arc<Mutex<NetfilterManager>>, on_remediation: F) -> Result<Self>
+    where
+        F: Fn(RemediationEvent) + Send + Sync + 'static
+    {
+        let allowlist = config.allowlist.iter()
+            .map(|entry| parse_cidr(entry))
+            .collect::<Result<Vec<_>>>()?;
+        firewall.lock()
+            .map_err(|_| anyhow!("Firewall lock poisoned"))?
+            .enable_block_sets()?;
+
+        Ok(Self {
+            config,
+            allowlist,
+            firewall,
+            on_remediation: Arc::new(on_remediation),
+            skipped: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn config(&self) -> &AutoBlockConfig {
+        &self.config
+    }
+
+    pub fn is_allowlisted(&self, ip: IpAddr) -> bool {
+        self.allowlist.iter().any(|(network, prefix)| ip_in_network(ip, *network, *prefix))
+    }
+
+    // Call with every correlated detection; ones from other rules are ignored.
+    // Sources that are already banned are not banned again, since the rule
+    // keeps firing while the window still holds their events.
+    pub fn handle(&self, correlated: &CorrelatedEvent) -> Vec<RemediationEvent> {
+        if !self.config.rule_ids.contains(&correlated.rule.id) {
+            return Vec::new();
+        }
+
+        let Ok(mut firewall) = self.firewall.lock() else { return Vec::new() };
+        let banned: Vec<IpAddr> = firewall.list_bans().into_iter().map(|ban| ban.ip).collect();
+
+        let mut remediations = Vec::new();
+        for (ip, event_count) in source_counts(correlated) {
+            if event_count < self.config.min_events_per_source || banned.contains(&ip) {
+                continue;
+            }
+
+            let remediation = |action, expires_at, description: String| RemediationEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                timestamp: Utc::now(),
+                action,
+                ip,
+                rule_id: correlated.rule.id.clone(),
+                correlation_id: correlated.id.clone(),
+                event_count,
+                expires_at,
+                description,
+            };
+
+            let event = if self.is_allowlisted(ip) {
+                if !self.first_skip(ip) {
+                    continue;
+                }
+                remediation(RemediationAction::Skipped, None,
+                    format!("{} matched {} ({} events) but is allowlisted", ip, correlated.rule.name, event_count))
+            } else {
+                let reason = format!("{} ({} events)", correlated.rule.name, event_count);
+                match firewall.ban_ip(ip, Some(self.config.ban_duration), &reason) {
+                    Ok(ban) => remediation(RemediationAction::Banned, ban.expires_at,
+                        format!("Blocked {} for {}s after {}", ip, self.config.ban_duration.as_secs(), reason)),
+                    Err(e) => remediation(RemediationAction::Failed, None,
+                        format!("Failed to block {} after {}: {:#}", ip, reason, e)),
+                }
+            };
+
+            match event.action {
+                RemediationAction::Banned => info!("{}", event.description),
+                _ => warn!("{}", event.description),
+            }
+            (self.on_remediation)(event.clone());
+            remediations.push(event);
+        }
+        remediations
+    }
+
+    fn first_skip(&self, ip: IpAddr) -> bool {
+        let Ok(mut skipped) = self.skipped.lock() else { return true };
+        let now = Instant::now();
+        skipped.retain(|_, at| now.duration_since(*at) < self.config.ban_duration);
+        skipped.insert(ip, now).is_none()
+    }
+}
+
+// Remote addresses behind a correlation with how many of its events each
+// accounts for, most active first
+fn source_counts(correlated: &CorrelatedEvent) -> Vec<(IpAddr, usize)> {
+    let mut counts: HashMap<IpAddr, usize> = HashMap::new();
+    for event in &correlated.events {
+        let source = match &event.event_type {
+            SecurityEventType::NetworkConnection { remote_ip, .. } => Some(remote_ip.as_str()),
+            SecurityEventType::Authentication { remote_host: Some(host), .. } => Some(host.as_str()),
+            _ => None,
+        };
+        if let Some(ip) = source.and_then(|source| source.parse::<IpAddr>().ok()) {
+            *counts.entry(ip).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<(IpAddr, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8)> {
+    let (addr, prefix) = match cidr.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (cidr, None),
+    };
+
+    let network: IpAddr = addr.trim().parse()
+        .map_err(|_| anyhow!("Invalid allowlist address '{}'", cidr))?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= max)
+            .ok_or_else(|| anyhow!("Invalid prefix length in '{}'", cidr))?,
+        None => max,
+    };
+
+    Ok((network, prefix))
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::linux_security::EventCorrelator;
+    use crate::monitor::{NetworkProtocol, ProcessInfo, SecurityEvent, Verdict};
+
+    fn ssh_connection(remote_ip: &str) -> SecurityEvent {
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::NetworkConnection {
+                remote_ip: remote_ip.to_string(),
+                remote_port: 22,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            },
+            process_info: ProcessInfo {
+                pid: 812,
+                path: PathBuf::from("/usr/sbin/sshd"),
+                parent_pid: Some(1),
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Allow,
+            policy_reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_sources_and_allowlist() {
+        let rule = EventCorrelator::new().unwrap().rules().into_iter()
+            .find(|rule| rule.id == "brute_force")
+            .unwrap();
+        let mut events: Vec<SecurityEvent> = (0..8).map(|_| ssh_connection("203.0.113.9")).collect();
+        events.push(ssh_connection("198.51.100.4"));
+        events.extend((0..3).map(|_| ssh_connection("10.1.2.3")));
+        let correlated = CorrelatedEvent {
+            id: "corr-1".to_string(),
+            severity: rule.severity,
+            rule,
+            events,
+            detected_at: Instant::now(),
+            description: String::new(),
+        };
+
+        let counts = source_counts(&correlated);
+        assert_eq!(counts[0], ("203.0.113.9".parse().unwrap(), 8));
+        assert_eq!(counts[1], ("10.1.2.3".parse().unwrap(), 3));
+        assert_eq!(counts[2], ("198.51.100.4".parse().unwrap(), 1));
+
+        let allowlist: Vec<(IpAddr, u8)> = ["10.0.0.0/8", "::1"].iter()
+            .map(|entry| parse_cidr(entry).unwrap())
+            .collect();
+        let allowed = |ip: &str| allowlist.iter().any(|(net, prefix)| ip_in_network(ip.parse().unwrap(), *net, *prefix));
+        assert!(allowed("10.1.2.3"));
+        assert!(allowed("::1"));
+        assert!(!allowed("203.0.113.9"));
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+    }
+}
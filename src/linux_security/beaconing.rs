@@ -0,0 +1,275 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+// Beacon heuristics: enough connections to one destination, spaced at a
+// steady interval and carrying similar amounts of data, for long enough
+const BEACON_MIN_CONNECTIONS: usize = 6;
+const BEACON_MIN_INTERVAL_SECS: f64 = 5.0;
+const BEACON_MIN_SCORE: f64 = 0.75;
+// Beaconing sustained this long earns the full duration score
+const BEACON_FULL_DURATION_SECS: f64 = 3600.0;
+const BEACON_HISTORY_WINDOW_HOURS: i64 = 24;
+const BEACON_MAX_HISTORY: usize = 128;
+const BEACON_TRACKER_MAX_CHANNELS: usize = 10_000;
+const BEACON_MAX_DETECTIONS: usize = 256;
+// Resolvers, DHCP, NTP and mDNS talk on a fixed schedule by design
+const BEACON_IGNORED_PORTS: [u16; 6] = [53, 67, 68, 123, 137, 5353];
+
+// One outbound connection: who opened it, where to, when and how much it sent
+#[derive(Debug, Clone)]
+pub struct BeaconSample {
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub destination: IpAddr,
+    pub port: u16,
+    pub hostname: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BeaconDetection {
+    pub id: String,
+    pub detected_at: DateTime<Utc>,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub destination: IpAddr,
+    pub port: u16,
+    pub hostname: Option<String>,
+    // Median spacing between connections
+    pub interval_secs: f64,
+    // Median deviation from that spacing, relative to it
+    pub jitter: f64,
+    // Median deviation of the bytes sent per connection, relative to the median
+    pub size_variation: f64,
+    pub score: f64,
+    pub connections: usize,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl BeaconDetection {
+    pub fn description(&self) -> String {
+        format!(
+            "{} beaconing to {}:{} every {:.0}s ({} connections since {}, jitter {:.0}%, score {:.2})",
+            self.process.as_deref().unwrap_or("unknown process"),
+            self.hostname.clone().unwrap_or_else(|| self.destination.to_string()),
+            self.port,
+            self.interval_secs,
+            self.connections,
+            self.first_seen.format("%Y-%m-%d %H:%M:%S UTC"),
+            self.jitter * 100.0,
+            self.score,
+        )
+    }
+}
+
+// Connections are grouped by the process that opened them and the
+// destination name when one was seen, so rotating addresses stay together
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ChannelKey {
+    process: Option<String>,
+    destination: String,
+    port: u16,
+}
+
+#[derive(Debug)]
+struct ChannelHistory {
+    // (start, bytes), oldest first
+    samples: VecDeque<(DateTime<Utc>, u64)>,
+    latest: BeaconSample,
+    reported: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct BeaconTracker {
+    channels: HashMap<ChannelKey, ChannelHistory>,
+    detections: VecDeque<BeaconDetection>,
+}
+
+impl BeaconTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a finished connection; returns a detection the first time its
+    // channel looks like a beacon
+    pub fn record(&mut self, sample: BeaconSample) -> Option<BeaconDetection> {
+        if BEACON_IGNORED_PORTS.contains(&sample.port) {
+            return None;
+        }
+        let key = ChannelKey {
+            process: sample.process.clone(),
+            destination: sample.hostname.clone().unwrap_or_else(|| sample.destination.to_string()),
+            port: sample.port,
+        };
+
+        if self.channels.len() >= BEACON_TRACKER_MAX_CHANNELS && !self.channels.contains_key(&key) {
+            self.prune(Utc::now());
+            if self.channels.len() >= BEACON_TRACKER_MAX_CHANNELS {
+                return None;
+            }
+        }
+
+        let history = self.channels.entry(key).or_insert_with(|| ChannelHistory {
+            samples: VecDeque::new(),
+            latest: sample.clone(),
+            reported: false,
+        });
+        // Connections expire in batches, not strictly in the order they started
+        let position = history.samples.iter().rposition(|(start, _)| *start <= sample.started_at).map_or(0, |i| i + 1);
+        history.samples.insert(position, (sample.started_at, sample.bytes));
+        if history.samples.len() > BEACON_MAX_HISTORY {
+            history.samples.pop_front();
+        }
+        history.latest = sample;
+
+        if history.reported {
+            return None;
+        }
+        let detection = score(history)?;
+        history.reported = true;
+        self.detections.push_front(detection.clone());
+        self.detections.truncate(BEACON_MAX_DETECTIONS);
+        Some(detection)
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::hours(BEACON_HISTORY_WINDOW_HOURS);
+        self.channels.retain(|_, history| {
+            while history.samples.front().is_some_and(|(start, _)| *start < cutoff) {
+                history.samples.pop_front();
+            }
+            !history.samples.is_empty()
+        });
+    }
+
+    // Newest first
+    pub fn detections(&self, limit: usize) -> Vec<BeaconDetection> {
+        self.detections.iter().take(limit).cloned().collect()
+    }
+}
+
+fn score(history: &ChannelHistory) -> Option<BeaconDetection> {
+    if history.samples.len() < BEACON_MIN_CONNECTIONS {
+        return None;
+    }
+    let intervals: Vec<f64> = history.samples.iter()
+        .zip(history.samples.iter().skip(1))
+        .map(|((earlier, _), (later, _))| (*later - *earlier).num_milliseconds() as f64 / 1000.0)
+        .collect();
+    let interval = median(&intervals);
+    if interval < BEACON_MIN_INTERVAL_SECS {
+        return None;
+    }
+    let jitter = relative_deviation(&intervals, interval);
+    let sizes: Vec<f64> = history.samples.iter().map(|(_, bytes)| *bytes as f64).collect();
+    let size_variation = relative_deviation(&sizes, median(&sizes));
+
+    let first_seen = history.samples.front()?.0;
+    let last_seen = history.samples.back()?.0;
+    let duration = (last_seen - first_seen).num_seconds() as f64;
+    let score = 0.5 * (1.0 - jitter).max(0.0)
+        + 0.2 * (1.0 - size_variation).max(0.0)
+        + 0.3 * (duration / BEACON_FULL_DURATION_SECS).min(1.0);
+    if score < BEACON_MIN_SCORE {
+        return None;
+    }
+
+    let latest = &history.latest;
+    Some(BeaconDetection {
+        id: Uuid::new_v4().to_string(),
+        detected_at: Utc::now(),
+        pid: latest.pid,
+        process: latest.process.clone(),
+        destination: latest.destination,
+        port: latest.port,
+        hostname: latest.hostname.clone(),
+        interval_secs: interval,
+        jitter,
+        size_variation,
+        score,
+        connections: history.samples.len(),
+        first_seen,
+        last_seen,
+    })
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    match sorted.len() {
+        0 => 0.0,
+        n if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+        n => sorted[n / 2],
+    }
+}
+
+// Median absolute deviation over the median; unlike a standard deviation
+// it shrugs off the odd missed or doubled beacon
+fn relative_deviation(values: &[f64], center: f64) -> f64 {
+    if center <= 0.0 {
+        return if values.iter().all(|value| *value == center) { 0.0 } else { 1.0 };
+    }
+    let deviations: Vec<f64> = values.iter().map(|value| (value - center).abs()).collect();
+    median(&deviations) / center
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(process: &str, port: u16, started_at: DateTime<Utc>, bytes: u64) -> BeaconSample {
+        BeaconSample {
+            pid: Some(4242),
+            process: Some(process.to_string()),
+            destination: "203.0.113.7".parse().unwrap(),
+            port,
+            hostname: Some("c2.example".to_string()),
+            started_at,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_beacon_detection() {
+        let mut tracker = BeaconTracker::new();
+        let start = Utc::now() - Duration::hours(2);
+
+        // Every five minutes give or take a few seconds, with one missed check-in;
+        // two arrive out of order the way expiry batches deliver them
+        let offsets = [0, 302, 598, 1199, 901, 1803];
+        for (i, offset) in offsets.iter().enumerate() {
+            let found = tracker.record(sample("implant", 443, start + Duration::seconds(*offset), 512 + i as u64));
+            assert_eq!(found.is_some(), i == offsets.len() - 1);
+        }
+        let detection = tracker.detections(10).pop().unwrap();
+        assert_eq!(detection.connections, offsets.len());
+        assert!((detection.interval_secs - 300.0).abs() < 5.0);
+        assert_eq!(detection.first_seen, start);
+        assert_eq!(detection.process.as_deref(), Some("implant"));
+        assert!(detection.description().contains("c2.example:443"));
+
+        // Only reported once per channel
+        assert!(tracker.record(sample("implant", 443, start + Duration::seconds(2700), 512)).is_none());
+        assert_eq!(tracker.detections(10).len(), 1);
+
+        // Irregular browsing to the same host is not a beacon
+        let mut browsing = None;
+        for offset in [0, 4, 90, 95, 700, 1500, 1520, 3400] {
+            browsing = browsing.or(tracker.record(sample("firefox", 443, start + Duration::seconds(offset), 40_000 * (offset as u64 % 7 + 1))));
+        }
+        assert!(browsing.is_none());
+
+        // NTP polls like clockwork on purpose
+        for i in 0..20 {
+            assert!(tracker.record(sample("chronyd", 123, start + Duration::seconds(i * 64), 76)).is_none());
+        }
+
+        tracker.prune(start + Duration::hours(BEACON_HISTORY_WINDOW_HOURS + 1));
+        assert!(tracker.channels.is_empty());
+    }
+}
@@ -0,0 +1,715 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+use tracing::{info, warn, debug};
+use pcap::{Active, Capture, Device, Linktype};
+
+use super::tpacket::TpacketRing;
+use super::supervisor::Supervisor;
+use crate::health::{ComponentState, HealthRegistry};
+
+// How often interfaces are re-listed to pick up hot-plugged or restarted ones
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+const READ_TIMEOUT_MS: i32 = 100;
+
+// Pseudo-devices that are never useful for traffic inspection
+const SKIPPED_INTERFACES: &[&str] = &["any", "nflog", "nfqueue", "dbus-system", "dbus-session"];
+const SKIPPED_PREFIXES: &[&str] = &["bluetooth", "usbmon"];
+
+// DLT_RAW: ring sockets deliver packets starting at the IP header
+const DLT_RAW: i32 = 12;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureBackend {
+    // libpcap, one packet per call
+    Pcap,
+    // AF_PACKET TPACKET_V3 ring, packets read in blocks straight from shared memory
+    Tpacket,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureOptions {
+    pub backend: CaptureBackend,
+    // Explicit BPF expression; takes precedence over the rule-derived one
+    pub bpf_filter: Option<String>,
+    // Only capture traffic the active rules, DNS and TLS inspection can act on
+    pub filter_from_rules: bool,
+    // Packets handed to the processing path per call
+    pub batch_size: usize,
+    // libpcap kernel buffer in bytes
+    pub buffer_size: usize,
+    pub ring_block_size: usize,
+    pub ring_block_count: usize,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            backend: CaptureBackend::Pcap,
+            bpf_filter: None,
+            filter_from_rules: false,
+            batch_size: 64,
+            buffer_size: 8 * 1024 * 1024,
+            ring_block_size: 1024 * 1024,
+            ring_block_count: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InterfaceStats {
+    pub interface: String,
+    pub attached: bool,
+    pub packets: u64,
+    pub bytes: u64,
+    // Dropped by the kernel buffer / by the interface
+    pub dropped: u64,
+    pub if_dropped: u64,
+    pub errors: u64,
+    pub attach_count: u32,
+    pub last_attached: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+// One packet, starting at the IPv4/IPv6 or ARP header
+pub struct CapturedPacket<'a> {
+    pub data: &'a [u8],
+    pub wire_len: usize,
+    // EtherType of `data`: ETHERTYPE_IPV4, ETHERTYPE_IPV6 or ETHERTYPE_ARP
+    pub protocol: u16,
+}
+
+// Called with the interface name and a batch of packets
+pub type PacketHandler = Arc<dyn Fn(&str, &[CapturedPacket<'_>]) + Send + Sync>;
+
+// Offset of the IPv4/IPv6 header for the capture's link type, or None for
+// non-IP frames
+pub fn network_offset(linktype: i32, data: &[u8]) -> Option<usize> {
+    network_layer(linktype, data)
+        .filter(|&(_, protocol)| protocol != ETHERTYPE_ARP)
+        .map(|(offset, _)| offset)
+}
+
+// Offset and EtherType of the IPv4, IPv6 or ARP header for the capture's
+// link type, or None for anything else
+pub fn network_layer(linktype: i32, data: &[u8]) -> Option<(usize, u16)> {
+    let (offset, ethertype) = match linktype {
+        // Ethernet, with up to two VLAN tags
+        1 => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                offset += 4;
+                ethertype = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+            }
+            (offset + 2, Some(ethertype))
+        }
+        // Linux cooked capture v1 ("any")
+        113 => (16, Some(u16::from_be_bytes([*data.get(14)?, *data.get(15)?]))),
+        // Linux cooked capture v2
+        276 => (20, Some(u16::from_be_bytes([*data.first()?, *data.get(1)?]))),
+        // BSD loopback / OpenBSD loopback
+        0 | 108 => (4, None),
+        // Raw IP (tun devices, some VPNs)
+        12 | 14 | 101 | 228 | 229 => (0, None),
+        _ => return None,
+    };
+
+    match ethertype {
+        Some(ETHERTYPE_ARP) if data.len() > offset => return Some((offset, ETHERTYPE_ARP)),
+        Some(ETHERTYPE_IPV4) | Some(ETHERTYPE_IPV6) | None => {}
+        Some(_) => return None,
+    }
+
+    match data.get(offset)? >> 4 {
+        4 => Some((offset, ETHERTYPE_IPV4)),
+        6 => Some((offset, ETHERTYPE_IPV6)),
+        _ => None,
+    }
+}
+
+// Interface selector: an exact name, "any", or a prefix wildcard such as "eth*"
+// ("*" alone selects every real interface)
+pub fn interface_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => {
+            name.starts_with(prefix)
+                && !SKIPPED_INTERFACES.contains(&name)
+                && !SKIPPED_PREFIXES.iter().any(|skip| name.starts_with(skip))
+        }
+        None => pattern == name,
+    }
+}
+
+// Compiles a BPF expression for raw IP packets into the kernel's classic BPF
+// instructions
+pub fn compile_filter(expression: &str) -> Result<Vec<libc::sock_filter>> {
+    let dead = Capture::dead(Linktype(DLT_RAW))?;
+    let program = dead.compile(expression, true)
+        .map_err(|e| anyhow!("Invalid BPF filter '{}': {}", expression, e))?;
+
+    program.get_instructions().iter()
+        .map(|instruction| {
+            // Displayed as "code jt jf k", the same as `tcpdump -ddd`
+            let text = instruction.to_string();
+            let fields: Vec<u32> = text.split_whitespace().filter_map(|f| f.parse().ok()).collect();
+            match fields[..] {
+                [code, jt, jf, k] => Ok(libc::sock_filter { code: code as u16, jt: jt as u8, jf: jf as u8, k }),
+                _ => Err(anyhow!("Unexpected BPF instruction '{}'", text)),
+            }
+        })
+        .collect()
+}
+
+// Packets copied out of libpcap's buffer until a batch is full
+#[derive(Default)]
+struct Batch {
+    data: Vec<u8>,
+    packets: Vec<(usize, usize, usize, u16)>,
+}
+
+impl Batch {
+    fn push(&mut self, data: &[u8], wire_len: usize, protocol: u16) {
+        let start = self.data.len();
+        self.data.extend_from_slice(data);
+        self.packets.push((start, self.data.len(), wire_len, protocol));
+    }
+
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    fn dispatch(&mut self, name: &str, handler: &PacketHandler) {
+        if self.packets.is_empty() {
+            return;
+        }
+        let packets: Vec<CapturedPacket<'_>> = self.packets.iter()
+            .map(|&(start, end, wire_len, protocol)| CapturedPacket { data: &self.data[start..end], wire_len, protocol })
+            .collect();
+        handler(name, &packets);
+        self.data.clear();
+        self.packets.clear();
+    }
+}
+
+enum Source {
+    Pcap { cap: Capture<Active>, linktype: i32 },
+    Ring { ring: TpacketRing, loopback: i32 },
+}
+
+impl Source {
+    fn open(name: &str, options: &CaptureOptions) -> Result<Self> {
+        match options.backend {
+            CaptureBackend::Pcap => {
+                let cap = Capture::from_device(name)?
+                    .promisc(true)
+                    .snaplen(65535)
+                    .buffer_size(options.buffer_size as i32)
+                    .timeout(READ_TIMEOUT_MS)
+                    .open()?;
+                let linktype = cap.get_datalink().0;
+                Ok(Source::Pcap { cap, linktype })
+            }
+            CaptureBackend::Tpacket => {
+                let ring = TpacketRing::open(Some(name), options.ring_block_size, options.ring_block_count)?;
+                let loopback = unsafe { libc::if_nametoindex(c"lo".as_ptr()) } as i32;
+                Ok(Source::Ring { ring, loopback })
+            }
+        }
+    }
+
+    fn set_filter(&mut self, filter: Option<&str>) -> Result<()> {
+        match self {
+            Source::Pcap { cap, .. } => {
+                // An empty expression matches everything
+                cap.filter(filter.unwrap_or(""), true)?;
+                Ok(())
+            }
+            Source::Ring { ring, .. } => match filter {
+                Some(expression) => ring.set_filter(Some(&compile_filter(expression)?)),
+                None => ring.set_filter(None),
+            },
+        }
+    }
+
+    // Reads one batch and hands it to the handler. Returns the packets and
+    // bytes seen on the wire.
+    fn read_batch(&mut self, name: &str, handler: &PacketHandler, batch: &mut Batch, batch_size: usize) -> Result<(u64, u64)> {
+        let (mut packets, mut bytes) = (0u64, 0u64);
+        match self {
+            Source::Pcap { cap, linktype } => {
+                while batch.len() < batch_size {
+                    match cap.next_packet() {
+                        Ok(packet) => {
+                            packets += 1;
+                            bytes += packet.header.len as u64;
+                            if let Some((offset, protocol)) = network_layer(*linktype, packet.data) {
+                                batch.push(&packet.data[offset..], packet.header.len as usize, protocol);
+                            }
+                        }
+                        Err(pcap::Error::TimeoutExpired) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                batch.dispatch(name, handler);
+            }
+            Source::Ring { ring, loopback } => {
+                ring.next_block(READ_TIMEOUT_MS, |block| {
+                    let mut selected = Vec::with_capacity(block.len());
+                    for packet in block {
+                        // Loopback traffic is seen once outgoing and once incoming
+                        if packet.ifindex == *loopback && packet.outgoing {
+                            continue;
+                        }
+                        packets += 1;
+                        bytes += packet.wire_len as u64;
+                        let protocol = match packet.data.first().map(|b| b >> 4) {
+                            _ if packet.protocol == ETHERTYPE_ARP => ETHERTYPE_ARP,
+                            Some(4) => ETHERTYPE_IPV4,
+                            Some(6) => ETHERTYPE_IPV6,
+                            _ => continue,
+                        };
+                        selected.push(CapturedPacket { data: packet.data, wire_len: packet.wire_len, protocol });
+                    }
+                    for chunk in selected.chunks(batch_size.max(1)) {
+                        handler(name, chunk);
+                    }
+                })?;
+            }
+        }
+        Ok((packets, bytes))
+    }
+
+    // Drops since the handle was opened, (kernel buffer, interface)
+    fn drops(&mut self, ring_dropped: &mut u64) -> (u64, u64) {
+        match self {
+            Source::Pcap { cap, .. } => cap.stats()
+                .map(|stats| (stats.dropped as u64, stats.if_dropped as u64))
+                .unwrap_or_default(),
+            Source::Ring { ring, .. } => {
+                // The kernel resets these counters on every read
+                if let Ok((_, dropped)) = ring.stats() {
+                    *ring_dropped += dropped;
+                }
+                (*ring_dropped, 0)
+            }
+        }
+    }
+}
+
+// A capture thread; `running` is cleared to detach it
+struct Attachment {
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Attachment {
+    // The thread ended without detaching itself, i.e. it panicked
+    fn crashed(&self) -> bool {
+        self.thread.is_finished() && self.running.load(Ordering::Relaxed)
+    }
+}
+
+struct Shared {
+    patterns: Vec<String>,
+    options: CaptureOptions,
+    handler: PacketHandler,
+    attached: Mutex<HashMap<String, Attachment>>,
+    watcher: Mutex<Option<JoinHandle<()>>>,
+    stats: Mutex<HashMap<String, InterfaceStats>>,
+    filter: RwLock<Option<String>>,
+    filter_generation: AtomicU64,
+    running: AtomicBool,
+}
+
+// Captures on a set of interfaces at once, one thread per interface. A
+// watcher re-attaches interfaces that go down and come back and attaches new
+// ones matching a wildcard.
+pub struct CaptureSet {
+    shared: Arc<Shared>,
+}
+
+impl CaptureSet {
+    pub fn new(patterns: Vec<String>, options: CaptureOptions, handler: PacketHandler) -> Self {
+        let filter = options.bpf_filter.clone();
+        Self {
+            shared: Arc::new(Shared {
+                patterns,
+                options,
+                handler,
+                attached: Mutex::new(HashMap::new()),
+                watcher: Mutex::new(None),
+                stats: Mutex::new(HashMap::new()),
+                filter: RwLock::new(filter),
+                filter_generation: AtomicU64::new(0),
+                running: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn start(&self) -> Result<()> {
+        if self.shared.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let attached = Self::reconcile(&self.shared);
+        let has_wildcard = self.shared.patterns.iter().any(|p| p.ends_with('*'));
+        if attached == 0 && !has_wildcard {
+            self.shared.running.store(false, Ordering::SeqCst);
+            return Err(anyhow!("None of the interfaces {:?} could be opened", self.shared.patterns));
+        }
+
+        if let Err(e) = Self::spawn_watcher(&self.shared) {
+            self.stop();
+            return Err(e);
+        }
+
+        info!("Packet capture started on {:?} ({:?} backend)", self.shared.patterns, self.shared.options.backend);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        if let Ok(attached) = self.shared.attached.lock() {
+            for attachment in attached.values() {
+                attachment.running.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Replaces the BPF filter on every attached interface; None captures everything
+    pub fn set_filter(&self, filter: Option<String>) -> Result<()> {
+        if let Some(ref expression) = filter {
+            // Reject bad expressions here rather than in every capture thread
+            compile_filter(expression)?;
+        }
+        let mut current = self.shared.filter.write()
+            .map_err(|_| anyhow!("Failed to acquire capture filter write lock"))?;
+        if *current != filter {
+            info!("Capture filter set to {}", filter.as_deref().unwrap_or("<none>"));
+            *current = filter;
+            self.shared.filter_generation.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    pub fn filter(&self) -> Option<String> {
+        self.shared.filter.read().ok().and_then(|filter| filter.clone())
+    }
+
+    pub fn interface_stats(&self) -> Vec<InterfaceStats> {
+        let mut stats: Vec<InterfaceStats> = self.shared.stats.lock()
+            .map(|stats| stats.values().cloned().collect())
+            .unwrap_or_default();
+        stats.sort_by(|a, b| a.interface.cmp(&b.interface));
+        stats
+    }
+
+    // Reports which interfaces are being captured on to `registry`
+    pub fn register_health(&self, registry: &HealthRegistry) {
+        let shared = Arc::clone(&self.shared);
+        registry.probe("pcap", false, move || {
+            if !shared.running.load(Ordering::Relaxed) {
+                return (ComponentState::Down, "capture stopped".to_string());
+            }
+            let mut interfaces: Vec<String> = shared.attached.lock()
+                .map(|attached| attached.keys().cloned().collect())
+                .unwrap_or_default();
+            interfaces.sort();
+            if interfaces.is_empty() {
+                (ComponentState::Degraded, format!("no interface matching {:?} attached", shared.patterns))
+            } else {
+                (ComponentState::Up, format!("capturing on {}", interfaces.join(", ")))
+            }
+        });
+    }
+
+    // Restarts the interface watcher and interfaces whose capture thread
+    // panicked. Interfaces that went away are re-attached by the watcher itself.
+    pub fn supervise(&self, supervisor: &Supervisor) {
+        let shared = Arc::clone(&self.shared);
+        let restart_shared = Arc::clone(&self.shared);
+        supervisor.supervise("pcap", None,
+            move || !shared.running.load(Ordering::Relaxed) || (Self::watcher_alive(&shared) && Self::crashed_interfaces(&shared).is_empty()),
+            move || Self::recover(&restart_shared));
+    }
+
+    fn spawn_watcher(shared: &Arc<Shared>) -> Result<()> {
+        let watcher_shared = Arc::clone(shared);
+        let watcher = thread::Builder::new()
+            .name("flux-capture-watch".to_string())
+            .spawn(move || {
+                while watcher_shared.running.load(Ordering::Relaxed) {
+                    thread::sleep(WATCH_INTERVAL);
+                    if watcher_shared.running.load(Ordering::Relaxed) {
+                        Self::reconcile(&watcher_shared);
+                    }
+                }
+            })?;
+        *shared.watcher.lock().map_err(|_| anyhow!("Failed to acquire capture watcher lock"))? = Some(watcher);
+        Ok(())
+    }
+
+    fn watcher_alive(shared: &Shared) -> bool {
+        shared.watcher.lock()
+            .map(|watcher| watcher.as_ref().is_some_and(|watcher| !watcher.is_finished()))
+            .unwrap_or(false)
+    }
+
+    fn crashed_interfaces(shared: &Shared) -> Vec<String> {
+        shared.attached.lock()
+            .map(|attached| attached.iter()
+                .filter(|(_, attachment)| attachment.crashed())
+                .map(|(name, _)| name.clone())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn recover(shared: &Arc<Shared>) -> Result<()> {
+        if !shared.running.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        for name in Self::crashed_interfaces(shared) {
+            warn!("Packet capture thread on {} died, re-attaching", name);
+            if let Ok(mut attached) = shared.attached.lock() {
+                attached.remove(&name);
+            }
+            Self::update_stats(shared, &name, |entry| {
+                entry.attached = false;
+                entry.errors += 1;
+                entry.last_error = Some("capture thread panicked".to_string());
+            });
+        }
+        if !Self::watcher_alive(shared) {
+            Self::spawn_watcher(shared)?;
+        }
+        Self::reconcile(shared);
+        Ok(())
+    }
+
+    // Interfaces that should be captured on right now
+    fn wanted_interfaces(patterns: &[String]) -> Vec<String> {
+        let devices = match Device::list() {
+            Ok(devices) => devices,
+            Err(e) => {
+                warn!("Failed to list capture devices: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let mut wanted: Vec<String> = devices.into_iter()
+            .filter(|device| device.name == "any" || (device.flags.is_up() && device.flags.is_running()))
+            .map(|device| device.name)
+            .filter(|name| patterns.iter().any(|pattern| interface_matches(pattern, name)))
+            .collect();
+        // "any" is not always listed but can always be opened
+        if patterns.iter().any(|p| p == "any") && !wanted.iter().any(|n| n == "any") {
+            wanted.push("any".to_string());
+        }
+        wanted
+    }
+
+    // Attaches wanted interfaces that are not being captured on and detaches the
+    // ones that are no longer wanted. Returns how many are attached afterwards.
+    fn reconcile(shared: &Arc<Shared>) -> usize {
+        let wanted = Self::wanted_interfaces(&shared.patterns);
+        let mut current = match shared.attached.lock() {
+            Ok(current) => current,
+            Err(_) => return 0,
+        };
+
+        // Crashed threads stay listed until the supervisor restarts them
+        current.retain(|name, attachment| {
+            let keep = wanted.contains(name) && attachment.running.load(Ordering::Relaxed);
+            if !keep {
+                attachment.running.store(false, Ordering::Relaxed);
+            }
+            keep
+        });
+
+        for name in wanted {
+            if current.contains_key(&name) {
+                continue;
+            }
+            match Self::attach(&name, shared) {
+                Ok(attachment) => {
+                    current.insert(name, attachment);
+                }
+                Err(e) => {
+                    debug!("Failed to open {}: {}", name, e);
+                    Self::update_stats(shared, &name, |entry| {
+                        entry.errors += 1;
+                        entry.last_error = Some(e.to_string());
+                    });
+                }
+            }
+        }
+        current.len()
+    }
+
+    fn attach(name: &str, shared: &Arc<Shared>) -> Result<Attachment> {
+        let mut source = Source::open(name, &shared.options)?;
+
+        // Drop counters restart with every handle, so carry the old totals over
+        let mut base = (0, 0);
+        Self::update_stats(shared, name, |entry| {
+            entry.attached = true;
+            entry.attach_count += 1;
+            entry.last_attached = Some(Utc::now());
+            base = (entry.dropped, entry.if_dropped);
+        });
+        info!("Packet capture attached to {}", name);
+
+        let flag = Arc::new(AtomicBool::new(true));
+        let interface_running = Arc::clone(&flag);
+        let name = name.to_string();
+        let shared = Arc::clone(shared);
+
+        let thread = thread::spawn(move || {
+            let batch_size = shared.options.batch_size.max(1);
+            let mut batch = Batch::default();
+            let (mut packets, mut bytes) = (0u64, 0u64);
+            let mut ring_dropped = 0u64;
+            let mut filter_generation = None;
+            let mut last_flush = Instant::now();
+            let mut failure = None;
+
+            while interface_running.load(Ordering::Relaxed) && shared.running.load(Ordering::Relaxed) {
+                let generation = shared.filter_generation.load(Ordering::SeqCst);
+                if filter_generation != Some(generation) {
+                    filter_generation = Some(generation);
+                    let filter = shared.filter.read().ok().and_then(|filter| filter.clone());
+                    if let Err(e) = source.set_filter(filter.as_deref()) {
+                        warn!("Failed to apply capture filter on {}: {}", name, e);
+                        Self::update_stats(&shared, &name, |entry| {
+                            entry.errors += 1;
+                            entry.last_error = Some(e.to_string());
+                        });
+                    }
+                }
+
+                match source.read_batch(&name, &shared.handler, &mut batch, batch_size) {
+                    Ok((batch_packets, batch_bytes)) => {
+                        packets += batch_packets;
+                        bytes += batch_bytes;
+                    }
+                    Err(e) => {
+                        // Typically the interface went away; the watcher re-attaches it
+                        failure = Some(e.to_string());
+                        break;
+                    }
+                }
+
+                if last_flush.elapsed() >= STATS_INTERVAL {
+                    let drops = source.drops(&mut ring_dropped);
+                    Self::flush_stats(&shared, &name, &mut packets, &mut bytes, base, drops, None, true);
+                    last_flush = Instant::now();
+                }
+            }
+
+            if let Some(ref e) = failure {
+                warn!("Packet capture on {} stopped: {}", name, e);
+            }
+            let drops = source.drops(&mut ring_dropped);
+            Self::flush_stats(&shared, &name, &mut packets, &mut bytes, base, drops, failure, false);
+            interface_running.store(false, Ordering::Relaxed);
+            if let Ok(mut attached) = shared.attached.lock() {
+                if attached.get(&name).is_some_and(|attachment| Arc::ptr_eq(&attachment.running, &interface_running)) {
+                    attached.remove(&name);
+                }
+            }
+            info!("Packet capture detached from {}", name);
+        });
+
+        Ok(Attachment { running: flag, thread })
+    }
+
+    fn update_stats<F: FnOnce(&mut InterfaceStats)>(shared: &Shared, name: &str, f: F) {
+        if let Ok(mut stats) = shared.stats.lock() {
+            let entry = stats.entry(name.to_string()).or_insert_with(|| InterfaceStats { interface: name.to_string(), ..Default::default() });
+            f(entry);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn flush_stats(
+        shared: &Shared,
+        name: &str,
+        packets: &mut u64,
+        bytes: &mut u64,
+        base: (u64, u64),
+        drops: (u64, u64),
+        error: Option<String>,
+        attached: bool,
+    ) {
+        Self::update_stats(shared, name, |entry| {
+            entry.packets += *packets;
+            entry.bytes += *bytes;
+            entry.dropped = base.0 + drops.0;
+            entry.if_dropped = base.1 + drops.1;
+            if error.is_some() {
+                entry.errors += 1;
+                entry.last_error = error;
+            }
+            entry.attached = attached;
+        });
+        *packets = 0;
+        *bytes = 0;
+    }
+}
+
+impl Drop for CaptureSet {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_offsets_and_patterns() {
+        let mut ethernet = vec![0u8; 34];
+        ethernet[12..14].copy_from_slice(&[0x08, 0x00]);
+        ethernet[14] = 0x45;
+        assert_eq!(network_offset(1, &ethernet), Some(14));
+
+        let mut vlan = vec![0u8; 38];
+        vlan[12..14].copy_from_slice(&[0x81, 0x00]);
+        vlan[16..18].copy_from_slice(&[0x86, 0xdd]);
+        vlan[18] = 0x60;
+        assert_eq!(network_offset(1, &vlan), Some(18));
+
+        let mut arp = ethernet.clone();
+        arp[12..14].copy_from_slice(&[0x08, 0x06]);
+        assert_eq!(network_offset(1, &arp), None);
+        assert_eq!(network_layer(1, &arp), Some((14, ETHERTYPE_ARP)));
+        assert_eq!(network_layer(1, &vlan), Some((18, ETHERTYPE_IPV6)));
+
+        let mut cooked = vec![0u8; 36];
+        cooked[14..16].copy_from_slice(&[0x08, 0x00]);
+        cooked[16] = 0x45;
+        assert_eq!(network_offset(113, &cooked), Some(16));
+        assert_eq!(network_offset(101, &[0x45, 0, 0, 20]), Some(0));
+
+        assert!(interface_matches("eth*", "eth1"));
+        assert!(interface_matches("*", "wlan0"));
+        assert!(!interface_matches("*", "any"));
+        assert!(!interface_matches("*", "usbmon0"));
+        assert!(interface_matches("any", "any"));
+        assert!(!interface_matches("eth0", "eth1"));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use serde::{Deserialize, Serialize};
+
+pub use crate::config::DEFAULT_DECISION_CACHE_CAPACITY;
+
+// The executable as the kernel handed it over, plus the policy version the
+// verdict was reached under. Replacing or touching the binary changes the
+// identity, and so does rewriting it: mtime can be set back from userspace,
+// ctime cannot. An entry computed while the policy changed keeps the old
+// version and can never be hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DecisionKey {
+    dev: u64,
+    inode: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+    policy_version: u64,
+}
+
+impl DecisionKey {
+    fn of(metadata: &Metadata, policy_version: u64) -> Self {
+        Self {
+            dev: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec(),
+            policy_version,
+        }
+    }
+}
+
+// What evaluating an executed file found about the file itself. Executable
+// rules and behavior patterns depend on the process and are not cached
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecVerdict {
+    pub hash: String,
+    // Some(false) when the hash is denied by policy, Some(true) when allowed
+    pub hash_rule: Option<bool>,
+}
+
+struct CacheEntry {
+    verdict: ExecVerdict,
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecisionCacheStats {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    // Entries dropped because the policy changed
+    pub invalidations: u64,
+    pub evictions: u64,
+    pub hit_rate: f64,
+}
+
+// Exec verdicts by file identity and policy version, bounded by least-recent
+// use, so a binary executed over and over is hashed and checked once
+pub struct DecisionCache {
+    entries: HashMap<DecisionKey, CacheEntry>,
+    // Use counter -> key, oldest first
+    recency: BTreeMap<u64, DecisionKey>,
+    clock: u64,
+    capacity: usize,
+    stats: DecisionCacheStats,
+}
+
+impl DecisionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            capacity: capacity.max(1),
+            stats: DecisionCacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, metadata: &Metadata, policy_version: u64) -> Option<ExecVerdict> {
+        let key = DecisionKey::of(metadata, policy_version);
+        if !self.entries.contains_key(&key) {
+            self.stats.misses += 1;
+            return None;
+        }
+
+        self.stats.hits += 1;
+        let last_used = self.tick();
+        let entry = self.entries.get_mut(&key)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = last_used;
+        self.recency.insert(last_used, key);
+        Some(entry.verdict.clone())
+    }
+
+    // The metadata should come from the event's descriptor, so the entry
+    // describes exactly the file that was evaluated
+    pub fn insert(&mut self, metadata: &Metadata, policy_version: u64, verdict: ExecVerdict) {
+        let key = DecisionKey::of(metadata, policy_version);
+        if let Some(entry) = self.entries.remove(&key) {
+            self.recency.remove(&entry.last_used);
+        }
+        let last_used = self.tick();
+        self.entries.insert(key, CacheEntry { verdict, last_used });
+        self.recency.insert(last_used, key);
+        self.evict_to(self.capacity);
+    }
+
+    // Drops every verdict; called whenever the policy changes
+    pub fn invalidate(&mut self) {
+        self.stats.invalidations += self.entries.len() as u64;
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict_to(self.capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> DecisionCacheStats {
+        let lookups = self.stats.hits + self.stats.misses;
+        DecisionCacheStats {
+            capacity: self.capacity,
+            entries: self.entries.len(),
+            hit_rate: if lookups == 0 { 0.0 } else { self.stats.hits as f64 / lookups as f64 },
+            ..self.stats.clone()
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn evict_to(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let Some((_, key)) = self.recency.pop_first() else { break };
+            self.entries.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl Default for DecisionCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_DECISION_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_decision_cache_keys_and_invalidation() {
+        let dir = std::env::temp_dir().join(format!("flux-decision-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = (0..3).map(|i| dir.join(format!("bin{}", i))).collect();
+        for path in &paths {
+            fs::write(path, b"original").unwrap();
+        }
+        let verdict = |hash: &str| ExecVerdict { hash: hash.to_string(), hash_rule: None };
+
+        // Linking changes the ctime, so it comes first
+        fs::hard_link(&paths[0], dir.join("link")).unwrap();
+        let mut cache = DecisionCache::new(2);
+        let metadata = fs::metadata(&paths[0]).unwrap();
+        assert_eq!(cache.get(&metadata, 1), None);
+        cache.insert(&metadata, 1, verdict("hash0"));
+        assert_eq!(cache.get(&metadata, 1), Some(verdict("hash0")));
+        // A verdict from another policy version does not apply
+        assert_eq!(cache.get(&metadata, 2), None);
+
+        // A hard link is the same file
+        assert!(cache.get(&fs::metadata(dir.join("link")).unwrap(), 1).is_some());
+
+        // Touching the binary changes its identity
+        let touched = fs::File::options().write(true).open(&paths[0]).unwrap();
+        touched.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+        assert_eq!(cache.get(&fs::metadata(&paths[0]).unwrap(), 1), None);
+
+        cache.insert(&fs::metadata(&paths[1]).unwrap(), 1, verdict("hash1"));
+        cache.insert(&fs::metadata(&paths[2]).unwrap(), 1, verdict("hash2"));
+        assert_eq!(cache.len(), 2);
+        cache.invalidate();
+        assert!(cache.is_empty());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.invalidations, 2);
+        assert_eq!(stats.hit_rate, 0.4);
+
+        // Rewritten with the mtime set back: the ctime still moves
+        let metadata = fs::metadata(&paths[1]).unwrap();
+        cache.insert(&metadata, 1, verdict("hash1"));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&paths[1], b"modified").unwrap();
+        fs::File::options().write(true).open(&paths[1]).unwrap().set_modified(metadata.modified().unwrap()).unwrap();
+        let rewritten = fs::metadata(&paths[1]).unwrap();
+        assert_eq!(rewritten.mtime_nsec(), metadata.mtime_nsec());
+        assert_eq!(cache.get(&rewritten, 1), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+// DNS wire-format parsing (RFC 1035) for passively captured queries and responses
+
+const TYPE_A: u16 = 1;
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+
+// Compression pointers followed before a name is considered malformed
+const MAX_POINTER_JUMPS: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsRecordData {
+    Address(IpAddr),
+    // CNAME, NS and PTR targets
+    Name(String),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsAnswer {
+    pub name: String,
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub is_response: bool,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsAnswer>,
+}
+
+impl DnsMessage {
+    pub fn query_name(&self) -> Option<&str> {
+        self.questions.first().map(|q| q.name.as_str())
+    }
+
+    pub fn resolved_ips(&self) -> Vec<IpAddr> {
+        self.answers
+            .iter()
+            .filter_map(|a| match a.data {
+                DnsRecordData::Address(ip) => Some(ip),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Lowest TTL among the address records
+    pub fn min_ttl(&self) -> Option<u32> {
+        self.answers
+            .iter()
+            .filter(|a| matches!(a.data, DnsRecordData::Address(_)))
+            .map(|a| a.ttl)
+            .min()
+    }
+}
+
+pub fn parse(data: &[u8]) -> Option<DnsMessage> {
+    if data.len() < 12 {
+        return None;
+    }
+
+    let id = u16::from_be_bytes([data[0], data[1]]);
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    let qdcount = u16::from_be_bytes([data[4], data[5]]);
+    let ancount = u16::from_be_bytes([data[6], data[7]]);
+
+    let mut offset = 12;
+    let mut questions = Vec::with_capacity(qdcount.min(8) as usize);
+    for _ in 0..qdcount {
+        let (name, next) = read_name(data, offset)?;
+        let qtype = read_u16(data, next)?;
+        offset = next + 4; // type + class
+        questions.push(DnsQuestion { name, qtype });
+    }
+
+    // Truncated answer sections still yield the records parsed so far
+    let mut answers = Vec::new();
+    for _ in 0..ancount {
+        let (name, next) = match read_name(data, offset) {
+            Some(r) => r,
+            None => break,
+        };
+        let (rtype, ttl, rdlength) = match (read_u16(data, next), read_u32(data, next + 4), read_u16(data, next + 8)) {
+            (Some(t), Some(ttl), Some(len)) => (t, ttl, len as usize),
+            _ => break,
+        };
+        let rdata_start = next + 10;
+        let rdata = match data.get(rdata_start..rdata_start + rdlength) {
+            Some(rdata) => rdata,
+            None => break,
+        };
+
+        let record = match rtype {
+            TYPE_A if rdlength == 4 => DnsRecordData::Address(IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]))),
+            TYPE_AAAA if rdlength == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                DnsRecordData::Address(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            TYPE_CNAME | TYPE_NS | TYPE_PTR => match read_name(data, rdata_start) {
+                Some((target, _)) => DnsRecordData::Name(target),
+                None => DnsRecordData::Other,
+            },
+            _ => DnsRecordData::Other,
+        };
+
+        answers.push(DnsAnswer { name, rtype, ttl, data: record });
+        offset = rdata_start + rdlength;
+    }
+
+    Some(DnsMessage {
+        id,
+        is_response: flags & 0x8000 != 0,
+        rcode: (flags & 0x000f) as u8,
+        questions,
+        answers,
+    })
+}
+
+// Read a possibly compressed name; returns it and the offset just past it
+fn read_name(data: &[u8], start: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut offset = start;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *data.get(offset)? as usize;
+        match len & 0xc0 {
+            0x00 => {
+                if len == 0 {
+                    offset += 1;
+                    break;
+                }
+                let label = data.get(offset + 1..offset + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).to_lowercase());
+                offset += 1 + len;
+            }
+            0xc0 => {
+                let pointer = ((len & 0x3f) << 8) | *data.get(offset + 1)? as usize;
+                if end.is_none() {
+                    end = Some(offset + 2);
+                }
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS || pointer >= data.len() {
+                    return None;
+                }
+                offset = pointer;
+            }
+            _ => return None,
+        }
+    }
+
+    Some((labels.join("."), end.unwrap_or(offset)))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn query_type_name(qtype: u16) -> String {
+    match qtype {
+        TYPE_A => "A".to_string(),
+        TYPE_NS => "NS".to_string(),
+        TYPE_CNAME => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        TYPE_PTR => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        TYPE_AAAA => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        65 => "HTTPS".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+pub fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+// Addresses a public name should never resolve to. 0.0.0.0 is left out on
+// purpose: it is the usual sinkhole answer from blocking resolvers.
+pub fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || (v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64) // CGNAT 100.64/10
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || (first & 0xfe00) == 0xfc00 // Unique local
+                || (first & 0xffc0) == 0xfe80 // Link local
+                || v6.to_ipv4_mapped().is_some_and(|v4| is_internal_address(IpAddr::V4(v4)))
+        }
+    }
+}
+
+// Names that legitimately resolve to internal addresses
+pub fn is_local_domain(domain: &str) -> bool {
+    const LOCAL_SUFFIXES: [&str; 8] = [
+        "localhost", "local", "lan", "home", "internal", "localdomain", "home.arpa", "in-addr.arpa",
+    ];
+
+    !domain.contains('.')
+        || LOCAL_SUFFIXES.iter().any(|suffix| domain == *suffix || domain.ends_with(&format!(".{}", suffix)))
+        || domain.ends_with(".ip6.arpa")
+}
+
+// Fast-flux heuristics: many addresses across many networks behind short TTLs
+const FAST_FLUX_WINDOW: Duration = Duration::from_secs(3600);
+const FAST_FLUX_MIN_IPS: usize = 10;
+const FAST_FLUX_MIN_NETWORKS: usize = 5;
+const FAST_FLUX_MAX_TTL: u32 = 300;
+const FLUX_TRACKER_MAX_DOMAINS: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct FastFluxObservation {
+    pub domain: String,
+    pub unique_ips: Vec<IpAddr>,
+    pub unique_networks: usize,
+    pub min_ttl: u32,
+    pub observed_for: Duration,
+}
+
+#[derive(Debug)]
+struct FluxHistory {
+    first_seen: Instant,
+    ips: HashSet<IpAddr>,
+    min_ttl: u32,
+    reported: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct FluxTracker {
+    domains: HashMap<String, FluxHistory>,
+}
+
+impl FluxTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a response; returns an observation the first time a domain crosses the thresholds
+    pub fn record(&mut self, domain: &str, ips: &[IpAddr], ttl: u32, now: Instant) -> Option<FastFluxObservation> {
+        if ips.is_empty() {
+            return None;
+        }
+
+        if self.domains.len() >= FLUX_TRACKER_MAX_DOMAINS && !self.domains.contains_key(domain) {
+            self.prune(now);
+            if self.domains.len() >= FLUX_TRACKER_MAX_DOMAINS {
+                return None;
+            }
+        }
+
+        let history = self.domains.entry(domain.to_string()).or_insert_with(|| FluxHistory {
+            first_seen: now,
+            ips: HashSet::new(),
+            min_ttl: ttl,
+            reported: false,
+        });
+
+        if now.duration_since(history.first_seen) > FAST_FLUX_WINDOW {
+            *history = FluxHistory { first_seen: now, ips: HashSet::new(), min_ttl: ttl, reported: false };
+        }
+
+        history.ips.extend(ips.iter().copied());
+        history.min_ttl = history.min_ttl.min(ttl);
+
+        if history.reported || history.min_ttl > FAST_FLUX_MAX_TTL || history.ips.len() < FAST_FLUX_MIN_IPS {
+            return None;
+        }
+
+        let networks: HashSet<_> = history.ips.iter().map(|ip| network_of(*ip)).collect();
+        if networks.len() < FAST_FLUX_MIN_NETWORKS {
+            return None;
+        }
+
+        history.reported = true;
+        Some(FastFluxObservation {
+            domain: domain.to_string(),
+            unique_ips: history.ips.iter().copied().collect(),
+            unique_networks: networks.len(),
+            min_ttl: history.min_ttl,
+            observed_for: now.duration_since(history.first_seen),
+        })
+    }
+
+    pub fn prune(&mut self, now: Instant) {
+        self.domains.retain(|_, h| now.duration_since(h.first_seen) <= FAST_FLUX_WINDOW);
+    }
+}
+
+// /16 for IPv4, /32 for IPv6: roughly one hosting network each
+fn network_of(ip: IpAddr) -> (u8, u32) {
+    match ip {
+        IpAddr::V4(v4) => (4, u32::from(v4) >> 16),
+        IpAddr::V6(v6) => (6, (u128::from(v6) >> 96) as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(name: &str, ips: &[Ipv4Addr], ttl: u32) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, (ips.len() + 1) as u8, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+
+        // CNAME back to the question name via a compression pointer, then the A records
+        packet.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1]);
+        packet.extend_from_slice(&ttl.to_be_bytes());
+        packet.extend_from_slice(&[0, 2, 0xc0, 12]);
+        for ip in ips {
+            packet.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+            packet.extend_from_slice(&ttl.to_be_bytes());
+            packet.extend_from_slice(&[0, 4]);
+            packet.extend_from_slice(&ip.octets());
+        }
+        packet
+    }
+
+    #[test]
+    fn test_parse_response_and_rebinding() {
+        let packet = response("Rebind.Example.com", &[Ipv4Addr::new(192, 168, 0, 1), Ipv4Addr::new(93, 184, 216, 34)], 60);
+        let message = parse(&packet).unwrap();
+
+        assert!(message.is_response);
+        assert_eq!(message.query_name(), Some("rebind.example.com"));
+        assert_eq!(message.answers.len(), 3);
+        assert_eq!(message.answers[0].data, DnsRecordData::Name("rebind.example.com".to_string()));
+        assert_eq!(message.min_ttl(), Some(60));
+
+        let internal: Vec<_> = message.resolved_ips().into_iter().filter(|ip| is_internal_address(*ip)).collect();
+        assert_eq!(internal, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))]);
+        assert!(!is_local_domain("rebind.example.com"));
+        assert!(is_local_domain("printer.local") && is_local_domain("nas"));
+        assert!(!is_internal_address("0.0.0.0".parse().unwrap()));
+
+        // Pointer loops are rejected rather than followed forever
+        assert!(read_name(&[0xc0, 0x00], 0).is_none());
+    }
+
+    #[test]
+    fn test_fast_flux_tracker() {
+        let mut tracker = FluxTracker::new();
+        let now = Instant::now();
+
+        // Many addresses in one network is load balancing, not flux
+        let same_net: Vec<IpAddr> = (1..=20).map(|i| IpAddr::V4(Ipv4Addr::new(93, 184, 0, i))).collect();
+        assert!(tracker.record("cdn.example.com", &same_net, 60, now).is_none());
+
+        let mut observation = None;
+        for i in 0..12u8 {
+            let ip = IpAddr::V4(Ipv4Addr::new(20 + i, 10 + i, 1, 1));
+            observation = observation.or(tracker.record("flux.example.net", &[ip], 120, now));
+        }
+        let observation = observation.unwrap();
+        assert_eq!(observation.unique_ips.len(), FAST_FLUX_MIN_IPS);
+        assert!(observation.unique_networks >= FAST_FLUX_MIN_NETWORKS);
+
+        // Reported only once per window
+        let ip = IpAddr::V4(Ipv4Addr::new(99, 1, 1, 1));
+        assert!(tracker.record("flux.example.net", &[ip], 120, now).is_none());
+    }
+}
@@ -1,32 +1,61 @@
 use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
-use std::sync::{Arc, RwLock};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use super::dns;
+use super::netfilter::NetfilterManager;
+use crate::health::{ComponentState, HealthRegistry};
 // use trust_dns_resolver::TokioAsyncResolver;
 // use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 
 // DNS filtering and monitoring module
 // Provides DNS request interception, filtering, and caching
 
+// Large enough for EDNS0 answers
+const MAX_DNS_PACKET: usize = 4096;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_REFUSED: u8 = 5;
+// "FLUX"
+pub const DEFAULT_UPSTREAM_MARK: u32 = 0x464c_5558;
+// Queries sent from one upstream socket before the next goes out from a
+// fresh one, so a spoofer has to guess the source port as well as the id
+const QUERIES_PER_SOCKET: u32 = 32;
+
+// Cheap to clone; every clone shares the same lists, cache and upstream
+// connections, so the proxy can hand one to each in-flight query
 #[derive(Clone)]
 pub struct DnsFilter {
+    inner: Arc<DnsFilterInner>,
+}
+
+struct DnsFilterInner {
     // Filtering lists
-    blacklist_domains: Arc<RwLock<HashSet<String>>>,
-    whitelist_domains: Arc<RwLock<HashSet<String>>>,
-    blacklist_patterns: Arc<RwLock<Vec<regex::Regex>>>,
+    blacklist_domains: RwLock<HashSet<String>>,
+    whitelist_domains: RwLock<HashSet<String>>,
+    blacklist_patterns: RwLock<Vec<regex::Regex>>,
     
     // Caching
-    cache: Arc<RwLock<DnsCache>>,
+    cache: RwLock<DnsCache>,
     
     // Configuration
-    config: Arc<RwLock<DnsFilterConfig>>,
+    config: RwLock<DnsFilterConfig>,
     
     // Statistics
-    stats: Arc<RwLock<DnsStats>>,
+    stats: RwLock<DnsStats>,
+    
+    // One shared socket per upstream server
+    upstreams: UpstreamPool,
+    
+    // Set while `serve` is answering queries
+    listening: AtomicBool,
 }
 
 #[derive(Debug, Clone)]
@@ -35,10 +64,16 @@ pub struct DnsFilterConfig {
     pub block_mode: BlockMode,
     pub cache_ttl: Duration,
     pub upstream_servers: Vec<SocketAddr>,
+    // How long to wait on one upstream before trying the next
+    pub upstream_timeout: Duration,
     pub listen_port: u16,
     pub log_queries: bool,
     pub block_suspicious_tlds: bool,
     pub block_dga_domains: bool,
+    // Install nftables rules sending this host's DNS to the proxy
+    pub transparent_redirect: bool,
+    // Firewall mark on upstream queries so the redirect lets them through
+    pub upstream_mark: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -56,20 +91,13 @@ struct DnsCache {
 
 #[derive(Debug, Clone)]
 struct CacheEntry {
-    domain: String,
-    result: DnsResult,
+    // Upstream answer as received; the transaction id is rewritten per client
+    response: Vec<u8>,
     created_at: Instant,
     ttl: Duration,
     hit_count: u64,
 }
 
-#[derive(Debug, Clone)]
-enum DnsResult {
-    Resolved(Vec<IpAddr>),
-    Blocked(String), // Reason
-    Error(String),
-}
-
 #[derive(Debug, Clone, Default)]
 pub struct DnsStats {
     pub total_queries: u64,
@@ -88,6 +116,9 @@ pub struct DnsEvent {
     pub source: SocketAddr,
     pub action: DnsAction,
     pub reason: Option<String>,
+    // From the response sent back to the client, when there was one
+    pub answers: Vec<IpAddr>,
+    pub rcode: Option<u8>,
 }
 
 #[derive(Debug, Clone)]
@@ -108,6 +139,22 @@ pub enum DnsAction {
     Error,
 }
 
+// Clears the listening flag however `serve` ends, including cancellation
+struct ListeningGuard<'a>(&'a AtomicBool);
+
+impl<'a> ListeningGuard<'a> {
+    fn new(flag: &'a AtomicBool) -> Self {
+        flag.store(true, Ordering::Release);
+        Self(flag)
+    }
+}
+
+impl Drop for ListeningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
 impl DnsFilter {
     pub fn new() -> Result<Self> {
         let config = DnsFilterConfig {
@@ -119,27 +166,70 @@ impl DnsFilter {
                 "8.8.4.4:53".parse()?,
                 "1.1.1.1:53".parse()?,
             ],
+            upstream_timeout: Duration::from_secs(2),
             listen_port: 5353, // Alternative DNS port
             log_queries: true,
             block_suspicious_tlds: true,
             block_dga_domains: true,
+            transparent_redirect: false,
+            upstream_mark: DEFAULT_UPSTREAM_MARK,
         };
         
-        Ok(Self {
-            blacklist_domains: Arc::new(RwLock::new(HashSet::new())),
-            whitelist_domains: Arc::new(RwLock::new(HashSet::new())),
-            blacklist_patterns: Arc::new(RwLock::new(Vec::new())),
-            cache: Arc::new(RwLock::new(DnsCache {
-                entries: HashMap::new(),
-                max_entries: 10000,
-            })),
-            config: Arc::new(RwLock::new(config)),
-            stats: Arc::new(RwLock::new(DnsStats::default())),
-        })
+        Ok(Self::with_config(config))
+    }
+    
+    pub fn with_config(config: DnsFilterConfig) -> Self {
+        Self {
+            inner: Arc::new(DnsFilterInner {
+                blacklist_domains: RwLock::new(HashSet::new()),
+                whitelist_domains: RwLock::new(HashSet::new()),
+                blacklist_patterns: RwLock::new(Vec::new()),
+                cache: RwLock::new(DnsCache {
+                    entries: HashMap::new(),
+                    max_entries: 10000,
+                }),
+                config: RwLock::new(config),
+                stats: RwLock::new(DnsStats::default()),
+                upstreams: UpstreamPool::default(),
+                listening: AtomicBool::new(false),
+            }),
+        }
+    }
+    
+    pub fn is_listening(&self) -> bool {
+        self.inner.listening.load(Ordering::Acquire)
+    }
+    
+    // Reports whether the proxy is answering queries to `registry`
+    pub fn register_health(&self, registry: &HealthRegistry) {
+        let filter = self.clone();
+        registry.probe("dns_proxy", false, move || {
+            let config = filter.config();
+            if !config.enabled {
+                (ComponentState::Disabled, "DNS filtering disabled".to_string())
+            } else if filter.is_listening() {
+                (ComponentState::Up, format!("listening on 127.0.0.1:{}", config.listen_port))
+            } else {
+                (ComponentState::Down, "not listening".to_string())
+            }
+        });
+    }
+    
+    pub fn config(&self) -> DnsFilterConfig {
+        self.inner.config.read().map(|config| config.clone()).unwrap_or_else(|e| e.into_inner().clone())
+    }
+    
+    // Upstream sockets for servers no longer listed are closed
+    pub fn set_config(&self, config: DnsFilterConfig) -> Result<()> {
+        self.inner.upstreams.retain(&config.upstream_servers);
+        let mut current = self.inner.config.write()
+            .map_err(|_| anyhow!("Failed to acquire config write lock"))?;
+        *current = config;
+        Ok(())
     }
     
     pub fn load_default_blacklists(&self) -> Result<()> {
-        let mut blacklist = self.blacklist_domains.write()
+        let mut blacklist = self.inner.blacklist_domains.write()
             .map_err(|_| anyhow!("Failed to acquire blacklist write lock"))?;
         
         // Add known malicious domains
@@ -168,7 +258,7 @@ impl DnsFilter {
         }
         
         // Add pattern-based filtering
-        let mut patterns = self.blacklist_patterns.write()
+        let mut patterns = self.inner.blacklist_patterns.write()
             .map_err(|_| anyhow!("Failed to acquire patterns write lock"))?;
         
         // DGA (Domain Generation Algorithm) patterns
@@ -176,7 +266,7 @@ impl DnsFilter {
         patterns.push(regex::Regex::new(r"^[0-9a-f]{16,}\.")?); // Hex strings
         
         // Suspicious TLD patterns
-        if self.config.read().unwrap().block_suspicious_tlds {
+        if self.inner.config.read().unwrap().block_suspicious_tlds {
             patterns.push(regex::Regex::new(r"\.tk$")?);
             patterns.push(regex::Regex::new(r"\.ml$")?);
             patterns.push(regex::Regex::new(r"\.ga$")?);
@@ -189,11 +279,8 @@ impl DnsFilter {
         Ok(())
     }
     
-    #[allow(dead_code)]
     pub async fn start_dns_proxy(&self, event_handler: mpsc::Sender<DnsEvent>) -> Result<()> {
-        let config = self.config.read()
-            .map_err(|_| anyhow!("Failed to read config"))?;
-        
+        let config = self.config();
         if !config.enabled {
             return Ok(());
         }
@@ -203,26 +290,32 @@ impl DnsFilter {
         
         info!("DNS filter proxy listening on {}", listen_addr);
         
-        let mut buf = vec![0u8; 512]; // DNS max packet size
+        // Held for as long as the proxy runs; dropping it, including when
+        // this future is cancelled, removes the rules again
+        let _redirect = if config.transparent_redirect {
+            Some(NetfilterManager::new()?.redirect_dns(config.listen_port, config.upstream_mark)?)
+        } else {
+            None
+        };
+        self.serve(socket, event_handler).await
+    }
+    
+    // Answers queries arriving on `socket`, each in its own task so a slow
+    // upstream never holds up other clients
+    pub async fn serve(&self, socket: Arc<UdpSocket>, event_handler: mpsc::Sender<DnsEvent>) -> Result<()> {
+        let mut buf = vec![0u8; MAX_DNS_PACKET];
+        let _listening = ListeningGuard::new(&self.inner.listening);
         
         loop {
             match socket.recv_from(&mut buf).await {
                 Ok((len, src)) => {
                     let packet = buf[..len].to_vec();
-                    
-                    // Process DNS query
-                    // TODO: Fix Send + Sync issue with tokio::spawn
-                    // let socket_clone = Arc::clone(&socket);
-                    // let filter_clone = self.clone();
-                    // let handler_clone = event_handler.clone();
-                    // tokio::spawn(async move {
-                    //     filter_clone.process_dns_query(
-                    //         packet,
-                    //         src,
-                    //         socket_clone,
-                    //         handler_clone
-                    //     ).await;
-                    // });
+                    let filter = self.clone();
+                    let socket = Arc::clone(&socket);
+                    let event_handler = event_handler.clone();
+                    tokio::spawn(async move {
+                        filter.process_dns_query(packet, src, socket, event_handler).await;
+                    });
                 }
                 Err(e) => {
                     error!("Error receiving DNS query: {}", e);
@@ -239,71 +332,99 @@ impl DnsFilter {
         event_handler: mpsc::Sender<DnsEvent>,
     ) {
         // Update stats
-        if let Ok(mut stats) = self.stats.write() {
+        if let Ok(mut stats) = self.inner.stats.write() {
             stats.total_queries += 1;
         }
         
         // Parse DNS query (simplified - in production use trust-dns-proto)
-        if let Some(domain) = self.parse_dns_query(&packet) {
-            let query_type = self.get_query_type(&packet);
-            
-            // Check if domain should be blocked
-            let (action, reason) = self.check_domain(&domain).await;
-            
-            match action {
-                DnsAction::Blocked => {
-                    // Send blocked response
-                    if let Ok(response) = self.create_blocked_response(&packet).await {
-                        let _ = socket.send_to(&response, src).await;
+        let Some(domain) = self.parse_dns_query(&packet) else { return };
+        let query_type = self.get_query_type(&packet);
+        let cache_key = Self::cache_key(&domain, &query_type);
+        
+        // Check if domain should be blocked
+        let (mut action, reason) = self.check_domain(&domain, &cache_key);
+        let mut response = None;
+        
+        match action {
+            DnsAction::Blocked => {
+                // Send blocked response
+                if let Ok(blocked) = self.create_blocked_response(&packet) {
+                    let _ = socket.send_to(&blocked, src).await;
+                    response = Some(blocked);
+                }
+                
+                if let Ok(mut stats) = self.inner.stats.write() {
+                    stats.blocked_queries += 1;
+                    if reason.as_ref().is_some_and(|r| r.contains("malicious")) {
+                        stats.malicious_domains_blocked += 1;
                     }
-                    
-                    if let Ok(mut stats) = self.stats.write() {
-                        stats.blocked_queries += 1;
-                        if reason.as_ref().map_or(false, |r| r.contains("malicious")) {
-                            stats.malicious_domains_blocked += 1;
-                        }
-                        if reason.as_ref().map_or(false, |r| r.contains("DGA")) {
-                            stats.dga_domains_blocked += 1;
-                        }
+                    if reason.as_ref().is_some_and(|r| r.contains("DGA")) {
+                        stats.dga_domains_blocked += 1;
                     }
                 }
-                DnsAction::Cached => {
-                    // Return cached response
-                    if let Some(response) = self.get_cached_response(&domain).await {
-                        let _ = socket.send_to(&response, src).await;
-                        
-                        if let Ok(mut stats) = self.stats.write() {
+            }
+            DnsAction::Cached => {
+                // Return cached response, or resolve again if it expired meanwhile
+                match self.get_cached_response(&cache_key, &packet) {
+                    Some(cached) => {
+                        let _ = socket.send_to(&cached, src).await;
+                        if let Ok(mut stats) = self.inner.stats.write() {
                             stats.cached_responses += 1;
                         }
+                        response = Some(cached);
                     }
+                    None => (action, response) = self.resolve(&packet, &cache_key, src, &socket).await,
                 }
-                DnsAction::Allowed => {
-                    // Forward to upstream
-                    if let Ok(response) = self.forward_to_upstream(&packet).await {
-                        let _ = socket.send_to(&response, src).await;
-                        
-                        // Cache the response
-                        self.cache_response(&domain, &response).await;
-                        
-                        if let Ok(mut stats) = self.stats.write() {
-                            stats.upstream_queries += 1;
-                        }
-                    }
+            }
+            DnsAction::Allowed => {
+                (action, response) = self.resolve(&packet, &cache_key, src, &socket).await;
+            }
+            DnsAction::Error => {}
+        }
+        
+        if self.inner.config.read().is_ok_and(|config| config.log_queries) {
+            debug!("DNS {:?} {} from {} -> {:?}", query_type, domain, src, action);
+        }
+        
+        // Send event
+        let answer = response.as_deref().and_then(dns::parse);
+        let event = DnsEvent {
+            timestamp: Instant::now(),
+            query_type,
+            domain,
+            source: src,
+            action,
+            reason,
+            answers: answer.as_ref().map(|message| message.resolved_ips()).unwrap_or_default(),
+            rcode: answer.map(|message| message.rcode),
+        };
+        
+        let _ = event_handler.send(event).await;
+    }
+    
+    // Forwards to upstream, answers the client and caches the result.
+    // Returns the response the client got, if any.
+    async fn resolve(&self, packet: &[u8], cache_key: &str, src: SocketAddr, socket: &UdpSocket) -> (DnsAction, Option<Vec<u8>>) {
+        match self.forward_to_upstream(packet).await {
+            Ok(response) => {
+                let _ = socket.send_to(&response, src).await;
+                
+                // Cache the response
+                self.cache_response(cache_key, &response);
+                
+                if let Ok(mut stats) = self.inner.stats.write() {
+                    stats.upstream_queries += 1;
                 }
-                _ => {}
+                (DnsAction::Allowed, Some(response))
+            }
+            Err(e) => {
+                warn!("DNS query from {} failed: {}", src, e);
+                let response = Self::error_response(packet, RCODE_SERVFAIL).ok();
+                if let Some(response) = &response {
+                    let _ = socket.send_to(response, src).await;
+                }
+                (DnsAction::Error, response)
             }
-            
-            // Send event
-            let event = DnsEvent {
-                timestamp: Instant::now(),
-                query_type,
-                domain: domain.clone(),
-                source: src,
-                action,
-                reason,
-            };
-            
-            let _ = event_handler.send(event).await;
         }
     }
     
@@ -348,28 +469,41 @@ impl DnsFilter {
     }
     
     fn get_query_type(&self, packet: &[u8]) -> DnsQueryType {
-        // Extract query type from DNS packet
-        // This is simplified - in production use proper DNS parsing
-        DnsQueryType::A
+        let Some(end) = question_end(packet) else {
+            return DnsQueryType::Other("unknown".to_string());
+        };
+        match u16::from_be_bytes([packet[end - 4], packet[end - 3]]) {
+            1 => DnsQueryType::A,
+            5 => DnsQueryType::CNAME,
+            15 => DnsQueryType::MX,
+            16 => DnsQueryType::TXT,
+            28 => DnsQueryType::AAAA,
+            other => DnsQueryType::Other(other.to_string()),
+        }
     }
     
-    async fn check_domain(&self, domain: &str) -> (DnsAction, Option<String>) {
+    // Answers differ per record type, so the type is part of the key
+    fn cache_key(domain: &str, query_type: &DnsQueryType) -> String {
+        format!("{}/{:?}", domain, query_type)
+    }
+    
+    fn check_domain(&self, domain: &str, cache_key: &str) -> (DnsAction, Option<String>) {
         // Check whitelist first
-        if let Ok(whitelist) = self.whitelist_domains.read() {
+        if let Ok(whitelist) = self.inner.whitelist_domains.read() {
             if whitelist.contains(domain) {
                 return (DnsAction::Allowed, None);
             }
         }
         
         // Check exact blacklist
-        if let Ok(blacklist) = self.blacklist_domains.read() {
+        if let Ok(blacklist) = self.inner.blacklist_domains.read() {
             if blacklist.contains(domain) {
                 return (DnsAction::Blocked, Some("Blacklisted domain".to_string()));
             }
         }
         
         // Check patterns
-        if let Ok(patterns) = self.blacklist_patterns.read() {
+        if let Ok(patterns) = self.inner.blacklist_patterns.read() {
             for pattern in patterns.iter() {
                 if pattern.is_match(domain) {
                     return (DnsAction::Blocked, Some("Matches blacklist pattern".to_string()));
@@ -378,14 +512,12 @@ impl DnsFilter {
         }
         
         // Check for DGA domains
-        if self.config.read().unwrap().block_dga_domains {
-            if self.is_dga_domain(domain) {
-                return (DnsAction::Blocked, Some("Suspected DGA domain".to_string()));
-            }
+        if self.inner.config.read().unwrap().block_dga_domains && self.is_dga_domain(domain) {
+            return (DnsAction::Blocked, Some("Suspected DGA domain".to_string()));
         }
         
         // Check cache
-        if self.is_cached(domain).await {
+        if self.is_cached(cache_key) {
             return (DnsAction::Cached, None);
         }
         
@@ -453,9 +585,9 @@ impl DnsFilter {
         }
     }
     
-    async fn is_cached(&self, domain: &str) -> bool {
-        if let Ok(cache) = self.cache.read() {
-            if let Some(entry) = cache.entries.get(domain) {
+    fn is_cached(&self, key: &str) -> bool {
+        if let Ok(cache) = self.inner.cache.read() {
+            if let Some(entry) = cache.entries.get(key) {
                 if entry.created_at.elapsed() < entry.ttl {
                     return true;
                 }
@@ -464,58 +596,86 @@ impl DnsFilter {
         false
     }
     
-    async fn get_cached_response(&self, domain: &str) -> Option<Vec<u8>> {
-        // In a real implementation, we would construct a proper DNS response
-        // from the cached data
-        None
+    fn get_cached_response(&self, key: &str, query: &[u8]) -> Option<Vec<u8>> {
+        if query.len() < 2 {
+            return None;
+        }
+        let mut cache = self.inner.cache.write().ok()?;
+        let entry = cache.entries.get_mut(key)?;
+        if entry.created_at.elapsed() >= entry.ttl {
+            return None;
+        }
+        entry.hit_count += 1;
+        
+        // Answer with the client's transaction id
+        let mut response = entry.response.clone();
+        response[..2].copy_from_slice(&query[..2]);
+        Some(response)
     }
     
-    async fn create_blocked_response(&self, query: &[u8]) -> Result<Vec<u8>> {
+    fn create_blocked_response(&self, query: &[u8]) -> Result<Vec<u8>> {
         // Create a DNS response based on block mode
-        let config = self.config.read()
-            .map_err(|_| anyhow!("Failed to read config"))?;
-        
-        // In a real implementation, we would properly construct a DNS response
-        // For now, return a simple NXDOMAIN response
-        let mut response = query.to_vec();
-        if response.len() >= 3 {
-            response[2] |= 0x80; // Set response bit
-            response[3] = 0x83; // Set NXDOMAIN
-        }
+        let block_mode = self.inner.config.read()
+            .map_err(|_| anyhow!("Failed to read config"))?
+            .block_mode.clone();
         
+        // Sinkhole answers are not synthesized yet and fall back to NXDOMAIN
+        let rcode = match block_mode {
+            BlockMode::Refused => RCODE_REFUSED,
+            BlockMode::Nxdomain | BlockMode::SinkHole(_) => RCODE_NXDOMAIN,
+        };
+        Self::error_response(query, rcode)
+    }
+    
+    // Echoes the question back with no records and the given rcode
+    fn error_response(query: &[u8], rcode: u8) -> Result<Vec<u8>> {
+        let end = question_end(query).ok_or_else(|| anyhow!("Malformed DNS query"))?;
+        let mut response = query[..end].to_vec();
+        // QR set; opcode and RD copied from the query
+        response[2] = 0x80 | (query[2] & 0x79);
+        // RA set
+        response[3] = 0x80 | rcode;
+        // One question, no answer, authority or additional records
+        response[4..12].copy_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
         Ok(response)
     }
     
     async fn forward_to_upstream(&self, query: &[u8]) -> Result<Vec<u8>> {
-        let config = self.config.read()
-            .map_err(|_| anyhow!("Failed to read config"))?;
+        // Copy what's needed so no lock is held while waiting on the network
+        let (upstream_servers, timeout, mark) = {
+            let config = self.inner.config.read()
+                .map_err(|_| anyhow!("Failed to read config"))?;
+            (config.upstream_servers.clone(), config.upstream_timeout, config.upstream_mark)
+        };
         
         // Try each upstream server
-        for upstream in &config.upstream_servers {
-            let socket = UdpSocket::bind("0.0.0.0:0").await?;
-            socket.send_to(query, upstream).await?;
-            
-            let mut buf = vec![0u8; 512];
-            match tokio::time::timeout(
-                Duration::from_secs(2),
-                socket.recv_from(&mut buf)
-            ).await {
-                Ok(Ok((len, _))) => {
-                    return Ok(buf[..len].to_vec());
+        for upstream in upstream_servers {
+            let connection = match self.inner.upstreams.get(upstream, mark).await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    debug!("DNS upstream {} unavailable: {}", upstream, e);
+                    continue;
                 }
-                _ => continue,
+            };
+            match connection.query(query, timeout).await {
+                Ok(response) => return Ok(response),
+                Err(e) => debug!("DNS upstream {} failed: {}", upstream, e),
             }
         }
         
         Err(anyhow!("All upstream servers failed"))
     }
     
-    async fn cache_response(&self, domain: &str, response: &[u8]) {
-        let config = self.config.read().unwrap();
+    fn cache_response(&self, key: &str, response: &[u8]) {
+        // Only successful, complete answers are worth replaying
+        if response.len() < 12 || response[2] & 0x02 != 0 || response[3] & 0x0f != 0 {
+            return;
+        }
+        let Ok(cache_ttl) = self.inner.config.read().map(|config| config.cache_ttl) else { return };
         
-        if let Ok(mut cache) = self.cache.write() {
+        if let Ok(mut cache) = self.inner.cache.write() {
             // Implement cache eviction if needed
-            if cache.entries.len() >= cache.max_entries {
+            if cache.entries.len() >= cache.max_entries && !cache.entries.contains_key(key) {
                 // Remove oldest entry
                 if let Some(oldest) = cache.entries.iter()
                     .min_by_key(|(_, entry)| entry.created_at)
@@ -524,11 +684,10 @@ impl DnsFilter {
                 }
             }
             
-            cache.entries.insert(domain.to_string(), CacheEntry {
-                domain: domain.to_string(),
-                result: DnsResult::Resolved(Vec::new()), // Would parse from response
+            cache.entries.insert(key.to_string(), CacheEntry {
+                response: response.to_vec(),
                 created_at: Instant::now(),
-                ttl: config.cache_ttl,
+                ttl: cache_ttl,
                 hit_count: 0,
             });
         }
@@ -536,21 +695,21 @@ impl DnsFilter {
     
     // Management methods
     pub fn add_blacklist_domain(&self, domain: String) -> Result<()> {
-        let mut blacklist = self.blacklist_domains.write()
+        let mut blacklist = self.inner.blacklist_domains.write()
             .map_err(|_| anyhow!("Failed to acquire blacklist write lock"))?;
         blacklist.insert(domain);
         Ok(())
     }
     
     pub fn remove_blacklist_domain(&self, domain: &str) -> Result<()> {
-        let mut blacklist = self.blacklist_domains.write()
+        let mut blacklist = self.inner.blacklist_domains.write()
             .map_err(|_| anyhow!("Failed to acquire blacklist write lock"))?;
         blacklist.remove(domain);
         Ok(())
     }
     
     pub fn add_whitelist_domain(&self, domain: String) -> Result<()> {
-        let mut whitelist = self.whitelist_domains.write()
+        let mut whitelist = self.inner.whitelist_domains.write()
             .map_err(|_| anyhow!("Failed to acquire whitelist write lock"))?;
         whitelist.insert(domain);
         Ok(())
@@ -558,26 +717,203 @@ impl DnsFilter {
     
     pub fn add_blacklist_pattern(&self, pattern: &str) -> Result<()> {
         let regex = regex::Regex::new(pattern)?;
-        let mut patterns = self.blacklist_patterns.write()
+        let mut patterns = self.inner.blacklist_patterns.write()
             .map_err(|_| anyhow!("Failed to acquire patterns write lock"))?;
         patterns.push(regex);
         Ok(())
     }
     
     pub fn get_stats(&self) -> Result<DnsStats> {
-        let stats = self.stats.read()
+        let stats = self.inner.stats.read()
             .map_err(|_| anyhow!("Failed to read stats"))?;
         Ok(stats.clone())
     }
     
     pub fn clear_cache(&self) -> Result<()> {
-        let mut cache = self.cache.write()
+        let mut cache = self.inner.cache.write()
             .map_err(|_| anyhow!("Failed to acquire cache write lock"))?;
         cache.entries.clear();
         Ok(())
     }
 }
 
+// Offset just past the first question (name, type and class)
+fn question_end(packet: &[u8]) -> Option<usize> {
+    let mut offset = 12;
+    loop {
+        let len = *packet.get(offset)? as usize;
+        offset += 1;
+        if len == 0 {
+            break;
+        }
+        // Queries never use compression pointers
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        offset += len;
+    }
+    (offset + 4 <= packet.len()).then_some(offset + 4)
+}
+
+// Needs CAP_NET_ADMIN; without it queries go out unmarked, which only
+// matters while the transparent redirect is installed
+fn set_socket_mark(socket: &UdpSocket, mark: u32) {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_MARK,
+            &mark as *const u32 as *const libc::c_void,
+            std::mem::size_of::<u32>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        warn!("Failed to mark DNS upstream socket: {}", std::io::Error::last_os_error());
+    }
+}
+
+// Transaction id -> question section of the query, and who waits for it
+type PendingQueries = Arc<Mutex<HashMap<u16, (Vec<u8>, oneshot::Sender<Vec<u8>>)>>>;
+
+#[derive(Default)]
+struct UpstreamPool {
+    connections: Mutex<HashMap<SocketAddr, Arc<UpstreamConnection>>>,
+}
+
+impl UpstreamPool {
+    async fn get(&self, upstream: SocketAddr, mark: u32) -> Result<Arc<UpstreamConnection>> {
+        if let Some(connection) = self.lock().get(&upstream).filter(|connection| !connection.retired()) {
+            return Ok(Arc::clone(connection));
+        }
+        
+        // A retired socket lives on until its queries in flight are answered
+        let connection = Arc::new(UpstreamConnection::connect(upstream, mark).await?);
+        let mut connections = self.lock();
+        match connections.get(&upstream) {
+            // Another query may have connected meanwhile; keep whichever came first
+            Some(current) if !current.retired() => Ok(Arc::clone(current)),
+            _ => {
+                connections.insert(upstream, Arc::clone(&connection));
+                Ok(connection)
+            }
+        }
+    }
+    
+    fn retain(&self, upstreams: &[SocketAddr]) {
+        self.lock().retain(|addr, _| upstreams.contains(addr));
+    }
+    
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, Arc<UpstreamConnection>>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// A connected UDP socket shared by a batch of queries to one upstream. Each
+// query goes out under a random transaction id and a reader task routes
+// answers back by that id and the question they answer.
+struct UpstreamConnection {
+    socket: Arc<UdpSocket>,
+    pending: PendingQueries,
+    reader: JoinHandle<()>,
+    sent: AtomicU32,
+}
+
+impl UpstreamConnection {
+    async fn connect(upstream: SocketAddr, mark: u32) -> Result<Self> {
+        let bind_addr = if upstream.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+        if mark != 0 {
+            set_socket_mark(&socket, mark);
+        }
+        // Connected, so the kernel discards datagrams from anyone else
+        socket.connect(upstream).await?;
+        
+        let pending: PendingQueries = Arc::new(Mutex::new(HashMap::new()));
+        let reader = tokio::spawn(Self::read_responses(Arc::clone(&socket), Arc::clone(&pending)));
+        Ok(Self { socket, pending, reader, sent: AtomicU32::new(0) })
+    }
+    
+    fn retired(&self) -> bool {
+        self.sent.load(Ordering::Relaxed) >= QUERIES_PER_SOCKET
+    }
+    
+    async fn read_responses(socket: Arc<UdpSocket>, pending: PendingQueries) {
+        let mut buf = vec![0u8; MAX_DNS_PACKET];
+        loop {
+            match socket.recv(&mut buf).await {
+                Ok(len) if len >= 12 => {
+                    let response = &buf[..len];
+                    let id = u16::from_be_bytes([response[0], response[1]]);
+                    let Ok(mut pending) = pending.lock() else { continue };
+                    // 16 bits of id are guessable; an answer to another
+                    // question is forged or stale and must not be cached
+                    let answers = pending.get(&id).is_some_and(|(question, _)| {
+                        question_end(response).is_some_and(|end| response[12..end].eq_ignore_ascii_case(question))
+                    });
+                    if !answers {
+                        debug!("Dropping DNS upstream answer {} that matches no pending question", id);
+                        continue;
+                    }
+                    if let Some((_, waiter)) = pending.remove(&id) {
+                        let _ = waiter.send(response.to_vec());
+                    }
+                }
+                Ok(_) => {}
+                // ICMP errors surface here; the affected query times out
+                Err(e) => debug!("DNS upstream receive error: {}", e),
+            }
+        }
+    }
+    
+    async fn query(&self, packet: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let end = question_end(packet).ok_or_else(|| anyhow!("DNS query without a question"))?;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        let (id, response) = self.register(&packet[12..end])?;
+        
+        let mut request = packet.to_vec();
+        request[..2].copy_from_slice(&id.to_be_bytes());
+        let result = match self.socket.send(&request).await {
+            Ok(_) => match tokio::time::timeout(timeout, response).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(_)) => Err(anyhow!("DNS upstream reader stopped")),
+                Err(_) => Err(anyhow!("DNS upstream timed out")),
+            },
+            Err(e) => Err(e.into()),
+        };
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+        
+        // Hand the answer back under the client's own id
+        let mut response = result?;
+        response[..2].copy_from_slice(&packet[..2]);
+        Ok(response)
+    }
+    
+    // An off-path spoofer has to guess the id, the socket's port and the
+    // question together
+    fn register(&self, question: &[u8]) -> Result<(u16, oneshot::Receiver<Vec<u8>>)> {
+        let mut pending = self.pending.lock()
+            .map_err(|_| anyhow!("Failed to acquire pending queries lock"))?;
+        for _ in 0..32 {
+            let bytes = uuid::Uuid::new_v4().into_bytes();
+            let id = u16::from_be_bytes([bytes[0], bytes[1]]);
+            if let std::collections::hash_map::Entry::Vacant(slot) = pending.entry(id) {
+                let (sender, receiver) = oneshot::channel();
+                slot.insert((question.to_vec(), sender));
+                return Ok((id, receiver));
+            }
+        }
+        Err(anyhow!("Too many DNS queries in flight"))
+    }
+}
+
+impl Drop for UpstreamConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -609,4 +945,83 @@ mod tests {
         let low_entropy = filter.calculate_entropy("aaaaaaa");
         assert!(low_entropy < 1.0);
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_concurrent_queries_share_upstream() {
+        // Fake upstream that answers two queries in reverse order
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let mut queries = Vec::new();
+            let mut sources = Vec::new();
+            while queries.len() < 2 {
+                let (len, src) = upstream.recv_from(&mut buf).await.unwrap();
+                queries.push(buf[..len].to_vec());
+                sources.push(src);
+            }
+            for query in queries.iter().rev() {
+                let mut response = query.clone();
+                response[2] |= 0x80;
+                upstream.send_to(&response, sources[0]).await.unwrap();
+            }
+            // Both queries arrived over the same socket
+            assert_eq!(sources[0], sources[1]);
+        });
+        
+        let config = DnsFilterConfig {
+            upstream_servers: vec![upstream_addr],
+            ..DnsFilter::new().unwrap().config()
+        };
+        let filter = DnsFilter::with_config(config);
+        let query = |name: &[u8]| {
+            let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+            packet.push(name.len() as u8);
+            packet.extend_from_slice(name);
+            packet.extend_from_slice(&[3, b'c', b'o', b'm', 0, 0, 1, 0, 1]);
+            packet
+        };
+        let (first, second) = (query(b"first"), query(b"second"));
+        
+        let (a, b) = tokio::join!(filter.forward_to_upstream(&first), filter.forward_to_upstream(&second));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        // Each caller gets its own answer under its original transaction id
+        assert_eq!(filter.parse_dns_query(&a).as_deref(), Some("first.com"));
+        assert_eq!(filter.parse_dns_query(&b).as_deref(), Some("second.com"));
+        assert_eq!(&a[..2], &[0x12, 0x34]);
+        assert_eq!(&b[..2], &[0x12, 0x34]);
+        
+        let blocked = DnsFilter::error_response(&first, RCODE_NXDOMAIN).unwrap();
+        assert_eq!(blocked.len(), first.len());
+        assert_eq!(blocked[3] & 0x0f, RCODE_NXDOMAIN);
+    }
+    
+    #[tokio::test]
+    async fn test_answer_to_another_question_is_dropped() {
+        // Fake upstream that first answers under the right id for another name
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, src) = upstream.recv_from(&mut buf).await.unwrap();
+            let mut response = buf[..len].to_vec();
+            response[2] |= 0x80;
+            let mut forged = response.clone();
+            forged[13..18].copy_from_slice(b"other");
+            upstream.send_to(&forged, src).await.unwrap();
+            upstream.send_to(&response, src).await.unwrap();
+        });
+        
+        let config = DnsFilterConfig {
+            upstream_servers: vec![upstream_addr],
+            ..DnsFilter::new().unwrap().config()
+        };
+        let filter = DnsFilter::with_config(config);
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0, 5];
+        query.extend_from_slice(b"FiRsT");
+        query.extend_from_slice(&[3, b'c', b'o', b'm', 0, 0, 1, 0, 1]);
+        
+        let answer = filter.forward_to_upstream(&query).await.unwrap();
+        assert_eq!(filter.parse_dns_query(&answer).as_deref(), Some("first.com"));
+    }
+}
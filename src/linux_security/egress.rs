@@ -0,0 +1,678 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::net::IpAddr;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, warn, debug};
+
+// Per-process outbound connection enforcement with cgroup/connect4 and
+// cgroup/connect6 eBPF programs. The programs are generated here and loaded
+// with bpf(2) directly, so no BPF toolchain is needed at build or run time.
+//
+// In-kernel the programs look up the connecting tgid in a process map to find
+// the rule group of its executable hash, then check the rule map for
+// (group, destination, port) with wildcards. Group 0 holds rules that apply to
+// every process. Processes are assigned to groups from userspace, so a process
+// is only covered by hash rules once it has been seen by the tracker.
+
+const BPF_MAP_CREATE: libc::c_long = 0;
+const BPF_MAP_LOOKUP_ELEM: libc::c_long = 1;
+const BPF_MAP_UPDATE_ELEM: libc::c_long = 2;
+const BPF_MAP_DELETE_ELEM: libc::c_long = 3;
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_PROG_ATTACH: libc::c_long = 8;
+const BPF_PROG_DETACH: libc::c_long = 9;
+
+const BPF_MAP_TYPE_HASH: u32 = 1;
+const BPF_MAP_TYPE_ARRAY: u32 = 2;
+const BPF_PROG_TYPE_CGROUP_SOCK_ADDR: u32 = 18;
+const BPF_CGROUP_INET4_CONNECT: u32 = 10;
+const BPF_CGROUP_INET6_CONNECT: u32 = 11;
+const BPF_F_ALLOW_MULTI: u32 = 2;
+
+const BPF_FUNC_MAP_LOOKUP_ELEM: i32 = 1;
+const BPF_FUNC_GET_CURRENT_PID_TGID: i32 = 14;
+
+// struct bpf_sock_addr offsets
+const CTX_USER_IP4: i16 = 4;
+const CTX_USER_IP6: i16 = 8;
+const CTX_USER_PORT: i16 = 24;
+
+const KEY_SIZE: usize = 24;
+const MAX_RULES: u32 = 16_384;
+const MAX_TRACKED_PROCESSES: u32 = 65_536;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EgressRule {
+    // Exact address; unset matches any destination
+    #[serde(default)]
+    pub destination: Option<IpAddr>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    // SHA-256 of the originating executable; unset applies to every process
+    #[serde(default)]
+    pub executable_hash: Option<String>,
+}
+
+impl EgressRule {
+    fn validate(&self) -> Result<()> {
+        if self.destination.is_none() && self.port.is_none() && self.executable_hash.is_none() {
+            return Err(anyhow!("Egress rule must set a destination, port or executable hash"));
+        }
+        Ok(())
+    }
+}
+
+// Matches the key layout the programs build on the BPF stack:
+// group (u32), port (network order in the low 16 bits), IPv6 or v4-mapped address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct EgressKey {
+    group: u32,
+    port: Option<u16>,
+    destination: Option<IpAddr>,
+}
+
+impl EgressKey {
+    fn to_bytes(self) -> [u8; KEY_SIZE] {
+        let mut key = [0u8; KEY_SIZE];
+        key[0..4].copy_from_slice(&self.group.to_ne_bytes());
+        if let Some(port) = self.port {
+            key[4..6].copy_from_slice(&port.to_be_bytes());
+        }
+        match self.destination {
+            Some(IpAddr::V4(ip)) => key[8..24].copy_from_slice(&ip.to_ipv6_mapped().octets()),
+            Some(IpAddr::V6(ip)) => key[8..24].copy_from_slice(&ip.octets()),
+            None => {}
+        }
+        key
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct BpfInsn {
+    code: u8,
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> BpfInsn {
+    BpfInsn { code, regs: (src << 4) | (dst & 0x0f), off, imm }
+}
+
+// Just the instructions the egress programs need
+struct Asm {
+    insns: Vec<BpfInsn>,
+}
+
+impl Asm {
+    fn mov_reg(&mut self, dst: u8, src: u8) { self.insns.push(insn(0xbf, dst, src, 0, 0)); }
+    fn mov_imm(&mut self, dst: u8, imm: i32) { self.insns.push(insn(0xb7, dst, 0, 0, imm)); }
+    fn add_imm(&mut self, dst: u8, imm: i32) { self.insns.push(insn(0x07, dst, 0, 0, imm)); }
+    fn rsh_imm(&mut self, dst: u8, imm: i32) { self.insns.push(insn(0x77, dst, 0, 0, imm)); }
+    fn ldx_w(&mut self, dst: u8, src: u8, off: i16) { self.insns.push(insn(0x61, dst, src, off, 0)); }
+    fn stx_w(&mut self, dst: u8, off: i16, src: u8) { self.insns.push(insn(0x63, dst, src, off, 0)); }
+    fn st_w(&mut self, dst: u8, off: i16, imm: i32) { self.insns.push(insn(0x62, dst, 0, off, imm)); }
+    fn atomic_add_dw(&mut self, dst: u8, src: u8) { self.insns.push(insn(0xdb, dst, src, 0, 0)); }
+    fn call(&mut self, helper: i32) { self.insns.push(insn(0x85, 0, 0, 0, helper)); }
+    fn exit(&mut self) { self.insns.push(insn(0x95, 0, 0, 0, 0)); }
+    fn jeq_imm(&mut self, dst: u8, imm: i32, off: i16) { self.insns.push(insn(0x15, dst, 0, off, imm)); }
+
+    fn jne_imm(&mut self, dst: u8, imm: i32) -> usize {
+        self.insns.push(insn(0x55, dst, 0, 0, imm));
+        self.insns.len() - 1
+    }
+
+    // Two-slot load of a map fd, resolved to the map by the kernel
+    fn ld_map_fd(&mut self, dst: u8, fd: RawFd) {
+        self.insns.push(insn(0x18, dst, 1, 0, fd));
+        self.insns.push(insn(0, 0, 0, 0, 0));
+    }
+
+    fn lookup(&mut self, map_fd: RawFd, key_offset: i32) {
+        self.ld_map_fd(1, map_fd);
+        self.mov_reg(2, 10);
+        self.add_imm(2, key_offset);
+        self.call(BPF_FUNC_MAP_LOOKUP_ELEM);
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) {
+        self.insns[at].off = (target as i64 - at as i64 - 1) as i16;
+    }
+}
+
+// r6 = ctx, r7 = rule group of the current process, r9 = destination port
+fn build_program(ipv6: bool, proc_map: RawFd, rule_map: RawFd, stats_map: RawFd) -> Vec<BpfInsn> {
+    let mut asm = Asm { insns: Vec::new() };
+
+    asm.mov_reg(6, 1);
+    asm.call(BPF_FUNC_GET_CURRENT_PID_TGID);
+    asm.rsh_imm(0, 32);
+    asm.stx_w(10, -4, 0);
+    asm.lookup(proc_map, -4);
+    asm.mov_imm(7, 0);
+    asm.jeq_imm(0, 0, 1);
+    asm.ldx_w(7, 0, 0);
+    asm.ldx_w(9, 6, CTX_USER_PORT);
+
+    let mut deny_jumps = Vec::new();
+    for process_group in [true, false] {
+        for (any_destination, any_port) in [(false, false), (false, true), (true, false), (true, true)] {
+            // Key lives at r10-24: group, port, 16 address bytes
+            if process_group {
+                asm.stx_w(10, -24, 7);
+            } else {
+                asm.st_w(10, -24, 0);
+            }
+            if any_port {
+                asm.st_w(10, -20, 0);
+            } else {
+                asm.stx_w(10, -20, 9);
+            }
+
+            if any_destination {
+                for word in 0..4 {
+                    asm.st_w(10, -16 + word * 4, 0);
+                }
+            } else if ipv6 {
+                for word in 0..4 {
+                    asm.ldx_w(1, 6, CTX_USER_IP6 + word * 4);
+                    asm.stx_w(10, -16 + word * 4, 1);
+                }
+            } else {
+                asm.st_w(10, -16, 0);
+                asm.st_w(10, -12, 0);
+                asm.st_w(10, -8, i32::from_ne_bytes([0, 0, 0xff, 0xff]));
+                asm.ldx_w(1, 6, CTX_USER_IP4);
+                asm.stx_w(10, -4, 1);
+            }
+
+            asm.lookup(rule_map, -24);
+            deny_jumps.push(asm.jne_imm(0, 0));
+        }
+    }
+
+    asm.mov_imm(0, 1);
+    asm.exit();
+
+    let deny = asm.insns.len();
+    for at in deny_jumps {
+        asm.patch_jump(at, deny);
+    }
+
+    // Count the denial in stats[0]
+    asm.st_w(10, -28, 0);
+    asm.lookup(stats_map, -28);
+    asm.jeq_imm(0, 0, 2);
+    asm.mov_imm(1, 1);
+    asm.atomic_add_dw(0, 1);
+    asm.mov_imm(0, 0);
+    asm.exit();
+
+    asm.insns
+}
+
+fn bpf<T>(cmd: libc::c_long, attr: &mut T) -> Result<libc::c_long> {
+    let ret = unsafe {
+        libc::syscall(libc::SYS_bpf, cmd, attr as *mut T as *mut libc::c_void, std::mem::size_of::<T>())
+    };
+    if ret < 0 {
+        return Err(anyhow!("bpf({}) failed: {}", cmd, std::io::Error::last_os_error()));
+    }
+    Ok(ret)
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapElemAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgAttachAttr {
+    target_fd: u32,
+    attach_bpf_fd: u32,
+    attach_type: u32,
+    attach_flags: u32,
+}
+
+struct BpfMap {
+    fd: OwnedFd,
+}
+
+impl BpfMap {
+    fn create(map_type: u32, key_size: u32, value_size: u32, max_entries: u32) -> Result<Self> {
+        let mut attr = MapCreateAttr { map_type, key_size, value_size, max_entries, map_flags: 0 };
+        let fd = bpf(BPF_MAP_CREATE, &mut attr).context("Failed to create BPF map")?;
+        Ok(Self { fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) } })
+    }
+
+    fn update(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut attr = MapElemAttr {
+            map_fd: self.fd.as_raw_fd() as u32,
+            key: key.as_ptr() as u64,
+            value: value.as_ptr() as u64,
+            ..Default::default()
+        };
+        bpf(BPF_MAP_UPDATE_ELEM, &mut attr).map(|_| ())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        let mut attr = MapElemAttr {
+            map_fd: self.fd.as_raw_fd() as u32,
+            key: key.as_ptr() as u64,
+            ..Default::default()
+        };
+        bpf(BPF_MAP_DELETE_ELEM, &mut attr).map(|_| ())
+    }
+
+    fn lookup(&self, key: &[u8], value: &mut [u8]) -> Result<()> {
+        let mut attr = MapElemAttr {
+            map_fd: self.fd.as_raw_fd() as u32,
+            key: key.as_ptr() as u64,
+            value: value.as_mut_ptr() as u64,
+            ..Default::default()
+        };
+        bpf(BPF_MAP_LOOKUP_ELEM, &mut attr).map(|_| ())
+    }
+}
+
+fn load_program(name: &str, insns: &[BpfInsn], attach_type: u32) -> Result<OwnedFd> {
+    let license = b"GPL\0";
+    let mut log = vec![0u8; 64 * 1024];
+    let mut prog_name = [0u8; 16];
+    prog_name[..name.len().min(15)].copy_from_slice(&name.as_bytes()[..name.len().min(15)]);
+
+    let mut attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_CGROUP_SOCK_ADDR,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        license: license.as_ptr() as u64,
+        log_level: 1,
+        log_size: log.len() as u32,
+        log_buf: log.as_mut_ptr() as u64,
+        prog_name,
+        expected_attach_type: attach_type,
+        ..Default::default()
+    };
+
+    match bpf(BPF_PROG_LOAD, &mut attr) {
+        Ok(fd) => Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }),
+        Err(e) => {
+            let end = log.iter().position(|&b| b == 0).unwrap_or(log.len());
+            let verifier = String::from_utf8_lossy(&log[..end]);
+            let tail: Vec<&str> = verifier.lines().rev().take(5).collect();
+            Err(anyhow!("Failed to load {}: {} (verifier: {})", name, e, tail.into_iter().rev().collect::<Vec<_>>().join(" | ")))
+        }
+    }
+}
+
+// Executable hashes are cached by path, inode and mtime
+type ExecutableHashCache = HashMap<PathBuf, (u64, i64, String)>;
+
+pub struct EgressEnforcer {
+    cgroup_path: PathBuf,
+    cgroup: File,
+    proc_map: Arc<BpfMap>,
+    rule_map: BpfMap,
+    stats_map: BpfMap,
+    programs: Vec<(OwnedFd, u32)>,
+    attached: Mutex<bool>,
+    // executable hash -> rule group
+    groups: Arc<RwLock<HashMap<String, u32>>>,
+    // Bumped when groups change so running processes are re-evaluated
+    generation: Arc<AtomicU64>,
+    installed: Mutex<HashSet<EgressKey>>,
+    tracked: Arc<Mutex<HashSet<u32>>>,
+    running: Arc<Mutex<bool>>,
+}
+
+impl EgressEnforcer {
+    // Loads the programs; nothing is enforced until attach()
+    pub fn new(cgroup_path: &Path) -> Result<Self> {
+        let cgroup = File::open(cgroup_path)
+            .with_context(|| format!("Failed to open cgroup {:?}", cgroup_path))?;
+
+        let proc_map = BpfMap::create(BPF_MAP_TYPE_HASH, 4, 4, MAX_TRACKED_PROCESSES)?;
+        let rule_map = BpfMap::create(BPF_MAP_TYPE_HASH, KEY_SIZE as u32, 4, MAX_RULES)?;
+        let stats_map = BpfMap::create(BPF_MAP_TYPE_ARRAY, 4, 8, 1)?;
+
+        let mut programs = Vec::new();
+        for (name, ipv6, attach_type) in [
+            ("flux_egress4", false, BPF_CGROUP_INET4_CONNECT),
+            ("flux_egress6", true, BPF_CGROUP_INET6_CONNECT),
+        ] {
+            let insns = build_program(ipv6, proc_map.fd.as_raw_fd(), rule_map.fd.as_raw_fd(), stats_map.fd.as_raw_fd());
+            programs.push((load_program(name, &insns, attach_type)?, attach_type));
+        }
+
+        info!("Loaded eBPF egress programs for cgroup {:?}", cgroup_path);
+        Ok(Self {
+            cgroup_path: cgroup_path.to_path_buf(),
+            cgroup,
+            proc_map: Arc::new(proc_map),
+            rule_map,
+            stats_map,
+            programs,
+            attached: Mutex::new(false),
+            groups: Arc::new(RwLock::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+            installed: Mutex::new(HashSet::new()),
+            tracked: Arc::new(Mutex::new(HashSet::new())),
+            running: Arc::new(Mutex::new(false)),
+        })
+    }
+
+    pub fn attach(&self) -> Result<()> {
+        let mut attached = self.attached.lock().unwrap();
+        if *attached {
+            return Ok(());
+        }
+
+        for (prog, attach_type) in &self.programs {
+            let mut attr = ProgAttachAttr {
+                target_fd: self.cgroup.as_raw_fd() as u32,
+                attach_bpf_fd: prog.as_raw_fd() as u32,
+                attach_type: *attach_type,
+                attach_flags: BPF_F_ALLOW_MULTI,
+            };
+            bpf(BPF_PROG_ATTACH, &mut attr)
+                .with_context(|| format!("Failed to attach egress program to {:?}", self.cgroup_path))?;
+        }
+
+        *attached = true;
+        info!("eBPF egress enforcement attached to {:?}", self.cgroup_path);
+        Ok(())
+    }
+
+    pub fn detach(&self) -> Result<()> {
+        *self.running.lock().unwrap() = false;
+
+        let mut attached = self.attached.lock().unwrap();
+        if !*attached {
+            return Ok(());
+        }
+
+        for (prog, attach_type) in &self.programs {
+            let mut attr = ProgAttachAttr {
+                target_fd: self.cgroup.as_raw_fd() as u32,
+                attach_bpf_fd: prog.as_raw_fd() as u32,
+                attach_type: *attach_type,
+                attach_flags: 0,
+            };
+            if let Err(e) = bpf(BPF_PROG_DETACH, &mut attr) {
+                warn!("Failed to detach egress program: {}", e);
+            }
+        }
+
+        *attached = false;
+        info!("eBPF egress enforcement detached from {:?}", self.cgroup_path);
+        Ok(())
+    }
+
+    // Replaces the installed rule set
+    pub fn set_rules(&self, rules: &[EgressRule]) -> Result<()> {
+        for rule in rules {
+            rule.validate()?;
+        }
+
+        let mut groups = self.groups.write()
+            .map_err(|_| anyhow!("Failed to acquire egress groups write lock"))?;
+        let mut installed = self.installed.lock()
+            .map_err(|_| anyhow!("Failed to acquire egress rules lock"))?;
+
+        for rule in rules {
+            if let Some(ref hash) = rule.executable_hash {
+                let next = groups.len() as u32 + 1;
+                groups.entry(hash.to_lowercase()).or_insert(next);
+            }
+        }
+
+        let wanted: HashSet<EgressKey> = rules.iter()
+            .map(|rule| EgressKey {
+                group: rule.executable_hash.as_ref()
+                    .and_then(|hash| groups.get(&hash.to_lowercase()).copied())
+                    .unwrap_or(0),
+                port: rule.port,
+                destination: rule.destination,
+            })
+            .collect();
+
+        for key in installed.difference(&wanted) {
+            if let Err(e) = self.rule_map.delete(&key.to_bytes()) {
+                debug!("Failed to remove egress rule {:?}: {}", key, e);
+            }
+        }
+        for key in wanted.difference(&installed) {
+            self.rule_map.update(&key.to_bytes(), &1u32.to_ne_bytes())?;
+        }
+
+        info!("Installed {} eBPF egress rules", wanted.len());
+        *installed = wanted;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Assigns a process to the rule group of its executable hash. The tracking
+    // thread does this by polling /proc; exec event sources can call it directly
+    // to close the gap for short-lived processes.
+    pub fn track_process(&self, pid: u32, executable_hash: &str) -> Result<()> {
+        Self::assign_group(&self.proc_map, &self.groups, &self.tracked, pid, executable_hash)
+    }
+
+    fn assign_group(
+        proc_map: &BpfMap,
+        groups: &RwLock<HashMap<String, u32>>,
+        tracked: &Mutex<HashSet<u32>>,
+        pid: u32,
+        executable_hash: &str,
+    ) -> Result<()> {
+        let group = groups.read()
+            .map_err(|_| anyhow!("Failed to acquire egress groups read lock"))?
+            .get(&executable_hash.to_lowercase())
+            .copied();
+
+        if let Some(group) = group {
+            proc_map.update(&pid.to_ne_bytes(), &group.to_ne_bytes())?;
+            tracked.lock().unwrap().insert(pid);
+            debug!("Process {} assigned to egress group {}", pid, group);
+        }
+        Ok(())
+    }
+
+    pub fn start_process_tracking(&self) {
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return;
+            }
+            *running = true;
+        }
+
+        let proc_map = Arc::clone(&self.proc_map);
+        let groups = Arc::clone(&self.groups);
+        let tracked = Arc::clone(&self.tracked);
+        let generation = Arc::clone(&self.generation);
+        let running = Arc::clone(&self.running);
+
+        thread::spawn(move || {
+            info!("Egress process tracking thread started");
+            let mut seen: HashSet<u32> = HashSet::new();
+            let mut seen_generation = generation.load(Ordering::Relaxed);
+            let mut hashes: ExecutableHashCache = HashMap::new();
+
+            while *running.lock().unwrap() {
+                let current = list_pids();
+
+                let current_generation = generation.load(Ordering::Relaxed);
+                if current_generation != seen_generation {
+                    seen.clear();
+                    seen_generation = current_generation;
+                }
+
+                for &pid in current.difference(&seen) {
+                    if let Some(hash) = executable_hash(pid, &mut hashes) {
+                        if let Err(e) = Self::assign_group(&proc_map, &groups, &tracked, pid, &hash) {
+                            warn!("Failed to track process {}: {}", pid, e);
+                        }
+                    }
+                }
+
+                // Exited processes free their slot; pids are reused
+                let mut tracked_pids = tracked.lock().unwrap();
+                tracked_pids.retain(|pid| {
+                    if current.contains(pid) {
+                        return true;
+                    }
+                    let _ = proc_map.delete(&pid.to_ne_bytes());
+                    false
+                });
+                drop(tracked_pids);
+
+                seen = current;
+                thread::sleep(Duration::from_secs(1));
+            }
+
+            info!("Egress process tracking thread stopped");
+        });
+    }
+
+    pub fn denied_connections(&self) -> u64 {
+        let mut value = [0u8; 8];
+        match self.stats_map.lookup(&0u32.to_ne_bytes(), &mut value) {
+            Ok(()) => u64::from_ne_bytes(value),
+            Err(_) => 0,
+        }
+    }
+
+    pub fn is_attached(&self) -> bool {
+        *self.attached.lock().unwrap()
+    }
+}
+
+impl Drop for EgressEnforcer {
+    fn drop(&mut self) {
+        let _ = self.detach();
+    }
+}
+
+fn list_pids() -> HashSet<u32> {
+    std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries.filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().to_str().and_then(|name| name.parse().ok()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn executable_hash(pid: u32, cache: &mut ExecutableHashCache) -> Option<String> {
+    let exe = PathBuf::from(format!("/proc/{}/exe", pid));
+    let path = std::fs::read_link(&exe).ok()?;
+    let metadata = std::fs::metadata(&exe).ok()?;
+
+    if let Some((ino, mtime, hash)) = cache.get(&path) {
+        if *ino == metadata.ino() && *mtime == metadata.mtime() {
+            return Some(hash.clone());
+        }
+    }
+
+    // Read through /proc so deleted or replaced binaries still hash correctly
+    let mut file = File::open(&exe).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).ok()?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let hash = hex::encode(hasher.finalize());
+    cache.insert(path, (metadata.ino(), metadata.mtime(), hash.clone()));
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_key_layout_and_program_shape() {
+        let key = EgressKey {
+            group: 3,
+            port: Some(443),
+            destination: Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5))),
+        };
+        let bytes = key.to_bytes();
+        assert_eq!(&bytes[0..4], &3u32.to_ne_bytes());
+        assert_eq!(&bytes[4..8], &[0x01, 0xbb, 0, 0]);
+        assert_eq!(&bytes[8..24], &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 203, 0, 113, 5]);
+
+        let any = EgressKey { group: 0, port: None, destination: None };
+        assert_eq!(any.to_bytes(), [0u8; KEY_SIZE]);
+
+        for ipv6 in [false, true] {
+            let insns = build_program(ipv6, 7, 8, 9);
+            assert_eq!(insns.last().unwrap().code, 0x95);
+            // Every jump lands inside the program
+            for (i, ins) in insns.iter().enumerate() {
+                if matches!(ins.code, 0x15 | 0x55) {
+                    let target = i as i64 + 1 + ins.off as i64;
+                    assert!(target > i as i64 && (target as usize) < insns.len());
+                }
+            }
+            let map_loads: Vec<i32> = insns.iter().filter(|i| i.code == 0x18).map(|i| i.imm).collect();
+            assert_eq!(map_loads.iter().filter(|&&fd| fd == 8).count(), 8);
+            assert_eq!(map_loads.first(), Some(&7));
+            assert_eq!(map_loads.last(), Some(&9));
+        }
+
+        let invalid = EgressRule { destination: None, port: None, executable_hash: None };
+        assert!(invalid.validate().is_err());
+    }
+}
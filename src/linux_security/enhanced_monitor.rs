@@ -1,16 +1,46 @@
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
-use std::thread;
 use std::time::Duration;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
 use sha2::{Sha256, Digest};
 use std::io::Read;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::task::AbortHandle;
 
 use super::fanotify::{FanotifyMonitor, FanotifyEvent};
 use super::netlink::{NetlinkMonitor, NetworkConnection};
-use super::process_monitor::{ProcessMonitor, ProcessInfo};
+use super::process_monitor::{ProcessMonitor, ProcessInfo, ProcessChange};
+use super::proc_connector::ProcConnector;
+use super::process_tree::ProcessTreeSource;
+use super::patterns::{PatternMatcher, PatternCategory, Severity};
+use super::escalation::{self, EnforcementAction, EscalationMatrix};
+use super::reputation::{ReputationPipeline, HashVerdict};
+use super::egress::{EgressEnforcer, EgressRule};
+use super::tasks::TaskGroup;
+use super::supervisor::{Heartbeat, Supervisor, SupervisorConfig, SELF_MONITORING_KEY};
+use super::permission_watchdog::{PermissionWatchdog, WatchdogStats};
+use super::hash_cache::{HashCache, HashCacheStats};
+use super::decision_cache::{DecisionCache, DecisionCacheStats, ExecVerdict};
+use super::loader_hijack::{LoaderFinding, LoaderFindingKind, LoaderHijackDetector};
+use crate::scanner::{DropperAnalysis, PackageVerifier};
+use crate::cgroup_metrics::workload_for_pid;
+use crate::scanner::directory::TEMP_DIRECTORIES;
+use crate::event_bus::{EventBus, EventBusMetrics, DEFAULT_SINK_CAPACITY};
+use crate::event_log::event_type_name;
+use crate::incidents::event_severity;
+use crate::sampling::{AdaptiveSampler, SamplingConfig, SamplingMetrics};
+use crate::aggregation::{AggregationConfig, FileAccessAggregator};
+use crate::file_watch::FileWatchConfig;
+use crate::exemptions::{ExemptionConfig, Exemptions};
+use crate::health::{ComponentState, HealthRegistry};
+use crate::rule_stats::RuleStats;
+use crate::config::{Config, ReloadableConfig};
+use crate::shadow::ShadowLog;
 use crate::monitor::{SecurityEvent, SecurityEventType, ProcessInfo as MonitorProcessInfo, 
                       FileAccessType, NetworkProtocol, Verdict};
 
@@ -37,6 +67,14 @@ pub struct SecurityPolicy {
     // Behavior patterns
     suspicious_patterns: Vec<SuspiciousPattern>,
     
+    // What to do when a behavior pattern matches
+    escalation: EscalationMatrix,
+    quarantine_dir: PathBuf,
+    // Never blocked, killed or quarantined, whatever the rules above say
+    exemptions: Exemptions,
+    // Bumped on every change, so cached exec verdicts never outlive the policy
+    version: u64,
+    
     // Mode settings
     enforcement_mode: EnforcementMode,
     log_allowed: bool,
@@ -54,17 +92,87 @@ pub enum EnforcementMode {
 pub struct SuspiciousPattern {
     name: String,
     description: String,
+    category: PatternCategory,
+    severity: Severity,
     check_fn: fn(&ProcessInfo, &FanotifyEvent) -> bool,
 }
 
+// Events from the monitoring tasks are queued for the handler, which runs on
+// its own thread so a slow handler never holds up a fanotify permission decision
+#[derive(Clone)]
+struct EventSender {
+    bus: Arc<EventBus<SecurityEvent>>,
+    sampler: Arc<AdaptiveSampler>,
+    aggregator: Arc<FileAccessAggregator>,
+}
+
+impl EventSender {
+    // Detections always go through
+    fn publish(&self, event: SecurityEvent) {
+        self.bus.publish(event);
+    }
+    
+    // Routine activity, which is summarized and thinned out when it floods in
+    fn publish_routine(&self, event: SecurityEvent) {
+        let Some(event) = self.aggregator.offer(event) else { return };
+        if self.sampler.admit(event_type_name(&event), event_severity(&event)) {
+            self.bus.publish(event);
+        }
+    }
+    
+    fn publish_summaries(&self, all: bool) {
+        let summaries = if all { self.aggregator.take_all() } else { self.aggregator.take_due() };
+        for summary in summaries {
+            self.bus.publish(summary.to_security_event());
+        }
+    }
+}
+
+const PROCESS_SCAN_INTERVAL: Duration = Duration::from_secs(5);
+const PROCESS_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+// An idle fanotify task still beats this often for the supervisor
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// Dropped files are analysed from this prefix; overlays past it go unseen
+const MAX_DROPPER_SCAN_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_PROCESS_CHAIN: usize = 8;
+// Per-process CPU and memory samples for resource usage patterns
+const RESOURCE_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+// ld.so.preload and the loader directories
+const LOADER_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+// Executable and loader variable pairs already reported
+const MAX_LOADER_REPORTS: usize = 4096;
+const SUMMARY_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// New containers get their mounts marked within this
+const MOUNT_NAMESPACE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Lets the fanotify descriptor be polled without taking ownership of it
+struct FanotifyFd(RawFd);
+
+impl AsRawFd for FanotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
 pub struct EnhancedSecurityMonitor {
     fanotify: Arc<Mutex<FanotifyMonitor>>,
+    permission_watchdog: Arc<PermissionWatchdog>,
     netlink: Arc<Mutex<NetlinkMonitor>>,
     process_monitor: Arc<Mutex<ProcessMonitor>>,
     policy: Arc<RwLock<SecurityPolicy>>,
+    pattern_matcher: Arc<PatternMatcher>,
+    reputation: Arc<ReputationPipeline>,
+    egress: Mutex<Option<EgressEnforcer>>,
+    tasks: Option<Arc<Mutex<TaskGroup>>>,
+    supervisor: Arc<Supervisor>,
     running: Arc<Mutex<bool>>,
-    event_handler: Arc<dyn Fn(SecurityEvent) + Send + Sync>,
-    hash_cache: Arc<Mutex<HashMap<PathBuf, String>>>,
+    event_bus: EventSender,
+    process_changes: Arc<EventBus<ProcessChange>>,
+    hash_cache: Arc<Mutex<HashCache>>,
+    decision_cache: Arc<Mutex<DecisionCache>>,
+    package_verifier: Arc<RwLock<Option<Arc<PackageVerifier>>>>,
+    dropper_directories: Arc<RwLock<Vec<PathBuf>>>,
+    loader_hijack: Arc<LoaderHijackDetector>,
 }
 
 impl EnhancedSecurityMonitor {
@@ -75,7 +183,11 @@ impl EnhancedSecurityMonitor {
         info!("Initializing enhanced Linux security monitor");
         
         // Initialize components
-        let fanotify = Arc::new(Mutex::new(FanotifyMonitor::new()?));
+        let dropper_directories: Vec<PathBuf> = TEMP_DIRECTORIES.iter().map(PathBuf::from).collect();
+        let mut fanotify = FanotifyMonitor::new()?;
+        fanotify.set_write_watch_paths(dropper_directories.clone());
+        let permission_watchdog = fanotify.watchdog();
+        let fanotify = Arc::new(Mutex::new(fanotify));
         let netlink = Arc::new(Mutex::new(NetlinkMonitor::new()?));
         let process_monitor = Arc::new(Mutex::new(ProcessMonitor::new()));
         
@@ -92,27 +204,90 @@ impl EnhancedSecurityMonitor {
             allowed_ports: HashSet::new(),
             denied_ports: HashSet::new(),
             suspicious_patterns: Self::default_suspicious_patterns(),
+            escalation: EscalationMatrix::default(),
+            quarantine_dir: PathBuf::from("/var/quarantine/fluxdefense"),
+            exemptions: Exemptions::default(),
+            version: 0,
             enforcement_mode: EnforcementMode::Passive,
             log_allowed: false,
             log_denied: true,
         };
         
+        let event_bus = EventSender {
+            bus: Arc::new(EventBus::new(DEFAULT_SINK_CAPACITY)),
+            sampler: Arc::new(AdaptiveSampler::default()),
+            aggregator: Arc::new(FileAccessAggregator::default()),
+        };
+        event_bus.bus.subscribe("security-events", event_handler);
+        
+        let pattern_matcher = Arc::new(PatternMatcher::new()?);
+        let reputation = Arc::new(ReputationPipeline::new(Arc::clone(&pattern_matcher)));
+        
+        // Restarts of the monitoring tasks are reported like any other event
+        let supervisor = Arc::new(Supervisor::new(SupervisorConfig::default()));
+        let restart_events = event_bus.clone();
+        supervisor.on_restart(move |restart| restart_events.publish(restart.to_security_event()));
+        let watchdog_events = event_bus.clone();
+        permission_watchdog.on_degrade(move |stats| watchdog_events.publish(Self::watchdog_event(stats)));
+        
         Ok(Self {
             fanotify,
+            permission_watchdog,
             netlink,
             process_monitor,
             policy: Arc::new(RwLock::new(policy)),
+            pattern_matcher,
+            reputation,
+            egress: Mutex::new(None),
+            tasks: None,
+            supervisor,
             running: Arc::new(Mutex::new(false)),
-            event_handler: Arc::new(event_handler),
-            hash_cache: Arc::new(Mutex::new(HashMap::new())),
+            event_bus,
+            process_changes: Arc::new(EventBus::new(DEFAULT_SINK_CAPACITY)),
+            hash_cache: Arc::new(Mutex::new(HashCache::default())),
+            decision_cache: Arc::new(Mutex::new(DecisionCache::default())),
+            package_verifier: Arc::new(RwLock::new(None)),
+            dropper_directories: Arc::new(RwLock::new(dropper_directories)),
+            loader_hijack: Arc::new(LoaderHijackDetector::new()),
         })
     }
     
+    // Raised when stalled permission decisions made fanotify fall back to
+    // notifications
+    fn watchdog_event(stats: &WatchdogStats) -> SecurityEvent {
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: SecurityEventType::Syscall {
+                syscall: "fanotify:notification_only".to_string(),
+                success: false,
+                exit_code: None,
+                audit_key: Some(SELF_MONITORING_KEY.to_string()),
+            },
+            process_info: MonitorProcessInfo {
+                pid: std::process::id(),
+                path: std::env::current_exe().unwrap_or_else(|_| PathBuf::from("fluxdefense")),
+                parent_pid: None,
+                user_id: unsafe { libc::getuid() },
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: format!(
+                "Self-monitoring (Critical): {} permission decisions overran the deadline (slowest {:.0}ms); \
+                 file access is no longer blocked, only reported",
+                stats.overruns, stats.max_latency_ms
+            ),
+        }
+    }
+    
     fn default_suspicious_patterns() -> Vec<SuspiciousPattern> {
         vec![
             SuspiciousPattern {
                 name: "Crypto Miner".to_string(),
                 description: "Potential cryptocurrency miner detected".to_string(),
+                category: PatternCategory::CryptoMiner,
+                severity: Severity::High,
                 check_fn: |proc, event| {
                     if let Some(path) = &event.path {
                         let path_str = path.to_string_lossy().to_lowercase();
@@ -133,6 +308,8 @@ impl EnhancedSecurityMonitor {
             SuspiciousPattern {
                 name: "Reverse Shell".to_string(),
                 description: "Potential reverse shell detected".to_string(),
+                category: PatternCategory::ReverseShell,
+                severity: Severity::Critical,
                 check_fn: |proc, _event| {
                     proc.cmdline.iter().any(|arg| {
                         arg.contains("/dev/tcp/") || 
@@ -145,6 +322,8 @@ impl EnhancedSecurityMonitor {
             SuspiciousPattern {
                 name: "Privilege Escalation".to_string(),
                 description: "Potential privilege escalation attempt".to_string(),
+                category: PatternCategory::PrivilegeEscalation,
+                severity: Severity::High,
                 check_fn: |proc, event| {
                     if let Some(path) = &event.path {
                         let path_str = path.to_string_lossy();
@@ -189,72 +368,190 @@ impl EnhancedSecurityMonitor {
             nm.start_monitoring()?;
         }
         
-        // Start monitoring threads
-        self.start_fanotify_thread();
-        self.start_netlink_thread();
-        self.start_process_scanning_thread();
+        // Start monitoring tasks; fanotify and netlink are restarted if they die
+        let tasks = Arc::new(Mutex::new(TaskGroup::new("flux-monitor")?));
+        let heartbeat = Heartbeat::new();
+        let spawn = self.fanotify_spawner(self.event_bus.clone(), heartbeat.clone());
+        self.supervise_task(&tasks, "fanotify", heartbeat, spawn)?;
+        let heartbeat = Heartbeat::new();
+        let spawn = self.netlink_spawner(self.event_bus.clone(), heartbeat.clone());
+        self.supervise_task(&tasks, "netlink", heartbeat, spawn)?;
+        {
+            let mut group = tasks.lock()
+                .map_err(|_| anyhow!("Failed to acquire task group lock"))?;
+            self.start_process_tracking(&mut group);
+        }
+        self.tasks = Some(tasks);
+        self.supervisor.start()?;
         
         Ok(())
     }
     
-    fn start_fanotify_thread(&self) {
+    pub fn event_bus_metrics(&self) -> EventBusMetrics {
+        self.event_bus.bus.metrics()
+    }
+    
+    pub fn permission_watchdog_stats(&self) -> WatchdogStats {
+        self.permission_watchdog.stats()
+    }
+    
+    pub fn sampling_metrics(&self) -> SamplingMetrics {
+        self.event_bus.sampler.metrics()
+    }
+    
+    pub fn set_sampling(&self, config: SamplingConfig) {
+        self.event_bus.sampler.configure(config);
+    }
+    
+    pub fn set_file_access_aggregation(&self, config: AggregationConfig) {
+        self.event_bus.aggregator.configure(config);
+    }
+    
+    // Other subsystems, e.g. a NetworkFilter's packet capture, can be handed
+    // to the same supervisor
+    pub fn supervisor(&self) -> Arc<Supervisor> {
+        Arc::clone(&self.supervisor)
+    }
+    
+    // Receives process start, exec and exit as they are observed
+    pub fn on_process_change<F>(&self, name: &str, handler: F)
+    where
+        F: Fn(ProcessChange) + Send + 'static,
+    {
+        self.process_changes.subscribe(name, handler);
+    }
+    
+    // Spawns the fanotify task; called again by the supervisor if the task dies
+    fn fanotify_spawner(&self, events: EventSender, heartbeat: Heartbeat) -> impl Fn(&mut TaskGroup) -> Result<AbortHandle> + Send + 'static {
         let fanotify = Arc::clone(&self.fanotify);
         let process_monitor = Arc::clone(&self.process_monitor);
         let policy = Arc::clone(&self.policy);
-        let running = Arc::clone(&self.running);
-        let event_handler = Arc::clone(&self.event_handler);
+        let pattern_matcher = Arc::clone(&self.pattern_matcher);
+        let reputation = Arc::clone(&self.reputation);
         let hash_cache = Arc::clone(&self.hash_cache);
+        let decision_cache = Arc::clone(&self.decision_cache);
+        let package_verifier = Arc::clone(&self.package_verifier);
+        let dropper_directories = Arc::clone(&self.dropper_directories);
         
-        thread::spawn(move || {
-            info!("Fanotify monitoring thread started");
+        move |tasks: &mut TaskGroup| {
+            let fd = {
+                let mut fm = fanotify.lock()
+                    .map_err(|_| anyhow!("Failed to acquire fanotify lock"))?;
+                if !fm.is_running() {
+                    fm.start_monitoring()?;
+                }
+                fm.as_raw_fd()
+            };
+            let readiness = {
+                // Registration needs the runtime's reactor
+                let _runtime = tasks.handle().enter();
+                AsyncFd::with_interest(FanotifyFd(fd), Interest::READABLE)?
+            };
             
-            while *running.lock().unwrap() {
-                let mut fm = match fanotify.lock() {
-                    Ok(fm) => fm,
-                    Err(_) => {
-                        error!("Failed to lock fanotify monitor");
-                        break;
-                    }
-                };
-                
-                // Read events with decision callback
-                let policy_clone = Arc::clone(&policy);
-                let process_monitor_clone = Arc::clone(&process_monitor);
-                let hash_cache_clone = Arc::clone(&hash_cache);
+            let fanotify = Arc::clone(&fanotify);
+            let process_monitor = Arc::clone(&process_monitor);
+            let policy = Arc::clone(&policy);
+            let pattern_matcher = Arc::clone(&pattern_matcher);
+            let reputation = Arc::clone(&reputation);
+            let hash_cache = Arc::clone(&hash_cache);
+            let decision_cache = Arc::clone(&decision_cache);
+            let package_verifier = Arc::clone(&package_verifier);
+            let dropper_directories = Arc::clone(&dropper_directories);
+            let events = events.clone();
+            let heartbeat = heartbeat.clone();
+            
+            Ok(tasks.spawn(move |mut shutdown| async move {
+                info!("Fanotify monitoring task started");
                 
-                match fm.read_events(|event| {
-                    Self::make_decision(event, &policy_clone, &process_monitor_clone, &hash_cache_clone)
-                }) {
-                    Ok(events) => {
-                        drop(fm); // Release lock before processing
-                        
-                        for event in events {
-                            Self::handle_fanotify_event(
-                                &event,
-                                &process_monitor,
-                                &policy,
-                                &event_handler,
-                                &hash_cache,
-                            );
+                loop {
+                    heartbeat.beat();
+                    let mut ready = tokio::select! {
+                        _ = shutdown.wait() => break,
+                        _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => continue,
+                        ready = readiness.readable() => match ready {
+                            Ok(ready) => ready,
+                            Err(e) => {
+                                error!("Error polling fanotify: {}", e);
+                                break;
+                            }
+                        },
+                    };
+                    
+                    // Decisions hash files and consult policy, so they run on the blocking pool
+                    let fanotify = Arc::clone(&fanotify);
+                    let process_monitor = Arc::clone(&process_monitor);
+                    let policy = Arc::clone(&policy);
+                    let pattern_matcher = Arc::clone(&pattern_matcher);
+                    let reputation = Arc::clone(&reputation);
+                    let hash_cache = Arc::clone(&hash_cache);
+                    let decision_cache = Arc::clone(&decision_cache);
+                    let package_verifier = Arc::clone(&package_verifier);
+                    let dropper_directories = Arc::clone(&dropper_directories);
+                    let events = events.clone();
+                    let drained = tokio::task::spawn_blocking(move || {
+                        Self::drain_fanotify(&fanotify, &process_monitor, &policy, &hash_cache, &decision_cache, &pattern_matcher, &reputation, &package_verifier, &dropper_directories, &events)
+                    }).await;
+                    
+                    match drained {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => error!("Error reading fanotify events: {}", e),
+                        Err(e) => {
+                            error!("Fanotify task failed: {}", e);
+                            break;
                         }
                     }
-                    Err(e) => {
-                        error!("Error reading fanotify events: {}", e);
-                    }
+                    ready.clear_ready();
                 }
                 
-                thread::sleep(Duration::from_millis(10));
+                info!("Fanotify monitoring task stopped");
+            }))
+        }
+    }
+    
+    // Reads until the queue is empty; readiness is edge-triggered
+    #[allow(clippy::too_many_arguments)]
+    fn drain_fanotify(
+        fanotify: &Arc<Mutex<FanotifyMonitor>>,
+        process_monitor: &Arc<Mutex<ProcessMonitor>>,
+        policy: &Arc<RwLock<SecurityPolicy>>,
+        hash_cache: &Arc<Mutex<HashCache>>,
+        decision_cache: &Arc<Mutex<DecisionCache>>,
+        pattern_matcher: &Arc<PatternMatcher>,
+        reputation: &Arc<ReputationPipeline>,
+        package_verifier: &Arc<RwLock<Option<Arc<PackageVerifier>>>>,
+        dropper_directories: &Arc<RwLock<Vec<PathBuf>>>,
+        events: &EventSender,
+    ) -> Result<()> {
+        loop {
+            let batch = {
+                let fm = fanotify.lock()
+                    .map_err(|_| anyhow!("Failed to lock fanotify monitor"))?;
+                fm.read_events(|event| {
+                    Self::make_decision(event, policy, process_monitor, hash_cache, decision_cache, pattern_matcher, reputation, events)
+                })?
+            };
+            if batch.is_empty() {
+                return Ok(());
             }
             
-            info!("Fanotify monitoring thread stopped");
-        });
+            let package_verifier = package_verifier.read().ok().and_then(|verifier| verifier.clone());
+            let dropper_directories = dropper_directories.read().map(|dirs| dirs.clone()).unwrap_or_default();
+            for event in batch {
+                Self::handle_fanotify_event(&event, process_monitor, policy, events, hash_cache, package_verifier.as_deref(), &dropper_directories);
+            }
+        }
     }
     
+    #[allow(clippy::too_many_arguments)]
     fn make_decision(
         event: &FanotifyEvent,
         policy: &Arc<RwLock<SecurityPolicy>>,
         process_monitor: &Arc<Mutex<ProcessMonitor>>,
-        hash_cache: &Arc<Mutex<HashMap<PathBuf, String>>>,
+        hash_cache: &Arc<Mutex<HashCache>>,
+        decision_cache: &Arc<Mutex<DecisionCache>>,
+        pattern_matcher: &Arc<PatternMatcher>,
+        reputation: &Arc<ReputationPipeline>,
+        events: &EventSender,
     ) -> bool {
         let policy = match policy.read() {
             Ok(p) => p,
@@ -272,6 +569,20 @@ impl EnhancedSecurityMonitor {
             .ok()
             .and_then(|pm| pm.get_process_by_pid(event.pid as u32).cloned());
         
+        // Tracked processes are looked up once per start; an exec replaces
+        // the image the exemption was granted for
+        let exemption = match process_info {
+            Some(ref info) => policy.exemptions.cached_exemption(info.pid, info.start_time),
+            None => policy.exemptions.exemption(event.pid as u32),
+        };
+        if event.is_exec() {
+            policy.exemptions.forget(event.pid as u32);
+        }
+        if let Some(reason) = exemption {
+            debug!("Allowing pid {} ({}) to access {:?}", event.pid, reason, event.path);
+            return true;
+        }
+        
         if let Some(path) = &event.path {
             // Check denied paths first
             if policy.denied_paths.contains(path) {
@@ -279,6 +590,50 @@ impl EnhancedSecurityMonitor {
                 return false;
             }
             
+            // Gated executions are looked up by the file behind the event's descriptor
+            let exec_metadata = if event.is_exec() && event.is_permission_event() {
+                std::fs::metadata(format!("/proc/self/fd/{}", event.fd)).ok()
+            } else {
+                None
+            };
+            let mut exec_verdict = exec_metadata.as_ref()
+                .and_then(|metadata| decision_cache.lock().ok()?.get(metadata, policy.version));
+            
+            // Hash reputation for gated executions and, with on-access scanning, opens.
+            // Our own accesses are skipped so enrichment reads cannot feed back.
+            if event.is_permission_event() && event.pid as u32 != std::process::id() && reputation.is_active() {
+                // A cached hash is not rehashed, but its reputation is looked up
+                // again since enrichment may have judged it meanwhile
+                let (hash, verdict) = match exec_verdict {
+                    Some(ref cached) => (Some(cached.hash.clone()), reputation.verdict_for_hash(&cached.hash)),
+                    None => reputation.check_fd(event.fd, path),
+                };
+                if let (Some(ref hash), None) = (&hash, &exec_verdict) {
+                    // Describe the file behind the event's descriptor, which is what was hashed
+                    let metadata = std::fs::metadata(format!("/proc/self/fd/{}", event.fd));
+                    if let (Ok(metadata), Ok(mut cache)) = (metadata, hash_cache.lock()) {
+                        cache.insert(path, &metadata, hash.clone());
+                    }
+                    if event.is_exec() {
+                        exec_verdict = Some(Self::exec_verdict(decision_cache, exec_metadata.as_ref(), &policy, hash.clone()));
+                    }
+                }
+                
+                match verdict {
+                    HashVerdict::Malicious(reason) => {
+                        let enforce = policy.enforcement_mode == EnforcementMode::Enforcing;
+                        warn!("Malicious file {:?} accessed by pid {}: {}", path, event.pid, reason);
+                        let verdict = if enforce { Verdict::Deny } else { Verdict::Allow };
+                        events.publish(Self::detection_event(event, path, process_info.as_ref(), hash, verdict, reason));
+                        if enforce {
+                            return false;
+                        }
+                    }
+                    HashVerdict::Trusted => return true,
+                    HashVerdict::Unknown => {}
+                }
+            }
+            
             // Check allowed paths
             if policy.allowed_paths.contains(path) {
                 return true;
@@ -299,27 +654,62 @@ impl EnhancedSecurityMonitor {
                 }
                 
                 // Check file hash if available
-                if let Ok(mut cache) = hash_cache.lock() {
-                    if let Some(hash) = cache.get(path).cloned() {
-                        if policy.denied_hashes.contains(&hash) {
-                            debug!("File hash denied by policy: {}", hash);
+                let exec_verdict = exec_verdict.or_else(|| {
+                    let mut cache = hash_cache.lock().ok()?;
+                    // Against the descriptor's identity when there is one, so a
+                    // hash is never cached for a file it does not describe
+                    let hash = match exec_metadata {
+                        Some(ref metadata) => cache.get_with_metadata(path, metadata)?,
+                        None => cache.get(path)?,
+                    };
+                    drop(cache);
+                    Some(Self::exec_verdict(decision_cache, exec_metadata.as_ref(), &policy, hash))
+                });
+                if let Some(verdict) = exec_verdict {
+                    match verdict.hash_rule {
+                        Some(false) => {
+                            debug!("File hash denied by policy: {}", verdict.hash);
                             return false;
                         }
-                        if policy.allowed_hashes.contains(&hash) {
-                            return true;
-                        }
+                        Some(true) => return true,
+                        None => {}
                     }
                 }
             }
             
-            // Check suspicious patterns
+            // Escalate behavior detections according to the matrix; the strongest action wins
             if let Some(ref proc_info) = process_info {
-                for pattern in &policy.suspicious_patterns {
-                    if (pattern.check_fn)(proc_info, event) {
-                        warn!("Suspicious pattern detected: {} - {}", pattern.name, pattern.description);
-                        if policy.enforcement_mode == EnforcementMode::Enforcing {
-                            return false;
-                        }
+                let mut detections: Vec<(String, PatternCategory, Severity)> = policy.suspicious_patterns.iter()
+                    .filter(|pattern| (pattern.check_fn)(proc_info, event))
+                    .map(|pattern| (pattern.name.clone(), pattern.category.clone(), pattern.severity))
+                    .collect();
+                detections.extend(
+                    pattern_matcher.check_process(proc_info, Some(event))
+                        .into_iter()
+                        .map(|(pattern, severity)| (pattern.name, pattern.category, severity))
+                );
+                
+                let strongest = detections.into_iter()
+                    .map(|(name, category, severity)| (policy.escalation.action_for(&category, severity), name, category, severity))
+                    .max_by_key(|(action, ..)| *action);
+                
+                if let Some((action, name, category, severity)) = strongest {
+                    warn!("Suspicious pattern detected: {} ({:?}, {:?}) -> {:?}", name, category, severity, action);
+                    let enforce = policy.enforcement_mode == EnforcementMode::Enforcing && action.denies();
+                    
+                    if action >= EnforcementAction::Alert {
+                        let reason = if enforce || !action.denies() {
+                            format!("{} ({:?} {:?}): {:?}", name, severity, category, action)
+                        } else {
+                            format!("{} ({:?} {:?}): would {:?} in enforcing mode", name, severity, category, action)
+                        };
+                        let verdict = if enforce { Verdict::Deny } else { Verdict::Allow };
+                        events.publish(Self::detection_event(event, path, Some(proc_info), None, verdict, reason));
+                    }
+                    
+                    if enforce {
+                        Self::apply_enforcement(action, event.pid as u32, path, &policy);
+                        return false;
                     }
                 }
             }
@@ -329,12 +719,110 @@ impl EnhancedSecurityMonitor {
         policy.enforcement_mode != EnforcementMode::Enforcing
     }
     
+    // The hash rules of `policy` for an executed file, cached when the file's
+    // identity is known
+    fn exec_verdict(
+        decision_cache: &Arc<Mutex<DecisionCache>>,
+        metadata: Option<&std::fs::Metadata>,
+        policy: &SecurityPolicy,
+        hash: String,
+    ) -> ExecVerdict {
+        let hash_rule = if policy.denied_hashes.contains(&hash) {
+            Some(false)
+        } else if policy.allowed_hashes.contains(&hash) {
+            Some(true)
+        } else {
+            None
+        };
+        let verdict = ExecVerdict { hash, hash_rule };
+        if let (Some(metadata), Ok(mut cache)) = (metadata, decision_cache.lock()) {
+            cache.insert(metadata, policy.version, verdict.clone());
+        }
+        verdict
+    }
+    
+    // Kill and quarantine run as separate blocking tasks: the permission response
+    // for this event must be written before the file can be touched again
+    fn apply_enforcement(action: EnforcementAction, pid: u32, path: &Path, policy: &SecurityPolicy) {
+        let path = path.to_path_buf();
+        let quarantine_dir = policy.quarantine_dir.clone();
+        let exemptions = policy.exemptions.clone();
+        
+        match action {
+            EnforcementAction::Kill => {
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = escalation::kill_process(pid, &exemptions) {
+                        error!("Failed to kill process {}: {}", pid, e);
+                    }
+                });
+            }
+            EnforcementAction::Quarantine => {
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = escalation::quarantine_file(&path, &quarantine_dir, &exemptions) {
+                        error!("Failed to quarantine {:?}: {}", path, e);
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+    
+    fn detection_event(
+        event: &FanotifyEvent,
+        path: &Path,
+        proc_info: Option<&ProcessInfo>,
+        file_hash: Option<String>,
+        verdict: Verdict,
+        reason: String,
+    ) -> SecurityEvent {
+        let event_type = if event.is_exec() {
+            SecurityEventType::FileExecution {
+                target_path: path.to_path_buf(),
+                file_hash,
+                code_signature: None,
+            }
+        } else {
+            SecurityEventType::FileAccess {
+                target_path: path.to_path_buf(),
+                access_type: if event.is_modify() { FileAccessType::Write } else { FileAccessType::Read },
+            }
+        };
+        
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            process_info: match proc_info {
+                Some(info) => MonitorProcessInfo {
+                    pid: info.pid,
+                    path: info.exe_path.clone().unwrap_or_else(|| PathBuf::from(&info.name)),
+                    parent_pid: Some(info.ppid),
+                    user_id: info.uid,
+                    executable_hash: None,
+                    command_line: Some(info.cmdline.join(" ")),
+                },
+                None => MonitorProcessInfo {
+                    pid: event.pid as u32,
+                    path: PathBuf::from(format!("pid:{}", event.pid)),
+                    parent_pid: None,
+                    user_id: 0,
+                    executable_hash: None,
+                    command_line: None,
+                },
+            },
+            verdict,
+            policy_reason: reason,
+        }
+    }
+    
     fn handle_fanotify_event(
         event: &FanotifyEvent,
         process_monitor: &Arc<Mutex<ProcessMonitor>>,
         policy: &Arc<RwLock<SecurityPolicy>>,
-        event_handler: &Arc<dyn Fn(SecurityEvent) + Send + Sync>,
-        hash_cache: &Arc<Mutex<HashMap<PathBuf, String>>>,
+        events: &EventSender,
+        hash_cache: &Arc<Mutex<HashCache>>,
+        package_verifier: Option<&PackageVerifier>,
+        dropper_directories: &[PathBuf],
     ) {
         let process_info = process_monitor
             .lock()
@@ -388,6 +876,43 @@ impl EnhancedSecurityMonitor {
                 }
             };
             
+            // Executions of system binaries that differ from their package, or
+            // that no package installed, are reported as tampering
+            let tampering = match package_verifier.filter(|_| event.is_exec()).map(|verifier| verifier.verify(path)) {
+                Some(Ok(verdict)) if verdict.is_tampering() => {
+                    warn!("Tampered system binary executed: {}", verdict.describe(path));
+                    Some(SecurityEvent {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        timestamp: chrono::Utc::now(),
+                        event_type: event_type.clone(),
+                        process_info: monitor_process_info.clone(),
+                        verdict: Verdict::Log,
+                        policy_reason: format!("Package verification: {}", verdict.describe(path)),
+                    })
+                }
+                Some(Err(e)) => {
+                    debug!("Package verification of {:?} failed: {}", path, e);
+                    None
+                }
+                _ => None,
+            };
+            
+            let dropper = if event.is_close_write() && dropper_directories.iter().any(|dir| path.starts_with(dir)) {
+                Self::analyze_dropped_file(path, event.pid, process_monitor).map(|reason| SecurityEvent {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    timestamp: chrono::Utc::now(),
+                    event_type: SecurityEventType::FileAccess {
+                        target_path: path.clone(),
+                        access_type: FileAccessType::Write,
+                    },
+                    process_info: monitor_process_info.clone(),
+                    verdict: Verdict::Log,
+                    policy_reason: reason,
+                })
+            } else {
+                None
+            };
+            
             let security_event = SecurityEvent {
                 id: uuid::Uuid::new_v4().to_string(),
                 timestamp: chrono::Utc::now(),
@@ -397,20 +922,80 @@ impl EnhancedSecurityMonitor {
                 policy_reason: "Fanotify event".to_string(),
             };
             
-            event_handler(security_event);
+            events.publish_routine(security_event);
+            if let Some(tampering) = tampering {
+                events.publish(tampering);
+            }
+            if let Some(dropper) = dropper {
+                events.publish(dropper);
+            }
         }
     }
     
-    fn calculate_file_hash(path: &Path, cache: &Arc<Mutex<HashMap<PathBuf, String>>>) -> Option<String> {
-        // Check cache first
-        if let Ok(cache) = cache.lock() {
-            if let Some(hash) = cache.get(path) {
-                return Some(hash.clone());
-            }
+    // Entropy and packer heuristics on a file that was just written; the
+    // reason names the indicators and the process chain that wrote it
+    fn analyze_dropped_file(path: &Path, pid: i32, process_monitor: &Arc<Mutex<ProcessMonitor>>) -> Option<String> {
+        // Our own writes, e.g. quarantine copies
+        if pid as u32 == std::process::id() {
+            return None;
+        }
+        let file = std::fs::File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let mut data = Vec::new();
+        if let Err(e) = file.take(MAX_DROPPER_SCAN_SIZE).read_to_end(&mut data) {
+            debug!("Failed to read dropped file {:?}: {}", path, e);
+            return None;
         }
         
-        // Calculate hash
+        let analysis = DropperAnalysis::analyze(&data, metadata.len());
+        if !analysis.is_suspicious() {
+            return None;
+        }
+        let chain = Self::process_chain(process_monitor, pid as u32);
+        warn!("Suspicious dropper {:?} written by {}: {}", path, chain, analysis.describe());
+        Some(format!("Suspicious dropper: {}; written by {}", analysis.describe(), chain))
+    }
+    
+    // "name[pid] <- parent[ppid] <- ..." up to init or the first unknown ancestor
+    fn process_chain(process_monitor: &Arc<Mutex<ProcessMonitor>>, pid: u32) -> String {
+        let Ok(pm) = process_monitor.lock() else {
+            return format!("pid {}", pid);
+        };
+        let mut chain = Vec::new();
+        let mut current = pid;
+        while chain.len() < MAX_PROCESS_CHAIN {
+            match pm.get_process_by_pid(current) {
+                Some(info) => {
+                    chain.push(format!("{}[{}]", info.name, info.pid));
+                    if info.ppid == 0 || info.ppid == info.pid {
+                        break;
+                    }
+                    current = info.ppid;
+                }
+                None => {
+                    chain.push(format!("[{}]", current));
+                    break;
+                }
+            }
+        }
+        chain.join(" <- ")
+    }
+    
+    fn calculate_file_hash(path: &Path, cache: &Arc<Mutex<HashCache>>) -> Option<String> {
         if let Ok(mut file) = std::fs::File::open(path) {
+            let metadata = file.metadata().ok()?;
+            
+            // Check cache first; entries for a since-modified file are discarded
+            if let Ok(mut cache) = cache.lock() {
+                if let Some(hash) = cache.get_with_metadata(path, &metadata) {
+                    return Some(hash);
+                }
+            }
+            
+            // Calculate hash
             let mut hasher = Sha256::new();
             let mut buffer = vec![0; 8192];
             
@@ -426,7 +1011,7 @@ impl EnhancedSecurityMonitor {
             
             // Cache the result
             if let Ok(mut cache) = cache.lock() {
-                cache.insert(path.to_path_buf(), hash.clone());
+                cache.insert(path, &metadata, hash.clone());
             }
             
             Some(hash)
@@ -435,65 +1020,90 @@ impl EnhancedSecurityMonitor {
         }
     }
     
-    fn start_netlink_thread(&self) {
+    fn netlink_spawner(&self, events: EventSender, heartbeat: Heartbeat) -> impl Fn(&mut TaskGroup) -> Result<AbortHandle> + Send + 'static {
         let netlink = Arc::clone(&self.netlink);
         let process_monitor = Arc::clone(&self.process_monitor);
         let policy = Arc::clone(&self.policy);
-        let running = Arc::clone(&self.running);
-        let event_handler = Arc::clone(&self.event_handler);
+        let pattern_matcher = Arc::clone(&self.pattern_matcher);
         
-        thread::spawn(move || {
-            info!("Network monitoring thread started");
+        move |tasks: &mut TaskGroup| {
+            let netlink = Arc::clone(&netlink);
+            let process_monitor = Arc::clone(&process_monitor);
+            let policy = Arc::clone(&policy);
+            let pattern_matcher = Arc::clone(&pattern_matcher);
+            let events = events.clone();
+            let heartbeat = heartbeat.clone();
             
-            while *running.lock().unwrap() {
-                let nm = match netlink.lock() {
-                    Ok(nm) => nm,
+            Ok(tasks.spawn_periodic("Network monitoring", Duration::from_secs(1), move || {
+                heartbeat.beat();
+                let connections = match netlink.lock() {
+                    Ok(nm) => nm.get_tcp_connections(),
                     Err(_) => {
                         error!("Failed to lock netlink monitor");
-                        break;
+                        return;
                     }
                 };
                 
-                match nm.get_tcp_connections() {
+                match connections {
                     Ok(connections) => {
-                        drop(nm); // Release lock
-                        
                         for conn in connections {
-                            Self::handle_network_connection(
-                                &conn,
-                                &process_monitor,
-                                &policy,
-                                &event_handler,
-                            );
+                            Self::handle_network_connection(&conn, &process_monitor, &policy, &pattern_matcher, &events);
                         }
                     }
                     Err(e) => {
                         error!("Error getting network connections: {}", e);
                     }
                 }
-                
-                thread::sleep(Duration::from_secs(1));
-            }
-            
-            info!("Network monitoring thread stopped");
-        });
+            }))
+        }
+    }
+    
+    // Spawns a task and hands it to the supervisor, which aborts and respawns
+    // it when it exits or stops beating
+    fn supervise_task<S>(&self, tasks: &Arc<Mutex<TaskGroup>>, name: &str, heartbeat: Heartbeat, spawn: S) -> Result<()>
+    where
+        S: Fn(&mut TaskGroup) -> Result<AbortHandle> + Send + 'static
+    {
+        let current = {
+            let mut group = tasks.lock()
+                .map_err(|_| anyhow!("Failed to acquire task group lock"))?;
+            spawn(&mut group)?
+        };
+        let current = Arc::new(Mutex::new(current));
+        let alive = Arc::clone(&current);
+        let tasks = Arc::clone(tasks);
+        
+        self.supervisor.supervise(name, Some(heartbeat),
+            move || alive.lock().map(|task| !task.is_finished()).unwrap_or(false),
+            move || {
+                let mut group = tasks.lock()
+                    .map_err(|_| anyhow!("Failed to acquire task group lock"))?;
+                let mut current = current.lock()
+                    .map_err(|_| anyhow!("Failed to acquire task handle lock"))?;
+                // A hung task still holds its descriptor registration
+                current.abort();
+                *current = spawn(&mut group)?;
+                Ok(())
+            });
+        Ok(())
     }
     
     fn handle_network_connection(
         conn: &NetworkConnection,
         process_monitor: &Arc<Mutex<ProcessMonitor>>,
-        policy: &Arc<RwLock<SecurityPolicy>>,
-        event_handler: &Arc<dyn Fn(SecurityEvent) + Send + Sync>,
+        policy_lock: &Arc<RwLock<SecurityPolicy>>,
+        pattern_matcher: &PatternMatcher,
+        events: &EventSender,
     ) {
         let process_info = process_monitor
             .lock()
             .ok()
             .and_then(|pm| pm.find_process_by_inode(conn.inode).cloned());
         
-        let monitor_process_info = if let Some(info) = process_info {
+        let monitor_process_info = if let Some(ref info) = process_info {
             MonitorProcessInfo {
                 pid: info.pid,
-                path: info.exe_path.unwrap_or_else(|| PathBuf::from(&info.name)),
+                path: info.exe_path.clone().unwrap_or_else(|| PathBuf::from(&info.name)),
                 parent_pid: Some(info.ppid),
                 user_id: info.uid,
                 executable_hash: None,
@@ -511,7 +1121,7 @@ impl EnhancedSecurityMonitor {
         };
         
         // Check policy
-        let policy = match policy.read() {
+        let policy = match policy_lock.read() {
             Ok(p) => p,
             Err(_) => return,
         };
@@ -542,30 +1152,338 @@ impl EnhancedSecurityMonitor {
             policy_reason: "Network policy".to_string(),
         };
         
-        event_handler(security_event);
+        events.publish_routine(security_event);
+        drop(policy);
+        
+        // Only a connection not seen before can make a network pattern match
+        let Some(process) = process_info else {
+            return;
+        };
+        if !pattern_matcher.record_network_connection(&process, conn.remote_addr, conn.remote_port) {
+            return;
+        }
+        for (pattern, _) in pattern_matcher.check_network_activity(&process) {
+            let detail = format!("connected to {}", SocketAddr::new(conn.remote_addr, conn.remote_port));
+            let event_type = SecurityEventType::NetworkConnection {
+                remote_ip: conn.remote_addr.to_string(),
+                remote_port: conn.remote_port,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            };
+            Self::report_detection(policy_lock, events, &process, (&pattern.name, &pattern.category, pattern.severity), &detail, event_type);
+        }
     }
     
-    fn start_process_scanning_thread(&self) {
-        let process_monitor = Arc::clone(&self.process_monitor);
+    // Kernel process events keep the table current; the /proc scan then only
+    // reconciles. Without the connector (no CAP_NET_ADMIN) scanning does it all.
+    fn start_process_tracking(&self, tasks: &mut TaskGroup) {
+        let reconcile_period = match self.start_proc_connector_task(tasks) {
+            Ok(()) => PROCESS_RECONCILE_INTERVAL,
+            Err(e) => {
+                warn!("Process events unavailable, falling back to /proc scanning: {}", e);
+                PROCESS_SCAN_INTERVAL
+            }
+        };
+        self.start_process_scanning_task(tasks, reconcile_period);
+        self.start_resource_sampling_task(tasks);
+        self.start_loader_hijack_detection(tasks);
+        
         let policy = Arc::clone(&self.policy);
-        let running = Arc::clone(&self.running);
-        let event_handler = Arc::clone(&self.event_handler);
+        self.process_changes.subscribe("exemptions", move |change| {
+            if let (ProcessChange::Exec(process) | ProcessChange::Exited(process), Ok(policy)) = (change, policy.read()) {
+                policy.exemptions.forget(process.pid);
+            }
+        });
         
-        thread::spawn(move || {
-            info!("Process scanning thread started");
+        let events = self.event_bus.clone();
+        tasks.spawn_periodic("File access summaries", SUMMARY_FLUSH_INTERVAL, move || events.publish_summaries(false));
+        
+        let fanotify = Arc::clone(&self.fanotify);
+        tasks.spawn_periodic("Mount namespace marks", MOUNT_NAMESPACE_INTERVAL, move || {
+            if let Ok(mut fanotify) = fanotify.lock() {
+                let marked = fanotify.mark_mount_namespaces();
+                if marked > 0 {
+                    info!("Marked {} new mount namespaces", marked);
+                }
+            }
+        });
+    }
+    
+    fn start_proc_connector_task(&self, tasks: &mut TaskGroup) -> Result<()> {
+        let connector = Arc::new(ProcConnector::open()?);
+        let readiness = {
+            let _runtime = tasks.handle().enter();
+            AsyncFd::with_interest(Arc::clone(&connector), Interest::READABLE)?
+        };
+        let process_monitor = Arc::clone(&self.process_monitor);
+        let changes = Arc::clone(&self.process_changes);
+        
+        tasks.spawn(move |mut shutdown| async move {
+            info!("Process event task started");
             
-            while *running.lock().unwrap() {
-                if let Ok(mut pm) = process_monitor.lock() {
-                    if let Err(e) = pm.refresh_processes() {
-                        error!("Error refreshing process list: {}", e);
+            loop {
+                let mut ready = tokio::select! {
+                    _ = shutdown.wait() => break,
+                    ready = readiness.readable() => match ready {
+                        Ok(ready) => ready,
+                        Err(e) => {
+                            error!("Error polling process events: {}", e);
+                            break;
+                        }
+                    },
+                };
+                
+                // Applying an event reads /proc, so it runs on the blocking pool
+                let connector = Arc::clone(&connector);
+                let process_monitor = Arc::clone(&process_monitor);
+                let changes = Arc::clone(&changes);
+                let drained = tokio::task::spawn_blocking(move || {
+                    Self::drain_proc_events(&connector, &process_monitor, &changes)
+                }).await;
+                
+                if let Err(e) = drained {
+                    error!("Process event task failed: {}", e);
+                    break;
+                }
+                ready.clear_ready();
+            }
+            
+            info!("Process event task stopped");
+        });
+        Ok(())
+    }
+    
+    fn drain_proc_events(
+        connector: &ProcConnector,
+        process_monitor: &Arc<Mutex<ProcessMonitor>>,
+        changes: &EventBus<ProcessChange>,
+    ) {
+        let events = match connector.read_events() {
+            Ok(events) => events,
+            Err(e) if e.kind() == std::io::ErrorKind::OutOfMemory => {
+                // The kernel dropped events; resynchronise from /proc now
+                warn!("Process events overflowed, rescanning /proc");
+                Self::refresh_process_table(process_monitor, changes);
+                return;
+            }
+            Err(e) => {
+                error!("Error reading process events: {}", e);
+                return;
+            }
+        };
+        
+        if let Ok(mut pm) = process_monitor.lock() {
+            for event in &events {
+                if let Some(change) = pm.apply_event(event) {
+                    changes.publish(change);
+                }
+            }
+        }
+    }
+    
+    fn refresh_process_table(process_monitor: &Arc<Mutex<ProcessMonitor>>, changes: &EventBus<ProcessChange>) {
+        if let Ok(mut pm) = process_monitor.lock() {
+            match pm.refresh_processes() {
+                Ok(diff) => {
+                    for change in diff {
+                        changes.publish(change);
                     }
                 }
-                
-                thread::sleep(Duration::from_secs(5));
+                Err(e) => error!("Error refreshing process list: {}", e),
             }
+        }
+    }
+    
+    fn start_process_scanning_task(&self, tasks: &mut TaskGroup, period: Duration) {
+        let process_monitor = Arc::clone(&self.process_monitor);
+        let changes = Arc::clone(&self.process_changes);
+        
+        tasks.spawn_periodic("Process scanning", period, move || {
+            Self::refresh_process_table(&process_monitor, &changes);
+        });
+    }
+    
+    // Samples every tracked process and reports each resource usage pattern
+    // once per process, until its usage drops back below the thresholds
+    fn start_resource_sampling_task(&self, tasks: &mut TaskGroup) {
+        let process_monitor = Arc::clone(&self.process_monitor);
+        let pattern_matcher = Arc::clone(&self.pattern_matcher);
+        let policy = Arc::clone(&self.policy);
+        let events = self.event_bus.clone();
+        let mut reported: HashSet<(u32, u64, String)> = HashSet::new();
+        
+        tasks.spawn_periodic("Resource sampling", RESOURCE_SAMPLE_INTERVAL, move || {
+            let processes = match process_monitor.lock() {
+                Ok(pm) => pm.processes(),
+                Err(_) => return,
+            };
+            let pids: Vec<u32> = processes.iter().map(|process| process.pid).collect();
+            pattern_matcher.sample_resource_usage(&pids);
             
-            info!("Process scanning thread stopped");
+            let mut current = HashSet::new();
+            for process in &processes {
+                for (pattern, usage) in pattern_matcher.check_resource_usage(process) {
+                    let key = (process.pid, process.start_time, pattern.id.clone());
+                    if !reported.contains(&key) {
+                        let detail = format!(
+                            "{:.0}% CPU, {} MiB resident for {}s",
+                            usage.average_cpu, usage.peak_memory >> 20, usage.duration.as_secs()
+                        );
+                        let path = process.exe_path.clone().unwrap_or_else(|| PathBuf::from(&process.name));
+                        let event_type = SecurityEventType::FileExecution {
+                            target_path: path,
+                            file_hash: None,
+                            code_signature: None,
+                        };
+                        Self::report_detection(&policy, &events, process, (&pattern.name, &pattern.category, pattern.severity), &detail, event_type);
+                    }
+                    current.insert(key);
+                }
+            }
+            reported = current;
+        });
+    }
+    
+    // Loader variables are checked as processes start and exec, once per
+    // executable and value since children inherit them; the preload file
+    // and loader directories are checked on a timer
+    fn start_loader_hijack_detection(&self, tasks: &mut TaskGroup) {
+        let detector = Arc::clone(&self.loader_hijack);
+        let policy = Arc::clone(&self.policy);
+        let events = self.event_bus.clone();
+        let reported = Mutex::new(HashSet::new());
+        self.process_changes.subscribe("loader-hijack", move |change| {
+            let (ProcessChange::Started(process) | ProcessChange::Exec(process)) = change else {
+                return;
+            };
+            if process.pid == std::process::id() {
+                return;
+            }
+            let Some(finding) = detector.check_process(process.pid) else {
+                return;
+            };
+            let LoaderFindingKind::Environment { ref variable, ref value, .. } = finding.kind else {
+                return;
+            };
+            let Ok(mut reported) = reported.lock() else {
+                return;
+            };
+            if reported.len() >= MAX_LOADER_REPORTS {
+                reported.clear();
+            }
+            if !reported.insert((process.exe_path.clone(), value.clone())) {
+                return;
+            }
+            drop(reported);
+            let path = process.exe_path.clone().unwrap_or_else(|| PathBuf::from(&process.name));
+            let detail = format!("{}={}", variable, value);
+            let event_type = SecurityEventType::FileExecution {
+                target_path: path,
+                file_hash: None,
+                code_signature: None,
+            };
+            let name = format!("Loader hijack via {}", variable);
+            Self::report_detection(&policy, &events, &process, (&name, &PatternCategory::LibraryInjection, finding.severity), &detail, event_type);
+        });
+        
+        let detector = Arc::clone(&self.loader_hijack);
+        let policy = Arc::clone(&self.policy);
+        let events = self.event_bus.clone();
+        tasks.spawn_periodic("Loader hijack scan", LOADER_SCAN_INTERVAL, move || {
+            for finding in detector.scan() {
+                Self::report_loader_file(&policy, &events, &finding);
+            }
+        });
+    }
+    
+    // Preload file and shared object findings have no process to act on,
+    // so they are only reported
+    fn report_loader_file(policy: &Arc<RwLock<SecurityPolicy>>, events: &EventSender, finding: &LoaderFinding) {
+        let Ok(policy) = policy.read() else {
+            return;
+        };
+        let action = policy.escalation.action_for(&PatternCategory::LibraryInjection, finding.severity);
+        warn!("{} -> {:?}", finding.description, action);
+        if action < EnforcementAction::Alert {
+            return;
+        }
+        let target_path = match finding.kind {
+            LoaderFindingKind::PreloadFile { ref path, .. } | LoaderFindingKind::NewSharedObject { ref path } => path.clone(),
+            LoaderFindingKind::Environment { .. } => return,
+        };
+        events.publish(SecurityEvent {
+            id: finding.id.clone(),
+            timestamp: finding.timestamp,
+            event_type: SecurityEventType::FileAccess {
+                target_path,
+                access_type: FileAccessType::Write,
+            },
+            process_info: MonitorProcessInfo {
+                pid: 0,
+                path: PathBuf::from("unknown"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: format!("Loader hijack ({:?} {:?}): {}", finding.severity, PatternCategory::LibraryInjection, finding.description),
+        });
+    }
+    
+    // Publishes a detection (name, category, severity) for a process outside
+    // of a fanotify decision, acting on it as the escalation matrix says
+    fn report_detection(
+        policy: &Arc<RwLock<SecurityPolicy>>,
+        events: &EventSender,
+        process: &ProcessInfo,
+        (name, category, severity): (&str, &PatternCategory, Severity),
+        detail: &str,
+        event_type: SecurityEventType,
+    ) {
+        let Ok(policy) = policy.read() else {
+            return;
+        };
+        // Name the unit or container so the workload behind it is clear
+        let detail = match workload_for_pid(process.pid) {
+            Some(workload) => format!("{} in {}", detail, workload.name),
+            None => detail.to_string(),
+        };
+        let action = policy.escalation.action_for(category, severity);
+        let enforcing = policy.enforcement_mode == EnforcementMode::Enforcing && action.denies();
+        let exemption = if enforcing { policy.exemptions.exemption(process.pid) } else { None };
+        let enforce = enforcing && exemption.is_none();
+        warn!("{} by {} (pid {}): {} -> {:?}", name, process.name, process.pid, detail, action);
+        if action < EnforcementAction::Alert {
+            return;
+        }
+        
+        let path = process.exe_path.clone().unwrap_or_else(|| PathBuf::from(&process.name));
+        let reason = if let Some(exemption) = exemption {
+            format!("{} ({:?} {:?}, {}): {:?} skipped, {}", name, severity, category, detail, action, exemption)
+        } else if enforce || !action.denies() {
+            format!("{} ({:?} {:?}, {}): {:?}", name, severity, category, detail, action)
+        } else {
+            format!("{} ({:?} {:?}, {}): would {:?} in enforcing mode", name, severity, category, detail, action)
+        };
+        events.publish(SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            process_info: MonitorProcessInfo {
+                pid: process.pid,
+                path: path.clone(),
+                parent_pid: Some(process.ppid),
+                user_id: process.uid,
+                executable_hash: None,
+                command_line: Some(process.cmdline.join(" ")),
+            },
+            verdict: if enforce { Verdict::Deny } else { Verdict::Allow },
+            policy_reason: reason,
         });
+        if enforce {
+            Self::apply_enforcement(action, process.pid, &path, &policy);
+        }
     }
     
     pub fn stop(&mut self) -> Result<()> {
@@ -579,6 +1497,16 @@ impl EnhancedSecurityMonitor {
         
         info!("Stopping enhanced security monitoring");
         
+        // Stop supervising first so nothing is restarted during shutdown
+        self.supervisor.stop();
+        if let Some(tasks) = self.tasks.take() {
+            if let Ok(mut tasks) = tasks.lock() {
+                tasks.shutdown();
+            }
+        }
+        
+        self.event_bus.publish_summaries(true);
+        
         // Stop all monitors
         if let Ok(mut fm) = self.fanotify.lock() {
             fm.stop()?;
@@ -592,6 +1520,12 @@ impl EnhancedSecurityMonitor {
             pm.stop()?;
         }
         
+        if let Ok(mut egress) = self.egress.lock() {
+            if let Some(enforcer) = egress.take() {
+                enforcer.detach()?;
+            }
+        }
+        
         Ok(())
     }
     
@@ -603,6 +1537,10 @@ impl EnhancedSecurityMonitor {
         let mut policy = self.policy.write()
             .map_err(|_| anyhow!("Failed to acquire policy write lock"))?;
         update_fn(&mut policy);
+        policy.version += 1;
+        if let Ok(mut cache) = self.decision_cache.lock() {
+            cache.invalidate();
+        }
         Ok(())
     }
     
@@ -633,6 +1571,233 @@ impl EnhancedSecurityMonitor {
     pub fn add_denied_executable(&self, path: PathBuf) -> Result<()> {
         self.update_policy(|p| { p.denied_executables.insert(path); })
     }
+    
+    pub fn set_escalation_matrix(&self, matrix: EscalationMatrix) -> Result<()> {
+        self.update_policy(|p| p.escalation = matrix)
+    }
+    
+    pub fn load_escalation_matrix(&self, path: &Path) -> Result<()> {
+        let matrix = EscalationMatrix::load_from_file(path)?;
+        self.set_escalation_matrix(matrix)
+    }
+    
+    pub fn set_quarantine_dir(&self, dir: PathBuf) -> Result<()> {
+        self.update_policy(|p| p.quarantine_dir = dir)
+    }
+    
+    // Configured processes add to the built-in exemptions
+    pub fn set_exemptions(&self, config: &ExemptionConfig) -> Result<()> {
+        let exemptions = Exemptions::new(config);
+        self.update_policy(|p| p.exemptions = exemptions)
+    }
+    
+    // Known-bad/known-good databases and enrichment sources are configured here
+    pub fn set_hash_cache_capacity(&self, capacity: usize) -> Result<()> {
+        let mut cache = self.hash_cache.lock().map_err(|_| anyhow!("Failed to acquire hash cache lock"))?;
+        cache.set_capacity(capacity);
+        Ok(())
+    }
+    
+    // Loader hijack findings, newest first, with the environment of each flagged process
+    pub fn loader_findings(&self, limit: usize) -> Vec<LoaderFinding> {
+        self.loader_hijack.recent_findings(limit)
+    }
+    
+    pub fn hash_cache_stats(&self) -> HashCacheStats {
+        self.hash_cache.lock().map(|cache| cache.stats()).unwrap_or_default()
+    }
+    
+    pub fn set_decision_cache_capacity(&self, capacity: usize) -> Result<()> {
+        let mut cache = self.decision_cache.lock().map_err(|_| anyhow!("Failed to acquire decision cache lock"))?;
+        cache.set_capacity(capacity);
+        Ok(())
+    }
+    
+    pub fn decision_cache_stats(&self) -> DecisionCacheStats {
+        self.decision_cache.lock().map(|cache| cache.stats()).unwrap_or_default()
+    }
+    
+    // The parts of the agent config this monitor takes; call before start()
+    pub fn apply_config(&self, config: &Config) -> Result<()> {
+        self.set_file_watch(config.file_watch.clone())?;
+        self.set_decision_cache_capacity(config.decision_cache_capacity)
+    }
+    
+    // Re-applies the file watch whenever `config` is reloaded with a new one.
+    // Exclusions and per-path events change at once, new paths are only
+    // marked on the next start
+    pub fn follow_reloads(&self, config: &ReloadableConfig) {
+        let fanotify = Arc::clone(&self.fanotify);
+        config.on_reload(move |config, report| {
+            if !report.applied.iter().any(|field| field == "file_watch") {
+                return;
+            }
+            match fanotify.lock() {
+                Ok(mut fanotify) => fanotify.set_watch_config(config.file_watch.clone()),
+                Err(_) => warn!("Failed to acquire fanotify lock; file watch not reloaded"),
+            }
+        });
+    }
+    
+    // Trees built from this monitor's live process table and spawn history
+    pub fn process_tree_source(&self) -> ProcessTreeSource {
+        ProcessTreeSource::new(
+            Arc::clone(&self.process_monitor),
+            Arc::clone(&self.pattern_matcher),
+            Arc::clone(&self.hash_cache),
+        )
+    }
+    
+    pub fn reputation(&self) -> Arc<ReputationPipeline> {
+        Arc::clone(&self.reputation)
+    }
+    
+    // Verify executed binaries against the dpkg/rpm databases, e.g. with
+    // PackageVerifier::load(); takes effect for the next batch of events
+    pub fn set_package_verifier(&self, verifier: PackageVerifier) -> Result<()> {
+        let verifier = Arc::new(verifier);
+        self.pattern_matcher.set_package_verifier(Arc::clone(&verifier))?;
+        *self.package_verifier.write()
+            .map_err(|_| anyhow!("Failed to acquire package verifier lock"))? = Some(verifier);
+        Ok(())
+    }
+    
+    // Trial a behavior pattern: its matches are counted in `log` but
+    // neither reported nor enforced
+    pub fn set_pattern_shadow(&self, id: &str, shadow: bool, log: ShadowLog) -> Result<()> {
+        self.pattern_matcher.set_shadow_log(log)?;
+        self.pattern_matcher.set_pattern_shadow(id, shadow)
+    }
+    
+    // Count pattern matches, so patterns that never fire can be found
+    pub fn set_rule_stats(&self, stats: RuleStats) -> Result<()> {
+        self.pattern_matcher.set_rule_stats(stats)
+    }
+    
+    // Feed DNS answers seen elsewhere (e.g. a NetworkFilter) so network
+    // patterns can match the domains of later connections
+    pub fn record_dns_answer(&self, domain: &str, addresses: &[IpAddr]) {
+        self.pattern_matcher.record_dns_answer(domain, addresses);
+    }
+    
+    // Directories where completed writes get the dropper heuristics; defaults
+    // to the temporary directories. Call before start() so newly listed
+    // mounts are watched.
+    pub fn set_dropper_directories(&self, directories: Vec<PathBuf>) -> Result<()> {
+        self.fanotify.lock()
+            .map_err(|_| anyhow!("Failed to acquire fanotify lock"))?
+            .set_write_watch_paths(directories.clone());
+        *self.dropper_directories.write()
+            .map_err(|_| anyhow!("Failed to acquire dropper directories lock"))? = directories;
+        Ok(())
+    }
+    
+    // Gate every open (not only executions) on the hash reputation; call before start()
+    pub fn set_on_access_scanning(&self, enabled: bool) -> Result<()> {
+        self.fanotify.lock()
+            .map_err(|_| anyhow!("Failed to acquire fanotify lock"))?
+            .set_open_permission_checks(enabled);
+        Ok(())
+    }
+    
+    // Paths watched and the events wanted for each; call before start() so
+    // newly included mounts are marked
+    pub fn set_file_watch(&self, config: FileWatchConfig) -> Result<()> {
+        self.fanotify.lock()
+            .map_err(|_| anyhow!("Failed to acquire fanotify lock"))?
+            .set_watch_config(config);
+        Ok(())
+    }
+    
+    // Denies matching outbound connections at connect() time for every process in
+    // the cgroup (the cgroup v2 root covers the whole host). Calling it again
+    // replaces the rules.
+    pub fn set_egress_rules(&self, cgroup_path: &Path, rules: &[EgressRule]) -> Result<()> {
+        let mut egress = self.egress.lock()
+            .map_err(|_| anyhow!("Failed to acquire egress lock"))?;
+        
+        if egress.is_none() {
+            let enforcer = EgressEnforcer::new(cgroup_path)?;
+            enforcer.set_rules(rules)?;
+            enforcer.attach()?;
+            enforcer.start_process_tracking();
+            *egress = Some(enforcer);
+        } else if let Some(ref enforcer) = *egress {
+            enforcer.set_rules(rules)?;
+        }
+        Ok(())
+    }
+    
+    pub fn denied_egress_connections(&self) -> u64 {
+        self.egress.lock().ok()
+            .and_then(|egress| egress.as_ref().map(|e| e.denied_connections()))
+            .unwrap_or(0)
+    }
+    
+    // Reports fanotify and netlink attachment, pending restarts, queued events
+    // and the time of the last event to `registry`
+    pub fn register_health(&self, registry: &Arc<HealthRegistry>) {
+        let fanotify = Arc::clone(&self.fanotify);
+        registry.probe("fanotify", true, move || match fanotify.lock() {
+            Ok(fanotify) if fanotify.is_running() => (ComponentState::Up, format!("attached (fd {})", fanotify.as_raw_fd())),
+            Ok(_) => (ComponentState::Starting, "not attached".to_string()),
+            Err(_) => (ComponentState::Down, "monitor lock poisoned".to_string()),
+        });
+        
+        let watchdog = Arc::clone(&self.permission_watchdog);
+        let decision_cache = Arc::clone(&self.decision_cache);
+        registry.probe("fanotify_decisions", false, move || {
+            let stats = watchdog.stats();
+            let cache = decision_cache.lock().map(|cache| cache.stats()).unwrap_or_default();
+            if stats.notification_only {
+                (ComponentState::Degraded, format!("notification only after {} overruns", stats.overruns))
+            } else if stats.overruns > 0 {
+                (ComponentState::Degraded, format!("{} overruns, slowest {:.0}ms", stats.overruns, stats.max_latency_ms))
+            } else {
+                (ComponentState::Up, format!("{} decisions, average {:.1}ms, {:.0}% exec cache hits",
+                                             stats.decisions, stats.average_latency_ms, cache.hit_rate * 100.0))
+            }
+        });
+        
+        let decision_cache = Arc::clone(&self.decision_cache);
+        registry.probe("exec_decision_cache", false, move || match decision_cache.lock() {
+            Ok(cache) => {
+                let stats = cache.stats();
+                (ComponentState::Up, format!("{} of {} entries, {} hits, {} misses ({:.0}%), {} evicted, {} invalidated",
+                                             stats.entries, stats.capacity, stats.hits, stats.misses,
+                                             stats.hit_rate * 100.0, stats.evictions, stats.invalidations))
+            }
+            Err(_) => (ComponentState::Down, "cache lock poisoned".to_string()),
+        });
+        
+        let netlink = Arc::clone(&self.netlink);
+        registry.probe("netlink", false, move || match netlink.lock() {
+            Ok(netlink) if netlink.is_running() => (ComponentState::Up, "watching connections".to_string()),
+            Ok(_) => (ComponentState::Starting, "not started".to_string()),
+            Err(_) => (ComponentState::Down, "monitor lock poisoned".to_string()),
+        });
+        
+        self.supervisor.register_health(registry);
+        
+        let sampler = Arc::clone(&self.event_bus.sampler);
+        registry.probe("event_sampling", false, move || {
+            let sampled: Vec<String> = sampler.metrics().types.into_iter()
+                .filter(|sampling| sampling.rate < 1.0)
+                .map(|sampling| format!("{} at {:.1}%", sampling.event_type, sampling.rate * 100.0))
+                .collect();
+            if sampled.is_empty() {
+                (ComponentState::Up, "all events kept".to_string())
+            } else {
+                (ComponentState::Degraded, format!("sampling {}", sampled.join(", ")))
+            }
+        });
+        
+        let event_bus = Arc::clone(&self.event_bus.bus);
+        registry.set_queue_depth(move || event_bus.metrics().sinks.iter().map(|sink| sink.depth).sum());
+        
+        let registry = Arc::clone(registry);
+        self.event_bus.bus.subscribe("health", move |event: SecurityEvent| registry.record_event(event.timestamp));
+    }
 }
 
 #[cfg(test)]
@@ -653,6 +1818,10 @@ mod tests {
             allowed_ports: HashSet::new(),
             denied_ports: HashSet::new(),
             suspicious_patterns: Vec::new(),
+            escalation: EscalationMatrix::default(),
+            quarantine_dir: PathBuf::from("/var/quarantine/fluxdefense"),
+            exemptions: Exemptions::default(),
+            version: 0,
             enforcement_mode: EnforcementMode::Passive,
             log_allowed: false,
             log_denied: true,
@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, warn};
+
+use super::patterns::{PatternCategory, Severity};
+use crate::exemptions::Exemptions;
+
+// Ordered from least to most disruptive so the strongest of several detections wins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnforcementAction {
+    Log,
+    Alert,
+    Block,
+    Kill,
+    Quarantine,
+}
+
+impl EnforcementAction {
+    pub fn denies(&self) -> bool {
+        *self >= EnforcementAction::Block
+    }
+}
+
+// One cell of the matrix. A missing category applies to every category.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationEntry {
+    #[serde(default)]
+    pub category: Option<PatternCategory>,
+    pub severity: Severity,
+    pub action: EnforcementAction,
+}
+
+// Maps (pattern category, severity) to an enforcement action. Category-specific
+// entries take precedence over category-wide ones; severities without any
+// entry fall back to `default_action`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationMatrix {
+    #[serde(default)]
+    pub entries: Vec<EscalationEntry>,
+    #[serde(default = "default_action")]
+    pub default_action: EnforcementAction,
+}
+
+fn default_action() -> EnforcementAction {
+    EnforcementAction::Log
+}
+
+impl Default for EscalationMatrix {
+    fn default() -> Self {
+        let entry = |severity, action| EscalationEntry { category: None, severity, action };
+        Self {
+            entries: vec![
+                entry(Severity::Critical, EnforcementAction::Block),
+                entry(Severity::High, EnforcementAction::Block),
+                entry(Severity::Medium, EnforcementAction::Alert),
+                entry(Severity::Low, EnforcementAction::Log),
+            ],
+            default_action: default_action(),
+        }
+    }
+}
+
+impl EscalationMatrix {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read escalation matrix {:?}", path))?;
+        let matrix: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid escalation matrix {:?}", path))?;
+        info!("Loaded escalation matrix with {} entries from {:?}", matrix.entries.len(), path);
+        Ok(matrix)
+    }
+
+    pub fn action_for(&self, category: &PatternCategory, severity: Severity) -> EnforcementAction {
+        let specific = self.entries.iter()
+            .find(|e| e.severity == severity && e.category.as_ref() == Some(category));
+        let wildcard = || self.entries.iter()
+            .find(|e| e.severity == severity && e.category.is_none());
+
+        specific.or_else(wildcard)
+            .map(|e| e.action)
+            .unwrap_or(self.default_action)
+    }
+
+    // Replaces any existing entry for the same cell
+    pub fn set_action(&mut self, category: Option<PatternCategory>, severity: Severity, action: EnforcementAction) {
+        self.entries.retain(|e| !(e.severity == severity && e.category == category));
+        self.entries.push(EscalationEntry { category, severity, action });
+    }
+}
+
+pub fn kill_process(pid: u32, exemptions: &Exemptions) -> Result<()> {
+    if pid <= 1 {
+        return Err(anyhow!("Refusing to kill pid {}", pid));
+    }
+    if let Some(reason) = exemptions.exemption(pid) {
+        return Err(anyhow!("Refusing to kill pid {}: {}", pid, reason));
+    }
+    if unsafe { libc::kill(pid as i32, libc::SIGKILL) } != 0 {
+        return Err(anyhow!("kill({}) failed: {}", pid, std::io::Error::last_os_error()));
+    }
+    warn!("Killed process {}", pid);
+    Ok(())
+}
+
+// Kills a process and everything it started, children before parents. The
+// root is stopped first so it cannot fork replacements meanwhile. Exempt
+// descendants are spared. Returns the pids that were signalled.
+pub fn kill_process_tree(pid: u32, exemptions: &Exemptions) -> Result<Vec<u32>> {
+    if pid <= 1 {
+        return Err(anyhow!("Refusing to kill pid {}", pid));
+    }
+    if let Some(reason) = exemptions.exemption(pid) {
+        return Err(anyhow!("Refusing to kill pid {}: {}", pid, reason));
+    }
+    if unsafe { libc::kill(pid as i32, libc::SIGSTOP) } != 0 {
+        return Err(anyhow!("kill({}) failed: {}", pid, std::io::Error::last_os_error()));
+    }
+
+    let parents = parent_pids();
+    let mut tree = vec![pid];
+    let mut next = 0;
+    while next < tree.len() {
+        let parent = tree[next];
+        tree.extend(parents.iter().filter(|(_, ppid)| *ppid == parent).map(|(child, _)| *child));
+        next += 1;
+    }
+
+    let mut killed = Vec::new();
+    for &member in tree.iter().rev() {
+        if member != pid {
+            if let Some(reason) = exemptions.exemption(member) {
+                warn!("Sparing pid {} in the process tree of {}: {}", member, pid, reason);
+                continue;
+            }
+        }
+        // Children may already have exited
+        if unsafe { libc::kill(member as i32, libc::SIGKILL) } == 0 {
+            killed.push(member);
+        }
+    }
+    warn!("Killed process tree of {} ({} processes)", pid, killed.len());
+    Ok(killed)
+}
+
+fn parent_pids() -> Vec<(u32, u32)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            // The command name can hold spaces, so count from the last `)`
+            let ppid = stat[stat.rfind(')')? + 1..].split_whitespace().nth(1)?.parse().ok()?;
+            Some((pid, ppid))
+        })
+        .collect()
+}
+
+// Moves a file into the quarantine directory and strips its permissions.
+// Returns the quarantined path.
+pub fn quarantine_file(path: &Path, quarantine_dir: &Path, exemptions: &Exemptions) -> Result<PathBuf> {
+    if let Some(reason) = exemptions.protected_file(path) {
+        return Err(anyhow!("Refusing to quarantine {:?}: {}", path, reason));
+    }
+    std::fs::create_dir_all(quarantine_dir)
+        .with_context(|| format!("Failed to create quarantine directory {:?}", quarantine_dir))?;
+
+    let name = path.file_name()
+        .ok_or_else(|| anyhow!("Cannot quarantine {:?}", path))?
+        .to_string_lossy();
+    let target = quarantine_dir.join(format!("{}.{}", chrono::Utc::now().format("%Y%m%dT%H%M%S"), name));
+
+    // rename fails across filesystems
+    if std::fs::rename(path, &target).is_err() {
+        std::fs::copy(path, &target)
+            .with_context(|| format!("Failed to copy {:?} to quarantine", path))?;
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove {:?} after quarantine", path))?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o000))?;
+    }
+
+    warn!("Quarantined {:?} to {:?}", path, target);
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_lookup_precedence() {
+        let mut matrix = EscalationMatrix::default();
+        matrix.set_action(Some(PatternCategory::ReverseShell), Severity::Critical, EnforcementAction::Kill);
+        matrix.entries.retain(|e| e.severity != Severity::Low);
+
+        assert_eq!(matrix.action_for(&PatternCategory::ReverseShell, Severity::Critical), EnforcementAction::Kill);
+        assert_eq!(matrix.action_for(&PatternCategory::CryptoMiner, Severity::Critical), EnforcementAction::Block);
+        assert_eq!(matrix.action_for(&PatternCategory::Reconnaissance, Severity::Medium), EnforcementAction::Alert);
+        assert_eq!(matrix.action_for(&PatternCategory::Reconnaissance, Severity::Low), EnforcementAction::Log);
+        assert!(EnforcementAction::Quarantine.denies());
+        assert!(!EnforcementAction::Alert.denies());
+
+        let json = r#"{"entries": [{"category": "reverse_shell", "severity": "critical", "action": "kill"}]}"#;
+        let parsed: EscalationMatrix = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.action_for(&PatternCategory::ReverseShell, Severity::Critical), EnforcementAction::Kill);
+        assert_eq!(parsed.action_for(&PatternCategory::ReverseShell, Severity::High), EnforcementAction::Log);
+    }
+}
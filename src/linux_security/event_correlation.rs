@@ -1,30 +1,82 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn, error, debug};
 
 use crate::monitor::{SecurityEvent, SecurityEventType, ProcessInfo, Verdict};
+use crate::scripting::{CompiledScript, RuleScript};
+use crate::policy::PolicyVerifier;
+use crate::rule_stats::{RuleEngine, RuleStats};
+use crate::shadow::ShadowLog;
+pub use crate::rate_limit::{RateLimiter, RateLimiterConfig};
 
 // Event correlation engine for detecting complex attack patterns
 // by analyzing relationships between multiple security events
 
-#[derive(Debug, Clone)]
+// Rules can also be written as YAML or JSON files; durations are in seconds:
+//
+//   rules:
+//     - id: ssh_brute_force
+//       name: SSH brute force
+//       severity: high
+//       time_window: 60
+//       pattern:
+//         type: event_cluster
+//         event_type: { event_type: authentication_failure, process_name: sshd }
+//         min_count: 5
+//
+// A rule's `script` runs when its pattern matches, with the triggering
+// `event` and the matched `events`. Returning false drops the match and a
+// string replaces its description. Rules with `shadow: true` are evaluated
+// as usual but their matches are only counted in the shadow log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CorrelationRule {
     pub id: String,
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub pattern: CorrelationPattern,
+    #[serde(with = "duration_secs")]
     pub time_window: Duration,
     pub severity: Severity,
+    #[serde(default = "default_enabled")]
     pub enabled: bool,
+    // Matches only go to the shadow log, so a new rule can be trialed
+    // before it raises anything
+    #[serde(default)]
+    pub shadow: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<RuleScript>,
 }
 
-#[derive(Debug, Clone)]
+fn default_enabled() -> bool {
+    true
+}
+
+mod duration_secs {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum CorrelationPattern {
     // Sequential events from same process
     ProcessSequence {
         events: Vec<EventMatcher>,
+        #[serde(with = "duration_secs")]
         max_time_between: Duration,
     },
     
@@ -60,39 +112,52 @@ pub enum CorrelationPattern {
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventMatcher {
     pub event_type: EventTypePattern,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub process_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub path_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub network_pattern: Option<NetworkPattern>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum EventTypePattern {
     FileExecution,
     FileAccess,
     NetworkConnection,
     ProcessSpawn,
     PrivilegeEscalation,
+    Authentication,
+    AuthenticationFailure,
+    Syscall,
+    SyscallFailure,
     Any,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkPattern {
+    #[serde(default)]
     pub port: Option<u16>,
+    #[serde(default)]
     pub ip_pattern: Option<String>,
+    #[serde(default)]
     pub protocol: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KillChainStage {
     pub name: String,
     pub events: Vec<EventMatcher>,
+    #[serde(with = "duration_secs")]
     pub time_limit: Duration,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Severity {
     Low,
     Medium,
@@ -100,7 +165,8 @@ pub enum Severity {
     Critical,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileAccessType {
     Read,
     Write,
@@ -109,9 +175,30 @@ pub enum FileAccessType {
 
 pub struct EventCorrelator {
     rules: Arc<RwLock<Vec<CorrelationRule>>>,
+    // Rule files (*.yaml, *.yml, *.json) loaded on top of the built-in rules
+    rules_dir: Option<PathBuf>,
+    // Rule files must carry a valid .sig when set
+    verifier: Option<Arc<PolicyVerifier>>,
+    // Compiled `script` of each rule that has one, by rule id
+    scripts: Arc<RwLock<HashMap<String, Arc<CompiledScript>>>>,
     event_buffer: Arc<RwLock<EventBuffer>>,
     correlations: Arc<RwLock<HashMap<String, ActiveCorrelation>>>,
     rate_limiter: Arc<RwLock<RateLimiter>>,
+    shadow_log: ShadowLog,
+    rule_stats: RuleStats,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RuleFile {
+    #[serde(default)]
+    rules: Vec<CorrelationRule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleReloadSummary {
+    pub total: usize,
+    pub from_files: usize,
+    pub files: Vec<PathBuf>,
 }
 
 struct EventBuffer {
@@ -127,24 +214,6 @@ struct ActiveCorrelation {
     stage: usize,
 }
 
-pub struct RateLimiter {
-    buckets: HashMap<String, TokenBucket>,
-    config: RateLimiterConfig,
-}
-
-pub struct RateLimiterConfig {
-    pub default_rate: u32,
-    pub default_burst: u32,
-    pub cleanup_interval: Duration,
-}
-
-struct TokenBucket {
-    tokens: f64,
-    last_update: Instant,
-    rate: f64,
-    capacity: f64,
-}
-
 #[derive(Debug, Clone)]
 pub struct CorrelatedEvent {
     pub id: String,
@@ -157,8 +226,23 @@ pub struct CorrelatedEvent {
 
 impl EventCorrelator {
     pub fn new() -> Result<Self> {
-        let mut correlator = Self {
+        Self::build(None, None)
+    }
+    
+    pub fn with_rules_dir(dir: PathBuf) -> Result<Self> {
+        Self::build(Some(dir), None)
+    }
+    
+    pub fn with_signed_rules_dir(dir: PathBuf, verifier: Arc<PolicyVerifier>) -> Result<Self> {
+        Self::build(Some(dir), Some(verifier))
+    }
+    
+    fn build(rules_dir: Option<PathBuf>, verifier: Option<Arc<PolicyVerifier>>) -> Result<Self> {
+        let correlator = Self {
             rules: Arc::new(RwLock::new(Vec::new())),
+            rules_dir,
+            verifier,
+            scripts: Arc::new(RwLock::new(HashMap::new())),
             event_buffer: Arc::new(RwLock::new(EventBuffer {
                 events: VecDeque::new(),
                 max_age: Duration::from_secs(600), // 10 minutes
@@ -170,14 +254,131 @@ impl EventCorrelator {
                 default_burst: 200,
                 cleanup_interval: Duration::from_secs(60),
             }))),
+            shadow_log: ShadowLog::new(),
+            rule_stats: RuleStats::new(),
         };
         
-        correlator.load_default_rules()?;
+        correlator.reload_rules()?;
         Ok(correlator)
     }
     
-    fn load_default_rules(&mut self) -> Result<()> {
-        let rules = vec![
+    pub fn rules(&self) -> Vec<CorrelationRule> {
+        self.rules.read().map(|rules| rules.clone()).unwrap_or_default()
+    }
+    
+    pub fn rules_dir(&self) -> Option<&Path> {
+        self.rules_dir.as_deref()
+    }
+    
+    // Where matches of shadow rules are counted
+    pub fn set_shadow_log(&mut self, log: ShadowLog) {
+        self.shadow_log = log;
+    }
+    
+    // Where matches of every rule are counted; reloads keep it in step
+    // with the rule set
+    pub fn set_rule_stats(&mut self, stats: RuleStats) {
+        self.rule_stats = stats;
+        self.sync_rule_stats();
+    }
+    
+    fn sync_rule_stats(&self) {
+        if let Ok(rules) = self.rules.read() {
+            self.rule_stats.sync(RuleEngine::Correlation, rules.iter().map(|rule| (rule.id.as_str(), rule.name.as_str())));
+        }
+    }
+    
+    // Rebuilds the rule set from the built-in rules and the rules directory.
+    // Everything is validated first; on any error the current rules stay active.
+    pub fn reload_rules(&self) -> Result<RuleReloadSummary> {
+        let mut rules = Self::default_rules();
+        let mut files = Vec::new();
+        let mut from_files = 0;
+        
+        if let Some(dir) = &self.rules_dir {
+            let mut file_rules = Vec::new();
+            files = Self::load_rules_dir(dir, &mut file_rules, self.verifier.as_deref())?;
+            from_files = file_rules.len();
+            // A file rule replaces the built-in rule with the same id
+            let overridden: HashSet<String> = file_rules.iter().map(|rule| rule.id.clone()).collect();
+            rules.retain(|rule| !overridden.contains(&rule.id));
+            rules.extend(file_rules);
+        }
+        
+        let mut scripts = HashMap::new();
+        for rule in &rules {
+            if let Some(script) = &rule.script {
+                let compiled = script.compile().with_context(|| format!("rule '{}'", rule.id))?;
+                scripts.insert(rule.id.clone(), Arc::new(compiled));
+            }
+        }
+        
+        let total = rules.len();
+        let ids: HashSet<String> = rules.iter().map(|rule| rule.id.clone()).collect();
+        {
+            let mut current = self.rules.write()
+                .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+            *current = rules;
+        }
+        if let Ok(mut current) = self.scripts.write() {
+            *current = scripts;
+        }
+        // In-progress matches of removed or changed rules restart from scratch
+        if let Ok(mut correlations) = self.correlations.write() {
+            correlations.retain(|_, active| ids.contains(&active.rule_id));
+        }
+        self.sync_rule_stats();
+        
+        info!("Loaded {} correlation rules ({} from files)", total, from_files);
+        Ok(RuleReloadSummary { total, from_files, files })
+    }
+    
+    // Reads every rule file in the directory, in name order. Ids must be
+    // unique across files.
+    pub fn load_rules_dir(dir: &Path, rules: &mut Vec<CorrelationRule>, verifier: Option<&PolicyVerifier>) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read correlation rules directory {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yaml") | Some("yml") | Some("json")
+            ))
+            .collect();
+        paths.sort();
+        
+        let mut seen = HashSet::new();
+        for path in &paths {
+            for rule in Self::load_rules_file(path, verifier)? {
+                if !seen.insert(rule.id.clone()) {
+                    return Err(anyhow!("Duplicate correlation rule id '{}' in {:?}", rule.id, path));
+                }
+                rules.push(rule);
+            }
+        }
+        Ok(paths)
+    }
+    
+    pub fn load_rules_file(path: &Path, verifier: Option<&PolicyVerifier>) -> Result<Vec<CorrelationRule>> {
+        let content = match verifier {
+            Some(verifier) => verifier.read_verified(path)?,
+            None => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {:?}", path))?,
+        };
+        let file: RuleFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .with_context(|| format!("Invalid correlation rules in {:?}", path))?,
+            _ => serde_yaml::from_str(&content)
+                .with_context(|| format!("Invalid correlation rules in {:?}", path))?,
+        };
+        
+        for rule in &file.rules {
+            rule.validate().with_context(|| format!("Invalid correlation rule in {:?}", path))?;
+        }
+        Ok(file.rules)
+    }
+    
+    fn default_rules() -> Vec<CorrelationRule> {
+        vec![
             // Reconnaissance followed by exploitation
             CorrelationRule {
                 id: "recon_exploit".to_string(),
@@ -224,6 +425,8 @@ impl EventCorrelator {
                 time_window: Duration::from_secs(900),
                 severity: Severity::Critical,
                 enabled: true,
+                shadow: false,
+                script: None,
             },
             
             // Rapid file access (ransomware pattern)
@@ -239,6 +442,8 @@ impl EventCorrelator {
                 time_window: Duration::from_secs(60),
                 severity: Severity::Critical,
                 enabled: true,
+                shadow: false,
+                script: None,
             },
             
             // Port scanning
@@ -253,6 +458,8 @@ impl EventCorrelator {
                 time_window: Duration::from_secs(30),
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
+                script: None,
             },
             
             // Process injection chain
@@ -280,6 +487,8 @@ impl EventCorrelator {
                 time_window: Duration::from_secs(60),
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
+                script: None,
             },
             
             // Brute force detection
@@ -304,18 +513,54 @@ impl EventCorrelator {
                 time_window: Duration::from_secs(60),
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
+                script: None,
             },
-        ];
-        
-        let mut rules_guard = self.rules.write()
-            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
-        
-        for rule in rules {
-            rules_guard.push(rule);
-        }
-        
-        info!("Loaded {} correlation rules", rules_guard.len());
-        Ok(())
+            
+            // Failed logins reported by the audit subsystem (PAM, sudo, su)
+            CorrelationRule {
+                id: "auth_failures".to_string(),
+                name: "Repeated Authentication Failures".to_string(),
+                description: "Detects repeated failed authentication attempts from audit records".to_string(),
+                pattern: CorrelationPattern::EventCluster {
+                    event_type: EventMatcher {
+                        event_type: EventTypePattern::AuthenticationFailure,
+                        process_name: None,
+                        path_pattern: None,
+                        network_pattern: None,
+                    },
+                    min_count: 5,
+                    unique_sources: false,
+                },
+                time_window: Duration::from_secs(60),
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                script: None,
+            },
+            
+            // Bursts of denied syscalls (EPERM/EACCES probing)
+            CorrelationRule {
+                id: "syscall_denials".to_string(),
+                name: "Denied Syscall Burst".to_string(),
+                description: "Detects processes repeatedly hitting denied syscalls".to_string(),
+                pattern: CorrelationPattern::EventCluster {
+                    event_type: EventMatcher {
+                        event_type: EventTypePattern::SyscallFailure,
+                        process_name: None,
+                        path_pattern: None,
+                        network_pattern: None,
+                    },
+                    min_count: 20,
+                    unique_sources: false,
+                },
+                time_window: Duration::from_secs(60),
+                severity: Severity::Medium,
+                enabled: true,
+                shadow: false,
+                script: None,
+            },
+        ]
     }
     
     pub fn process_event(&self, event: SecurityEvent) -> Option<CorrelatedEvent> {
@@ -345,13 +590,46 @@ impl EventCorrelator {
             }
             
             if let Some(correlated) = self.check_correlation(rule, &event, now) {
-                return Some(correlated);
+                if let Some(correlated) = self.apply_script(rule, &event, correlated) {
+                    self.rule_stats.record(RuleEngine::Correlation, &rule.id, &rule.name);
+                    if rule.shadow {
+                        self.shadow_log.record(RuleEngine::Correlation, &rule.id, &rule.name, "alert", correlated.description);
+                        continue;
+                    }
+                    return Some(correlated);
+                }
             }
         }
         
         None
     }
     
+    // A failing script keeps the match, so a broken script cannot hide attacks
+    fn apply_script(&self, rule: &CorrelationRule, event: &SecurityEvent, mut correlated: CorrelatedEvent) -> Option<CorrelatedEvent> {
+        let script = self.scripts.read().ok()?.get(&rule.id).cloned();
+        let Some(script) = script else {
+            return Some(correlated);
+        };
+        let (Ok(trigger), Ok(events)) = (serde_json::to_value(event), serde_json::to_value(&correlated.events)) else {
+            return Some(correlated);
+        };
+        match script.run(&[("event", &trigger), ("events", &events)]) {
+            Ok(result) if result.is_bool() => result.as_bool().unwrap_or(true).then_some(correlated),
+            Ok(result) if result.is_string() => {
+                correlated.description = result.to_string();
+                Some(correlated)
+            }
+            Ok(result) => {
+                warn!("Script of correlation rule '{}' returned {}, expected a boolean or string", rule.id, result.type_name());
+                Some(correlated)
+            }
+            Err(e) => {
+                warn!("Script of correlation rule '{}': {:#}", rule.id, e);
+                Some(correlated)
+            }
+        }
+    }
+    
     fn check_correlation(&self, rule: &CorrelationRule, event: &SecurityEvent, now: Instant) -> Option<CorrelatedEvent> {
         match &rule.pattern {
             CorrelationPattern::ProcessSequence { events, max_time_between } => {
@@ -625,6 +903,13 @@ impl EventCorrelator {
             (EventTypePattern::FileExecution, SecurityEventType::FileExecution { .. }) => true,
             (EventTypePattern::FileAccess, SecurityEventType::FileAccess { .. }) => true,
             (EventTypePattern::NetworkConnection, SecurityEventType::NetworkConnection { .. }) => true,
+            (EventTypePattern::Authentication, SecurityEventType::Authentication { .. }) => true,
+            (EventTypePattern::AuthenticationFailure, SecurityEventType::Authentication { success, .. }) => !success,
+            (EventTypePattern::PrivilegeEscalation, SecurityEventType::Authentication { service, success, .. }) => {
+                *success && matches!(service.as_str(), "sudo" | "su" | "pkexec" | "doas")
+            }
+            (EventTypePattern::Syscall, SecurityEventType::Syscall { .. }) => true,
+            (EventTypePattern::SyscallFailure, SecurityEventType::Syscall { success, .. }) => !success,
             (EventTypePattern::Any, _) => true,
             _ => false,
         };
@@ -671,6 +956,61 @@ impl EventCorrelator {
     }
 }
 
+impl CorrelationRule {
+    pub fn validate(&self) -> Result<()> {
+        if self.id.trim().is_empty() {
+            return Err(anyhow!("rule id must not be empty"));
+        }
+        let invalid = |reason: &str| Err(anyhow!("rule '{}': {}", self.id, reason));
+        if self.time_window.is_zero() {
+            return invalid("time_window must be at least one second");
+        }
+        
+        if let Some(script) = &self.script {
+            script.compile().with_context(|| format!("rule '{}'", self.id))?;
+        }
+        
+        match &self.pattern {
+            CorrelationPattern::ProcessSequence { events, .. } if events.is_empty() => {
+                invalid("process_sequence needs at least one event")
+            }
+            CorrelationPattern::EventCluster { min_count: 0, .. } => {
+                invalid("event_cluster min_count must be at least 1")
+            }
+            CorrelationPattern::ProcessTreePattern { child_events, .. } if child_events.is_empty() => {
+                invalid("process_tree_pattern needs at least one child event")
+            }
+            CorrelationPattern::NetworkSweep { min_targets: 0, .. } => {
+                invalid("network_sweep min_targets must be at least 1")
+            }
+            CorrelationPattern::NetworkSweep { port_range: Some((low, high)), .. } if low > high => {
+                invalid("network_sweep port_range is reversed")
+            }
+            CorrelationPattern::MassFileAccess { path_pattern, min_files, access_types } => {
+                if path_pattern.is_empty() {
+                    invalid("mass_file_access needs a path_pattern")
+                } else if *min_files == 0 {
+                    invalid("mass_file_access min_files must be at least 1")
+                } else if access_types.is_empty() {
+                    invalid("mass_file_access needs at least one access type")
+                } else {
+                    Ok(())
+                }
+            }
+            CorrelationPattern::KillChain { stages } => {
+                if stages.is_empty() {
+                    invalid("kill_chain needs at least one stage")
+                } else if stages.iter().any(|stage| stage.events.is_empty()) {
+                    invalid("every kill_chain stage needs at least one event")
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl EventBuffer {
     fn add_event(&mut self, timestamp: Instant, event: SecurityEvent) {
         // Remove old events
@@ -693,56 +1033,14 @@ impl EventBuffer {
     }
 }
 
-impl RateLimiter {
-    fn new(config: RateLimiterConfig) -> Self {
-        Self {
-            buckets: HashMap::new(),
-            config,
-        }
-    }
-    
-    fn check_and_update(&mut self, key: &str) -> bool {
-        let now = Instant::now();
-        
-        let bucket = self.buckets.entry(key.to_string()).or_insert_with(|| {
-            TokenBucket {
-                tokens: self.config.default_burst as f64,
-                last_update: now,
-                rate: self.config.default_rate as f64,
-                capacity: self.config.default_burst as f64,
-            }
-        });
-        
-        // Update tokens
-        let elapsed = now.duration_since(bucket.last_update).as_secs_f64();
-        bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.capacity);
-        bucket.last_update = now;
-        
-        // Check if we can consume a token
-        if bucket.tokens >= 1.0 {
-            bucket.tokens -= 1.0;
-            true
-        } else {
-            false
-        }
-    }
-    
-    fn cleanup_old_buckets(&mut self) {
-        let now = Instant::now();
-        let cutoff = self.config.cleanup_interval;
-        
-        self.buckets.retain(|_, bucket| {
-            now.duration_since(bucket.last_update) < cutoff
-        });
-    }
-}
-
 impl SecurityEventType {
     fn type_name(&self) -> &'static str {
         match self {
             SecurityEventType::FileExecution { .. } => "file_execution",
             SecurityEventType::FileAccess { .. } => "file_access",
             SecurityEventType::NetworkConnection { .. } => "network_connection",
+            SecurityEventType::Authentication { .. } => "authentication",
+            SecurityEventType::Syscall { .. } => "syscall",
         }
     }
 }
@@ -767,4 +1065,120 @@ mod tests {
         // Should be rate limited
         assert!(!limiter.check_and_update("test_key"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_rules_loaded_from_directory() {
+        let dir = std::env::temp_dir().join(format!("flux-correlation-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("10-ssh.yaml"), r#"
+rules:
+  - id: port_scan
+    name: Wide port scan
+    severity: critical
+    time_window: 10
+    pattern:
+      type: network_sweep
+      min_targets: 50
+      port_range: [1, 1024]
+  - id: ssh_brute_force
+    name: SSH brute force
+    severity: high
+    time_window: 60
+    pattern:
+      type: event_cluster
+      event_type: { event_type: authentication_failure, process_name: sshd }
+      min_count: 5
+      unique_sources: false
+"#).unwrap();
+        
+        let correlator = EventCorrelator::with_rules_dir(dir.clone()).unwrap();
+        let rules = correlator.rules();
+        let builtin = EventCorrelator::default_rules().len();
+        assert_eq!(rules.len(), builtin + 1);
+        let port_scan = rules.iter().find(|rule| rule.id == "port_scan").unwrap();
+        assert_eq!(port_scan.severity, Severity::Critical);
+        assert_eq!(port_scan.time_window, Duration::from_secs(10));
+        
+        // A broken file is rejected and the loaded rules stay in place
+        fs::write(dir.join("20-bad.json"), r#"{"rules": [{"id": "bad", "name": "Bad", "severity": "low",
+            "time_window": 0, "pattern": {"type": "kill_chain", "stages": []}}]}"#).unwrap();
+        assert!(correlator.reload_rules().is_err());
+        assert_eq!(correlator.rules().len(), builtin + 1);
+        
+        fs::remove_file(dir.join("20-bad.json")).unwrap();
+        let summary = correlator.reload_rules().unwrap();
+        assert_eq!(summary.from_files, 2);
+        
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn test_rule_scripts() {
+        let dir = std::env::temp_dir().join(format!("flux-correlation-scripts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ssh.yaml"), r#"
+rules:
+  - id: ssh_failures_trial
+    name: SSH failures (trial)
+    severity: high
+    time_window: 60
+    shadow: true
+    pattern:
+      type: event_cluster
+      event_type: { event_type: authentication_failure, process_name: sshd }
+      min_count: 2
+      unique_sources: false
+  - id: ssh_failures
+    name: SSH failures
+    severity: medium
+    time_window: 60
+    pattern:
+      type: event_cluster
+      event_type: { event_type: authentication_failure, process_name: sshd }
+      min_count: 2
+      unique_sources: false
+    script:
+      source: |
+        let host = event.event_type.Authentication.remote_host;
+        if host in params.trusted { false } else { `${events.len()} failures from ${host}` }
+      params: { trusted: ["10.0.0.5"] }
+"#).unwrap();
+        let mut correlator = EventCorrelator::with_rules_dir(dir.clone()).unwrap();
+        let shadow = ShadowLog::new();
+        correlator.set_shadow_log(shadow.clone());
+        fs::remove_dir_all(&dir).unwrap();
+        
+        let failure = |pid: u32, host: &str| SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: SecurityEventType::Authentication {
+                user: "root".to_string(),
+                service: "sshd".to_string(),
+                success: false,
+                remote_host: Some(host.to_string()),
+            },
+            process_info: ProcessInfo {
+                pid,
+                path: PathBuf::from("/usr/sbin/sshd"),
+                parent_pid: Some(1),
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        };
+        assert!(correlator.process_event(failure(10, "10.0.0.5")).is_none());
+        assert!(correlator.process_event(failure(11, "10.0.0.5")).is_none());
+        let correlated = correlator.process_event(failure(12, "203.0.113.9")).unwrap();
+        assert_eq!(correlated.description, "3 failures from 203.0.113.9");
+        // The shadow rule matched as well, but only the log knows
+        assert_eq!(shadow.stats()[0].rule_id, "ssh_failures_trial");
+        assert_eq!(shadow.stats()[0].hits, 2);
+        
+        // Scripts that do not parse are rejected like any other invalid rule
+        let mut rule = correlated.rule.clone();
+        rule.script.as_mut().unwrap().source = "if {".to_string();
+        assert!(rule.validate().is_err());
+    }
+}
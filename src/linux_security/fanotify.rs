@@ -1,16 +1,20 @@
 use std::fs::File;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
 use libc::{self, c_int};
 use std::mem;
 
+use crate::file_watch::{self, FileWatchConfig, WatchEvents};
+use super::permission_watchdog::{self, PermissionWatchdog};
+
 // Fanotify constants
 const FAN_CLOEXEC: c_int = 0x00000001;
+const FAN_NONBLOCK: c_int = 0x00000002;
 const FAN_CLASS_CONTENT: c_int = 0x00000004;
 const FAN_CLASS_PRE_CONTENT: c_int = 0x00000008;
 const FAN_UNLIMITED_QUEUE: c_int = 0x00000010;
@@ -50,12 +54,6 @@ struct FanotifyEventMetadata {
     pid: i32,
 }
 
-#[repr(C)]
-struct FanotifyResponse {
-    fd: i32,
-    response: u32,
-}
-
 #[derive(Debug, Clone)]
 struct FileMetadata {
     path: PathBuf,
@@ -70,24 +68,30 @@ pub struct FanotifyMonitor {
     running: bool,
     file_cache: HashMap<PathBuf, FileMetadata>,
     cache_ttl: Duration,
+    open_permission_checks: bool,
+    write_watch_paths: Vec<PathBuf>,
+    watch: FileWatchConfig,
+    // Mount namespaces whose mounts carry our marks
+    marked_namespaces: HashSet<u64>,
+    watchdog: Arc<PermissionWatchdog>,
 }
 
 impl FanotifyMonitor {
     pub fn new() -> Result<Self> {
         info!("Initializing fanotify monitor");
         
-        // Check if we have CAP_SYS_ADMIN capability
-        let uid = unsafe { libc::geteuid() };
-        if uid != 0 {
+        // Root alone is not enough, e.g. in an unprivileged container
+        if !crate::privileges::has_capability("CAP_SYS_ADMIN") {
             warn!("Fanotify requires root privileges or CAP_SYS_ADMIN capability");
             return Err(anyhow!("Insufficient privileges for fanotify"));
         }
         
-        // Initialize fanotify
+        // Initialize fanotify; non-blocking so reads are driven by readiness
+        // and never hold the monitor lock while waiting
         let fd = unsafe {
             libc::syscall(
                 libc::SYS_fanotify_init,
-                FAN_CLOEXEC | FAN_CLASS_PRE_CONTENT | FAN_UNLIMITED_QUEUE | FAN_UNLIMITED_MARKS,
+                FAN_CLOEXEC | FAN_NONBLOCK | FAN_CLASS_PRE_CONTENT | FAN_UNLIMITED_QUEUE | FAN_UNLIMITED_MARKS,
                 libc::O_RDONLY | libc::O_LARGEFILE
             )
         };
@@ -105,6 +109,11 @@ impl FanotifyMonitor {
             running: false,
             file_cache: HashMap::new(),
             cache_ttl: Duration::from_secs(300), // 5 minute cache
+            open_permission_checks: false,
+            write_watch_paths: Vec::new(),
+            watch: FileWatchConfig::default(),
+            marked_namespaces: HashSet::new(),
+            watchdog: Arc::new(PermissionWatchdog::new(fd, Default::default())),
         })
     }
     
@@ -127,6 +136,7 @@ impl FanotifyMonitor {
             return Err(anyhow!("Failed to add fanotify mark on {}: {}", mount_path, err));
         }
         
+        self.watchdog.add_mark(mount_path, mask);
         info!("Added fanotify mark on mount: {} with mask: 0x{:x}", mount_path, mask);
         Ok(())
     }
@@ -222,29 +232,87 @@ impl FanotifyMonitor {
             return Ok(());
         }
         
-        // Add marks for monitoring
-        // Monitor file execution with permission checks
-        let exec_mask = FAN_OPEN_EXEC_PERM | FAN_OPEN_EXEC;
-        
-        // Monitor file access and modifications
-        let access_mask = FAN_OPEN | FAN_ACCESS | FAN_MODIFY | FAN_CLOSE_WRITE;
+        self.mark_watch_paths("");
         
-        // Try to monitor the root filesystem
-        if let Err(e) = self.add_mount_mark("/", exec_mask | access_mask) {
-            warn!("Failed to monitor root filesystem: {}", e);
-            // Try specific directories instead
-            for path in &["/usr", "/bin", "/sbin", "/opt", "/home"] {
-                if let Err(e) = self.add_mount_mark(path, exec_mask | access_mask) {
-                    warn!("Failed to monitor {}: {}", path, e);
-                }
+        // Directories like /tmp and /dev/shm are often separate mounts the
+        // root mark does not cover; completed writes there are reported too
+        for path in &self.write_watch_paths {
+            if !path.is_dir() {
+                continue;
+            }
+            if let Err(e) = self.add_mount_mark(&path.to_string_lossy(), FAN_CLOSE_WRITE) {
+                warn!("Failed to watch writes in {}: {}", path.display(), e);
             }
         }
         
         self.running = true;
+        self.watchdog.start();
+        let namespaces = self.mark_mount_namespaces();
+        if namespaces > 0 {
+            info!("Watching {} other mount namespaces", namespaces);
+        }
         info!("Fanotify monitoring started");
         Ok(())
     }
     
+    fn mask_for(&self, events: WatchEvents) -> u64 {
+        // Executions are gated
+        let exec_mask = FAN_OPEN_EXEC_PERM | FAN_OPEN_EXEC;
+        let mut access_mask = FAN_OPEN | FAN_ACCESS | FAN_MODIFY | FAN_CLOSE_WRITE;
+        // On-access scanning gates every open, not just executions
+        if self.open_permission_checks {
+            access_mask |= FAN_OPEN_PERM;
+        }
+        match events {
+            WatchEvents::Exec => exec_mask,
+            WatchEvents::ReadWrite => access_mask,
+            WatchEvents::All => exec_mask | access_mask,
+        }
+    }
+    
+    // Marks the mount holding each included path; `root` is prepended to
+    // reach another mount namespace
+    fn mark_watch_paths(&self, root: &str) {
+        for include in &self.watch.include {
+            let mask = self.mask_for(include.events);
+            let path = format!("{}{}", root, include.path.display());
+            let Err(e) = self.add_mount_mark(&path, mask) else { continue };
+            warn!("Failed to monitor {}: {}", path, e);
+            // Without the root mount, try the usual binary and home mounts
+            if root.is_empty() && include.path == Path::new("/") {
+                for path in &["/usr", "/bin", "/sbin", "/opt", "/home"] {
+                    if let Err(e) = self.add_mount_mark(path, mask) {
+                        warn!("Failed to monitor {}: {}", path, e);
+                    }
+                }
+            }
+        }
+    }
+    
+    // Marks the included paths in mount namespaces that appeared since the
+    // last call, e.g. new containers; returns how many were marked
+    pub fn mark_mount_namespaces(&mut self) -> usize {
+        if !self.running || !self.watch.mount_namespaces {
+            return 0;
+        }
+        let namespaces = file_watch::other_mount_namespaces();
+        // Forget namespaces that are gone, their marks went with them
+        self.marked_namespaces.retain(|marked| namespaces.iter().any(|(namespace, _)| namespace == marked));
+        let mut marked = 0;
+        for (namespace, pid) in namespaces {
+            if self.marked_namespaces.insert(namespace) {
+                debug!("Marking mount namespace {} through pid {}", namespace, pid);
+                self.mark_watch_paths(&format!("/proc/{}/root", pid));
+                marked += 1;
+            }
+        }
+        marked
+    }
+    
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    
     pub fn read_events<F>(&self, decision_callback: F) -> Result<Vec<FanotifyEvent>> 
     where
         F: Fn(&FanotifyEvent) -> bool
@@ -286,13 +354,38 @@ impl FanotifyMonitor {
                 path: self.get_path_from_fd(metadata.fd),
             };
             
-            // For permission events, get decision from callback
+            // Marks cover whole mounts; what the watch config leaves out is
+            // allowed without a decision and not reported
+            if !self.is_watched(&event) {
+                if event.is_permission_event() {
+                    self.respond_to_event(metadata.fd, FAN_ALLOW)?;
+                }
+                if metadata.fd >= 0 {
+                    unsafe { libc::close(metadata.fd) };
+                }
+                offset += metadata.event_len as usize;
+                continue;
+            }
+            
+            // For permission events, get decision from callback. Once the
+            // watchdog has given up on permission events, the ones still
+            // queued are allowed as they come
             if event.is_permission_event() {
-                let allow = decision_callback(&event);
-                let response = if allow { FAN_ALLOW } else { FAN_DENY };
-                
-                debug!("Permission event for {:?}: {}", event.path, if allow { "ALLOW" } else { "DENY" });
-                self.respond_to_event(metadata.fd, response)?;
+                let decision = if self.watchdog.is_notification_only() {
+                    Some(true)
+                } else {
+                    self.watchdog.begin(metadata.fd);
+                    let allow = decision_callback(&event);
+                    self.watchdog.finish(metadata.fd).then_some(allow)
+                };
+                match decision {
+                    Some(allow) => {
+                        let response = if allow { FAN_ALLOW } else { FAN_DENY };
+                        debug!("Permission event for {:?}: {}", event.path, if allow { "ALLOW" } else { "DENY" });
+                        self.respond_to_event(metadata.fd, response)?;
+                    }
+                    None => warn!("Decision for {:?} came after the watchdog allowed it", event.path),
+                }
             }
             
             events.push(event);
@@ -308,6 +401,14 @@ impl FanotifyMonitor {
         Ok(events)
     }
     
+    fn is_watched(&self, event: &FanotifyEvent) -> bool {
+        let Some(ref path) = event.path else { return true };
+        if event.is_close_write() && self.write_watch_paths.iter().any(|dir| path.starts_with(dir)) {
+            return true;
+        }
+        self.watch.wants(path, event.is_exec())
+    }
+    
     fn get_path_from_fd(&self, fd: RawFd) -> Option<PathBuf> {
         if fd < 0 {
             return None;
@@ -318,22 +419,8 @@ impl FanotifyMonitor {
     }
     
     fn respond_to_event(&self, fd: RawFd, response: u32) -> Result<()> {
-        let resp = FanotifyResponse { fd, response };
-        
-        let ret = unsafe {
-            libc::write(
-                self.fd,
-                &resp as *const _ as *const libc::c_void,
-                mem::size_of::<FanotifyResponse>()
-            )
-        };
-        
-        if ret < 0 {
-            let err = std::io::Error::last_os_error();
-            return Err(anyhow!("Failed to respond to fanotify event: {}", err));
-        }
-        
-        Ok(())
+        permission_watchdog::respond(self.fd, fd, response)
+            .map_err(|e| anyhow!("Failed to respond to fanotify event: {}", e))
     }
     
     pub fn stop(&mut self) -> Result<()> {
@@ -341,6 +428,7 @@ impl FanotifyMonitor {
             info!("Stopping fanotify monitoring");
             self.running = false;
         }
+        self.watchdog.stop();
         Ok(())
     }
     
@@ -376,10 +464,41 @@ impl FanotifyMonitor {
     pub fn set_cache_ttl(&mut self, ttl: Duration) {
         self.cache_ttl = ttl;
     }
+    
+    // Takes effect on the next start_monitoring
+    pub fn set_write_watch_paths(&mut self, paths: Vec<PathBuf>) {
+        self.write_watch_paths = paths;
+    }
+    
+    // Exclusions and per-path events apply to the next event read; marks
+    // for newly included paths are added on the next start_monitoring
+    pub fn set_watch_config(&mut self, config: FileWatchConfig) {
+        self.watchdog.configure(config.permission_timeout.clone());
+        self.watch = config;
+    }
+    
+    // Shared so decision latency can be read without the monitor lock,
+    // which a stalled decision holds
+    pub fn watchdog(&self) -> Arc<PermissionWatchdog> {
+        Arc::clone(&self.watchdog)
+    }
+    
+    // Takes effect on the next start_monitoring
+    pub fn set_open_permission_checks(&mut self, enabled: bool) {
+        self.open_permission_checks = enabled;
+    }
+}
+
+impl AsRawFd for FanotifyMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
 }
 
 impl Drop for FanotifyMonitor {
     fn drop(&mut self) {
+        // Its thread writes to the descriptor
+        self.watchdog.stop();
         if self.fd >= 0 {
             unsafe { libc::close(self.fd) };
         }
@@ -411,6 +530,10 @@ impl FanotifyEvent {
         self.mask & FAN_MODIFY != 0
     }
     
+    pub fn is_close_write(&self) -> bool {
+        self.mask & FAN_CLOSE_WRITE != 0
+    }
+    
     pub fn is_permission_event(&self) -> bool {
         self.mask & (FAN_OPEN_PERM | FAN_ACCESS_PERM | FAN_OPEN_EXEC_PERM) != 0
     }
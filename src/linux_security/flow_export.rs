@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, debug};
+
+// Keeps each export datagram under a typical path MTU
+const MAX_PACKET_SIZE: usize = 1400;
+
+const V4_TEMPLATE_ID: u16 = 256;
+const V6_TEMPLATE_ID: u16 = 257;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowFormat {
+    NetflowV9,
+    Ipfix,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowExportConfig {
+    // Collector address, e.g. "10.0.0.20:2055"
+    pub collector: String,
+    #[serde(default = "default_format")]
+    pub format: FlowFormat,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub observation_domain_id: u32,
+}
+
+fn default_format() -> FlowFormat {
+    FlowFormat::Ipfix
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+// Cumulative counters for one tracked connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowRecord {
+    pub protocol: u8,
+    pub src_addr: IpAddr,
+    pub src_port: u16,
+    pub dst_addr: IpAddr,
+    pub dst_port: u16,
+    pub packets: u64,
+    pub bytes: u64,
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+type FlowKey = (u8, IpAddr, u16, IpAddr, u16);
+
+impl FlowRecord {
+    fn key(&self) -> FlowKey {
+        (self.protocol, self.src_addr, self.src_port, self.dst_addr, self.dst_port)
+    }
+}
+
+// (element id, length) pairs; the same ids are used by NetFlow v9 and IPFIX
+fn template_fields(ipv6: bool, format: FlowFormat) -> Vec<(u16, u16)> {
+    let (src, dst, addr_len) = if ipv6 { (27, 28, 16) } else { (8, 12, 4) };
+    let mut fields = vec![(src, addr_len), (dst, addr_len), (7, 2), (11, 2), (4, 1), (2, 8), (1, 8)];
+    match format {
+        // FIRST_SWITCHED / LAST_SWITCHED, milliseconds of exporter uptime
+        FlowFormat::NetflowV9 => fields.extend([(22, 4), (21, 4)]),
+        // flowStartMilliseconds / flowEndMilliseconds
+        FlowFormat::Ipfix => fields.extend([(152, 8), (153, 8)]),
+    }
+    fields
+}
+
+fn millis_since(time: SystemTime, since: SystemTime) -> u64 {
+    time.duration_since(since).unwrap_or_default().as_millis() as u64
+}
+
+// Encodes flow records into NetFlow v9 or IPFIX messages. Templates are
+// included in every message so collectors can decode from any point.
+pub struct FlowEncoder {
+    format: FlowFormat,
+    domain_id: u32,
+    sequence: u32,
+    boot: SystemTime,
+}
+
+impl FlowEncoder {
+    pub fn new(format: FlowFormat, domain_id: u32) -> Self {
+        Self { format, domain_id, sequence: 0, boot: SystemTime::now() }
+    }
+
+    fn template_set(&self) -> Vec<u8> {
+        let mut set = Vec::new();
+        let set_id: u16 = match self.format {
+            FlowFormat::NetflowV9 => 0,
+            FlowFormat::Ipfix => 2,
+        };
+        set.extend_from_slice(&set_id.to_be_bytes());
+        set.extend_from_slice(&0u16.to_be_bytes()); // length, patched below
+        for (template_id, ipv6) in [(V4_TEMPLATE_ID, false), (V6_TEMPLATE_ID, true)] {
+            let fields = template_fields(ipv6, self.format);
+            set.extend_from_slice(&template_id.to_be_bytes());
+            set.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+            for (id, len) in fields {
+                set.extend_from_slice(&id.to_be_bytes());
+                set.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+        let len = set.len() as u16;
+        set[2..4].copy_from_slice(&len.to_be_bytes());
+        set
+    }
+
+    fn encode_record(&self, record: &FlowRecord, out: &mut Vec<u8>) {
+        match (record.src_addr, record.dst_addr) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                out.extend_from_slice(&src.octets());
+                out.extend_from_slice(&dst.octets());
+            }
+            (src, dst) => {
+                out.extend_from_slice(&to_v6(src).octets());
+                out.extend_from_slice(&to_v6(dst).octets());
+            }
+        }
+        out.extend_from_slice(&record.src_port.to_be_bytes());
+        out.extend_from_slice(&record.dst_port.to_be_bytes());
+        out.push(record.protocol);
+        out.extend_from_slice(&record.packets.to_be_bytes());
+        out.extend_from_slice(&record.bytes.to_be_bytes());
+        match self.format {
+            FlowFormat::NetflowV9 => {
+                out.extend_from_slice(&(millis_since(record.start, self.boot) as u32).to_be_bytes());
+                out.extend_from_slice(&(millis_since(record.end, self.boot) as u32).to_be_bytes());
+            }
+            FlowFormat::Ipfix => {
+                out.extend_from_slice(&millis_since(record.start, UNIX_EPOCH).to_be_bytes());
+                out.extend_from_slice(&millis_since(record.end, UNIX_EPOCH).to_be_bytes());
+            }
+        }
+    }
+
+    // Returns one or more export messages covering all records
+    pub fn encode(&mut self, records: &[FlowRecord], now: SystemTime) -> Vec<Vec<u8>> {
+        let header_len = match self.format {
+            FlowFormat::NetflowV9 => 20,
+            FlowFormat::Ipfix => 16,
+        };
+        let template = self.template_set();
+
+        let mut messages = Vec::new();
+        let mut remaining: Vec<&FlowRecord> = records.iter().collect();
+        while !remaining.is_empty() {
+            let mut body = template.clone();
+            let mut count = 2u16; // template records
+            let mut data_records = 0u32;
+
+            for ipv6 in [false, true] {
+                let template_id = if ipv6 { V6_TEMPLATE_ID } else { V4_TEMPLATE_ID };
+                let set_start = body.len();
+                body.extend_from_slice(&template_id.to_be_bytes());
+                body.extend_from_slice(&0u16.to_be_bytes());
+
+                let mut kept = Vec::new();
+                for record in remaining.drain(..) {
+                    let is_v6 = !(record.src_addr.is_ipv4() && record.dst_addr.is_ipv4());
+                    let record_len = template_fields(ipv6, self.format).iter().map(|(_, len)| *len as usize).sum::<usize>();
+                    if is_v6 != ipv6 || header_len + body.len() + record_len + 3 > MAX_PACKET_SIZE {
+                        kept.push(record);
+                        continue;
+                    }
+                    self.encode_record(record, &mut body);
+                    count += 1;
+                    data_records += 1;
+                }
+                remaining = kept;
+
+                if body.len() == set_start + 4 {
+                    body.truncate(set_start);
+                    continue;
+                }
+                // Sets are padded to a 4-byte boundary
+                while !(body.len() - set_start).is_multiple_of(4) {
+                    body.push(0);
+                }
+                let set_len = (body.len() - set_start) as u16;
+                body[set_start + 2..set_start + 4].copy_from_slice(&set_len.to_be_bytes());
+            }
+
+            let export_secs = millis_since(now, UNIX_EPOCH) / 1000;
+            let mut message = Vec::with_capacity(header_len + body.len());
+            match self.format {
+                FlowFormat::NetflowV9 => {
+                    message.extend_from_slice(&9u16.to_be_bytes());
+                    message.extend_from_slice(&count.to_be_bytes());
+                    message.extend_from_slice(&(millis_since(now, self.boot) as u32).to_be_bytes());
+                    message.extend_from_slice(&(export_secs as u32).to_be_bytes());
+                    message.extend_from_slice(&self.sequence.to_be_bytes());
+                    self.sequence = self.sequence.wrapping_add(1);
+                }
+                FlowFormat::Ipfix => {
+                    message.extend_from_slice(&10u16.to_be_bytes());
+                    message.extend_from_slice(&((header_len + body.len()) as u16).to_be_bytes());
+                    message.extend_from_slice(&(export_secs as u32).to_be_bytes());
+                    // IPFIX counts data records sent before this message
+                    message.extend_from_slice(&self.sequence.to_be_bytes());
+                    self.sequence = self.sequence.wrapping_add(data_records);
+                }
+            }
+            message.extend_from_slice(&self.domain_id.to_be_bytes());
+            message.extend_from_slice(&body);
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+fn to_v6(addr: IpAddr) -> std::net::Ipv6Addr {
+    match addr {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+// Sends per-interval deltas of tracked connections to a flow collector
+pub struct FlowExporter {
+    socket: UdpSocket,
+    collector: SocketAddr,
+    encoder: FlowEncoder,
+    // Counters already reported, per flow
+    exported: HashMap<FlowKey, (u64, u64)>,
+    interval: Duration,
+}
+
+impl FlowExporter {
+    pub fn new(config: &FlowExportConfig) -> Result<Self> {
+        let collector = config.collector.to_socket_addrs()
+            .with_context(|| format!("Invalid flow collector {}", config.collector))?
+            .next()
+            .ok_or_else(|| anyhow!("Flow collector {} did not resolve", config.collector))?;
+        let bind = if collector.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+
+        info!("Exporting {:?} flows to {} every {}s", config.format, collector, config.interval_secs);
+        Ok(Self {
+            socket: UdpSocket::bind(bind)?,
+            collector,
+            encoder: FlowEncoder::new(config.format, config.observation_domain_id),
+            exported: HashMap::new(),
+            interval: Duration::from_secs(config.interval_secs.max(1)),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Takes cumulative counters for every tracked connection and exports the
+    // traffic seen since the previous call. Returns the number of flows sent.
+    pub fn export(&mut self, flows: Vec<FlowRecord>) -> Result<usize> {
+        let mut exported = HashMap::with_capacity(flows.len());
+        let mut deltas = Vec::new();
+
+        for mut flow in flows {
+            let key = flow.key();
+            let (sent_packets, sent_bytes) = self.exported.get(&key).copied().unwrap_or((0, 0));
+            exported.insert(key, (flow.packets, flow.bytes));
+
+            if flow.packets <= sent_packets {
+                continue;
+            }
+            flow.packets -= sent_packets;
+            flow.bytes = flow.bytes.saturating_sub(sent_bytes);
+            deltas.push(flow);
+        }
+        // Connections no longer tracked are forgotten
+        self.exported = exported;
+
+        if deltas.is_empty() {
+            return Ok(0);
+        }
+
+        for message in self.encoder.encode(&deltas, SystemTime::now()) {
+            self.socket.send_to(&message, self.collector)?;
+        }
+        debug!("Exported {} flows to {}", deltas.len(), self.collector);
+        Ok(deltas.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(src: &str, dst: &str) -> FlowRecord {
+        let start = UNIX_EPOCH + Duration::from_millis(1_709_632_800_000);
+        FlowRecord {
+            protocol: 6,
+            src_addr: src.parse().unwrap(),
+            src_port: 51000,
+            dst_addr: dst.parse().unwrap(),
+            dst_port: 443,
+            packets: 10,
+            bytes: 4200,
+            start,
+            end: start + Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_encodes_ipfix_and_netflow_v9() {
+        let records = vec![record("10.0.0.5", "203.0.113.7"), record("2001:db8::1", "2001:db8::2")];
+        let now = SystemTime::now();
+
+        let mut ipfix = FlowEncoder::new(FlowFormat::Ipfix, 7);
+        let messages = ipfix.encode(&records, now);
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!(u16::from_be_bytes([message[0], message[1]]), 10);
+        assert_eq!(u16::from_be_bytes([message[2], message[3]]) as usize, message.len());
+        assert_eq!(u32::from_be_bytes(message[12..16].try_into().unwrap()), 7);
+        // Template set follows the header
+        assert_eq!(u16::from_be_bytes([message[16], message[17]]), 2);
+        // Next message's sequence counts the two data records
+        assert_eq!(u32::from_be_bytes(ipfix.encode(&records, now)[0][8..12].try_into().unwrap()), 2);
+
+        let mut v9 = FlowEncoder::new(FlowFormat::NetflowV9, 0);
+        let message = &v9.encode(&records[..1], now)[0];
+        assert_eq!(u16::from_be_bytes([message[0], message[1]]), 9);
+        // Two templates plus one data record
+        assert_eq!(u16::from_be_bytes([message[2], message[3]]), 3);
+
+        // Large exports are split across messages
+        let many: Vec<FlowRecord> = (0..200).map(|_| record("10.0.0.5", "203.0.113.7")).collect();
+        let messages = FlowEncoder::new(FlowFormat::Ipfix, 0).encode(&many, now);
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|m| m.len() <= MAX_PACKET_SIZE));
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn, error};
+
+use crate::incidents::{CorrelationSignal, IncidentArtifact, IncidentManager};
+use crate::monitor::SecurityEvent;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForensicConfig {
+    pub dir: PathBuf,
+    // Larger executables are hashed but not copied
+    pub max_executable_bytes: u64,
+    // Newest events kept in a bundle
+    pub max_events: usize,
+    // Processes snapshotted per detection
+    pub max_processes: usize,
+}
+
+impl Default for ForensicConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/var/lib/fluxdefense/forensics"),
+            max_executable_bytes: 64 * 1024 * 1024,
+            max_events: 500,
+            max_processes: 4,
+        }
+    }
+}
+
+// Describes a bundle; stored in it as manifest.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForensicManifest {
+    pub id: String,
+    pub pid: u32,
+    pub collected_at: DateTime<Utc>,
+    pub detection: String,
+    pub executable: Option<PathBuf>,
+    pub executable_sha256: Option<String>,
+    pub files: Vec<String>,
+    // What could not be read, usually because the process exited mid-way
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForensicBundle {
+    pub manifest: ForensicManifest,
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl ForensicBundle {
+    pub fn to_artifact(&self) -> IncidentArtifact {
+        IncidentArtifact {
+            id: self.manifest.id.clone(),
+            kind: "forensic_snapshot".to_string(),
+            path: self.path.clone(),
+            size: self.size,
+            sha256: self.sha256.clone(),
+            created_at: self.manifest.collected_at,
+            description: format!("Forensic snapshot of pid {} ({} files)", self.manifest.pid, self.manifest.files.len()),
+        }
+    }
+}
+
+// Captures the volatile state of a process as a .tar.gz: the /proc files
+// that describe it, its descriptors and sockets, loaded libraries, a copy
+// of the executable and the events that led to the detection
+pub struct ForensicCollector {
+    config: ForensicConfig,
+}
+
+impl ForensicCollector {
+    pub fn new(config: ForensicConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create forensics directory {:?}", config.dir))?;
+        Ok(Self { config })
+    }
+
+    pub fn collect(&self, pid: u32, events: &[SecurityEvent], detection: &str) -> Result<ForensicBundle> {
+        let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+        if !proc_dir.exists() {
+            return Err(anyhow!("Process {} is gone", pid));
+        }
+        let collected_at = Utc::now();
+        let id = format!("{}-{}", collected_at.format("%Y%m%dT%H%M%S%.3fZ"), pid);
+        let path = self.config.dir.join(format!("{}.tar.gz", id));
+        let mut manifest = ForensicManifest {
+            id,
+            pid,
+            collected_at,
+            detection: detection.to_string(),
+            executable: fs::read_link(proc_dir.join("exe")).ok(),
+            executable_sha256: None,
+            files: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        let mut tar = TarWriter::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+        let mtime = collected_at.timestamp().max(0) as u64;
+        let mut add = |manifest: &mut ForensicManifest, name: &str, data: io::Result<Vec<u8>>| -> Result<()> {
+            match data {
+                Ok(data) => {
+                    tar.append(name, &data, mtime)?;
+                    manifest.files.push(name.to_string());
+                }
+                Err(e) => manifest.errors.push(format!("{}: {}", name, e)),
+            }
+            Ok(())
+        };
+
+        for name in ["cmdline", "environ", "maps", "status", "stat", "cgroup", "limits", "mountinfo"] {
+            add(&mut manifest, name, fs::read(proc_dir.join(name)))?;
+        }
+        let fds = fd_listing(&proc_dir);
+        let inodes: BTreeSet<u64> = fds.iter().filter_map(|(_, target)| socket_inode(target)).collect();
+        let listing: String = fds.iter().map(|(fd, target)| format!("{} -> {}\n", fd, target)).collect();
+        add(&mut manifest, "fd.txt", Ok(listing.into_bytes()))?;
+        add(&mut manifest, "sockets.txt", Ok(socket_rows(&proc_dir, &inodes).into_bytes()))?;
+        let maps = fs::read_to_string(proc_dir.join("maps")).unwrap_or_default();
+        add(&mut manifest, "libraries.txt", Ok(loaded_libraries(&maps).join("\n").into_bytes()))?;
+
+        // /proc/<pid>/exe still opens when the file was deleted after launch
+        match read_executable(&proc_dir.join("exe"), self.config.max_executable_bytes) {
+            Ok((data, sha256)) => {
+                manifest.executable_sha256 = Some(sha256);
+                match data {
+                    Some(data) => add(&mut manifest, "exe", Ok(data))?,
+                    None => manifest.errors.push(format!("exe: larger than {} bytes, not copied", self.config.max_executable_bytes)),
+                }
+            }
+            Err(e) => manifest.errors.push(format!("exe: {}", e)),
+        }
+
+        let start = events.len().saturating_sub(self.config.max_events);
+        add(&mut manifest, "events.json", Ok(serde_json::to_vec_pretty(&events[start..])?))?;
+        manifest.files.push("manifest.json".to_string());
+        tar.append("manifest.json", &serde_json::to_vec_pretty(&manifest)?, mtime)?;
+        tar.finish()?.finish()?.sync_all()?;
+
+        let (size, sha256) = hash_file(&path)?;
+        info!("Collected forensic snapshot of pid {} into {:?} ({} bytes)", pid, path, size);
+        Ok(ForensicBundle { manifest, path, size, sha256 })
+    }
+
+    // Snapshots the processes behind every Critical correlation and attaches
+    // the bundles to its incident. Collection runs on a worker thread;
+    // processes that exit before their turn are reported and skipped.
+    pub fn attach_to(self: Arc<Self>, incidents: &Arc<IncidentManager>) -> Result<()> {
+        let (sender, receiver) = mpsc::channel::<(String, CorrelationSignal)>();
+        let sender = Mutex::new(sender);
+        incidents.on_critical(move |id, signal| {
+            if let Ok(sender) = sender.lock() {
+                let _ = sender.send((id.to_string(), signal.clone()));
+            }
+        });
+
+        let incidents = Arc::downgrade(incidents);
+        std::thread::Builder::new()
+            .name("forensics".to_string())
+            .spawn(move || {
+                for (id, signal) in receiver {
+                    let Some(incidents) = incidents.upgrade() else { break };
+                    let own_pid = std::process::id();
+                    let pids: BTreeSet<u32> = signal.events.iter()
+                        .map(|event| event.process_info.pid)
+                        .filter(|pid| *pid != own_pid)
+                        .collect();
+                    for pid in pids.into_iter().take(self.config.max_processes) {
+                        let detection = format!("{}: {}", signal.rule_name, signal.description);
+                        match self.collect(pid, &signal.events, &detection) {
+                            Ok(bundle) => {
+                                if let Err(e) = incidents.attach_artifact(&id, bundle.to_artifact()) {
+                                    warn!("Forensic snapshot {:?} not attached: {}", bundle.path, e);
+                                }
+                            }
+                            Err(e) => error!("Forensic snapshot of pid {} failed: {:#}", pid, e),
+                        }
+                    }
+                }
+            })?;
+        Ok(())
+    }
+}
+
+fn fd_listing(proc_dir: &Path) -> Vec<(u32, String)> {
+    let mut fds: Vec<(u32, String)> = fs::read_dir(proc_dir.join("fd"))
+        .map(|entries| entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let fd = entry.file_name().to_str()?.parse().ok()?;
+                Some((fd, fs::read_link(entry.path()).ok()?.display().to_string()))
+            })
+            .collect())
+        .unwrap_or_default();
+    fds.sort();
+    fds
+}
+
+fn socket_inode(target: &str) -> Option<u64> {
+    target.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+}
+
+// Rows of the process's network namespace tables that belong to its sockets
+fn socket_rows(proc_dir: &Path, inodes: &BTreeSet<u64>) -> String {
+    let mut out = String::new();
+    // Column holding the inode in each table
+    for (table, column) in [("tcp", 9), ("tcp6", 9), ("udp", 9), ("udp6", 9), ("raw", 9), ("raw6", 9), ("unix", 6)] {
+        let Ok(content) = fs::read_to_string(proc_dir.join("net").join(table)) else { continue };
+        let mut lines = content.lines();
+        let header = lines.next().unwrap_or_default();
+        let rows: Vec<&str> = lines
+            .filter(|line| line.split_whitespace().nth(column)
+                .and_then(|inode| inode.parse().ok())
+                .is_some_and(|inode: u64| inodes.contains(&inode)))
+            .collect();
+        if !rows.is_empty() {
+            out.push_str(&format!("# {}\n{}\n{}\n", table, header.trim(), rows.join("\n")));
+        }
+    }
+    out
+}
+
+// Shared objects mapped into the process, in load order
+fn loaded_libraries(maps: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .filter(|path| path.starts_with('/') && path.contains(".so"))
+        .filter(|path| seen.insert(path.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+// The executable's hash, and its contents when within the size limit
+fn read_executable(exe: &Path, max_bytes: u64) -> Result<(Option<Vec<u8>>, String)> {
+    let mut file = File::open(exe)?;
+    let mut hasher = Sha256::new();
+    let mut data = Vec::new();
+    let mut keep = true;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        if keep {
+            data.extend_from_slice(&buffer[..read]);
+            if data.len() as u64 > max_bytes {
+                keep = false;
+                data = Vec::new();
+            }
+        }
+    }
+    Ok((keep.then_some(data), hex::encode(hasher.finalize())))
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+// Enough of ustar to write regular files with short names
+struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    fn append(&mut self, name: &str, data: &[u8], mtime: u64) -> io::Result<()> {
+        if name.len() > 99 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tar entry name too long"));
+        }
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let mut field = |offset: usize, width: usize, value: u64| {
+            let text = format!("{:0width$o}\0", value, width = width - 1);
+            header[offset..offset + width].copy_from_slice(text.as_bytes());
+        };
+        field(100, 8, 0o600);
+        field(108, 8, 0);
+        field(116, 8, 0);
+        field(124, 12, data.len() as u64);
+        field(136, 12, mtime);
+        header[148..156].fill(b' ');
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.out.write_all(&header)?;
+        self.out.write_all(data)?;
+        let padding = (512 - data.len() % 512) % 512;
+        self.out.write_all(&vec![0u8; padding])
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0u8; 1024])?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incidents::IncidentSeverity;
+    use crate::monitor::{ProcessInfo, SecurityEventType, Verdict};
+
+    #[test]
+    fn test_forensic_snapshot() {
+        let dir = std::env::temp_dir().join(format!("flux-forensics-{}", std::process::id()));
+        let collector = Arc::new(ForensicCollector::new(ForensicConfig { dir: dir.clone(), ..Default::default() }).unwrap());
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let event = SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::FileExecution { target_path: PathBuf::from("/bin/sleep"), file_hash: None, code_signature: None },
+            process_info: ProcessInfo {
+                pid: child.id(),
+                path: PathBuf::from("/bin/sleep"),
+                parent_pid: Some(std::process::id()),
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        };
+
+        let bundle = collector.collect(child.id(), std::slice::from_ref(&event), "test").unwrap();
+        assert!(bundle.manifest.executable_sha256.is_some());
+        for name in ["cmdline", "maps", "fd.txt", "libraries.txt", "exe", "events.json", "manifest.json"] {
+            assert!(bundle.manifest.files.iter().any(|file| file == name), "{} missing", name);
+        }
+        let listing = std::process::Command::new("tar").arg("tzf").arg(&bundle.path).output().unwrap();
+        let listing = String::from_utf8_lossy(&listing.stdout);
+        assert!(listing.lines().any(|line| line == "manifest.json") && listing.lines().any(|line| line == "exe"));
+        let (size, sha256) = hash_file(&bundle.path).unwrap();
+        assert_eq!((size, sha256), (bundle.size, bundle.sha256.clone()));
+
+        // A Critical correlation snapshots its processes into the incident
+        let incidents = Arc::new(IncidentManager::default());
+        Arc::clone(&collector).attach_to(&incidents).unwrap();
+        let id = incidents.record_correlation(None, CorrelationSignal {
+            rule_id: "reverse_shell".to_string(),
+            rule_name: "Reverse Shell".to_string(),
+            description: "test".to_string(),
+            severity: IncidentSeverity::Critical,
+            detected_at: Utc::now(),
+            events: vec![event],
+        }).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while incidents.get(&id).unwrap().artifacts.is_empty() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let artifact = incidents.get(&id).unwrap().artifacts.remove(0);
+        assert!(artifact.path.exists() && artifact.kind == "forensic_snapshot");
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(collector.collect(u32::MAX, &[], "gone").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_HASH_CACHE_CAPACITY: usize = 16384;
+
+// What a cached hash was computed against. A rewrite, truncation or rename
+// over the path changes at least one of these, so the entry stops matching;
+// ctime too, since mtime can be set back after a rewrite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileIdentity {
+    dev: u64,
+    inode: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+}
+
+impl FileIdentity {
+    fn of(metadata: &Metadata) -> Self {
+        Self {
+            dev: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec(),
+        }
+    }
+}
+
+struct CacheEntry {
+    identity: FileIdentity,
+    hash: String,
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HashCacheStats {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    // Entries dropped because the file changed since it was hashed
+    pub invalidations: u64,
+    pub evictions: u64,
+}
+
+// SHA-256 results for executables, bounded by least-recent use. Lookups
+// re-check the file's inode, size and times so a modified file is rehashed.
+pub struct HashCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    // Use counter -> path, oldest first
+    recency: BTreeMap<u64, PathBuf>,
+    clock: u64,
+    capacity: usize,
+    stats: HashCacheStats,
+}
+
+impl HashCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            capacity: capacity.max(1),
+            stats: HashCacheStats::default(),
+        }
+    }
+
+    // Looks the path up against its current metadata
+    pub fn get(&mut self, path: &Path) -> Option<String> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => self.get_with_metadata(path, &metadata),
+            Err(_) => {
+                self.remove(path);
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn get_with_metadata(&mut self, path: &Path, metadata: &Metadata) -> Option<String> {
+        let identity = FileIdentity::of(metadata);
+        let stale = match self.entries.get(path) {
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+            Some(entry) => entry.identity != identity,
+        };
+        if stale {
+            self.remove(path);
+            self.stats.invalidations += 1;
+            self.stats.misses += 1;
+            return None;
+        }
+
+        self.stats.hits += 1;
+        let last_used = self.tick();
+        let entry = self.entries.get_mut(path)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = last_used;
+        self.recency.insert(last_used, path.to_path_buf());
+        Some(entry.hash.clone())
+    }
+
+    // The metadata should come from the handle that was hashed, so the entry
+    // describes exactly the content the hash covers
+    pub fn insert(&mut self, path: &Path, metadata: &Metadata, hash: String) {
+        self.remove(path);
+        let last_used = self.tick();
+        self.entries.insert(path.to_path_buf(), CacheEntry {
+            identity: FileIdentity::of(metadata),
+            hash,
+            last_used,
+        });
+        self.recency.insert(last_used, path.to_path_buf());
+        self.evict_to(self.capacity);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict_to(self.capacity);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> HashCacheStats {
+        HashCacheStats {
+            capacity: self.capacity,
+            entries: self.entries.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn evict_to(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let Some((_, path)) = self.recency.pop_first() else { break };
+            self.entries.remove(&path);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl Default for HashCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_lru_eviction_and_invalidation() {
+        let dir = std::env::temp_dir().join(format!("flux-hash-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("bin{}", i))).collect();
+        for path in &paths {
+            fs::write(path, b"original").unwrap();
+        }
+
+        let mut cache = HashCache::new(2);
+        for (i, path) in paths.iter().take(2).enumerate() {
+            cache.insert(path, &fs::metadata(path).unwrap(), format!("hash{}", i));
+        }
+        // Touch bin0 so bin1 is the least recently used
+        assert_eq!(cache.get(&paths[0]).as_deref(), Some("hash0"));
+        cache.insert(&paths[2], &fs::metadata(&paths[2]).unwrap(), "hash2".to_string());
+        assert_eq!(cache.get(&paths[1]), None);
+        assert_eq!(cache.get(&paths[2]).as_deref(), Some("hash2"));
+
+        // A rewrite with a different size no longer matches
+        fs::write(&paths[0], b"modified contents").unwrap();
+        assert_eq!(cache.get(&paths[0]), None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.invalidations, 1);
+        assert_eq!(stats.entries, 1);
+
+        // Rewritten in place with the mtime set back: the ctime still moves
+        let metadata = fs::metadata(&paths[2]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&paths[2], b"tampered").unwrap();
+        fs::File::options().write(true).open(&paths[2]).unwrap().set_modified(metadata.modified().unwrap()).unwrap();
+        assert_eq!(fs::metadata(&paths[2]).unwrap().mtime_nsec(), metadata.mtime_nsec());
+        assert_eq!(cache.get(&paths[2]), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::monitor::{ProcessInfo as MonitorProcessInfo, SecurityEvent, SecurityEventType, Verdict};
+use super::audit::{syscall_name, AuditRecord, AuditRecordType};
+use super::patterns::{PatternCategory, Severity};
+
+// Audit rules that produce the records the detector reads; load them with
+// `auditctl -R` or drop them into /etc/audit/rules.d. The last one logs
+// every open for writing and is the one to leave out on busy hosts.
+pub const INJECTION_AUDIT_RULES: &[&str] = &[
+    "-a always,exit -F arch=b64 -S ptrace -F success=1 -k flux_injection",
+    "-a always,exit -F arch=b64 -S process_vm_writev -F success=1 -k flux_injection",
+    "-a always,exit -F arch=b64 -S memfd_create -k flux_injection",
+    "-a always,exit -F arch=b64 -S execve,execveat -k flux_exec",
+    "-a always,exit -F arch=b64 -S openat -F a2&0x3 -F success=1 -k flux_open_write",
+];
+
+// from linux/ptrace.h
+const PTRACE_POKETEXT: u64 = 4;
+const PTRACE_POKEDATA: u64 = 5;
+const PTRACE_POKEUSER: u64 = 6;
+const PTRACE_SETREGS: u64 = 13;
+const PTRACE_ATTACH: u64 = 16;
+const PTRACE_SEIZE: u64 = 0x4206;
+
+// Tracers whose attaches are expected on developer machines
+const DEBUGGERS: &[&str] = &["gdb", "lldb", "strace", "ltrace", "perf", "rr", "valgrind"];
+
+// The same injector and target are reported once in this window, since an
+// injection writes memory many times
+const REPEAT_WINDOW: Duration = Duration::from_secs(60);
+// How long a memfd_create is remembered for the execution that follows it
+const MEMFD_WINDOW: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InjectionKind {
+    PtraceAttach,
+    // POKETEXT, POKEDATA, POKEUSER or SETREGS on a traced process
+    PtraceWrite { request: String },
+    ProcessVmWrite,
+    ProcMemWrite,
+    // A program executed from an anonymous memory file
+    MemfdExec { name: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectionProcess {
+    pub pid: u32,
+    pub exe: Option<PathBuf>,
+    pub comm: Option<String>,
+    pub uid: Option<u32>,
+    pub command_line: Option<String>,
+}
+
+impl InjectionProcess {
+    // Read from /proc at detection time; the target may already be gone
+    fn from_proc(pid: u32) -> Self {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+        let uid = status.lines()
+            .find_map(|line| line.strip_prefix("Uid:"))
+            .and_then(|uids| uids.split_whitespace().next())
+            .and_then(|uid| uid.parse().ok());
+        Self {
+            pid,
+            exe: fs::read_link(format!("/proc/{}/exe", pid)).ok(),
+            comm: fs::read_to_string(format!("/proc/{}/comm", pid)).ok().map(|comm| comm.trim().to_string()),
+            uid,
+            command_line: fs::read(format!("/proc/{}/cmdline", pid)).ok()
+                .filter(|cmdline| !cmdline.is_empty())
+                .map(|cmdline| String::from_utf8_lossy(&cmdline).trim_end_matches('\0').replace('\0', " ")),
+        }
+    }
+
+    fn from_syscall(record: &AuditRecord) -> Self {
+        Self {
+            pid: record.field_u32("pid").unwrap_or(0),
+            exe: record.field("exe").map(PathBuf::from),
+            comm: record.field("comm").map(String::from),
+            uid: record.field_u32("uid"),
+            command_line: None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let name = self.exe.as_ref().map(|exe| exe.display().to_string())
+            .or_else(|| self.comm.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+        format!("{} (pid {})", name, self.pid)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectionDetection {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: InjectionKind,
+    pub severity: Severity,
+    pub injector: InjectionProcess,
+    pub target: Option<InjectionProcess>,
+    pub description: String,
+}
+
+impl InjectionDetection {
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let event_type = match self.kind {
+            InjectionKind::MemfdExec { .. } => SecurityEventType::FileExecution {
+                target_path: self.injector.exe.clone().unwrap_or_else(|| PathBuf::from("unknown")),
+                file_hash: None,
+                code_signature: None,
+            },
+            _ => SecurityEventType::Syscall {
+                syscall: match self.kind {
+                    InjectionKind::ProcessVmWrite => "process_vm_writev",
+                    InjectionKind::ProcMemWrite => "openat",
+                    _ => "ptrace",
+                }.to_string(),
+                success: true,
+                exit_code: None,
+                audit_key: None,
+            },
+        };
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type,
+            process_info: MonitorProcessInfo {
+                pid: self.injector.pid,
+                path: self.injector.exe.clone().unwrap_or_else(|| PathBuf::from("unknown")),
+                parent_pid: None,
+                user_id: self.injector.uid.unwrap_or(0),
+                executable_hash: None,
+                command_line: self.injector.command_line.clone(),
+            },
+            verdict: Verdict::Log,
+            policy_reason: format!("Process injection ({:?} {:?}): {}", self.severity, PatternCategory::MemoryInjection, self.description),
+        }
+    }
+}
+
+#[derive(Default)]
+struct InjectionState {
+    reported: HashMap<(u32, u32, &'static str), Instant>,
+    // pid -> when it created a memory file
+    memfds: HashMap<u32, Instant>,
+}
+
+// Runtime process injection from audit records: ptrace attaches and memory
+// writes, process_vm_writev, /proc/<pid>/mem opened for writing, and
+// programs executed from memfd_create files
+#[derive(Default)]
+pub struct InjectionDetector {
+    state: Mutex<InjectionState>,
+}
+
+impl InjectionDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // One complete audit event (records sharing a serial)
+    pub fn observe(&self, records: &[AuditRecord]) -> Option<InjectionDetection> {
+        let syscall = records.iter().find(|record| record.record_type == AuditRecordType::Syscall)?;
+        let name = syscall_name(syscall.field("arch").unwrap_or(""), syscall.field("syscall").unwrap_or(""));
+        let injector = InjectionProcess::from_syscall(syscall);
+        let argument = |index: usize| syscall.field(&format!("a{}", index)).and_then(|value| u64::from_str_radix(value, 16).ok());
+
+        match name.as_str() {
+            "memfd_create" => {
+                if let Ok(mut state) = self.state.lock() {
+                    state.memfds.retain(|_, created| created.elapsed() < MEMFD_WINDOW);
+                    state.memfds.insert(injector.pid, Instant::now());
+                }
+                None
+            }
+            "execve" | "execveat" => {
+                let exe = injector.exe.as_ref()?.to_str()?;
+                let memfd = exe.strip_prefix("/memfd:")?;
+                let memfd = memfd.trim_end_matches(" (deleted)").to_string();
+                let created = self.state.lock().ok()
+                    .map(|state| [Some(injector.pid), syscall.field_u32("ppid")].iter().flatten()
+                        .any(|pid| state.memfds.get(pid).is_some_and(|created| created.elapsed() < MEMFD_WINDOW)))
+                    .unwrap_or(false);
+                let description = format!("{} executed from memory file \"{}\"{}", injector.describe(), memfd,
+                    if created { " it created just before" } else { "" });
+                self.detection(InjectionKind::MemfdExec { name: memfd }, Severity::Critical, injector, None, description)
+            }
+            "ptrace" => {
+                let request = argument(0)?;
+                let target = InjectionProcess::from_proc(u32::try_from(argument(1)?).ok()?);
+                let (kind, verb) = match request {
+                    PTRACE_ATTACH | PTRACE_SEIZE => (InjectionKind::PtraceAttach, "attached to"),
+                    PTRACE_POKETEXT => (InjectionKind::PtraceWrite { request: "POKETEXT".to_string() }, "wrote the code of"),
+                    PTRACE_POKEDATA => (InjectionKind::PtraceWrite { request: "POKEDATA".to_string() }, "wrote the memory of"),
+                    PTRACE_POKEUSER => (InjectionKind::PtraceWrite { request: "POKEUSER".to_string() }, "wrote the registers of"),
+                    PTRACE_SETREGS => (InjectionKind::PtraceWrite { request: "SETREGS".to_string() }, "set the registers of"),
+                    _ => return None,
+                };
+                let debugger = injector.comm.as_deref().is_some_and(|comm| DEBUGGERS.contains(&comm));
+                let severity = match (&kind, debugger) {
+                    (InjectionKind::PtraceAttach, true) => Severity::Low,
+                    (InjectionKind::PtraceAttach, false) => Severity::High,
+                    (_, true) => Severity::Medium,
+                    (_, false) => Severity::Critical,
+                };
+                let description = format!("{} {} {} via ptrace", injector.describe(), verb, target.describe());
+                self.detection(kind, severity, injector, Some(target), description)
+            }
+            "process_vm_writev" => {
+                let target = InjectionProcess::from_proc(u32::try_from(argument(0)?).ok()?);
+                let description = format!("{} wrote the memory of {} with process_vm_writev", injector.describe(), target.describe());
+                self.detection(InjectionKind::ProcessVmWrite, Severity::Critical, injector, Some(target), description)
+            }
+            "open" | "openat" => {
+                let flags = argument(if name == "open" { 1 } else { 2 })?;
+                // O_WRONLY or O_RDWR
+                if flags & 3 == 0 {
+                    return None;
+                }
+                let target = records.iter()
+                    .filter(|record| record.record_type == AuditRecordType::Path)
+                    .filter_map(|record| record.field("name"))
+                    .find_map(|name| name.strip_prefix("/proc/")?.strip_suffix("/mem")?.parse::<u32>().ok())?;
+                if target == injector.pid {
+                    return None;
+                }
+                let target = InjectionProcess::from_proc(target);
+                let description = format!("{} opened /proc/{}/mem of {} for writing", injector.describe(), target.pid, target.describe());
+                self.detection(InjectionKind::ProcMemWrite, Severity::Critical, injector, Some(target), description)
+            }
+            _ => None,
+        }
+    }
+
+    fn detection(
+        &self,
+        kind: InjectionKind,
+        severity: Severity,
+        injector: InjectionProcess,
+        target: Option<InjectionProcess>,
+        description: String,
+    ) -> Option<InjectionDetection> {
+        let key = (injector.pid, target.as_ref().map(|target| target.pid).unwrap_or(0), match kind {
+            InjectionKind::PtraceAttach => "ptrace_attach",
+            InjectionKind::PtraceWrite { .. } => "ptrace_write",
+            InjectionKind::ProcessVmWrite => "process_vm_write",
+            InjectionKind::ProcMemWrite => "proc_mem_write",
+            InjectionKind::MemfdExec { .. } => "memfd_exec",
+        });
+        {
+            let mut state = self.state.lock().ok()?;
+            state.reported.retain(|_, reported| reported.elapsed() < REPEAT_WINDOW);
+            if state.reported.contains_key(&key) {
+                return None;
+            }
+            state.reported.insert(key, Instant::now());
+        }
+        Some(InjectionDetection {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            kind,
+            severity,
+            injector,
+            target,
+            description,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::audit::parse_log_line;
+
+    fn event(serial: u64, syscall: &str, fields: &str, paths: &[&str]) -> Vec<AuditRecord> {
+        let mut records = vec![parse_log_line(&format!(
+            "type=SYSCALL msg=audit(1700000001.000:{}): arch=c000003e syscall={} success=yes exit=0 {} \
+             ppid=1 pid=4000 uid=1000 comm=\"inject\" exe=\"/tmp/inject\" key=\"flux_injection\"",
+            serial, syscall, fields)).unwrap()];
+        for path in paths {
+            records.push(parse_log_line(&format!(
+                "type=PATH msg=audit(1700000001.000:{}): item=0 name=\"{}\" inode=1 nametype=NORMAL", serial, path)).unwrap());
+        }
+        records
+    }
+
+    #[test]
+    fn test_injection_detection() {
+        let detector = InjectionDetector::new();
+        let me = std::process::id();
+
+        let attach = detector.observe(&event(1, "101", &format!("a0=10 a1={:x}", me), &[])).unwrap();
+        assert_eq!((attach.kind.clone(), attach.severity), (InjectionKind::PtraceAttach, Severity::High));
+        assert_eq!(attach.target.as_ref().map(|target| target.pid), Some(me));
+        assert!(attach.target.as_ref().unwrap().exe.is_some());
+        assert_eq!(attach.injector.exe.as_deref(), Some(std::path::Path::new("/tmp/inject")));
+
+        // Many POKEDATA calls make one detection
+        let poke = detector.observe(&event(2, "101", &format!("a0=5 a1={:x}", me), &[])).unwrap();
+        assert_eq!(poke.severity, Severity::Critical);
+        assert!(detector.observe(&event(3, "101", &format!("a0=5 a1={:x}", me), &[])).is_none());
+        // PTRACE_GETREGS reads only
+        assert!(detector.observe(&event(4, "101", &format!("a0=c a1={:x}", me), &[])).is_none());
+
+        let proc_mem = format!("/proc/{}/mem", me);
+        assert!(detector.observe(&event(5, "257", "a0=ffffff9c a1=7ffd a2=0", &[&proc_mem])).is_none());
+        let write = detector.observe(&event(5, "257", "a0=ffffff9c a1=7ffd a2=2", &[&proc_mem])).unwrap();
+        assert_eq!(write.kind, InjectionKind::ProcMemWrite);
+        assert!(write.to_security_event().policy_reason.contains("MemoryInjection"));
+
+        assert!(detector.observe(&event(6, "319", "a0=7ffd a1=1", &[])).is_none());
+        let exec = parse_log_line("type=SYSCALL msg=audit(1700000002.000:7): arch=c000003e syscall=59 success=yes exit=0 \
+            ppid=4000 pid=4001 uid=1000 comm=\"3\" exe=\"/memfd:payload (deleted)\"").unwrap();
+        let memfd = detector.observe(&[exec]).unwrap();
+        assert_eq!(memfd.kind, InjectionKind::MemfdExec { name: "payload".to_string() });
+        assert!(memfd.description.ends_with("it created just before"));
+    }
+}
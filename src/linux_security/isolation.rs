@@ -0,0 +1,413 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn, error};
+
+use super::netfilter::{Direction, IpMatch, NetfilterManager, NetfilterRule, NftRule, PortMatch};
+
+// What stays reachable while the host is isolated. Everything else is
+// dropped in both directions until the isolation is released or expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IsolationConfig {
+    // Addresses or CIDRs reachable on every port, e.g. a jump host network
+    pub management: Vec<String>,
+    // Local TCP ports that keep accepting connections, normally the API server's
+    pub api_ports: Vec<u16>,
+    // host:port or URLs; names are resolved when isolating, while DNS still works
+    pub fleet_servers: Vec<String>,
+    // Resolvers that can still be queried on port 53; empty means the
+    // nameservers in /etc/resolv.conf
+    pub dns_resolvers: Vec<String>,
+    // Used when a request does not say how long to isolate; 0 isolates
+    // until released
+    pub default_minutes: u64,
+}
+
+impl Default for IsolationConfig {
+    fn default() -> Self {
+        Self {
+            management: Vec::new(),
+            api_ports: vec![3177],
+            fleet_servers: Vec::new(),
+            dns_resolvers: Vec::new(),
+            default_minutes: 60,
+        }
+    }
+}
+
+// Always reachable, so local services keep working during isolation
+const LOOPBACK: [&str; 2] = ["127.0.0.0/8", "::1/128"];
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+impl IsolationConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read isolation config {:?}", path))?;
+        let config: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid isolation config {:?}", path))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for entry in &self.management {
+            parse_network(entry).context("isolation management network")?;
+        }
+        if self.api_ports.contains(&0) {
+            return Err(anyhow!("isolation api_ports cannot contain port 0"));
+        }
+        for server in &self.fleet_servers {
+            endpoint_authority(server)?;
+        }
+        for resolver in &self.dns_resolvers {
+            resolver.parse::<IpAddr>().map_err(|_| anyhow!("Invalid DNS resolver '{}'", resolver))?;
+        }
+        Ok(())
+    }
+
+    // Turns names into addresses; `extra` networks are allowed for this
+    // isolation only
+    pub fn resolve(&self, extra: &[String]) -> Result<ResolvedIsolation> {
+        let mut networks: Vec<String> = LOOPBACK.iter().map(|entry| entry.to_string()).collect();
+        for entry in self.management.iter().chain(extra) {
+            parse_network(entry)?;
+            if !networks.contains(entry) {
+                networks.push(entry.clone());
+            }
+        }
+        let mut fleet_servers = Vec::new();
+        for server in &self.fleet_servers {
+            let authority = endpoint_authority(server)?;
+            let addrs = authority.to_socket_addrs()
+                .with_context(|| format!("Cannot resolve fleet server '{}'", server))?;
+            fleet_servers.extend(addrs.filter(|addr| !fleet_servers.contains(addr)).collect::<Vec<_>>());
+        }
+        let dns_resolvers = if self.dns_resolvers.is_empty() {
+            system_resolvers()
+        } else {
+            self.dns_resolvers.iter().filter_map(|resolver| resolver.parse().ok()).collect()
+        };
+        Ok(ResolvedIsolation { networks, api_ports: self.api_ports.clone(), fleet_servers, dns_resolvers })
+    }
+}
+
+// The management plane with every name resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedIsolation {
+    pub networks: Vec<String>,
+    pub api_ports: Vec<u16>,
+    pub fleet_servers: Vec<SocketAddr>,
+    pub dns_resolvers: Vec<IpAddr>,
+}
+
+impl ResolvedIsolation {
+    // Accept rules for the management plane followed by drops, installed in
+    // this order on the fluxdefense input and output chains. Replies are
+    // matched by port since the accepts come before any conntrack rule.
+    pub fn rules(&self, prefix: &str) -> Result<Vec<NetfilterRule>> {
+        let mut rules = Vec::new();
+        let mut push = |name: String, chain: &str, rule: NftRule, comment: String| {
+            rules.push(NetfilterRule {
+                id: format!("{}_{}_{}", prefix, name, chain),
+                table: "fluxdefense".to_string(),
+                chain: chain.to_string(),
+                priority: 1,
+                rule,
+                comment,
+                shadow: false,
+            });
+        };
+
+        for (index, network) in self.networks.iter().enumerate() {
+            for (chain, direction) in [("input", Direction::Source), ("output", Direction::Destination)] {
+                push(format!("net{}", index), chain, address_rule(network, direction, NftRule::Accept)?,
+                    format!("Isolation: allow {}", network));
+            }
+        }
+        for port in &self.api_ports {
+            push(format!("api{}", port), "input", tcp_ports(None, Some(*port)), format!("Isolation: allow API port {}", port));
+            push(format!("api{}", port), "output", tcp_ports(Some(*port), None), format!("Isolation: allow API port {}", port));
+        }
+        for (index, server) in self.fleet_servers.iter().enumerate() {
+            let ip = server.ip().to_string();
+            let comment = format!("Isolation: allow fleet server {}", server);
+            push(format!("fleet{}", index), "output",
+                address_rule(&ip, Direction::Destination, tcp_ports(None, Some(server.port())))?, comment.clone());
+            push(format!("fleet{}", index), "input",
+                address_rule(&ip, Direction::Source, tcp_ports(Some(server.port()), None))?, comment);
+        }
+        for (index, resolver) in self.dns_resolvers.iter().enumerate() {
+            let ip = resolver.to_string();
+            for proto in ["udp", "tcp"] {
+                let comment = format!("Isolation: allow DNS to {}", resolver);
+                push(format!("dns{}_{}", index, proto), "output",
+                    address_rule(&ip, Direction::Destination, ports(proto, None, Some(53)))?, comment.clone());
+                push(format!("dns{}_{}", index, proto), "input",
+                    address_rule(&ip, Direction::Source, ports(proto, Some(53), None))?, comment);
+            }
+        }
+        for chain in ["input", "output"] {
+            push("drop".to_string(), chain, NftRule::Drop, "Isolation: drop everything else".to_string());
+        }
+        Ok(rules)
+    }
+
+    // What the isolation lets through, for status output
+    pub fn describe(&self) -> Vec<String> {
+        self.networks.iter().cloned()
+            .chain(self.api_ports.iter().map(|port| format!("tcp/{} inbound", port)))
+            .chain(self.fleet_servers.iter().map(|server| format!("fleet {}", server)))
+            .chain(self.dns_resolvers.iter().map(|resolver| format!("dns {}", resolver)))
+            .collect()
+    }
+}
+
+fn address_rule(network: &str, direction: Direction, action: NftRule) -> Result<NftRule> {
+    let is_v6 = parse_network(network)?;
+    let addr = IpMatch::Subnet(match (network.contains('/'), is_v6) {
+        (true, _) => network.to_string(),
+        (false, true) => format!("{}/128", network),
+        (false, false) => format!("{}/32", network),
+    });
+    let action = Box::new(action);
+    Ok(if is_v6 { NftRule::Ip6Match { direction, addr, action } } else { NftRule::IpMatch { direction, addr, action } })
+}
+
+fn ports(proto: &str, sport: Option<u16>, dport: Option<u16>) -> NftRule {
+    NftRule::Protocol {
+        proto: proto.to_string(),
+        sport: sport.map(PortMatch::Single),
+        dport: dport.map(PortMatch::Single),
+        action: Box::new(NftRule::Accept),
+    }
+}
+
+fn tcp_ports(sport: Option<u16>, dport: Option<u16>) -> NftRule {
+    ports("tcp", sport, dport)
+}
+
+// Whether an address or CIDR is IPv6
+pub(crate) fn parse_network(entry: &str) -> Result<bool> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+    let addr: IpAddr = addr.parse().map_err(|_| anyhow!("Invalid address '{}'", entry))?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    if prefix.is_some_and(|prefix| prefix.parse::<u8>().map_or(true, |prefix| prefix > max)) {
+        return Err(anyhow!("Invalid prefix length in '{}'", entry));
+    }
+    Ok(addr.is_ipv6())
+}
+
+// "https://fleet.example.com/api" -> "fleet.example.com:443"; IPv6
+// literals need brackets
+fn endpoint_authority(server: &str) -> Result<String> {
+    let (scheme, rest) = match server.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, server),
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    if authority.is_empty() {
+        return Err(anyhow!("Fleet server '{}' has no host", server));
+    }
+    let has_port = authority.rsplit_once(':').is_some_and(|(host, port)| {
+        port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']'))
+    });
+    if has_port {
+        return Ok(authority.to_string());
+    }
+    match scheme {
+        Some("https") | Some("wss") => Ok(format!("{}:443", authority)),
+        Some("http") | Some("ws") => Ok(format!("{}:80", authority)),
+        _ => Err(anyhow!("Fleet server '{}' needs a port or an http(s) URL", server)),
+    }
+}
+
+// A local stub such as systemd-resolved's 127.0.0.53 is covered by the
+// loopback accept, but its upstream servers are not
+fn system_resolvers() -> Vec<IpAddr> {
+    let content = std::fs::read_to_string(RESOLV_CONF).unwrap_or_default();
+    content.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|addr| addr.trim().parse().ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IsolationStatus {
+    pub isolated: bool,
+    pub since: Option<DateTime<Utc>>,
+    // None while isolated means until released
+    pub expires_at: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+    pub allowed: Vec<String>,
+}
+
+struct ActiveIsolation {
+    status: IsolationStatus,
+    rule_ids: Vec<String>,
+}
+
+// Host network isolation for incident response. One isolation is active at
+// a time; isolating again replaces its rules and expiry.
+pub struct HostIsolation {
+    firewall: Arc<Mutex<NetfilterManager>>,
+    config: IsolationConfig,
+    active: Mutex<Option<ActiveIsolation>>,
+    // Rule ids differ per isolation so a replacement can go in before the
+    // rules it replaces come out
+    generation: AtomicU64,
+}
+
+impl HostIsolation {
+    pub fn new(firewall: Arc<Mutex<NetfilterManager>>, config: IsolationConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self { firewall, config, active: Mutex::new(None), generation: AtomicU64::new(0) })
+    }
+
+    pub fn config(&self) -> &IsolationConfig {
+        &self.config
+    }
+
+    // `duration` None uses the configured default
+    pub fn isolate(&self, duration: Option<Duration>, reason: &str, extra_allow: &[String]) -> Result<IsolationStatus> {
+        let resolved = self.config.resolve(extra_allow)?;
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        let rules = resolved.rules(&format!("isolate{}", generation))?;
+        let rule_ids: Vec<String> = rules.iter().map(|rule| rule.id.clone()).collect();
+        let duration = duration.unwrap_or(Duration::from_secs(self.config.default_minutes * 60));
+
+        let mut active = self.active.lock().map_err(|_| anyhow!("Isolation lock poisoned"))?;
+        let mut firewall = self.firewall.lock().map_err(|_| anyhow!("Firewall lock poisoned"))?;
+        firewall.add_rules(rules)?;
+        let previous = active.take();
+        if let Some(ref previous) = previous {
+            remove_rules(&mut firewall, &previous.rule_ids);
+        }
+
+        let now = Utc::now();
+        let status = IsolationStatus {
+            isolated: true,
+            since: Some(previous.and_then(|previous| previous.status.since).unwrap_or(now)),
+            expires_at: (!duration.is_zero()).then(|| now + chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX)),
+            reason: Some(reason.to_string()),
+            allowed: resolved.describe(),
+        };
+        warn!("Host isolated ({}), allowing {}", reason, status.allowed.join(", "));
+        *active = Some(ActiveIsolation { status: status.clone(), rule_ids });
+        Ok(status)
+    }
+
+    // False when the host was not isolated
+    pub fn release(&self) -> Result<bool> {
+        let mut active = self.active.lock().map_err(|_| anyhow!("Isolation lock poisoned"))?;
+        let Some(isolation) = active.take() else {
+            return Ok(false);
+        };
+        let mut firewall = self.firewall.lock().map_err(|_| anyhow!("Firewall lock poisoned"))?;
+        let failed = remove_rules(&mut firewall, &isolation.rule_ids);
+        if failed > 0 {
+            // Keep the state so a later release retries
+            *active = Some(isolation);
+            return Err(anyhow!("Failed to remove {} isolation rules", failed));
+        }
+        info!("Host isolation released");
+        Ok(true)
+    }
+
+    pub fn status(&self) -> IsolationStatus {
+        self.active.lock().ok()
+            .and_then(|active| active.as_ref().map(|isolation| isolation.status.clone()))
+            .unwrap_or_default()
+    }
+
+    pub fn release_if_expired(&self) -> Result<bool> {
+        let expired = self.status().expires_at.is_some_and(|expires_at| expires_at <= Utc::now());
+        if expired {
+            info!("Host isolation expired");
+            return self.release();
+        }
+        Ok(false)
+    }
+
+    // Checks for expiry every few seconds for as long as the isolation
+    // manager is alive
+    pub fn start_auto_release(self: &Arc<Self>) -> Result<()> {
+        let isolation = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("isolation-release".to_string())
+            .spawn(move || {
+                while let Some(isolation) = isolation.upgrade() {
+                    if let Err(e) = isolation.release_if_expired() {
+                        error!("Failed to release expired isolation: {:#}", e);
+                    }
+                    drop(isolation);
+                    std::thread::sleep(Duration::from_secs(5));
+                }
+            })?;
+        Ok(())
+    }
+}
+
+// Returns how many rules could not be removed
+fn remove_rules(firewall: &mut NetfilterManager, ids: &[String]) -> usize {
+    ids.iter()
+        .filter(|id| match firewall.remove_rule(id) {
+            Ok(()) => false,
+            Err(e) => {
+                warn!("Failed to remove isolation rule {}: {}", id, e);
+                true
+            }
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isolation_rules() {
+        let config = IsolationConfig {
+            management: vec!["10.0.0.0/8".to_string()],
+            api_ports: vec![3177],
+            fleet_servers: vec!["https://192.0.2.10/api".to_string()],
+            dns_resolvers: vec!["192.0.2.53".to_string()],
+            default_minutes: 30,
+        };
+        let resolved = config.resolve(&["2001:db8::/32".to_string(), "10.0.0.0/8".to_string()]).unwrap();
+        assert_eq!(resolved.networks, ["127.0.0.0/8", "::1/128", "10.0.0.0/8", "2001:db8::/32"]);
+        assert_eq!(resolved.fleet_servers, ["192.0.2.10:443".parse::<SocketAddr>().unwrap()]);
+
+        // Four networks and the API port both ways, fleet and DNS (udp and
+        // tcp) both ways, then a drop per chain
+        let rules = resolved.rules("isolate0").unwrap();
+        assert_eq!(rules.len(), 8 + 2 + 2 + 4 + 2);
+        assert!(matches!(rules[3].rule, NftRule::Ip6Match { .. }));
+        assert!(rules[..rules.len() - 2].iter().all(|rule| !matches!(rule.rule, NftRule::Drop)));
+        assert!(rules[rules.len() - 2..].iter().all(|rule| matches!(rule.rule, NftRule::Drop)));
+        let ids: std::collections::HashSet<_> = rules.iter().map(|rule| &rule.id).collect();
+        assert_eq!(ids.len(), rules.len());
+        assert_eq!(resolved.describe().last().unwrap(), "dns 192.0.2.53");
+
+        assert_eq!(endpoint_authority("fleet.example.com:8443").unwrap(), "fleet.example.com:8443");
+        assert_eq!(endpoint_authority("http://[2001:db8::1]/x").unwrap(), "[2001:db8::1]:80");
+        assert!(endpoint_authority("fleet.example.com").is_err());
+        assert!(IsolationConfig { management: vec!["10.0.0.0/40".to_string()], ..Default::default() }.validate().is_err());
+
+        // Without an initialized firewall nothing is recorded as isolated
+        let firewall = Arc::new(Mutex::new(NetfilterManager::new().unwrap()));
+        let isolation = HostIsolation::new(firewall, config).unwrap();
+        assert!(isolation.isolate(Some(Duration::from_secs(60)), "test", &[]).is_err());
+        assert!(!isolation.status().isolated);
+        assert!(!isolation.release().unwrap());
+        assert!(!isolation.release_if_expired().unwrap());
+    }
+}
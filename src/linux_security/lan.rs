@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+use uuid::Uuid;
+
+// This many MAC changes for one address inside the window is flapping
+const FLAP_WINDOW: Duration = Duration::from_secs(300);
+const FLAP_CHANGES: usize = 3;
+// A rogue DHCP server that keeps answering is reported again after this long
+const DHCP_REALERT: Duration = Duration::from_secs(600);
+const MAX_ARP_ENTRIES: usize = 4096;
+const MAX_DHCP_SERVERS: usize = 64;
+const MAX_LAN_ALERTS: usize = 256;
+
+const DHCP_MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const DHCP_OFFER: u8 = 2;
+const DHCP_ACK: u8 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArpEntry {
+    pub ip: Ipv4Addr,
+    pub mac: String,
+    pub previous_mac: Option<String>,
+    pub mac_changes: u32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DhcpServer {
+    pub address: Ipv4Addr,
+    pub authorized: bool,
+    pub replies: u64,
+    // Gateway and resolvers handed out in the latest reply
+    pub routers: Vec<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanAlertKind {
+    // A gratuitous ARP claiming an address already bound to another MAC
+    ArpConflict,
+    // An address moving back and forth between MACs
+    MacFlapping,
+    // DHCP offers or acks from a server that is not authorized
+    RogueDhcpServer,
+}
+
+impl LanAlertKind {
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            LanAlertKind::ArpConflict => "lan_arp_conflict",
+            LanAlertKind::MacFlapping => "lan_mac_flapping",
+            LanAlertKind::RogueDhcpServer => "lan_rogue_dhcp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanAlert {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: LanAlertKind,
+    pub ip: Ipv4Addr,
+    pub mac: Option<String>,
+    pub previous_mac: Option<String>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanStatus {
+    pub arp_table: Vec<ArpEntry>,
+    pub dhcp_servers: Vec<DhcpServer>,
+    pub alerts: Vec<LanAlert>,
+}
+
+struct ArpState {
+    entry: ArpEntry,
+    changes: VecDeque<Instant>,
+    flapping_reported: Option<Instant>,
+}
+
+struct DhcpState {
+    server: DhcpServer,
+    reported: Option<Instant>,
+}
+
+#[derive(Default)]
+struct LanState {
+    arp: HashMap<Ipv4Addr, ArpState>,
+    // Empty until configured, in which case the first server seen is trusted
+    authorized_dhcp: HashSet<Ipv4Addr>,
+    dhcp: HashMap<Ipv4Addr, DhcpState>,
+    alerts: VecDeque<LanAlert>,
+}
+
+// IP to MAC bindings and DHCP servers seen on the captured segments
+#[derive(Default)]
+pub struct LanMonitor {
+    state: Mutex<LanState>,
+}
+
+impl LanMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_authorized_dhcp_servers(&self, servers: Vec<Ipv4Addr>) {
+        let mut state = self.state.lock().unwrap();
+        state.authorized_dhcp = servers.into_iter().collect();
+        let authorized = state.authorized_dhcp.clone();
+        for dhcp in state.dhcp.values_mut() {
+            dhcp.server.authorized = authorized.contains(&dhcp.server.address);
+        }
+    }
+
+    // An ARP packet, starting at the ARP header
+    pub fn observe_arp(&self, data: &[u8]) -> Option<LanAlert> {
+        let packet = ArpPacket::parse(data)?;
+        // Probes carry no sender address yet
+        if packet.sender_ip.is_unspecified() {
+            return None;
+        }
+        let mac = format_mac(&packet.sender_mac);
+        let now = Instant::now();
+        let timestamp = Utc::now();
+
+        let mut state = self.state.lock().unwrap();
+        if !state.arp.contains_key(&packet.sender_ip) {
+            if state.arp.len() >= MAX_ARP_ENTRIES {
+                let stalest = state.arp.iter().min_by_key(|(_, arp)| arp.entry.last_seen).map(|(ip, _)| *ip);
+                if let Some(ip) = stalest {
+                    state.arp.remove(&ip);
+                }
+            }
+            state.arp.insert(packet.sender_ip, ArpState {
+                entry: ArpEntry {
+                    ip: packet.sender_ip,
+                    mac,
+                    previous_mac: None,
+                    mac_changes: 0,
+                    first_seen: timestamp,
+                    last_seen: timestamp,
+                },
+                changes: VecDeque::new(),
+                flapping_reported: None,
+            });
+            return None;
+        }
+
+        let arp = state.arp.get_mut(&packet.sender_ip)?;
+        arp.entry.last_seen = timestamp;
+        if arp.entry.mac == mac {
+            return None;
+        }
+        let previous = std::mem::replace(&mut arp.entry.mac, mac.clone());
+        arp.entry.previous_mac = Some(previous.clone());
+        arp.entry.mac_changes += 1;
+        arp.changes.push_back(now);
+        while arp.changes.front().is_some_and(|changed| now.duration_since(*changed) > FLAP_WINDOW) {
+            arp.changes.pop_front();
+        }
+
+        let flapping = arp.changes.len() >= FLAP_CHANGES
+            && arp.flapping_reported.is_none_or(|reported| now.duration_since(reported) > FLAP_WINDOW);
+        let (kind, description) = if flapping {
+            arp.flapping_reported = Some(now);
+            (LanAlertKind::MacFlapping, format!(
+                "{} changed MAC {} times in {}s, now {} (was {})",
+                packet.sender_ip, arp.changes.len(), FLAP_WINDOW.as_secs(), mac, previous,
+            ))
+        } else if packet.gratuitous() {
+            (LanAlertKind::ArpConflict, format!(
+                "Gratuitous ARP rebinds {} from {} to {}",
+                packet.sender_ip, previous, mac,
+            ))
+        } else {
+            return None;
+        };
+
+        let alert = LanAlert {
+            id: Uuid::new_v4().to_string(),
+            timestamp,
+            kind,
+            ip: packet.sender_ip,
+            mac: Some(mac),
+            previous_mac: Some(previous),
+            description,
+        };
+        state.push_alert(alert.clone());
+        Some(alert)
+    }
+
+    // A UDP payload sent from port 67 by `source`
+    pub fn observe_dhcp(&self, source: Ipv4Addr, payload: &[u8]) -> Option<LanAlert> {
+        let reply = DhcpReply::parse(payload)?;
+        if reply.message_type != DHCP_OFFER && reply.message_type != DHCP_ACK {
+            return None;
+        }
+        let address = reply.server_id.unwrap_or(source);
+        let now = Instant::now();
+        let timestamp = Utc::now();
+
+        let mut state = self.state.lock().unwrap();
+        if state.authorized_dhcp.is_empty() {
+            info!("Trusting {} as the segment's DHCP server", address);
+            state.authorized_dhcp.insert(address);
+        }
+        let authorized = state.authorized_dhcp.contains(&address);
+        if !state.dhcp.contains_key(&address) && state.dhcp.len() >= MAX_DHCP_SERVERS {
+            return None;
+        }
+        let dhcp = state.dhcp.entry(address).or_insert_with(|| DhcpState {
+            server: DhcpServer {
+                address,
+                authorized,
+                replies: 0,
+                routers: Vec::new(),
+                dns_servers: Vec::new(),
+                first_seen: timestamp,
+                last_seen: timestamp,
+            },
+            reported: None,
+        });
+        dhcp.server.replies += 1;
+        dhcp.server.last_seen = timestamp;
+        dhcp.server.routers = reply.routers;
+        dhcp.server.dns_servers = reply.dns_servers;
+
+        if authorized || dhcp.reported.is_some_and(|reported| now.duration_since(reported) <= DHCP_REALERT) {
+            return None;
+        }
+        dhcp.reported = Some(now);
+        let list = |addresses: &[Ipv4Addr]| addresses.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+        let alert = LanAlert {
+            id: Uuid::new_v4().to_string(),
+            timestamp,
+            kind: LanAlertKind::RogueDhcpServer,
+            ip: address,
+            mac: None,
+            previous_mac: None,
+            description: format!(
+                "Unauthorized DHCP server {} offered {} to {} (gateway [{}], DNS [{}])",
+                address, reply.your_ip, format_mac(&reply.client_mac),
+                list(&dhcp.server.routers), list(&dhcp.server.dns_servers),
+            ),
+        };
+        state.push_alert(alert.clone());
+        Some(alert)
+    }
+
+    // Alerts newest first
+    pub fn status(&self, limit: usize) -> LanStatus {
+        let state = self.state.lock().unwrap();
+        let mut arp_table: Vec<ArpEntry> = state.arp.values().map(|arp| arp.entry.clone()).collect();
+        arp_table.sort_by_key(|entry| entry.ip);
+        let mut dhcp_servers: Vec<DhcpServer> = state.dhcp.values().map(|dhcp| dhcp.server.clone()).collect();
+        dhcp_servers.sort_by_key(|server| server.address);
+        LanStatus {
+            arp_table,
+            dhcp_servers,
+            alerts: state.alerts.iter().take(limit).cloned().collect(),
+        }
+    }
+}
+
+impl LanState {
+    fn push_alert(&mut self, alert: LanAlert) {
+        self.alerts.push_front(alert);
+        self.alerts.truncate(MAX_LAN_ALERTS);
+    }
+}
+
+struct ArpPacket {
+    operation: u16,
+    sender_mac: [u8; 6],
+    sender_ip: Ipv4Addr,
+    target_mac: [u8; 6],
+    target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    // Ethernet/IPv4 ARP only
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 28 || data[0..2] != [0, 1] || data[2..4] != [0x08, 0x00] || data[4] != 6 || data[5] != 4 {
+            return None;
+        }
+        Some(Self {
+            operation: u16::from_be_bytes([data[6], data[7]]),
+            sender_mac: data[8..14].try_into().ok()?,
+            sender_ip: Ipv4Addr::new(data[14], data[15], data[16], data[17]),
+            target_mac: data[18..24].try_into().ok()?,
+            target_ip: Ipv4Addr::new(data[24], data[25], data[26], data[27]),
+        })
+    }
+
+    // An announcement of the sender's own address, or a reply nobody asked for
+    fn gratuitous(&self) -> bool {
+        self.sender_ip == self.target_ip
+            || (self.operation == 2 && (self.target_mac == [0; 6] || self.target_mac == [0xff; 6]))
+    }
+}
+
+struct DhcpReply {
+    message_type: u8,
+    your_ip: Ipv4Addr,
+    client_mac: [u8; 6],
+    server_id: Option<Ipv4Addr>,
+    routers: Vec<Ipv4Addr>,
+    dns_servers: Vec<Ipv4Addr>,
+}
+
+impl DhcpReply {
+    fn parse(payload: &[u8]) -> Option<Self> {
+        // BOOTREPLY with the DHCP magic cookie after the fixed BOOTP fields
+        if payload.len() < 240 || payload[0] != 2 || payload[236..240] != DHCP_MAGIC_COOKIE {
+            return None;
+        }
+        let mut reply = Self {
+            message_type: 0,
+            your_ip: Ipv4Addr::new(payload[16], payload[17], payload[18], payload[19]),
+            client_mac: payload[28..34].try_into().ok()?,
+            server_id: None,
+            routers: Vec::new(),
+            dns_servers: Vec::new(),
+        };
+
+        let mut options = &payload[240..];
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                0 => {
+                    options = rest;
+                    continue;
+                }
+                255 => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let value = rest.get(..len as usize)?;
+            options = &rest[len as usize..];
+            let addresses = || value.chunks_exact(4).map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3])).collect::<Vec<_>>();
+            match code {
+                53 => reply.message_type = *value.first()?,
+                54 => reply.server_id = addresses().first().copied(),
+                3 => reply.routers = addresses(),
+                6 => reply.dns_servers = addresses(),
+                _ => {}
+            }
+        }
+        Some(reply)
+    }
+}
+
+fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arp(operation: u16, sender: ([u8; 6], [u8; 4]), target: ([u8; 6], [u8; 4])) -> Vec<u8> {
+        let mut packet = vec![0, 1, 0x08, 0x00, 6, 4];
+        packet.extend_from_slice(&operation.to_be_bytes());
+        packet.extend_from_slice(&sender.0);
+        packet.extend_from_slice(&sender.1);
+        packet.extend_from_slice(&target.0);
+        packet.extend_from_slice(&target.1);
+        packet
+    }
+
+    fn dhcp_offer(server: [u8; 4], router: [u8; 4]) -> Vec<u8> {
+        let mut payload = vec![0u8; 240];
+        payload[0] = 2;
+        payload[16..20].copy_from_slice(&[192, 168, 1, 50]);
+        payload[28..34].copy_from_slice(&[0x02, 0, 0, 0, 0, 0x50]);
+        payload[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        payload.extend_from_slice(&[53, 1, DHCP_OFFER, 0, 54, 4]);
+        payload.extend_from_slice(&server);
+        payload.extend_from_slice(&[3, 4]);
+        payload.extend_from_slice(&router);
+        payload.extend_from_slice(&[6, 8, 1, 1, 1, 1, 8, 8, 8, 8, 255]);
+        payload
+    }
+
+    #[test]
+    fn test_arp_and_dhcp_detection() {
+        let monitor = LanMonitor::new();
+        let gateway = [192, 168, 1, 1];
+        let real = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let attacker = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+        let host = ([0x02, 0, 0, 0, 0, 0x50], [192, 168, 1, 50]);
+
+        // Ordinary request and reply learn the binding
+        assert!(monitor.observe_arp(&arp(1, (real, gateway), ([0; 6], host.1))).is_none());
+        assert!(monitor.observe_arp(&arp(2, (real, gateway), host)).is_none());
+        // Probes are ignored
+        assert!(monitor.observe_arp(&arp(1, (attacker, [0; 4]), ([0; 6], gateway))).is_none());
+
+        // Unsolicited reply rebinding the gateway
+        let alert = monitor.observe_arp(&arp(2, (attacker, gateway), ([0xff; 6], gateway))).unwrap();
+        assert_eq!(alert.kind, LanAlertKind::ArpConflict);
+        assert_eq!(alert.previous_mac.as_deref(), Some("00:11:22:33:44:55"));
+        assert_eq!(alert.mac.as_deref(), Some("de:ad:be:ef:00:01"));
+
+        // The real gateway answers a request, then the attacker wins it back
+        assert!(monitor.observe_arp(&arp(2, (real, gateway), host)).is_none());
+        let alert = monitor.observe_arp(&arp(2, (attacker, gateway), host)).unwrap();
+        assert_eq!(alert.kind, LanAlertKind::MacFlapping);
+        assert!(monitor.observe_arp(&arp(2, (real, gateway), host)).is_none());
+
+        // The first DHCP server seen is trusted, a second one is not
+        assert!(monitor.observe_dhcp(Ipv4Addr::new(192, 168, 1, 1), &dhcp_offer(gateway, gateway)).is_none());
+        let rogue = monitor.observe_dhcp(Ipv4Addr::new(192, 168, 1, 66), &dhcp_offer([192, 168, 1, 66], [192, 168, 1, 66])).unwrap();
+        assert_eq!(rogue.kind, LanAlertKind::RogueDhcpServer);
+        assert!(rogue.description.contains("gateway [192.168.1.66]"));
+        assert!(rogue.description.contains("02:00:00:00:00:50"));
+        // Reported once per realert period
+        assert!(monitor.observe_dhcp(Ipv4Addr::new(192, 168, 1, 66), &dhcp_offer([192, 168, 1, 66], [192, 168, 1, 66])).is_none());
+
+        let status = monitor.status(10);
+        assert_eq!(status.arp_table.len(), 1);
+        assert_eq!(status.arp_table[0].mac_changes, 4);
+        assert_eq!(status.dhcp_servers.len(), 2);
+        assert!(status.dhcp_servers.iter().any(|server| server.authorized && server.address == Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(status.alerts.len(), 3);
+        assert_eq!(status.alerts[0].kind, LanAlertKind::RogueDhcpServer);
+
+        // Configured servers replace the learned one
+        monitor.set_authorized_dhcp_servers(vec![Ipv4Addr::new(192, 168, 1, 66)]);
+        assert!(monitor.status(0).dhcp_servers.iter().all(|server| server.authorized == (server.address == Ipv4Addr::new(192, 168, 1, 66))));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use tracing::debug;
+use uuid::Uuid;
+
+use super::patterns::Severity;
+use crate::scanner::directory::TEMP_DIRECTORIES;
+use crate::redaction::Redactor;
+
+// Variables that make the dynamic loader pull in code the binary did not ask for
+const LOADER_VARIABLES: &[&str] = &["LD_PRELOAD", "LD_AUDIT", "LD_LIBRARY_PATH"];
+const DEFAULT_LIBRARY_DIRS: &[&str] = &["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/usr/local/lib"];
+const MAX_RECENT_FINDINGS: usize = 200;
+
+#[derive(Debug, Clone)]
+pub enum LoaderFindingKind {
+    // A process started with a loader variable naming a non-standard location
+    Environment { pid: u32, variable: String, value: String, paths: Vec<PathBuf> },
+    // /etc/ld.so.preload appeared or changed; it applies to every process
+    PreloadFile { path: PathBuf, entries: Vec<String> },
+    // A shared object that was not in a loader directory at the previous scan
+    NewSharedObject { path: PathBuf },
+}
+
+#[derive(Debug, Clone)]
+pub struct LoaderFinding {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: LoaderFindingKind,
+    pub severity: Severity,
+    pub description: String,
+    // The whole environment of the process, read when it was flagged
+    pub environment: Option<BTreeMap<String, String>>,
+}
+
+impl LoaderFinding {
+    fn new(kind: LoaderFindingKind, severity: Severity, description: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            kind,
+            severity,
+            description,
+            environment: None,
+        }
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        match self.kind {
+            LoaderFindingKind::Environment { pid, .. } => Some(pid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct LoaderState {
+    // None until the first scan
+    preload: Option<String>,
+    libraries: Option<HashSet<PathBuf>>,
+    recent: VecDeque<LoaderFinding>,
+}
+
+// Dynamic-loader hijacking: loader variables in process environments,
+// /etc/ld.so.preload, and shared objects dropped into the loader's directories
+pub struct LoaderHijackDetector {
+    preload_file: PathBuf,
+    library_dirs: Vec<PathBuf>,
+    state: Mutex<LoaderState>,
+    redactor: Redactor,
+}
+
+impl LoaderHijackDetector {
+    // The system's preload file and the directories ld.so.conf configures
+    pub fn new() -> Self {
+        let mut library_dirs: Vec<PathBuf> = DEFAULT_LIBRARY_DIRS.iter().map(PathBuf::from).collect();
+        for dir in ld_so_conf_dirs(Path::new("/etc/ld.so.conf"), 0) {
+            if !library_dirs.contains(&dir) {
+                library_dirs.push(dir);
+            }
+        }
+        Self::with_paths(PathBuf::from("/etc/ld.so.preload"), library_dirs)
+    }
+
+    pub fn with_paths(preload_file: PathBuf, library_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            preload_file,
+            library_dirs,
+            state: Mutex::new(LoaderState::default()),
+            redactor: Redactor::default(),
+        }
+    }
+
+    pub fn library_dirs(&self) -> &[PathBuf] {
+        &self.library_dirs
+    }
+
+    // Reads the environment of a running process; None when nothing is off
+    // or the process is gone
+    pub fn check_process(&self, pid: u32) -> Option<LoaderFinding> {
+        let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+        let environment: BTreeMap<String, String> = environ.split(|&b| b == 0)
+            .filter_map(|entry| {
+                let entry = String::from_utf8_lossy(entry);
+                let (key, value) = entry.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        self.check_environment(pid, &environment)
+    }
+
+    pub fn check_environment(&self, pid: u32, environment: &BTreeMap<String, String>) -> Option<LoaderFinding> {
+        let mut worst: Option<(Severity, &str, &String, Vec<PathBuf>)> = None;
+        for variable in LOADER_VARIABLES {
+            let Some(value) = environment.get(*variable) else { continue };
+            // LD_PRELOAD takes spaces or colons, the others colons; bare names
+            // are looked up in the standard directories
+            let paths: Vec<PathBuf> = value.split([':', ' '])
+                .filter(|entry| entry.contains('/'))
+                .map(PathBuf::from)
+                .filter(|path| !self.is_standard(path))
+                .collect();
+            if paths.is_empty() {
+                continue;
+            }
+            let writable = paths.iter().any(|path| is_user_writable_location(path));
+            let severity = match (writable, *variable) {
+                (true, _) => Severity::Critical,
+                (false, "LD_LIBRARY_PATH") => Severity::Medium,
+                (false, _) => Severity::High,
+            };
+            if worst.as_ref().is_none_or(|(current, ..)| severity > *current) {
+                worst = Some((severity, variable, value, paths));
+            }
+        }
+
+        let (severity, variable, value, paths) = worst?;
+        let listed = paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+        let mut finding = LoaderFinding::new(
+            LoaderFindingKind::Environment { pid, variable: variable.to_string(), value: value.clone(), paths },
+            severity,
+            format!("Process {} started with {} pointing at {}", pid, variable, listed),
+        );
+        // Kept for context, minus the credentials most environments carry
+        let mut environment = environment.clone();
+        self.redactor.redact_environment(&mut environment);
+        finding.environment = Some(environment);
+        self.remember(&finding);
+        Some(finding)
+    }
+
+    // Checks the preload file and the loader directories; the first scan
+    // only records what is there, except for a preload file already in use
+    pub fn scan(&self) -> Vec<LoaderFinding> {
+        let mut findings = Vec::new();
+        let preload = fs::read_to_string(&self.preload_file).unwrap_or_default();
+        let libraries = self.snapshot_libraries();
+        let Ok(mut state) = self.state.lock() else {
+            return findings;
+        };
+
+        if state.preload.as_ref() != Some(&preload) {
+            let entries: Vec<String> = preload.lines()
+                .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+                .map(String::from)
+                .collect();
+            if !entries.is_empty() {
+                let verb = if state.preload.is_none() { "lists" } else { "changed and now lists" };
+                findings.push(LoaderFinding::new(
+                    LoaderFindingKind::PreloadFile { path: self.preload_file.clone(), entries: entries.clone() },
+                    Severity::Critical,
+                    format!("{} {} {}, loaded into every process", self.preload_file.display(), verb, entries.join(", ")),
+                ));
+            }
+            state.preload = Some(preload);
+        }
+
+        if let Some(ref previous) = state.libraries {
+            let mut added: Vec<&PathBuf> = libraries.iter().filter(|path| !previous.contains(*path)).collect();
+            added.sort();
+            for path in added {
+                let severity = if is_user_writable_location(path) { Severity::High } else { Severity::Medium };
+                findings.push(LoaderFinding::new(
+                    LoaderFindingKind::NewSharedObject { path: path.clone() },
+                    severity,
+                    format!("New shared object {} in a loader directory", path.display()),
+                ));
+            }
+        }
+        debug!("Loader scan: {} shared objects in {} directories", libraries.len(), self.library_dirs.len());
+        state.libraries = Some(libraries);
+        drop(state);
+
+        for finding in &findings {
+            self.remember(finding);
+        }
+        findings
+    }
+
+    // Newest first
+    pub fn recent_findings(&self, limit: usize) -> Vec<LoaderFinding> {
+        self.state.lock()
+            .map(|state| state.recent.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn remember(&self, finding: &LoaderFinding) {
+        if let Ok(mut state) = self.state.lock() {
+            state.recent.push_back(finding.clone());
+            while state.recent.len() > MAX_RECENT_FINDINGS {
+                state.recent.pop_front();
+            }
+        }
+    }
+
+    fn is_standard(&self, path: &Path) -> bool {
+        // $LIB and $PLATFORM expand to subdirectories of the prefix before them
+        let path = path.to_str()
+            .and_then(|path| path.split('$').next())
+            .map(Path::new)
+            .unwrap_or(path);
+        self.library_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    // Shared objects directly in each loader directory
+    fn snapshot_libraries(&self) -> HashSet<PathBuf> {
+        let mut libraries = HashSet::new();
+        for dir in &self.library_dirs {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            for entry in entries.flatten() {
+                let is_library = entry.file_name().to_str().is_some_and(|name| name.ends_with(".so") || name.contains(".so."));
+                if is_library {
+                    libraries.insert(entry.path());
+                }
+            }
+        }
+        libraries
+    }
+}
+
+impl Default for LoaderHijackDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_user_writable_location(path: &Path) -> bool {
+    TEMP_DIRECTORIES.iter().any(|dir| path.starts_with(dir))
+        || path.starts_with("/home")
+        || path.starts_with("/run/user")
+}
+
+// Directories listed in ld.so.conf and the files it includes
+fn ld_so_conf_dirs(path: &Path, depth: usize) -> Vec<PathBuf> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let mut dirs = Vec::new();
+    for line in content.lines().map(|line| line.split('#').next().unwrap_or("").trim()) {
+        if let Some(pattern) = line.strip_prefix("include ") {
+            if depth >= 4 {
+                continue;
+            }
+            // Only the usual "<dir>/*.conf" form of glob
+            let pattern = Path::new(pattern.trim());
+            let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name().and_then(|name| name.to_str())) else { continue };
+            let suffix = name.trim_start_matches('*');
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            let mut included: Vec<PathBuf> = entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.to_str().is_some_and(|path| path.ends_with(suffix)))
+                .collect();
+            included.sort();
+            for file in included {
+                dirs.extend(ld_so_conf_dirs(&file, depth + 1));
+            }
+        } else if line.starts_with('/') {
+            dirs.push(PathBuf::from(line));
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loader_hijack_detection() {
+        let dir = std::env::temp_dir().join(format!("fluxdefense-loader-{}", Uuid::new_v4()));
+        let lib = dir.join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("libc.so.6"), b"").unwrap();
+        let preload = dir.join("ld.so.preload");
+        let detector = LoaderHijackDetector::with_paths(preload.clone(), vec![lib.clone(), PathBuf::from("/usr/lib")]);
+
+        let environment = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>();
+        assert!(detector.check_environment(10, &environment(&[("LD_PRELOAD", "libjemalloc.so /usr/lib/$LIB/libfoo.so")])).is_none());
+        let finding = detector.check_environment(10, &environment(&[
+            ("LD_LIBRARY_PATH", "/opt/app/lib"),
+            ("LD_PRELOAD", "/dev/shm/.x.so"),
+            ("HOME", "/root"),
+        ])).unwrap();
+        assert_eq!(finding.severity, Severity::Critical);
+        assert!(matches!(finding.kind, LoaderFindingKind::Environment { ref variable, .. } if variable == "LD_PRELOAD"));
+        assert_eq!(finding.environment.as_ref().map(|env| env.len()), Some(3));
+        let opt = detector.check_environment(11, &environment(&[("LD_LIBRARY_PATH", "/opt/app/lib")])).unwrap();
+        assert_eq!(opt.severity, Severity::Medium);
+
+        // First scan records the libraries, later ones report additions
+        assert!(detector.scan().is_empty());
+        fs::write(lib.join("libevil.so"), b"").unwrap();
+        fs::write(&preload, "# comment\n/usr/lib/libevil.so\n").unwrap();
+        let findings = detector.scan();
+        assert_eq!(findings.len(), 2);
+        assert!(matches!(findings[0].kind, LoaderFindingKind::PreloadFile { ref entries, .. } if entries == &["/usr/lib/libevil.so"]));
+        assert!(matches!(findings[1].kind, LoaderFindingKind::NewSharedObject { ref path } if path == &lib.join("libevil.so")));
+        assert!(detector.scan().is_empty());
+        assert_eq!(detector.recent_findings(10).len(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
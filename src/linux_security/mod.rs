@@ -1,5 +1,6 @@
 pub mod monitor;
 pub mod fanotify;
+pub mod permission_watchdog;
 pub mod netlink;
 pub mod process_monitor;
 pub mod enhanced_monitor;
@@ -7,17 +8,68 @@ pub mod network_filter;
 pub mod iptables;
 pub mod patterns;
 pub mod netfilter;
+pub mod nft_netlink;
 pub mod dns_filter;
 pub mod event_correlation;
+pub mod tls;
+pub mod dns;
+pub mod audit;
+pub mod rootkit;
+pub mod escalation;
+pub mod reputation;
+pub mod egress;
+pub mod flow_export;
+pub mod capture_set;
+pub mod tpacket;
+pub mod tasks;
+pub mod hash_cache;
+pub mod decision_cache;
+pub mod proc_connector;
+pub mod process_tree;
+pub mod auto_block;
+pub mod supervisor;
+pub mod resource_usage;
+pub mod playbooks;
+pub mod isolation;
+pub mod forensics;
+pub mod loader_hijack;
+pub mod injection;
+pub mod beaconing;
+pub mod lan;
+pub mod tunneling;
 
 pub use monitor::LinuxSecurityMonitor;
 pub use fanotify::FanotifyMonitor;
+pub use permission_watchdog::{PermissionWatchdog, WatchdogStats};
 pub use netlink::NetlinkMonitor;
-pub use process_monitor::ProcessMonitor;
+pub use process_monitor::{ProcessMonitor, ProcessChange};
+pub use proc_connector::{ProcConnector, ProcEvent};
+pub use process_tree::{ProcessTree, ProcessTreeNode, ProcessTreeSource};
 pub use enhanced_monitor::{EnhancedSecurityMonitor, SecurityPolicy, EnforcementMode};
-pub use network_filter::{NetworkFilter, NetworkFilterRule, NetworkEvent, FilterAction, Direction, Protocol};
+pub use network_filter::{NetworkFilter, NetworkFilterRule, NetworkEvent, FilterAction, Direction, Protocol, ConnectionTable};
 pub use iptables::{IptablesManager, IptablesRule, Chain, RuleAction};
 pub use patterns::{PatternMatcher, BehaviorPattern, PatternCategory, Severity, ProcessChain};
-pub use netfilter::{NetfilterManager, NetfilterRule, NftRule};
+pub use netfilter::{NetfilterManager, NetfilterRule, NftRule, NftBackendKind, DnsRedirect, ReconcileReport, TempBan, BulkLoadSummary};
 pub use dns_filter::{DnsFilter, DnsFilterConfig, DnsEvent, DnsAction};
-pub use event_correlation::{EventCorrelator, CorrelationRule, CorrelatedEvent};
\ No newline at end of file
+pub use event_correlation::{EventCorrelator, CorrelationRule, CorrelatedEvent};
+pub use tls::{TlsFingerprint, TlsHandshakeKind};
+pub use audit::{AuditMonitor, AuditRecord, AuditSource};
+pub use rootkit::{RootkitDetector, RootkitFinding, RootkitFindingKind};
+pub use escalation::{EnforcementAction, EscalationMatrix, EscalationEntry};
+pub use reputation::{ReputationPipeline, HashVerdict};
+pub use egress::{EgressEnforcer, EgressRule};
+pub use flow_export::{FlowExporter, FlowExportConfig, FlowFormat, FlowRecord};
+pub use capture_set::{CaptureSet, CaptureOptions, CaptureBackend, InterfaceStats};
+pub use hash_cache::{HashCache, HashCacheStats};
+pub use decision_cache::{DecisionCache, DecisionCacheStats};
+pub use auto_block::{BruteForceBlocker, AutoBlockConfig, RemediationEvent, RemediationAction};
+pub use supervisor::{Supervisor, SupervisorConfig, Heartbeat, RestartEvent, SubsystemStatus};
+pub use resource_usage::{ResourceUsage, SustainedUsage};
+pub use playbooks::{Playbook, PlaybookEngine, PlaybookSettings, PlaybookContext, PlaybookRun, PlaybookAlert};
+pub use isolation::{HostIsolation, IsolationConfig, IsolationStatus};
+pub use forensics::{ForensicCollector, ForensicConfig, ForensicBundle};
+pub use loader_hijack::{LoaderHijackDetector, LoaderFinding, LoaderFindingKind};
+pub use injection::{InjectionDetector, InjectionDetection, InjectionKind, INJECTION_AUDIT_RULES};
+pub use beaconing::{BeaconTracker, BeaconSample, BeaconDetection};
+pub use lan::{LanMonitor, LanAlert, LanAlertKind, LanStatus, ArpEntry, DhcpServer};
+pub use tunneling::{TunnelDetector, TunnelDetection, TunnelKind};
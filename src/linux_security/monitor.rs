@@ -1,7 +1,5 @@
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
 use std::sync::mpsc::{channel, Sender, Receiver};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
@@ -10,6 +8,7 @@ use crate::monitor::{PassiveMonitor, ProcessInfo as MonitorProcessInfo, FileAcce
 use super::fanotify::{FanotifyMonitor, FanotifyEvent};
 use super::netlink::{NetlinkMonitor, NetworkConnection};
 use super::process_monitor::{ProcessMonitor, ProcessInfo};
+use super::tasks::{Shutdown, TaskGroup};
 
 #[derive(Debug)]
 pub enum SecurityEvent {
@@ -37,6 +36,7 @@ pub struct LinuxSecurityMonitor {
     fanotify: Option<FanotifyMonitor>,
     netlink: Option<NetlinkMonitor>,
     process_monitor: Arc<Mutex<ProcessMonitor>>,
+    tasks: Option<TaskGroup>,
     running: Arc<Mutex<bool>>,
     event_sender: Option<Sender<SecurityEvent>>,
     event_receiver: Option<Receiver<SecurityEvent>>,
@@ -91,6 +91,7 @@ impl LinuxSecurityMonitor {
             fanotify,
             netlink,
             process_monitor: Arc::new(Mutex::new(process_monitor)),
+            tasks: None,
             running: Arc::new(Mutex::new(false)),
             event_sender: Some(sender),
             event_receiver: Some(receiver),
@@ -108,42 +109,37 @@ impl LinuxSecurityMonitor {
         
         info!("Starting Linux security monitoring");
         
-        // Start fanotify event processing thread if available
+        let mut tasks = TaskGroup::new("flux-linux-monitor")?;
+        
+        // Start fanotify event processing task if available
         if self.fanotify.is_some() {
             let process_monitor = Arc::clone(&self.process_monitor);
-            let running = Arc::clone(&self.running);
             let sender = self.event_sender.as_ref().unwrap().clone();
             
-            thread::spawn(move || {
-                Self::process_fanotify_events(process_monitor, running, sender);
-            });
+            tasks.spawn(move |shutdown| Self::process_fanotify_events(process_monitor, shutdown, sender));
         }
         
-        // Start network monitoring thread if available
+        // Start network monitoring task if available
         if self.netlink.is_some() {
             let process_monitor = Arc::clone(&self.process_monitor);
-            let running = Arc::clone(&self.running);
             let sender = self.event_sender.as_ref().unwrap().clone();
             
-            thread::spawn(move || {
-                Self::process_network_events(process_monitor, running, sender);
-            });
+            tasks.spawn(move |shutdown| Self::process_network_events(process_monitor, shutdown, sender));
         }
         
+        self.tasks = Some(tasks);
         Ok(())
     }
     
-    fn process_fanotify_events(
+    async fn process_fanotify_events(
         process_monitor: Arc<Mutex<ProcessMonitor>>,
-        running: Arc<Mutex<bool>>,
+        mut shutdown: Shutdown,
         sender: Sender<SecurityEvent>,
     ) {
-        info!("Starting fanotify event processing thread");
+        info!("Starting fanotify event processing task");
         
-        while *running.lock().unwrap() {
-            // For now, just sleep - actual implementation would read events
-            thread::sleep(Duration::from_secs(1));
-        }
+        // For now, just wait - actual implementation would read events
+        shutdown.wait().await;
         
         info!("Stopped fanotify event processing");
     }
@@ -208,17 +204,15 @@ impl LinuxSecurityMonitor {
         }
     }
     
-    fn process_network_events(
+    async fn process_network_events(
         process_monitor: Arc<Mutex<ProcessMonitor>>,
-        running: Arc<Mutex<bool>>,
+        mut shutdown: Shutdown,
         sender: Sender<SecurityEvent>,
     ) {
-        info!("Starting network event processing thread");
+        info!("Starting network event processing task");
         
-        while *running.lock().unwrap() {
-            // For now, just sleep - actual implementation would monitor connections
-            thread::sleep(Duration::from_secs(1));
-        }
+        // For now, just wait - actual implementation would monitor connections
+        shutdown.wait().await;
         
         info!("Stopped network event processing");
     }
@@ -277,6 +271,10 @@ impl LinuxSecurityMonitor {
         
         info!("Stopping Linux security monitoring");
         
+        if let Some(mut tasks) = self.tasks.take() {
+            tasks.shutdown();
+        }
+        
         if let Some(mut fanotify) = self.fanotify.take() {
             fanotify.stop()?;
         }
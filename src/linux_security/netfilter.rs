@@ -2,12 +2,30 @@ use std::process::{Command, Stdio};
 use std::io::Write;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use tracing::{info, warn, error, debug};
+use crate::shadow::netfilter_log_prefix;
+use super::nft_netlink::{
+    self, ChainHook, CmpOp, Expr, NftBatch, NftNetlink, Verdict, NFPROTO_INET, NFPROTO_IPV4, NFT_REG_1,
+};
 
 // Netfilter/nftables integration for packet filtering
 // This module provides a Rust interface to nftables for advanced packet filtering
 
+// Kept apart from the main table so removing the redirect never touches
+// filter rules, and the main table's cleanup never touches the redirect
+const DNS_REDIRECT_TABLE: &str = "fluxdefense_dns";
+
+pub const BLOCK_SET_V4: &str = "flux_block_v4";
+pub const BLOCK_SET_V6: &str = "flux_block_v6";
+// Elements per "add element" command for the nft CLI
+const MAX_CLI_ELEMENTS: usize = 1000;
+
 #[derive(Debug, Clone)]
 pub struct NetfilterRule {
     pub id: String,
@@ -16,6 +34,71 @@ pub struct NetfilterRule {
     pub priority: i32,
     pub rule: NftRule,
     pub comment: String,
+    // Installed to log its matches instead of accepting, dropping or
+    // rejecting; see crate::shadow
+    pub shadow: bool,
+}
+
+impl NetfilterRule {
+    // What goes into the kernel for this rule: the rule with a counter of
+    // its matches, see `rule_counters`
+    fn installed(&self) -> NftRule {
+        if !self.shadow {
+            if verdict(&self.rule).is_none() {
+                return NftRule::Compound(vec![self.rule.clone(), NftRule::Counter]);
+            }
+            return map_verdicts(&self.rule, &|verdict| NftRule::Compound(vec![NftRule::Counter, verdict.clone()]));
+        }
+        let prefix = netfilter_log_prefix(verdict(&self.rule).unwrap_or("match"), &self.id);
+        NftRule::Compound(vec![
+            without_verdicts(&self.rule),
+            NftRule::Counter,
+            NftRule::Log { prefix, level: LogLevel::Info, continue_rule: None },
+        ])
+    }
+}
+
+fn verdict(rule: &NftRule) -> Option<&'static str> {
+    match rule {
+        NftRule::Accept => Some("accept"),
+        NftRule::Drop => Some("drop"),
+        NftRule::Reject { .. } => Some("reject"),
+        NftRule::ConnTrack { .. } | NftRule::Counter => None,
+        NftRule::Protocol { action, .. } | NftRule::IpMatch { action, .. } | NftRule::Ip6Match { action, .. }
+        | NftRule::RateLimit { action, .. } => verdict(action),
+        NftRule::Log { continue_rule, .. } => continue_rule.as_deref().and_then(verdict),
+        NftRule::Compound(rules) => rules.iter().find_map(verdict),
+    }
+}
+
+// The rule's matches with its verdicts left out
+fn without_verdicts(rule: &NftRule) -> NftRule {
+    map_verdicts(rule, &|_| NftRule::Compound(Vec::new()))
+}
+
+// The rule with each verdict replaced by `f` of it
+fn map_verdicts(rule: &NftRule, f: &dyn Fn(&NftRule) -> NftRule) -> NftRule {
+    let keep = |action: &NftRule| Box::new(map_verdicts(action, f));
+    match rule {
+        NftRule::Accept | NftRule::Drop | NftRule::Reject { .. } => f(rule),
+        NftRule::ConnTrack { .. } | NftRule::Counter => rule.clone(),
+        NftRule::Protocol { proto, sport, dport, action } => NftRule::Protocol {
+            proto: proto.clone(), sport: sport.clone(), dport: dport.clone(), action: keep(action),
+        },
+        NftRule::IpMatch { direction, addr, action } => NftRule::IpMatch {
+            direction: direction.clone(), addr: addr.clone(), action: keep(action),
+        },
+        NftRule::Ip6Match { direction, addr, action } => NftRule::Ip6Match {
+            direction: direction.clone(), addr: addr.clone(), action: keep(action),
+        },
+        NftRule::RateLimit { rate, per, burst, action } => NftRule::RateLimit {
+            rate: *rate, per: per.clone(), burst: *burst, action: keep(action),
+        },
+        NftRule::Log { prefix, level, continue_rule } => NftRule::Log {
+            prefix: prefix.clone(), level: level.clone(), continue_rule: continue_rule.as_deref().map(keep),
+        },
+        NftRule::Compound(rules) => NftRule::Compound(rules.iter().map(|rule| map_verdicts(rule, f)).collect()),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +125,11 @@ pub enum NftRule {
         addr: IpMatch,
         action: Box<NftRule>,
     },
+    Ip6Match {
+        direction: Direction,
+        addr: IpMatch,
+        action: Box<NftRule>,
+    },
     
     // Rate limiting
     RateLimit {
@@ -58,6 +146,9 @@ pub enum NftRule {
         continue_rule: Option<Box<NftRule>>,
     },
     
+    // Counts the packets and bytes that get this far
+    Counter,
+    
     // Complex rules
     Compound(Vec<NftRule>),
 }
@@ -111,11 +202,54 @@ pub enum LogLevel {
     Debug,
 }
 
+// How rules reach the kernel. `Auto` prefers netlink and falls back to
+// the nft binary when nf_tables can't be programmed directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NftBackendKind {
+    Auto,
+    Netlink,
+    Cli,
+}
+
 pub struct NetfilterManager {
     rules: Arc<RwLock<HashMap<String, NetfilterRule>>>,
+    // Kernel-assigned handles of the installed rules, by rule id
+    handles: Arc<RwLock<HashMap<String, u64>>>,
     tables: Arc<RwLock<HashMap<String, NftTable>>>,
     sets: Arc<RwLock<HashMap<String, NftSet>>>,
+    bans: Arc<RwLock<HashMap<IpAddr, TempBan>>>,
     enabled: bool,
+    backend: NftBackendKind,
+    // Set once initialized with the netlink backend
+    netlink: Option<NftNetlink>,
+}
+
+// Differences between the rules we track and what the kernel has installed
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileReport {
+    // Tracked rules that were no longer installed and have been added back
+    pub reinstalled: Vec<String>,
+    // Tracked rules that were missing and could not be added back
+    pub failed: Vec<String>,
+    // Installed rules in our chains that we did not add, as (chain, handle)
+    pub unknown: Vec<(String, u64)>,
+    // Whether the unknown rules were deleted
+    pub unknown_removed: bool,
+}
+
+impl ReconcileReport {
+    pub fn in_sync(&self) -> bool {
+        self.reinstalled.is_empty() && self.failed.is_empty() && self.unknown.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct InstalledRule {
+    table: String,
+    chain: String,
+    handle: u64,
+    // Packets counted by the rule's counter
+    packets: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -140,21 +274,103 @@ struct NftSet {
     table: String,
     set_type: String,
     elements: Vec<String>,
+    timeout: bool,
+    default_ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BlockSet {
+    V4,
+    V6,
+}
+
+impl BlockSet {
+    fn name(self) -> &'static str {
+        match self {
+            BlockSet::V4 => BLOCK_SET_V4,
+            BlockSet::V6 => BLOCK_SET_V6,
+        }
+    }
+    
+    fn set_type(self) -> &'static str {
+        match self {
+            BlockSet::V4 => "ipv4_addr",
+            BlockSet::V6 => "ipv6_addr",
+        }
+    }
+}
+
+// An address in the block sets, added through `ban_ip`
+#[derive(Debug, Clone, Serialize)]
+pub struct TempBan {
+    pub ip: IpAddr,
+    pub reason: String,
+    pub banned_at: DateTime<Utc>,
+    // None when the ban lasts until it is lifted
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BulkLoadSummary {
+    pub loaded_v4: usize,
+    pub loaded_v6: usize,
+    pub invalid: usize,
 }
 
 impl NetfilterManager {
     pub fn new() -> Result<Self> {
         let manager = Self {
             rules: Arc::new(RwLock::new(HashMap::new())),
+            handles: Arc::new(RwLock::new(HashMap::new())),
             tables: Arc::new(RwLock::new(HashMap::new())),
             sets: Arc::new(RwLock::new(HashMap::new())),
+            bans: Arc::new(RwLock::new(HashMap::new())),
             enabled: false,
+            backend: NftBackendKind::Auto,
+            netlink: None,
         };
         
         Ok(manager)
     }
     
+    // Takes effect on the next `initialize`
+    pub fn set_backend(&mut self, backend: NftBackendKind) {
+        self.backend = backend;
+    }
+    
+    pub fn active_backend(&self) -> Option<NftBackendKind> {
+        match (self.enabled, &self.netlink) {
+            (false, _) => None,
+            (true, Some(_)) => Some(NftBackendKind::Netlink),
+            (true, None) => Some(NftBackendKind::Cli),
+        }
+    }
+    
     pub fn initialize(&mut self) -> Result<()> {
+        if self.backend != NftBackendKind::Cli {
+            match NftNetlink::open() {
+                Ok(netlink) => {
+                    self.netlink = Some(netlink);
+                    match self.create_fluxdefense_table() {
+                        Ok(()) => {
+                            self.enabled = true;
+                            info!("Netfilter manager initialized (netlink backend)");
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            self.netlink = None;
+                            if self.backend == NftBackendKind::Netlink {
+                                return Err(e);
+                            }
+                            warn!("Netlink nftables backend unavailable, falling back to nft: {}", e);
+                        }
+                    }
+                }
+                Err(e) if self.backend == NftBackendKind::Netlink => return Err(e),
+                Err(e) => warn!("Netlink nftables backend unavailable, falling back to nft: {}", e),
+            }
+        }
+        
         // Check if nftables is available
         if !self.check_nftables_available()? {
             return Err(anyhow!("nftables is not available on this system"));
@@ -198,30 +414,49 @@ impl NetfilterManager {
     }
     
     fn create_fluxdefense_table(&mut self) -> Result<()> {
-        // Create the main FluxDefense table
-        let table_cmd = "add table inet fluxdefense";
-        self.execute_nft_command(table_cmd)?;
-        
-        // Create input chain for incoming packets
-        let input_chain = r#"add chain inet fluxdefense input { 
-            type filter hook input priority 0; 
-            policy accept; 
-        }"#;
-        self.execute_nft_command(input_chain)?;
-        
-        // Create output chain for outgoing packets
-        let output_chain = r#"add chain inet fluxdefense output { 
-            type filter hook output priority 0; 
-            policy accept; 
-        }"#;
-        self.execute_nft_command(output_chain)?;
-        
-        // Create forward chain
-        let forward_chain = r#"add chain inet fluxdefense forward { 
-            type filter hook forward priority 0; 
-            policy accept; 
-        }"#;
-        self.execute_nft_command(forward_chain)?;
+        if let Some(netlink) = &self.netlink {
+            // Table and chains appear together or not at all
+            let mut batch = NftBatch::new(NFPROTO_INET);
+            batch.add_table("fluxdefense");
+            for (chain, hooknum) in [
+                ("input", nft_netlink::NF_INET_LOCAL_IN),
+                ("output", nft_netlink::NF_INET_LOCAL_OUT),
+                ("forward", nft_netlink::NF_INET_FORWARD),
+            ] {
+                batch.add_chain("fluxdefense", chain, Some(&ChainHook {
+                    chain_type: "filter",
+                    hooknum,
+                    priority: 0,
+                    policy: Verdict::Accept,
+                }));
+            }
+            netlink.commit(&batch)?;
+        } else {
+            // Create the main FluxDefense table
+            let table_cmd = "add table inet fluxdefense";
+            self.execute_nft_command(table_cmd)?;
+            
+            // Create input chain for incoming packets
+            let input_chain = r#"add chain inet fluxdefense input { 
+                type filter hook input priority 0; 
+                policy accept; 
+            }"#;
+            self.execute_nft_command(input_chain)?;
+            
+            // Create output chain for outgoing packets
+            let output_chain = r#"add chain inet fluxdefense output { 
+                type filter hook output priority 0; 
+                policy accept; 
+            }"#;
+            self.execute_nft_command(output_chain)?;
+            
+            // Create forward chain
+            let forward_chain = r#"add chain inet fluxdefense forward { 
+                type filter hook forward priority 0; 
+                policy accept; 
+            }"#;
+            self.execute_nft_command(forward_chain)?;
+        }
         
         // Store table info
         let mut tables = self.tables.write()
@@ -261,55 +496,128 @@ impl NetfilterManager {
     }
     
     fn execute_nft_command(&self, command: &str) -> Result<String> {
-        debug!("Executing nft command: {}", command);
-        
-        let output = Command::new("nft")
-            .arg("-f")
-            .arg("-")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .and_then(|mut child| {
-                if let Some(mut stdin) = child.stdin.take() {
-                    stdin.write_all(command.as_bytes())?;
+        run_nft(command)
+    }
+    
+    // Steers this host's outbound IPv4 DNS over UDP to a local resolver on
+    // `to_port`. Packets carrying `bypass_mark` (the resolver's own upstream
+    // queries) pass untouched. The rules stay until the guard is dropped.
+    pub fn redirect_dns(&self, to_port: u16, bypass_mark: u32) -> Result<DnsRedirect> {
+        if self.backend != NftBackendKind::Cli {
+            let opened;
+            let netlink = match &self.netlink {
+                Some(netlink) => Some(netlink),
+                None => {
+                    opened = NftNetlink::open();
+                    opened.as_ref().ok()
+                }
+            };
+            if let Some(netlink) = netlink {
+                match netlink.commit(&dns_redirect_batch(to_port, bypass_mark)) {
+                    Ok(_) => {
+                        info!("Redirecting outbound DNS to local port {}", to_port);
+                        return Ok(DnsRedirect { to_port, active: true, native: true });
+                    }
+                    Err(e) if self.backend == NftBackendKind::Netlink => return Err(e),
+                    Err(e) => warn!("Netlink DNS redirect failed, falling back to nft: {}", e),
                 }
-                child.wait_with_output()
-            })?;
+            }
+        }
         
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            Err(anyhow!("nft command failed: {}", error))
+        if !self.check_nftables_available()? {
+            return Err(anyhow!("nftables is not available on this system"));
         }
+        if !self.check_permissions()? {
+            return Err(anyhow!("Insufficient permissions for netfilter operations (need CAP_NET_ADMIN)"));
+        }
+        
+        run_nft(&dns_redirect_script(to_port, bypass_mark))?;
+        info!("Redirecting outbound DNS to local port {}", to_port);
+        Ok(DnsRedirect { to_port, active: true, native: false })
     }
     
     pub fn add_rule(&mut self, rule: NetfilterRule) -> Result<()> {
+        self.add_rules(vec![rule])
+    }
+    
+    // Installs all the rules in one transaction: either every rule is added
+    // or none is
+    pub fn add_rules(&mut self, rules: Vec<NetfilterRule>) -> Result<()> {
         if !self.enabled {
             return Err(anyhow!("Netfilter manager is not initialized"));
         }
         
-        // Generate nftables command from rule
-        let nft_cmd = self.generate_nft_rule_command(&rule)?;
+        // Re-adding an id replaces the installed rule instead of duplicating it
+        let replaced: Vec<String> = {
+            let handles = self.handles.read()
+                .map_err(|_| anyhow!("Failed to acquire handles read lock"))?;
+            rules.iter().filter(|rule| handles.contains_key(&rule.id)).map(|rule| rule.id.clone()).collect()
+        };
+        for id in replaced {
+            self.remove_rule(&id)?;
+        }
         
-        // Execute the command
-        self.execute_nft_command(&nft_cmd)?;
+        let handles = self.install_rules(&rules)?;
         
-        // Store the rule
-        let mut rules = self.rules.write()
+        // Store the rules
+        let mut tracked = self.rules.write()
             .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
-        rules.insert(rule.id.clone(), rule.clone());
-        
-        info!("Added netfilter rule: {}", rule.id);
+        for (rule, handle) in rules.into_iter().zip(handles) {
+            info!("Added netfilter rule: {} (handle {})", rule.id, handle);
+            tracked.insert(rule.id.clone(), rule);
+        }
         Ok(())
     }
     
+    fn install_rule(&self, rule: &NetfilterRule) -> Result<u64> {
+        Ok(self.install_rules(std::slice::from_ref(rule))?[0])
+    }
+    
+    // Adds the rules atomically and records the handles the kernel assigned.
+    // Rules the netlink backend can't express go through nft instead.
+    fn install_rules(&self, rules: &[NetfilterRule]) -> Result<Vec<u64>> {
+        let compiled: Option<Vec<Vec<Expr>>> = rules.iter().map(|rule| compile_rule(&rule.installed())).collect();
+        let handles = match (&self.netlink, compiled) {
+            (Some(netlink), Some(compiled)) => {
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                for (rule, exprs) in rules.iter().zip(&compiled) {
+                    batch.add_rule(&rule.table, &rule.chain, exprs, Some(&rule.comment));
+                }
+                netlink.commit(&batch)?
+            }
+            _ => {
+                // Generate nftables commands from the rules
+                let script: String = rules.iter()
+                    .map(|rule| self.generate_nft_rule_command(rule).map(|cmd| cmd + "\n"))
+                    .collect::<Result<_>>()?;
+                
+                // Execute the commands
+                let output = run_nft_with(&["--echo", "--handle"], &script)?;
+                let handles = parse_echoed_handles(&output);
+                if handles.len() != rules.len() {
+                    return Err(anyhow!("nft reported {} handles for {} rules", handles.len(), rules.len()));
+                }
+                handles
+            }
+        };
+        
+        let mut tracked = self.handles.write()
+            .map_err(|_| anyhow!("Failed to acquire handles write lock"))?;
+        for (rule, handle) in rules.iter().zip(&handles) {
+            tracked.insert(rule.id.clone(), *handle);
+        }
+        Ok(handles)
+    }
+    
+    pub fn rule_handle(&self, rule_id: &str) -> Option<u64> {
+        self.handles.read().ok()?.get(rule_id).copied()
+    }
+    
     fn generate_nft_rule_command(&self, rule: &NetfilterRule) -> Result<String> {
         let mut cmd = format!("add rule inet {} {} ", rule.table, rule.chain);
         
         // Add rule expression
-        let expr = self.generate_rule_expression(&rule.rule)?;
+        let expr = self.generate_rule_expression(&rule.installed())?;
         cmd.push_str(&expr);
         
         // Add comment if provided
@@ -360,7 +668,8 @@ impl NetfilterManager {
                 Ok(expr)
             }
             
-            NftRule::IpMatch { direction, addr, action } => {
+            NftRule::IpMatch { direction, addr, action } | NftRule::Ip6Match { direction, addr, action } => {
+                let family = if matches!(rule, NftRule::Ip6Match { .. }) { "ip6" } else { "ip" };
                 let dir = match direction {
                     Direction::Source => "saddr",
                     Direction::Destination => "daddr",
@@ -374,7 +683,7 @@ impl NetfilterManager {
                 };
                 
                 let action_expr = self.generate_rule_expression(action)?;
-                Ok(format!("ip {} {} {}", dir, addr_expr, action_expr))
+                Ok(format!("{} {} {} {}", family, dir, addr_expr, action_expr))
             }
             
             NftRule::RateLimit { rate, per, burst, action } => {
@@ -416,11 +725,16 @@ impl NetfilterManager {
                 Ok(expr)
             }
             
+            NftRule::Counter => Ok("counter".to_string()),
+            
             NftRule::Compound(rules) => {
                 let exprs: Result<Vec<String>> = rules.iter()
                     .map(|r| self.generate_rule_expression(r))
                     .collect();
-                Ok(exprs?.join(" "))
+                // Rules stripped of their verdict leave empty parts behind
+                let exprs = exprs?;
+                let parts: Vec<&str> = exprs.iter().map(|expr| expr.trim()).filter(|expr| !expr.is_empty()).collect();
+                Ok(parts.join(" "))
             }
         }
     }
@@ -434,51 +748,521 @@ impl NetfilterManager {
     }
     
     pub fn remove_rule(&mut self, rule_id: &str) -> Result<()> {
-        let mut rules = self.rules.write()
-            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+        let Some(rule) = self.rules.read()
+            .map_err(|_| anyhow!("Failed to acquire rules read lock"))?
+            .get(rule_id)
+            .cloned() else {
+            return Ok(());
+        };
+        let handle = self.handles.read()
+            .map_err(|_| anyhow!("Failed to acquire handles read lock"))?
+            .get(rule_id)
+            .copied();
         
-        if let Some(rule) = rules.remove(rule_id) {
-            // In production, we would need to find and delete the specific rule
-            // This is complex with nftables, so we might need to track rule handles
-            warn!("Rule removal from nftables not fully implemented");
-            info!("Removed rule from tracking: {}", rule_id);
+        if let Some(handle) = handle {
+            let result = match &self.netlink {
+                Some(netlink) => {
+                    let mut batch = NftBatch::new(NFPROTO_INET);
+                    batch.delete_rule(&rule.table, &rule.chain, handle);
+                    netlink.commit(&batch).map(|_| ())
+                }
+                None => {
+                    let cmd = format!("delete rule inet {} {} handle {}", rule.table, rule.chain, handle);
+                    self.execute_nft_command(&cmd).map(|_| ())
+                }
+            };
+            match result {
+                Ok(_) => {}
+                // Already gone, e.g. flushed by someone else
+                Err(e) if e.to_string().contains("No such file or directory") => {
+                    warn!("Rule {} (handle {}) was no longer installed", rule_id, handle);
+                }
+                Err(e) => return Err(e),
+            }
+        } else {
+            warn!("No handle recorded for rule {}, removing it from tracking only", rule_id);
         }
         
+        if let Ok(mut handles) = self.handles.write() {
+            handles.remove(rule_id);
+        }
+        let mut rules = self.rules.write()
+            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+        rules.remove(rule_id);
+        info!("Removed netfilter rule: {}", rule_id);
+        
         Ok(())
     }
     
-    pub fn create_ip_set(&mut self, name: &str, set_type: &str) -> Result<()> {
-        let cmd = format!("add set inet fluxdefense {} {{ type {}; }}", name, set_type);
-        self.execute_nft_command(&cmd)?;
+    // Compares tracked rules with what is installed. Missing rules are added
+    // back; rules in our chains that we never added are reported and, with
+    // `remove_unknown`, deleted.
+    pub fn reconcile(&mut self, remove_unknown: bool) -> Result<ReconcileReport> {
+        if !self.enabled {
+            return Err(anyhow!("Netfilter manager is not initialized"));
+        }
         
-        let mut sets = self.sets.write()
-            .map_err(|_| anyhow!("Failed to acquire sets write lock"))?;
+        let tracked: Vec<NetfilterRule> = self.rules.read()
+            .map_err(|_| anyhow!("Failed to acquire rules read lock"))?
+            .values()
+            .cloned()
+            .collect();
+        let handles = self.handles.read()
+            .map_err(|_| anyhow!("Failed to acquire handles read lock"))?
+            .clone();
+        
+        let mut table_names: Vec<String> = tracked.iter().map(|rule| rule.table.clone()).collect();
+        table_names.push("fluxdefense".to_string());
+        table_names.sort();
+        table_names.dedup();
+        
+        let mut installed = Vec::new();
+        for table in &table_names {
+            match self.list_installed(table) {
+                Ok(rules) => installed.extend(rules),
+                Err(e) if table == "fluxdefense" => {
+                    // The whole table was deleted; put the chains back first
+                    warn!("FluxDefense table missing ({}), recreating it", e);
+                    self.create_fluxdefense_table()?;
+                    self.restore_sets();
+                }
+                Err(e) => warn!("Failed to list table {}: {}", table, e),
+            }
+        }
+        
+        let mut report = ReconcileReport::default();
+        let mut sorted = tracked;
+        sorted.sort_by_key(|rule| rule.priority);
+        for rule in &sorted {
+            let present = handles.get(&rule.id).is_some_and(|handle| installed.iter().any(|installed| {
+                installed.table == rule.table && installed.chain == rule.chain && installed.handle == *handle
+            }));
+            if present {
+                continue;
+            }
+            match self.install_rule(rule) {
+                Ok(_) => report.reinstalled.push(rule.id.clone()),
+                Err(e) => {
+                    warn!("Failed to reinstall rule {}: {}", rule.id, e);
+                    report.failed.push(rule.id.clone());
+                }
+            }
+        }
+        
+        let known: Vec<(&String, u64)> = sorted.iter()
+            .filter_map(|rule| self.rule_handle(&rule.id).map(|handle| (&rule.table, handle)))
+            .collect();
+        let unknown: Vec<&InstalledRule> = installed.iter()
+            .filter(|rule| !known.contains(&(&rule.table, rule.handle)))
+            .collect();
+        report.unknown = unknown.iter().map(|rule| (rule.chain.clone(), rule.handle)).collect();
+        
+        if remove_unknown && !unknown.is_empty() {
+            if let Some(netlink) = &self.netlink {
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                for rule in &unknown {
+                    batch.delete_rule(&rule.table, &rule.chain, rule.handle);
+                }
+                netlink.commit(&batch)?;
+            } else {
+                let script: String = unknown.iter()
+                    .map(|rule| format!("delete rule inet {} {} handle {}\n", rule.table, rule.chain, rule.handle))
+                    .collect();
+                self.execute_nft_command(&script)?;
+            }
+            report.unknown_removed = true;
+        }
         
-        sets.insert(name.to_string(), NftSet {
+        if report.in_sync() {
+            debug!("Netfilter rules in sync ({} tracked)", sorted.len());
+        } else {
+            warn!(
+                "Netfilter drift: {} reinstalled, {} failed, {} unknown",
+                report.reinstalled.len(), report.failed.len(), report.unknown.len()
+            );
+        }
+        Ok(report)
+    }
+    
+    fn list_installed(&self, table: &str) -> Result<Vec<InstalledRule>> {
+        match &self.netlink {
+            Some(netlink) => match netlink.table_exists(NFPROTO_INET, table) {
+                Ok(true) => netlink.list_rules(NFPROTO_INET, table).map(|rules| {
+                    rules.into_iter()
+                        .map(|rule| InstalledRule { table: table.to_string(), chain: rule.chain, handle: rule.handle, packets: rule.packets })
+                        .collect()
+                }),
+                Ok(false) => Err(anyhow!("table {} does not exist", table)),
+                Err(e) => Err(e),
+            },
+            None => run_nft_with(&["--json", "--handle"], &format!("list table inet {}", table))
+                .and_then(|listing| parse_installed_rules(&listing, table)),
+        }
+    }
+    
+    // Every tracked rule with the packets its counter has seen since it
+    // was installed; None when the rule is not installed
+    pub fn rule_counters(&self) -> Result<Vec<(NetfilterRule, Option<u64>)>> {
+        let tracked: Vec<NetfilterRule> = self.rules.read()
+            .map_err(|_| anyhow!("Failed to acquire rules read lock"))?
+            .values()
+            .cloned()
+            .collect();
+        let mut table_names: Vec<&String> = tracked.iter().map(|rule| &rule.table).collect();
+        table_names.sort();
+        table_names.dedup();
+        
+        let mut installed = Vec::new();
+        if self.enabled {
+            for table in table_names {
+                match self.list_installed(table) {
+                    Ok(rules) => installed.extend(rules),
+                    Err(e) => debug!("No rule counters for table {}: {}", table, e),
+                }
+            }
+        }
+        Ok(tracked.into_iter()
+            .map(|rule| {
+                let handle = self.rule_handle(&rule.id);
+                let packets = installed.iter()
+                    .find(|installed| installed.table == rule.table && Some(installed.handle) == handle)
+                    .and_then(|installed| installed.packets);
+                (rule, packets)
+            })
+            .collect())
+    }
+    
+    pub fn create_ip_set(&mut self, name: &str, set_type: &str) -> Result<()> {
+        self.create_set(NftSet {
             name: name.to_string(),
             table: "fluxdefense".to_string(),
             set_type: set_type.to_string(),
             elements: Vec::new(),
-        });
+            timeout: false,
+            default_ttl: None,
+        })
+    }
+    
+    // Elements of a timeout set can expire on their own; `default_ttl`
+    // applies to elements added without one
+    pub fn create_timeout_set(&mut self, name: &str, set_type: &str, default_ttl: Option<Duration>) -> Result<()> {
+        self.create_set(NftSet {
+            name: name.to_string(),
+            table: "fluxdefense".to_string(),
+            set_type: set_type.to_string(),
+            elements: Vec::new(),
+            timeout: true,
+            default_ttl,
+        })
+    }
+    
+    fn create_set(&mut self, set: NftSet) -> Result<()> {
+        self.install_set(&set)?;
+        
+        let mut sets = self.sets.write()
+            .map_err(|_| anyhow!("Failed to acquire sets write lock"))?;
         
-        info!("Created IP set: {}", name);
+        info!("Created IP set: {}", set.name);
+        sets.insert(set.name.clone(), set);
+        Ok(())
+    }
+    
+    fn install_set(&self, set: &NftSet) -> Result<()> {
+        match (&self.netlink, set_key_type(&set.set_type)) {
+            (Some(netlink), Some((key_type, key_len))) => {
+                let flags = if set.timeout { nft_netlink::NFT_SET_TIMEOUT } else { 0 };
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                batch.add_set(&set.table, &set.name, key_type, key_len, flags, set.default_ttl);
+                netlink.commit(&batch)?;
+            }
+            _ => {
+                let flags = match (set.timeout, set.default_ttl) {
+                    (false, _) => String::new(),
+                    (true, None) => " flags timeout;".to_string(),
+                    (true, Some(ttl)) => format!(" flags timeout; timeout {}s;", ttl.as_secs().max(1)),
+                };
+                let cmd = format!("add set inet {} {} {{ type {};{} }}", set.table, set.name, set.set_type, flags);
+                self.execute_nft_command(&cmd)?;
+            }
+        }
         Ok(())
     }
     
     pub fn add_to_set(&mut self, set_name: &str, element: &str) -> Result<()> {
-        let cmd = format!("add element inet fluxdefense {} {{ {} }}", set_name, element);
-        self.execute_nft_command(&cmd)?;
+        self.add_to_set_with_ttl(set_name, &[element.to_string()], None)
+    }
+    
+    // Adds all elements in one transaction. Elements with a TTL are not
+    // tracked since the kernel expires them on its own.
+    pub fn add_to_set_with_ttl(&mut self, set_name: &str, elements: &[String], ttl: Option<Duration>) -> Result<()> {
+        self.install_elements(set_name, elements, ttl)?;
+        
+        if ttl.is_none() {
+            let mut sets = self.sets.write()
+                .map_err(|_| anyhow!("Failed to acquire sets write lock"))?;
+            
+            if let Some(set) = sets.get_mut(set_name) {
+                set.elements.extend(elements.iter().cloned());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn install_elements(&self, set_name: &str, elements: &[String], ttl: Option<Duration>) -> Result<()> {
+        if elements.is_empty() {
+            return Ok(());
+        }
+        let set_type = self.sets.read()
+            .map_err(|_| anyhow!("Failed to acquire sets read lock"))?
+            .get(set_name)
+            .map(|set| set.set_type.clone());
+        let keys: Option<Vec<Vec<u8>>> = set_type.as_deref()
+            .and_then(|set_type| elements.iter().map(|element| encode_set_element(set_type, element)).collect());
+        match (&self.netlink, keys) {
+            (Some(netlink), Some(keys)) => {
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                batch.add_set_elements("fluxdefense", set_name, &keys, ttl);
+                netlink.commit(&batch)?;
+            }
+            _ => {
+                let timeout = ttl.map(|ttl| format!(" timeout {}s", ttl.as_secs().max(1))).unwrap_or_default();
+                let script: String = elements.chunks(MAX_CLI_ELEMENTS)
+                    .map(|chunk| {
+                        let chunk: Vec<String> = chunk.iter().map(|element| format!("{}{}", element, timeout)).collect();
+                        format!("add element inet fluxdefense {} {{ {} }}\n", set_name, chunk.join(", "))
+                    })
+                    .collect();
+                self.execute_nft_command(&script)?;
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn remove_from_set(&mut self, set_name: &str, element: &str) -> Result<()> {
+        let set_type = self.sets.read()
+            .map_err(|_| anyhow!("Failed to acquire sets read lock"))?
+            .get(set_name)
+            .map(|set| set.set_type.clone());
+        let key = set_type.and_then(|set_type| encode_set_element(&set_type, element));
+        match (&self.netlink, key) {
+            (Some(netlink), Some(key)) => {
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                batch.delete_set_elements("fluxdefense", set_name, &[key]);
+                netlink.commit(&batch)?;
+            }
+            _ => {
+                let cmd = format!("delete element inet fluxdefense {} {{ {} }}", set_name, element);
+                self.execute_nft_command(&cmd)?;
+            }
+        }
         
         let mut sets = self.sets.write()
             .map_err(|_| anyhow!("Failed to acquire sets write lock"))?;
         
         if let Some(set) = sets.get_mut(set_name) {
-            set.elements.push(element.to_string());
+            set.elements.retain(|existing| existing != element);
         }
         
         Ok(())
     }
     
+    // Creates the IPv4/IPv6 block sets and the rules dropping traffic to and
+    // from their members. Bans and bulk loads only touch set elements, so
+    // blocking thousands of addresses costs four rules.
+    pub fn enable_block_sets(&mut self) -> Result<()> {
+        let exists = self.sets.read()
+            .map_err(|_| anyhow!("Failed to acquire sets read lock"))?
+            .contains_key(BLOCK_SET_V4);
+        if exists {
+            return Ok(());
+        }
+        
+        self.create_timeout_set(BLOCK_SET_V4, "ipv4_addr", None)?;
+        self.create_timeout_set(BLOCK_SET_V6, "ipv6_addr", None)?;
+        
+        let mut rules = Vec::new();
+        for (set, ipv6) in [(BLOCK_SET_V4, false), (BLOCK_SET_V6, true)] {
+            for (chain, direction) in [("input", Direction::Source), ("output", Direction::Destination)] {
+                let addr = IpMatch::Set(set.to_string());
+                let action = Box::new(NftRule::Drop);
+                rules.push(NetfilterRule {
+                    id: format!("{}_{}", set, chain),
+                    table: "fluxdefense".to_string(),
+                    chain: chain.to_string(),
+                    // Ahead of the established/related accept
+                    priority: 5,
+                    rule: if ipv6 {
+                        NftRule::Ip6Match { direction, addr, action }
+                    } else {
+                        NftRule::IpMatch { direction, addr, action }
+                    },
+                    comment: format!("Drop members of {}", set),
+                    shadow: false,
+                });
+            }
+        }
+        self.add_rules(rules)
+    }
+    
+    // Blocks `ip` until the TTL runs out, or until unbanned when there is
+    // none. Banning an already banned address restarts its timer.
+    pub fn ban_ip(&mut self, ip: IpAddr, ttl: Option<Duration>, reason: &str) -> Result<TempBan> {
+        let set = self.block_set_for(ip)?;
+        let element = ip.to_string();
+        let already_banned = self.list_bans().iter().any(|ban| ban.ip == ip);
+        
+        // The kernel keeps the old timeout when an existing element is added
+        // again, so a re-ban replaces the element. It may also have expired
+        // just before the delete, leaving a plain add.
+        let replaced = already_banned && self.replace_element(set, &element, ttl).is_ok();
+        if !replaced {
+            self.add_to_set_with_ttl(set.name(), &[element], ttl)?;
+        }
+        
+        let banned_at = Utc::now();
+        let ban = TempBan {
+            ip,
+            reason: reason.to_string(),
+            banned_at,
+            expires_at: ttl.and_then(|ttl| chrono::Duration::from_std(ttl).ok()).map(|ttl| banned_at + ttl),
+        };
+        self.bans.write()
+            .map_err(|_| anyhow!("Failed to acquire bans write lock"))?
+            .insert(ip, ban.clone());
+        
+        info!("Banned {} ({}){}", ip, reason, ttl.map(|ttl| format!(" for {}s", ttl.as_secs())).unwrap_or_default());
+        Ok(ban)
+    }
+    
+    // Deletes and re-adds an element in one transaction
+    fn replace_element(&self, set: BlockSet, element: &str, ttl: Option<Duration>) -> Result<()> {
+        match &self.netlink {
+            Some(netlink) => {
+                let key = encode_set_element(set.set_type(), element)
+                    .ok_or_else(|| anyhow!("Invalid address {}", element))?;
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                batch.delete_set_elements("fluxdefense", set.name(), std::slice::from_ref(&key));
+                batch.add_set_elements("fluxdefense", set.name(), &[key], ttl);
+                netlink.commit(&batch)?;
+            }
+            None => {
+                let timeout = ttl.map(|ttl| format!(" timeout {}s", ttl.as_secs().max(1))).unwrap_or_default();
+                self.execute_nft_command(&format!(
+                    "delete element inet fluxdefense {set} {{ {element} }}\nadd element inet fluxdefense {set} {{ {element}{timeout} }}\n",
+                    set = set.name(), element = element, timeout = timeout,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+    
+    // Returns whether the address was banned
+    pub fn unban_ip(&mut self, ip: IpAddr) -> Result<bool> {
+        let set = self.block_set_for(ip)?;
+        let was_banned = self.list_bans().iter().any(|ban| ban.ip == ip);
+        self.bans.write()
+            .map_err(|_| anyhow!("Failed to acquire bans write lock"))?
+            .remove(&ip);
+        
+        match self.remove_from_set(set.name(), &ip.to_string()) {
+            Ok(()) => {
+                info!("Unbanned {}", ip);
+                Ok(true)
+            }
+            // Already expired, or never in the set
+            Err(e) if e.to_string().contains("No such file or directory") => Ok(was_banned),
+            Err(e) => Err(e),
+        }
+    }
+    
+    // Active bans, oldest first; expired ones are dropped
+    pub fn list_bans(&self) -> Vec<TempBan> {
+        let now = Utc::now();
+        let Ok(mut bans) = self.bans.write() else { return Vec::new() };
+        bans.retain(|_, ban| ban.expires_at.is_none_or(|expires_at| expires_at > now));
+        
+        let mut active: Vec<TempBan> = bans.values().cloned().collect();
+        active.sort_by_key(|ban| ban.banned_at);
+        active
+    }
+    
+    // Bulk-loads addresses, e.g. from a threat intel feed, into the block
+    // sets. Entries that are not a single IPv4 or IPv6 address are skipped.
+    pub fn block_ips(&mut self, entries: &[String], ttl: Option<Duration>) -> Result<BulkLoadSummary> {
+        self.block_set_for(IpAddr::from([0, 0, 0, 0]))?;
+        
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        let mut summary = BulkLoadSummary::default();
+        for entry in entries {
+            match entry.trim().parse::<IpAddr>() {
+                Ok(IpAddr::V4(ip)) => v4.push(ip.to_string()),
+                Ok(IpAddr::V6(ip)) => v6.push(ip.to_string()),
+                Err(_) => summary.invalid += 1,
+            }
+        }
+        v4.sort();
+        v4.dedup();
+        v6.sort();
+        v6.dedup();
+        
+        self.add_to_set_with_ttl(BLOCK_SET_V4, &v4, ttl)?;
+        self.add_to_set_with_ttl(BLOCK_SET_V6, &v6, ttl)?;
+        summary.loaded_v4 = v4.len();
+        summary.loaded_v6 = v6.len();
+        
+        info!("Loaded {} IPv4 and {} IPv6 addresses into the block sets ({} invalid)",
+              summary.loaded_v4, summary.loaded_v6, summary.invalid);
+        Ok(summary)
+    }
+    
+    pub fn load_blocklist_file(&mut self, path: &Path, ttl: Option<Duration>) -> Result<BulkLoadSummary> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read blocklist {}: {}", path.display(), e))?;
+        self.block_ips(&parse_blocklist(&content), ttl)
+    }
+    
+    fn block_set_for(&self, ip: IpAddr) -> Result<BlockSet> {
+        let sets = self.sets.read()
+            .map_err(|_| anyhow!("Failed to acquire sets read lock"))?;
+        if !sets.contains_key(BLOCK_SET_V4) {
+            return Err(anyhow!("Block sets are not enabled"));
+        }
+        Ok(if ip.is_ipv4() { BlockSet::V4 } else { BlockSet::V6 })
+    }
+    
+    // Puts the tracked sets, their elements and the active bans back after
+    // the table was deleted from under us
+    fn restore_sets(&self) {
+        let sets: Vec<NftSet> = match self.sets.read() {
+            Ok(sets) => sets.values().cloned().collect(),
+            Err(_) => return,
+        };
+        for set in &sets {
+            let restored = self.install_set(set)
+                .and_then(|_| self.install_elements(&set.name, &set.elements, None));
+            if let Err(e) = restored {
+                warn!("Failed to restore set {}: {}", set.name, e);
+            }
+        }
+        
+        let now = Utc::now();
+        for ban in self.list_bans() {
+            let ttl = match ban.expires_at {
+                Some(expires_at) => match (expires_at - now).to_std() {
+                    Ok(ttl) => Some(ttl),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let Ok(set) = self.block_set_for(ban.ip) else { break };
+            if let Err(e) = self.install_elements(set.name(), &[ban.ip.to_string()], ttl) {
+                warn!("Failed to restore ban on {}: {}", ban.ip, e);
+            }
+        }
+    }
+    
     // Helper methods for common rule patterns
     pub fn block_ip(&mut self, ip: &str, comment: &str) -> Result<()> {
         let rule = NetfilterRule {
@@ -492,6 +1276,7 @@ impl NetfilterManager {
                 action: Box::new(NftRule::Drop),
             },
             comment: comment.to_string(),
+            shadow: false,
         };
         
         self.add_rule(rule)
@@ -515,6 +1300,7 @@ impl NetfilterManager {
                 }),
             },
             comment: format!("Rate limit port {}", port),
+            shadow: false,
         };
         
         self.add_rule(rule)
@@ -533,6 +1319,7 @@ impl NetfilterManager {
                 action: Box::new(NftRule::Drop),
             },
             comment: format!("Block {} ports {}-{}", proto, start, end),
+            shadow: false,
         };
         
         self.add_rule(rule)
@@ -548,6 +1335,7 @@ impl NetfilterManager {
                 state: vec![ConnState::Established, ConnState::Related],
             },
             comment: "Allow established connections".to_string(),
+            shadow: false,
         };
         
         self.add_rule(rule)
@@ -570,6 +1358,7 @@ impl NetfilterManager {
                 },
             ]),
             comment: "Log and drop invalid connections".to_string(),
+            shadow: false,
         };
         
         self.add_rule(rule)
@@ -578,14 +1367,31 @@ impl NetfilterManager {
     pub fn cleanup(&mut self) -> Result<()> {
         if self.enabled {
             // Remove our table and all rules
-            let cmd = "delete table inet fluxdefense";
-            match self.execute_nft_command(cmd) {
+            let result = match &self.netlink {
+                Some(netlink) => {
+                    let mut batch = NftBatch::new(NFPROTO_INET);
+                    batch.delete_table("fluxdefense");
+                    netlink.commit(&batch).map(|_| String::new())
+                }
+                None => self.execute_nft_command("delete table inet fluxdefense"),
+            };
+            match result {
                 Ok(_) => info!("Cleaned up FluxDefense nftables rules"),
                 Err(e) => warn!("Failed to cleanup nftables: {}", e),
             }
             
             self.enabled = false;
         }
+        if let Ok(mut handles) = self.handles.write() {
+            handles.clear();
+        }
+        // Sets went away with the table
+        if let Ok(mut sets) = self.sets.write() {
+            sets.clear();
+        }
+        if let Ok(mut bans) = self.bans.write() {
+            bans.clear();
+        }
         
         Ok(())
     }
@@ -601,6 +1407,332 @@ impl Drop for NetfilterManager {
     }
 }
 
+// Active DNS redirect; dropping it removes the rules
+pub struct DnsRedirect {
+    to_port: u16,
+    active: bool,
+    // Installed over netlink rather than with nft
+    native: bool,
+}
+
+impl DnsRedirect {
+    pub fn to_port(&self) -> u16 {
+        self.to_port
+    }
+    
+    pub fn remove(&mut self) -> Result<()> {
+        if self.active {
+            if self.native {
+                let mut batch = NftBatch::new(NFPROTO_INET);
+                batch.delete_table(DNS_REDIRECT_TABLE);
+                NftNetlink::open()?.commit(&batch)?;
+            } else {
+                run_nft(&format!("delete table inet {}", DNS_REDIRECT_TABLE))?;
+            }
+            self.active = false;
+            info!("Removed DNS redirect");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DnsRedirect {
+    fn drop(&mut self) {
+        if let Err(e) = self.remove() {
+            error!("Failed to remove DNS redirect: {}", e);
+        }
+    }
+}
+
+fn dns_redirect_script(to_port: u16, bypass_mark: u32) -> String {
+    // Recreating the table in one transaction also clears rules left behind
+    // by a run that never got to clean up
+    format!(
+        "add table inet {table}\n\
+         delete table inet {table}\n\
+         add table inet {table}\n\
+         add chain inet {table} output {{ type nat hook output priority -100; }}\n\
+         add rule inet {table} output meta mark {mark:#x} return\n\
+         add rule inet {table} output meta nfproto ipv4 udp dport 53 redirect to :{port} comment \"FluxDefense DNS filter\"\n",
+        table = DNS_REDIRECT_TABLE,
+        mark = bypass_mark,
+        port = to_port,
+    )
+}
+
+fn dns_redirect_batch(to_port: u16, bypass_mark: u32) -> NftBatch {
+    let mut batch = NftBatch::new(NFPROTO_INET);
+    batch.add_table(DNS_REDIRECT_TABLE)
+        .delete_table(DNS_REDIRECT_TABLE)
+        .add_table(DNS_REDIRECT_TABLE)
+        .add_chain(DNS_REDIRECT_TABLE, "output", Some(&ChainHook {
+            chain_type: "nat",
+            hooknum: nft_netlink::NF_INET_LOCAL_OUT,
+            priority: -100,
+            policy: Verdict::Accept,
+        }))
+        .add_rule(DNS_REDIRECT_TABLE, "output", &[
+            Expr::Meta { key: nft_netlink::NFT_META_MARK, dreg: NFT_REG_1 },
+            Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: bypass_mark.to_ne_bytes().to_vec() },
+            Expr::Verdict(Verdict::Return),
+        ], None)
+        .add_rule(DNS_REDIRECT_TABLE, "output", &[
+            Expr::Meta { key: nft_netlink::NFT_META_NFPROTO, dreg: NFT_REG_1 },
+            Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: vec![NFPROTO_IPV4] },
+            Expr::Meta { key: nft_netlink::NFT_META_L4PROTO, dreg: NFT_REG_1 },
+            Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: vec![libc::IPPROTO_UDP as u8] },
+            Expr::Payload { base: nft_netlink::NFT_PAYLOAD_TRANSPORT_HEADER, offset: 2, len: 2, dreg: NFT_REG_1 },
+            Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: 53u16.to_be_bytes().to_vec() },
+            Expr::Immediate { dreg: NFT_REG_1, data: to_port.to_be_bytes().to_vec() },
+            Expr::Redirect { proto_min_reg: NFT_REG_1 },
+        ], Some("FluxDefense DNS filter"));
+    batch
+}
+
+// Translates a rule into nf_tables expressions, or None when it uses
+// something only the nft binary can express
+fn compile_rule(rule: &NftRule) -> Option<Vec<Expr>> {
+    let mut exprs = Vec::new();
+    compile_into(rule, &mut exprs)?;
+    Some(exprs)
+}
+
+fn compile_into(rule: &NftRule, exprs: &mut Vec<Expr>) -> Option<()> {
+    match rule {
+        NftRule::Accept => exprs.push(Expr::Verdict(Verdict::Accept)),
+        NftRule::Drop => exprs.push(Expr::Verdict(Verdict::Drop)),
+        NftRule::Reject { with_type: None } => exprs.push(Expr::Reject {
+            kind: nft_netlink::NFT_REJECT_ICMPX_UNREACH,
+            code: nft_netlink::NFT_REJECT_ICMPX_PORT_UNREACH,
+        }),
+        NftRule::Reject { with_type: Some(_) } => return None,
+        
+        NftRule::ConnTrack { state } => {
+            let bits = state.iter().fold(0u32, |bits, state| bits | match state {
+                ConnState::Invalid => 1,
+                ConnState::Established => 2,
+                ConnState::Related => 4,
+                ConnState::New => 8,
+            });
+            exprs.push(Expr::Ct { key: nft_netlink::NFT_CT_STATE, dreg: NFT_REG_1 });
+            exprs.push(Expr::Bitwise { sreg: NFT_REG_1, dreg: NFT_REG_1, mask: bits.to_ne_bytes().to_vec(), xor: vec![0; 4] });
+            exprs.push(Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Neq, data: vec![0; 4] });
+        }
+        
+        NftRule::Protocol { proto, sport, dport, action } => {
+            let number = match proto.as_str() {
+                "tcp" => libc::IPPROTO_TCP,
+                "udp" => libc::IPPROTO_UDP,
+                "sctp" => libc::IPPROTO_SCTP,
+                "icmp" => libc::IPPROTO_ICMP,
+                "icmpv6" => libc::IPPROTO_ICMPV6,
+                _ => return None,
+            } as u8;
+            exprs.push(Expr::Meta { key: nft_netlink::NFT_META_L4PROTO, dreg: NFT_REG_1 });
+            exprs.push(Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: vec![number] });
+            for (offset, port) in [(0, sport), (2, dport)] {
+                let Some(port) = port else { continue };
+                exprs.push(Expr::Payload { base: nft_netlink::NFT_PAYLOAD_TRANSPORT_HEADER, offset, len: 2, dreg: NFT_REG_1 });
+                exprs.push(match port {
+                    PortMatch::Single(port) => Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: port.to_be_bytes().to_vec() },
+                    PortMatch::Range(start, end) => Expr::Range {
+                        sreg: NFT_REG_1,
+                        from: start.to_be_bytes().to_vec(),
+                        to: end.to_be_bytes().to_vec(),
+                    },
+                    PortMatch::Set(set) => Expr::Lookup { set: set.clone(), sreg: NFT_REG_1 },
+                });
+            }
+            compile_into(action, exprs)?;
+        }
+        
+        NftRule::IpMatch { direction, addr, action } => {
+            compile_addr_match(false, direction, addr, exprs)?;
+            compile_into(action, exprs)?;
+        }
+        NftRule::Ip6Match { direction, addr, action } => {
+            compile_addr_match(true, direction, addr, exprs)?;
+            compile_into(action, exprs)?;
+        }
+        
+        NftRule::RateLimit { rate, per, burst, action } => {
+            let unit_secs = match per {
+                RatePer::Second => 1,
+                RatePer::Minute => 60,
+                RatePer::Hour => 3600,
+                RatePer::Day => 86400,
+            };
+            // nft's default burst
+            exprs.push(Expr::Limit { rate: *rate as u64, unit_secs, burst: burst.unwrap_or(5) });
+            compile_into(action, exprs)?;
+        }
+        
+        NftRule::Log { prefix, level, continue_rule } => {
+            let level = match level {
+                LogLevel::Emergency => 0,
+                LogLevel::Alert => 1,
+                LogLevel::Critical => 2,
+                LogLevel::Error => 3,
+                LogLevel::Warning => 4,
+                LogLevel::Notice => 5,
+                LogLevel::Info => 6,
+                LogLevel::Debug => 7,
+            };
+            exprs.push(Expr::Log { prefix: prefix.clone(), level });
+            if let Some(cont) = continue_rule {
+                compile_into(cont, exprs)?;
+            }
+        }
+        
+        NftRule::Counter => exprs.push(Expr::Counter),
+        
+        NftRule::Compound(rules) => {
+            for rule in rules {
+                compile_into(rule, exprs)?;
+            }
+        }
+    }
+    Some(())
+}
+
+// "ip saddr/daddr" or "ip6 saddr/daddr"; the rule only applies to packets
+// of that family
+fn compile_addr_match(ipv6: bool, direction: &Direction, addr: &IpMatch, exprs: &mut Vec<Expr>) -> Option<()> {
+    let (nfproto, offset, len) = match (ipv6, direction) {
+        (false, Direction::Source) => (NFPROTO_IPV4, 12, 4),
+        (false, Direction::Destination) => (NFPROTO_IPV4, 16, 4),
+        (true, Direction::Source) => (nft_netlink::NFPROTO_IPV6, 8, 16),
+        (true, Direction::Destination) => (nft_netlink::NFPROTO_IPV6, 24, 16),
+    };
+    let octets = |ip: &str| -> Option<Vec<u8>> {
+        match ip.parse::<IpAddr>().ok()? {
+            IpAddr::V4(ip) if !ipv6 => Some(ip.octets().to_vec()),
+            IpAddr::V6(ip) if ipv6 => Some(ip.octets().to_vec()),
+            _ => None,
+        }
+    };
+    
+    exprs.push(Expr::Meta { key: nft_netlink::NFT_META_NFPROTO, dreg: NFT_REG_1 });
+    exprs.push(Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: vec![nfproto] });
+    exprs.push(Expr::Payload { base: nft_netlink::NFT_PAYLOAD_NETWORK_HEADER, offset, len, dreg: NFT_REG_1 });
+    match addr {
+        IpMatch::Single(ip) => exprs.push(Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: octets(ip)? }),
+        IpMatch::Range(start, end) => exprs.push(Expr::Range { sreg: NFT_REG_1, from: octets(start)?, to: octets(end)? }),
+        IpMatch::Subnet(subnet) => {
+            let (network, prefix) = subnet.split_once('/')?;
+            let network = octets(network)?;
+            let bits = network.len() as u32 * 8;
+            let prefix: u32 = prefix.parse().ok().filter(|prefix| *prefix <= bits)?;
+            let mask: Vec<u8> = (0..network.len() as u32)
+                .map(|byte| {
+                    let set = prefix.saturating_sub(byte * 8).min(8);
+                    (0xffu16 << (8 - set)) as u8
+                })
+                .collect();
+            let network: Vec<u8> = network.iter().zip(&mask).map(|(byte, mask)| byte & mask).collect();
+            exprs.push(Expr::Bitwise { sreg: NFT_REG_1, dreg: NFT_REG_1, mask, xor: vec![0; len as usize] });
+            exprs.push(Expr::Cmp { sreg: NFT_REG_1, op: CmpOp::Eq, data: network });
+        }
+        IpMatch::Set(set) => exprs.push(Expr::Lookup { set: set.clone(), sreg: NFT_REG_1 }),
+    }
+    Some(())
+}
+
+// Key type and length for the set types the netlink backend can create
+fn set_key_type(set_type: &str) -> Option<(u32, u32)> {
+    match set_type {
+        "ipv4_addr" => Some((nft_netlink::NFT_TYPE_IPADDR, 4)),
+        "ipv6_addr" => Some((nft_netlink::NFT_TYPE_IP6ADDR, 16)),
+        "inet_service" => Some((nft_netlink::NFT_TYPE_INET_SERVICE, 2)),
+        _ => None,
+    }
+}
+
+fn encode_set_element(set_type: &str, element: &str) -> Option<Vec<u8>> {
+    let element = element.trim();
+    match set_type {
+        "ipv4_addr" => match element.parse::<IpAddr>().ok()? {
+            IpAddr::V4(ip) => Some(ip.octets().to_vec()),
+            IpAddr::V6(_) => None,
+        },
+        "ipv6_addr" => match element.parse::<IpAddr>().ok()? {
+            IpAddr::V6(ip) => Some(ip.octets().to_vec()),
+            IpAddr::V4(_) => None,
+        },
+        "inet_service" => element.parse::<u16>().ok().map(|port| port.to_be_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+// One address per line; anything after the first token, and lines
+// starting with '#' or ';', are comments
+fn parse_blocklist(content: &str) -> Vec<String> {
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+fn run_nft(command: &str) -> Result<String> {
+    run_nft_with(&[], command)
+}
+
+fn run_nft_with(flags: &[&str], command: &str) -> Result<String> {
+    debug!("Executing nft command: {}", command);
+    
+    let output = Command::new("nft")
+        .args(flags)
+        .arg("-f")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(command.as_bytes())?;
+            }
+            child.wait_with_output()
+        })?;
+    
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow!("nft command failed: {}", error))
+    }
+}
+
+// `nft --echo --handle` prints each added rule followed by "# handle N"
+fn parse_echoed_handles(output: &str) -> Vec<u64> {
+    output.lines()
+        .filter(|line| line.trim_start().starts_with("add rule"))
+        .filter_map(|line| line.rsplit_once("# handle ").and_then(|(_, handle)| handle.trim().parse().ok()))
+        .collect()
+}
+
+fn parse_installed_rules(listing: &str, table: &str) -> Result<Vec<InstalledRule>> {
+    let json: serde_json::Value = serde_json::from_str(listing)?;
+    let objects = json.get("nftables")
+        .and_then(|objects| objects.as_array())
+        .ok_or_else(|| anyhow!("Unexpected nft JSON output"))?;
+    
+    Ok(objects.iter()
+        .filter_map(|object| object.get("rule"))
+        .filter(|rule| rule.get("family").and_then(|f| f.as_str()) == Some("inet")
+            && rule.get("table").and_then(|t| t.as_str()) == Some(table))
+        .filter_map(|rule| Some(InstalledRule {
+            table: table.to_string(),
+            chain: rule.get("chain")?.as_str()?.to_string(),
+            handle: rule.get("handle")?.as_u64()?,
+            packets: rule.get("expr")?.as_array()?.iter()
+                .find_map(|expr| expr.get("counter")?.get("packets")?.as_u64()),
+        }))
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -622,5 +1754,87 @@ mod tests {
         };
         let expr = manager.generate_rule_expression(&proto_rule).unwrap();
         assert!(expr.contains("tcp dport 80 accept"));
+        
+        // In shadow mode the drop becomes a log of the match
+        let shadow = NetfilterRule {
+            id: "trial_ssh".to_string(),
+            table: "fluxdefense".to_string(),
+            chain: "input".to_string(),
+            priority: 100,
+            rule: NftRule::Protocol { proto: "tcp".to_string(), sport: None, dport: Some(PortMatch::Single(22)), action: Box::new(NftRule::Drop) },
+            comment: String::new(),
+            shadow: true,
+        };
+        let command = manager.generate_nft_rule_command(&shadow).unwrap();
+        assert!(command.contains("tcp dport 22 counter") && command.ends_with("log prefix \"flux-shadow:drop:trial_ssh \" level info"), "{}", command);
+        assert!(!command.contains(" drop"));
+        
+        // Enforced rules count their matches ahead of the verdict
+        let enforced = NetfilterRule { shadow: false, ..shadow.clone() };
+        assert!(manager.generate_nft_rule_command(&enforced).unwrap().ends_with("tcp dport 22 counter drop"));
+        assert!(compile_rule(&shadow.installed()).unwrap().iter().all(|expr| !matches!(expr, Expr::Verdict(_))));
+    }
+    
+    #[test]
+    fn test_dns_redirect_script() {
+        let script = dns_redirect_script(5353, 0x464c5558);
+        assert!(script.contains("type nat hook output"));
+        assert!(script.contains("meta mark 0x464c5558 return"));
+        assert!(script.contains("udp dport 53 redirect to :5353"));
+        // The bypass has to come before the redirect
+        assert!(script.find("return").unwrap() < script.find("redirect").unwrap());
+    }
+    
+    #[test]
+    fn test_parse_rule_handles() {
+        let echoed = "add rule inet fluxdefense input ip saddr 10.0.0.1 drop comment \"x\" # handle 12\n\
+                      add rule inet fluxdefense input ip saddr 10.0.0.2 drop # handle 13\n";
+        assert_eq!(parse_echoed_handles(echoed), vec![12, 13]);
+        assert!(parse_echoed_handles("add table inet fluxdefense\n").is_empty());
+        
+        let listing = r#"{"nftables": [
+            {"metainfo": {"version": "1.0.9", "json_schema_version": 1}},
+            {"table": {"family": "inet", "name": "fluxdefense", "handle": 3}},
+            {"chain": {"family": "inet", "table": "fluxdefense", "name": "input", "handle": 1}},
+            {"rule": {"family": "inet", "table": "fluxdefense", "chain": "input", "handle": 12, "expr": []}},
+            {"rule": {"family": "inet", "table": "fluxdefense", "chain": "output", "handle": 14, "expr": [{"counter": {"packets": 42, "bytes": 5040}}, {"drop": null}]}},
+            {"rule": {"family": "ip", "table": "fluxdefense", "chain": "input", "handle": 2, "expr": []}}
+        ]}"#;
+        let rules = parse_installed_rules(listing, "fluxdefense").unwrap();
+        assert_eq!(rules.iter().map(|rule| (rule.chain.as_str(), rule.handle, rule.packets)).collect::<Vec<_>>(),
+                   vec![("input", 12, None), ("output", 14, Some(42))]);
+    }
+    
+    #[test]
+    fn test_blocklist_and_ip6_match() {
+        let list = "# feed header\n203.0.113.7\n\n; comment\n2001:db8::1  # c2\n198.51.100.0/24\n";
+        assert_eq!(parse_blocklist(list), vec!["203.0.113.7", "2001:db8::1", "198.51.100.0/24"]);
+        
+        let manager = NetfilterManager::new().unwrap();
+        let rule = NftRule::Ip6Match {
+            direction: Direction::Source,
+            addr: IpMatch::Set(BLOCK_SET_V6.to_string()),
+            action: Box::new(NftRule::Drop),
+        };
+        assert_eq!(manager.generate_rule_expression(&rule).unwrap(), "ip6 saddr @flux_block_v6 drop");
+        
+        // A /33 keeps the top bit of the fifth byte
+        let subnet = NftRule::Ip6Match {
+            direction: Direction::Destination,
+            addr: IpMatch::Subnet("2001:db8:8000::/33".to_string()),
+            action: Box::new(NftRule::Drop),
+        };
+        let exprs = compile_rule(&subnet).unwrap();
+        let mask = exprs.iter().find_map(|expr| match expr {
+            Expr::Bitwise { mask, .. } => Some(mask.clone()),
+            _ => None,
+        }).unwrap();
+        assert_eq!(&mask[..6], &[0xff, 0xff, 0xff, 0xff, 0x80, 0x00]);
+        // IPv4 addresses don't fit an ip6 match
+        assert!(compile_rule(&NftRule::Ip6Match {
+            direction: Direction::Source,
+            addr: IpMatch::Single("10.0.0.1".to_string()),
+            action: Box::new(NftRule::Drop),
+        }).is_none());
     }
-}
\ No newline at end of file
+}
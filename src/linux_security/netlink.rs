@@ -6,6 +6,8 @@ use libc::{self, c_int, sockaddr_nl};
 use std::mem;
 use std::os::unix::io::{AsRawFd, RawFd};
 
+use crate::network::geoip::{GeoIpDatabase, GeoIpInfo};
+
 // Netlink constants
 const NETLINK_INET_DIAG: c_int = 4;
 const SOCK_DIAG_BY_FAMILY: c_int = 20;
@@ -73,6 +75,7 @@ struct InetDiagMsg {
 pub struct NetlinkMonitor {
     socket: RawFd,
     running: bool,
+    geoip: Option<Arc<GeoIpDatabase>>,
 }
 
 impl NetlinkMonitor {
@@ -114,6 +117,7 @@ impl NetlinkMonitor {
         Ok(Self {
             socket,
             running: false,
+            geoip: None,
         })
     }
     
@@ -127,6 +131,10 @@ impl NetlinkMonitor {
         Ok(())
     }
     
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    
     pub fn get_tcp_connections(&self) -> Result<Vec<NetworkConnection>> {
         let mut connections = Vec::new();
         
@@ -136,9 +144,20 @@ impl NetlinkMonitor {
         // Query IPv6 TCP connections
         connections.extend(self.query_connections(AF_INET6, libc::IPPROTO_TCP as u8)?);
         
+        if let Some(ref geoip) = self.geoip {
+            for conn in connections.iter_mut() {
+                conn.geo = geoip.lookup(conn.remote_addr);
+            }
+        }
+        
         Ok(connections)
     }
     
+    // Enrich returned connections with the remote address's country/ASN
+    pub fn set_geoip_database(&mut self, database: Option<Arc<GeoIpDatabase>>) {
+        self.geoip = database;
+    }
+    
     pub fn get_udp_connections(&self) -> Result<Vec<NetworkConnection>> {
         let mut connections = Vec::new();
         
@@ -302,6 +321,7 @@ impl NetlinkMonitor {
             state: ConnectionState::from_tcp_state(msg.idiag_state),
             uid: msg.idiag_uid,
             inode: msg.idiag_inode,
+            geo: None,
         })
     }
     
@@ -331,6 +351,7 @@ pub struct NetworkConnection {
     pub state: ConnectionState,
     pub uid: u32,
     pub inode: u32,
+    pub geo: Option<GeoIpInfo>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
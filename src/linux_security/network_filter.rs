@@ -1,13 +1,27 @@
 use std::collections::{HashMap, HashSet};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use anyhow::{Result, anyhow};
-use tracing::{info, warn, error, debug};
+use tracing::{info, warn, debug};
+
+use super::dns::{self, FluxTracker};
+use super::beaconing::{BeaconDetection, BeaconSample, BeaconTracker};
+use super::lan::{LanAlert, LanMonitor};
+use super::tunneling::{TunnelDetection, TunnelDetector};
+use super::tls::{HelloParse, HelloReassembler, TlsHandshakeKind};
+use super::flow_export::{FlowExportConfig, FlowExporter, FlowRecord};
+use super::capture_set::{CaptureOptions, CaptureSet, CapturedPacket, InterfaceStats, PacketHandler, ETHERTYPE_ARP};
+use super::supervisor::Supervisor;
+use crate::network::geoip::{GeoIpDatabase, GeoIpInfo};
+use crate::event_bus::{EventBus, EventBusMetrics, DEFAULT_SINK_CAPACITY};
+use crate::socket_index::{SocketIndex, SocketOwner, SocketProtocol};
+use crate::rule_stats::{RuleEngine, RuleStats};
+use crate::shadow::ShadowLog;
 
 // For packet capture
-use pcap::{Capture, Device, Packet, Active};
+use pcap::Device;
 
 // For DNS parsing
 use std::str;
@@ -24,8 +38,17 @@ pub struct NetworkFilterRule {
     pub dest_ip: Option<IpMatcher>,
     pub source_port: Option<PortMatcher>,
     pub dest_port: Option<PortMatcher>,
+    // JA3/JA3S hashes; when set the rule only matches TLS connections with one of these fingerprints
+    pub tls_fingerprints: Option<Vec<String>>,
+    // Destination hostname seen via TLS SNI or HTTP Host header
+    pub dest_hostname: Option<HostnameMatcher>,
+    // Name (comm) of the local process owning the socket
+    pub process: Option<String>,
     pub priority: i32,
     pub enabled: bool,
+    // Matches are reported as RuleMatched events with `shadow` set instead
+    // of deciding the packet's fate
+    pub shadow: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -55,6 +78,8 @@ pub enum IpMatcher {
     Single(IpAddr),
     Range(IpAddr, IpAddr),
     Subnet(IpAddr, u8), // CIDR notation
+    Country(Vec<String>), // ISO country codes; needs a GeoIP database
+    Asn(Vec<u32>),        // Autonomous system numbers; needs a GeoIP ASN database
     Any,
 }
 
@@ -66,6 +91,19 @@ pub enum PortMatcher {
     Any,
 }
 
+#[derive(Debug, Clone)]
+pub enum HostnameMatcher {
+    Exact(String),
+    Suffix(String), // Matches the domain and any subdomain
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostnameSource {
+    TlsSni,
+    HttpHost,
+}
+
 // DNS cache entry
 #[derive(Debug, Clone)]
 struct DnsCacheEntry {
@@ -87,12 +125,24 @@ pub struct NetworkStats {
     pub connections_tracked: usize,
     pub dns_queries: u64,
     pub dns_blocked: u64,
+    pub dns_responses: u64,
+    pub dns_rebinding_detected: u64,
+    pub dns_fast_flux_detected: u64,
+    pub beacons_detected: u64,
+    pub arp_packets: u64,
+    pub lan_alerts: u64,
+    pub tunnels_detected: u64,
+    pub tls_fingerprints: u64,
+    pub tls_fingerprints_blocked: u64,
+    pub hostnames_observed: u64,
+    pub hostnames_blocked: u64,
+    pub tls_hellos_incomplete: u64,
 }
 
 pub struct NetworkFilter {
-    // Packet capture
-    pcap_handle: Option<Arc<Mutex<Capture<Active>>>>,
-    capture_interface: Option<String>,
+    // Packet capture, one handle per interface
+    capture_set: Option<CaptureSet>,
+    capture_options: CaptureOptions,
     
     // Filtering rules
     rules: Arc<RwLock<Vec<NetworkFilterRule>>>,
@@ -101,9 +151,28 @@ pub struct NetworkFilter {
     dns_blacklist: Arc<RwLock<HashSet<String>>>,
     dns_whitelist: Arc<RwLock<HashSet<String>>>,
     dns_cache: Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
+    dns_flux_tracker: Arc<Mutex<FluxTracker>>,
+    
+    // TLS fingerprint threat intel (JA3/JA3S hash -> description)
+    tls_fingerprint_blacklist: Arc<RwLock<HashMap<String, String>>>,
+    // Hellos that span TCP segments, until they are complete
+    tls_reassembly: Arc<Mutex<HelloReassembler<ConnectionKey>>>,
+    
+    // GeoIP enrichment and country/ASN matching
+    geoip: Arc<RwLock<Option<Arc<GeoIpDatabase>>>>,
     
     // Connection tracking
     active_connections: Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
+    beacon_tracker: Arc<Mutex<BeaconTracker>>,
+    
+    // ARP bindings and DHCP servers on the captured segments
+    lan_monitor: Arc<LanMonitor>,
+    
+    // ICMP and DNS traffic shaped like a covert channel
+    tunnel_detector: Arc<TunnelDetector>,
+    
+    // Socket owners for per-process rules
+    socket_index: SocketIndex,
     
     // Statistics
     stats: Arc<Mutex<NetworkStats>>,
@@ -113,12 +182,36 @@ pub struct NetworkFilter {
     filtering_enabled: bool,
     dns_filtering_enabled: bool,
     
-    // Event callback
+    // Event callback; capture threads only publish to the bus, the caller's
+    // handler runs on the bus's delivery thread
     event_handler: Arc<dyn Fn(NetworkEvent) + Send + Sync>,
+    event_bus: Arc<EventBus<NetworkEvent>>,
+    rule_stats: Option<RuleStats>,
     
     running: Arc<Mutex<bool>>,
 }
 
+// What the capture threads check every packet against and record it in,
+// shared with the filter that started them
+struct PacketContext {
+    rules: Arc<RwLock<Vec<NetworkFilterRule>>>,
+    dns_blacklist: Arc<RwLock<HashSet<String>>>,
+    dns_whitelist: Arc<RwLock<HashSet<String>>>,
+    dns_cache: Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
+    dns_flux_tracker: Arc<Mutex<FluxTracker>>,
+    tls_fingerprint_blacklist: Arc<RwLock<HashMap<String, String>>>,
+    tls_reassembly: Arc<Mutex<HelloReassembler<ConnectionKey>>>,
+    geoip: Arc<RwLock<Option<Arc<GeoIpDatabase>>>>,
+    socket_index: SocketIndex,
+    active_connections: Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
+    lan_monitor: Arc<LanMonitor>,
+    tunnel_detector: Arc<TunnelDetector>,
+    stats: Arc<Mutex<NetworkStats>>,
+    event_handler: Arc<dyn Fn(NetworkEvent) + Send + Sync>,
+    filtering_enabled: bool,
+    dns_filtering_enabled: bool,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct ConnectionKey {
     protocol: u8,
@@ -135,6 +228,67 @@ struct ConnectionInfo {
     packets: u64,
     bytes: u64,
     state: ConnectionState,
+    tls_fingerprint: Option<String>,
+    hostname: Option<String>,
+    source_geo: Option<GeoIpInfo>,
+    dest_geo: Option<GeoIpInfo>,
+    // Whether this direction was seen before its reverse, i.e. opened the connection
+    initiator: bool,
+    process: Option<SocketOwner>,
+}
+
+// Shared handle on the tracked connections, one entry per direction
+#[derive(Clone)]
+pub struct ConnectionTable {
+    connections: Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
+    beacons: Arc<Mutex<BeaconTracker>>,
+}
+
+impl ConnectionTable {
+    // Cumulative counters of every tracked direction, timestamped in wall-clock time
+    pub fn flows(&self) -> Vec<FlowRecord> {
+        let Ok(connections) = self.connections.lock() else {
+            return Vec::new();
+        };
+        let now = SystemTime::now();
+        connections.iter()
+            .map(|(key, info)| FlowRecord {
+                protocol: key.protocol,
+                src_addr: key.local_addr,
+                src_port: key.local_port,
+                dst_addr: key.remote_addr,
+                dst_port: key.remote_port,
+                packets: info.packets,
+                bytes: info.bytes,
+                start: now - info.first_seen.elapsed(),
+                end: now - info.last_seen.elapsed(),
+            })
+            .collect()
+    }
+    
+    // Beaconing found in expired connections, newest first
+    pub fn beacons(&self, limit: usize) -> Vec<BeaconDetection> {
+        self.beacons.lock().map(|tracker| tracker.detections(limit)).unwrap_or_default()
+    }
+}
+
+impl ConnectionKey {
+    fn reversed(&self) -> Self {
+        Self {
+            protocol: self.protocol,
+            local_addr: self.remote_addr,
+            local_port: self.remote_port,
+            remote_addr: self.local_addr,
+            remote_port: self.local_port,
+        }
+    }
+}
+
+impl ConnectionInfo {
+    // Location of the public end of the connection
+    fn remote_geo(&self) -> Option<GeoIpInfo> {
+        self.dest_geo.clone().or_else(|| self.source_geo.clone())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -155,19 +309,64 @@ pub enum NetworkEvent {
         size: usize,
         action: FilterAction,
         rule_id: Option<String>,
+        geo: Option<GeoIpInfo>,
     },
     DnsQuery {
         timestamp: Instant,
         domain: String,
         query_type: String,
-        source: IpAddr,
+        source: (IpAddr, u16),
+        action: FilterAction,
+    },
+    // A response on its way back to `client`, whether or not it carried addresses
+    DnsAnswer {
+        timestamp: Instant,
+        domain: String,
+        query_type: String,
+        answers: Vec<IpAddr>,
+        rcode: u8,
+        resolver: IpAddr,
+        client: (IpAddr, u16),
+    },
+    // A public name resolved to a private/loopback/link-local address
+    DnsRebinding {
+        timestamp: Instant,
+        domain: String,
+        resolved_ips: Vec<IpAddr>,
+        resolver: IpAddr,
         action: FilterAction,
     },
+    // ICMP echo or DNS queries carrying what looks like tunneled data
+    Tunneling {
+        timestamp: Instant,
+        detection: TunnelDetection,
+    },
+    // ARP spoofing or a rogue DHCP server on a captured segment
+    LanAnomaly {
+        timestamp: Instant,
+        alert: LanAlert,
+    },
+    // Regularly spaced, similarly sized connections from one process to one destination
+    Beaconing {
+        timestamp: Instant,
+        detection: BeaconDetection,
+    },
+    // A name rotating through many addresses on many networks with short TTLs
+    DnsFastFlux {
+        timestamp: Instant,
+        domain: String,
+        unique_ips: Vec<IpAddr>,
+        unique_networks: usize,
+        min_ttl: u32,
+        observed_for: Duration,
+        resolver: IpAddr,
+    },
     ConnectionNew {
         timestamp: Instant,
         protocol: Protocol,
         source: (IpAddr, u16),
         destination: (IpAddr, u16),
+        geo: Option<GeoIpInfo>,
     },
     ConnectionClosed {
         timestamp: Instant,
@@ -177,6 +376,8 @@ pub enum NetworkEvent {
         duration: Duration,
         packets: u64,
         bytes: u64,
+        hostname: Option<String>,
+        geo: Option<GeoIpInfo>,
     },
     RuleMatched {
         timestamp: Instant,
@@ -184,6 +385,39 @@ pub enum NetworkEvent {
         rule_name: String,
         action: FilterAction,
         packet_info: String,
+        // The rule is in shadow mode and `action` was not taken
+        shadow: bool,
+    },
+    TlsFingerprint {
+        timestamp: Instant,
+        kind: TlsHandshakeKind,
+        source: (IpAddr, u16),
+        destination: (IpAddr, u16),
+        fingerprint: String,
+        fingerprint_string: String,
+        action: FilterAction,
+        rule_id: Option<String>,
+        threat: Option<String>,
+        geo: Option<GeoIpInfo>,
+    },
+    HostnameObserved {
+        timestamp: Instant,
+        protocol: Protocol,
+        source: (IpAddr, u16),
+        destination: (IpAddr, u16),
+        hostname: String,
+        hostname_source: HostnameSource,
+        action: FilterAction,
+        rule_id: Option<String>,
+        geo: Option<GeoIpInfo>,
+    },
+    // A TLS hello that started but could not be reassembled, so its SNI is unknown
+    TlsHelloIncomplete {
+        timestamp: Instant,
+        source: (IpAddr, u16),
+        destination: (IpAddr, u16),
+        action: FilterAction,
+        geo: Option<GeoIpInfo>,
     },
 }
 
@@ -194,194 +428,236 @@ impl NetworkFilter {
     {
         info!("Initializing network filter with pcap support");
         
+        let event_bus = Arc::new(EventBus::new(DEFAULT_SINK_CAPACITY));
+        event_bus.subscribe("network-events", event_handler);
+        let publisher = Arc::clone(&event_bus);
+        
         Ok(Self {
-            pcap_handle: None,
-            capture_interface: None,
+            capture_set: None,
+            capture_options: CaptureOptions::default(),
             rules: Arc::new(RwLock::new(Vec::new())),
             dns_blacklist: Arc::new(RwLock::new(HashSet::new())),
             dns_whitelist: Arc::new(RwLock::new(HashSet::new())),
             dns_cache: Arc::new(Mutex::new(HashMap::new())),
+            dns_flux_tracker: Arc::new(Mutex::new(FluxTracker::new())),
+            tls_fingerprint_blacklist: Arc::new(RwLock::new(HashMap::new())),
+            tls_reassembly: Arc::new(Mutex::new(HelloReassembler::default())),
+            geoip: Arc::new(RwLock::new(None)),
             active_connections: Arc::new(Mutex::new(HashMap::new())),
+            beacon_tracker: Arc::new(Mutex::new(BeaconTracker::new())),
+            lan_monitor: Arc::new(LanMonitor::new()),
+            tunnel_detector: Arc::new(TunnelDetector::new()),
+            socket_index: SocketIndex::new(),
             stats: Arc::new(Mutex::new(NetworkStats::default())),
             capture_enabled: false,
             filtering_enabled: true,
             dns_filtering_enabled: true,
-            event_handler: Arc::new(event_handler),
+            event_handler: Arc::new(move |event| publisher.publish(event)),
+            event_bus,
+            rule_stats: None,
             running: Arc::new(Mutex::new(false)),
         })
     }
     
     pub fn start_capture(&mut self, interface: Option<&str>) -> Result<()> {
-        if self.capture_enabled {
-            return Ok(());
-        }
-        
-        // Find capture device
-        let device = if let Some(iface) = interface {
-            Device::list()?
-                .into_iter()
-                .find(|d| d.name == iface)
-                .ok_or_else(|| anyhow!("Interface {} not found", iface))?
-        } else {
+        let interface = match interface {
+            Some(iface) => iface.to_string(),
             // Use default device
-            Device::lookup()
+            None => Device::lookup()
                 .map_err(|e| anyhow!("Failed to find default device: {}", e))?
                 .ok_or_else(|| anyhow!("No default device found"))?
+                .name,
         };
+        self.start_capture_interfaces(vec![interface])
+    }
+    
+    // Captures on several interfaces at once. Entries may be exact names, "any",
+    // or prefix wildcards like "eth*"; interfaces that go down are re-attached
+    // when they come back up.
+    pub fn start_capture_interfaces(&mut self, interfaces: Vec<String>) -> Result<()> {
+        if self.capture_enabled {
+            return Ok(());
+        }
+        if interfaces.is_empty() {
+            return Err(anyhow!("No capture interfaces given"));
+        }
         
-        info!("Starting packet capture on interface: {}", device.name);
-        self.capture_interface = Some(device.name.clone());
+        info!("Starting packet capture on interfaces: {}", interfaces.join(", "));
         
-        // Create capture handle
-        let cap = Capture::from_device(device)?
-            .promisc(true)
-            .snaplen(65535)
-            .timeout(100) // 100ms timeout for reads
-            .open()?;
+        let context = self.packet_context();
+        let running = Arc::clone(&self.running);
         
-        // Set BPF filter to reduce overhead (optional)
-        // cap.filter("tcp or udp or icmp", true)?;
+        let handler: PacketHandler = Arc::new(move |_interface: &str, packets: &[CapturedPacket<'_>]| {
+            if !*running.lock().unwrap() {
+                return;
+            }
+            Self::process_batch(packets, &context);
+        });
         
-        self.pcap_handle = Some(Arc::new(Mutex::new(cap)));
+        let capture_set = CaptureSet::new(interfaces, self.capture_options.clone(), handler);
+        if let Some(filter) = self.capture_filter()? {
+            capture_set.set_filter(Some(filter))?;
+        }
+        capture_set.start()?;
+        self.capture_set = Some(capture_set);
         self.capture_enabled = true;
         
-        // Start capture thread
-        self.start_capture_thread();
-        
         Ok(())
     }
     
-    fn start_capture_thread(&self) {
-        let pcap_handle = match &self.pcap_handle {
-            Some(handle) => Arc::clone(handle),
-            None => return,
-        };
-        
-        let rules = Arc::clone(&self.rules);
-        let dns_blacklist = Arc::clone(&self.dns_blacklist);
-        let dns_whitelist = Arc::clone(&self.dns_whitelist);
-        let dns_cache = Arc::clone(&self.dns_cache);
-        let active_connections = Arc::clone(&self.active_connections);
-        let stats = Arc::clone(&self.stats);
-        let event_handler = Arc::clone(&self.event_handler);
-        let running = Arc::clone(&self.running);
-        let filtering_enabled = self.filtering_enabled;
-        let dns_filtering_enabled = self.dns_filtering_enabled;
+    // Backend, BPF pre-filter and batching; takes effect on the next start_capture
+    pub fn set_capture_options(&mut self, options: CaptureOptions) {
+        self.capture_options = options;
+    }
+    
+    // Shares an index with other users, e.g. the API; takes effect on the next start_capture
+    pub fn set_socket_index(&mut self, socket_index: SocketIndex) {
+        self.socket_index = socket_index;
+    }
+    
+    // The BPF expression applied to captured traffic, if any
+    pub fn capture_filter(&self) -> Result<Option<String>> {
+        if let Some(ref filter) = self.capture_options.bpf_filter {
+            return Ok(Some(filter.clone()));
+        }
+        if !self.capture_options.filter_from_rules {
+            return Ok(None);
+        }
         
-        thread::spawn(move || {
-            info!("Packet capture thread started");
-            
-            while *running.lock().unwrap() {
-                let mut cap = match pcap_handle.lock() {
-                    Ok(cap) => cap,
-                    Err(_) => {
-                        error!("Failed to lock pcap handle");
-                        break;
-                    }
-                };
-                
-                match cap.next_packet() {
-                    Ok(packet) => {
-                        Self::process_packet(
-                            &packet,
-                            &rules,
-                            &dns_blacklist,
-                            &dns_whitelist,
-                            &dns_cache,
-                            &active_connections,
-                            &stats,
-                            &event_handler,
-                            filtering_enabled,
-                            dns_filtering_enabled,
-                        );
-                    }
-                    Err(pcap::Error::TimeoutExpired) => {
-                        // Normal timeout, continue
-                        continue;
-                    }
-                    Err(e) => {
-                        error!("Error capturing packet: {}", e);
-                        thread::sleep(Duration::from_millis(100));
-                    }
-                }
+        let rules = self.rules.read()
+            .map_err(|_| anyhow!("Failed to acquire rules read lock"))?;
+        let tls_blacklist = !self.tls_fingerprint_blacklist.read()
+            .map_err(|_| anyhow!("Failed to acquire TLS fingerprint blacklist read lock"))?
+            .is_empty();
+        Ok(build_capture_filter(&rules, tls_blacklist))
+    }
+    
+    // Re-derives the rule-based filter after the rules changed
+    fn refresh_capture_filter(&self) -> Result<()> {
+        if !self.capture_options.filter_from_rules || self.capture_options.bpf_filter.is_some() {
+            return Ok(());
+        }
+        if let Some(ref capture_set) = self.capture_set {
+            capture_set.set_filter(self.capture_filter()?)?;
+        }
+        Ok(())
+    }
+    
+    // Hands the running capture to `supervisor`, which restarts capture
+    // threads that die
+    pub fn supervise_capture(&self, supervisor: &Supervisor) -> Result<()> {
+        let capture_set = self.capture_set.as_ref()
+            .ok_or_else(|| anyhow!("Packet capture is not running"))?;
+        capture_set.supervise(supervisor);
+        Ok(())
+    }
+    
+    // Per-interface packet, byte and drop counters for the running capture
+    pub fn get_interface_stats(&self) -> Vec<InterfaceStats> {
+        self.capture_set.as_ref()
+            .map(|capture_set| capture_set.interface_stats())
+            .unwrap_or_default()
+    }
+    
+    pub fn event_bus_metrics(&self) -> EventBusMetrics {
+        self.event_bus.metrics()
+    }
+    
+    // Counts the matches of every rule in `stats`, which also learns the
+    // rules that never match
+    pub fn set_rule_stats(&mut self, stats: RuleStats) -> Result<()> {
+        let recorder = stats.clone();
+        self.event_bus.subscribe("rule-stats", move |event| {
+            if let NetworkEvent::RuleMatched { rule_id, rule_name, .. } = event {
+                recorder.record(RuleEngine::NetworkFilter, &rule_id, &rule_name);
             }
-            
-            info!("Packet capture thread stopped");
         });
+        self.rule_stats = Some(stats);
+        self.sync_rule_stats()
     }
     
-    fn process_packet(
-        packet: &Packet,
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        dns_blacklist: &Arc<RwLock<HashSet<String>>>,
-        dns_whitelist: &Arc<RwLock<HashSet<String>>>,
-        dns_cache: &Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
-        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
-        dns_filtering_enabled: bool,
-    ) {
-        // Update stats
-        if let Ok(mut stats) = stats.lock() {
-            stats.packets_captured += 1;
+    fn sync_rule_stats(&self) -> Result<()> {
+        if let Some(stats) = &self.rule_stats {
+            let rules = self.rules.read()
+                .map_err(|_| anyhow!("Failed to acquire rules read lock"))?;
+            stats.sync(RuleEngine::NetworkFilter, rules.iter().map(|rule| (rule.id.as_str(), rule.name.as_str())));
         }
+        Ok(())
+    }
+    
+    // Counts the matches of shadow rules in `log`
+    pub fn record_shadow_matches(&self, log: ShadowLog) {
+        self.event_bus.subscribe("shadow-rules", move |event| {
+            if let NetworkEvent::RuleMatched { rule_id, rule_name, action, packet_info, shadow: true, .. } = event {
+                log.record(RuleEngine::NetworkFilter, &rule_id, &rule_name, &format!("{:?}", action).to_lowercase(), packet_info);
+            }
+        });
+    }
+    
+    fn packet_context(&self) -> PacketContext {
+        PacketContext {
+            rules: Arc::clone(&self.rules),
+            dns_blacklist: Arc::clone(&self.dns_blacklist),
+            dns_whitelist: Arc::clone(&self.dns_whitelist),
+            dns_cache: Arc::clone(&self.dns_cache),
+            dns_flux_tracker: Arc::clone(&self.dns_flux_tracker),
+            tls_fingerprint_blacklist: Arc::clone(&self.tls_fingerprint_blacklist),
+            tls_reassembly: Arc::clone(&self.tls_reassembly),
+            geoip: Arc::clone(&self.geoip),
+            socket_index: self.socket_index.clone(),
+            active_connections: Arc::clone(&self.active_connections),
+            lan_monitor: Arc::clone(&self.lan_monitor),
+            tunnel_detector: Arc::clone(&self.tunnel_detector),
+            stats: Arc::clone(&self.stats),
+            event_handler: Arc::clone(&self.event_handler),
+            filtering_enabled: self.filtering_enabled,
+            dns_filtering_enabled: self.dns_filtering_enabled,
+        }
+    }
+    
+    // Packets start at the IPv4/IPv6 or ARP header; link-layer framing has
+    // already been stripped by the capture set. Shared state that does not
+    // change per packet is looked up once per batch.
+    fn process_batch(packets: &[CapturedPacket<'_>], context: &PacketContext) {
+        let geoip = context.geoip.read().ok().and_then(|db| db.clone());
         
-        // Parse packet headers
-        let data = packet.data;
-        if data.len() < 14 {
-            return; // Too small for Ethernet header
+        // Update stats
+        if let Ok(mut stats) = context.stats.lock() {
+            stats.packets_captured += packets.len() as u64;
         }
         
-        // Skip Ethernet header (14 bytes)
-        let ip_data = &data[14..];
+        for packet in packets {
+            if packet.protocol == ETHERTYPE_ARP {
+                if let Ok(mut stats) = context.stats.lock() {
+                    stats.arp_packets += 1;
+                }
+                if let Some(alert) = context.lan_monitor.observe_arp(packet.data) {
+                    Self::report_lan_alert(alert, &context.stats, &context.event_handler);
+                }
+                continue;
+            }
+            Self::process_packet(packet.data, context, geoip.as_deref());
+        }
+    }
+    
+    fn process_packet(ip_data: &[u8], context: &PacketContext, geoip: Option<&GeoIpDatabase>) {
+        if ip_data.is_empty() {
+            return;
+        }
         
         // Check IP version
         let ip_version = (ip_data[0] >> 4) & 0x0f;
         
         match ip_version {
-            4 => Self::process_ipv4_packet(
-                ip_data,
-                rules,
-                dns_blacklist,
-                dns_whitelist,
-                dns_cache,
-                active_connections,
-                stats,
-                event_handler,
-                filtering_enabled,
-                dns_filtering_enabled,
-            ),
-            6 => Self::process_ipv6_packet(
-                ip_data,
-                rules,
-                dns_blacklist,
-                dns_whitelist,
-                dns_cache,
-                active_connections,
-                stats,
-                event_handler,
-                filtering_enabled,
-                dns_filtering_enabled,
-            ),
+            4 => Self::process_ipv4_packet(ip_data, context, geoip),
+            6 => Self::process_ipv6_packet(ip_data, context, geoip),
             _ => {
                 debug!("Unknown IP version: {}", ip_version);
             }
         }
     }
     
-    fn process_ipv4_packet(
-        data: &[u8],
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        dns_blacklist: &Arc<RwLock<HashSet<String>>>,
-        dns_whitelist: &Arc<RwLock<HashSet<String>>>,
-        dns_cache: &Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
-        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
-        dns_filtering_enabled: bool,
-    ) {
+    fn process_ipv4_packet(data: &[u8], context: &PacketContext, geoip: Option<&GeoIpDatabase>) {
         if data.len() < 20 {
             return; // Too small for IPv4 header
         }
@@ -396,21 +672,64 @@ impl NetworkFilter {
         let src_ip = IpAddr::V4(Ipv4Addr::new(data[12], data[13], data[14], data[15]));
         let dst_ip = IpAddr::V4(Ipv4Addr::new(data[16], data[17], data[18], data[19]));
         
-        // Parse transport layer
-        let transport_data = &data[header_len..];
+        Self::process_transport(protocol, (src_ip, dst_ip), &data[header_len..], data.len(), context, geoip);
+    }
+    
+    fn process_ipv6_packet(data: &[u8], context: &PacketContext, geoip: Option<&GeoIpDatabase>) {
+        if data.len() < 40 {
+            return; // Too small for IPv6 header
+        }
+        
+        let mut next_header = data[6];
+        let src_ip = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&data[8..24]).unwrap()));
+        let dst_ip = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&data[24..40]).unwrap()));
+        
+        // Skip extension headers up to the transport header. Only the first
+        // fragment carries it
+        let mut offset = 40;
+        while matches!(next_header, 0 | 43 | 44 | 51 | 60) {
+            let header = match data.get(offset..offset + 8) {
+                Some(header) => header,
+                None => return,
+            };
+            let length = match next_header {
+                51 => (header[1] as usize + 2) * 4, // Authentication header
+                44 => {                             // Fragment
+                    if u16::from_be_bytes([header[2], header[3]]) >> 3 != 0 {
+                        return;
+                    }
+                    8
+                }
+                _ => (header[1] as usize + 1) * 8,  // Hop-by-hop, routing, destination options
+            };
+            next_header = header[0];
+            offset += length;
+        }
+        let transport_data = match data.get(offset..) {
+            Some(transport_data) => transport_data,
+            None => return,
+        };
         
+        Self::process_transport(next_header, (src_ip, dst_ip), transport_data, data.len(), context, geoip);
+    }
+    
+    // Dispatches on the IP protocol number, the same for both IP versions
+    // except for ICMP
+    fn process_transport(
+        protocol: u8,
+        (src_ip, dst_ip): (IpAddr, IpAddr),
+        transport_data: &[u8],
+        packet_size: usize,
+        context: &PacketContext,
+        geoip: Option<&GeoIpDatabase>,
+    ) {
         match protocol {
             6 => { // TCP
                 if transport_data.len() >= 4 {
                     let src_port = u16::from_be_bytes([transport_data[0], transport_data[1]]);
                     let dst_port = u16::from_be_bytes([transport_data[2], transport_data[3]]);
                     
-                    Self::process_tcp_packet(
-                        src_ip, src_port, dst_ip, dst_port,
-                        transport_data, data.len(),
-                        rules, active_connections, stats, event_handler,
-                        filtering_enabled,
-                    );
+                    Self::process_tcp_packet((src_ip, src_port), (dst_ip, dst_port), transport_data, packet_size, context, geoip);
                 }
             }
             17 => { // UDP
@@ -419,68 +738,49 @@ impl NetworkFilter {
                     let dst_port = u16::from_be_bytes([transport_data[2], transport_data[3]]);
                     
                     // Check if it's DNS (port 53)
-                    if (dst_port == 53 || src_port == 53) && dns_filtering_enabled {
-                        Self::process_dns_packet(
-                            &transport_data[8..], // Skip UDP header
-                            src_ip,
-                            dns_blacklist,
-                            dns_whitelist,
-                            dns_cache,
-                            stats,
-                            event_handler,
-                        );
+                    if (dst_port == 53 || src_port == 53) && context.dns_filtering_enabled {
+                        // Skip the UDP header
+                        Self::process_dns_packet(&transport_data[8..], (src_ip, src_port), (dst_ip, dst_port), context);
+                    }
+                    
+                    // DHCP replies, to spot servers that should not be there
+                    if let (IpAddr::V4(server), 67) = (src_ip, src_port) {
+                        if let Some(alert) = context.lan_monitor.observe_dhcp(server, &transport_data[8..]) {
+                            Self::report_lan_alert(alert, &context.stats, &context.event_handler);
+                        }
                     }
                     
-                    Self::process_udp_packet(
-                        src_ip, src_port, dst_ip, dst_port,
-                        transport_data, data.len(),
-                        rules, active_connections, stats, event_handler,
-                        filtering_enabled,
-                    );
+                    Self::process_udp_packet((src_ip, src_port), (dst_ip, dst_port), packet_size, context, geoip);
                 }
             }
             1 => { // ICMP
-                Self::process_icmp_packet(
-                    src_ip, dst_ip, data.len(),
-                    rules, stats, event_handler,
-                    filtering_enabled,
-                );
+                if let Some(detection) = context.tunnel_detector.observe_icmp(src_ip, dst_ip, transport_data) {
+                    Self::report_tunnel(detection, &context.stats, &context.event_handler);
+                }
+                Self::process_icmp_packet(src_ip, dst_ip, packet_size, context, geoip);
             }
+            // ICMPv6 goes through the ICMP rules; its echo types differ, so
+            // the tunnel detector does not see it
+            58 => Self::process_icmp_packet(src_ip, dst_ip, packet_size, context, geoip),
             _ => {
                 debug!("Unknown protocol: {}", protocol);
             }
         }
     }
     
-    fn process_ipv6_packet(
-        data: &[u8],
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        dns_blacklist: &Arc<RwLock<HashSet<String>>>,
-        dns_whitelist: &Arc<RwLock<HashSet<String>>>,
-        dns_cache: &Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
-        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
-        dns_filtering_enabled: bool,
-    ) {
-        // TODO: Implement IPv6 packet processing
-        // Similar to IPv4 but with 40-byte fixed header
-    }
-    
     fn process_tcp_packet(
-        src_ip: IpAddr,
-        src_port: u16,
-        dst_ip: IpAddr,
-        dst_port: u16,
+        (src_ip, src_port): (IpAddr, u16),
+        (dst_ip, dst_port): (IpAddr, u16),
         tcp_data: &[u8],
         packet_size: usize,
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
+        context: &PacketContext,
+        geoip: Option<&GeoIpDatabase>,
     ) {
+        let PacketContext {
+            rules, dns_blacklist, dns_whitelist, tls_fingerprint_blacklist, tls_reassembly, socket_index,
+            active_connections, stats, event_handler, filtering_enabled, ..
+        } = context;
+        let filtering_enabled = *filtering_enabled;
         let conn_key = ConnectionKey {
             protocol: 6, // TCP
             local_addr: src_ip,
@@ -489,8 +789,27 @@ impl NetworkFilter {
             remote_port: dst_port,
         };
         
+        // Fingerprint TLS ClientHello/ServerHello once the whole hello has
+        // arrived, which may take more than one segment
+        let payload = Self::tcp_payload(tcp_data);
+        let tls_parse = payload.and_then(|payload| {
+            let seq = u32::from_be_bytes([tcp_data[4], tcp_data[5], tcp_data[6], tcp_data[7]]);
+            Some(tls_reassembly.lock().ok()?.push(&conn_key, seq, payload))
+        });
+        let hello_incomplete = matches!(tls_parse, Some(HelloParse::Incomplete));
+        let tls_hello = tls_parse.and_then(HelloParse::complete);
+        
+        // Destination hostname from TLS SNI, falling back to a plaintext HTTP Host header
+        let observed_host = tls_hello.as_ref()
+            .and_then(|hello| hello.server_name.clone())
+            .map(|host| (host, HostnameSource::TlsSni))
+            .or_else(|| payload.and_then(Self::extract_http_host).map(|host| (host, HostnameSource::HttpHost)));
+        
         // Update connection tracking
         let mut new_connection = false;
+        let mut conn_fingerprint = None;
+        let mut conn_hostname = None;
+        let mut conn_geo = (None, None);
         if let Ok(mut connections) = active_connections.lock() {
             let now = Instant::now();
             
@@ -498,38 +817,175 @@ impl NetworkFilter {
                 conn_info.last_seen = now;
                 conn_info.packets += 1;
                 conn_info.bytes += packet_size as u64;
+                if let Some(ref hello) = tls_hello {
+                    conn_info.tls_fingerprint = Some(hello.fingerprint.clone());
+                }
+                if let Some((ref host, _)) = observed_host {
+                    conn_info.hostname = Some(host.clone());
+                }
+                conn_fingerprint = conn_info.tls_fingerprint.clone();
+                conn_hostname = conn_info.hostname.clone();
+                conn_geo = (conn_info.source_geo.clone(), conn_info.dest_geo.clone());
             } else {
                 new_connection = true;
+                let initiator = !connections.contains_key(&conn_key.reversed());
+                conn_fingerprint = tls_hello.as_ref().map(|hello| hello.fingerprint.clone());
+                conn_hostname = observed_host.as_ref().map(|(host, _)| host.clone());
+                conn_geo = Self::lookup_geo(geoip, src_ip, dst_ip);
                 connections.insert(conn_key.clone(), ConnectionInfo {
                     first_seen: now,
                     last_seen: now,
                     packets: 1,
                     bytes: packet_size as u64,
                     state: ConnectionState::New,
+                    tls_fingerprint: conn_fingerprint.clone(),
+                    hostname: conn_hostname.clone(),
+                    source_geo: conn_geo.0.clone(),
+                    dest_geo: conn_geo.1.clone(),
+                    initiator,
+                    process: None,
                 });
             }
         }
+        let (src_geo, dst_geo) = conn_geo;
+        let remote_geo = dst_geo.clone().or_else(|| src_geo.clone());
         
         if new_connection {
+            Self::attribute_connection(active_connections, socket_index, &conn_key, Protocol::Tcp);
             event_handler(NetworkEvent::ConnectionNew {
                 timestamp: Instant::now(),
                 protocol: Protocol::Tcp,
                 source: (src_ip, src_port),
                 destination: (dst_ip, dst_port),
+                geo: remote_geo.clone(),
             });
         }
         
         // Apply filtering rules
-        if filtering_enabled {
-            let action = Self::evaluate_rules(
-                &rules,
+        let action = if filtering_enabled {
+            Self::evaluate_rules(
+                rules,
                 Protocol::Tcp,
                 src_ip,
                 src_port,
                 dst_ip,
                 dst_port,
-            );
+                conn_fingerprint.as_deref(),
+                conn_hostname.as_deref(),
+                src_geo.as_ref(),
+                dst_geo.as_ref(),
+                Some(socket_index),
+                event_handler,
+            )
+        } else {
+            None
+        };
+        
+        if let Some((hostname, hostname_source)) = observed_host {
+            // Observed hostnames are subject to the same lists as DNS queries
+            let whitelisted = dns_whitelist.read()
+                .map(|whitelist| whitelist.contains(&hostname))
+                .unwrap_or(false);
+            let blacklisted = !whitelisted && dns_blacklist.read()
+                .map(|blacklist| blacklist.contains(&hostname))
+                .unwrap_or(false);
+            
+            let (host_action, rule_id) = match (&action, blacklisted) {
+                (_, true) if filtering_enabled => (FilterAction::Block, None),
+                (_, true) => (FilterAction::Log, None),
+                (Some((action, rule_id)), false) => (*action, Some(rule_id.clone())),
+                (None, false) => (FilterAction::Allow, None),
+            };
+            
+            if let Ok(mut stats) = stats.lock() {
+                stats.hostnames_observed += 1;
+                if host_action == FilterAction::Block {
+                    stats.hostnames_blocked += 1;
+                }
+            }
+            
+            event_handler(NetworkEvent::HostnameObserved {
+                timestamp: Instant::now(),
+                protocol: Protocol::Tcp,
+                source: (src_ip, src_port),
+                destination: (dst_ip, dst_port),
+                hostname,
+                hostname_source,
+                action: host_action,
+                rule_id,
+                geo: remote_geo.clone(),
+            });
+        }
+        
+        if hello_incomplete {
+            // With the SNI unknown, hostname blocking can't tell where the
+            // connection goes, so it fails closed
+            let hostname_blocking = dns_blacklist.read().map(|blacklist| !blacklist.is_empty()).unwrap_or(false)
+                || rules.read().map(|rules| rules.iter().any(|rule| {
+                    rule.enabled && !rule.shadow && rule.action == FilterAction::Block && rule.dest_hostname.is_some()
+                })).unwrap_or(false);
+            let hello_action = match (hostname_blocking, filtering_enabled) {
+                (true, true) => FilterAction::Block,
+                (true, false) => FilterAction::Log,
+                (false, _) => FilterAction::Allow,
+            };
+            
+            if let Ok(mut stats) = stats.lock() {
+                stats.tls_hellos_incomplete += 1;
+                if hello_action == FilterAction::Block {
+                    stats.hostnames_blocked += 1;
+                }
+            }
+            debug!("Incomplete TLS hello from {}:{} to {}:{} -> {:?}", src_ip, src_port, dst_ip, dst_port, hello_action);
+            
+            event_handler(NetworkEvent::TlsHelloIncomplete {
+                timestamp: Instant::now(),
+                source: (src_ip, src_port),
+                destination: (dst_ip, dst_port),
+                action: hello_action,
+                geo: remote_geo.clone(),
+            });
+        }
+        
+        if let Some(hello) = tls_hello {
+            let threat = tls_fingerprint_blacklist.read()
+                .ok()
+                .and_then(|blacklist| blacklist.get(&hello.fingerprint).cloned());
+            
+            let (tls_action, rule_id) = match (&threat, &action) {
+                (Some(_), _) if filtering_enabled => (FilterAction::Block, None),
+                (Some(_), _) => (FilterAction::Log, None),
+                (None, Some((action, rule_id))) => (*action, Some(rule_id.clone())),
+                (None, None) => (FilterAction::Allow, None),
+            };
+            
+            if let Ok(mut stats) = stats.lock() {
+                stats.tls_fingerprints += 1;
+                if tls_action == FilterAction::Block {
+                    stats.tls_fingerprints_blocked += 1;
+                }
+            }
+            
+            if let Some(ref description) = threat {
+                warn!("Known malicious TLS fingerprint {} ({}) from {}:{} to {}:{}",
+                      hello.fingerprint, description, src_ip, src_port, dst_ip, dst_port);
+            }
             
+            event_handler(NetworkEvent::TlsFingerprint {
+                timestamp: Instant::now(),
+                kind: hello.kind,
+                source: (src_ip, src_port),
+                destination: (dst_ip, dst_port),
+                fingerprint: hello.fingerprint,
+                fingerprint_string: hello.fingerprint_string,
+                action: tls_action,
+                rule_id,
+                threat,
+                geo: remote_geo.clone(),
+            });
+        }
+        
+        if filtering_enabled {
             match action {
                 Some((FilterAction::Block, rule_id)) => {
                     if let Ok(mut stats) = stats.lock() {
@@ -544,6 +1000,7 @@ impl NetworkFilter {
                         size: packet_size,
                         action: FilterAction::Block,
                         rule_id: Some(rule_id),
+                        geo: remote_geo.clone(),
                     });
                 }
                 Some((FilterAction::Log, rule_id)) => {
@@ -559,6 +1016,7 @@ impl NetworkFilter {
                         size: packet_size,
                         action: FilterAction::Log,
                         rule_id: Some(rule_id),
+                        geo: remote_geo.clone(),
                     });
                 }
                 _ => {
@@ -570,19 +1028,66 @@ impl NetworkFilter {
         }
     }
     
+    // Extract the Host header from a plaintext HTTP request
+    fn extract_http_host(payload: &[u8]) -> Option<String> {
+        const METHODS: [&str; 9] = [
+            "GET ", "POST ", "HEAD ", "PUT ", "DELETE ", "OPTIONS ", "PATCH ", "CONNECT ", "TRACE ",
+        ];
+        
+        if !METHODS.iter().any(|m| payload.starts_with(m.as_bytes())) {
+            return None;
+        }
+        
+        // Only the header block is of interest; ignore non-UTF8 bodies
+        let header_end = payload.windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .unwrap_or(payload.len());
+        let headers = String::from_utf8_lossy(&payload[..header_end]);
+        
+        for line in headers.lines().skip(1) {
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("host") {
+                    let value = value.trim();
+                    // Strip an optional port, keeping bracketed IPv6 literals intact
+                    let host = if value.starts_with('[') {
+                        value.split(']').next().map(|h| h.trim_start_matches('['))
+                    } else {
+                        value.split(':').next()
+                    }?;
+                    
+                    if host.is_empty() {
+                        return None;
+                    }
+                    return Some(host.trim_end_matches('.').to_lowercase());
+                }
+            }
+        }
+        
+        None
+    }
+    
+    // Returns the TCP payload, skipping the variable-length TCP header
+    fn tcp_payload(tcp_data: &[u8]) -> Option<&[u8]> {
+        if tcp_data.len() < 20 {
+            return None;
+        }
+        
+        let header_len = ((tcp_data[12] >> 4) as usize) * 4;
+        if header_len < 20 || tcp_data.len() <= header_len {
+            return None;
+        }
+        
+        Some(&tcp_data[header_len..])
+    }
+    
     fn process_udp_packet(
-        src_ip: IpAddr,
-        src_port: u16,
-        dst_ip: IpAddr,
-        dst_port: u16,
-        udp_data: &[u8],
+        (src_ip, src_port): (IpAddr, u16),
+        (dst_ip, dst_port): (IpAddr, u16),
         packet_size: usize,
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
+        context: &PacketContext,
+        geoip: Option<&GeoIpDatabase>,
     ) {
+        let PacketContext { rules, socket_index, active_connections, stats, event_handler, filtering_enabled, .. } = context;
         // Similar to TCP processing but for UDP
         let conn_key = ConnectionKey {
             protocol: 17, // UDP
@@ -594,6 +1099,7 @@ impl NetworkFilter {
         
         // Update connection tracking
         let mut new_connection = false;
+        let mut conn_geo = (None, None);
         if let Ok(mut connections) = active_connections.lock() {
             let now = Instant::now();
             
@@ -601,36 +1107,55 @@ impl NetworkFilter {
                 conn_info.last_seen = now;
                 conn_info.packets += 1;
                 conn_info.bytes += packet_size as u64;
+                conn_geo = (conn_info.source_geo.clone(), conn_info.dest_geo.clone());
             } else {
                 new_connection = true;
+                let initiator = !connections.contains_key(&conn_key.reversed());
+                conn_geo = Self::lookup_geo(geoip, src_ip, dst_ip);
                 connections.insert(conn_key.clone(), ConnectionInfo {
                     first_seen: now,
                     last_seen: now,
                     packets: 1,
                     bytes: packet_size as u64,
                     state: ConnectionState::New,
+                    tls_fingerprint: None,
+                    hostname: None,
+                    source_geo: conn_geo.0.clone(),
+                    dest_geo: conn_geo.1.clone(),
+                    initiator,
+                    process: None,
                 });
             }
         }
+        let (src_geo, dst_geo) = conn_geo;
+        let remote_geo = dst_geo.clone().or_else(|| src_geo.clone());
         
         if new_connection {
+            Self::attribute_connection(active_connections, socket_index, &conn_key, Protocol::Udp);
             event_handler(NetworkEvent::ConnectionNew {
                 timestamp: Instant::now(),
                 protocol: Protocol::Udp,
                 source: (src_ip, src_port),
                 destination: (dst_ip, dst_port),
+                geo: remote_geo.clone(),
             });
         }
         
         // Apply filtering rules
-        if filtering_enabled {
+        if *filtering_enabled {
             let action = Self::evaluate_rules(
-                &rules,
+                rules,
                 Protocol::Udp,
                 src_ip,
                 src_port,
                 dst_ip,
                 dst_port,
+                None,
+                None,
+                src_geo.as_ref(),
+                dst_geo.as_ref(),
+                Some(socket_index),
+                event_handler,
             );
             
             match action {
@@ -647,6 +1172,7 @@ impl NetworkFilter {
                         size: packet_size,
                         action: FilterAction::Block,
                         rule_id: Some(rule_id),
+                        geo: remote_geo.clone(),
                     });
                 }
                 _ => {
@@ -662,20 +1188,27 @@ impl NetworkFilter {
         src_ip: IpAddr,
         dst_ip: IpAddr,
         packet_size: usize,
-        rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
-        filtering_enabled: bool,
+        context: &PacketContext,
+        geoip: Option<&GeoIpDatabase>,
     ) {
+        let PacketContext { rules, stats, event_handler, filtering_enabled, .. } = context;
         // Apply filtering rules for ICMP
-        if filtering_enabled {
+        if *filtering_enabled {
+            let (src_geo, dst_geo) = Self::lookup_geo(geoip, src_ip, dst_ip);
+            let remote_geo = dst_geo.clone().or_else(|| src_geo.clone());
             let action = Self::evaluate_rules(
-                &rules,
+                rules,
                 Protocol::Icmp,
                 src_ip,
                 0,
                 dst_ip,
                 0,
+                None,
+                None,
+                src_geo.as_ref(),
+                dst_geo.as_ref(),
+                None,
+                event_handler,
             );
             
             match action {
@@ -692,6 +1225,7 @@ impl NetworkFilter {
                         size: packet_size,
                         action: FilterAction::Block,
                         rule_id: Some(rule_id),
+                        geo: remote_geo.clone(),
                     });
                 }
                 _ => {
@@ -705,55 +1239,30 @@ impl NetworkFilter {
     
     fn process_dns_packet(
         dns_data: &[u8],
-        src_ip: IpAddr,
-        dns_blacklist: &Arc<RwLock<HashSet<String>>>,
-        dns_whitelist: &Arc<RwLock<HashSet<String>>>,
-        dns_cache: &Arc<Mutex<HashMap<String, DnsCacheEntry>>>,
-        stats: &Arc<Mutex<NetworkStats>>,
-        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
+        source: (IpAddr, u16),
+        destination: (IpAddr, u16),
+        context: &PacketContext,
     ) {
-        // Basic DNS parsing (simplified)
-        if dns_data.len() < 12 {
-            return; // Too small for DNS header
-        }
+        let message = match dns::parse(dns_data) {
+            Some(message) => message,
+            None => return,
+        };
         
-        // Check if it's a query (QR bit = 0)
-        let flags = u16::from_be_bytes([dns_data[2], dns_data[3]]);
-        if (flags & 0x8000) != 0 {
-            return; // It's a response, not a query
+        if message.is_response {
+            Self::process_dns_response(&message, source.0, destination, context);
+            return;
         }
+        let PacketContext { dns_blacklist, dns_whitelist, dns_cache, tunnel_detector, stats, event_handler, .. } = context;
         
-        // Parse question section
-        let mut offset = 12;
-        let mut domain = String::new();
-        
-        // Read domain name
-        while offset < dns_data.len() {
-            let label_len = dns_data[offset] as usize;
-            if label_len == 0 {
-                offset += 1;
-                break;
-            }
-            
-            if !domain.is_empty() {
-                domain.push('.');
-            }
-            
-            offset += 1;
-            if offset + label_len > dns_data.len() {
-                return;
-            }
-            
-            if let Ok(label) = str::from_utf8(&dns_data[offset..offset + label_len]) {
-                domain.push_str(label);
+        let (domain, query_type) = match message.questions.first() {
+            Some(question) if !question.name.is_empty() => {
+                if let Some(detection) = tunnel_detector.observe_dns_query(source.0, destination.0, &question.name, question.qtype) {
+                    Self::report_tunnel(detection, stats, event_handler);
+                }
+                (question.name.clone(), dns::query_type_name(question.qtype))
             }
-            
-            offset += label_len;
-        }
-        
-        if domain.is_empty() {
-            return;
-        }
+            _ => return,
+        };
         
         // Update stats
         if let Ok(mut stats) = stats.lock() {
@@ -783,17 +1292,17 @@ impl NetworkFilter {
         event_handler(NetworkEvent::DnsQuery {
             timestamp: Instant::now(),
             domain: domain.clone(),
-            query_type: "A".to_string(), // Simplified
-            source: src_ip,
+            query_type,
+            source,
             action,
         });
         
         // Cache the domain for future reference
         if action != FilterAction::Block {
             if let Ok(mut cache) = dns_cache.lock() {
-                cache.insert(domain.clone(), DnsCacheEntry {
+                cache.entry(domain.clone()).or_insert_with(|| DnsCacheEntry {
                     domain,
-                    ips: Vec::new(), // Will be populated when response is seen
+                    ips: Vec::new(), // Populated when the response is seen
                     cached_at: Instant::now(),
                     ttl: Duration::from_secs(300),
                 });
@@ -801,6 +1310,163 @@ impl NetworkFilter {
         }
     }
     
+    fn process_dns_response(
+        message: &dns::DnsMessage,
+        resolver: IpAddr,
+        client: (IpAddr, u16),
+        context: &PacketContext,
+    ) {
+        let PacketContext { dns_whitelist, dns_cache, dns_flux_tracker, stats, event_handler, .. } = context;
+        let domain = match message.query_name() {
+            Some(domain) if !domain.is_empty() => domain.to_string(),
+            _ => return,
+        };
+        
+        if let Ok(mut stats) = stats.lock() {
+            stats.dns_responses += 1;
+        }
+        
+        // Addresses from the whole answer chain are attributed to the queried name
+        let ips = message.resolved_ips();
+        event_handler(NetworkEvent::DnsAnswer {
+            timestamp: Instant::now(),
+            domain: domain.clone(),
+            query_type: message.questions.first().map(|question| dns::query_type_name(question.qtype)).unwrap_or_default(),
+            answers: ips.clone(),
+            rcode: message.rcode,
+            resolver,
+            client,
+        });
+        if ips.is_empty() {
+            return;
+        }
+        let ttl = message.min_ttl().unwrap_or(0);
+        let now = Instant::now();
+        
+        if let Ok(mut cache) = dns_cache.lock() {
+            cache.insert(domain.clone(), DnsCacheEntry {
+                domain: domain.clone(),
+                ips: ips.clone(),
+                cached_at: now,
+                ttl: Duration::from_secs(ttl.max(1) as u64),
+            });
+        }
+        
+        let whitelisted = dns_whitelist.read()
+            .map(|whitelist| whitelist.contains(&domain))
+            .unwrap_or(false);
+        
+        // DNS rebinding: an external name answering with internal addresses
+        let internal: Vec<IpAddr> = ips.iter().copied().filter(|ip| dns::is_internal_address(*ip)).collect();
+        if !internal.is_empty() && !whitelisted && !dns::is_local_domain(&domain) {
+            warn!("Possible DNS rebinding: {} resolved to {:?} via {}", domain, internal, resolver);
+            
+            if let Ok(mut stats) = stats.lock() {
+                stats.dns_rebinding_detected += 1;
+            }
+            
+            event_handler(NetworkEvent::DnsRebinding {
+                timestamp: now,
+                domain: domain.clone(),
+                resolved_ips: internal,
+                resolver,
+                action: FilterAction::Block,
+            });
+        }
+        
+        if whitelisted {
+            return;
+        }
+        
+        let observation = dns_flux_tracker.lock()
+            .ok()
+            .and_then(|mut tracker| tracker.record(&domain, &ips, ttl, now));
+        
+        if let Some(flux) = observation {
+            warn!("Fast-flux behaviour for {}: {} addresses across {} networks, min TTL {}s",
+                  flux.domain, flux.unique_ips.len(), flux.unique_networks, flux.min_ttl);
+            
+            if let Ok(mut stats) = stats.lock() {
+                stats.dns_fast_flux_detected += 1;
+            }
+            
+            event_handler(NetworkEvent::DnsFastFlux {
+                timestamp: now,
+                domain: flux.domain,
+                unique_ips: flux.unique_ips,
+                unique_networks: flux.unique_networks,
+                min_ttl: flux.min_ttl,
+                observed_for: flux.observed_for,
+                resolver,
+            });
+        }
+    }
+    
+    fn report_tunnel(
+        detection: TunnelDetection,
+        stats: &Arc<Mutex<NetworkStats>>,
+        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
+    ) {
+        warn!("{}", detection.description);
+        if let Ok(mut stats) = stats.lock() {
+            stats.tunnels_detected += 1;
+        }
+        event_handler(NetworkEvent::Tunneling { timestamp: Instant::now(), detection });
+    }
+    
+    fn report_lan_alert(
+        alert: LanAlert,
+        stats: &Arc<Mutex<NetworkStats>>,
+        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
+    ) {
+        warn!("LAN anomaly: {}", alert.description);
+        if let Ok(mut stats) = stats.lock() {
+            stats.lan_alerts += 1;
+        }
+        event_handler(NetworkEvent::LanAnomaly { timestamp: Instant::now(), alert });
+    }
+    
+    // Records which process opened a new connection, for beacon analysis. The
+    // lookup may rescan /proc, so it runs without the connection table locked.
+    fn attribute_connection(
+        active_connections: &Arc<Mutex<HashMap<ConnectionKey, ConnectionInfo>>>,
+        socket_index: &SocketIndex,
+        conn_key: &ConnectionKey,
+        protocol: Protocol,
+    ) {
+        let initiator = active_connections.lock()
+            .map(|connections| connections.get(conn_key).is_some_and(|info| info.initiator))
+            .unwrap_or(false);
+        if !initiator {
+            return;
+        }
+        let protocol = if protocol == Protocol::Tcp { SocketProtocol::Tcp } else { SocketProtocol::Udp };
+        let Some(owner) = socket_index.owner_of(protocol, SocketAddr::new(conn_key.local_addr, conn_key.local_port)) else {
+            return;
+        };
+        if let Ok(mut connections) = active_connections.lock() {
+            if let Some(info) = connections.get_mut(conn_key) {
+                info.process = Some(owner);
+            }
+        }
+    }
+    
+    // Outbound packets come from a local socket, inbound ones are addressed to one
+    fn local_process(
+        socket_index: &SocketIndex,
+        protocol: Protocol,
+        source: (IpAddr, u16),
+        destination: (IpAddr, u16),
+    ) -> Option<SocketOwner> {
+        let protocol = match protocol {
+            Protocol::Tcp => SocketProtocol::Tcp,
+            Protocol::Udp => SocketProtocol::Udp,
+            Protocol::Icmp | Protocol::Any => return None,
+        };
+        socket_index.owner_of(protocol, SocketAddr::from(source))
+            .or_else(|| socket_index.owner_of(protocol, SocketAddr::from(destination)))
+    }
+    
     fn evaluate_rules(
         rules: &Arc<RwLock<Vec<NetworkFilterRule>>>,
         protocol: Protocol,
@@ -808,22 +1474,51 @@ impl NetworkFilter {
         src_port: u16,
         dst_ip: IpAddr,
         dst_port: u16,
+        tls_fingerprint: Option<&str>,
+        hostname: Option<&str>,
+        src_geo: Option<&GeoIpInfo>,
+        dst_geo: Option<&GeoIpInfo>,
+        socket_index: Option<&SocketIndex>,
+        event_handler: &Arc<dyn Fn(NetworkEvent) + Send + Sync>,
     ) -> Option<(FilterAction, String)> {
         let rules = match rules.read() {
             Ok(r) => r,
             Err(_) => return None,
         };
         
+        // The local process is only looked up when a rule asks for it
+        let process = match socket_index {
+            Some(index) if rules.iter().any(|rule| rule.enabled && rule.process.is_some()) => {
+                Self::local_process(index, protocol, (src_ip, src_port), (dst_ip, dst_port))
+            }
+            _ => None,
+        };
+        
         // Sort by priority and evaluate
         let mut matching_rules: Vec<_> = rules
             .iter()
             .filter(|rule| rule.enabled && Self::rule_matches(
-                rule, protocol, src_ip, src_port, dst_ip, dst_port
+                rule, protocol, src_ip, src_port, dst_ip, dst_port, tls_fingerprint, hostname, src_geo, dst_geo,
+                process.as_ref().map(|owner| owner.name.as_str()),
             ))
             .collect();
         
         matching_rules.sort_by_key(|r| -r.priority); // Higher priority first
         
+        // Every shadow rule that matched is reported, of the others only
+        // the one that decides
+        let (shadow_rules, matching_rules): (Vec<_>, Vec<_>) = matching_rules.into_iter().partition(|rule| rule.shadow);
+        for rule in shadow_rules.iter().chain(matching_rules.first()) {
+            event_handler(NetworkEvent::RuleMatched {
+                timestamp: Instant::now(),
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                action: rule.action,
+                packet_info: format!("{:?} {} -> {}", protocol, SocketAddr::new(src_ip, src_port), SocketAddr::new(dst_ip, dst_port)),
+                shadow: rule.shadow,
+            });
+        }
+        
         if let Some(rule) = matching_rules.first() {
             Some((rule.action, rule.id.clone()))
         } else {
@@ -838,6 +1533,11 @@ impl NetworkFilter {
         src_port: u16,
         dst_ip: IpAddr,
         dst_port: u16,
+        tls_fingerprint: Option<&str>,
+        hostname: Option<&str>,
+        src_geo: Option<&GeoIpInfo>,
+        dst_geo: Option<&GeoIpInfo>,
+        process: Option<&str>,
     ) -> bool {
         // Check protocol
         if let Some(rule_protocol) = rule.protocol {
@@ -848,14 +1548,14 @@ impl NetworkFilter {
         
         // Check source IP
         if let Some(ref matcher) = rule.source_ip {
-            if !Self::ip_matches(matcher, src_ip) {
+            if !Self::ip_matches(matcher, src_ip, src_geo) {
                 return false;
             }
         }
         
         // Check destination IP
         if let Some(ref matcher) = rule.dest_ip {
-            if !Self::ip_matches(matcher, dst_ip) {
+            if !Self::ip_matches(matcher, dst_ip, dst_geo) {
                 return false;
             }
         }
@@ -874,10 +1574,52 @@ impl NetworkFilter {
             }
         }
         
+        // Check TLS fingerprint
+        if let Some(ref fingerprints) = rule.tls_fingerprints {
+            match tls_fingerprint {
+                Some(fp) if fingerprints.iter().any(|f| f.eq_ignore_ascii_case(fp)) => {}
+                _ => return false,
+            }
+        }
+        
+        // Check destination hostname
+        if let Some(ref matcher) = rule.dest_hostname {
+            match hostname {
+                Some(host) if Self::hostname_matches(matcher, host) => {}
+                _ => return false,
+            }
+        }
+        
+        // Check the process owning the local end
+        if let Some(ref name) = rule.process {
+            if process != Some(name.as_str()) {
+                return false;
+            }
+        }
+        
         true
     }
     
-    fn ip_matches(matcher: &IpMatcher, ip: IpAddr) -> bool {
+    fn hostname_matches(matcher: &HostnameMatcher, hostname: &str) -> bool {
+        match matcher {
+            HostnameMatcher::Exact(name) => hostname.eq_ignore_ascii_case(name),
+            HostnameMatcher::Suffix(suffix) => {
+                let hostname = hostname.to_lowercase();
+                let suffix = suffix.trim_start_matches('.').to_lowercase();
+                hostname == suffix || hostname.ends_with(&format!(".{}", suffix))
+            }
+            HostnameMatcher::Any => true,
+        }
+    }
+    
+    fn lookup_geo(geoip: Option<&GeoIpDatabase>, src_ip: IpAddr, dst_ip: IpAddr) -> (Option<GeoIpInfo>, Option<GeoIpInfo>) {
+        match geoip {
+            Some(db) => (db.lookup(src_ip), db.lookup(dst_ip)),
+            None => (None, None),
+        }
+    }
+    
+    fn ip_matches(matcher: &IpMatcher, ip: IpAddr, geo: Option<&GeoIpInfo>) -> bool {
         match matcher {
             IpMatcher::Single(match_ip) => ip == *match_ip,
             IpMatcher::Range(start, end) => {
@@ -894,6 +1636,13 @@ impl NetworkFilter {
             IpMatcher::Subnet(network, prefix_len) => {
                 Self::ip_in_subnet(ip, *network, *prefix_len)
             }
+            IpMatcher::Country(countries) => {
+                geo.and_then(|g| g.country.as_deref())
+                    .is_some_and(|country| countries.iter().any(|c| c.eq_ignore_ascii_case(country)))
+            }
+            IpMatcher::Asn(asns) => {
+                geo.and_then(|g| g.asn).is_some_and(|asn| asns.contains(&asn))
+            }
             IpMatcher::Any => true,
         }
     }
@@ -943,6 +1692,11 @@ impl NetworkFilter {
     
     fn start_cleanup_thread(&self) {
         let active_connections = Arc::clone(&self.active_connections);
+        let dns_cache = Arc::clone(&self.dns_cache);
+        let dns_flux_tracker = Arc::clone(&self.dns_flux_tracker);
+        let beacon_tracker = Arc::clone(&self.beacon_tracker);
+        let tunnel_detector = Arc::clone(&self.tunnel_detector);
+        let stats = Arc::clone(&self.stats);
         let event_handler = Arc::clone(&self.event_handler);
         let running = Arc::clone(&self.running);
         
@@ -950,7 +1704,17 @@ impl NetworkFilter {
             while *running.lock().unwrap() {
                 thread::sleep(Duration::from_secs(30));
                 
+                // Expire DNS answers past their TTL
+                if let Ok(mut cache) = dns_cache.lock() {
+                    cache.retain(|_, entry| entry.cached_at.elapsed() <= entry.ttl);
+                }
+                if let Ok(mut tracker) = dns_flux_tracker.lock() {
+                    tracker.prune(Instant::now());
+                }
+                tunnel_detector.prune();
+                
                 // Clean up old connections
+                let mut finished = Vec::new();
                 if let Ok(mut connections) = active_connections.lock() {
                     let now = Instant::now();
                     let timeout = Duration::from_secs(300); // 5 minute timeout
@@ -964,6 +1728,19 @@ impl NetworkFilter {
                     for (key, info) in expired {
                         connections.remove(&key);
                         
+                        if info.initiator {
+                            let started = chrono::Utc::now() - chrono::Duration::from_std(now.duration_since(info.first_seen)).unwrap_or_default();
+                            finished.push(BeaconSample {
+                                pid: info.process.as_ref().map(|owner| owner.pid),
+                                process: info.process.as_ref().map(|owner| owner.name.clone()),
+                                destination: key.remote_addr,
+                                port: key.remote_port,
+                                hostname: info.hostname.clone(),
+                                started_at: started,
+                                bytes: info.bytes,
+                            });
+                        }
+                        
                         event_handler(NetworkEvent::ConnectionClosed {
                             timestamp: now,
                             protocol: match key.protocol {
@@ -976,11 +1753,61 @@ impl NetworkFilter {
                             duration: now.duration_since(info.first_seen),
                             packets: info.packets,
                             bytes: info.bytes,
+                            geo: info.remote_geo(),
+                            hostname: info.hostname,
                         });
                     }
                 }
+                
+                // Look for beacons among the connections that just finished
+                let detections: Vec<_> = match beacon_tracker.lock() {
+                    Ok(mut tracker) => {
+                        tracker.prune(chrono::Utc::now());
+                        finished.into_iter().filter_map(|sample| tracker.record(sample)).collect()
+                    }
+                    Err(_) => Vec::new(),
+                };
+                for detection in detections {
+                    warn!("Possible C2 beacon: {}", detection.description());
+                    if let Ok(mut stats) = stats.lock() {
+                        stats.beacons_detected += 1;
+                    }
+                    event_handler(NetworkEvent::Beaconing { timestamp: Instant::now(), detection });
+                }
+            }
+        });
+    }
+    
+    // Periodically exports per-connection traffic deltas as NetFlow v9/IPFIX;
+    // runs until the filter is stopped
+    pub fn start_flow_export(&self, config: FlowExportConfig) -> Result<()> {
+        let mut exporter = FlowExporter::new(&config)?;
+        let table = self.connection_table();
+        let running = Arc::clone(&self.running);
+        
+        thread::spawn(move || {
+            while *running.lock().unwrap() {
+                thread::sleep(exporter.interval());
+                
+                if let Err(e) = exporter.export(table.flows()) {
+                    warn!("Flow export failed: {}", e);
+                }
             }
         });
+        
+        Ok(())
+    }
+    
+    // Shared with the API for the ARP table, DHCP servers and alerts
+    pub fn lan_monitor(&self) -> Arc<LanMonitor> {
+        Arc::clone(&self.lan_monitor)
+    }
+    
+    pub fn connection_table(&self) -> ConnectionTable {
+        ConnectionTable {
+            connections: Arc::clone(&self.active_connections),
+            beacons: Arc::clone(&self.beacon_tracker),
+        }
     }
     
     pub fn stop(&mut self) -> Result<()> {
@@ -992,6 +1819,9 @@ impl NetworkFilter {
             *running = false;
         }
         
+        if let Some(capture_set) = self.capture_set.take() {
+            capture_set.stop();
+        }
         self.capture_enabled = false;
         info!("Network filter stopped");
         Ok(())
@@ -999,26 +1829,35 @@ impl NetworkFilter {
     
     // Rule management
     pub fn add_rule(&self, rule: NetworkFilterRule) -> Result<()> {
-        let mut rules = self.rules.write()
-            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
-        rules.push(rule);
-        Ok(())
+        {
+            let mut rules = self.rules.write()
+                .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+            rules.push(rule);
+        }
+        self.sync_rule_stats()?;
+        self.refresh_capture_filter()
     }
     
     pub fn remove_rule(&self, rule_id: &str) -> Result<()> {
-        let mut rules = self.rules.write()
-            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
-        rules.retain(|r| r.id != rule_id);
-        Ok(())
+        {
+            let mut rules = self.rules.write()
+                .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+            rules.retain(|r| r.id != rule_id);
+        }
+        self.sync_rule_stats()?;
+        self.refresh_capture_filter()
     }
     
     pub fn update_rule(&self, rule_id: &str, updated_rule: NetworkFilterRule) -> Result<()> {
-        let mut rules = self.rules.write()
-            .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
-        if let Some(rule) = rules.iter_mut().find(|r| r.id == rule_id) {
-            *rule = updated_rule;
+        {
+            let mut rules = self.rules.write()
+                .map_err(|_| anyhow!("Failed to acquire rules write lock"))?;
+            if let Some(rule) = rules.iter_mut().find(|r| r.id == rule_id) {
+                *rule = updated_rule;
+            }
         }
-        Ok(())
+        self.sync_rule_stats()?;
+        self.refresh_capture_filter()
     }
     
     pub fn get_rules(&self) -> Result<Vec<NetworkFilterRule>> {
@@ -1049,6 +1888,39 @@ impl NetworkFilter {
         Ok(())
     }
     
+    // TLS fingerprint threat intel
+    pub fn add_tls_fingerprint_blacklist(&self, fingerprint: String, description: String) -> Result<()> {
+        {
+            let mut blacklist = self.tls_fingerprint_blacklist.write()
+                .map_err(|_| anyhow!("Failed to acquire TLS fingerprint blacklist write lock"))?;
+            blacklist.insert(fingerprint.to_lowercase(), description);
+        }
+        self.refresh_capture_filter()
+    }
+    
+    pub fn remove_tls_fingerprint_blacklist(&self, fingerprint: &str) -> Result<()> {
+        {
+            let mut blacklist = self.tls_fingerprint_blacklist.write()
+                .map_err(|_| anyhow!("Failed to acquire TLS fingerprint blacklist write lock"))?;
+            blacklist.remove(&fingerprint.to_lowercase());
+        }
+        self.refresh_capture_filter()
+    }
+    
+    // Enables country/ASN enrichment and IpMatcher::Country/Asn rules; None disables it
+    pub fn set_geoip_database(&self, database: Option<GeoIpDatabase>) -> Result<()> {
+        let mut geoip = self.geoip.write()
+            .map_err(|_| anyhow!("Failed to acquire GeoIP write lock"))?;
+        *geoip = database.map(Arc::new);
+        Ok(())
+    }
+    
+    pub fn get_tls_fingerprint_blacklist(&self) -> Result<HashMap<String, String>> {
+        let blacklist = self.tls_fingerprint_blacklist.read()
+            .map_err(|_| anyhow!("Failed to acquire TLS fingerprint blacklist read lock"))?;
+        Ok(blacklist.clone())
+    }
+    
     pub fn get_stats(&self) -> Result<NetworkStats> {
         let stats = self.stats.lock()
             .map_err(|_| anyhow!("Failed to acquire stats lock"))?;
@@ -1071,6 +1943,82 @@ impl NetworkFilter {
     }
 }
 
+// BPF expression that only lets through traffic the rules can act on, plus DNS
+// and (with a fingerprint blacklist) TLS. Returns None when some rule can match
+// any packet, since nothing can then be filtered out in the kernel. Country and
+// ASN matchers and address ranges cannot be expressed and are left open.
+pub fn build_capture_filter(rules: &[NetworkFilterRule], tls_blacklist: bool) -> Option<String> {
+    // DNS telemetry and filtering always needs port 53
+    let mut clauses = vec!["port 53".to_string()];
+    if tls_blacklist {
+        clauses.push("tcp port 443".to_string());
+    }
+    
+    for rule in rules.iter().filter(|r| r.enabled) {
+        let mut parts = Vec::new();
+        match rule.protocol {
+            Some(Protocol::Tcp) => parts.push("tcp".to_string()),
+            Some(Protocol::Udp) => parts.push("udp".to_string()),
+            Some(Protocol::Icmp) => parts.push("(icmp or icmp6)".to_string()),
+            Some(Protocol::Any) | None => {}
+        }
+        parts.extend(rule.source_ip.as_ref().and_then(|m| ip_filter("src", m)));
+        parts.extend(rule.dest_ip.as_ref().and_then(|m| ip_filter("dst", m)));
+        if rule.protocol != Some(Protocol::Icmp) {
+            parts.extend(rule.source_port.as_ref().and_then(|m| port_filter("src", m)));
+            parts.extend(rule.dest_port.as_ref().and_then(|m| port_filter("dst", m)));
+        }
+        if rule.dest_hostname.is_some() && rule.dest_port.is_none() {
+            // Hostnames are only seen via HTTP Host and TLS SNI on the standard ports
+            parts.push("(tcp port 80 or tcp port 443)".to_string());
+        } else if rule.tls_fingerprints.is_some() && rule.protocol.is_none() {
+            parts.push("tcp".to_string());
+        }
+        
+        if parts.is_empty() {
+            return None;
+        }
+        clauses.push(parts.join(" and "));
+    }
+    
+    Some(clauses.iter().map(|c| format!("({})", c)).collect::<Vec<_>>().join(" or "))
+}
+
+fn ip_filter(qualifier: &str, matcher: &IpMatcher) -> Option<String> {
+    match matcher {
+        IpMatcher::Single(ip) => Some(format!("{} host {}", qualifier, ip)),
+        IpMatcher::Subnet(network, prefix) => {
+            // libpcap rejects networks with host bits set
+            let network = match network {
+                IpAddr::V4(v4) => {
+                    let prefix = (*prefix).min(32) as u32;
+                    let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+                    IpAddr::V4(Ipv4Addr::from(u32::from(*v4) & mask))
+                }
+                IpAddr::V6(v6) => {
+                    let prefix = (*prefix).min(128) as u32;
+                    let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+                    IpAddr::V6(Ipv6Addr::from(u128::from(*v6) & mask))
+                }
+            };
+            Some(format!("{} net {}/{}", qualifier, network, prefix))
+        }
+        IpMatcher::Range(..) | IpMatcher::Country(_) | IpMatcher::Asn(_) | IpMatcher::Any => None,
+    }
+}
+
+fn port_filter(qualifier: &str, matcher: &PortMatcher) -> Option<String> {
+    match matcher {
+        PortMatcher::Single(port) => Some(format!("{} port {}", qualifier, port)),
+        PortMatcher::Range(start, end) => Some(format!("{} portrange {}-{}", qualifier, start, end)),
+        PortMatcher::List(ports) if !ports.is_empty() => Some(format!(
+            "({})",
+            ports.iter().map(|port| format!("{} port {}", qualifier, port)).collect::<Vec<_>>().join(" or ")
+        )),
+        PortMatcher::List(_) | PortMatcher::Any => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1084,6 +2032,23 @@ mod tests {
         assert!(!NetworkFilter::ip_in_subnet(ip, network, 32));
     }
     
+    #[test]
+    fn test_country_and_asn_matching() {
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+        let geo = GeoIpInfo {
+            country: Some("KP".to_string()),
+            country_name: None,
+            asn: Some(64500),
+            as_organization: None,
+        };
+        
+        let country = IpMatcher::Country(vec!["IR".to_string(), "kp".to_string()]);
+        assert!(NetworkFilter::ip_matches(&country, ip, Some(&geo)));
+        assert!(!NetworkFilter::ip_matches(&country, ip, None));
+        assert!(NetworkFilter::ip_matches(&IpMatcher::Asn(vec![64500]), ip, Some(&geo)));
+        assert!(!NetworkFilter::ip_matches(&IpMatcher::Asn(vec![64501]), ip, Some(&geo)));
+    }
+    
     #[test]
     fn test_port_matching() {
         let matcher = PortMatcher::Range(80, 443);
@@ -1092,4 +2057,182 @@ mod tests {
         assert!(NetworkFilter::port_matches(&matcher, 200));
         assert!(!NetworkFilter::port_matches(&matcher, 444));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_hostname_extraction_and_matching() {
+        let request = b"GET /beacon HTTP/1.1\r\nUser-Agent: x\r\nHost: Evil.Example.com:8080\r\n\r\n";
+        let host = NetworkFilter::extract_http_host(request).unwrap();
+        assert_eq!(host, "evil.example.com");
+        assert!(NetworkFilter::extract_http_host(b"\x16\x03\x01\x00").is_none());
+        
+        let matcher = HostnameMatcher::Suffix("example.com".to_string());
+        assert!(NetworkFilter::hostname_matches(&matcher, &host));
+        assert!(NetworkFilter::hostname_matches(&matcher, "example.com"));
+        assert!(!NetworkFilter::hostname_matches(&matcher, "notexample.com"));
+    }
+    
+    #[test]
+    fn test_process_rule_matching() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        let comm = std::fs::read_to_string("/proc/self/comm").unwrap().trim().to_string();
+        let rule = |process: &str| NetworkFilterRule {
+            id: process.to_string(),
+            name: format!("Block {}", process),
+            direction: Direction::Inbound,
+            action: FilterAction::Block,
+            protocol: Some(Protocol::Tcp),
+            source_ip: None,
+            dest_ip: None,
+            source_port: None,
+            dest_port: None,
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: Some(process.to_string()),
+            priority: 10,
+            enabled: true,
+            shadow: false,
+        };
+        let rules = Arc::new(RwLock::new(vec![rule("not-this-process"), rule(&comm)]));
+        let index = SocketIndex::new();
+        let matched = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&matched);
+        let handler: Arc<dyn Fn(NetworkEvent) + Send + Sync> = Arc::new(move |event| {
+            if let NetworkEvent::RuleMatched { rule_id, shadow: true, .. } = event {
+                recorder.lock().unwrap().push(rule_id);
+            }
+        });
+        let evaluate = |protocol, index| NetworkFilter::evaluate_rules(
+            &rules, protocol, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 40000, local.ip(), local.port(),
+            None, None, None, None, index, &handler,
+        );
+        
+        // Inbound to our listener is attributed to us
+        assert_eq!(evaluate(Protocol::Tcp, Some(&index)), Some((FilterAction::Block, comm.clone())));
+        assert_eq!(evaluate(Protocol::Udp, Some(&index)), None);
+        assert_eq!(evaluate(Protocol::Tcp, None), None);
+        
+        // A shadow rule only reports that it would have blocked
+        rules.write().unwrap()[1].shadow = true;
+        assert_eq!(evaluate(Protocol::Tcp, Some(&index)), None);
+        assert_eq!(*matched.lock().unwrap(), vec![comm.clone()]);
+    }
+
+    #[test]
+    fn test_ipv6_packets_are_filtered() {
+        let filter = NetworkFilter::new(|_| {}).unwrap();
+        filter.add_rule(NetworkFilterRule {
+            id: "mdns".to_string(),
+            name: "Block mDNS".to_string(),
+            direction: Direction::Outbound,
+            action: FilterAction::Block,
+            protocol: Some(Protocol::Udp),
+            source_ip: None,
+            dest_ip: None,
+            source_port: None,
+            dest_port: Some(PortMatcher::Single(5353)),
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: None,
+            priority: 10,
+            enabled: true,
+            shadow: false,
+        }).unwrap();
+        let matched = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&matched);
+        let context = PacketContext {
+            event_handler: Arc::new(move |event| {
+                if let NetworkEvent::RuleMatched { rule_id, .. } = event {
+                    recorder.lock().unwrap().push(rule_id);
+                }
+            }),
+            ..filter.packet_context()
+        };
+
+        // fe80::1 -> ff02::fb behind an extension header, then UDP 5353
+        let packet = |next_header: u8, extension: [u8; 8]| {
+            let mut packet = vec![0x60, 0, 0, 0, 0, 16, next_header, 255];
+            packet.extend_from_slice(&"fe80::1".parse::<Ipv6Addr>().unwrap().octets());
+            packet.extend_from_slice(&"ff02::fb".parse::<Ipv6Addr>().unwrap().octets());
+            packet.extend_from_slice(&extension);
+            packet.extend_from_slice(&[0x14, 0xe9, 0x14, 0xe9, 0, 8, 0, 0]);
+            packet
+        };
+        NetworkFilter::process_packet(&packet(0, [17, 0, 0, 0, 0, 0, 0, 0]), &context, None);
+        assert_eq!(*matched.lock().unwrap(), vec!["mdns".to_string()]);
+
+        // A later fragment has no UDP header to match
+        NetworkFilter::process_packet(&packet(44, [17, 0, 0, 8, 0, 0, 0, 0]), &context, None);
+        assert_eq!(matched.lock().unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_incomplete_tls_hello_fails_closed() {
+        let filter = NetworkFilter::new(|_| {}).unwrap();
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&actions);
+        let context = PacketContext {
+            event_handler: Arc::new(move |event| {
+                if let NetworkEvent::TlsHelloIncomplete { action, .. } = event {
+                    recorder.lock().unwrap().push(action);
+                }
+            }),
+            ..filter.packet_context()
+        };
+
+        // A hello announcing 3000 bytes whose second segment never arrives
+        let segment = |seq: u32, payload: &[u8]| {
+            let mut tcp = vec![0xc3, 0x50, 0x01, 0xbb];
+            tcp.extend_from_slice(&seq.to_be_bytes());
+            tcp.extend_from_slice(&[0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+            tcp.extend_from_slice(payload);
+            tcp
+        };
+        let mut first = vec![0x16, 0x03, 0x01, 0x0b, 0xb8, 0x01, 0x00, 0x0b, 0xb4];
+        first.resize(1000, 0);
+        let send = |context: &PacketContext, tcp: Vec<u8>| NetworkFilter::process_tcp_packet(
+            (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 50000),
+            (IpAddr::V4(Ipv4Addr::new(203, 0, 113, 9)), 443),
+            &tcp, tcp.len(), context, None,
+        );
+
+        send(&context, segment(1, &first));
+        send(&context, segment(2001, &[0; 1000]));
+        assert_eq!(*actions.lock().unwrap(), vec![FilterAction::Allow]);
+
+        // With hostnames to block, a hello whose SNI can't be read is blocked
+        filter.add_dns_blacklist("c2.example.com".to_string()).unwrap();
+        send(&context, segment(1, &first));
+        send(&context, segment(2001, &[0; 1000]));
+        assert_eq!(*actions.lock().unwrap(), vec![FilterAction::Allow, FilterAction::Block]);
+    }
+    
+    #[test]
+    fn test_capture_filter_from_rules() {
+        let rule = NetworkFilterRule {
+            id: "ssh".to_string(),
+            name: "Block SSH from lab".to_string(),
+            direction: Direction::Inbound,
+            action: FilterAction::Block,
+            protocol: Some(Protocol::Tcp),
+            source_ip: Some(IpMatcher::Subnet(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)), 16)),
+            dest_ip: None,
+            source_port: None,
+            dest_port: Some(PortMatcher::List(vec![22, 2222])),
+            tls_fingerprints: None,
+            dest_hostname: None,
+            process: None,
+            priority: 10,
+            enabled: true,
+            shadow: false,
+        };
+        assert_eq!(
+            build_capture_filter(&[rule.clone()], true).unwrap(),
+            "(port 53) or (tcp port 443) or (tcp and src net 10.1.0.0/16 and (dst port 22 or dst port 2222))"
+        );
+        
+        // A rule that can match anything disables pre-filtering
+        let catch_all = NetworkFilterRule { protocol: None, source_ip: None, dest_port: None, ..rule };
+        assert!(build_capture_filter(&[catch_all], false).is_none());
+    }
+}
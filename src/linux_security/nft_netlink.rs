@@ -0,0 +1,857 @@
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use libc::{c_int, sockaddr_nl};
+use tracing::debug;
+
+// Native nf_tables programming over NETLINK_NETFILTER, the same messages
+// libnftnl builds (linux/netfilter/nf_tables.h, linux/netfilter/nfnetlink.h)
+
+const NETLINK_NETFILTER: c_int = 12;
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+const NFNL_MSG_BATCH_BEGIN: u16 = 0x10;
+const NFNL_MSG_BATCH_END: u16 = 0x11;
+
+const NLMSG_HDR_LEN: usize = 16;
+const NFGENMSG_LEN: usize = 4;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_ECHO: u16 = 0x8;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_CREATE: u16 = 0x400;
+const NLM_F_APPEND: u16 = 0x800;
+const NLA_F_NESTED: u16 = 0x8000;
+
+const NFT_MSG_NEWTABLE: u16 = 0;
+const NFT_MSG_GETTABLE: u16 = 1;
+const NFT_MSG_DELTABLE: u16 = 2;
+const NFT_MSG_NEWCHAIN: u16 = 3;
+const NFT_MSG_NEWRULE: u16 = 6;
+const NFT_MSG_GETRULE: u16 = 7;
+const NFT_MSG_DELRULE: u16 = 8;
+const NFT_MSG_NEWSET: u16 = 9;
+const NFT_MSG_NEWSETELEM: u16 = 12;
+const NFT_MSG_DELSETELEM: u16 = 14;
+
+const NFTA_TABLE_NAME: u16 = 1;
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_POLICY: u16 = 5;
+const NFTA_CHAIN_TYPE: u16 = 7;
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+const NFTA_RULE_TABLE: u16 = 1;
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_HANDLE: u16 = 3;
+const NFTA_RULE_EXPRESSIONS: u16 = 4;
+const NFTA_RULE_USERDATA: u16 = 7;
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+const NFTA_SET_TABLE: u16 = 1;
+const NFTA_SET_NAME: u16 = 2;
+const NFTA_SET_FLAGS: u16 = 3;
+const NFTA_SET_KEY_TYPE: u16 = 4;
+const NFTA_SET_KEY_LEN: u16 = 5;
+const NFTA_SET_ID: u16 = 10;
+const NFTA_SET_TIMEOUT: u16 = 11;
+const NFTA_SET_ELEM_LIST_TABLE: u16 = 1;
+const NFTA_SET_ELEM_LIST_SET: u16 = 2;
+const NFTA_SET_ELEM_LIST_ELEMENTS: u16 = 3;
+const NFTA_SET_ELEM_KEY: u16 = 1;
+const NFTA_SET_ELEM_TIMEOUT: u16 = 4;
+const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_DATA_VERDICT: u16 = 2;
+const NFTA_VERDICT_CODE: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
+
+// Comment TLV inside rule userdata, as written by nft
+const NFTNL_UDATA_RULE_COMMENT: u8 = 0;
+
+pub const NFPROTO_INET: u8 = 1;
+pub const NFPROTO_IPV4: u8 = 2;
+pub const NFPROTO_IPV6: u8 = 10;
+
+pub const NF_INET_LOCAL_IN: u32 = 1;
+pub const NF_INET_FORWARD: u32 = 2;
+pub const NF_INET_LOCAL_OUT: u32 = 3;
+
+// Legacy 16-byte register; enough for everything built here
+pub const NFT_REG_1: u32 = 1;
+
+pub const NFT_META_MARK: u32 = 3;
+pub const NFT_META_NFPROTO: u32 = 15;
+pub const NFT_META_L4PROTO: u32 = 16;
+pub const NFT_CT_STATE: u32 = 0;
+pub const NFT_PAYLOAD_NETWORK_HEADER: u32 = 1;
+pub const NFT_PAYLOAD_TRANSPORT_HEADER: u32 = 2;
+
+pub const NFT_REJECT_ICMPX_UNREACH: u32 = 2;
+pub const NFT_REJECT_ICMPX_PORT_UNREACH: u8 = 1;
+
+// Elements may carry a timeout and expire on their own
+pub const NFT_SET_TIMEOUT: u32 = 0x10;
+
+// Keeps each element list well under the 64KiB attribute length limit
+const MAX_ELEMENTS_PER_MESSAGE: usize = 1024;
+
+// Element key types understood by nft when listing sets
+pub const NFT_TYPE_IPADDR: u32 = 7;
+pub const NFT_TYPE_IP6ADDR: u32 = 8;
+pub const NFT_TYPE_INET_SERVICE: u32 = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Drop,
+    Return,
+}
+
+impl Verdict {
+    fn code(self) -> i32 {
+        match self {
+            Verdict::Drop => 0,
+            Verdict::Accept => 1,
+            Verdict::Return => -5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq = 0,
+    Neq = 1,
+}
+
+// The subset of nf_tables expressions NetfilterManager compiles rules into
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Meta { key: u32, dreg: u32 },
+    Ct { key: u32, dreg: u32 },
+    Payload { base: u32, offset: u32, len: u32, dreg: u32 },
+    Cmp { sreg: u32, op: CmpOp, data: Vec<u8> },
+    Range { sreg: u32, from: Vec<u8>, to: Vec<u8> },
+    Bitwise { sreg: u32, dreg: u32, mask: Vec<u8>, xor: Vec<u8> },
+    Lookup { set: String, sreg: u32 },
+    Verdict(Verdict),
+    // Loads a constant into a register
+    Immediate { dreg: u32, data: Vec<u8> },
+    Limit { rate: u64, unit_secs: u64, burst: u32 },
+    Log { prefix: String, level: u32 },
+    Reject { kind: u32, code: u8 },
+    Redirect { proto_min_reg: u32 },
+    Counter,
+}
+
+pub struct ChainHook {
+    pub chain_type: &'static str,
+    pub hooknum: u32,
+    pub priority: i32,
+    pub policy: Verdict,
+}
+
+// One atomic transaction. Nothing is applied unless every message in it is.
+pub struct NftBatch {
+    family: u8,
+    messages: Vec<Vec<u8>>,
+    // Message index of each added rule, in order, for collecting handles
+    rule_messages: Vec<usize>,
+    next_set_id: u32,
+}
+
+impl NftBatch {
+    pub fn new(family: u8) -> Self {
+        Self { family, messages: Vec::new(), rule_messages: Vec::new(), next_set_id: 1 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn add_table(&mut self, table: &str) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_NEWTABLE, NLM_F_CREATE, self.family);
+        msg.put_str(NFTA_TABLE_NAME, table);
+        self.push(msg)
+    }
+
+    pub fn delete_table(&mut self, table: &str) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_DELTABLE, 0, self.family);
+        msg.put_str(NFTA_TABLE_NAME, table);
+        self.push(msg)
+    }
+
+    pub fn add_chain(&mut self, table: &str, chain: &str, hook: Option<&ChainHook>) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_NEWCHAIN, NLM_F_CREATE, self.family);
+        msg.put_str(NFTA_CHAIN_TABLE, table);
+        msg.put_str(NFTA_CHAIN_NAME, chain);
+        if let Some(hook) = hook {
+            let nest = msg.nest_start(NFTA_CHAIN_HOOK);
+            msg.put_u32(NFTA_HOOK_HOOKNUM, hook.hooknum);
+            msg.put_u32(NFTA_HOOK_PRIORITY, hook.priority as u32);
+            msg.nest_end(nest);
+            msg.put_u32(NFTA_CHAIN_POLICY, hook.policy.code() as u32);
+            msg.put_str(NFTA_CHAIN_TYPE, hook.chain_type);
+        }
+        self.push(msg)
+    }
+
+    // Appends a rule; its handle is reported by `NftNetlink::commit`
+    pub fn add_rule(&mut self, table: &str, chain: &str, exprs: &[Expr], comment: Option<&str>) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_NEWRULE, NLM_F_CREATE | NLM_F_APPEND | NLM_F_ECHO, self.family);
+        msg.put_str(NFTA_RULE_TABLE, table);
+        msg.put_str(NFTA_RULE_CHAIN, chain);
+        let list = msg.nest_start(NFTA_RULE_EXPRESSIONS);
+        for expr in exprs {
+            msg.put_expr(expr);
+        }
+        msg.nest_end(list);
+        if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+            let mut value = comment.as_bytes().to_vec();
+            value.truncate(127);
+            value.push(0);
+            let mut userdata = vec![NFTNL_UDATA_RULE_COMMENT, value.len() as u8];
+            userdata.extend_from_slice(&value);
+            msg.put(NFTA_RULE_USERDATA, &userdata);
+        }
+        self.rule_messages.push(self.messages.len());
+        self.push(msg)
+    }
+
+    pub fn delete_rule(&mut self, table: &str, chain: &str, handle: u64) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_DELRULE, 0, self.family);
+        msg.put_str(NFTA_RULE_TABLE, table);
+        msg.put_str(NFTA_RULE_CHAIN, chain);
+        msg.put(NFTA_RULE_HANDLE, &handle.to_be_bytes());
+        self.push(msg)
+    }
+
+    // `default_timeout` applies to elements added without their own; it
+    // requires NFT_SET_TIMEOUT in `flags`
+    pub fn add_set(
+        &mut self,
+        table: &str,
+        set: &str,
+        key_type: u32,
+        key_len: u32,
+        flags: u32,
+        default_timeout: Option<Duration>,
+    ) -> &mut Self {
+        let mut msg = Message::new(NFT_MSG_NEWSET, NLM_F_CREATE, self.family);
+        msg.put_str(NFTA_SET_TABLE, table);
+        msg.put_str(NFTA_SET_NAME, set);
+        msg.put_u32(NFTA_SET_FLAGS, flags);
+        msg.put_u32(NFTA_SET_KEY_TYPE, key_type);
+        msg.put_u32(NFTA_SET_KEY_LEN, key_len);
+        msg.put_u32(NFTA_SET_ID, self.next_set_id);
+        if let Some(timeout) = default_timeout {
+            msg.put_u64(NFTA_SET_TIMEOUT, timeout.as_millis() as u64);
+        }
+        self.next_set_id += 1;
+        self.push(msg)
+    }
+
+    // A `timeout` needs a set created with NFT_SET_TIMEOUT. Re-adding an
+    // element that is already present is not an error.
+    pub fn add_set_elements(&mut self, table: &str, set: &str, keys: &[Vec<u8>], timeout: Option<Duration>) -> &mut Self {
+        self.set_elements(NFT_MSG_NEWSETELEM, NLM_F_CREATE, table, set, keys, timeout)
+    }
+
+    pub fn delete_set_elements(&mut self, table: &str, set: &str, keys: &[Vec<u8>]) -> &mut Self {
+        self.set_elements(NFT_MSG_DELSETELEM, 0, table, set, keys, None)
+    }
+
+    fn set_elements(
+        &mut self,
+        msg_type: u16,
+        flags: u16,
+        table: &str,
+        set: &str,
+        keys: &[Vec<u8>],
+        timeout: Option<Duration>,
+    ) -> &mut Self {
+        for chunk in keys.chunks(MAX_ELEMENTS_PER_MESSAGE) {
+            let mut msg = Message::new(msg_type, flags, self.family);
+            msg.put_str(NFTA_SET_ELEM_LIST_TABLE, table);
+            msg.put_str(NFTA_SET_ELEM_LIST_SET, set);
+            let list = msg.nest_start(NFTA_SET_ELEM_LIST_ELEMENTS);
+            for key in chunk {
+                let elem = msg.nest_start(NFTA_LIST_ELEM);
+                let nest = msg.nest_start(NFTA_SET_ELEM_KEY);
+                msg.put(NFTA_DATA_VALUE, key);
+                msg.nest_end(nest);
+                if let Some(timeout) = timeout {
+                    msg.put_u64(NFTA_SET_ELEM_TIMEOUT, timeout.as_millis() as u64);
+                }
+                msg.nest_end(elem);
+            }
+            msg.nest_end(list);
+            self.push(msg);
+        }
+        self
+    }
+
+    fn push(&mut self, msg: Message) -> &mut Self {
+        self.messages.push(msg.buf);
+        self
+    }
+
+    // Frames the messages between batch markers and numbers them from `seq`
+    fn encode(&self, seq: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let marker = |buf: &mut Vec<u8>, msg_type: u16, seq: u32| {
+            let mut msg = Message::raw(msg_type, NLM_F_REQUEST, 0, NFNL_SUBSYS_NFTABLES);
+            msg.set_seq(seq);
+            buf.extend_from_slice(&msg.finish());
+        };
+        marker(&mut buf, NFNL_MSG_BATCH_BEGIN, seq);
+        for (i, msg) in self.messages.iter().enumerate() {
+            let mut msg = Message { buf: msg.clone() };
+            msg.set_seq(seq + 1 + i as u32);
+            buf.extend_from_slice(&msg.finish());
+        }
+        marker(&mut buf, NFNL_MSG_BATCH_END, seq + 1 + self.messages.len() as u32);
+        buf
+    }
+}
+
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(msg_type: u16, flags: u16, family: u8) -> Self {
+        Self::raw((NFNL_SUBSYS_NFTABLES << 8) | msg_type, NLM_F_REQUEST | NLM_F_ACK | flags, family, 0)
+    }
+
+    fn raw(msg_type: u16, flags: u16, family: u8, res_id: u16) -> Self {
+        let mut buf = Vec::with_capacity(256);
+        // nlmsghdr; length and seq are filled in later
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&msg_type.to_ne_bytes());
+        buf.extend_from_slice(&flags.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        // nfgenmsg
+        buf.push(family);
+        buf.push(0);
+        buf.extend_from_slice(&res_id.to_be_bytes());
+        Self { buf }
+    }
+
+    fn set_seq(&mut self, seq: u32) {
+        self.buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+
+    fn put(&mut self, attr: u16, data: &[u8]) {
+        self.buf.extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+        self.buf.extend_from_slice(&attr.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.pad();
+    }
+
+    fn put_str(&mut self, attr: u16, value: &str) {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        self.put(attr, &data);
+    }
+
+    // nf_tables integers are big-endian on the wire
+    fn put_u32(&mut self, attr: u16, value: u32) {
+        self.put(attr, &value.to_be_bytes());
+    }
+
+    fn put_u64(&mut self, attr: u16, value: u64) {
+        self.put(attr, &value.to_be_bytes());
+    }
+
+    fn put_data(&mut self, attr: u16, value: &[u8]) {
+        let nest = self.nest_start(attr);
+        self.put(NFTA_DATA_VALUE, value);
+        self.nest_end(nest);
+    }
+
+    fn nest_start(&mut self, attr: u16) -> usize {
+        let start = self.buf.len();
+        self.buf.extend_from_slice(&0u16.to_ne_bytes());
+        self.buf.extend_from_slice(&(attr | NLA_F_NESTED).to_ne_bytes());
+        start
+    }
+
+    fn nest_end(&mut self, start: usize) {
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    fn pad(&mut self) {
+        while !self.buf.len().is_multiple_of(4) {
+            self.buf.push(0);
+        }
+    }
+
+    fn put_expr(&mut self, expr: &Expr) {
+        let name = match expr {
+            Expr::Meta { .. } => "meta",
+            Expr::Ct { .. } => "ct",
+            Expr::Payload { .. } => "payload",
+            Expr::Cmp { .. } => "cmp",
+            Expr::Range { .. } => "range",
+            Expr::Bitwise { .. } => "bitwise",
+            Expr::Lookup { .. } => "lookup",
+            Expr::Verdict(_) | Expr::Immediate { .. } => "immediate",
+            Expr::Limit { .. } => "limit",
+            Expr::Log { .. } => "log",
+            Expr::Reject { .. } => "reject",
+            Expr::Redirect { .. } => "redir",
+            Expr::Counter => "counter",
+        };
+        let elem = self.nest_start(NFTA_LIST_ELEM);
+        self.put_str(NFTA_EXPR_NAME, name);
+        let data = self.nest_start(NFTA_EXPR_DATA);
+        match expr {
+            Expr::Meta { key, dreg } | Expr::Ct { key, dreg } => {
+                // NFTA_META_DREG / NFTA_CT_DREG, NFTA_META_KEY / NFTA_CT_KEY
+                self.put_u32(1, *dreg);
+                self.put_u32(2, *key);
+            }
+            Expr::Payload { base, offset, len, dreg } => {
+                self.put_u32(1, *dreg);
+                self.put_u32(2, *base);
+                self.put_u32(3, *offset);
+                self.put_u32(4, *len);
+            }
+            Expr::Cmp { sreg, op, data } => {
+                self.put_u32(1, *sreg);
+                self.put_u32(2, *op as u32);
+                self.put_data(3, data);
+            }
+            Expr::Range { sreg, from, to } => {
+                self.put_u32(1, *sreg);
+                // NFT_RANGE_EQ
+                self.put_u32(2, 0);
+                self.put_data(3, from);
+                self.put_data(4, to);
+            }
+            Expr::Bitwise { sreg, dreg, mask, xor } => {
+                self.put_u32(1, *sreg);
+                self.put_u32(2, *dreg);
+                self.put_u32(3, mask.len() as u32);
+                self.put_data(4, mask);
+                self.put_data(5, xor);
+            }
+            Expr::Lookup { set, sreg } => {
+                self.put_str(1, set);
+                self.put_u32(2, *sreg);
+            }
+            Expr::Verdict(verdict) => {
+                // NFT_REG_VERDICT
+                self.put_u32(1, 0);
+                let nest = self.nest_start(2);
+                let inner = self.nest_start(NFTA_DATA_VERDICT);
+                self.put_u32(NFTA_VERDICT_CODE, verdict.code() as u32);
+                self.nest_end(inner);
+                self.nest_end(nest);
+            }
+            Expr::Immediate { dreg, data } => {
+                self.put_u32(1, *dreg);
+                self.put_data(2, data);
+            }
+            Expr::Limit { rate, unit_secs, burst } => {
+                self.put_u64(1, *rate);
+                self.put_u64(2, *unit_secs);
+                self.put_u32(3, *burst);
+                // NFT_LIMIT_PKTS
+                self.put_u32(4, 0);
+            }
+            Expr::Log { prefix, level } => {
+                self.put_str(2, prefix);
+                self.put_u32(5, *level);
+            }
+            Expr::Reject { kind, code } => {
+                self.put_u32(1, *kind);
+                self.put(2, &[*code]);
+            }
+            Expr::Redirect { proto_min_reg } => {
+                self.put_u32(1, *proto_min_reg);
+            }
+            // Starts from zero
+            Expr::Counter => {}
+        }
+        self.nest_end(data);
+        self.nest_end(elem);
+    }
+}
+
+// An installed rule as reported by the kernel
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedRule {
+    pub chain: String,
+    pub handle: u64,
+    // Packets seen by the rule's counter expression, if it has one
+    pub packets: Option<u64>,
+}
+
+// Netlink socket to nf_tables. Needs CAP_NET_ADMIN for anything but reads.
+pub struct NftNetlink {
+    socket: RawFd,
+    seq: AtomicU32,
+    // Serializes request/response exchanges on the socket
+    exchange: Mutex<()>,
+}
+
+impl NftNetlink {
+    pub fn open() -> Result<Self> {
+        let socket = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_NETFILTER)
+        };
+        if socket < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to create nf_tables netlink socket: {}", err));
+        }
+        let netlink = Self {
+            socket,
+            seq: AtomicU32::new(std::process::id().wrapping_mul(1000)),
+            exchange: Mutex::new(()),
+        };
+
+        let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        let ret = unsafe {
+            libc::bind(socket, &addr as *const _ as *const libc::sockaddr, mem::size_of::<sockaddr_nl>() as u32)
+        };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to bind nf_tables netlink socket: {}", err));
+        }
+
+        // Never wait forever on a kernel that doesn't answer
+        let timeout = libc::timeval { tv_sec: 5, tv_usec: 0 };
+        // A whole batch goes out in one datagram, so bulk set loads need
+        // more than the default send buffer
+        let sndbuf: c_int = 8 * 1024 * 1024;
+        unsafe {
+            libc::setsockopt(
+                socket,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            );
+            if libc::setsockopt(
+                socket,
+                libc::SOL_SOCKET,
+                libc::SO_SNDBUFFORCE,
+                &sndbuf as *const _ as *const libc::c_void,
+                mem::size_of::<c_int>() as u32,
+            ) < 0 {
+                libc::setsockopt(
+                    socket,
+                    libc::SOL_SOCKET,
+                    libc::SO_SNDBUF,
+                    &sndbuf as *const _ as *const libc::c_void,
+                    mem::size_of::<c_int>() as u32,
+                );
+            }
+        }
+        Ok(netlink)
+    }
+
+    // Applies the batch atomically and returns the handles of the rules it
+    // added, in the order they were added
+    pub fn commit(&self, batch: &NftBatch) -> Result<Vec<u64>> {
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+        let _exchange = self.exchange.lock().unwrap_or_else(|e| e.into_inner());
+        let count = batch.messages.len() as u32;
+        let first = self.seq.fetch_add(count + 2, Ordering::Relaxed);
+        self.send(&batch.encode(first))?;
+
+        // Messages are numbered first + 1 ..= first + count
+        let mut acked = vec![false; count as usize];
+        let mut handles = vec![None; count as usize];
+        let mut error = None;
+        let mut buffer = vec![0u8; 65536];
+        while acked.iter().any(|acked| !acked) {
+            let len = match self.recv(&mut buffer) {
+                Ok(len) => len,
+                // The kernel stops answering once a batch is aborted
+                Err(_) if error.is_some() => break,
+                Err(e) => return Err(anyhow!("No answer from nf_tables: {}", e)),
+            };
+            for (msg_type, seq, payload) in messages(&buffer[..len]) {
+                let Some(index) = seq.checked_sub(first + 1).map(|i| i as usize).filter(|i| *i < acked.len()) else {
+                    continue;
+                };
+                if msg_type == NLMSG_ERROR {
+                    acked[index] = true;
+                    let code = error_code(payload);
+                    if code != 0 && error.is_none() {
+                        error = Some((index, std::io::Error::from_raw_os_error(-code)));
+                    }
+                } else if msg_type == (NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_NEWRULE {
+                    handles[index] = attributes(payload.get(NFGENMSG_LEN..).unwrap_or_default())
+                        .find(|(attr, _)| *attr == NFTA_RULE_HANDLE)
+                        .and_then(|(_, value)| value.try_into().ok().map(u64::from_be_bytes));
+                }
+            }
+        }
+
+        if let Some((index, err)) = error {
+            return Err(anyhow!("nf_tables rejected batch at message {}: {}", index + 1, err));
+        }
+        batch.rule_messages.iter()
+            .map(|index| handles[*index].ok_or_else(|| anyhow!("nf_tables did not report a rule handle")))
+            .collect()
+    }
+
+    pub fn table_exists(&self, family: u8, table: &str) -> Result<bool> {
+        let _exchange = self.exchange.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let mut msg = Message::raw((NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_GETTABLE, NLM_F_REQUEST | NLM_F_ACK, family, 0);
+        msg.put_str(NFTA_TABLE_NAME, table);
+        msg.set_seq(seq);
+        self.send(&msg.finish())?;
+
+        let mut buffer = vec![0u8; 8192];
+        loop {
+            let len = self.recv(&mut buffer)?;
+            for (msg_type, msg_seq, payload) in messages(&buffer[..len]) {
+                if msg_seq != seq {
+                    continue;
+                }
+                if msg_type != NLMSG_ERROR {
+                    return Ok(true);
+                }
+                match error_code(payload) {
+                    0 => return Ok(true),
+                    code if code == -libc::ENOENT => return Ok(false),
+                    code => return Err(anyhow!("Failed to look up table {}: {}", table, std::io::Error::from_raw_os_error(-code))),
+                }
+            }
+        }
+    }
+
+    // Listing a table that doesn't exist yields no rules rather than an error
+    pub fn list_rules(&self, family: u8, table: &str) -> Result<Vec<ListedRule>> {
+        let _exchange = self.exchange.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let mut msg = Message::raw((NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_GETRULE, NLM_F_REQUEST | NLM_F_DUMP, family, 0);
+        msg.put_str(NFTA_RULE_TABLE, table);
+        msg.set_seq(seq);
+        self.send(&msg.finish())?;
+
+        let mut rules = Vec::new();
+        let mut buffer = vec![0u8; 65536];
+        loop {
+            let len = self.recv(&mut buffer)?;
+            for (msg_type, msg_seq, payload) in messages(&buffer[..len]) {
+                if msg_seq != seq {
+                    continue;
+                }
+                match msg_type {
+                    NLMSG_DONE => return Ok(rules),
+                    NLMSG_ERROR => {
+                        let code = error_code(payload);
+                        if code != 0 {
+                            return Err(anyhow!("Failed to list rules in {}: {}", table, std::io::Error::from_raw_os_error(-code)));
+                        }
+                    }
+                    _ => {
+                        let mut rule_table = None;
+                        let mut chain = None;
+                        let mut handle = None;
+                        let mut packets = None;
+                        for (attr, value) in attributes(payload.get(NFGENMSG_LEN..).unwrap_or_default()) {
+                            match attr {
+                                NFTA_RULE_TABLE => rule_table = Some(c_string(value)),
+                                NFTA_RULE_CHAIN => chain = Some(c_string(value)),
+                                NFTA_RULE_HANDLE => handle = value.try_into().ok().map(u64::from_be_bytes),
+                                NFTA_RULE_EXPRESSIONS => packets = counter_packets(value),
+                                _ => {}
+                            }
+                        }
+                        if let (Some(chain), Some(handle)) = (chain, handle) {
+                            if rule_table.as_deref() == Some(table) {
+                                rules.push(ListedRule { chain, handle, packets });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&self, data: &[u8]) -> Result<()> {
+        let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        let sent = unsafe {
+            libc::sendto(
+                self.socket,
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+                0,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<sockaddr_nl>() as u32,
+            )
+        };
+        if sent < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to send to nf_tables: {}", err));
+        }
+        debug!("Sent {} bytes to nf_tables", sent);
+        Ok(())
+    }
+
+    fn recv(&self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let len = unsafe { libc::recv(self.socket, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+            if len >= 0 {
+                return Ok(len as usize);
+            }
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINTR) {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Drop for NftNetlink {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.socket) };
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_ne_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// (type, seq, payload) of each netlink message in a datagram
+fn messages(data: &[u8]) -> impl Iterator<Item = (u16, u32, &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let len = read_u32(data, offset)? as usize;
+        if len < NLMSG_HDR_LEN || offset + len > data.len() {
+            return None;
+        }
+        let msg_type = read_u16(data, offset + 4)?;
+        let seq = read_u32(data, offset + 8)?;
+        let payload = &data[offset + NLMSG_HDR_LEN..offset + len];
+        offset += (len + 3) & !3;
+        Some((msg_type, seq, payload))
+    })
+}
+
+// (type, value) of each top-level attribute
+fn attributes(data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let len = read_u16(data, offset)? as usize;
+        let attr = read_u16(data, offset + 2)? & !NLA_F_NESTED;
+        if len < 4 || offset + len > data.len() {
+            return None;
+        }
+        let value = &data[offset + 4..offset + len];
+        offset += (len + 3) & !3;
+        Some((attr, value))
+    })
+}
+
+// Packets of the first counter in a rule's expression list
+fn counter_packets(expressions: &[u8]) -> Option<u64> {
+    attributes(expressions)
+        .filter(|(attr, _)| *attr == NFTA_LIST_ELEM)
+        .find_map(|(_, elem)| {
+            let mut name = None;
+            let mut data = None;
+            for (attr, value) in attributes(elem) {
+                match attr {
+                    NFTA_EXPR_NAME => name = Some(c_string(value)),
+                    NFTA_EXPR_DATA => data = Some(value),
+                    _ => {}
+                }
+            }
+            if name.as_deref() != Some("counter") {
+                return None;
+            }
+            attributes(data?).find(|(attr, _)| *attr == NFTA_COUNTER_PACKETS)
+                .and_then(|(_, value)| value.try_into().ok().map(u64::from_be_bytes))
+        })
+}
+
+// Negative errno carried by NLMSG_ERROR; zero acknowledges success
+fn error_code(payload: &[u8]) -> i32 {
+    payload.get(..4).map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]])).unwrap_or(0)
+}
+
+fn c_string(value: &[u8]) -> String {
+    let end = value.iter().position(|b| *b == 0).unwrap_or(value.len());
+    String::from_utf8_lossy(&value[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_encoding() {
+        let mut batch = NftBatch::new(NFPROTO_INET);
+        batch.add_table("t")
+            .add_rule("t", "input", &[Expr::Verdict(Verdict::Drop)], Some("x"));
+        let encoded = batch.encode(100);
+
+        let parsed: Vec<(u16, u32, &[u8])> = messages(&encoded).collect();
+        assert_eq!(parsed.len(), 4);
+        assert_eq!((parsed[0].0, parsed[0].1), (NFNL_MSG_BATCH_BEGIN, 100));
+        assert_eq!((parsed[3].0, parsed[3].1), (NFNL_MSG_BATCH_END, 103));
+        // The batch markers name the nf_tables subsystem
+        assert_eq!(&parsed[0].2[2..4], &NFNL_SUBSYS_NFTABLES.to_be_bytes());
+
+        let (msg_type, seq, payload) = parsed[2];
+        assert_eq!(msg_type, (NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_NEWRULE);
+        assert_eq!(seq, 102);
+        let attrs: Vec<(u16, &[u8])> = attributes(&payload[NFGENMSG_LEN..]).collect();
+        assert_eq!(attrs.iter().map(|(attr, _)| *attr).collect::<Vec<_>>(),
+                   vec![NFTA_RULE_TABLE, NFTA_RULE_CHAIN, NFTA_RULE_EXPRESSIONS, NFTA_RULE_USERDATA]);
+        assert_eq!(c_string(attrs[1].1), "input");
+        assert_eq!(attrs[3].1, &[NFTNL_UDATA_RULE_COMMENT, 2, b'x', 0]);
+
+        // expressions -> list elem -> name "immediate"
+        let elem: Vec<(u16, &[u8])> = attributes(attrs[2].1).collect();
+        let expr: Vec<(u16, &[u8])> = attributes(elem[0].1).collect();
+        assert_eq!(c_string(expr[0].1), "immediate");
+        assert_eq!(batch.rule_messages, vec![1]);
+        assert_eq!(counter_packets(attrs[2].1), None);
+
+        // A listed rule's counter, as the kernel dumps it
+        let mut listed = Message::raw(0, 0, NFPROTO_INET, 0);
+        let list = listed.nest_start(NFTA_RULE_EXPRESSIONS);
+        listed.put_expr(&Expr::Verdict(Verdict::Accept));
+        let elem = listed.nest_start(NFTA_LIST_ELEM);
+        listed.put_str(NFTA_EXPR_NAME, "counter");
+        let data = listed.nest_start(NFTA_EXPR_DATA);
+        listed.put_u64(1, 840);
+        listed.put_u64(NFTA_COUNTER_PACKETS, 7);
+        listed.nest_end(data);
+        listed.nest_end(elem);
+        listed.nest_end(list);
+        let attrs: Vec<(u16, &[u8])> = attributes(&listed.buf[NLMSG_HDR_LEN + NFGENMSG_LEN..]).collect();
+        assert_eq!(counter_packets(attrs[0].1), Some(7));
+    }
+}
@@ -1,13 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use super::process_monitor::ProcessInfo;
 use super::fanotify::FanotifyEvent;
+use super::resource_usage::{ResourceUsage, SustainedUsage};
+use crate::scanner::memory::{MemoryScanner, MemoryMatch};
+use crate::scanner::PackageVerifier;
+use crate::rule_stats::{RuleEngine, RuleStats};
+use crate::shadow::ShadowLog;
 
 #[derive(Debug, Clone)]
 pub struct BehaviorPattern {
@@ -17,24 +24,33 @@ pub struct BehaviorPattern {
     pub category: PatternCategory,
     pub severity: Severity,
     pub enabled: bool,
+    // Matches go to the shadow log instead of being returned as detections
+    pub shadow: bool,
     pub detection_logic: DetectionLogic,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PatternCategory {
     CryptoMiner,
     ReverseShell,
     PrivilegeEscalation,
     MemoryInjection,
+    // LD_PRELOAD, ld.so.preload and other dynamic-loader hijacking
+    LibraryInjection,
     DataExfiltration,
     Persistence,
     Evasion,
     Reconnaissance,
     LateralMovement,
     ResourceAbuse,
+    CommandAndControl,
+    // Reads of stored secrets: browser logins, SSH keys, cloud credentials, shadow
+    CredentialAccess,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Severity {
     Low,
     Medium,
@@ -46,6 +62,8 @@ pub enum Severity {
 pub enum DetectionLogic {
     CommandLinePattern(Vec<String>),
     FileAccessPattern(Vec<String>),
+    // A recent connection of the process meeting every non-empty list:
+    // remote port, address or CIDR range, and domain or parent domain
     NetworkPattern {
         ports: Vec<u16>,
         ips: Vec<String>,
@@ -55,12 +73,67 @@ pub enum DetectionLogic {
         parent_pattern: String,
         child_pattern: String,
     },
+    // Every sample over `duration` at or above both thresholds (percent of
+    // one core, resident bytes); zero leaves a threshold out
     ResourceUsagePattern {
         cpu_threshold: f32,
         memory_threshold: u64,
         duration: Duration,
+        // Only binaries no installed package owns
+        unpackaged_only: bool,
     },
+    // The process is none of these, by name or executable file name; pairs
+    // with a file pattern in AllOf to leave out the owner of the file
+    ProcessNotIn(Vec<String>),
+    // /proc/<pid>/mem of a process with one of these names opened by another
+    ProcessMemoryAccess(Vec<String>),
+    // Any of the logics matches
     Combined(Vec<DetectionLogic>),
+    // Every logic matches, e.g. a command line together with a connection
+    AllOf(Vec<DetectionLogic>),
+}
+
+impl DetectionLogic {
+    fn command_line_keywords(&self) -> Vec<&String> {
+        match self {
+            DetectionLogic::CommandLinePattern(keywords) => keywords.iter().collect(),
+            DetectionLogic::Combined(logics) | DetectionLogic::AllOf(logics) => {
+                logics.iter().flat_map(|logic| logic.command_line_keywords()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+    
+    fn involves_network(&self) -> bool {
+        match self {
+            DetectionLogic::NetworkPattern { .. } => true,
+            DetectionLogic::Combined(logics) | DetectionLogic::AllOf(logics) => {
+                logics.iter().any(|logic| logic.involves_network())
+            }
+            _ => false,
+        }
+    }
+}
+
+// How long a connection counts towards NetworkPattern, and how long a DNS
+// answer names the addresses in it
+const NETWORK_ACTIVITY_WINDOW: Duration = Duration::from_secs(600);
+const DNS_ANSWER_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub struct NetworkActivity {
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+    pub domain: Option<String>,
+    pub seen_at: Instant,
+}
+
+// Recent connections of one process and the network patterns already
+// reported for them
+struct ProcessNetwork {
+    start_time: u64,
+    connections: Vec<NetworkActivity>,
+    reported: HashSet<String>,
 }
 
 // Process execution chain tracking
@@ -83,7 +156,8 @@ pub struct ProcessChainNode {
     pub events: Vec<ChainEvent>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ChainEvent {
     ProcessSpawn {
         child_pid: u32,
@@ -108,6 +182,12 @@ pub struct PatternMatcher {
     process_chains: Arc<RwLock<HashMap<u32, ProcessChain>>>,
     compiled_regexes: Arc<RwLock<HashMap<String, Regex>>>,
     reputation_cache: Arc<RwLock<HashMap<String, ReputationScore>>>,
+    resource_usage: Arc<RwLock<ResourceUsage>>,
+    package_verifier: Arc<RwLock<Option<Arc<PackageVerifier>>>>,
+    network_activity: Arc<RwLock<HashMap<u32, ProcessNetwork>>>,
+    resolved_domains: Arc<RwLock<HashMap<IpAddr, (String, Instant)>>>,
+    shadow_log: Arc<RwLock<ShadowLog>>,
+    rule_stats: Arc<RwLock<RuleStats>>,
 }
 
 #[derive(Debug, Clone)]
@@ -125,6 +205,12 @@ impl PatternMatcher {
             process_chains: Arc::new(RwLock::new(HashMap::new())),
             compiled_regexes: Arc::new(RwLock::new(HashMap::new())),
             reputation_cache: Arc::new(RwLock::new(HashMap::new())),
+            resource_usage: Arc::new(RwLock::new(ResourceUsage::new())),
+            package_verifier: Arc::new(RwLock::new(None)),
+            network_activity: Arc::new(RwLock::new(HashMap::new())),
+            resolved_domains: Arc::new(RwLock::new(HashMap::new())),
+            shadow_log: Arc::new(RwLock::new(ShadowLog::new())),
+            rule_stats: Arc::new(RwLock::new(RuleStats::new())),
         };
         
         matcher.load_default_patterns()?;
@@ -141,6 +227,7 @@ impl PatternMatcher {
                 category: PatternCategory::CryptoMiner,
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "xmrig".to_string(),
                     "minerd".to_string(),
@@ -155,6 +242,79 @@ impl PatternMatcher {
                 ]),
             },
             
+            BehaviorPattern {
+                id: "crypto_miner_sustained_cpu".to_string(),
+                name: "Sustained CPU Use by Unpackaged Binary".to_string(),
+                description: "A binary outside the package database keeping a core busy for ten minutes".to_string(),
+                category: PatternCategory::CryptoMiner,
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::ResourceUsagePattern {
+                    cpu_threshold: 90.0,
+                    memory_threshold: 0,
+                    duration: Duration::from_secs(600),
+                    unpackaged_only: true,
+                },
+            },
+            
+            BehaviorPattern {
+                id: "resource_abuse_cpu".to_string(),
+                name: "Prolonged Multi-core CPU Use".to_string(),
+                description: "Any process keeping three or more cores busy for half an hour".to_string(),
+                category: PatternCategory::ResourceAbuse,
+                severity: Severity::Medium,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::ResourceUsagePattern {
+                    cpu_threshold: 300.0,
+                    memory_threshold: 0,
+                    duration: Duration::from_secs(1800),
+                    unpackaged_only: false,
+                },
+            },
+            
+            BehaviorPattern {
+                id: "crypto_miner_pool_connection".to_string(),
+                name: "Mining Pool Connection".to_string(),
+                description: "Connections to well-known cryptocurrency mining pools".to_string(),
+                category: PatternCategory::CryptoMiner,
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::NetworkPattern {
+                    ports: Vec::new(),
+                    ips: Vec::new(),
+                    domains: vec![
+                        "supportxmr.com".to_string(),
+                        "minexmr.com".to_string(),
+                        "moneroocean.stream".to_string(),
+                        "nanopool.org".to_string(),
+                        "2miners.com".to_string(),
+                        "f2pool.com".to_string(),
+                        "hashvault.pro".to_string(),
+                        "herominers.com".to_string(),
+                        "c3pool.com".to_string(),
+                    ],
+                },
+            },
+            
+            // Command and Control
+            BehaviorPattern {
+                id: "c2_irc_connection".to_string(),
+                name: "IRC Connection".to_string(),
+                description: "Connections to IRC ports, still a common botnet control channel".to_string(),
+                category: PatternCategory::CommandAndControl,
+                severity: Severity::Medium,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::NetworkPattern {
+                    ports: vec![6660, 6661, 6662, 6663, 6664, 6665, 6666, 6667, 6668, 6669, 6697],
+                    ips: Vec::new(),
+                    domains: Vec::new(),
+                },
+            },
+            
             // Reverse Shells
             BehaviorPattern {
                 id: "reverse_shell_bash".to_string(),
@@ -163,6 +323,7 @@ impl PatternMatcher {
                 category: PatternCategory::ReverseShell,
                 severity: Severity::Critical,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "bash -i".to_string(),
                     "/dev/tcp/".to_string(),
@@ -178,6 +339,33 @@ impl PatternMatcher {
                 ]),
             },
             
+            BehaviorPattern {
+                id: "reverse_shell_port".to_string(),
+                name: "Interpreter Connected to Reverse Shell Port".to_string(),
+                description: "A shell, interpreter or netcat connected to a port exploit kits default to".to_string(),
+                category: PatternCategory::ReverseShell,
+                severity: Severity::Critical,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::AllOf(vec![
+                    DetectionLogic::CommandLinePattern(vec![
+                        "sh".to_string(),
+                        "bash".to_string(),
+                        "nc".to_string(),
+                        "ncat".to_string(),
+                        "socat".to_string(),
+                        "python".to_string(),
+                        "python3".to_string(),
+                        "perl".to_string(),
+                    ]),
+                    DetectionLogic::NetworkPattern {
+                        ports: vec![4444, 1337, 31337, 9001],
+                        ips: Vec::new(),
+                        domains: Vec::new(),
+                    },
+                ]),
+            },
+            
             BehaviorPattern {
                 id: "reverse_shell_python".to_string(),
                 name: "Python Reverse Shell".to_string(),
@@ -185,6 +373,7 @@ impl PatternMatcher {
                 category: PatternCategory::ReverseShell,
                 severity: Severity::Critical,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "python -c".to_string(),
                     "python3 -c".to_string(),
@@ -203,6 +392,7 @@ impl PatternMatcher {
                 category: PatternCategory::PrivilegeEscalation,
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::Combined(vec![
                     DetectionLogic::CommandLinePattern(vec![
                         "sudo -l".to_string(),
@@ -227,6 +417,7 @@ impl PatternMatcher {
                 category: PatternCategory::PrivilegeEscalation,
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "find / -perm -4000".to_string(),
                     "find / -perm -u=s".to_string(),
@@ -244,6 +435,7 @@ impl PatternMatcher {
                 category: PatternCategory::MemoryInjection,
                 severity: Severity::Critical,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::FileAccessPattern(vec![
                     "/proc/*/mem".to_string(),
                     "/proc/*/maps".to_string(),
@@ -259,6 +451,7 @@ impl PatternMatcher {
                 category: PatternCategory::DataExfiltration,
                 severity: Severity::Medium,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "tar -czf".to_string(),
                     "tar -cjf".to_string(),
@@ -279,6 +472,7 @@ impl PatternMatcher {
                 category: PatternCategory::Persistence,
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::FileAccessPattern(vec![
                     "/etc/crontab".to_string(),
                     "/etc/cron.d/".to_string(),
@@ -295,6 +489,7 @@ impl PatternMatcher {
                 category: PatternCategory::Persistence,
                 severity: Severity::High,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::FileAccessPattern(vec![
                     "/etc/systemd/system/".to_string(),
                     "/lib/systemd/system/".to_string(),
@@ -311,6 +506,7 @@ impl PatternMatcher {
                 category: PatternCategory::Evasion,
                 severity: Severity::Medium,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::Combined(vec![
                     DetectionLogic::CommandLinePattern(vec![
                         "unset HISTFILE".to_string(),
@@ -326,6 +522,113 @@ impl PatternMatcher {
                 ]),
             },
             
+            // Credential Access
+            BehaviorPattern {
+                id: "cred_browser_store".to_string(),
+                name: "Browser Credential Store Read".to_string(),
+                description: "Detects saved browser logins and keys read by something other than the browser".to_string(),
+                category: PatternCategory::CredentialAccess,
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::AllOf(vec![
+                    DetectionLogic::FileAccessPattern(vec![
+                        "*/.mozilla/firefox/*/logins.json".to_string(),
+                        "*/.mozilla/firefox/*/key4.db".to_string(),
+                        "*/.config/google-chrome/*/Login Data".to_string(),
+                        "*/.config/chromium/*/Login Data".to_string(),
+                        "*/.config/BraveSoftware/*/Login Data".to_string(),
+                        "*/.config/microsoft-edge/*/Login Data".to_string(),
+                    ]),
+                    DetectionLogic::ProcessNotIn(names(&[
+                        "firefox", "firefox-bin", "firefox-esr", "chrome", "chromium", "chromium-browser",
+                        "brave", "msedge",
+                    ])),
+                ]),
+            },
+            
+            BehaviorPattern {
+                id: "cred_ssh_private_key".to_string(),
+                name: "SSH Private Key Read".to_string(),
+                description: "Detects ~/.ssh/id_* private keys read outside the OpenSSH tools".to_string(),
+                category: PatternCategory::CredentialAccess,
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::AllOf(vec![
+                    DetectionLogic::FileAccessPattern(vec![
+                        "*/.ssh/id_rsa".to_string(),
+                        "*/.ssh/id_dsa".to_string(),
+                        "*/.ssh/id_ecdsa".to_string(),
+                        "*/.ssh/id_ed25519".to_string(),
+                        "*/.ssh/id_ecdsa_sk".to_string(),
+                        "*/.ssh/id_ed25519_sk".to_string(),
+                    ]),
+                    DetectionLogic::ProcessNotIn(names(&[
+                        "ssh", "ssh-add", "ssh-agent", "ssh-keygen", "scp", "sftp", "sshd",
+                    ])),
+                ]),
+            },
+            
+            BehaviorPattern {
+                id: "cred_cloud_files".to_string(),
+                name: "Cloud Credential File Read".to_string(),
+                description: "Detects cloud and cluster credentials read by something other than their CLI".to_string(),
+                category: PatternCategory::CredentialAccess,
+                severity: Severity::High,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::AllOf(vec![
+                    DetectionLogic::FileAccessPattern(vec![
+                        "*/.aws/credentials".to_string(),
+                        "*/.kube/config".to_string(),
+                        "/etc/kubernetes/admin.conf".to_string(),
+                        "*/.config/gcloud/credentials.db".to_string(),
+                        "*/.config/gcloud/application_default_credentials.json".to_string(),
+                        "*/.azure/msal_token_cache.json".to_string(),
+                    ]),
+                    DetectionLogic::ProcessNotIn(names(&[
+                        "aws", "kubectl", "helm", "k9s", "kubelet", "gcloud", "az", "terraform",
+                    ])),
+                ]),
+            },
+            
+            BehaviorPattern {
+                id: "cred_shadow_read".to_string(),
+                name: "Shadow Password File Read".to_string(),
+                description: "Detects /etc/shadow read by anything but the login and account tools".to_string(),
+                category: PatternCategory::CredentialAccess,
+                severity: Severity::Critical,
+                enabled: true,
+                shadow: false,
+                detection_logic: DetectionLogic::AllOf(vec![
+                    DetectionLogic::FileAccessPattern(vec![
+                        "/etc/shadow".to_string(),
+                        "/etc/gshadow".to_string(),
+                    ]),
+                    DetectionLogic::ProcessNotIn(names(&[
+                        "login", "sshd", "su", "sudo", "passwd", "chpasswd", "chage", "unix_chkpwd",
+                        "useradd", "usermod", "userdel", "groupadd", "groupmod", "groupdel", "newusers",
+                        "pwck", "grpck", "vipw", "vigr", "gdm-session-worker", "lightdm", "polkit-agent-helper-1",
+                        "cron", "crond", "systemd-logind",
+                    ])),
+                ]),
+            },
+            
+            BehaviorPattern {
+                id: "cred_process_memory".to_string(),
+                name: "Authentication Process Memory Read".to_string(),
+                description: "Detects the memory of sshd or a key agent opened by another process".to_string(),
+                category: PatternCategory::CredentialAccess,
+                severity: Severity::Critical,
+                enabled: true,
+                shadow: false,
+                // comm is cut at 15 characters
+                detection_logic: DetectionLogic::ProcessMemoryAccess(names(&[
+                    "sshd", "ssh-agent", "gpg-agent", "gnome-keyring-d", "keepassxc",
+                ])),
+            },
+            
             // Reconnaissance
             BehaviorPattern {
                 id: "recon_network_scan".to_string(),
@@ -334,6 +637,7 @@ impl PatternMatcher {
                 category: PatternCategory::Reconnaissance,
                 severity: Severity::Medium,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "nmap".to_string(),
                     "masscan".to_string(),
@@ -354,6 +658,7 @@ impl PatternMatcher {
                 category: PatternCategory::Reconnaissance,
                 severity: Severity::Low,
                 enabled: true,
+                shadow: false,
                 detection_logic: DetectionLogic::CommandLinePattern(vec![
                     "uname -a".to_string(),
                     "id".to_string(),
@@ -370,8 +675,9 @@ impl PatternMatcher {
             .map_err(|_| anyhow!("Failed to acquire patterns write lock"))?;
         
         for pattern in patterns {
-            // Compile regex patterns
-            if let DetectionLogic::CommandLinePattern(ref keywords) = pattern.detection_logic {
+            // Compile regex patterns, including those nested in combinations
+            let keywords = pattern.detection_logic.command_line_keywords();
+            if !keywords.is_empty() {
                 let mut regexes = self.compiled_regexes.write()
                     .map_err(|_| anyhow!("Failed to acquire regex write lock"))?;
                 
@@ -412,11 +718,86 @@ impl PatternMatcher {
             }
         }
         
-        matches
+        self.without_shadow(matches, process)
+    }
+    
+    // Where matches of every pattern are counted
+    pub fn set_rule_stats(&self, stats: RuleStats) -> Result<()> {
+        {
+            let patterns = self.patterns.read()
+                .map_err(|_| anyhow!("Failed to acquire patterns read lock"))?;
+            stats.sync(RuleEngine::BehaviorPattern, patterns.iter().map(|pattern| (pattern.id.as_str(), pattern.name.as_str())));
+        }
+        *self.rule_stats.write()
+            .map_err(|_| anyhow!("Failed to acquire rule stats write lock"))? = stats;
+        Ok(())
+    }
+    
+    // Where matches of shadow patterns are counted
+    pub fn set_shadow_log(&self, log: ShadowLog) -> Result<()> {
+        *self.shadow_log.write()
+            .map_err(|_| anyhow!("Failed to acquire shadow log write lock"))? = log;
+        Ok(())
+    }
+    
+    // Puts a pattern in or out of shadow mode
+    pub fn set_pattern_shadow(&self, id: &str, shadow: bool) -> Result<()> {
+        let mut patterns = self.patterns.write()
+            .map_err(|_| anyhow!("Failed to acquire patterns write lock"))?;
+        let pattern = patterns.iter_mut().find(|pattern| pattern.id == id)
+            .ok_or_else(|| anyhow!("No behavior pattern '{}'", id))?;
+        pattern.shadow = shadow;
+        Ok(())
+    }
+    
+    // Counts every match and leaves out those of shadow patterns
+    fn without_shadow<T>(&self, matches: Vec<(BehaviorPattern, T)>, process: &ProcessInfo) -> Vec<(BehaviorPattern, T)> {
+        if let Ok(stats) = self.rule_stats.read() {
+            for (pattern, _) in &matches {
+                stats.record(RuleEngine::BehaviorPattern, &pattern.id, &pattern.name);
+            }
+        }
+        if matches.iter().all(|(pattern, _)| !pattern.shadow) {
+            return matches;
+        }
+        let log = self.shadow_log.read().map(|log| log.clone()).unwrap_or_default();
+        matches.into_iter()
+            .filter(|(pattern, _)| {
+                if pattern.shadow {
+                    let detail = format!("{} (pid {}): {}", process.name, process.pid, process.cmdline.join(" "));
+                    log.record(RuleEngine::BehaviorPattern, &pattern.id, &pattern.name, &format!("{:?}", pattern.severity).to_lowercase(), detail);
+                }
+                !pattern.shadow
+            })
+            .collect()
+    }
+    
+    // Scan the memory of a process once its behavior matches a pattern at or above min_severity
+    pub fn scan_flagged_process(
+        &self,
+        process: &ProcessInfo,
+        event: Option<&FanotifyEvent>,
+        scanner: &MemoryScanner,
+        min_severity: Severity,
+    ) -> Result<Vec<MemoryMatch>> {
+        let flagged = self.check_process(process, event)
+            .iter()
+            .any(|(_, severity)| *severity >= min_severity);
+        
+        if !flagged {
+            return Ok(Vec::new());
+        }
+        
+        debug!("Scanning memory of flagged process {} ({})", process.pid, process.name);
+        scanner.scan_process(process.pid)
     }
     
     fn pattern_matches(&self, pattern: &BehaviorPattern, process: &ProcessInfo, event: Option<&FanotifyEvent>) -> bool {
-        match &pattern.detection_logic {
+        self.logic_matches(&pattern.detection_logic, process, event)
+    }
+    
+    fn logic_matches(&self, logic: &DetectionLogic, process: &ProcessInfo, event: Option<&FanotifyEvent>) -> bool {
+        match logic {
             DetectionLogic::CommandLinePattern(keywords) => {
                 self.check_command_line_pattern(keywords, process)
             }
@@ -428,28 +809,182 @@ impl PatternMatcher {
                 }
             }
             DetectionLogic::NetworkPattern { ports, ips, domains } => {
-                // This would need network event data
-                false
+                self.check_network_pattern(ports, ips, domains, process)
             }
             DetectionLogic::ProcessChainPattern { parent_pattern, child_pattern } => {
                 self.check_process_chain_pattern(parent_pattern, child_pattern, process)
             }
-            DetectionLogic::ResourceUsagePattern { cpu_threshold, memory_threshold, duration } => {
-                // This would need resource monitoring data
-                false
+            DetectionLogic::ResourceUsagePattern { .. } => {
+                self.check_resource_usage_pattern(logic, process).is_some()
+            }
+            DetectionLogic::ProcessNotIn(names) => {
+                let exe_name = process.exe_path.as_ref()
+                    .and_then(|exe| exe.file_name())
+                    .map(|name| name.to_string_lossy().to_string());
+                !names.iter().any(|name| *name == process.name || exe_name.as_deref() == Some(name.as_str()))
+            }
+            DetectionLogic::ProcessMemoryAccess(names) => {
+                event.is_some_and(|event| self.check_process_memory_access(names, process, event))
             }
             DetectionLogic::Combined(logics) => {
-                logics.iter().any(|logic| {
-                    match logic {
-                        DetectionLogic::CommandLinePattern(keywords) => 
-                            self.check_command_line_pattern(keywords, process),
-                        DetectionLogic::FileAccessPattern(paths) => 
-                            event.map_or(false, |e| self.check_file_access_pattern(paths, e)),
-                        _ => false,
-                    }
-                })
+                logics.iter().any(|logic| self.logic_matches(logic, process, event))
+            }
+            DetectionLogic::AllOf(logics) => {
+                !logics.is_empty() && logics.iter().all(|logic| self.logic_matches(logic, process, event))
+            }
+        }
+    }
+    
+    // Remembers a connection of the process for NetworkPattern; returns
+    // false if it was already seen within the activity window
+    pub fn record_network_connection(&self, process: &ProcessInfo, remote_ip: IpAddr, remote_port: u16) -> bool {
+        let now = Instant::now();
+        let domain = self.resolved_domains.read().ok().and_then(|domains| {
+            domains.get(&remote_ip)
+                .filter(|(_, resolved_at)| now.duration_since(*resolved_at) <= DNS_ANSWER_TTL)
+                .map(|(domain, _)| domain.clone())
+        });
+        let Ok(mut activity) = self.network_activity.write() else {
+            return false;
+        };
+        activity.retain(|_, network| {
+            network.connections.retain(|connection| now.duration_since(connection.seen_at) <= NETWORK_ACTIVITY_WINDOW);
+            !network.connections.is_empty()
+        });
+        
+        let network = activity.entry(process.pid).or_insert_with(|| ProcessNetwork {
+            start_time: process.start_time,
+            connections: Vec::new(),
+            reported: HashSet::new(),
+        });
+        // The pid was reused
+        if network.start_time != process.start_time {
+            *network = ProcessNetwork { start_time: process.start_time, connections: Vec::new(), reported: HashSet::new() };
+        }
+        if let Some(connection) = network.connections.iter_mut()
+            .find(|connection| connection.remote_ip == remote_ip && connection.remote_port == remote_port)
+        {
+            connection.seen_at = now;
+            if domain.is_some() {
+                connection.domain = domain;
             }
+            return false;
         }
+        network.connections.push(NetworkActivity { remote_ip, remote_port, domain, seen_at: now });
+        true
+    }
+    
+    // Names the addresses of a DNS answer so domain patterns can match
+    // connections made to them afterwards
+    pub fn record_dns_answer(&self, domain: &str, addresses: &[IpAddr]) {
+        let now = Instant::now();
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        if let Ok(mut domains) = self.resolved_domains.write() {
+            domains.retain(|_, (_, resolved_at)| now.duration_since(*resolved_at) <= DNS_ANSWER_TTL);
+            for address in addresses {
+                domains.insert(*address, (domain.clone(), now));
+            }
+        }
+    }
+    
+    // Enabled patterns involving the network that the process now matches,
+    // each returned once while its connections stay in the window
+    pub fn check_network_activity(&self, process: &ProcessInfo) -> Vec<(BehaviorPattern, Severity)> {
+        let patterns = match self.patterns.read() {
+            Ok(p) => p,
+            Err(_) => return Vec::new(),
+        };
+        let matches: Vec<(BehaviorPattern, Severity)> = patterns.iter()
+            .filter(|pattern| pattern.enabled && pattern.detection_logic.involves_network())
+            .filter(|pattern| self.pattern_matches(pattern, process, None))
+            .map(|pattern| (pattern.clone(), pattern.severity))
+            .collect();
+        
+        let Ok(mut activity) = self.network_activity.write() else {
+            return Vec::new();
+        };
+        let Some(network) = activity.get_mut(&process.pid) else {
+            return Vec::new();
+        };
+        let matches: Vec<(BehaviorPattern, Severity)> = matches.into_iter()
+            .filter(|(pattern, _)| network.reported.insert(pattern.id.clone()))
+            .collect();
+        drop(activity);
+        self.without_shadow(matches, process)
+    }
+    
+    fn check_network_pattern(&self, ports: &[u16], ips: &[String], domains: &[String], process: &ProcessInfo) -> bool {
+        if ports.is_empty() && ips.is_empty() && domains.is_empty() {
+            return false;
+        }
+        let networks: Vec<(IpAddr, u8)> = ips.iter().filter_map(|ip| parse_cidr(ip)).collect();
+        if !ips.is_empty() && networks.is_empty() {
+            return false;
+        }
+        let Ok(activity) = self.network_activity.read() else {
+            return false;
+        };
+        let Some(network) = activity.get(&process.pid).filter(|network| network.start_time == process.start_time) else {
+            return false;
+        };
+        network.connections.iter()
+            .filter(|connection| connection.seen_at.elapsed() <= NETWORK_ACTIVITY_WINDOW)
+            .any(|connection| {
+                (ports.is_empty() || ports.contains(&connection.remote_port))
+                    && (networks.is_empty() || networks.iter().any(|(net, prefix)| ip_in_network(connection.remote_ip, *net, *prefix)))
+                    && (domains.is_empty() || connection.domain.as_deref().is_some_and(|domain| {
+                        domains.iter().any(|pattern| domain_matches(domain, pattern))
+                    }))
+            })
+    }
+    
+    // Samples CPU and memory of the given processes for ResourceUsagePattern;
+    // meant to run periodically, since patterns need minutes of samples
+    pub fn sample_resource_usage(&self, pids: &[u32]) {
+        if let Ok(mut usage) = self.resource_usage.write() {
+            usage.sample(pids);
+        }
+    }
+    
+    // Enabled resource usage patterns the process currently meets; shadow
+    // patterns count a hit for every sample that meets them
+    pub fn check_resource_usage(&self, process: &ProcessInfo) -> Vec<(BehaviorPattern, SustainedUsage)> {
+        let patterns = match self.patterns.read() {
+            Ok(p) => p,
+            Err(_) => return Vec::new(),
+        };
+        let matches = patterns.iter()
+            .filter(|pattern| pattern.enabled)
+            .filter_map(|pattern| {
+                self.check_resource_usage_pattern(&pattern.detection_logic, process)
+                    .map(|usage| (pattern.clone(), usage))
+            })
+            .collect();
+        self.without_shadow(matches, process)
+    }
+    
+    // Enables `unpackaged_only` patterns; without it they never match
+    pub fn set_package_verifier(&self, verifier: Arc<PackageVerifier>) -> Result<()> {
+        *self.package_verifier.write()
+            .map_err(|_| anyhow!("Failed to acquire package verifier write lock"))? = Some(verifier);
+        Ok(())
+    }
+    
+    fn check_resource_usage_pattern(&self, logic: &DetectionLogic, process: &ProcessInfo) -> Option<SustainedUsage> {
+        let DetectionLogic::ResourceUsagePattern { cpu_threshold, memory_threshold, duration, unpackaged_only } = logic else {
+            return None;
+        };
+        let usage = self.resource_usage.read().ok()?
+            .sustained(process.pid, *cpu_threshold, *memory_threshold, *duration)?;
+        if *unpackaged_only {
+            let verifier = self.package_verifier.read().ok()?.clone()?;
+            // A deleted binary reads as "/path (deleted)" and has no owner either
+            let packaged = process.exe_path.as_deref().is_some_and(|exe| verifier.owner(exe).is_some());
+            if packaged {
+                return None;
+            }
+        }
+        Some(usage)
     }
     
     fn check_command_line_pattern(&self, keywords: &[String], process: &ProcessInfo) -> bool {
@@ -516,6 +1051,17 @@ impl PatternMatcher {
         false
     }
     
+    fn check_process_memory_access(&self, names: &[String], process: &ProcessInfo, event: &FanotifyEvent) -> bool {
+        let target = event.path.as_ref()
+            .and_then(|path| path.to_str())
+            .and_then(|path| path.strip_prefix("/proc/")?.strip_suffix("/mem")?.parse::<u32>().ok());
+        let Some(target) = target.filter(|target| *target != process.pid) else {
+            return false;
+        };
+        std::fs::read_to_string(format!("/proc/{}/comm", target))
+            .is_ok_and(|comm| names.iter().any(|name| name == comm.trim()))
+    }
+    
     fn check_process_chain_pattern(&self, parent_pattern: &str, child_pattern: &str, process: &ProcessInfo) -> bool {
         // Look up the process chain
         let chains = match self.process_chains.read() {
@@ -693,6 +1239,10 @@ impl PatternMatcher {
         Ok(())
     }
     
+    pub fn get_process_chain(&self, pid: u32) -> Option<ProcessChain> {
+        self.process_chains.read().ok()?.get(&pid).cloned()
+    }
+    
     pub fn get_chain_analysis(&self, pid: u32) -> Option<String> {
         let chains = match self.process_chains.read() {
             Ok(c) => c,
@@ -726,6 +1276,46 @@ impl PatternMatcher {
     }
 }
 
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+// The domain itself or any name under it
+fn domain_matches(domain: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    domain == pattern || domain.strip_suffix(pattern.as_str()).is_some_and(|rest| rest.ends_with('.'))
+}
+
+// An address or CIDR range; entries that do not parse never match
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match cidr.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (cidr, None),
+    };
+    let network: IpAddr = addr.trim().parse().ok()?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    Some((network, prefix))
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -756,5 +1346,114 @@ mod tests {
         let matches = matcher.check_process(&process, None);
         assert!(!matches.is_empty());
         assert!(matches.iter().any(|(p, _)| p.category == PatternCategory::ReverseShell));
+        
+        // Shadow patterns are counted instead of reported
+        let log = ShadowLog::new();
+        matcher.set_shadow_log(log.clone()).unwrap();
+        let reverse_shells: Vec<String> = matches.iter()
+            .filter(|(p, _)| p.category == PatternCategory::ReverseShell)
+            .map(|(p, _)| p.id.clone())
+            .collect();
+        for id in &reverse_shells {
+            matcher.set_pattern_shadow(id, true).unwrap();
+        }
+        let matches = matcher.check_process(&process, None);
+        assert!(!matches.iter().any(|(p, _)| p.category == PatternCategory::ReverseShell));
+        assert_eq!(log.stats().len(), reverse_shells.len());
+        assert!(matcher.set_pattern_shadow("no_such_pattern", true).is_err());
+    }
+    
+    #[test]
+    fn test_network_pattern_detection() {
+        let matcher = PatternMatcher::new().unwrap();
+        let process = |pid, name: &str, start_time| ProcessInfo {
+            pid,
+            ppid: 1,
+            name: name.to_string(),
+            exe_path: Some(std::path::PathBuf::from(format!("/usr/bin/{}", name))),
+            cmdline: vec![name.to_string()],
+            uid: 1000,
+            gid: 1000,
+            start_time,
+        };
+        let ids = |matches: Vec<(BehaviorPattern, Severity)>| -> Vec<String> {
+            matches.into_iter().map(|(pattern, _)| pattern.id).collect()
+        };
+        
+        // IRC port, reported once however often the connection is seen
+        let bot = process(100, "kworkerd", 1);
+        assert!(matcher.record_network_connection(&bot, "198.51.100.7".parse().unwrap(), 6667));
+        assert!(!matcher.record_network_connection(&bot, "198.51.100.7".parse().unwrap(), 6667));
+        assert_eq!(ids(matcher.check_network_activity(&bot)), vec!["c2_irc_connection"]);
+        assert!(matcher.check_network_activity(&bot).is_empty());
+        // A new process behind the same pid starts over
+        assert!(matcher.check_process(&process(100, "kworkerd", 2), None).is_empty());
+        
+        // Domains come from DNS answers, parent domains included
+        let miner = process(200, "updater", 1);
+        matcher.record_dns_answer("gulf.MoneroOcean.stream.", &["203.0.113.9".parse().unwrap()]);
+        matcher.record_network_connection(&miner, "203.0.113.9".parse().unwrap(), 10128);
+        assert_eq!(ids(matcher.check_network_activity(&miner)), vec!["crypto_miner_pool_connection"]);
+        
+        // Command line and connection must both match
+        let shell = process(300, "bash", 1);
+        let curl = process(301, "curl", 1);
+        for p in [&shell, &curl] {
+            matcher.record_network_connection(p, "192.0.2.10".parse().unwrap(), 4444);
+        }
+        assert_eq!(ids(matcher.check_network_activity(&shell)), vec!["reverse_shell_port"]);
+        assert!(matcher.check_network_activity(&curl).is_empty());
+        
+        // CIDR ranges
+        matcher.record_network_connection(&curl, "192.0.2.20".parse().unwrap(), 443);
+        let c2 = DetectionLogic::NetworkPattern {
+            ports: vec![443],
+            ips: vec!["192.0.2.0/24".to_string(), "2001:db8::/32".to_string()],
+            domains: Vec::new(),
+        };
+        assert!(matcher.logic_matches(&c2, &curl, None));
+        matcher.record_network_connection(&shell, "2001:db8::1".parse().unwrap(), 443);
+        assert!(matcher.logic_matches(&c2, &shell, None));
+        let other = DetectionLogic::NetworkPattern { ports: Vec::new(), ips: vec!["10.0.0.0/8".to_string()], domains: Vec::new() };
+        assert!(!matcher.logic_matches(&other, &shell, None));
+        assert!(!domain_matches("notmoneroocean.stream", "moneroocean.stream"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_credential_access_detection() {
+        let matcher = PatternMatcher::new().unwrap();
+        let process = |name: &str| ProcessInfo {
+            pid: 4321,
+            ppid: 1,
+            name: name.to_string(),
+            exe_path: Some(std::path::PathBuf::from(format!("/usr/bin/{}", name))),
+            cmdline: vec![name.to_string()],
+            uid: 1000,
+            gid: 1000,
+            start_time: 0,
+        };
+        let open = |path: &str| FanotifyEvent { mask: 0, fd: -1, pid: 4321, path: Some(path.into()) };
+        let credential = |name: &str, path: &str| -> Vec<String> {
+            matcher.check_process(&process(name), Some(&open(path))).into_iter()
+                .filter(|(pattern, _)| pattern.category == PatternCategory::CredentialAccess)
+                .map(|(pattern, _)| pattern.id)
+                .collect()
+        };
+        
+        assert_eq!(credential("python3", "/home/bob/.ssh/id_ed25519"), ["cred_ssh_private_key"]);
+        assert!(credential("ssh", "/home/bob/.ssh/id_ed25519").is_empty());
+        assert!(credential("python3", "/home/bob/.ssh/id_ed25519.pub").is_empty());
+        assert_eq!(credential("curl", "/home/bob/.config/google-chrome/Default/Login Data"), ["cred_browser_store"]);
+        assert!(credential("chrome", "/home/bob/.config/google-chrome/Default/Login Data").is_empty());
+        assert_eq!(credential("node", "/home/bob/.aws/credentials"), ["cred_cloud_files"]);
+        assert_eq!(credential("cat", "/etc/shadow"), ["cred_shadow_read"]);
+        assert!(credential("unix_chkpwd", "/etc/shadow").is_empty());
+        
+        // Our own memory stands in for sshd's
+        let comm = std::fs::read_to_string("/proc/self/comm").unwrap().trim().to_string();
+        let memory = DetectionLogic::ProcessMemoryAccess(vec![comm]);
+        let mem = open(&format!("/proc/{}/mem", std::process::id()));
+        assert!(matcher.logic_matches(&memory, &process("dumper"), Some(&mem)));
+        assert!(!matcher.logic_matches(&memory, &process("dumper"), Some(&open("/proc/1/maps"))));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tracing::{debug, error, warn};
+
+use crate::file_watch::PermissionTimeoutConfig;
+
+// Smallest pause between checks, whatever the deadline
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchdogStats {
+    pub decisions: u64,
+    pub average_latency_ms: f64,
+    pub max_latency_ms: f64,
+    // Permission events the watchdog allowed because no decision came in time
+    pub overruns: u64,
+    // Permission events are no longer requested; accesses are only reported
+    pub notification_only: bool,
+}
+
+type DegradeHandler = Box<dyn Fn(&WatchdogStats) + Send + Sync>;
+
+#[derive(Default)]
+struct Latency {
+    decisions: u64,
+    total: Duration,
+    max: Duration,
+    overruns: u64,
+    recent_overruns: VecDeque<Instant>,
+}
+
+// Times every fanotify permission decision. One that outlives the deadline
+// is answered ALLOW from the watchdog thread, and repeated overruns switch
+// the marks to notification events so a stuck decision path cannot hang
+// the system
+pub struct PermissionWatchdog {
+    fanotify_fd: RawFd,
+    config: Mutex<PermissionTimeoutConfig>,
+    // Undecided permission events by event descriptor
+    pending: Mutex<HashMap<RawFd, Instant>>,
+    latency: Mutex<Latency>,
+    notification_only: AtomicBool,
+    // Mount marks as (path, mask), for taking the permission bits back off
+    marks: Mutex<Vec<(String, u64)>>,
+    running: AtomicBool,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    on_degrade: Mutex<Option<DegradeHandler>>,
+}
+
+impl PermissionWatchdog {
+    pub fn new(fanotify_fd: RawFd, config: PermissionTimeoutConfig) -> Self {
+        Self {
+            fanotify_fd,
+            config: Mutex::new(config),
+            pending: Mutex::new(HashMap::new()),
+            latency: Mutex::new(Latency::default()),
+            notification_only: AtomicBool::new(false),
+            marks: Mutex::new(Vec::new()),
+            running: AtomicBool::new(false),
+            thread: Mutex::new(None),
+            on_degrade: Mutex::new(None),
+        }
+    }
+
+    pub fn configure(&self, config: PermissionTimeoutConfig) {
+        if let Ok(mut current) = self.config.lock() {
+            *current = config;
+        }
+    }
+
+    // Called once, when the watchdog gives up on permission events
+    pub fn on_degrade<F>(&self, handler: F)
+    where
+        F: Fn(&WatchdogStats) + Send + Sync + 'static
+    {
+        if let Ok(mut current) = self.on_degrade.lock() {
+            *current = Some(Box::new(handler));
+        }
+    }
+
+    pub fn add_mark(&self, path: &str, mask: u64) {
+        if let Ok(mut marks) = self.marks.lock() {
+            marks.push((path.to_string(), mask));
+        }
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let watchdog = Arc::clone(self);
+        let thread = std::thread::spawn(move || {
+            while watchdog.running.load(Ordering::Relaxed) {
+                let deadline = watchdog.deadline();
+                std::thread::sleep((deadline / 4).max(MIN_CHECK_INTERVAL));
+                watchdog.check(Instant::now());
+            }
+        });
+        if let Ok(mut current) = self.thread.lock() {
+            *current = Some(thread);
+        }
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.lock().ok().and_then(|mut thread| thread.take()) {
+            thread.join().ok();
+        }
+    }
+
+    pub fn is_notification_only(&self) -> bool {
+        self.notification_only.load(Ordering::Relaxed)
+    }
+
+    pub fn begin(&self, fd: RawFd) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(fd, Instant::now());
+        }
+    }
+
+    // False when the watchdog already answered for `fd`, so the late
+    // decision must not be written
+    pub fn finish(&self, fd: RawFd) -> bool {
+        let Some(started) = self.pending.lock().ok().and_then(|mut pending| pending.remove(&fd)) else {
+            return false;
+        };
+        if let Ok(mut latency) = self.latency.lock() {
+            let elapsed = started.elapsed();
+            latency.decisions += 1;
+            latency.total += elapsed;
+            latency.max = latency.max.max(elapsed);
+        }
+        true
+    }
+
+    pub fn stats(&self) -> WatchdogStats {
+        let latency = self.latency.lock();
+        let Ok(latency) = latency else { return WatchdogStats::default() };
+        WatchdogStats {
+            decisions: latency.decisions,
+            average_latency_ms: if latency.decisions == 0 {
+                0.0
+            } else {
+                latency.total.as_secs_f64() * 1000.0 / latency.decisions as f64
+            },
+            max_latency_ms: latency.max.as_secs_f64() * 1000.0,
+            overruns: latency.overruns,
+            notification_only: self.is_notification_only(),
+        }
+    }
+
+    fn deadline(&self) -> Duration {
+        self.config.lock()
+            .map(|config| Duration::from_millis(config.deadline_ms))
+            .unwrap_or(Duration::from_secs(1))
+    }
+
+    fn check(&self, now: Instant) {
+        let Ok(config) = self.config.lock().map(|config| config.clone()) else { return };
+        let deadline = Duration::from_millis(config.deadline_ms);
+        // Answered with `pending` held: the read loop closes an event's fd
+        // once `finish` finds it gone, and a reused fd number must never get
+        // this late answer
+        let overdue: Vec<RawFd> = {
+            let Ok(mut pending) = self.pending.lock() else { return };
+            let overdue: Vec<RawFd> = pending.iter()
+                .filter(|(_, started)| now.saturating_duration_since(**started) >= deadline)
+                .map(|(fd, _)| *fd)
+                .collect();
+            for fd in &overdue {
+                if let Err(e) = respond(self.fanotify_fd, *fd, libc::FAN_ALLOW) {
+                    error!("Watchdog could not allow a stalled permission event: {}", e);
+                }
+                pending.remove(fd);
+            }
+            overdue
+        };
+        if overdue.is_empty() {
+            return;
+        }
+
+        let overruns = {
+            let Ok(mut latency) = self.latency.lock() else { return };
+            latency.overruns += overdue.len() as u64;
+            latency.max = latency.max.max(deadline);
+            let window = Duration::from_secs(config.window_secs);
+            latency.recent_overruns.extend(std::iter::repeat_n(now, overdue.len()));
+            while latency.recent_overruns.front().is_some_and(|at| now.saturating_duration_since(*at) > window) {
+                latency.recent_overruns.pop_front();
+            }
+            latency.recent_overruns.len()
+        };
+        warn!("{} permission decisions took longer than {:?} and were allowed", overdue.len(), deadline);
+
+        if overruns >= config.max_overruns && !self.notification_only.swap(true, Ordering::SeqCst) {
+            self.degrade(overruns, config.window_secs);
+        }
+    }
+
+    fn degrade(&self, overruns: usize, window_secs: u64) {
+        error!("{} permission decision overruns in {}s; file access is only reported from now on", overruns, window_secs);
+        for (path, mask) in self.marks.lock().map(|marks| marks.clone()).unwrap_or_default() {
+            let permission_bits = mask & PERMISSION_EVENTS;
+            if permission_bits == 0 {
+                continue;
+            }
+            if let Err(e) = remove_mount_mark(self.fanotify_fd, &path, permission_bits) {
+                warn!("Failed to drop permission events on {}: {}", path, e);
+            } else {
+                debug!("Dropped permission events on {}", path);
+            }
+        }
+        let stats = self.stats();
+        if let Ok(handler) = self.on_degrade.lock() {
+            if let Some(ref handler) = *handler {
+                handler(&stats);
+            }
+        }
+    }
+}
+
+const PERMISSION_EVENTS: u64 = libc::FAN_OPEN_PERM | libc::FAN_ACCESS_PERM | libc::FAN_OPEN_EXEC_PERM;
+
+pub(super) fn respond(fanotify_fd: RawFd, fd: RawFd, response: u32) -> std::io::Result<()> {
+    let resp = libc::fanotify_response { fd, response };
+    let ret = unsafe {
+        libc::write(fanotify_fd, &resp as *const _ as *const libc::c_void, std::mem::size_of::<libc::fanotify_response>())
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn remove_mount_mark(fanotify_fd: RawFd, path: &str, mask: u64) -> std::io::Result<()> {
+    let path = std::ffi::CString::new(path)?;
+    let ret = unsafe {
+        libc::syscall(libc::SYS_fanotify_mark, fanotify_fd, libc::FAN_MARK_REMOVE | libc::FAN_MARK_MOUNT, mask, libc::AT_FDCWD, path.as_ptr())
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    #[test]
+    fn test_permission_watchdog() {
+        // A pipe stands in for the fanotify descriptor the answers go to
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut answers = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+
+        let watchdog = PermissionWatchdog::new(fds[1], PermissionTimeoutConfig { deadline_ms: 50, max_overruns: 2, window_secs: 60 });
+        let degraded = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&degraded);
+        watchdog.on_degrade(move |stats| flag.store(stats.notification_only, Ordering::SeqCst));
+
+        watchdog.begin(7);
+        assert!(watchdog.finish(7));
+        assert_eq!(watchdog.stats().decisions, 1);
+
+        let start = Instant::now();
+        watchdog.begin(8);
+        watchdog.check(start);
+        watchdog.check(start + Duration::from_millis(100));
+        // Already allowed, the late decision is dropped
+        assert!(!watchdog.finish(8));
+        let mut answer = [0u8; 8];
+        answers.read_exact(&mut answer).unwrap();
+        assert_eq!(i32::from_ne_bytes(answer[..4].try_into().unwrap()), 8);
+        assert_eq!(u32::from_ne_bytes(answer[4..].try_into().unwrap()), libc::FAN_ALLOW);
+        assert!(!watchdog.is_notification_only());
+
+        watchdog.begin(9);
+        watchdog.check(Instant::now() + Duration::from_millis(100));
+        assert!(watchdog.is_notification_only());
+        assert!(degraded.load(Ordering::SeqCst));
+        let stats = watchdog.stats();
+        assert_eq!(stats.overruns, 2);
+        assert_eq!(stats.decisions, 1);
+
+        unsafe { libc::close(fds[1]) };
+    }
+}
@@ -0,0 +1,787 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn, error};
+
+use crate::exemptions::Exemptions;
+use crate::monitor::{SecurityEvent, SecurityEventType};
+use crate::scripting::{CompiledScript, RuleScript};
+use super::escalation;
+use super::event_correlation::{CorrelatedEvent, Severity};
+use super::isolation::{parse_network, HostIsolation};
+
+// Runs kept for GET /api/playbooks/runs and manual rollback
+const MAX_RUNS: usize = 200;
+
+// Ordered response actions run when a correlation fires. One playbook per
+// YAML or JSON file in the playbooks directory:
+//
+//   id: contain_reverse_shell
+//   name: Contain reverse shell
+//   trigger: { rule_ids: [reverse_shell], min_severity: high }
+//   on_failure: rollback
+//   steps:
+//     - { name: evidence, action: { type: snapshot_process } }
+//     - { name: kill, action: { type: kill_process_tree }, timeout: 10 }
+//     - name: quarantine
+//       action: { type: quarantine_file }
+//       condition: { source: "detection.pid != ()" }
+//     - { name: isolate, action: { type: isolate_network, allow: ["10.0.0.0/8"] } }
+//     - { name: notify, action: { type: alert, message: "Contained reverse shell" } }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playbook {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub trigger: PlaybookTrigger,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+    pub steps: Vec<PlaybookStep>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// Which correlations start the playbook; no rule ids means any rule
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlaybookTrigger {
+    #[serde(default)]
+    pub rule_ids: Vec<String>,
+    #[serde(default)]
+    pub min_severity: Option<Severity>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    // Undo the steps that ran, newest first, and stop
+    #[default]
+    Rollback,
+    Abort,
+    Continue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookStep {
+    pub name: String,
+    pub action: PlaybookAction,
+    // Runs with `detection` and `events`; the step is skipped unless it
+    // returns true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<RuleScript>,
+    // Seconds
+    #[serde(default = "default_step_timeout")]
+    pub timeout: u64,
+}
+
+fn default_step_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlaybookAction {
+    // Host isolation, additionally allowing these addresses or CIDRs;
+    // minutes defaults to the isolation config's
+    IsolateNetwork {
+        #[serde(default)]
+        allow: Vec<String>,
+        #[serde(default)]
+        minutes: Option<u64>,
+    },
+    KillProcessTree,
+    // The detection's file unless a path is given
+    QuarantineFile {
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    // Copies /proc data of the detection's process
+    SnapshotProcess,
+    Alert {
+        #[serde(default)]
+        message: Option<String>,
+    },
+}
+
+// What a playbook acts on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookContext {
+    pub detection_id: String,
+    #[serde(default)]
+    pub rule_id: Option<String>,
+    pub severity: Severity,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    // The executable or file the detection is about
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub events: Vec<SecurityEvent>,
+}
+
+impl From<&CorrelatedEvent> for PlaybookContext {
+    fn from(correlated: &CorrelatedEvent) -> Self {
+        let latest = correlated.events.iter().max_by_key(|event| event.timestamp);
+        let path = latest.map(|event| match &event.event_type {
+            SecurityEventType::FileExecution { target_path, .. } => target_path.clone(),
+            _ => event.process_info.path.clone(),
+        });
+        Self {
+            detection_id: correlated.id.clone(),
+            rule_id: Some(correlated.rule.id.clone()),
+            severity: correlated.severity,
+            description: correlated.description.clone(),
+            pid: latest.map(|event| event.process_info.pid),
+            path,
+            events: correlated.events.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Completed,
+    // A step failed and was left as it was
+    Failed,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Succeeded,
+    Skipped,
+    Failed,
+    TimedOut,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub name: String,
+    pub status: StepStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookRun {
+    pub id: String,
+    pub playbook_id: String,
+    pub detection_id: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    pub steps: Vec<StepResult>,
+    // Whether some steps can still be undone with a rollback
+    pub reversible: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookAlert {
+    pub playbook_id: String,
+    pub run_id: String,
+    pub detection_id: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybookReloadSummary {
+    pub total: usize,
+    pub files: Vec<PathBuf>,
+}
+
+// Where responses put their output and what they act through
+#[derive(Clone)]
+pub struct PlaybookSettings {
+    pub quarantine_dir: PathBuf,
+    pub snapshot_dir: PathBuf,
+    // Isolation fails without it
+    pub isolation: Option<Arc<HostIsolation>>,
+    // Processes and binaries kills and quarantines leave alone
+    pub exemptions: Exemptions,
+}
+
+impl Default for PlaybookSettings {
+    fn default() -> Self {
+        Self {
+            quarantine_dir: PathBuf::from("/var/quarantine/fluxdefense"),
+            snapshot_dir: PathBuf::from("/var/lib/fluxdefense/snapshots"),
+            isolation: None,
+            exemptions: Exemptions::default(),
+        }
+    }
+}
+
+// How to reverse a step
+enum Undo {
+    // Lifts the host isolation, including one renewed after this step
+    Release,
+    Restore { quarantined: PathBuf, original: PathBuf, mode: u32 },
+}
+
+struct LoadedPlaybook {
+    playbook: Playbook,
+    source: Option<PathBuf>,
+    conditions: Vec<Option<Arc<CompiledScript>>>,
+}
+
+pub struct PlaybookEngine {
+    dir: Option<PathBuf>,
+    settings: PlaybookSettings,
+    playbooks: RwLock<Vec<Arc<LoadedPlaybook>>>,
+    on_alert: Arc<dyn Fn(PlaybookAlert) + Send + Sync>,
+    runs: Mutex<VecDeque<PlaybookRun>>,
+    // Undo steps of each run, by step index
+    undo: Mutex<HashMap<String, Vec<(usize, Undo)>>>,
+}
+
+impl PlaybookEngine {
+    // Playbooks are read from `dir` now and on reload_playbooks
+    pub fn new<F>(dir: Option<PathBuf>, settings: PlaybookSettings, on_alert: F) -> Result<Self>
+    where
+        F: Fn(PlaybookAlert) + Send + Sync + 'static,
+    {
+        let engine = Self {
+            dir,
+            settings,
+            playbooks: RwLock::new(Vec::new()),
+            on_alert: Arc::new(on_alert),
+            runs: Mutex::new(VecDeque::new()),
+            undo: Mutex::new(HashMap::new()),
+        };
+        engine.reload_playbooks()?;
+        Ok(engine)
+    }
+
+    pub fn playbooks(&self) -> Vec<Playbook> {
+        self.playbooks.read()
+            .map(|playbooks| playbooks.iter().map(|loaded| loaded.playbook.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn playbook(&self, id: &str) -> Option<Playbook> {
+        self.find(id).map(|loaded| loaded.playbook.clone())
+    }
+
+    fn find(&self, id: &str) -> Option<Arc<LoadedPlaybook>> {
+        self.playbooks.read().ok()?.iter().find(|loaded| loaded.playbook.id == id).cloned()
+    }
+
+    // Re-reads the directory; on any error the current playbooks stay active
+    pub fn reload_playbooks(&self) -> Result<PlaybookReloadSummary> {
+        let Some(dir) = &self.dir else {
+            return Ok(PlaybookReloadSummary { total: self.playbooks().len(), files: Vec::new() });
+        };
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read playbooks directory {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yaml") | Some("yml") | Some("json")
+            ))
+            .collect();
+        files.sort();
+
+        let mut loaded = Vec::new();
+        let mut seen = HashSet::new();
+        for path in &files {
+            let playbook = load_playbook_file(path)?;
+            if !seen.insert(playbook.id.clone()) {
+                return Err(anyhow!("Duplicate playbook id '{}' in {:?}", playbook.id, path));
+            }
+            loaded.push(Arc::new(compile(playbook, Some(path.clone()))?));
+        }
+
+        let total = loaded.len();
+        *self.playbooks.write().map_err(|_| anyhow!("Failed to acquire playbooks write lock"))? = loaded;
+        info!("Loaded {} response playbooks", total);
+        Ok(PlaybookReloadSummary { total, files })
+    }
+
+    // Adds or replaces a playbook, writing it to the playbooks directory
+    // when there is one
+    pub fn save_playbook(&self, playbook: Playbook) -> Result<()> {
+        let existing = self.find(&playbook.id);
+        let source = match (existing.as_ref().and_then(|loaded| loaded.source.clone()), &self.dir) {
+            (Some(source), _) => Some(source),
+            (None, Some(dir)) => Some(dir.join(format!("{}.yaml", playbook.id))),
+            (None, None) => None,
+        };
+        let loaded = compile(playbook, source.clone())?;
+        if let Some(source) = &source {
+            let content = match source.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => serde_json::to_string_pretty(&loaded.playbook)?,
+                _ => serde_yaml::to_string(&loaded.playbook)?,
+            };
+            fs::write(source, content).with_context(|| format!("Failed to write playbook {:?}", source))?;
+        }
+
+        let mut playbooks = self.playbooks.write().map_err(|_| anyhow!("Failed to acquire playbooks write lock"))?;
+        playbooks.retain(|current| current.playbook.id != loaded.playbook.id);
+        playbooks.push(Arc::new(loaded));
+        Ok(())
+    }
+
+    pub fn delete_playbook(&self, id: &str) -> Result<bool> {
+        let Some(existing) = self.find(id) else {
+            return Ok(false);
+        };
+        if let Some(source) = &existing.source {
+            fs::remove_file(source).with_context(|| format!("Failed to remove playbook {:?}", source))?;
+        }
+        self.playbooks.write()
+            .map_err(|_| anyhow!("Failed to acquire playbooks write lock"))?
+            .retain(|current| current.playbook.id != id);
+        Ok(true)
+    }
+
+    // Call with every correlated detection; runs each enabled playbook whose
+    // trigger matches, in id order
+    pub fn handle(&self, correlated: &CorrelatedEvent) -> Vec<PlaybookRun> {
+        let mut matching: Vec<Arc<LoadedPlaybook>> = match self.playbooks.read() {
+            Ok(playbooks) => playbooks.iter()
+                .filter(|loaded| loaded.playbook.enabled && loaded.playbook.trigger.matches(correlated))
+                .cloned()
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        matching.sort_by(|a, b| a.playbook.id.cmp(&b.playbook.id));
+
+        let context = PlaybookContext::from(correlated);
+        matching.iter().map(|loaded| self.execute(loaded, &context)).collect()
+    }
+
+    // Runs a playbook by hand, whatever its trigger says
+    pub fn run_playbook(&self, id: &str, context: &PlaybookContext) -> Result<PlaybookRun> {
+        let loaded = self.find(id).ok_or_else(|| anyhow!("No playbook '{}'", id))?;
+        Ok(self.execute(&loaded, context))
+    }
+
+    pub fn runs(&self) -> Vec<PlaybookRun> {
+        self.runs.lock().map(|runs| runs.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    // Undoes what a finished run left in place, e.g. to lift an isolation
+    pub fn rollback_run(&self, run_id: &str) -> Result<PlaybookRun> {
+        let undo = self.undo.lock()
+            .map_err(|_| anyhow!("Playbook undo lock poisoned"))?
+            .remove(run_id)
+            .ok_or_else(|| anyhow!("Run {} has nothing to roll back", run_id))?;
+        let mut runs = self.runs.lock().map_err(|_| anyhow!("Playbook runs lock poisoned"))?;
+        let run = runs.iter_mut()
+            .find(|run| run.id == run_id)
+            .ok_or_else(|| anyhow!("No playbook run {}", run_id))?;
+        self.undo_steps(undo, &mut run.steps);
+        run.status = RunStatus::RolledBack;
+        run.reversible = false;
+        Ok(run.clone())
+    }
+
+    fn execute(&self, loaded: &LoadedPlaybook, context: &PlaybookContext) -> PlaybookRun {
+        let playbook = &loaded.playbook;
+        let run_id = uuid::Uuid::new_v4().to_string();
+        let started_at = Utc::now();
+        info!("Running playbook '{}' for detection {}", playbook.id, context.detection_id);
+
+        let detection = serde_json::to_value(context).unwrap_or_default();
+        let events = detection.get("events").cloned().unwrap_or_default();
+        let mut steps = Vec::new();
+        let mut undo = Vec::new();
+        let mut status = RunStatus::Completed;
+        for (index, step) in playbook.steps.iter().enumerate() {
+            if let Some(condition) = &loaded.conditions[index] {
+                match condition.run_bool(&[("detection", &detection), ("events", &events)]) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        steps.push(StepResult { name: step.name.clone(), status: StepStatus::Skipped, detail: "Condition not met".to_string() });
+                        continue;
+                    }
+                    Err(e) => {
+                        steps.push(StepResult { name: step.name.clone(), status: StepStatus::Failed, detail: format!("Condition: {:#}", e) });
+                        status = RunStatus::Failed;
+                        if self.stop_after_failure(playbook, &mut undo, &mut steps, &mut status) {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let outcome = self.run_step(playbook, &run_id, step, context);
+            match outcome {
+                Ok((detail, step_undo)) => {
+                    info!("Playbook '{}' step '{}': {}", playbook.id, step.name, detail);
+                    if let Some(step_undo) = step_undo {
+                        undo.push((steps.len(), step_undo));
+                    }
+                    steps.push(StepResult { name: step.name.clone(), status: StepStatus::Succeeded, detail });
+                }
+                Err((step_status, detail)) => {
+                    error!("Playbook '{}' step '{}': {}", playbook.id, step.name, detail);
+                    steps.push(StepResult { name: step.name.clone(), status: step_status, detail });
+                    status = RunStatus::Failed;
+                    if self.stop_after_failure(playbook, &mut undo, &mut steps, &mut status) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let run = PlaybookRun {
+            id: run_id.clone(),
+            playbook_id: playbook.id.clone(),
+            detection_id: context.detection_id.clone(),
+            started_at,
+            finished_at: Utc::now(),
+            status,
+            steps,
+            reversible: !undo.is_empty(),
+        };
+        if !undo.is_empty() {
+            if let Ok(mut pending) = self.undo.lock() {
+                pending.insert(run_id, undo);
+            }
+        }
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.push_back(run.clone());
+            while runs.len() > MAX_RUNS {
+                if let Some(evicted) = runs.pop_front() {
+                    if let Ok(mut pending) = self.undo.lock() {
+                        pending.remove(&evicted.id);
+                    }
+                }
+            }
+        }
+        run
+    }
+
+    // Applies the failure policy; returns whether to stop
+    fn stop_after_failure(&self, playbook: &Playbook, undo: &mut Vec<(usize, Undo)>, steps: &mut [StepResult], status: &mut RunStatus) -> bool {
+        match playbook.on_failure {
+            FailurePolicy::Continue => false,
+            FailurePolicy::Abort => true,
+            FailurePolicy::Rollback => {
+                self.undo_steps(std::mem::take(undo), steps);
+                *status = RunStatus::RolledBack;
+                true
+            }
+        }
+    }
+
+    fn undo_steps(&self, undo: Vec<(usize, Undo)>, steps: &mut [StepResult]) {
+        for (index, step_undo) in undo.into_iter().rev() {
+            let result = match step_undo {
+                Undo::Release => isolation(&self.settings).and_then(|isolation| isolation.release()).map(|_| ()),
+                Undo::Restore { quarantined, original, mode } => restore_file(&quarantined, &original, mode),
+            };
+            let Some(step) = steps.get_mut(index) else { continue };
+            match result {
+                Ok(()) => {
+                    step.status = StepStatus::RolledBack;
+                    step.detail = format!("{} (rolled back)", step.detail);
+                }
+                Err(e) => {
+                    warn!("Failed to roll back step '{}': {:#}", step.name, e);
+                    step.detail = format!("{} (rollback failed: {:#})", step.detail, e);
+                }
+            }
+        }
+    }
+
+    // Runs the action on its own thread so a hung step cannot stall the
+    // playbook; one that times out may still finish later and is not undone
+    fn run_step(&self, playbook: &Playbook, run_id: &str, step: &PlaybookStep, context: &PlaybookContext)
+        -> std::result::Result<(String, Option<Undo>), (StepStatus, String)>
+    {
+        let action = step.action.clone();
+        let context = context.clone();
+        let settings = self.settings.clone();
+        let on_alert = Arc::clone(&self.on_alert);
+        let alert_context = (playbook.id.clone(), playbook.name.clone(), run_id.to_string());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name(format!("playbook-{}", step.name))
+            .spawn(move || {
+                let result = perform(&action, &context, &settings, &on_alert, &alert_context);
+                let _ = sender.send(result);
+            })
+            .map_err(|e| (StepStatus::Failed, format!("Failed to start step: {}", e)))?;
+
+        match receiver.recv_timeout(Duration::from_secs(step.timeout.max(1))) {
+            Ok(Ok(outcome)) => Ok(outcome),
+            Ok(Err(e)) => Err((StepStatus::Failed, format!("{:#}", e))),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err((StepStatus::TimedOut, format!("Timed out after {}s", step.timeout))),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err((StepStatus::Failed, "Step panicked".to_string())),
+        }
+    }
+}
+
+impl PlaybookTrigger {
+    pub fn matches(&self, correlated: &CorrelatedEvent) -> bool {
+        (self.rule_ids.is_empty() || self.rule_ids.contains(&correlated.rule.id))
+            && self.min_severity.is_none_or(|min| correlated.severity >= min)
+    }
+}
+
+impl Playbook {
+    pub fn validate(&self) -> Result<()> {
+        let valid_id = !self.id.is_empty()
+            && self.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_id {
+            return Err(anyhow!("playbook id '{}' must be letters, digits, '_' or '-'", self.id));
+        }
+        if self.steps.is_empty() {
+            return Err(anyhow!("playbook '{}' has no steps", self.id));
+        }
+        for step in &self.steps {
+            if step.timeout == 0 {
+                return Err(anyhow!("playbook '{}' step '{}': timeout must be at least one second", self.id, step.name));
+            }
+            if let PlaybookAction::IsolateNetwork { allow, .. } = &step.action {
+                for entry in allow {
+                    parse_network(entry).with_context(|| format!("playbook '{}' step '{}'", self.id, step.name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn load_playbook_file(path: &Path) -> Result<Playbook> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content).with_context(|| format!("Invalid playbook in {:?}", path)),
+        _ => serde_yaml::from_str(&content).with_context(|| format!("Invalid playbook in {:?}", path)),
+    }
+}
+
+fn compile(playbook: Playbook, source: Option<PathBuf>) -> Result<LoadedPlaybook> {
+    playbook.validate()?;
+    let conditions = playbook.steps.iter()
+        .map(|step| step.condition.as_ref()
+            .map(|condition| condition.compile().map(Arc::new)
+                .with_context(|| format!("playbook '{}' step '{}'", playbook.id, step.name)))
+            .transpose())
+        .collect::<Result<Vec<_>>>()?;
+    Ok(LoadedPlaybook { playbook, source, conditions })
+}
+
+fn perform(
+    action: &PlaybookAction,
+    context: &PlaybookContext,
+    settings: &PlaybookSettings,
+    on_alert: &Arc<dyn Fn(PlaybookAlert) + Send + Sync>,
+    (playbook_id, playbook_name, run_id): &(String, String, String),
+) -> Result<(String, Option<Undo>)> {
+    let pid = || context.pid.ok_or_else(|| anyhow!("Detection has no process"));
+    match action {
+        PlaybookAction::IsolateNetwork { allow, minutes } => {
+            let reason = format!("playbook {}: {}", playbook_name, context.description);
+            let duration = minutes.map(|minutes| Duration::from_secs(minutes * 60));
+            let status = isolation(settings)?.isolate(duration, &reason, allow)?;
+            Ok((format!("Isolated host, allowing {}", status.allowed.join(", ")), Some(Undo::Release)))
+        }
+        PlaybookAction::KillProcessTree => {
+            let killed = escalation::kill_process_tree(pid()?, &settings.exemptions)?;
+            Ok((format!("Killed {} processes", killed.len()), None))
+        }
+        PlaybookAction::QuarantineFile { path } => {
+            let original = path.clone().or_else(|| context.path.clone())
+                .ok_or_else(|| anyhow!("Detection has no file"))?;
+            let mode = file_mode(&original)?;
+            let quarantined = escalation::quarantine_file(&original, &settings.quarantine_dir, &settings.exemptions)?;
+            Ok((format!("Quarantined {} to {}", original.display(), quarantined.display()),
+                Some(Undo::Restore { quarantined, original, mode })))
+        }
+        PlaybookAction::SnapshotProcess => {
+            let dir = settings.snapshot_dir.join(format!("{}-{}", run_id, pid()?));
+            let files = snapshot_process(pid()?, &dir)?;
+            Ok((format!("Saved {} files to {}", files, dir.display()), None))
+        }
+        PlaybookAction::Alert { message } => {
+            let message = message.clone().unwrap_or_else(|| format!("{}: {}", playbook_name, context.description));
+            warn!("Playbook alert: {}", message);
+            on_alert(PlaybookAlert {
+                playbook_id: playbook_id.clone(),
+                run_id: run_id.clone(),
+                detection_id: context.detection_id.clone(),
+                severity: context.severity,
+                message: message.clone(),
+            });
+            Ok((format!("Sent alert: {}", message), None))
+        }
+    }
+}
+
+fn isolation(settings: &PlaybookSettings) -> Result<Arc<HostIsolation>> {
+    settings.isolation.clone().ok_or_else(|| anyhow!("Host isolation is not enabled"))
+}
+
+fn file_mode(path: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path).with_context(|| format!("Cannot quarantine {:?}", path))?.permissions().mode() & 0o7777)
+}
+
+fn restore_file(quarantined: &Path, original: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if original.exists() {
+        return Err(anyhow!("{:?} exists again, leaving the quarantined copy in place", original));
+    }
+    if fs::rename(quarantined, original).is_err() {
+        fs::copy(quarantined, original).with_context(|| format!("Failed to restore {:?}", original))?;
+        fs::remove_file(quarantined)?;
+    }
+    fs::set_permissions(original, fs::Permissions::from_mode(mode))?;
+    info!("Restored {:?} from quarantine", original);
+    Ok(())
+}
+
+// Copies what /proc knows about the process before it is killed; returns the
+// number of files written
+fn snapshot_process(pid: u32, dir: &Path) -> Result<usize> {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    if !proc_dir.exists() {
+        return Err(anyhow!("Process {} is gone", pid));
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create snapshot directory {:?}", dir))?;
+
+    let mut written = 0;
+    for name in ["cmdline", "environ", "maps", "status", "stat", "cgroup"] {
+        match fs::read(proc_dir.join(name)) {
+            Ok(data) => {
+                fs::write(dir.join(name), data)?;
+                written += 1;
+            }
+            Err(e) => warn!("Snapshot of {} skipped {}: {}", pid, name, e),
+        }
+    }
+    let fds: String = fs::read_dir(proc_dir.join("fd"))
+        .map(|entries| entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let target = fs::read_link(entry.path()).ok()?;
+                Some(format!("{} -> {}\n", entry.file_name().to_string_lossy(), target.display()))
+            })
+            .collect())
+        .unwrap_or_default();
+    fs::write(dir.join("fd"), fds)?;
+    Ok(written + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::monitor::{ProcessInfo, Verdict};
+
+    #[test]
+    fn test_playbook_run_and_rollback() {
+        let root = std::env::temp_dir().join(format!("flux-playbooks-{}", std::process::id()));
+        let dir = root.join("playbooks");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("contain.yaml"), r#"
+id: contain
+name: Contain
+trigger: { rule_ids: [reverse_shell], min_severity: high }
+steps:
+  - { name: evidence, action: { type: snapshot_process } }
+  - { name: kill, action: { type: kill_process_tree } }
+  - name: notify
+    action: { type: alert }
+    condition: { source: "detection.severity == \"low\"" }
+  - { name: quarantine, action: { type: quarantine_file } }
+  - { name: isolate, action: { type: isolate_network, allow: ["10.0.0.0/8"] } }
+"#).unwrap();
+
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&alerts);
+        let settings = PlaybookSettings {
+            quarantine_dir: root.join("quarantine"),
+            snapshot_dir: root.join("snapshots"),
+            isolation: None,
+            exemptions: Exemptions::default(),
+        };
+        let engine = PlaybookEngine::new(Some(dir.clone()), settings, move |alert| sink.lock().unwrap().push(alert)).unwrap();
+        assert_eq!(engine.playbooks().len(), 1);
+
+        let mut child = std::process::Command::new("sh").args(["-c", "sleep 30 & wait"]).spawn().unwrap();
+        let payload = root.join("payload");
+        fs::write(&payload, "#!/bin/sh\n").unwrap();
+        let event = SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::FileExecution { target_path: payload.clone(), file_hash: None, code_signature: None },
+            process_info: ProcessInfo {
+                pid: child.id(),
+                path: PathBuf::from("/bin/sh"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        };
+        let rule = super::super::EventCorrelator::new().unwrap().rules().remove(0);
+        let mut correlated = CorrelatedEvent {
+            id: "corr-1".to_string(),
+            rule,
+            events: vec![event],
+            detected_at: Instant::now(),
+            severity: Severity::Critical,
+            description: "reverse shell".to_string(),
+        };
+        assert!(engine.handle(&correlated).is_empty());
+        correlated.rule.id = "reverse_shell".to_string();
+
+        // Isolation fails without host isolation set up, so the quarantine is undone
+        let runs = engine.handle(&correlated);
+        assert_eq!(runs.len(), 1);
+        let run = &runs[0];
+        let statuses: Vec<StepStatus> = run.steps.iter().map(|step| step.status).collect();
+        assert_eq!(statuses, [StepStatus::Succeeded, StepStatus::Succeeded, StepStatus::Skipped,
+            StepStatus::RolledBack, StepStatus::Failed]);
+        assert_eq!(run.status, RunStatus::RolledBack);
+        assert!(!run.reversible);
+        assert!(payload.exists());
+        assert!(alerts.lock().unwrap().is_empty());
+        assert!(root.join("snapshots").join(format!("{}-{}", run.id, child.id())).join("cmdline").exists());
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+
+        // Managing playbooks rewrites the directory
+        let mut playbook = engine.playbook("contain").unwrap();
+        playbook.on_failure = FailurePolicy::Continue;
+        playbook.steps.retain(|step| step.name == "quarantine");
+        engine.save_playbook(playbook).unwrap();
+        assert_eq!(engine.reload_playbooks().unwrap().total, 1);
+        let run = engine.run_playbook("contain", &PlaybookContext::from(&correlated)).unwrap();
+        assert!(run.reversible && !payload.exists());
+        assert_eq!(engine.rollback_run(&run.id).unwrap().status, RunStatus::RolledBack);
+        assert!(payload.exists());
+        assert!(engine.rollback_run(&run.id).is_err());
+        assert!(engine.delete_playbook("contain").unwrap());
+        assert!(engine.playbooks().is_empty() && fs::read_dir(&dir).unwrap().next().is_none());
+
+        let mut invalid = engine.playbook("contain");
+        assert!(invalid.is_none());
+        invalid = serde_yaml::from_str("{id: ../x, name: Escape, steps: [{name: a, action: {type: kill_process_tree}}]}").ok();
+        assert!(engine.save_playbook(invalid.unwrap()).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
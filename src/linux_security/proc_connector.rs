@@ -0,0 +1,236 @@
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use anyhow::{Result, anyhow};
+use libc::{c_int, sockaddr_nl};
+use tracing::{info, debug};
+
+// Netlink connector constants (linux/connector.h, linux/cn_proc.h)
+const NETLINK_CONNECTOR: c_int = 11;
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_CN_MCAST_IGNORE: u32 = 2;
+
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+const NLMSG_HDR_LEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+// what, cpu, timestamp_ns
+const PROC_EVENT_HDR_LEN: usize = 16;
+
+// Process lifecycle changes reported by the kernel. Thread creation and
+// exit are filtered out; pids are thread group ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcEvent {
+    Fork { parent_pid: u32, child_pid: u32 },
+    Exec { pid: u32 },
+    Exit { pid: u32, exit_code: u32 },
+}
+
+// Subscription to the kernel's process event multicast group. Needs
+// CAP_NET_ADMIN; the socket is non-blocking so it can be polled.
+pub struct ProcConnector {
+    socket: RawFd,
+}
+
+impl ProcConnector {
+    pub fn open() -> Result<Self> {
+        let socket = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                NETLINK_CONNECTOR,
+            )
+        };
+        if socket < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to create proc connector socket: {}", err));
+        }
+        let connector = Self { socket };
+
+        let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = CN_IDX_PROC;
+        let ret = unsafe {
+            libc::bind(
+                socket,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<sockaddr_nl>() as u32,
+            )
+        };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to bind proc connector socket: {}", err));
+        }
+
+        connector.send_op(PROC_CN_MCAST_LISTEN)?;
+        info!("Subscribed to kernel process events");
+        Ok(connector)
+    }
+
+    fn send_op(&self, op: u32) -> Result<()> {
+        let total = NLMSG_HDR_LEN + CN_MSG_LEN + 4;
+        let mut msg = Vec::with_capacity(total);
+        // nlmsghdr
+        msg.extend_from_slice(&(total as u32).to_ne_bytes());
+        msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&std::process::id().to_ne_bytes());
+        // cn_msg
+        msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&4u16.to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&op.to_ne_bytes());
+
+        let sent = unsafe { libc::send(self.socket, msg.as_ptr() as *const libc::c_void, msg.len(), 0) };
+        if sent < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(anyhow!("Failed to send proc connector request: {}", err));
+        }
+        Ok(())
+    }
+
+    // Returns whatever is queued without blocking. An error of kind
+    // `OutOfMemory` (ENOBUFS) means events were lost and the caller should
+    // rescan /proc.
+    pub fn read_events(&self) -> std::io::Result<Vec<ProcEvent>> {
+        let mut events = Vec::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            let len = unsafe {
+                libc::recv(self.socket, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+            };
+            if len < 0 {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) => return Ok(events),
+                    Some(libc::EINTR) => continue,
+                    Some(libc::ENOBUFS) => {
+                        return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "process events overflowed"));
+                    }
+                    _ => return Err(err),
+                }
+            }
+            if len == 0 {
+                return Ok(events);
+            }
+            parse_messages(&buffer[..len as usize], &mut events);
+        }
+    }
+}
+
+impl AsRawFd for ProcConnector {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket
+    }
+}
+
+impl Drop for ProcConnector {
+    fn drop(&mut self) {
+        if let Err(e) = self.send_op(PROC_CN_MCAST_IGNORE) {
+            debug!("{}", e);
+        }
+        unsafe { libc::close(self.socket) };
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// One datagram may carry several netlink messages
+fn parse_messages(data: &[u8], events: &mut Vec<ProcEvent>) {
+    let mut offset = 0;
+    while offset + NLMSG_HDR_LEN <= data.len() {
+        let Some(msg_len) = read_u32(data, offset).map(|len| len as usize) else { break };
+        if msg_len < NLMSG_HDR_LEN || offset + msg_len > data.len() {
+            break;
+        }
+        let payload = &data[offset + NLMSG_HDR_LEN..offset + msg_len];
+        if let Some(event) = parse_proc_event(payload) {
+            events.push(event);
+        }
+        // NLMSG_ALIGN
+        offset += (msg_len + 3) & !3;
+    }
+}
+
+fn parse_proc_event(payload: &[u8]) -> Option<ProcEvent> {
+    if read_u32(payload, 0)? != CN_IDX_PROC || read_u32(payload, 4)? != CN_VAL_PROC {
+        return None;
+    }
+    let event = payload.get(CN_MSG_LEN..)?;
+    let what = read_u32(event, 0)?;
+    let data = event.get(PROC_EVENT_HDR_LEN..)?;
+
+    match what {
+        PROC_EVENT_FORK => {
+            let parent_tgid = read_u32(data, 4)?;
+            let child_pid = read_u32(data, 8)?;
+            let child_tgid = read_u32(data, 12)?;
+            // A new thread rather than a new process
+            if child_pid != child_tgid {
+                return None;
+            }
+            Some(ProcEvent::Fork { parent_pid: parent_tgid, child_pid: child_tgid })
+        }
+        PROC_EVENT_EXEC => Some(ProcEvent::Exec { pid: read_u32(data, 4)? }),
+        PROC_EVENT_EXIT => {
+            let pid = read_u32(data, 0)?;
+            let tgid = read_u32(data, 4)?;
+            if pid != tgid {
+                return None;
+            }
+            Some(ProcEvent::Exit { pid: tgid, exit_code: read_u32(data, 8)? })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(what: u32, fields: &[u32]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for value in [CN_IDX_PROC, CN_VAL_PROC, 0, 0] {
+            body.extend_from_slice(&value.to_ne_bytes());
+        }
+        body.extend_from_slice(&((PROC_EVENT_HDR_LEN + fields.len() * 4) as u16).to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        body.extend_from_slice(&what.to_ne_bytes());
+        body.extend_from_slice(&[0u8; 12]);
+        for value in fields {
+            body.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&((NLMSG_HDR_LEN + body.len()) as u32).to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 12]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[test]
+    fn test_parse_process_events() {
+        let mut data = message(PROC_EVENT_FORK, &[100, 100, 200, 200]);
+        // Thread creation inside pid 100
+        data.extend(message(PROC_EVENT_FORK, &[100, 100, 201, 100]));
+        data.extend(message(PROC_EVENT_EXEC, &[200, 200]));
+        data.extend(message(PROC_EVENT_EXIT, &[200, 200, 256, 17]));
+
+        let mut events = Vec::new();
+        parse_messages(&data, &mut events);
+        assert_eq!(events, vec![
+            ProcEvent::Fork { parent_pid: 100, child_pid: 200 },
+            ProcEvent::Exec { pid: 200 },
+            ProcEvent::Exit { pid: 200, exit_code: 256 },
+        ]);
+    }
+}
@@ -4,6 +4,8 @@ use std::collections::HashMap;
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, error, debug};
 
+use super::proc_connector::ProcEvent;
+
 #[derive(Debug, Clone)]
 pub struct ProcessInfo {
     pub pid: u32,
@@ -16,6 +18,14 @@ pub struct ProcessInfo {
     pub start_time: u64,
 }
 
+// A difference between the tracked process table and the system
+#[derive(Debug, Clone)]
+pub enum ProcessChange {
+    Started(ProcessInfo),
+    Exec(ProcessInfo),
+    Exited(ProcessInfo),
+}
+
 pub struct ProcessMonitor {
     processes: HashMap<u32, ProcessInfo>,
     running: bool,
@@ -192,6 +202,10 @@ impl ProcessMonitor {
         self.processes.get(&pid)
     }
     
+    pub fn processes(&self) -> Vec<ProcessInfo> {
+        self.processes.values().cloned().collect()
+    }
+    
     pub fn refresh_process(&mut self, pid: u32) -> Result<()> {
         match self.get_process_info(pid) {
             Ok(info) => {
@@ -214,8 +228,9 @@ impl ProcessMonitor {
         Ok(())
     }
     
-    pub fn refresh_processes(&mut self) -> Result<()> {
-        // Re-scan all processes to update our tracking
+    // Full rescan of /proc. With the proc connector running this is only a
+    // reconciliation pass that catches events the kernel dropped.
+    pub fn refresh_processes(&mut self) -> Result<Vec<ProcessChange>> {
         let proc_dir = fs::read_dir("/proc")?;
         let mut new_processes = HashMap::new();
         
@@ -236,8 +251,32 @@ impl ProcessMonitor {
             }
         }
         
+        let changes = diff_processes(&self.processes, &new_processes);
         self.processes = new_processes;
-        Ok(())
+        Ok(changes)
+    }
+    
+    // Updates the table for a single kernel process event
+    pub fn apply_event(&mut self, event: &ProcEvent) -> Option<ProcessChange> {
+        match *event {
+            ProcEvent::Fork { child_pid, .. } => {
+                // Gone already if it was short-lived; its exit event follows
+                let info = self.get_process_info(child_pid).ok()?;
+                self.processes.insert(child_pid, info.clone());
+                Some(ProcessChange::Started(info))
+            }
+            ProcEvent::Exec { pid } => match self.get_process_info(pid) {
+                Ok(info) => {
+                    self.processes.insert(pid, info.clone());
+                    Some(ProcessChange::Exec(info))
+                }
+                Err(_) => {
+                    self.processes.remove(&pid);
+                    None
+                }
+            },
+            ProcEvent::Exit { pid, .. } => self.processes.remove(&pid).map(ProcessChange::Exited),
+        }
     }
     
     pub fn get_process_children(&self, parent_pid: u32) -> Vec<&ProcessInfo> {
@@ -283,6 +322,30 @@ impl ProcessMonitor {
     }
 }
 
+// A pid whose start time changed was reused by a new process
+fn diff_processes(old: &HashMap<u32, ProcessInfo>, new: &HashMap<u32, ProcessInfo>) -> Vec<ProcessChange> {
+    let mut changes = Vec::new();
+    
+    for (pid, before) in old {
+        match new.get(pid) {
+            Some(after) if after.start_time == before.start_time => {
+                if after.exe_path != before.exe_path || after.cmdline != before.cmdline {
+                    changes.push(ProcessChange::Exec(after.clone()));
+                }
+            }
+            _ => changes.push(ProcessChange::Exited(before.clone())),
+        }
+    }
+    for (pid, after) in new {
+        let reused = old.get(pid).is_some_and(|before| before.start_time != after.start_time);
+        if !old.contains_key(pid) || reused {
+            changes.push(ProcessChange::Started(after.clone()));
+        }
+    }
+    
+    changes
+}
+
 #[derive(Debug)]
 struct StatInfo {
     pid: u32,
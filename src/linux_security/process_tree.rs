@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::hash_cache::HashCache;
+use super::patterns::{ChainEvent, PatternMatcher, ProcessChainNode};
+use super::process_monitor::ProcessMonitor;
+
+pub const DEFAULT_TREE_DEPTH: usize = 16;
+const MAX_TREE_NODES: usize = 2048;
+// Larger executables are reported without a hash rather than stalling the request
+const MAX_HASH_SIZE: u64 = 64 * 1024 * 1024;
+// How stale a standalone process table may get before a request rescans /proc
+const STANDALONE_REFRESH: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessTreeNode {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    pub exe_path: Option<PathBuf>,
+    pub cmdline: Vec<String>,
+    // Unknown for processes that have exited and are only known from chain history
+    pub uid: Option<u32>,
+    pub sha256: Option<String>,
+    pub running: bool,
+    // Spawns, file and network activity recorded by the pattern matcher
+    pub activity: Vec<ChainEvent>,
+    pub event_ids: Vec<String>,
+    pub children: Vec<ProcessTreeNode>,
+}
+
+// The ancestors of a process down to it, and everything it spawned
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessTree {
+    pub pid: u32,
+    pub root_pid: u32,
+    // Root first, ending with the requested pid
+    pub ancestry: Vec<u32>,
+    pub suspicious_score: u32,
+    pub node_count: usize,
+    // Descendants were cut off by the depth or node limit
+    pub truncated: bool,
+    pub root: ProcessTreeNode,
+}
+
+// Builds process trees from the live process table and the pattern
+// matcher's spawn history, which still knows processes that have exited
+pub struct ProcessTreeSource {
+    process_monitor: Arc<Mutex<ProcessMonitor>>,
+    pattern_matcher: Arc<PatternMatcher>,
+    hash_cache: Arc<Mutex<HashCache>>,
+    // Set when no running monitor keeps the process table current
+    last_refresh: Option<Mutex<Option<Instant>>>,
+}
+
+struct TreeBuilder<'a> {
+    process_monitor: &'a ProcessMonitor,
+    history: HashMap<u32, ProcessChainNode>,
+    events: &'a dyn Fn(u32) -> Vec<String>,
+    node_count: usize,
+    truncated: bool,
+}
+
+impl<'a> TreeBuilder<'a> {
+    fn node(&self, pid: u32) -> Option<ProcessTreeNode> {
+        let history = self.history.get(&pid);
+        let activity = history.map(|node| node.events.clone()).unwrap_or_default();
+        let event_ids = (self.events)(pid);
+
+        if let Some(process) = self.process_monitor.get_process_by_pid(pid) {
+            return Some(ProcessTreeNode {
+                pid,
+                ppid: process.ppid,
+                name: process.name.clone(),
+                exe_path: process.exe_path.clone(),
+                cmdline: process.cmdline.clone(),
+                uid: Some(process.uid),
+                sha256: None,
+                running: true,
+                activity,
+                event_ids,
+                children: Vec::new(),
+            });
+        }
+
+        history.map(|node| ProcessTreeNode {
+            pid,
+            ppid: node.ppid,
+            name: node.name.clone(),
+            exe_path: node.exe_path.clone(),
+            cmdline: node.cmdline.clone(),
+            uid: None,
+            sha256: None,
+            running: false,
+            activity,
+            event_ids,
+            children: Vec::new(),
+        })
+    }
+
+    fn children(&self, pid: u32) -> Vec<u32> {
+        let mut children: Vec<u32> = self.process_monitor.get_process_children(pid)
+            .iter()
+            .map(|child| child.pid)
+            .collect();
+        children.extend(self.history.values()
+            .filter(|node| node.ppid == pid && node.pid != pid)
+            .map(|node| node.pid));
+        children.sort_unstable();
+        children.dedup();
+        children
+    }
+
+    fn ancestry(&self, pid: u32) -> Vec<ProcessTreeNode> {
+        let mut ancestry = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = self.node(pid);
+
+        while let Some(node) = current {
+            visited.insert(node.pid);
+            let ppid = node.ppid;
+            ancestry.push(node);
+            if ppid == 0 || visited.contains(&ppid) {
+                break;
+            }
+            current = self.node(ppid);
+        }
+
+        ancestry.reverse();
+        ancestry
+    }
+
+    fn descendants(&mut self, node: &mut ProcessTreeNode, depth: usize, visited: &mut HashSet<u32>) {
+        let children = self.children(node.pid);
+        if children.is_empty() {
+            return;
+        }
+        if depth == 0 {
+            self.truncated = true;
+            return;
+        }
+
+        for pid in children {
+            if self.node_count >= MAX_TREE_NODES {
+                self.truncated = true;
+                return;
+            }
+            if !visited.insert(pid) {
+                continue;
+            }
+            if let Some(mut child) = self.node(pid) {
+                self.node_count += 1;
+                self.descendants(&mut child, depth - 1, visited);
+                node.children.push(child);
+            }
+        }
+    }
+}
+
+impl ProcessTreeSource {
+    pub fn new(
+        process_monitor: Arc<Mutex<ProcessMonitor>>,
+        pattern_matcher: Arc<PatternMatcher>,
+        hash_cache: Arc<Mutex<HashCache>>,
+    ) -> Self {
+        Self { process_monitor, pattern_matcher, hash_cache, last_refresh: None }
+    }
+
+    // For callers without a running monitor, e.g. the API server; the process
+    // table is rescanned on demand
+    pub fn standalone() -> anyhow::Result<Self> {
+        Ok(Self {
+            process_monitor: Arc::new(Mutex::new(ProcessMonitor::new())),
+            pattern_matcher: Arc::new(PatternMatcher::new()?),
+            hash_cache: Arc::new(Mutex::new(HashCache::default())),
+            last_refresh: Some(Mutex::new(None)),
+        })
+    }
+
+    pub fn build(&self, pid: u32, max_depth: usize) -> Option<ProcessTree> {
+        self.build_with_events(pid, max_depth, &|_| Vec::new())
+    }
+
+    // `events` returns the ids of security events attributed to a pid
+    pub fn build_with_events(&self, pid: u32, max_depth: usize, events: &dyn Fn(u32) -> Vec<String>) -> Option<ProcessTree> {
+        self.refresh_if_stale();
+
+        let chain = self.pattern_matcher.get_process_chain(pid);
+        let suspicious_score = chain.as_ref().map(|chain| chain.suspicious_score).unwrap_or(0);
+        let history: HashMap<u32, ProcessChainNode> = chain
+            .map(|chain| chain.chain.into_iter().map(|node| (node.pid, node)).collect())
+            .unwrap_or_default();
+
+        let (mut root, ancestry, node_count, truncated) = {
+            let process_monitor = self.process_monitor.lock().ok()?;
+            let mut builder = TreeBuilder {
+                process_monitor: &process_monitor,
+                history,
+                events,
+                node_count: 0,
+                truncated: false,
+            };
+
+            let mut lineage = builder.ancestry(pid);
+            let mut focus = lineage.pop()?;
+            let ancestry: Vec<u32> = lineage.iter().map(|node| node.pid).chain([pid]).collect();
+            builder.node_count = ancestry.len();
+
+            let mut visited: HashSet<u32> = ancestry.iter().copied().collect();
+            builder.descendants(&mut focus, max_depth, &mut visited);
+
+            // Wrap the requested process in its ancestors, innermost first
+            let mut root = focus;
+            while let Some(mut parent) = lineage.pop() {
+                parent.children.push(root);
+                root = parent;
+            }
+            (root, ancestry, builder.node_count, builder.truncated)
+        };
+
+        // Hashing reads files, so it happens after the process table is released
+        self.fill_hashes(&mut root);
+
+        Some(ProcessTree {
+            pid,
+            root_pid: root.pid,
+            ancestry,
+            suspicious_score,
+            node_count,
+            truncated,
+            root,
+        })
+    }
+
+    fn refresh_if_stale(&self) {
+        let Some(last_refresh) = &self.last_refresh else { return };
+        let Ok(mut last_refresh) = last_refresh.lock() else { return };
+        if last_refresh.is_some_and(|at| at.elapsed() < STANDALONE_REFRESH) {
+            return;
+        }
+        if let Ok(mut process_monitor) = self.process_monitor.lock() {
+            if let Err(e) = process_monitor.refresh_processes() {
+                warn!("Failed to refresh process table: {}", e);
+            }
+        }
+        *last_refresh = Some(Instant::now());
+    }
+
+    fn fill_hashes(&self, node: &mut ProcessTreeNode) {
+        if let Some(path) = &node.exe_path {
+            node.sha256 = self.hash_executable(path);
+        }
+        for child in &mut node.children {
+            self.fill_hashes(child);
+        }
+    }
+
+    fn hash_executable(&self, path: &Path) -> Option<String> {
+        let mut file = std::fs::File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() || metadata.len() > MAX_HASH_SIZE {
+            return None;
+        }
+        if let Ok(mut cache) = self.hash_cache.lock() {
+            if let Some(hash) = cache.get_with_metadata(path, &metadata) {
+                return Some(hash);
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 65536];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buffer[..n]),
+                Err(e) => {
+                    debug!("Failed to hash {:?}: {}", path, e);
+                    return None;
+                }
+            }
+        }
+        let hash = format!("{:x}", hasher.finalize());
+        if let Ok(mut cache) = self.hash_cache.lock() {
+            cache.insert(path, &metadata, hash.clone());
+        }
+        Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_for_current_process() {
+        let source = ProcessTreeSource::standalone().unwrap();
+        let pid = std::process::id();
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+
+        let tree = source.build_with_events(pid, DEFAULT_TREE_DEPTH, &|node| {
+            if node == pid { vec!["evt-1".to_string()] } else { Vec::new() }
+        }).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(tree.ancestry.last(), Some(&pid));
+        assert_eq!(tree.root_pid, tree.ancestry[0]);
+
+        // Walk down the ancestry to the requested process
+        let mut node = &tree.root;
+        for expected in &tree.ancestry[1..] {
+            assert_eq!(node.children.len(), 1);
+            node = &node.children[0];
+            assert_eq!(node.pid, *expected);
+        }
+        assert_eq!(node.event_ids, vec!["evt-1".to_string()]);
+        let spawned = node.children.iter().find(|c| c.pid == child.id()).unwrap();
+        assert!(spawned.running);
+        assert_eq!(spawned.sha256.as_ref().map(|hash| hash.len()), Some(64));
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn, debug};
+
+use super::patterns::PatternMatcher;
+use crate::scanner::load_hash_list;
+use crate::scanner::yara::YaraRules;
+use crate::scanner::StaticRisk;
+
+// Files larger than this are not hashed on access
+const DEFAULT_MAX_SCAN_SIZE: u64 = 64 * 1024 * 1024;
+
+// Scores in the shared reputation cache run from 0.0 (malicious) to 1.0 (benign)
+const MALICIOUS_THRESHOLD: f32 = 0.3;
+const TRUSTED_THRESHOLD: f32 = 0.9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashVerdict {
+    Malicious(String),
+    Trusted,
+    Unknown,
+}
+
+// External lookup for hashes not in any local source, e.g. a threat intel API.
+// Returns a score and reason, or None when the source knows nothing about the hash.
+pub type HashEnricher = Arc<dyn Fn(&str, &Path) -> Result<Option<(f32, String)>> + Send + Sync>;
+
+struct EnrichmentJob {
+    hash: String,
+    path: PathBuf,
+    file: File,
+}
+
+#[derive(Default)]
+struct HashDatabase {
+    known_bad: HashSet<String>,
+    known_good: HashSet<String>,
+}
+
+// Hashes files at access time and checks them against local known-bad/known-good
+// lists and the pattern matcher's reputation cache. Unknown hashes are enriched in
+// the background so later accesses get a verdict.
+pub struct ReputationPipeline {
+    database: RwLock<HashDatabase>,
+    matcher: Arc<PatternMatcher>,
+    // path -> (size, mtime, hash); revalidated against the file on each access
+    hash_cache: Mutex<HashMap<PathBuf, (u64, i64, String)>>,
+    max_scan_size: u64,
+    yara_rules: Arc<RwLock<Option<YaraRules>>>,
+    static_analysis: Arc<AtomicBool>,
+    enricher: Arc<RwLock<Option<HashEnricher>>>,
+    pending: Arc<Mutex<HashSet<String>>>,
+    enrichment_tx: Mutex<Option<mpsc::Sender<EnrichmentJob>>>,
+}
+
+impl ReputationPipeline {
+    pub fn new(matcher: Arc<PatternMatcher>) -> Self {
+        Self {
+            database: RwLock::new(HashDatabase::default()),
+            matcher,
+            hash_cache: Mutex::new(HashMap::new()),
+            max_scan_size: DEFAULT_MAX_SCAN_SIZE,
+            yara_rules: Arc::new(RwLock::new(None)),
+            static_analysis: Arc::new(AtomicBool::new(false)),
+            enricher: Arc::new(RwLock::new(None)),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            enrichment_tx: Mutex::new(None),
+        }
+    }
+
+    pub fn set_max_scan_size(&mut self, size: u64) {
+        self.max_scan_size = size;
+    }
+
+    // One SHA-256 per line; anything after the hash (as in sha256sum output) is ignored
+    pub fn load_known_bad(&self, path: &Path) -> Result<usize> {
+        let hashes = load_hash_list(path)?;
+        let count = hashes.len();
+        self.database.write()
+            .map_err(|_| anyhow!("Failed to acquire hash database write lock"))?
+            .known_bad.extend(hashes);
+        info!("Loaded {} known-bad hashes from {:?}", count, path);
+        Ok(count)
+    }
+
+    pub fn load_known_good(&self, path: &Path) -> Result<usize> {
+        let hashes = load_hash_list(path)?;
+        let count = hashes.len();
+        self.database.write()
+            .map_err(|_| anyhow!("Failed to acquire hash database write lock"))?
+            .known_good.extend(hashes);
+        info!("Loaded {} known-good hashes from {:?}", count, path);
+        Ok(count)
+    }
+
+    pub fn add_known_bad(&self, hash: &str) -> Result<()> {
+        self.database.write()
+            .map_err(|_| anyhow!("Failed to acquire hash database write lock"))?
+            .known_bad.insert(hash.to_lowercase());
+        Ok(())
+    }
+
+    pub fn add_known_good(&self, hash: &str) -> Result<()> {
+        self.database.write()
+            .map_err(|_| anyhow!("Failed to acquire hash database write lock"))?
+            .known_good.insert(hash.to_lowercase());
+        Ok(())
+    }
+
+    pub fn set_yara_rules(&self, rules: YaraRules) -> Result<()> {
+        *self.yara_rules.write().map_err(|_| anyhow!("Failed to acquire YARA rules write lock"))? = Some(rules);
+        self.ensure_worker();
+        Ok(())
+    }
+
+    // Inspect unknown ELF binaries for packers, W+X segments and the like; red
+    // flags lower the hash's reputation
+    pub fn set_static_analysis(&self, enabled: bool) {
+        self.static_analysis.store(enabled, Ordering::Relaxed);
+        if enabled {
+            self.ensure_worker();
+        }
+    }
+
+    pub fn set_enricher<F>(&self, enricher: F) -> Result<()>
+    where
+        F: Fn(&str, &Path) -> Result<Option<(f32, String)>> + Send + Sync + 'static,
+    {
+        *self.enricher.write().map_err(|_| anyhow!("Failed to acquire enricher write lock"))? = Some(Arc::new(enricher));
+        self.ensure_worker();
+        Ok(())
+    }
+
+    // Nothing to check against, so accesses need not be hashed at all
+    pub fn is_active(&self) -> bool {
+        let has_db = self.database.read()
+            .map(|db| !db.known_bad.is_empty() || !db.known_good.is_empty())
+            .unwrap_or(false);
+        has_db || self.has_enrichment()
+    }
+
+    fn has_enrichment(&self) -> bool {
+        self.yara_rules.read().map(|r| r.is_some()).unwrap_or(false)
+            || self.static_analysis.load(Ordering::Relaxed)
+            || self.enricher.read().map(|e| e.is_some()).unwrap_or(false)
+    }
+
+    pub fn verdict_for_hash(&self, hash: &str) -> HashVerdict {
+        if let Ok(db) = self.database.read() {
+            if db.known_bad.contains(hash) {
+                return HashVerdict::Malicious("Hash is in the known-bad database".to_string());
+            }
+            if db.known_good.contains(hash) {
+                return HashVerdict::Trusted;
+            }
+        }
+
+        match self.matcher.check_reputation(hash) {
+            Some(rep) if rep.score <= MALICIOUS_THRESHOLD => {
+                HashVerdict::Malicious(format!("Reputation {:.2}: {}", rep.score, rep.reasons.join("; ")))
+            }
+            Some(rep) if rep.score >= TRUSTED_THRESHOLD => HashVerdict::Trusted,
+            _ => HashVerdict::Unknown,
+        }
+    }
+
+    // Checks the file behind a fanotify event fd. Reads go through that fd, which
+    // does not generate further fanotify events.
+    pub fn check_fd(&self, fd: RawFd, path: &Path) -> (Option<String>, HashVerdict) {
+        let file = match dup_fd(fd) {
+            Some(file) => file,
+            None => return (None, HashVerdict::Unknown),
+        };
+
+        let hash = match self.hash_file(&file, path) {
+            Some(hash) => hash,
+            None => return (None, HashVerdict::Unknown),
+        };
+
+        let verdict = self.verdict_for_hash(&hash);
+        if verdict == HashVerdict::Unknown {
+            self.queue_enrichment(&hash, path, file);
+        }
+        (Some(hash), verdict)
+    }
+
+    fn hash_file(&self, file: &File, path: &Path) -> Option<String> {
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() || metadata.len() > self.max_scan_size {
+            return None;
+        }
+
+        let key = (metadata.len(), metadata.mtime());
+        if let Ok(cache) = self.hash_cache.lock() {
+            if let Some((size, mtime, hash)) = cache.get(path) {
+                if (*size, *mtime) == key {
+                    return Some(hash.clone());
+                }
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 65536];
+        let mut offset = 0u64;
+        loop {
+            match read_at(file, &mut buffer, offset) {
+                Ok(0) => break,
+                Ok(n) => {
+                    hasher.update(&buffer[..n]);
+                    offset += n as u64;
+                }
+                Err(e) => {
+                    debug!("Failed to hash {:?}: {}", path, e);
+                    return None;
+                }
+            }
+        }
+        let hash = hex::encode(hasher.finalize());
+
+        if let Ok(mut cache) = self.hash_cache.lock() {
+            cache.insert(path.to_path_buf(), (key.0, key.1, hash.clone()));
+        }
+        Some(hash)
+    }
+
+    fn queue_enrichment(&self, hash: &str, path: &Path, file: File) {
+        if !self.has_enrichment() {
+            return;
+        }
+        if let Ok(mut pending) = self.pending.lock() {
+            if !pending.insert(hash.to_string()) {
+                return;
+            }
+        }
+
+        let job = EnrichmentJob { hash: hash.to_string(), path: path.to_path_buf(), file };
+        let sent = self.enrichment_tx.lock().ok()
+            .and_then(|tx| tx.as_ref().map(|tx| tx.send(job).is_ok()))
+            .unwrap_or(false);
+        if !sent {
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.remove(hash);
+            }
+        }
+    }
+
+    fn ensure_worker(&self) {
+        let mut tx_slot = match self.enrichment_tx.lock() {
+            Ok(slot) => slot,
+            Err(_) => return,
+        };
+        if tx_slot.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel::<EnrichmentJob>();
+        *tx_slot = Some(tx);
+
+        let matcher = Arc::clone(&self.matcher);
+        let yara_rules = Arc::clone(&self.yara_rules);
+        let static_analysis = Arc::clone(&self.static_analysis);
+        let enricher = Arc::clone(&self.enricher);
+        let pending = Arc::clone(&self.pending);
+        let max_scan_size = self.max_scan_size;
+
+        thread::spawn(move || {
+            info!("Hash enrichment worker started");
+
+            for mut job in rx {
+                let result = enrich(&mut job, max_scan_size, &yara_rules, static_analysis.load(Ordering::Relaxed), &enricher);
+                match result {
+                    Ok(Some((score, reason))) => {
+                        if score <= MALICIOUS_THRESHOLD {
+                            warn!("Hash {} ({:?}) flagged: {}", job.hash, job.path, reason);
+                        }
+                        if let Err(e) = matcher.update_reputation(job.hash.clone(), score, reason) {
+                            warn!("Failed to record reputation for {}: {}", job.hash, e);
+                        }
+                    }
+                    Ok(None) => debug!("No reputation data for {}", job.hash),
+                    Err(e) => debug!("Enrichment of {} failed: {}", job.hash, e),
+                }
+
+                if let Ok(mut pending) = pending.lock() {
+                    pending.remove(&job.hash);
+                }
+            }
+        });
+    }
+}
+
+fn enrich(
+    job: &mut EnrichmentJob,
+    max_scan_size: u64,
+    yara_rules: &RwLock<Option<YaraRules>>,
+    static_analysis: bool,
+    enricher: &RwLock<Option<HashEnricher>>,
+) -> Result<Option<(f32, String)>> {
+    let rules = yara_rules.read().map_err(|_| anyhow!("Failed to acquire YARA rules read lock"))?.clone();
+    let mut data = Vec::new();
+    if rules.is_some() || static_analysis {
+        (&mut job.file).take(max_scan_size).read_to_end(&mut data)?;
+    }
+    if let Some(rules) = rules {
+        let matches = rules.scan(&data);
+        if !matches.is_empty() {
+            let names: Vec<String> = matches.into_iter().map(|m| m.rule).collect();
+            return Ok(Some((0.0, format!("YARA match: {}", names.join(", ")))));
+        }
+    }
+
+    let risk = if static_analysis {
+        StaticRisk::analyze(&data, &job.path).filter(|risk| !risk.is_clean())
+    } else {
+        None
+    };
+    let enricher = enricher.read().map_err(|_| anyhow!("Failed to acquire enricher read lock"))?.clone();
+    let external = match enricher {
+        Some(enricher) => enricher(&job.hash, &job.path)?,
+        None => None,
+    };
+
+    // The lower score wins, so a clean lookup cannot vouch for a packed binary
+    Ok(match (risk, external) {
+        (Some(risk), Some((score, reason))) => {
+            Some((score.min(risk.reputation_score()), format!("{}; {}", reason, risk.describe())))
+        }
+        (Some(risk), None) => Some((risk.reputation_score(), risk.describe())),
+        (None, external) => external,
+    })
+}
+
+fn dup_fd(fd: RawFd) -> Option<File> {
+    if fd < 0 {
+        return None;
+    }
+    let dup = unsafe { libc::dup(fd) };
+    if dup < 0 {
+        return None;
+    }
+    Some(unsafe { File::from_raw_fd(dup) })
+}
+
+// Positional reads keep the shared offset at 0 for the enrichment worker's copy of the fd
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn test_hash_verdicts() {
+        let dir = std::env::temp_dir().join(format!("fluxdefense-reputation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sample = dir.join("sample.bin");
+        std::fs::write(&sample, b"malicious payload").unwrap();
+        let sample_hash = hex::encode(Sha256::digest(b"malicious payload"));
+
+        let list = dir.join("bad.txt");
+        std::fs::write(&list, format!("# test list\n{}  sample.bin\nnot-a-hash\n", sample_hash.to_uppercase())).unwrap();
+
+        let pipeline = ReputationPipeline::new(Arc::new(PatternMatcher::new().unwrap()));
+        assert!(!pipeline.is_active());
+        assert_eq!(pipeline.load_known_bad(&list).unwrap(), 1);
+        assert!(pipeline.is_active());
+
+        let file = File::open(&sample).unwrap();
+        let (hash, verdict) = pipeline.check_fd(file.as_raw_fd(), &sample);
+        assert_eq!(hash.as_deref(), Some(sample_hash.as_str()));
+        assert!(matches!(verdict, HashVerdict::Malicious(_)));
+
+        let other = "a".repeat(64);
+        assert_eq!(pipeline.verdict_for_hash(&other), HashVerdict::Unknown);
+        pipeline.matcher.update_reputation(other.clone(), 0.95, "signed vendor binary".to_string()).unwrap();
+        assert_eq!(pipeline.verdict_for_hash(&other), HashVerdict::Trusted);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Samples older than this are dropped; patterns cannot ask for longer spans
+const MAX_RETENTION: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsageSample {
+    // The interval this sample covers
+    pub since: Instant,
+    pub at: Instant,
+    // Percent of one core, so a process on four cores can reach 400
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+}
+
+// How a process has used resources over a span in which every sample met
+// the thresholds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SustainedUsage {
+    pub duration: Duration,
+    pub average_cpu: f32,
+    pub peak_memory: u64,
+}
+
+struct ProcessUsage {
+    start_time: u64,
+    cpu_ticks: u64,
+    read_at: Instant,
+    samples: VecDeque<UsageSample>,
+}
+
+// CPU and resident memory of each process over time, from /proc/<pid>/stat
+pub struct ResourceUsage {
+    processes: HashMap<u32, ProcessUsage>,
+    ticks_per_second: f64,
+    page_size: u64,
+}
+
+impl Default for ResourceUsage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceUsage {
+    pub fn new() -> Self {
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Self {
+            processes: HashMap::new(),
+            ticks_per_second: if ticks > 0 { ticks as f64 } else { 100.0 },
+            page_size: if page > 0 { page as u64 } else { 4096 },
+        }
+    }
+
+    // Reads the given processes; anything not listed is forgotten
+    pub fn sample(&mut self, pids: &[u32]) {
+        let now = Instant::now();
+        self.processes.retain(|pid, _| pids.contains(pid));
+        for &pid in pids {
+            let Some((start_time, cpu_ticks, rss_pages)) = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+                .ok()
+                .and_then(|stat| parse_stat(&stat))
+            else {
+                self.processes.remove(&pid);
+                continue;
+            };
+            self.record(pid, start_time, cpu_ticks, rss_pages * self.page_size, now);
+        }
+    }
+
+    // `cpu_ticks` is the process's user plus system time in clock ticks
+    pub fn record(&mut self, pid: u32, start_time: u64, cpu_ticks: u64, memory_bytes: u64, at: Instant) {
+        let usage = self.processes.entry(pid).or_insert_with(|| ProcessUsage {
+            start_time,
+            cpu_ticks,
+            read_at: at,
+            samples: VecDeque::new(),
+        });
+        // The pid was reused
+        if usage.start_time != start_time {
+            *usage = ProcessUsage { start_time, cpu_ticks, read_at: at, samples: VecDeque::new() };
+            return;
+        }
+        let elapsed = at.saturating_duration_since(usage.read_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+        let busy = cpu_ticks.saturating_sub(usage.cpu_ticks) as f64 / self.ticks_per_second;
+        usage.samples.push_back(UsageSample {
+            since: usage.read_at,
+            at,
+            cpu_percent: (busy / elapsed * 100.0) as f32,
+            memory_bytes,
+        });
+        usage.cpu_ticks = cpu_ticks;
+        usage.read_at = at;
+        while usage.samples.front().is_some_and(|sample| at.saturating_duration_since(sample.at) > MAX_RETENTION) {
+            usage.samples.pop_front();
+        }
+    }
+
+    // Whether the most recent samples of `pid` have stayed at or above both
+    // thresholds for at least `duration`; a zero threshold is not checked
+    pub fn sustained(&self, pid: u32, cpu_threshold: f32, memory_threshold: u64, duration: Duration) -> Option<SustainedUsage> {
+        let samples = &self.processes.get(&pid)?.samples;
+        let newest = samples.back()?;
+        let mut covered = Duration::ZERO;
+        let mut cpu_time = 0.0;
+        let mut peak_memory = 0;
+        for sample in samples.iter().rev() {
+            if sample.cpu_percent < cpu_threshold || sample.memory_bytes < memory_threshold {
+                break;
+            }
+            let span = sample.at.saturating_duration_since(sample.since);
+            cpu_time += f64::from(sample.cpu_percent) * span.as_secs_f64();
+            peak_memory = peak_memory.max(sample.memory_bytes);
+            covered = newest.at.saturating_duration_since(sample.since);
+        }
+        (covered >= duration && !covered.is_zero()).then(|| SustainedUsage {
+            duration: covered,
+            average_cpu: (cpu_time / covered.as_secs_f64()) as f32,
+            peak_memory,
+        })
+    }
+}
+
+// Start time, utime + stime and resident pages; the command name can hold
+// spaces and parentheses, so fields are counted from the last `)`
+fn parse_stat(stat: &str) -> Option<(u64, u64, u64)> {
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // Field 3 of proc(5) (state) is fields[0]
+    let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+    Some((field(22)?, field(14)? + field(15)?, field(24)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sustained_usage() {
+        let stat = "4242 (x (m) iner) R 1 4242 4242 0 -1 4194560 100 0 0 0 5000 1000 0 0 20 0 8 0 123456 1000000 2048 18446744073709551615";
+        assert_eq!(parse_stat(stat), Some((123456, 6000, 2048)));
+
+        let mut usage = ResourceUsage::new();
+        usage.ticks_per_second = 100.0;
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        // Idle for a minute, then two cores busy for ten
+        usage.record(7, 1, 0, 1 << 20, at(0));
+        usage.record(7, 1, 100, 1 << 20, at(60));
+        for minute in 1..=10u64 {
+            usage.record(7, 1, 100 + minute * 12_000, 1 << 30, at(60 + minute * 60));
+        }
+
+        let sustained = usage.sustained(7, 90.0, 0, Duration::from_secs(600)).unwrap();
+        assert_eq!(sustained.duration, Duration::from_secs(600));
+        assert!((sustained.average_cpu - 200.0).abs() < 0.1);
+        assert_eq!(sustained.peak_memory, 1 << 30);
+        assert!(usage.sustained(7, 90.0, 0, Duration::from_secs(660)).is_none());
+        assert!(usage.sustained(7, 250.0, 0, Duration::from_secs(60)).is_none());
+        assert!(usage.sustained(7, 0.0, 2 << 30, Duration::from_secs(60)).is_none());
+
+        // A new process behind the same pid starts over
+        usage.record(7, 2, 0, 0, at(700));
+        assert!(usage.sustained(7, 0.0, 0, Duration::from_secs(1)).is_none());
+
+        // This process shows up when sampled from /proc
+        let me = std::process::id();
+        usage.sample(&[me]);
+        assert!(usage.processes.contains_key(&me));
+        usage.sample(&[]);
+        assert!(usage.processes.is_empty());
+    }
+}
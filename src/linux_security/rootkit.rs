@@ -0,0 +1,487 @@
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use tracing::{info, warn, debug};
+use uuid::Uuid;
+
+use crate::monitor::{
+    SecurityEvent, SecurityEventType, ProcessInfo, Verdict, FileAccessType, NetworkProtocol,
+};
+use super::netlink::NetlinkMonitor;
+use super::patterns::Severity;
+
+// Kernel taint bits that indicate module tampering (see Documentation/admin-guide/tainted-kernels.rst)
+const TAINT_FLAGS: &[(u32, char, &str)] = &[
+    (0, 'P', "proprietary module loaded"),
+    (1, 'F', "module force loaded"),
+    (2, 'S', "SMP with non-SMP CPU"),
+    (3, 'R', "module force unloaded"),
+    (4, 'M', "machine check exception"),
+    (5, 'B', "bad page referenced"),
+    (6, 'U', "taint requested by userspace"),
+    (7, 'D', "kernel died recently"),
+    (8, 'A', "ACPI table overridden"),
+    (9, 'W', "kernel warning"),
+    (10, 'C', "staging driver loaded"),
+    (11, 'I', "firmware bug workaround"),
+    (12, 'O', "out-of-tree module loaded"),
+    (13, 'E', "unsigned module loaded"),
+    (14, 'L', "soft lockup"),
+    (15, 'K', "kernel live patched"),
+    (16, 'X', "auxiliary taint"),
+    (17, 'T', "struct randomization plugin"),
+];
+const SUSPICIOUS_TAINT_MASK: u64 = (1 << 1) | (1 << 3) | (1 << 12) | (1 << 13);
+
+#[derive(Debug, Clone)]
+pub enum RootkitFindingKind {
+    // Process reachable via /proc/<pid> but missing from the kernel's directory listing
+    HiddenProcess { pid: u32, name: String },
+    // Process listed by getdents64 but filtered from libc readdir (userland hook, e.g. LD_PRELOAD)
+    ReaddirHook { pid: u32, name: String },
+    KernelTainted { taint: u64, flags: String, reasons: Vec<String> },
+    // Module present in /sys/module but unlinked from /proc/modules
+    HiddenModule { name: String },
+    // Socket reported by sock_diag (what ss uses) but missing from /proc/net
+    HiddenSocket {
+        local: (IpAddr, u16),
+        remote: (IpAddr, u16),
+        inode: u32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RootkitFinding {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: RootkitFindingKind,
+    pub severity: Severity,
+    pub description: String,
+}
+
+impl RootkitFinding {
+    fn new(kind: RootkitFindingKind, severity: Severity, description: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            kind,
+            severity,
+            description,
+        }
+    }
+
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let mut process_info = ProcessInfo {
+            pid: 0,
+            path: PathBuf::from("kernel"),
+            parent_pid: None,
+            user_id: 0,
+            executable_hash: None,
+            command_line: None,
+        };
+
+        let event_type = match &self.kind {
+            RootkitFindingKind::HiddenProcess { pid, .. } | RootkitFindingKind::ReaddirHook { pid, .. } => {
+                let exe = fs::read_link(format!("/proc/{}/exe", pid))
+                    .unwrap_or_else(|_| PathBuf::from("unknown"));
+                process_info.pid = *pid;
+                process_info.path = exe.clone();
+                SecurityEventType::FileExecution {
+                    target_path: exe,
+                    file_hash: None,
+                    code_signature: None,
+                }
+            }
+            RootkitFindingKind::KernelTainted { .. } => SecurityEventType::FileAccess {
+                target_path: PathBuf::from("/proc/sys/kernel/tainted"),
+                access_type: FileAccessType::Read,
+            },
+            RootkitFindingKind::HiddenModule { name } => SecurityEventType::FileAccess {
+                target_path: PathBuf::from("/sys/module").join(name),
+                access_type: FileAccessType::Read,
+            },
+            RootkitFindingKind::HiddenSocket { remote, .. } => SecurityEventType::NetworkConnection {
+                remote_ip: remote.0.to_string(),
+                remote_port: remote.1,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            },
+        };
+
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type,
+            process_info,
+            verdict: Verdict::Log,
+            policy_reason: format!("Rootkit detection ({:?}): {}", self.severity, self.description),
+        }
+    }
+}
+
+pub struct RootkitDetector {
+    running: Arc<Mutex<bool>>,
+    event_handler: Arc<dyn Fn(RootkitFinding) + Send + Sync>,
+}
+
+impl RootkitDetector {
+    pub fn new<F>(event_handler: F) -> Self
+    where
+        F: Fn(RootkitFinding) + Send + Sync + 'static
+    {
+        Self {
+            running: Arc::new(Mutex::new(false)),
+            event_handler: Arc::new(event_handler),
+        }
+    }
+
+    // Run all checks once
+    pub fn scan(&self) -> Vec<RootkitFinding> {
+        Self::run_checks()
+    }
+
+    fn run_checks() -> Vec<RootkitFinding> {
+        let mut findings = Vec::new();
+
+        match Self::check_hidden_processes() {
+            Ok(mut f) => findings.append(&mut f),
+            Err(e) => warn!("Hidden process check failed: {}", e),
+        }
+
+        findings.extend(Self::check_kernel_taint());
+
+        match Self::check_hidden_modules() {
+            Ok(mut f) => findings.append(&mut f),
+            Err(e) => warn!("Hidden module check failed: {}", e),
+        }
+
+        match Self::check_hidden_sockets() {
+            Ok(mut f) => findings.append(&mut f),
+            Err(e) => warn!("Hidden socket check failed: {}", e),
+        }
+
+        findings
+    }
+
+    pub fn start(&self, interval: Duration) -> Result<()> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return Ok(());
+            }
+            *running = true;
+        }
+
+        let running = Arc::clone(&self.running);
+        let event_handler = Arc::clone(&self.event_handler);
+
+        thread::spawn(move || {
+            info!("Rootkit detector started (interval: {:?})", interval);
+
+            while *running.lock().unwrap() {
+                for finding in Self::run_checks() {
+                    warn!("Rootkit indicator: {}", finding.description);
+                    event_handler(finding);
+                }
+
+                let mut waited = Duration::ZERO;
+                while waited < interval && *running.lock().unwrap() {
+                    thread::sleep(Duration::from_secs(1));
+                    waited += Duration::from_secs(1);
+                }
+            }
+
+            info!("Rootkit detector stopped");
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        *self.running.lock().unwrap() = false;
+    }
+
+    // List numeric /proc entries with the raw getdents64 syscall, bypassing any libc hooks
+    fn getdents_pids() -> Result<HashSet<u32>> {
+        let path = CString::new("/proc").unwrap();
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(anyhow!("Failed to open /proc: {}", std::io::Error::last_os_error()));
+        }
+
+        let mut pids = HashSet::new();
+        let mut buffer = vec![0u8; 32768];
+
+        loop {
+            let n = unsafe {
+                libc::syscall(libc::SYS_getdents64, fd, buffer.as_mut_ptr(), buffer.len())
+            };
+
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                unsafe { libc::close(fd) };
+                return Err(anyhow!("getdents64 failed: {}", err));
+            }
+            if n == 0 {
+                break;
+            }
+
+            // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+            let mut offset = 0usize;
+            while offset + 19 < n as usize {
+                let reclen = u16::from_ne_bytes([buffer[offset + 16], buffer[offset + 17]]) as usize;
+                if reclen == 0 {
+                    break;
+                }
+                let name_bytes = &buffer[offset + 19..offset + reclen];
+                let name_len = name_bytes.iter().position(|&b| b == 0).unwrap_or(name_bytes.len());
+                if let Ok(name) = std::str::from_utf8(&name_bytes[..name_len]) {
+                    if let Ok(pid) = name.parse::<u32>() {
+                        pids.insert(pid);
+                    }
+                }
+                offset += reclen;
+            }
+        }
+
+        unsafe { libc::close(fd) };
+        Ok(pids)
+    }
+
+    // List numeric /proc entries through libc readdir
+    fn readdir_pids() -> Result<HashSet<u32>> {
+        Ok(fs::read_dir("/proc")?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().and_then(|n| n.parse().ok()))
+            .collect())
+    }
+
+    fn pid_exists(pid: u32) -> bool {
+        let ret = unsafe { libc::kill(pid as i32, 0) };
+        ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    // Threads are reachable via /proc/<tid> but are never listed, so only thread group leaders count
+    fn is_thread_group_leader(pid: u32) -> bool {
+        fs::read_to_string(format!("/proc/{}/status", pid))
+            .ok()
+            .and_then(|status| {
+                status.lines()
+                    .find(|l| l.starts_with("Tgid:"))
+                    .and_then(|l| l.split_whitespace().nth(1))
+                    .and_then(|v| v.parse::<u32>().ok())
+            })
+            .map(|tgid| tgid == pid)
+            .unwrap_or(false)
+    }
+
+    fn process_name(pid: u32) -> String {
+        fs::read_to_string(format!("/proc/{}/comm", pid))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|_| "unknown".to_string())
+    }
+
+    fn check_hidden_processes() -> Result<Vec<RootkitFinding>> {
+        let mut findings = Vec::new();
+        let listed = Self::getdents_pids()?;
+        let readdir = Self::readdir_pids()?;
+
+        let pid_max: u32 = fs::read_to_string("/proc/sys/kernel/pid_max")
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(32768);
+
+        // Brute force every PID and look for live processes the kernel listing omits
+        let mut candidates = Vec::new();
+        for pid in 1..=pid_max {
+            if !listed.contains(&pid) && Self::pid_exists(pid) && Self::is_thread_group_leader(pid) {
+                candidates.push(pid);
+            }
+        }
+
+        // Re-list to rule out processes that started during the sweep
+        if !candidates.is_empty() {
+            let relisted = Self::getdents_pids()?;
+            for pid in candidates {
+                if !relisted.contains(&pid) && Self::pid_exists(pid) {
+                    let name = Self::process_name(pid);
+                    findings.push(RootkitFinding::new(
+                        RootkitFindingKind::HiddenProcess { pid, name: name.clone() },
+                        Severity::Critical,
+                        format!("Process {} ({}) is alive but hidden from /proc listing", pid, name),
+                    ));
+                }
+            }
+        }
+
+        // Entries the kernel lists but libc readdir filters indicate a userland hook
+        let filtered: Vec<u32> = listed.difference(&readdir).copied().collect();
+        if !filtered.is_empty() {
+            let reread = Self::readdir_pids()?;
+            for pid in filtered {
+                if !reread.contains(&pid) && Self::pid_exists(pid) {
+                    let name = Self::process_name(pid);
+                    findings.push(RootkitFinding::new(
+                        RootkitFindingKind::ReaddirHook { pid, name: name.clone() },
+                        Severity::Critical,
+                        format!("Process {} ({}) is filtered from readdir (possible LD_PRELOAD rootkit)", pid, name),
+                    ));
+                }
+            }
+        }
+
+        debug!("Hidden process check complete: {} listed PIDs", listed.len());
+        Ok(findings)
+    }
+
+    fn check_kernel_taint() -> Option<RootkitFinding> {
+        let taint: u64 = fs::read_to_string("/proc/sys/kernel/tainted")
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+
+        if taint & SUSPICIOUS_TAINT_MASK == 0 {
+            return None;
+        }
+
+        let (flags, reasons) = Self::decode_taint(taint);
+
+        // Proprietary drivers taint the kernel too, so taint alone is High rather than Critical
+        let severity = if taint & ((1 << 1) | (1 << 3)) != 0 {
+            Severity::Critical
+        } else {
+            Severity::High
+        };
+
+        Some(RootkitFinding::new(
+            RootkitFindingKind::KernelTainted { taint, flags: flags.clone(), reasons: reasons.clone() },
+            severity,
+            format!("Kernel is tainted ({}): {}", flags, reasons.join(", ")),
+        ))
+    }
+
+    fn decode_taint(taint: u64) -> (String, Vec<String>) {
+        let mut flags = String::new();
+        let mut reasons = Vec::new();
+
+        for (bit, flag, reason) in TAINT_FLAGS {
+            if taint & (1 << bit) != 0 {
+                flags.push(*flag);
+                reasons.push(reason.to_string());
+            }
+        }
+
+        (flags, reasons)
+    }
+
+    fn check_hidden_modules() -> Result<Vec<RootkitFinding>> {
+        let listed = Self::proc_modules()?;
+
+        // Loadable modules expose initstate in sysfs; built-in ones do not
+        let sysfs: Vec<String> = fs::read_dir("/sys/module")?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().join("initstate").exists())
+            .filter_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+            .collect();
+
+        let candidates: Vec<String> = sysfs.into_iter().filter(|m| !listed.contains(m)).collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Re-read to avoid racing module load/unload
+        let relisted = Self::proc_modules()?;
+        Ok(candidates.into_iter()
+            .filter(|m| !relisted.contains(m) && PathBuf::from("/sys/module").join(m).join("initstate").exists())
+            .map(|name| RootkitFinding::new(
+                RootkitFindingKind::HiddenModule { name: name.clone() },
+                Severity::Critical,
+                format!("Kernel module {} is present in /sys/module but hidden from /proc/modules", name),
+            ))
+            .collect())
+    }
+
+    fn proc_modules() -> Result<HashSet<String>> {
+        Ok(fs::read_to_string("/proc/modules")?
+            .lines()
+            .filter_map(|l| l.split_whitespace().next())
+            .map(|s| s.to_string())
+            .collect())
+    }
+
+    fn check_hidden_sockets() -> Result<Vec<RootkitFinding>> {
+        let netlink = NetlinkMonitor::new()?;
+        let diag = netlink.get_tcp_connections()?;
+        let procfs = Self::proc_net_tcp_inodes();
+
+        let candidates: Vec<_> = diag.into_iter()
+            .filter(|c| c.inode != 0 && !procfs.contains(&c.inode))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Sockets come and go quickly; require both sources to agree on a second pass
+        let diag_again: HashSet<u32> = netlink.get_tcp_connections()?
+            .into_iter()
+            .map(|c| c.inode)
+            .collect();
+        let procfs_again = Self::proc_net_tcp_inodes();
+
+        Ok(candidates.into_iter()
+            .filter(|c| diag_again.contains(&c.inode) && !procfs_again.contains(&c.inode))
+            .map(|c| RootkitFinding::new(
+                RootkitFindingKind::HiddenSocket {
+                    local: (c.local_addr, c.local_port),
+                    remote: (c.remote_addr, c.remote_port),
+                    inode: c.inode,
+                },
+                Severity::Critical,
+                format!("TCP socket {}:{} -> {}:{} (inode {}) is hidden from /proc/net/tcp",
+                        c.local_addr, c.local_port, c.remote_addr, c.remote_port, c.inode),
+            ))
+            .collect())
+    }
+
+    fn proc_net_tcp_inodes() -> HashSet<u32> {
+        let mut inodes = HashSet::new();
+
+        for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            if let Ok(content) = fs::read_to_string(path) {
+                for line in content.lines().skip(1) {
+                    if let Some(inode) = line.split_whitespace().nth(9).and_then(|v| v.parse().ok()) {
+                        inodes.insert(inode);
+                    }
+                }
+            }
+        }
+
+        inodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_taint() {
+        let (flags, reasons) = RootkitDetector::decode_taint((1 << 0) | (1 << 12) | (1 << 13));
+        assert_eq!(flags, "POE");
+        assert_eq!(reasons.len(), 3);
+    }
+
+    #[test]
+    fn test_getdents_lists_self() {
+        let pids = RootkitDetector::getdents_pids().unwrap();
+        assert!(pids.contains(&std::process::id()));
+    }
+}
@@ -0,0 +1,428 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{info, warn, error};
+use uuid::Uuid;
+
+use crate::health::{ComponentState, HealthRegistry};
+use crate::monitor::{SecurityEvent, SecurityEventType, ProcessInfo, Verdict};
+use super::patterns::Severity;
+
+// Audit key carried by self-monitoring events so they can be told apart from
+// syscalls observed on the host
+pub const SELF_MONITORING_KEY: &str = "fluxdefense-self";
+
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    pub check_interval: Duration,
+    // A subsystem that has not beaten for this long is treated as hung
+    pub heartbeat_timeout: Duration,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Running this long after a restart resets the backoff
+    pub stable_after: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(1),
+            heartbeat_timeout: Duration::from_secs(30),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            stable_after: Duration::from_secs(300),
+        }
+    }
+}
+
+// Proof of life from a monitoring loop; loops that can block waiting for
+// events beat on a timer as well so an idle loop is not mistaken for a hung one
+#[derive(Debug, Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn beat(&self) {
+        if let Ok(mut last) = self.0.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.0.lock().map(|last| last.elapsed()).unwrap_or(Duration::MAX)
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStatus {
+    pub name: String,
+    // False while waiting for the next restart attempt
+    pub running: bool,
+    pub restarts: u32,
+    pub last_failure: Option<String>,
+    pub last_restart: Option<DateTime<Utc>>,
+}
+
+// Raised for every restart attempt
+#[derive(Debug, Clone)]
+pub struct RestartEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub subsystem: String,
+    pub reason: String,
+    pub attempt: u32,
+    // Set when the restart itself failed; another attempt follows after `retry_in`
+    pub error: Option<String>,
+    pub retry_in: Option<Duration>,
+    pub severity: Severity,
+}
+
+impl RestartEvent {
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let outcome = match (&self.error, self.retry_in) {
+            (Some(e), Some(retry_in)) => format!("restart failed ({}), retrying in {:?}", e, retry_in),
+            (Some(e), None) => format!("restart failed ({})", e),
+            (None, _) => "restarted".to_string(),
+        };
+
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type: SecurityEventType::Syscall {
+                syscall: format!("restart:{}", self.subsystem),
+                success: self.error.is_none(),
+                exit_code: None,
+                audit_key: Some(SELF_MONITORING_KEY.to_string()),
+            },
+            process_info: ProcessInfo {
+                pid: std::process::id(),
+                path: std::env::current_exe().unwrap_or_else(|_| PathBuf::from("fluxdefense")),
+                parent_pid: None,
+                user_id: unsafe { libc::getuid() },
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: format!(
+                "Self-monitoring ({:?}): {} {}, attempt {}: {}",
+                self.severity, self.subsystem, self.reason, self.attempt, outcome
+            ),
+        }
+    }
+}
+
+struct Subsystem {
+    name: String,
+    heartbeat: Option<Heartbeat>,
+    alive: Box<dyn Fn() -> bool + Send>,
+    restart: Box<dyn FnMut() -> Result<()> + Send>,
+    restarts: u32,
+    backoff: Duration,
+    // Set while the subsystem is down and waiting for its next restart attempt
+    retry_at: Option<Instant>,
+    started: Instant,
+    last_failure: Option<String>,
+    last_restart: Option<DateTime<Utc>>,
+}
+
+type RestartHook = Box<dyn Fn(&RestartEvent) + Send + Sync>;
+
+// Watches the monitoring loops so the daemon never keeps running blind. A
+// subsystem whose task or thread has exited, or whose heartbeat has gone
+// stale, is restarted with exponential backoff and a Critical self-monitoring
+// event is raised for each attempt.
+pub struct Supervisor {
+    config: SupervisorConfig,
+    subsystems: Arc<Mutex<Vec<Subsystem>>>,
+    hooks: Arc<Mutex<Vec<RestartHook>>>,
+    running: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            subsystems: Arc::new(Mutex::new(Vec::new())),
+            hooks: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    // `alive` reports whether the subsystem's task or thread still exists;
+    // `restart` brings it back. Replaces an earlier subsystem of the same name.
+    pub fn supervise<A, R>(&self, name: &str, heartbeat: Option<Heartbeat>, alive: A, restart: R)
+    where
+        A: Fn() -> bool + Send + 'static,
+        R: FnMut() -> Result<()> + Send + 'static,
+    {
+        if let Ok(mut subsystems) = self.subsystems.lock() {
+            subsystems.retain(|subsystem| subsystem.name != name);
+            subsystems.push(Subsystem {
+                name: name.to_string(),
+                heartbeat,
+                alive: Box::new(alive),
+                restart: Box::new(restart),
+                restarts: 0,
+                backoff: self.config.initial_backoff,
+                retry_at: None,
+                started: Instant::now(),
+                last_failure: None,
+                last_restart: None,
+            });
+        }
+    }
+
+    pub fn on_restart<F>(&self, hook: F)
+    where
+        F: Fn(&RestartEvent) + Send + Sync + 'static,
+    {
+        if let Ok(mut hooks) = self.hooks.lock() {
+            hooks.push(Box::new(hook));
+        }
+    }
+
+    pub fn start(&self) -> Result<()> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let config = self.config.clone();
+        let subsystems = Arc::clone(&self.subsystems);
+        let hooks = Arc::clone(&self.hooks);
+        let running = Arc::clone(&self.running);
+        let handle = thread::Builder::new()
+            .name("flux-supervisor".to_string())
+            .spawn(move || {
+                info!("Supervisor started");
+                while running.load(Ordering::SeqCst) {
+                    thread::sleep(config.check_interval);
+                    if running.load(Ordering::SeqCst) {
+                        Self::check_subsystems(&config, &subsystems, &hooks);
+                    }
+                }
+                info!("Supervisor stopped");
+            })?;
+        *self.thread.lock().map_err(|_| anyhow!("Failed to acquire supervisor lock"))? = Some(handle);
+        Ok(())
+    }
+
+    // Waits for an in-progress check so nothing is restarted after this returns
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        let handle = self.thread.lock().ok().and_then(|mut thread| thread.take());
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+
+    // One supervision pass; returns the restart events it raised
+    pub fn check(&self) -> Vec<RestartEvent> {
+        Self::check_subsystems(&self.config, &self.subsystems, &self.hooks)
+    }
+
+    fn check_subsystems(
+        config: &SupervisorConfig,
+        subsystems: &Mutex<Vec<Subsystem>>,
+        hooks: &Mutex<Vec<RestartHook>>,
+    ) -> Vec<RestartEvent> {
+        let mut events = Vec::new();
+        let Ok(mut subsystems) = subsystems.lock() else { return events };
+        let now = Instant::now();
+
+        for subsystem in subsystems.iter_mut() {
+            if subsystem.retry_at.is_none() {
+                let failure = if !(subsystem.alive)() {
+                    Some("stopped unexpectedly".to_string())
+                } else {
+                    subsystem.heartbeat.as_ref()
+                        .map(|heartbeat| heartbeat.elapsed())
+                        .filter(|elapsed| *elapsed > config.heartbeat_timeout)
+                        .map(|elapsed| format!("missed heartbeats for {}s", elapsed.as_secs()))
+                };
+                let Some(failure) = failure else { continue };
+
+                // Back off harder when the last restart did not stick
+                subsystem.backoff = if subsystem.restarts == 0 || subsystem.started.elapsed() >= config.stable_after {
+                    config.initial_backoff
+                } else {
+                    (subsystem.backoff * 2).min(config.max_backoff)
+                };
+                error!("Subsystem {} {}, restarting in {:?}", subsystem.name, failure, subsystem.backoff);
+                subsystem.last_failure = Some(failure);
+                subsystem.retry_at = Some(now + subsystem.backoff);
+            }
+
+            if subsystem.retry_at.is_some_and(|retry_at| retry_at > now) {
+                continue;
+            }
+
+            subsystem.restarts += 1;
+            subsystem.last_restart = Some(Utc::now());
+            let result = (subsystem.restart)();
+            let (error, retry_in) = match result {
+                Ok(()) => {
+                    info!("Subsystem {} restarted (restart {})", subsystem.name, subsystem.restarts);
+                    subsystem.retry_at = None;
+                    subsystem.started = Instant::now();
+                    if let Some(ref heartbeat) = subsystem.heartbeat {
+                        heartbeat.beat();
+                    }
+                    (None, None)
+                }
+                Err(e) => {
+                    subsystem.backoff = (subsystem.backoff * 2).min(config.max_backoff);
+                    warn!("Failed to restart {}: {:#}, retrying in {:?}", subsystem.name, e, subsystem.backoff);
+                    subsystem.retry_at = Some(Instant::now() + subsystem.backoff);
+                    (Some(format!("{:#}", e)), Some(subsystem.backoff))
+                }
+            };
+
+            events.push(RestartEvent {
+                id: Uuid::new_v4().to_string(),
+                timestamp: Utc::now(),
+                subsystem: subsystem.name.clone(),
+                reason: subsystem.last_failure.clone().unwrap_or_default(),
+                attempt: subsystem.restarts,
+                error,
+                retry_in,
+                severity: Severity::Critical,
+            });
+        }
+        drop(subsystems);
+
+        if let Ok(hooks) = hooks.lock() {
+            for event in &events {
+                for hook in hooks.iter() {
+                    hook(event);
+                }
+            }
+        }
+        events
+    }
+
+    pub fn status(&self) -> Vec<SubsystemStatus> {
+        self.subsystems.lock()
+            .map(|subsystems| subsystems.iter().map(|subsystem| SubsystemStatus {
+                name: subsystem.name.clone(),
+                running: subsystem.retry_at.is_none(),
+                restarts: subsystem.restarts,
+                last_failure: subsystem.last_failure.clone(),
+                last_restart: subsystem.last_restart,
+            }).collect())
+            .unwrap_or_default()
+    }
+
+    // Reports supervised subsystems that are waiting to be restarted to `registry`
+    pub fn register_health(&self, registry: &HealthRegistry) {
+        let subsystems = Arc::clone(&self.subsystems);
+        registry.probe("supervisor", false, move || {
+            let Ok(subsystems) = subsystems.lock() else {
+                return (ComponentState::Down, "supervisor lock poisoned".to_string());
+            };
+            let restarts: u32 = subsystems.iter().map(|subsystem| subsystem.restarts).sum();
+            let down: Vec<&str> = subsystems.iter()
+                .filter(|subsystem| subsystem.retry_at.is_some())
+                .map(|subsystem| subsystem.name.as_str())
+                .collect();
+            if down.is_empty() {
+                (ComponentState::Up, format!("{} subsystems supervised, {} restarts", subsystems.len(), restarts))
+            } else {
+                (ComponentState::Degraded, format!("restarting {}", down.join(", ")))
+            }
+        });
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_restarts_dead_and_hung_subsystems_with_backoff() {
+        let supervisor = Supervisor::new(SupervisorConfig {
+            check_interval: Duration::from_millis(10),
+            heartbeat_timeout: Duration::from_millis(50),
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::from_secs(60),
+            stable_after: Duration::from_secs(300),
+        });
+        let raised = Arc::new(AtomicUsize::new(0));
+        let hook_raised = Arc::clone(&raised);
+        supervisor.on_restart(move |_| {
+            hook_raised.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let alive = Arc::new(AtomicBool::new(true));
+        let fail_restarts = Arc::new(AtomicBool::new(true));
+        let heartbeat = Heartbeat::new();
+        let (probe_alive, restart_alive, restart_fails) = (Arc::clone(&alive), Arc::clone(&alive), Arc::clone(&fail_restarts));
+        supervisor.supervise("capture", Some(heartbeat.clone()),
+            move || probe_alive.load(Ordering::SeqCst),
+            move || {
+                if restart_fails.load(Ordering::SeqCst) {
+                    return Err(anyhow!("device busy"));
+                }
+                restart_alive.store(true, Ordering::SeqCst);
+                Ok(())
+            });
+        assert!(supervisor.check().is_empty());
+
+        // The thread died and the first restart fails, so the next one backs off
+        alive.store(false, Ordering::SeqCst);
+        let events = supervisor.check();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].severity, Severity::Critical);
+        assert_eq!(events[0].reason, "stopped unexpectedly");
+        assert_eq!(events[0].retry_in, Some(Duration::ZERO));
+        let event = events[0].to_security_event();
+        assert!(matches!(event.event_type, SecurityEventType::Syscall { success: false, audit_key: Some(ref key), .. } if key == SELF_MONITORING_KEY));
+        assert!(event.policy_reason.contains("Critical"));
+
+        fail_restarts.store(false, Ordering::SeqCst);
+        let events = supervisor.check();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].error.is_none());
+        assert_eq!(events[0].attempt, 2);
+        assert!(alive.load(Ordering::SeqCst));
+        assert!(supervisor.status()[0].running);
+
+        // Alive but no longer beating
+        thread::sleep(Duration::from_millis(80));
+        let events = supervisor.check();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].reason.starts_with("missed heartbeats"));
+        heartbeat.beat();
+        assert!(supervisor.check().is_empty());
+
+        let status = supervisor.status();
+        assert_eq!(status[0].restarts, 3);
+        assert_eq!(raised.load(Ordering::SeqCst), 3);
+
+        let registry = HealthRegistry::new();
+        supervisor.register_health(&registry);
+        assert_eq!(registry.report().components[0].state, ComponentState::Up);
+    }
+}
@@ -0,0 +1,175 @@
+use std::future::Future;
+use std::time::Duration;
+use anyhow::Result;
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::{debug, warn};
+
+// Worker threads for the runtime created when the caller has none; blocking
+// work goes to tokio's blocking pool, whose idle threads exit on their own
+const OWNED_WORKER_THREADS: usize = 2;
+
+// Resolves once shutdown has been requested
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub async fn wait(&mut self) {
+        while !*self.0.borrow() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+// The monitoring loops of one component, run as tokio tasks instead of
+// dedicated threads. Uses the caller's runtime when there is one, otherwise a
+// small runtime owned by the group, so the thread count stays flat however
+// many features are enabled.
+pub struct TaskGroup {
+    runtime: Option<Runtime>,
+    handle: Handle,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl TaskGroup {
+    pub fn new(name: &str) -> Result<Self> {
+        let (runtime, handle) = match Handle::try_current() {
+            Ok(handle) => (None, handle),
+            Err(_) => {
+                let runtime = Builder::new_multi_thread()
+                    .worker_threads(OWNED_WORKER_THREADS)
+                    .thread_name(name)
+                    .enable_all()
+                    .build()?;
+                let handle = runtime.handle().clone();
+                (Some(runtime), handle)
+            }
+        };
+        let (shutdown, _) = watch::channel(false);
+
+        Ok(Self { runtime, handle, shutdown, tasks: Vec::new() })
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn shutdown_signal(&self) -> Shutdown {
+        Shutdown(self.shutdown.subscribe())
+    }
+
+    // The returned handle tells whether the task is still running and can
+    // cancel it, e.g. before a supervisor replaces it
+    pub fn spawn<F, Fut>(&mut self, task: F) -> AbortHandle
+    where
+        F: FnOnce(Shutdown) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let future = task(self.shutdown_signal());
+        let handle = self.handle.spawn(future);
+        let abort = handle.abort_handle();
+        // Restarted tasks would otherwise pile up here
+        self.tasks.retain(|task| !task.is_finished());
+        self.tasks.push(handle);
+        abort
+    }
+
+    // Runs `tick` on the blocking pool every `period` until shutdown. A tick that
+    // is still running when the next one is due delays it rather than overlapping.
+    pub fn spawn_periodic<F>(&mut self, name: &'static str, period: Duration, tick: F) -> AbortHandle
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let tick = std::sync::Arc::new(std::sync::Mutex::new(tick));
+        self.spawn(move |mut shutdown| async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = shutdown.wait() => break,
+                    _ = interval.tick() => {}
+                }
+                let tick = std::sync::Arc::clone(&tick);
+                let result = tokio::task::spawn_blocking(move || {
+                    if let Ok(mut tick) = tick.lock() {
+                        tick();
+                    }
+                }).await;
+                if let Err(e) = result {
+                    warn!("{} task failed: {}", name, e);
+                }
+            }
+            debug!("{} task stopped", name);
+        })
+    }
+
+    // Signals every task to finish. Tasks exit at their next await point;
+    // blocking work already running completes in the background.
+    pub fn shutdown(&mut self) {
+        let _ = self.shutdown.send(true);
+        self.tasks.clear();
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tasks.iter().all(|task| task.is_finished())
+    }
+}
+
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    #[test]
+    fn test_periodic_tasks_stop_promptly() {
+        let mut group = TaskGroup::new("test-tasks").unwrap();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&ticks);
+        group.spawn_periodic("counter", Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        // A loop that would otherwise sleep for a long time
+        group.spawn(|mut shutdown| async move {
+            tokio::select! {
+                _ = shutdown.wait() => {}
+                _ = tokio::time::sleep(Duration::from_secs(3600)) => {}
+            }
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while ticks.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(ticks.load(Ordering::SeqCst) >= 3);
+        assert!(!group.is_finished());
+
+        let signal = group.shutdown_signal();
+        let started = Instant::now();
+        group.shutdown();
+        assert!(signal.is_requested());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let after = ticks.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(ticks.load(Ordering::SeqCst) <= after + 1);
+    }
+}
@@ -0,0 +1,472 @@
+// TLS handshake parsing and JA3/JA3S fingerprinting
+// Fingerprints are computed from the first handshake message of a flow. Hellos
+// that span TCP segments or TLS records are reassembled by `HelloReassembler`.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// A hello is buffered up to the size of the largest TLS record
+pub const MAX_HELLO_BYTES: usize = 16 * 1024;
+// Flows whose hello stops arriving are forgotten after this long
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_PENDING_HELLOS: usize = 1024;
+
+const TLS_CONTENT_HANDSHAKE: u8 = 0x16;
+const TLS_HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const TLS_HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsHandshakeKind {
+    ClientHello,
+    ServerHello,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsFingerprint {
+    pub kind: TlsHandshakeKind,
+    pub version: u16,
+    // Raw JA3/JA3S string before hashing, e.g. "771,4865-4866,0-23,29-23,0"
+    pub fingerprint_string: String,
+    // MD5 of the fingerprint string, the form used by threat-intel feeds
+    pub fingerprint: String,
+    // SNI hostname from a ClientHello, if present
+    pub server_name: Option<String>,
+}
+
+// GREASE values (RFC 8701) are random per connection and excluded from JA3
+fn is_grease(value: u16) -> bool {
+    (value & 0x0f0f) == 0x0a0a && (value >> 8) == (value & 0xff)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.offset)?;
+        self.offset += 1;
+        Some(value)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.offset..self.offset + 2)?;
+        self.offset += 2;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        let bytes = self.data.get(self.offset..self.offset + 3)?;
+        self.offset += 3;
+        Some(((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset + len)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+}
+
+// Returns true if the payload looks like the start of a TLS handshake record
+pub fn is_tls_handshake(payload: &[u8]) -> bool {
+    payload.len() >= 6 && payload[0] == TLS_CONTENT_HANDSHAKE && payload[1] == 0x03
+}
+
+#[derive(Debug, Clone)]
+pub enum HelloParse {
+    Complete(TlsFingerprint),
+    // The start of a hello whose remaining bytes are still to come
+    Partial,
+    // A hello that started but can't be completed: a segment went missing,
+    // or it outgrew the buffer. Its SNI is unknown, so policy may fail closed.
+    Incomplete,
+    NotHello,
+}
+
+impl HelloParse {
+    pub fn complete(self) -> Option<TlsFingerprint> {
+        match self {
+            HelloParse::Complete(hello) => Some(hello),
+            _ => None,
+        }
+    }
+}
+
+// Parse a TCP payload and compute a JA3 (ClientHello) or JA3S (ServerHello) fingerprint
+pub fn fingerprint(payload: &[u8]) -> Option<TlsFingerprint> {
+    parse_hello(payload).complete()
+}
+
+// Parses the handshake bytes of a flow seen so far. The hello may be split
+// over several records, and the records over several segments.
+pub fn parse_hello(data: &[u8]) -> HelloParse {
+    if !is_tls_handshake(data) {
+        return HelloParse::NotHello;
+    }
+
+    let mut records = Reader::new(data);
+    let mut handshake = Vec::new();
+    let handshake_len = loop {
+        if let Some(len) = handshake.get(1..).and_then(|header| Reader::new(header).u24()) {
+            if len > MAX_HELLO_BYTES {
+                return HelloParse::Incomplete;
+            }
+            if handshake.len() >= 4 + len {
+                break len;
+            }
+        }
+        let Some(content_type) = records.u8() else { return HelloParse::Partial };
+        if content_type != TLS_CONTENT_HANDSHAKE {
+            return HelloParse::NotHello;
+        }
+        let (Some(_version), Some(record_len)) = (records.u16(), records.u16()) else { return HelloParse::Partial };
+        let Some(body) = records.bytes(record_len as usize) else { return HelloParse::Partial };
+        handshake.extend_from_slice(body);
+    };
+
+    let hello = &handshake[4..4 + handshake_len];
+    let parsed = match handshake[0] {
+        TLS_HANDSHAKE_CLIENT_HELLO => parse_client_hello(hello),
+        TLS_HANDSHAKE_SERVER_HELLO => parse_server_hello(hello),
+        _ => None,
+    };
+    parsed.map_or(HelloParse::NotHello, HelloParse::Complete)
+}
+
+struct PendingHello {
+    data: Vec<u8>,
+    // Sequence number of the byte after the last one buffered
+    next_seq: u32,
+    started: Instant,
+}
+
+// Buffers the start of hellos that span TCP segments, per flow, until the
+// whole hello has arrived
+pub struct HelloReassembler<K> {
+    pending: HashMap<K, PendingHello>,
+}
+
+impl<K: Hash + Eq + Clone> Default for HelloReassembler<K> {
+    fn default() -> Self {
+        Self { pending: HashMap::new() }
+    }
+}
+
+impl<K: Hash + Eq + Clone> HelloReassembler<K> {
+    // Feeds a segment of a flow, `seq` being the TCP sequence number of its
+    // first payload byte
+    pub fn push(&mut self, flow: &K, seq: u32, payload: &[u8]) -> HelloParse {
+        let Some(pending) = self.pending.get_mut(flow) else {
+            let result = parse_hello(payload);
+            if matches!(result, HelloParse::Partial) {
+                return self.start(flow, seq, payload);
+            }
+            return result;
+        };
+
+        // A retransmission may overlap what is already buffered; a gap means
+        // a segment was lost and the hello can't be completed
+        let offset = seq.wrapping_sub(pending.next_seq) as i32;
+        if offset > 0 {
+            self.pending.remove(flow);
+            return HelloParse::Incomplete;
+        }
+        let skip = offset.unsigned_abs() as usize;
+        if skip >= payload.len() {
+            return HelloParse::Partial;
+        }
+        let payload = &payload[skip..];
+        pending.data.extend_from_slice(payload);
+        pending.next_seq = pending.next_seq.wrapping_add(payload.len() as u32);
+
+        match parse_hello(&pending.data) {
+            HelloParse::Partial if pending.data.len() < MAX_HELLO_BYTES + 5 => HelloParse::Partial,
+            HelloParse::Partial => {
+                self.pending.remove(flow);
+                HelloParse::Incomplete
+            }
+            result => {
+                self.pending.remove(flow);
+                result
+            }
+        }
+    }
+
+    fn start(&mut self, flow: &K, seq: u32, payload: &[u8]) -> HelloParse {
+        if self.pending.len() >= MAX_PENDING_HELLOS {
+            self.pending.retain(|_, pending| pending.started.elapsed() < HELLO_TIMEOUT);
+        }
+        if self.pending.len() >= MAX_PENDING_HELLOS || payload.len() >= MAX_HELLO_BYTES + 5 {
+            return HelloParse::Incomplete;
+        }
+        self.pending.insert(flow.clone(), PendingHello {
+            data: payload.to_vec(),
+            next_seq: seq.wrapping_add(payload.len() as u32),
+            started: Instant::now(),
+        });
+        HelloParse::Partial
+    }
+}
+
+fn parse_client_hello(hello: &[u8]) -> Option<TlsFingerprint> {
+    let mut reader = Reader::new(hello);
+    let version = reader.u16()?;
+    reader.skip(32)?; // random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+
+    let cipher_len = reader.u16()? as usize;
+    let mut ciphers = Reader::new(reader.bytes(cipher_len)?);
+    let mut cipher_suites = Vec::new();
+    while !ciphers.is_empty() {
+        let cipher = ciphers.u16()?;
+        if !is_grease(cipher) {
+            cipher_suites.push(cipher);
+        }
+    }
+
+    let compression_len = reader.u8()? as usize;
+    reader.skip(compression_len)?;
+
+    let mut extensions = Vec::new();
+    let mut curves = Vec::new();
+    let mut point_formats = Vec::new();
+    let mut server_name = None;
+
+    // Extensions are optional in very old ClientHellos
+    if !reader.is_empty() {
+        let extensions_len = reader.u16()? as usize;
+        let mut ext_reader = Reader::new(reader.bytes(extensions_len)?);
+
+        while !ext_reader.is_empty() {
+            let ext_type = ext_reader.u16()?;
+            let ext_len = ext_reader.u16()? as usize;
+            let ext_data = ext_reader.bytes(ext_len)?;
+
+            if is_grease(ext_type) {
+                continue;
+            }
+            extensions.push(ext_type);
+
+            match ext_type {
+                EXT_SERVER_NAME => {
+                    server_name = parse_server_name(ext_data);
+                }
+                EXT_SUPPORTED_GROUPS => {
+                    let mut groups = Reader::new(ext_data);
+                    let list_len = groups.u16()? as usize;
+                    let mut list = Reader::new(groups.bytes(list_len)?);
+                    while !list.is_empty() {
+                        let group = list.u16()?;
+                        if !is_grease(group) {
+                            curves.push(group);
+                        }
+                    }
+                }
+                EXT_EC_POINT_FORMATS => {
+                    let mut formats = Reader::new(ext_data);
+                    let list_len = formats.u8()? as usize;
+                    for format in formats.bytes(list_len)? {
+                        point_formats.push(*format as u16);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let fingerprint_string = format!(
+        "{},{},{},{},{}",
+        version,
+        join_values(&cipher_suites),
+        join_values(&extensions),
+        join_values(&curves),
+        join_values(&point_formats),
+    );
+
+    Some(TlsFingerprint {
+        kind: TlsHandshakeKind::ClientHello,
+        version,
+        fingerprint: format!("{:x}", md5::compute(fingerprint_string.as_bytes())),
+        fingerprint_string,
+        server_name,
+    })
+}
+
+fn parse_server_hello(hello: &[u8]) -> Option<TlsFingerprint> {
+    let mut reader = Reader::new(hello);
+    let version = reader.u16()?;
+    reader.skip(32)?; // random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+    let cipher = reader.u16()?;
+    reader.skip(1)?; // compression method
+
+    let mut extensions = Vec::new();
+    if !reader.is_empty() {
+        let extensions_len = reader.u16()? as usize;
+        let mut ext_reader = Reader::new(reader.bytes(extensions_len)?);
+
+        while !ext_reader.is_empty() {
+            let ext_type = ext_reader.u16()?;
+            let ext_len = ext_reader.u16()? as usize;
+            ext_reader.skip(ext_len)?;
+            extensions.push(ext_type);
+        }
+    }
+
+    let fingerprint_string = format!("{},{},{}", version, cipher, join_values(&extensions));
+
+    Some(TlsFingerprint {
+        kind: TlsHandshakeKind::ServerHello,
+        version,
+        fingerprint: format!("{:x}", md5::compute(fingerprint_string.as_bytes())),
+        fingerprint_string,
+        server_name: None,
+    })
+}
+
+// Extract the host_name entry from a server_name extension (RFC 6066)
+fn parse_server_name(ext_data: &[u8]) -> Option<String> {
+    let mut reader = Reader::new(ext_data);
+    let list_len = reader.u16()? as usize;
+    let mut list = Reader::new(reader.bytes(list_len)?);
+
+    while !list.is_empty() {
+        let name_type = list.u8()?;
+        let name_len = list.u16()? as usize;
+        let name = list.bytes(name_len)?;
+
+        if name_type == 0 {
+            let name = std::str::from_utf8(name).ok()?;
+            return Some(name.trim_end_matches('.').to_lowercase());
+        }
+    }
+
+    None
+}
+
+fn join_values(values: &[u16]) -> String {
+    values.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_client_hello(ciphers: &[u16], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut hello = Vec::new();
+        hello.extend_from_slice(&0x0303u16.to_be_bytes());
+        hello.extend_from_slice(&[0u8; 32]);
+        hello.push(0); // session id
+        hello.extend_from_slice(&((ciphers.len() * 2) as u16).to_be_bytes());
+        for cipher in ciphers {
+            hello.extend_from_slice(&cipher.to_be_bytes());
+        }
+        hello.extend_from_slice(&[1, 0]); // null compression
+
+        let mut ext_bytes = Vec::new();
+        for (ext_type, data) in extensions {
+            ext_bytes.extend_from_slice(&ext_type.to_be_bytes());
+            ext_bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            ext_bytes.extend_from_slice(data);
+        }
+        hello.extend_from_slice(&(ext_bytes.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&ext_bytes);
+
+        let mut handshake = vec![TLS_HANDSHAKE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&hello);
+
+        let mut record = vec![TLS_CONTENT_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    fn server_name_ext(name: &str) -> Vec<u8> {
+        let mut entry = vec![0u8];
+        entry.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        entry.extend_from_slice(name.as_bytes());
+
+        let mut ext = (entry.len() as u16).to_be_bytes().to_vec();
+        ext.extend_from_slice(&entry);
+        ext
+    }
+
+    #[test]
+    fn test_ja3_client_hello() {
+        let payload = build_client_hello(
+            &[0x0a0a, 0x1301, 0x1302],
+            &[
+                (0x0a0a, vec![]),
+                (EXT_SERVER_NAME, server_name_ext("C2.Example.com")),
+                (EXT_SUPPORTED_GROUPS, vec![0, 4, 0x00, 0x1d, 0x00, 0x17]),
+                (EXT_EC_POINT_FORMATS, vec![1, 0]),
+            ],
+        );
+
+        let fp = fingerprint(&payload).unwrap();
+        assert_eq!(fp.kind, TlsHandshakeKind::ClientHello);
+        assert_eq!(fp.fingerprint_string, "771,4865-4866,0-10-11,29-23,0");
+        assert_eq!(fp.fingerprint, format!("{:x}", md5::compute("771,4865-4866,0-10-11,29-23,0")));
+        assert_eq!(fp.server_name.as_deref(), Some("c2.example.com"));
+    }
+
+    #[test]
+    fn test_client_hello_split_across_segments() {
+        let padding = vec![0u8; 3000];
+        let payload = build_client_hello(
+            &[0x1301],
+            &[(EXT_SERVER_NAME, server_name_ext("c2.example.com")), (0x0015, padding)],
+        );
+        let (first, rest) = payload.split_at(1400);
+        let (second, third) = rest.split_at(1400);
+        assert!(fingerprint(first).is_none());
+
+        let mut reassembler = HelloReassembler::default();
+        let seq = 0xffff_ff00u32;
+        assert!(matches!(reassembler.push(&1, seq, first), HelloParse::Partial));
+        // A retransmitted segment is not buffered twice
+        assert!(matches!(reassembler.push(&1, seq, first), HelloParse::Partial));
+        assert!(matches!(reassembler.push(&1, seq.wrapping_add(1400), second), HelloParse::Partial));
+        let hello = reassembler.push(&1, seq.wrapping_add(2800), third).complete().unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("c2.example.com"));
+        assert_eq!(hello.fingerprint_string, "771,4865,0-21,,");
+        assert!(reassembler.pending.is_empty());
+
+        // A lost segment gives up on the flow, and says so
+        reassembler.push(&2, 0, first);
+        assert!(matches!(reassembler.push(&2, 2800, third), HelloParse::Incomplete));
+        assert!(reassembler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_non_tls_payload() {
+        assert!(fingerprint(b"GET / HTTP/1.1\r\n").is_none());
+        assert!(!is_grease(0x1301));
+        assert!(is_grease(0xfafa));
+    }
+}
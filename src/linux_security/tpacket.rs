@@ -0,0 +1,307 @@
+use std::ffi::CString;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use anyhow::{anyhow, Result};
+
+// linux/if_packet.h
+const PACKET_RX_RING: libc::c_int = 5;
+const PACKET_STATISTICS: libc::c_int = 6;
+const PACKET_VERSION: libc::c_int = 10;
+const TPACKET_V3: libc::c_int = 2;
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1;
+const SO_ATTACH_FILTER: libc::c_int = 26;
+const SO_DETACH_FILTER: libc::c_int = 27;
+
+// Header offsets inside a block (struct tpacket_block_desc / tpacket_hdr_v1)
+const BLOCK_STATUS: usize = 8;
+const BLOCK_NUM_PKTS: usize = 12;
+const BLOCK_FIRST_PKT: usize = 16;
+// Offsets inside struct tpacket3_hdr, which is followed by a sockaddr_ll
+const PKT_NEXT_OFFSET: usize = 0;
+const PKT_SNAPLEN: usize = 12;
+const PKT_LEN: usize = 16;
+const PKT_NET: usize = 26;
+const PKT_SLL: usize = 48;
+
+const FRAME_SIZE: u32 = 2048;
+// Blocks are handed to user space after this long even when not full
+const BLOCK_RETIRE_MS: u32 = 100;
+
+#[repr(C)]
+struct TpacketReq3 {
+    tp_block_size: u32,
+    tp_block_nr: u32,
+    tp_frame_size: u32,
+    tp_frame_nr: u32,
+    tp_retire_blk_tov: u32,
+    tp_sizeof_priv: u32,
+    tp_feature_req_word: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct TpacketStatsV3 {
+    tp_packets: u32,
+    tp_drops: u32,
+    tp_freeze_q_cnt: u32,
+}
+
+// One packet in a ring block, starting at the network-layer header
+pub struct RingPacket<'a> {
+    pub data: &'a [u8],
+    pub wire_len: usize,
+    pub ifindex: i32,
+    pub outgoing: bool,
+    // EtherType from the link-layer address, e.g. 0x0806 for ARP
+    pub protocol: u16,
+}
+
+// AF_PACKET socket with a TPACKET_V3 receive ring. The kernel fills whole
+// blocks of packets in shared memory, so packets are read without a copy or a
+// syscall per packet. SOCK_DGRAM strips link-layer headers, which keeps "any"
+// and non-Ethernet interfaces uniform and lets filters be compiled for raw IP.
+pub struct TpacketRing {
+    socket: OwnedFd,
+    map: *mut u8,
+    block_size: usize,
+    block_count: usize,
+    current: usize,
+}
+
+// The mapping is only touched through &mut self
+unsafe impl Send for TpacketRing {}
+
+impl TpacketRing {
+    // `interface` of None (or "any") captures on every interface
+    pub fn open(interface: Option<&str>, block_size: usize, block_count: usize) -> Result<Self> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        if block_size == 0 || !block_size.is_multiple_of(page_size) || !block_size.is_power_of_two() || block_count == 0 {
+            return Err(anyhow!("Ring block size must be a power of two multiple of the page size"));
+        }
+
+        let fd = unsafe {
+            libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, (libc::ETH_P_ALL as u16).to_be() as i32)
+        };
+        if fd < 0 {
+            return Err(anyhow!("Failed to open packet socket: {}", std::io::Error::last_os_error()));
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        set_option(&socket, libc::SOL_PACKET, PACKET_VERSION, &TPACKET_V3)?;
+        let request = TpacketReq3 {
+            tp_block_size: block_size as u32,
+            tp_block_nr: block_count as u32,
+            tp_frame_size: FRAME_SIZE,
+            tp_frame_nr: (block_size / FRAME_SIZE as usize * block_count) as u32,
+            tp_retire_blk_tov: BLOCK_RETIRE_MS,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        set_option(&socket, libc::SOL_PACKET, PACKET_RX_RING, &request)?;
+
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(), block_size * block_count, libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED, socket.as_raw_fd(), 0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(anyhow!("Failed to map packet ring: {}", std::io::Error::last_os_error()));
+        }
+        let ring = Self { socket, map: map as *mut u8, block_size, block_count, current: 0 };
+
+        let ifindex = match interface {
+            Some(name) if name != "any" => {
+                let c_name = CString::new(name)?;
+                let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+                if index == 0 {
+                    return Err(anyhow!("Unknown interface {}", name));
+                }
+                index as i32
+            }
+            _ => 0,
+        };
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+        addr.sll_ifindex = ifindex;
+        let ret = unsafe {
+            libc::bind(ring.socket.as_raw_fd(), &addr as *const _ as *const libc::sockaddr, std::mem::size_of::<libc::sockaddr_ll>() as u32)
+        };
+        if ret != 0 {
+            return Err(anyhow!("Failed to bind packet socket: {}", std::io::Error::last_os_error()));
+        }
+
+        Ok(ring)
+    }
+
+    // Installs a classic BPF program in the kernel, or removes it with None
+    pub fn set_filter(&self, program: Option<&[libc::sock_filter]>) -> Result<()> {
+        match program {
+            Some(program) => {
+                let fprog = libc::sock_fprog { len: program.len() as u16, filter: program.as_ptr() as *mut libc::sock_filter };
+                set_option(&self.socket, libc::SOL_SOCKET, SO_ATTACH_FILTER, &fprog)
+            }
+            None => {
+                // ENOENT just means no filter was attached
+                let _ = set_option(&self.socket, libc::SOL_SOCKET, SO_DETACH_FILTER, &0i32);
+                Ok(())
+            }
+        }
+    }
+
+    // Packets received and dropped since the previous call
+    pub fn stats(&self) -> Result<(u64, u64)> {
+        let mut stats = TpacketStatsV3::default();
+        let mut len = std::mem::size_of::<TpacketStatsV3>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.socket.as_raw_fd(), libc::SOL_PACKET, PACKET_STATISTICS,
+                &mut stats as *mut _ as *mut libc::c_void, &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(anyhow!("Failed to read packet statistics: {}", std::io::Error::last_os_error()));
+        }
+        Ok((stats.tp_packets as u64, stats.tp_drops as u64))
+    }
+
+    // Waits up to `timeout_ms` for the next retired block and hands its packets
+    // to `f`. Returns false on timeout.
+    pub fn next_block<F>(&mut self, timeout_ms: i32, mut f: F) -> Result<bool>
+    where
+        F: FnMut(&[RingPacket<'_>]),
+    {
+        let block = unsafe { self.map.add(self.current * self.block_size) };
+        let status = block_status(block);
+        if unsafe { ptr::read_volatile(status) } & TP_STATUS_USER == 0 {
+            let mut pfd = libc::pollfd { fd: self.socket.as_raw_fd(), events: libc::POLLIN | libc::POLLERR, revents: 0 };
+            let ret = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    return Ok(false);
+                }
+                return Err(anyhow!("Packet ring poll failed: {}", err));
+            }
+            if pfd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+                return Err(anyhow!("Packet socket error (interface gone?)"));
+            }
+            if unsafe { ptr::read_volatile(status) } & TP_STATUS_USER == 0 {
+                return Ok(false);
+            }
+        }
+        std::sync::atomic::fence(std::sync::atomic::Ordering::Acquire);
+
+        let block_data = unsafe { std::slice::from_raw_parts(block, self.block_size) };
+        let packets = parse_block(block_data);
+        f(&packets);
+        drop(packets);
+
+        std::sync::atomic::fence(std::sync::atomic::Ordering::Release);
+        unsafe { ptr::write_volatile(status, TP_STATUS_KERNEL) };
+        self.current = (self.current + 1) % self.block_count;
+        Ok(true)
+    }
+}
+
+impl Drop for TpacketRing {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map as *mut libc::c_void, self.block_size * self.block_count);
+        }
+    }
+}
+
+fn block_status(block: *mut u8) -> *mut u32 {
+    unsafe { block.add(BLOCK_STATUS) as *mut u32 }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_ne_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+// Walks the packets of a retired block; anything that runs past the block is
+// dropped rather than trusted
+fn parse_block(block: &[u8]) -> Vec<RingPacket<'_>> {
+    let count = read_u32(block, BLOCK_NUM_PKTS).unwrap_or(0) as usize;
+    let mut offset = read_u32(block, BLOCK_FIRST_PKT).unwrap_or(0) as usize;
+    let mut packets = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let header = match block.get(offset..) {
+            Some(header) if header.len() >= PKT_SLL + std::mem::size_of::<libc::sockaddr_ll>() => header,
+            _ => break,
+        };
+        let (Some(snaplen), Some(len), Some(net), Some(next)) = (
+            read_u32(header, PKT_SNAPLEN), read_u32(header, PKT_LEN), read_u16(header, PKT_NET), read_u32(header, PKT_NEXT_OFFSET),
+        ) else {
+            break;
+        };
+        // With SOCK_DGRAM tp_mac == tp_net, so the snaplen covers the network layer
+        if let Some(data) = header.get(net as usize..net as usize + snaplen as usize) {
+            let ifindex = read_u32(header, PKT_SLL + 4).unwrap_or(0) as i32;
+            let pkttype = header[PKT_SLL + 10];
+            let protocol = u16::from_be_bytes([header[PKT_SLL + 2], header[PKT_SLL + 3]]);
+            packets.push(RingPacket { data, wire_len: len as usize, ifindex, outgoing: pkttype == libc::PACKET_OUTGOING, protocol });
+        }
+        if next == 0 {
+            break;
+        }
+        offset += next as usize;
+    }
+    packets
+}
+
+fn set_option<T>(socket: &OwnedFd, level: libc::c_int, name: libc::c_int, value: &T) -> Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(), level, name,
+            value as *const T as *const libc::c_void, std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(anyhow!("setsockopt({}, {}) failed: {}", level, name, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block() {
+        let mut block = vec![0u8; 4096];
+        block[BLOCK_NUM_PKTS..BLOCK_NUM_PKTS + 4].copy_from_slice(&2u32.to_ne_bytes());
+        block[BLOCK_FIRST_PKT..BLOCK_FIRST_PKT + 4].copy_from_slice(&48u32.to_ne_bytes());
+
+        // Two packets 128 bytes apart, network header 80 bytes into each frame
+        for (i, len) in [(0usize, 20u32), (1, 40)] {
+            let base = 48 + i * 128;
+            let next = if i == 0 { 128u32 } else { 0 };
+            block[base..base + 4].copy_from_slice(&next.to_ne_bytes());
+            block[base + PKT_SNAPLEN..base + PKT_SNAPLEN + 4].copy_from_slice(&len.to_ne_bytes());
+            block[base + PKT_LEN..base + PKT_LEN + 4].copy_from_slice(&(len + 100).to_ne_bytes());
+            block[base + PKT_NET..base + PKT_NET + 2].copy_from_slice(&80u16.to_ne_bytes());
+            block[base + PKT_SLL + 4..base + PKT_SLL + 8].copy_from_slice(&3u32.to_ne_bytes());
+            block[base + PKT_SLL + 2..base + PKT_SLL + 4].copy_from_slice(&[0x08, 0x00]);
+            block[base + PKT_SLL + 10] = if i == 1 { libc::PACKET_OUTGOING } else { 0 };
+            block[base + 80] = 0x45;
+        }
+
+        let packets = parse_block(&block);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].data.len(), 20);
+        assert_eq!(packets[0].data[0], 0x45);
+        assert_eq!(packets[1].wire_len, 140);
+        assert_eq!(packets[1].ifindex, 3);
+        assert_eq!(packets[0].protocol, 0x0800);
+        assert!(!packets[0].outgoing && packets[1].outgoing);
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::monitor::{NetworkProtocol, ProcessInfo, SecurityEvent, SecurityEventType, Verdict};
+
+// Echo traffic between two hosts, counted per minute. Ping sends one
+// fixed-size packet a second; tunnels send faster, bigger and varying ones.
+const ICMP_WINDOW: Duration = Duration::from_secs(60);
+const ICMP_MAX_RATE: usize = 100;
+const ICMP_LARGE_PAYLOAD: usize = 128;
+const ICMP_MIN_LARGE: usize = 5;
+const ICMP_MIN_DISTINCT_SIZES: usize = 5;
+
+// Queries from one client under one domain, counted per five minutes
+const DNS_WINDOW: Duration = Duration::from_secs(300);
+const DNS_ENTROPY_BITS: f64 = 3.5;
+const DNS_ENTROPY_MIN_LEN: usize = 16;
+const DNS_LONG_LABEL: usize = 40;
+const DNS_MIN_HIGH_ENTROPY: usize = 10;
+const DNS_MIN_LONG_LABELS: usize = 5;
+const DNS_MIN_TXT_NULL: usize = 20;
+const DNS_MIN_UNIQUE_SUBDOMAINS: usize = 50;
+const QTYPE_NULL: u16 = 10;
+const QTYPE_TXT: u16 = 16;
+
+// A detection needs this many indicators at once
+const MIN_INDICATORS: usize = 2;
+const MAX_TRACKED: usize = 10_000;
+// Cap on the subdomains or payload sizes remembered per window
+const MAX_DISTINCT: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelKind {
+    Icmp,
+    Dns,
+}
+
+impl TunnelKind {
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            TunnelKind::Icmp => "icmp_tunneling",
+            TunnelKind::Dns => "dns_tunneling",
+        }
+    }
+
+    pub fn rule_name(&self) -> &'static str {
+        match self {
+            TunnelKind::Icmp => "ICMP tunneling",
+            TunnelKind::Dns => "DNS tunneling",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TunnelDetection {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: TunnelKind,
+    // The host sending the echo requests or DNS queries
+    pub source: IpAddr,
+    // Echo peer or resolver
+    pub destination: IpAddr,
+    pub domain: Option<String>,
+    pub indicators: Vec<String>,
+    // Packets or queries in the window that tripped the indicators
+    pub packets: usize,
+    pub description: String,
+}
+
+impl TunnelDetection {
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let (remote_port, protocol) = match self.kind {
+            TunnelKind::Icmp => (0, NetworkProtocol::Icmp),
+            TunnelKind::Dns => (53, NetworkProtocol::Udp),
+        };
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type: SecurityEventType::NetworkConnection {
+                remote_ip: self.destination.to_string(),
+                remote_port,
+                domain: self.domain.clone(),
+                protocol,
+            },
+            // Seen on the wire only, so no process is known
+            process_info: ProcessInfo {
+                pid: 0,
+                path: PathBuf::from("unknown"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: self.description.clone(),
+        }
+    }
+}
+
+struct IcmpWindow {
+    started: Instant,
+    requester: IpAddr,
+    packets: usize,
+    large: usize,
+    sizes: HashSet<usize>,
+}
+
+struct DnsWindow {
+    started: Instant,
+    queries: usize,
+    subdomains: HashSet<String>,
+    high_entropy: usize,
+    long_labels: usize,
+    txt_null: usize,
+}
+
+#[derive(Default)]
+struct TunnelState {
+    // Keyed by the address pair in sorted order, so replies count too
+    icmp: HashMap<(IpAddr, IpAddr), IcmpWindow>,
+    dns: HashMap<(IpAddr, String), DnsWindow>,
+    // Last report per pair or per client and domain, one per window
+    icmp_reported: HashMap<(IpAddr, IpAddr), Instant>,
+    dns_reported: HashMap<(IpAddr, String), Instant>,
+}
+
+// Flags ICMP echo and DNS traffic shaped like a covert channel
+#[derive(Default)]
+pub struct TunnelDetector {
+    state: Mutex<TunnelState>,
+}
+
+impl TunnelDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An ICMPv4 message, starting at the ICMP header
+    pub fn observe_icmp(&self, source: IpAddr, destination: IpAddr, icmp: &[u8]) -> Option<TunnelDetection> {
+        // Echo reply / echo request
+        let icmp_type = *icmp.first()?;
+        if icmp.len() < 8 || (icmp_type != 0 && icmp_type != 8) {
+            return None;
+        }
+        let payload = icmp.len() - 8;
+        let now = Instant::now();
+        let key = if source <= destination { (source, destination) } else { (destination, source) };
+
+        let mut state = self.state.lock().unwrap();
+        if !state.icmp.contains_key(&key) && state.icmp.len() >= MAX_TRACKED {
+            state.prune(now);
+            if state.icmp.len() >= MAX_TRACKED {
+                return None;
+            }
+        }
+        let requester = if icmp_type == 8 { source } else { destination };
+        let window = state.icmp.entry(key).or_insert_with(|| IcmpWindow {
+            started: now,
+            requester,
+            packets: 0,
+            large: 0,
+            sizes: HashSet::new(),
+        });
+        if now.duration_since(window.started) > ICMP_WINDOW {
+            *window = IcmpWindow { started: now, requester, packets: 0, large: 0, sizes: HashSet::new() };
+        }
+        window.packets += 1;
+        if payload > ICMP_LARGE_PAYLOAD {
+            window.large += 1;
+        }
+        if window.sizes.len() < MAX_DISTINCT {
+            window.sizes.insert(payload);
+        }
+
+        let mut indicators = Vec::new();
+        if window.packets >= ICMP_MAX_RATE {
+            indicators.push(format!("{} echo packets in {}s", window.packets, ICMP_WINDOW.as_secs()));
+        }
+        if window.large >= ICMP_MIN_LARGE {
+            indicators.push(format!("{} payloads over {} bytes", window.large, ICMP_LARGE_PAYLOAD));
+        }
+        if window.sizes.len() >= ICMP_MIN_DISTINCT_SIZES {
+            indicators.push(format!("{} distinct payload sizes", window.sizes.len()));
+        }
+        if indicators.len() < MIN_INDICATORS {
+            return None;
+        }
+        let (requester, packets) = (window.requester, window.packets);
+        if state.icmp_reported.get(&key).is_some_and(|reported| now.duration_since(*reported) <= ICMP_WINDOW) {
+            return None;
+        }
+        state.icmp_reported.insert(key, now);
+
+        let peer = if requester == key.0 { key.1 } else { key.0 };
+        Some(TunnelDetection {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            kind: TunnelKind::Icmp,
+            source: requester,
+            destination: peer,
+            domain: None,
+            description: format!("Possible ICMP tunnel {} -> {}: {}", requester, peer, indicators.join(", ")),
+            indicators,
+            packets,
+        })
+    }
+
+    // A DNS question sent by `client` to `resolver`
+    pub fn observe_dns_query(&self, client: IpAddr, resolver: IpAddr, name: &str, qtype: u16) -> Option<TunnelDetection> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let labels: Vec<&str> = name.split('.').filter(|label| !label.is_empty()).collect();
+        // Reverse lookups are long and hex-heavy by nature
+        if labels.len() < 2 || labels.last() == Some(&"arpa") {
+            return None;
+        }
+        let split = labels.len() - 2;
+        let domain = labels[split..].join(".");
+        let subdomain = &labels[..split];
+        let now = Instant::now();
+        let key = (client, domain.clone());
+
+        let mut state = self.state.lock().unwrap();
+        if !state.dns.contains_key(&key) && state.dns.len() >= MAX_TRACKED {
+            state.prune(now);
+            if state.dns.len() >= MAX_TRACKED {
+                return None;
+            }
+        }
+        let window = state.dns.entry(key.clone()).or_insert_with(|| DnsWindow::new(now));
+        if now.duration_since(window.started) > DNS_WINDOW {
+            *window = DnsWindow::new(now);
+        }
+        window.queries += 1;
+        if qtype == QTYPE_TXT || qtype == QTYPE_NULL {
+            window.txt_null += 1;
+        }
+        if !subdomain.is_empty() {
+            let joined = subdomain.concat();
+            if joined.len() >= DNS_ENTROPY_MIN_LEN && entropy(joined.as_bytes()) >= DNS_ENTROPY_BITS {
+                window.high_entropy += 1;
+            }
+            if subdomain.iter().any(|label| label.len() >= DNS_LONG_LABEL) {
+                window.long_labels += 1;
+            }
+            if window.subdomains.len() < MAX_DISTINCT {
+                window.subdomains.insert(subdomain.join("."));
+            }
+        }
+
+        let mut indicators = Vec::new();
+        if window.high_entropy >= DNS_MIN_HIGH_ENTROPY {
+            indicators.push(format!("{} high-entropy subdomains", window.high_entropy));
+        }
+        if window.long_labels >= DNS_MIN_LONG_LABELS {
+            indicators.push(format!("{} labels of {}+ characters", window.long_labels, DNS_LONG_LABEL));
+        }
+        if window.txt_null >= DNS_MIN_TXT_NULL {
+            indicators.push(format!("{} TXT/NULL queries", window.txt_null));
+        }
+        if window.subdomains.len() >= DNS_MIN_UNIQUE_SUBDOMAINS {
+            indicators.push(format!("{} unique subdomains in {}s", window.subdomains.len(), DNS_WINDOW.as_secs()));
+        }
+        if indicators.len() < MIN_INDICATORS {
+            return None;
+        }
+        let queries = window.queries;
+        if state.dns_reported.get(&key).is_some_and(|reported| now.duration_since(*reported) <= DNS_WINDOW) {
+            return None;
+        }
+        state.dns_reported.insert(key, now);
+
+        Some(TunnelDetection {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            kind: TunnelKind::Dns,
+            source: client,
+            destination: resolver,
+            description: format!("Possible DNS tunnel from {} through {}: {}", client, domain, indicators.join(", ")),
+            domain: Some(domain),
+            indicators,
+            packets: queries,
+        })
+    }
+
+    pub fn prune(&self) {
+        self.state.lock().unwrap().prune(Instant::now());
+    }
+}
+
+impl TunnelState {
+    fn prune(&mut self, now: Instant) {
+        self.icmp.retain(|_, window| now.duration_since(window.started) <= ICMP_WINDOW);
+        self.dns.retain(|_, window| now.duration_since(window.started) <= DNS_WINDOW);
+        self.icmp_reported.retain(|_, reported| now.duration_since(*reported) <= ICMP_WINDOW);
+        self.dns_reported.retain(|_, reported| now.duration_since(*reported) <= DNS_WINDOW);
+    }
+}
+
+impl DnsWindow {
+    fn new(started: Instant) -> Self {
+        Self { started, queries: 0, subdomains: HashSet::new(), high_entropy: 0, long_labels: 0, txt_null: 0 }
+    }
+}
+
+// Shannon entropy in bits per byte
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(icmp_type: u8, payload: usize) -> Vec<u8> {
+        let mut packet = vec![icmp_type, 0, 0, 0, 0, 1, 0, 1];
+        packet.extend((0..payload).map(|i| i as u8));
+        packet
+    }
+
+    #[test]
+    fn test_tunnel_detection() {
+        let detector = TunnelDetector::new();
+        let host: IpAddr = "10.0.0.5".parse().unwrap();
+        let gateway: IpAddr = "10.0.0.1".parse().unwrap();
+        let relay: IpAddr = "198.51.100.9".parse().unwrap();
+
+        // A minute of ordinary pings
+        for _ in 0..60 {
+            assert!(detector.observe_icmp(host, gateway, &echo(8, 56)).is_none());
+            assert!(detector.observe_icmp(gateway, host, &echo(0, 56)).is_none());
+        }
+
+        // Large, varying payloads to a relay, requests and replies alike
+        let mut detection = None;
+        for i in 0..8 {
+            detection = detection.or(detector.observe_icmp(host, relay, &echo(8, 200 + i * 37)));
+            detection = detection.or(detector.observe_icmp(relay, host, &echo(0, 300 + i * 11)));
+        }
+        let detection = detection.expect("ICMP tunnel should be flagged");
+        assert_eq!(detection.kind, TunnelKind::Icmp);
+        assert_eq!((detection.source, detection.destination), (host, relay));
+        assert_eq!(detection.indicators.len(), 2);
+        assert!(detector.observe_icmp(host, relay, &echo(8, 999)).is_none());
+
+        // Everyday lookups, TXT records included, stay quiet
+        for i in 0..30 {
+            let name = format!("{}.example.com", ["www", "mail", "api", "cdn"][i % 4]);
+            assert!(detector.observe_dns_query(host, gateway, &name, if i % 3 == 0 { QTYPE_TXT } else { 1 }).is_none());
+        }
+        for i in 0..30 {
+            let name = format!("{:x}.1.0.0.10.in-addr.arpa", i);
+            assert!(detector.observe_dns_query(host, gateway, &name, 12).is_none());
+        }
+
+        // Encoded chunks in long random labels, fetched as TXT
+        let mut detection = None;
+        for i in 0..20u64 {
+            let chunk: String = (0..48).map(|j| {
+                let x = (i * 7919 + j * 104_729) ^ (j * j * 31 + i);
+                char::from(b"abcdefghijklmnopqrstuvwxyz0123456789"[(x % 36) as usize])
+            }).collect();
+            let name = format!("{}.{}.t.tunnel.example.net", chunk, i);
+            detection = detection.or(detector.observe_dns_query(host, gateway, &name, QTYPE_TXT));
+        }
+        let detection = detection.expect("DNS tunnel should be flagged");
+        assert_eq!(detection.kind, TunnelKind::Dns);
+        assert_eq!(detection.domain.as_deref(), Some("example.net"));
+        assert!(detection.indicators.iter().any(|indicator| indicator.contains("high-entropy")));
+        assert!(detection.indicators.iter().any(|indicator| indicator.contains("40+")));
+
+        let event = detection.to_security_event();
+        assert!(matches!(event.event_type, SecurityEventType::NetworkConnection { remote_port: 53, .. }));
+        assert!(event.policy_reason.contains("DNS tunnel"));
+    }
+}
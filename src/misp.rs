@@ -0,0 +1,462 @@
+use std::collections::{BTreeSet, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn, debug};
+
+use crate::incidents::{Incident, IncidentManager, IncidentStatus};
+use crate::monitor::SecurityEvent;
+use crate::policy::{Condition, FilePolicy, NetworkPolicy, Rule, RuleAction};
+use crate::stix::{self, Observable};
+
+// File policy rule that carries the hashes pulled from MISP
+pub const MISP_RULE_ID: &str = "misp-blocklist";
+
+// Attribute types requested from MISP; everything else is ignored
+const PULL_TYPES: [&str; 9] = [
+    "sha256", "filename|sha256", "domain", "hostname", "domain|ip",
+    "ip-dst", "ip-src", "ip-dst|port", "ip-src|port",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MispConfig {
+    // Base URL, e.g. https://misp.example.com
+    pub url: String,
+    pub api_key: String,
+    // Events whose attributes are pulled into the blocklists
+    #[serde(default)]
+    pub pull_events: Vec<String>,
+    #[serde(default = "default_pull_interval")]
+    pub pull_interval_secs: u64,
+    // Only pull attributes flagged for detection (to_ids)
+    #[serde(default = "default_ids_only")]
+    pub ids_only: bool,
+    // Event that IOCs of resolved incidents are added to; nothing is pushed without one
+    #[serde(default)]
+    pub push_event: Option<String>,
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+}
+
+fn default_pull_interval() -> u64 {
+    3600
+}
+
+fn default_ids_only() -> bool {
+    true
+}
+
+impl MispConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read MISP config {:?}", path))?;
+        let config: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid MISP config {:?}", path))?;
+        if config.url.is_empty() || config.api_key.is_empty() {
+            bail!("MISP config {:?} needs a url and an api_key", path);
+        }
+        Ok(config)
+    }
+}
+
+// Indicators taken from MISP attributes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MispIocs {
+    pub hashes: BTreeSet<String>,
+    pub domains: BTreeSet<String>,
+    pub ips: BTreeSet<IpAddr>,
+}
+
+impl MispIocs {
+    // Reads the `response.Attribute` list of an attributes/restSearch reply
+    pub fn from_response(response: &Value) -> Self {
+        let mut iocs = Self::default();
+        let attributes = response.pointer("/response/Attribute").and_then(Value::as_array);
+        for attribute in attributes.into_iter().flatten() {
+            if let (Some(kind), Some(value)) = (attribute["type"].as_str(), attribute["value"].as_str()) {
+                iocs.add(kind, value);
+            }
+        }
+        iocs
+    }
+
+    fn add(&mut self, kind: &str, value: &str) {
+        // Composite types keep their parts apart with '|'
+        let mut parts = value.split('|').map(str::trim);
+        let (first, second) = (parts.next().unwrap_or_default(), parts.next());
+        match kind {
+            "sha256" => self.add_hash(first),
+            "filename|sha256" => self.add_hash(second.unwrap_or_default()),
+            "domain" | "hostname" => self.add_domain(first),
+            "domain|ip" => {
+                self.add_domain(first);
+                self.add_ip(second.unwrap_or_default());
+            }
+            "ip-dst" | "ip-src" | "ip-dst|port" | "ip-src|port" => self.add_ip(first),
+            _ => {}
+        }
+    }
+
+    fn add_hash(&mut self, hash: &str) {
+        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            self.hashes.insert(hash.to_lowercase());
+        }
+    }
+
+    fn add_domain(&mut self, domain: &str) {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        if !domain.is_empty() {
+            self.domains.insert(domain);
+        }
+    }
+
+    // Network ranges cannot go into the address blocklist and are skipped
+    fn add_ip(&mut self, ip: &str) {
+        if let Ok(ip) = ip.parse() {
+            self.ips.insert(ip);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len() + self.domains.len() + self.ips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, observable: &Observable) -> bool {
+        match observable {
+            Observable::Sha256 { hash, .. } => self.hashes.contains(hash),
+            Observable::Domain(domain) => self.domains.contains(domain),
+            Observable::Ip(ip) => self.ips.contains(ip),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MispStatus {
+    pub last_pull: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub hashes: usize,
+    pub domains: usize,
+    pub ips: usize,
+    pub pushed_incidents: usize,
+    pub pushed_attributes: u64,
+}
+
+// Pulls MISP attributes into the file and network policy blocklists and
+// pushes the IOCs of resolved incidents back to a MISP event
+pub struct MispConnector {
+    config: MispConfig,
+    client: reqwest::Client,
+    file_policy: Arc<RwLock<FilePolicy>>,
+    network_policy: Arc<RwLock<NetworkPolicy>>,
+    // Entries the pulls added to the policies, so ones MISP drops are lifted
+    // again while anything the policy already had stays
+    applied: Mutex<MispIocs>,
+    pushed: Mutex<HashSet<String>>,
+    status: RwLock<MispStatus>,
+}
+
+impl MispConnector {
+    pub fn new(
+        config: MispConfig,
+        file_policy: Arc<RwLock<FilePolicy>>,
+        network_policy: Arc<RwLock<NetworkPolicy>>,
+    ) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(60));
+        if let Some(ref path) = config.ca_cert_path {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read MISP CA certificate {:?}", path))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            config,
+            file_policy,
+            network_policy,
+            applied: Mutex::new(MispIocs::default()),
+            pushed: Mutex::new(HashSet::new()),
+            status: RwLock::new(MispStatus::default()),
+        })
+    }
+
+    pub fn status(&self) -> MispStatus {
+        self.status.read().map(|status| status.clone()).unwrap_or_default()
+    }
+
+    async fn request(&self, path: &str, body: &Value) -> Result<reqwest::Response> {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), path);
+        self.client.post(&url)
+            .header(reqwest::header::AUTHORIZATION, &self.config.api_key)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("MISP request to {} failed", url))
+    }
+
+    // Fetches the configured events' attributes and replaces the previous
+    // pull's entries in the blocklists
+    pub async fn pull(&self) -> Result<MispStatus> {
+        let result = self.fetch().await.and_then(|iocs| self.apply(iocs));
+        let mut status = self.status.write().map_err(|_| anyhow!("Failed to acquire MISP status write lock"))?;
+        match result {
+            Ok(iocs) => {
+                status.last_pull = Some(Utc::now());
+                status.last_error = None;
+                status.hashes = iocs.hashes.len();
+                status.domains = iocs.domains.len();
+                status.ips = iocs.ips.len();
+                Ok(status.clone())
+            }
+            Err(e) => {
+                status.last_error = Some(format!("{:#}", e));
+                Err(e)
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<MispIocs> {
+        if self.config.pull_events.is_empty() {
+            bail!("No MISP events configured to pull");
+        }
+        let mut body = json!({
+            "returnFormat": "json",
+            "eventid": self.config.pull_events,
+            "type": PULL_TYPES,
+        });
+        if self.config.ids_only {
+            body["to_ids"] = json!(1);
+        }
+
+        let response = self.request("attributes/restSearch", &body).await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("MISP attribute search returned {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        let response: Value = response.json().await.context("Invalid MISP attribute search response")?;
+        Ok(MispIocs::from_response(&response))
+    }
+
+    fn apply(&self, iocs: MispIocs) -> Result<MispIocs> {
+        let mut applied = self.applied.lock().map_err(|_| anyhow!("Failed to acquire MISP state lock"))?;
+        let mut added = MispIocs { hashes: iocs.hashes.clone(), ..Default::default() };
+        {
+            let mut policy = self.network_policy.write()
+                .map_err(|_| anyhow!("Failed to acquire network policy write lock"))?;
+            for ip in applied.ips.difference(&iocs.ips) {
+                policy.blocked_ips.remove(ip);
+            }
+            for domain in applied.domains.difference(&iocs.domains) {
+                policy.blocked_domains.remove(domain);
+            }
+            for ip in &iocs.ips {
+                if policy.blocked_ips.insert(*ip) || applied.ips.contains(ip) {
+                    added.ips.insert(*ip);
+                }
+            }
+            for domain in &iocs.domains {
+                if policy.blocked_domains.insert(domain.clone()) || applied.domains.contains(domain) {
+                    added.domains.insert(domain.clone());
+                }
+            }
+        }
+        {
+            let mut policy = self.file_policy.write()
+                .map_err(|_| anyhow!("Failed to acquire file policy write lock"))?;
+            policy.remove_rule(MISP_RULE_ID);
+            if !iocs.hashes.is_empty() {
+                policy.add_rule(Rule {
+                    id: MISP_RULE_ID.to_string(),
+                    description: "Hashes pulled from MISP".to_string(),
+                    action: RuleAction::Deny,
+                    priority: 1000,
+                    enabled: true,
+                    conditions: vec![Condition::Hash { values: iocs.hashes.iter().cloned().collect() }],
+                    unless: Vec::new(),
+                })?;
+            }
+        }
+
+        info!("MISP blocklists: {} hashes, {} domains, {} addresses", iocs.hashes.len(), iocs.domains.len(), iocs.ips.len());
+        *applied = added;
+        Ok(iocs)
+    }
+
+    // Attributes for the shareable IOCs of an incident, leaving out what came
+    // from MISP in the first place
+    fn attributes(&self, incident: &Incident, events: &[SecurityEvent]) -> Vec<Value> {
+        let applied = self.applied.lock().map(|applied| applied.clone()).unwrap_or_default();
+        let comment = format!("FluxDefense incident {}: {}", incident.id, incident.title);
+        stix::observables(incident, events).into_iter()
+            .filter(|observable| observable.shareable() && !applied.contains(observable))
+            .map(|observable| {
+                let (kind, category, value) = match observable {
+                    Observable::Sha256 { hash, name: Some(name) } => ("filename|sha256", "Payload delivery", format!("{}|{}", name, hash)),
+                    Observable::Sha256 { hash, name: None } => ("sha256", "Payload delivery", hash),
+                    Observable::Domain(domain) => ("domain", "Network activity", domain),
+                    Observable::Ip(ip) => ("ip-dst", "Network activity", ip.to_string()),
+                };
+                json!({ "type": kind, "category": category, "value": value, "to_ids": true, "comment": comment })
+            })
+            .collect()
+    }
+
+    // Adds an incident's IOCs to the push event; returns how many MISP took.
+    // Attributes MISP refuses, such as ones the event already has, are skipped.
+    pub async fn push_incident(&self, incident: &Incident, events: &[SecurityEvent]) -> Result<usize> {
+        let Some(ref event_id) = self.config.push_event else {
+            bail!("No MISP event configured to push to");
+        };
+        if incident.status == IncidentStatus::FalsePositive {
+            bail!("Incident {} is a false positive", incident.id);
+        }
+
+        let path = format!("attributes/add/{}", event_id);
+        let mut added = 0;
+        for attribute in self.attributes(incident, events) {
+            let response = self.request(&path, &attribute).await?;
+            let status = response.status();
+            if status.is_success() {
+                added += 1;
+            } else if status == reqwest::StatusCode::FORBIDDEN {
+                debug!("MISP refused {}: {}", attribute["value"], response.text().await.unwrap_or_default());
+            } else {
+                bail!("MISP attribute add returned {}: {}", status, response.text().await.unwrap_or_default());
+            }
+        }
+
+        let first_push = self.pushed.lock()
+            .map_err(|_| anyhow!("Failed to acquire MISP state lock"))?
+            .insert(incident.id.clone());
+        if let Ok(mut status) = self.status.write() {
+            status.pushed_incidents += usize::from(first_push);
+            status.pushed_attributes += added as u64;
+        }
+        info!("Pushed {} IOCs of incident {} to MISP event {}", added, incident.id, event_id);
+        Ok(added)
+    }
+
+    // Pulls on the configured interval and pushes incidents as they are resolved
+    pub fn start(self: &Arc<Self>, incidents: Arc<IncidentManager>) {
+        if !self.config.pull_events.is_empty() {
+            let connector = Arc::clone(self);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(connector.config.pull_interval_secs.max(60)));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = connector.pull().await {
+                        warn!("MISP pull failed: {:#}", e);
+                    }
+                }
+            });
+        }
+
+        if self.config.push_event.is_some() {
+            let connector = Arc::clone(self);
+            let mut updates = incidents.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    let incident = match updates.recv().await {
+                        Ok(incident) => incident,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("MISP push missed {} incident updates", skipped);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    let pushed = connector.pushed.lock().is_ok_and(|pushed| pushed.contains(&incident.id));
+                    if incident.status != IncidentStatus::Resolved || pushed {
+                        continue;
+                    }
+                    let events = incidents.events(&incident.id);
+                    if let Err(e) = connector.push_incident(&incident, &events).await {
+                        warn!("MISP push of incident {} failed: {:#}", incident.id, e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incidents::IncidentEntities;
+
+    #[test]
+    fn test_misp_pull_and_push_attributes() {
+        let hash = "a".repeat(64);
+        let response = json!({ "response": { "Attribute": [
+            { "type": "sha256", "value": hash.to_uppercase() },
+            { "type": "filename|sha256", "value": format!("drop.bin|{}", "b".repeat(64)) },
+            { "type": "hostname", "value": "C2.example.com." },
+            { "type": "domain|ip", "value": "evil.example.net|198.51.100.7" },
+            { "type": "ip-dst|port", "value": "203.0.113.9|443" },
+            { "type": "ip-src", "value": "192.0.2.0/24" },
+            { "type": "url", "value": "https://evil.example.net/x" },
+        ] } });
+        let iocs = MispIocs::from_response(&response);
+        assert_eq!(iocs.hashes, BTreeSet::from([hash.clone(), "b".repeat(64)]));
+        assert_eq!(iocs.domains, BTreeSet::from(["c2.example.com".to_string(), "evil.example.net".to_string()]));
+        assert_eq!(iocs.ips, BTreeSet::from(["198.51.100.7".parse().unwrap(), "203.0.113.9".parse().unwrap()]));
+
+        let file_policy = Arc::new(RwLock::new(FilePolicy::default()));
+        let network_policy = Arc::new(RwLock::new(NetworkPolicy::default()));
+        let kept: IpAddr = "203.0.113.9".parse().unwrap();
+        network_policy.write().unwrap().add_blocked_ip(kept);
+        let config: MispConfig = serde_json::from_value(json!({ "url": "https://misp.test", "api_key": "k" })).unwrap();
+        let connector = MispConnector::new(config, Arc::clone(&file_policy), Arc::clone(&network_policy)).unwrap();
+        connector.apply(iocs).unwrap();
+        assert!(!network_policy.read().unwrap().is_ip_allowed("198.51.100.7".parse().unwrap()));
+        assert!(!network_policy.read().unwrap().is_domain_allowed("c2.example.com"));
+        let ctx = crate::policy::RuleContext { hash: Some(&hash), ..Default::default() };
+        assert_eq!(file_policy.read().unwrap().evaluate_execution(&ctx).0, RuleAction::Deny);
+
+        // Entries MISP drops are lifted again, but not ones the policy had before
+        connector.apply(MispIocs::default()).unwrap();
+        let policy = network_policy.read().unwrap();
+        assert!(policy.blocked_domains.is_empty());
+        assert_eq!(policy.blocked_ips, HashSet::from([kept]));
+        assert!(file_policy.read().unwrap().rules.is_empty());
+        drop(policy);
+
+        // Pushed IOCs leave out private addresses and anything pulled from MISP
+        connector.apply(MispIocs { ips: BTreeSet::from(["198.51.100.7".parse().unwrap()]), ..Default::default() }).unwrap();
+        let now = Utc::now();
+        let mut entities = IncidentEntities::default();
+        entities.remote_ips = BTreeSet::from(["198.51.100.7".to_string(), "203.0.113.20".to_string(), "10.0.0.5".to_string()]);
+        let incident = Incident {
+            id: "inc-1".to_string(),
+            title: "Beaconing".to_string(),
+            status: IncidentStatus::Resolved,
+            severity: crate::incidents::IncidentSeverity::High,
+            host: None,
+            created_at: now,
+            updated_at: now,
+            first_seen: now,
+            last_seen: now,
+            entities,
+            event_count: 0,
+            correlation_rules: BTreeSet::new(),
+            timeline: Vec::new(),
+            artifacts: Vec::new(),
+        };
+        let attributes = connector.attributes(&incident, &[]);
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0]["type"], "ip-dst");
+        assert_eq!(attributes[0]["value"], "203.0.113.20");
+        assert_eq!(attributes[0]["comment"], "FluxDefense incident inc-1: Beaconing");
+    }
+}
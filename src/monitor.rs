@@ -1,16 +1,26 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tracing::{info, warn, debug};
 use uuid::Uuid;
 
-use crate::policy::{FilePolicy, NetworkPolicy};
+use crate::policy::{FilePolicy, NetworkPolicy, RuleAction, RuleContext};
 use crate::scanner::FileRecord;
+use crate::scanner::macho::{MachOAssessment, MachOAssessor};
 use crate::system_metrics::{SystemMetrics, SystemMetricsCollector};
+use crate::event_bus::{EventBus, EventBusMetrics, DEFAULT_SINK_CAPACITY};
+use crate::output::{LogRotationConfig, RotatingLogWriter};
+use crate::event_log::{self, EventLogFormat};
+use crate::redaction::Redactor;
+use crate::privacy::Pseudonymizer;
+use crate::incidents::event_severity;
+use crate::sampling::{AdaptiveSampler, SamplingConfig, SamplingMetrics};
+use crate::aggregation::{AggregationConfig, FileAccessAggregator};
+use crate::audit_log::{AuditLog, AuditRecord};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityEvent {
@@ -39,9 +49,21 @@ pub enum SecurityEventType {
         domain: Option<String>,
         protocol: NetworkProtocol,
     },
+    Authentication {
+        user: String,
+        service: String,
+        success: bool,
+        remote_host: Option<String>,
+    },
+    Syscall {
+        syscall: String,
+        success: bool,
+        exit_code: Option<i64>,
+        audit_key: Option<String>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FileAccessType {
     Read,
     Write,
@@ -94,12 +116,26 @@ pub struct EventStatistics {
 pub struct PassiveMonitor {
     events: Arc<Mutex<Vec<SecurityEvent>>>,
     statistics: Arc<Mutex<EventStatistics>>,
-    file_policy: FilePolicy,
-    network_policy: NetworkPolicy,
-    log_file_path: PathBuf,
+    file_policy: Arc<RwLock<FilePolicy>>,
+    network_policy: Arc<RwLock<NetworkPolicy>>,
+    // Delivers a copy of every logged event to sinks such as the fleet agent,
+    // each on its own bounded queue so a slow sink never stalls the monitor
+    event_bus: Arc<EventBus<Arc<SecurityEvent>>>,
+    event_log: Arc<RotatingLogWriter>,
+    event_log_format: EventLogFormat,
     passive_mode: bool,
     system_metrics_collector: SystemMetricsCollector,
     latest_system_metrics: Arc<Mutex<Option<SystemMetrics>>>,
+    macho: MachOAssessor,
+    redactor: Arc<Redactor>,
+    // Privacy mode; events are pseudonymized before they are stored or shipped
+    pseudonymizer: Option<Arc<Pseudonymizer>>,
+    // Thins low severity events of a type that floods in faster than its budget
+    sampler: Arc<AdaptiveSampler>,
+    aggregator: Arc<FileAccessAggregator>,
+    // Decisions are chained here on the logging path itself, not through the
+    // event bus, which drops the oldest events when a sink falls behind
+    audit_log: Option<Arc<AuditLog>>,
 }
 
 impl PassiveMonitor {
@@ -118,12 +154,20 @@ impl PassiveMonitor {
         Ok(Self {
             events: Arc::new(Mutex::new(Vec::new())),
             statistics: Arc::new(Mutex::new(statistics)),
-            file_policy: FilePolicy::default(),
-            network_policy: NetworkPolicy::default(),
-            log_file_path,
+            file_policy: Arc::new(RwLock::new(FilePolicy::default())),
+            network_policy: Arc::new(RwLock::new(NetworkPolicy::default())),
+            event_bus: Arc::new(EventBus::new(DEFAULT_SINK_CAPACITY)),
+            event_log: Arc::new(event_log::open_writer(log_file_path, None, EventLogFormat::default())),
+            event_log_format: EventLogFormat::default(),
             passive_mode,
             system_metrics_collector: SystemMetricsCollector::new(),
             latest_system_metrics: Arc::new(Mutex::new(None)),
+            macho: MachOAssessor::new(),
+            redactor: Arc::new(Redactor::default()),
+            pseudonymizer: None,
+            sampler: Arc::new(AdaptiveSampler::default()),
+            aggregator: Arc::new(FileAccessAggregator::default()),
+            audit_log: None,
         })
     }
 
@@ -147,21 +191,23 @@ impl PassiveMonitor {
         
         info!("Loading {} file records from whitelist", manifest.file_records.len());
         
+        let mut file_policy = self.file_policy.write()
+            .map_err(|_| anyhow!("Failed to acquire file policy write lock"))?;
         let mut loaded_count = 0;
         for (uuid, filename) in &manifest.file_records {
             let file_path = whitelist_dir.join(filename);
             if let Ok(content) = fs::read_to_string(&file_path) {
                 if let Ok(file_record) = serde_json::from_str::<FileRecord>(&content) {
                     // Add to file policy whitelist
-                    self.file_policy.add_allowed_path(file_record.path.clone());
+                    file_policy.add_allowed_path(file_record.path.clone());
                     
                     if !file_record.sha256_hash.is_empty() {
-                        self.file_policy.add_allowed_hash(file_record.sha256_hash.clone());
+                        file_policy.add_allowed_hash(file_record.sha256_hash.clone());
                     }
                     
                     if let Some(ref sig) = file_record.code_signature {
                         if !sig.authority.is_empty() {
-                            self.file_policy.add_allowed_signer(sig.authority.clone());
+                            file_policy.add_allowed_signer(sig.authority.clone());
                         }
                     }
                     
@@ -187,11 +233,29 @@ impl PassiveMonitor {
             code_signature: code_signature.clone(),
         };
 
-        let (verdict, reason) = if self.passive_mode {
+        let assessment = self.assess_macho(&target_path);
+        let (verdict, mut reason) = if self.passive_mode {
             (Verdict::Log, "Passive mode - logging only".to_string())
         } else {
-            self.evaluate_file_policy(&target_path, file_hash.as_deref(), code_signature.as_deref())
+            let ctx = RuleContext {
+                path: Some(&target_path),
+                hash: file_hash.as_deref(),
+                signer: code_signature.as_deref(),
+                parent_path: Some(&process_info.path),
+                user_id: Some(process_info.user_id),
+                signing: assessment.as_ref().map(|assessment| assessment.signing_level()),
+                ..Default::default()
+            };
+            self.file_policy.read()
+                .map(|policy| rule_verdict(policy.evaluate_execution(&ctx)))
+                .unwrap_or_else(|_| (Verdict::Deny, "File policy unavailable".to_string()))
         };
+        if let Some(ref assessment) = assessment {
+            let flags: Vec<String> = assessment.red_flags().iter().map(ToString::to_string).collect();
+            if !flags.is_empty() {
+                reason = format!("{}; Mach-O: {}", reason, flags.join(", "));
+            }
+        }
 
         let event = SecurityEvent {
             id: Uuid::new_v4().to_string(),
@@ -206,6 +270,21 @@ impl PassiveMonitor {
         verdict
     }
 
+    // Signature, entitlements, load commands and quarantine of a macOS executable
+    fn assess_macho(&self, path: &Path) -> Option<Arc<MachOAssessment>> {
+        if !cfg!(target_os = "macos") {
+            return None;
+        }
+        let check_notarization = self.file_policy.read().ok()?.requires_notarization(path);
+        match self.macho.assess(path, check_notarization) {
+            Ok(assessment) => assessment,
+            Err(e) => {
+                debug!("Failed to assess {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
     pub fn handle_file_access_event(
         &self,
         process_info: ProcessInfo,
@@ -220,12 +299,15 @@ impl PassiveMonitor {
         let (verdict, reason) = if self.passive_mode {
             (Verdict::Log, "Passive mode - logging only".to_string())
         } else {
-            // For file access, we're generally more permissive
-            if self.file_policy.is_path_allowed(&target_path) {
-                (Verdict::Allow, "Path in whitelist".to_string())
-            } else {
-                (Verdict::Deny, "Path not in whitelist".to_string())
-            }
+            let ctx = RuleContext {
+                path: Some(&target_path),
+                parent_path: Some(&process_info.path),
+                user_id: Some(process_info.user_id),
+                ..Default::default()
+            };
+            self.file_policy.read()
+                .map(|policy| rule_verdict(policy.evaluate_access(&ctx)))
+                .unwrap_or_else(|_| (Verdict::Deny, "File policy unavailable".to_string()))
         };
 
         let event = SecurityEvent {
@@ -275,11 +357,17 @@ impl PassiveMonitor {
                 }
             };
 
-            if self.network_policy.is_connection_allowed(ip_addr, remote_port, domain.as_deref()) {
-                (Verdict::Allow, "Connection allowed by policy".to_string())
-            } else {
-                (Verdict::Deny, "Connection denied by policy".to_string())
-            }
+            let ctx = RuleContext {
+                parent_path: Some(&process_info.path),
+                user_id: Some(process_info.user_id),
+                remote_ip: Some(ip_addr),
+                remote_port: Some(remote_port),
+                domain: domain.as_deref(),
+                ..Default::default()
+            };
+            self.network_policy.read()
+                .map(|policy| rule_verdict(policy.evaluate_connection(&ctx)))
+                .unwrap_or_else(|_| (Verdict::Deny, "Network policy unavailable".to_string()))
         };
 
         let event = SecurityEvent {
@@ -295,19 +383,6 @@ impl PassiveMonitor {
         verdict
     }
 
-    fn evaluate_file_policy(
-        &self,
-        path: &Path,
-        hash: Option<&str>,
-        signer: Option<&str>,
-    ) -> (Verdict, String) {
-        if self.file_policy.is_execution_allowed(path, hash, signer) {
-            (Verdict::Allow, "File execution allowed by policy".to_string())
-        } else {
-            (Verdict::Deny, "File execution denied by policy".to_string())
-        }
-    }
-
     fn log_and_return_verdict(&self, event: SecurityEvent) -> Verdict {
         let verdict = event.verdict.clone();
         self.log_event(event);
@@ -315,6 +390,37 @@ impl PassiveMonitor {
     }
 
     fn log_event(&self, event: SecurityEvent) {
+        let Some(event) = self.aggregator.offer(event) else { return };
+        if self.admit(&event) {
+            self.record_event(event, None);
+        }
+    }
+
+    // Logs file access summaries whose window has ended; `all` also closes
+    // the open ones. Returns how many were logged
+    pub fn flush_file_access_summaries(&self, all: bool) -> usize {
+        let summaries = if all { self.aggregator.take_all() } else { self.aggregator.take_due() };
+        let count = summaries.len();
+        for summary in summaries {
+            self.record_event(summary.to_security_event(), None);
+        }
+        count
+    }
+
+    fn admit(&self, event: &SecurityEvent) -> bool {
+        self.sampler.admit(event_log::event_type_name(event), event_severity(event))
+    }
+
+    // The one place events are redacted and pseudonymized, before anything
+    // stores or ships them
+    fn record_event(&self, mut event: SecurityEvent, metrics: Option<SystemMetrics>) {
+        self.redactor.redact_event(&mut event);
+        if let Some(ref pseudonymizer) = self.pseudonymizer {
+            pseudonymizer.pseudonymize_event(&mut event);
+        }
+        if let Some(ref audit_log) = self.audit_log {
+            audit_log.record(AuditRecord::decision(&event));
+        }
         // Log to structured log
         match &event.event_type {
             SecurityEventType::FileExecution { target_path, .. } => {
@@ -326,6 +432,12 @@ impl PassiveMonitor {
             SecurityEventType::NetworkConnection { remote_ip, remote_port, .. } => {
                 debug!("Network connection event: {}:{} -> {:?}", remote_ip, remote_port, event.verdict);
             }
+            SecurityEventType::Authentication { user, service, success, .. } => {
+                debug!("Authentication event: {} via {} (success: {}) -> {:?}", user, service, success, event.verdict);
+            }
+            SecurityEventType::Syscall { syscall, success, .. } => {
+                debug!("Syscall event: {} (success: {}) -> {:?}", syscall, success, event.verdict);
+            }
         }
 
         // Add to in-memory event store
@@ -344,6 +456,8 @@ impl PassiveMonitor {
                 SecurityEventType::FileExecution { .. } => "file_execution",
                 SecurityEventType::FileAccess { .. } => "file_access",
                 SecurityEventType::NetworkConnection { .. } => "network_connection",
+                SecurityEventType::Authentication { .. } => "authentication",
+                SecurityEventType::Syscall { .. } => "syscall",
             };
             *stats.events_by_type.entry(event_type_key.to_string()).or_insert(0) += 1;
             
@@ -352,32 +466,53 @@ impl PassiveMonitor {
         }
 
         // Write to log file
-        if let Err(e) = self.write_event_to_file(&event) {
+        if let Err(e) = self.write_event_to_file(&event, metrics) {
             warn!("Failed to write event to log file: {}", e);
         }
+
+        self.event_bus.publish(Arc::new(event));
     }
 
-    fn write_event_to_file(&self, event: &SecurityEvent) -> Result<()> {
-        let json = serde_json::to_string(event)?;
-        
-        // Ensure parent directory exists
-        if let Some(parent) = self.log_file_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        
-        // Append to log file
-        use std::fs::OpenOptions;
-        use std::io::Write;
-        
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_file_path)?;
-        
-        writeln!(file, "{}", json)?;
+    pub fn add_event_sink<F>(&mut self, name: &str, sink: F)
+    where
+        F: Fn(&SecurityEvent) + Send + Sync + 'static,
+    {
+        self.event_bus.subscribe(name, move |event: Arc<SecurityEvent>| sink(&event));
+    }
+
+    pub fn event_bus_metrics(&self) -> EventBusMetrics {
+        self.event_bus.metrics()
+    }
+
+    pub fn sampling_metrics(&self) -> SamplingMetrics {
+        self.sampler.metrics()
+    }
+
+    // Waits for queued events to reach their sinks; the monitor logs no further events to them
+    pub fn flush_event_sinks(&self) {
+        self.event_bus.drain();
+    }
+
+    // Handles that stay valid after the monitor is moved, so policies can be replaced while running
+    pub fn shared_policies(&self) -> (Arc<RwLock<FilePolicy>>, Arc<RwLock<NetworkPolicy>>) {
+        (Arc::clone(&self.file_policy), Arc::clone(&self.network_policy))
+    }
+
+    pub fn set_file_policy(&self, policy: FilePolicy) -> Result<()> {
+        *self.file_policy.write().map_err(|_| anyhow!("Failed to acquire file policy write lock"))? = policy;
         Ok(())
     }
 
+    pub fn set_network_policy(&self, policy: NetworkPolicy) -> Result<()> {
+        *self.network_policy.write().map_err(|_| anyhow!("Failed to acquire network policy write lock"))? = policy;
+        Ok(())
+    }
+
+    fn write_event_to_file(&self, event: &SecurityEvent, metrics: Option<SystemMetrics>) -> Result<()> {
+        let line = event_log::format_event(event, metrics, self.event_log_format)?;
+        self.event_log.write_line(&line)
+    }
+
     pub fn get_event_log(&self) -> EventLog {
         let events = self.events.lock().unwrap().clone();
         let statistics = self.statistics.lock().unwrap().clone();
@@ -400,6 +535,38 @@ impl PassiveMonitor {
         Ok(())
     }
 
+    // Rotates the event log by size and age from now on
+    pub fn set_log_rotation(&mut self, rotation: Option<LogRotationConfig>) {
+        self.event_log = Arc::new(event_log::open_writer(self.event_log.path().to_path_buf(), rotation, self.event_log_format));
+    }
+
+    // Legacy keeps writing bare events for parsers that predate the schema
+    pub fn set_redactor(&mut self, redactor: Arc<Redactor>) {
+        self.redactor = redactor;
+    }
+
+    pub fn set_pseudonymizer(&mut self, pseudonymizer: Option<Arc<Pseudonymizer>>) {
+        self.pseudonymizer = pseudonymizer;
+    }
+
+    pub fn set_audit_log(&mut self, audit_log: Option<Arc<AuditLog>>) {
+        self.audit_log = audit_log;
+    }
+
+    pub fn set_sampling(&mut self, config: SamplingConfig) {
+        self.sampler.configure(config);
+    }
+
+    pub fn set_file_access_aggregation(&mut self, config: AggregationConfig) {
+        self.aggregator.configure(config);
+    }
+
+    pub fn set_event_log_format(&mut self, format: EventLogFormat) {
+        self.event_log_format = format;
+        let rotation = self.event_log.rotation().cloned();
+        self.set_log_rotation(rotation);
+    }
+
     pub fn set_passive_mode(&mut self, passive: bool) {
         self.passive_mode = passive;
         if passive {
@@ -465,28 +632,14 @@ impl PassiveMonitor {
 
     /// Enhanced event logging that includes system metrics
     pub fn log_event_with_metrics(&mut self, event: SecurityEvent) -> Result<()> {
+        if !self.admit(&event) {
+            return Ok(());
+        }
         // Collect current system metrics
         let current_metrics = self.collect_system_metrics().ok();
         
-        // Create enhanced event with system context
-        let enhanced_event = EnhancedSecurityEvent {
-            security_event: event.clone(),
-            system_metrics: current_metrics,
-            timestamp: Utc::now(),
-        };
-
-        // Log the enhanced event
-        let log_entry = serde_json::to_string(&enhanced_event)?;
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_file_path)?;
-        
-        use std::io::Write;
-        writeln!(file, "{}", log_entry)?;
-        
-        // Also store in memory
-        self.log_event(event);
+        // Log and store the event with its system context
+        self.record_event(event, current_metrics);
         
         Ok(())
     }
@@ -500,6 +653,14 @@ pub struct EnhancedSecurityEvent {
     pub timestamp: DateTime<Utc>,
 }
 
+// Alert rules still allow the event; the reason records the rule that flagged it
+fn rule_verdict((action, reason): (RuleAction, String)) -> (Verdict, String) {
+    match action {
+        RuleAction::Allow | RuleAction::Alert => (Verdict::Allow, reason),
+        RuleAction::Deny => (Verdict::Deny, reason),
+    }
+}
+
 /// Format bytes for human-readable display
 fn format_bytes(bytes: f64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -516,4 +677,54 @@ fn format_bytes(bytes: f64) -> String {
     } else {
         format!("{:.1}{}", size, UNITS[unit_index])
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privacy::PrivacyConfig;
+
+    #[test]
+    fn test_logged_event_is_pseudonymized_once() {
+        let dir = std::env::temp_dir().join(format!("flux-monitor-privacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log_path = dir.join("events.log");
+        let privacy = Arc::new(Pseudonymizer::open(PrivacyConfig {
+            key_path: dir.join("privacy.key"),
+            vault_path: dir.join("vault.jsonl"),
+            usernames: true,
+            home_paths: true,
+            remote_ips: true,
+        }).unwrap());
+        let mut monitor = PassiveMonitor::new(log_path.clone(), true).unwrap();
+        monitor.set_pseudonymizer(Some(privacy.clone()));
+        let audit_log = Arc::new(AuditLog::open(&dir.join("audit.jsonl")).unwrap());
+        monitor.set_audit_log(Some(audit_log.clone()));
+
+        monitor.log_event_with_metrics(SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::NetworkConnection {
+                remote_ip: "203.0.113.9".to_string(),
+                remote_port: 443,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            },
+            process_info: ProcessInfo { pid: 42, path: PathBuf::from("/usr/bin/curl"), parent_pid: None, user_id: 0, executable_hash: None, command_line: None },
+            verdict: Verdict::Log,
+            policy_reason: String::new(),
+        }).unwrap();
+
+        let events = monitor.get_event_log().events;
+        let SecurityEventType::NetworkConnection { remote_ip, .. } = &events[0].event_type else { unreachable!() };
+        // The token in the log is the one the vault resolves to the address
+        let logged = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(logged.lines().filter(|line| line.contains(remote_ip.as_str())).count(), 1);
+        assert_eq!(privacy.reidentify(std::slice::from_ref(remote_ip)).unwrap()[remote_ip].as_deref(), Some("203.0.113.9"));
+        // The decision is chained as it is logged, under the same token
+        let audited = audit_log.tail(1).unwrap();
+        assert!(audited[0].record.target.starts_with(&format!("connect {}:443", remote_ip)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result, Context};
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use tracing::{info, debug};
+
+// Where distributions and geoipupdate put the GeoLite2 databases
+const DEFAULT_DB_DIRS: [&str; 3] = ["/usr/share/GeoIP", "/var/lib/GeoIP", "/usr/local/share/GeoIP"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoIpInfo {
+    // ISO 3166-1 alpha-2 code, e.g. "US"
+    pub country: Option<String>,
+    pub country_name: Option<String>,
+    pub asn: Option<u32>,
+    pub as_organization: Option<String>,
+}
+
+impl GeoIpInfo {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none() && self.asn.is_none()
+    }
+}
+
+// MaxMind-format (.mmdb) country and ASN databases. Either may be absent; a
+// City database can be used in place of a Country one.
+pub struct GeoIpDatabase {
+    country_reader: Option<Reader<Vec<u8>>>,
+    asn_reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIpDatabase {
+    pub fn open(country_db: Option<&Path>, asn_db: Option<&Path>) -> Result<Self> {
+        let open = |path: &Path| -> Result<Reader<Vec<u8>>> {
+            let reader = Reader::open_readfile(path)
+                .map_err(|e| anyhow!("{}", e))
+                .with_context(|| format!("Failed to open GeoIP database {:?}", path))?;
+            info!("Loaded GeoIP database {:?} ({})", path, reader.metadata.database_type);
+            Ok(reader)
+        };
+
+        let country_reader = country_db.map(open).transpose()?;
+        let asn_reader = asn_db.map(open).transpose()?;
+
+        if country_reader.is_none() && asn_reader.is_none() {
+            return Err(anyhow!("No GeoIP database given"));
+        }
+
+        Ok(Self { country_reader, asn_reader })
+    }
+
+    // Look for GeoLite2/GeoIP2 databases in the usual locations
+    pub fn open_default() -> Result<Self> {
+        let find = |names: &[&str]| -> Option<PathBuf> {
+            DEFAULT_DB_DIRS.iter()
+                .flat_map(|dir| names.iter().map(move |name| Path::new(dir).join(name)))
+                .find(|path| path.exists())
+        };
+
+        let country_db = find(&["GeoIP2-Country.mmdb", "GeoLite2-Country.mmdb", "GeoIP2-City.mmdb", "GeoLite2-City.mmdb"]);
+        let asn_db = find(&["GeoIP2-ASN.mmdb", "GeoLite2-ASN.mmdb"]);
+
+        Self::open(country_db.as_deref(), asn_db.as_deref())
+    }
+
+    // Returns None for addresses with no public location (private, loopback, ...)
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoIpInfo> {
+        if !is_public(ip) {
+            return None;
+        }
+
+        let mut info = GeoIpInfo::default();
+
+        if let Some(reader) = &self.country_reader {
+            match reader.lookup::<geoip2::Country>(ip) {
+                Ok(record) => {
+                    // Fall back to the registered country for anycast/satellite ranges
+                    if let Some(country) = record.country.or(record.registered_country) {
+                        info.country = country.iso_code.map(|c| c.to_string());
+                        info.country_name = country.names
+                            .and_then(|names| names.get("en").map(|n| n.to_string()));
+                    }
+                }
+                Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+                Err(e) => debug!("GeoIP country lookup failed for {}: {}", ip, e),
+            }
+        }
+
+        if let Some(reader) = &self.asn_reader {
+            match reader.lookup::<geoip2::Asn>(ip) {
+                Ok(record) => {
+                    info.asn = record.autonomous_system_number;
+                    info.as_organization = record.autonomous_system_organization.map(|o| o.to_string());
+                }
+                Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+                Err(e) => debug!("GeoIP ASN lookup failed for {}: {}", ip, e),
+            }
+        }
+
+        if info.is_empty() { None } else { Some(info) }
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || v4.is_unspecified()
+                || v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64) // CGNAT 100.64/10
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // Unique local
+                || (first & 0xffc0) == 0xfe80) // Link local
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_address_filter() {
+        assert!(is_public("8.8.8.8".parse().unwrap()));
+        assert!(is_public("2001:4860:4860::8888".parse().unwrap()));
+        assert!(!is_public("192.168.1.10".parse().unwrap()));
+        assert!(!is_public("100.72.0.1".parse().unwrap()));
+        assert!(!is_public("fe80::1".parse().unwrap()));
+        assert!(GeoIpDatabase::open(None, None).is_err());
+    }
+}
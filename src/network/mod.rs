@@ -1,3 +1,5 @@
 pub mod filter;
+pub mod geoip;
 
-pub use filter::NetworkFilter;
\ No newline at end of file
+pub use filter::NetworkFilter;
+pub use geoip::{GeoIpDatabase, GeoIpInfo};
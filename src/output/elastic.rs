@@ -0,0 +1,358 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use anyhow::{anyhow, Result, Context};
+use reqwest::StatusCode;
+use tracing::{info, warn, debug};
+
+use super::{OutputRecord, RecordQueue};
+
+// Cap for the exponential backoff between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElasticConfig {
+    // Base URL of the cluster, e.g. https://elastic.example.com:9200
+    pub url: String,
+    // strftime pattern applied to the record timestamp; `{kind}` expands to
+    // security_event, log, metrics or dns
+    #[serde(default = "default_index")]
+    pub index: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    // Takes precedence over username/password
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval_secs: u64,
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_index() -> String {
+    "fluxdefense-{kind}-%Y.%m.%d".to_string()
+}
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_flush_interval() -> u64 {
+    5
+}
+
+fn default_max_queue() -> usize {
+    50_000
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+impl ElasticConfig {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            index: default_index(),
+            username: None,
+            password: None,
+            api_key: None,
+            ca_cert_path: None,
+            batch_size: default_batch_size(),
+            flush_interval_secs: default_flush_interval(),
+            max_queue: default_max_queue(),
+            max_retries: default_max_retries(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ElasticStats {
+    pub shipped: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    pub queued: usize,
+}
+
+// Ships records to Elasticsearch or OpenSearch through the _bulk API
+#[derive(Clone)]
+pub struct ElasticShipper {
+    config: ElasticConfig,
+    client: reqwest::Client,
+    queue: Arc<RecordQueue>,
+    running: Arc<Mutex<bool>>,
+    shipped: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+}
+
+impl ElasticShipper {
+    pub fn new(config: ElasticConfig) -> Result<Self> {
+        // Catch bad strftime patterns up front instead of on every batch
+        index_name(&config.index, &OutputRecord::Log(sample_log_entry()))?;
+
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(60));
+        if let Some(ref path) = config.ca_cert_path {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read Elasticsearch CA certificate {:?}", path))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        Ok(Self {
+            queue: Arc::new(RecordQueue::new(config.max_queue)),
+            client: builder.build()?,
+            config,
+            running: Arc::new(Mutex::new(false)),
+            shipped: Arc::new(AtomicU64::new(0)),
+            failed: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn submit(&self, record: OutputRecord) {
+        self.queue.push(record);
+    }
+
+    pub fn get_stats(&self) -> ElasticStats {
+        ElasticStats {
+            shipped: self.shipped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.queue.dropped(),
+            queued: self.queue.len(),
+        }
+    }
+
+    pub fn start(&self) -> tokio::task::JoinHandle<()> {
+        *self.running.lock().unwrap() = true;
+        let shipper = self.clone();
+
+        tokio::spawn(async move {
+            info!("Elasticsearch shipper started for {}", shipper.config.url);
+            let flush_interval = Duration::from_secs(shipper.config.flush_interval_secs.max(1));
+
+            loop {
+                let running = *shipper.running.lock().unwrap();
+                let batch = shipper.queue.take(shipper.config.batch_size.max(1));
+
+                if !batch.is_empty() {
+                    shipper.ship(batch).await;
+                }
+
+                if !running {
+                    // Final flush done
+                    if shipper.queue.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+
+                // Keep draining while a full batch is waiting
+                if shipper.queue.len() < shipper.config.batch_size {
+                    tokio::time::sleep(flush_interval).await;
+                }
+            }
+
+            info!("Elasticsearch shipper stopped");
+        })
+    }
+
+    // The task flushes what is queued before exiting
+    pub fn stop(&self) {
+        *self.running.lock().unwrap() = false;
+    }
+
+    async fn ship(&self, batch: Vec<OutputRecord>) {
+        let mut pending = batch;
+        let mut attempt = 0;
+
+        while !pending.is_empty() {
+            if attempt > self.config.max_retries {
+                warn!("Giving up on {} records after {} attempts", pending.len(), attempt);
+                self.failed.fetch_add(pending.len() as u64, Ordering::Relaxed);
+                return;
+            }
+            if attempt > 0 {
+                let delay = Duration::from_secs(1 << attempt.min(6)).min(MAX_RETRY_DELAY);
+                debug!("Retrying {} records in {:?}", pending.len(), delay);
+                tokio::time::sleep(delay).await;
+            }
+            attempt += 1;
+
+            let body = match build_bulk_body(&self.config.index, &pending) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("Failed to encode bulk request: {}", e);
+                    self.failed.fetch_add(pending.len() as u64, Ordering::Relaxed);
+                    return;
+                }
+            };
+
+            let mut request = self.client
+                .post(format!("{}/_bulk", self.config.url.trim_end_matches('/')))
+                .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                .body(body);
+            if let Some(ref key) = self.config.api_key {
+                request = request.header(reqwest::header::AUTHORIZATION, format!("ApiKey {}", key));
+            } else if let Some(ref user) = self.config.username {
+                request = request.basic_auth(user, self.config.password.as_ref());
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Elasticsearch bulk request failed: {}", e);
+                    continue;
+                }
+            };
+
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                warn!("Elasticsearch returned {}, backing off", status);
+                continue;
+            }
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                warn!("Elasticsearch rejected bulk request ({}): {}", status, text);
+                self.failed.fetch_add(pending.len() as u64, Ordering::Relaxed);
+                return;
+            }
+
+            let result: Value = match response.json().await {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!("Unreadable Elasticsearch bulk response: {}", e);
+                    continue;
+                }
+            };
+
+            let outcome = split_bulk_response(&result, pending);
+            self.shipped.fetch_add(outcome.succeeded as u64, Ordering::Relaxed);
+            if !outcome.failed.is_empty() {
+                warn!("Elasticsearch rejected {} documents, first error: {}", outcome.failed.len(), outcome.failed[0]);
+                self.failed.fetch_add(outcome.failed.len() as u64, Ordering::Relaxed);
+            }
+            pending = outcome.retry;
+        }
+    }
+}
+
+pub(crate) fn index_name(pattern: &str, record: &OutputRecord) -> Result<String> {
+    let pattern = pattern.replace("{kind}", record.kind());
+    let mut name = String::new();
+    write!(name, "{}", record.timestamp().format(&pattern))
+        .map_err(|_| anyhow!("Invalid index pattern: {}", pattern))?;
+    Ok(name)
+}
+
+fn build_bulk_body(index_pattern: &str, records: &[OutputRecord]) -> Result<String> {
+    let mut body = String::new();
+    for record in records {
+        let mut action = json!({ "_index": index_name(index_pattern, record)? });
+        if let Some(id) = record.id() {
+            action["_id"] = Value::String(id.to_string());
+        }
+        body.push_str(&json!({ "index": action }).to_string());
+        body.push('\n');
+        body.push_str(&record.to_document()?.to_string());
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+struct BulkOutcome {
+    succeeded: usize,
+    // Items throttled with a per-item 429
+    retry: Vec<OutputRecord>,
+    failed: Vec<String>,
+}
+
+// Items in a bulk response are in request order
+fn split_bulk_response(response: &Value, records: Vec<OutputRecord>) -> BulkOutcome {
+    let mut outcome = BulkOutcome { succeeded: 0, retry: Vec::new(), failed: Vec::new() };
+
+    if !response["errors"].as_bool().unwrap_or(false) {
+        outcome.succeeded = records.len();
+        return outcome;
+    }
+
+    let items = response["items"].as_array().cloned().unwrap_or_default();
+    for (i, record) in records.into_iter().enumerate() {
+        let item = items.get(i)
+            .and_then(|item| item.as_object())
+            .and_then(|item| item.values().next());
+        let status = item.and_then(|item| item["status"].as_u64()).unwrap_or(500);
+
+        match status {
+            200..=299 => outcome.succeeded += 1,
+            429 => outcome.retry.push(record),
+            _ => outcome.failed.push(
+                item.map(|item| item["error"].to_string()).unwrap_or_else(|| "missing bulk item".to_string())
+            ),
+        }
+    }
+    outcome
+}
+
+fn sample_log_entry() -> crate::api::models::LogEntry {
+    crate::api::models::LogEntry {
+        id: String::new(),
+        timestamp: chrono::Utc::now(),
+        level: crate::api::models::LogLevel::Info,
+        category: crate::api::models::LogCategory::System,
+        source: String::new(),
+        message: String::new(),
+        details: None,
+        user: None,
+        pid: None,
+        tags: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_record(id: &str) -> OutputRecord {
+        let mut entry = sample_log_entry();
+        entry.id = id.to_string();
+        entry.timestamp = "2024-03-05T10:00:00Z".parse().unwrap();
+        OutputRecord::Log(entry)
+    }
+
+    #[test]
+    fn test_bulk_body_and_partial_failures() {
+        let records = vec![log_record("a"), log_record("b"), log_record("c")];
+        let body = build_bulk_body(&default_index(), &records).unwrap();
+        let lines: Vec<Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0]["index"]["_index"], "fluxdefense-log-2024.03.05");
+        assert_eq!(lines[0]["index"]["_id"], "a");
+        assert_eq!(lines[1]["kind"], "log");
+
+        let response = json!({
+            "errors": true,
+            "items": [
+                { "index": { "status": 201 } },
+                { "index": { "status": 429, "error": { "type": "es_rejected_execution_exception" } } },
+                { "index": { "status": 400, "error": { "type": "mapper_parsing_exception" } } },
+            ]
+        });
+        let outcome = split_bulk_response(&response, records);
+        assert_eq!(outcome.succeeded, 1);
+        assert_eq!(outcome.retry.len(), 1);
+        assert_eq!(outcome.retry[0].id(), Some("b"));
+        assert_eq!(outcome.failed.len(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+pub mod elastic;
+pub mod rotating_log;
+pub mod splunk;
+
+pub use elastic::{ElasticConfig, ElasticShipper};
+pub use rotating_log::{LogCompression, LogRotationConfig, RotatingLogWriter};
+pub use splunk::{SplunkConfig, SplunkHecSink, SplunkSourcetypes};
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, TimeZone, Utc};
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::anomaly::AnomalyEvent;
+use crate::api::models::{DnsQuery, LogEntry};
+use crate::monitor::SecurityEvent;
+use crate::system_metrics::SystemMetrics;
+
+// Anything an external output can receive
+#[derive(Debug, Clone)]
+pub enum OutputRecord {
+    SecurityEvent(SecurityEvent),
+    Log(LogEntry),
+    Metrics(SystemMetrics),
+    Dns(DnsQuery),
+    Anomaly(AnomalyEvent),
+}
+
+impl OutputRecord {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OutputRecord::SecurityEvent(_) => "security_event",
+            OutputRecord::Log(_) => "log",
+            OutputRecord::Metrics(_) => "metrics",
+            OutputRecord::Dns(_) => "dns",
+            OutputRecord::Anomaly(_) => "anomaly",
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            OutputRecord::SecurityEvent(event) => event.timestamp,
+            OutputRecord::Log(entry) => entry.timestamp,
+            OutputRecord::Metrics(metrics) => Utc.timestamp_opt(metrics.timestamp as i64, 0)
+                .single()
+                .unwrap_or_else(Utc::now),
+            OutputRecord::Dns(query) => query.timestamp,
+            OutputRecord::Anomaly(anomaly) => anomaly.timestamp,
+        }
+    }
+
+    // Stable id, so a retried delivery overwrites instead of duplicating
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            OutputRecord::SecurityEvent(event) => Some(&event.id),
+            OutputRecord::Log(entry) => Some(&entry.id),
+            OutputRecord::Metrics(_) => None,
+            OutputRecord::Dns(query) => Some(&query.id),
+            OutputRecord::Anomaly(anomaly) => Some(&anomaly.id),
+        }
+    }
+
+    // JSON body with the common `@timestamp` and `kind` fields added
+    pub fn to_document(&self) -> Result<Value> {
+        let mut document = match self {
+            OutputRecord::SecurityEvent(event) => serde_json::to_value(event)?,
+            OutputRecord::Log(entry) => serde_json::to_value(entry)?,
+            OutputRecord::Metrics(metrics) => serde_json::to_value(metrics)?,
+            OutputRecord::Dns(query) => serde_json::to_value(query)?,
+            OutputRecord::Anomaly(anomaly) => serde_json::to_value(anomaly)?,
+        };
+
+        if let Value::Object(ref mut map) = document {
+            map.insert("@timestamp".to_string(), Value::String(self.timestamp().to_rfc3339()));
+            map.insert("kind".to_string(), Value::String(self.kind().to_string()));
+        }
+        Ok(document)
+    }
+}
+
+// Bounded FIFO between producers and an output's delivery task. When the
+// output falls behind, the oldest records are dropped and counted.
+pub struct RecordQueue {
+    records: Mutex<VecDeque<OutputRecord>>,
+    max_len: usize,
+    dropped: AtomicU64,
+}
+
+impl RecordQueue {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::new()),
+            max_len: max_len.max(1),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn push(&self, record: OutputRecord) {
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() >= self.max_len {
+                records.pop_front();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            records.push_back(record);
+        }
+    }
+
+    pub fn take(&self, max: usize) -> Vec<OutputRecord> {
+        match self.records.lock() {
+            Ok(mut records) => {
+                let count = records.len().min(max);
+                records.drain(..count).collect()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().map(|r| r.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,384 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogCompression {
+    None,
+    #[default]
+    Gzip,
+    Zstd,
+}
+
+impl LogCompression {
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            LogCompression::None => None,
+            LogCompression::Gzip => Some("gz"),
+            LogCompression::Zstd => Some("zst"),
+        }
+    }
+}
+
+// When the event log is rotated and how many rotated files are kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRotationConfig {
+    // Rotate once the file reaches this size; 0 disables size-based rotation
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    // Rotate files older than this, e.g. 86400 for daily logs
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    #[serde(default)]
+    pub compression: LogCompression,
+    // Rotated files kept next to the live log; older ones are deleted
+    #[serde(default = "default_retention")]
+    pub retention: usize,
+}
+
+fn default_max_size_mb() -> u64 {
+    100
+}
+
+fn default_retention() -> usize {
+    10
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_max_size_mb(),
+            max_age_secs: None,
+            compression: LogCompression::default(),
+            retention: default_retention(),
+        }
+    }
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+    opened_at: SystemTime,
+}
+
+// Line-oriented append-only log shared by every writer in the process. Lines
+// are written whole under one lock, and rotation renames the file under the
+// same lock, so no line is split across files or lost. Rotated files are
+// compressed and pruned on a background thread.
+pub struct RotatingLogWriter {
+    path: PathBuf,
+    rotation: Option<LogRotationConfig>,
+    // First line of every new file, e.g. a schema header
+    header: Option<Box<dyn Fn() -> String + Send + Sync>>,
+    active: Mutex<Option<ActiveFile>>,
+    compressing: Mutex<Vec<JoinHandle<()>>>,
+    // Held while compressing and pruning, so jobs from quick successive
+    // rotations don't count or delete each other's files mid-way
+    maintenance: Arc<Mutex<()>>,
+}
+
+impl RotatingLogWriter {
+    // Without a rotation config the file simply grows, as before
+    pub fn new(path: PathBuf, rotation: Option<LogRotationConfig>) -> Self {
+        Self {
+            path,
+            rotation,
+            header: None,
+            active: Mutex::new(None),
+            compressing: Mutex::new(Vec::new()),
+            maintenance: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn with_header<F>(mut self, header: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static
+    {
+        self.header = Some(Box::new(header));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn rotation(&self) -> Option<&LogRotationConfig> {
+        self.rotation.as_ref()
+    }
+
+    pub fn write_line(&self, line: &str) -> Result<()> {
+        let mut active = self.active.lock().map_err(|_| anyhow!("Event log lock poisoned"))?;
+        if active.as_ref().is_some_and(|file| self.due(file)) {
+            *active = None;
+            self.rotate_locked()?;
+        }
+        if active.is_none() {
+            *active = Some(self.open()?);
+        }
+
+        let file = active.as_mut().expect("opened above");
+        let mut buffer = String::with_capacity(line.len() + 1);
+        buffer.push_str(line);
+        buffer.push('\n');
+        file.file.write_all(buffer.as_bytes())
+            .with_context(|| format!("Failed to write event log {:?}", self.path))?;
+        file.size += buffer.len() as u64;
+        Ok(())
+    }
+
+    // Rotates now regardless of size or age; returns the rotated file before
+    // compression, if there was anything to rotate
+    pub fn rotate(&self) -> Result<Option<PathBuf>> {
+        let mut active = self.active.lock().map_err(|_| anyhow!("Event log lock poisoned"))?;
+        *active = None;
+        self.rotate_locked()
+    }
+
+    // Blocks until rotated files handed to the background have been compressed
+    pub fn wait_for_compression(&self) {
+        let handles: Vec<JoinHandle<()>> = match self.compressing.lock() {
+            Ok(mut handles) => handles.drain(..).collect(),
+            Err(_) => return,
+        };
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+
+    fn open(&self) -> Result<ActiveFile> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open event log {:?}", self.path))?;
+        let metadata = file.metadata()?;
+        if metadata.len() == 0 {
+            let mut size = 0;
+            if let Some(header) = &self.header {
+                let line = format!("{}\n", header());
+                file.write_all(line.as_bytes())?;
+                size = line.len() as u64;
+            }
+            return Ok(ActiveFile { file, size, opened_at: SystemTime::now() });
+        }
+        // A log continued after a restart keeps aging from when it was created
+        let opened_at = metadata.created().or_else(|_| metadata.modified()).unwrap_or_else(|_| SystemTime::now());
+        Ok(ActiveFile { file, size: metadata.len(), opened_at })
+    }
+
+    fn due(&self, file: &ActiveFile) -> bool {
+        let Some(rotation) = &self.rotation else { return false };
+        if rotation.max_size_mb > 0 && file.size >= rotation.max_size_mb * 1024 * 1024 {
+            return true;
+        }
+        rotation.max_age_secs.is_some_and(|max_age| {
+            file.size > 0 && file.opened_at.elapsed().unwrap_or_default() >= Duration::from_secs(max_age)
+        })
+    }
+
+    // Caller holds the `active` lock and has closed the file
+    fn rotate_locked(&self) -> Result<Option<PathBuf>> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) if metadata.len() > 0 => {}
+            _ => return Ok(None),
+        }
+
+        let rotated = self.rotated_path();
+        fs::rename(&self.path, &rotated)
+            .with_context(|| format!("Failed to rotate event log {:?}", self.path))?;
+        info!("Rotated event log to {:?}", rotated);
+
+        let rotation = self.rotation.clone().unwrap_or_default();
+        let path = self.path.clone();
+        let source = rotated.clone();
+        let maintenance = Arc::clone(&self.maintenance);
+        let handle = std::thread::spawn(move || {
+            let _guard = maintenance.lock();
+            if let Err(e) = compress(&source, rotation.compression) {
+                warn!("Failed to compress {:?}: {:#}", source, e);
+            }
+            if let Err(e) = prune(&path, rotation.retention) {
+                warn!("Failed to prune rotated logs of {:?}: {:#}", path, e);
+            }
+        });
+        if let Ok(mut handles) = self.compressing.lock() {
+            handles.retain(|handle| !handle.is_finished());
+            handles.push(handle);
+        }
+        Ok(Some(rotated))
+    }
+
+    // events.log -> events.log.20240102T030405.678901Z; the timestamp sorts
+    // rotated files oldest first
+    fn rotated_path(&self) -> PathBuf {
+        loop {
+            let stamp = Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string();
+            let rotated = rotated_name(&self.path, &stamp);
+            // Two rotations within the same microsecond: take the next one
+            if !rotated.exists() && with_any_extension(&rotated).is_none() {
+                return rotated;
+            }
+            std::thread::sleep(Duration::from_micros(1));
+        }
+    }
+}
+
+impl Drop for RotatingLogWriter {
+    fn drop(&mut self) {
+        self.wait_for_compression();
+    }
+}
+
+fn rotated_name(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn with_any_extension(path: &Path) -> Option<PathBuf> {
+    ["gz", "zst"].iter()
+        .map(|ext| {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(".");
+            name.push(ext);
+            PathBuf::from(name)
+        })
+        .find(|candidate| candidate.exists())
+}
+
+fn compress(source: &Path, compression: LogCompression) -> Result<()> {
+    let Some(extension) = compression.extension() else { return Ok(()) };
+    let mut target = source.as_os_str().to_os_string();
+    target.push(".");
+    target.push(extension);
+    let target = PathBuf::from(target);
+
+    let mut input = BufReader::new(File::open(source)?);
+    let output = BufWriter::new(File::create(&target)?);
+    let result = match compression {
+        LogCompression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder).and_then(|_| encoder.finish()?.flush())
+        }
+        LogCompression::Zstd => zstd::stream::copy_encode(&mut input, output, 0),
+        LogCompression::None => Ok(()),
+    };
+    if let Err(e) = result {
+        // Keep the uncompressed file rather than a truncated archive
+        let _ = fs::remove_file(&target);
+        return Err(e.into());
+    }
+    fs::remove_file(source)?;
+    Ok(())
+}
+
+// Rotated files of `path`, oldest first
+pub fn rotated_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(dir) = path.parent().map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else {
+        return Ok(Vec::new());
+    };
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}.", name);
+
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_str().is_some_and(|file| {
+            file.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        }))
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn prune(path: &Path, retention: usize) -> Result<()> {
+    let files = rotated_files(path)?;
+    let excess = files.len().saturating_sub(retention);
+    for old in &files[..excess] {
+        fs::remove_file(old).with_context(|| format!("Failed to remove {:?}", old))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_rotation_compresses_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("flux-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("events.log");
+
+        for compression in [LogCompression::Gzip, LogCompression::Zstd] {
+            let rotation = LogRotationConfig { max_size_mb: 0, max_age_secs: None, compression, retention: 2 };
+            let writer = Arc::new(RotatingLogWriter::new(path.clone(), Some(rotation)));
+
+            // Concurrent writers never interleave within a line
+            let threads: Vec<_> = (0..4).map(|t| {
+                let writer = Arc::clone(&writer);
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        writer.write_line(&format!("{{\"thread\":{},\"seq\":{}}}", t, i)).unwrap();
+                    }
+                })
+            }).collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+            let first = writer.rotate().unwrap().unwrap();
+            writer.wait_for_compression();
+            let mut content = String::new();
+            let file = File::open(format!("{}.{}", first.display(), compression.extension().unwrap())).unwrap();
+            match compression {
+                LogCompression::Gzip => flate2::read::GzDecoder::new(file).read_to_string(&mut content).unwrap(),
+                _ => zstd::stream::read::Decoder::new(file).unwrap().read_to_string(&mut content).unwrap(),
+            };
+            assert_eq!(content.lines().count(), 200);
+            assert!(content.lines().all(|line| serde_json::from_str::<serde_json::Value>(line).is_ok()));
+
+            // Only the newest `retention` rotated files are kept
+            for _ in 0..3 {
+                writer.write_line("more").unwrap();
+                writer.rotate().unwrap();
+            }
+            writer.write_line("live").unwrap();
+            writer.wait_for_compression();
+            let rotated = rotated_files(&path).unwrap();
+            assert_eq!(rotated.len(), 2);
+            assert!(rotated.iter().all(|file| file.extension().and_then(|e| e.to_str()) == compression.extension()));
+            assert!(!rotated.iter().any(|file| file.to_string_lossy().starts_with(&*first.to_string_lossy())));
+            assert_eq!(fs::read_to_string(&path).unwrap(), "live\n");
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        // Size-based rotation happens on the write that finds the file full
+        let rotation = LogRotationConfig { max_size_mb: 1, max_age_secs: None, compression: LogCompression::None, retention: 5 };
+        let writer = RotatingLogWriter::new(path.clone(), Some(rotation));
+        let line = "x".repeat(64 * 1024 - 1);
+        for _ in 0..17 {
+            writer.write_line(&line).unwrap();
+        }
+        writer.wait_for_compression();
+        let rotated = rotated_files(&path).unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(fs::metadata(&rotated[0]).unwrap().len(), 1024 * 1024);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 64 * 1024);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use anyhow::{anyhow, Result, Context};
+use reqwest::StatusCode;
+use tracing::{info, warn, debug};
+
+use crate::system_metrics::SystemMetricsCollector;
+use super::{OutputRecord, RecordQueue};
+
+const EVENT_ENDPOINT: &str = "/services/collector/event";
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// Sourcetype per record kind, so each lands with its own field extractions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplunkSourcetypes {
+    #[serde(default = "default_event_sourcetype")]
+    pub security_event: String,
+    #[serde(default = "default_metrics_sourcetype")]
+    pub metrics: String,
+    #[serde(default = "default_dns_sourcetype")]
+    pub dns: String,
+    #[serde(default = "default_log_sourcetype")]
+    pub log: String,
+    #[serde(default = "default_anomaly_sourcetype")]
+    pub anomaly: String,
+}
+
+impl Default for SplunkSourcetypes {
+    fn default() -> Self {
+        Self {
+            security_event: default_event_sourcetype(),
+            metrics: default_metrics_sourcetype(),
+            dns: default_dns_sourcetype(),
+            log: default_log_sourcetype(),
+            anomaly: default_anomaly_sourcetype(),
+        }
+    }
+}
+
+fn default_event_sourcetype() -> String {
+    "fluxdefense:security_event".to_string()
+}
+
+fn default_metrics_sourcetype() -> String {
+    "fluxdefense:metrics".to_string()
+}
+
+fn default_dns_sourcetype() -> String {
+    "fluxdefense:dns".to_string()
+}
+
+fn default_log_sourcetype() -> String {
+    "fluxdefense:log".to_string()
+}
+
+fn default_anomaly_sourcetype() -> String {
+    "fluxdefense:anomaly".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplunkConfig {
+    // HEC base URL, e.g. https://splunk.example.com:8088
+    pub url: String,
+    pub token: String,
+    // Falls back to the token's default index
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default = "default_source")]
+    pub source: String,
+    #[serde(default)]
+    pub sourcetypes: SplunkSourcetypes,
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+    // Also send system metrics at this interval
+    #[serde(default)]
+    pub metrics_interval_secs: Option<u64>,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval_secs: u64,
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_source() -> String {
+    "fluxdefense".to_string()
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval() -> u64 {
+    5
+}
+
+fn default_max_queue() -> usize {
+    50_000
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+impl SplunkConfig {
+    pub fn new(url: String, token: String) -> Self {
+        Self {
+            url,
+            token,
+            index: None,
+            source: default_source(),
+            sourcetypes: SplunkSourcetypes::default(),
+            ca_cert_path: None,
+            metrics_interval_secs: None,
+            batch_size: default_batch_size(),
+            flush_interval_secs: default_flush_interval(),
+            max_queue: default_max_queue(),
+            max_retries: default_max_retries(),
+        }
+    }
+
+    fn sourcetype(&self, record: &OutputRecord) -> &str {
+        match record {
+            OutputRecord::SecurityEvent(_) => &self.sourcetypes.security_event,
+            OutputRecord::Metrics(_) => &self.sourcetypes.metrics,
+            OutputRecord::Dns(_) => &self.sourcetypes.dns,
+            OutputRecord::Log(_) => &self.sourcetypes.log,
+            OutputRecord::Anomaly(_) => &self.sourcetypes.anomaly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SplunkStats {
+    pub sent: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    pub queued: usize,
+}
+
+// Sends records to a Splunk HTTP Event Collector
+#[derive(Clone)]
+pub struct SplunkHecSink {
+    config: SplunkConfig,
+    client: reqwest::Client,
+    host: String,
+    queue: Arc<RecordQueue>,
+    running: Arc<Mutex<bool>>,
+    sent: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+}
+
+impl SplunkHecSink {
+    pub fn new(config: SplunkConfig) -> Result<Self> {
+        if config.token.is_empty() {
+            return Err(anyhow!("Splunk HEC token is empty"));
+        }
+
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(60));
+        if let Some(ref path) = config.ca_cert_path {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read Splunk CA certificate {:?}", path))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        Ok(Self {
+            queue: Arc::new(RecordQueue::new(config.max_queue)),
+            client: builder.build()?,
+            host: sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            config,
+            running: Arc::new(Mutex::new(false)),
+            sent: Arc::new(AtomicU64::new(0)),
+            failed: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn submit(&self, record: OutputRecord) {
+        self.queue.push(record);
+    }
+
+    pub fn get_stats(&self) -> SplunkStats {
+        SplunkStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.queue.dropped(),
+            queued: self.queue.len(),
+        }
+    }
+
+    pub fn start(&self) -> tokio::task::JoinHandle<()> {
+        *self.running.lock().unwrap() = true;
+
+        if let Some(secs) = self.config.metrics_interval_secs {
+            let sink = self.clone();
+            tokio::spawn(async move {
+                let mut collector = SystemMetricsCollector::new();
+                while *sink.running.lock().unwrap() {
+                    match collector.collect_metrics() {
+                        Ok(metrics) => sink.submit(OutputRecord::Metrics(metrics)),
+                        Err(e) => debug!("Failed to collect metrics for Splunk: {}", e),
+                    }
+                    tokio::time::sleep(Duration::from_secs(secs.max(1))).await;
+                }
+            });
+        }
+
+        let sink = self.clone();
+        tokio::spawn(async move {
+            info!("Splunk HEC output started for {}", sink.config.url);
+            let flush_interval = Duration::from_secs(sink.config.flush_interval_secs.max(1));
+
+            loop {
+                let running = *sink.running.lock().unwrap();
+                let batch = sink.queue.take(sink.config.batch_size.max(1));
+
+                if !batch.is_empty() {
+                    sink.send(batch).await;
+                }
+
+                if !running {
+                    if sink.queue.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+
+                if sink.queue.len() < sink.config.batch_size {
+                    tokio::time::sleep(flush_interval).await;
+                }
+            }
+
+            info!("Splunk HEC output stopped");
+        })
+    }
+
+    pub fn stop(&self) {
+        *self.running.lock().unwrap() = false;
+    }
+
+    async fn send(&self, batch: Vec<OutputRecord>) {
+        let count = batch.len() as u64;
+        let body = match self.build_payload(&batch) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode HEC payload: {}", e);
+                self.failed.fetch_add(count, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let url = format!("{}{}", self.config.url.trim_end_matches('/'), EVENT_ENDPOINT);
+        for attempt in 0..=self.config.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_secs(1 << attempt.min(6)).min(MAX_RETRY_DELAY)).await;
+            }
+
+            let response = self.client.post(&url)
+                .header(reqwest::header::AUTHORIZATION, format!("Splunk {}", self.config.token))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Splunk HEC request failed: {}", e);
+                    continue;
+                }
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                self.sent.fetch_add(count, Ordering::Relaxed);
+                return;
+            }
+
+            let text = response.text().await.unwrap_or_default();
+            if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                // 503 also covers HEC's "server is busy" back-pressure
+                warn!("Splunk HEC returned {}, backing off: {}", status, text);
+                continue;
+            }
+
+            warn!("Splunk HEC rejected {} events ({}): {}", count, status, text);
+            self.failed.fetch_add(count, Ordering::Relaxed);
+            return;
+        }
+
+        warn!("Giving up on {} events after {} attempts", count, self.config.max_retries + 1);
+        self.failed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    // HEC accepts a stream of concatenated event objects in one request
+    fn build_payload(&self, records: &[OutputRecord]) -> Result<String> {
+        let mut payload = String::new();
+        for record in records {
+            let timestamp = record.timestamp();
+            let mut envelope = json!({
+                "time": timestamp.timestamp() as f64 + f64::from(timestamp.timestamp_subsec_millis()) / 1000.0,
+                "host": self.host,
+                "source": self.config.source,
+                "sourcetype": self.config.sourcetype(record),
+                "event": record.to_document()?,
+            });
+            if let Some(ref index) = self.config.index {
+                envelope["index"] = Value::String(index.clone());
+            }
+            payload.push_str(&envelope.to_string());
+            payload.push('\n');
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::models::DnsQuery;
+
+    #[test]
+    fn test_payload_routes_sourcetypes() {
+        let mut config = SplunkConfig::new("https://splunk:8088".to_string(), "token".to_string());
+        config.index = Some("security".to_string());
+        let sink = SplunkHecSink::new(config).unwrap();
+
+        let dns = OutputRecord::Dns(DnsQuery {
+            id: "q1".to_string(),
+            timestamp: "2024-03-05T10:00:00.250Z".parse().unwrap(),
+            domain: "example.com".to_string(),
+            query_type: "A".to_string(),
+            source_ip: "10.0.0.5".to_string(),
+            source_port: Some(40512),
+            status: "allowed".to_string(),
+            response: None,
+            answers: Vec::new(),
+            reason: None,
+            process: None,
+            pid: None,
+        });
+        let payload = sink.build_payload(&[dns]).unwrap();
+        let envelope: Value = serde_json::from_str(payload.trim()).unwrap();
+
+        assert_eq!(envelope["sourcetype"], "fluxdefense:dns");
+        assert_eq!(envelope["index"], "security");
+        assert_eq!(envelope["time"], 1709632800.25);
+        assert_eq!(envelope["event"]["domain"], "example.com");
+
+        assert!(SplunkHecSink::new(SplunkConfig::new("https://splunk:8088".to_string(), String::new())).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::info;
+
+use super::replay::CandidatePolicy;
+use super::rules::{Condition, Rule, RuleAction};
+use super::{FilePolicy, NetworkPolicy};
+
+// Kinds accepted by `PolicyEntry::parse`, for command line help
+pub const ENTRY_KINDS: &[&str] = &[
+    "hash", "path", "glob", "signer", "trusted-dir",
+    "allow-ip", "allow-domain", "allow-port", "deny-ip", "deny-domain", "deny-port",
+];
+
+// A single allow/block entry added to or removed from the policy files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyEntry {
+    Hash(String),
+    Path(PathBuf),
+    // A rule with one path condition; its id is derived from the pattern
+    Glob { pattern: String, action: RuleAction },
+    Signer(String),
+    TrustedDirectory(PathBuf),
+    AllowedIp(IpAddr),
+    AllowedDomain(String),
+    AllowedPort(u16),
+    BlockedIp(IpAddr),
+    BlockedDomain(String),
+    BlockedPort(u16),
+}
+
+impl PolicyEntry {
+    // `action` only applies to globs
+    pub fn parse(kind: &str, value: &str, action: RuleAction) -> Result<Self> {
+        let value = value.trim();
+        Ok(match kind {
+            "hash" => PolicyEntry::Hash(parse_hash(value)?),
+            "path" => PolicyEntry::Path(parse_absolute(value)?),
+            "glob" => {
+                if !value.starts_with('/') {
+                    bail!("Path glob must be absolute: {}", value);
+                }
+                PolicyEntry::Glob { pattern: value.to_string(), action }
+            }
+            "signer" if !value.is_empty() => PolicyEntry::Signer(value.to_string()),
+            "trusted-dir" => PolicyEntry::TrustedDirectory(parse_absolute(value)?),
+            "allow-ip" => PolicyEntry::AllowedIp(parse_ip(value)?),
+            "allow-domain" => PolicyEntry::AllowedDomain(parse_domain(value)?),
+            "allow-port" => PolicyEntry::AllowedPort(parse_port(value)?),
+            "deny-ip" => PolicyEntry::BlockedIp(parse_ip(value)?),
+            "deny-domain" => PolicyEntry::BlockedDomain(parse_domain(value)?),
+            "deny-port" => PolicyEntry::BlockedPort(parse_port(value)?),
+            _ => bail!("Invalid {} entry: '{}'", kind, value),
+        })
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(self,
+            PolicyEntry::AllowedIp(_) | PolicyEntry::AllowedDomain(_) | PolicyEntry::AllowedPort(_)
+            | PolicyEntry::BlockedIp(_) | PolicyEntry::BlockedDomain(_) | PolicyEntry::BlockedPort(_))
+    }
+
+    // Returns false when the entry was already there
+    pub fn add_to(&self, policy: &mut CandidatePolicy) -> Result<bool> {
+        if self.is_network() {
+            let network = policy.network_policy.get_or_insert_with(NetworkPolicy::default);
+            return Ok(match self {
+                PolicyEntry::AllowedIp(ip) => network.allowed_ips.insert(*ip),
+                PolicyEntry::AllowedDomain(domain) => network.allowed_domains.insert(domain.clone()),
+                PolicyEntry::AllowedPort(port) => network.allowed_ports.insert(*port),
+                PolicyEntry::BlockedIp(ip) => network.blocked_ips.insert(*ip),
+                PolicyEntry::BlockedDomain(domain) => network.blocked_domains.insert(domain.clone()),
+                PolicyEntry::BlockedPort(port) => network.blocked_ports.insert(*port),
+                _ => unreachable!(),
+            });
+        }
+
+        let file = policy.file_policy.get_or_insert_with(FilePolicy::default);
+        Ok(match self {
+            PolicyEntry::Hash(hash) => file.allowed_hashes.insert(hash.clone()),
+            PolicyEntry::Path(path) => file.allowed_paths.insert(path.clone()),
+            PolicyEntry::Signer(signer) => file.allowed_signers.insert(signer.clone()),
+            PolicyEntry::TrustedDirectory(dir) => file.trusted_directories.insert(dir.clone()),
+            PolicyEntry::Glob { pattern, action } => {
+                let id = glob_rule_id(pattern);
+                if file.rules.iter().any(|rule| rule.id == id) {
+                    return Ok(false);
+                }
+                file.add_rule(Rule {
+                    id,
+                    description: format!("Path {}", pattern),
+                    action: *action,
+                    priority: 0,
+                    enabled: true,
+                    conditions: vec![Condition::Path { pattern: pattern.clone() }],
+                    unless: Vec::new(),
+                })?;
+                true
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    // Returns false when the entry was not there
+    pub fn remove_from(&self, policy: &mut CandidatePolicy) -> bool {
+        if self.is_network() {
+            let Some(network) = policy.network_policy.as_mut() else { return false };
+            return match self {
+                PolicyEntry::AllowedIp(ip) => network.allowed_ips.remove(ip),
+                PolicyEntry::AllowedDomain(domain) => network.allowed_domains.remove(domain),
+                PolicyEntry::AllowedPort(port) => network.allowed_ports.remove(port),
+                PolicyEntry::BlockedIp(ip) => network.blocked_ips.remove(ip),
+                PolicyEntry::BlockedDomain(domain) => network.blocked_domains.remove(domain),
+                PolicyEntry::BlockedPort(port) => network.blocked_ports.remove(port),
+                _ => unreachable!(),
+            };
+        }
+
+        let Some(file) = policy.file_policy.as_mut() else { return false };
+        match self {
+            PolicyEntry::Hash(hash) => file.allowed_hashes.remove(hash),
+            PolicyEntry::Path(path) => file.allowed_paths.remove(path),
+            PolicyEntry::Signer(signer) => file.allowed_signers.remove(signer),
+            PolicyEntry::TrustedDirectory(dir) => file.trusted_directories.remove(dir),
+            PolicyEntry::Glob { pattern, .. } => file.remove_rule(&glob_rule_id(pattern)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn glob_rule_id(pattern: &str) -> String {
+    format!("path:{}", pattern)
+}
+
+fn parse_hash(value: &str) -> Result<String> {
+    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Not a SHA-256 hash: {}", value);
+    }
+    Ok(value.to_lowercase())
+}
+
+fn parse_absolute(value: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(value);
+    if !path.is_absolute() {
+        bail!("Path must be absolute: {}", value);
+    }
+    Ok(path)
+}
+
+fn parse_ip(value: &str) -> Result<IpAddr> {
+    value.parse().map_err(|_| anyhow!("Invalid IP address: {}", value))
+}
+
+fn parse_port(value: &str) -> Result<u16> {
+    value.parse().map_err(|_| anyhow!("Invalid port: {}", value))
+}
+
+// Domains match by suffix, so `example.com` also covers its subdomains
+fn parse_domain(value: &str) -> Result<String> {
+    let domain = value.trim_end_matches('.').to_lowercase();
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        bail!("Invalid domain: {}", value);
+    }
+    Ok(domain)
+}
+
+// Checks what serde does not: rules compile, hashes are SHA-256, and paths
+// and domains are well formed
+pub fn validate_policy(policy: &mut CandidatePolicy) -> Result<()> {
+    policy.validate()?;
+    if let Some(ref file) = policy.file_policy {
+        for hash in &file.allowed_hashes {
+            parse_hash(hash).context("Invalid file policy")?;
+        }
+        for path in file.allowed_paths.iter().chain(&file.trusted_directories).chain(&file.system_paths) {
+            if !path.is_absolute() {
+                bail!("Invalid file policy: path must be absolute: {}", path.display());
+            }
+        }
+    }
+    if let Some(ref network) = policy.network_policy {
+        for domain in network.allowed_domains.iter().chain(&network.blocked_domains) {
+            parse_domain(domain).context("Invalid network policy")?;
+        }
+    }
+    Ok(())
+}
+
+// The file and network policy JSON files edited by `flux-monitor policy`
+#[derive(Debug, Clone)]
+pub struct PolicyFiles {
+    pub file_policy: PathBuf,
+    pub network_policy: PathBuf,
+}
+
+impl PolicyFiles {
+    // A missing file loads as the default policy
+    pub fn load(&self) -> Result<CandidatePolicy> {
+        let mut policy = CandidatePolicy {
+            file_policy: Some(load_or_default(&self.file_policy, FilePolicy::load_from_file)?),
+            network_policy: Some(load_or_default(&self.network_policy, NetworkPolicy::load_from_file)?),
+        };
+        validate_policy(&mut policy)?;
+        Ok(policy)
+    }
+
+    // Validates before writing, and replaces each file in one rename so a
+    // daemon reading it never sees a partial policy
+    pub fn save(&self, policy: &mut CandidatePolicy) -> Result<()> {
+        validate_policy(policy)?;
+        if let Some(ref file) = policy.file_policy {
+            write_atomic(&self.file_policy, &serde_json::to_string_pretty(file)?)?;
+        }
+        if let Some(ref network) = policy.network_policy {
+            write_atomic(&self.network_policy, &serde_json::to_string_pretty(network)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn load_or_default<T: Default>(path: &Path, load: fn(&Path) -> Result<T>) -> Result<T> {
+    if path.exists() {
+        load(path).with_context(|| format!("Failed to load {}", path.display()))
+    } else {
+        Ok(T::default())
+    }
+}
+
+pub(crate) fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    info!("Policy saved to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_edit_and_round_trip_policy_files() {
+        let dir = std::env::temp_dir().join(format!("flux-policy-edit-{}", std::process::id()));
+        let files = PolicyFiles {
+            file_policy: dir.join("file_policy.json"),
+            network_policy: dir.join("network_policy.json"),
+        };
+        let mut policy = files.load().unwrap();
+
+        let hash = "AB".repeat(32);
+        let entries = [
+            PolicyEntry::parse("hash", &hash, RuleAction::Deny).unwrap(),
+            PolicyEntry::parse("glob", "/tmp/**", RuleAction::Deny).unwrap(),
+            PolicyEntry::parse("deny-ip", "203.0.113.7", RuleAction::Deny).unwrap(),
+            PolicyEntry::parse("deny-domain", "Evil.Example.", RuleAction::Deny).unwrap(),
+        ];
+        for entry in &entries {
+            assert!(entry.add_to(&mut policy).unwrap());
+            assert!(!entry.add_to(&mut policy).unwrap());
+        }
+        assert!(PolicyEntry::parse("hash", "abc", RuleAction::Deny).is_err());
+        assert!(PolicyEntry::parse("path", "relative/bin", RuleAction::Deny).is_err());
+        assert!(PolicyEntry::parse("deny-domain", "bad domain", RuleAction::Deny).is_err());
+        files.save(&mut policy).unwrap();
+
+        let mut loaded = files.load().unwrap();
+        let file = loaded.file_policy.as_ref().unwrap();
+        assert!(file.is_hash_allowed(&hash.to_lowercase()));
+        assert!(!file.is_execution_allowed(Path::new("/tmp/x"), None, None));
+        let network = loaded.network_policy.as_ref().unwrap();
+        assert!(network.blocked_domains.contains("evil.example"));
+        assert!(!network.is_connection_allowed("203.0.113.7".parse().unwrap(), 443, None));
+
+        assert!(entries[1].remove_from(&mut loaded));
+        assert!(!entries[1].remove_from(&mut loaded));
+        assert!(loaded.file_policy.as_ref().unwrap().is_execution_allowed(Path::new("/usr/bin/ls"), None, None));
+
+        std::fs::write(&files.file_policy, "{\"allowed_paths\": 5}").unwrap();
+        assert!(files.load().is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::collections::HashSet;
-use anyhow::Result;
+use std::sync::{Arc, OnceLock};
+use anyhow::{anyhow, Result};
 use tracing::{info, warn, debug};
 
+use super::rules::{Rule, RuleAction, RuleContext, RuleSet, SigningLevel};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilePolicy {
     pub allowed_paths: HashSet<PathBuf>,
@@ -11,6 +14,17 @@ pub struct FilePolicy {
     pub allowed_signers: HashSet<String>,
     pub trusted_directories: HashSet<PathBuf>,
     pub system_paths: HashSet<PathBuf>,
+    // Evaluated before the allow sets above
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    // macOS executables outside the exempt directories must be signed at least
+    // this well; checked after the rules, before the allow sets
+    #[serde(default)]
+    pub required_signing: Option<SigningLevel>,
+    #[serde(default = "default_signing_exempt_directories")]
+    pub signing_exempt_directories: Vec<PathBuf>,
+    #[serde(skip)]
+    compiled_rules: OnceLock<Arc<RuleSet>>,
 }
 
 impl Default for FilePolicy {
@@ -21,6 +35,10 @@ impl Default for FilePolicy {
             allowed_signers: HashSet::new(),
             trusted_directories: HashSet::new(),
             system_paths: HashSet::new(),
+            rules: Vec::new(),
+            required_signing: None,
+            signing_exempt_directories: default_signing_exempt_directories(),
+            compiled_rules: OnceLock::new(),
         };
         
         // Add default macOS system paths
@@ -119,6 +137,62 @@ impl FilePolicy {
         path: &Path,
         hash: Option<&str>,
         signer: Option<&str>,
+    ) -> bool {
+        let ctx = RuleContext { path: Some(path), hash, signer, ..Default::default() };
+        self.evaluate_execution(&ctx).0 != RuleAction::Deny
+    }
+
+    // Rules first, then the allow sets; returns the action and a reason for logging
+    pub fn evaluate_execution(&self, ctx: &RuleContext) -> (RuleAction, String) {
+        if let Some(decision) = self.rule_set().evaluate(ctx) {
+            debug!("File execution matched rule {}: {:?}", decision.rule_id, decision.action);
+            return (decision.action, format!("File execution {} by rule {}", action_verb(decision.action), decision.rule_id));
+        }
+
+        if let Some(reason) = self.signing_violation(ctx) {
+            return (RuleAction::Deny, reason);
+        }
+
+        match ctx.path {
+            Some(path) if self.is_execution_allowed_by_lists(path, ctx.hash, ctx.signer) => {
+                (RuleAction::Allow, "File execution allowed by policy".to_string())
+            }
+            _ => (RuleAction::Deny, "File execution denied by policy".to_string()),
+        }
+    }
+
+    pub fn evaluate_access(&self, ctx: &RuleContext) -> (RuleAction, String) {
+        if let Some(decision) = self.rule_set().evaluate(ctx) {
+            return (decision.action, format!("File access {} by rule {}", action_verb(decision.action), decision.rule_id));
+        }
+
+        match ctx.path {
+            Some(path) if self.is_path_allowed(path) => (RuleAction::Allow, "Path in whitelist".to_string()),
+            _ => (RuleAction::Deny, "Path not in whitelist".to_string()),
+        }
+    }
+
+    // Notarization needs a codesign run, so callers only check it when it can matter
+    pub fn requires_notarization(&self, path: &Path) -> bool {
+        self.required_signing == Some(SigningLevel::Notarized)
+            && !self.signing_exempt_directories.iter().any(|dir| path.starts_with(dir))
+    }
+
+    // Only applies when the caller assessed the signature, so other platforms are unaffected
+    fn signing_violation(&self, ctx: &RuleContext) -> Option<String> {
+        let required = self.required_signing?;
+        let (path, signing) = (ctx.path?, ctx.signing?);
+        if signing >= required || self.signing_exempt_directories.iter().any(|dir| path.starts_with(dir)) {
+            return None;
+        }
+        Some(format!("File execution denied: executable is {} but policy requires {}", signing, required))
+    }
+
+    fn is_execution_allowed_by_lists(
+        &self,
+        path: &Path,
+        hash: Option<&str>,
+        signer: Option<&str>,
     ) -> bool {
         // If path is allowed, allow execution
         if self.is_path_allowed(path) {
@@ -162,6 +236,43 @@ impl FilePolicy {
         self.trusted_directories.insert(directory);
     }
     
+    pub fn rule_set(&self) -> Arc<RuleSet> {
+        self.compiled_rules
+            .get_or_init(|| {
+                Arc::new(RuleSet::compile(&self.rules).unwrap_or_else(|e| {
+                    warn!("Ignoring invalid file policy rules: {}", e);
+                    RuleSet::default()
+                }))
+            })
+            .clone()
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> Result<()> {
+        if self.rules.iter().any(|r| r.id == rule.id) {
+            return Err(anyhow!("Rule {} already exists", rule.id));
+        }
+
+        let mut rules = self.rules.clone();
+        rules.push(rule);
+        self.set_rules(rules)
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> Result<()> {
+        let rule_set = RuleSet::compile(&rules)?;
+        info!("File policy now has {} active rules", rule_set.len());
+
+        self.rules = rules;
+        self.compiled_rules = OnceLock::from(Arc::new(rule_set));
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, id: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.id != id);
+        self.compiled_rules = OnceLock::new();
+        self.rules.len() != before
+    }
+
     pub fn remove_allowed_path(&mut self, path: &Path) {
         self.allowed_paths.remove(path);
     }
@@ -183,8 +294,28 @@ impl FilePolicy {
     
     pub fn load_from_file(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let policy: Self = serde_json::from_str(&content)?;
+        let policy = Self::from_json(&content)?;
         info!("File policy loaded from: {:?}", path);
         Ok(policy)
     }
-}
\ No newline at end of file
+
+    // For content read elsewhere, e.g. after its signature was checked
+    pub fn from_json(content: &str) -> Result<Self> {
+        let mut policy: Self = serde_json::from_str(content)?;
+        let rules = std::mem::take(&mut policy.rules);
+        policy.set_rules(rules)?;
+        Ok(policy)
+    }
+}
+
+fn default_signing_exempt_directories() -> Vec<PathBuf> {
+    vec![PathBuf::from("/Applications")]
+}
+
+pub(crate) fn action_verb(action: RuleAction) -> &'static str {
+    match action {
+        RuleAction::Allow => "allowed",
+        RuleAction::Deny => "denied",
+        RuleAction::Alert => "alerted",
+    }
+}
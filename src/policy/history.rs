@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use super::replay::CandidatePolicy;
+use super::signing::SignedPolicy;
+
+// One entry of the policy history: the complete policy after a change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyVersion {
+    pub version: u64,
+    pub author: String,
+    // api, reload, rollback or startup
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+    // Set on rollbacks: the version that was restored
+    #[serde(default)]
+    pub restored: Option<u64>,
+    // Against the previous version
+    pub changes: Vec<FieldChange>,
+    pub policy: CandidatePolicy,
+    // The signed files behind `policy`, so a rollback can restore them
+    // while signing is enabled
+    #[serde(default)]
+    pub signed: SignedPolicy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyVersionSummary {
+    pub version: u64,
+    pub author: String,
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+    pub restored: Option<u64>,
+    pub changes: usize,
+}
+
+// A field of the file or network policy that differs between two versions.
+// Sets and rule lists report what was added and removed, other fields their
+// old and new value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub policy: String,
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<Value>,
+}
+
+pub fn diff_policies(old: &CandidatePolicy, new: &CandidatePolicy) -> Result<Vec<FieldChange>> {
+    let mut changes = diff_half("file", serde_json::to_value(&old.file_policy)?, serde_json::to_value(&new.file_policy)?);
+    changes.extend(diff_half("network", serde_json::to_value(&old.network_policy)?, serde_json::to_value(&new.network_policy)?));
+    Ok(changes)
+}
+
+fn diff_half(policy: &str, old: Value, new: Value) -> Vec<FieldChange> {
+    let (old, new) = (as_fields(old), as_fields(new));
+    let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    let mut changes = Vec::new();
+    for field in fields {
+        let (before, after) = (old.get(field), new.get(field));
+        if before == after {
+            continue;
+        }
+        let mut change = FieldChange {
+            policy: policy.to_string(),
+            field: field.clone(),
+            added: Vec::new(),
+            removed: Vec::new(),
+            from: None,
+            to: None,
+        };
+        match (as_list(before), as_list(after)) {
+            // Sets serialize in no particular order, so lists are compared by
+            // their elements; a changed rule shows as removed and added
+            (Some(before), Some(after)) => {
+                change.added = after.iter().filter(|value| !before.contains(value)).cloned().collect();
+                change.removed = before.iter().filter(|value| !after.contains(value)).cloned().collect();
+                if change.added.is_empty() && change.removed.is_empty() {
+                    continue;
+                }
+            }
+            _ => {
+                change.from = before.cloned();
+                change.to = after.cloned();
+            }
+        }
+        changes.push(change);
+    }
+    changes
+}
+
+// A missing field compares as an empty list
+fn as_list(value: Option<&Value>) -> Option<&[Value]> {
+    match value {
+        Some(Value::Array(values)) => Some(values),
+        None => Some(&[]),
+        _ => None,
+    }
+}
+
+fn as_fields(value: Value) -> BTreeMap<String, Value> {
+    match value {
+        Value::Object(fields) => fields.into_iter().collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+// Every change to the enforced policies, appended as JSON lines so the
+// history survives restarts
+pub struct PolicyHistory {
+    path: PathBuf,
+    versions: Mutex<Vec<PolicyVersion>>,
+}
+
+impl PolicyHistory {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut versions = Vec::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let mut version: PolicyVersion = serde_json::from_str(&line)
+                        .with_context(|| format!("Corrupt entry in policy history {:?}", path))?;
+                    version.policy.validate()?;
+                    versions.push(version);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read policy history {:?}", path)),
+        }
+        info!("Policy history has {} versions", versions.len());
+        Ok(Self { path: path.to_path_buf(), versions: Mutex::new(versions) })
+    }
+
+    // Runs `apply` under the history lock and records the policy it returns,
+    // so concurrent changes cannot interleave between applying one and
+    // recording it. Nothing is recorded when the policy did not change
+    pub fn record_with<F>(&self, author: &str, source: &str, restored: Option<u64>, apply: F) -> Result<Option<PolicyVersion>>
+    where
+        F: FnOnce(Option<&PolicyVersion>) -> Result<(CandidatePolicy, SignedPolicy)>,
+    {
+        let mut versions = self.versions.lock().map_err(|_| anyhow!("Failed to acquire policy history lock"))?;
+        let latest = versions.last();
+        let (policy, signed) = apply(latest)?;
+        let changes = match latest {
+            Some(latest) => diff_policies(&latest.policy, &policy)?,
+            None => diff_policies(&CandidatePolicy::default(), &policy)?,
+        };
+        if latest.is_some() && changes.is_empty() && restored.is_none() {
+            return Ok(None);
+        }
+
+        let version = PolicyVersion {
+            version: latest.map(|latest| latest.version).unwrap_or(0) + 1,
+            author: author.to_string(),
+            source: source.to_string(),
+            timestamp: Utc::now(),
+            restored,
+            changes,
+            policy,
+            signed,
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+            .with_context(|| format!("Failed to open policy history {:?}", self.path))?;
+        writeln!(file, "{}", serde_json::to_string(&version)?)?;
+        info!("Recorded policy version {} ({} by {}, {} changes)", version.version, source, author, version.changes.len());
+        versions.push(version.clone());
+        Ok(Some(version))
+    }
+
+    pub fn record(&self, author: &str, source: &str, policy: CandidatePolicy, signed: SignedPolicy) -> Result<Option<PolicyVersion>> {
+        self.record_with(author, source, None, |_| Ok((policy, signed)))
+    }
+
+    // Newest first
+    pub fn versions(&self) -> Vec<PolicyVersionSummary> {
+        let Ok(versions) = self.versions.lock() else { return Vec::new() };
+        versions.iter().rev()
+            .map(|version| PolicyVersionSummary {
+                version: version.version,
+                author: version.author.clone(),
+                source: version.source.clone(),
+                timestamp: version.timestamp,
+                restored: version.restored,
+                changes: version.changes.len(),
+            })
+            .collect()
+    }
+
+    pub fn version(&self, version: u64) -> Option<PolicyVersion> {
+        let versions = self.versions.lock().ok()?;
+        versions.iter().find(|entry| entry.version == version).cloned()
+    }
+
+    pub fn diff(&self, from: u64, to: u64) -> Result<Vec<FieldChange>> {
+        let from = self.version(from).ok_or_else(|| anyhow!("No policy version {}", from))?;
+        let to = self.version(to).ok_or_else(|| anyhow!("No policy version {}", to))?;
+        diff_policies(&from.policy, &to.policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{FilePolicy, NetworkPolicy};
+
+    #[test]
+    fn test_policy_history() {
+        let dir = std::env::temp_dir().join(format!("flux-history-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("policy_history.jsonl");
+        let history = PolicyHistory::open(&path).unwrap();
+
+        let policy = |hashes: &[&str], allow_local: bool| {
+            let mut file = FilePolicy::default();
+            file.allowed_hashes = hashes.iter().map(|hash| hash.to_string()).collect();
+            let mut network = NetworkPolicy::default();
+            network.allow_local_network = allow_local;
+            CandidatePolicy { file_policy: Some(file), network_policy: Some(network) }
+        };
+        assert_eq!(history.record("system", "startup", policy(&["aa"], true), SignedPolicy::default()).unwrap().unwrap().version, 1);
+        // Unchanged policies are not recorded again
+        assert!(history.record("system", "reload", policy(&["aa"], true), SignedPolicy::default()).unwrap().is_none());
+
+        let second = history.record("admin:1", "api", policy(&["bb", "aa"], false), SignedPolicy::default()).unwrap().unwrap();
+        assert_eq!(second.changes, vec![
+            FieldChange { policy: "file".into(), field: "allowed_hashes".into(), added: vec!["bb".into()], removed: vec![], from: None, to: None },
+            FieldChange { policy: "network".into(), field: "allow_local_network".into(), added: vec![], removed: vec![], from: Some(true.into()), to: Some(false.into()) },
+        ]);
+        assert_eq!(history.diff(2, 1).unwrap()[0].removed, vec![Value::from("bb")]);
+
+        // A rollback is recorded even when it restores the current policy
+        let first = history.version(1).unwrap().policy;
+        let restored = history.record_with("admin:1", "rollback", Some(1), |latest| {
+            assert_eq!(latest.unwrap().version, 2);
+            Ok((first, SignedPolicy::default()))
+        }).unwrap().unwrap();
+        assert_eq!((restored.version, restored.restored), (3, Some(1)));
+
+        let reopened = PolicyHistory::open(&path).unwrap();
+        let versions = reopened.versions();
+        assert_eq!(versions.iter().map(|v| v.version).collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert!(reopened.diff(1, 3).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,5 +1,15 @@
+pub mod edit;
 pub mod file_policy;
+pub mod history;
 pub mod network_policy;
+pub mod replay;
+pub mod rules;
+pub mod signing;
 
+pub use edit::{validate_policy, PolicyEntry, PolicyFiles, ENTRY_KINDS};
 pub use file_policy::FilePolicy;
-pub use network_policy::NetworkPolicy;
\ No newline at end of file
+pub use history::{diff_policies, FieldChange, PolicyHistory, PolicyVersion, PolicyVersionSummary};
+pub use network_policy::NetworkPolicy;
+pub use replay::{CandidatePolicy, PolicyReplay, ReplayReport, ReplayVerdict, ReplayWindow};
+pub use rules::{Condition, Rule, RuleAction, RuleContext, RuleDecision, RuleSet, SigningLevel};
+pub use signing::{read_policy_files, signature_path, PolicySigner, PolicySigningConfig, PolicyVerifier, SignedDocument, SignedPolicy, write_signed};
@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::collections::HashSet;
-use anyhow::Result;
+use std::sync::{Arc, OnceLock};
+use anyhow::{anyhow, Result};
 use tracing::{info, warn, debug};
 use std::path::Path;
 
+use super::file_policy::action_verb;
+use super::rules::{Rule, RuleAction, RuleContext, RuleSet};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkPolicy {
     pub allowed_ips: HashSet<IpAddr>,
@@ -15,6 +19,11 @@ pub struct NetworkPolicy {
     pub blocked_ports: HashSet<u16>,
     pub allow_local_network: bool,
     pub allow_system_processes: bool,
+    // Evaluated before the allow/block sets above
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(skip)]
+    compiled_rules: OnceLock<Arc<RuleSet>>,
 }
 
 impl Default for NetworkPolicy {
@@ -28,6 +37,8 @@ impl Default for NetworkPolicy {
             blocked_ports: HashSet::new(),
             allow_local_network: true,
             allow_system_processes: true,
+            rules: Vec::new(),
+            compiled_rules: OnceLock::new(),
         };
         
         policy.add_default_rules();
@@ -129,6 +140,36 @@ impl NetworkPolicy {
         remote_ip: IpAddr,
         remote_port: u16,
         domain: Option<&str>,
+    ) -> bool {
+        let ctx = RuleContext {
+            remote_ip: Some(remote_ip),
+            remote_port: Some(remote_port),
+            domain,
+            ..Default::default()
+        };
+        self.evaluate_connection(&ctx).0 != RuleAction::Deny
+    }
+
+    // Rules first, then the allow/block sets; returns the action and a reason for logging
+    pub fn evaluate_connection(&self, ctx: &RuleContext) -> (RuleAction, String) {
+        if let Some(decision) = self.rule_set().evaluate(ctx) {
+            debug!("Connection matched rule {}: {:?}", decision.rule_id, decision.action);
+            return (decision.action, format!("Connection {} by rule {}", action_verb(decision.action), decision.rule_id));
+        }
+
+        match (ctx.remote_ip, ctx.remote_port) {
+            (Some(ip), Some(port)) if self.is_connection_allowed_by_lists(ip, port, ctx.domain) => {
+                (RuleAction::Allow, "Connection allowed by policy".to_string())
+            }
+            _ => (RuleAction::Deny, "Connection denied by policy".to_string()),
+        }
+    }
+
+    fn is_connection_allowed_by_lists(
+        &self,
+        remote_ip: IpAddr,
+        remote_port: u16,
+        domain: Option<&str>,
     ) -> bool {
         // Check port first
         if !self.is_port_allowed(remote_port) {
@@ -194,6 +235,43 @@ impl NetworkPolicy {
         self.blocked_ports.insert(port);
     }
     
+    pub fn rule_set(&self) -> Arc<RuleSet> {
+        self.compiled_rules
+            .get_or_init(|| {
+                Arc::new(RuleSet::compile(&self.rules).unwrap_or_else(|e| {
+                    warn!("Ignoring invalid network policy rules: {}", e);
+                    RuleSet::default()
+                }))
+            })
+            .clone()
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> Result<()> {
+        if self.rules.iter().any(|r| r.id == rule.id) {
+            return Err(anyhow!("Rule {} already exists", rule.id));
+        }
+
+        let mut rules = self.rules.clone();
+        rules.push(rule);
+        self.set_rules(rules)
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> Result<()> {
+        let rule_set = RuleSet::compile(&rules)?;
+        info!("Network policy now has {} active rules", rule_set.len());
+
+        self.rules = rules;
+        self.compiled_rules = OnceLock::from(Arc::new(rule_set));
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, id: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.id != id);
+        self.compiled_rules = OnceLock::new();
+        self.rules.len() != before
+    }
+
     pub fn remove_allowed_ip(&mut self, ip: &IpAddr) {
         self.allowed_ips.remove(ip);
     }
@@ -215,8 +293,16 @@ impl NetworkPolicy {
     
     pub fn load_from_file(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let policy: Self = serde_json::from_str(&content)?;
+        let policy = Self::from_json(&content)?;
         info!("Network policy loaded from: {:?}", path);
         Ok(policy)
     }
+
+    // For content read elsewhere, e.g. after its signature was checked
+    pub fn from_json(content: &str) -> Result<Self> {
+        let mut policy: Self = serde_json::from_str(content)?;
+        let rules = std::mem::take(&mut policy.rules);
+        policy.set_rules(rules)?;
+        Ok(policy)
+    }
 }
\ No newline at end of file
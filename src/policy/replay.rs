@@ -0,0 +1,393 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, debug};
+
+use crate::event_log;
+use crate::monitor::{SecurityEvent, SecurityEventType, Verdict};
+use super::{FilePolicy, NetworkPolicy};
+use super::rules::{RuleAction, RuleContext};
+
+// Candidate policy evaluated during a replay. Either half may be omitted, in which
+// case events of that kind are reported as allowed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CandidatePolicy {
+    #[serde(default)]
+    pub file_policy: Option<FilePolicy>,
+    #[serde(default)]
+    pub network_policy: Option<NetworkPolicy>,
+}
+
+impl CandidatePolicy {
+    // Accepts a combined policy file or a bare file/network policy as written by save_to_file
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read candidate policy {:?}", path))?;
+
+        let mut policy = if let Ok(policy) = serde_json::from_str::<FilePolicy>(&content) {
+            Self { file_policy: Some(policy), network_policy: None }
+        } else if let Ok(policy) = serde_json::from_str::<NetworkPolicy>(&content) {
+            Self { file_policy: None, network_policy: Some(policy) }
+        } else {
+            serde_json::from_str(&content)
+                .with_context(|| format!("{:?} is not a file, network or combined policy", path))?
+        };
+
+        if policy.file_policy.is_none() && policy.network_policy.is_none() {
+            return Err(anyhow!("Candidate policy {:?} contains no file or network policy", path));
+        }
+
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    // Compile the rules up front so invalid ones are reported instead of ignored
+    pub fn validate(&mut self) -> Result<()> {
+        if let Some(policy) = self.file_policy.as_mut() {
+            let rules = std::mem::take(&mut policy.rules);
+            policy.set_rules(rules).context("Invalid file policy rules")?;
+        }
+        if let Some(policy) = self.network_policy.as_mut() {
+            let rules = std::mem::take(&mut policy.rules);
+            policy.set_rules(rules).context("Invalid network policy rules")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayVerdict {
+    Allow,
+    Deny,
+    Alert,
+}
+
+impl From<RuleAction> for ReplayVerdict {
+    fn from(action: RuleAction) -> Self {
+        match action {
+            RuleAction::Allow => ReplayVerdict::Allow,
+            RuleAction::Deny => ReplayVerdict::Deny,
+            RuleAction::Alert => ReplayVerdict::Alert,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayWindow {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl ReplayWindow {
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| timestamp >= start)
+            && self.end.is_none_or(|end| timestamp <= end)
+    }
+
+    // Parse an RFC 3339 timestamp or a relative offset into the past such as `30m`, `12h` or `7d`
+    pub fn parse_bound(value: &str) -> Result<DateTime<Utc>> {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+            return Ok(timestamp.with_timezone(&Utc));
+        }
+
+        let value = value.trim();
+        let split = value.char_indices().last().map_or(0, |(i, _)| i);
+        let (amount, unit) = value.split_at(split);
+        let amount: i64 = amount.parse()
+            .map_err(|_| anyhow!("Invalid time bound '{}': expected RFC 3339 or e.g. 30m, 12h, 7d", value))?;
+
+        let offset = match unit {
+            "s" => Duration::seconds(amount),
+            "m" => Duration::minutes(amount),
+            "h" => Duration::hours(amount),
+            "d" => Duration::days(amount),
+            _ => return Err(anyhow!("Invalid time unit in '{}': use s, m, h or d", value)),
+        };
+
+        Ok(Utc::now() - offset)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub event_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub event_type: SecurityEventType,
+    pub process_path: String,
+    pub original_verdict: Verdict,
+    pub verdict: ReplayVerdict,
+    pub reason: String,
+    // Enforcement outcome differs from what was recorded at the time
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub window: ReplayWindow,
+    pub total_events: usize,
+    pub allowed: usize,
+    pub denied: usize,
+    pub alerted: usize,
+    pub changed: usize,
+    pub skipped_lines: usize,
+    pub results: Vec<ReplayResult>,
+}
+
+pub struct PolicyReplay {
+    policy: CandidatePolicy,
+    include_allowed: bool,
+}
+
+impl PolicyReplay {
+    pub fn new(policy: CandidatePolicy) -> Self {
+        Self {
+            policy,
+            include_allowed: false,
+        }
+    }
+
+    // Also list unchanged allowed events in the report (counts always cover everything)
+    pub fn set_include_allowed(&mut self, include: bool) {
+        self.include_allowed = include;
+    }
+
+    // Read events from a JSON-lines event log, keeping those inside the window.
+    // Returns the events and the number of lines that could not be parsed.
+    pub fn load_events(log_path: &Path, window: &ReplayWindow) -> Result<(Vec<SecurityEvent>, usize)> {
+        let file = File::open(log_path)
+            .with_context(|| format!("Failed to open event log {:?}", log_path))?;
+
+        let mut events = Vec::new();
+        let mut skipped = 0;
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Versioned records, or legacy plain events and events wrapped with system metrics
+            let event = match event_log::parse_event_line(&line) {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Skipping unparseable event log line: {}", e);
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            if window.contains(event.timestamp) {
+                events.push(event);
+            }
+        }
+
+        Ok((events, skipped))
+    }
+
+    pub fn evaluate(&self, event: &SecurityEvent) -> (ReplayVerdict, String) {
+        let process = &event.process_info;
+        let mut ctx = RuleContext {
+            parent_path: Some(process.path.as_path()),
+            user_id: Some(process.user_id),
+            timestamp: Some(event.timestamp),
+            ..Default::default()
+        };
+
+        match &event.event_type {
+            SecurityEventType::FileExecution { target_path, file_hash, code_signature } => {
+                let policy = match &self.policy.file_policy {
+                    Some(policy) => policy,
+                    None => return (ReplayVerdict::Allow, "No candidate file policy".to_string()),
+                };
+
+                ctx.path = Some(target_path);
+                ctx.hash = file_hash.as_deref();
+                ctx.signer = code_signature.as_deref();
+                let (action, reason) = policy.evaluate_execution(&ctx);
+                (action.into(), reason)
+            }
+            SecurityEventType::FileAccess { target_path, .. } => {
+                let policy = match &self.policy.file_policy {
+                    Some(policy) => policy,
+                    None => return (ReplayVerdict::Allow, "No candidate file policy".to_string()),
+                };
+
+                ctx.path = Some(target_path);
+                let (action, reason) = policy.evaluate_access(&ctx);
+                (action.into(), reason)
+            }
+            SecurityEventType::NetworkConnection { remote_ip, remote_port, domain, .. } => {
+                let policy = match &self.policy.network_policy {
+                    Some(policy) => policy,
+                    None => return (ReplayVerdict::Allow, "No candidate network policy".to_string()),
+                };
+
+                ctx.remote_ip = match remote_ip.parse() {
+                    Ok(ip) => Some(ip),
+                    Err(_) => return (ReplayVerdict::Deny, "Invalid IP address".to_string()),
+                };
+                ctx.remote_port = Some(*remote_port);
+                ctx.domain = domain.as_deref();
+                let (action, reason) = policy.evaluate_connection(&ctx);
+                (action.into(), reason)
+            }
+            // Not enforceable by file/network policy, but worth surfacing
+            SecurityEventType::Authentication { success: false, user, service, .. } => {
+                (ReplayVerdict::Alert, format!("Failed authentication for {} via {}", user, service))
+            }
+            SecurityEventType::Syscall { success: false, syscall, .. } => {
+                (ReplayVerdict::Alert, format!("Failed syscall {}", syscall))
+            }
+            SecurityEventType::Authentication { .. } | SecurityEventType::Syscall { .. } => {
+                (ReplayVerdict::Allow, "Not subject to policy".to_string())
+            }
+        }
+    }
+
+    pub fn replay(&self, events: &[SecurityEvent], window: ReplayWindow) -> ReplayReport {
+        let mut report = ReplayReport {
+            window,
+            total_events: events.len(),
+            allowed: 0,
+            denied: 0,
+            alerted: 0,
+            changed: 0,
+            skipped_lines: 0,
+            results: Vec::new(),
+        };
+
+        for event in events {
+            let (verdict, reason) = self.evaluate(event);
+
+            // Log verdicts were never enforced, so only a recorded Deny counts as blocked
+            let was_denied = matches!(event.verdict, Verdict::Deny);
+            let changed = was_denied != (verdict == ReplayVerdict::Deny);
+
+            match verdict {
+                ReplayVerdict::Allow => report.allowed += 1,
+                ReplayVerdict::Deny => report.denied += 1,
+                ReplayVerdict::Alert => report.alerted += 1,
+            }
+            if changed {
+                report.changed += 1;
+            }
+
+            if verdict == ReplayVerdict::Allow && !changed && !self.include_allowed {
+                continue;
+            }
+
+            report.results.push(ReplayResult {
+                event_id: event.id.clone(),
+                timestamp: event.timestamp,
+                event_type: event.event_type.clone(),
+                process_path: event.process_info.path.display().to_string(),
+                original_verdict: event.verdict.clone(),
+                verdict,
+                reason,
+                changed,
+            });
+        }
+
+        report
+    }
+
+    pub fn replay_log(&self, log_path: &Path, window: ReplayWindow) -> Result<ReplayReport> {
+        let (events, skipped) = Self::load_events(log_path, &window)?;
+        info!("Replaying {} events from {:?} through candidate policy", events.len(), log_path);
+
+        let mut report = self.replay(&events, window);
+        report.skipped_lines = skipped;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::monitor::{NetworkProtocol, ProcessInfo};
+
+    fn event(event_type: SecurityEventType, verdict: Verdict, age_minutes: i64) -> SecurityEvent {
+        SecurityEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now() - Duration::minutes(age_minutes),
+            event_type,
+            process_info: ProcessInfo {
+                pid: 100,
+                path: PathBuf::from("/bin/sh"),
+                parent_pid: None,
+                user_id: 0,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict,
+            policy_reason: "Passive mode - logging only".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_replay_log_window_and_verdicts() {
+        let mut network_policy = NetworkPolicy::new();
+        network_policy.add_blocked_port(4444);
+
+        let replay = PolicyReplay::new(CandidatePolicy {
+            file_policy: Some(FilePolicy::new()),
+            network_policy: Some(network_policy),
+        });
+
+        let events = vec![
+            event(SecurityEventType::FileExecution {
+                target_path: PathBuf::from("/tmp/dropper"),
+                file_hash: None,
+                code_signature: None,
+            }, Verdict::Log, 5),
+            event(SecurityEventType::FileExecution {
+                target_path: PathBuf::from("/usr/bin/ls"),
+                file_hash: None,
+                code_signature: None,
+            }, Verdict::Log, 5),
+            event(SecurityEventType::NetworkConnection {
+                remote_ip: "203.0.113.7".to_string(),
+                remote_port: 4444,
+                domain: None,
+                protocol: NetworkProtocol::Tcp,
+            }, Verdict::Deny, 10),
+            event(SecurityEventType::Authentication {
+                user: "root".to_string(),
+                service: "sshd".to_string(),
+                success: false,
+                remote_host: None,
+            }, Verdict::Log, 15),
+            // Outside a one hour window
+            event(SecurityEventType::FileExecution {
+                target_path: PathBuf::from("/tmp/old"),
+                file_hash: None,
+                code_signature: None,
+            }, Verdict::Log, 180),
+        ];
+
+        let log_path = std::env::temp_dir().join(format!("replay-{}.log", uuid::Uuid::new_v4()));
+        let mut content: String = events.iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect();
+        content.push_str("not json\n");
+        std::fs::write(&log_path, content).unwrap();
+
+        let window = ReplayWindow { start: Some(ReplayWindow::parse_bound("1h").unwrap()), end: None };
+        let report = replay.replay_log(&log_path, window).unwrap();
+        std::fs::remove_file(&log_path).ok();
+
+        assert_eq!(report.total_events, 4);
+        assert_eq!(report.skipped_lines, 1);
+        assert_eq!((report.allowed, report.denied, report.alerted), (1, 2, 1));
+        // Only the dropper flips from logged to denied; the port 4444 deny was already enforced
+        assert_eq!(report.changed, 1);
+        assert_eq!(report.results.len(), 3);
+        assert!(report.results.iter().any(|r| r.changed && r.verdict == ReplayVerdict::Deny));
+    }
+}
@@ -0,0 +1,360 @@
+use std::net::IpAddr;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use regex::Regex;
+use anyhow::{anyhow, Result, Context};
+
+// A single policy rule. All `conditions` must match for the rule to apply,
+// and any matching `unless` condition exempts the event from it.
+//
+// Example: deny exec from /tmp unless signed
+//   { "id": "tmp-exec", "action": "deny", "priority": 100,
+//     "conditions": [{ "type": "path", "pattern": "/tmp/**" }],
+//     "unless": [{ "type": "signed" }] }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    pub action: RuleAction,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub unless: Vec<Condition>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Allow,
+    Deny,
+    // Allow, but flag the event
+    Alert,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    // Glob on the target path; `*` stays within a path component, `**` crosses them
+    Path { pattern: String },
+    Hash { values: Vec<String> },
+    // Glob on the code signing authority
+    Signer { pattern: String },
+    // Any code signature present
+    Signed,
+    // Glob on the parent process path, or its file name when the pattern has no `/`
+    ParentProcess { pattern: String },
+    User { uids: Vec<u32> },
+    // Local wall-clock window, `HH:MM` to `HH:MM`; wraps past midnight when end < start
+    TimeOfDay { start: String, end: String },
+    // IP address or CIDR block
+    RemoteIp { cidr: String },
+    Port { ports: Vec<u16> },
+    // Glob on the domain name, e.g. `*.example.com`
+    Domain { pattern: String },
+}
+
+// Facts about the event being evaluated; conditions on missing facts never match
+#[derive(Debug, Clone, Default)]
+pub struct RuleContext<'a> {
+    pub path: Option<&'a Path>,
+    pub hash: Option<&'a str>,
+    pub signer: Option<&'a str>,
+    pub parent_path: Option<&'a Path>,
+    pub user_id: Option<u32>,
+    pub remote_ip: Option<IpAddr>,
+    pub remote_port: Option<u16>,
+    pub domain: Option<&'a str>,
+    // Defaults to now
+    pub timestamp: Option<DateTime<Utc>>,
+    // How the target executable is signed, where that was assessed (macOS)
+    pub signing: Option<SigningLevel>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningLevel {
+    // Includes ad-hoc signatures, which Gatekeeper treats the same
+    Unsigned,
+    Signed,
+    // Notarized by Apple, or an Apple platform binary
+    Notarized,
+}
+
+impl std::fmt::Display for SigningLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SigningLevel::Unsigned => "unsigned",
+            SigningLevel::Signed => "signed",
+            SigningLevel::Notarized => "notarized",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleDecision {
+    pub rule_id: String,
+    pub action: RuleAction,
+    pub description: String,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Path(Regex),
+    Hash(Vec<String>),
+    Signer(Regex),
+    Signed,
+    ParentProcess { pattern: Regex, name_only: bool },
+    User(Vec<u32>),
+    TimeOfDay { start: NaiveTime, end: NaiveTime },
+    RemoteIp { network: IpAddr, prefix: u8 },
+    Port(Vec<u16>),
+    Domain(Regex),
+}
+
+impl Matcher {
+    fn compile(condition: &Condition) -> Result<Self> {
+        Ok(match condition {
+            Condition::Path { pattern } => Matcher::Path(compile_glob(pattern, true)?),
+            Condition::Hash { values } => Matcher::Hash(values.iter().map(|v| v.to_lowercase()).collect()),
+            Condition::Signer { pattern } => Matcher::Signer(compile_glob(pattern, false)?),
+            Condition::Signed => Matcher::Signed,
+            Condition::ParentProcess { pattern } => Matcher::ParentProcess {
+                pattern: compile_glob(pattern, true)?,
+                name_only: !pattern.contains('/'),
+            },
+            Condition::User { uids } => Matcher::User(uids.clone()),
+            Condition::TimeOfDay { start, end } => Matcher::TimeOfDay {
+                start: NaiveTime::parse_from_str(start, "%H:%M")
+                    .with_context(|| format!("Invalid time of day '{}'", start))?,
+                end: NaiveTime::parse_from_str(end, "%H:%M")
+                    .with_context(|| format!("Invalid time of day '{}'", end))?,
+            },
+            Condition::RemoteIp { cidr } => {
+                let (network, prefix) = parse_cidr(cidr)?;
+                Matcher::RemoteIp { network, prefix }
+            }
+            Condition::Port { ports } => Matcher::Port(ports.clone()),
+            Condition::Domain { pattern } => Matcher::Domain(compile_glob(&pattern.to_lowercase(), false)?),
+        })
+    }
+
+    fn matches(&self, ctx: &RuleContext) -> bool {
+        match self {
+            Matcher::Path(re) => ctx.path.is_some_and(|p| re.is_match(&p.to_string_lossy())),
+            Matcher::Hash(values) => ctx.hash.is_some_and(|h| values.contains(&h.to_lowercase())),
+            Matcher::Signer(re) => ctx.signer.is_some_and(|s| re.is_match(s)),
+            Matcher::Signed => ctx.signer.is_some_and(|s| !s.is_empty()),
+            Matcher::ParentProcess { pattern, name_only } => ctx.parent_path.is_some_and(|p| {
+                if *name_only {
+                    p.file_name().is_some_and(|n| pattern.is_match(&n.to_string_lossy()))
+                } else {
+                    pattern.is_match(&p.to_string_lossy())
+                }
+            }),
+            Matcher::User(uids) => ctx.user_id.is_some_and(|uid| uids.contains(&uid)),
+            Matcher::TimeOfDay { start, end } => {
+                let now = ctx.timestamp.unwrap_or_else(Utc::now).with_timezone(&Local).time();
+                if start <= end {
+                    now >= *start && now < *end
+                } else {
+                    now >= *start || now < *end
+                }
+            }
+            Matcher::RemoteIp { network, prefix } => ctx.remote_ip.is_some_and(|ip| ip_in_network(ip, *network, *prefix)),
+            Matcher::Port(ports) => ctx.remote_port.is_some_and(|p| ports.contains(&p)),
+            Matcher::Domain(re) => ctx.domain.is_some_and(|d| re.is_match(&d.to_lowercase())),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    id: String,
+    description: String,
+    action: RuleAction,
+    conditions: Vec<Matcher>,
+    unless: Vec<Matcher>,
+}
+
+// Rules compiled into matchers, ordered by priority (highest first, deny before allow on ties)
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    pub fn compile(rules: &[Rule]) -> Result<Self> {
+        let mut ordered: Vec<&Rule> = rules.iter().filter(|r| r.enabled).collect();
+        ordered.sort_by_key(|r| (std::cmp::Reverse(r.priority), action_rank(r.action)));
+
+        let mut compiled = Vec::with_capacity(ordered.len());
+        for rule in ordered {
+            let compile_all = |conditions: &[Condition]| -> Result<Vec<Matcher>> {
+                conditions.iter().map(Matcher::compile).collect()
+            };
+
+            compiled.push(CompiledRule {
+                id: rule.id.clone(),
+                description: rule.description.clone(),
+                action: rule.action,
+                conditions: compile_all(&rule.conditions).with_context(|| format!("Invalid rule '{}'", rule.id))?,
+                unless: compile_all(&rule.unless).with_context(|| format!("Invalid rule '{}'", rule.id))?,
+            });
+        }
+
+        Ok(Self { rules: compiled })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // First matching rule wins; None means no rule applies
+    pub fn evaluate(&self, ctx: &RuleContext) -> Option<RuleDecision> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.conditions.iter().all(|m| m.matches(ctx))
+                    && !rule.unless.iter().any(|m| m.matches(ctx))
+            })
+            .map(|rule| RuleDecision {
+                rule_id: rule.id.clone(),
+                action: rule.action,
+                description: rule.description.clone(),
+            })
+    }
+}
+
+fn action_rank(action: RuleAction) -> u8 {
+    match action {
+        RuleAction::Deny => 0,
+        RuleAction::Alert => 1,
+        RuleAction::Allow => 2,
+    }
+}
+
+fn compile_glob(pattern: &str, path_mode: bool) -> Result<Regex> {
+    let single = if path_mode { "[^/]*" } else { ".*" };
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str(single),
+            '?' => regex.push_str(if path_mode { "[^/]" } else { "." }),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).with_context(|| format!("Invalid glob '{}'", pattern))
+}
+
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8)> {
+    let (addr, prefix) = match cidr.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (cidr, None),
+    };
+
+    let network: IpAddr = addr.trim().parse()
+        .map_err(|_| anyhow!("Invalid IP address in '{}'", cidr))?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= max)
+            .ok_or_else(|| anyhow!("Invalid prefix length in '{}'", cidr))?,
+        None => max,
+    };
+
+    Ok((network, prefix))
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_tmp_exec_unless_signed() {
+        let rules: Vec<Rule> = serde_json::from_str(r#"[
+            { "id": "tmp-exec", "action": "deny", "priority": 100,
+              "conditions": [{ "type": "path", "pattern": "/tmp/**" }],
+              "unless": [{ "type": "signed" }] },
+            { "id": "curl-from-shell", "action": "alert", "priority": 50,
+              "conditions": [
+                { "type": "path", "pattern": "/usr/bin/curl" },
+                { "type": "parent_process", "pattern": "*sh" }
+              ] },
+            { "id": "c2", "action": "deny",
+              "conditions": [{ "type": "remote_ip", "cidr": "203.0.113.0/24" }, { "type": "port", "ports": [4444] }] }
+        ]"#).unwrap();
+        let rule_set = RuleSet::compile(&rules).unwrap();
+        assert_eq!(rule_set.len(), 3);
+
+        let dropper = Path::new("/tmp/x/dropper");
+        let ctx = RuleContext { path: Some(dropper), ..Default::default() };
+        assert_eq!(rule_set.evaluate(&ctx).unwrap().action, RuleAction::Deny);
+
+        let ctx = RuleContext { path: Some(dropper), signer: Some("Developer ID Application: Acme"), ..Default::default() };
+        assert!(rule_set.evaluate(&ctx).is_none());
+
+        let ctx = RuleContext {
+            path: Some(Path::new("/usr/bin/curl")),
+            parent_path: Some(Path::new("/bin/bash")),
+            ..Default::default()
+        };
+        assert_eq!(rule_set.evaluate(&ctx).unwrap().rule_id, "curl-from-shell");
+
+        let ctx = RuleContext { remote_ip: "203.0.113.9".parse().ok(), remote_port: Some(4444), ..Default::default() };
+        assert_eq!(rule_set.evaluate(&ctx).unwrap().rule_id, "c2");
+        let ctx = RuleContext { remote_ip: "198.51.100.9".parse().ok(), remote_port: Some(4444), ..Default::default() };
+        assert!(rule_set.evaluate(&ctx).is_none());
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let bad_cidr = Rule {
+            id: "bad".to_string(),
+            description: String::new(),
+            action: RuleAction::Deny,
+            priority: 0,
+            enabled: true,
+            conditions: vec![Condition::RemoteIp { cidr: "10.0.0.0/33".to_string() }],
+            unless: vec![],
+        };
+        assert!(RuleSet::compile(&[bad_cidr]).is_err());
+
+        let bad_time = Condition::TimeOfDay { start: "25:00".to_string(), end: "06:00".to_string() };
+        assert!(Matcher::compile(&bad_time).is_err());
+    }
+}
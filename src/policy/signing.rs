@@ -0,0 +1,250 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+
+use super::edit::write_atomic;
+use super::replay::CandidatePolicy;
+use super::{FilePolicy, NetworkPolicy};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicySigningConfig {
+    // Hex Ed25519 public keys; a policy signed by any of them is accepted.
+    // More than one allows rotating the signing key
+    pub public_keys: Vec<String>,
+}
+
+impl PolicySigningConfig {
+    pub fn validate(&self) -> Result<()> {
+        PolicyVerifier::new(self).map(|_| ())
+    }
+}
+
+// Where the detached signature of a policy or rules file is kept
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+// Checks the detached signatures of policy files. Signatures cover the exact
+// bytes on disk, since policies hold sets and do not re-serialize stably
+pub struct PolicyVerifier {
+    public_keys: Vec<Vec<u8>>,
+}
+
+impl PolicyVerifier {
+    pub fn new(config: &PolicySigningConfig) -> Result<Self> {
+        if config.public_keys.is_empty() {
+            bail!("Policy signing needs at least one public key");
+        }
+        let public_keys = config.public_keys.iter()
+            .map(|key| {
+                let bytes = hex::decode(key.trim()).with_context(|| format!("Policy public key {:?} is not hex", key))?;
+                if bytes.len() != 32 {
+                    bail!("Policy public key {:?} is not a 32 byte Ed25519 key", key);
+                }
+                Ok(bytes)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { public_keys })
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<()> {
+        let signature = hex::decode(signature.trim()).context("Policy signature is not hex")?;
+        let trusted = self.public_keys.iter()
+            .any(|key| UnparsedPublicKey::new(&ED25519, key).verify(data, &signature).is_ok());
+        if !trusted {
+            bail!("Policy signature does not match any trusted key");
+        }
+        Ok(())
+    }
+
+    // Reads a policy file only if its .sig is present and valid
+    pub fn read_verified(&self, path: &Path) -> Result<String> {
+        self.read_document(path).map(|document| document.content)
+    }
+
+    pub fn read_document(&self, path: &Path) -> Result<SignedDocument> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let sig_path = signature_path(path);
+        let signature = std::fs::read_to_string(&sig_path)
+            .with_context(|| format!("Refusing unsigned policy {}: cannot read {}", path.display(), sig_path.display()))?;
+        self.verify(content.as_bytes(), &signature)
+            .with_context(|| format!("Refusing tampered policy {}", path.display()))?;
+        Ok(SignedDocument { content, signature })
+    }
+}
+
+pub struct PolicySigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl PolicySigner {
+    // A new PKCS#8 private key, hex encoded for the key file
+    pub fn generate() -> Result<String> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate an Ed25519 key"))?;
+        Ok(hex::encode(document.as_ref()))
+    }
+
+    pub fn from_pkcs8_hex(key: &str) -> Result<Self> {
+        let bytes = hex::decode(key.trim()).context("Policy signing key is not hex")?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(&bytes)
+            .map_err(|err| anyhow!("Invalid policy signing key: {}", err))?;
+        Ok(Self { key_pair })
+    }
+
+    pub fn from_key_file(path: &Path) -> Result<Self> {
+        let key = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read signing key {}", path.display()))?;
+        Self::from_pkcs8_hex(&key)
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, data: &[u8]) -> String {
+        hex::encode(self.key_pair.sign(data).as_ref())
+    }
+
+    // Writes <path>.sig next to the file
+    pub fn sign_file(&self, path: &Path) -> Result<PathBuf> {
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let sig_path = signature_path(path);
+        std::fs::write(&sig_path, self.sign(&content))?;
+        Ok(sig_path)
+    }
+}
+
+// A policy file's exact content with its detached signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedDocument {
+    pub content: String,
+    pub signature: String,
+}
+
+// Writes the document and its .sig; in between, the file fails
+// verification rather than passing with a stale signature
+pub fn write_signed(path: &Path, document: &SignedDocument) -> Result<()> {
+    write_atomic(path, &document.content)?;
+    write_atomic(&signature_path(path), &document.signature)?;
+    Ok(())
+}
+
+// How signed policies travel to fleet agents, which write each document
+// and its signature out unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SignedPolicy {
+    #[serde(default)]
+    pub file_policy: Option<SignedDocument>,
+    #[serde(default)]
+    pub network_policy: Option<SignedDocument>,
+}
+
+impl SignedPolicy {
+    pub fn sign(signer: &PolicySigner, file_policy: Option<String>, network_policy: Option<String>) -> Self {
+        let document = |content: String| SignedDocument { signature: signer.sign(content.as_bytes()), content };
+        Self {
+            file_policy: file_policy.map(document),
+            network_policy: network_policy.map(document),
+        }
+    }
+
+    // Parses the documents without checking signatures, as the fleet server
+    // does; agents hold the keys and call verify
+    pub fn parse(&self) -> Result<CandidatePolicy> {
+        let mut policy = CandidatePolicy {
+            file_policy: self.file_policy.as_ref().map(|doc| serde_json::from_str::<FilePolicy>(&doc.content)).transpose()
+                .context("Invalid signed file policy")?,
+            network_policy: self.network_policy.as_ref().map(|doc| serde_json::from_str::<NetworkPolicy>(&doc.content)).transpose()
+                .context("Invalid signed network policy")?,
+        };
+        if policy.file_policy.is_none() && policy.network_policy.is_none() {
+            bail!("Signed policy contains no file or network policy");
+        }
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn verify(&self, verifier: &PolicyVerifier) -> Result<CandidatePolicy> {
+        if let Some(doc) = &self.file_policy {
+            verifier.verify(doc.content.as_bytes(), &doc.signature).context("Refusing file policy")?;
+        }
+        if let Some(doc) = &self.network_policy {
+            verifier.verify(doc.content.as_bytes(), &doc.signature).context("Refusing network policy")?;
+        }
+        self.parse()
+    }
+}
+
+// Reads whichever of the policy files exist, through the verifier when
+// signing is enabled; the signed half carries each verified file
+pub fn read_policy_files(
+    file_policy: Option<&Path>,
+    network_policy: Option<&Path>,
+    verifier: Option<&PolicyVerifier>,
+) -> Result<(CandidatePolicy, SignedPolicy)> {
+    let read = |path: &Path| -> Result<(String, Option<SignedDocument>)> {
+        match verifier {
+            Some(verifier) => {
+                let document = verifier.read_document(path)?;
+                Ok((document.content.clone(), Some(document)))
+            }
+            None => Ok((std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?, None)),
+        }
+    };
+
+    let mut policy = CandidatePolicy::default();
+    let mut signed = SignedPolicy::default();
+    if let Some(path) = file_policy.filter(|path| path.exists()) {
+        let (content, document) = read(path)?;
+        policy.file_policy = Some(FilePolicy::from_json(&content).with_context(|| format!("Invalid file policy {}", path.display()))?);
+        signed.file_policy = document;
+    }
+    if let Some(path) = network_policy.filter(|path| path.exists()) {
+        let (content, document) = read(path)?;
+        policy.network_policy = Some(NetworkPolicy::from_json(&content).with_context(|| format!("Invalid network policy {}", path.display()))?);
+        signed.network_policy = document;
+    }
+    Ok((policy, signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_signing() {
+        let signer = PolicySigner::from_pkcs8_hex(&PolicySigner::generate().unwrap()).unwrap();
+        let other = PolicySigner::from_pkcs8_hex(&PolicySigner::generate().unwrap()).unwrap();
+        let verifier = PolicyVerifier::new(&PolicySigningConfig { public_keys: vec![signer.public_key_hex()] }).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("flux-signing-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file_policy.json");
+        let content = serde_json::to_string_pretty(&FilePolicy::default()).unwrap();
+        std::fs::write(&path, &content).unwrap();
+
+        // Unsigned, then signed, then tampered with after signing
+        assert!(verifier.read_verified(&path).is_err());
+        signer.sign_file(&path).unwrap();
+        assert_eq!(verifier.read_verified(&path).unwrap(), content);
+        std::fs::write(&path, content.replace("{", "{ ")).unwrap();
+        assert!(verifier.read_verified(&path).is_err());
+
+        let signed = SignedPolicy::sign(&signer, Some(content.clone()), None);
+        assert!(signed.verify(&verifier).unwrap().file_policy.is_some());
+        let forged = SignedPolicy::sign(&other, Some(content), None);
+        assert!(forged.parse().is_ok());
+        assert!(forged.verify(&verifier).is_err());
+
+        assert!(PolicyVerifier::new(&PolicySigningConfig { public_keys: vec!["abcd".to_string()] }).is_err());
+        assert!(PolicyVerifier::new(&PolicySigningConfig::default()).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::health::{ComponentState, HealthRegistry};
+
+pub const FANOTIFY_PERMISSION: &str = "fanotify_permission";
+pub const NET_ADMIN: &str = "net_admin";
+pub const NFT_BINARY: &str = "nft_binary";
+pub const PCAP: &str = "pcap";
+pub const ES_ENTITLEMENT: &str = "es_entitlement";
+pub const PROC_EVENTS: &str = "proc_events";
+pub const PF: &str = "pf";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapabilityCheck {
+    pub name: String,
+    pub available: bool,
+    pub detail: String,
+    // The subsystem that needs it
+    pub needed_by: String,
+    // How that subsystem runs without it
+    pub fallback: String,
+}
+
+// The privileges and platform features this host offers, probed once at
+// startup so subsystems can fall back to observing instead of failing
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CapabilityReport {
+    pub checked_at: DateTime<Utc>,
+    pub checks: Vec<CapabilityCheck>,
+}
+
+impl CapabilityReport {
+    pub fn probe() -> Self {
+        Self::from_checks(platform_checks())
+    }
+
+    pub fn from_checks(checks: Vec<CapabilityCheck>) -> Self {
+        Self { checked_at: Utc::now(), checks }
+    }
+
+    // Checks that do not apply to this platform count as available
+    pub fn has(&self, name: &str) -> bool {
+        self.checks.iter().all(|check| check.name != name || check.available)
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &CapabilityCheck> {
+        self.checks.iter().filter(|check| !check.available)
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            if check.available {
+                info!("Capability {}: {}", check.name, check.detail);
+            } else {
+                warn!("Capability {} missing ({}): {} falls back to {}", check.name, check.detail, check.needed_by, check.fallback);
+            }
+        }
+    }
+
+    // One "capabilities" component, degraded while anything is missing
+    pub fn report_health(&self, health: &HealthRegistry) {
+        let missing: Vec<&str> = self.missing().map(|check| check.name.as_str()).collect();
+        if missing.is_empty() {
+            health.set("capabilities", false, ComponentState::Up, format!("{} checks passed", self.checks.len()));
+        } else {
+            health.set("capabilities", false, ComponentState::Degraded, format!("missing: {}", missing.join(", ")));
+        }
+    }
+}
+
+fn check(name: &str, available: bool, detail: impl Into<String>, needed_by: &str, fallback: &str) -> CapabilityCheck {
+    CapabilityCheck {
+        name: name.to_string(),
+        available,
+        detail: detail.into(),
+        needed_by: needed_by.to_string(),
+        fallback: fallback.to_string(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn platform_checks() -> Vec<CapabilityCheck> {
+    use crate::privileges::has_capability;
+
+    let capability = |name: &str| if has_capability(name) {
+        format!("{} is effective", name)
+    } else {
+        format!("{} is not effective", name)
+    };
+    let nft = find_in_path("nft");
+    let pcap_detail = if !cfg!(feature = "pcap") {
+        "built without the pcap feature".to_string()
+    } else {
+        capability("CAP_NET_RAW")
+    };
+    vec![
+        check(FANOTIFY_PERMISSION, has_capability("CAP_SYS_ADMIN"), capability("CAP_SYS_ADMIN"),
+              "file access blocking (fanotify permission events)", "logging verdicts without enforcing them"),
+        check(NET_ADMIN, has_capability("CAP_NET_ADMIN"), capability("CAP_NET_ADMIN"),
+              "nftables rules, bans and host isolation", "logging network verdicts only"),
+        check(NFT_BINARY, nft.is_some(),
+              nft.map_or_else(|| "nft not found in PATH".to_string(), |path| path.display().to_string()),
+              "the nft CLI firewall backend", "the netlink backend"),
+        check(PCAP, cfg!(feature = "pcap") && has_capability("CAP_NET_RAW"), pcap_detail,
+              "traffic capture and DNS telemetry", "connection tracking without packet data"),
+    ]
+}
+
+#[cfg(target_os = "macos")]
+fn platform_checks() -> Vec<CapabilityCheck> {
+    let (available, detail) = es_entitlement();
+    vec![check(ES_ENTITLEMENT, available, detail,
+               "Endpoint Security exec and open authorization", "passive monitoring")]
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+fn platform_checks() -> Vec<CapabilityCheck> {
+    let root = unsafe { libc::geteuid() } == 0;
+    let pf = std::fs::File::open("/dev/pf");
+    vec![
+        check(PROC_EVENTS, root, if root { "running as root" } else { "not running as root" },
+              "kqueue process events for every user", "only this user's processes"),
+        check(PF, pf.is_ok() && find_in_path("pfctl").is_some(),
+              pf.map_or_else(|e| format!("/dev/pf: {}", e), |_| "/dev/pf is readable".to_string()),
+              "connection monitoring through pf states", "no connection events"),
+    ]
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+fn platform_checks() -> Vec<CapabilityCheck> {
+    Vec::new()
+}
+
+// Endpoint Security clients must be signed with this entitlement, and run
+// as root
+#[cfg(target_os = "macos")]
+fn es_entitlement() -> (bool, String) {
+    if !cfg!(feature = "esf") {
+        return (false, "built without the esf feature".to_string());
+    }
+    if unsafe { libc::geteuid() } != 0 {
+        return (false, "not running as root".to_string());
+    }
+    let Ok(exe) = std::env::current_exe() else {
+        return (false, "cannot locate the running binary".to_string());
+    };
+    match std::process::Command::new("codesign").arg("-d").arg("--entitlements").arg("-").arg(&exe).output() {
+        Ok(output) if String::from_utf8_lossy(&output.stdout).contains("com.apple.developer.endpoint-security.client") => {
+            (true, "com.apple.developer.endpoint-security.client is granted".to_string())
+        }
+        Ok(_) => (false, format!("{} is not signed with com.apple.developer.endpoint-security.client", exe.display())),
+        Err(e) => (false, format!("codesign failed: {}", e)),
+    }
+}
+
+pub fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_report() {
+        let report = CapabilityReport::from_checks(vec![
+            check(FANOTIFY_PERMISSION, false, "CAP_SYS_ADMIN is not effective", "blocking", "logging"),
+            check(NET_ADMIN, true, "CAP_NET_ADMIN is effective", "nftables", "logging"),
+        ]);
+        assert!(!report.has(FANOTIFY_PERMISSION));
+        assert!(report.has(NET_ADMIN));
+        // Not probed on this platform
+        assert!(report.has(ES_ENTITLEMENT));
+        assert_eq!(report.missing().count(), 1);
+
+        let health = HealthRegistry::new();
+        report.report_health(&health);
+        let component = health.report().components.into_iter().find(|component| component.name == "capabilities").unwrap();
+        assert_eq!(component.state, ComponentState::Degraded);
+        assert_eq!(component.detail, "missing: fanotify_permission");
+
+        // Whatever the host offers, every check explains itself
+        assert!(CapabilityReport::probe().checks.iter().all(|check| !check.detail.is_empty()));
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::monitor::{SecurityEvent, SecurityEventType};
+
+const HMAC_BLOCK_SIZE: usize = 64;
+// Hex digits of the HMAC kept in a pseudonym
+const PSEUDONYM_DIGITS: usize = 16;
+const MIN_KEY_BYTES: usize = 16;
+
+// Data minimization: usernames, home directories and remote addresses are
+// replaced by keyed pseudonyms before events are stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    // Hex HMAC key; a random one is written here on first use
+    pub key_path: PathBuf,
+    // Pseudonym -> original value, only read back for re-identification
+    pub vault_path: PathBuf,
+    #[serde(default = "default_true")]
+    pub usernames: bool,
+    #[serde(default = "default_true")]
+    pub home_paths: bool,
+    #[serde(default = "default_true")]
+    pub remote_ips: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultEntry {
+    pseudonym: String,
+    value: String,
+}
+
+struct Vault {
+    file: File,
+    entries: HashMap<String, String>,
+}
+
+pub struct Pseudonymizer {
+    config: PrivacyConfig,
+    key: Vec<u8>,
+    home: Regex,
+    vault: Mutex<Vault>,
+}
+
+impl Pseudonymizer {
+    pub fn open(config: PrivacyConfig) -> Result<Self> {
+        let key = load_or_create_key(&config.key_path)?;
+        let entries = read_vault(&config.vault_path)?;
+        let file = open_private(&config.vault_path)
+            .with_context(|| format!("Failed to open pseudonym vault {:?}", config.vault_path))?;
+        info!("Privacy mode on: {} known pseudonyms in {:?}", entries.len(), config.vault_path);
+        Ok(Self {
+            config,
+            key,
+            home: Regex::new(r#"(/home/|/Users/)([^/\s'"]+)"#)?,
+            vault: Mutex::new(Vault { file, entries }),
+        })
+    }
+
+    // The same value always maps to the same pseudonym under one key, so
+    // pseudonymized events can still be grouped and correlated
+    pub fn pseudonym(&self, kind: &str, value: &str) -> String {
+        let digest = hmac_sha256(&self.key, format!("{}:{}", kind, value).as_bytes());
+        let pseudonym = format!("{}-{}", kind, &hex::encode(digest)[..PSEUDONYM_DIGITS]);
+        self.remember(&pseudonym, value);
+        pseudonym
+    }
+
+    pub fn pseudonymize_event(&self, event: &mut SecurityEvent) {
+        if self.config.home_paths {
+            event.process_info.path = self.home_path(&event.process_info.path);
+            if let Some(command_line) = event.process_info.command_line.as_mut() {
+                *command_line = self.home_text(command_line);
+            }
+            event.policy_reason = self.home_text(&event.policy_reason);
+        }
+        match &mut event.event_type {
+            SecurityEventType::FileExecution { target_path, .. } | SecurityEventType::FileAccess { target_path, .. } => {
+                if self.config.home_paths {
+                    *target_path = self.home_path(target_path);
+                }
+            }
+            SecurityEventType::NetworkConnection { remote_ip, .. } => {
+                if self.config.remote_ips {
+                    let pseudonym = self.pseudonym("ip", remote_ip);
+                    event.policy_reason = event.policy_reason.replace(remote_ip.as_str(), &pseudonym);
+                    *remote_ip = pseudonym;
+                }
+            }
+            SecurityEventType::Authentication { user, remote_host, .. } => {
+                if self.config.usernames {
+                    *user = self.pseudonym("user", user);
+                }
+                if let Some(host) = remote_host.as_mut().filter(|_| self.config.remote_ips) {
+                    let pseudonym = self.pseudonym("ip", host);
+                    event.policy_reason = event.policy_reason.replace(host.as_str(), &pseudonym);
+                    *host = pseudonym;
+                }
+            }
+            SecurityEventType::Syscall { .. } => {}
+        }
+    }
+
+    // Original values for the given pseudonyms; None for ones never issued
+    pub fn reidentify(&self, pseudonyms: &[String]) -> Result<BTreeMap<String, Option<String>>> {
+        let mut vault = self.vault.lock().unwrap();
+        if pseudonyms.iter().any(|pseudonym| !vault.entries.contains_key(pseudonym)) {
+            // The monitor may share the vault and have issued them since
+            let entries = read_vault(&self.config.vault_path)?;
+            vault.entries.extend(entries);
+        }
+        Ok(pseudonyms.iter().map(|pseudonym| (pseudonym.clone(), vault.entries.get(pseudonym).cloned())).collect())
+    }
+
+    // /home/alice/... becomes /home/user-<hmac>/..., the same pseudonym the
+    // account gets elsewhere
+    fn home_text(&self, text: &str) -> String {
+        self.home.replace_all(text, |caps: &Captures| format!("{}{}", &caps[1], self.pseudonym("user", &caps[2]))).into_owned()
+    }
+
+    fn home_path(&self, path: &Path) -> PathBuf {
+        match path.to_str() {
+            Some(text) => PathBuf::from(self.home_text(text)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn remember(&self, pseudonym: &str, value: &str) {
+        let mut vault = self.vault.lock().unwrap();
+        if vault.entries.contains_key(pseudonym) {
+            return;
+        }
+        let entry = VaultEntry { pseudonym: pseudonym.to_string(), value: value.to_string() };
+        let written = serde_json::to_string(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(vault.file, "{}", line)?));
+        if let Err(e) = written {
+            warn!("Failed to record pseudonym {} in the vault: {}", pseudonym, e);
+        }
+        vault.entries.insert(entry.pseudonym, entry.value);
+    }
+}
+
+fn load_or_create_key(path: &Path) -> Result<Vec<u8>> {
+    if path.exists() {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read privacy key {:?}", path))?;
+        let key = hex::decode(content.trim()).with_context(|| format!("Privacy key {:?} is not hex", path))?;
+        if key.len() < MIN_KEY_BYTES {
+            return Err(anyhow!("Privacy key {:?} is shorter than {} bytes", path, MIN_KEY_BYTES));
+        }
+        return Ok(key);
+    }
+    // Two v4 UUIDs: 244 random bits from the OS generator
+    let key: Vec<u8> = [Uuid::new_v4(), Uuid::new_v4()].iter().flat_map(|uuid| *uuid.as_bytes()).collect();
+    let mut file = open_private(path).with_context(|| format!("Failed to create privacy key {:?}", path))?;
+    writeln!(file, "{}", hex::encode(&key))?;
+    info!("Generated a new privacy key at {:?}", path);
+    Ok(key)
+}
+
+fn read_vault(path: &Path) -> Result<HashMap<String, String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read pseudonym vault {:?}", path)),
+    };
+    let mut entries = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match serde_json::from_str::<VaultEntry>(&line) {
+            Ok(entry) => {
+                entries.insert(entry.pseudonym, entry.value);
+            }
+            Err(e) if !line.trim().is_empty() => warn!("Skipping malformed vault entry in {:?}: {}", path, e),
+            Err(_) => {}
+        }
+    }
+    Ok(entries)
+}
+
+// Appends, creating the file readable by its owner only
+fn open_private(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    Ok(options.open(path)?)
+}
+
+// RFC 2104 over SHA-256
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK_SIZE];
+    if key.len() > HMAC_BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::monitor::{NetworkProtocol, ProcessInfo, Verdict};
+
+    #[test]
+    fn test_pseudonymization() {
+        // RFC 4231 test case 2
+        assert_eq!(hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        let dir = std::env::temp_dir().join(format!("flux-privacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = PrivacyConfig {
+            key_path: dir.join("privacy.key"),
+            vault_path: dir.join("vault.jsonl"),
+            usernames: true,
+            home_paths: true,
+            remote_ips: true,
+        };
+        let privacy = Pseudonymizer::open(config.clone()).unwrap();
+
+        let event = |event_type| SecurityEvent {
+            id: "e1".to_string(),
+            timestamp: Utc::now(),
+            event_type,
+            process_info: ProcessInfo {
+                pid: 42,
+                path: PathBuf::from("/home/alice/bin/tool"),
+                parent_pid: None,
+                user_id: 1000,
+                executable_hash: None,
+                command_line: Some("tool --out /home/alice/report.txt".to_string()),
+            },
+            verdict: Verdict::Allow,
+            policy_reason: "connection to 203.0.113.9".to_string(),
+        };
+        let mut connection = event(SecurityEventType::NetworkConnection {
+            remote_ip: "203.0.113.9".to_string(),
+            remote_port: 443,
+            domain: None,
+            protocol: NetworkProtocol::Tcp,
+        });
+        privacy.pseudonymize_event(&mut connection);
+        let mut login = event(SecurityEventType::Authentication {
+            user: "alice".to_string(),
+            service: "sshd".to_string(),
+            success: true,
+            remote_host: Some("203.0.113.9".to_string()),
+        });
+        privacy.pseudonymize_event(&mut login);
+
+        let user = privacy.pseudonym("user", "alice");
+        let ip = privacy.pseudonym("ip", "203.0.113.9");
+        assert!(user.starts_with("user-") && ip.starts_with("ip-"));
+        assert_eq!(connection.process_info.path, PathBuf::from(format!("/home/{}/bin/tool", user)));
+        assert_eq!(connection.process_info.command_line.unwrap(), format!("tool --out /home/{}/report.txt", user));
+        assert_eq!(connection.policy_reason, format!("connection to {}", ip));
+        let SecurityEventType::NetworkConnection { remote_ip, .. } = &connection.event_type else { unreachable!() };
+        assert_eq!(remote_ip, &ip);
+        let SecurityEventType::Authentication { user: login_user, remote_host, .. } = &login.event_type else { unreachable!() };
+        assert_eq!((login_user, remote_host.as_ref()), (&user, Some(&ip)));
+
+        // Another process with the same key and vault re-identifies them
+        drop(privacy);
+        let reader = Pseudonymizer::open(config.clone()).unwrap();
+        assert_eq!(reader.pseudonym("user", "alice"), user);
+        let originals = reader.reidentify(&[user.clone(), ip.clone(), "user-0000000000000000".to_string()]).unwrap();
+        assert_eq!(originals[&user].as_deref(), Some("alice"));
+        assert_eq!(originals[&ip].as_deref(), Some("203.0.113.9"));
+        assert_eq!(originals["user-0000000000000000"], None);
+
+        // A different key gives unrelated pseudonyms
+        let other = Pseudonymizer::open(PrivacyConfig { key_path: dir.join("other.key"), ..config }).unwrap();
+        assert_ne!(other.pseudonym("user", "alice"), user);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// Capability numbers from linux/capability.h
+const CAPABILITIES: &[(&str, u32)] = &[
+    ("CAP_CHOWN", 0),
+    ("CAP_DAC_OVERRIDE", 1),
+    ("CAP_DAC_READ_SEARCH", 2),
+    ("CAP_FOWNER", 3),
+    ("CAP_FSETID", 4),
+    ("CAP_KILL", 5),
+    ("CAP_SETGID", 6),
+    ("CAP_SETUID", 7),
+    ("CAP_SETPCAP", 8),
+    ("CAP_LINUX_IMMUTABLE", 9),
+    ("CAP_NET_BIND_SERVICE", 10),
+    ("CAP_NET_BROADCAST", 11),
+    ("CAP_NET_ADMIN", 12),
+    ("CAP_NET_RAW", 13),
+    ("CAP_IPC_LOCK", 14),
+    ("CAP_IPC_OWNER", 15),
+    ("CAP_SYS_MODULE", 16),
+    ("CAP_SYS_RAWIO", 17),
+    ("CAP_SYS_CHROOT", 18),
+    ("CAP_SYS_PTRACE", 19),
+    ("CAP_SYS_PACCT", 20),
+    ("CAP_SYS_ADMIN", 21),
+    ("CAP_SYS_BOOT", 22),
+    ("CAP_SYS_NICE", 23),
+    ("CAP_SYS_RESOURCE", 24),
+    ("CAP_SYS_TIME", 25),
+    ("CAP_SYS_TTY_CONFIG", 26),
+    ("CAP_MKNOD", 27),
+    ("CAP_LEASE", 28),
+    ("CAP_AUDIT_WRITE", 29),
+    ("CAP_AUDIT_CONTROL", 30),
+    ("CAP_SETFCAP", 31),
+    ("CAP_MAC_OVERRIDE", 32),
+    ("CAP_MAC_ADMIN", 33),
+    ("CAP_SYSLOG", 34),
+    ("CAP_WAKE_ALARM", 35),
+    ("CAP_BLOCK_SUSPEND", 36),
+    ("CAP_AUDIT_READ", 37),
+    ("CAP_PERFMON", 38),
+    ("CAP_BPF", 39),
+    ("CAP_CHECKPOINT_RESTORE", 40),
+];
+
+// Who the daemon runs as. Privileges are dropped once the handles that need
+// root are open and before any other thread starts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivilegeConfig {
+    pub user: String,
+    // Defaults to the user's primary group
+    pub group: Option<String>,
+    // Kept in the permitted and effective sets; everything else, including
+    // the bounding set, is dropped. Names with or without the CAP_ prefix.
+    pub retain_capabilities: Vec<String>,
+}
+
+impl Default for PrivilegeConfig {
+    fn default() -> Self {
+        Self {
+            user: "fluxdefense".to_string(),
+            group: None,
+            // Firewall and nftables changes, and reading other users' /proc
+            // entries; everything else is opened before the drop
+            retain_capabilities: vec!["CAP_NET_ADMIN".to_string(), "CAP_SYS_PTRACE".to_string()],
+        }
+    }
+}
+
+impl PrivilegeConfig {
+    // Bit mask of the retained capabilities; unknown names are an error
+    pub fn capability_mask(&self) -> Result<u64> {
+        self.retain_capabilities.iter().try_fold(0u64, |mask, name| {
+            Ok(mask | (1u64 << capability_number(name)?))
+        })
+    }
+}
+
+pub fn capability_number(name: &str) -> Result<u32> {
+    let upper = name.trim().to_uppercase();
+    let full = if upper.starts_with("CAP_") { upper } else { format!("CAP_{}", upper) };
+    CAPABILITIES.iter()
+        .find(|(known, _)| *known == full)
+        .map(|(_, number)| *number)
+        .ok_or_else(|| anyhow!("Unknown capability: {}", name))
+}
+
+pub fn capability_names(mask: u64) -> Vec<String> {
+    CAPABILITIES.iter()
+        .filter(|(_, number)| mask & (1u64 << number) != 0)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+// Whether the named capability is in this process's effective set
+pub fn has_capability(name: &str) -> bool {
+    match (capability_number(name), effective_capabilities()) {
+        (Ok(number), Ok(mask)) => mask & (1u64 << number) != 0,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivilegeReport {
+    pub user: String,
+    pub uid: u32,
+    pub gid: u32,
+    // What the kernel reports as effective afterwards
+    pub capabilities: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{drop_privileges, effective_capabilities, hand_over};
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_privileges(_config: &PrivilegeConfig) -> Result<PrivilegeReport> {
+    Err(anyhow!("Dropping privileges is only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hand_over(_config: &PrivilegeConfig, _paths: &[std::path::PathBuf]) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn effective_capabilities() -> Result<u64> {
+    Err(anyhow!("Capabilities are only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::path::PathBuf;
+    use anyhow::{anyhow, bail, Context, Result};
+    use tracing::{info, warn};
+
+    use super::{capability_names, PrivilegeConfig, PrivilegeReport, CAPABILITIES};
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    // Switches to the configured user and group, keeping only the retained
+    // capabilities. Descriptors opened before the call stay usable. Running
+    // as a non-root user already is not an error; there is nothing to drop.
+    // Capability sets and the bounding set are per thread, so this must run
+    // before any other thread starts: one that already exists would keep the
+    // full bounding set and lose the retained capabilities. It fails if any do
+    pub fn drop_privileges(config: &PrivilegeConfig) -> Result<PrivilegeReport> {
+        let mask = config.capability_mask()?;
+        let (uid, primary_gid) = lookup_user(&config.user)?;
+        let gid = match config.group {
+            Some(ref group) => lookup_group(group)?,
+            None => primary_gid,
+        };
+
+        if unsafe { libc::geteuid() } != 0 {
+            warn!("Not running as root, keeping the current user");
+            return Ok(PrivilegeReport {
+                user: config.user.clone(),
+                uid: unsafe { libc::getuid() },
+                gid: unsafe { libc::getgid() },
+                capabilities: capability_names(effective_capabilities()?),
+            });
+        }
+
+        let threads = std::fs::read_dir("/proc/self/task").map(|tasks| tasks.count()).unwrap_or(1);
+        if threads > 1 {
+            bail!("Cannot drop privileges with {} other threads running; drop them before starting any", threads - 1);
+        }
+
+        // Nothing dropped from the bounding set can come back through an exec.
+        // Numbers the running kernel does not know fail with EINVAL and are skipped.
+        for (name, number) in CAPABILITIES {
+            if mask & (1u64 << number) == 0
+                && unsafe { libc::prctl(libc::PR_CAPBSET_DROP, *number as libc::c_ulong, 0, 0, 0) } != 0
+            {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EINVAL) {
+                    return Err(anyhow!("Failed to drop {} from the bounding set: {}", name, err));
+                }
+            }
+        }
+
+        // Keep the permitted set across the uid change; it is narrowed below
+        check(unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) }, "PR_SET_KEEPCAPS")?;
+        check(unsafe { libc::setgroups(1, &gid) }, "setgroups")?;
+        check(unsafe { libc::setresgid(gid, gid, gid) }, "setresgid")?;
+        check(unsafe { libc::setresuid(uid, uid, uid) }, "setresuid")?;
+        check(unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 0, 0, 0, 0) }, "PR_SET_KEEPCAPS")?;
+
+        let header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+        let data = [
+            CapData { effective: mask as u32, permitted: mask as u32, inheritable: 0 },
+            CapData { effective: (mask >> 32) as u32, permitted: (mask >> 32) as u32, inheritable: 0 },
+        ];
+        check(unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) } as libc::c_int, "capset")?;
+
+        // Regaining root must be impossible from here on
+        if unsafe { libc::setuid(0) } == 0 {
+            bail!("Privileges were not dropped: setuid(0) still succeeds");
+        }
+
+        let capabilities = capability_names(effective_capabilities()?);
+        info!("Running as {} (uid {}, gid {}) with {:?}", config.user, uid, gid, capabilities);
+        Ok(PrivilegeReport { user: config.user.clone(), uid, gid, capabilities })
+    }
+
+    // Gives the configured user the files and directories it writes once
+    // privileges are dropped; paths that do not exist are skipped
+    pub fn hand_over(config: &PrivilegeConfig, paths: &[PathBuf]) -> Result<()> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(());
+        }
+        let (uid, primary_gid) = lookup_user(&config.user)?;
+        let gid = match config.group {
+            Some(ref group) => lookup_group(group)?,
+            None => primary_gid,
+        };
+        for path in paths.iter().filter(|path| path.exists()) {
+            std::os::unix::fs::chown(path, Some(uid), Some(gid))
+                .with_context(|| format!("Failed to hand {} to {}", path.display(), config.user))?;
+        }
+        Ok(())
+    }
+
+    fn check(result: libc::c_int, call: &str) -> Result<()> {
+        if result != 0 {
+            return Err(anyhow!("{} failed: {}", call, std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    pub fn effective_capabilities() -> Result<u64> {
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        let value = status.lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .ok_or_else(|| anyhow!("No CapEff in /proc/self/status"))?;
+        u64::from_str_radix(value.trim(), 16).context("Invalid CapEff")
+    }
+
+    fn lookup_user(name: &str) -> Result<(libc::uid_t, libc::gid_t)> {
+        let cname = CString::new(name)?;
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        let mut buffer = vec![0 as libc::c_char; 16384];
+        let rc = unsafe {
+            libc::getpwnam_r(cname.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if rc != 0 || result.is_null() {
+            bail!("Unknown user: {}", name);
+        }
+        Ok((passwd.pw_uid, passwd.pw_gid))
+    }
+
+    fn lookup_group(name: &str) -> Result<libc::gid_t> {
+        let cname = CString::new(name)?;
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::group = std::ptr::null_mut();
+        let mut buffer = vec![0 as libc::c_char; 16384];
+        let rc = unsafe {
+            libc::getgrnam_r(cname.as_ptr(), &mut group, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if rc != 0 || result.is_null() {
+            bail!("Unknown group: {}", name);
+        }
+        Ok(group.gr_gid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_names_round_trip() {
+        let config = PrivilegeConfig {
+            retain_capabilities: vec!["CAP_NET_ADMIN".to_string(), "sys_ptrace".to_string(), "bpf".to_string()],
+            ..Default::default()
+        };
+        let mask = config.capability_mask().unwrap();
+        assert_eq!(mask, (1 << 12) | (1 << 19) | (1 << 39));
+        assert_eq!(capability_names(mask), vec!["CAP_NET_ADMIN", "CAP_SYS_PTRACE", "CAP_BPF"]);
+
+        let config = PrivilegeConfig { retain_capabilities: vec!["CAP_EVERYTHING".to_string()], ..Default::default() };
+        assert!(config.capability_mask().is_err());
+
+        // Only what the daemon needs once its handles are open
+        let mask = PrivilegeConfig::default().capability_mask().unwrap();
+        assert_eq!(capability_names(mask), vec!["CAP_NET_ADMIN", "CAP_SYS_PTRACE"]);
+    }
+}
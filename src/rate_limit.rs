@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Token buckets keyed by string: each key may spend `default_burst` at once
+// and regains `default_rate` tokens per second
+pub struct RateLimiter {
+    buckets: HashMap<String, TokenBucket>,
+    config: RateLimiterConfig,
+}
+
+pub struct RateLimiterConfig {
+    pub default_rate: u32,
+    pub default_burst: u32,
+    pub cleanup_interval: Duration,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_update: Instant,
+    rate: f64,
+    capacity: f64,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimiterConfig) -> Self {
+        Self {
+            buckets: HashMap::new(),
+            config,
+        }
+    }
+    
+    pub fn check_and_update(&mut self, key: &str) -> bool {
+        let now = Instant::now();
+        
+        let bucket = self.buckets.entry(key.to_string()).or_insert_with(|| {
+            TokenBucket {
+                tokens: self.config.default_burst as f64,
+                last_update: now,
+                rate: self.config.default_rate as f64,
+                capacity: self.config.default_burst as f64,
+            }
+        });
+        
+        // Update tokens
+        let elapsed = now.duration_since(bucket.last_update).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.capacity);
+        bucket.last_update = now;
+        
+        // Check if we can consume a token
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+    
+    // How long until `key` has a token again; zero if it has one now
+    pub fn retry_after(&self, key: &str) -> Duration {
+        let Some(bucket) = self.buckets.get(key) else {
+            return Duration::ZERO;
+        };
+        let elapsed = bucket.last_update.elapsed().as_secs_f64();
+        let missing = 1.0 - (bucket.tokens + elapsed * bucket.rate);
+        if missing <= 0.0 || bucket.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing / bucket.rate)
+    }
+    
+    pub fn cleanup_old_buckets(&mut self) {
+        let now = Instant::now();
+        let cutoff = self.config.cleanup_interval;
+        
+        self.buckets.retain(|_, bucket| {
+            now.duration_since(bucket.last_update) < cutoff
+        });
+    }
+}
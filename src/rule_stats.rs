@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::policy::edit::write_atomic;
+
+// Which engine a rule belongs to; rule ids are only unique per engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleEngine {
+    NetworkFilter,
+    Netfilter,
+    BehaviorPattern,
+    Correlation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleHits {
+    pub engine: RuleEngine,
+    pub rule_id: String,
+    pub rule_name: String,
+    // Matches, shadow-mode ones included
+    pub hits: u64,
+    pub last_hit: Option<DateTime<Utc>>,
+    // When counting started for the rule
+    pub tracked_since: DateTime<Utc>,
+    // Last total of a counter the engine keeps itself, e.g. in the kernel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter: Option<u64>,
+}
+
+impl RuleHits {
+    fn new(engine: RuleEngine, rule_id: &str, rule_name: &str) -> Self {
+        Self {
+            engine,
+            rule_id: rule_id.to_string(),
+            rule_name: rule_name.to_string(),
+            hits: 0,
+            last_hit: None,
+            tracked_since: Utc::now(),
+            counter: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnusedRulesReport {
+    pub days: u32,
+    pub cutoff: DateTime<Utc>,
+    // Tracked since before the cutoff without matching after it
+    pub unused: Vec<RuleHits>,
+    // Unmatched so far but tracked for less than `days`
+    pub tracked_too_briefly: Vec<RuleHits>,
+}
+
+// Match counters and last-match times of every rule the engines know
+// about, including rules that never matched. Clones share the counters;
+// with a path they survive restarts
+#[derive(Clone, Default)]
+pub struct RuleStats {
+    rules: Arc<Mutex<HashMap<(RuleEngine, String), RuleHits>>>,
+    path: Option<Arc<PathBuf>>,
+}
+
+impl RuleStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let rules = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str::<Vec<RuleHits>>(&content)
+                .with_context(|| format!("Corrupt rule statistics {:?}", path))?
+                .into_iter()
+                .map(|hits| ((hits.engine, hits.rule_id.clone()), hits))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read rule statistics {:?}", path)),
+        };
+        Ok(Self { rules: Arc::new(Mutex::new(rules)), path: Some(Arc::new(path.to_path_buf())) })
+    }
+
+    // Without a path there is nothing to save
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let content = serde_json::to_string(&self.stats(None))?;
+        write_atomic(path, &content)
+    }
+
+    // Makes the engine's rules exactly `rules` (id, name): new ones start at
+    // zero hits, removed ones are forgotten and the others keep counting
+    pub fn sync<'a, I>(&self, engine: RuleEngine, rules: I)
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let Ok(mut tracked) = self.rules.lock() else { return };
+        let mut current = HashSet::new();
+        for (id, name) in rules {
+            current.insert(id.to_string());
+            tracked.entry((engine, id.to_string()))
+                .and_modify(|hits| hits.rule_name = name.to_string())
+                .or_insert_with(|| RuleHits::new(engine, id, name));
+        }
+        tracked.retain(|(rule_engine, id), _| *rule_engine != engine || current.contains(id));
+    }
+
+    pub fn record(&self, engine: RuleEngine, rule_id: &str, rule_name: &str) {
+        let Ok(mut tracked) = self.rules.lock() else { return };
+        let hits = tracked.entry((engine, rule_id.to_string()))
+            .or_insert_with(|| RuleHits::new(engine, rule_id, rule_name));
+        hits.hits += 1;
+        hits.last_hit = Some(Utc::now());
+    }
+
+    // Takes the running total of a counter kept elsewhere; a total below
+    // the last one means the counter was reset, e.g. by reinstalling the rule
+    pub fn observe_counter(&self, engine: RuleEngine, rule_id: &str, total: u64) {
+        let Ok(mut tracked) = self.rules.lock() else { return };
+        let Some(hits) = tracked.get_mut(&(engine, rule_id.to_string())) else { return };
+        let new = match hits.counter {
+            Some(last) if total >= last => total - last,
+            _ => total,
+        };
+        hits.counter = Some(total);
+        if new > 0 {
+            hits.hits += new;
+            hits.last_hit = Some(Utc::now());
+        }
+    }
+
+    // Most hits first
+    pub fn stats(&self, engine: Option<RuleEngine>) -> Vec<RuleHits> {
+        let Ok(tracked) = self.rules.lock() else { return Vec::new() };
+        let mut stats: Vec<RuleHits> = tracked.values()
+            .filter(|hits| engine.is_none_or(|engine| hits.engine == engine))
+            .cloned()
+            .collect();
+        stats.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.rule_id.cmp(&b.rule_id)));
+        stats
+    }
+
+    // Rules with no match in the last `days` days, longest idle first
+    pub fn unused(&self, days: u32) -> Result<UnusedRulesReport> {
+        let cutoff = Utc::now() - Duration::try_days(days.into()).ok_or_else(|| anyhow!("{} days is too long", days))?;
+        let (mut unused, mut tracked_too_briefly) = (Vec::new(), Vec::new());
+        for hits in self.stats(None) {
+            if hits.last_hit.is_some_and(|last_hit| last_hit >= cutoff) {
+                continue;
+            }
+            if hits.tracked_since <= cutoff {
+                unused.push(hits);
+            } else {
+                tracked_too_briefly.push(hits);
+            }
+        }
+        unused.sort_by_key(|hits| hits.last_hit.unwrap_or(hits.tracked_since));
+        Ok(UnusedRulesReport { days, cutoff, unused, tracked_too_briefly })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_stats() {
+        let dir = std::env::temp_dir().join(format!("flux-rule-stats-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("rule_stats.json");
+        let stats = RuleStats::open(&path).unwrap();
+        stats.sync(RuleEngine::Correlation, [("brute_force", "Brute force"), ("sweep", "Port sweep"), ("old", "Old")]);
+        stats.record(RuleEngine::Correlation, "brute_force", "Brute force");
+        stats.record(RuleEngine::Correlation, "brute_force", "Brute force");
+        // A reload dropping a rule forgets it
+        stats.sync(RuleEngine::Correlation, [("brute_force", "Brute force"), ("sweep", "Port sweep")]);
+
+        stats.sync(RuleEngine::Netfilter, [("block_ssh", "block_ssh")]);
+        stats.observe_counter(RuleEngine::Netfilter, "block_ssh", 5);
+        stats.observe_counter(RuleEngine::Netfilter, "block_ssh", 7);
+        // Reinstalled, so the kernel counts from zero again
+        stats.observe_counter(RuleEngine::Netfilter, "block_ssh", 1);
+
+        let all = stats.stats(None);
+        assert_eq!(all.iter().map(|hits| (hits.rule_id.as_str(), hits.hits)).collect::<Vec<_>>(),
+            vec![("block_ssh", 8), ("brute_force", 2), ("sweep", 0)]);
+
+        // Everything was just registered, so nothing qualifies for 30 days yet
+        let report = stats.unused(30).unwrap();
+        assert!(report.unused.is_empty());
+        assert_eq!(report.tracked_too_briefly.len(), 1);
+
+        stats.save().unwrap();
+        let reopened = RuleStats::open(&path).unwrap();
+        {
+            let mut tracked = reopened.rules.lock().unwrap();
+            for hits in tracked.values_mut() {
+                hits.tracked_since -= Duration::days(60);
+            }
+        }
+        let report = reopened.unused(30).unwrap();
+        assert_eq!(report.unused.iter().map(|hits| hits.rule_id.as_str()).collect::<Vec<_>>(), vec!["sweep"]);
+        assert_eq!(reopened.stats(Some(RuleEngine::Netfilter))[0].counter, Some(1));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
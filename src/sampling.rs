@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::incidents::IncidentSeverity;
+
+// Load is measured over windows this long; each window sets the rate for the next
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Events of one type kept per second before sampling starts
+    #[serde(default = "default_budget")]
+    pub budget_per_sec: u64,
+    // The lowest fraction of low severity events still kept in a storm
+    #[serde(default = "default_min_rate")]
+    pub min_rate: f64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_budget() -> u64 {
+    2000
+}
+
+fn default_min_rate() -> f64 {
+    0.01
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            budget_per_sec: default_budget(),
+            min_rate: default_min_rate(),
+        }
+    }
+}
+
+impl SamplingConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.budget_per_sec == 0 {
+            return Err(anyhow!("sampling.budget_per_sec must be positive"));
+        }
+        if !(self.min_rate > 0.0 && self.min_rate <= 1.0) {
+            return Err(anyhow!("sampling.min_rate must be in (0, 1], got {}", self.min_rate));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TypeSampling {
+    pub event_type: String,
+    // Fraction of low severity events kept right now; medium severity
+    // events are kept at its square root
+    pub rate: f64,
+    // Measured over the last complete window
+    pub events_per_sec: f64,
+    pub seen: u64,
+    pub kept: u64,
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SamplingMetrics {
+    pub enabled: bool,
+    pub budget_per_sec: u64,
+    pub types: Vec<TypeSampling>,
+}
+
+impl SamplingMetrics {
+    pub fn is_sampling(&self) -> bool {
+        self.types.iter().any(|sampling| sampling.rate < 1.0)
+    }
+}
+
+struct TypeState {
+    window_start: Instant,
+    window_count: u64,
+    rate: f64,
+    events_per_sec: f64,
+    // Fractional keeps owed, so a rate of 0.25 keeps exactly every fourth event
+    low_credit: f64,
+    medium_credit: f64,
+    seen: u64,
+    kept: u64,
+    dropped: u64,
+}
+
+impl TypeState {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            window_count: 0,
+            rate: 1.0,
+            events_per_sec: 0.0,
+            low_credit: 0.0,
+            medium_credit: 0.0,
+            seen: 0,
+            kept: 0,
+            dropped: 0,
+        }
+    }
+}
+
+// Decides per event type which events go through when a storm (a build
+// spawning thousands of processes, say) outruns the budget. Each type is
+// throttled on its own, to the fraction that fits the budget at the last
+// window's rate. High and critical severity events are always kept
+pub struct AdaptiveSampler {
+    config: Mutex<SamplingConfig>,
+    types: Mutex<HashMap<&'static str, TypeState>>,
+}
+
+impl Default for AdaptiveSampler {
+    fn default() -> Self {
+        Self::new(SamplingConfig::default())
+    }
+}
+
+impl AdaptiveSampler {
+    pub fn new(config: SamplingConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            types: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn configure(&self, config: SamplingConfig) {
+        if let Ok(mut current) = self.config.lock() {
+            *current = config;
+        }
+    }
+
+    pub fn admit(&self, event_type: &'static str, severity: IncidentSeverity) -> bool {
+        self.admit_at(event_type, severity, Instant::now())
+    }
+
+    fn admit_at(&self, event_type: &'static str, severity: IncidentSeverity, now: Instant) -> bool {
+        let Some(config) = self.config.lock().ok().map(|config| config.clone()) else { return true };
+        let Ok(mut types) = self.types.lock() else { return true };
+        let state = types.entry(event_type).or_insert_with(|| TypeState::new(now));
+
+        let elapsed = now.saturating_duration_since(state.window_start);
+        if elapsed >= WINDOW {
+            state.events_per_sec = state.window_count as f64 / elapsed.as_secs_f64();
+            let rate = if !config.enabled || state.events_per_sec <= config.budget_per_sec as f64 {
+                1.0
+            } else {
+                (config.budget_per_sec as f64 / state.events_per_sec).max(config.min_rate)
+            };
+            if rate < 1.0 && state.rate >= 1.0 {
+                warn!("Sampling {} events: {:.0}/s against a budget of {}/s, keeping {:.1}% of low severity events",
+                      event_type, state.events_per_sec, config.budget_per_sec, rate * 100.0);
+            } else if rate >= 1.0 && state.rate < 1.0 {
+                info!("{} events are no longer sampled ({:.0}/s)", event_type, state.events_per_sec);
+            }
+            state.rate = rate;
+            state.window_start = now;
+            state.window_count = 0;
+        }
+        state.window_count += 1;
+        state.seen += 1;
+
+        let keep = match severity {
+            IncidentSeverity::High | IncidentSeverity::Critical => true,
+            IncidentSeverity::Medium => take_credit(&mut state.medium_credit, state.rate.sqrt()),
+            IncidentSeverity::Low => take_credit(&mut state.low_credit, state.rate),
+        };
+        if keep {
+            state.kept += 1;
+        } else {
+            state.dropped += 1;
+        }
+        keep
+    }
+
+    pub fn metrics(&self) -> SamplingMetrics {
+        let config = self.config.lock().map(|config| config.clone()).unwrap_or_default();
+        let mut types: Vec<TypeSampling> = self.types.lock()
+            .map(|types| types.iter().map(|(event_type, state)| TypeSampling {
+                event_type: event_type.to_string(),
+                rate: state.rate,
+                events_per_sec: state.events_per_sec,
+                seen: state.seen,
+                kept: state.kept,
+                dropped: state.dropped,
+            }).collect())
+            .unwrap_or_default();
+        types.sort_by(|a, b| a.event_type.cmp(&b.event_type));
+        SamplingMetrics {
+            enabled: config.enabled,
+            budget_per_sec: config.budget_per_sec,
+            types,
+        }
+    }
+}
+
+fn take_credit(credit: &mut f64, rate: f64) -> bool {
+    *credit += rate;
+    if *credit >= 1.0 {
+        *credit -= 1.0;
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_sampling() {
+        let sampler = AdaptiveSampler::new(SamplingConfig { enabled: true, budget_per_sec: 100, min_rate: 0.01 });
+        let start = Instant::now();
+
+        // A storm of 400 events in the first window sets the next window to a quarter
+        for _ in 0..400 {
+            assert!(sampler.admit_at("file_execution", IncidentSeverity::Low, start));
+        }
+        let next = start + WINDOW;
+        let kept = (0..400).filter(|_| sampler.admit_at("file_execution", IncidentSeverity::Low, next)).count();
+        assert_eq!(kept, 100);
+        // Medium severity keeps the square root of the rate, high never drops
+        let medium = (0..100).filter(|_| sampler.admit_at("file_execution", IncidentSeverity::Medium, next)).count();
+        assert_eq!(medium, 50);
+        assert!((0..100).all(|_| sampler.admit_at("file_execution", IncidentSeverity::Critical, next)));
+        // Other types are throttled on their own
+        assert!(sampler.admit_at("network_connection", IncidentSeverity::Low, next));
+
+        let metrics = sampler.metrics();
+        assert!(metrics.is_sampling());
+        let execution = &metrics.types[0];
+        assert_eq!(execution.event_type, "file_execution");
+        assert_eq!(execution.rate, 0.25);
+        assert_eq!(execution.seen, 1000);
+        assert_eq!(execution.dropped, 350);
+
+        // The storm has passed
+        let quiet = next + WINDOW * 10;
+        assert!(sampler.admit_at("file_execution", IncidentSeverity::Low, quiet));
+        assert_eq!(sampler.metrics().types[0].rate, 1.0);
+    }
+}
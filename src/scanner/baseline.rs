@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use walkdir::WalkDir;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+
+use crate::policy::FilePolicy;
+use super::{file_times, is_executable};
+use super::packages::package_owners;
+
+pub const DEFAULT_MANIFEST_PATH: &str = "/var/lib/fluxdefense/baseline.json";
+
+// Where installed executables live; package databases add the rest
+#[cfg(target_os = "macos")]
+const DEFAULT_ROOTS: &[&str] = &[
+    "/bin", "/sbin", "/usr/bin", "/usr/sbin", "/usr/libexec", "/usr/local/bin", "/opt/homebrew/bin", "/Applications",
+];
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_ROOTS: &[&str] = &[
+    "/bin", "/sbin", "/usr/bin", "/usr/sbin", "/usr/libexec", "/usr/local/bin", "/usr/local/sbin", "/opt",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub sha256: String,
+    pub size: u64,
+    // Nanoseconds since the epoch
+    pub mtime_ns: i64,
+    pub ctime_ns: i64,
+    pub package: Option<String>,
+    pub signer: Option<String>,
+}
+
+// Every executable the baseline allowed, so an update knows which policy
+// entries it owns and which files it can skip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineManifest {
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub roots: Vec<PathBuf>,
+    pub entries: BTreeMap<PathBuf, BaselineEntry>,
+}
+
+impl BaselineManifest {
+    // None when no baseline has been built yet
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline {}", path.display()))?;
+        Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Invalid baseline {}", path.display()))?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+        info!("Baseline saved to {}", path.display());
+        Ok(())
+    }
+
+    pub fn hashes(&self) -> HashSet<&str> {
+        self.entries.values().map(|entry| entry.sha256.as_str()).collect()
+    }
+
+    pub fn signers(&self) -> HashSet<&str> {
+        self.entries.values().filter_map(|entry| entry.signer.as_deref()).collect()
+    }
+
+    // Replaces what `previous` contributed to the policy with this baseline's
+    // hashes and signers; entries added by hand are kept
+    pub fn apply_to(&self, policy: &mut FilePolicy, previous: Option<&BaselineManifest>) {
+        let current = self.hashes();
+        let signers = self.signers();
+        if let Some(previous) = previous {
+            for hash in previous.hashes().difference(&current) {
+                policy.remove_allowed_hash(hash);
+            }
+            for signer in previous.signers().difference(&signers) {
+                policy.remove_allowed_signer(signer);
+            }
+        }
+        for hash in current {
+            policy.add_allowed_hash(hash.to_string());
+        }
+        for signer in signers {
+            policy.add_allowed_signer(signer.to_string());
+        }
+    }
+}
+
+// A fresh policy allows executables by hash only: trusting whole directories
+// would let a replaced binary in /usr/bin run
+pub fn empty_policy() -> FilePolicy {
+    let mut policy = FilePolicy::default();
+    policy.system_paths.clear();
+    policy.trusted_directories.clear();
+    policy
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BaselineChanges {
+    pub added: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub unchanged: usize,
+    // Files that could not be read, with the error
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BaselineOptions {
+    pub roots: Vec<PathBuf>,
+    // Also include executables the package manager installed outside the roots
+    pub include_packages: bool,
+    // Hash everything again instead of trusting unchanged size and times
+    pub rehash: bool,
+}
+
+impl Default for BaselineOptions {
+    fn default() -> Self {
+        Self {
+            roots: DEFAULT_ROOTS.iter().map(PathBuf::from).collect(),
+            include_packages: true,
+            rehash: false,
+        }
+    }
+}
+
+pub struct BaselineBuilder {
+    options: BaselineOptions,
+}
+
+impl BaselineBuilder {
+    pub fn new(options: BaselineOptions) -> Self {
+        Self { options }
+    }
+
+    // Fingerprints every executable under the roots; with a previous manifest
+    // only new and modified files are hashed
+    pub fn build(&self, previous: Option<&BaselineManifest>) -> Result<(BaselineManifest, BaselineChanges)> {
+        let roots = self.roots();
+        let owners: HashMap<PathBuf, String> = if self.options.include_packages {
+            package_owners().into_iter()
+                .filter_map(|(path, package)| Some((fs::canonicalize(&path).ok()?, package)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        // Canonical paths, so /bin/ls and /usr/bin/ls on merged-/usr systems are one file
+        let mut candidates: HashSet<PathBuf> = HashSet::new();
+        for root in &roots {
+            for entry in WalkDir::new(root).into_iter().filter_map(|entry| entry.ok()) {
+                if entry.file_type().is_file() {
+                    if let Ok(path) = fs::canonicalize(entry.path()) {
+                        candidates.insert(path);
+                    }
+                }
+            }
+        }
+        candidates.extend(owners.keys().cloned());
+
+        let mut manifest = BaselineManifest {
+            created_at: previous.map(|previous| previous.created_at).unwrap_or_else(Utc::now),
+            updated_at: Utc::now(),
+            roots: roots.clone(),
+            entries: BTreeMap::new(),
+        };
+        let mut changes = BaselineChanges::default();
+
+        for path in candidates {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() && is_executable(&metadata) => metadata,
+                _ => continue,
+            };
+            let (mtime_ns, ctime_ns) = file_times(&metadata);
+            let old = previous.and_then(|previous| previous.entries.get(&path));
+
+            let sha256 = match old {
+                Some(old) if !self.options.rehash && old.size == metadata.len() && old.mtime_ns == mtime_ns && old.ctime_ns == ctime_ns => {
+                    old.sha256.clone()
+                }
+                _ => match hash_file(&path) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        changes.errors.push(format!("{}: {}", path.display(), e));
+                        continue;
+                    }
+                },
+            };
+
+            let entry = BaselineEntry {
+                sha256,
+                size: metadata.len(),
+                mtime_ns,
+                ctime_ns,
+                package: owners.get(&path).cloned(),
+                signer: signer(&path),
+            };
+            match old {
+                None => changes.added.push(path.clone()),
+                Some(old) if old.sha256 != entry.sha256 => changes.changed.push(path.clone()),
+                Some(_) => changes.unchanged += 1,
+            }
+            manifest.entries.insert(path, entry);
+        }
+
+        if let Some(previous) = previous {
+            changes.removed = previous.entries.keys()
+                .filter(|path| !manifest.entries.contains_key(*path))
+                .cloned()
+                .collect();
+        }
+        changes.added.sort();
+        changes.changed.sort();
+        changes.removed.sort();
+
+        info!("Baseline has {} executables: {} added, {} changed, {} removed",
+              manifest.entries.len(), changes.added.len(), changes.changed.len(), changes.removed.len());
+        Ok((manifest, changes))
+    }
+
+    // Existing roots, canonicalized, without those inside another root
+    fn roots(&self) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = self.options.roots.iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .collect();
+        roots.sort();
+        roots.dedup();
+        let all = roots.clone();
+        roots.retain(|root| !all.iter().any(|other| other != root && root.starts_with(other)));
+        debug!("Baseline roots: {:?}", roots);
+        roots
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(target_os = "macos")]
+fn signer(path: &Path) -> Option<String> {
+    super::code_signature_info(path).1
+        .map(|signature| signature.authority)
+        .filter(|authority| !authority.is_empty())
+}
+
+// Linux executables carry no code signature; the owning package is recorded instead
+#[cfg(not(target_os = "macos"))]
+fn signer(_path: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn write_executable(path: &Path, content: &[u8]) {
+        fs::write(path, content).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_build_and_incremental_update() {
+        let dir = std::env::temp_dir().join(format!("flux-baseline-{}", std::process::id()));
+        let bin = dir.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        write_executable(&bin.join("tool"), b"#!/bin/sh\necho tool\n");
+        write_executable(&bin.join("old"), b"#!/bin/sh\necho old\n");
+        fs::write(bin.join("README"), b"not executable").unwrap();
+
+        let builder = BaselineBuilder::new(BaselineOptions {
+            roots: vec![bin.clone()],
+            include_packages: false,
+            rehash: false,
+        });
+        let (first, changes) = builder.build(None).unwrap();
+        assert_eq!(first.entries.len(), 2);
+        assert_eq!(changes.added.len(), 2);
+
+        let mut policy = empty_policy();
+        policy.add_allowed_hash("f".repeat(64));
+        first.apply_to(&mut policy, None);
+        assert_eq!(policy.allowed_hashes.len(), 3);
+        assert!(policy.trusted_directories.is_empty());
+
+        // A patched tool, a removed binary and a new one
+        write_executable(&bin.join("tool"), b"#!/bin/sh\necho patched tool\n");
+        fs::remove_file(bin.join("old")).unwrap();
+        write_executable(&bin.join("new"), b"#!/bin/sh\necho new\n");
+
+        let (second, changes) = builder.build(Some(&first)).unwrap();
+        let bin = fs::canonicalize(&bin).unwrap();
+        assert_eq!(changes.added, vec![bin.join("new")]);
+        assert_eq!(changes.changed, vec![bin.join("tool")]);
+        assert_eq!(changes.removed, vec![bin.join("old")]);
+        assert_eq!(second.created_at, first.created_at);
+
+        second.apply_to(&mut policy, Some(&first));
+        let old_hash = &first.entries[&bin.join("old")].sha256;
+        assert!(!policy.allowed_hashes.contains(old_hash));
+        assert!(policy.allowed_hashes.contains(&"f".repeat(64)));
+        assert!(policy.allowed_hashes.contains(&second.entries[&bin.join("tool")].sha256));
+        assert_eq!(policy.allowed_hashes.len(), 3);
+
+        let (_, changes) = builder.build(Some(&second)).unwrap();
+        assert_eq!((changes.added.len(), changes.changed.len(), changes.unchanged), (0, 0, 2));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use walkdir::WalkDir;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+use tracing::{info, debug};
+
+use super::{is_executable, load_hash_list};
+use super::yara::YaraRules;
+
+// Files above this are hashed but not matched against YARA rules
+const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_PROGRESS_INTERVAL: usize = 250;
+pub(crate) const TEMP_DIRECTORIES: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+
+// Reputation source for hashes missing from the local list; returns the reason
+// when the hash is known to be malicious
+pub type HashLookup = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub max_depth: Option<usize>,
+    pub max_file_size: u64,
+    pub follow_symlinks: bool,
+    // Emit progress every this many files, besides on findings and completion
+    pub progress_interval: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            follow_symlinks: false,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FindingReason {
+    KnownBadHash { reason: String },
+    YaraMatch { rule: String, tags: Vec<String> },
+    ExecutableInTempDirectory,
+    HiddenExecutable,
+}
+
+impl FindingReason {
+    // Hash and rule hits are detections; the rest only merit a look
+    pub fn is_malicious(&self) -> bool {
+        matches!(self, FindingReason::KnownBadHash { .. } | FindingReason::YaraMatch { .. })
+    }
+}
+
+impl fmt::Display for FindingReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingReason::KnownBadHash { reason } => write!(f, "known-bad hash: {}", reason),
+            FindingReason::YaraMatch { rule, tags } if tags.is_empty() => write!(f, "YARA rule {}", rule),
+            FindingReason::YaraMatch { rule, tags } => write!(f, "YARA rule {} ({})", rule, tags.join(", ")),
+            FindingReason::ExecutableInTempDirectory => write!(f, "executable in a temporary directory"),
+            FindingReason::HiddenExecutable => write!(f, "hidden executable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanFinding {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+    pub reasons: Vec<FindingReason>,
+}
+
+impl ScanFinding {
+    pub fn is_malicious(&self) -> bool {
+        self.reasons.iter().any(FindingReason::is_malicious)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanProgress {
+    pub scan_id: String,
+    pub root: PathBuf,
+    pub files_scanned: usize,
+    pub bytes_scanned: u64,
+    pub findings: usize,
+    pub current_path: Option<PathBuf>,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub scan_id: String,
+    pub root: PathBuf,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub files_scanned: usize,
+    pub bytes_scanned: u64,
+    // Unreadable entries, with the error
+    pub errors: Vec<String>,
+    pub yara_rules: usize,
+    pub known_bad_hashes: usize,
+    pub findings: Vec<ScanFinding>,
+}
+
+// On-demand scan of a directory tree: every regular file is hashed and checked
+// against known-bad hashes, an optional reputation lookup and YARA rules, and
+// executables in suspicious places are flagged
+pub struct DirectoryScanner {
+    options: ScanOptions,
+    rules: Option<YaraRules>,
+    known_bad: HashSet<String>,
+    lookup: Option<HashLookup>,
+}
+
+impl DirectoryScanner {
+    pub fn new(options: ScanOptions) -> Self {
+        Self {
+            options,
+            rules: None,
+            known_bad: HashSet::new(),
+            lookup: None,
+        }
+    }
+
+    pub fn set_rules(&mut self, rules: YaraRules) {
+        self.rules = Some(rules);
+    }
+
+    // A rules file, or a directory of them
+    pub fn load_rules(&mut self, path: &Path) -> Result<usize> {
+        let rules = if path.is_dir() { YaraRules::load_dir(path)? } else { YaraRules::load(path)? };
+        let count = rules.len();
+        self.rules = Some(rules);
+        Ok(count)
+    }
+
+    pub fn load_known_bad(&mut self, path: &Path) -> Result<usize> {
+        let hashes = load_hash_list(path)?;
+        let count = hashes.len();
+        self.known_bad.extend(hashes);
+        Ok(count)
+    }
+
+    pub fn set_lookup(&mut self, lookup: HashLookup) {
+        self.lookup = Some(lookup);
+    }
+
+    pub fn scan<F>(&self, root: &Path, mut progress: F) -> Result<ScanReport>
+    where
+        F: FnMut(&ScanProgress),
+    {
+        // Absolute paths, so locations like /tmp are recognised from relative roots
+        let root = &fs::canonicalize(root)
+            .map_err(|e| anyhow!("Cannot scan {}: {}", root.display(), e))?;
+
+        let mut report = ScanReport {
+            scan_id: Uuid::new_v4().to_string(),
+            root: root.to_path_buf(),
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            files_scanned: 0,
+            bytes_scanned: 0,
+            errors: Vec::new(),
+            yara_rules: self.rules.as_ref().map(YaraRules::len).unwrap_or(0),
+            known_bad_hashes: self.known_bad.len(),
+            findings: Vec::new(),
+        };
+        info!("Scanning {} ({} YARA rules, {} known-bad hashes)", root.display(), report.yara_rules, report.known_bad_hashes);
+
+        let mut walker = WalkDir::new(root).follow_links(self.options.follow_symlinks);
+        if let Some(depth) = self.options.max_depth {
+            walker = walker.max_depth(depth);
+        }
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report.errors.push(e.to_string());
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            match self.scan_file(path) {
+                Ok((size, finding)) => {
+                    report.files_scanned += 1;
+                    report.bytes_scanned += size;
+                    let found = finding.is_some();
+                    if let Some(finding) = finding {
+                        report.findings.push(finding);
+                    }
+                    if found || report.files_scanned.is_multiple_of(self.options.progress_interval.max(1)) {
+                        progress(&Self::progress(&report, Some(path), false));
+                    }
+                }
+                Err(e) => {
+                    debug!("Failed to scan {}: {}", path.display(), e);
+                    report.errors.push(format!("{}: {}", path.display(), e));
+                }
+            }
+        }
+
+        report.finished_at = Utc::now();
+        progress(&Self::progress(&report, None, true));
+        info!("Scan of {} finished: {} files, {} findings", root.display(), report.files_scanned, report.findings.len());
+        Ok(report)
+    }
+
+    fn progress(report: &ScanReport, current_path: Option<&Path>, done: bool) -> ScanProgress {
+        ScanProgress {
+            scan_id: report.scan_id.clone(),
+            root: report.root.clone(),
+            files_scanned: report.files_scanned,
+            bytes_scanned: report.bytes_scanned,
+            findings: report.findings.len(),
+            current_path: current_path.map(Path::to_path_buf),
+            done,
+        }
+    }
+
+    fn scan_file(&self, path: &Path) -> Result<(u64, Option<ScanFinding>)> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+
+        // Small enough files are read once for both the hash and the rules
+        let mut reasons = Vec::new();
+        let sha256 = if size <= self.options.max_file_size {
+            let mut data = Vec::with_capacity(size as usize);
+            file.read_to_end(&mut data)?;
+            if let Some(ref rules) = self.rules {
+                for m in rules.scan(&data) {
+                    reasons.push(FindingReason::YaraMatch { rule: m.rule, tags: m.tags });
+                }
+            }
+            hex::encode(Sha256::digest(&data))
+        } else {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut file, &mut hasher)?;
+            hex::encode(hasher.finalize())
+        };
+
+        if self.known_bad.contains(&sha256) {
+            reasons.push(FindingReason::KnownBadHash { reason: "Hash is in the known-bad list".to_string() });
+        } else if let Some(reason) = self.lookup.as_ref().and_then(|lookup| lookup(&sha256)) {
+            reasons.push(FindingReason::KnownBadHash { reason });
+        }
+
+        if is_executable(&metadata) {
+            if TEMP_DIRECTORIES.iter().any(|dir| path.starts_with(dir)) {
+                reasons.push(FindingReason::ExecutableInTempDirectory);
+            }
+            let hidden = path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'));
+            if hidden {
+                reasons.push(FindingReason::HiddenExecutable);
+            }
+        }
+
+        let finding = (!reasons.is_empty()).then(|| ScanFinding {
+            path: path.to_path_buf(),
+            sha256,
+            size,
+            reasons,
+        });
+        Ok((size, finding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_reports_findings_and_progress() {
+        let dir = std::env::temp_dir().join(format!("flux-dirscan-{}", std::process::id()));
+        let nested = dir.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.join("clean.txt"), b"nothing to see").unwrap();
+        fs::write(nested.join("dropper.bin"), b"header EVIL_MARKER payload").unwrap();
+        fs::write(dir.join("bad.dat"), b"known bad contents").unwrap();
+        let bad_hash = hex::encode(Sha256::digest(b"known bad contents"));
+        fs::write(dir.join("hashes.txt"), format!("{}  bad.dat\n", bad_hash)).unwrap();
+
+        let mut scanner = DirectoryScanner::new(ScanOptions { progress_interval: 1, ..Default::default() });
+        scanner.set_rules(YaraRules::parse(r#"
+            rule Evil_Marker : dropper {
+                strings:
+                    $a = "EVIL_MARKER"
+                condition:
+                    $a
+            }
+        "#).unwrap());
+        assert_eq!(scanner.load_known_bad(&dir.join("hashes.txt")).unwrap(), 1);
+
+        let mut updates = Vec::new();
+        let report = scanner.scan(&dir, |p| updates.push(p.clone())).unwrap();
+        assert_eq!(report.files_scanned, 4);
+        assert_eq!(report.findings.len(), 2);
+
+        let dropper = report.findings.iter().find(|f| f.path.ends_with("dropper.bin")).unwrap();
+        assert_eq!(dropper.reasons, vec![FindingReason::YaraMatch { rule: "Evil_Marker".to_string(), tags: vec!["dropper".to_string()] }]);
+        let bad = report.findings.iter().find(|f| f.path.ends_with("bad.dat")).unwrap();
+        assert_eq!(bad.sha256, bad_hash);
+        assert!(bad.is_malicious());
+
+        assert_eq!(updates.len(), 5);
+        let last = updates.last().unwrap();
+        assert!(last.done);
+        assert_eq!((last.files_scanned, last.findings), (4, 2));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use super::elf::ElfInfo;
+
+// Compressed or encrypted data sits close to 8 bits per byte; code and text
+// stay well below
+const SECTION_ENTROPY_THRESHOLD: f32 = 7.2;
+const PAYLOAD_ENTROPY_THRESHOLD: f32 = 7.6;
+// Smaller regions are too short for a meaningful entropy estimate
+const MIN_SECTION_SIZE: u64 = 1024;
+const MIN_OVERLAY_SIZE: u64 = 4096;
+const MIN_PAYLOAD_SIZE: u64 = 4096;
+// Packers leave a small stub that unpacks into a much larger image
+const INFLATION_RATIO: u64 = 8;
+
+// Formats that are high-entropy by design
+const COMPRESSED_MAGIC: &[&[u8]] = &[
+    b"\x1f\x8b",             // gzip
+    b"PK\x03\x04",           // zip, jar, docx
+    b"\xfd7zXZ\x00",         // xz
+    b"\x28\xb5\x2f\xfd",     // zstd
+    b"BZh",                  // bzip2
+    b"7z\xbc\xaf\x27\x1c",   // 7z
+    b"Rar!",                 // rar
+    b"\x89PNG",              // png
+    b"\xff\xd8\xff",         // jpeg
+    b"GIF8",                 // gif
+    b"%PDF",                 // pdf
+    b"RIFF",                 // wav, webp, avi
+    b"OggS",                 // ogg
+    b"!<arch>",              // deb
+    b"\xed\xab\xee\xdb",     // rpm
+];
+
+pub fn shannon_entropy(data: &[u8]) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    let entropy: f64 = counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    entropy as f32
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DropperIndicator {
+    HighEntropySection { section: String, entropy: f32 },
+    PackedExecutable { packer: String },
+    InflatedImage { file_size: u64, memory_size: u64 },
+    HighEntropyOverlay { size: u64, entropy: f32 },
+    EncryptedPayload { entropy: f32 },
+}
+
+impl fmt::Display for DropperIndicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropperIndicator::HighEntropySection { section, entropy } => write!(f, "section {} has entropy {:.2}", section, entropy),
+            DropperIndicator::PackedExecutable { packer } => write!(f, "packed with {}", packer),
+            DropperIndicator::InflatedImage { file_size, memory_size } => {
+                write!(f, "{} bytes on disk load as {} bytes", file_size, memory_size)
+            }
+            DropperIndicator::HighEntropyOverlay { size, entropy } => {
+                write!(f, "{} byte overlay with entropy {:.2}", size, entropy)
+            }
+            DropperIndicator::EncryptedPayload { entropy } => write!(f, "unrecognised payload with entropy {:.2}", entropy),
+        }
+    }
+}
+
+// Entropy and size heuristics for a freshly written file, meant to catch
+// packed or encrypted payloads before anything executes them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropperAnalysis {
+    pub size: u64,
+    pub entropy: f32,
+    pub is_elf: bool,
+    pub indicators: Vec<DropperIndicator>,
+}
+
+impl DropperAnalysis {
+    // `data` may be a prefix of a larger file of `file_size` bytes
+    pub fn analyze(data: &[u8], file_size: u64) -> Self {
+        let info = ElfInfo::is_elf(data).then(|| ElfInfo::parse(data).ok()).flatten();
+        let indicators = match info {
+            Some(ref info) => Self::elf_indicators(info, data),
+            None => Self::payload_indicators(data, file_size),
+        };
+        Self {
+            size: file_size,
+            entropy: shannon_entropy(data),
+            is_elf: info.is_some(),
+            indicators,
+        }
+    }
+
+    fn elf_indicators(info: &ElfInfo, data: &[u8]) -> Vec<DropperIndicator> {
+        let mut indicators = Vec::new();
+        if let Some(packer) = info.packer() {
+            indicators.push(DropperIndicator::PackedExecutable { packer: packer.to_string() });
+        }
+
+        // Stripped images have no section table; their loadable segments stand in
+        let regions: Vec<(String, u64, u64)> = if info.sections.iter().any(|section| section.has_file_data()) {
+            info.sections.iter()
+                .filter(|section| section.has_file_data())
+                .map(|section| (section.name.clone(), section.offset, section.size))
+                .collect()
+        } else {
+            info.segments.iter()
+                .filter(|segment| segment.is_load())
+                .enumerate()
+                .map(|(index, segment)| (format!("LOAD[{}]", index), segment.offset, segment.file_size))
+                .collect()
+        };
+        for (name, offset, size) in &regions {
+            if *size < MIN_SECTION_SIZE {
+                continue;
+            }
+            if let Some(bytes) = region(data, *offset, *size) {
+                let entropy = shannon_entropy(bytes);
+                if entropy > SECTION_ENTROPY_THRESHOLD {
+                    indicators.push(DropperIndicator::HighEntropySection { section: name.clone(), entropy });
+                }
+            }
+        }
+
+        let (file_size, memory_size) = info.segments.iter()
+            .filter(|segment| segment.is_load())
+            .fold((0u64, 0u64), |(file, memory), segment| {
+                (file.saturating_add(segment.file_size), memory.saturating_add(segment.memory_size))
+            });
+        if file_size > 0 && memory_size >= file_size.saturating_mul(INFLATION_RATIO) {
+            indicators.push(DropperIndicator::InflatedImage { file_size, memory_size });
+        }
+
+        // Data appended past everything the headers describe
+        let end = regions.iter()
+            .map(|(_, offset, size)| offset.saturating_add(*size))
+            .chain(info.segments.iter().map(|segment| segment.offset.saturating_add(segment.file_size)))
+            .chain([info.section_table_end])
+            .max()
+            .unwrap_or(0);
+        if let Some(overlay) = data.get(end as usize..).filter(|overlay| overlay.len() as u64 >= MIN_OVERLAY_SIZE) {
+            let entropy = shannon_entropy(overlay);
+            if entropy > SECTION_ENTROPY_THRESHOLD {
+                indicators.push(DropperIndicator::HighEntropyOverlay { size: overlay.len() as u64, entropy });
+            }
+        }
+        indicators
+    }
+
+    fn payload_indicators(data: &[u8], file_size: u64) -> Vec<DropperIndicator> {
+        if file_size < MIN_PAYLOAD_SIZE || (data.len() as u64) < MIN_PAYLOAD_SIZE || is_known_compressed(data) {
+            return Vec::new();
+        }
+        let entropy = shannon_entropy(data);
+        if entropy >= PAYLOAD_ENTROPY_THRESHOLD {
+            vec![DropperIndicator::EncryptedPayload { entropy }]
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        !self.indicators.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let indicators: Vec<String> = self.indicators.iter().map(ToString::to_string).collect();
+        indicators.join(", ")
+    }
+}
+
+fn region(data: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start.checked_add(usize::try_from(size).ok()?)?;
+    data.get(start..end.min(data.len())).filter(|bytes| !bytes.is_empty())
+}
+
+fn is_known_compressed(data: &[u8]) -> bool {
+    // MP4 and QuickTime put their marker after the box size
+    COMPRESSED_MAGIC.iter().any(|magic| data.starts_with(magic)) || data.get(4..8) == Some(b"ftyp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::elf::tests::build_elf;
+
+    // Deterministic bytes that look random to an entropy estimate
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dropper_heuristics() {
+        assert_eq!(shannon_entropy(&[0u8; 64]), 0.0);
+        assert!((shannon_entropy(&(0..=255).collect::<Vec<u8>>()) - 8.0).abs() < 1e-4);
+
+        let code: Vec<u8> = b"\x55\x48\x89\xe5\x31\xc0\x5d\xc3".repeat(512);
+        let normal = build_elf(5, Some("/lib64/ld-linux-x86-64.so.2"), &[(".text", 1, &code)]);
+        let analysis = DropperAnalysis::analyze(&normal, normal.len() as u64);
+        assert!(analysis.is_elf && !analysis.is_suspicious(), "{:?}", analysis.indicators);
+
+        let packed_data = noise(8192);
+        let packed = build_elf(7, None, &[("UPX0", 8, b""), ("UPX1", 1, &packed_data)]);
+        let analysis = DropperAnalysis::analyze(&packed, packed.len() as u64);
+        assert_eq!(analysis.indicators[0], DropperIndicator::PackedExecutable { packer: "UPX".to_string() });
+        assert!(matches!(&analysis.indicators[1], DropperIndicator::HighEntropySection { section, .. } if section == "UPX1"));
+        assert_eq!(analysis.indicators.len(), 2);
+
+        // An encrypted blob appended to an otherwise ordinary binary
+        let mut overlaid = normal.clone();
+        overlaid.extend_from_slice(&noise(8192));
+        let analysis = DropperAnalysis::analyze(&overlaid, overlaid.len() as u64);
+        assert!(matches!(analysis.indicators.as_slice(), [DropperIndicator::HighEntropyOverlay { size: 8192, .. }]));
+
+        // Raw encrypted payloads are flagged, archives and short files are not
+        let payload = noise(16384);
+        let analysis = DropperAnalysis::analyze(&payload, payload.len() as u64);
+        assert!(matches!(analysis.indicators.as_slice(), [DropperIndicator::EncryptedPayload { .. }]));
+        assert!(analysis.describe().starts_with("unrecognised payload with entropy"));
+        let mut archive = b"\x1f\x8b\x08\x00".to_vec();
+        archive.extend_from_slice(&payload);
+        assert!(!DropperAnalysis::analyze(&archive, archive.len() as u64).is_suspicious());
+        assert!(!DropperAnalysis::analyze(&payload[..1024], 1024).is_suspicious());
+    }
+}
@@ -0,0 +1,439 @@
+use std::fmt;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use super::directory::TEMP_DIRECTORIES;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2MSB: u8 = 2;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const SHT_NULL: u32 = 0;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+
+// Headers are read from the start of the file; UPX writes its marker right
+// after the program headers
+const PACKER_MAGIC_WINDOW: usize = 4096;
+const MAX_HEADERS: usize = 4096;
+
+// Section names packers leave behind, with the packer they belong to
+const PACKER_SECTIONS: &[(&str, &str)] = &[
+    ("UPX0", "UPX"), ("UPX1", "UPX"), ("UPX2", "UPX"), (".upx", "UPX"),
+    ("MPRESS1", "MPRESS"), ("MPRESS2", "MPRESS"),
+    (".petite", "Petite"), (".aspack", "ASPack"),
+];
+
+// Directories the dynamic loader is installed in; the file name must still look like a loader
+const LOADER_DIRECTORIES: &[&str] = &["/lib", "/lib32", "/lib64", "/usr/lib", "/usr/lib32", "/usr/lib64", "/nix/store"];
+const LOADER_NAMES: &[&str] = &["ld-linux", "ld-musl-", "ld64.so", "ld.so"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElfSegment {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+}
+
+impl ElfSegment {
+    pub fn is_load(&self) -> bool {
+        self.kind == PT_LOAD
+    }
+
+    fn is_writable_executable(&self) -> bool {
+        self.is_load() && self.flags & PF_W != 0 && self.flags & PF_X != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElfSection {
+    pub name: String,
+    pub kind: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl ElfSection {
+    // .bss and the like take no space in the file
+    pub fn has_file_data(&self) -> bool {
+        self.kind != SHT_NOBITS && self.kind != SHT_NULL
+    }
+}
+
+// The parts of an ELF image that matter for triage. Section data beyond the
+// buffer (files are read up to a size limit) is treated as absent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElfInfo {
+    pub is_64bit: bool,
+    pub machine: u16,
+    pub segments: Vec<ElfSegment>,
+    pub sections: Vec<ElfSection>,
+    // Where the section header table ends; usually the end of the file
+    pub section_table_end: u64,
+    pub interpreter: Option<String>,
+    pub has_symbol_table: bool,
+    pub upx_marker: bool,
+}
+
+impl ElfInfo {
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(ELF_MAGIC)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if !Self::is_elf(data) {
+            return Err(anyhow!("Not an ELF file"));
+        }
+        let reader = Reader {
+            data,
+            is_64bit: data.get(4) == Some(&ELFCLASS64),
+            big_endian: data.get(5) == Some(&ELFDATA2MSB),
+        };
+        let wide = reader.is_64bit;
+
+        let machine = reader.u16(18)?;
+        let (phoff, shoff) = if wide {
+            (reader.u64(32)?, reader.u64(40)?)
+        } else {
+            (reader.u32(28)? as u64, reader.u32(32)? as u64)
+        };
+        let base = if wide { 54 } else { 42 };
+        let phentsize = reader.u16(base)? as u64;
+        let phnum = (reader.u16(base + 2)? as usize).min(MAX_HEADERS);
+        let shentsize = reader.u16(base + 4)? as u64;
+        let shnum = (reader.u16(base + 6)? as usize).min(MAX_HEADERS);
+        let shstrndx = reader.u16(base + 8)? as usize;
+
+        let mut segments = Vec::with_capacity(phnum);
+        for index in 0..phnum {
+            let at = reader.entry(phoff, index, phentsize)?;
+            let segment = if wide {
+                ElfSegment {
+                    kind: reader.u32(at)?,
+                    flags: reader.u32(at + 4)?,
+                    offset: reader.u64(at + 8)?,
+                    file_size: reader.u64(at + 32)?,
+                    memory_size: reader.u64(at + 40)?,
+                }
+            } else {
+                ElfSegment {
+                    kind: reader.u32(at)?,
+                    flags: reader.u32(at + 24)?,
+                    offset: reader.u32(at + 4)? as u64,
+                    file_size: reader.u32(at + 16)? as u64,
+                    memory_size: reader.u32(at + 20)? as u64,
+                }
+            };
+            segments.push(segment);
+        }
+
+        let interpreter = segments.iter()
+            .find(|segment| segment.kind == PT_INTERP)
+            .and_then(|segment| reader.bytes(segment.offset, segment.file_size))
+            .map(|bytes| String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string());
+
+        // (name offset, type, offset, size) per section; the table sits at the
+        // end of the file, past the buffer for large binaries
+        let headers: Vec<(u32, u32, u64, u64)> = (0..shnum)
+            .map(|index| {
+                let at = reader.entry(shoff, index, shentsize)?;
+                Ok(if wide {
+                    (reader.u32(at)?, reader.u32(at + 4)?, reader.u64(at + 24)?, reader.u64(at + 32)?)
+                } else {
+                    (reader.u32(at)?, reader.u32(at + 4)?, reader.u32(at + 16)? as u64, reader.u32(at + 20)? as u64)
+                })
+            })
+            .collect::<Result<_>>()
+            .unwrap_or_default();
+        let names = headers.get(shstrndx).and_then(|&(_, _, offset, size)| reader.bytes(offset, size));
+        let sections = headers.iter()
+            .map(|&(name, kind, offset, size)| ElfSection {
+                name: names.map(|names| section_name(names, name as usize)).unwrap_or_default(),
+                kind,
+                offset,
+                size,
+            })
+            .collect();
+
+        let window = &data[..data.len().min(PACKER_MAGIC_WINDOW)];
+        Ok(Self {
+            is_64bit: wide,
+            machine,
+            segments,
+            sections,
+            section_table_end: if shnum == 0 { 0 } else { shoff.saturating_add(shentsize.saturating_mul(shnum as u64)) },
+            interpreter,
+            has_symbol_table: headers.iter().any(|&(_, kind, ..)| kind == SHT_SYMTAB),
+            upx_marker: window.windows(4).any(|bytes| bytes == b"UPX!"),
+        })
+    }
+
+    pub fn is_static(&self) -> bool {
+        !self.segments.iter().any(|segment| segment.kind == PT_INTERP || segment.kind == PT_DYNAMIC)
+    }
+
+    pub fn packer(&self) -> Option<&'static str> {
+        let by_section = self.sections.iter()
+            .find_map(|section| PACKER_SECTIONS.iter().find(|(name, _)| *name == section.name).map(|(_, packer)| *packer));
+        by_section.or(self.upx_marker.then_some("UPX"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ElfRedFlag {
+    Packed { packer: String },
+    WritableExecutableSegment,
+    UnusualInterpreter { interpreter: String },
+    StrippedStaticInTempDirectory,
+}
+
+impl ElfRedFlag {
+    // Contribution to the static risk; a single flag stays below the
+    // malicious threshold unless it is strong on its own
+    fn weight(&self) -> f32 {
+        match self {
+            ElfRedFlag::Packed { .. } => 0.3,
+            ElfRedFlag::WritableExecutableSegment => 0.2,
+            ElfRedFlag::UnusualInterpreter { .. } => 0.4,
+            ElfRedFlag::StrippedStaticInTempDirectory => 0.3,
+        }
+    }
+}
+
+impl fmt::Display for ElfRedFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfRedFlag::Packed { packer } => write!(f, "packed with {}", packer),
+            ElfRedFlag::WritableExecutableSegment => write!(f, "writable and executable segment"),
+            ElfRedFlag::UnusualInterpreter { interpreter } => write!(f, "unusual interpreter {}", interpreter),
+            ElfRedFlag::StrippedStaticInTempDirectory => write!(f, "stripped static binary in a temporary directory"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticRisk {
+    pub flags: Vec<ElfRedFlag>,
+    // 0.0 (nothing found) to 1.0
+    pub score: f32,
+}
+
+impl StaticRisk {
+    // Red flags in an ELF image; None for other files and ELF files that
+    // could not be parsed
+    pub fn analyze(data: &[u8], path: &Path) -> Option<Self> {
+        let info = ElfInfo::parse(data).ok()?;
+        let mut flags = Vec::new();
+
+        if let Some(packer) = info.packer() {
+            flags.push(ElfRedFlag::Packed { packer: packer.to_string() });
+        }
+        if info.segments.iter().any(ElfSegment::is_writable_executable) {
+            flags.push(ElfRedFlag::WritableExecutableSegment);
+        }
+        if let Some(ref interpreter) = info.interpreter {
+            if !is_standard_loader(interpreter) {
+                flags.push(ElfRedFlag::UnusualInterpreter { interpreter: interpreter.clone() });
+            }
+        }
+        if info.is_static() && !info.has_symbol_table && TEMP_DIRECTORIES.iter().any(|dir| path.starts_with(dir)) {
+            flags.push(ElfRedFlag::StrippedStaticInTempDirectory);
+        }
+
+        let score = flags.iter().map(ElfRedFlag::weight).sum::<f32>().min(1.0);
+        Some(Self { flags, score })
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    // Reputation runs from 0.0 (malicious) to 1.0 (benign). Static analysis
+    // can only lower it: a clean binary is unknown, not trusted.
+    pub fn reputation_score(&self) -> f32 {
+        0.5 * (1.0 - self.score)
+    }
+
+    pub fn describe(&self) -> String {
+        let flags: Vec<String> = self.flags.iter().map(ToString::to_string).collect();
+        format!("ELF static analysis: {}", flags.join(", "))
+    }
+}
+
+fn is_standard_loader(interpreter: &str) -> bool {
+    let path = Path::new(interpreter);
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    LOADER_DIRECTORIES.iter().any(|dir| path.starts_with(dir))
+        && LOADER_NAMES.iter().any(|loader| name.starts_with(loader))
+}
+
+fn section_name(names: &[u8], offset: usize) -> String {
+    names.get(offset..)
+        .map(|rest| {
+            let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..end]).to_string()
+        })
+        .unwrap_or_default()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64bit: bool,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    // Offset of a table entry; tables pointing outside the file are rejected
+    // so the field offsets added to it cannot overflow
+    fn entry(&self, table: u64, index: usize, size: u64) -> Result<usize> {
+        (index as u64).checked_mul(size)
+            .and_then(|offset| offset.checked_add(table))
+            .and_then(|offset| usize::try_from(offset).ok())
+            .filter(|&offset| offset < self.data.len())
+            .ok_or_else(|| anyhow!("ELF header table outside the file"))
+    }
+
+    fn array<const N: usize>(&self, at: usize) -> Result<[u8; N]> {
+        self.data.get(at..at.saturating_add(N))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Truncated ELF header at offset {}", at))
+    }
+
+    fn u16(&self, at: usize) -> Result<u16> {
+        let bytes = self.array(at)?;
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Result<u32> {
+        let bytes = self.array(at)?;
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn u64(&self, at: usize) -> Result<u64> {
+        let bytes = self.array(at)?;
+        Ok(if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+    }
+
+    fn bytes(&self, offset: u64, size: u64) -> Option<&[u8]> {
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(usize::try_from(size).ok()?)?;
+        self.data.get(start..end)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A minimal little-endian ELF64 executable: one PT_LOAD with the given
+    // flags, an optional PT_INTERP, and sections (name, type, contents) named
+    // by a .shstrtab
+    pub(crate) fn build_elf(load_flags: u32, interpreter: Option<&str>, sections: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut phdrs: Vec<(u32, u32, Vec<u8>)> = vec![(PT_LOAD, load_flags, Vec::new())];
+        if let Some(interp) = interpreter {
+            let mut bytes = interp.as_bytes().to_vec();
+            bytes.push(0);
+            phdrs.push((PT_INTERP, 4, bytes));
+        }
+
+        let mut names = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for (name, ..) in sections {
+            name_offsets.push(names.len() as u32);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        name_offsets.push(names.len() as u32);
+        names.extend_from_slice(b".shstrtab\0");
+
+        let phoff = 64u64;
+        let mut out = vec![0u8; phoff as usize + 56 * phdrs.len()];
+        let mut payload_offsets = Vec::new();
+        for (_, _, payload) in &phdrs {
+            payload_offsets.push(out.len() as u64);
+            out.extend_from_slice(payload);
+        }
+        let mut section_ranges = Vec::new();
+        for (_, _, contents) in sections {
+            section_ranges.push((out.len() as u64, contents.len() as u64));
+            out.extend_from_slice(contents);
+        }
+        section_ranges.push((out.len() as u64, names.len() as u64));
+        out.extend_from_slice(&names);
+        let shoff = out.len() as u64;
+
+        out[..4].copy_from_slice(ELF_MAGIC);
+        out[4] = ELFCLASS64;
+        out[5] = 1;
+        out[6] = 1;
+        out[16..18].copy_from_slice(&2u16.to_le_bytes());
+        out[18..20].copy_from_slice(&62u16.to_le_bytes());
+        out[32..40].copy_from_slice(&phoff.to_le_bytes());
+        out[40..48].copy_from_slice(&shoff.to_le_bytes());
+        out[54..56].copy_from_slice(&56u16.to_le_bytes());
+        out[56..58].copy_from_slice(&(phdrs.len() as u16).to_le_bytes());
+        out[58..60].copy_from_slice(&64u16.to_le_bytes());
+        out[60..62].copy_from_slice(&(sections.len() as u16 + 1).to_le_bytes());
+        out[62..64].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+
+        for (index, (kind, flags, payload)) in phdrs.iter().enumerate() {
+            let at = phoff as usize + index * 56;
+            out[at..at + 4].copy_from_slice(&kind.to_le_bytes());
+            out[at + 4..at + 8].copy_from_slice(&flags.to_le_bytes());
+            out[at + 8..at + 16].copy_from_slice(&payload_offsets[index].to_le_bytes());
+            out[at + 32..at + 40].copy_from_slice(&(payload.len() as u64).to_le_bytes());
+            out[at + 40..at + 48].copy_from_slice(&(payload.len() as u64).to_le_bytes());
+        }
+
+        let kinds = sections.iter().map(|(_, kind, _)| *kind).chain([3]);
+        for (index, kind) in kinds.enumerate() {
+            let (offset, size) = section_ranges[index];
+            let mut header = vec![0u8; 64];
+            header[0..4].copy_from_slice(&name_offsets[index].to_le_bytes());
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[24..32].copy_from_slice(&offset.to_le_bytes());
+            header[32..40].copy_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(&header);
+        }
+        out
+    }
+
+    #[test]
+    fn test_static_risk_flags() {
+        let normal = build_elf(PF_X | 4, Some("/lib64/ld-linux-x86-64.so.2"), &[(".text", 1, b"\xc3"), (".symtab", SHT_SYMTAB, b"")]);
+        let info = ElfInfo::parse(&normal).unwrap();
+        assert_eq!(info.interpreter.as_deref(), Some("/lib64/ld-linux-x86-64.so.2"));
+        let names: Vec<&str> = info.sections.iter().map(|section| section.name.as_str()).collect();
+        assert_eq!(names, vec![".text", ".symtab", ".shstrtab"]);
+        assert_eq!(info.sections[0].size, 1);
+        assert!(info.has_symbol_table && !info.is_static());
+        assert!(StaticRisk::analyze(&normal, Path::new("/tmp/normal")).unwrap().is_clean());
+
+        let packed = build_elf(PF_X | PF_W | 4, Some("/tmp/.x/ld.so"), &[("UPX0", 8, b""), ("UPX1", 1, b"")]);
+        let risk = StaticRisk::analyze(&packed, Path::new("/usr/bin/packed")).unwrap();
+        assert_eq!(risk.flags, vec![
+            ElfRedFlag::Packed { packer: "UPX".to_string() },
+            ElfRedFlag::WritableExecutableSegment,
+            ElfRedFlag::UnusualInterpreter { interpreter: "/tmp/.x/ld.so".to_string() },
+        ]);
+        assert!(risk.reputation_score() < 0.1);
+
+        // Static and stripped only matters outside the system directories
+        let dropper = build_elf(PF_X | 4, None, &[(".text", 1, b"")]);
+        assert!(StaticRisk::analyze(&dropper, Path::new("/usr/bin/busybox")).unwrap().is_clean());
+        let risk = StaticRisk::analyze(&dropper, Path::new("/dev/shm/kworker")).unwrap();
+        assert_eq!(risk.flags, vec![ElfRedFlag::StrippedStaticInTempDirectory]);
+
+        assert!(StaticRisk::analyze(b"#!/bin/sh\n", Path::new("/tmp/script")).is_none());
+        assert!(ElfInfo::parse(&normal[..40]).is_err());
+    }
+}
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use crate::policy::SigningLevel;
+use super::file_times;
+
+const MH_MAGIC: u32 = 0xfeedface;
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+const MH_CIGAM: u32 = 0xcefaedfe;
+const MH_CIGAM_64: u32 = 0xcffaedfe;
+const FAT_MAGIC: u32 = 0xcafebabe;
+const FAT_MAGIC_64: u32 = 0xcafebabf;
+// Java class files share the fat magic; their version field is far above any arch count
+const MAX_FAT_ARCHS: u32 = 16;
+
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+const LC_UNIXTHREAD: u32 = 0x5;
+const LC_LOAD_DYLIB: u32 = 0xc;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+const LC_ENCRYPTION_INFO: u32 = 0x21;
+const LC_DYLD_ENVIRONMENT: u32 = 0x27;
+const LC_ENCRYPTION_INFO_64: u32 = 0x2c;
+const LC_LOAD_WEAK_DYLIB: u32 = 0x8000_0018;
+const LC_RPATH: u32 = 0x8000_001c;
+const LC_REEXPORT_DYLIB: u32 = 0x8000_001f;
+const LC_LOAD_UPWARD_DYLIB: u32 = 0x8000_0023;
+const LC_MAIN: u32 = 0x8000_0028;
+
+// Code signature blobs are big-endian regardless of the binary
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade0c02;
+const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade7171;
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_ENTITLEMENTS: u32 = 5;
+const CS_ADHOC: u32 = 0x2;
+const CS_RUNTIME: u32 = 0x10000;
+const CS_SUPPORTSTEAMID: u32 = 0x20200;
+
+const MAX_LOAD_COMMANDS_SIZE: usize = 4 * 1024 * 1024;
+const MAX_SIGNATURE_SIZE: usize = 16 * 1024 * 1024;
+
+#[cfg(target_os = "macos")]
+const QUARANTINE_XATTR: &str = "com.apple.quarantine";
+// Set once Gatekeeper has let the user open the file
+const QTN_FLAG_USER_APPROVED: u32 = 0x0040;
+
+// Entitlements that switch off protections malware relies on being off
+const DANGEROUS_ENTITLEMENTS: &[&str] = &[
+    "com.apple.security.cs.disable-library-validation",
+    "com.apple.security.cs.allow-dyld-environment-variables",
+    "com.apple.security.cs.allow-unsigned-executable-memory",
+    "com.apple.security.cs.disable-executable-page-protection",
+    "com.apple.security.get-task-allow",
+    "com.apple.security.cs.debugger",
+];
+const WRITABLE_DIRECTORIES: &[&str] = &["/tmp/", "/private/tmp/", "/var/tmp/", "/private/var/tmp/", "/Users/Shared/"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachOSignature {
+    pub identifier: Option<String>,
+    pub team_id: Option<String>,
+    pub ad_hoc: bool,
+    pub hardened_runtime: bool,
+    pub entitlements: Vec<String>,
+}
+
+// Headers of the slice that runs on this machine (or the first one) of a thin or universal binary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachOInfo {
+    pub architectures: Vec<String>,
+    pub is_64bit: bool,
+    pub file_type: u32,
+    pub load_commands: usize,
+    pub has_entry_point: bool,
+    pub dylibs: Vec<String>,
+    pub rpaths: Vec<String>,
+    pub dyld_environment: Vec<String>,
+    pub encrypted: bool,
+    pub signature: Option<MachOSignature>,
+}
+
+impl MachOInfo {
+    pub fn is_macho(data: &[u8]) -> bool {
+        match data.get(..4) {
+            Some(magic) => {
+                let le = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+                let be = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+                matches!(le, MH_MAGIC | MH_MAGIC_64 | MH_CIGAM | MH_CIGAM_64)
+                    || (matches!(be, FAT_MAGIC | FAT_MAGIC_64) && data.get(4..8).is_some_and(|n| {
+                        (1..=MAX_FAT_ARCHS).contains(&u32::from_be_bytes([n[0], n[1], n[2], n[3]]))
+                    }))
+            }
+            None => false,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        Self::parse_from(&|offset, len| {
+            let start = usize::try_from(offset).ok()?;
+            data.get(start..start.checked_add(len)?).map(<[u8]>::to_vec)
+        })
+    }
+
+    // Reads only the headers and the code signature, not the whole binary.
+    // None for files that are not Mach-O.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        use std::os::unix::fs::FileExt;
+
+        let file = File::open(path)?;
+        let mut magic = [0u8; 8];
+        if file.read_at(&mut magic, 0)? < magic.len() || !Self::is_macho(&magic) {
+            return Ok(None);
+        }
+        let read = |offset: u64, len: usize| {
+            let mut buffer = vec![0u8; len];
+            file.read_exact_at(&mut buffer, offset).ok()?;
+            Some(buffer)
+        };
+        Self::parse_from(&read).map(Some)
+    }
+
+    fn parse_from(read: &dyn Fn(u64, usize) -> Option<Vec<u8>>) -> Result<Self> {
+        let truncated = || anyhow!("Truncated Mach-O header");
+        let head = read(0, 8).ok_or_else(truncated)?;
+        let fat_magic = be32(&head, 0).ok_or_else(truncated)?;
+
+        // (cpu type, slice offset) for each architecture
+        let slices: Vec<(u32, u64)> = if fat_magic == FAT_MAGIC || fat_magic == FAT_MAGIC_64 {
+            let count = be32(&head, 4).ok_or_else(truncated)?.min(MAX_FAT_ARCHS) as usize;
+            let entry = if fat_magic == FAT_MAGIC_64 { 32 } else { 20 };
+            let table = read(8, count * entry).ok_or_else(truncated)?;
+            (0..count)
+                .filter_map(|index| {
+                    let at = index * entry;
+                    let offset = if fat_magic == FAT_MAGIC_64 { be64(&table, at + 8)? } else { be32(&table, at + 8)? as u64 };
+                    Some((be32(&table, at)?, offset))
+                })
+                .collect()
+        } else {
+            vec![(0, 0)]
+        };
+
+        let native = if cfg!(target_arch = "aarch64") { CPU_TYPE_ARM64 } else { CPU_TYPE_X86_64 };
+        let &(_, base) = slices.iter()
+            .find(|(cpu, _)| *cpu == native)
+            .or_else(|| slices.first())
+            .ok_or_else(|| anyhow!("Universal binary has no architectures"))?;
+
+        let header = read(base, 32).or_else(|| read(base, 28)).ok_or_else(truncated)?;
+        let reader = match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+            MH_MAGIC => Reader { big_endian: false, is_64bit: false },
+            MH_MAGIC_64 => Reader { big_endian: false, is_64bit: true },
+            MH_CIGAM => Reader { big_endian: true, is_64bit: false },
+            MH_CIGAM_64 => Reader { big_endian: true, is_64bit: true },
+            _ => return Err(anyhow!("Not a Mach-O file")),
+        };
+
+        let cpu_type = reader.u32(&header, 4).ok_or_else(truncated)?;
+        let architectures = if slices.len() > 1 || slices[0].0 != 0 {
+            slices.iter().map(|(cpu, _)| cpu_name(*cpu)).collect()
+        } else {
+            vec![cpu_name(cpu_type)]
+        };
+
+        let file_type = reader.u32(&header, 12).ok_or_else(truncated)?;
+        let command_count = reader.u32(&header, 16).ok_or_else(truncated)? as usize;
+        let commands_size = (reader.u32(&header, 20).ok_or_else(truncated)? as usize).min(MAX_LOAD_COMMANDS_SIZE);
+        let header_size = if reader.is_64bit { 32 } else { 28 };
+        let commands = read(base + header_size, commands_size).ok_or_else(truncated)?;
+
+        let mut info = MachOInfo {
+            architectures,
+            is_64bit: reader.is_64bit,
+            file_type,
+            load_commands: 0,
+            has_entry_point: false,
+            dylibs: Vec::new(),
+            rpaths: Vec::new(),
+            dyld_environment: Vec::new(),
+            encrypted: false,
+            signature: None,
+        };
+
+        let mut signature_range = None;
+        let mut at = 0usize;
+        for _ in 0..command_count {
+            let (Some(cmd), Some(size)) = (reader.u32(&commands, at), reader.u32(&commands, at + 4)) else {
+                break;
+            };
+            let size = size as usize;
+            let Some(command) = commands.get(at..at.saturating_add(size)).filter(|_| size >= 8) else {
+                break;
+            };
+            info.load_commands += 1;
+
+            match cmd {
+                LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LAZY_LOAD_DYLIB | LC_LOAD_UPWARD_DYLIB => {
+                    info.dylibs.extend(lc_str(&reader, command));
+                }
+                LC_RPATH => info.rpaths.extend(lc_str(&reader, command)),
+                LC_DYLD_ENVIRONMENT => info.dyld_environment.extend(lc_str(&reader, command)),
+                LC_MAIN | LC_UNIXTHREAD => info.has_entry_point = true,
+                LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64 => {
+                    info.encrypted |= reader.u32(command, 16).is_some_and(|id| id != 0);
+                }
+                LC_CODE_SIGNATURE => {
+                    signature_range = reader.u32(command, 8).zip(reader.u32(command, 12));
+                }
+                _ => {}
+            }
+            at += size;
+        }
+
+        if let Some((offset, size)) = signature_range {
+            if let Some(blob) = read(base + offset as u64, (size as usize).min(MAX_SIGNATURE_SIZE)) {
+                info.signature = parse_signature(&blob);
+            }
+        }
+        Ok(info)
+    }
+
+    pub fn red_flags(&self) -> Vec<MachORedFlag> {
+        let mut flags = Vec::new();
+        match self.signature {
+            None => flags.push(MachORedFlag::Unsigned),
+            Some(ref signature) => {
+                if signature.ad_hoc {
+                    flags.push(MachORedFlag::AdHocSigned);
+                }
+                for entitlement in &signature.entitlements {
+                    if DANGEROUS_ENTITLEMENTS.contains(&entitlement.as_str()) {
+                        flags.push(MachORedFlag::DangerousEntitlement { entitlement: entitlement.clone() });
+                    }
+                }
+            }
+        }
+        if !self.dyld_environment.is_empty() {
+            flags.push(MachORedFlag::DyldEnvironment { variables: self.dyld_environment.clone() });
+        }
+        for path in self.dylibs.iter().chain(&self.rpaths) {
+            let relative = !path.starts_with('/') && !path.starts_with('@');
+            if relative || WRITABLE_DIRECTORIES.iter().any(|dir| path.starts_with(dir)) {
+                flags.push(MachORedFlag::UntrustedLibraryPath { path: path.clone() });
+            }
+        }
+        flags
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MachORedFlag {
+    Unsigned,
+    AdHocSigned,
+    DangerousEntitlement { entitlement: String },
+    DyldEnvironment { variables: Vec<String> },
+    UntrustedLibraryPath { path: String },
+    QuarantinedNotApproved,
+}
+
+impl fmt::Display for MachORedFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachORedFlag::Unsigned => write!(f, "unsigned"),
+            MachORedFlag::AdHocSigned => write!(f, "ad-hoc signed"),
+            MachORedFlag::DangerousEntitlement { entitlement } => write!(f, "entitlement {}", entitlement),
+            MachORedFlag::DyldEnvironment { variables } => write!(f, "embedded dyld environment {}", variables.join(" ")),
+            MachORedFlag::UntrustedLibraryPath { path } => write!(f, "loads library from {}", path),
+            MachORedFlag::QuarantinedNotApproved => write!(f, "quarantined and never approved by Gatekeeper"),
+        }
+    }
+}
+
+// The com.apple.quarantine attribute: "flags;timestamp;agent;event id", hex fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineInfo {
+    pub flags: u32,
+    pub timestamp: Option<i64>,
+    pub agent: Option<String>,
+    pub event_id: Option<String>,
+}
+
+impl QuarantineInfo {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.trim().trim_end_matches('\0').split(';');
+        let flags = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let non_empty = |field: Option<&str>| field.filter(|f| !f.is_empty()).map(str::to_string);
+        Some(Self {
+            flags,
+            timestamp: fields.next().and_then(|t| i64::from_str_radix(t, 16).ok()),
+            agent: non_empty(fields.next()),
+            event_id: non_empty(fields.next()),
+        })
+    }
+
+    pub fn user_approved(&self) -> bool {
+        self.flags & QTN_FLAG_USER_APPROVED != 0
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn read(path: &Path) -> Option<Self> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let name = CString::new(QUARANTINE_XATTR).ok()?;
+        let mut value = vec![0u8; 1024];
+        let len = unsafe {
+            libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr() as *mut libc::c_void, value.len(), 0, 0)
+        };
+        if len <= 0 {
+            return None;
+        }
+        value.truncate(len as usize);
+        Self::parse(&String::from_utf8_lossy(&value))
+    }
+
+    // Only macOS quarantines downloads
+    #[cfg(not(target_os = "macos"))]
+    pub fn read(_path: &Path) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotarizationStatus {
+    // Notarized by Apple, or an Apple platform binary
+    Notarized,
+    NotNotarized,
+    Unsigned,
+    // Not checked, or codesign is unavailable
+    Unknown,
+}
+
+impl NotarizationStatus {
+    // Asks codesign to check the signature against Apple's requirements; stapled
+    // tickets are checked offline, others need the notarization service
+    pub fn check(path: &Path) -> Self {
+        let output = std::process::Command::new("codesign")
+            .args(["--verify", "--strict", "--check-notarization", "-R=anchor apple or notarized"])
+            .arg(path)
+            .output();
+        match output {
+            Ok(output) => Self::from_codesign(output.status.success(), &String::from_utf8_lossy(&output.stderr)),
+            Err(_) => NotarizationStatus::Unknown,
+        }
+    }
+
+    fn from_codesign(success: bool, stderr: &str) -> Self {
+        if success {
+            NotarizationStatus::Notarized
+        } else if stderr.contains("not signed at all") || stderr.contains("code object is not signed") {
+            NotarizationStatus::Unsigned
+        } else if stderr.contains("test-requirement") || stderr.contains("does not satisfy") {
+            NotarizationStatus::NotNotarized
+        } else {
+            NotarizationStatus::Unknown
+        }
+    }
+}
+
+// Everything known about an executable before it runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachOAssessment {
+    pub path: PathBuf,
+    pub info: MachOInfo,
+    pub quarantine: Option<QuarantineInfo>,
+    pub notarization: NotarizationStatus,
+}
+
+impl MachOAssessment {
+    // None for files that are not Mach-O
+    pub fn assess(path: &Path, check_notarization: bool) -> Result<Option<Self>> {
+        let Some(info) = MachOInfo::read(path)? else {
+            return Ok(None);
+        };
+        let notarization = if check_notarization {
+            NotarizationStatus::check(path)
+        } else {
+            NotarizationStatus::Unknown
+        };
+        Ok(Some(Self {
+            path: path.to_path_buf(),
+            info,
+            quarantine: QuarantineInfo::read(path),
+            notarization,
+        }))
+    }
+
+    pub fn signing_level(&self) -> SigningLevel {
+        match self.info.signature {
+            _ if self.notarization == NotarizationStatus::Notarized => SigningLevel::Notarized,
+            Some(ref signature) if !signature.ad_hoc && self.notarization != NotarizationStatus::Unsigned => SigningLevel::Signed,
+            _ => SigningLevel::Unsigned,
+        }
+    }
+
+    pub fn red_flags(&self) -> Vec<MachORedFlag> {
+        let mut flags = self.info.red_flags();
+        if self.quarantine.as_ref().is_some_and(|quarantine| !quarantine.user_approved()) {
+            flags.push(MachORedFlag::QuarantinedNotApproved);
+        }
+        flags
+    }
+}
+
+// path -> ((size, mtime, ctime), assessment)
+type AssessmentCache = HashMap<PathBuf, ((u64, i64, i64), Arc<MachOAssessment>)>;
+
+// Assessments keyed by path and revalidated against size and times, since
+// the notarization check runs codesign
+#[derive(Default)]
+pub struct MachOAssessor {
+    cache: Mutex<AssessmentCache>,
+}
+
+impl MachOAssessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn assess(&self, path: &Path, check_notarization: bool) -> Result<Option<Arc<MachOAssessment>>> {
+        let metadata = fs::metadata(path)?;
+        let (mtime, ctime) = file_times(&metadata);
+        let stamp = (metadata.len(), mtime, ctime);
+
+        if let Ok(cache) = self.cache.lock() {
+            if let Some((cached, assessment)) = cache.get(path) {
+                let checked = !check_notarization || assessment.notarization != NotarizationStatus::Unknown;
+                if *cached == stamp && checked {
+                    return Ok(Some(Arc::clone(assessment)));
+                }
+            }
+        }
+
+        let Some(assessment) = MachOAssessment::assess(path, check_notarization)? else {
+            return Ok(None);
+        };
+        let assessment = Arc::new(assessment);
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(path.to_path_buf(), (stamp, Arc::clone(&assessment)));
+        }
+        Ok(Some(assessment))
+    }
+}
+
+fn parse_signature(blob: &[u8]) -> Option<MachOSignature> {
+    if be32(blob, 0)? != CSMAGIC_EMBEDDED_SIGNATURE {
+        return None;
+    }
+    let count = be32(blob, 8)? as usize;
+    let mut signature = MachOSignature {
+        identifier: None,
+        team_id: None,
+        ad_hoc: false,
+        hardened_runtime: false,
+        entitlements: Vec::new(),
+    };
+    let mut has_code_directory = false;
+
+    for index in 0..count.min(64) {
+        let at = 12 + index * 8;
+        let (slot, offset) = (be32(blob, at)?, be32(blob, at + 4)? as usize);
+        let Some(data) = blob.get(offset..) else { continue };
+        match (slot, be32(data, 0)) {
+            (CSSLOT_CODEDIRECTORY, Some(CSMAGIC_CODEDIRECTORY)) => {
+                has_code_directory = true;
+                let version = be32(data, 8)?;
+                let flags = be32(data, 12)?;
+                signature.ad_hoc = flags & CS_ADHOC != 0;
+                signature.hardened_runtime = flags & CS_RUNTIME != 0;
+                signature.identifier = cstring_at(data, be32(data, 20)? as usize);
+                if version >= CS_SUPPORTSTEAMID {
+                    signature.team_id = be32(data, 48)
+                        .filter(|&offset| offset != 0)
+                        .and_then(|offset| cstring_at(data, offset as usize));
+                }
+            }
+            (CSSLOT_ENTITLEMENTS, Some(CSMAGIC_EMBEDDED_ENTITLEMENTS)) => {
+                let length = (be32(data, 4)? as usize).min(data.len());
+                let plist = String::from_utf8_lossy(data.get(8..length)?);
+                signature.entitlements = entitlement_keys(&plist);
+            }
+            _ => {}
+        }
+    }
+    has_code_directory.then_some(signature)
+}
+
+// Keys of the entitlements plist whose value is not <false/>
+fn entitlement_keys(plist: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut rest = plist;
+    while let Some(start) = rest.find("<key>") {
+        rest = &rest[start + 5..];
+        let Some(end) = rest.find("</key>") else { break };
+        let key = rest[..end].trim().to_string();
+        rest = &rest[end + 6..];
+        if !rest.trim_start().starts_with("<false/>") {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+fn lc_str(reader: &Reader, command: &[u8]) -> Option<String> {
+    let offset = reader.u32(command, 8)? as usize;
+    cstring_at(command, offset)
+}
+
+fn cstring_at(data: &[u8], offset: usize) -> Option<String> {
+    let rest = data.get(offset..)?;
+    let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    Some(String::from_utf8_lossy(&rest[..end]).to_string()).filter(|s| !s.is_empty())
+}
+
+fn cpu_name(cpu_type: u32) -> String {
+    match cpu_type {
+        CPU_TYPE_X86_64 => "x86_64".to_string(),
+        CPU_TYPE_ARM64 => "arm64".to_string(),
+        7 => "i386".to_string(),
+        12 => "arm".to_string(),
+        other => format!("cpu {:#x}", other),
+    }
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at.checked_add(4)?).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn be64(data: &[u8], at: usize) -> Option<u64> {
+    data.get(at..at.checked_add(8)?).and_then(|b| b.try_into().ok()).map(u64::from_be_bytes)
+}
+
+struct Reader {
+    big_endian: bool,
+    is_64bit: bool,
+}
+
+impl Reader {
+    fn u32(&self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = data.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(cmd: u32, body: &[u8]) -> Vec<u8> {
+        let size = (8 + body.len()).next_multiple_of(8);
+        let mut out = Vec::with_capacity(size);
+        out.extend_from_slice(&cmd.to_le_bytes());
+        out.extend_from_slice(&(size as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        out.resize(size, 0);
+        out
+    }
+
+    fn dylib(cmd: u32, name: &str) -> Vec<u8> {
+        let mut body = 24u32.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0u8; 12]);
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        command(cmd, &body)
+    }
+
+    // A 64-bit executable with a code signature carrying the given flags and entitlements
+    fn build_macho(cs_flags: u32, entitlements: &str, extra: &[Vec<u8>]) -> Vec<u8> {
+        let mut code_directory = Vec::new();
+        for word in [CSMAGIC_CODEDIRECTORY, 0, CS_SUPPORTSTEAMID, cs_flags, 0, 52, 0, 0, 0, 0, 0, 0, 69] {
+            code_directory.extend_from_slice(&word.to_be_bytes());
+        }
+        code_directory.extend_from_slice(b"com.example.tool\0TEAM123\0");
+        let mut entitlement_blob = CSMAGIC_EMBEDDED_ENTITLEMENTS.to_be_bytes().to_vec();
+        entitlement_blob.extend_from_slice(&(8 + entitlements.len() as u32).to_be_bytes());
+        entitlement_blob.extend_from_slice(entitlements.as_bytes());
+
+        let mut signature = Vec::new();
+        for word in [CSMAGIC_EMBEDDED_SIGNATURE, 0, 2, CSSLOT_CODEDIRECTORY, 28, CSSLOT_ENTITLEMENTS, 28 + code_directory.len() as u32] {
+            signature.extend_from_slice(&word.to_be_bytes());
+        }
+        signature.extend_from_slice(&code_directory);
+        signature.extend_from_slice(&entitlement_blob);
+
+        let mut commands: Vec<Vec<u8>> = vec![command(LC_MAIN, &[0u8; 16])];
+        commands.extend(extra.iter().cloned());
+        let signature_command_size = 16;
+        let commands_size: usize = commands.iter().map(Vec::len).sum::<usize>() + signature_command_size;
+        let signature_offset = (32 + commands_size) as u32;
+        let mut body = signature_offset.to_le_bytes().to_vec();
+        body.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+        commands.push(command(LC_CODE_SIGNATURE, &body));
+
+        let mut out = Vec::new();
+        for word in [MH_MAGIC_64, CPU_TYPE_ARM64, 0, 2, commands.len() as u32, commands_size as u32, 0, 0] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        for cmd in commands {
+            out.extend_from_slice(&cmd);
+        }
+        out.extend_from_slice(&signature);
+        out
+    }
+
+    #[test]
+    fn test_parse_signature_entitlements_and_flags() {
+        let entitlements = "<?xml version=\"1.0\"?><plist><dict>\
+            <key>com.apple.security.cs.disable-library-validation</key><true/>\
+            <key>com.apple.security.get-task-allow</key><false/>\
+            </dict></plist>";
+        let data = build_macho(CS_RUNTIME, entitlements, &[
+            dylib(LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib"),
+            dylib(LC_LOAD_WEAK_DYLIB, "/tmp/.hidden/libinject.dylib"),
+        ]);
+        assert!(MachOInfo::is_macho(&data));
+
+        let info = MachOInfo::parse(&data).unwrap();
+        assert_eq!(info.architectures, vec!["arm64"]);
+        assert!(info.has_entry_point);
+        assert_eq!(info.dylibs.len(), 2);
+        let signature = info.signature.clone().unwrap();
+        assert_eq!(signature.identifier.as_deref(), Some("com.example.tool"));
+        assert_eq!(signature.team_id.as_deref(), Some("TEAM123"));
+        assert!(signature.hardened_runtime && !signature.ad_hoc);
+        assert_eq!(signature.entitlements, vec!["com.apple.security.cs.disable-library-validation"]);
+        assert_eq!(info.red_flags(), vec![
+            MachORedFlag::DangerousEntitlement { entitlement: "com.apple.security.cs.disable-library-validation".to_string() },
+            MachORedFlag::UntrustedLibraryPath { path: "/tmp/.hidden/libinject.dylib".to_string() },
+        ]);
+
+        let assessment = MachOAssessment {
+            path: PathBuf::from("/Users/me/Downloads/tool"),
+            info: MachOInfo::parse(&build_macho(CS_ADHOC, "", &[])).unwrap(),
+            quarantine: QuarantineInfo::parse("0081;65a1b2c3;Safari;5D1C8A2E"),
+            notarization: NotarizationStatus::from_codesign(false, "test-requirement: code failed to satisfy specified code requirement(s)"),
+        };
+        assert_eq!(assessment.quarantine.as_ref().unwrap().agent.as_deref(), Some("Safari"));
+        assert_eq!(assessment.notarization, NotarizationStatus::NotNotarized);
+        assert_eq!(assessment.signing_level(), SigningLevel::Unsigned);
+        assert_eq!(assessment.red_flags(), vec![MachORedFlag::AdHocSigned, MachORedFlag::QuarantinedNotApproved]);
+
+        // Unsigned and ad-hoc binaries only run from /Applications
+        let mut policy = crate::policy::FilePolicy::default();
+        policy.required_signing = Some(SigningLevel::Signed);
+        let ctx = |path: &'static str| crate::policy::RuleContext {
+            path: Some(Path::new(path)),
+            signing: Some(assessment.signing_level()),
+            ..Default::default()
+        };
+        let (action, reason) = policy.evaluate_execution(&ctx("/Users/me/Downloads/tool"));
+        assert_eq!(action, crate::policy::RuleAction::Deny);
+        assert!(reason.contains("unsigned"));
+        assert_eq!(policy.evaluate_execution(&ctx("/Applications/Tool.app/Contents/MacOS/tool")).0, crate::policy::RuleAction::Allow);
+
+        assert!(!MachOInfo::is_macho(b"\xca\xfe\xba\xbe\x00\x00\x00\x34"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use anyhow::{Result, Context};
+use tracing::{info, warn, debug};
+
+use super::yara::YaraRules;
+
+// Regions larger than this are skipped; injected payloads are rarely this big
+const DEFAULT_MAX_REGION_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub end: u64,
+    pub permissions: String,
+    pub offset: u64,
+    pub pathname: Option<String>,
+}
+
+impl MemoryRegion {
+    pub fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.permissions.contains('x')
+    }
+
+    // Memory not backed by a file on disk: anonymous mappings, memfd and deleted files
+    pub fn is_anonymous(&self) -> bool {
+        match &self.pathname {
+            None => true,
+            Some(path) => {
+                path == "[heap]"
+                    || path == "[stack]"
+                    || path.starts_with("[anon:")
+                    || path.starts_with("/memfd:")
+                    || path.ends_with(" (deleted)")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryMatch {
+    pub pid: u32,
+    pub rule: String,
+    pub tags: Vec<String>,
+    pub region: MemoryRegion,
+    // (string identifier, absolute address, offset within the region)
+    pub offsets: Vec<(String, u64, u64)>,
+    pub detected_at: DateTime<Utc>,
+}
+
+pub struct MemoryScanner {
+    rules: YaraRules,
+    max_region_size: u64,
+    scan_file_backed: bool,
+}
+
+impl MemoryScanner {
+    pub fn new(rules: YaraRules) -> Self {
+        info!("Initializing memory scanner with {} rules", rules.len());
+
+        Self {
+            rules,
+            max_region_size: DEFAULT_MAX_REGION_SIZE,
+            scan_file_backed: false,
+        }
+    }
+
+    pub fn set_max_region_size(&mut self, size: u64) {
+        self.max_region_size = size;
+    }
+
+    // Also scan executable regions mapped from files on disk (slower, mostly libraries)
+    pub fn set_scan_file_backed(&mut self, enabled: bool) {
+        self.scan_file_backed = enabled;
+    }
+
+    pub fn read_maps(pid: u32) -> Result<Vec<MemoryRegion>> {
+        let content = fs::read_to_string(format!("/proc/{}/maps", pid))
+            .with_context(|| format!("Failed to read memory map for pid {}", pid))?;
+
+        Ok(content.lines().filter_map(Self::parse_maps_line).collect())
+    }
+
+    // Parse a /proc/pid/maps line: `7f12a000-7f12b000 r-xp 00000000 00:00 0    [pathname]`
+    fn parse_maps_line(line: &str) -> Option<MemoryRegion> {
+        let mut parts = line.splitn(6, char::is_whitespace);
+        let range = parts.next()?;
+        let permissions = parts.next()?.to_string();
+        let offset = u64::from_str_radix(parts.next()?, 16).ok()?;
+        let _device = parts.next()?;
+        let _inode = parts.next()?;
+        let pathname = parts.next()
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string());
+
+        let (start, end) = range.split_once('-')?;
+
+        Some(MemoryRegion {
+            start: u64::from_str_radix(start, 16).ok()?,
+            end: u64::from_str_radix(end, 16).ok()?,
+            permissions,
+            offset,
+            pathname,
+        })
+    }
+
+    // Regions worth scanning for in-memory-only payloads
+    pub fn candidate_regions(&self, pid: u32) -> Result<Vec<MemoryRegion>> {
+        Ok(Self::read_maps(pid)?
+            .into_iter()
+            .filter(|r| r.is_executable())
+            .filter(|r| self.scan_file_backed || r.is_anonymous())
+            .filter(|r| r.pathname.as_deref() != Some("[vsyscall]") && r.pathname.as_deref() != Some("[vdso]"))
+            .collect())
+    }
+
+    pub fn scan_process(&self, pid: u32) -> Result<Vec<MemoryMatch>> {
+        let regions = self.candidate_regions(pid)?;
+        if regions.is_empty() {
+            debug!("No candidate memory regions for pid {}", pid);
+            return Ok(Vec::new());
+        }
+
+        // Reading another process's memory requires ptrace access (root or CAP_SYS_PTRACE)
+        let mem = File::open(format!("/proc/{}/mem", pid))
+            .with_context(|| format!("Failed to open memory of pid {}", pid))?;
+
+        let mut matches = Vec::new();
+
+        for region in regions {
+            if region.size() > self.max_region_size {
+                debug!("Skipping large region {:x}-{:x} in pid {}", region.start, region.end, pid);
+                continue;
+            }
+
+            let mut buffer = vec![0u8; region.size() as usize];
+            if let Err(e) = mem.read_exact_at(&mut buffer, region.start) {
+                debug!("Failed to read region {:x}-{:x} in pid {}: {}", region.start, region.end, pid, e);
+                continue;
+            }
+
+            for rule_match in self.rules.scan(&buffer) {
+                let offsets = rule_match.strings
+                    .iter()
+                    .map(|s| (s.identifier.clone(), region.start + s.offset as u64, s.offset as u64))
+                    .collect();
+
+                warn!(
+                    "Memory rule {} matched in pid {} region {:x}-{:x} ({})",
+                    rule_match.rule, pid, region.start, region.end, region.permissions
+                );
+
+                matches.push(MemoryMatch {
+                    pid,
+                    rule: rule_match.rule,
+                    tags: rule_match.tags,
+                    region: region.clone(),
+                    offsets,
+                    detected_at: Utc::now(),
+                });
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_maps_line() {
+        let anon = MemoryScanner::parse_maps_line("7f1c2a000000-7f1c2a021000 rwxp 00000000 00:00 0 ").unwrap();
+        assert_eq!(anon.start, 0x7f1c2a000000);
+        assert_eq!(anon.size(), 0x21000);
+        assert!(anon.is_executable() && anon.is_anonymous());
+
+        let lib = MemoryScanner::parse_maps_line(
+            "7f1c2b000000-7f1c2b1b0000 r-xp 00028000 08:01 1835 /usr/lib/x86_64-linux-gnu/libc.so.6"
+        ).unwrap();
+        assert_eq!(lib.offset, 0x28000);
+        assert!(!lib.is_anonymous());
+
+        let memfd = MemoryScanner::parse_maps_line("7f00-8f00 r-xp 00000000 00:01 42 /memfd:payload (deleted)").unwrap();
+        assert!(memfd.is_anonymous());
+    }
+}
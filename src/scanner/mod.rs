@@ -1,6 +1,17 @@
+pub mod persistence;
+pub mod yara;
+pub mod directory;
+pub mod baseline;
+pub mod packages;
+pub mod elf;
+pub mod dropper;
+pub mod macho;
+#[cfg(target_os = "linux")]
+pub mod memory;
+
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 use walkdir::WalkDir;
@@ -9,6 +20,13 @@ use chrono::{DateTime, Utc};
 use anyhow::{Result, Context};
 use tracing::{info, warn, debug, error};
 
+pub use directory::{DirectoryScanner, FindingReason, ScanFinding, ScanOptions, ScanProgress, ScanReport};
+pub use baseline::{BaselineBuilder, BaselineChanges, BaselineEntry, BaselineManifest, BaselineOptions};
+pub use packages::{PackageVerifier, PackageVerdict};
+pub use elf::{ElfInfo, ElfRedFlag, StaticRisk};
+pub use dropper::{DropperAnalysis, DropperIndicator};
+pub use macho::{MachOAssessment, MachOAssessor, MachOInfo, MachORedFlag, NotarizationStatus, QuarantineInfo};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRecord {
     pub uuid: String,
@@ -155,7 +173,7 @@ impl FileScanner {
         let is_executable = self.is_executable(path, metadata);
         
         // Get code signature info (macOS specific)
-        let (is_signed, code_signature) = self.get_code_signature_info(path);
+        let (is_signed, code_signature) = code_signature_info(path);
         
         // Get bundle info if applicable
         let bundle_info = self.get_bundle_info(path);
@@ -234,62 +252,6 @@ impl FileScanner {
         metadata.permissions().mode()
     }
     
-    fn get_code_signature_info(&self, path: &Path) -> (bool, Option<CodeSignature>) {
-        // Use macOS codesign command to check signature
-        let output = std::process::Command::new("codesign")
-            .args(&["-dv", "--verbose=4"])
-            .arg(path)
-            .output();
-        
-        match output {
-            Ok(output) if output.status.success() => {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                self.parse_codesign_output(&stderr)
-            }
-            _ => (false, None)
-        }
-    }
-    
-    fn parse_codesign_output(&self, output: &str) -> (bool, Option<CodeSignature>) {
-        if output.contains("code object is not signed") {
-            return (false, None);
-        }
-        
-        let mut authority = String::new();
-        let mut team_identifier = None;
-        let mut bundle_identifier = None;
-        let is_apple_signed = output.contains("Apple") || output.contains("Software Signing");
-        
-        for line in output.lines() {
-            if line.contains("Authority=") {
-                authority = line.split("Authority=").nth(1)
-                    .unwrap_or("")
-                    .trim()
-                    .to_string();
-            } else if line.contains("TeamIdentifier=") {
-                team_identifier = Some(line.split("TeamIdentifier=").nth(1)
-                    .unwrap_or("")
-                    .trim()
-                    .to_string());
-            } else if line.contains("Identifier=") {
-                bundle_identifier = Some(line.split("Identifier=").nth(1)
-                    .unwrap_or("")
-                    .trim()
-                    .to_string());
-            }
-        }
-        
-        let signature = CodeSignature {
-            authority,
-            team_identifier,
-            bundle_identifier,
-            is_valid: true,
-            is_apple_signed,
-        };
-        
-        (true, Some(signature))
-    }
-    
     fn get_bundle_info(&self, path: &Path) -> Option<BundleInfo> {
         // Check if this is part of an app bundle
         let path_str = path.to_string_lossy();
@@ -389,4 +351,104 @@ impl FileScanner {
             self.manifest.scan_paths
         )
     }
-}
\ No newline at end of file
+}
+
+// Signature of a file according to macOS codesign; unsigned everywhere else
+pub fn code_signature_info(path: &Path) -> (bool, Option<CodeSignature>) {
+    // Use macOS codesign command to check signature
+    let output = std::process::Command::new("codesign")
+        .args(["-dv", "--verbose=4"])
+        .arg(path)
+        .output();
+    
+    match output {
+        Ok(output) if output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            parse_codesign_output(&stderr)
+        }
+        _ => (false, None)
+    }
+}
+
+fn parse_codesign_output(output: &str) -> (bool, Option<CodeSignature>) {
+    if output.contains("code object is not signed") {
+        return (false, None);
+    }
+    
+    let mut authority = String::new();
+    let mut team_identifier = None;
+    let mut bundle_identifier = None;
+    let is_apple_signed = output.contains("Apple") || output.contains("Software Signing");
+    
+    for line in output.lines() {
+        if line.contains("Authority=") {
+            authority = line.split("Authority=").nth(1)
+                .unwrap_or("")
+                .trim()
+                .to_string();
+        } else if line.contains("TeamIdentifier=") {
+            team_identifier = Some(line.split("TeamIdentifier=").nth(1)
+                .unwrap_or("")
+                .trim()
+                .to_string());
+        } else if line.contains("Identifier=") {
+            bundle_identifier = Some(line.split("Identifier=").nth(1)
+                .unwrap_or("")
+                .trim()
+                .to_string());
+        }
+    }
+    
+    let signature = CodeSignature {
+        authority,
+        team_identifier,
+        bundle_identifier,
+        is_valid: true,
+        is_apple_signed,
+    };
+    
+    (true, Some(signature))
+}
+
+// Lowercased SHA-256 hashes from a list file; '#' lines are comments and
+// trailing columns, such as file names in sha256sum output, are skipped
+pub fn load_hash_list(path: &Path) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hash list {:?}", path))?;
+
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|hash| hash.to_lowercase())
+        .collect())
+}
+
+// Any of the execute bits
+#[cfg(unix)]
+pub(crate) fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+// (mtime, ctime) in nanoseconds; a file whose size and both times are
+// unchanged is treated as unmodified
+#[cfg(unix)]
+pub(crate) fn file_times(metadata: &fs::Metadata) -> (i64, i64) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec(),
+        metadata.ctime() * 1_000_000_000 + metadata.ctime_nsec(),
+    )
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_times(_metadata: &fs::Metadata) -> (i64, i64) {
+    (0, 0)
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use anyhow::{anyhow, Result, Context};
+use tracing::{debug, info, warn};
+
+use super::file_times;
+
+pub const DPKG_INFO_DIR: &str = "/var/lib/dpkg/info";
+const RPM_DB_DIRS: &[&str] = &["/var/lib/rpm", "/usr/lib/sysimage/rpm"];
+
+// Everything in these directories is expected to come from a package
+const PACKAGE_MANAGED_DIRS: &[&str] = &[
+    "/bin", "/sbin", "/lib", "/lib64", "/usr/bin", "/usr/sbin", "/usr/lib", "/usr/lib64", "/usr/libexec",
+];
+
+const VERDICT_CACHE_CAPACITY: usize = 4096;
+
+// (size, mtime, ctime) of the file a cached verdict was made for
+type FileStamp = (u64, i64, i64);
+
+// A digest the package database recorded at install time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDigest {
+    Md5(String),
+    Sha256(String),
+    Sha512(String),
+}
+
+impl FileDigest {
+    pub fn value(&self) -> &str {
+        match self {
+            FileDigest::Md5(value) | FileDigest::Sha256(value) | FileDigest::Sha512(value) => value,
+        }
+    }
+
+    // The same algorithm over the file as it is now
+    fn compute(&self, path: &Path) -> Result<String> {
+        let mut file = File::open(path)?;
+        Ok(match self {
+            FileDigest::Md5(_) => {
+                let mut context = md5::Context::new();
+                std::io::copy(&mut file, &mut context)?;
+                format!("{:x}", context.compute())
+            }
+            FileDigest::Sha256(_) => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            FileDigest::Sha512(_) => {
+                let mut hasher = Sha512::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+        })
+    }
+
+    // rpm's FILEDIGESTALGO values (PGPHASHALGO_*); SHA-1 and others are not verified
+    fn from_rpm(algorithm: &str, value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return None;
+        }
+        match algorithm {
+            "1" => Some(FileDigest::Md5(value.to_lowercase())),
+            "8" => Some(FileDigest::Sha256(value.to_lowercase())),
+            "10" => Some(FileDigest::Sha512(value.to_lowercase())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageFile {
+    pub package: String,
+    // None for directories, symlinks and files recorded without a digest
+    pub digest: Option<FileDigest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PackageVerdict {
+    Verified { package: String },
+    // The file differs from what the package installed
+    Modified { package: String, expected: String, actual: String },
+    // Owned, but there is no digest to check against
+    Unverifiable { package: String },
+    // In a package-managed directory, but no package owns it
+    Orphaned,
+    // Outside the package-managed directories
+    Unmanaged,
+}
+
+impl PackageVerdict {
+    pub fn is_tampering(&self) -> bool {
+        matches!(self, PackageVerdict::Modified { .. } | PackageVerdict::Orphaned)
+    }
+
+    pub fn describe(&self, path: &Path) -> String {
+        match self {
+            PackageVerdict::Verified { package } => format!("{} matches package {}", path.display(), package),
+            PackageVerdict::Modified { package, expected, actual } => format!(
+                "{} was modified after package {} installed it (digest {}, expected {})",
+                path.display(), package, actual, expected
+            ),
+            PackageVerdict::Unverifiable { package } => format!("{} belongs to package {} but has no recorded digest", path.display(), package),
+            PackageVerdict::Orphaned => format!("{} is in a system directory but no installed package owns it", path.display()),
+            PackageVerdict::Unmanaged => format!("{} is not managed by the package manager", path.display()),
+        }
+    }
+}
+
+// Checks files against the digests the dpkg/rpm databases recorded, like
+// `dpkg --verify` and `rpm -V` but without running them. Verdicts are cached
+// until the file's size or times change.
+pub struct PackageVerifier {
+    files: HashMap<PathBuf, PackageFile>,
+    cache: Mutex<HashMap<PathBuf, (FileStamp, PackageVerdict)>>,
+}
+
+impl PackageVerifier {
+    // Whichever of the dpkg and rpm databases exist on this system
+    pub fn load() -> Self {
+        let verifier = Self::from_files(package_files());
+        info!("Package verification covers {} files", verifier.len());
+        verifier
+    }
+
+    pub fn from_dpkg(info_dir: &Path) -> Result<Self> {
+        Ok(Self::from_files(dpkg_files(info_dir)?))
+    }
+
+    fn from_files(files: HashMap<PathBuf, PackageFile>) -> Self {
+        Self {
+            files,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn owner(&self, path: &Path) -> Option<&PackageFile> {
+        self.files.get(path)
+            .or_else(|| merged_usr_alias(path).and_then(|alias| self.files.get(&alias)))
+    }
+
+    pub fn verify(&self, path: &Path) -> Result<PackageVerdict> {
+        let metadata = fs::metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?;
+        let (mtime, ctime) = file_times(&metadata);
+        let key: FileStamp = (metadata.len(), mtime, ctime);
+        if let Ok(cache) = self.cache.lock() {
+            if let Some((cached_key, verdict)) = cache.get(path) {
+                if *cached_key == key {
+                    return Ok(verdict.clone());
+                }
+            }
+        }
+
+        let verdict = match self.owner(path) {
+            Some(PackageFile { package, digest: Some(digest) }) => {
+                let actual = digest.compute(path)?;
+                if actual == digest.value() {
+                    PackageVerdict::Verified { package: package.clone() }
+                } else {
+                    PackageVerdict::Modified { package: package.clone(), expected: digest.value().to_string(), actual }
+                }
+            }
+            Some(file) => PackageVerdict::Unverifiable { package: file.package.clone() },
+            // Without a package database everything would look orphaned
+            None if !self.files.is_empty() && PACKAGE_MANAGED_DIRS.iter().any(|dir| path.starts_with(dir)) => {
+                PackageVerdict::Orphaned
+            }
+            None => PackageVerdict::Unmanaged,
+        };
+        debug!("{}", verdict.describe(path));
+
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.len() >= VERDICT_CACHE_CAPACITY {
+                cache.clear();
+            }
+            cache.insert(path.to_path_buf(), (key, verdict.clone()));
+        }
+        Ok(verdict)
+    }
+}
+
+// On merged-/usr systems /bin is a symlink to /usr/bin, so a package may have
+// recorded /bin/ls for what executes as /usr/bin/ls
+fn merged_usr_alias(path: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix("/usr").ok()?;
+    let top = rest.components().next()?.as_os_str().to_str()?;
+    matches!(top, "bin" | "sbin" | "lib" | "lib32" | "lib64" | "libx32").then(|| Path::new("/").join(rest))
+}
+
+fn package_files() -> HashMap<PathBuf, PackageFile> {
+    let mut files = HashMap::new();
+
+    if Path::new(DPKG_INFO_DIR).is_dir() {
+        match dpkg_files(Path::new(DPKG_INFO_DIR)) {
+            Ok(dpkg) => files.extend(dpkg),
+            Err(e) => warn!("Failed to read the dpkg database: {:#}", e),
+        }
+    }
+
+    if RPM_DB_DIRS.iter().any(|dir| Path::new(dir).is_dir()) {
+        match rpm_files() {
+            Ok(rpm) => files.extend(rpm),
+            Err(e) => warn!("Failed to query the rpm database: {:#}", e),
+        }
+    }
+
+    files
+}
+
+// Files installed by the system package manager (dpkg and/or rpm), mapped to
+// the owning package. Paths are as the package database records them, so on
+// merged-/usr systems they may go through a symlinked directory.
+pub fn package_owners() -> HashMap<PathBuf, String> {
+    let owners: HashMap<PathBuf, String> = package_files().into_iter()
+        .map(|(path, file)| (path, file.package))
+        .collect();
+    debug!("{} files owned by installed packages", owners.len());
+    owners
+}
+
+// One <package>[:<arch>].list per installed package, listing its files and directories
+pub fn dpkg_owners(info_dir: &Path) -> Result<HashMap<PathBuf, String>> {
+    let mut owners = HashMap::new();
+    for (package, path) in dpkg_info_files(info_dir, "list")? {
+        let content = fs::read_to_string(&path)?;
+        for line in content.lines().map(str::trim).filter(|line| line.starts_with('/')) {
+            owners.insert(PathBuf::from(line), package.clone());
+        }
+    }
+    Ok(owners)
+}
+
+// The .list ownership plus the MD5s from <package>.md5sums, whose paths are
+// relative to /. Conffiles are not in .md5sums and stay unverifiable.
+fn dpkg_files(info_dir: &Path) -> Result<HashMap<PathBuf, PackageFile>> {
+    let mut files: HashMap<PathBuf, PackageFile> = dpkg_owners(info_dir)?.into_iter()
+        .map(|(path, package)| (path, PackageFile { package, digest: None }))
+        .collect();
+
+    for (package, path) in dpkg_info_files(info_dir, "md5sums")? {
+        let content = fs::read_to_string(&path)?;
+        for line in content.lines() {
+            let Some((digest, file)) = line.split_once("  ") else { continue };
+            files.insert(Path::new("/").join(file.trim()), PackageFile {
+                package: package.clone(),
+                digest: Some(FileDigest::Md5(digest.trim().to_lowercase())),
+            });
+        }
+    }
+    Ok(files)
+}
+
+// (package, path) for each <package>[:<arch>].<extension> in the dpkg info directory
+fn dpkg_info_files(info_dir: &Path, extension: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(info_dir).with_context(|| format!("Failed to read {}", info_dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+            continue;
+        }
+        let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+        let package = stem.split(':').next().unwrap_or(stem).to_string();
+        found.push((package, path));
+    }
+    Ok(found)
+}
+
+fn rpm_files() -> Result<HashMap<PathBuf, PackageFile>> {
+    let output = Command::new("rpm")
+        .args(["-qa", "--qf", "[%{FILENAMES}\t%{FILEDIGESTS}\t%{FILEDIGESTALGO}\t%{NAME}\n]"])
+        .output()
+        .context("Failed to run rpm")?;
+    if !output.status.success() {
+        return Err(anyhow!("rpm -qa failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let (path, digest, algorithm, package) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+            Some((PathBuf::from(path), PackageFile {
+                package: package.to_string(),
+                digest: FileDigest::from_rpm(algorithm, digest),
+            }))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dpkg_owners() {
+        let dir = std::env::temp_dir().join(format!("flux-dpkg-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("coreutils.list"), "/.\n/usr\n/usr/bin\n/usr/bin/ls\n").unwrap();
+        fs::write(dir.join("libc6:amd64.list"), "/usr/lib/x86_64-linux-gnu/libc.so.6\n").unwrap();
+        fs::write(dir.join("coreutils.md5sums"), "d41d8cd98f00b204e9800998ecf8427e  usr/bin/ls\n").unwrap();
+
+        let owners = dpkg_owners(&dir).unwrap();
+        assert_eq!(owners.get(Path::new("/usr/bin/ls")).map(String::as_str), Some("coreutils"));
+        assert_eq!(owners.get(Path::new("/usr/lib/x86_64-linux-gnu/libc.so.6")).map(String::as_str), Some("libc6"));
+        assert_eq!(owners.len(), 5);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_verify_against_dpkg_digests() {
+        let dir = std::env::temp_dir().join(format!("flux-dpkg-verify-{}", std::process::id()));
+        let info = dir.join("info");
+        fs::create_dir_all(&info).unwrap();
+        let tool = dir.join("tool");
+        fs::write(&tool, b"original").unwrap();
+        let relative = tool.strip_prefix("/").unwrap().display().to_string();
+        fs::write(info.join("tools.list"), format!("{}\n", tool.display())).unwrap();
+        fs::write(info.join("tools.md5sums"), format!("{:x}  {}\n", md5::compute(b"original"), relative)).unwrap();
+
+        let verifier = PackageVerifier::from_dpkg(&info).unwrap();
+        assert_eq!(verifier.verify(&tool).unwrap(), PackageVerdict::Verified { package: "tools".to_string() });
+
+        fs::write(&tool, b"patched by an attacker").unwrap();
+        let verdict = verifier.verify(&tool).unwrap();
+        assert!(matches!(verdict, PackageVerdict::Modified { ref package, .. } if package == "tools"));
+        assert!(verdict.is_tampering());
+
+        let stray = dir.join("stray");
+        fs::write(&stray, b"x").unwrap();
+        assert_eq!(verifier.verify(&stray).unwrap(), PackageVerdict::Unmanaged);
+        assert_eq!(merged_usr_alias(Path::new("/usr/bin/ls")), Some(PathBuf::from("/bin/ls")));
+        assert_eq!(merged_usr_alias(Path::new("/usr/share/doc")), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
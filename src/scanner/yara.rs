@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use regex::bytes::Regex;
+use anyhow::{Result, Context, anyhow, bail};
+
+// Minimal YARA-compatible rule engine.
+// Supports the commonly used subset of the rule language:
+//   - text strings with nocase/wide/ascii modifiers, hex strings with ?? wildcards and [n-m] jumps,
+//     and /regex/ strings
+//   - conditions: `any of them`, `all of them`, `N of them`, and boolean expressions over
+//     string identifiers using and/or/not and parentheses
+// Rules are parsed line by line, so each string definition must fit on one line.
+
+// Cap on reported offsets per string to keep results bounded on repetitive data
+const MAX_MATCHES_PER_STRING: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct YaraRules {
+    rules: Vec<YaraRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct YaraRule {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub meta: HashMap<String, String>,
+    strings: Vec<YaraString>,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone)]
+struct YaraString {
+    identifier: String,
+    regex: Regex,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    AnyOf,
+    AllOf,
+    CountOf(usize),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    True,
+    String(String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub tags: Vec<String>,
+    pub strings: Vec<StringMatch>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StringMatch {
+    pub identifier: String,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(PartialEq)]
+enum Section {
+    None,
+    Meta,
+    Strings,
+    Condition,
+}
+
+impl YaraRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read rules file {:?}", path))?;
+        Self::parse(&source).with_context(|| format!("Failed to parse rules file {:?}", path))
+    }
+
+    // Load every .yar/.yara file in a directory
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_rule_file = path.extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e == "yar" || e == "yara")
+                .unwrap_or(false);
+
+            if is_rule_file {
+                rules.extend(Self::load(&path)?.rules);
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn parse(source: &str) -> Result<Self> {
+        let source = strip_comments(source);
+        let mut rules = Vec::new();
+        let mut lines = source.lines().map(str::trim).filter(|l| !l.is_empty()).peekable();
+
+        while let Some(line) = lines.next() {
+            if line.starts_with("import ") || line.starts_with("include ") {
+                bail!("Unsupported directive: {}", line);
+            }
+
+            let header = line.trim_start_matches("private ").trim_start_matches("global ");
+            let header = header.strip_prefix("rule ")
+                .ok_or_else(|| anyhow!("Expected rule declaration, found: {}", line))?;
+
+            // Brace may be on the header line or the next one
+            let (header, mut inline_body) = match header.split_once('{') {
+                Some((h, rest)) => (h.trim(), rest.trim().to_string()),
+                None => {
+                    match lines.next() {
+                        Some(l) if l.starts_with('{') => (header.trim(), l[1..].trim().to_string()),
+                        _ => bail!("Expected '{{' after rule header: {}", line),
+                    }
+                }
+            };
+
+            let (name, tags) = match header.split_once(':') {
+                Some((name, tags)) => (name.trim(), tags.split_whitespace().map(String::from).collect()),
+                None => (header, Vec::new()),
+            };
+
+            let mut meta = HashMap::new();
+            let mut strings = Vec::new();
+            let mut condition_text = String::new();
+            let mut section = Section::None;
+            let mut closed = false;
+
+            loop {
+                let body_line = if !inline_body.is_empty() {
+                    std::mem::take(&mut inline_body)
+                } else {
+                    match lines.next() {
+                        Some(l) => l.to_string(),
+                        None => break,
+                    }
+                };
+
+                let mut body_line = body_line.as_str();
+                if body_line == "}" || (section == Section::Condition && body_line.ends_with('}')) {
+                    body_line = body_line.trim_end_matches('}').trim();
+                    closed = true;
+                }
+
+                for (label, next) in [("meta:", Section::Meta), ("strings:", Section::Strings), ("condition:", Section::Condition)] {
+                    if let Some(rest) = body_line.strip_prefix(label) {
+                        section = next;
+                        body_line = rest.trim();
+                        break;
+                    }
+                }
+
+                if !body_line.is_empty() {
+                    match section {
+                        Section::Meta => {
+                            if let Some((key, value)) = body_line.split_once('=') {
+                                meta.insert(key.trim().to_string(), value.trim().trim_matches('"').to_string());
+                            }
+                        }
+                        Section::Strings => strings.push(parse_string(body_line)?),
+                        Section::Condition => {
+                            condition_text.push(' ');
+                            condition_text.push_str(body_line);
+                        }
+                        Section::None => bail!("Unexpected content in rule {}: {}", name, body_line),
+                    }
+                }
+
+                if closed {
+                    break;
+                }
+            }
+
+            if !closed {
+                bail!("Unterminated rule: {}", name);
+            }
+
+            let condition = parse_condition(condition_text.trim())
+                .with_context(|| format!("Invalid condition in rule {}", name))?;
+
+            // Referenced strings must exist
+            if let Condition::Expr(ref expr) = condition {
+                let mut refs = Vec::new();
+                expr.identifiers(&mut refs);
+                for id in refs {
+                    if !strings.iter().any(|s: &YaraString| s.identifier == id) {
+                        bail!("Rule {} references undefined string {}", name, id);
+                    }
+                }
+            }
+
+            rules.push(YaraRule {
+                name: name.to_string(),
+                tags,
+                meta,
+                strings,
+                condition,
+            });
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &[YaraRule] {
+        &self.rules
+    }
+
+    pub fn scan(&self, data: &[u8]) -> Vec<RuleMatch> {
+        let mut results = Vec::new();
+
+        for rule in &self.rules {
+            let mut matched: HashMap<&str, Vec<StringMatch>> = HashMap::new();
+
+            for string in &rule.strings {
+                let hits: Vec<StringMatch> = string.regex.find_iter(data)
+                    .take(MAX_MATCHES_PER_STRING)
+                    .map(|m| StringMatch {
+                        identifier: string.identifier.clone(),
+                        offset: m.start(),
+                        length: m.len(),
+                    })
+                    .collect();
+
+                if !hits.is_empty() {
+                    matched.insert(string.identifier.as_str(), hits);
+                }
+            }
+
+            let satisfied = match &rule.condition {
+                Condition::AnyOf => !matched.is_empty(),
+                Condition::AllOf => matched.len() == rule.strings.len(),
+                Condition::CountOf(n) => matched.len() >= *n,
+                Condition::Expr(expr) => expr.evaluate(&|id| matched.contains_key(id)),
+            };
+
+            if satisfied {
+                results.push(RuleMatch {
+                    rule: rule.name.clone(),
+                    tags: rule.tags.clone(),
+                    strings: matched.into_values().flatten().collect(),
+                });
+            }
+        }
+
+        results
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, is_match: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Expr::True => true,
+            Expr::String(id) => is_match(id),
+            Expr::And(a, b) => a.evaluate(is_match) && b.evaluate(is_match),
+            Expr::Or(a, b) => a.evaluate(is_match) || b.evaluate(is_match),
+            Expr::Not(a) => !a.evaluate(is_match),
+        }
+    }
+
+    fn identifiers(&self, out: &mut Vec<String>) {
+        match self {
+            Expr::True => {}
+            Expr::String(id) => out.push(id.clone()),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.identifiers(out);
+                b.identifiers(out);
+            }
+            Expr::Not(a) => a.identifiers(out),
+        }
+    }
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    let mut in_string = false;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            out.push(c);
+            if c == '\\' && i + 1 < chars.len() {
+                out.push(chars[i + 1]);
+                i += 1;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                if chars[i] == '\n' {
+                    out.push('\n');
+                }
+                i += 1;
+            }
+            i += 2;
+            continue;
+        } else {
+            out.push(c);
+        }
+        i += 1;
+    }
+
+    out
+}
+
+// Parse `$id = "text" nocase`, `$id = { 4D 5A ?? }` or `$id = /regex/`
+fn parse_string(line: &str) -> Result<YaraString> {
+    let (identifier, definition) = line.split_once('=')
+        .ok_or_else(|| anyhow!("Invalid string definition: {}", line))?;
+    let identifier = identifier.trim().to_string();
+    if !identifier.starts_with('$') {
+        bail!("String identifier must start with '$': {}", identifier);
+    }
+    let definition = definition.trim();
+
+    let pattern = if let Some(rest) = definition.strip_prefix('"') {
+        let (text, modifiers) = split_quoted(rest)?;
+        let modifiers: Vec<&str> = modifiers.split_whitespace().collect();
+        let nocase = modifiers.contains(&"nocase");
+        let wide = modifiers.contains(&"wide");
+        let ascii = modifiers.contains(&"ascii") || !wide;
+
+        let mut variants = Vec::new();
+        if ascii {
+            variants.push(escape_bytes(&text));
+        }
+        if wide {
+            let widened: Vec<u8> = text.iter().flat_map(|&b| [b, 0]).collect();
+            variants.push(escape_bytes(&widened));
+        }
+
+        format!("(?{}s-u){}", if nocase { "i" } else { "" }, variants.join("|"))
+    } else if let Some(rest) = definition.strip_prefix('{') {
+        let hex = rest.split('}').next().unwrap_or("");
+        format!("(?s-u){}", hex_to_regex(hex)?)
+    } else if let Some(rest) = definition.strip_prefix('/') {
+        let end = rest.rfind('/').ok_or_else(|| anyhow!("Unterminated regex: {}", line))?;
+        let flags = &rest[end + 1..];
+        let nocase = flags.starts_with('i') || flags.contains("nocase");
+        format!("(?{}s-u){}", if nocase { "i" } else { "" }, &rest[..end])
+    } else {
+        bail!("Unsupported string definition: {}", line);
+    };
+
+    let regex = Regex::new(&pattern)
+        .with_context(|| format!("Failed to compile string {}", identifier))?;
+
+    Ok(YaraString { identifier, regex })
+}
+
+// Returns the unescaped bytes of a quoted string and the remaining modifiers
+fn split_quoted(rest: &str) -> Result<(Vec<u8>, &str)> {
+    let bytes = rest.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return Ok((out, &rest[i + 1..])),
+            b'\\' if i + 1 < bytes.len() => {
+                i += 1;
+                match bytes[i] {
+                    b'n' => out.push(b'\n'),
+                    b't' => out.push(b'\t'),
+                    b'r' => out.push(b'\r'),
+                    b'x' if i + 2 < bytes.len() => {
+                        let hex = std::str::from_utf8(&bytes[i + 1..i + 3])?;
+                        out.push(u8::from_str_radix(hex, 16)?);
+                        i += 2;
+                    }
+                    other => out.push(other),
+                }
+            }
+            other => out.push(other),
+        }
+        i += 1;
+    }
+
+    bail!("Unterminated string literal")
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\x{:02x}", b)).collect()
+}
+
+fn hex_to_regex(hex: &str) -> Result<String> {
+    let mut out = String::new();
+    let tokens: Vec<&str> = hex.split_whitespace().collect();
+
+    for token in tokens {
+        if token == "??" {
+            out.push('.');
+        } else if let Some(jump) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            match jump.split_once('-') {
+                Some((lo, "")) => out.push_str(&format!(".{{{},}}", lo.trim().parse::<usize>()?)),
+                Some((lo, hi)) => out.push_str(&format!(
+                    ".{{{},{}}}",
+                    lo.trim().parse::<usize>()?,
+                    hi.trim().parse::<usize>()?
+                )),
+                None => out.push_str(&format!(".{{{}}}", jump.trim().parse::<usize>()?)),
+            }
+        } else {
+            // Tokens may be written without spaces, e.g. "4D5A"
+            if token.len() % 2 != 0 {
+                bail!("Invalid hex token: {}", token);
+            }
+            for pair in token.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair)?;
+                if pair == "??" {
+                    out.push('.');
+                } else {
+                    out.push_str(&format!("\\x{:02x}", u8::from_str_radix(pair, 16)?));
+                }
+            }
+        }
+    }
+
+    if out.is_empty() {
+        bail!("Empty hex string");
+    }
+
+    Ok(out)
+}
+
+fn parse_condition(text: &str) -> Result<Condition> {
+    let lowered = text.to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["any", "of", "them"] => return Ok(Condition::AnyOf),
+        ["all", "of", "them"] => return Ok(Condition::AllOf),
+        [n, "of", "them"] => {
+            if let Ok(n) = n.parse() {
+                return Ok(Condition::CountOf(n));
+            }
+        }
+        _ => {}
+    }
+
+    let tokens = tokenize_condition(text)?;
+    let mut pos = 0;
+    let expr = parse_or(&tokens, &mut pos)?;
+    if pos != tokens.len() {
+        bail!("Unexpected token in condition: {}", tokens[pos]);
+    }
+    Ok(Condition::Expr(expr))
+}
+
+fn tokenize_condition(text: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+
+    for c in text.chars() {
+        match c {
+            '(' | ')' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    if tokens.is_empty() {
+        bail!("Empty condition");
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> Result<Expr> {
+    let mut left = parse_and(tokens, pos)?;
+    while tokens.get(*pos).map(|t| t == "or").unwrap_or(false) {
+        *pos += 1;
+        let right = parse_and(tokens, pos)?;
+        left = Expr::Or(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> Result<Expr> {
+    let mut left = parse_unary(tokens, pos)?;
+    while tokens.get(*pos).map(|t| t == "and").unwrap_or(false) {
+        *pos += 1;
+        let right = parse_unary(tokens, pos)?;
+        left = Expr::And(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> Result<Expr> {
+    let token = tokens.get(*pos).ok_or_else(|| anyhow!("Unexpected end of condition"))?;
+    *pos += 1;
+
+    match token.as_str() {
+        "not" => Ok(Expr::Not(Box::new(parse_unary(tokens, pos)?))),
+        "true" => Ok(Expr::True),
+        "(" => {
+            let expr = parse_or(tokens, pos)?;
+            if tokens.get(*pos).map(|t| t == ")").unwrap_or(false) {
+                *pos += 1;
+                Ok(expr)
+            } else {
+                bail!("Missing ')' in condition")
+            }
+        }
+        t if t.starts_with('$') => Ok(Expr::String(t.to_string())),
+        t => bail!("Unsupported condition token: {}", t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_scan() {
+        let rules = YaraRules::parse(r#"
+            // Cobalt Strike style beacon stub
+            rule Beacon_Stub : memory c2 {
+                meta:
+                    author = "fluxdefense"
+                strings:
+                    $mz = { 4D 5A ?? 00 }
+                    $pipe = "\\\\.\\pipe\\msagent" nocase
+                    $ua = /Mozilla\/5\.0 \(compatible; MSIE [0-9]+/
+                condition:
+                    $mz and ($pipe or $ua)
+            }
+
+            rule Two_Of_Them {
+                strings:
+                    $a = "alpha" wide
+                    $b = "beta"
+                    $c = "gamma"
+                condition:
+                    2 of them
+            }
+        "#).unwrap();
+
+        assert_eq!(rules.len(), 2);
+
+        let data = b"junk MZ\x90\x00 more \\\\.\\PIPE\\MSAGENT_12 junk";
+        let matches = rules.scan(data);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule, "Beacon_Stub");
+        assert_eq!(matches[0].tags, vec!["memory", "c2"]);
+        let mz = matches[0].strings.iter().find(|s| s.identifier == "$mz").unwrap();
+        assert_eq!(mz.offset, 5);
+
+        let data = b"a\x00l\x00p\x00h\x00a\x00 beta";
+        let matches = rules.scan(data);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule, "Two_Of_Them");
+    }
+
+    #[test]
+    fn test_rejects_undefined_string() {
+        let result = YaraRules::parse(r#"
+            rule Broken {
+                strings:
+                    $a = "x"
+                condition:
+                    $a and $b
+            }
+        "#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::{info, warn, error};
+
+// Longest the scheduler thread sleeps between checks for due tasks
+const MAX_IDLE: Duration = Duration::from_secs(30);
+
+const MONTH_NAMES: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// A five-field cron expression: minute, hour, day of month, month and day of
+// week. Fields take `*`, numbers, ranges, lists and `/step`; months and
+// weekdays also take three-letter names, and Sunday is 0 or 7. As in cron,
+// when both day fields are restricted a day matching either one is due.
+// @hourly, @daily, @weekly, @monthly and @yearly stand for the usual expressions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = expression.trim();
+        let expanded = match expression {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("Cron expression needs 5 fields: {}", expression);
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAY_NAMES, 0)
+            .with_context(|| format!("Invalid day of week in {}", expression))?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            expression: expression.to_string(),
+            minutes: parse_field(minute, 0, 59, &[], 0).with_context(|| format!("Invalid minute in {}", expression))?,
+            hours: parse_field(hour, 0, 23, &[], 0).with_context(|| format!("Invalid hour in {}", expression))?,
+            days: parse_field(day, 1, 31, &[], 0).with_context(|| format!("Invalid day of month in {}", expression))?,
+            months: parse_field(month, 1, 12, &MONTH_NAMES, 1).with_context(|| format!("Invalid month in {}", expression))?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    // The first matching minute after `after`, in its time zone. Minutes that
+    // a DST change skips never match; None for schedules that cannot occur,
+    // such as February 30th.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let local = after.naive_local();
+        let mut time = local.date().and_hms_opt(local.hour(), local.minute(), 0)? + ChronoDuration::minutes(1);
+        let last_year = local.year() + 5;
+
+        while time.year() <= last_year {
+            if self.months & (1 << time.month()) == 0 {
+                time = first_of_next_month(time)?;
+            } else if !self.day_matches(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.date().and_hms_opt(time.hour(), 0, 0)? + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += ChronoDuration::minutes(1);
+            } else {
+                match timezone.from_local_datetime(&time).earliest() {
+                    Some(due) => return Some(due),
+                    None => time += ChronoDuration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+}
+
+impl TryFrom<String> for CronSchedule {
+    type Error = anyhow::Error;
+
+    fn try_from(expression: String) -> Result<Self> {
+        Self::parse(&expression)
+    }
+}
+
+impl From<CronSchedule> for String {
+    fn from(schedule: CronSchedule) -> Self {
+        schedule.expression
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn first_of_next_month(time: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = if time.month() == 12 { (time.year() + 1, 1) } else { (time.year(), time.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+// Bit n is set for each value n the field allows. `names[i]` stands for
+// `offset + i`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u64> {
+    let value = |text: &str| -> Result<u32> {
+        let lower = text.to_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + offset,
+            None => text.parse().map_err(|_| anyhow!("'{}' is not a number", text))?,
+        };
+        if value < min || value > max {
+            bail!("{} is outside {}-{}", value, min, max);
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| anyhow!("Invalid step '{}'", step))?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            bail!("Step must be at least 1");
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            bail!("Range {} runs backwards", range);
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+// Work done by a scheduled task, on its own thread; the message summarizes the run
+pub type TaskJob = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub id: String,
+    pub kind: String,
+    pub schedule: String,
+    pub enabled: bool,
+    pub running: bool,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub last_outcome: Option<RunOutcome>,
+    pub last_message: Option<String>,
+    pub next_run: Option<DateTime<Utc>>,
+    pub runs: u64,
+    pub failures: u64,
+    // Scheduled runs dropped because the previous run was still going
+    pub skipped: u64,
+}
+
+struct Task {
+    schedule: CronSchedule,
+    job: TaskJob,
+    status: Mutex<TaskStatus>,
+}
+
+// Runs tasks on cron schedules in local time. A task never overlaps itself:
+// when it is due, or run by hand, while the previous run is still going, the
+// new run is skipped.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: RwLock<Vec<Arc<Task>>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, id: &str, kind: &str, schedule: CronSchedule, enabled: bool, job: TaskJob) -> Result<()> {
+        let mut tasks = self.tasks.write().map_err(|_| anyhow!("Failed to acquire scheduler write lock"))?;
+        if tasks.iter().any(|task| task.status.lock().is_ok_and(|status| status.id == id)) {
+            bail!("Scheduled task {} already exists", id);
+        }
+
+        let next_run = enabled.then(|| schedule.next_after(&Local::now())).flatten();
+        let status = TaskStatus {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            schedule: schedule.to_string(),
+            enabled,
+            running: false,
+            last_run: None,
+            last_duration_ms: None,
+            last_outcome: None,
+            last_message: None,
+            next_run: next_run.map(|next_run| next_run.with_timezone(&Utc)),
+            runs: 0,
+            failures: 0,
+            skipped: 0,
+        };
+        info!("Scheduled {} task {} ({})", kind, id, schedule);
+        tasks.push(Arc::new(Task { schedule, job, status: Mutex::new(status) }));
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<TaskStatus> {
+        self.tasks.read()
+            .map(|tasks| tasks.iter().filter_map(|task| task.status.lock().ok().map(|status| status.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<TaskStatus> {
+        self.list().into_iter().find(|status| status.id == id)
+    }
+
+    // Starts a run outside the schedule, disabled tasks included; Ok(false)
+    // when the task is already running
+    pub fn run_now(&self, id: &str) -> Result<bool> {
+        let task = self.tasks.read()
+            .map_err(|_| anyhow!("Failed to acquire scheduler read lock"))?
+            .iter()
+            .find(|task| task.status.lock().is_ok_and(|status| status.id == id))
+            .cloned()
+            .ok_or_else(|| anyhow!("No scheduled task {}", id))?;
+        launch(&task)
+    }
+
+    // Launches the enabled tasks due at `now` and returns how long until the next one
+    fn run_due(&self, now: DateTime<Local>) -> Duration {
+        let tasks = match self.tasks.read() {
+            Ok(tasks) => tasks.clone(),
+            Err(_) => return MAX_IDLE,
+        };
+
+        let mut idle = MAX_IDLE;
+        for task in tasks {
+            let due = {
+                let Ok(mut status) = task.status.lock() else { continue };
+                let Some(next_run) = status.next_run.filter(|_| status.enabled) else { continue };
+                let due = next_run <= now;
+                if due {
+                    status.next_run = task.schedule.next_after(&now).map(|next_run| next_run.with_timezone(&Utc));
+                }
+                if let Some(next_run) = status.next_run {
+                    idle = idle.min((next_run - now.with_timezone(&Utc)).to_std().unwrap_or_default());
+                }
+                due
+            };
+            if due {
+                if let Err(e) = launch(&task) {
+                    error!("Failed to start scheduled task: {:#}", e);
+                }
+            }
+        }
+        idle
+    }
+
+    // Checks for due tasks on a background thread until the scheduler is dropped
+    pub fn start(self: &Arc<Self>) -> Result<()> {
+        let scheduler = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("scheduler".to_string())
+            .spawn(move || {
+                while let Some(scheduler) = scheduler.upgrade() {
+                    let idle = scheduler.run_due(Local::now());
+                    drop(scheduler);
+                    std::thread::sleep(idle.max(Duration::from_millis(200)));
+                }
+            })?;
+        Ok(())
+    }
+}
+
+fn launch(task: &Arc<Task>) -> Result<bool> {
+    let id = {
+        let mut status = task.status.lock().map_err(|_| anyhow!("Failed to acquire task status lock"))?;
+        if status.running {
+            status.skipped += 1;
+            warn!("Scheduled task {} is still running, skipping this run", status.id);
+            return Ok(false);
+        }
+        status.running = true;
+        status.last_run = Some(Utc::now());
+        status.id.clone()
+    };
+
+    let running = Arc::clone(task);
+    let spawned = std::thread::Builder::new()
+        .name(format!("task-{}", id))
+        .spawn(move || {
+            let task = running;
+            let started = Instant::now();
+            let result = (task.job)();
+            let Ok(mut status) = task.status.lock() else { return };
+            status.running = false;
+            status.runs += 1;
+            status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+            match result {
+                Ok(message) => {
+                    info!("Scheduled task {} finished: {}", status.id, message);
+                    status.last_outcome = Some(RunOutcome::Succeeded);
+                    status.last_message = Some(message);
+                }
+                Err(e) => {
+                    warn!("Scheduled task {} failed: {:#}", status.id, e);
+                    status.failures += 1;
+                    status.last_outcome = Some(RunOutcome::Failed);
+                    status.last_message = Some(format!("{:#}", e));
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        if let Ok(mut status) = task.status.lock() {
+            status.running = false;
+        }
+        return Err(e.into());
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_cron_schedule() {
+        let next = |expression: &str, after: &str| CronSchedule::parse(expression).unwrap().next_after(&at(after));
+        assert_eq!(next("*/15 * * * *", "2026-03-10T10:07:30Z"), Some(at("2026-03-10T10:15:00Z")));
+        assert_eq!(next("30 2 * * *", "2026-03-10T02:30:00Z"), Some(at("2026-03-11T02:30:00Z")));
+        assert_eq!(next("0 9-17/4 * * mon-fri", "2026-03-13T17:01:00Z"), Some(at("2026-03-16T09:00:00Z")));
+        assert_eq!(next("@monthly", "2026-12-05T00:00:00Z"), Some(at("2027-01-01T00:00:00Z")));
+        // Both day fields restricted: the 1st or any Sunday
+        assert_eq!(next("0 0 1 * 7", "2026-03-02T00:00:00Z"), Some(at("2026-03-08T00:00:00Z")));
+        assert_eq!(next("0 0 30 feb *", "2026-01-01T00:00:00Z"), None);
+        for invalid in ["* * * *", "60 * * * *", "0 0 * * 8", "5-1 * * * *", "*/0 * * * *", "0 0 * foo *"] {
+            assert!(CronSchedule::parse(invalid).is_err(), "{}", invalid);
+        }
+        let schedule: CronSchedule = serde_json::from_str("\"0 3 * * sun\"").unwrap();
+        assert_eq!(serde_json::to_string(&schedule).unwrap(), "\"0 3 * * sun\"");
+    }
+
+    #[test]
+    fn test_scheduler_skips_overlapping_runs() {
+        let scheduler = Scheduler::new();
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        scheduler.add("scan", "full_scan", CronSchedule::parse("* * * * *").unwrap(), true, Arc::new(move || {
+            gate.lock().unwrap().recv()?;
+            Ok("done".to_string())
+        })).unwrap();
+        assert!(scheduler.add("scan", "full_scan", CronSchedule::parse("@daily").unwrap(), true, Arc::new(|| Ok(String::new()))).is_err());
+
+        assert!(scheduler.run_now("scan").unwrap());
+        assert!(!scheduler.run_now("scan").unwrap());
+        // Due on schedule while the manual run is still going
+        scheduler.run_due(Local::now() + ChronoDuration::minutes(2));
+        let status = scheduler.get("scan").unwrap();
+        assert!(status.running);
+        assert_eq!(status.skipped, 2);
+        assert!(status.next_run.unwrap() > Utc::now() + ChronoDuration::minutes(2));
+
+        release.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.get("scan").unwrap().running && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let status = scheduler.get("scan").unwrap();
+        assert_eq!((status.runs, status.last_outcome, status.last_message.as_deref()), (1, Some(RunOutcome::Succeeded), Some("done")));
+        assert!(scheduler.run_now("missing").is_err());
+    }
+}
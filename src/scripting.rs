@@ -0,0 +1,249 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+// Custom logic for correlation rules and response playbooks, written in Rhai.
+// Scripts see their inputs as constants, cannot import modules or call
+// eval, and are stopped once they exceed their limits:
+//
+//   script:
+//     source: |
+//       let host = event.event_type.NetworkConnection.domain ?? "";
+//       shannon_entropy(host) > params.max_entropy
+//     params: { max_entropy: 3.5 }
+//     limits: { max_operations: 20000 }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleScript {
+    pub source: String,
+    // Lookup tables and thresholds, visible to the script as `params`
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub limits: ScriptLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptLimits {
+    // Rhai operations, roughly one per expression evaluated
+    pub max_operations: u64,
+    pub timeout_ms: u64,
+    // Sizes in characters and elements, which bound the memory a run can use
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+    pub max_call_depth: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 100_000,
+            timeout_ms: 50,
+            max_string_size: 64 * 1024,
+            max_array_size: 10_000,
+            max_map_size: 10_000,
+            max_call_depth: 32,
+        }
+    }
+}
+
+impl ScriptLimits {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_operations == 0 || self.timeout_ms == 0 {
+            return Err(anyhow!("script max_operations and timeout_ms must be above zero"));
+        }
+        if self.max_string_size == 0 || self.max_array_size == 0 || self.max_map_size == 0 || self.max_call_depth == 0 {
+            return Err(anyhow!("script size limits must be above zero"));
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    // When the script running on this thread has to stop
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// Checking the clock on every operation would dominate short scripts
+const CLOCK_CHECK_INTERVAL: u64 = 256;
+// Patterns passed to regex_match are compiled per call
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+// A parsed script with an engine configured for its limits
+pub struct CompiledScript {
+    engine: Engine,
+    ast: AST,
+    params: Dynamic,
+    limits: ScriptLimits,
+}
+
+impl std::fmt::Debug for CompiledScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompiledScript").field("limits", &self.limits).finish_non_exhaustive()
+    }
+}
+
+impl RuleScript {
+    pub fn compile(&self) -> Result<CompiledScript> {
+        self.limits.validate()?;
+        let engine = sandboxed_engine(&self.limits);
+        let ast = engine.compile(&self.source).map_err(|e| anyhow!("script does not parse: {}", e))?;
+        let params = rhai::serde::to_dynamic(&self.params).map_err(|e| anyhow!("script params: {}", e))?;
+        Ok(CompiledScript { engine, ast, params, limits: self.limits })
+    }
+}
+
+impl CompiledScript {
+    // Runs the script with each input bound to a constant of that name
+    pub fn run(&self, inputs: &[(&str, &serde_json::Value)]) -> Result<Dynamic> {
+        let mut scope = Scope::new();
+        scope.push_constant_dynamic("params", self.params.clone());
+        for (name, value) in inputs {
+            let value = rhai::serde::to_dynamic(value).map_err(|e| anyhow!("script input {}: {}", name, e))?;
+            scope.push_constant_dynamic(*name, value);
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(self.limits.timeout_ms);
+        let previous = DEADLINE.with(|cell| cell.replace(Some(deadline)));
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        DEADLINE.with(|cell| cell.set(previous));
+        result.map_err(|e| anyhow!("script failed: {}", e))
+    }
+
+    // For scripts that decide whether something applies; anything but a
+    // boolean is an error
+    pub fn run_bool(&self, inputs: &[(&str, &serde_json::Value)]) -> Result<bool> {
+        let value = self.run(inputs)?;
+        value.as_bool().map_err(|kind| anyhow!("script returned {} instead of a boolean", kind))
+    }
+
+    // The script's result converted back to JSON
+    pub fn run_json(&self, inputs: &[(&str, &serde_json::Value)]) -> Result<serde_json::Value> {
+        let value = self.run(inputs)?;
+        rhai::serde::from_dynamic(&value).context("script result is not representable as JSON")
+    }
+}
+
+fn sandboxed_engine(limits: &ScriptLimits) -> Engine {
+    let mut engine = Engine::new();
+    // The default resolver loads modules from the file system
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(limits.max_operations);
+    engine.set_max_string_size(limits.max_string_size);
+    engine.set_max_array_size(limits.max_array_size);
+    engine.set_max_map_size(limits.max_map_size);
+    engine.set_max_call_levels(limits.max_call_depth);
+    engine.set_max_expr_depths(64, 32);
+    engine.on_progress(|operations| {
+        if operations % CLOCK_CHECK_INTERVAL != 0 {
+            return None;
+        }
+        let expired = DEADLINE.with(|cell| cell.get()).is_some_and(|deadline| Instant::now() >= deadline);
+        expired.then(|| Dynamic::from("timeout"))
+    });
+    // Scripts log through tracing rather than stdout
+    engine.on_print(|text| tracing::info!("script: {}", text));
+    engine.on_debug(|text, _, position| tracing::debug!("script {}: {}", position, text));
+
+    engine.register_fn("regex_match", |text: &str, pattern: &str| -> Result<bool, Box<rhai::EvalAltResult>> {
+        let regex = regex::RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(regex.is_match(text))
+    });
+    engine.register_fn("shannon_entropy", |text: &str| -> f64 { shannon_entropy(text) });
+    engine.register_fn("basename", |path: &str| -> String {
+        path.rsplit('/').next().unwrap_or(path).to_string()
+    });
+    engine.register_fn("in_cidr", |ip: &str, cidr: &str| -> bool { in_cidr(ip, cidr) });
+    engine
+}
+
+// Bits per character; random-looking names (DGA domains, packed strings)
+// score high
+pub fn shannon_entropy(text: &str) -> f64 {
+    let mut counts = std::collections::HashMap::new();
+    let mut total = 0usize;
+    for c in text.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+        total += 1;
+    }
+    counts.values().fold(0.0, |entropy, &count| {
+        let p = count as f64 / total as f64;
+        entropy - p * p.log2()
+    })
+}
+
+fn in_cidr(ip: &str, cidr: &str) -> bool {
+    let (Ok(ip), Some((network, prefix))) = (ip.parse::<std::net::IpAddr>(), cidr.split_once('/')) else {
+        return false;
+    };
+    let (Ok(network), Ok(prefix)) = (network.parse::<std::net::IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+    match (ip, network) {
+        (std::net::IpAddr::V4(ip), std::net::IpAddr::V4(network)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (std::net::IpAddr::V6(ip), std::net::IpAddr::V6(network)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(source: &str, params: serde_json::Value, limits: ScriptLimits) -> CompiledScript {
+        RuleScript {
+            source: source.to_string(),
+            params: params.as_object().cloned().unwrap_or_default(),
+            limits,
+        }.compile().unwrap()
+    }
+
+    #[test]
+    fn test_sandboxed_scripts() {
+        let event = json!({ "process": "/usr/bin/curl", "domain": "xk2j9qv7zt.example", "ip": "10.1.2.3" });
+        let check = script(
+            r#"basename(event.process) in params.tools && shannon_entropy(event.domain) > params.entropy
+                && !in_cidr(event.ip, "192.168.0.0/16") && regex_match(event.domain, "\\.example$")"#,
+            json!({ "tools": ["curl", "wget"], "entropy": 3.0 }),
+            ScriptLimits::default(),
+        );
+        assert!(check.run_bool(&[("event", &event)]).unwrap());
+        let calm = json!({ "process": "/usr/bin/curl", "domain": "aaaa.example", "ip": "10.1.2.3" });
+        assert!(!check.run_bool(&[("event", &calm)]).unwrap());
+        assert!(check.run_bool(&[]).is_err());
+
+        let transform = script(r#"#{ user: event.process.to_upper(), n: 2 }"#, json!({}), ScriptLimits::default());
+        assert_eq!(transform.run_json(&[("event", &event)]).unwrap(), json!({ "user": "/USR/BIN/CURL", "n": 2 }));
+
+        // Runaway loops and allocations are cut off
+        let spin = script("loop {}", json!({}), ScriptLimits { max_operations: 1000, ..Default::default() });
+        assert!(spin.run(&[]).is_err());
+        let slow = script("loop {}", json!({}), ScriptLimits { max_operations: u64::MAX, timeout_ms: 20, ..Default::default() });
+        let started = Instant::now();
+        assert!(slow.run(&[]).is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let grow = script(r#"let s = "x"; loop { s += s; }"#, json!({}), ScriptLimits { max_string_size: 1024, ..Default::default() });
+        assert!(grow.run(&[]).unwrap_err().to_string().contains("too large"));
+
+        // No file access through modules, and no eval
+        assert!(script(r#"import "/etc/passwd" as p; 1"#, json!({}), ScriptLimits::default()).run(&[]).is_err());
+        let eval = RuleScript { source: r#"eval("1")"#.to_string(), params: Default::default(), limits: ScriptLimits::default() };
+        assert!(eval.compile().is_err());
+        let bad = RuleScript { source: "1".to_string(), params: Default::default(), limits: ScriptLimits { timeout_ms: 0, ..Default::default() } };
+        assert!(bad.compile().is_err());
+    }
+}
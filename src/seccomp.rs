@@ -0,0 +1,265 @@
+use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompMode {
+    // Everything is allowed, syscalls outside the allowlist are logged by the
+    // kernel (audit type=SECCOMP); use this to build the allowlist
+    #[default]
+    Log,
+    // Syscalls outside the allowlist fail with EPERM
+    Errno,
+    // Syscalls outside the allowlist kill the daemon
+    Kill,
+}
+
+// Restricts the daemon to the syscalls it needs once it has initialised.
+// The filter covers every thread and everything the daemon executes (the nft
+// and iptables CLI fallbacks), and cannot be removed again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeccompConfig {
+    pub mode: SeccompMode,
+    // Added to the built-in allowlist, e.g. from `flux-monitor seccomp-report`
+    pub allow: Vec<String>,
+    // Removed from it, e.g. execve when no external tools are used
+    pub deny: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{allowlist, install, syscall_name, syscall_number};
+
+#[cfg(not(target_os = "linux"))]
+pub fn install(_config: &SeccompConfig) -> anyhow::Result<usize> {
+    Err(anyhow::anyhow!("Seccomp is only supported on Linux"))
+}
+
+// Syscalls the kernel logged for a seccomp filter, from audit.log or kernel
+// log text. Only records for `pid` count when given.
+pub fn logged_syscalls(log: &str, pid: Option<u32>) -> BTreeSet<u64> {
+    log.lines()
+        .filter(|line| line.contains("type=SECCOMP") || line.contains("type=1326"))
+        .filter_map(|line| {
+            let field = |key: &str| line.split_whitespace()
+                .find_map(|part| part.strip_prefix(key))
+                .and_then(|value| value.parse::<u64>().ok());
+            if pid.is_some_and(|pid| field("pid=") != Some(pid as u64)) {
+                return None;
+            }
+            field("syscall=")
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::{anyhow, bail, Result};
+    use tracing::info;
+
+    use super::{SeccompConfig, SeccompMode};
+
+    // From linux/audit.h; the filter refuses syscalls made through another ABI
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    // Offsets into struct seccomp_data
+    const SECCOMP_DATA_NR: u32 = 0;
+    const SECCOMP_DATA_ARCH: u32 = 4;
+
+    macro_rules! syscalls {
+        ($($name:ident),* $(,)?) => {
+            &[$((stringify!($name), libc::$name as i64)),*]
+        };
+    }
+
+    // What the daemon uses: threads and the async runtime, file and /proc
+    // access, sockets (netlink, packet, inet, unix), fanotify, and starting
+    // helper processes
+    const ALLOWED: &[(&str, i64)] = syscalls![
+        SYS_read, SYS_write, SYS_openat, SYS_openat2, SYS_close, SYS_close_range, SYS_fstat,
+        SYS_newfstatat, SYS_statx, SYS_statfs, SYS_fstatfs, SYS_lseek, SYS_pread64, SYS_pwrite64,
+        SYS_readv, SYS_writev, SYS_mmap, SYS_mprotect, SYS_munmap, SYS_mremap, SYS_madvise,
+        SYS_mincore, SYS_msync, SYS_brk, SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_rt_sigreturn,
+        SYS_sigaltstack, SYS_restart_syscall, SYS_ioctl, SYS_fcntl, SYS_flock, SYS_fsync,
+        SYS_fdatasync, SYS_ftruncate, SYS_fadvise64, SYS_copy_file_range, SYS_sendfile, SYS_splice,
+        SYS_dup, SYS_dup3, SYS_pipe2, SYS_getdents64, SYS_readlinkat, SYS_renameat, SYS_renameat2,
+        SYS_unlinkat, SYS_mkdirat, SYS_linkat, SYS_symlinkat, SYS_faccessat, SYS_faccessat2,
+        SYS_fchmod, SYS_fchmodat, SYS_fchown, SYS_fchownat, SYS_utimensat, SYS_getcwd, SYS_chdir,
+        SYS_fchdir, SYS_umask, SYS_getxattr, SYS_lgetxattr, SYS_fgetxattr, SYS_listxattr,
+        SYS_flistxattr, SYS_name_to_handle_at, SYS_open_by_handle_at, SYS_fanotify_init,
+        SYS_fanotify_mark, SYS_inotify_init1, SYS_inotify_add_watch, SYS_inotify_rm_watch,
+        SYS_memfd_create, SYS_socket, SYS_socketpair, SYS_connect, SYS_accept4, SYS_bind, SYS_listen,
+        SYS_sendto, SYS_recvfrom, SYS_sendmsg, SYS_recvmsg, SYS_sendmmsg, SYS_recvmmsg, SYS_shutdown,
+        SYS_getsockname, SYS_getpeername, SYS_setsockopt, SYS_getsockopt, SYS_bpf, SYS_epoll_create1,
+        SYS_epoll_ctl, SYS_epoll_pwait, SYS_ppoll, SYS_pselect6, SYS_eventfd2, SYS_timerfd_create,
+        SYS_timerfd_settime, SYS_signalfd4, SYS_clone, SYS_clone3, SYS_execve, SYS_exit,
+        SYS_exit_group, SYS_wait4, SYS_waitid, SYS_kill, SYS_tgkill, SYS_pidfd_open,
+        SYS_pidfd_send_signal, SYS_process_vm_readv, SYS_futex, SYS_set_tid_address,
+        SYS_set_robust_list, SYS_get_robust_list, SYS_rseq, SYS_membarrier, SYS_sched_yield,
+        SYS_sched_getaffinity, SYS_sched_setaffinity, SYS_sched_getparam, SYS_sched_getscheduler,
+        SYS_getpriority, SYS_setpriority, SYS_nanosleep, SYS_clock_gettime, SYS_clock_getres,
+        SYS_clock_nanosleep, SYS_gettimeofday, SYS_getitimer, SYS_setitimer, SYS_times,
+        SYS_getrandom, SYS_uname, SYS_sysinfo, SYS_getrlimit, SYS_prlimit64, SYS_getrusage,
+        SYS_prctl, SYS_capget, SYS_getpid, SYS_getppid, SYS_gettid, SYS_getuid, SYS_geteuid,
+        SYS_getgid, SYS_getegid, SYS_getgroups, SYS_getresuid, SYS_getresgid, SYS_getpgid,
+        SYS_setpgid, SYS_setsid,
+    ];
+
+    // Older calls without an *at/*2 replacement on aarch64
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_ARCH: &[(&str, i64)] = syscalls![
+        SYS_open, SYS_stat, SYS_lstat, SYS_access, SYS_readlink, SYS_rename, SYS_unlink, SYS_mkdir,
+        SYS_rmdir, SYS_chmod, SYS_getdents, SYS_poll, SYS_select, SYS_pipe, SYS_dup2, SYS_fork,
+        SYS_vfork, SYS_arch_prctl, SYS_epoll_create, SYS_epoll_wait, SYS_eventfd, SYS_time,
+        SYS_getpgrp,
+    ];
+    #[cfg(target_arch = "aarch64")]
+    const ALLOWED_ARCH: &[(&str, i64)] = &[];
+
+    // Only needed to install the filter or to drop privileges, and to add to
+    // the allowlist explicitly
+    const KNOWN: &[(&str, i64)] = syscalls![
+        SYS_seccomp, SYS_setresuid, SYS_setresgid, SYS_setgroups, SYS_capset, SYS_ptrace,
+        SYS_mount, SYS_umount2, SYS_pivot_root, SYS_chroot, SYS_setns, SYS_unshare, SYS_init_module,
+        SYS_finit_module, SYS_delete_module, SYS_kexec_load, SYS_reboot, SYS_swapon, SYS_swapoff,
+        SYS_perf_event_open, SYS_process_vm_writev, SYS_io_uring_setup, SYS_io_uring_enter,
+        SYS_userfaultfd, SYS_keyctl, SYS_add_key, SYS_request_key, SYS_mlock, SYS_munlock,
+        SYS_mlockall, SYS_munlockall, SYS_settimeofday, SYS_clock_settime, SYS_acct, SYS_quotactl,
+        SYS_sethostname, SYS_setdomainname, SYS_syslog, SYS_personality, SYS_mknodat,
+        SYS_setuid, SYS_setgid, SYS_setfsuid, SYS_setfsgid, SYS_pidfd_getfd, SYS_execveat,
+    ];
+
+    fn table() -> impl Iterator<Item = &'static (&'static str, i64)> {
+        ALLOWED.iter().chain(ALLOWED_ARCH).chain(KNOWN)
+    }
+
+    // Accepts "openat" or "SYS_openat"
+    pub fn syscall_number(name: &str) -> Result<i64> {
+        let name = name.trim();
+        let full = if name.starts_with("SYS_") { name.to_string() } else { format!("SYS_{}", name) };
+        table()
+            .find(|(known, _)| *known == full)
+            .map(|(_, number)| *number)
+            .ok_or_else(|| anyhow!("Unknown syscall: {}", name))
+    }
+
+    pub fn syscall_name(number: i64) -> Option<&'static str> {
+        table()
+            .find(|(_, known)| *known == number)
+            .map(|(name, _)| name.trim_start_matches("SYS_"))
+    }
+
+    // The built-in allowlist with the configured additions and removals
+    pub fn allowlist(config: &SeccompConfig) -> Result<Vec<i64>> {
+        let denied = config.deny.iter()
+            .map(|name| syscall_number(name))
+            .collect::<Result<Vec<i64>>>()?;
+        let mut allowed: Vec<i64> = ALLOWED.iter().chain(ALLOWED_ARCH)
+            .map(|(_, number)| *number)
+            .collect();
+        for name in &config.allow {
+            allowed.push(syscall_number(name)?);
+        }
+        allowed.retain(|number| !denied.contains(number));
+        allowed.sort_unstable();
+        allowed.dedup();
+        Ok(allowed)
+    }
+
+    fn statement(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    pub(super) fn build_filter(allowed: &[i64], mode: SeccompMode) -> Vec<libc::sock_filter> {
+        let otherwise = match mode {
+            SeccompMode::Log => libc::SECCOMP_RET_LOG,
+            SeccompMode::Errno => libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            SeccompMode::Kill => libc::SECCOMP_RET_KILL_PROCESS,
+        };
+
+        let mut program = vec![
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARCH),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+            statement(libc::BPF_RET | libc::BPF_K, otherwise),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+        ];
+        for number in allowed {
+            program.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, *number as u32, 0, 1));
+            program.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        }
+        program.push(statement(libc::BPF_RET | libc::BPF_K, otherwise));
+        program
+    }
+
+    // Installs the filter on every thread of the process. Returns how many
+    // syscalls are allowed.
+    pub fn install(config: &SeccompConfig) -> Result<usize> {
+        let allowed = allowlist(config)?;
+        let program = build_filter(&allowed, config.mode);
+        let fprog = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_ptr() as *mut libc::sock_filter,
+        };
+
+        // Required to install a filter without CAP_SYS_ADMIN; also stops
+        // anything executed from gaining privileges through setuid binaries
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            bail!("PR_SET_NO_NEW_PRIVS failed: {}", std::io::Error::last_os_error());
+        }
+        let result = unsafe {
+            libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, libc::SECCOMP_FILTER_FLAG_TSYNC, &fprog)
+        };
+        if result < 0 {
+            bail!("Failed to install seccomp filter: {}", std::io::Error::last_os_error());
+        }
+        if result > 0 {
+            bail!("Failed to install seccomp filter: thread {} could not be synchronised", result);
+        }
+
+        info!("Seccomp filter installed ({:?} mode, {} syscalls allowed)", config.mode, allowed.len());
+        Ok(allowed.len())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_and_audit_records() {
+        let config = SeccompConfig {
+            mode: SeccompMode::Errno,
+            allow: vec!["ptrace".to_string()],
+            deny: vec!["SYS_execve".to_string()],
+        };
+        let allowed = allowlist(&config).unwrap();
+        assert!(allowed.contains(&syscall_number("ptrace").unwrap()));
+        assert!(allowed.contains(&syscall_number("openat").unwrap()));
+        assert!(!allowed.contains(&(libc::SYS_execve as i64)));
+        assert_eq!(syscall_name(libc::SYS_fanotify_mark as i64), Some("fanotify_mark"));
+
+        // Arch check, a compare and return per syscall, and the fallback
+        let program = linux::build_filter(&allowed, config.mode);
+        assert_eq!(program.len(), 4 + 2 * allowed.len() + 1);
+        assert_eq!(program.last().unwrap().k, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32);
+
+        let bad = SeccompConfig { allow: vec!["not_a_syscall".to_string()], ..Default::default() };
+        assert!(allowlist(&bad).is_err());
+
+        let log = format!(
+            "type=SECCOMP msg=audit(1700000000.123:42): auid=0 uid=0 pid=4242 comm=\"flux-monitor\" arch=c000003e syscall={} compat=0 code=0x7ffc0000\n\
+             type=SECCOMP msg=audit(1700000000.456:43): pid=99 syscall=1 code=0x7ffc0000\n\
+             type=SYSCALL msg=audit(1700000000.789:44): pid=4242 syscall=2",
+            libc::SYS_ptrace
+        );
+        assert_eq!(logged_syscalls(&log, Some(4242)).into_iter().collect::<Vec<_>>(), vec![libc::SYS_ptrace as u64]);
+        assert_eq!(logged_syscalls(&log, None).len(), 2);
+    }
+}
@@ -0,0 +1,766 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Local, TimeZone, Timelike, Utc};
+use anyhow::{anyhow, Result, Context};
+use tracing::{info, warn, debug};
+use uuid::Uuid;
+
+use crate::api::models::LogEntry;
+use crate::monitor::{ProcessInfo, SecurityEvent, SecurityEventType, Verdict};
+
+// glibc `struct utmp` on 64-bit Linux
+const UTMP_RECORD_SIZE: usize = 384;
+const USER_PROCESS: i16 = 7;
+const DEAD_PROCESS: i16 = 8;
+// Processes remembered per session
+const MAX_SESSION_PROCESSES: usize = 200;
+const MAX_ANOMALIES: usize = 500;
+// Ancestors walked when attributing a process to a session
+const MAX_ANCESTRY: usize = 64;
+// A utmp record this close to an sshd login for the same user and address is the same login
+const MERGE_WINDOW_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionConfig {
+    #[serde(default = "default_wtmp_path")]
+    pub wtmp_path: PathBuf,
+    #[serde(default = "default_utmp_path")]
+    pub utmp_path: PathBuf,
+    // Logins a user needs on record before their hours and addresses count as known
+    #[serde(default = "default_learning_logins")]
+    pub learning_logins: u64,
+    // Logins within this many hours of one seen before are not unusual
+    #[serde(default = "default_hour_tolerance")]
+    pub hour_tolerance: u32,
+    // sudo or su this soon after login is reported
+    #[serde(default = "default_chain_window")]
+    pub chain_window_secs: i64,
+    #[serde(default = "default_max_closed")]
+    pub max_closed: usize,
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_wtmp_path() -> PathBuf {
+    PathBuf::from("/var/log/wtmp")
+}
+
+fn default_utmp_path() -> PathBuf {
+    PathBuf::from("/run/utmp")
+}
+
+fn default_learning_logins() -> u64 {
+    5
+}
+
+fn default_hour_tolerance() -> u32 {
+    1
+}
+
+fn default_chain_window() -> i64 {
+    300
+}
+
+fn default_max_closed() -> usize {
+    500
+}
+
+fn default_poll_interval() -> u64 {
+    2
+}
+
+impl SessionConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read session config {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid session config {:?}", path))
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            wtmp_path: default_wtmp_path(),
+            utmp_path: default_utmp_path(),
+            learning_logins: default_learning_logins(),
+            hour_tolerance: default_hour_tolerance(),
+            chain_window_secs: default_chain_window(),
+            max_closed: default_max_closed(),
+            poll_interval_secs: default_poll_interval(),
+        }
+    }
+}
+
+// One login/logout record from utmp or wtmp
+#[derive(Debug, Clone, PartialEq)]
+pub struct UtmpRecord {
+    pub kind: i16,
+    pub pid: u32,
+    pub line: String,
+    pub user: String,
+    pub host: String,
+    pub addr: Option<IpAddr>,
+    pub timestamp: DateTime<Utc>,
+}
+
+pub fn parse_utmp(data: &[u8]) -> Vec<UtmpRecord> {
+    data.chunks_exact(UTMP_RECORD_SIZE).map(|record| {
+        let text = |range: std::ops::Range<usize>| {
+            let bytes = &record[range];
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).to_string()
+        };
+        let int = |at: usize| i32::from_ne_bytes(record[at..at + 4].try_into().unwrap());
+        let addr: [u8; 16] = record[348..364].try_into().unwrap();
+        let addr = if addr == [0; 16] {
+            None
+        } else if addr[4..] == [0; 12] {
+            Some(IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3])))
+        } else {
+            Some(IpAddr::V6(Ipv6Addr::from(addr)))
+        };
+        UtmpRecord {
+            kind: i16::from_ne_bytes([record[0], record[1]]),
+            pid: int(4) as u32,
+            line: text(8..40),
+            user: text(44..76),
+            host: text(76..332),
+            addr,
+            timestamp: Utc.timestamp_opt(i64::from(int(340)), int(344) as u32 * 1000).single().unwrap_or_default(),
+        }
+    }).collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionProcess {
+    pub pid: u32,
+    pub path: PathBuf,
+    pub command_line: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivilegeChange {
+    pub timestamp: DateTime<Utc>,
+    // sudo or su
+    pub tool: String,
+    pub target_user: Option<String>,
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub user: String,
+    pub uid: Option<u32>,
+    pub tty: Option<String>,
+    pub remote_ip: Option<IpAddr>,
+    // utmp host field when it is a name rather than an address
+    pub remote_host: Option<String>,
+    pub auth_method: Option<String>,
+    // sshd or login processes the session's processes descend from
+    pub leader_pids: Vec<u32>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub processes: Vec<SessionProcess>,
+    pub privilege_changes: Vec<PrivilegeChange>,
+}
+
+impl Session {
+    fn new(user: &str, started_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user: user.to_string(),
+            uid: uid_of(user),
+            tty: None,
+            remote_ip: None,
+            remote_host: None,
+            auth_method: None,
+            leader_pids: Vec::new(),
+            started_at,
+            ended_at: None,
+            processes: Vec::new(),
+            privilege_changes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AnomalyKind {
+    // Local hour of a login the user has not logged in around before
+    UnusualHour { hour: u32 },
+    NewSourceIp { ip: IpAddr },
+    PrivilegeChain { tool: String, target_user: Option<String>, seconds_after_login: i64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAnomaly {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub session_id: String,
+    pub user: String,
+    pub remote_ip: Option<IpAddr>,
+    pub kind: AnomalyKind,
+    pub description: String,
+}
+
+impl SessionAnomaly {
+    pub fn rule_id(&self) -> &'static str {
+        match self.kind {
+            AnomalyKind::UnusualHour { .. } => "session_unusual_hour",
+            AnomalyKind::NewSourceIp { .. } => "session_new_source",
+            AnomalyKind::PrivilegeChain { .. } => "session_privilege_chain",
+        }
+    }
+
+    // The login behind the anomaly, for incident correlation
+    pub fn to_security_event(&self, session: &Session) -> SecurityEvent {
+        SecurityEvent {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            event_type: SecurityEventType::Authentication {
+                user: session.user.clone(),
+                service: if session.remote_ip.is_some() { "sshd" } else { "login" }.to_string(),
+                success: true,
+                remote_host: session.remote_ip.map(|ip| ip.to_string()).or_else(|| session.remote_host.clone()),
+            },
+            process_info: ProcessInfo {
+                pid: session.leader_pids.first().copied().unwrap_or(0),
+                path: PathBuf::from("unknown"),
+                parent_pid: None,
+                // (uid_t)-1 when the account is not in /etc/passwd
+                user_id: session.uid.unwrap_or(u32::MAX),
+                executable_hash: None,
+                command_line: None,
+            },
+            verdict: Verdict::Log,
+            policy_reason: self.description.clone(),
+        }
+    }
+}
+
+// When and from where a user has logged in
+#[derive(Debug, Clone, Default)]
+struct LoginProfile {
+    logins: u64,
+    hours: [u64; 24],
+    sources: HashSet<IpAddr>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    active: Vec<Session>,
+    closed: VecDeque<Session>,
+    profiles: HashMap<String, LoginProfile>,
+    anomalies: VecDeque<SessionAnomaly>,
+    wtmp_offset: u64,
+}
+
+pub type ParentLookup = Box<dyn Fn(u32) -> Option<u32> + Send + Sync>;
+type AnomalyHandler = Arc<dyn Fn(&SessionAnomaly, &Session) + Send + Sync>;
+
+// Tracks interactive sessions from utmp/wtmp and the sshd, sudo and su
+// records of the system log, attributes processes to the session they were
+// started from, and reports logins at unusual hours, from new addresses, and
+// privilege changes right after login
+pub struct SessionMonitor {
+    config: SessionConfig,
+    state: RwLock<SessionState>,
+    parent_of: ParentLookup,
+    anomaly_handler: AnomalyHandler,
+}
+
+impl SessionMonitor {
+    pub fn new<F>(config: SessionConfig, anomaly_handler: F) -> Self
+    where
+        F: Fn(&SessionAnomaly, &Session) + Send + Sync + 'static
+    {
+        Self {
+            config,
+            state: RwLock::new(SessionState::default()),
+            parent_of: Box::new(proc_parent),
+            anomaly_handler: Arc::new(anomaly_handler),
+        }
+    }
+
+    pub fn set_parent_lookup(&mut self, lookup: ParentLookup) {
+        self.parent_of = lookup;
+    }
+
+    // Learns the login history in wtmp without reporting on it and picks up
+    // the sessions utmp lists as open; later wtmp records are followed by poll_wtmp
+    pub fn load_history(&self) -> Result<()> {
+        let history = std::fs::read(&self.config.wtmp_path)
+            .with_context(|| format!("Failed to read {}", self.config.wtmp_path.display()))?;
+        let current = std::fs::read(&self.config.utmp_path).unwrap_or_default();
+        let mut state = self.state.write().map_err(|_| anyhow!("Failed to acquire session state write lock"))?;
+
+        let records = parse_utmp(&history);
+        for record in records.iter().filter(|record| record.kind == USER_PROCESS) {
+            let profile = state.profiles.entry(record.user.clone()).or_default();
+            learn(profile, record.timestamp, record_ip(record));
+        }
+        for record in parse_utmp(&current).into_iter().filter(|record| record.kind == USER_PROCESS) {
+            let mut session = Session::new(&record.user, record.timestamp);
+            fill_from_utmp(&mut session, &record);
+            state.active.push(session);
+        }
+        state.wtmp_offset = (records.len() * UTMP_RECORD_SIZE) as u64;
+        info!("Session history: {} logins of {} users, {} sessions open",
+              records.iter().filter(|record| record.kind == USER_PROCESS).count(), state.profiles.len(), state.active.len());
+        Ok(())
+    }
+
+    // Reads wtmp records appended since the last call
+    pub fn poll_wtmp(&self) -> Result<Vec<SessionAnomaly>> {
+        let mut file = File::open(&self.config.wtmp_path)
+            .with_context(|| format!("Failed to open {}", self.config.wtmp_path.display()))?;
+        let length = file.metadata()?.len();
+        let offset = {
+            let mut state = self.state.write().map_err(|_| anyhow!("Failed to acquire session state write lock"))?;
+            // Rotated or truncated
+            if length < state.wtmp_offset {
+                state.wtmp_offset = 0;
+            }
+            state.wtmp_offset
+        };
+        let whole = (length - offset) / UTMP_RECORD_SIZE as u64 * UTMP_RECORD_SIZE as u64;
+        if whole == 0 {
+            return Ok(Vec::new());
+        }
+        let mut data = vec![0; whole as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        if let Ok(mut state) = self.state.write() {
+            state.wtmp_offset = offset + whole;
+        }
+        Ok(parse_utmp(&data).iter().flat_map(|record| self.observe_utmp(record)).collect())
+    }
+
+    pub fn observe_utmp(&self, record: &UtmpRecord) -> Vec<SessionAnomaly> {
+        match record.kind {
+            USER_PROCESS => {
+                let merged = {
+                    let Ok(mut state) = self.state.write() else { return Vec::new() };
+                    let ancestors = self.ancestors(record.pid);
+                    let ip = record_ip(record);
+                    let existing = state.active.iter_mut().find(|session| {
+                        session.leader_pids.iter().any(|pid| ancestors.contains(pid))
+                            || (session.user == record.user && session.tty.is_none() && ip.is_some() && session.remote_ip == ip
+                                && (record.timestamp - session.started_at).num_seconds().abs() <= MERGE_WINDOW_SECS)
+                    });
+                    match existing {
+                        Some(session) => {
+                            fill_from_utmp(session, record);
+                            true
+                        }
+                        None => false,
+                    }
+                };
+                if merged {
+                    return Vec::new();
+                }
+                let mut session = Session::new(&record.user, record.timestamp);
+                fill_from_utmp(&mut session, record);
+                self.open(session)
+            }
+            DEAD_PROCESS => {
+                self.close(record.timestamp, |session| {
+                    session.leader_pids.contains(&record.pid)
+                        || (!record.line.is_empty() && session.tty.as_deref() == Some(record.line.as_str()))
+                });
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // sshd logins and logouts, and sudo/su privilege changes
+    pub fn observe_log(&self, entry: &LogEntry) -> Vec<SessionAnomaly> {
+        let detail = |key: &str| entry.details.as_ref()
+            .and_then(|details| details.get(key))
+            .and_then(|value| value.as_str())
+            .map(String::from);
+        let tagged = |tag: &str| entry.tags.as_ref().is_some_and(|tags| tags.iter().any(|t| t == tag));
+
+        if entry.source.starts_with("sshd") {
+            if tagged("login") {
+                let Some(ref user) = entry.user else { return Vec::new() };
+                let mut session = Session::new(user, entry.timestamp);
+                session.remote_ip = detail("remote_ip").and_then(|ip| ip.parse().ok());
+                session.auth_method = detail("auth_method");
+                session.leader_pids.extend(entry.pid);
+                return self.open(session);
+            }
+            if entry.message.contains("session closed for user") || entry.message.starts_with("Disconnected from user") {
+                if let Some(pid) = entry.pid {
+                    self.close(entry.timestamp, |session| session.leader_pids.contains(&pid));
+                }
+            }
+            return Vec::new();
+        }
+
+        match entry.source.as_str() {
+            "sudo" if detail("command").is_some() => {
+                let Some(ref user) = entry.user else { return Vec::new() };
+                let tty = detail("tty");
+                self.privilege_change(entry.timestamp, user, tty.as_deref(), PrivilegeChange {
+                    timestamp: entry.timestamp,
+                    tool: "sudo".to_string(),
+                    target_user: detail("target_user"),
+                    command: detail("command"),
+                })
+            }
+            // "pam_unix(su-l:session): session opened for user root(uid=0) by alice(uid=1000)"
+            "su" | "su-l" if entry.message.contains("session opened for user ") => {
+                let name = |marker: &str| entry.message.split(marker).nth(1)
+                    .and_then(|rest| rest.split(|c: char| c == '(' || c.is_whitespace()).next())
+                    .filter(|name| !name.is_empty())
+                    .map(String::from);
+                let Some(user) = name(" by ") else { return Vec::new() };
+                self.privilege_change(entry.timestamp, &user, None, PrivilegeChange {
+                    timestamp: entry.timestamp,
+                    tool: "su".to_string(),
+                    target_user: name("session opened for user "),
+                    command: None,
+                })
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Adds an executed process to the session it descends from
+    pub fn observe_event(&self, event: &SecurityEvent) -> Option<String> {
+        let SecurityEventType::FileExecution { ref target_path, .. } = event.event_type else { return None };
+        self.record_process(SessionProcess {
+            pid: event.process_info.pid,
+            path: target_path.clone(),
+            command_line: event.process_info.command_line.clone(),
+            timestamp: event.timestamp,
+        })
+    }
+
+    pub fn record_process(&self, process: SessionProcess) -> Option<String> {
+        let id = self.session_for_pid(process.pid)?;
+        let mut state = self.state.write().ok()?;
+        let session = state.active.iter_mut().find(|session| session.id == id)?;
+        if session.processes.len() >= MAX_SESSION_PROCESSES {
+            session.processes.remove(0);
+        }
+        session.processes.push(process);
+        Some(id)
+    }
+
+    // The open session whose leader the process descends from
+    pub fn session_for_pid(&self, pid: u32) -> Option<String> {
+        let ancestors = self.ancestors(pid);
+        let state = self.state.read().ok()?;
+        state.active.iter()
+            .filter(|session| session.leader_pids.iter().any(|leader| ancestors.contains(leader)))
+            // Nested logins belong to the innermost session
+            .max_by_key(|session| session.started_at)
+            .map(|session| session.id.clone())
+    }
+
+    // Running processes that descend from a session's leaders
+    pub fn live_processes(&self, id: &str) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir("/proc") else { return Vec::new() };
+        entries.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|pid| self.session_for_pid(*pid).as_deref() == Some(id))
+            .collect()
+    }
+
+    pub fn active(&self) -> Vec<Session> {
+        self.state.read().map(|state| state.active.clone()).unwrap_or_default()
+    }
+
+    // Most recently ended first
+    pub fn closed(&self, limit: usize) -> Vec<Session> {
+        self.state.read()
+            .map(|state| state.closed.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let state = self.state.read().ok()?;
+        state.active.iter().chain(state.closed.iter()).find(|session| session.id == id).cloned()
+    }
+
+    // Newest first
+    pub fn anomalies(&self, limit: usize) -> Vec<SessionAnomaly> {
+        self.state.read()
+            .map(|state| state.anomalies.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Follows wtmp on a background thread until the monitor is dropped
+    pub fn start(self: &Arc<Self>) -> Result<()> {
+        let monitor = Arc::downgrade(self);
+        let interval = Duration::from_secs(self.config.poll_interval_secs.max(1));
+        std::thread::Builder::new()
+            .name("session-monitor".to_string())
+            .spawn(move || {
+                while let Some(monitor) = monitor.upgrade() {
+                    if let Err(e) = monitor.poll_wtmp() {
+                        debug!("wtmp poll failed: {:#}", e);
+                    }
+                    drop(monitor);
+                    std::thread::sleep(interval);
+                }
+            })?;
+        Ok(())
+    }
+
+    fn open(&self, session: Session) -> Vec<SessionAnomaly> {
+        let anomalies = {
+            let Ok(mut state) = self.state.write() else { return Vec::new() };
+            let profile = state.profiles.entry(session.user.clone()).or_default();
+            let mut anomalies = Vec::new();
+            if profile.logins >= self.config.learning_logins {
+                let hour = session.started_at.with_timezone(&Local).hour();
+                let tolerance = self.config.hour_tolerance.min(11);
+                let seen = (24 - tolerance..=24 + tolerance).any(|offset| profile.hours[((hour + offset) % 24) as usize] > 0);
+                if !seen {
+                    anomalies.push(self.anomaly(&session, session.started_at, AnomalyKind::UnusualHour { hour },
+                        format!("{} logged in at {:02}:00, outside their usual hours", session.user, hour)));
+                }
+                if let Some(ip) = session.remote_ip.filter(|ip| !profile.sources.contains(ip)) {
+                    anomalies.push(self.anomaly(&session, session.started_at, AnomalyKind::NewSourceIp { ip },
+                        format!("{} logged in from {}, an address not seen for them before", session.user, ip)));
+                }
+            }
+            learn(profile, session.started_at, session.remote_ip);
+            debug!("Session {} opened for {} from {:?}", session.id, session.user, session.remote_ip);
+            state.active.push(session.clone());
+            anomalies
+        };
+        self.report(anomalies, &session)
+    }
+
+    fn close(&self, at: DateTime<Utc>, matches: impl Fn(&Session) -> bool) {
+        let Ok(mut state) = self.state.write() else { return };
+        let (ended, active): (Vec<Session>, Vec<Session>) = std::mem::take(&mut state.active).into_iter().partition(|session| matches(session));
+        state.active = active;
+        for mut session in ended {
+            debug!("Session {} of {} closed", session.id, session.user);
+            session.ended_at = Some(at);
+            state.closed.push_back(session);
+        }
+        while state.closed.len() > self.config.max_closed {
+            state.closed.pop_front();
+        }
+    }
+
+    fn privilege_change(&self, at: DateTime<Utc>, user: &str, tty: Option<&str>, change: PrivilegeChange) -> Vec<SessionAnomaly> {
+        let (session, anomalies) = {
+            let Ok(mut state) = self.state.write() else { return Vec::new() };
+            // Prefer the session on the same terminal, else the user's latest
+            let Some(session) = state.active.iter_mut()
+                .filter(|session| session.user == user)
+                .max_by_key(|session| (tty.is_some() && session.tty.as_deref() == tty, session.started_at))
+            else {
+                return Vec::new();
+            };
+            let seconds = (at - session.started_at).num_seconds();
+            let mut anomalies = Vec::new();
+            if (0..=self.config.chain_window_secs).contains(&seconds) {
+                let target = change.target_user.clone().unwrap_or_else(|| "root".to_string());
+                anomalies.push(self.anomaly(session, at, AnomalyKind::PrivilegeChain {
+                    tool: change.tool.clone(),
+                    target_user: change.target_user.clone(),
+                    seconds_after_login: seconds,
+                }, format!("{} ran {} to {} {}s after logging in", user, change.tool, target, seconds)));
+            }
+            session.privilege_changes.push(change);
+            (session.clone(), anomalies)
+        };
+        self.report(anomalies, &session)
+    }
+
+    fn anomaly(&self, session: &Session, at: DateTime<Utc>, kind: AnomalyKind, description: String) -> SessionAnomaly {
+        SessionAnomaly {
+            id: Uuid::new_v4().to_string(),
+            timestamp: at,
+            session_id: session.id.clone(),
+            user: session.user.clone(),
+            remote_ip: session.remote_ip,
+            kind,
+            description,
+        }
+    }
+
+    fn report(&self, anomalies: Vec<SessionAnomaly>, session: &Session) -> Vec<SessionAnomaly> {
+        if anomalies.is_empty() {
+            return anomalies;
+        }
+        if let Ok(mut state) = self.state.write() {
+            state.anomalies.extend(anomalies.iter().cloned());
+            while state.anomalies.len() > MAX_ANOMALIES {
+                state.anomalies.pop_front();
+            }
+        }
+        for anomaly in &anomalies {
+            warn!("Session anomaly: {}", anomaly.description);
+            (self.anomaly_handler)(anomaly, session);
+        }
+        anomalies
+    }
+
+    // The process and its ancestors, nearest first
+    fn ancestors(&self, pid: u32) -> Vec<u32> {
+        let mut chain = vec![pid];
+        let mut current = pid;
+        while chain.len() < MAX_ANCESTRY {
+            match (self.parent_of)(current) {
+                Some(parent) if parent > 1 && !chain.contains(&parent) => {
+                    chain.push(parent);
+                    current = parent;
+                }
+                _ => break,
+            }
+        }
+        chain
+    }
+}
+
+fn learn(profile: &mut LoginProfile, at: DateTime<Utc>, ip: Option<IpAddr>) {
+    profile.logins += 1;
+    profile.hours[at.with_timezone(&Local).hour() as usize] += 1;
+    profile.sources.extend(ip);
+}
+
+fn record_ip(record: &UtmpRecord) -> Option<IpAddr> {
+    record.addr.or_else(|| record.host.parse().ok())
+}
+
+fn fill_from_utmp(session: &mut Session, record: &UtmpRecord) {
+    if !record.line.is_empty() {
+        session.tty = Some(record.line.clone());
+    }
+    if !session.leader_pids.contains(&record.pid) {
+        session.leader_pids.push(record.pid);
+    }
+    session.remote_ip = session.remote_ip.or_else(|| record_ip(record));
+    if session.remote_ip.is_none() && !record.host.is_empty() {
+        session.remote_host = Some(record.host.clone());
+    }
+}
+
+fn proc_parent(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses
+    let rest = &stat[stat.rfind(')')? + 2..];
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn uid_of(user: &str) -> Option<u32> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&user))
+        .and_then(|fields| fields.get(2)?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use chrono::Duration as ChronoDuration;
+    use serde_json::Value;
+    use crate::api::models::{LogCategory, LogLevel};
+
+    fn utmp(kind: i16, pid: u32, line: &str, user: &str, host: &str, at: DateTime<Utc>) -> Vec<u8> {
+        let mut record = vec![0u8; UTMP_RECORD_SIZE];
+        record[0..2].copy_from_slice(&kind.to_ne_bytes());
+        record[4..8].copy_from_slice(&(pid as i32).to_ne_bytes());
+        record[8..8 + line.len()].copy_from_slice(line.as_bytes());
+        record[44..44 + user.len()].copy_from_slice(user.as_bytes());
+        record[76..76 + host.len()].copy_from_slice(host.as_bytes());
+        record[340..344].copy_from_slice(&(at.timestamp() as i32).to_ne_bytes());
+        if let Ok(IpAddr::V4(ip)) = host.parse() {
+            record[348..352].copy_from_slice(&ip.octets());
+        }
+        record
+    }
+
+    fn log(source: &str, pid: u32, user: &str, message: &str, details: &[(&str, &str)], tags: &[&str], at: DateTime<Utc>) -> LogEntry {
+        LogEntry {
+            id: Uuid::new_v4().to_string(),
+            timestamp: at,
+            level: LogLevel::Info,
+            category: LogCategory::Auth,
+            source: source.to_string(),
+            message: message.to_string(),
+            details: Some(details.iter().map(|(key, value)| (key.to_string(), Value::from(*value))).collect()),
+            user: Some(user.to_string()),
+            pid: Some(pid),
+            tags: Some(tags.iter().map(|tag| tag.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_sessions_and_anomalies() {
+        let dir = std::env::temp_dir().join(format!("fluxdefense-sessions-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Five weekday-morning logins from one address make up bob's history
+        let morning = Local.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap().with_timezone(&Utc);
+        let history: Vec<u8> = (0..5).flat_map(|day| utmp(USER_PROCESS, 100 + day, "pts/0", "bob", "10.0.0.9", morning + ChronoDuration::days(day.into()))).collect();
+        std::fs::write(dir.join("wtmp"), &history).unwrap();
+        let parsed = parse_utmp(&history);
+        assert_eq!((parsed.len(), parsed[0].user.as_str(), parsed[0].addr), (5, "bob", Some("10.0.0.9".parse().unwrap())));
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reported);
+        let config = SessionConfig { wtmp_path: dir.join("wtmp"), utmp_path: dir.join("utmp"), ..Default::default() };
+        let mut monitor = SessionMonitor::new(config, move |anomaly, _| sink.lock().unwrap().push(anomaly.rule_id()));
+        // sshd[500] -> sshd[501] -> bash[502] -> vim[503]
+        monitor.set_parent_lookup(Box::new(|pid| match pid { 501..=503 => Some(pid - 1), _ => None }));
+        monitor.load_history().unwrap();
+        assert!(monitor.poll_wtmp().unwrap().is_empty());
+
+        // 03:00 from a new address, then sudo a minute later
+        let night = Local.with_ymd_and_hms(2026, 3, 9, 3, 0, 0).unwrap().with_timezone(&Utc);
+        let accepted = log("sshd", 500, "bob", "Accepted publickey for bob from 203.0.113.5 port 40022 ssh2",
+            &[("remote_ip", "203.0.113.5"), ("auth_method", "publickey")], &["ssh", "login"], night);
+        let anomalies = monitor.observe_log(&accepted);
+        assert_eq!(anomalies.iter().map(SessionAnomaly::rule_id).collect::<Vec<_>>(), ["session_unusual_hour", "session_new_source"]);
+        let id = anomalies[0].session_id.clone();
+
+        // sshd records the login shell in wtmp; it joins the same session
+        let mut file = std::fs::OpenOptions::new().append(true).open(dir.join("wtmp")).unwrap();
+        std::io::Write::write_all(&mut file, &utmp(USER_PROCESS, 502, "pts/3", "bob", "203.0.113.5", night)).unwrap();
+        assert!(monitor.poll_wtmp().unwrap().is_empty());
+        assert_eq!(monitor.active().len(), 1);
+        assert_eq!(monitor.get(&id).unwrap().tty.as_deref(), Some("pts/3"));
+
+        let process = SessionProcess { pid: 503, path: PathBuf::from("/usr/bin/vim"), command_line: None, timestamp: night };
+        assert_eq!(monitor.record_process(process).as_deref(), Some(id.as_str()));
+        assert_eq!(monitor.session_for_pid(42), None);
+
+        let sudo = log("sudo", 600, "bob", "bob : TTY=pts/3 ; PWD=/home/bob ; USER=root ; COMMAND=/bin/bash",
+            &[("tty", "pts/3"), ("target_user", "root"), ("command", "/bin/bash")], &["sudo"], night + ChronoDuration::seconds(60));
+        let chain = monitor.observe_log(&sudo);
+        assert_eq!(chain[0].kind, AnomalyKind::PrivilegeChain { tool: "sudo".to_string(), target_user: Some("root".to_string()), seconds_after_login: 60 });
+        assert_eq!(reported.lock().unwrap().len(), 3);
+
+        let closed = log("sshd", 500, "bob", "pam_unix(sshd:session): session closed for user bob", &[], &["ssh"], night + ChronoDuration::hours(1));
+        monitor.observe_log(&closed);
+        assert!(monitor.active().is_empty());
+        let session = monitor.get(&id).unwrap();
+        assert_eq!((session.processes.len(), session.privilege_changes.len()), (1, 1));
+        assert!(session.ended_at.is_some());
+
+        // A later login from the new address at the same hour is known by then
+        let again = log("sshd", 700, "bob", "Accepted publickey for bob from 203.0.113.5 port 40100 ssh2",
+            &[("remote_ip", "203.0.113.5")], &["ssh", "login"], night + ChronoDuration::days(1));
+        assert!(monitor.observe_log(&again).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
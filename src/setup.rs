@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+
+use crate::config::{Config, Enforcement, Profile};
+use crate::preflight::{self, CapabilityReport};
+
+// What `flux-monitor init` finds on the host before asking anything
+#[derive(Debug, Clone, Serialize)]
+pub struct HostCapabilities {
+    pub root: bool,
+    // CAP_SYS_ADMIN, needed for fanotify permission events (blocking)
+    pub fanotify_permission: bool,
+    // CAP_NET_ADMIN, needed to change nftables
+    pub net_admin: bool,
+    pub nft_binary: Option<PathBuf>,
+    // Built with packet capture and allowed to open raw sockets
+    pub pcap: bool,
+    pub systemd: bool,
+    // containerd, Docker or a kubelet is installed
+    pub container_runtime: bool,
+    // Network interfaces other than loopback
+    pub interfaces: Vec<String>,
+}
+
+impl HostCapabilities {
+    pub fn detect() -> Self {
+        let report = CapabilityReport::probe();
+        Self {
+            root: unsafe { libc::geteuid() } == 0,
+            fanotify_permission: report.has(preflight::FANOTIFY_PERMISSION),
+            net_admin: report.has(preflight::NET_ADMIN),
+            nft_binary: preflight::find_in_path("nft"),
+            pcap: cfg!(feature = "pcap") && report.has(preflight::PCAP),
+            systemd: Path::new("/run/systemd/system").is_dir(),
+            container_runtime: ["/run/containerd/containerd.sock", "/var/run/docker.sock", "/var/lib/kubelet"]
+                .iter()
+                .any(|path| Path::new(path).exists()),
+            interfaces: list_interfaces(Path::new("/sys/class/net")),
+        }
+    }
+
+    // Container runtimes make a container host; everything else is treated
+    // as a server until told otherwise
+    pub fn suggested_profile(&self) -> Profile {
+        if self.container_runtime {
+            Profile::ContainerHost
+        } else {
+            Profile::Server
+        }
+    }
+
+    // Blocking needs fanotify permission events; without them a host can
+    // only watch
+    pub fn suggested_enforcement(&self) -> Enforcement {
+        if self.fanotify_permission {
+            Enforcement::Enforcing
+        } else {
+            Enforcement::Passive
+        }
+    }
+}
+
+fn list_interfaces(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut interfaces: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name != "lo")
+        .collect();
+    interfaces.sort();
+    interfaces
+}
+
+// The answers given to the setup wizard
+#[derive(Debug, Clone)]
+pub struct SetupAnswers {
+    pub profile: Profile,
+    pub enforcement: Enforcement,
+    // Capture interfaces; empty leaves traffic capture off
+    pub interfaces: Vec<String>,
+    pub log_file_path: Option<PathBuf>,
+    pub config_path: PathBuf,
+}
+
+impl SetupAnswers {
+    // The profile's config with the answers applied; the policies are kept
+    // next to the config file
+    pub fn config(&self) -> Config {
+        let dir = self.config_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Config {
+            file_policy_path: Some(dir.join("file_policy.json")),
+            network_policy_path: Some(dir.join("network_policy.json")),
+            enforcement: Some(self.enforcement),
+            capture_interfaces: self.interfaces.clone(),
+            log_file_path: self.log_file_path.clone(),
+            ..self.profile.config()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_answers() {
+        let answers = SetupAnswers {
+            profile: Profile::Workstation,
+            enforcement: Enforcement::Passive,
+            interfaces: vec!["eth0".to_string()],
+            log_file_path: Some(PathBuf::from("/var/log/flux/events.log")),
+            config_path: PathBuf::from("/etc/fluxdefense/config.toml"),
+        };
+        let config = answers.config();
+        assert_eq!(config.profile, Some(Profile::Workstation));
+        assert_eq!(config.enforcement, Some(Enforcement::Passive));
+        assert_eq!(config.network_policy_path, Some(PathBuf::from("/etc/fluxdefense/network_policy.json")));
+        assert_eq!(config.capture_interfaces, vec!["eth0"]);
+        // Profile settings the answers do not touch are kept
+        assert!(config.behavior_baseline.is_none());
+
+        let dir = std::env::temp_dir().join(format!("flux-setup-{}", uuid::Uuid::new_v4()));
+        for name in ["lo", "eth0", "docker0"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        assert_eq!(list_interfaces(&dir), vec!["docker0", "eth0"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+
+use crate::rule_stats::RuleEngine;
+
+// Kept per rule, newest last
+const RECENT_HITS: usize = 20;
+
+// Netfilter rules in shadow mode are installed as LOG rules with this
+// prefix followed by "<verdict>:<rule id>"; the kernel log lines count
+// their hits
+pub const NETFILTER_SHADOW_PREFIX: &str = "flux-shadow:";
+// The kernel truncates log prefixes longer than this
+const NETFILTER_PREFIX_MAX: usize = 127;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowHit {
+    pub timestamp: DateTime<Utc>,
+    pub detail: String,
+}
+
+// What a rule in shadow mode would have done, had it been enforced
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowRuleStats {
+    pub engine: RuleEngine,
+    pub rule_id: String,
+    pub rule_name: String,
+    // The action it would have taken: block, drop, alert, ...
+    pub action: String,
+    pub hits: u64,
+    pub first_hit: DateTime<Utc>,
+    pub last_hit: DateTime<Utc>,
+    pub recent: VecDeque<ShadowHit>,
+}
+
+// Matches of rules marked `shadow`, which are counted here instead of being
+// enforced so new rules can be trialed. Clones share the same counters
+#[derive(Clone, Default)]
+pub struct ShadowLog {
+    rules: Arc<Mutex<HashMap<(RuleEngine, String), ShadowRuleStats>>>,
+}
+
+impl ShadowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, engine: RuleEngine, rule_id: &str, rule_name: &str, action: &str, detail: String) {
+        let Ok(mut rules) = self.rules.lock() else { return };
+        let now = Utc::now();
+        let stats = rules.entry((engine, rule_id.to_string())).or_insert_with(|| {
+            info!("Shadow rule {} ({:?}) matched for the first time: would {}", rule_id, engine, action);
+            ShadowRuleStats {
+                engine,
+                rule_id: rule_id.to_string(),
+                rule_name: rule_name.to_string(),
+                action: action.to_string(),
+                hits: 0,
+                first_hit: now,
+                last_hit: now,
+                recent: VecDeque::new(),
+            }
+        });
+        stats.hits += 1;
+        stats.last_hit = now;
+        if stats.recent.len() == RECENT_HITS {
+            stats.recent.pop_front();
+        }
+        stats.recent.push_back(ShadowHit { timestamp: now, detail });
+    }
+
+    // Most hits first
+    pub fn stats(&self) -> Vec<ShadowRuleStats> {
+        let Ok(rules) = self.rules.lock() else { return Vec::new() };
+        let mut stats: Vec<ShadowRuleStats> = rules.values().cloned().collect();
+        stats.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.rule_id.cmp(&b.rule_id)));
+        stats
+    }
+
+    // Forgets a rule's hits, e.g. once it is enabled for real
+    pub fn clear(&self, engine: RuleEngine, rule_id: &str) -> bool {
+        self.rules.lock().is_ok_and(|mut rules| rules.remove(&(engine, rule_id.to_string())).is_some())
+    }
+}
+
+pub fn netfilter_log_prefix(verdict: &str, rule_id: &str) -> String {
+    let mut prefix = format!("{}{}:{} ", NETFILTER_SHADOW_PREFIX, verdict, rule_id.replace(char::is_whitespace, "_"));
+    if prefix.len() > NETFILTER_PREFIX_MAX {
+        let mut end = NETFILTER_PREFIX_MAX - 1;
+        while !prefix.is_char_boundary(end) {
+            end -= 1;
+        }
+        prefix.truncate(end);
+        prefix.push(' ');
+    }
+    prefix
+}
+
+// The verdict and rule id in a kernel log line written by a shadow
+// netfilter rule
+pub fn parse_netfilter_log(message: &str) -> Option<(&str, &str)> {
+    message.split_whitespace()
+        .find_map(|word| word.strip_prefix(NETFILTER_SHADOW_PREFIX)?.split_once(':'))
+        .filter(|(_, id)| !id.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shadow_log() {
+        let log = ShadowLog::new();
+        let shared = log.clone();
+        for port in 0..25 {
+            shared.record(RuleEngine::NetworkFilter, "block-telnet", "Block telnet", "block", format!("10.0.0.1:{}", port));
+        }
+        log.record(RuleEngine::Correlation, "block-telnet", "Telnet burst", "alert", String::new());
+
+        let stats = log.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].engine, stats[0].hits), (RuleEngine::NetworkFilter, 25));
+        assert_eq!(stats[0].recent.len(), RECENT_HITS);
+        assert_eq!(stats[0].recent.back().unwrap().detail, "10.0.0.1:24");
+        assert!(log.clear(RuleEngine::Correlation, "block-telnet"));
+        assert_eq!(log.stats().len(), 1);
+
+        let prefix = netfilter_log_prefix("drop", "trial ssh");
+        assert_eq!(prefix, "flux-shadow:drop:trial_ssh ");
+        let line = format!("{}IN=eth0 OUT= SRC=10.0.0.5 DST=10.0.0.1 PROTO=TCP DPT=22", prefix);
+        assert_eq!(parse_netfilter_log(&line), Some(("drop", "trial_ssh")));
+        assert!(netfilter_log_prefix("drop", &"x".repeat(300)).len() <= NETFILTER_PREFIX_MAX);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Lookups that miss rescan /proc at most this often; a miss that survives
+// the incremental pass falls back to re-reading every descriptor, less often
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const FULL_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SocketProtocol {
+    Tcp,
+    Udp,
+}
+
+impl SocketProtocol {
+    fn tables(self) -> [&'static str; 2] {
+        match self {
+            SocketProtocol::Tcp => ["/proc/net/tcp", "/proc/net/tcp6"],
+            SocketProtocol::Udp => ["/proc/net/udp", "/proc/net/udp6"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+struct ProcessFds {
+    name: String,
+    // Descriptor number -> socket inode, None for anything that is not a socket
+    fds: HashMap<String, Option<u64>>,
+}
+
+#[derive(Default)]
+struct IndexState {
+    processes: HashMap<u32, ProcessFds>,
+    // Socket inode -> (pid, descriptor number)
+    owners: HashMap<u64, (u32, String)>,
+    // Local address of every TCP/UDP socket -> inode
+    sockets: HashMap<(SocketProtocol, SocketAddr), u64>,
+    sockets_read_at: Option<Instant>,
+    refreshed_at: Option<Instant>,
+    fully_refreshed_at: Option<Instant>,
+}
+
+// Socket inode -> owning process, built from /proc/*/fd. Refreshes only
+// read the links of descriptors not seen before, and cached owners are
+// re-checked on every hit, so lookups stay cheap enough for per-packet use.
+#[derive(Clone, Default)]
+pub struct SocketIndex {
+    state: Arc<Mutex<IndexState>>,
+}
+
+impl SocketIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Rescans now regardless of when the last refresh ran
+    pub fn refresh(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.refresh_processes(false);
+        state.refresh_sockets();
+    }
+
+    pub fn owner(&self, inode: u64) -> Option<SocketOwner> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(owner) = state.verified_owner(inode) {
+            return Some(owner);
+        }
+        let now = Instant::now();
+        if is_due(state.refreshed_at, REFRESH_INTERVAL, now) {
+            state.refresh_processes(false);
+            if let Some(owner) = state.verified_owner(inode) {
+                return Some(owner);
+            }
+        }
+        // Descriptor numbers are reused, so a socket can hide behind one
+        // that was cached as something else
+        if is_due(state.fully_refreshed_at, FULL_REFRESH_INTERVAL, now) {
+            state.refresh_processes(true);
+        }
+        state.verified_owner(inode)
+    }
+
+    // The process holding the socket bound to `local`, including sockets
+    // bound to the wildcard address and dual-stack sockets carrying IPv4
+    pub fn owner_of(&self, protocol: SocketProtocol, local: SocketAddr) -> Option<SocketOwner> {
+        let cached = self.state.lock().unwrap().socket_inode(protocol, local);
+        if let Some(owner) = cached.and_then(|inode| self.owner(inode)) {
+            return Some(owner);
+        }
+        // New socket, or the port changed hands since the tables were read
+        let inode = {
+            let mut state = self.state.lock().unwrap();
+            if !is_due(state.sockets_read_at, REFRESH_INTERVAL, Instant::now()) {
+                return None;
+            }
+            state.refresh_sockets();
+            state.socket_inode(protocol, local)?
+        };
+        self.owner(inode)
+    }
+}
+
+impl IndexState {
+    fn refresh_processes(&mut self, full: bool) {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return;
+        };
+        let mut alive = HashSet::new();
+        for entry in entries.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+                continue;
+            };
+            alive.insert(pid);
+            // Other users' processes are unreadable without privileges
+            let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+                continue;
+            };
+            let process = self.processes.entry(pid).or_insert_with(|| ProcessFds {
+                name: std::fs::read_to_string(entry.path().join("comm"))
+                    .map(|comm| comm.trim().to_string())
+                    .unwrap_or_else(|_| format!("pid{}", pid)),
+                fds: HashMap::new(),
+            });
+            let mut current = HashMap::with_capacity(process.fds.len());
+            for fd in fds.flatten() {
+                let number = fd.file_name().to_string_lossy().into_owned();
+                let inode = match process.fds.get(&number) {
+                    Some(inode) if !full => *inode,
+                    _ => std::fs::read_link(fd.path()).ok().and_then(|link| socket_inode(&link.to_string_lossy())),
+                };
+                current.insert(number, inode);
+            }
+            process.fds = current;
+        }
+        self.processes.retain(|pid, _| alive.contains(pid));
+
+        self.owners = self.processes.iter()
+            .flat_map(|(pid, process)| {
+                process.fds.iter().filter_map(move |(number, inode)| inode.map(|inode| (inode, (*pid, number.clone()))))
+            })
+            .collect();
+        let now = Instant::now();
+        self.refreshed_at = Some(now);
+        if full {
+            self.fully_refreshed_at = Some(now);
+        }
+    }
+
+    fn refresh_sockets(&mut self) {
+        self.sockets.clear();
+        for protocol in [SocketProtocol::Tcp, SocketProtocol::Udp] {
+            for table in protocol.tables() {
+                let Ok(content) = std::fs::read_to_string(table) else {
+                    continue;
+                };
+                for (local, inode) in content.lines().skip(1).filter_map(parse_socket_line) {
+                    // Sockets in TIME_WAIT have no inode and no owner
+                    if inode != 0 {
+                        self.sockets.insert((protocol, local), inode);
+                    }
+                }
+            }
+        }
+        self.sockets_read_at = Some(Instant::now());
+    }
+
+    fn socket_inode(&self, protocol: SocketProtocol, local: SocketAddr) -> Option<u64> {
+        let port = local.port();
+        let mut candidates = vec![local];
+        if let IpAddr::V4(ip) = local.ip() {
+            candidates.push(SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), port));
+            candidates.push(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port));
+        }
+        candidates.push(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port));
+        candidates.iter().find_map(|address| self.sockets.get(&(protocol, *address)).copied())
+    }
+
+    fn verified_owner(&mut self, inode: u64) -> Option<SocketOwner> {
+        let (pid, number) = self.owners.get(&inode)?.clone();
+        let link = std::fs::read_link(format!("/proc/{}/fd/{}", pid, number)).ok();
+        if link.as_ref().and_then(|link| socket_inode(&link.to_string_lossy())) == Some(inode) {
+            let name = self.processes.get(&pid)?.name.clone();
+            return Some(SocketOwner { pid, name });
+        }
+        // Closed or reused; the next refresh reads this descriptor again
+        self.owners.remove(&inode);
+        if let Some(process) = self.processes.get_mut(&pid) {
+            process.fds.remove(&number);
+        }
+        None
+    }
+}
+
+fn is_due(last: Option<Instant>, interval: Duration, now: Instant) -> bool {
+    last.is_none_or(|last| now.duration_since(last) >= interval)
+}
+
+fn socket_inode(link: &str) -> Option<u64> {
+    link.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+}
+
+// Local address and inode of a /proc/net/{tcp,udp}[6] row. Addresses are
+// printed as native-endian 32-bit words.
+pub fn parse_socket_line(line: &str) -> Option<(SocketAddr, u64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    let (address, port) = fields[1].split_once(':')?;
+    let word = |hex: &str| u32::from_str_radix(hex, 16).ok().map(u32::to_ne_bytes);
+    let ip = match address.len() {
+        8 => IpAddr::V4(Ipv4Addr::from(word(address)?)),
+        32 => {
+            let mut octets = [0u8; 16];
+            for (i, chunk) in octets.chunks_mut(4).enumerate() {
+                chunk.copy_from_slice(&word(&address[i * 8..i * 8 + 8])?);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    let port = u16::from_str_radix(port, 16).ok()?;
+    Some((SocketAddr::new(ip, port), fields[9].parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_index() {
+        assert_eq!(
+            parse_socket_line("  12: 0100007F:0035 00000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 48211 2 0000000000000000 0"),
+            Some(("127.0.0.1:53".parse().unwrap(), 48211))
+        );
+        assert_eq!(socket_inode("socket:[48211]"), Some(48211));
+        assert_eq!(socket_inode("pipe:[48211]"), None);
+
+        let index = SocketIndex::new();
+        let udp = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let me = Some(std::process::id());
+
+        // Wildcard-bound sockets answer for any local address
+        let port = udp.local_addr().unwrap().port();
+        let owner = index.owner_of(SocketProtocol::Udp, SocketAddr::new("127.0.0.1".parse().unwrap(), port));
+        assert_eq!(owner.map(|owner| owner.pid), me);
+        let owner = index.owner_of(SocketProtocol::Tcp, tcp.local_addr().unwrap());
+        assert_eq!(owner.map(|owner| owner.pid), me);
+
+        // A closed socket is dropped on the next lookup
+        let inode = {
+            let state = index.state.lock().unwrap();
+            state.socket_inode(SocketProtocol::Tcp, tcp.local_addr().unwrap()).unwrap()
+        };
+        drop(tcp);
+        assert!(index.state.lock().unwrap().verified_owner(inode).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use crate::incidents::{Incident, IncidentSeverity, IncidentStatus};
+use crate::monitor::{SecurityEvent, SecurityEventType};
+
+pub const STIX_MEDIA_TYPE: &str = "application/stix+json;version=2.1";
+
+// Namespace the STIX 2.1 specification fixes for deterministic
+// cyber-observable ids
+const SCO_NAMESPACE: Uuid = Uuid::from_u128(0x00abedb4_aa42_466c_9c01_fed23315a9b7);
+
+// Objects that do not change between exports carry this as created and
+// modified, so their content stays identical too
+const FIXED_TIMESTAMP: &str = "2024-01-01T00:00:00.000Z";
+
+// ATT&CK techniques behind the built-in correlation rules
+const ATTACK_TECHNIQUES: [(&str, &str, &str); 6] = [
+    ("recon_exploit", "T1190", "Exploit Public-Facing Application"),
+    ("ransomware_pattern", "T1486", "Data Encrypted for Impact"),
+    ("port_scan", "T1046", "Network Service Discovery"),
+    ("process_injection", "T1055", "Process Injection"),
+    ("brute_force", "T1110", "Brute Force"),
+    ("auth_failures", "T1110", "Brute Force"),
+];
+
+// One observable involved in an incident
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Observable {
+    Sha256 { hash: String, name: Option<String> },
+    Domain(String),
+    Ip(IpAddr),
+}
+
+impl Observable {
+    fn object(&self) -> Value {
+        let mut object = match self {
+            Observable::Sha256 { hash, name } => {
+                let mut object = json!({ "type": "file", "hashes": { "SHA-256": hash } });
+                if let Some(name) = name {
+                    object["name"] = json!(name);
+                }
+                object
+            }
+            Observable::Domain(domain) => json!({ "type": "domain-name", "value": domain }),
+            Observable::Ip(ip) => json!({ "type": if ip.is_ipv4() { "ipv4-addr" } else { "ipv6-addr" }, "value": ip.to_string() }),
+        };
+        object["spec_version"] = json!("2.1");
+        object["id"] = json!(self.id());
+        object
+    }
+
+    // Derived from the properties the spec lists as id contributing, so any
+    // producer exporting the same observable arrives at the same id
+    fn id(&self) -> String {
+        match self {
+            Observable::Sha256 { hash, .. } => stix_id("file", &json!({ "hashes": { "SHA-256": hash } }).to_string()),
+            Observable::Domain(domain) => stix_id("domain-name", &json!({ "value": domain }).to_string()),
+            Observable::Ip(ip) => {
+                let kind = if ip.is_ipv4() { "ipv4-addr" } else { "ipv6-addr" };
+                stix_id(kind, &json!({ "value": ip.to_string() }).to_string())
+            }
+        }
+    }
+
+    fn pattern(&self) -> String {
+        match self {
+            Observable::Sha256 { hash, .. } => format!("[file:hashes.'SHA-256' = '{}']", escape_pattern(hash)),
+            Observable::Domain(domain) => format!("[domain-name:value = '{}']", escape_pattern(domain)),
+            Observable::Ip(IpAddr::V4(ip)) => format!("[ipv4-addr:value = '{}']", ip),
+            Observable::Ip(IpAddr::V6(ip)) => format!("[ipv6-addr:value = '{}']", ip),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Observable::Sha256 { hash, name: Some(name) } => format!("File {} ({})", name, hash),
+            Observable::Sha256 { hash, name: None } => format!("File {}", hash),
+            Observable::Domain(domain) => format!("Domain {}", domain),
+            Observable::Ip(ip) => format!("Address {}", ip),
+        }
+    }
+
+    // Internal addresses mean nothing to other organizations
+    pub(crate) fn shareable(&self) -> bool {
+        match self {
+            Observable::Ip(IpAddr::V4(ip)) => !(ip.is_private() || ip.is_loopback() || ip.is_link_local()
+                || ip.is_unspecified() || ip.is_broadcast()),
+            Observable::Ip(IpAddr::V6(ip)) => !(ip.is_loopback() || ip.is_unspecified()
+                || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80),
+            _ => true,
+        }
+    }
+}
+
+// type--UUIDv5 of `name` in the SCO namespace
+fn stix_id(kind: &str, name: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(SCO_NAMESPACE.as_bytes());
+    hasher.update(name.as_bytes());
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    format!("{}--{}", kind, uuid::Builder::from_sha1_bytes(bytes).into_uuid())
+}
+
+fn escape_pattern(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+pub(crate) fn observables(incident: &Incident, events: &[SecurityEvent]) -> BTreeSet<Observable> {
+    let mut observables = BTreeSet::new();
+    let mut add_hash = |hash: &Option<String>, path: &std::path::Path| {
+        if let Some(hash) = hash.as_ref().filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())) {
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+            observables.insert(Observable::Sha256 { hash: hash.to_lowercase(), name });
+        }
+    };
+    for event in events {
+        add_hash(&event.process_info.executable_hash, &event.process_info.path);
+        if let SecurityEventType::FileExecution { target_path, file_hash, .. } = &event.event_type {
+            add_hash(file_hash, target_path);
+        }
+    }
+    for event in events {
+        if let SecurityEventType::NetworkConnection { domain: Some(domain), .. } = &event.event_type {
+            observables.insert(Observable::Domain(domain.trim_end_matches('.').to_lowercase()));
+        }
+    }
+    observables.extend(incident.entities.remote_ips.iter().filter_map(|ip| ip.parse().ok()).map(Observable::Ip));
+    observables
+}
+
+// Builds a STIX 2.1 bundle from incidents and their events: a report per
+// incident grouping observed-data for everything involved, an indicator per
+// shareable observable, and attack-patterns for the detections. Ids are
+// derived from content, so exporting again yields the same objects.
+// Incidents closed as false positives are left out.
+pub fn export_bundle(incidents: &[(Incident, Vec<SecurityEvent>)]) -> Value {
+    let identity_id = stix_id("identity", "fluxdefense");
+    let mut objects: BTreeMap<String, Value> = BTreeMap::new();
+    objects.insert(identity_id.clone(), json!({
+        "type": "identity",
+        "spec_version": "2.1",
+        "id": identity_id,
+        "created": FIXED_TIMESTAMP,
+        "modified": FIXED_TIMESTAMP,
+        "name": "FluxDefense",
+        "identity_class": "system",
+    }));
+
+    for (incident, events) in incidents {
+        if incident.status == IncidentStatus::FalsePositive {
+            continue;
+        }
+        let created = timestamp(incident.created_at);
+        let modified = timestamp(incident.updated_at);
+        let observables = observables(incident, events);
+        let mut refs: Vec<String> = Vec::new();
+
+        let patterns: Vec<String> = incident.correlation_rules.iter()
+            .filter_map(|rule| ATTACK_TECHNIQUES.iter().find(|(id, _, _)| id == rule))
+            .map(|(_, technique, name)| {
+                let id = stix_id("attack-pattern", technique);
+                objects.insert(id.clone(), json!({
+                    "type": "attack-pattern",
+                    "spec_version": "2.1",
+                    "id": id,
+                    "created": FIXED_TIMESTAMP,
+                    "modified": FIXED_TIMESTAMP,
+                    "created_by_ref": identity_id,
+                    "name": name,
+                    "external_references": [{
+                        "source_name": "mitre-attack",
+                        "external_id": technique,
+                        "url": format!("https://attack.mitre.org/techniques/{}/", technique),
+                    }],
+                }));
+                id
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        refs.extend(patterns.iter().cloned());
+
+        if !observables.is_empty() {
+            let observed_id = stix_id("observed-data", &incident.id);
+            objects.insert(observed_id.clone(), json!({
+                "type": "observed-data",
+                "spec_version": "2.1",
+                "id": observed_id,
+                "created": created,
+                "modified": modified,
+                "created_by_ref": identity_id,
+                "first_observed": timestamp(incident.first_seen),
+                "last_observed": timestamp(incident.last_seen),
+                "number_observed": incident.event_count.clamp(1, 999_999_999),
+                "object_refs": observables.iter().map(Observable::id).collect::<Vec<_>>(),
+            }));
+            refs.push(observed_id);
+        }
+
+        let indicator_type = if incident.severity >= IncidentSeverity::High { "malicious-activity" } else { "anomalous-activity" };
+        for observable in &observables {
+            let object = observable.object();
+            refs.push(observable.id());
+            objects.insert(observable.id(), object);
+            if !observable.shareable() {
+                continue;
+            }
+            let indicator_id = stix_id("indicator", &format!("{}|{}", incident.id, observable.pattern()));
+            objects.insert(indicator_id.clone(), json!({
+                "type": "indicator",
+                "spec_version": "2.1",
+                "id": indicator_id,
+                "created": created,
+                "modified": modified,
+                "created_by_ref": identity_id,
+                "name": observable.label(),
+                "description": incident.title,
+                "indicator_types": [indicator_type],
+                "pattern": observable.pattern(),
+                "pattern_type": "stix",
+                "valid_from": timestamp(incident.first_seen),
+            }));
+            refs.push(indicator_id.clone());
+            for pattern in &patterns {
+                let relationship_id = stix_id("relationship", &format!("{}|{}", indicator_id, pattern));
+                objects.insert(relationship_id.clone(), json!({
+                    "type": "relationship",
+                    "spec_version": "2.1",
+                    "id": relationship_id,
+                    "created": created,
+                    "modified": modified,
+                    "created_by_ref": identity_id,
+                    "relationship_type": "indicates",
+                    "source_ref": indicator_id,
+                    "target_ref": pattern,
+                }));
+                refs.push(relationship_id);
+            }
+        }
+
+        // A report needs at least one reference
+        if refs.is_empty() {
+            refs.push(identity_id.clone());
+        }
+        let report_id = stix_id("report", &incident.id);
+        objects.insert(report_id.clone(), json!({
+            "type": "report",
+            "spec_version": "2.1",
+            "id": report_id,
+            "created": created,
+            "modified": modified,
+            "created_by_ref": identity_id,
+            "name": incident.title,
+            "description": format!("FluxDefense incident {} ({:?} severity, {} events)", incident.id, incident.severity, incident.event_count),
+            "report_types": ["attack-pattern", "indicator", "observed-data"],
+            "published": modified,
+            "object_refs": refs,
+        }));
+    }
+
+    json!({
+        "type": "bundle",
+        "id": format!("bundle--{}", Uuid::new_v4()),
+        "objects": objects.into_values().collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::incidents::{CorrelationSignal, IncidentManager};
+    use crate::monitor::{NetworkProtocol, ProcessInfo, Verdict};
+
+    #[test]
+    fn test_stix_bundle() {
+        // The example from the STIX 2.1 specification
+        assert_eq!(Observable::Domain("example.com".to_string()).id(), "domain-name--bedb4899-d24b-5401-bc86-8f6b4cc18ec7");
+        assert_eq!(escape_pattern(r"a'b\c"), r"a\'b\\c");
+
+        let manager = IncidentManager::default();
+        let event = |remote_ip: &str| SecurityEvent {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type: SecurityEventType::NetworkConnection {
+                remote_ip: remote_ip.to_string(),
+                remote_port: 443,
+                domain: Some("Evil.Example.".to_string()),
+                protocol: NetworkProtocol::Tcp,
+            },
+            process_info: ProcessInfo {
+                pid: 50,
+                path: PathBuf::from("/tmp/dropper"),
+                parent_pid: Some(1),
+                user_id: 1000,
+                executable_hash: Some("AB".repeat(32)),
+                command_line: None,
+            },
+            verdict: Verdict::Deny,
+            policy_reason: String::new(),
+        };
+        let id = manager.record_correlation(None, CorrelationSignal {
+            rule_id: "port_scan".to_string(),
+            rule_name: "Port Scanning Activity".to_string(),
+            description: "scan".to_string(),
+            severity: IncidentSeverity::High,
+            detected_at: Utc::now(),
+            events: vec![event("198.51.100.7"), event("10.0.0.9")],
+        }).unwrap();
+
+        let incident = manager.get(&id).unwrap();
+        let bundle = export_bundle(&[(incident.clone(), manager.events(&id))]);
+        let objects = bundle["objects"].as_array().unwrap();
+        let count = |kind: &str| objects.iter().filter(|object| object["type"] == kind).count();
+        assert_eq!(count("attack-pattern"), 1);
+        assert_eq!((count("file"), count("domain-name"), count("ipv4-addr")), (1, 1, 2));
+        // The private address is observed but not shared as an indicator
+        assert_eq!(count("indicator"), 3);
+        assert_eq!(count("relationship"), 3);
+        assert_eq!((count("observed-data"), count("report"), count("identity")), (1, 1, 1));
+        let patterns: Vec<&str> = objects.iter().filter_map(|object| object["pattern"].as_str()).collect();
+        assert!(patterns.contains(&"[domain-name:value = 'evil.example']"));
+        assert!(patterns.contains(&format!("[file:hashes.'SHA-256' = '{}']", "ab".repeat(32)).as_str()));
+
+        // Same ids on re-export; false positives are not shared
+        let again = export_bundle(&[(incident.clone(), manager.events(&id))]);
+        assert_eq!(again["objects"], bundle["objects"]);
+        let mut dismissed = incident;
+        dismissed.status = IncidentStatus::FalsePositive;
+        assert_eq!(export_bundle(&[(dismissed, Vec::new())])["objects"].as_array().unwrap().len(), 1);
+    }
+}
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::io::BufReader;
-use std::process::Command;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_os = "macos")]
+use sysinfo::Networks;
+use sysinfo::System;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMetrics {
@@ -12,6 +15,8 @@ pub struct SystemMetrics {
     pub memory_usage: f64,
     pub memory_total: u64,
     pub memory_used: u64,
+    // Read from the block devices on Linux; on macOS the per-process I/O of
+    // the processes seen since the collector started
     pub disk_read_bytes: u64,
     pub disk_write_bytes: u64,
     pub disk_read_rate: f64,
@@ -23,8 +28,57 @@ pub struct SystemMetrics {
     pub load_average: [f64; 3],
     pub process_count: u32,
     pub uptime_seconds: u64,
+    #[serde(default)]
+    pub disks: Vec<DiskMetrics>,
+    #[serde(default)]
+    pub temperatures: Vec<TemperatureReading>,
+    #[serde(default)]
+    pub gpus: Vec<GpuMetrics>,
 }
 
+// I/O of one physical block device; utilization is the share of wall time
+// the device had requests in flight since the previous collection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiskMetrics {
+    pub name: String,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_rate: f64,
+    pub write_rate: f64,
+    pub utilization: f64,
+}
+
+// A CPU sensor from hwmon, e.g. coretemp's "Package id 0" or k10temp's "Tctl"
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TemperatureReading {
+    pub sensor: String,
+    pub label: String,
+    pub celsius: f64,
+    pub critical: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GpuMetrics {
+    pub index: u32,
+    pub vendor: GpuVendor,
+    pub name: String,
+    pub utilization: f64,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub temperature: Option<f64>,
+    pub power_watts: Option<f64>,
+}
+
+// hwmon drivers that report CPU temperatures
+const CPU_SENSORS: [&str; 5] = ["coretemp", "k10temp", "zenpower", "cpu_thermal", "soc_thermal"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessMetrics {
     pub pid: u32,
@@ -39,6 +93,24 @@ pub struct ProcessMetrics {
 pub struct SystemMetricsCollector {
     previous_metrics: Option<SystemMetrics>,
     previous_timestamp: Option<u64>,
+    // Sectors read, sectors written and milliseconds busy per disk
+    previous_disks: HashMap<String, (u64, u64, u64)>,
+    previous_disks_at: Option<Instant>,
+    // Bytes read and written per process at the last sample; macOS disk I/O
+    // is summed from these
+    #[cfg(target_os = "macos")]
+    process_disk_io: HashMap<u32, (u64, u64)>,
+    // NVIDIA's management library, loaded on first use; None without the driver
+    #[cfg(target_os = "linux")]
+    nvml: std::sync::OnceLock<Option<nvml::Nvml>>,
+    // Process table, and on macOS the CPU and memory counters
+    system: Mutex<System>,
+}
+
+impl Default for SystemMetricsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SystemMetricsCollector {
@@ -46,6 +118,13 @@ impl SystemMetricsCollector {
         Self {
             previous_metrics: None,
             previous_timestamp: None,
+            previous_disks: HashMap::new(),
+            previous_disks_at: None,
+            #[cfg(target_os = "macos")]
+            process_disk_io: HashMap::new(),
+            #[cfg(target_os = "linux")]
+            nvml: std::sync::OnceLock::new(),
+            system: Mutex::new(System::new()),
         }
     }
 
@@ -63,6 +142,9 @@ impl SystemMetricsCollector {
         let load_average = self.get_load_average()?;
         let process_count = self.get_process_count()?;
         let uptime_seconds = self.get_uptime()?;
+        let disks = self.collect_disks();
+        let temperatures = self.collect_temperatures();
+        let gpus = self.collect_gpus();
 
         let metrics = SystemMetrics {
             timestamp,
@@ -81,6 +163,9 @@ impl SystemMetricsCollector {
             load_average,
             process_count,
             uptime_seconds,
+            disks,
+            temperatures,
+            gpus,
         };
 
         // Store for next calculation
@@ -93,40 +178,11 @@ impl SystemMetricsCollector {
     fn get_cpu_usage(&self) -> anyhow::Result<f64> {
         #[cfg(target_os = "macos")]
         {
-            use std::mem;
-            use std::ptr;
-
-            // Get CPU info using host_processor_info
-            let mut processor_count: u32 = 0;
-            let mut cpu_info: *mut i32 = ptr::null_mut();
-            let mut cpu_info_count: u32 = 0;
-
-            // This is a simplified approach - in a real implementation,
-            // you'd use mach system calls to get actual CPU usage
-            let output = Command::new("top")
-                .args(&["-l", "1", "-n", "0", "-s", "0"])
-                .output()?;
-
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            
-            // Parse CPU usage from top output
-            for line in output_str.lines() {
-                if line.contains("CPU usage:") {
-                    // Example: "CPU usage: 5.12% user, 2.34% sys, 92.54% idle"
-                    if let Some(idle_start) = line.find("% idle") {
-                        if let Some(idle_percent_start) = line[..idle_start].rfind(' ') {
-                            if let Ok(idle_percent) = line[idle_percent_start + 1..idle_start].parse::<f64>() {
-                                return Ok(100.0 - idle_percent);
-                            }
-                        }
-                    }
-                }
-            }
-            
-            // Fallback to load average approximation
-            let load = self.get_load_average()?;
-            let cpu_count = num_cpus::get() as f64;
-            Ok((load[0] / cpu_count * 100.0).min(100.0))
+            // sysinfo keeps the host_statistics ticks between refreshes, so
+            // the first collection reads 0
+            let mut system = self.system()?;
+            system.refresh_cpu();
+            Ok(f64::from(system.global_cpu_info().cpu_usage()))
         }
 
         #[cfg(target_os = "linux")]
@@ -171,43 +227,10 @@ impl SystemMetricsCollector {
     fn get_memory_usage(&self) -> anyhow::Result<(f64, u64, u64)> {
         #[cfg(target_os = "macos")]
         {
-            let output = Command::new("vm_stat").output()?;
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            
-            let mut page_size = 4096u64; // Default page size
-            let mut pages_free = 0u64;
-            let mut pages_active = 0u64;
-            let mut pages_inactive = 0u64;
-            let mut pages_speculative = 0u64;
-            let mut pages_wired = 0u64;
-            let mut pages_compressed = 0u64;
-
-            for line in output_str.lines() {
-                if line.starts_with("page size of ") {
-                    if let Some(size_str) = line.split_whitespace().nth(3) {
-                        page_size = size_str.parse().unwrap_or(4096);
-                    }
-                } else if line.contains("Pages free:") {
-                    pages_free = Self::extract_pages(line);
-                } else if line.contains("Pages active:") {
-                    pages_active = Self::extract_pages(line);
-                } else if line.contains("Pages inactive:") {
-                    pages_inactive = Self::extract_pages(line);
-                } else if line.contains("Pages speculative:") {
-                    pages_speculative = Self::extract_pages(line);
-                } else if line.contains("Pages wired down:") {
-                    pages_wired = Self::extract_pages(line);
-                } else if line.contains("Pages stored in compressor:") {
-                    pages_compressed = Self::extract_pages(line);
-                }
-            }
-
-            let total_pages = pages_free + pages_active + pages_inactive + 
-                            pages_speculative + pages_wired + pages_compressed;
-            let used_pages = total_pages - pages_free - pages_speculative;
-            
-            let total_memory = total_pages * page_size;
-            let used_memory = used_pages * page_size;
+            let mut system = self.system()?;
+            system.refresh_memory();
+            let total_memory = system.total_memory();
+            let used_memory = system.used_memory();
             let usage_percent = if total_memory > 0 {
                 (used_memory as f64 / total_memory as f64) * 100.0
             } else {
@@ -259,65 +282,60 @@ impl SystemMetricsCollector {
         }
     }
 
-    fn extract_pages(line: &str) -> u64 {
-        line.split_whitespace()
-            .nth(2)
-            .and_then(|s| s.trim_end_matches('.').parse().ok())
-            .unwrap_or(0)
-    }
-
     fn get_disk_io_metrics(&mut self) -> anyhow::Result<(u64, u64, f64, f64)> {
         #[cfg(target_os = "macos")]
         {
-            let output = Command::new("iostat")
-                .args(&["-d", "-c", "1"])
-                .output()?;
-            
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let mut total_read_bytes = 0u64;
-            let mut total_write_bytes = 0u64;
-
-            // Parse iostat output to get disk I/O statistics
-            let lines: Vec<&str> = output_str.lines().collect();
-            for (i, line) in lines.iter().enumerate() {
-                if line.contains("disk") && i + 1 < lines.len() {
-                    let data_line = lines[i + 1];
-                    let parts: Vec<&str> = data_line.split_whitespace().collect();
-                    if parts.len() >= 3 {
-                        // iostat typically shows KB read/write per second
-                        if let (Ok(read_kb), Ok(write_kb)) = (
-                            parts[1].parse::<f64>(),
-                            parts[2].parse::<f64>()
-                        ) {
-                            total_read_bytes = (read_kb * 1024.0) as u64;
-                            total_write_bytes = (write_kb * 1024.0) as u64;
-                        }
+            // Without IOKit bindings this is per-process I/O: what the running
+            // processes have read and written. Processes that exit drop out of
+            // that sum, so the rate comes from each process's own increase
+            // since the last sample and the totals add those increases up
+            let current: HashMap<u32, (u64, u64)> = {
+                let mut system = self.system()?;
+                system.refresh_processes();
+                system.processes().iter()
+                    .map(|(pid, process)| {
+                        let usage = process.disk_usage();
+                        (pid.as_u32(), (usage.total_read_bytes, usage.total_written_bytes))
+                    })
+                    .collect()
+            };
+            let (read_diff, write_diff) = current.iter().fold((0u64, 0u64), |(read, written), (pid, &(total_read, total_written))| {
+                // A new process, or a reused pid whose counters went
+                // backwards, starts a new baseline at zero
+                let (previous_read, previous_written) = match self.process_disk_io.get(pid) {
+                    Some(&(previous_read, previous_written)) if previous_read <= total_read && previous_written <= total_written => {
+                        (previous_read, previous_written)
                     }
-                    break;
-                }
-            }
+                    _ => (0, 0),
+                };
+                (read + total_read - previous_read, written + total_written - previous_written)
+            });
+            let first_sample = self.process_disk_io.is_empty();
+            self.process_disk_io = current;
+
+            let (previous_read, previous_written) = self.previous_metrics.as_ref()
+                .map_or((0, 0), |prev| (prev.disk_read_bytes, prev.disk_write_bytes));
+            let total_read_bytes = previous_read + read_diff;
+            let total_write_bytes = previous_written + write_diff;
 
             // Calculate rates if we have previous data
-            let (read_rate, write_rate) = if let (Some(prev), Some(prev_time)) = 
-                (&self.previous_metrics, self.previous_timestamp) {
-                let time_diff = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() - prev_time;
-                
-                if time_diff > 0 {
-                    let read_diff = total_read_bytes.saturating_sub(prev.disk_read_bytes);
-                    let write_diff = total_write_bytes.saturating_sub(prev.disk_write_bytes);
-                    
-                    (
-                        read_diff as f64 / time_diff as f64,
-                        write_diff as f64 / time_diff as f64
-                    )
-                } else {
-                    (0.0, 0.0)
+            let (read_rate, write_rate) = match self.previous_timestamp {
+                Some(prev_time) if !first_sample => {
+                    let time_diff = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs() - prev_time;
+
+                    if time_diff > 0 {
+                        (
+                            read_diff as f64 / time_diff as f64,
+                            write_diff as f64 / time_diff as f64
+                        )
+                    } else {
+                        (0.0, 0.0)
+                    }
                 }
-            } else {
-                (0.0, 0.0)
+                _ => (0.0, 0.0),
             };
 
             Ok((total_read_bytes, total_write_bytes, read_rate, write_rate))
@@ -389,27 +407,13 @@ impl SystemMetricsCollector {
     fn get_network_metrics(&mut self) -> anyhow::Result<(u64, u64, f64, f64)> {
         #[cfg(target_os = "macos")]
         {
-            let output = Command::new("netstat")
-                .args(&["-ib"])
-                .output()?;
-            
-            let output_str = String::from_utf8_lossy(&output.stdout);
             let mut total_rx_bytes = 0u64;
             let mut total_tx_bytes = 0u64;
-
-            for line in output_str.lines() {
-                if line.contains("en") && !line.contains("Name") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 10 {
-                        // netstat -ib format: Name Mtu Network Address Ipkts Ierrs Ibytes Opkts Oerrs Obytes Coll
-                        if let (Ok(rx_bytes), Ok(tx_bytes)) = (
-                            parts[6].parse::<u64>(),
-                            parts[9].parse::<u64>()
-                        ) {
-                            total_rx_bytes += rx_bytes;
-                            total_tx_bytes += tx_bytes;
-                        }
-                    }
+            for (interface, data) in &Networks::new_with_refreshed_list() {
+                // Physical interfaces, as netstat -ib showed them before
+                if interface.starts_with("en") {
+                    total_rx_bytes += data.total_received();
+                    total_tx_bytes += data.total_transmitted();
                 }
             }
 
@@ -504,21 +508,9 @@ impl SystemMetricsCollector {
     fn get_load_average(&self) -> anyhow::Result<[f64; 3]> {
         #[cfg(target_os = "macos")]
         {
-            let output = Command::new("uptime").output()?;
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            
-            // Parse load averages from uptime output
-            if let Some(load_start) = output_str.find("load averages:") {
-                let load_part = &output_str[load_start + 14..];
-                let loads: Vec<&str> = load_part.trim().split_whitespace().collect();
-                
-                if loads.len() >= 3 {
-                    let load1 = loads[0].parse().unwrap_or(0.0);
-                    let load5 = loads[1].parse().unwrap_or(0.0);
-                    let load15 = loads[2].parse().unwrap_or(0.0);
-                    return Ok([load1, load5, load15]);
-                }
-            }
+            // getloadavg(3)
+            let load = System::load_average();
+            Ok([load.one, load.five, load.fifteen])
         }
         
         #[cfg(target_os = "linux")]
@@ -535,44 +527,36 @@ impl SystemMetricsCollector {
             }
         }
         
-        Ok([0.0, 0.0, 0.0])
+        #[cfg(not(target_os = "macos"))]
+        {
+            Ok([0.0, 0.0, 0.0])
+        }
     }
 
     fn get_process_count(&self) -> anyhow::Result<u32> {
-        let output = Command::new("ps")
-            .args(&["-ax"])
-            .output()?;
-        
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let count = output_str.lines().count().saturating_sub(1) as u32; // Subtract header
-        Ok(count)
+        #[cfg(target_os = "linux")]
+        {
+            // One numeric directory per process
+            let count = fs::read_dir("/proc")?
+                .flatten()
+                .filter(|entry| entry.file_name().to_str().is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit())))
+                .count();
+            Ok(count as u32)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut system = self.system()?;
+            system.refresh_processes();
+            Ok(system.processes().len() as u32)
+        }
     }
 
     fn get_uptime(&self) -> anyhow::Result<u64> {
         #[cfg(target_os = "macos")]
         {
-            let output = Command::new("uptime").output()?;
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            
-            // Parse uptime - this is a simplified parser
-            if let Some(up_pos) = output_str.find("up ") {
-                let uptime_part = &output_str[up_pos + 3..];
-                
-                // Look for patterns like "1 day", "2:30", etc.
-                let mut total_seconds = 0u64;
-                
-                if uptime_part.contains("day") {
-                    if let Some(day_match) = uptime_part.split_whitespace().next() {
-                        if let Ok(days) = day_match.parse::<u64>() {
-                            total_seconds += days * 24 * 3600;
-                        }
-                    }
-                }
-                
-                // This is a simplified implementation
-                // In practice, you'd want more robust uptime parsing
-                return Ok(total_seconds);
-            }
+            // kern.boottime
+            Ok(System::uptime())
         }
         
         #[cfg(target_os = "linux")]
@@ -588,42 +572,350 @@ impl SystemMetricsCollector {
             }
         }
         
-        Ok(0)
+        #[cfg(not(target_os = "macos"))]
+        {
+            Ok(0)
+        }
+    }
+
+    // Per-device I/O of the physical disks, those with a device link in
+    // /sys/block; rates and utilization need a previous collection
+    pub fn collect_disks(&mut self) -> Vec<DiskMetrics> {
+        #[cfg(target_os = "linux")]
+        {
+            let Ok(diskstats) = fs::read_to_string("/proc/diskstats") else {
+                return Vec::new();
+            };
+            let now = Instant::now();
+            let elapsed = self.previous_disks_at.map(|at| now.duration_since(at).as_secs_f64());
+            let mut current = HashMap::new();
+            let mut disks = Vec::new();
+            for (name, sectors_read, sectors_written, busy_ms) in parse_diskstats(&diskstats) {
+                if !Path::new("/sys/block").join(&name).join("device").exists() {
+                    continue;
+                }
+                let (read_rate, write_rate, utilization) = match (self.previous_disks.get(&name), elapsed.filter(|e| *e > 0.0)) {
+                    (Some(&(read, written, busy)), Some(elapsed)) => (
+                        sectors_read.saturating_sub(read) as f64 * 512.0 / elapsed,
+                        sectors_written.saturating_sub(written) as f64 * 512.0 / elapsed,
+                        (busy_ms.saturating_sub(busy) as f64 / 10.0 / elapsed).min(100.0),
+                    ),
+                    _ => (0.0, 0.0, 0.0),
+                };
+                disks.push(DiskMetrics {
+                    name: name.clone(),
+                    read_bytes: sectors_read * 512,
+                    write_bytes: sectors_written * 512,
+                    read_rate,
+                    write_rate,
+                    utilization,
+                });
+                current.insert(name, (sectors_read, sectors_written, busy_ms));
+            }
+            self.previous_disks = current;
+            self.previous_disks_at = Some(now);
+            disks
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            Vec::new()
+        }
+    }
+
+    // CPU package and core temperatures from hwmon
+    pub fn collect_temperatures(&self) -> Vec<TemperatureReading> {
+        let mut readings = Vec::new();
+        let Ok(entries) = fs::read_dir("/sys/class/hwmon") else {
+            return readings;
+        };
+        let read = |path: &Path| fs::read_to_string(path).ok().map(|value| value.trim().to_string());
+        let mut hwmons: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        hwmons.sort();
+        for hwmon in hwmons {
+            let Some(sensor) = read(&hwmon.join("name")) else {
+                continue;
+            };
+            if !CPU_SENSORS.contains(&sensor.as_str()) {
+                continue;
+            }
+            // Inputs are numbered with gaps, e.g. coretemp skips missing cores
+            let mut indexes: Vec<u32> = fs::read_dir(&hwmon).into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok()
+                })
+                .collect();
+            indexes.sort_unstable();
+            for index in indexes {
+                let Some(millidegrees) = read(&hwmon.join(format!("temp{}_input", index)))
+                    .and_then(|value| value.parse::<f64>().ok())
+                else {
+                    continue;
+                };
+                let label = read(&hwmon.join(format!("temp{}_label", index)))
+                    .unwrap_or_else(|| format!("temp{}", index));
+                let critical = read(&hwmon.join(format!("temp{}_crit", index)))
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .map(|value| value / 1000.0);
+                readings.push(TemperatureReading {
+                    sensor: sensor.clone(),
+                    label,
+                    celsius: millidegrees / 1000.0,
+                    critical,
+                });
+            }
+        }
+        readings
+    }
+
+    // NVIDIA GPUs through NVML and AMD GPUs through amdgpu's sysfs files;
+    // empty on hosts with neither
+    pub fn collect_gpus(&mut self) -> Vec<GpuMetrics> {
+        #[cfg(target_os = "linux")]
+        {
+            let mut gpus = match self.nvml.get_or_init(nvml::Nvml::load) {
+                Some(nvml) => nvml.gpus(),
+                None => Vec::new(),
+            };
+            let Ok(entries) = fs::read_dir("/sys/class/drm") else {
+                return gpus;
+            };
+            let mut cards: Vec<_> = entries.flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| name.strip_prefix("card").is_some_and(|n| n.parse::<u32>().is_ok()))
+                .collect();
+            cards.sort();
+            for card in cards {
+                let device = Path::new("/sys/class/drm").join(&card).join("device");
+                let read = |file: &str| fs::read_to_string(device.join(file)).ok()
+                    .and_then(|value| value.trim().parse::<u64>().ok());
+                // Only amdgpu exposes gpu_busy_percent
+                let Some(busy) = read("gpu_busy_percent") else {
+                    continue;
+                };
+                let temperature = fs::read_dir(device.join("hwmon")).ok()
+                    .and_then(|mut hwmons| hwmons.next())
+                    .and_then(|hwmon| hwmon.ok())
+                    .and_then(|hwmon| fs::read_to_string(hwmon.path().join("temp1_input")).ok())
+                    .and_then(|value| value.trim().parse::<f64>().ok())
+                    .map(|value| value / 1000.0);
+                gpus.push(GpuMetrics {
+                    index: card.trim_start_matches("card").parse().unwrap_or(0),
+                    vendor: GpuVendor::Amd,
+                    name: fs::read_to_string(device.join("product_name"))
+                        .map(|name| name.trim().to_string())
+                        .unwrap_or_else(|_| card.clone()),
+                    utilization: busy as f64,
+                    memory_used: read("mem_info_vram_used").unwrap_or(0),
+                    memory_total: read("mem_info_vram_total").unwrap_or(0),
+                    temperature,
+                    power_watts: None,
+                });
+            }
+            gpus
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            Vec::new()
+        }
     }
 
+    // Busiest processes by CPU since the previous call (all zero on the
+    // first), then by resident memory
     pub fn get_top_processes(&self, limit: usize) -> anyhow::Result<Vec<ProcessMetrics>> {
-        let output = Command::new("ps")
-            .args(&["-axo", "pid,pcpu,pmem,comm", "-r"])
-            .output()?;
+        let mut system = self.system()?;
+        system.refresh_memory();
+        system.refresh_processes();
+        let total_memory = system.total_memory();
         
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let mut processes = Vec::new();
+        let mut processes: Vec<ProcessMetrics> = system.processes().values()
+            .map(|process| ProcessMetrics {
+                pid: process.pid().as_u32(),
+                name: process.name().to_string(),
+                cpu_percent: f64::from(process.cpu_usage()),
+                memory_bytes: process.memory(),
+                memory_percent: if total_memory > 0 {
+                    process.memory() as f64 / total_memory as f64 * 100.0
+                } else {
+                    0.0
+                },
+                command: if process.cmd().is_empty() {
+                    process.name().to_string()
+                } else {
+                    process.cmd().join(" ")
+                },
+            })
+            .collect();
+        processes.sort_by(|a, b| {
+            b.cpu_percent.total_cmp(&a.cpu_percent).then(b.memory_bytes.cmp(&a.memory_bytes))
+        });
+        processes.truncate(limit);
         
-        for (i, line) in output_str.lines().enumerate() {
-            if i == 0 || processes.len() >= limit {
-                continue; // Skip header or if we have enough
-            }
-            
+        Ok(processes)
+    }
+
+    fn system(&self) -> anyhow::Result<MutexGuard<'_, System>> {
+        self.system.lock().map_err(|_| anyhow::anyhow!("Failed to acquire system info lock"))
+    }
+}
+
+// Device name, sectors read, sectors written and milliseconds spent doing
+// I/O for each line of /proc/diskstats
+fn parse_diskstats(content: &str) -> Vec<(String, u64, u64, u64)> {
+    content.lines()
+        .filter_map(|line| {
             let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 4 {
-                if let (Ok(pid), Ok(cpu), Ok(mem)) = (
-                    parts[0].parse::<u32>(),
-                    parts[1].parse::<f64>(),
-                    parts[2].parse::<f64>()
-                ) {
-                    processes.push(ProcessMetrics {
-                        pid,
-                        name: parts[3].to_string(),
-                        cpu_percent: cpu,
-                        memory_bytes: 0, // Would need additional calculation
-                        memory_percent: mem,
-                        command: parts[3..].join(" "),
-                    });
+            let field = |index: usize| parts.get(index)?.parse::<u64>().ok();
+            Some((parts.get(2)?.to_string(), field(5)?, field(9)?, field(12)?))
+        })
+        .collect()
+}
+
+// NVIDIA's management library, the one nvidia-smi reports from, opened at
+// run time so hosts without the NVIDIA driver need nothing installed
+#[cfg(target_os = "linux")]
+mod nvml {
+    use std::ffi::{c_char, c_int, c_uint, c_ulonglong, c_void, CStr};
+    use super::{GpuMetrics, GpuVendor};
+
+    const NVML_SUCCESS: c_int = 0;
+    const NVML_TEMPERATURE_GPU: c_int = 0;
+    const NVML_DEVICE_NAME_BUFFER_SIZE: usize = 96;
+
+    type Device = *mut c_void;
+    type InitFn = unsafe extern "C" fn() -> c_int;
+    type DeviceCountFn = unsafe extern "C" fn(*mut c_uint) -> c_int;
+    type DeviceHandleFn = unsafe extern "C" fn(c_uint, *mut Device) -> c_int;
+    type NameFn = unsafe extern "C" fn(Device, *mut c_char, c_uint) -> c_int;
+    type UtilizationFn = unsafe extern "C" fn(Device, *mut Utilization) -> c_int;
+    type MemoryFn = unsafe extern "C" fn(Device, *mut Memory) -> c_int;
+    type TemperatureFn = unsafe extern "C" fn(Device, c_int, *mut c_uint) -> c_int;
+    type PowerFn = unsafe extern "C" fn(Device, *mut c_uint) -> c_int;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Utilization {
+        gpu: c_uint,
+        memory: c_uint,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Memory {
+        total: c_ulonglong,
+        free: c_ulonglong,
+        used: c_ulonglong,
+    }
+
+    #[derive(Debug)]
+    pub struct Nvml {
+        // Never closed; the functions below point into it
+        _library: *mut c_void,
+        device_count: DeviceCountFn,
+        device_handle: DeviceHandleFn,
+        name: NameFn,
+        utilization: UtilizationFn,
+        memory: MemoryFn,
+        temperature: TemperatureFn,
+        power: PowerFn,
+    }
+
+    // NVML is documented as thread-safe
+    unsafe impl Send for Nvml {}
+    unsafe impl Sync for Nvml {}
+
+    impl Nvml {
+        pub fn load() -> Option<Self> {
+            // SAFETY: the symbols are looked up by their documented names and
+            // called with the signatures of the NVML headers
+            unsafe {
+                let library = libc::dlopen(c"libnvidia-ml.so.1".as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+                if library.is_null() {
+                    return None;
                 }
+                let nvml = Self::bind(library);
+                if nvml.is_none() {
+                    libc::dlclose(library);
+                }
+                nvml
+            }
+        }
+
+        unsafe fn bind(library: *mut c_void) -> Option<Self> {
+            let symbol = |name: &CStr| {
+                let symbol = unsafe { libc::dlsym(library, name.as_ptr()) };
+                (!symbol.is_null()).then_some(symbol)
+            };
+            let init = unsafe { std::mem::transmute::<*mut c_void, InitFn>(symbol(c"nvmlInit_v2")?) };
+            let nvml = unsafe {
+                Self {
+                    _library: library,
+                    device_count: std::mem::transmute::<*mut c_void, DeviceCountFn>(symbol(c"nvmlDeviceGetCount_v2")?),
+                    device_handle: std::mem::transmute::<*mut c_void, DeviceHandleFn>(symbol(c"nvmlDeviceGetHandleByIndex_v2")?),
+                    name: std::mem::transmute::<*mut c_void, NameFn>(symbol(c"nvmlDeviceGetName")?),
+                    utilization: std::mem::transmute::<*mut c_void, UtilizationFn>(symbol(c"nvmlDeviceGetUtilizationRates")?),
+                    memory: std::mem::transmute::<*mut c_void, MemoryFn>(symbol(c"nvmlDeviceGetMemoryInfo")?),
+                    temperature: std::mem::transmute::<*mut c_void, TemperatureFn>(symbol(c"nvmlDeviceGetTemperature")?),
+                    power: std::mem::transmute::<*mut c_void, PowerFn>(symbol(c"nvmlDeviceGetPowerUsage")?),
+                }
+            };
+            // Fails when the library is there but the driver isn't loaded
+            (unsafe { init() } == NVML_SUCCESS).then_some(nvml)
+        }
+
+        // Readings the card does not support are left out rather than
+        // failing the whole GPU
+        pub fn gpus(&self) -> Vec<GpuMetrics> {
+            let mut count: c_uint = 0;
+            // SAFETY: every out pointer is to a local of the type NVML writes
+            unsafe {
+                if (self.device_count)(&mut count) != NVML_SUCCESS {
+                    return Vec::new();
+                }
+                (0..count)
+                    .filter_map(|index| {
+                        let mut device: Device = std::ptr::null_mut();
+                        if (self.device_handle)(index, &mut device) != NVML_SUCCESS {
+                            return None;
+                        }
+                        let mut name = [0 as c_char; NVML_DEVICE_NAME_BUFFER_SIZE];
+                        let name = match (self.name)(device, name.as_mut_ptr(), name.len() as c_uint) {
+                            NVML_SUCCESS => CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned(),
+                            _ => String::new(),
+                        };
+                        let mut utilization = Utilization::default();
+                        let utilization = match (self.utilization)(device, &mut utilization) {
+                            NVML_SUCCESS => utilization.gpu as f64,
+                            _ => 0.0,
+                        };
+                        let mut memory = Memory::default();
+                        if (self.memory)(device, &mut memory) != NVML_SUCCESS {
+                            memory = Memory::default();
+                        }
+                        let mut celsius: c_uint = 0;
+                        let temperature = ((self.temperature)(device, NVML_TEMPERATURE_GPU, &mut celsius) == NVML_SUCCESS)
+                            .then_some(celsius as f64);
+                        let mut milliwatts: c_uint = 0;
+                        let power_watts = ((self.power)(device, &mut milliwatts) == NVML_SUCCESS)
+                            .then(|| milliwatts as f64 / 1000.0);
+                        Some(GpuMetrics {
+                            index,
+                            vendor: GpuVendor::Nvidia,
+                            name,
+                            utilization,
+                            memory_used: memory.used,
+                            memory_total: memory.total,
+                            temperature,
+                            power_watts,
+                        })
+                    })
+                    .collect()
             }
         }
-        
-        Ok(processes)
     }
 }
 
@@ -638,6 +930,35 @@ mod tests {
         assert!(metrics.is_ok());
     }
 
+    #[test]
+    fn test_hardware_metric_parsing() {
+        let diskstats = "\
+ 259       0 nvme0n1 51880 19312 4137722 11634 120310 95232 6422264 88350 0 60240 102480 0 0 0 0 1734 2495
+ 259       1 nvme0n1p1 300 0 12044 60 2 0 8 0 0 92 60 0 0 0 0 0 0
+   7       0 loop0 12 0 26 3 0 0 0 0 0 8 3 0 0 0 0 0 0";
+        let disks = parse_diskstats(diskstats);
+        assert_eq!(disks.len(), 3);
+        assert_eq!(disks[0], ("nvme0n1".to_string(), 4137722, 6422264, 60240));
+
+        // Missing sensors and GPUs just leave the lists empty
+        let mut collector = SystemMetricsCollector::new();
+        collector.collect_temperatures();
+        collector.collect_gpus();
+        collector.collect_disks();
+        assert!(collector.collect_disks().iter().all(|disk| disk.utilization <= 100.0));
+    }
+
+    #[test]
+    fn test_native_process_table() {
+        let collector = SystemMetricsCollector::new();
+        let count = collector.get_process_count().unwrap();
+        let processes = collector.get_top_processes(usize::MAX).unwrap();
+        assert!(count > 0);
+        let me = processes.iter().find(|process| process.pid == std::process::id()).unwrap();
+        assert!(me.memory_bytes > 0);
+        assert!(processes.windows(2).all(|pair| pair[0].cpu_percent >= pair[1].cpu_percent));
+    }
+
     #[test]
     fn test_process_metrics() {
         let collector = SystemMetricsCollector::new();
@@ -0,0 +1,282 @@
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+
+pub const DEFAULT_UNIT_NAME: &str = "fluxdefense";
+pub const DEFAULT_UNIT_DIR: &str = "/etc/systemd/system";
+
+// Capabilities the daemon may need before it drops privileges: fanotify
+// (CAP_SYS_ADMIN), packet capture, nftables, reading other processes and
+// their files, response actions, and the privilege drop itself, including
+// handing its files to the daemon user
+const BOUNDING_CAPABILITIES: &[&str] = &[
+    "CAP_SYS_ADMIN", "CAP_NET_ADMIN", "CAP_NET_RAW", "CAP_SYS_PTRACE", "CAP_DAC_READ_SEARCH",
+    "CAP_KILL", "CAP_AUDIT_READ", "CAP_SETUID", "CAP_SETGID", "CAP_SETPCAP", "CAP_CHOWN",
+];
+
+// Sends a state change ("READY=1", "WATCHDOG=1", "STATUS=...") to the service
+// manager. Returns false when not started by systemd with Type=notify.
+pub fn notify(state: &str) -> Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => {
+            notify_socket(Path::new(&socket), state)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn notify_socket(socket: &Path, state: &str) -> Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    let name = socket.as_os_str().as_encoded_bytes();
+    match name.strip_prefix(b"@") {
+        // Abstract namespace socket
+        #[cfg(target_os = "linux")]
+        Some(abstract_name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(abstract_name)?;
+            sender.send_to_addr(state.as_bytes(), &address)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(anyhow!("Abstract notify sockets are only supported on Linux")),
+        None => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn notify_ready(status: &str) {
+    if let Err(e) = notify(&format!("READY=1\nSTATUS={}", status)) {
+        warn!("Failed to notify systemd: {:#}", e);
+    }
+}
+
+pub fn notify_stopping() {
+    if let Err(e) = notify("STOPPING=1") {
+        warn!("Failed to notify systemd: {:#}", e);
+    }
+}
+
+// The WatchdogSec= interval when systemd expects keep-alive pings from us
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+// Pings the watchdog at half its interval from the async runtime, so a
+// wedged runtime stops the pings and systemd restarts the daemon
+pub fn spawn_watchdog() -> Option<tokio::task::JoinHandle<()>> {
+    let interval = watchdog_interval()? / 2;
+    info!("systemd watchdog enabled, pinging every {:?}", interval);
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = notify("WATCHDOG=1") {
+                warn!("Failed to ping systemd watchdog: {:#}", e);
+            }
+        }
+    }))
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitOptions {
+    pub name: String,
+    pub exec_path: PathBuf,
+    pub config_path: Option<PathBuf>,
+    pub watchdog: Duration,
+}
+
+impl UnitOptions {
+    pub fn new(exec_path: PathBuf, config_path: Option<PathBuf>) -> Self {
+        Self {
+            name: DEFAULT_UNIT_NAME.to_string(),
+            exec_path,
+            config_path,
+            watchdog: Duration::from_secs(30),
+        }
+    }
+}
+
+// A hardened unit for `flux-monitor start`: the filesystem is read-only
+// except for the paths the configuration writes to, and the capability
+// bounding set is limited to what startup needs
+pub fn render_unit(options: &UnitOptions, config: &Config) -> String {
+    let mut exec = format!("{} start", options.exec_path.display());
+    if let Some(ref path) = options.config_path {
+        exec.push_str(&format!(" --config {}", path.display()));
+    }
+
+    // A leading '-' lets the unit start when a path does not exist yet
+    let mut writable: Vec<String> = vec!["-/var/lib/fluxdefense".to_string()];
+    let mut add_dir = |path: Option<&Path>| {
+        if let Some(dir) = path.filter(|dir| !dir.as_os_str().is_empty()) {
+            let entry = format!("-{}", dir.display());
+            if !writable.contains(&entry) {
+                writable.push(entry);
+            }
+        }
+    };
+    add_dir(config.log_file_path.as_deref().and_then(Path::parent));
+    add_dir(config.audit_log_path.as_deref().and_then(Path::parent));
+    add_dir(Some(config.quarantine_directory.as_path()));
+
+    let mut capabilities: Vec<String> = BOUNDING_CAPABILITIES.iter().map(|cap| cap.to_string()).collect();
+    if let Some(ref privileges) = config.privileges {
+        for cap in &privileges.retain_capabilities {
+            let cap = cap.trim().to_uppercase();
+            let cap = if cap.starts_with("CAP_") { cap } else { format!("CAP_{}", cap) };
+            if !capabilities.contains(&cap) {
+                capabilities.push(cap);
+            }
+        }
+    }
+
+    format!(
+"[Unit]
+Description=FluxDefense endpoint monitoring
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+ExecStart={exec}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+RestartForceExitStatus={restart_status}
+RestartSec=5s
+WatchdogSec={watchdog}s
+TimeoutStopSec=30s
+
+# Hardening
+CapabilityBoundingSet={capabilities}
+NoNewPrivileges=yes
+ProtectSystem=strict
+ReadWritePaths={writable}
+ProtectHome=read-only
+PrivateTmp=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+ProtectClock=yes
+ProtectHostname=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+RestrictNamespaces=yes
+LockPersonality=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK AF_PACKET
+
+[Install]
+WantedBy=multi-user.target
+",
+        exec = exec,
+        watchdog = options.watchdog.as_secs(),
+        capabilities = capabilities.join(" "),
+        writable = writable.join(" "),
+        restart_status = crate::update::RESTART_EXIT_CODE,
+    )
+}
+
+pub fn unit_path(unit_dir: &Path, name: &str) -> PathBuf {
+    unit_dir.join(format!("{}.service", name))
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    debug!("systemctl {}", args.join(" "));
+    let status = Command::new("systemctl")
+        .args(args)
+        .status()
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        return Err(anyhow!("systemctl {} failed: {}", args.join(" "), status));
+    }
+    Ok(())
+}
+
+// Writes the unit and enables it; `start` also starts it right away
+pub fn install_unit(unit_dir: &Path, options: &UnitOptions, config: &Config, start: bool) -> Result<PathBuf> {
+    let path = unit_path(unit_dir, &options.name);
+    std::fs::create_dir_all(unit_dir)?;
+    std::fs::write(&path, render_unit(options, config))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("Wrote {}", path.display());
+
+    systemctl(&["daemon-reload"])?;
+    let unit = format!("{}.service", options.name);
+    if start {
+        systemctl(&["enable", "--now", &unit])?;
+    } else {
+        systemctl(&["enable", &unit])?;
+    }
+    Ok(path)
+}
+
+pub fn uninstall_unit(unit_dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = unit_path(unit_dir, name);
+    if !path.exists() {
+        return Err(anyhow!("{} is not installed", path.display()));
+    }
+    let unit = format!("{}.service", name);
+    // Already stopped or disabled is fine
+    if let Err(e) = systemctl(&["disable", "--now", &unit]) {
+        warn!("{:#}", e);
+    }
+    std::fs::remove_file(&path)?;
+    systemctl(&["daemon-reload"])?;
+    Ok(path)
+}
+
+pub fn unit_status(name: &str) -> Result<()> {
+    // Exits non-zero for inactive units, which is still a valid answer
+    Command::new("systemctl")
+        .args(["status", "--no-pager", &format!("{}.service", name)])
+        .status()
+        .context("Failed to run systemctl")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_and_notify_socket() {
+        let mut config = Config::default();
+        config.privileges = Some(crate::privileges::PrivilegeConfig {
+            retain_capabilities: vec!["net_bind_service".to_string()],
+            ..Default::default()
+        });
+        let options = UnitOptions::new(PathBuf::from("/usr/local/bin/flux-monitor"), Some(PathBuf::from("/etc/fluxdefense/config.yaml")));
+        let unit = render_unit(&options, &config);
+        assert!(unit.contains("ExecStart=/usr/local/bin/flux-monitor start --config /etc/fluxdefense/config.yaml\n"));
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains("WatchdogSec=30s\n"));
+        assert!(unit.contains("ReadWritePaths=-/var/lib/fluxdefense -/var/log -/var/quarantine/fluxdefense\n"));
+        assert!(unit.lines().any(|line| line.starts_with("CapabilityBoundingSet=") && line.ends_with(" CAP_NET_BIND_SERVICE")));
+
+        let dir = std::env::temp_dir().join(format!("flux-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("notify.sock");
+        let _ = std::fs::remove_file(&socket_path);
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+        notify_socket(&socket_path, "READY=1\nSTATUS=Monitoring").unwrap();
+        let mut buffer = [0u8; 64];
+        let n = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"READY=1\nSTATUS=Monitoring");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,437 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::policy::edit::write_atomic;
+use crate::policy::{PolicySigningConfig, PolicyVerifier};
+
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Starts of a new version that may fail before it is rolled back
+const MAX_TRIAL_BOOTS: u32 = 3;
+// The daemon exits with this (EX_TEMPFAIL) to be started again by its
+// supervisor, as root, when an update is waiting or a trial failed
+pub const RESTART_EXIT_CODE: i32 = 75;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateConfig {
+    // Release manifest; "{target}" is replaced with e.g. x86_64-linux
+    pub manifest_url: String,
+    // Hex Ed25519 keys; a binary signed by any of them is accepted
+    pub public_keys: Vec<String>,
+    // Check and apply updates on this interval; unset only updates when asked
+    #[serde(default)]
+    pub check_interval_secs: Option<u64>,
+    // Must answer 2xx once a new version has run for the grace period;
+    // unset, staying up that long is enough
+    #[serde(default)]
+    pub health_url: Option<String>,
+    #[serde(default = "default_health_grace_secs")]
+    pub health_grace_secs: u64,
+}
+
+fn default_health_grace_secs() -> u64 {
+    60
+}
+
+impl UpdateConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.manifest_url.is_empty() {
+            bail!("Update manifest_url is empty");
+        }
+        self.verifier().map(|_| ())
+    }
+
+    fn verifier(&self) -> Result<PolicyVerifier> {
+        PolicyVerifier::new(&PolicySigningConfig { public_keys: self.public_keys.clone() })
+    }
+
+    pub fn manifest_url(&self) -> String {
+        self.manifest_url.replace("{target}", &current_target())
+    }
+}
+
+// The platform releases are built for, e.g. x86_64-linux
+pub fn current_target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+// What the manifest URL serves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    pub target: String,
+    pub url: String,
+    pub sha256: String,
+    // Detached Ed25519 signature over `signed_payload`, hex
+    pub signature: String,
+}
+
+impl Release {
+    // Binds the binary's digest to its version and target, so a manifest
+    // cannot pass an old signed binary off as a newer release or another
+    // platform's build
+    pub fn signed_payload(&self) -> Vec<u8> {
+        format!(
+            "fluxdefense-release\nversion:{}\ntarget:{}\nsha256:{}\n",
+            self.version,
+            self.target,
+            self.sha256.trim().to_lowercase()
+        ).into_bytes()
+    }
+}
+
+// Kept next to the binary while a new version is on trial
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingUpdate {
+    pub from_version: String,
+    pub to_version: String,
+    pub installed_at: DateTime<Utc>,
+    pub boots: u32,
+    // The health check verdict, settled at the next start
+    #[serde(default)]
+    pub healthy: Option<bool>,
+}
+
+fn sibling(binary: &Path, suffix: &str) -> PathBuf {
+    let mut name = binary.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn previous_path(binary: &Path) -> PathBuf {
+    sibling(binary, ".previous")
+}
+
+fn pending_path(binary: &Path) -> PathBuf {
+    sibling(binary, ".pending.json")
+}
+
+// The last version rolled back, which is not installed again
+fn rejected_path(binary: &Path) -> PathBuf {
+    sibling(binary, ".rejected")
+}
+
+fn rejected_version(binary: &Path) -> Option<String> {
+    std::fs::read_to_string(rejected_path(binary)).ok().map(|version| version.trim().to_string())
+}
+
+// Dotted numeric versions; a pre-release suffix is ignored
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version.trim_start_matches('v')
+            .split(['-', '+']).next().unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+pub struct Updater {
+    config: UpdateConfig,
+    verifier: PolicyVerifier,
+    client: reqwest::Client,
+    binary: PathBuf,
+}
+
+impl Updater {
+    pub fn new(config: UpdateConfig) -> Result<Self> {
+        let binary = std::env::current_exe().context("Cannot locate the running binary")?;
+        Self::for_binary(config, binary)
+    }
+
+    pub fn for_binary(config: UpdateConfig, binary: PathBuf) -> Result<Self> {
+        Ok(Self {
+            verifier: config.verifier()?,
+            client: reqwest::Client::builder().timeout(Duration::from_secs(300)).build()?,
+            config,
+            binary,
+        })
+    }
+
+    // The advertised release, if newer than this binary and not rolled back
+    pub async fn check(&self) -> Result<Option<Release>> {
+        let url = self.config.manifest_url();
+        let release: Release = self.client.get(&url).send().await?
+            .error_for_status()?
+            .json().await
+            .with_context(|| format!("Invalid release manifest at {}", url))?;
+        if rejected_version(&self.binary).as_deref() == Some(release.version.as_str()) {
+            return Ok(None);
+        }
+        Ok(is_newer(&release.version, CURRENT_VERSION).then_some(release))
+    }
+
+    pub async fn download(&self, release: &Release) -> Result<Vec<u8>> {
+        let bytes = self.client.get(&release.url).send().await?
+            .error_for_status()?
+            .bytes().await?
+            .to_vec();
+        self.verify(release, &bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn verify(&self, release: &Release, binary: &[u8]) -> Result<()> {
+        if release.target != current_target() {
+            bail!("Refusing update {}: built for {}, not {}", release.version, release.target, current_target());
+        }
+        if !is_newer(&release.version, CURRENT_VERSION) {
+            bail!("Refusing update {}: not newer than the running {}", release.version, CURRENT_VERSION);
+        }
+        let digest = hex::encode(Sha256::digest(binary));
+        if !digest.eq_ignore_ascii_case(release.sha256.trim()) {
+            bail!("Refusing update {}: SHA-256 {} does not match the manifest", release.version, digest);
+        }
+        self.verifier.verify(&release.signed_payload(), &release.signature)
+            .with_context(|| format!("Refusing update {}", release.version))
+    }
+
+    // Swaps the verified binary in with a rename, so the path always holds a
+    // complete binary; the old one is kept for the rollback
+    pub fn install(&self, release: &Release, binary: &[u8]) -> Result<()> {
+        self.verify(release, binary)?;
+        let staged = sibling(&self.binary, ".new");
+        std::fs::write(&staged, binary)
+            .with_context(|| format!("Failed to stage {}", staged.display()))?;
+        let permissions = std::fs::metadata(&self.binary)?.permissions();
+        std::fs::set_permissions(&staged, permissions)?;
+        std::fs::File::open(&staged)?.sync_all()?;
+
+        let previous = previous_path(&self.binary);
+        std::fs::remove_file(&previous).ok();
+        std::fs::hard_link(&self.binary, &previous)
+            .or_else(|_| std::fs::copy(&self.binary, &previous).map(|_| ()))
+            .with_context(|| format!("Failed to keep {}", previous.display()))?;
+
+        let pending = PendingUpdate {
+            from_version: CURRENT_VERSION.to_string(),
+            to_version: release.version.clone(),
+            installed_at: Utc::now(),
+            boots: 0,
+            healthy: None,
+        };
+        write_atomic(&pending_path(&self.binary), &serde_json::to_string(&pending)?)?;
+        std::fs::rename(&staged, &self.binary)
+            .with_context(|| format!("Failed to replace {}", self.binary.display()))?;
+        info!("Installed version {} at {}", release.version, self.binary.display());
+        Ok(())
+    }
+
+    // Checks, downloads and installs; the new version runs after `restart`.
+    // Writes next to the binary, so only call it while still root
+    pub async fn update(&self) -> Result<Option<String>> {
+        let Some(release) = self.check().await? else { return Ok(None) };
+        info!("Updating from {} to {}", CURRENT_VERSION, release.version);
+        let binary = self.download(&release).await?;
+        self.install(&release, &binary)?;
+        Ok(Some(release.version))
+    }
+}
+
+pub enum TrialState {
+    // No update waiting to be confirmed
+    Settled,
+    OnTrial(TrialRecord),
+    // The new version kept failing and the old one is back in place
+    RolledBack(PendingUpdate),
+}
+
+// The trial file, opened while still root so the health verdict can be
+// written after privileges are dropped
+pub struct TrialRecord {
+    pub pending: PendingUpdate,
+    file: std::fs::File,
+}
+
+impl TrialRecord {
+    pub fn record(&mut self, healthy: bool) -> Result<()> {
+        use std::io::{Seek, Write};
+        self.pending.healthy = Some(healthy);
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        self.file.write_all(serde_json::to_string(&self.pending)?.as_bytes())?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+// Called at startup, as root: settles the health verdict of a version on
+// trial, or counts another start and rolls back once it has failed to
+// settle too often, e.g. by crashing under a supervisor that keeps
+// restarting it
+pub fn resume_pending(binary: &Path) -> Result<TrialState> {
+    let path = pending_path(binary);
+    let mut pending: PendingUpdate = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).with_context(|| format!("Corrupt {}", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(TrialState::Settled),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    match pending.healthy {
+        Some(true) => {
+            confirm(binary)?;
+            info!("Version {} confirmed healthy", pending.to_version);
+            return Ok(TrialState::Settled);
+        }
+        Some(false) => {
+            warn!("Version {} failed its health check", pending.to_version);
+            rollback(binary)?;
+            return Ok(TrialState::RolledBack(pending));
+        }
+        None => {}
+    }
+    pending.boots += 1;
+    if pending.boots > MAX_TRIAL_BOOTS {
+        warn!("Version {} did not settle after {} starts", pending.to_version, MAX_TRIAL_BOOTS);
+        rollback(binary)?;
+        return Ok(TrialState::RolledBack(pending));
+    }
+    write_atomic(&path, &serde_json::to_string(&pending)?)?;
+    let file = std::fs::File::options().write(true).open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(TrialState::OnTrial(TrialRecord { pending, file }))
+}
+
+// Puts the previous binary back and ends the trial. The version rolled
+// back from is not installed again
+pub fn rollback(binary: &Path) -> Result<()> {
+    let previous = previous_path(binary);
+    let pending = std::fs::read_to_string(pending_path(binary)).ok()
+        .and_then(|content| serde_json::from_str::<PendingUpdate>(&content).ok());
+    std::fs::rename(&previous, binary)
+        .with_context(|| format!("Failed to restore {}", previous.display()))?;
+    if let Some(pending) = pending {
+        write_atomic(&rejected_path(binary), &pending.to_version)?;
+    }
+    std::fs::remove_file(pending_path(binary)).ok();
+    warn!("Rolled back {}", binary.display());
+    Ok(())
+}
+
+// Ends the trial of the running version
+pub fn confirm(binary: &Path) -> Result<()> {
+    match std::fs::remove_file(pending_path(binary)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Waits out the grace period and records whether the running version is
+// healthy; the next start confirms it or rolls back. Returns the verdict
+pub async fn confirm_when_healthy(mut trial: TrialRecord, health_url: Option<&str>, grace: Duration) -> Result<bool> {
+    tokio::time::sleep(grace).await;
+    let healthy = match health_url {
+        None => true,
+        Some(url) => match reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?.get(url).send().await {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                warn!("Health check {} answered {}", url, response.status());
+                false
+            }
+            Err(e) => {
+                warn!("Health check {} failed: {}", url, e);
+                false
+            }
+        },
+    };
+    trial.record(healthy)?;
+    if healthy {
+        info!("Version {} is healthy", CURRENT_VERSION);
+    }
+    Ok(healthy)
+}
+
+// Replaces this process with whatever binary is now at its path, with the
+// same arguments; only returns on failure. The new process keeps the
+// credentials and seccomp filter, so only call it before dropping them
+pub fn restart() -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    let binary = std::env::current_exe()?;
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let err = std::process::Command::new(&binary).args(args).exec();
+    Err(anyhow!("Failed to restart {}: {}", binary.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PolicySigner;
+
+    #[test]
+    fn test_update_install_and_rollback() {
+        assert!(is_newer("0.10.0", "0.9.3"));
+        assert!(is_newer("v1.0.1-rc1", "1.0.0"));
+        assert!(!is_newer("1.0.0", "1.0.0"));
+
+        let signer = PolicySigner::from_pkcs8_hex(&PolicySigner::generate().unwrap()).unwrap();
+        let config = UpdateConfig {
+            manifest_url: "https://updates.example.com/{target}/latest.json".to_string(),
+            public_keys: vec![signer.public_key_hex()],
+            check_interval_secs: None,
+            health_url: None,
+            health_grace_secs: 60,
+        };
+        assert!(!config.manifest_url().contains("{target}"));
+
+        let dir = std::env::temp_dir().join(format!("flux-update-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("flux-monitor");
+        std::fs::write(&binary, b"old").unwrap();
+        let updater = Updater::for_binary(config, binary.clone()).unwrap();
+
+        let new = b"new".to_vec();
+        let sign = |release: Release| Release { signature: signer.sign(&release.signed_payload()), ..release };
+        let release = sign(Release {
+            version: "99.0.0".to_string(),
+            target: current_target(),
+            url: String::new(),
+            sha256: hex::encode(Sha256::digest(&new)),
+            signature: String::new(),
+        });
+        // Tampered and unsigned binaries are refused
+        assert!(updater.install(&release, b"evil").is_err());
+        let unsigned = Release { signature: hex::encode([0u8; 64]), ..release.clone() };
+        assert!(updater.install(&unsigned, &new).is_err());
+        // So are relabelled, older and foreign releases, even when signed
+        let relabelled = Release { version: "100.0.0".to_string(), ..release.clone() };
+        assert!(updater.install(&relabelled, &new).is_err());
+        let old = sign(Release { version: "0.0.1".to_string(), ..release.clone() });
+        assert!(updater.install(&old, &new).is_err());
+        let foreign = sign(Release { target: "sparc-plan9".to_string(), ..release.clone() });
+        assert!(updater.install(&foreign, &new).is_err());
+        assert_eq!(std::fs::read(&binary).unwrap(), b"old");
+
+        updater.install(&release, &new).unwrap();
+        assert_eq!(std::fs::read(&binary).unwrap(), b"new");
+        for _ in 0..MAX_TRIAL_BOOTS {
+            assert!(matches!(resume_pending(&binary).unwrap(), TrialState::OnTrial(_)));
+        }
+        // One start too many
+        assert!(matches!(resume_pending(&binary).unwrap(), TrialState::RolledBack(_)));
+        assert_eq!(std::fs::read(&binary).unwrap(), b"old");
+        assert!(matches!(resume_pending(&binary).unwrap(), TrialState::Settled));
+
+        // A rolled back version is not offered again
+        assert_eq!(rejected_version(&binary).as_deref(), Some("99.0.0"));
+
+        // The verdict is recorded on trial and acted on at the next start
+        updater.install(&release, &new).unwrap();
+        let TrialState::OnTrial(mut trial) = resume_pending(&binary).unwrap() else { panic!("not on trial") };
+        trial.record(false).unwrap();
+        assert!(matches!(resume_pending(&binary).unwrap(), TrialState::RolledBack(_)));
+        assert_eq!(std::fs::read(&binary).unwrap(), b"old");
+
+        updater.install(&release, &new).unwrap();
+        let TrialState::OnTrial(mut trial) = resume_pending(&binary).unwrap() else { panic!("not on trial") };
+        trial.record(true).unwrap();
+        assert!(matches!(resume_pending(&binary).unwrap(), TrialState::Settled));
+        assert_eq!(std::fs::read(&binary).unwrap(), b"new");
+        assert!(!pending_path(&binary).exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}